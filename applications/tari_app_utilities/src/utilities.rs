@@ -26,6 +26,7 @@ use log::*;
 use std::{net::SocketAddr, path::Path};
 use tari_common::{CommsTransport, GlobalConfig, SocksAuthentication, TorControlAuthentication};
 use tari_comms::{
+    connection_manager::PeerVersionPolicy,
     connectivity::ConnectivityError,
     peer_manager::NodeId,
     protocol::rpc::RpcError,
@@ -36,7 +37,10 @@ use tari_comms::{
     types::CommsPublicKey,
 };
 use tari_core::tari_utilities::hex::Hex;
-use tari_p2p::transport::{TorConfig, TransportType};
+use tari_p2p::{
+    initialization::CommsConfig,
+    transport::{TorConfig, TransportType},
+};
 use tari_wallet::{
     error::{WalletError, WalletStorageError},
     output_manager_service::error::OutputManagerError,
@@ -226,6 +230,31 @@ pub fn setup_wallet_transport_type(config: &GlobalConfig) -> TransportType {
             },
             listener_address,
         },
+        CommsTransport::WebSocket { listener_address } => TransportType::WebSocket { listener_address },
+        CommsTransport::Quic {
+            listener_address,
+            tor_socks_address,
+            tor_socks_auth,
+        } => TransportType::Quic {
+            listener_address,
+            tor_socks_config: tor_socks_address.map(|proxy_address| SocksConfig {
+                proxy_address,
+                authentication: tor_socks_auth.map(convert_socks_authentication).unwrap_or_default(),
+            }),
+        },
+        CommsTransport::Tls {
+            listener_address,
+            tor_socks_address,
+            tor_socks_auth,
+            plaintext_fallback,
+        } => TransportType::Tls {
+            listener_address,
+            tor_socks_config: tor_socks_address.map(|proxy_address| SocksConfig {
+                proxy_address,
+                authentication: tor_socks_auth.map(convert_socks_authentication).unwrap_or_default(),
+            }),
+            plaintext_fallback,
+        },
     }
 }
 
@@ -244,6 +273,20 @@ pub fn convert_socks_authentication(auth: SocksAuthentication) -> socks::Authent
     }
 }
 
+/// Creates the peer version policy from the configuration
+/// ## Parameters
+/// `config` - The reference to the configuration, see [GlobalConfig]
+///
+/// ## Returns
+/// The policy to apply to peers below the configured minimum protocol version
+pub fn peer_version_policy(config: &GlobalConfig) -> PeerVersionPolicy {
+    match config.min_peer_protocol_version {
+        Some(min_version) if config.refuse_peers_below_min_version => PeerVersionPolicy::RefuseBelow(min_version),
+        Some(min_version) => PeerVersionPolicy::WarnBelow(min_version),
+        None => CommsConfig::DEFAULT_PEER_VERSION_POLICY,
+    }
+}
+
 /// Sets up the tokio runtime based on the configuration
 /// ## Parameters
 /// `config` - The configuration  of the base node
@@ -232,7 +232,9 @@ where B: BlockchainBackend + 'static
             datastore_path: self.config.peer_db_path.clone(),
             peer_database_name: "peers".to_string(),
             max_concurrent_inbound_tasks: 100,
+            max_pending_inbound_per_peer: Some(20),
             outbound_buffer_size: 100,
+            messaging_shutdown_grace_period: self.config.messaging_shutdown_grace_period,
             dht: DhtConfig {
                 database_url: DbConnectionUrl::File(self.config.data_dir.join("dht.db")),
                 auto_join: true,
@@ -240,6 +242,12 @@ where B: BlockchainBackend + 'static
                 network: self.config.network.into(),
                 flood_ban_max_msg_count: self.config.flood_ban_max_msg_count,
                 saf_msg_validity: self.config.saf_expiry_duration,
+                pipeline_error_log_capacity: self.config.pipeline_error_log_capacity,
+                metrics_snapshot_path: self
+                    .config
+                    .metrics_snapshot_interval
+                    .map(|_| self.config.data_dir.join("metrics_snapshots.dat")),
+                metrics_snapshot_interval: self.config.metrics_snapshot_interval.unwrap_or_default(),
                 ..Default::default()
             },
             allow_test_addresses: self.config.allow_test_addresses,
@@ -254,9 +262,17 @@ where B: BlockchainBackend + 'static
                 .cloned()
                 .chain(self.config.force_sync_peers.clone())
                 .collect(),
+            trusted_peers: self.config.trusted_peers.clone(),
             dns_seeds: self.config.dns_seeds.clone(),
             dns_seeds_name_server: self.config.dns_seeds_name_server,
             dns_seeds_use_dnssec: self.config.dns_seeds_use_dnssec,
+            dial_connect_timeout: self.config.dial_connect_timeout,
+            noise_handshake_timeout: self.config.noise_handshake_timeout,
+            identity_exchange_timeout: self.config.identity_exchange_timeout,
+            peer_version_policy: utilities::peer_version_policy(&self.config),
+            dial_backoff: Default::default(),
+            log_target_levels: self.config.log_target_levels.clone(),
+            admin_socket_address: None,
         }
     }
 
@@ -338,6 +354,35 @@ where B: BlockchainBackend + 'static
                 },
                 listener_address,
             },
+            CommsTransport::WebSocket { listener_address } => TransportType::WebSocket { listener_address },
+            CommsTransport::Quic {
+                listener_address,
+                tor_socks_address,
+                tor_socks_auth,
+            } => TransportType::Quic {
+                listener_address,
+                tor_socks_config: tor_socks_address.map(|proxy_address| SocksConfig {
+                    proxy_address,
+                    authentication: tor_socks_auth
+                        .map(utilities::convert_socks_authentication)
+                        .unwrap_or_default(),
+                }),
+            },
+            CommsTransport::Tls {
+                listener_address,
+                tor_socks_address,
+                tor_socks_auth,
+                plaintext_fallback,
+            } => TransportType::Tls {
+                listener_address,
+                tor_socks_config: tor_socks_address.map(|proxy_address| SocksConfig {
+                    proxy_address,
+                    authentication: tor_socks_auth
+                        .map(utilities::convert_socks_authentication)
+                        .unwrap_or_default(),
+                }),
+                plaintext_fallback,
+            },
         }
     }
 }
@@ -26,20 +26,31 @@ use chrono::{DateTime, Utc};
 use log::*;
 use std::{
     cmp,
+    collections::BTreeMap,
+    fs,
     fs::File,
     io::{self, Write},
     string::ToString,
     sync::Arc,
     time::{Duration, Instant},
 };
-use tari_common::GlobalConfig;
+use tari_common::{logging, GlobalConfig};
 use tari_comms::{
-    connectivity::ConnectivityRequester,
-    peer_manager::{NodeId, Peer, PeerFeatures, PeerManager, PeerManagerError, PeerQuery},
+    connectivity::{ConnectivityError, ConnectivityRequester},
+    peer_manager::{BanList, NodeId, Peer, PeerFeatures, PeerManager, PeerManagerError, PeerQuery, PeerTrustLevel},
     protocol::rpc::RpcServerHandle,
+    types::CommsPublicKey,
     NodeIdentity,
+    PeerConnection,
+};
+use tari_comms_dht::{
+    envelope::NodeDestination,
+    inbound::PipelineErrorLog,
+    outbound::{OutboundAuditLog, OutboundAuditQuery, OutboundMessageRequester, SendMessageParams},
+    DhtDiscoveryRequester,
+    MetricsCollectorHandle,
+    MetricsSnapshotStore,
 };
-use tari_comms_dht::{envelope::NodeDestination, DhtDiscoveryRequester, MetricsCollectorHandle};
 use tari_core::{
     base_node::{
         comms_interface::BlockEvent,
@@ -55,6 +66,7 @@ use tari_core::{
     transactions::types::{Commitment, HashOutput, Signature},
 };
 use tari_crypto::ristretto::RistrettoPublicKey;
+use tari_p2p::tari_message::TariMessageType;
 use tari_wallet::util::emoji::EmojiId;
 use tokio::{runtime, sync::watch};
 // Import the auto-generated const values from the Manifest and Git
@@ -67,6 +79,10 @@ pub struct CommandHandler {
     blockchain_db: AsyncBlockchainDb<LMDBDatabase>,
     discovery_service: DhtDiscoveryRequester,
     dht_metrics_collector: MetricsCollectorHandle,
+    outbound_messaging: OutboundMessageRequester,
+    outbound_audit_log: OutboundAuditLog,
+    pipeline_error_log: PipelineErrorLog,
+    metrics_snapshots: Option<MetricsSnapshotStore>,
     rpc_server: RpcServerHandle,
     base_node_identity: Arc<NodeIdentity>,
     peer_manager: Arc<PeerManager>,
@@ -84,6 +100,10 @@ impl CommandHandler {
             blockchain_db: ctx.blockchain_db().into(),
             discovery_service: ctx.base_node_dht().discovery_service_requester(),
             dht_metrics_collector: ctx.base_node_dht().metrics_collector(),
+            outbound_messaging: ctx.base_node_dht().outbound_requester(),
+            outbound_audit_log: ctx.base_node_dht().outbound_audit_log(),
+            pipeline_error_log: ctx.base_node_dht().pipeline_error_log(),
+            metrics_snapshots: ctx.base_node_dht().metrics_snapshots(),
             rpc_server: ctx.rpc_server(),
             base_node_identity: ctx.base_node_identity(),
             peer_manager: ctx.base_node_comms().peer_manager(),
@@ -146,12 +166,26 @@ impl CommandHandler {
             let banned_peers = fetch_banned_peers(&peer_manager).await.unwrap();
             let conns = connectivity.get_active_connections().await.unwrap();
             status_line.add_field("Connections", conns.len());
+            let versions = fetch_peer_version_distribution(&peer_manager, &conns).await.unwrap();
+            status_line.add_field(
+                "Versions",
+                versions
+                    .iter()
+                    .map(|(user_agent, count)| format!("{} ({})", user_agent, count))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
 
             let num_messages = metrics
                 .get_total_message_count_in_timespan(Duration::from_secs(60))
                 .await
                 .unwrap();
             status_line.add_field("Messages (last 60s)", num_messages);
+            let wire_formats = metrics.get_wire_format_counts().await.unwrap();
+            status_line.add_field(
+                "Legacy envelopes",
+                format!("{:.0}% in", wire_formats.inbound_legacy_ratio() * 100.0),
+            );
             status_line.add_field("Banned", banned_peers.len());
 
             let num_active_rpc_sessions = rpc_server.get_num_active_sessions().await.unwrap();
@@ -359,6 +393,165 @@ impl CommandHandler {
         });
     }
 
+    /// Send an encoded domain message directly to a peer. This is intended for protocol debugging and network tooling.
+    pub fn send_message(&self, dest_pubkey: Box<RistrettoPublicKey>, message_type: i32, payload: Vec<u8>) {
+        let mut outbound_messaging = self.outbound_messaging.clone();
+
+        self.executor.spawn(async move {
+            let message_type_name = TariMessageType::from_i32(message_type)
+                .map(|t| format!("{:?}", t))
+                .unwrap_or_else(|| "Unknown".to_string());
+            println!(
+                "Sending {} byte(s) message of type {} ({}) to {}",
+                payload.len(),
+                message_type,
+                message_type_name,
+                dest_pubkey
+            );
+            let params = SendMessageParams::new()
+                .direct_public_key(*dest_pubkey)
+                .with_discovery(true)
+                .finish();
+            let response = try_or_print!(
+                outbound_messaging
+                    .send_encoded_message(params, message_type, payload)
+                    .await
+            );
+            match response.resolve().await {
+                Ok(send_states) => {
+                    if send_states.wait_single().await {
+                        println!("📨 Message sent");
+                    } else {
+                        println!("💀 Message failed to send");
+                    }
+                },
+                Err(err) => {
+                    println!("💀 Message failed to send: {}", err);
+                },
+            }
+        });
+    }
+
+    /// Set or reset the log level for a log target. If no target is given, the current overrides are listed.
+    pub fn outbound_log(&self, query: OutboundAuditQuery) {
+        if self.outbound_audit_log.capacity() == 0 {
+            println!("The outbound audit log is disabled");
+            return;
+        }
+        let entries = self.outbound_audit_log.query(&query);
+        if entries.is_empty() {
+            println!("No matching outbound messages");
+            return;
+        }
+
+        let mut table = Table::new();
+        table.set_titles(vec!["Time", "Tag", "Destination", "Strategy", "Type", "Size", "Result"]);
+        for entry in &entries {
+            table.add_row(row![
+                entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                entry.info.tag,
+                entry.info.destination_node_id.short_str(),
+                entry.info.strategy,
+                entry.info.dht_message_type,
+                entry.info.size,
+                entry
+                    .failure
+                    .map(|reason| format!("Failed: {}", reason))
+                    .unwrap_or_else(|| "Sent".to_string()),
+            ]);
+        }
+        table.print_std();
+        println!("{} message(s)", entries.len());
+    }
+
+    pub fn pipeline_errors(&self, limit: usize) {
+        if !self.pipeline_error_log.is_enabled() {
+            println!("The pipeline error log is disabled. Set pipeline_error_log_capacity in the config to enable it.");
+            return;
+        }
+        let records = self.pipeline_error_log.records(Some(limit));
+        if records.is_empty() {
+            println!("No pipeline errors recorded");
+            return;
+        }
+
+        for record in &records {
+            println!("{}", record);
+        }
+        println!(
+            "{} of {} record(s) (capacity {})",
+            records.len(),
+            self.pipeline_error_log.len(),
+            self.pipeline_error_log.capacity()
+        );
+    }
+
+    pub fn metrics_history(&self, period: Duration) {
+        let store = match self.metrics_snapshots.as_ref() {
+            Some(store) => store,
+            None => {
+                println!("Metrics snapshots are disabled. Set metrics_snapshot_interval in the config to enable them.");
+                return;
+            },
+        };
+        let since = chrono::Duration::from_std(period)
+            .ok()
+            .and_then(|period| Utc::now().checked_sub_signed(period));
+        let mut snapshots = try_or_print!(store.snapshots(None));
+        if let Some(since) = since {
+            snapshots.retain(|snapshot| snapshot.timestamp >= since);
+        }
+        if snapshots.is_empty() {
+            println!("No metrics snapshots in the given period");
+            return;
+        }
+
+        for snapshot in snapshots.iter().rev() {
+            println!("{}", snapshot);
+        }
+        println!("{} of {} snapshot(s)", snapshots.len(), store.len());
+    }
+
+    pub fn log_level(&self, target_level: Option<(String, Option<LevelFilter>)>) {
+        match target_level {
+            Some((target, Some(level))) => {
+                try_or_print!(logging::set_log_target_level(&target, level));
+                println!("Log level for '{}' set to {}", target, level);
+            },
+            Some((target, None)) => {
+                if try_or_print!(logging::reset_log_target_level(&target)) {
+                    println!("Log level for '{}' reset", target);
+                } else {
+                    println!("No log level override for '{}'", target);
+                }
+            },
+            None => {
+                let target_levels = logging::log_target_levels();
+                if target_levels.is_empty() {
+                    println!("No log level overrides");
+                    return;
+                }
+                let mut table = Table::new();
+                table.set_titles(vec!["Target", "Level"]);
+                for (target, level) in target_levels {
+                    table.add_row(row![target, level]);
+                }
+                table.print_std();
+            },
+        }
+    }
+
+    pub fn log_redaction(&self, enabled: Option<bool>) {
+        if let Some(enabled) = enabled {
+            logging::set_log_redaction(enabled);
+        }
+        if logging::is_log_redaction_enabled() {
+            println!("Log redaction is enabled");
+        } else {
+            println!("Log redaction is disabled");
+        }
+    }
+
     pub fn get_peer(&self, node_id: NodeId) {
         let peer_manager = self.peer_manager.clone();
 
@@ -547,6 +740,38 @@ impl CommandHandler {
         });
     }
 
+    pub fn forgive_peer(&self, node_id: NodeId) {
+        let mut connectivity = self.connectivity.clone();
+        self.executor.spawn(async move {
+            match connectivity.forgive(node_id).await {
+                Ok(_) => println!("Peer was forgiven"),
+                Err(ConnectivityError::PeerManagerError(err)) if err.is_peer_not_found() => {
+                    println!("Peer not found in base node");
+                },
+                Err(err) => {
+                    println!("Failed to forgive peer: {:?}", err);
+                    error!(target: LOG_TARGET, "Could not forgive peer: {:?}", err);
+                },
+            }
+        });
+    }
+
+    pub fn set_trust_level(&self, node_id: NodeId, trust_level: PeerTrustLevel) {
+        let peer_manager = self.peer_manager.clone();
+        self.executor.spawn(async move {
+            match peer_manager.set_trust_level(&node_id, trust_level).await {
+                Ok(prev) => println!("Peer trust level changed from {} to {}", prev, trust_level),
+                Err(err) if err.is_peer_not_found() => {
+                    println!("Peer not found in base node");
+                },
+                Err(err) => {
+                    println!("Failed to set peer trust level: {:?}", err);
+                    error!(target: LOG_TARGET, "Could not set peer trust level: {:?}", err);
+                },
+            }
+        });
+    }
+
     pub fn unban_all_peers(&self) {
         let peer_manager = self.peer_manager.clone();
         self.executor.spawn(async move {
@@ -593,6 +818,39 @@ impl CommandHandler {
         });
     }
 
+    pub fn export_ban_list(&self, filename: String, sign: bool) {
+        let peer_manager = self.peer_manager.clone();
+        let node_identity = self.base_node_identity.clone();
+        self.executor.spawn(async move {
+            let mut ban_list = try_or_print!(peer_manager.export_ban_list().await);
+            if sign {
+                try_or_print!(ban_list.sign(&node_identity));
+            }
+            let json = try_or_print!(ban_list.to_json());
+            try_or_print!(fs::write(&filename, json));
+            println!(
+                "Exported {} banned peer(s) to {}{}",
+                ban_list.entries.len(),
+                filename,
+                if sign { " (signed)" } else { "" }
+            );
+        });
+    }
+
+    pub fn import_ban_list(&self, filename: String, publisher: Option<CommsPublicKey>) {
+        let peer_manager = self.peer_manager.clone();
+        self.executor.spawn(async move {
+            let json = try_or_print!(fs::read_to_string(&filename));
+            let ban_list = try_or_print!(BanList::from_json(&json));
+            let trusted_publishers = publisher.into_iter().collect::<Vec<_>>();
+            let result = try_or_print!(peer_manager.import_ban_list(&ban_list, &trusted_publishers).await);
+            println!(
+                "Imported ban list: {} peer(s) added, {} updated, {} skipped",
+                result.num_added, result.num_updated, result.num_skipped
+            );
+        });
+    }
+
     /// Function to process the list-connections command
     pub fn list_connections(&self) {
         let mut connectivity = self.connectivity.clone();
@@ -1027,6 +1285,25 @@ async fn fetch_banned_peers(pm: &PeerManager) -> Result<Vec<Peer>, PeerManagerEr
     pm.perform_query(query).await
 }
 
+/// Returns the number of connected peers for each user agent
+async fn fetch_peer_version_distribution(
+    pm: &PeerManager,
+    conns: &[PeerConnection],
+) -> Result<BTreeMap<String, usize>, PeerManagerError>
+{
+    let mut versions = BTreeMap::new();
+    for conn in conns {
+        let peer = pm.find_by_node_id(conn.peer_node_id()).await?;
+        let user_agent = if peer.user_agent.is_empty() {
+            "unknown".to_string()
+        } else {
+            peer.user_agent
+        };
+        *versions.entry(user_agent).or_insert(0) += 1;
+    }
+    Ok(versions)
+}
+
 pub enum Format {
     Json,
     Text,
@@ -68,11 +68,17 @@
 /// `list-peers` - Lists information about peers known by this base node
 /// `ban-peer` - Bans a peer
 /// `unban-peer` - Removes a ban for a peer
+/// `forgive-peer` - Clears misbehaviour and failed dial penalties for a peer and removes any ban
+/// `set-trust-level` - Sets the trust level (untrusted, normal, trusted or seed) of a peer
 /// `list-connections` - Lists active connections to this Base Node
 /// `list-headers` - Lists header information. Either the first header height and the last header height needs to be
 /// specified, or the amount of headers from the top `check-db` - Checks the blockchain database for missing blocks and
 /// headers `calc-timing` - Calculates the time average time taken to mine a given range of blocks
 /// `discover-peer` - Attempts to discover a peer on the network, a public key or emoji id needs to be specified
+/// `send-message` - Sends a hex-encoded domain message of the given message type directly to a peer
+/// `pipeline-errors` - Lists diagnostic records of recent inbound messages that failed in the DHT pipeline
+/// `metrics-history` - Lists the periodic snapshots of key metrics taken over the given number of hours
+/// `log-redaction` - Enables or disables redaction of node ids, public keys and addresses in log messages
 /// `get-block` - Retrieves a block, the height of the block needs to be specified
 /// `get-mempool-stats` - Displays information about the mempool
 /// `get-mempool-state` - Displays state information for the mempool
@@ -113,7 +119,7 @@ use tari_app_utilities::{
     initialization::init_configuration,
     utilities::{setup_runtime, ExitCodes},
 };
-use tari_common::{configuration::bootstrap::ApplicationType, ConfigBootstrap, GlobalConfig};
+use tari_common::{configuration::bootstrap::ApplicationType, logging, ConfigBootstrap, GlobalConfig};
 use tari_comms::peer_manager::PeerFeatures;
 use tari_shutdown::{Shutdown, ShutdownSignal};
 use tokio::{runtime, task, time};
@@ -136,6 +142,7 @@ fn main() {
 
 fn main_inner() -> Result<(), ExitCodes> {
     let (bootstrap, node_config, _) = init_configuration(ApplicationType::BaseNode)?;
+    logging::set_log_redaction(node_config.log_redaction);
 
     debug!(target: LOG_TARGET, "Using configuration: {:?}", node_config);
 
@@ -40,6 +40,8 @@ use tari_app_utilities::utilities::{
     parse_emoji_id_or_public_key,
     parse_emoji_id_or_public_key_or_node_id,
 };
+use tari_comms::peer_manager::PeerTrustLevel;
+use tari_comms_dht::outbound::OutboundAuditQuery;
 use tari_core::{
     crypto::tari_utilities::hex::from_hex,
     proof_of_work::PowAlgorithm,
@@ -48,6 +50,13 @@ use tari_core::{
 };
 use tari_shutdown::Shutdown;
 
+/// The number of entries listed by the outbound-log command if no number is given
+const DEFAULT_OUTBOUND_LOG_LIMIT: usize = 50;
+/// The number of records listed by the pipeline-errors command if no number is given
+const DEFAULT_PIPELINE_ERRORS_LIMIT: usize = 10;
+/// The number of hours of metrics snapshots listed by the metrics-history command if no number is given
+const DEFAULT_METRICS_HISTORY_HOURS: u64 = 12;
+
 /// Enum representing commands used by the basenode
 #[derive(Clone, Copy, PartialEq, Debug, Display, EnumIter, EnumString)]
 #[strum(serialize_all = "kebab_case")]
@@ -64,7 +73,11 @@ pub enum BaseNodeCommand {
     BanPeer,
     UnbanPeer,
     UnbanAllPeers,
+    ForgivePeer,
+    SetTrustLevel,
     ListBannedPeers,
+    ExportBanList,
+    ImportBanList,
     ListConnections,
     ListHeaders,
     CheckDb,
@@ -72,6 +85,12 @@ pub enum BaseNodeCommand {
     HeaderStats,
     CalcTiming,
     DiscoverPeer,
+    SendMessage,
+    OutboundLog,
+    PipelineErrors,
+    MetricsHistory,
+    LogLevel,
+    LogRedaction,
     GetBlock,
     SearchUtxo,
     SearchKernel,
@@ -190,6 +209,24 @@ impl Parser {
             DiscoverPeer => {
                 self.process_discover_peer(args);
             },
+            SendMessage => {
+                self.process_send_message(args);
+            },
+            OutboundLog => {
+                self.process_outbound_log(args);
+            },
+            PipelineErrors => {
+                self.process_pipeline_errors(args);
+            },
+            MetricsHistory => {
+                self.process_metrics_history(args);
+            },
+            LogLevel => {
+                self.process_log_level(args);
+            },
+            LogRedaction => {
+                self.process_log_redaction(args);
+            },
             GetPeer => {
                 self.process_get_peer(args);
             },
@@ -220,9 +257,21 @@ impl Parser {
             UnbanAllPeers => {
                 self.command_handler.unban_all_peers();
             },
+            ForgivePeer => {
+                self.process_forgive_peer(args);
+            },
+            SetTrustLevel => {
+                self.process_set_trust_level(args);
+            },
             ListBannedPeers => {
                 self.command_handler.list_banned_peers();
             },
+            ExportBanList => {
+                self.process_export_ban_list(args);
+            },
+            ImportBanList => {
+                self.process_import_ban_list(args);
+            },
             ListConnections => {
                 self.command_handler.list_connections();
             },
@@ -288,6 +337,51 @@ impl Parser {
             DiscoverPeer => {
                 println!("Attempt to discover a peer on the Tari network");
             },
+            SendMessage => {
+                println!("Send an arbitrary domain message directly to a peer. Intended for protocol debugging.");
+                println!(
+                    "Usage: {} [public key or emoji id] [message type number] [hex payload]",
+                    help_for
+                );
+            },
+            OutboundLog => {
+                println!("Lists the most recent outbound DHT messages sent by this node and their send results");
+                println!(
+                    "Usage: {} (number of messages) (failed) (NodeId|PublicKey|EmojiId)",
+                    help_for
+                );
+                println!("e.g. {} 20 failed", help_for);
+            },
+            PipelineErrors => {
+                println!(
+                    "Lists diagnostic records (header fields, sizes, timing, error chain and peer) of the most recent \
+                     inbound messages that failed in the DHT pipeline. Records are only kept if \
+                     pipeline_error_log_capacity is set in the config."
+                );
+                println!("Usage: {} (number of records)", help_for);
+            },
+            MetricsHistory => {
+                println!(
+                    "Lists the periodic snapshots of key metrics (connections, peers, SAF occupancy, bandwidth and \
+                     error counts) taken over the given number of hours, oldest first."
+                );
+                println!("Usage: {} (number of hours)", help_for);
+            },
+            LogLevel => {
+                println!("Set the log level for a log target at runtime, or list the current overrides");
+                println!(
+                    "Usage: {} [log target] [off|error|warn|info|debug|trace|reset]",
+                    help_for
+                );
+                println!("e.g. {} comms::dht::store_forward debug", help_for);
+            },
+            LogRedaction => {
+                println!(
+                    "Enable or disable redaction of node ids, public keys and addresses in log messages, or show \
+                     whether redaction is enabled"
+                );
+                println!("Usage: {} [on|off]", help_for);
+            },
             GetPeer => {
                 println!("Get all available info about peer");
             },
@@ -311,9 +405,36 @@ impl Parser {
             UnbanAllPeers => {
                 println!("Unbans all peers");
             },
+            ForgivePeer => {
+                println!("Clears misbehaviour and failed dial penalties for a peer and removes any ban");
+                println!("Usage: {} [hex public key or emoji id]", help_for);
+            },
+            SetTrustLevel => {
+                println!("Sets the level of trust this node places in a peer");
+                println!(
+                    "Usage: {} [hex public key or emoji id] [untrusted|normal|trusted|seed]",
+                    help_for
+                );
+                println!(
+                    "Trusted and seed peers are exempt from flood bans. Untrusted peers cannot send cleartext \
+                     messages and their messages are not stored for store and forward."
+                );
+            },
             ListBannedPeers => {
                 println!("Lists peers that have been banned by the node or wallet");
             },
+            ExportBanList => {
+                println!("Exports the current ban list to a JSON file, optionally signed by this node");
+                println!("Usage: {} [file name] (sign)", help_for);
+            },
+            ImportBanList => {
+                println!(
+                    "Imports a ban list JSON file and merges it with the local ban list. A signed list is only \
+                     imported if it was signed by the given publisher. If no publisher is given, only unsigned lists \
+                     are imported."
+                );
+                println!("Usage: {} [file name] (publisher hex public key or emoji id)", help_for);
+            },
             CheckDb => {
                 println!("Checks the blockchain database for missing blocks and headers");
             },
@@ -495,6 +616,135 @@ impl Parser {
         self.command_handler.discover_peer(dest_pubkey)
     }
 
+    fn process_send_message<'a, I: Iterator<Item = &'a str>>(&mut self, mut args: I) {
+        let usage = "send-message [hex public key or emoji id] [message type number] [hex payload]";
+        let dest_pubkey = match args.next().and_then(parse_emoji_id_or_public_key) {
+            Some(v) => Box::new(v),
+            None => {
+                println!("Please enter a valid destination public key or emoji id");
+                println!("{}", usage);
+                return;
+            },
+        };
+        let message_type = match args.next().map(i32::from_str) {
+            Some(Ok(v)) => v,
+            _ => {
+                println!("Please enter a valid message type number");
+                println!("{}", usage);
+                return;
+            },
+        };
+        let payload = match args.next().map(from_hex) {
+            Some(Ok(v)) => v,
+            _ => {
+                println!("Please enter a valid hex payload");
+                println!("{}", usage);
+                return;
+            },
+        };
+
+        self.command_handler.send_message(dest_pubkey, message_type, payload)
+    }
+
+    fn process_outbound_log<'a, I: Iterator<Item = &'a str>>(&mut self, args: I) {
+        let mut query = OutboundAuditQuery::new().with_limit(DEFAULT_OUTBOUND_LOG_LIMIT);
+        for arg in args {
+            if let Ok(limit) = usize::from_str(arg) {
+                query = query.with_limit(limit);
+                continue;
+            }
+            if arg == "failed" {
+                query = query.failed_only();
+                continue;
+            }
+            match parse_emoji_id_or_public_key_or_node_id(arg).map(either_to_node_id) {
+                Some(node_id) => {
+                    query = query.with_destination(node_id);
+                },
+                None => {
+                    println!("Unrecognised argument '{}'", arg);
+                    self.print_help(BaseNodeCommand::OutboundLog);
+                    return;
+                },
+            }
+        }
+
+        self.command_handler.outbound_log(query)
+    }
+
+    fn process_pipeline_errors<'a, I: Iterator<Item = &'a str>>(&mut self, mut args: I) {
+        let limit = match args.next().map(usize::from_str) {
+            Some(Ok(limit)) => limit,
+            Some(Err(_)) => {
+                println!("Please enter a valid number of records");
+                self.print_help(BaseNodeCommand::PipelineErrors);
+                return;
+            },
+            None => DEFAULT_PIPELINE_ERRORS_LIMIT,
+        };
+
+        self.command_handler.pipeline_errors(limit)
+    }
+
+    fn process_metrics_history<'a, I: Iterator<Item = &'a str>>(&mut self, mut args: I) {
+        let hours = match args.next().map(u64::from_str) {
+            Some(Ok(hours)) => hours,
+            Some(Err(_)) => {
+                println!("Please enter a valid number of hours");
+                self.print_help(BaseNodeCommand::MetricsHistory);
+                return;
+            },
+            None => DEFAULT_METRICS_HISTORY_HOURS,
+        };
+
+        self.command_handler
+            .metrics_history(Duration::from_secs(hours.saturating_mul(60 * 60)))
+    }
+
+    fn process_log_level<'a, I: Iterator<Item = &'a str>>(&mut self, mut args: I) {
+        let usage = "log-level [log target] [off|error|warn|info|debug|trace|reset]";
+        let target = match args.next() {
+            Some(t) => t.to_string(),
+            None => {
+                self.command_handler.log_level(None);
+                return;
+            },
+        };
+        let level = match args.next() {
+            Some("reset") => None,
+            Some(l) => match LevelFilter::from_str(l) {
+                Ok(l) => Some(l),
+                Err(_) => {
+                    println!("Please enter a valid log level");
+                    println!("{}", usage);
+                    return;
+                },
+            },
+            None => {
+                println!("Please enter a log level");
+                println!("{}", usage);
+                return;
+            },
+        };
+
+        self.command_handler.log_level(Some((target, level)))
+    }
+
+    fn process_log_redaction<'a, I: Iterator<Item = &'a str>>(&mut self, mut args: I) {
+        let enabled = match args.next() {
+            Some("on") => Some(true),
+            Some("off") => Some(false),
+            Some(_) => {
+                println!("Please enter 'on' or 'off'");
+                println!("log-redaction [on|off]");
+                return;
+            },
+            None => None,
+        };
+
+        self.command_handler.log_redaction(enabled)
+    }
+
     fn process_get_peer<'a, I: Iterator<Item = &'a str>>(&mut self, mut args: I) {
         let node_id = match args
             .next()
@@ -563,6 +813,94 @@ impl Parser {
         self.command_handler.ban_peer(node_id, duration, must_ban)
     }
 
+    /// Function to process the forgive-peer command
+    fn process_forgive_peer<'a, I: Iterator<Item = &'a str>>(&mut self, mut args: I) {
+        let node_id = match args
+            .next()
+            .and_then(parse_emoji_id_or_public_key_or_node_id)
+            .map(either_to_node_id)
+        {
+            Some(v) => v,
+            None => {
+                println!("Please enter a valid destination public key or emoji id");
+                println!("forgive-peer [hex public key or emoji id]");
+                return;
+            },
+        };
+
+        self.command_handler.forgive_peer(node_id)
+    }
+
+    /// Function to process the set-trust-level command
+    fn process_set_trust_level<'a, I: Iterator<Item = &'a str>>(&mut self, mut args: I) {
+        let node_id = match args
+            .next()
+            .and_then(parse_emoji_id_or_public_key_or_node_id)
+            .map(either_to_node_id)
+        {
+            Some(v) => v,
+            None => {
+                println!("Please enter a valid destination public key or emoji id");
+                println!("set-trust-level [hex public key or emoji id] [untrusted|normal|trusted|seed]");
+                return;
+            },
+        };
+
+        let trust_level = match args.next().map(|s| s.parse::<PeerTrustLevel>()) {
+            Some(Ok(trust_level)) => trust_level,
+            Some(Err(err)) => {
+                println!("{}", err);
+                return;
+            },
+            None => {
+                println!("Please enter a trust level");
+                println!("set-trust-level [hex public key or emoji id] [untrusted|normal|trusted|seed]");
+                return;
+            },
+        };
+
+        self.command_handler.set_trust_level(node_id, trust_level)
+    }
+
+    /// Function to process the export-ban-list command
+    fn process_export_ban_list<'a, I: Iterator<Item = &'a str>>(&self, mut args: I) {
+        let filename = match args.next() {
+            Some(filename) => filename.to_string(),
+            None => {
+                println!("Please enter a file name");
+                println!("export-ban-list [file name] (sign)");
+                return;
+            },
+        };
+        let sign = args.next().map(|s| s == "sign").unwrap_or(false);
+        self.command_handler.export_ban_list(filename, sign)
+    }
+
+    /// Function to process the import-ban-list command
+    fn process_import_ban_list<'a, I: Iterator<Item = &'a str>>(&self, mut args: I) {
+        let usage = "import-ban-list [file name] (publisher hex public key or emoji id)";
+        let filename = match args.next() {
+            Some(filename) => filename.to_string(),
+            None => {
+                println!("Please enter a file name");
+                println!("{}", usage);
+                return;
+            },
+        };
+        let publisher = match args.next() {
+            Some(arg) => match parse_emoji_id_or_public_key(arg) {
+                Some(publisher) => Some(publisher),
+                None => {
+                    println!("Please enter a valid publisher public key or emoji id");
+                    println!("{}", usage);
+                    return;
+                },
+            },
+            None => None,
+        };
+        self.command_handler.import_ban_list(filename, publisher)
+    }
+
     /// Function to process the list-headers command
     fn process_list_headers<'a, I: Iterator<Item = &'a str>>(&self, mut args: I) {
         let start = args.next().map(u64::from_str).map(Result::ok).flatten();
@@ -0,0 +1,82 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::{io, time::Duration};
+use tari_comms::{
+    connectivity::ConnectivityError,
+    peer_manager::{NodeIdentityError, PeerManagerError},
+    CommsBuilderError,
+};
+use tari_comms_dht::{
+    outbound::DhtOutboundError,
+    store_forward::StoreAndForwardError,
+    DhtActorError,
+    DhtDiscoveryError,
+    DhtInitializationError,
+};
+use tari_p2p::initialization::CommsInitializationError;
+use tari_storage::lmdb_store::LMDBError;
+use thiserror::Error;
+
+/// Errors that prevent the conformance harness from starting
+#[derive(Debug, Error)]
+pub enum ConformanceError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("LMDB error: {0}")]
+    LMDBError(#[from] LMDBError),
+    #[error("Node identity error: {0}")]
+    NodeIdentityError(#[from] NodeIdentityError),
+    #[error("Comms builder error: {0}")]
+    CommsBuilderError(#[from] CommsBuilderError),
+    #[error("DHT initialization error: {0}")]
+    DhtInitializationError(#[from] DhtInitializationError),
+    #[error("Comms initialization error: {0}")]
+    CommsInitializationError(#[from] CommsInitializationError),
+    #[error("Peer manager error: {0}")]
+    PeerManagerError(#[from] PeerManagerError),
+    #[error("Peer database was not found in the datastore")]
+    PeerDatabaseNotFound,
+}
+
+/// Errors that cause a single conformance check to fail
+#[derive(Debug, Error)]
+pub enum CheckError {
+    #[error("Check timed out after {0:.2?}")]
+    Timeout(Duration),
+    #[error("Connectivity error: {0}")]
+    ConnectivityError(#[from] ConnectivityError),
+    #[error("DHT actor error: {0}")]
+    DhtActorError(#[from] DhtActorError),
+    #[error("Discovery error: {0}")]
+    DiscoveryError(#[from] DhtDiscoveryError),
+    #[error("Store and forward error: {0}")]
+    StoreAndForwardError(#[from] StoreAndForwardError),
+    #[error("Outbound error: {0}")]
+    OutboundError(#[from] DhtOutboundError),
+    #[error("Failed to send message: {0}")]
+    SendFailed(String),
+    #[error("Peer manager error: {0}")]
+    PeerManagerError(#[from] PeerManagerError),
+    #[error("Unexpected response: {0}")]
+    Unexpected(String),
+}
@@ -0,0 +1,300 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    error::{CheckError, ConformanceError},
+    node::ConformanceNode,
+    report::{Check, CheckOutcome, ConformanceReport},
+};
+use futures::StreamExt;
+use log::*;
+use rand::{rngs::OsRng, RngCore};
+use std::time::{Duration, Instant};
+use tari_common::Network;
+use tari_comms::{
+    multiaddr::Multiaddr,
+    peer_manager::{NodeId, PeerFeatures},
+    types::CommsPublicKey,
+};
+use tari_comms_dht::{domain_message::OutboundDomainMessage, envelope::NodeDestination};
+use tari_p2p::{
+    seed_peer::SeedPeer,
+    services::liveness::{Metadata, PingPong, PingPongMessage},
+    tari_message::TariMessageType,
+};
+use tari_shutdown::ShutdownSignal;
+use tokio::time;
+
+const LOG_TARGET: &str = "comms::conformance::harness";
+
+/// How long to wait after sending a join before checking that the target is still connected
+const JOIN_SETTLE_PERIOD: Duration = Duration::from_secs(2);
+/// How often to poll the store and forward provider stats for a response from the target
+const SAF_RESPONSE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone)]
+pub struct ConformanceConfig {
+    /// The node to test
+    pub target: SeedPeer,
+    /// The network that the target node is running on
+    pub network: Network,
+    /// The address that the harness node listens on
+    pub listener_address: Multiaddr,
+    /// The address that the harness node advertises to the target
+    pub public_address: Multiaddr,
+    /// The maximum time that each check may take
+    pub check_timeout: Duration,
+    /// Allow loopback and other test addresses. Required when testing a node running locally.
+    pub allow_test_addresses: bool,
+}
+
+/// Runs the protocol conformance checks against a target node.
+pub struct ConformanceHarness {
+    config: ConformanceConfig,
+    node: ConformanceNode,
+    target_node_id: NodeId,
+}
+
+impl ConformanceHarness {
+    /// Start the harness node and add the target to its peer list. No connection is made to the target until `run` is
+    /// called.
+    pub async fn start(config: ConformanceConfig, shutdown_signal: ShutdownSignal) -> Result<Self, ConformanceError> {
+        let node = ConformanceNode::start(&config, shutdown_signal).await?;
+        let target_node_id = config.target.get_node_id();
+        node.comms.peer_manager().add_peer(config.target.clone().into()).await?;
+        debug!(
+            target: LOG_TARGET,
+            "Conformance node '{}' started. Target is '{}'",
+            node.comms.node_identity().node_id(),
+            target_node_id
+        );
+
+        Ok(Self {
+            config,
+            node,
+            target_node_id,
+        })
+    }
+
+    /// Run all checks in order and return the report. If the identity exchange fails, the remaining checks are
+    /// skipped.
+    pub async fn run(mut self) -> ConformanceReport {
+        let mut report = ConformanceReport::new(self.target_public_key().clone());
+        for check in Check::ALL.iter().copied() {
+            if check != Check::IdentityExchange && !report.has_passed(Check::IdentityExchange) {
+                report.push(
+                    check,
+                    CheckOutcome::Skipped("Identity exchange did not pass".to_string()),
+                    Duration::from_secs(0),
+                );
+                continue;
+            }
+
+            info!(target: LOG_TARGET, "Running check: {}", check);
+            let timer = Instant::now();
+            let check_timeout = self.config.check_timeout;
+            let outcome = match time::timeout(check_timeout, self.run_check(check)).await {
+                Ok(Ok(outcome)) => outcome,
+                Ok(Err(err)) => CheckOutcome::Failed(err.to_string()),
+                Err(_) => CheckOutcome::Failed(CheckError::Timeout(check_timeout).to_string()),
+            };
+            let elapsed = timer.elapsed();
+            info!(
+                target: LOG_TARGET,
+                "Check '{}' completed in {:.2?}: {:?}", check, elapsed, outcome
+            );
+            report.push(check, outcome, elapsed);
+        }
+
+        report
+    }
+
+    fn target_public_key(&self) -> &CommsPublicKey {
+        &self.config.target.public_key
+    }
+
+    async fn run_check(&mut self, check: Check) -> Result<CheckOutcome, CheckError> {
+        use Check::*;
+        match check {
+            IdentityExchange => self.check_identity_exchange().await,
+            Join => self.check_join().await,
+            Discovery => self.check_discovery().await,
+            StoreAndForward => self.check_store_and_forward().await,
+            Liveness => self.check_liveness().await,
+        }
+    }
+
+    async fn check_identity_exchange(&mut self) -> Result<CheckOutcome, CheckError> {
+        let conn = self
+            .node
+            .comms
+            .connectivity()
+            .dial_peer(self.target_node_id.clone())
+            .await?;
+        let peer = self
+            .node
+            .comms
+            .peer_manager()
+            .find_by_node_id(conn.peer_node_id())
+            .await?;
+        if peer.public_key != *self.target_public_key() {
+            return Err(CheckError::Unexpected(format!(
+                "Target identified with public key '{}'",
+                peer.public_key
+            )));
+        }
+
+        Ok(CheckOutcome::Passed(format!(
+            "user agent '{}', features {:?}, protocol versions {:#b}",
+            peer.user_agent, peer.features, peer.protocol_versions
+        )))
+    }
+
+    async fn check_join(&mut self) -> Result<CheckOutcome, CheckError> {
+        self.node
+            .dht
+            .dht_requester()
+            .send_join_to(vec![self.target_node_id.clone()])
+            .await?;
+        // A non-conforming node will typically disconnect (and may ban) a peer that sends it an invalid join
+        time::delay_for(JOIN_SETTLE_PERIOD).await;
+        let conn = self
+            .node
+            .comms
+            .connectivity()
+            .get_connection(self.target_node_id.clone())
+            .await?;
+
+        match conn {
+            Some(conn) if conn.is_connected() => {
+                Ok(CheckOutcome::Passed("Target remained connected after join".to_string()))
+            },
+            _ => Ok(CheckOutcome::Failed("Target disconnected after join".to_string())),
+        }
+    }
+
+    async fn check_discovery(&mut self) -> Result<CheckOutcome, CheckError> {
+        let public_key = self.target_public_key().clone();
+        let peer = self
+            .node
+            .dht
+            .discovery_service_requester()
+            .discover_peer(
+                Box::new(public_key.clone()),
+                NodeDestination::PublicKey(Box::new(public_key.clone())),
+            )
+            .await?;
+
+        if peer.public_key != public_key {
+            return Err(CheckError::Unexpected(format!(
+                "Discovery returned peer '{}'",
+                peer.public_key
+            )));
+        }
+
+        Ok(CheckOutcome::Passed(format!(
+            "Discovery response contained {} address(es)",
+            peer.addresses.len()
+        )))
+    }
+
+    async fn check_store_and_forward(&mut self) -> Result<CheckOutcome, CheckError> {
+        let peer = self
+            .node
+            .comms
+            .peer_manager()
+            .find_by_node_id(&self.target_node_id)
+            .await?;
+        if !peer.features.contains(PeerFeatures::DHT_STORE_FORWARD) {
+            return Ok(CheckOutcome::Skipped(
+                "Target does not advertise the store and forward feature".to_string(),
+            ));
+        }
+
+        let mut saf_requester = self.node.dht.store_and_forward_requester();
+        let num_responses_before = saf_requester
+            .get_provider_stats()
+            .await?
+            .get(&self.target_node_id)
+            .map(|stats| stats.num_responses)
+            .unwrap_or(0);
+
+        saf_requester
+            .request_saf_messages_from_peer(self.target_node_id.clone())
+            .await?;
+
+        // A conforming provider always responds, even if it has no messages for us
+        loop {
+            time::delay_for(SAF_RESPONSE_POLL_INTERVAL).await;
+            let provider_stats = saf_requester.get_provider_stats().await?;
+            if let Some(stats) = provider_stats.get(&self.target_node_id) {
+                if stats.num_responses > num_responses_before {
+                    return Ok(CheckOutcome::Passed(format!(
+                        "Target responded to stored message request ({} new, {} invalid message(s))",
+                        stats.num_new_messages, stats.num_invalid_messages
+                    )));
+                }
+            }
+        }
+    }
+
+    async fn check_liveness(&mut self) -> Result<CheckOutcome, CheckError> {
+        let nonce = OsRng.next_u64();
+        let ping = PingPongMessage::new(PingPong::Ping, nonce, Metadata::new());
+        let timer = Instant::now();
+        let send_states = self
+            .node
+            .dht
+            .outbound_requester()
+            .send_direct(
+                self.target_public_key().clone(),
+                OutboundDomainMessage::new(TariMessageType::PingPong, ping),
+            )
+            .await?
+            .resolve()
+            .await
+            .map_err(|err| CheckError::SendFailed(err.to_string()))?;
+        if !send_states.wait_single().await {
+            return Err(CheckError::SendFailed("Ping was not sent to the target".to_string()));
+        }
+
+        while let Some(msg) = self.node.inbound_rx.next().await {
+            if msg.source_peer.node_id != self.target_node_id ||
+                msg.message_header.message_type != TariMessageType::PingPong as i32
+            {
+                continue;
+            }
+
+            let pong = msg
+                .decode_message::<PingPongMessage>()
+                .map_err(|err| CheckError::Unexpected(format!("Invalid PingPong message: {}", err)))?;
+            if pong.kind() == Some(PingPong::Pong) && pong.nonce == nonce {
+                return Ok(CheckOutcome::Passed(format!(
+                    "Pong received in {:.2?}",
+                    timer.elapsed()
+                )));
+            }
+        }
+
+        Err(CheckError::Unexpected("Inbound message stream ended".to_string()))
+    }
+}
@@ -0,0 +1,48 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Tari comms conformance harness
+//!
+//! Connects to a target node and exercises the core comms and DHT protocols, producing a pass/fail report. This is
+//! useful for validating alternative implementations of the Tari protocols, and for checking that a config change has
+//! not broken a node's ability to take part in the network.
+//!
+//! The following checks are run in order:
+//! - identity exchange: a connection is established to the target and the peer identity is exchanged
+//! - join: a Join message is sent to the target, which should accept it without disconnecting or banning this node
+//! - discovery: the target is asked to discover itself, and should respond with a discovery response
+//! - store and forward: stored messages are requested from the target, which should respond (even if it has no
+//!   messages). Skipped if the target does not advertise the store and forward feature.
+//! - liveness: a ping is sent to the target, which should respond with a pong
+//!
+//! The remaining checks are skipped if the identity exchange fails.
+
+mod error;
+pub use error::{CheckError, ConformanceError};
+
+mod harness;
+pub use harness::{ConformanceConfig, ConformanceHarness};
+
+mod node;
+
+mod report;
+pub use report::{Check, CheckOutcome, CheckResult, ConformanceReport};
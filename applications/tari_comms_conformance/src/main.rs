@@ -0,0 +1,88 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Runs the comms protocol conformance checks against a target node and prints a pass/fail report.
+//!
+//! Example: `tari_comms_conformance --target <public key hex>::/ip4/127.0.0.1/tcp/18189 --allow-test-addresses`
+//!
+//! Exits with status 0 if all checks passed or were skipped, 1 if any check failed and 2 if the harness could not be
+//! started.
+
+use std::{process, time::Duration};
+use structopt::StructOpt;
+use tari_common::Network;
+use tari_comms::multiaddr::Multiaddr;
+use tari_comms_conformance::{ConformanceConfig, ConformanceHarness};
+use tari_p2p::seed_peer::SeedPeer;
+use tari_shutdown::Shutdown;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "tari_comms_conformance", about = "Tari comms protocol conformance harness")]
+struct Opts {
+    /// The node to test, in the form `<public key hex>::<address>`
+    #[structopt(long)]
+    target: SeedPeer,
+    /// The network that the target node is running on
+    #[structopt(long, default_value = "stibbons")]
+    network: Network,
+    /// The address that the harness node listens on
+    #[structopt(long, default_value = "/ip4/0.0.0.0/tcp/18199")]
+    listener_address: Multiaddr,
+    /// The address that the harness node advertises to the target
+    #[structopt(long, default_value = "/ip4/127.0.0.1/tcp/18199")]
+    public_address: Multiaddr,
+    /// The maximum time in seconds that each check may take
+    #[structopt(long, default_value = "30")]
+    timeout: u64,
+    /// Allow loopback and other test addresses. Required when testing a node running locally.
+    #[structopt(long)]
+    allow_test_addresses: bool,
+}
+
+#[tokio_macros::main]
+async fn main() {
+    env_logger::init();
+    let opts = Opts::from_args();
+
+    let config = ConformanceConfig {
+        target: opts.target,
+        network: opts.network,
+        listener_address: opts.listener_address,
+        public_address: opts.public_address,
+        check_timeout: Duration::from_secs(opts.timeout),
+        allow_test_addresses: opts.allow_test_addresses,
+    };
+
+    let mut shutdown = Shutdown::new();
+    let harness = match ConformanceHarness::start(config, shutdown.to_signal()).await {
+        Ok(harness) => harness,
+        Err(err) => {
+            eprintln!("Failed to start conformance harness: {}", err);
+            process::exit(2);
+        },
+    };
+
+    let report = harness.run().await;
+    println!("{}", report);
+    let _ = shutdown.trigger();
+    process::exit(if report.is_pass() { 0 } else { 1 });
+}
@@ -0,0 +1,144 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{error::ConformanceError, harness::ConformanceConfig};
+use futures::channel::mpsc;
+use rand::rngs::OsRng;
+use std::sync::Arc;
+use tari_comms::{
+    peer_manager::{NodeIdentity, PeerFeatures},
+    pipeline,
+    pipeline::SinkService,
+    protocol::messaging::MessagingProtocolExtension,
+    CommsBuilder,
+    CommsNode,
+};
+use tari_comms_dht::{Dht, DhtBuilder, DhtConfig};
+use tari_p2p::{
+    comms_connector::{InboundDomainConnector, PeerMessage},
+    initialization::spawn_comms_using_transport,
+    transport::TransportType,
+};
+use tari_shutdown::ShutdownSignal;
+use tari_storage::{
+    lmdb_store::{LMDBBuilder, LMDBConfig},
+    LMDBWrapper,
+};
+use tempfile::TempDir;
+use tokio::sync::broadcast;
+use tower::ServiceBuilder;
+
+const PEER_DATABASE_NAME: &str = "peerdb";
+
+/// A minimal, ephemeral comms node used to drive the conformance checks. The node has a random identity and keeps its
+/// peer database in a temporary directory that is removed when the node is dropped.
+pub struct ConformanceNode {
+    pub comms: CommsNode,
+    pub dht: Dht,
+    pub inbound_rx: mpsc::Receiver<Arc<PeerMessage>>,
+    _data_dir: TempDir,
+}
+
+impl ConformanceNode {
+    pub async fn start(config: &ConformanceConfig, shutdown_signal: ShutdownSignal) -> Result<Self, ConformanceError> {
+        let data_dir = tempfile::Builder::new().prefix("tari-conformance").tempdir()?;
+        let datastore = LMDBBuilder::new()
+            .set_path(data_dir.path())
+            .set_env_config(LMDBConfig::default())
+            .set_max_number_of_databases(1)
+            .add_database(PEER_DATABASE_NAME, lmdb_zero::db::CREATE)
+            .build()?;
+        let peer_database = datastore
+            .get_handle(PEER_DATABASE_NAME)
+            .ok_or(ConformanceError::PeerDatabaseNotFound)?;
+        let peer_database = LMDBWrapper::new(Arc::new(peer_database));
+
+        let node_identity = Arc::new(NodeIdentity::random(
+            &mut OsRng,
+            config.public_address.clone(),
+            PeerFeatures::COMMUNICATION_NODE,
+        )?);
+
+        let mut builder = CommsBuilder::new()
+            .with_node_identity(node_identity)
+            .with_user_agent(format!("tari/conformance/{}", env!("CARGO_PKG_VERSION")))
+            .with_peer_storage(peer_database, None)
+            .with_shutdown_signal(shutdown_signal)
+            .disable_connection_reaping();
+        if config.allow_test_addresses {
+            builder = builder.allow_test_addresses();
+        }
+        let comms = builder.build()?;
+
+        let (outbound_tx, outbound_rx) = mpsc::channel(10);
+        let dht = DhtBuilder::new(
+            comms.node_identity(),
+            comms.peer_manager(),
+            outbound_tx,
+            comms.connectivity(),
+            comms.shutdown_signal(),
+        )
+        .with_config(DhtConfig {
+            network: config.network.into(),
+            discovery_request_timeout: config.check_timeout,
+            // Stored messages are requested explicitly by the store and forward check
+            saf_auto_request: false,
+            auto_join: false,
+            allow_test_addresses: config.allow_test_addresses,
+            memory_usage_report_interval: None,
+            network_heartbeat_window: None,
+            ..Default::default()
+        })
+        .build()
+        .await?;
+
+        let (inbound_tx, inbound_rx) = mpsc::channel(100);
+        let dht_outbound_layer = dht.outbound_middleware_layer();
+        let (event_sender, _) = broadcast::channel(100);
+        let pipeline = pipeline::Builder::new()
+            .outbound_buffer_size(10)
+            .with_outbound_pipeline(outbound_rx, |sink| {
+                ServiceBuilder::new().layer(dht_outbound_layer).service(sink)
+            })
+            .max_concurrent_inbound_tasks(10)
+            .with_inbound_pipeline(
+                ServiceBuilder::new()
+                    .layer(dht.inbound_middleware_layer())
+                    .service(SinkService::new(InboundDomainConnector::new(inbound_tx))),
+            )
+            .build();
+
+        let comms = comms.add_protocol_extension(MessagingProtocolExtension::new(event_sender, pipeline));
+        let comms = spawn_comms_using_transport(comms, TransportType::Tcp {
+            listener_address: config.listener_address.clone(),
+            tor_socks_config: None,
+        })
+        .await?;
+
+        Ok(Self {
+            comms,
+            dht,
+            inbound_rx,
+            _data_dir: data_dir,
+        })
+    }
+}
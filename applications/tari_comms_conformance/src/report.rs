@@ -0,0 +1,216 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::{fmt, time::Duration};
+use tari_comms::types::CommsPublicKey;
+use tari_utilities::hex::Hex;
+
+/// The protocol checks run by the harness, in the order they are run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    IdentityExchange,
+    Join,
+    Discovery,
+    StoreAndForward,
+    Liveness,
+}
+
+impl Check {
+    pub const ALL: [Check; 5] = [
+        Check::IdentityExchange,
+        Check::Join,
+        Check::Discovery,
+        Check::StoreAndForward,
+        Check::Liveness,
+    ];
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Check::*;
+        match self {
+            IdentityExchange => write!(f, "Identity exchange"),
+            Join => write!(f, "Join"),
+            Discovery => write!(f, "Discovery"),
+            StoreAndForward => write!(f, "Store and forward"),
+            Liveness => write!(f, "Liveness"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOutcome {
+    Passed(String),
+    Failed(String),
+    Skipped(String),
+}
+
+impl CheckOutcome {
+    pub fn is_failed(&self) -> bool {
+        matches!(self, CheckOutcome::Failed(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub check: Check,
+    pub outcome: CheckOutcome,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CheckOutcome::*;
+        match &self.outcome {
+            Passed(detail) => write!(f, "[PASS] {} ({:.2?}): {}", self.check, self.elapsed, detail),
+            Failed(detail) => write!(f, "[FAIL] {} ({:.2?}): {}", self.check, self.elapsed, detail),
+            Skipped(reason) => write!(f, "[SKIP] {}: {}", self.check, reason),
+        }
+    }
+}
+
+/// The results of a conformance run against a single target node
+#[derive(Debug, Clone)]
+pub struct ConformanceReport {
+    target: CommsPublicKey,
+    results: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+    pub fn new(target: CommsPublicKey) -> Self {
+        Self {
+            target,
+            results: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, check: Check, outcome: CheckOutcome, elapsed: Duration) {
+        self.results.push(CheckResult {
+            check,
+            outcome,
+            elapsed,
+        });
+    }
+
+    pub fn target(&self) -> &CommsPublicKey {
+        &self.target
+    }
+
+    pub fn results(&self) -> &[CheckResult] {
+        &self.results
+    }
+
+    /// Returns true if the given check was run and passed
+    pub fn has_passed(&self, check: Check) -> bool {
+        self.results
+            .iter()
+            .any(|r| r.check == check && matches!(r.outcome, CheckOutcome::Passed(_)))
+    }
+
+    /// Returns true if no checks failed. Skipped checks do not cause the run to fail.
+    pub fn is_pass(&self) -> bool {
+        self.results.iter().all(|r| !r.outcome.is_failed())
+    }
+
+    /// Returns the number of (passed, failed, skipped) checks
+    pub fn counts(&self) -> (usize, usize, usize) {
+        self.results
+            .iter()
+            .fold((0, 0, 0), |(passed, failed, skipped), r| match r.outcome {
+                CheckOutcome::Passed(_) => (passed + 1, failed, skipped),
+                CheckOutcome::Failed(_) => (passed, failed + 1, skipped),
+                CheckOutcome::Skipped(_) => (passed, failed, skipped + 1),
+            })
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Conformance report for {}", self.target.to_hex())?;
+        for result in &self.results {
+            writeln!(f, "{}", result)?;
+        }
+        let (passed, failed, skipped) = self.counts();
+        write!(
+            f,
+            "{}: {} passed, {} failed, {} skipped",
+            if self.is_pass() { "PASS" } else { "FAIL" },
+            passed,
+            failed,
+            skipped
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn new_report() -> ConformanceReport {
+        ConformanceReport::new(CommsPublicKey::default())
+    }
+
+    #[test]
+    fn is_pass() {
+        let mut report = new_report();
+        assert!(report.is_pass());
+        report.push(
+            Check::IdentityExchange,
+            CheckOutcome::Passed("ok".into()),
+            Duration::from_millis(10),
+        );
+        report.push(
+            Check::StoreAndForward,
+            CheckOutcome::Skipped("n/a".into()),
+            Duration::from_secs(0),
+        );
+        assert!(report.is_pass());
+        assert!(report.has_passed(Check::IdentityExchange));
+        assert!(!report.has_passed(Check::StoreAndForward));
+        report.push(
+            Check::Liveness,
+            CheckOutcome::Failed("no pong".into()),
+            Duration::from_secs(1),
+        );
+        assert!(!report.is_pass());
+        assert_eq!(report.counts(), (1, 1, 1));
+    }
+
+    #[test]
+    fn display() {
+        let mut report = new_report();
+        report.push(
+            Check::Join,
+            CheckOutcome::Passed("accepted".into()),
+            Duration::from_millis(5),
+        );
+        report.push(
+            Check::Discovery,
+            CheckOutcome::Failed("timed out".into()),
+            Duration::from_secs(1),
+        );
+        let s = report.to_string();
+        assert!(s.contains("[PASS] Join"));
+        assert!(s.contains("[FAIL] Discovery"));
+        assert!(s.ends_with("FAIL: 1 passed, 1 failed, 0 skipped"));
+    }
+}
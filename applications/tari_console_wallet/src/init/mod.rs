@@ -29,7 +29,7 @@ use rand::rngs::OsRng;
 use rpassword::prompt_password_stdout;
 use rustyline::Editor;
 use std::{fs, path::PathBuf, str::FromStr, sync::Arc};
-use tari_app_utilities::utilities::{setup_wallet_transport_type, ExitCodes};
+use tari_app_utilities::utilities::{peer_version_policy, setup_wallet_transport_type, ExitCodes};
 use tari_common::{ConfigBootstrap, GlobalConfig, Network};
 use tari_comms::{
     peer_manager::{Peer, PeerFeatures},
@@ -350,7 +350,9 @@ pub async fn init_wallet(
         datastore_path: config.console_wallet_peer_db_path.clone(),
         peer_database_name: "peers".to_string(),
         max_concurrent_inbound_tasks: 100,
+        max_pending_inbound_per_peer: None,
         outbound_buffer_size: 100,
+        messaging_shutdown_grace_period: config.messaging_shutdown_grace_period,
         // TODO - make this configurable
         dht: DhtConfig {
             database_url: DbConnectionUrl::File(config.data_dir.join("dht-console-wallet.db")),
@@ -367,8 +369,16 @@ pub async fn init_wallet(
         listener_liveness_max_sessions: 0,
         dns_seeds_name_server: DEFAULT_DNS_SEED_RESOLVER.parse().unwrap(),
         peer_seeds: Default::default(),
+        trusted_peers: Default::default(),
         dns_seeds: Default::default(),
         dns_seeds_use_dnssec: true,
+        dial_connect_timeout: config.dial_connect_timeout,
+        noise_handshake_timeout: config.noise_handshake_timeout,
+        identity_exchange_timeout: config.identity_exchange_timeout,
+        peer_version_policy: peer_version_policy(config),
+        dial_backoff: Default::default(),
+        log_target_levels: Default::default(),
+        admin_socket_address: None,
     };
 
     let network = match &config.network {
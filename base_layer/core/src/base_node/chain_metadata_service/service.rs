@@ -82,6 +82,11 @@ impl ChainMetadataService {
         let mut block_event_stream = self.base_node.get_block_event_stream().fuse();
         let mut connectivity_events = self.connectivity.get_event_subscription().fuse();
 
+        log_if_error!(
+            target: LOG_TARGET,
+            "Failed to register the chain metadata liveness key: '{}'",
+            self.liveness.register_metadata_key(MetadataKey::ChainMetadata).await
+        );
         log_if_error!(
             target: LOG_TARGET,
             "Error when updating liveness chain metadata: '{}'",
@@ -26,12 +26,15 @@ use futures::{task::Context, Future, Sink, SinkExt};
 use log::*;
 use std::{pin::Pin, sync::Arc, task::Poll};
 use tari_comms::pipeline::PipelineError;
-use tari_comms_dht::{domain_message::MessageHeader, inbound::DecryptedDhtMessage};
+use tari_comms_dht::{
+    domain_message::{ContentType, MessageHeader},
+    inbound::DecryptedDhtMessage,
+};
 use tower::Service;
 
 const LOG_TARGET: &str = "comms::middleware::inbound_connector";
 /// This service receives DecryptedDhtMessage, deserializes the MessageHeader and
-/// sends a `PeerMessage` on the given sink.
+/// sends a `PeerMessage` on the given sink. Messages with an unrecognised body content type are rejected.
 #[derive(Clone)]
 pub struct InboundDomainConnector<TSink> {
     sink: TSink,
@@ -79,6 +82,10 @@ impl<TSink> InboundDomainConnector<TSink> {
             .decode_part::<MessageHeader>(0)?
             .ok_or_else(|| anyhow!("envelope body did not contain a header"))?;
 
+        if ContentType::from_i32(header.content_type).is_none() {
+            return Err(anyhow!("message has unsupported content type {}", header.content_type));
+        }
+
         let msg_bytes = envelope_body
             .take_part(1)
             .ok_or_else(|| anyhow!("envelope body did not contain a message body"))?;
@@ -138,7 +145,7 @@ mod test {
     use crate::test_utils::{make_dht_inbound_message, make_node_identity};
     use futures::{channel::mpsc, executor::block_on, StreamExt};
     use tari_comms::{message::MessageExt, wrap_in_envelope_body};
-    use tari_comms_dht::domain_message::MessageHeader;
+    use tari_comms_dht::domain_message::{DomainCodecError, JsonCodec, MessageHeader, ProtobufCodec};
     use tower::ServiceExt;
 
     #[tokio_macros::test_basic]
@@ -185,6 +192,42 @@ mod test {
         assert!(rx.try_next().unwrap().is_none());
     }
 
+    #[tokio_macros::test_basic]
+    async fn handle_message_with_content_type() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let header = MessageHeader::new(123).with_content_type(ContentType::Json);
+        let mut msg = wrap_in_envelope_body!(header);
+        // The body is encoded by the JSON codec rather than as a protobuf message
+        msg.push_part(b"\"my message\"".to_vec());
+
+        let inbound_message = make_dht_inbound_message(&make_node_identity(), msg.to_encoded_bytes());
+        let decrypted = DecryptedDhtMessage::succeeded(msg, None, inbound_message);
+        InboundDomainConnector::new(tx).oneshot(decrypted).await.unwrap();
+
+        let peer_message = block_on(rx.next()).unwrap();
+        assert_eq!(peer_message.content_type(), ContentType::Json);
+        let decoded: String = peer_message.decode_message_with(&JsonCodec::new()).unwrap();
+        assert_eq!(decoded, "my message");
+        let err = peer_message
+            .decode_message_with::<String, _>(&ProtobufCodec)
+            .unwrap_err();
+        assert!(matches!(err, DomainCodecError::ContentTypeMismatch { .. }));
+    }
+
+    #[tokio_macros::test_basic]
+    async fn handle_message_fail_unsupported_content_type() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut header = MessageHeader::new(123);
+        header.content_type = 999;
+        let msg = wrap_in_envelope_body!(header, b"my message".to_vec());
+
+        let inbound_message = make_dht_inbound_message(&make_node_identity(), msg.to_encoded_bytes());
+        let decrypted = DecryptedDhtMessage::succeeded(msg, None, inbound_message);
+        InboundDomainConnector::new(tx).oneshot(decrypted).await.unwrap_err();
+
+        assert!(rx.try_next().unwrap().is_none());
+    }
+
     #[tokio_macros::test_basic]
     async fn handle_message_fail_send() {
         // Drop the receiver of the channel, this is the only reason this middleware should return an error
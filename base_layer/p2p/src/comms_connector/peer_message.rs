@@ -25,7 +25,10 @@ use tari_comms::{
     peer_manager::{NodeId, Peer},
     types::CommsPublicKey,
 };
-use tari_comms_dht::{domain_message::MessageHeader, envelope::DhtMessageHeader};
+use tari_comms_dht::{
+    domain_message::{ContentType, DomainCodecError, DomainMessageCodec, MessageHeader},
+    envelope::DhtMessageHeader,
+};
 
 const LOG_TARGET: &str = "comms::dht::requests::inbound";
 
@@ -60,6 +63,24 @@ impl PeerMessage {
         Ok(msg)
     }
 
+    /// Returns the content type of the message body. Unknown content types are rejected by the inbound domain
+    /// connector so this defaults to protobuf if the header content type is not recognised.
+    pub fn content_type(&self) -> ContentType {
+        ContentType::from_i32(self.message_header.content_type).unwrap_or(ContentType::Protobuf)
+    }
+
+    /// Decode the message body using the given codec. An error is returned if the content type signalled in the
+    /// message header does not match the codec.
+    pub fn decode_message_with<T, C>(&self, codec: &C) -> Result<T, DomainCodecError>
+    where C: DomainMessageCodec<T> {
+        let actual = self.content_type();
+        let expected = codec.content_type();
+        if actual != expected {
+            return Err(DomainCodecError::ContentTypeMismatch { expected, actual });
+        }
+        codec.decode(&self.body)
+    }
+
     pub fn origin_node_id(&self) -> NodeId {
         self.authenticated_origin
             .as_ref()
@@ -0,0 +1,359 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Comms settings
+//!
+//! [`CommsSettings`] are the parts of [`CommsConfig`] that node operators manage in a configuration file, under the
+//! `comms` table. The DHT is configured in the `comms.dht` table. Every setting is optional and takes its default
+//! value if not given. Durations are given in seconds. For example:
+//!
+//! ```toml
+//! [comms]
+//! max_concurrent_inbound_tasks = 50
+//! peer_seeds = ["public_key1::address1"]
+//!
+//! [comms.dht]
+//! num_neighbouring_nodes = 10
+//! saf_msg_validity = 21600
+//!
+//! [comms.dht.network_discovery]
+//! min_desired_peers = 100
+//! ```
+//!
+//! Any setting can be overridden by an environment variable named `TARI_` followed by the path to the setting, with
+//! `__` separating each part of the path (e.g. `TARI_COMMS__DHT__NUM_NEIGHBOURING_NODES=10`).
+//!
+//! Unknown settings and out-of-range values are rejected with an error naming the setting.
+
+use crate::{initialization::CommsConfig, transport::TransportType, DEFAULT_DNS_SEED_RESOLVER};
+use config::{Config, ConfigError, Environment, File, FileFormat};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tari_common::{
+    configuration::seconds,
+    logging::parse_log_target_level,
+    ConfigPath,
+    ConfigurationError,
+};
+use tari_comms::{backoff::BackoffPolicy, connection_manager::PeerVersionPolicy, NodeIdentity};
+use tari_comms_dht::DhtConfig;
+
+const LOG_TARGET: &str = "p2p::comms_settings";
+
+/// Prefix of environment variables that override settings
+const ENV_PREFIX: &str = "tari";
+/// Separates the parts of a setting path in an environment variable name
+const ENV_SEPARATOR: &str = "__";
+
+/// Comms settings that can be loaded from a configuration file. See the [module documentation](self).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommsSettings {
+    /// Path to the LMDB data files
+    /// Default: "peer_db"
+    pub datastore_path: PathBuf,
+    /// Default: "peers"
+    pub peer_database_name: String,
+    /// Default: 100
+    pub max_concurrent_inbound_tasks: usize,
+    /// Default: 20
+    pub max_pending_inbound_per_peer: Option<usize>,
+    /// Default: 100
+    pub outbound_buffer_size: usize,
+    /// Default: 10 seconds
+    #[serde(with = "seconds")]
+    pub messaging_shutdown_grace_period: Duration,
+    /// Default: false
+    pub allow_test_addresses: bool,
+    /// Default: 0
+    pub listener_liveness_max_sessions: usize,
+    /// Default: empty
+    pub listener_liveness_allowlist_cidrs: Vec<String>,
+    /// Default: "tari/p2p/<version>"
+    pub user_agent: String,
+    /// Default: empty
+    pub peer_seeds: Vec<String>,
+    /// Default: empty
+    pub trusted_peers: Vec<String>,
+    /// Default: empty
+    pub dns_seeds: Vec<String>,
+    /// Default: 1.1.1.1:53
+    pub dns_seeds_name_server: SocketAddr,
+    /// Default: true
+    pub dns_seeds_use_dnssec: bool,
+    /// Default: 20 seconds
+    #[serde(with = "seconds")]
+    pub dial_connect_timeout: Duration,
+    /// Default: 30 seconds
+    #[serde(with = "seconds")]
+    pub noise_handshake_timeout: Duration,
+    /// Default: 15 seconds
+    #[serde(with = "seconds")]
+    pub identity_exchange_timeout: Duration,
+    /// The policy to apply to peers below a minimum comms protocol version, e.g.
+    /// `{ type = "refuse_below", min_version = 1 }`
+    /// Default: accept_all
+    pub peer_version_policy: PeerVersionPolicy,
+    /// The backoff between attempts to dial a peer. Unlike other durations, backoff delays are given in milliseconds.
+    /// Default: exponential, starting at 500ms and doubling up to 1 minute
+    pub dial_backoff: BackoffPolicy,
+    /// Log level overrides in the form "target=level"
+    /// Default: empty
+    pub log_target_levels: Vec<String>,
+    /// Address on which admin commands (e.g. security events) are served. This must be a loopback address.
+    /// Default: None (disabled)
+    pub admin_socket_address: Option<SocketAddr>,
+    pub dht: DhtConfig,
+}
+
+impl CommsSettings {
+    /// Loads the settings from the given TOML file, applying any environment variable overrides
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigurationError> {
+        let path = path.as_ref();
+        let filename = path
+            .to_str()
+            .ok_or_else(|| ConfigurationError::new("config file", "path is not valid UTF-8"))?;
+        debug!(target: LOG_TARGET, "Loading comms settings from '{}'", filename);
+        let mut config = Config::new();
+        config.merge(File::new(filename, FileFormat::Toml))?;
+        Self::from_config(config)
+    }
+
+    /// Loads the settings from the `comms` table of the given `Config`, applying any environment variable overrides
+    pub fn from_config(mut config: Config) -> Result<Self, ConfigurationError> {
+        config.merge(Environment::with_prefix(ENV_PREFIX).separator(ENV_SEPARATOR))?;
+        // Missing settings are filled in by serde rather than by merging in the serialized defaults, which would mix the
+        // fields of a default enum variant (e.g. an exponential `dial_backoff`) into the configured variant
+        let settings = match config.get::<Self>(Self::main_key_prefix()) {
+            Ok(settings) => settings,
+            Err(ConfigError::NotFound(_)) => Self::default(),
+            Err(err) => return Err(err.into()),
+        };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Checks that the configured values are within their valid ranges
+    pub fn validate(&self) -> Result<(), ConfigurationError> {
+        if self.peer_database_name.is_empty() {
+            return Err(ConfigurationError::new("peer_database_name", "must not be empty"));
+        }
+        let non_zero = [
+            ("max_concurrent_inbound_tasks", self.max_concurrent_inbound_tasks),
+            (
+                "max_pending_inbound_per_peer",
+                self.max_pending_inbound_per_peer.unwrap_or(1),
+            ),
+            ("outbound_buffer_size", self.outbound_buffer_size),
+            (
+                "messaging_shutdown_grace_period",
+                self.messaging_shutdown_grace_period.as_secs() as usize,
+            ),
+            ("dial_connect_timeout", self.dial_connect_timeout.as_secs() as usize),
+            (
+                "noise_handshake_timeout",
+                self.noise_handshake_timeout.as_secs() as usize,
+            ),
+            (
+                "identity_exchange_timeout",
+                self.identity_exchange_timeout.as_secs() as usize,
+            ),
+        ];
+        if let Some((field, _)) = non_zero.iter().find(|(_, value)| *value == 0) {
+            return Err(ConfigurationError::new(field, "must be greater than zero"));
+        }
+        self.dial_backoff
+            .validate()
+            .map_err(|err| ConfigurationError::new("dial_backoff", &err))?;
+        self.parse_log_target_levels()?;
+        self.dht.validate()
+    }
+
+    /// Creates a `CommsConfig` from these settings for the given node identity and transport
+    pub fn into_comms_config(
+        self,
+        node_identity: Arc<NodeIdentity>,
+        transport_type: TransportType,
+    ) -> Result<CommsConfig, ConfigurationError>
+    {
+        let log_target_levels = self.parse_log_target_levels()?;
+        Ok(CommsConfig {
+            datastore_path: self.datastore_path,
+            peer_database_name: self.peer_database_name,
+            max_concurrent_inbound_tasks: self.max_concurrent_inbound_tasks,
+            max_pending_inbound_per_peer: self.max_pending_inbound_per_peer,
+            outbound_buffer_size: self.outbound_buffer_size,
+            messaging_shutdown_grace_period: self.messaging_shutdown_grace_period,
+            dht: self.dht,
+            node_identity,
+            transport_type,
+            allow_test_addresses: self.allow_test_addresses,
+            listener_liveness_max_sessions: self.listener_liveness_max_sessions,
+            listener_liveness_allowlist_cidrs: self.listener_liveness_allowlist_cidrs,
+            user_agent: self.user_agent,
+            peer_seeds: self.peer_seeds,
+            trusted_peers: self.trusted_peers,
+            dns_seeds: self.dns_seeds,
+            dns_seeds_name_server: self.dns_seeds_name_server,
+            dns_seeds_use_dnssec: self.dns_seeds_use_dnssec,
+            dial_connect_timeout: self.dial_connect_timeout,
+            noise_handshake_timeout: self.noise_handshake_timeout,
+            identity_exchange_timeout: self.identity_exchange_timeout,
+            peer_version_policy: self.peer_version_policy,
+            dial_backoff: self.dial_backoff,
+            log_target_levels,
+            admin_socket_address: self.admin_socket_address,
+        })
+    }
+
+    fn parse_log_target_levels(&self) -> Result<Vec<(String, log::LevelFilter)>, ConfigurationError> {
+        self.log_target_levels
+            .iter()
+            .map(|s| {
+                parse_log_target_level(s).map_err(|err| ConfigurationError::new("log_target_levels", &err.to_string()))
+            })
+            .collect()
+    }
+}
+
+impl Default for CommsSettings {
+    fn default() -> Self {
+        Self {
+            datastore_path: PathBuf::from("peer_db"),
+            peer_database_name: "peers".to_string(),
+            max_concurrent_inbound_tasks: 100,
+            max_pending_inbound_per_peer: Some(20),
+            outbound_buffer_size: 100,
+            messaging_shutdown_grace_period: CommsConfig::DEFAULT_MESSAGING_SHUTDOWN_GRACE_PERIOD,
+            allow_test_addresses: false,
+            listener_liveness_max_sessions: 0,
+            listener_liveness_allowlist_cidrs: Vec::new(),
+            user_agent: format!("tari/p2p/{}", env!("CARGO_PKG_VERSION")),
+            peer_seeds: Vec::new(),
+            trusted_peers: Vec::new(),
+            dns_seeds: Vec::new(),
+            dns_seeds_name_server: DEFAULT_DNS_SEED_RESOLVER
+                .parse()
+                .expect("DEFAULT_DNS_SEED_RESOLVER is a valid socket address"),
+            dns_seeds_use_dnssec: true,
+            dial_connect_timeout: CommsConfig::DEFAULT_DIAL_CONNECT_TIMEOUT,
+            noise_handshake_timeout: CommsConfig::DEFAULT_NOISE_HANDSHAKE_TIMEOUT,
+            identity_exchange_timeout: CommsConfig::DEFAULT_IDENTITY_EXCHANGE_TIMEOUT,
+            peer_version_policy: CommsConfig::DEFAULT_PEER_VERSION_POLICY,
+            dial_backoff: Default::default(),
+            log_target_levels: Vec::new(),
+            admin_socket_address: None,
+            dht: Default::default(),
+        }
+    }
+}
+
+impl ConfigPath for CommsSettings {
+    fn main_key_prefix() -> &'static str {
+        "comms"
+    }
+
+    fn overload_key_prefix(_: &Config) -> Result<Option<String>, ConfigurationError> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn load_defaults() {
+        let settings = CommsSettings::from_config(Config::new()).unwrap();
+        assert_eq!(settings.max_concurrent_inbound_tasks, 100);
+        assert_eq!(
+            settings.dht.num_neighbouring_nodes,
+            DhtConfig::default().num_neighbouring_nodes
+        );
+        assert_eq!(
+            settings.dht.saf_handoff_interval,
+            DhtConfig::default().saf_handoff_interval
+        );
+    }
+
+    #[test]
+    fn load_from_file() {
+        let mut file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        file.write_all(
+            br#"
+            [comms]
+            max_concurrent_inbound_tasks = 50
+            log_target_levels = ["comms::dht=debug"]
+            dial_backoff = { type = "constant", delay = 250 }
+            peer_version_policy = { type = "refuse_below", min_version = 1 }
+            [comms.dht]
+            num_neighbouring_nodes = 10
+            saf_msg_validity = 60
+            [comms.dht.network_discovery]
+            min_desired_peers = 5
+            "#,
+        )
+        .unwrap();
+        let settings = CommsSettings::load_from_file(file.path()).unwrap();
+        assert_eq!(settings.max_concurrent_inbound_tasks, 50);
+        assert_eq!(settings.dht.num_neighbouring_nodes, 10);
+        assert_eq!(settings.dht.saf_msg_validity, Duration::from_secs(60));
+        assert_eq!(settings.dht.network_discovery.min_desired_peers, 5);
+        // Unset values take their defaults
+        assert_eq!(settings.dht.num_random_nodes, DhtConfig::default().num_random_nodes);
+        assert_eq!(settings.parse_log_target_levels().unwrap().len(), 1);
+        assert_eq!(
+            settings.dial_backoff,
+            BackoffPolicy::constant(Duration::from_millis(250))
+        );
+        assert_eq!(settings.peer_version_policy, PeerVersionPolicy::RefuseBelow(1));
+    }
+
+    #[test]
+    fn reject_invalid_settings() {
+        let mut config = Config::new();
+        config.set("comms.dht.num_neighbouring_nodez", 10).unwrap();
+        let err = CommsSettings::from_config(config).unwrap_err();
+        assert!(err.to_string().contains("num_neighbouring_nodez"));
+
+        let mut config = Config::new();
+        config.set("comms.dht.num_neighbouring_nodes", 0).unwrap();
+        let err = CommsSettings::from_config(config).unwrap_err();
+        assert!(err.to_string().contains("num_neighbouring_nodes"));
+
+        let mut config = Config::new();
+        config.set("comms.max_concurrent_inbound_tasks", "many").unwrap();
+        assert!(CommsSettings::from_config(config).is_err());
+
+        let mut config = Config::new();
+        config.set("comms.log_target_levels", vec!["comms=loud"]).unwrap();
+        let err = CommsSettings::from_config(config).unwrap_err();
+        assert!(err.to_string().contains("log_target_levels"));
+    }
+}
@@ -42,17 +42,26 @@ use std::{
     time::{Duration, Instant},
 };
 use tari_comms::{
-    backoff::ConstantBackoff,
-    peer_manager::{NodeIdentity, Peer, PeerFeatures, PeerManagerError},
+    backoff::BackoffPolicy,
+    connection_manager::PeerVersionPolicy,
+    peer_manager::{NodeIdentity, Peer, PeerFeatures, PeerManagerError, PeerTrustLevel},
     pipeline,
-    pipeline::SinkService,
+    pipeline::{OverflowPolicy, SinkService},
     protocol::{
-        messaging::{MessagingEventSender, MessagingProtocolExtension},
+        messaging::{MessagingConfig, MessagingEventSender, MessagingProtocolExtension, OutboundQueueUsage},
         rpc::RpcServer,
     },
     tor,
     tor::HiddenServiceControllerError,
-    transports::{MemoryTransport, SocksTransport, TcpWithTorTransport},
+    transports::{
+        MemoryTransport,
+        QuicTransport,
+        SocksTransport,
+        TcpTransport,
+        TcpWithTorTransport,
+        TlsTransport,
+        WebSocketTransport,
+    },
     utils::cidr::parse_cidrs,
     CommsBuilder,
     CommsBuilderError,
@@ -87,8 +96,11 @@ pub enum CommsInitializationError {
     InvalidLivenessCidrs(String),
     #[error("Could not add seed peers to comms layer: `{0}`")]
     FailedToAddSeedPeer(#[from] PeerManagerError),
-    #[error("Cannot acquire exclusive file lock, another instance of the application is already running")]
-    CannotAcquireFileLock,
+    #[error(
+        "Cannot acquire exclusive file lock, another comms instance is already using the data directory `{}`",
+        .0.display()
+    )]
+    CannotAcquireFileLock(PathBuf),
     #[error("IO Error: `{0}`")]
     IoError(#[from] std::io::Error),
 }
@@ -108,17 +120,26 @@ impl CommsInitializationError {
     }
 }
 
-/// Configuration for a comms node
+/// Configuration for a comms node. Several comms nodes (each with their own `CommsConfig`) may be initialized in one
+/// process provided that each has its own node identity, datastore path, listener address and (file-backed) DHT
+/// database.
 #[derive(Clone)]
 pub struct CommsConfig {
-    /// Path to the LMDB data files.
+    /// Path to the LMDB data files. This directory is exclusively locked by the comms node and cannot be shared with
+    /// another comms node, in this or another process.
     pub datastore_path: PathBuf,
     /// Name to use for the peer database
     pub peer_database_name: String,
     /// The maximum number of concurrent Inbound tasks allowed before back-pressure is applied to peers
     pub max_concurrent_inbound_tasks: usize,
+    /// If set, inbound messages are queued per peer (up to this many per peer) while all inbound tasks are busy, and
+    /// are handled round-robin across peers so that a single chatty peer cannot monopolise the inbound pipeline. If
+    /// None, back-pressure is applied to all peers.
+    pub max_pending_inbound_per_peer: Option<usize>,
     /// The size of the buffer (channel) which holds pending outbound message requests
     pub outbound_buffer_size: usize,
+    /// The length of time to allow queued outbound messages to be sent when shutting down
+    pub messaging_shutdown_grace_period: Duration,
     /// Configuration for DHT
     pub dht: DhtConfig,
     /// The identity of this node on the network
@@ -139,6 +160,9 @@ pub struct CommsConfig {
     pub user_agent: String,
     /// Unparsed peer seeds
     pub peer_seeds: Vec<String>,
+    /// Unparsed trusted peers, in the same format as `peer_seeds`. These peers are added to the peer list with the
+    /// `Trusted` trust level.
+    pub trusted_peers: Vec<String>,
     /// DNS seeds hosts. The DNS TXT records are queried from these hosts and the resulting peers added to the comms
     /// peer list.
     pub dns_seeds: Vec<String>,
@@ -146,6 +170,37 @@ pub struct CommsConfig {
     pub dns_seeds_name_server: SocketAddr,
     /// All DNS seed records must pass DNSSEC validation
     pub dns_seeds_use_dnssec: bool,
+    /// The maximum time to wait for the transport to establish a connection to a peer address
+    pub dial_connect_timeout: Duration,
+    /// The maximum time to wait for the noise handshake to complete
+    pub noise_handshake_timeout: Duration,
+    /// The maximum time to wait for the peer identity exchange to complete
+    pub identity_exchange_timeout: Duration,
+    /// The policy to apply to peers that do not support the required comms protocol version
+    pub peer_version_policy: PeerVersionPolicy,
+    /// The backoff between attempts to dial a peer
+    pub dial_backoff: BackoffPolicy,
+    /// Per log target verbosity overrides (e.g. `comms::dht::store_forward` at `Debug`), applied to the application
+    /// logger when comms is initialized. These have no effect if logging was not initialized using
+    /// `tari_common::initialize_logging`. The logger is process-wide, so these levels also apply to any other comms
+    /// nodes in the same process.
+    pub log_target_levels: Vec<(String, LevelFilter)>,
+    /// If set, admin commands (e.g. the security events stream for intrusion detection tooling) are served to clients
+    /// that connect to this address. This must be a loopback address.
+    pub admin_socket_address: Option<SocketAddr>,
+}
+
+impl CommsConfig {
+    /// The default for `dial_connect_timeout`
+    pub const DEFAULT_DIAL_CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+    /// The default for `identity_exchange_timeout`
+    pub const DEFAULT_IDENTITY_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(15);
+    /// The default for `messaging_shutdown_grace_period`
+    pub const DEFAULT_MESSAGING_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+    /// The default for `noise_handshake_timeout`
+    pub const DEFAULT_NOISE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+    /// The default for `peer_version_policy`
+    pub const DEFAULT_PEER_VERSION_POLICY: PeerVersionPolicy = PeerVersionPolicy::AcceptAll;
 }
 
 /// Initialize Tari Comms configured for tests
@@ -188,12 +243,18 @@ where
         .with_node_identity(node_identity)
         .with_user_agent("/test/1.0")
         .with_peer_storage(peer_database, None)
-        .with_dial_backoff(ConstantBackoff::new(Duration::from_millis(500)))
+        .with_dial_backoff(BackoffPolicy::constant(Duration::from_millis(500)))
         .with_min_connectivity(1.0)
         .with_shutdown_signal(shutdown_signal)
         .build()?;
 
-    add_all_peers(&comms.peer_manager(), &comms.node_identity(), seed_peers).await?;
+    add_all_peers(
+        &comms.peer_manager(),
+        &comms.node_identity(),
+        seed_peers,
+        PeerTrustLevel::Seed,
+    )
+    .await?;
 
     // Create outbound channel
     let (outbound_tx, outbound_rx) = mpsc::channel(10);
@@ -287,6 +348,46 @@ pub async fn spawn_comms_using_transport(
                 .spawn_with_transport(transport)
                 .await?
         },
+        TransportType::Quic {
+            listener_address,
+            tor_socks_config,
+        } => {
+            debug!(target: LOG_TARGET, "Building QUIC comms stack");
+            let mut tcp_transport = TcpWithTorTransport::new();
+            if let Some(config) = tor_socks_config {
+                tcp_transport.set_tor_socks_proxy(config);
+            }
+            let transport = QuicTransport::with_tcp_transport(tcp_transport, comms.node_identity());
+            comms
+                .with_listener_address(listener_address)
+                .spawn_with_transport(transport)
+                .await?
+        },
+        TransportType::Tls {
+            listener_address,
+            tor_socks_config,
+            plaintext_fallback,
+        } => {
+            debug!(target: LOG_TARGET, "Building TLS comms stack");
+            let mut tcp_transport = TcpWithTorTransport::new();
+            if let Some(config) = tor_socks_config {
+                tcp_transport.set_tor_socks_proxy(config);
+            }
+            let transport =
+                TlsTransport::new(tcp_transport, &comms.node_identity())?.with_plaintext_fallback(plaintext_fallback);
+            comms
+                .with_listener_address(listener_address)
+                .spawn_with_transport(transport)
+                .await?
+        },
+        TransportType::WebSocket { listener_address } => {
+            debug!(target: LOG_TARGET, "Building WebSocket comms stack");
+            let transport = WebSocketTransport::new(TcpTransport::new());
+            comms
+                .with_listener_address(listener_address)
+                .spawn_with_transport(transport)
+                .await?
+        },
     };
 
     Ok(comms)
@@ -319,6 +420,12 @@ where
     TSink: Sink<Arc<PeerMessage>> + Unpin + Clone + Send + Sync + 'static,
     TSink::Error: Error + Send + Sync,
 {
+    if !config.log_target_levels.is_empty() {
+        if let Err(err) = tari_common::logging::set_log_target_levels(&config.log_target_levels) {
+            warn!(target: LOG_TARGET, "Unable to apply log target levels: {}", err);
+        }
+    }
+
     let file_lock = acquire_exclusive_file_lock(&config.datastore_path)?;
 
     let datastore = LMDBBuilder::new()
@@ -334,15 +441,21 @@ where
     let listener_liveness_allowlist_cidrs = parse_cidrs(&config.listener_liveness_allowlist_cidrs)
         .map_err(CommsInitializationError::InvalidLivenessCidrs)?;
 
-    let mut comms = builder
+    let mut builder = builder
         .with_listener_liveness_max_sessions(config.listener_liveness_max_sessions)
         .with_listener_liveness_allowlist_cidrs(listener_liveness_allowlist_cidrs)
-        .with_dial_backoff(ConstantBackoff::new(Duration::from_millis(500)))
-        .with_peer_storage(peer_database, Some(file_lock))
-        .build()?;
+        .with_dial_backoff(config.dial_backoff)
+        .with_peer_storage(peer_database, Some(file_lock));
+
+    if let Some(addr) = config.admin_socket_address {
+        builder = builder.with_admin_socket(addr);
+    }
+
+    let mut comms = builder.build()?;
 
     // Create outbound channel
     let (outbound_tx, outbound_rx) = mpsc::channel(config.outbound_buffer_size);
+    let outbound_queue_usage = OutboundQueueUsage::new();
 
     let dht = DhtBuilder::new(
         comms.node_identity(),
@@ -352,6 +465,10 @@ where
         comms.shutdown_signal(),
     )
     .with_config(config.dht.clone())
+    .with_outbound_queue_usage(outbound_queue_usage.clone())
+    .with_shutdown_reporter(comms.shutdown_reporter())
+    .with_security_events(comms.security_events())
+    .with_admin_commands(comms.admin_commands())
     .build()
     .await?;
 
@@ -374,6 +491,12 @@ where
             ServiceBuilder::new().layer(dht_outbound_layer).service(sink)
         })
         .max_concurrent_inbound_tasks(config.max_concurrent_inbound_tasks)
+        .inbound_overflow_policy(
+            config
+                .max_pending_inbound_per_peer
+                .map(OverflowPolicy::fair_queue)
+                .unwrap_or_default(),
+        )
         .with_inbound_pipeline(
             ServiceBuilder::new()
                 .layer(dht.inbound_middleware_layer())
@@ -381,16 +504,21 @@ where
         )
         .build();
 
-    comms = comms.add_protocol_extension(MessagingProtocolExtension::new(
-        messaging_events_sender,
-        messaging_pipeline,
-    ));
+    comms = comms.add_protocol_extension(
+        MessagingProtocolExtension::new(messaging_events_sender, messaging_pipeline)
+            .with_config(MessagingConfig {
+                shutdown_grace_period: config.messaging_shutdown_grace_period,
+                ..Default::default()
+            })
+            .with_outbound_queue_usage(outbound_queue_usage),
+    );
 
     Ok((comms, dht))
 }
 
 /// Acquire an exclusive OS level write lock on a file in the provided path. This is used to check if another instance
-/// of this database has already been initialized in order to prevent two process from using it simultaneously
+/// of this database has already been initialized in order to prevent two processes, or two comms nodes in the same
+/// process, from using it simultaneously
 /// ## Parameters
 /// `db_path` - Path where the db will be initialized
 ///
@@ -399,14 +527,16 @@ where
 pub fn acquire_exclusive_file_lock(db_path: &PathBuf) -> Result<File, CommsInitializationError> {
     let lock_file_path = db_path.join(".p2p_file.lock");
 
-    let file = File::create(lock_file_path)?;
+    let file = File::create(&lock_file_path)?;
     // Attempt to acquire exclusive OS level Write Lock
     if let Err(e) = file.try_lock_exclusive() {
         error!(
             target: LOG_TARGET,
-            "Could not acquire exclusive write lock on database lock file: {:?}", e
+            "Could not acquire exclusive write lock on database lock file '{}': {:?}",
+            lock_file_path.display(),
+            e
         );
-        return Err(CommsInitializationError::CannotAcquireFileLock);
+        return Err(CommsInitializationError::CannotAcquireFileLock(db_path.clone()));
     }
 
     Ok(file)
@@ -417,6 +547,7 @@ pub fn acquire_exclusive_file_lock(db_path: &PathBuf) -> Result<File, CommsIniti
 /// `comms_node` - A reference to the comms node. This is the communications stack
 /// `peers` - A list of peers to be added to the comms node, the current node identity of the comms stack is excluded if
 /// found in the list.
+/// `trust_level` - The trust level given to the added peers
 ///
 /// ## Returns
 /// A Result to determine if the call was successful or not, string will indicate the reason on error
@@ -424,9 +555,10 @@ async fn add_all_peers(
     peer_manager: &PeerManager,
     node_identity: &NodeIdentity,
     peers: Vec<Peer>,
+    trust_level: PeerTrustLevel,
 ) -> Result<(), CommsInitializationError>
 {
-    for peer in peers {
+    for mut peer in peers {
         if &peer.public_key == node_identity.public_key() {
             debug!(
                 target: LOG_TARGET,
@@ -435,7 +567,10 @@ async fn add_all_peers(
             continue;
         }
 
-        debug!(target: LOG_TARGET, "Adding seed peer [{}]", peer);
+        debug!(target: LOG_TARGET, "Adding {} peer [{}]", trust_level, peer);
+        // Seed and trusted peers are never pruned from the peer database
+        peer.set_pinned(true);
+        peer.set_trust_level(trust_level);
         peer_manager
             .add_peer(peer)
             .await
@@ -539,7 +674,11 @@ impl ServiceInitializer for P2pInitializer {
             let mut builder = CommsBuilder::new()
                 .with_shutdown_signal(context.get_shutdown_signal())
                 .with_node_identity(config.node_identity.clone())
-                .with_user_agent(&config.user_agent);
+                .with_user_agent(&config.user_agent)
+                .with_dial_connect_timeout(config.dial_connect_timeout)
+                .with_noise_handshake_timeout(config.noise_handshake_timeout)
+                .with_identity_exchange_timeout(config.identity_exchange_timeout)
+                .with_peer_version_policy(config.peer_version_policy);
 
             if config.allow_test_addresses {
                 builder = builder.allow_test_addresses();
@@ -548,7 +687,22 @@ impl ServiceInitializer for P2pInitializer {
             let (comms, dht) = configure_comms_and_dht(builder, &config, connector).await?;
 
             let peers = Self::try_parse_seed_peers(&config.peer_seeds)?;
-            add_all_peers(&comms.peer_manager(), &comms.node_identity(), peers).await?;
+            add_all_peers(
+                &comms.peer_manager(),
+                &comms.node_identity(),
+                peers,
+                PeerTrustLevel::Seed,
+            )
+            .await?;
+
+            let peers = Self::try_parse_seed_peers(&config.trusted_peers)?;
+            add_all_peers(
+                &comms.peer_manager(),
+                &comms.node_identity(),
+                peers,
+                PeerTrustLevel::Trusted,
+            )
+            .await?;
 
             let peers = Self::try_resolve_dns_seeds(
                 config.dns_seeds_name_server,
@@ -556,7 +710,13 @@ impl ServiceInitializer for P2pInitializer {
                 config.dns_seeds_use_dnssec,
             )
             .await?;
-            add_all_peers(&comms.peer_manager(), &comms.node_identity(), peers).await?;
+            add_all_peers(
+                &comms.peer_manager(),
+                &comms.node_identity(),
+                peers,
+                PeerTrustLevel::Seed,
+            )
+            .await?;
 
             context.register_handle(comms.connectivity());
             context.register_handle(comms.peer_manager());
@@ -567,3 +727,24 @@ impl ServiceInitializer for P2pInitializer {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn file_lock_is_exclusive_per_data_directory() {
+        let dir1 = tempdir().unwrap();
+        let dir2 = tempdir().unwrap();
+        let _lock = acquire_exclusive_file_lock(&dir1.path().to_path_buf()).unwrap();
+
+        // A second comms node in the same process cannot use the same data directory...
+        match acquire_exclusive_file_lock(&dir1.path().to_path_buf()) {
+            Err(CommsInitializationError::CannotAcquireFileLock(path)) => assert_eq!(path, dir1.path()),
+            res => panic!("Unexpected result {:?}", res.map(|_| ())),
+        }
+        // ...but can use its own
+        acquire_exclusive_file_lock(&dir2.path().to_path_buf()).unwrap();
+    }
+}
@@ -38,6 +38,7 @@
 mod test_utils;
 
 pub mod comms_connector;
+pub mod comms_settings;
 pub mod dns_seed;
 pub mod domain_message;
 pub mod initialization;
@@ -36,6 +36,9 @@ pub struct LivenessConfig {
     pub num_peers_per_round: usize,
     /// Peers to include in every auto ping round (Default: <empty>)
     pub monitored_peers: Vec<NodeId>,
+    /// The maximum size in bytes of a single metadata entry attached to a ping or pong. Larger entries are rejected
+    /// when set locally and discarded when received. (Default: 1024)
+    pub max_metadata_entry_size: usize,
 }
 
 impl Default for LivenessConfig {
@@ -46,6 +49,7 @@ impl Default for LivenessConfig {
             refresh_random_pool_interval: Duration::from_secs(2 * 60 * 60),
             num_peers_per_round: 8,
             monitored_peers: Default::default(),
+            max_metadata_entry_size: 1024,
         }
     }
 }
@@ -20,6 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use crate::proto::liveness::MetadataKey;
 use tari_comms::{connectivity::ConnectivityError, message::MessageError};
 use tari_comms_dht::{outbound::DhtOutboundError, DhtActorError};
 use tari_service_framework::reply_channel::TransportChannelError;
@@ -49,4 +50,10 @@ pub enum LivenessError {
     InvalidPingPongType,
     #[error("NodeId does not exist")]
     NodeIdDoesNotExist,
+    #[error("Metadata key `{0:?}` has already been registered")]
+    MetadataKeyAlreadyRegistered(MetadataKey),
+    #[error("Metadata key `{0:?}` has not been registered")]
+    MetadataKeyNotRegistered(MetadataKey),
+    #[error("Metadata entry of {size} bytes exceeds the maximum size of {max} bytes")]
+    MetadataEntryTooLarge { size: usize, max: usize },
 }
@@ -39,6 +39,8 @@ pub enum LivenessRequest {
     GetPongCount,
     /// Get average latency for node ID
     GetAvgLatency(NodeId),
+    /// Register a metadata key, allowing the caller to set metadata entries for it
+    RegisterMetadataKey(MetadataKey),
     /// Set the metadata attached to each ping/pong message
     SetMetadataEntry(MetadataKey, Vec<u8>),
 }
@@ -138,7 +140,17 @@ impl LivenessHandle {
         }
     }
 
-    /// Set metadata entry for the pong message
+    /// Register a metadata key so that entries for it can be attached to ping and pong messages. Each key should be
+    /// registered by one service, which is responsible for keeping the entry up to date. An error is returned if the
+    /// key has already been registered.
+    pub async fn register_metadata_key(&mut self, key: MetadataKey) -> Result<(), LivenessError> {
+        match self.handle.call(LivenessRequest::RegisterMetadataKey(key)).await?? {
+            LivenessResponse::Ok => Ok(()),
+            _ => Err(LivenessError::UnexpectedApiResponse),
+        }
+    }
+
+    /// Set metadata entry for the pong message. The key must have been registered using `register_metadata_key`.
     pub async fn set_metadata_entry(&mut self, key: MetadataKey, value: Vec<u8>) -> Result<(), LivenessError> {
         match self
             .handle
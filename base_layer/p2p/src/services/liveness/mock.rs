@@ -135,7 +135,7 @@ impl LivenessMock {
             GetAvgLatency(_) => {
                 reply.send(Ok(LivenessResponse::AvgLatency(None))).unwrap();
             },
-            SetMetadataEntry(_, _) => {
+            RegisterMetadataKey(_) | SetMetadataEntry(_, _) => {
                 reply.send(Ok(LivenessResponse::Ok)).unwrap();
             },
         }
@@ -36,7 +36,7 @@
 //! [PingPong]: ./messages/enum.PingPong.html
 
 mod config;
-pub use config::LivenessConfig;
+pub use self::config::LivenessConfig;
 
 pub mod error;
 
@@ -51,6 +51,8 @@ pub use handle::{
 };
 
 mod message;
+pub use message::{PingPong, PingPongMessage};
+
 mod service;
 
 mod state;
@@ -59,7 +61,7 @@ pub use state::Metadata;
 #[cfg(feature = "test-mocks")]
 pub mod mock;
 
-use self::{message::PingPongMessage, service::LivenessService};
+use self::{handle::LivenessEventReceiver, service::LivenessService};
 pub use crate::proto::liveness::MetadataKey;
 use crate::{
     comms_connector::{PeerMessage, TopicSubscriptionFactory},
@@ -74,14 +76,14 @@ use futures::{future, Future, Stream, StreamExt};
 use log::*;
 use std::sync::Arc;
 use tari_comms::connectivity::ConnectivityRequester;
-use tari_comms_dht::Dht;
+use tari_comms_dht::{Dht, MetricsCollectorHandle};
 use tari_service_framework::{
     reply_channel,
     ServiceInitializationError,
     ServiceInitializer,
     ServiceInitializerContext,
 };
-use tokio::sync::broadcast;
+use tokio::{sync::broadcast, task};
 
 const LOG_TARGET: &str = "p2p::services::liveness";
 
@@ -138,6 +140,7 @@ impl ServiceInitializer for LivenessInitializer {
             let dht = handles.expect_handle::<Dht>();
             let connectivity = handles.expect_handle::<ConnectivityRequester>();
             let outbound_messages = dht.outbound_requester();
+            task::spawn(write_pong_metrics(publisher.subscribe(), dht.metrics_collector()));
 
             let service = LivenessService::new(
                 config,
@@ -156,3 +159,23 @@ impl ServiceInitializer for LivenessInitializer {
         future::ready(Ok(()))
     }
 }
+
+/// Counts received pongs in the DHT network heartbeat, which is used to detect a network partition
+async fn write_pong_metrics(mut events: LivenessEventReceiver, mut metrics_collector: MetricsCollectorHandle) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if let LivenessEvent::ReceivedPong(_) = &*event {
+                    metrics_collector.write_metric_pong_received();
+                }
+            },
+            Err(broadcast::RecvError::Lagged(n)) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Pong metrics lagged behind by {} liveness event(s)", n
+                );
+            },
+            Err(broadcast::RecvError::Closed) => break,
+        }
+    }
+}
@@ -24,19 +24,20 @@ use super::{
     config::LivenessConfig,
     error::LivenessError,
     message::{PingPong, PingPongMessage},
-    state::LivenessState,
+    state::{LivenessState, Metadata},
     LivenessRequest,
     LivenessResponse,
     LOG_TARGET,
 };
 use crate::{
     domain_message::DomainMessage,
+    proto::liveness::MetadataKey,
     services::liveness::{handle::LivenessEventSender, LivenessEvent, PingPongEvent},
     tari_message::TariMessageType,
 };
 use futures::{future::Either, pin_mut, stream::StreamExt, Stream};
 use log::*;
-use std::{iter, sync::Arc, time::Instant};
+use std::{collections::HashMap, iter, sync::Arc, time::Instant};
 use tari_comms::{
     connectivity::{ConnectivityRequester, ConnectivitySelection},
     peer_manager::NodeId,
@@ -44,7 +45,7 @@ use tari_comms::{
 };
 use tari_comms_dht::{
     domain_message::OutboundDomainMessage,
-    outbound::{DhtOutboundError, OutboundMessageRequester},
+    outbound::{DhtOutboundError, OutboundMessageRequester, SendMessageParams},
 };
 use tari_service_framework::reply_channel::RequestContext;
 use tari_shutdown::ShutdownSignal;
@@ -163,7 +164,8 @@ where
                     source_peer.user_agent,
                 );
 
-                let ping_event = PingPongEvent::new(node_id, None, ping_pong_msg.metadata.into());
+                let metadata = self.filter_metadata(&node_id, ping_pong_msg.metadata);
+                let ping_event = PingPongEvent::new(node_id, None, metadata);
                 self.publish_event(LivenessEvent::ReceivedPing(Box::new(ping_event)));
             },
             PingPong::Pong => {
@@ -186,20 +188,52 @@ where
                     maybe_latency.map(|ms| format!("Latency: {}ms", ms)).unwrap_or_default(),
                 );
 
-                let pong_event = PingPongEvent::new(node_id, maybe_latency, ping_pong_msg.metadata.into());
+                let metadata = self.filter_metadata(&node_id, ping_pong_msg.metadata);
+                let pong_event = PingPongEvent::new(node_id, maybe_latency, metadata);
                 self.publish_event(LivenessEvent::ReceivedPong(Box::new(pong_event)));
             },
         }
         Ok(())
     }
 
+    /// Discards metadata entries that have an unknown key or exceed the maximum entry size
+    fn filter_metadata(&self, node_id: &NodeId, mut metadata: HashMap<i32, Vec<u8>>) -> Metadata {
+        let max_size = self.config.max_metadata_entry_size;
+        metadata.retain(|key, value| {
+            let is_valid = MetadataKey::from_i32(*key)
+                .filter(|k| *k != MetadataKey::None)
+                .is_some() &&
+                value.len() <= max_size;
+            if !is_valid {
+                debug!(
+                    target: LOG_TARGET,
+                    "Discarding metadata entry (key = {}, {} bytes) from peer '{}'",
+                    key,
+                    value.len(),
+                    node_id.short_str()
+                );
+            }
+            is_valid
+        });
+        metadata.into()
+    }
+
     async fn send_ping(&mut self, node_id: NodeId) -> Result<(), LivenessError> {
         let msg = PingPongMessage::ping_with_metadata(self.state.metadata().clone());
         self.state.add_inflight_ping(msg.nonce, node_id.clone());
         debug!(target: LOG_TARGET, "Sending ping to peer '{}'", node_id.short_str(),);
 
+        // Pings are sent periodically to the same peers so they are exempt from outbound duplicate suppression
         self.outbound_messaging
-            .send_direct_node_id(node_id, OutboundDomainMessage::new(TariMessageType::PingPong, msg))
+            .send_message(
+                SendMessageParams::new()
+                    .direct_node_id(node_id)
+                    .allow_duplicates()
+                    .finish(),
+                OutboundDomainMessage::new(TariMessageType::PingPong, msg),
+            )
+            .await?
+            .resolve()
             .await
             .map_err(Into::<DhtOutboundError>::into)?;
 
@@ -235,7 +269,22 @@ where
                 let latency = self.state.get_avg_latency_ms(&node_id);
                 Ok(LivenessResponse::AvgLatency(latency))
             },
+            RegisterMetadataKey(key) => {
+                if key == MetadataKey::None || !self.state.register_metadata_key(key) {
+                    return Err(LivenessError::MetadataKeyAlreadyRegistered(key));
+                }
+                Ok(LivenessResponse::Ok)
+            },
             SetMetadataEntry(key, value) => {
+                if !self.state.is_metadata_key_registered(key) {
+                    return Err(LivenessError::MetadataKeyNotRegistered(key));
+                }
+                if value.len() > self.config.max_metadata_entry_size {
+                    return Err(LivenessError::MetadataEntryTooLarge {
+                        size: value.len(),
+                        max: self.config.max_metadata_entry_size,
+                    });
+                }
                 self.state.set_metadata_entry(key, value);
                 Ok(LivenessResponse::Ok)
             },
@@ -266,11 +315,7 @@ where
         debug!(target: LOG_TARGET, "Sending liveness ping to {} peer(s)", len_peers);
 
         for peer in selected_peers {
-            let msg = PingPongMessage::ping_with_metadata(self.state.metadata().clone());
-            self.state.add_inflight_ping(msg.nonce, peer.clone());
-            self.outbound_messaging
-                .send_direct_node_id(peer, OutboundDomainMessage::new(TariMessageType::PingPong, msg))
-                .await?;
+            self.send_ping(peer).await?;
         }
 
         self.publish_event(LivenessEvent::PingRoundBroadcast(len_peers));
@@ -322,6 +367,7 @@ mod test {
     use tari_crypto::keys::PublicKey;
     use tari_service_framework::reply_channel;
     use tari_shutdown::Shutdown;
+    use tari_test_utils::unpack_enum;
     use tokio::{sync::broadcast, task};
 
     #[tokio_macros::test_basic]
@@ -413,6 +459,60 @@ mod test {
         let _res = liveness_handle.send_ping(node_id).await.unwrap();
     }
 
+    #[tokio_macros::test_basic]
+    async fn set_metadata_entry() {
+        let (connectivity, mock) = create_connectivity_mock();
+        mock.spawn();
+        let (outbound_tx, _) = mpsc::channel(10);
+        let outbound_messaging = OutboundMessageRequester::new(outbound_tx);
+
+        let (sender_service, receiver) = reply_channel::unbounded();
+        let (publisher, _) = broadcast::channel(200);
+        let mut liveness_handle = LivenessHandle::new(sender_service, publisher.clone());
+
+        let shutdown = Shutdown::new();
+        let service = LivenessService::new(
+            LivenessConfig {
+                max_metadata_entry_size: 4,
+                ..Default::default()
+            },
+            receiver,
+            stream::empty(),
+            LivenessState::new(),
+            connectivity,
+            outbound_messaging,
+            publisher,
+            shutdown.to_signal(),
+        );
+        task::spawn(service.run());
+
+        let err = liveness_handle
+            .set_metadata_entry(MetadataKey::ChainMetadata, b"A".to_vec())
+            .await
+            .unwrap_err();
+        unpack_enum!(LivenessError::MetadataKeyNotRegistered(_k) = err);
+
+        liveness_handle
+            .register_metadata_key(MetadataKey::ChainMetadata)
+            .await
+            .unwrap();
+        let err = liveness_handle
+            .register_metadata_key(MetadataKey::ChainMetadata)
+            .await
+            .unwrap_err();
+        unpack_enum!(LivenessError::MetadataKeyAlreadyRegistered(_k) = err);
+
+        liveness_handle
+            .set_metadata_entry(MetadataKey::ChainMetadata, b"A".to_vec())
+            .await
+            .unwrap();
+        let err = liveness_handle
+            .set_metadata_entry(MetadataKey::ChainMetadata, b"ABCDE".to_vec())
+            .await
+            .unwrap_err();
+        unpack_enum!(LivenessError::MetadataEntryTooLarge { .. } = err);
+    }
+
     fn create_dummy_message<T>(inner: T) -> DomainMessage<T> {
         let (_, pk) = CommsPublicKey::random_keypair(&mut OsRng);
         let source_peer = Peer::new(
@@ -435,6 +535,8 @@ mod test {
                 flags: Default::default(),
                 message_tag: MessageTag::new(),
                 expires: None,
+                mailbox_tag: Vec::new(),
+                sequence: 0,
             },
             authenticated_origin: None,
             source_peer,
@@ -487,8 +589,11 @@ mod test {
         let (outbound_tx, _) = mpsc::channel(10);
         let outbound_messaging = OutboundMessageRequester::new(outbound_tx);
 
-        let mut metadata = Metadata::new();
-        metadata.insert(MetadataKey::ChainMetadata, b"dummy-data".to_vec());
+        let mut metadata = HashMap::new();
+        metadata.insert(MetadataKey::ChainMetadata as i32, b"dummy-data".to_vec());
+        // Unknown keys are discarded
+        metadata.insert(i32::max_value(), b"unknown".to_vec());
+        let metadata = Metadata::from(metadata);
         let msg = create_dummy_message(PingPongMessage::pong_with_metadata(123, metadata.clone()));
 
         state.add_inflight_ping(msg.inner.nonce, msg.source_peer.node_id.clone());
@@ -523,6 +628,7 @@ mod test {
         match &*event {
             LivenessEvent::ReceivedPong(event) => {
                 assert_eq!(event.metadata.get(MetadataKey::ChainMetadata).unwrap(), b"dummy-data");
+                assert_eq!(HashMap::from(event.metadata.clone()).len(), 1);
             },
             _ => panic!("Unexpected event"),
         }
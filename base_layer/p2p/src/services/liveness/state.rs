@@ -23,7 +23,7 @@
 use crate::proto::liveness::MetadataKey;
 use chrono::{NaiveDateTime, Utc};
 use std::{
-    collections::{hash_map::RandomState, HashMap},
+    collections::{hash_map::RandomState, HashMap, HashSet},
     time::Duration,
 };
 use tari_comms::peer_manager::NodeId;
@@ -76,6 +76,7 @@ pub struct LivenessState {
     num_active_peers: usize,
 
     local_metadata: Metadata,
+    registered_metadata_keys: HashSet<MetadataKey>,
 }
 
 impl LivenessState {
@@ -122,6 +123,15 @@ impl LivenessState {
         &self.local_metadata
     }
 
+    /// Register a metadata key. Returns false if the key was already registered.
+    pub fn register_metadata_key(&mut self, key: MetadataKey) -> bool {
+        self.registered_metadata_keys.insert(key)
+    }
+
+    pub fn is_metadata_key_registered(&self, key: MetadataKey) -> bool {
+        self.registered_metadata_keys.contains(&key)
+    }
+
     /// Set a metadata entry for the local node. Duplicate entries are replaced.
     pub fn set_metadata_entry(&mut self, key: MetadataKey, value: Vec<u8>) {
         self.local_metadata.insert(key, value);
@@ -0,0 +1,402 @@
+// Copyright 2020 The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::RequestResponseError;
+use crate::{domain_message::DomainMessage, tari_message::TariMessageType};
+use futures::{channel::oneshot, Stream, StreamExt};
+use log::*;
+use rand::{rngs::OsRng, RngCore};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tari_comms::peer_manager::NodeId;
+use tari_comms_dht::{
+    domain_message::OutboundDomainMessage,
+    outbound::{DhtOutboundError, FinalSendMessageParams, OutboundMessageRequester},
+};
+use tokio::time;
+
+const LOG_TARGET: &str = "p2p::services::request_response";
+
+/// The correlation id used to match a response to its request
+pub type RequestId = u64;
+
+/// Implemented by response messages so that they can be routed to the request that they are replying to
+pub trait CorrelatedMessage {
+    /// The id of the request to which this message is a response
+    fn request_id(&self) -> RequestId;
+}
+
+type Waiters<T> = Arc<Mutex<HashMap<RequestId, Waiter<T>>>>;
+
+struct Waiter<T> {
+    reply_tx: oneshot::Sender<DomainMessage<T>>,
+    /// The node that the request was sent to, or None if the request was not sent to a particular node
+    expected_responder: Option<NodeId>,
+}
+
+impl<T> Waiter<T> {
+    /// Returns true if the response was sent by, or originated from, the node that the request was sent to
+    fn is_expected_responder(&self, response: &DomainMessage<T>) -> bool {
+        match self.expected_responder.as_ref() {
+            Some(node_id) => {
+                response.source_peer.node_id == *node_id ||
+                    response
+                        .authenticated_origin
+                        .as_ref()
+                        .map(|origin| NodeId::from_public_key(origin) == *node_id)
+                        .unwrap_or(false)
+            },
+            None => true,
+        }
+    }
+}
+
+/// Removes the waiter for a request when the request completes or is dropped
+struct WaiterGuard<T> {
+    waiters: Waiters<T>,
+    request_id: RequestId,
+}
+
+impl<T> Drop for WaiterGuard<T> {
+    fn drop(&mut self) {
+        // The waiter will already have been removed if a response was received
+        if let Ok(mut waiters) = self.waiters.lock() {
+            waiters.remove(&self.request_id);
+        }
+    }
+}
+
+/// Sends domain requests and waits for the correlated response. Responses are delivered by the paired
+/// [RequestResponder](self::RequestResponder), obtained by calling `Requester::responder`.
+pub struct Requester<TResp> {
+    outbound: OutboundMessageRequester,
+    waiters: Waiters<TResp>,
+    timeout: Duration,
+}
+
+impl<TResp> Requester<TResp> {
+    /// Create a new Requester. Requests which do not receive a response within `timeout` fail with
+    /// `RequestResponseError::RequestTimedOut`.
+    pub fn new(outbound: OutboundMessageRequester, timeout: Duration) -> Self {
+        Self {
+            outbound,
+            waiters: Default::default(),
+            timeout,
+        }
+    }
+
+    /// Returns a `RequestResponder` which routes inbound responses to the requests made by this `Requester`
+    pub fn responder(&self) -> RequestResponder<TResp> {
+        RequestResponder {
+            waiters: self.waiters.clone(),
+        }
+    }
+
+    /// Returns the number of requests which are waiting for a response
+    pub fn num_pending(&self) -> usize {
+        self.waiters.lock().unwrap().len()
+    }
+
+    /// Send a request and wait for the response. `make_request` is called with the correlation id that the responding
+    /// peer MUST include in its response. If the request is sent to a particular node (a direct broadcast strategy or
+    /// a known destination), only a response from that node is accepted.
+    pub async fn request<TReq, F>(
+        &mut self,
+        params: FinalSendMessageParams,
+        message_type: TariMessageType,
+        make_request: F,
+    ) -> Result<DomainMessage<TResp>, RequestResponseError>
+    where
+        TReq: prost::Message,
+        F: FnOnce(RequestId) -> TReq,
+    {
+        let expected_responder = params.destination.to_derived_node_id().or_else(|| {
+            params.broadcast_strategy.direct_node_id().cloned().or_else(|| {
+                params
+                    .broadcast_strategy
+                    .direct_public_key()
+                    .map(NodeId::from_public_key)
+            })
+        });
+        let (request_id, reply_rx) = self.register_waiter(expected_responder);
+        let _guard = WaiterGuard {
+            waiters: self.waiters.clone(),
+            request_id,
+        };
+        let message = OutboundDomainMessage::new(message_type, make_request(request_id));
+        let outbound = &mut self.outbound;
+        let send_and_wait = async move {
+            outbound
+                .send_message(params, message)
+                .await?
+                .resolve()
+                .await
+                .map_err(DhtOutboundError::from)?;
+            reply_rx.await.map_err(|_| RequestResponseError::RequestCancelled)
+        };
+
+        let result = match time::timeout(self.timeout, send_and_wait).await {
+            Ok(result) => result,
+            Err(_) => Err(RequestResponseError::RequestTimedOut(self.timeout)),
+        };
+
+        if let Err(err) = result.as_ref() {
+            debug!(target: LOG_TARGET, "Request `{}` failed: {}", request_id, err);
+        }
+        result
+    }
+
+    fn register_waiter(
+        &self,
+        expected_responder: Option<NodeId>,
+    ) -> (RequestId, oneshot::Receiver<DomainMessage<TResp>>)
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let mut waiters = self.waiters.lock().unwrap();
+        let mut request_id = OsRng.next_u64();
+        while waiters.contains_key(&request_id) {
+            request_id = OsRng.next_u64();
+        }
+        waiters.insert(request_id, Waiter {
+            reply_tx,
+            expected_responder,
+        });
+        (request_id, reply_rx)
+    }
+}
+
+/// Routes inbound responses to the waiting request with the same correlation id
+pub struct RequestResponder<TResp> {
+    waiters: Waiters<TResp>,
+}
+
+impl<TResp> Clone for RequestResponder<TResp> {
+    fn clone(&self) -> Self {
+        Self {
+            waiters: self.waiters.clone(),
+        }
+    }
+}
+
+impl<TResp: CorrelatedMessage> RequestResponder<TResp> {
+    /// Deliver a response to the waiting request. An error is returned if no request is waiting for this response,
+    /// for e.g. because it has already timed out or was answered by another response, or if the response was not sent
+    /// by the node that the request was sent to. In the latter case, the request continues to wait for a response.
+    pub fn route(&self, response: DomainMessage<TResp>) -> Result<(), RequestResponseError> {
+        let request_id = response.inner().request_id();
+        let waiter = match self.waiters.lock().unwrap().entry(request_id) {
+            Entry::Occupied(entry) if entry.get().is_expected_responder(&response) => entry.remove(),
+            Entry::Occupied(_) => {
+                return Err(RequestResponseError::UnexpectedResponder {
+                    request_id,
+                    node_id: response.source_peer.node_id,
+                })
+            },
+            Entry::Vacant(_) => return Err(RequestResponseError::UnexpectedResponse(request_id)),
+        };
+        waiter
+            .reply_tx
+            .send(response)
+            .map_err(|_| RequestResponseError::RequestCancelled)
+    }
+
+    /// Route all responses from the given stream until it ends. Responses for which no request is waiting are
+    /// discarded.
+    pub async fn run<S>(self, mut responses: S)
+    where S: Stream<Item = DomainMessage<TResp>> + Unpin {
+        while let Some(response) = responses.next().await {
+            if let Err(err) = self.route(response) {
+                debug!(target: LOG_TARGET, "Discarding response: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        proto::liveness::PingPongMessage,
+        test_utils::{make_dht_header, make_node_identity},
+    };
+    use futures::channel::mpsc;
+    use tari_comms::{message::MessageTag, peer_manager::NodeIdentity};
+    use tari_comms_dht::outbound::{MessageSendState, SendMessageParams, SendMessageResponse};
+    use tari_test_utils::unpack_enum;
+    use tokio::task;
+
+    impl CorrelatedMessage for PingPongMessage {
+        fn request_id(&self) -> RequestId {
+            self.nonce
+        }
+    }
+
+    fn make_response(request_id: RequestId) -> DomainMessage<PingPongMessage> {
+        make_response_from(&make_node_identity(), request_id)
+    }
+
+    fn make_response_from(node_identity: &NodeIdentity, request_id: RequestId) -> DomainMessage<PingPongMessage> {
+        DomainMessage {
+            source_peer: node_identity.to_peer(),
+            dht_header: make_dht_header(MessageTag::new()),
+            authenticated_origin: None,
+            inner: PingPongMessage {
+                nonce: request_id,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn queued_response() -> SendMessageResponse {
+        let (_, rx) = oneshot::channel();
+        SendMessageResponse::Queued(vec![MessageSendState::new(MessageTag::new(), rx)].into())
+    }
+
+    #[tokio_macros::test_basic]
+    async fn request_receives_correlated_response() {
+        let (outbound_tx, mut outbound_rx) = mpsc::channel(10);
+        let mut requester =
+            Requester::<PingPongMessage>::new(OutboundMessageRequester::new(outbound_tx), Duration::from_secs(10));
+        let responder = requester.responder();
+
+        task::spawn(async move {
+            let (_, body) = unwrap_oms_send_msg!(outbound_rx.next().await.unwrap(), reply_value = queued_response());
+            assert!(!body.is_empty());
+            // A response for an unknown request is rejected
+            let err = responder.route(make_response(0)).unwrap_err();
+            unpack_enum!(RequestResponseError::UnexpectedResponse(_id) = err);
+            let request_id = *responder.waiters.lock().unwrap().keys().next().unwrap();
+            responder.route(make_response(request_id)).unwrap();
+        });
+
+        let resp = requester
+            .request(
+                SendMessageParams::new().flood(vec![]).finish(),
+                TariMessageType::PingPong,
+                |id| PingPongMessage {
+                    nonce: id,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_ne!(resp.inner().nonce, 0);
+        assert_eq!(requester.num_pending(), 0);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn request_times_out() {
+        let (outbound_tx, mut outbound_rx) = mpsc::channel(10);
+        let mut requester =
+            Requester::<PingPongMessage>::new(OutboundMessageRequester::new(outbound_tx), Duration::from_millis(10));
+        task::spawn(async move {
+            while let Some(req) = outbound_rx.next().await {
+                unwrap_oms_send_msg!(req, reply_value = queued_response());
+            }
+        });
+
+        let err = requester
+            .request(
+                SendMessageParams::new().flood(vec![]).finish(),
+                TariMessageType::PingPong,
+                |id| PingPongMessage {
+                    nonce: id,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        unpack_enum!(RequestResponseError::RequestTimedOut(_t) = err);
+        assert_eq!(requester.num_pending(), 0);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn dropped_request_is_removed() {
+        let (outbound_tx, mut outbound_rx) = mpsc::channel(10);
+        let mut requester =
+            Requester::<PingPongMessage>::new(OutboundMessageRequester::new(outbound_tx), Duration::from_secs(10));
+        task::spawn(async move {
+            while let Some(req) = outbound_rx.next().await {
+                unwrap_oms_send_msg!(req, reply_value = queued_response());
+            }
+        });
+
+        // The request future is dropped by the outer timeout before the request times out
+        let request = requester.request(
+            SendMessageParams::new().flood(vec![]).finish(),
+            TariMessageType::PingPong,
+            |id| PingPongMessage {
+                nonce: id,
+                ..Default::default()
+            },
+        );
+        time::timeout(Duration::from_millis(10), request).await.unwrap_err();
+        assert_eq!(requester.num_pending(), 0);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn response_from_unexpected_peer_is_rejected() {
+        let (outbound_tx, mut outbound_rx) = mpsc::channel(10);
+        let mut requester =
+            Requester::<PingPongMessage>::new(OutboundMessageRequester::new(outbound_tx), Duration::from_secs(10));
+        let responder = requester.responder();
+        let peer_identity = make_node_identity();
+
+        let responding_identity = peer_identity.clone();
+        task::spawn(async move {
+            unwrap_oms_send_msg!(outbound_rx.next().await.unwrap(), reply_value = queued_response());
+            let request_id = *responder.waiters.lock().unwrap().keys().next().unwrap();
+            let err = responder.route(make_response(request_id)).unwrap_err();
+            unpack_enum!(RequestResponseError::UnexpectedResponder { .. } = err);
+            // A response relayed by another peer is accepted if it originated from the expected peer
+            let mut response = make_response(request_id);
+            response.authenticated_origin = Some(responding_identity.public_key().clone());
+            responder.route(response).unwrap();
+        });
+
+        let resp = requester
+            .request(
+                SendMessageParams::new()
+                    .direct_public_key(peer_identity.public_key().clone())
+                    .finish(),
+                TariMessageType::PingPong,
+                |id| PingPongMessage {
+                    nonce: id,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.authenticated_origin.as_ref(), Some(peer_identity.public_key()));
+        assert_eq!(requester.num_pending(), 0);
+
+        // A response from the peer that the request was sent to is accepted
+        let (request_id, _reply_rx) = requester.register_waiter(Some(peer_identity.node_id().clone()));
+        requester
+            .responder()
+            .route(make_response_from(&peer_identity, request_id))
+            .unwrap();
+    }
+}
@@ -71,6 +71,8 @@ pub fn make_dht_header(trace: MessageTag) -> DhtMessageHeader {
         flags: DhtMessageFlags::NONE,
         message_tag: trace,
         expires: None,
+        mailbox_tag: Vec::new(),
+        sequence: 0,
     }
 }
 
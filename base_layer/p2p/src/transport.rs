@@ -41,6 +41,24 @@ pub enum TransportType {
         socks_config: SocksConfig,
         listener_address: Multiaddr,
     },
+    /// Use a WebSocket transport. This transport can connect to peers listening on `/ws` or `/wss` addresses.
+    WebSocket { listener_address: Multiaddr },
+    /// Use a QUIC transport. This transport dials QUIC addresses over QUIC and falls back to TCP (optionally with Tor
+    /// support) for all other addresses.
+    Quic {
+        listener_address: Multiaddr,
+        /// The optional SOCKS proxy to use when connecting to Tor onion addresses
+        tor_socks_config: Option<SocksConfig>,
+    },
+    /// Use a TCP transport (optionally with Tor support) which wraps connections in TLS. This is intended for
+    /// networks that drop traffic that does not look like TLS.
+    Tls {
+        listener_address: Multiaddr,
+        /// The optional SOCKS proxy to use when connecting to Tor onion addresses
+        tor_socks_config: Option<SocksConfig>,
+        /// If true, peers that do not complete the TLS handshake are dialed again without TLS
+        plaintext_fallback: bool,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,130 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use log::*;
+use tari_comms::{multiaddr, peer_manager::NodeIdentityError};
+use tari_comms_dht::outbound::DhtOutboundError;
+use tari_crypto::tari_utilities::hex::HexError;
+use tari_p2p::initialization::CommsInitializationError;
+use tari_service_framework::ServiceInitializationError;
+use thiserror::Error;
+
+const LOG_TARGET: &str = "p2p_ffi::error";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum InterfaceError {
+    #[error("An error has occurred due to one of the parameters being null: `{0}`")]
+    NullError(String),
+    #[error("An error has occurred when trying to create the tokio runtime: `{0}`")]
+    TokioError(String),
+    #[error("An invalid string was provided for parameter `{0}`")]
+    InvalidString(String),
+    #[error("The `{0}` service handle was not registered")]
+    MissingServiceHandle(String),
+    #[error("This function may not be called from a callback or any other thread owned by a node runtime")]
+    CalledFromRuntimeThread,
+}
+
+/// This struct is meant to hold an error for use by FFI client applications. The error has an integer code and string
+/// message
+#[derive(Debug, Clone)]
+pub struct LibP2pError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl From<InterfaceError> for LibP2pError {
+    fn from(v: InterfaceError) -> Self {
+        error!(target: LOG_TARGET, "{}", format!("{:?}", v));
+        let code = match v {
+            InterfaceError::NullError(_) => 1,
+            InterfaceError::TokioError(_) => 4,
+            InterfaceError::InvalidString(_) => 5,
+            InterfaceError::MissingServiceHandle(_) => 7,
+            InterfaceError::CalledFromRuntimeThread => 8,
+        };
+        Self {
+            code,
+            message: format!("{:?}", v),
+        }
+    }
+}
+
+impl From<HexError> for LibP2pError {
+    fn from(h: HexError) -> Self {
+        error!(target: LOG_TARGET, "{}", format!("{:?}", h));
+        Self {
+            code: 404,
+            message: format!("{:?}", h),
+        }
+    }
+}
+
+impl From<multiaddr::Error> for LibP2pError {
+    fn from(err: multiaddr::Error) -> Self {
+        error!(target: LOG_TARGET, "{}", format!("{:?}", err));
+        Self {
+            code: 301,
+            message: format!("{:?}", err),
+        }
+    }
+}
+
+impl From<NodeIdentityError> for LibP2pError {
+    fn from(err: NodeIdentityError) -> Self {
+        error!(target: LOG_TARGET, "{}", format!("{:?}", err));
+        Self {
+            code: 302,
+            message: format!("{:?}", err),
+        }
+    }
+}
+
+impl From<CommsInitializationError> for LibP2pError {
+    fn from(err: CommsInitializationError) -> Self {
+        error!(target: LOG_TARGET, "{}", format!("{:?}", err));
+        Self {
+            code: 501,
+            message: err.to_friendly_string(),
+        }
+    }
+}
+
+impl From<ServiceInitializationError> for LibP2pError {
+    fn from(err: ServiceInitializationError) -> Self {
+        error!(target: LOG_TARGET, "{}", format!("{:?}", err));
+        Self {
+            code: 502,
+            message: format!("{:?}", err),
+        }
+    }
+}
+
+impl From<DhtOutboundError> for LibP2pError {
+    fn from(err: DhtOutboundError) -> Self {
+        error!(target: LOG_TARGET, "{}", format!("{:?}", err));
+        Self {
+            code: 601,
+            message: format!("{:?}", err),
+        }
+    }
+}
@@ -0,0 +1,617 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+#![deny(unused_must_use)]
+#![deny(unreachable_patterns)]
+#![deny(unknown_lints)]
+
+//! # LibP2p API Definition
+//! A C ABI over the Tari comms and DHT stack for mobile and other embedded clients that need to speak the Tari
+//! peer-to-peer protocol without the full wallet.
+//!
+//! ## Flow
+//! 1. Create a `TariNodeIdentity` with `node_identity_create`.
+//! 2. Start a `TariP2pNode` with `p2p_node_create`, providing the callbacks that will be invoked for inbound domain
+//!    messages and connectivity events. Callbacks are called from a comms worker thread and must not call
+//!    `p2p_node_create`, which returns an error when called from such a thread.
+//! 3. Send domain messages with `p2p_node_send_message`. This never blocks and may be called from a callback.
+//! 4. Call `p2p_node_destroy` to shut down comms and release all resources.
+//!
+//! Every pointer returned by this library must be released with its matching `*_destroy` function.
+
+#![recursion_limit = "1024"]
+
+mod error;
+
+use crate::error::{InterfaceError, LibP2pError};
+use core::ptr;
+use futures::{channel::mpsc, StreamExt};
+use libc::{c_char, c_int, c_uchar, c_uint};
+use log::*;
+use rand::rngs::OsRng;
+use std::{
+    ffi::{CStr, CString},
+    net::SocketAddr,
+    path::PathBuf,
+    slice,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use tari_comms::{
+    connectivity::ConnectivityEvent,
+    multiaddr::Multiaddr,
+    peer_manager::{NodeId, NodeIdentity, PeerFeatures},
+    types::{CommsPublicKey, CommsSecretKey},
+    CommsNode,
+    UnspawnedCommsNode,
+};
+use tari_comms_dht::{domain_message::OutboundDomainMessage, DbConnectionUrl, Dht, DhtConfig};
+use tari_p2p::{
+    comms_connector::{InboundDomainConnector, PeerMessage},
+    initialization,
+    initialization::{CommsConfig, P2pInitializer},
+    transport::TransportType,
+};
+use tari_service_framework::StackBuilder;
+use tari_shutdown::Shutdown;
+use tari_utilities::hex::Hex;
+use tokio::runtime::{Handle, Runtime};
+
+const LOG_TARGET: &str = "p2p_ffi";
+
+pub type TariNodeIdentity = NodeIdentity;
+
+/// Called for every inbound domain message: `(source_public_key_hex, message_type, body, body_len)`.
+/// The pointers are only valid for the duration of the call.
+pub type InboundMessageCallback = unsafe extern "C" fn(*const c_char, c_int, *const c_uchar, c_uint);
+/// Called for every connectivity event: `(event_code, peer_node_id_hex)`. The node id may be null.
+pub type ConnectivityEventCallback = unsafe extern "C" fn(c_int, *const c_char);
+
+pub struct TariP2pNode {
+    comms: CommsNode,
+    dht: Dht,
+    runtime: Runtime,
+    shutdown: Shutdown,
+}
+
+/// Maps a `ConnectivityEvent` to the code passed to the `ConnectivityEventCallback`
+fn connectivity_event_code(event: &ConnectivityEvent) -> (c_int, Option<String>) {
+    use ConnectivityEvent::*;
+    match event {
+        PeerConnected(conn) => (1, Some(conn.peer_node_id().to_hex())),
+        PeerDisconnected(node_id) | ManagedPeerDisconnected(node_id) => (2, Some(node_id.to_hex())),
+        PeerConnectFailed(node_id) | ManagedPeerConnectFailed(node_id) => (3, Some(node_id.to_hex())),
+        PeerBanned(node_id) => (4, Some(node_id.to_hex())),
+        PeerOffline(node_id) => (5, Some(node_id.to_hex())),
+        PeerConnectionWillClose(node_id, _) => (6, Some(node_id.to_hex())),
+        PeerAddressesChanged(identity) => (7, Some(NodeId::from_public_key(identity.public_key()).to_hex())),
+        ConnectivityStateInitialized => (10, None),
+        ConnectivityStateOnline(_) => (11, None),
+        ConnectivityStateDegraded(_) => (12, None),
+        ConnectivityStateOffline => (13, None),
+    }
+}
+
+unsafe fn set_error(error_out: *mut c_int, err: LibP2pError) {
+    let mut code = err.code;
+    ptr::swap(error_out, &mut code as *mut c_int);
+}
+
+/// Returns true if the current thread belongs to a tokio runtime, e.g. when called from within a node callback
+fn is_runtime_thread() -> bool {
+    Handle::try_current().is_ok()
+}
+
+unsafe fn c_str_to_string(s: *const c_char, name: &str) -> Result<String, LibP2pError> {
+    if s.is_null() {
+        return Err(InterfaceError::NullError(name.to_string()).into());
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map(ToString::to_string)
+        .map_err(|_| InterfaceError::InvalidString(name.to_string()).into())
+}
+
+/// -------------------------------- Strings ------------------------------------------------ ///
+
+/// Frees memory for a char array
+///
+/// ## Arguments
+/// `ptr` - The pointer to be freed
+///
+/// ## Returns
+/// `()` - Does not return a value, equivalent to void in C.
+///
+/// # Safety
+/// None
+#[no_mangle]
+pub unsafe extern "C" fn p2p_string_destroy(ptr: *mut c_char) {
+    if !ptr.is_null() {
+        let _ = CString::from_raw(ptr);
+    }
+}
+
+/// -------------------------------------------------------------------------------------------- ///
+
+/// -------------------------------- Node Identity --------------------------------------------- ///
+
+/// Creates a TariNodeIdentity
+///
+/// ## Arguments
+/// `secret_key_hex` - The hex-encoded secret key for this node. If null, a random key is generated.
+/// `public_address` - The public multiaddr of this node, may not be null
+/// `error_out` - Pointer to an int which will be modified to an error code should one occur, may not be null. Functions
+/// as an out parameter.
+///
+/// ## Returns
+/// `*mut TariNodeIdentity` - Pointer to the created identity. Note that it will be ptr::null_mut() if an error
+/// occurred.
+///
+/// # Safety
+/// The ```node_identity_destroy``` function must be called when finished with a TariNodeIdentity to prevent a memory
+/// leak
+#[no_mangle]
+pub unsafe extern "C" fn node_identity_create(
+    secret_key_hex: *const c_char,
+    public_address: *const c_char,
+    error_out: *mut c_int,
+) -> *mut TariNodeIdentity
+{
+    let mut error = 0;
+    ptr::swap(error_out, &mut error as *mut c_int);
+    let result = (|| {
+        let address = c_str_to_string(public_address, "public_address")?.parse::<Multiaddr>()?;
+        let node_identity = if secret_key_hex.is_null() {
+            NodeIdentity::random(&mut OsRng, address, PeerFeatures::COMMUNICATION_CLIENT)?
+        } else {
+            let secret_key = CommsSecretKey::from_hex(&c_str_to_string(secret_key_hex, "secret_key_hex")?)?;
+            NodeIdentity::new(secret_key, address, PeerFeatures::COMMUNICATION_CLIENT)?
+        };
+        Result::<_, LibP2pError>::Ok(node_identity)
+    })();
+
+    match result {
+        Ok(node_identity) => Box::into_raw(Box::new(node_identity)),
+        Err(err) => {
+            set_error(error_out, err);
+            ptr::null_mut()
+        },
+    }
+}
+
+/// Returns the hex-encoded public key of a TariNodeIdentity
+///
+/// ## Arguments
+/// `node_identity` - The pointer to a TariNodeIdentity
+/// `error_out` - Pointer to an int which will be modified to an error code should one occur, may not be null. Functions
+/// as an out parameter.
+///
+/// ## Returns
+/// `*mut c_char` - The hex string or ptr::null_mut() if node_identity is null
+///
+/// # Safety
+/// The ```p2p_string_destroy``` function must be called when finished with the returned string
+#[no_mangle]
+pub unsafe extern "C" fn node_identity_get_public_key_hex(
+    node_identity: *const TariNodeIdentity,
+    error_out: *mut c_int,
+) -> *mut c_char
+{
+    let mut error = 0;
+    ptr::swap(error_out, &mut error as *mut c_int);
+    if node_identity.is_null() {
+        set_error(error_out, InterfaceError::NullError("node_identity".to_string()).into());
+        return ptr::null_mut();
+    }
+    match CString::new((*node_identity).public_key().to_hex()) {
+        Ok(s) => s.into_raw(),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Frees memory for a TariNodeIdentity
+///
+/// ## Arguments
+/// `node_identity` - The pointer to a TariNodeIdentity
+///
+/// ## Returns
+/// `()` - Does not return a value, equivalent to void in C
+///
+/// # Safety
+/// None
+#[no_mangle]
+pub unsafe extern "C" fn node_identity_destroy(node_identity: *mut TariNodeIdentity) {
+    if !node_identity.is_null() {
+        Box::from_raw(node_identity);
+    }
+}
+
+/// -------------------------------------------------------------------------------------------- ///
+
+/// -------------------------------- P2p Node -------------------------------------------------- ///
+
+/// Creates and starts a TariP2pNode
+///
+/// ## Arguments
+/// `node_identity` - The pointer to a TariNodeIdentity. The identity is copied and may be destroyed after this call.
+/// `listener_address` - The multiaddr to listen on, may not be null
+/// `datastore_path` - The directory in which the peer and DHT databases are stored, may not be null
+/// `discovery_timeout_in_secs` - The discovery timeout used when sending to peers whose address is not known
+/// `callback_message_received` - Called for each inbound domain message
+/// `callback_connectivity_event` - Called for each connectivity event
+/// `error_out` - Pointer to an int which will be modified to an error code should one occur, may not be null. Functions
+/// as an out parameter.
+///
+/// ## Returns
+/// `*mut TariP2pNode` - Pointer to the running node or ptr::null_mut() if an error occurred
+///
+/// # Safety
+/// The ```p2p_node_destroy``` function must be called to shut down the node and prevent a memory leak
+#[no_mangle]
+pub unsafe extern "C" fn p2p_node_create(
+    node_identity: *const TariNodeIdentity,
+    listener_address: *const c_char,
+    datastore_path: *const c_char,
+    discovery_timeout_in_secs: c_uint,
+    callback_message_received: InboundMessageCallback,
+    callback_connectivity_event: ConnectivityEventCallback,
+    error_out: *mut c_int,
+) -> *mut TariP2pNode
+{
+    let mut error = 0;
+    ptr::swap(error_out, &mut error as *mut c_int);
+    if node_identity.is_null() {
+        set_error(error_out, InterfaceError::NullError("node_identity".to_string()).into());
+        return ptr::null_mut();
+    }
+    if is_runtime_thread() {
+        set_error(error_out, InterfaceError::CalledFromRuntimeThread.into());
+        return ptr::null_mut();
+    }
+
+    let result = (|| {
+        let listener_address = c_str_to_string(listener_address, "listener_address")?.parse::<Multiaddr>()?;
+        let datastore_path = PathBuf::from(c_str_to_string(datastore_path, "datastore_path")?);
+        let mut runtime = Runtime::new().map_err(|err| InterfaceError::TokioError(err.to_string()))?;
+
+        let config = CommsConfig {
+            node_identity: Arc::new((*node_identity).clone()),
+            transport_type: TransportType::Tcp {
+                listener_address,
+                tor_socks_config: None,
+            },
+            datastore_path: datastore_path.clone(),
+            peer_database_name: "peers".to_string(),
+            max_concurrent_inbound_tasks: 100,
+            max_pending_inbound_per_peer: None,
+            outbound_buffer_size: 100,
+            messaging_shutdown_grace_period: CommsConfig::DEFAULT_MESSAGING_SHUTDOWN_GRACE_PERIOD,
+            dht: DhtConfig {
+                discovery_request_timeout: Duration::from_secs(discovery_timeout_in_secs as u64),
+                database_url: DbConnectionUrl::File(datastore_path.join("dht.sqlite")),
+                auto_join: true,
+                ..Default::default()
+            },
+            allow_test_addresses: false,
+            listener_liveness_allowlist_cidrs: Vec::new(),
+            listener_liveness_max_sessions: 0,
+            user_agent: format!("tari/p2p_ffi/{}", env!("CARGO_PKG_VERSION")),
+            dns_seeds_name_server: SocketAddr::from(([1, 1, 1, 1], 53)),
+            peer_seeds: Default::default(),
+            trusted_peers: Default::default(),
+            dns_seeds: Default::default(),
+            dns_seeds_use_dnssec: true,
+            dial_connect_timeout: CommsConfig::DEFAULT_DIAL_CONNECT_TIMEOUT,
+            noise_handshake_timeout: CommsConfig::DEFAULT_NOISE_HANDSHAKE_TIMEOUT,
+            identity_exchange_timeout: CommsConfig::DEFAULT_IDENTITY_EXCHANGE_TIMEOUT,
+            peer_version_policy: CommsConfig::DEFAULT_PEER_VERSION_POLICY,
+            dial_backoff: Default::default(),
+            log_target_levels: Default::default(),
+            admin_socket_address: None,
+        };
+
+        let shutdown = Shutdown::new();
+        let (inbound_tx, mut inbound_rx) = mpsc::channel::<Arc<PeerMessage>>(100);
+        let transport_type = config.transport_type.clone();
+        let shutdown_signal = shutdown.to_signal();
+        let (comms, dht) = runtime.block_on(async move {
+            let mut handles = StackBuilder::new(shutdown_signal)
+                .add_initializer(P2pInitializer::new(config, InboundDomainConnector::new(inbound_tx)))
+                .build()
+                .await?;
+            let comms = handles
+                .take_handle::<UnspawnedCommsNode>()
+                .ok_or_else(|| InterfaceError::MissingServiceHandle("UnspawnedCommsNode".to_string()))?;
+            let comms = initialization::spawn_comms_using_transport(comms, transport_type).await?;
+            let dht = handles
+                .get_handle::<Dht>()
+                .ok_or_else(|| InterfaceError::MissingServiceHandle("Dht".to_string()))?;
+            Result::<_, LibP2pError>::Ok((comms, dht))
+        })?;
+
+        runtime.spawn(async move {
+            while let Some(msg) = inbound_rx.next().await {
+                let source = match CString::new(msg.source_peer.public_key.to_hex()) {
+                    Ok(source) => source,
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "Dropping inbound message: {}", err);
+                        continue;
+                    },
+                };
+                callback_message_received(
+                    source.as_ptr(),
+                    msg.message_header.message_type,
+                    msg.body.as_ptr(),
+                    msg.body.len() as c_uint,
+                );
+            }
+            debug!(target: LOG_TARGET, "Inbound message stream ended");
+        });
+
+        let mut connectivity_events = comms.connectivity().get_event_subscription();
+        runtime.spawn(async move {
+            while let Some(Ok(event)) = connectivity_events.next().await {
+                let (code, node_id) = connectivity_event_code(&event);
+                let node_id = match node_id.map(CString::new).transpose() {
+                    Ok(node_id) => node_id,
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "Dropping connectivity event: {}", err);
+                        continue;
+                    },
+                };
+                callback_connectivity_event(code, node_id.as_ref().map(|s| s.as_ptr()).unwrap_or_else(ptr::null));
+            }
+        });
+
+        Result::<_, LibP2pError>::Ok(TariP2pNode {
+            comms,
+            dht,
+            runtime,
+            shutdown,
+        })
+    })();
+
+    match result {
+        Ok(node) => Box::into_raw(Box::new(node)),
+        Err(err) => {
+            set_error(error_out, err);
+            ptr::null_mut()
+        },
+    }
+}
+
+/// Sends a domain message directly to a peer, using discovery if the peer is not known. The send is queued on the
+/// node's runtime and this function returns without waiting for it, so it is safe to call from a callback. Errors that
+/// occur while sending are logged.
+///
+/// ## Arguments
+/// `node` - The pointer to a TariP2pNode
+/// `dest_public_key_hex` - The hex-encoded public key of the destination peer
+/// `message_type` - The domain message type
+/// `body` - Pointer to the message body bytes
+/// `body_len` - The number of bytes in body
+/// `error_out` - Pointer to an int which will be modified to an error code should one occur, may not be null. Functions
+/// as an out parameter.
+///
+/// ## Returns
+/// `bool` - Returns true if the message was queued for sending, otherwise false
+///
+/// # Safety
+/// None
+#[no_mangle]
+pub unsafe extern "C" fn p2p_node_send_message(
+    node: *mut TariP2pNode,
+    dest_public_key_hex: *const c_char,
+    message_type: c_int,
+    body: *const c_uchar,
+    body_len: c_uint,
+    error_out: *mut c_int,
+) -> bool
+{
+    let mut error = 0;
+    ptr::swap(error_out, &mut error as *mut c_int);
+    if node.is_null() {
+        set_error(error_out, InterfaceError::NullError("node".to_string()).into());
+        return false;
+    }
+    if body.is_null() {
+        set_error(error_out, InterfaceError::NullError("body".to_string()).into());
+        return false;
+    }
+
+    let result = (|| {
+        let dest = CommsPublicKey::from_hex(&c_str_to_string(dest_public_key_hex, "dest_public_key_hex")?)?;
+        let body = slice::from_raw_parts(body, body_len as usize).to_vec();
+        let mut outbound = (*node).dht.outbound_requester();
+        (*node).runtime.spawn(async move {
+            if let Err(err) = outbound
+                .send_direct(dest, OutboundDomainMessage::new(message_type, body))
+                .await
+            {
+                warn!(target: LOG_TARGET, "Failed to send message: {}", err);
+            }
+        });
+        Result::<_, LibP2pError>::Ok(())
+    })();
+
+    match result {
+        Ok(_) => true,
+        Err(err) => {
+            set_error(error_out, err);
+            false
+        },
+    }
+}
+
+/// Shuts down and frees memory for a TariP2pNode. When called from a callback, the node is shut down on a separate
+/// thread and this function returns immediately.
+///
+/// ## Arguments
+/// `node` - The pointer to a TariP2pNode
+///
+/// ## Returns
+/// `()` - Does not return a value, equivalent to void in C
+///
+/// # Safety
+/// None
+#[no_mangle]
+pub unsafe extern "C" fn p2p_node_destroy(node: *mut TariP2pNode) {
+    if !node.is_null() {
+        let mut node = Box::from_raw(node);
+        let _ = node.shutdown.trigger();
+        let TariP2pNode { comms, mut runtime, .. } = *node;
+        if is_runtime_thread() {
+            // Blocking on or dropping a runtime from within a runtime panics
+            thread::spawn(move || runtime.block_on(comms.wait_until_shutdown()));
+        } else {
+            runtime.block_on(comms.wait_until_shutdown());
+        }
+    }
+}
+
+/// -------------------------------------------------------------------------------------------- ///
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    unsafe extern "C" fn message_received_callback(_: *const c_char, _: c_int, _: *const c_uchar, _: c_uint) {}
+
+    unsafe extern "C" fn connectivity_event_callback(_: c_int, _: *const c_char) {}
+
+    #[test]
+    fn test_node_identity() {
+        unsafe {
+            let mut error = 0;
+            let error_ptr = &mut error as *mut c_int;
+            let address = CString::new("/ip4/127.0.0.1/tcp/0").unwrap();
+            let identity = node_identity_create(ptr::null(), address.as_ptr(), error_ptr);
+            assert_eq!(error, 0);
+            assert!(!identity.is_null());
+
+            let pk_hex = node_identity_get_public_key_hex(identity, error_ptr);
+            assert_eq!(error, 0);
+            assert_eq!(
+                CStr::from_ptr(pk_hex).to_str().unwrap(),
+                (*identity).public_key().to_hex()
+            );
+
+            let sk = CString::new((*identity).secret_key().to_hex()).unwrap();
+            let identity2 = node_identity_create(sk.as_ptr(), address.as_ptr(), error_ptr);
+            assert_eq!(error, 0);
+            assert_eq!((*identity2).public_key(), (*identity).public_key());
+
+            p2p_string_destroy(pk_hex);
+            node_identity_destroy(identity);
+            node_identity_destroy(identity2);
+        }
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        unsafe {
+            let mut error = 0;
+            let error_ptr = &mut error as *mut c_int;
+            let identity = node_identity_create(ptr::null(), ptr::null(), error_ptr);
+            assert!(identity.is_null());
+            assert_eq!(error, LibP2pError::from(InterfaceError::NullError("".into())).code);
+
+            let bad = CString::new("not-a-multiaddr").unwrap();
+            let identity = node_identity_create(ptr::null(), bad.as_ptr(), error_ptr);
+            assert!(identity.is_null());
+            assert_ne!(error, 0);
+
+            let pk = node_identity_get_public_key_hex(ptr::null(), error_ptr);
+            assert!(pk.is_null());
+            assert_eq!(error, 1);
+
+            assert!(!p2p_node_send_message(
+                ptr::null_mut(),
+                ptr::null(),
+                0,
+                ptr::null(),
+                0,
+                error_ptr
+            ));
+            assert_eq!(error, 1);
+
+            // Destroying null pointers is a no-op
+            p2p_string_destroy(ptr::null_mut());
+            node_identity_destroy(ptr::null_mut());
+            p2p_node_destroy(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_node_create_and_destroy() {
+        unsafe {
+            let mut error = 0;
+            let error_ptr = &mut error as *mut c_int;
+            let address = CString::new("/ip4/127.0.0.1/tcp/0").unwrap();
+            let identity = node_identity_create(ptr::null(), address.as_ptr(), error_ptr);
+            let tmp = tempdir().unwrap();
+            let path = CString::new(tmp.path().to_str().unwrap()).unwrap();
+            let node = p2p_node_create(
+                identity,
+                address.as_ptr(),
+                path.as_ptr(),
+                30,
+                message_received_callback,
+                connectivity_event_callback,
+                error_ptr,
+            );
+            assert_eq!(error, 0);
+            assert!(!node.is_null());
+            node_identity_destroy(identity);
+            p2p_node_destroy(node);
+        }
+    }
+
+    #[test]
+    fn test_node_create_from_runtime_thread() {
+        let mut rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            unsafe {
+                let mut error = 0;
+                let error_ptr = &mut error as *mut c_int;
+                let address = CString::new("/ip4/127.0.0.1/tcp/0").unwrap();
+                let identity = node_identity_create(ptr::null(), address.as_ptr(), error_ptr);
+                let tmp = tempdir().unwrap();
+                let path = CString::new(tmp.path().to_str().unwrap()).unwrap();
+                let node = p2p_node_create(
+                    identity,
+                    address.as_ptr(),
+                    path.as_ptr(),
+                    30,
+                    message_received_callback,
+                    connectivity_event_callback,
+                    error_ptr,
+                );
+                assert!(node.is_null());
+                assert_eq!(error, LibP2pError::from(InterfaceError::CalledFromRuntimeThread).code);
+                node_identity_destroy(identity);
+            }
+        });
+    }
+}
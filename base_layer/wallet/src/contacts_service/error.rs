@@ -30,6 +30,8 @@ use thiserror::Error;
 pub enum ContactsServiceError {
     #[error("Contact is not found")]
     ContactNotFound,
+    #[error("Peer is not being watched")]
+    PeerNotWatched,
     #[error("Received incorrect response from service request")]
     UnexpectedApiResponse,
     #[error("Contacts service storage error: `{0}`")]
@@ -20,9 +20,12 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::contacts_service::{error::ContactsServiceError, storage::database::Contact};
+use crate::contacts_service::{error::ContactsServiceError, presence::ContactPresence, storage::database::Contact};
+use futures::{stream::Fuse, StreamExt};
+use std::sync::Arc;
 use tari_comms::types::CommsPublicKey;
 use tari_service_framework::reply_channel::SenderService;
+use tokio::sync::broadcast;
 use tower::Service;
 
 #[derive(Debug)]
@@ -31,6 +34,8 @@ pub enum ContactsServiceRequest {
     UpsertContact(Contact),
     RemoveContact(CommsPublicKey),
     GetContacts,
+    WatchPeer(CommsPublicKey),
+    UnwatchPeer(CommsPublicKey),
 }
 
 #[derive(Debug)]
@@ -39,17 +44,39 @@ pub enum ContactsServiceResponse {
     ContactRemoved(Contact),
     Contact(Contact),
     Contacts(Vec<Contact>),
+    PeerWatched(ContactPresence),
+    PeerUnwatched,
+}
+
+pub type ContactsServiceEventSender = broadcast::Sender<Arc<ContactsServiceEvent>>;
+pub type ContactsServiceEventReceiver = broadcast::Receiver<Arc<ContactsServiceEvent>>;
+
+/// Events that can be published on the Contacts Service Event Stream
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContactsServiceEvent {
+    /// The presence of a watched peer changed
+    PresenceUpdated(ContactPresence),
 }
 
 #[derive(Clone)]
 pub struct ContactsServiceHandle {
     handle: SenderService<ContactsServiceRequest, Result<ContactsServiceResponse, ContactsServiceError>>,
+    event_stream_sender: ContactsServiceEventSender,
 }
 impl ContactsServiceHandle {
     pub fn new(
         handle: SenderService<ContactsServiceRequest, Result<ContactsServiceResponse, ContactsServiceError>>,
-    ) -> Self {
-        Self { handle }
+        event_stream_sender: ContactsServiceEventSender,
+    ) -> Self
+    {
+        Self {
+            handle,
+            event_stream_sender,
+        }
+    }
+
+    pub fn get_event_stream_fused(&self) -> Fuse<ContactsServiceEventReceiver> {
+        self.event_stream_sender.subscribe().fuse()
     }
 
     pub async fn get_contact(&mut self, pub_key: CommsPublicKey) -> Result<Contact, ContactsServiceError> {
@@ -87,4 +114,21 @@ impl ContactsServiceHandle {
             _ => Err(ContactsServiceError::UnexpectedApiResponse),
         }
     }
+
+    /// Start watching the presence of the given peer, returning its current presence. Subsequent changes are
+    /// published as `ContactsServiceEvent::PresenceUpdated` events on the event stream.
+    pub async fn watch_peer(&mut self, pub_key: CommsPublicKey) -> Result<ContactPresence, ContactsServiceError> {
+        match self.handle.call(ContactsServiceRequest::WatchPeer(pub_key)).await?? {
+            ContactsServiceResponse::PeerWatched(presence) => Ok(presence),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
+
+    /// Stop watching the presence of the given peer
+    pub async fn unwatch_peer(&mut self, pub_key: CommsPublicKey) -> Result<(), ContactsServiceError> {
+        match self.handle.call(ContactsServiceRequest::UnwatchPeer(pub_key)).await?? {
+            ContactsServiceResponse::PeerUnwatched => Ok(()),
+            _ => Err(ContactsServiceError::UnexpectedApiResponse),
+        }
+    }
 }
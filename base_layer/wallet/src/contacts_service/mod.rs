@@ -22,6 +22,7 @@
 
 pub mod error;
 pub mod handle;
+pub mod presence;
 pub mod service;
 pub mod storage;
 
@@ -32,12 +33,15 @@ use crate::contacts_service::{
 };
 use futures::{future, Future};
 use log::*;
+use tari_comms::connectivity::ConnectivityRequester;
+use tari_comms_dht::Dht;
 use tari_service_framework::{
     reply_channel,
     ServiceInitializationError,
     ServiceInitializer,
     ServiceInitializerContext,
 };
+use tokio::sync::broadcast;
 
 const LOG_TARGET: &str = "wallet::contacts_service::initializer";
 
@@ -62,8 +66,9 @@ where T: ContactsBackend + 'static
 
     fn initialize(&mut self, context: ServiceInitializerContext) -> Self::Future {
         let (sender, receiver) = reply_channel::unbounded();
+        let (publisher, _) = broadcast::channel(100);
 
-        let contacts_handle = ContactsServiceHandle::new(sender);
+        let contacts_handle = ContactsServiceHandle::new(sender, publisher.clone());
 
         // Register handle before waiting for handles to be ready
         context.register_handle(contacts_handle);
@@ -76,8 +81,20 @@ where T: ContactsBackend + 'static
         let shutdown_signal = context.get_shutdown_signal();
 
         context.spawn_when_ready(move |handles| async move {
-            let service =
-                ContactsService::new(receiver, ContactsDatabase::new(backend), handles.get_shutdown_signal()).start();
+            let mut service = ContactsService::new(
+                receiver,
+                ContactsDatabase::new(backend),
+                handles.get_shutdown_signal(),
+                publisher,
+            );
+            // Presence of watched peers is tracked when the contacts service is running alongside comms and the DHT
+            if let Some((connectivity, dht)) = handles
+                .get_handle::<ConnectivityRequester>()
+                .zip(handles.get_handle::<Dht>())
+            {
+                service = service.with_presence_sources(connectivity, dht.subscribe_dht_events());
+            }
+            let service = service.start();
             futures::pin_mut!(service);
             future::select(service, shutdown_signal).await;
             info!(target: LOG_TARGET, "Contacts service shutdown");
@@ -0,0 +1,270 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Error, Formatter},
+    time::Duration,
+};
+use tari_comms::{peer_manager::NodeId, types::CommsPublicKey};
+
+/// The presence of a watched peer on the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresenceStatus {
+    /// The peer is connected to this node
+    Online,
+    /// The peer is not connected to this node, but was connected or seen on the network within the recently seen
+    /// window
+    RecentlySeen,
+    /// The peer has not been connected or seen on the network within the recently seen window
+    Offline,
+}
+
+impl Display for PresenceStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            PresenceStatus::Online => write!(f, "Online"),
+            PresenceStatus::RecentlySeen => write!(f, "Recently Seen"),
+            PresenceStatus::Offline => write!(f, "Offline"),
+        }
+    }
+}
+
+/// The presence of a watched peer, published each time it changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContactPresence {
+    pub public_key: CommsPublicKey,
+    pub status: PresenceStatus,
+    /// The last time that the peer was connected or seen on the network since it was watched, if at all
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+struct WatchedPeer {
+    public_key: CommsPublicKey,
+    is_connected: bool,
+    last_seen: Option<DateTime<Utc>>,
+    status: PresenceStatus,
+    /// The last seen time included in the last presence returned for this peer
+    published_last_seen: Option<DateTime<Utc>>,
+}
+
+impl WatchedPeer {
+    fn to_presence(&self) -> ContactPresence {
+        ContactPresence {
+            public_key: self.public_key.clone(),
+            status: self.status,
+            last_seen: self.last_seen,
+        }
+    }
+}
+
+/// Combines connection events and DHT observations of watched peers into a presence status for each peer.
+///
+/// A peer is `Online` while it is connected. Once it disconnects, or if it is seen on the network (e.g. it sends a
+/// join or discovery message, or a store and forward provider returns a message that it authored) without connecting,
+/// it is `RecentlySeen` until the recently seen window has passed since it was last seen, after which it is `Offline`.
+pub struct PresenceTracker {
+    recently_seen_window: ChronoDuration,
+    peers: HashMap<NodeId, WatchedPeer>,
+}
+
+impl PresenceTracker {
+    pub fn new(recently_seen_window: Duration) -> Self {
+        Self {
+            recently_seen_window: ChronoDuration::from_std(recently_seen_window)
+                .unwrap_or_else(|_| ChronoDuration::max_value()),
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Start watching the given peer, returning its current presence. If the peer is already watched, its connection
+    /// state is updated.
+    pub fn watch(&mut self, public_key: CommsPublicKey, is_connected: bool, now: DateTime<Utc>) -> ContactPresence {
+        let node_id = NodeId::from_public_key(&public_key);
+        let peer = self.peers.entry(node_id.clone()).or_insert_with(|| WatchedPeer {
+            public_key,
+            is_connected,
+            last_seen: None,
+            status: PresenceStatus::Offline,
+            published_last_seen: None,
+        });
+        peer.is_connected = is_connected;
+        if is_connected {
+            peer.last_seen = Some(now);
+        }
+        self.update(&node_id, now);
+        self.peers[&node_id].to_presence()
+    }
+
+    /// Stop watching the given peer. Returns false if the peer was not watched.
+    pub fn unwatch(&mut self, public_key: &CommsPublicKey) -> bool {
+        self.peers.remove(&NodeId::from_public_key(public_key)).is_some()
+    }
+
+    pub fn is_watched(&self, public_key: &CommsPublicKey) -> bool {
+        self.peers.contains_key(&NodeId::from_public_key(public_key))
+    }
+
+    /// Record that a watched peer connected to this node
+    pub fn on_connected(&mut self, node_id: &NodeId, now: DateTime<Utc>) -> Option<ContactPresence> {
+        let peer = self.peers.get_mut(node_id)?;
+        peer.is_connected = true;
+        peer.last_seen = Some(now);
+        self.update(node_id, now)
+    }
+
+    /// Record that a watched peer disconnected from this node
+    pub fn on_disconnected(&mut self, node_id: &NodeId, now: DateTime<Utc>) -> Option<ContactPresence> {
+        let peer = self.peers.get_mut(node_id)?;
+        if !peer.is_connected {
+            return None;
+        }
+        peer.is_connected = false;
+        peer.last_seen = Some(now);
+        self.update(node_id, now)
+    }
+
+    /// Record that a watched peer was seen on the network at `seen_at`
+    pub fn on_seen(
+        &mut self,
+        public_key: &CommsPublicKey,
+        seen_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Option<ContactPresence>
+    {
+        let node_id = NodeId::from_public_key(public_key);
+        let peer = self.peers.get_mut(&node_id)?;
+        if peer.last_seen.map(|last_seen| last_seen >= seen_at).unwrap_or(false) {
+            return None;
+        }
+        peer.last_seen = Some(seen_at);
+        self.update(&node_id, now)
+    }
+
+    /// Move peers that have not been seen within the recently seen window to `Offline`, returning their presence
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Vec<ContactPresence> {
+        let node_ids = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| peer.status == PresenceStatus::RecentlySeen)
+            .map(|(node_id, _)| node_id.clone())
+            .collect::<Vec<_>>();
+        node_ids
+            .iter()
+            .filter_map(|node_id| self.update(node_id, now))
+            .collect()
+    }
+
+    /// Recalculates the status of the peer. Returns the presence of the peer if its status changed, or if it is
+    /// `RecentlySeen` and was seen more recently than the last returned presence.
+    fn update(&mut self, node_id: &NodeId, now: DateTime<Utc>) -> Option<ContactPresence> {
+        let recently_seen_window = self.recently_seen_window;
+        let peer = self.peers.get_mut(node_id)?;
+        let is_recently_seen = peer
+            .last_seen
+            .map(|last_seen| now.signed_duration_since(last_seen) <= recently_seen_window)
+            .unwrap_or(false);
+        let status = if peer.is_connected {
+            PresenceStatus::Online
+        } else if is_recently_seen {
+            PresenceStatus::RecentlySeen
+        } else {
+            PresenceStatus::Offline
+        };
+
+        let is_seen_more_recently =
+            status == PresenceStatus::RecentlySeen && peer.last_seen != peer.published_last_seen;
+        if status == peer.status && !is_seen_more_recently {
+            return None;
+        }
+        peer.status = status;
+        peer.published_last_seen = peer.last_seen;
+        Some(peer.to_presence())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::OsRng;
+    use tari_crypto::keys::PublicKey;
+
+    fn make_public_key() -> CommsPublicKey {
+        let (_, public_key) = CommsPublicKey::random_keypair(&mut OsRng);
+        public_key
+    }
+
+    #[test]
+    fn presence_transitions() {
+        let mut tracker = PresenceTracker::new(Duration::from_secs(60));
+        let public_key = make_public_key();
+        let node_id = NodeId::from_public_key(&public_key);
+        let now = Utc::now();
+
+        let presence = tracker.watch(public_key.clone(), false, now);
+        assert_eq!(presence.status, PresenceStatus::Offline);
+        assert!(presence.last_seen.is_none());
+
+        let presence = tracker.on_connected(&node_id, now).unwrap();
+        assert_eq!(presence.status, PresenceStatus::Online);
+        // Seen while online does not change the status
+        assert!(tracker.on_seen(&public_key, now, now).is_none());
+
+        let disconnected_at = now + ChronoDuration::seconds(10);
+        let presence = tracker.on_disconnected(&node_id, disconnected_at).unwrap();
+        assert_eq!(presence.status, PresenceStatus::RecentlySeen);
+        assert_eq!(presence.last_seen, Some(disconnected_at));
+
+        // An observation older than the last time it was seen is ignored
+        assert!(tracker.on_seen(&public_key, now, disconnected_at).is_none());
+        let seen_at = disconnected_at + ChronoDuration::seconds(10);
+        let presence = tracker.on_seen(&public_key, seen_at, seen_at).unwrap();
+        assert_eq!(presence.status, PresenceStatus::RecentlySeen);
+        assert_eq!(presence.last_seen, Some(seen_at));
+
+        assert!(tracker.expire(seen_at + ChronoDuration::seconds(60)).is_empty());
+        let expired = tracker.expire(seen_at + ChronoDuration::seconds(61));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].status, PresenceStatus::Offline);
+        assert_eq!(expired[0].last_seen, Some(seen_at));
+        assert!(tracker.expire(seen_at + ChronoDuration::seconds(120)).is_empty());
+    }
+
+    #[test]
+    fn unwatched_peers_are_ignored() {
+        let mut tracker = PresenceTracker::new(Duration::from_secs(60));
+        let public_key = make_public_key();
+        let node_id = NodeId::from_public_key(&public_key);
+        let now = Utc::now();
+
+        assert!(tracker.on_connected(&node_id, now).is_none());
+        assert!(tracker.on_seen(&public_key, now, now).is_none());
+
+        let presence = tracker.watch(public_key.clone(), true, now);
+        assert_eq!(presence.status, PresenceStatus::Online);
+        assert!(tracker.is_watched(&public_key));
+        assert!(tracker.unwatch(&public_key));
+        assert!(!tracker.unwatch(&public_key));
+        assert!(tracker.on_disconnected(&node_id, now).is_none());
+    }
+}
@@ -22,16 +22,31 @@
 
 use crate::contacts_service::{
     error::ContactsServiceError,
-    handle::{ContactsServiceRequest, ContactsServiceResponse},
+    handle::{ContactsServiceEvent, ContactsServiceEventSender, ContactsServiceRequest, ContactsServiceResponse},
+    presence::{ContactPresence, PresenceTracker},
     storage::database::{ContactsBackend, ContactsDatabase},
 };
-use futures::{pin_mut, StreamExt};
+use chrono::Utc;
+use futures::{pin_mut, stream, StreamExt};
 use log::*;
+use std::{sync::Arc, time::Duration};
+use tari_comms::{
+    connectivity::{ConnectivityEvent, ConnectivityRequester},
+    peer_manager::NodeId,
+    types::CommsPublicKey,
+};
+use tari_comms_dht::event::{DhtEvent, DhtEventReceiver};
 use tari_service_framework::reply_channel;
 use tari_shutdown::ShutdownSignal;
+use tokio::time;
 
 const LOG_TARGET: &str = "wallet:contacts_service";
 
+/// A watched peer that is not connected is `RecentlySeen` for this long after it was last seen, and `Offline` after
+const PRESENCE_RECENTLY_SEEN_WINDOW: Duration = Duration::from_secs(30 * 60);
+/// How often watched peers are checked for the end of their recently seen window
+const PRESENCE_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
 pub struct ContactsService<T>
 where T: ContactsBackend + 'static
 {
@@ -39,6 +54,10 @@ where T: ContactsBackend + 'static
     request_stream:
         Option<reply_channel::Receiver<ContactsServiceRequest, Result<ContactsServiceResponse, ContactsServiceError>>>,
     shutdown_signal: Option<ShutdownSignal>,
+    event_publisher: ContactsServiceEventSender,
+    connectivity: Option<ConnectivityRequester>,
+    dht_events: Option<DhtEventReceiver>,
+    presence: PresenceTracker,
 }
 
 impl<T> ContactsService<T>
@@ -52,15 +71,28 @@ where T: ContactsBackend + 'static
 
         db: ContactsDatabase<T>,
         shutdown_signal: ShutdownSignal,
+        event_publisher: ContactsServiceEventSender,
     ) -> Self
     {
         Self {
             db,
             request_stream: Some(request_stream),
             shutdown_signal: Some(shutdown_signal),
+            event_publisher,
+            connectivity: None,
+            dht_events: None,
+            presence: PresenceTracker::new(PRESENCE_RECENTLY_SEEN_WINDOW),
         }
     }
 
+    /// Use connection events and DHT presence signals to track the presence of watched peers. Without these, watched
+    /// peers are reported as `Offline`.
+    pub fn with_presence_sources(mut self, connectivity: ConnectivityRequester, dht_events: DhtEventReceiver) -> Self {
+        self.connectivity = Some(connectivity);
+        self.dht_events = Some(dht_events);
+        self
+    }
+
     pub async fn start(mut self) -> Result<(), ContactsServiceError> {
         let request_stream = self
             .request_stream
@@ -75,6 +107,15 @@ where T: ContactsBackend + 'static
             .expect("Output Manager Service initialized without shutdown signal");
         pin_mut!(shutdown);
 
+        // Either stream is immediately terminated if the corresponding presence source was not provided
+        let connectivity_events = stream::iter(self.connectivity.as_ref().map(|c| c.get_event_subscription()))
+            .flatten()
+            .fuse();
+        pin_mut!(connectivity_events);
+        let dht_events = stream::iter(self.dht_events.take()).flatten().fuse();
+        pin_mut!(dht_events);
+        let mut presence_expiry_ticker = time::interval(PRESENCE_EXPIRY_CHECK_INTERVAL).fuse();
+
         info!(target: LOG_TARGET, "Contacts Service started");
         loop {
             futures::select! {
@@ -89,6 +130,23 @@ where T: ContactsBackend + 'static
                         e
                     });
                 },
+                event = connectivity_events.select_next_some() => {
+                    match event {
+                        Ok(event) => self.handle_connectivity_event(&*event),
+                        Err(e) => warn!(target: LOG_TARGET, "Error reading connectivity event stream: {:?}", e),
+                    }
+                },
+                event = dht_events.select_next_some() => {
+                    match event {
+                        Ok(event) => self.handle_dht_event(&*event),
+                        Err(e) => warn!(target: LOG_TARGET, "Error reading DHT event stream: {:?}", e),
+                    }
+                },
+                _ = presence_expiry_ticker.select_next_some() => {
+                    for presence in self.presence.expire(Utc::now()) {
+                        self.publish_presence(presence);
+                    }
+                },
                 _ = shutdown => {
                     info!(target: LOG_TARGET, "Contacts service shutting down because it received the shutdown signal");
                     break;
@@ -131,6 +189,77 @@ where T: ContactsBackend + 'static
             ContactsServiceRequest::GetContacts => {
                 Ok(self.db.get_contacts().await.map(ContactsServiceResponse::Contacts)?)
             },
+            ContactsServiceRequest::WatchPeer(pk) => {
+                let presence = self.watch_peer(pk).await;
+                info!(
+                    target: LOG_TARGET,
+                    "Watching presence of peer {}: {}", presence.public_key, presence.status
+                );
+                self.publish_presence(presence.clone());
+                Ok(ContactsServiceResponse::PeerWatched(presence))
+            },
+            ContactsServiceRequest::UnwatchPeer(pk) => {
+                if !self.presence.unwatch(&pk) {
+                    return Err(ContactsServiceError::PeerNotWatched);
+                }
+                Ok(ContactsServiceResponse::PeerUnwatched)
+            },
         }
     }
+
+    async fn watch_peer(&mut self, public_key: CommsPublicKey) -> ContactPresence {
+        let is_connected = match self.connectivity.as_mut() {
+            Some(connectivity) => connectivity
+                .get_connection(NodeId::from_public_key(&public_key))
+                .await
+                .map(|conn| conn.is_some())
+                .unwrap_or_else(|err| {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Failed to check connection to watched peer: {}", err
+                    );
+                    false
+                }),
+            None => false,
+        };
+        self.presence.watch(public_key, is_connected, Utc::now())
+    }
+
+    fn handle_connectivity_event(&mut self, event: &ConnectivityEvent) {
+        use ConnectivityEvent::*;
+        let presence = match event {
+            PeerConnected(conn) => self.presence.on_connected(conn.peer_node_id(), Utc::now()),
+            PeerDisconnected(node_id) | ManagedPeerDisconnected(node_id) => {
+                self.presence.on_disconnected(node_id, Utc::now())
+            },
+            _ => None,
+        };
+        if let Some(presence) = presence {
+            self.publish_presence(presence);
+        }
+    }
+
+    fn handle_dht_event(&mut self, event: &DhtEvent) {
+        if let DhtEvent::PeerSeen(public_key, source, seen_at) = event {
+            if let Some(presence) = self.presence.on_seen(public_key, *seen_at, Utc::now()) {
+                debug!(
+                    target: LOG_TARGET,
+                    "Watched peer {} seen at {} ({:?})", public_key, seen_at, source
+                );
+                self.publish_presence(presence);
+            }
+        }
+    }
+
+    fn publish_presence(&self, presence: ContactPresence) {
+        let _ = self
+            .event_publisher
+            .send(Arc::new(ContactsServiceEvent::PresenceUpdated(presence)))
+            .map_err(|_| {
+                trace!(
+                    target: LOG_TARGET,
+                    "Could not publish ContactsServiceEvent as there are no subscribers"
+                )
+            });
+    }
 }
@@ -146,7 +146,9 @@ pub async fn create_wallet(
         datastore_path: datastore_path.clone(),
         peer_database_name: random_string(8),
         max_concurrent_inbound_tasks: 100,
+        max_pending_inbound_per_peer: None,
         outbound_buffer_size: 100,
+        messaging_shutdown_grace_period: CommsConfig::DEFAULT_MESSAGING_SHUTDOWN_GRACE_PERIOD,
         user_agent: "/tari/wallet/test".to_string(),
         dht: DhtConfig {
             discovery_request_timeout: Duration::from_secs(30),
@@ -159,7 +161,15 @@ pub async fn create_wallet(
         dns_seeds: Default::default(),
         dns_seeds_name_server: "1.1.1.1:53".parse().unwrap(),
         dns_seeds_use_dnssec: false,
+        dial_connect_timeout: CommsConfig::DEFAULT_DIAL_CONNECT_TIMEOUT,
+        noise_handshake_timeout: CommsConfig::DEFAULT_NOISE_HANDSHAKE_TIMEOUT,
+        identity_exchange_timeout: CommsConfig::DEFAULT_IDENTITY_EXCHANGE_TIMEOUT,
+        peer_version_policy: CommsConfig::DEFAULT_PEER_VERSION_POLICY,
+        dial_backoff: Default::default(),
+        log_target_levels: Default::default(),
+        admin_socket_address: None,
         peer_seeds: Default::default(),
+        trusted_peers: Default::default(),
     };
 
     let config = WalletConfig::new(comms_config, factories, None, None, Network::Stibbons, None, None, None);
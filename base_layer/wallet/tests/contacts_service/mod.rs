@@ -21,6 +21,7 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::support::utils::random_string;
+use futures::StreamExt;
 use rand::rngs::OsRng;
 use tari_core::transactions::types::PublicKey;
 use tari_crypto::keys::PublicKey as PublicKeyTrait;
@@ -29,7 +30,8 @@ use tari_shutdown::Shutdown;
 use tari_wallet::{
     contacts_service::{
         error::{ContactsServiceError, ContactsServiceStorageError},
-        handle::ContactsServiceHandle,
+        handle::{ContactsServiceEvent, ContactsServiceHandle},
+        presence::PresenceStatus,
         storage::{
             database::{Contact, ContactsBackend, ContactsDatabase, DbKey},
             memory_db::ContactsServiceMemoryDatabase,
@@ -184,3 +186,30 @@ fn contacts_service_sqlite_db() {
     let connection = run_migration_and_create_sqlite_connection(&db_path).unwrap();
     test_contacts_service(ContactsServiceSqliteDatabase::new(connection));
 }
+
+#[test]
+fn contacts_service_watch_peer() {
+    let mut runtime = Runtime::new().unwrap();
+    let (mut contacts_service, _shutdown) = setup_contacts_service(&mut runtime, ContactsServiceMemoryDatabase::new());
+    let mut event_stream = contacts_service.get_event_stream_fused();
+
+    let (_secret_key, public_key) = PublicKey::random_keypair(&mut OsRng);
+    // Without comms and the DHT there are no presence signals, so the peer is offline
+    let presence = runtime
+        .block_on(contacts_service.watch_peer(public_key.clone()))
+        .unwrap();
+    assert_eq!(presence.public_key, public_key);
+    assert_eq!(presence.status, PresenceStatus::Offline);
+    assert!(presence.last_seen.is_none());
+
+    let event = runtime.block_on(event_stream.next()).unwrap().unwrap();
+    assert_eq!(*event, ContactsServiceEvent::PresenceUpdated(presence));
+
+    runtime
+        .block_on(contacts_service.unwatch_peer(public_key.clone()))
+        .unwrap();
+    assert_eq!(
+        runtime.block_on(contacts_service.unwatch_peer(public_key)),
+        Err(ContactsServiceError::PeerNotWatched)
+    );
+}
@@ -94,6 +94,8 @@ pub fn create_dummy_message<T>(inner: T, public_key: &CommsPublicKey) -> DomainM
             destination: Default::default(),
             message_tag: MessageTag::new(),
             expires: None,
+            mailbox_tag: Vec::new(),
+            sequence: 0,
         },
         authenticated_origin: None,
         source_peer: peer_source,
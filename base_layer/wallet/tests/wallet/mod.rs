@@ -101,7 +101,9 @@ async fn create_wallet(
         datastore_path: data_path.to_path_buf(),
         peer_database_name: random_string(8),
         max_concurrent_inbound_tasks: 100,
+        max_pending_inbound_per_peer: None,
         outbound_buffer_size: 100,
+        messaging_shutdown_grace_period: CommsConfig::DEFAULT_MESSAGING_SHUTDOWN_GRACE_PERIOD,
         dht: DhtConfig {
             discovery_request_timeout: Duration::from_secs(1),
             auto_join: true,
@@ -114,8 +116,16 @@ async fn create_wallet(
         user_agent: "tari/test-wallet".to_string(),
         dns_seeds_name_server: DEFAULT_DNS_SEED_RESOLVER.parse().unwrap(),
         peer_seeds: Default::default(),
+        trusted_peers: Default::default(),
         dns_seeds: Default::default(),
         dns_seeds_use_dnssec: false,
+        dial_connect_timeout: CommsConfig::DEFAULT_DIAL_CONNECT_TIMEOUT,
+        noise_handshake_timeout: CommsConfig::DEFAULT_NOISE_HANDSHAKE_TIMEOUT,
+        identity_exchange_timeout: CommsConfig::DEFAULT_IDENTITY_EXCHANGE_TIMEOUT,
+        peer_version_policy: CommsConfig::DEFAULT_PEER_VERSION_POLICY,
+        dial_backoff: Default::default(),
+        log_target_levels: Default::default(),
+        admin_socket_address: None,
     };
 
     let sql_database_path = comms_config
@@ -587,7 +597,9 @@ async fn test_import_utxo() {
         datastore_path: temp_dir.path().to_path_buf(),
         peer_database_name: random_string(8),
         max_concurrent_inbound_tasks: 100,
+        max_pending_inbound_per_peer: None,
         outbound_buffer_size: 100,
+        messaging_shutdown_grace_period: CommsConfig::DEFAULT_MESSAGING_SHUTDOWN_GRACE_PERIOD,
         dht: Default::default(),
         allow_test_addresses: true,
         listener_liveness_allowlist_cidrs: Vec::new(),
@@ -595,8 +607,16 @@ async fn test_import_utxo() {
         user_agent: "tari/test-wallet".to_string(),
         dns_seeds_name_server: DEFAULT_DNS_SEED_RESOLVER.parse().unwrap(),
         peer_seeds: Default::default(),
+        trusted_peers: Default::default(),
         dns_seeds: Default::default(),
         dns_seeds_use_dnssec: false,
+        dial_connect_timeout: CommsConfig::DEFAULT_DIAL_CONNECT_TIMEOUT,
+        noise_handshake_timeout: CommsConfig::DEFAULT_NOISE_HANDSHAKE_TIMEOUT,
+        identity_exchange_timeout: CommsConfig::DEFAULT_IDENTITY_EXCHANGE_TIMEOUT,
+        peer_version_policy: CommsConfig::DEFAULT_PEER_VERSION_POLICY,
+        dial_backoff: Default::default(),
+        log_target_levels: Default::default(),
+        admin_socket_address: None,
     };
     let config = WalletConfig::new(
         comms_config,
@@ -666,7 +686,9 @@ async fn test_data_generation() {
         datastore_path: temp_dir.path().to_path_buf(),
         peer_database_name: random_string(8),
         max_concurrent_inbound_tasks: 100,
+        max_pending_inbound_per_peer: None,
         outbound_buffer_size: 100,
+        messaging_shutdown_grace_period: CommsConfig::DEFAULT_MESSAGING_SHUTDOWN_GRACE_PERIOD,
         dht: DhtConfig {
             discovery_request_timeout: Duration::from_millis(500),
             network: DhtNetwork::Stibbons,
@@ -678,8 +700,16 @@ async fn test_data_generation() {
         user_agent: "tari/test-wallet".to_string(),
         dns_seeds_name_server: DEFAULT_DNS_SEED_RESOLVER.parse().unwrap(),
         peer_seeds: Default::default(),
+        trusted_peers: Default::default(),
         dns_seeds: Default::default(),
         dns_seeds_use_dnssec: false,
+        dial_connect_timeout: CommsConfig::DEFAULT_DIAL_CONNECT_TIMEOUT,
+        noise_handshake_timeout: CommsConfig::DEFAULT_NOISE_HANDSHAKE_TIMEOUT,
+        identity_exchange_timeout: CommsConfig::DEFAULT_IDENTITY_EXCHANGE_TIMEOUT,
+        peer_version_policy: CommsConfig::DEFAULT_PEER_VERSION_POLICY,
+        dial_backoff: Default::default(),
+        log_target_levels: Default::default(),
+        admin_socket_address: None,
     };
 
     let config = WalletConfig::new(comms_config, factories, None, None, Network::Stibbons, None, None, None);
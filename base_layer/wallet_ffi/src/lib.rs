@@ -2752,7 +2752,9 @@ pub unsafe extern "C" fn comms_config_create(
                         datastore_path,
                         peer_database_name: database_name_string,
                         max_concurrent_inbound_tasks: 100,
+                        max_pending_inbound_per_peer: None,
                         outbound_buffer_size: 100,
+                        messaging_shutdown_grace_period: TariCommsConfig::DEFAULT_MESSAGING_SHUTDOWN_GRACE_PERIOD,
                         dht: DhtConfig {
                             discovery_request_timeout: Duration::from_secs(discovery_timeout_in_secs),
                             database_url: DbConnectionUrl::File(dht_database_path),
@@ -2769,8 +2771,16 @@ pub unsafe extern "C" fn comms_config_create(
                         user_agent: format!("tari/wallet/{}", env!("CARGO_PKG_VERSION")),
                         dns_seeds_name_server: "1.1.1.1:53".parse().unwrap(),
                         peer_seeds: Default::default(),
+                        trusted_peers: Default::default(),
                         dns_seeds: Default::default(),
                         dns_seeds_use_dnssec: true,
+                        dial_connect_timeout: TariCommsConfig::DEFAULT_DIAL_CONNECT_TIMEOUT,
+                        noise_handshake_timeout: TariCommsConfig::DEFAULT_NOISE_HANDSHAKE_TIMEOUT,
+                        identity_exchange_timeout: TariCommsConfig::DEFAULT_IDENTITY_EXCHANGE_TIMEOUT,
+                        peer_version_policy: TariCommsConfig::DEFAULT_PEER_VERSION_POLICY,
+                        dial_backoff: Default::default(),
+                        log_target_levels: Default::default(),
+                        admin_socket_address: None,
                     };
 
                     Box::into_raw(Box::new(config))
@@ -23,7 +23,9 @@
 //! # Global configuration of tari base layer system
 
 use super::ConfigurationError;
+use crate::logging::parse_log_target_level;
 use config::{Config, ConfigError, Environment};
+use log::LevelFilter;
 use multiaddr::Multiaddr;
 use std::{
     convert::TryInto,
@@ -89,6 +91,12 @@ pub struct GlobalConfig {
     pub fetch_blocks_timeout: Duration,
     pub fetch_utxos_timeout: Duration,
     pub service_request_timeout: Duration,
+    pub dial_connect_timeout: Duration,
+    pub noise_handshake_timeout: Duration,
+    pub identity_exchange_timeout: Duration,
+    pub messaging_shutdown_grace_period: Duration,
+    pub min_peer_protocol_version: Option<u8>,
+    pub refuse_peers_below_min_version: bool,
     pub base_node_query_timeout: Duration,
     pub saf_expiry_duration: Duration,
     pub transaction_broadcast_monitoring_timeout: Duration,
@@ -111,6 +119,7 @@ pub struct GlobalConfig {
     pub proxy_host_address: SocketAddr,
     pub proxy_submit_to_origin: bool,
     pub force_sync_peers: Vec<String>,
+    pub trusted_peers: Vec<String>,
     pub wait_for_initial_sync_at_startup: bool,
     pub max_randomx_vms: usize,
     pub console_wallet_notify_file: Option<PathBuf>,
@@ -118,6 +127,10 @@ pub struct GlobalConfig {
     pub blocks_behind_before_considered_lagging: u64,
     pub flood_ban_max_msg_count: usize,
     pub mine_on_tip_only: bool,
+    pub log_target_levels: Vec<(String, LevelFilter)>,
+    pub pipeline_error_log_capacity: usize,
+    pub metrics_snapshot_interval: Option<Duration>,
+    pub log_redaction: bool,
 }
 
 impl GlobalConfig {
@@ -377,6 +390,29 @@ fn convert_node_config(network: Network, cfg: Config) -> Result<GlobalConfig, Co
         .get_int(&key)
         .map_err(|e| ConfigurationError::new(&key, &e.to_string()))? as usize;
 
+    let key = config_string("base_node", &net_str, "log_target_levels");
+    let log_target_levels = optional(cfg.get_array(&key))?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|v| {
+            v.into_str()
+                .map_err(|e| ConfigurationError::new(&key, &e.to_string()))
+                .and_then(|s| parse_log_target_level(&s).map_err(|e| ConfigurationError::new(&key, &e.to_string())))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let key = config_string("base_node", &net_str, "pipeline_error_log_capacity");
+    let pipeline_error_log_capacity = optional(cfg.get_int(&key))?.unwrap_or(0) as usize;
+
+    let key = config_string("base_node", &net_str, "metrics_snapshot_interval");
+    let metrics_snapshot_interval = match optional(cfg.get_int(&key))?.unwrap_or(300) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs as u64)),
+    };
+
+    let key = config_string("base_node", &net_str, "log_redaction");
+    let log_redaction = optional(cfg.get_bool(&key))?.unwrap_or(false);
+
     // block sync
     let key = config_string("base_node", &net_str, "force_sync_peers");
     let force_sync_peers = optional(
@@ -385,6 +421,13 @@ fn convert_node_config(network: Network, cfg: Config) -> Result<GlobalConfig, Co
     )?
     .unwrap_or_default();
 
+    let key = config_string("base_node", &net_str, "trusted_peers");
+    let trusted_peers = optional(
+        cfg.get_array(&key)
+            .map(|values| values.into_iter().map(|v| v.into_str().unwrap()).collect()),
+    )?
+    .unwrap_or_default();
+
     // Liveness auto ping interval
     let key = config_string("base_node", &net_str, "auto_ping_interval");
     let auto_ping_interval = match cfg.get_int(&key) {
@@ -556,6 +599,41 @@ fn convert_node_config(network: Network, cfg: Config) -> Result<GlobalConfig, Co
             .map_err(|e| ConfigurationError::new(&key, &e.to_string()))? as u64,
     );
 
+    let key = "common.dial_connect_timeout";
+    let dial_connect_timeout = Duration::from_secs(
+        cfg.get_int(&key)
+            .map_err(|e| ConfigurationError::new(&key, &e.to_string()))? as u64,
+    );
+
+    let key = "common.noise_handshake_timeout";
+    let noise_handshake_timeout = Duration::from_secs(
+        cfg.get_int(&key)
+            .map_err(|e| ConfigurationError::new(&key, &e.to_string()))? as u64,
+    );
+
+    let key = "common.identity_exchange_timeout";
+    let identity_exchange_timeout = Duration::from_secs(
+        cfg.get_int(&key)
+            .map_err(|e| ConfigurationError::new(&key, &e.to_string()))? as u64,
+    );
+
+    let key = "common.messaging_shutdown_grace_period";
+    let messaging_shutdown_grace_period = Duration::from_secs(
+        cfg.get_int(&key)
+            .map_err(|e| ConfigurationError::new(&key, &e.to_string()))? as u64,
+    );
+
+    let key = "common.min_peer_protocol_version";
+    let min_peer_protocol_version = optional(cfg.get_int(key))?
+        .map(|v| {
+            v.try_into()
+                .map_err(|e: TryFromIntError| ConfigurationError::new(&key, &e.to_string()))
+        })
+        .transpose()?;
+
+    let key = "common.refuse_peers_below_min_version";
+    let refuse_peers_below_min_version = optional(cfg.get_bool(key))?.unwrap_or(false);
+
     let key = config_string("merge_mining_proxy", &net_str, "monerod_url");
     let monerod_url = cfg
         .get_str(&key)
@@ -638,6 +716,12 @@ fn convert_node_config(network: Network, cfg: Config) -> Result<GlobalConfig, Co
         fetch_blocks_timeout,
         fetch_utxos_timeout,
         service_request_timeout,
+        dial_connect_timeout,
+        noise_handshake_timeout,
+        identity_exchange_timeout,
+        messaging_shutdown_grace_period,
+        min_peer_protocol_version,
+        refuse_peers_below_min_version,
         base_node_query_timeout,
         saf_expiry_duration,
         transaction_broadcast_monitoring_timeout,
@@ -660,6 +744,7 @@ fn convert_node_config(network: Network, cfg: Config) -> Result<GlobalConfig, Co
         monerod_password,
         monerod_use_auth,
         force_sync_peers,
+        trusted_peers,
         wait_for_initial_sync_at_startup,
         max_randomx_vms,
         console_wallet_notify_file,
@@ -667,6 +752,10 @@ fn convert_node_config(network: Network, cfg: Config) -> Result<GlobalConfig, Co
         blocks_behind_before_considered_lagging,
         flood_ban_max_msg_count,
         mine_on_tip_only,
+        log_target_levels,
+        pipeline_error_log_capacity,
+        metrics_snapshot_interval,
+        log_redaction,
     })
 }
 
@@ -763,6 +852,43 @@ fn network_transport_config(cfg: &Config, network: &str) -> Result<CommsTranspor
                 auth,
             })
         },
+        "websocket" => {
+            let key = config_string("base_node", network, "websocket_listener_address");
+            let listener_address = get_conf_multiaddr(&key)?;
+
+            Ok(CommsTransport::WebSocket { listener_address })
+        },
+        "quic" => {
+            let key = config_string("base_node", network, "quic_listener_address");
+            let listener_address = get_conf_multiaddr(&key)?;
+            let key = config_string("base_node", network, "tcp_tor_socks_address");
+            let tor_socks_address = get_conf_multiaddr(&key).ok();
+            let key = config_string("base_node", network, "tcp_tor_socks_auth");
+            let tor_socks_auth = get_conf_str(&key).ok().and_then(|auth_str| auth_str.parse().ok());
+
+            Ok(CommsTransport::Quic {
+                listener_address,
+                tor_socks_address,
+                tor_socks_auth,
+            })
+        },
+        "tls" => {
+            let key = config_string("base_node", network, "tls_listener_address");
+            let listener_address = get_conf_multiaddr(&key)?;
+            let key = config_string("base_node", network, "tcp_tor_socks_address");
+            let tor_socks_address = get_conf_multiaddr(&key).ok();
+            let key = config_string("base_node", network, "tcp_tor_socks_auth");
+            let tor_socks_auth = get_conf_str(&key).ok().and_then(|auth_str| auth_str.parse().ok());
+            let key = config_string("base_node", network, "tls_plaintext_fallback");
+            let plaintext_fallback = cfg.get_bool(&key).unwrap_or(false);
+
+            Ok(CommsTransport::Tls {
+                listener_address,
+                tor_socks_address,
+                tor_socks_auth,
+                plaintext_fallback,
+            })
+        },
         t => Err(ConfigurationError::new(
             &transport_key,
             &format!("Invalid transport type '{}'", t),
@@ -919,4 +1045,23 @@ pub enum CommsTransport {
         auth: SocksAuthentication,
         listener_address: Multiaddr,
     },
+    /// Use the WebSocket transport. This transport can only communicate with peers that expose a WebSocket listener
+    /// (`/ws` or `/wss` addresses) and is intended for environments that only allow HTTP(S) egress.
+    WebSocket { listener_address: Multiaddr },
+    /// Use the QUIC transport. QUIC addresses (`/udp/<port>/quic`) are dialed over QUIC and all other addresses are
+    /// dialed over TCP.
+    Quic {
+        listener_address: Multiaddr,
+        tor_socks_address: Option<Multiaddr>,
+        tor_socks_auth: Option<SocksAuthentication>,
+    },
+    /// Use the TCP transport with connections wrapped in TLS, for networks that drop traffic that does not look like
+    /// TLS. Inbound connections may use TLS or plain TCP.
+    Tls {
+        listener_address: Multiaddr,
+        tor_socks_address: Option<Multiaddr>,
+        tor_socks_auth: Option<SocksAuthentication>,
+        /// Dial peers again without TLS if the TLS handshake fails
+        plaintext_fallback: bool,
+    },
 }
@@ -38,6 +38,7 @@ pub mod bootstrap;
 pub mod error;
 pub mod global;
 pub mod loader;
+pub mod optional_seconds;
 pub mod seconds;
 pub mod utils;
 pub mod writer;
@@ -60,6 +60,10 @@ pub fn default_config(bootstrap: &ConfigBootstrap) -> Config {
     cfg.set_default("common.fetch_blocks_timeout", 150).unwrap();
     cfg.set_default("common.fetch_utxos_timeout", 600).unwrap();
     cfg.set_default("common.service_request_timeout", 180).unwrap();
+    cfg.set_default("common.dial_connect_timeout", 20).unwrap();
+    cfg.set_default("common.noise_handshake_timeout", 30).unwrap();
+    cfg.set_default("common.identity_exchange_timeout", 15).unwrap();
+    cfg.set_default("common.messaging_shutdown_grace_period", 10).unwrap();
 
     // Wallet settings
     cfg.set_default("wallet.grpc_enabled", false).unwrap();
@@ -272,6 +276,16 @@ fn set_transport_defaults(cfg: &mut Config) {
         .unwrap();
     cfg.set_default("base_node.mainnet.socks5_auth", "none").unwrap();
 
+    cfg.set_default(
+        "base_node.mainnet.websocket_listener_address",
+        "/ip4/0.0.0.0/tcp/18098/ws",
+    )
+    .unwrap();
+    cfg.set_default("base_node.mainnet.quic_listener_address", "/ip4/0.0.0.0/udp/18099/quic")
+        .unwrap();
+    cfg.set_default("base_node.mainnet.tls_listener_address", "/ip4/0.0.0.0/tcp/18097")
+        .unwrap();
+
     // stibbons
     // Default transport for stibbons is tcp
     cfg.set_default("base_node.stibbons.transport", "tcp").unwrap();
@@ -290,6 +304,19 @@ fn set_transport_defaults(cfg: &mut Config) {
     cfg.set_default("base_node.stibbons.socks5_listener_address", "/ip4/0.0.0.0/tcp/18199")
         .unwrap();
     cfg.set_default("base_node.stibbons.socks5_auth", "none").unwrap();
+
+    cfg.set_default(
+        "base_node.stibbons.websocket_listener_address",
+        "/ip4/0.0.0.0/tcp/18198/ws",
+    )
+    .unwrap();
+    cfg.set_default(
+        "base_node.stibbons.quic_listener_address",
+        "/ip4/0.0.0.0/udp/18199/quic",
+    )
+    .unwrap();
+    cfg.set_default("base_node.stibbons.tls_listener_address", "/ip4/0.0.0.0/tcp/18197")
+        .unwrap();
 }
 
 fn get_local_ip() -> Option<Multiaddr> {
@@ -78,9 +78,10 @@
 
 pub mod configuration;
 #[macro_use]
-mod logging;
+pub mod logging;
 
 pub mod protobuf_build;
+pub mod redaction;
 pub use configuration::error::ConfigError;
 
 pub mod dir_utils;
@@ -90,7 +91,7 @@ pub use configuration::{
     loader::{ConfigLoader, ConfigPath, ConfigurationError, DefaultConfigLoader, NetworkConfigPath},
     utils::{default_config, install_default_config_file, load_configuration},
 };
-pub use logging::initialize_logging;
+pub use logging::{initialize_logging, LoggingError};
 
 pub const DEFAULT_CONFIG: &str = "config/config.toml";
 pub const DEFAULT_BASE_NODE_LOG_CONFIG: &str = "config/log4rs_base_node.yml";
@@ -21,7 +21,36 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 //
 
-use std::{fs, fs::File, io::Write, path::Path};
+use crate::redaction::redact_identifiers;
+use lazy_static::lazy_static;
+use log::{LevelFilter, Record};
+use log4rs::{
+    append::Append,
+    config::{Appender, Config, Logger},
+    file::{Deserializers, RawConfig},
+    filter::Response,
+    Handle,
+};
+use std::{
+    error::Error,
+    fmt,
+    fs,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+lazy_static! {
+    static ref SCOPED_LOGGING: Mutex<Option<ScopedLogging>> = Mutex::new(None);
+}
+
+static LOG_REDACTION_ENABLED: AtomicBool = AtomicBool::new(false);
 
 /// Set up application-level logging using the Log4rs configuration file specified in
 pub fn initialize_logging(config_file: &Path) -> bool {
@@ -29,13 +58,249 @@ pub fn initialize_logging(config_file: &Path) -> bool {
         "Initializing logging according to {:?}",
         config_file.to_str().unwrap_or("[??]")
     );
-    if let Err(e) = log4rs::init_file(config_file, Default::default()) {
-        println!("We couldn't load a logging configuration file. {}", e.to_string());
-        return false;
+    let raw_config = match read_raw_config(config_file) {
+        Ok(c) => c,
+        Err(e) => {
+            println!("We couldn't load a logging configuration file. {}", e.to_string());
+            return false;
+        },
+    };
+    let refresh_rate = raw_config.refresh_rate();
+    let handle = match log4rs::init_config(build_config(&raw_config, &[])) {
+        Ok(h) => h,
+        Err(e) => {
+            println!("We couldn't load a logging configuration file. {}", e.to_string());
+            return false;
+        },
+    };
+
+    *SCOPED_LOGGING.lock().unwrap() = Some(ScopedLogging {
+        config_file: config_file.to_path_buf(),
+        handle,
+        target_levels: Vec::new(),
+    });
+
+    if let Some(refresh_rate) = refresh_rate {
+        spawn_config_reloader(config_file.to_path_buf(), refresh_rate);
     }
     true
 }
 
+/// Error for runtime log target level changes
+#[derive(Debug)]
+pub enum LoggingError {
+    /// Logging was not initialized using [initialize_logging](self::initialize_logging)
+    NotInitialized,
+    /// The log target level was not in the form `target=level`
+    InvalidLogTargetLevel(String),
+    /// The logging configuration file could not be loaded
+    ConfigLoadFailed(String),
+}
+
+impl fmt::Display for LoggingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoggingError::NotInitialized => write!(f, "Logging has not been initialized"),
+            LoggingError::InvalidLogTargetLevel(s) => {
+                write!(f, "Invalid log target level '{}'. Expected 'target=level'", s)
+            },
+            LoggingError::ConfigLoadFailed(s) => write!(f, "Failed to load logging configuration: {}", s),
+        }
+    }
+}
+
+impl Error for LoggingError {}
+
+/// Parse a log target level of the form `target=level` e.g. `comms::dht::store_forward=debug`
+pub fn parse_log_target_level(s: &str) -> Result<(String, LevelFilter), LoggingError> {
+    let mut parts = s.splitn(2, '=');
+    let target = parts.next().map(str::trim).filter(|t| !t.is_empty());
+    let level = parts.next().and_then(|l| l.trim().parse::<LevelFilter>().ok());
+    match (target, level) {
+        (Some(target), Some(level)) => Ok((target.to_string(), level)),
+        _ => Err(LoggingError::InvalidLogTargetLevel(s.to_string())),
+    }
+}
+
+/// Set the verbosity of the given log targets, overriding the levels in the logging configuration file. Each target
+/// applies to itself and all of its child targets (e.g. `comms::dht` applies to `comms::dht::store_forward`) unless a
+/// more specific target is configured.
+pub fn set_log_target_levels(target_levels: &[(String, LevelFilter)]) -> Result<(), LoggingError> {
+    update_target_levels(|levels| {
+        for (target, level) in target_levels {
+            levels.retain(|(t, _)| t != target);
+            levels.push((target.clone(), *level));
+        }
+    })
+}
+
+/// Set the verbosity of a single log target. See [set_log_target_levels](self::set_log_target_levels).
+pub fn set_log_target_level(target: &str, level: LevelFilter) -> Result<(), LoggingError> {
+    set_log_target_levels(&[(target.to_string(), level)])
+}
+
+/// Remove a log target level override, restoring the level from the logging configuration file. Returns true if an
+/// override for the target existed.
+pub fn reset_log_target_level(target: &str) -> Result<bool, LoggingError> {
+    let mut existed = false;
+    update_target_levels(|levels| {
+        let len = levels.len();
+        levels.retain(|(t, _)| t != target);
+        existed = levels.len() != len;
+    })?;
+    Ok(existed)
+}
+
+/// Returns the currently active log target level overrides
+pub fn log_target_levels() -> Vec<(String, LevelFilter)> {
+    SCOPED_LOGGING
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|l| l.target_levels.clone())
+        .unwrap_or_default()
+}
+
+/// Enable or disable redaction of node ids, public keys and addresses in log messages. When enabled, each identifier is
+/// replaced with a pseudonym that is stable for the lifetime of the process, so that logs can be shared without
+/// revealing who this node has been communicating with. See [redact_identifiers](crate::redaction::redact_identifiers).
+pub fn set_log_redaction(enabled: bool) {
+    LOG_REDACTION_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Returns true if identifying data is redacted from log messages
+pub fn is_log_redaction_enabled() -> bool {
+    LOG_REDACTION_ENABLED.load(Ordering::Relaxed)
+}
+
+struct ScopedLogging {
+    config_file: PathBuf,
+    handle: Handle,
+    target_levels: Vec<(String, LevelFilter)>,
+}
+
+impl ScopedLogging {
+    fn reload(&self) -> Result<(), LoggingError> {
+        let raw_config = read_raw_config(&self.config_file)?;
+        self.handle.set_config(build_config(&raw_config, &self.target_levels));
+        Ok(())
+    }
+}
+
+/// Wraps a configured log4rs appender, redacting identifying data from the message if log redaction is enabled. The
+/// filters of the wrapped appender are applied to the original record.
+#[derive(Debug)]
+struct RedactingAppender(Appender);
+
+impl Append for RedactingAppender {
+    fn append(&self, record: &Record<'_>) -> Result<(), Box<dyn Error + Sync + Send>> {
+        for filter in self.0.filters() {
+            match filter.filter(record) {
+                Response::Accept => break,
+                Response::Neutral => {},
+                Response::Reject => return Ok(()),
+            }
+        }
+
+        if !is_log_redaction_enabled() {
+            return self.0.appender().append(record);
+        }
+
+        let message = redact_identifiers(&record.args().to_string());
+        self.0.appender().append(
+            &Record::builder()
+                .args(format_args!("{}", message))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        )
+    }
+
+    fn flush(&self) {
+        self.0.appender().flush();
+    }
+}
+
+fn update_target_levels<F>(f: F) -> Result<(), LoggingError>
+where F: FnOnce(&mut Vec<(String, LevelFilter)>) {
+    let mut lock = SCOPED_LOGGING.lock().unwrap();
+    let logging = lock.as_mut().ok_or(LoggingError::NotInitialized)?;
+    let mut target_levels = logging.target_levels.clone();
+    f(&mut target_levels);
+    let prev_levels = std::mem::replace(&mut logging.target_levels, target_levels);
+    if let Err(err) = logging.reload() {
+        logging.target_levels = prev_levels;
+        return Err(err);
+    }
+    Ok(())
+}
+
+fn read_raw_config(config_file: &Path) -> Result<RawConfig, LoggingError> {
+    let source = fs::read_to_string(config_file).map_err(|err| LoggingError::ConfigLoadFailed(err.to_string()))?;
+    match config_file.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&source).map_err(|err| LoggingError::ConfigLoadFailed(err.to_string())),
+        _ => serde_yaml::from_str(&source).map_err(|err| LoggingError::ConfigLoadFailed(err.to_string())),
+    }
+}
+
+/// Build a log4rs config from the raw config file, adding or replacing loggers for the target level overrides.
+/// Overridden loggers keep the appenders and additivity from the config file if they were defined there.
+fn build_config(raw_config: &RawConfig, target_levels: &[(String, LevelFilter)]) -> Config {
+    let (appenders, errors) = raw_config.appenders_lossy(&Deserializers::default());
+    for err in errors {
+        eprintln!("log4rs: {}", err);
+    }
+    let appenders = appenders.into_iter().map(|appender| {
+        let name = appender.name().to_string();
+        Appender::builder().build(name, Box::new(RedactingAppender(appender)))
+    });
+
+    let mut loggers = raw_config.loggers();
+    for (target, level) in target_levels {
+        let logger = match loggers.iter().position(|l| l.name() == target) {
+            Some(pos) => {
+                let existing = loggers.remove(pos);
+                Logger::builder()
+                    .appenders(existing.appenders().iter().cloned())
+                    .additive(existing.additive())
+                    .build(target.clone(), *level)
+            },
+            None => Logger::builder().build(target.clone(), *level),
+        };
+        loggers.push(logger);
+    }
+
+    let (config, errors) = Config::builder()
+        .appenders(appenders)
+        .loggers(loggers)
+        .build_lossy(raw_config.root());
+    for err in errors {
+        eprintln!("log4rs: {}", err);
+    }
+    config
+}
+
+/// Reload the logging configuration file when it changes, keeping any log target level overrides
+fn spawn_config_reloader(config_file: PathBuf, refresh_rate: Duration) {
+    let modified_time = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified: Option<SystemTime> = modified_time(&config_file);
+    thread::spawn(move || loop {
+        thread::sleep(refresh_rate);
+        let modified = modified_time(&config_file);
+        if modified.is_some() && modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+        if let Some(logging) = SCOPED_LOGGING.lock().unwrap().as_ref() {
+            if let Err(err) = logging.reload() {
+                eprintln!("log4rs: {}", err);
+            }
+        }
+    });
+}
+
 /// Installs a new default logfile configuration, copied from `log4rs_sample_base_node.yml` to the given path.
 pub fn install_default_base_node_logfile_config(path: &Path) -> Result<(), std::io::Error> {
     let source = include_str!("../logging/log4rs_sample_base_node.yml");
@@ -135,6 +400,67 @@ macro_rules! log_if_error_fmt {
 
 #[cfg(test)]
 mod test {
+    use super::*;
+
+    #[test]
+    fn parse_log_target_level_ok() {
+        let (target, level) = parse_log_target_level("comms::dht::store_forward=debug").unwrap();
+        assert_eq!(target, "comms::dht::store_forward");
+        assert_eq!(level, LevelFilter::Debug);
+
+        let (target, level) = parse_log_target_level(" comms::connection = warn ").unwrap();
+        assert_eq!(target, "comms::connection");
+        assert_eq!(level, LevelFilter::Warn);
+
+        assert!(parse_log_target_level("comms::connection").is_err());
+        assert!(parse_log_target_level("=debug").is_err());
+        assert!(parse_log_target_level("comms=verbose").is_err());
+    }
+
+    #[test]
+    fn build_config_with_target_levels() {
+        // Only console appenders are used, building file appenders would create log files
+        let raw_config: RawConfig = serde_yaml::from_str(
+            r#"
+appenders:
+  stdout:
+    kind: console
+root:
+  level: warn
+  appenders:
+    - stdout
+loggers:
+  comms:
+    level: info
+    appenders:
+      - stdout
+    additive: false
+"#,
+        )
+        .unwrap();
+        let config = build_config(&raw_config, &[
+            ("comms".to_string(), LevelFilter::Warn),
+            ("comms::dht::store_forward".to_string(), LevelFilter::Debug),
+        ]);
+
+        let comms = config.loggers().iter().find(|l| l.name() == "comms").unwrap();
+        assert_eq!(comms.level(), LevelFilter::Warn);
+        // Appenders from the config file are kept
+        assert!(!comms.appenders().is_empty());
+        let saf = config
+            .loggers()
+            .iter()
+            .find(|l| l.name() == "comms::dht::store_forward")
+            .unwrap();
+        assert_eq!(saf.level(), LevelFilter::Debug);
+        assert!(saf.additive());
+        assert_eq!(
+            config.loggers().len(),
+            raw_config.loggers().len() + 1,
+            "expected a single new logger"
+        );
+    }
+
     #[test]
     fn log_if_error() {
         let err = Result::<(), _>::Err("What a shame");
@@ -0,0 +1,218 @@
+// Copyright 2019. The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//
+
+//! Redaction of identifying data (node ids, public keys and addresses) from log messages, so that logs can be shared
+//! without revealing who a node has been communicating with.
+//!
+//! Each identifier is replaced with a pseudonym derived from a random key that is generated once per process. The same
+//! identifier always has the same pseudonym within a run, so the flow of messages to and from a peer can still be
+//! followed, but pseudonyms cannot be linked back to the identifier or between runs.
+
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Write,
+    hash::{BuildHasher, Hasher},
+    net::{IpAddr, Ipv4Addr},
+};
+
+/// The length of a hex-encoded public key (or any other 32-byte value, which cannot be told apart from a public key)
+const PUBLIC_KEY_HEX_LEN: usize = 64;
+/// The length of a hex-encoded node id
+const NODE_ID_HEX_LEN: usize = 26;
+/// The length of the short form of a hex-encoded node id, as returned by `NodeId::short_str`
+const SHORT_NODE_ID_HEX_LEN: usize = 16;
+/// Multiaddr protocols whose value is a host. IPv4 addresses are redacted wherever they appear.
+const HOST_PROTOCOLS: &[&str] = &["/ip6/", "/dns/", "/dns4/", "/dns6/", "/onion/", "/onion3/"];
+
+lazy_static! {
+    static ref PSEUDONYM_KEY: [u8; 16] = {
+        let mut key = [0u8; 16];
+        for chunk in key.chunks_mut(8) {
+            // RandomState is seeded from the OS random number generator
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_usize(chunk.len());
+            chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+        }
+        key
+    };
+}
+
+/// Replace the node ids, public keys and addresses in the message with stable per-run pseudonyms. Loopback and
+/// unspecified addresses are left as is.
+pub fn redact_identifiers(message: &str) -> String {
+    let message = redact_multiaddr_hosts(message);
+    redact_tokens(&message)
+}
+
+fn pseudonym(kind: &str, value: &str) -> String {
+    let hash = Sha256::new().chain(&*PSEUDONYM_KEY).chain(value.as_bytes()).result();
+    let mut pseudonym = String::with_capacity(kind.len() + 9);
+    pseudonym.push_str(kind);
+    pseudonym.push('#');
+    for b in &hash[..4] {
+        let _ = write!(pseudonym, "{:02x}", b);
+    }
+    pseudonym
+}
+
+fn is_local_address(host: &str) -> bool {
+    host.parse::<IpAddr>()
+        .map(|ip| ip.is_loopback() || ip.is_unspecified())
+        .unwrap_or(false)
+}
+
+fn is_address_delimiter(c: char) -> bool {
+    c.is_whitespace() ||
+        matches!(
+            c,
+            '/' | ',' | ';' | '(' | ')' | '[' | ']' | '{' | '}' | '"' | '\'' | '<' | '>'
+        )
+}
+
+fn redact_multiaddr_hosts(message: &str) -> String {
+    let mut redacted = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some((pos, protocol)) = HOST_PROTOCOLS
+        .iter()
+        .filter_map(|protocol| rest.find(protocol).map(|pos| (pos, protocol)))
+        .min_by_key(|(pos, _)| *pos)
+    {
+        let start = pos + protocol.len();
+        let end = rest[start..]
+            .find(is_address_delimiter)
+            .map(|len| start + len)
+            .unwrap_or_else(|| rest.len());
+        let host = &rest[start..end];
+        redacted.push_str(&rest[..start]);
+        if host.is_empty() || is_local_address(host) {
+            redacted.push_str(host);
+        } else {
+            redacted.push_str(&pseudonym("addr", host));
+        }
+        rest = &rest[end..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+fn redact_tokens(message: &str) -> String {
+    let mut redacted = String::with_capacity(message.len());
+    let mut token_start = None;
+    for (i, c) in message.char_indices() {
+        if c.is_ascii_alphanumeric() || c == '.' {
+            token_start.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = token_start.take() {
+            push_token(&mut redacted, &message[start..i]);
+        }
+        redacted.push(c);
+    }
+    if let Some(start) = token_start {
+        push_token(&mut redacted, &message[start..]);
+    }
+    redacted
+}
+
+fn push_token(redacted: &mut String, token: &str) {
+    // A full stop at the end of a sentence is not part of the identifier
+    let trimmed = token.trim_end_matches('.');
+    match redact_token(trimmed) {
+        Some(pseudonym) => {
+            redacted.push_str(&pseudonym);
+            redacted.push_str(&token[trimmed.len()..]);
+        },
+        None => redacted.push_str(token),
+    }
+}
+
+fn redact_token(token: &str) -> Option<String> {
+    let is_hex = !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    if is_hex {
+        return match token.len() {
+            // The short form of a node id is a prefix of the node id, so both share a pseudonym
+            NODE_ID_HEX_LEN | SHORT_NODE_ID_HEX_LEN => Some(pseudonym("node", &token[..SHORT_NODE_ID_HEX_LEN])),
+            PUBLIC_KEY_HEX_LEN => Some(pseudonym("key", token)),
+            _ => None,
+        };
+    }
+
+    match token.parse::<Ipv4Addr>() {
+        Ok(ip) if !ip.is_loopback() && !ip.is_unspecified() => Some(pseudonym("addr", token)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PUBLIC_KEY: &str = "7e6f4b801170db0bf86c9257fe562492469439556cba069a12afd1c72c585b0f";
+    const NODE_ID: &str = "3a5d8c3e0f8f9a1b2c3d4e5f60";
+
+    #[test]
+    fn redacts_node_ids_and_public_keys() {
+        let message = format!(
+            "Peer {} ({}) connected. Short id: {}.",
+            PUBLIC_KEY,
+            NODE_ID,
+            &NODE_ID[..16]
+        );
+        let redacted = redact_identifiers(&message);
+        assert!(!redacted.contains(PUBLIC_KEY));
+        assert!(!redacted.contains(&NODE_ID[..16]));
+        let node_pseudonym = pseudonym("node", &NODE_ID[..16]);
+        assert_eq!(
+            redacted,
+            format!(
+                "Peer {} ({}) connected. Short id: {}.",
+                pseudonym("key", PUBLIC_KEY),
+                node_pseudonym,
+                node_pseudonym
+            )
+        );
+        // Pseudonyms are stable
+        assert_eq!(redacted, redact_identifiers(&message));
+    }
+
+    #[test]
+    fn redacts_addresses() {
+        let redacted = redact_identifiers(
+            "Dialing /ip4/203.0.113.7/tcp/18189, \
+             /onion3/bsmuof2cn4y2ysz253gzsvg3s72fcgh4f3qcm3hdlxdtcwe6al2dicyd:18141 and 203.0.113.7:18189",
+        );
+        let addr = pseudonym("addr", "203.0.113.7");
+        assert!(!redacted.contains("203.0.113.7"));
+        assert!(!redacted.contains("bsmuof2cn4y2ysz"));
+        assert!(redacted.starts_with(&format!("Dialing /ip4/{}/tcp/18189, /onion3/addr#", addr)));
+        assert!(redacted.ends_with(&format!(" and {}:18189", addr)));
+    }
+
+    #[test]
+    fn leaves_other_data_alone() {
+        let message = "Received 123 messages from /ip4/127.0.0.1/tcp/18189 (/ip6/::1/tcp/1) in 1.5s. Height 1234.";
+        assert_eq!(redact_identifiers(message), message);
+    }
+}
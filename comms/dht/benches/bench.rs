@@ -0,0 +1,306 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+#[cfg(not(feature = "benches"))]
+mod benches {
+    pub fn main() {
+        println!("Enable the `benches` feature to run benches");
+    }
+}
+
+#[cfg(feature = "benches")]
+mod benches {
+    use criterion::{criterion_group, BatchSize, Criterion};
+    use prost::Message;
+    use rand::rngs::OsRng;
+    use std::{convert::TryFrom, time::Duration};
+    use tari_comms::{
+        message::MessageExt,
+        multiaddr::Multiaddr,
+        peer_manager::{NodeId, NodeIdentity, Peer, PeerFeatures, PeerId, PeerStorage},
+        types::{CommsPublicKey, CommsSecretKey},
+        utils::signature,
+    };
+    use tari_comms_dht::{
+        crypt,
+        envelope::{Destination, DhtEnvelope, DhtHeader, DhtMessageFlags, DhtMessageHeader, DhtMessageType, Network},
+        fuzz::InboundPipelineHarness,
+        store_forward::{NewStoredMessage, SafStorage, StoreAndForwardDatabase},
+        DbConnection,
+        DbConnectionUrl,
+        DhtConfig,
+    };
+    use tari_crypto::keys::PublicKey;
+    use tari_storage::HashmapDatabase;
+    use tari_utilities::{hex::Hex, message_format::MessageFormat};
+    use tokio::runtime::Runtime;
+
+    const BODY_SIZE: usize = 1024;
+    const SIGNATURE_BATCH_SIZE: usize = 64;
+    const NUM_SAF_PEERS: usize = 100;
+    const SAF_FETCH_LIMIT: i64 = 100;
+    const NUM_CLOSEST_PEERS: usize = 8;
+
+    fn make_node_identity() -> NodeIdentity {
+        NodeIdentity::random(&mut OsRng, Multiaddr::empty(), PeerFeatures::COMMUNICATION_NODE).unwrap()
+    }
+
+    fn make_envelope(body: Vec<u8>, flags: DhtMessageFlags) -> DhtEnvelope {
+        let (_, e_pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let header = DhtHeader {
+            destination: Some(Destination::Unknown(true)),
+            ephemeral_public_key: e_pk.to_vec(),
+            message_type: DhtMessageType::None as i32,
+            network: Network::LocalTest as i32,
+            flags: flags.bits(),
+            ..Default::default()
+        };
+        DhtEnvelope::new(header, body.into())
+    }
+
+    fn make_stored_message(destination: &CommsPublicKey, id: usize) -> NewStoredMessage {
+        NewStoredMessage {
+            message_type: DhtMessageType::None as i32,
+            destination_pubkey: Some(destination.to_hex()),
+            header: make_envelope(Vec::new(), DhtMessageFlags::ENCRYPTED)
+                .header
+                .unwrap()
+                .to_encoded_bytes(),
+            body: vec![0u8; BODY_SIZE],
+            is_encrypted: true,
+            body_hash: format!("{:064x}", id),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a store and forward database containing `num_messages` messages spread evenly across the given peers
+    async fn populate_saf_database(
+        db_url: DbConnectionUrl,
+        peers: &[CommsPublicKey],
+        num_messages: usize,
+    ) -> StoreAndForwardDatabase
+    {
+        let conn = DbConnection::connect_and_migrate(db_url).await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+        for i in 0..num_messages {
+            db.insert_message_if_unique(make_stored_message(&peers[i % peers.len()], i))
+                .await
+                .unwrap();
+        }
+        db
+    }
+
+    fn make_peer_public_keys(n: usize) -> Vec<CommsPublicKey> {
+        (0..n).map(|_| CommsPublicKey::random_keypair(&mut OsRng).1).collect()
+    }
+
+    fn make_node_ids(n: usize) -> Vec<NodeId> {
+        make_peer_public_keys(n).iter().map(NodeId::from_public_key).collect()
+    }
+
+    fn make_peer_storage(num_peers: usize) -> PeerStorage<HashmapDatabase<PeerId, Peer>> {
+        let mut peer_storage = PeerStorage::new_indexed(HashmapDatabase::new()).unwrap();
+        for _ in 0..num_peers {
+            peer_storage.add_peer(make_node_identity().to_peer()).unwrap();
+        }
+        peer_storage
+    }
+
+    fn envelope_codec(c: &mut Criterion) {
+        c.bench_function("envelope serialize", move |b| {
+            let envelope = make_envelope(vec![0u8; BODY_SIZE], DhtMessageFlags::ENCRYPTED);
+            b.iter(|| envelope.to_encoded_bytes());
+        });
+
+        c.bench_function("envelope deserialize", move |b| {
+            let bytes = make_envelope(vec![0u8; BODY_SIZE], DhtMessageFlags::ENCRYPTED).to_encoded_bytes();
+            b.iter(|| {
+                let envelope = DhtEnvelope::decode(bytes.as_slice()).unwrap();
+                DhtMessageHeader::try_from(envelope.header.unwrap()).unwrap()
+            });
+        });
+    }
+
+    fn encryption(c: &mut Criterion) {
+        c.bench_function("ecdh shared secret", move |b| {
+            let node_identity = make_node_identity();
+            let (e_sk, _) = CommsPublicKey::random_keypair(&mut OsRng);
+            b.iter(|| crypt::generate_ecdh_secret(&e_sk, node_identity.public_key()));
+        });
+
+        c.bench_function("encrypt", move |b| {
+            let (_, key) = CommsPublicKey::random_keypair(&mut OsRng);
+            let plain_text = vec![0u8; BODY_SIZE];
+            b.iter(|| crypt::encrypt(&key, &plain_text).unwrap());
+        });
+
+        c.bench_function("decrypt", move |b| {
+            let (_, key) = CommsPublicKey::random_keypair(&mut OsRng);
+            let cipher_text = crypt::encrypt(&key, &vec![0u8; BODY_SIZE]).unwrap();
+            b.iter(|| crypt::decrypt(&key, &cipher_text).unwrap());
+        });
+    }
+
+    fn signature_verification(c: &mut Criterion) {
+        fn make_signature(secret_key: &CommsSecretKey, body: &[u8]) -> Vec<u8> {
+            signature::sign(&mut OsRng, secret_key.clone(), body)
+                .unwrap()
+                .to_binary()
+                .unwrap()
+        }
+
+        c.bench_function("signature verify", move |b| {
+            let (sk, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+            let body = vec![0u8; BODY_SIZE];
+            let signature = make_signature(&sk, &body);
+            b.iter(|| assert!(signature::verify(&pk, &signature, &body)));
+        });
+
+        c.bench_function(
+            &format!("signature verify (batch of {})", SIGNATURE_BATCH_SIZE),
+            move |b| {
+                let batch = (0..SIGNATURE_BATCH_SIZE)
+                    .map(|_| {
+                        let (sk, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+                        let body = vec![0u8; BODY_SIZE];
+                        let signature = make_signature(&sk, &body);
+                        (pk, signature, body)
+                    })
+                    .collect::<Vec<_>>();
+                b.iter(|| {
+                    assert!(batch
+                        .iter()
+                        .all(|(pk, signature, body)| signature::verify(pk, signature, body)))
+                });
+            },
+        );
+    }
+
+    fn closest_peers(c: &mut Criterion) {
+        c.bench_function("node id distance", move |b| {
+            let node_ids = make_node_ids(2);
+            b.iter(|| node_ids[0].distance(&node_ids[1]));
+        });
+
+        c.bench_function("node id distance key", move |b| {
+            let node_ids = make_node_ids(2);
+            b.iter(|| node_ids[0].distance_key(&node_ids[1]));
+        });
+
+        for &num_peers in &[1_000, 5_000] {
+            c.bench_function(&format!("closest node ids ({} known)", num_peers), move |b| {
+                let node_ids = make_node_ids(num_peers);
+                let node_id = make_node_ids(1).remove(0);
+                b.iter(|| node_id.closest(&node_ids, NUM_CLOSEST_PEERS));
+            });
+
+            c.bench_function(&format!("closest peers ({} known)", num_peers), move |b| {
+                let peer_storage = make_peer_storage(num_peers);
+                let node_id = make_node_ids(1).remove(0);
+                b.iter(|| {
+                    peer_storage
+                        .closest_peers(&node_id, NUM_CLOSEST_PEERS, &[], None)
+                        .unwrap()
+                });
+            });
+
+            c.bench_function(&format!("closest peers indexed ({} known)", num_peers), move |b| {
+                let peer_storage = make_peer_storage(num_peers).with_distance_index_capacity(1);
+                let node_id = make_node_ids(1).remove(0);
+                b.iter(|| {
+                    peer_storage
+                        .closest_peers(&node_id, NUM_CLOSEST_PEERS, &[], None)
+                        .unwrap()
+                });
+            });
+        }
+    }
+
+    fn saf_storage(c: &mut Criterion) {
+        for &num_messages in &[10_000, 100_000] {
+            c.bench_function(&format!("saf insert ({} stored)", num_messages), move |b| {
+                let mut rt = Runtime::new().unwrap();
+                let peers = make_peer_public_keys(NUM_SAF_PEERS);
+                let db = rt.block_on(populate_saf_database(DbConnectionUrl::Memory, &peers, num_messages));
+                let mut next_id = num_messages;
+                b.iter_batched(
+                    || {
+                        next_id += 1;
+                        make_stored_message(&peers[next_id % peers.len()], next_id)
+                    },
+                    |message| rt.block_on(db.insert_message_if_unique(message)).unwrap(),
+                    BatchSize::SmallInput,
+                );
+            });
+
+            c.bench_function(&format!("saf lookup ({} stored)", num_messages), move |b| {
+                let mut rt = Runtime::new().unwrap();
+                let peers = make_peer_public_keys(NUM_SAF_PEERS);
+                let db = rt.block_on(populate_saf_database(DbConnectionUrl::Memory, &peers, num_messages));
+                let node_id = NodeId::from_key(&peers[0]).unwrap();
+                b.iter(|| {
+                    rt.block_on(db.find_messages_for_peer(&peers[0], &node_id, None, SAF_FETCH_LIMIT))
+                        .unwrap()
+                });
+            });
+        }
+    }
+
+    fn saf_request_handler(c: &mut Criterion) {
+        c.bench_function("saf request handler", move |b| {
+            let mut rt = Runtime::new().unwrap();
+            let db_url = DbConnectionUrl::MemoryShared("saf_request_handler_bench".to_string());
+            let mut harness = rt
+                .block_on(InboundPipelineHarness::with_config(DhtConfig {
+                    database_url: db_url.clone(),
+                    ..DhtConfig::default_local_test()
+                }))
+                .unwrap();
+            // Keep the shared in-memory database alive for the duration of the benchmark
+            let _db = rt.block_on(populate_saf_database(
+                db_url,
+                &[harness.source_identity().public_key().clone()],
+                SAF_FETCH_LIMIT as usize,
+            ));
+
+            b.iter(|| {
+                let request = rt.block_on(harness.encode_stored_messages_request()).unwrap();
+                rt.block_on(harness.process_bytes(&request))
+            });
+        });
+    }
+
+    criterion_group!(
+        name = dht;
+        config = Criterion::default().warm_up_time(Duration::from_millis(500)).sample_size(10);
+        targets = envelope_codec, encryption, signature_verification, closest_peers, saf_storage, saf_request_handler
+    );
+
+    pub fn main() {
+        dht();
+        criterion::Criterion::default().configure_from_args().final_summary();
+    }
+}
+
+fn main() {
+    benches::main();
+}
@@ -0,0 +1,130 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Fuzz Corpus
+//!
+//! Tools for working with inbound pipeline fuzz corpora.
+//!
+//! `export` writes the messages in a node's store and forward database, with identifying information removed, to a
+//! corpus directory. `replay` runs each file in a corpus directory through the inbound pipeline harness and prints the
+//! outcome, which is useful for reproducing a crashing input found by a fuzzer.
+//!
+//! ```text
+//! cargo run --example fuzz_corpus --features fuzzing -- export --database ~/.tari/dht.db --output corpus
+//! cargo run --example fuzz_corpus --features fuzzing -- replay corpus
+//! ```
+
+use clap::{App, Arg, SubCommand};
+use std::{fs, path::PathBuf};
+use tari_comms_dht::{
+    fuzz::{export_saf_corpus, InboundPipelineHarness},
+    DbConnectionUrl,
+};
+
+const DEFAULT_EXPORT_LIMIT: usize = 10_000;
+
+#[tokio_macros::main]
+async fn main() {
+    env_logger::init();
+    let matches = App::new("Fuzz Corpus")
+        .version("0.1.0")
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Export stored messages as a sanitised fuzz corpus")
+                .arg(
+                    Arg::with_name("database")
+                        .short("d")
+                        .long("database")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .required(true)
+                        .help("Path to the DHT database"),
+                )
+                .arg(
+                    Arg::with_name("output_dir")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .required(true)
+                        .help("Corpus output directory"),
+                )
+                .arg(
+                    Arg::with_name("limit")
+                        .short("l")
+                        .long("limit")
+                        .takes_value(true)
+                        .value_name("NUM")
+                        .help("Maximum number of messages to export"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("replay")
+                .about("Run each file in a corpus through the inbound pipeline harness")
+                .arg(
+                    Arg::with_name("corpus")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .required(true)
+                        .help("Corpus directory or a single input file"),
+                ),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        ("export", Some(matches)) => {
+            let database = PathBuf::from(matches.value_of("database").unwrap());
+            let output_dir = matches.value_of("output_dir").unwrap();
+            let limit = matches
+                .value_of("limit")
+                .map(|limit| limit.parse().expect("Invalid limit"))
+                .unwrap_or(DEFAULT_EXPORT_LIMIT);
+            let num_exported = export_saf_corpus(DbConnectionUrl::File(database), output_dir, limit)
+                .await
+                .unwrap();
+            println!("Exported {} message(s) to '{}'", num_exported, output_dir);
+        },
+        ("replay", Some(matches)) => {
+            let path = PathBuf::from(matches.value_of("corpus").unwrap());
+            let mut inputs = if path.is_dir() {
+                fs::read_dir(&path)
+                    .unwrap()
+                    .map(|entry| entry.unwrap().path())
+                    .collect::<Vec<_>>()
+            } else {
+                vec![path]
+            };
+            inputs.sort();
+
+            for input in inputs {
+                let data = fs::read(&input).unwrap();
+                // A new harness for each input so that the outcome does not depend on previous inputs
+                let mut harness = InboundPipelineHarness::new().await.unwrap();
+                let outcome = harness.process_bytes(&data).await;
+                println!("{}: {:?}", input.to_string_lossy(), outcome);
+            }
+        },
+        _ => {
+            println!("{}", matches.usage());
+        },
+    }
+}
@@ -634,7 +634,10 @@ fn connection_manager_logger(
                     node_name, err
                 );
             },
-            Listening(_) | ListenFailed(_) => unreachable!(),
+            Listening(_) | AuxiliaryListening(_) | ListenFailed(_) => unreachable!(),
+            PeerAddressesChanged(identity) => {
+                println!("'{}' learned new addresses for '{}'", node_name, identity.public_key());
+            },
             NewInboundSubstream(node_id, protocol, _) => {
                 println!(
                     "'{}' negotiated protocol '{}' to '{}'",
@@ -643,6 +646,14 @@ fn connection_manager_logger(
                     node_name
                 );
             },
+            PeerClockSkew(node_id, skew) => {
+                println!(
+                    "'{}' clock differs from '{}' by {}s",
+                    get_name(node_id),
+                    node_name,
+                    skew
+                );
+            },
         }
         event
     }
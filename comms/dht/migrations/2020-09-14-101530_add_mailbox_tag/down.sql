@@ -0,0 +1,4 @@
+DROP INDEX idx_stored_messages_mailbox_tag;
+
+ALTER TABLE stored_messages
+    DROP COLUMN mailbox_tag;
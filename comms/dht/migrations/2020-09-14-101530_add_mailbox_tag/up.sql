@@ -0,0 +1,4 @@
+ALTER TABLE stored_messages
+    ADD mailbox_tag TEXT;
+
+CREATE INDEX idx_stored_messages_mailbox_tag ON stored_messages (mailbox_tag);
@@ -0,0 +1,9 @@
+CREATE TABLE scheduled_messages (
+    id INTEGER NOT NULL PRIMARY KEY,
+    params BLOB NOT NULL,
+    body BLOB NOT NULL,
+    send_at TIMESTAMP NOT NULL,
+    scheduled_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_scheduled_messages_send_at ON scheduled_messages (send_at);
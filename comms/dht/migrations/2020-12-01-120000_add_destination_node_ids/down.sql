@@ -0,0 +1,2 @@
+ALTER TABLE stored_messages
+    DROP COLUMN destination_node_ids;
@@ -0,0 +1,2 @@
+ALTER TABLE stored_messages
+    ADD destination_node_ids TEXT;
@@ -23,15 +23,20 @@
 //! Actor for DHT functionality.
 //!
 //! The DhtActor is responsible for sending a join request on startup
-//! and furnishing [DhtRequest]s.
+//! and furnishing [DhtRequest]s. It also keeps DHT state that is persisted across restarts (last join time,
+//! per-peer store and forward retrieval times and discovery stats).
 //!
 //! [DhtRequest]: ./enum.DhtRequest.html
 
 use crate::{
     broadcast_strategy::BroadcastStrategy,
-    discovery::DhtDiscoveryError,
+    dedup::{DedupCache, DedupCacheStats},
+    discovery::{DhtDiscoveryError, DiscoveryStats},
     outbound::{DhtOutboundError, OutboundMessageRequester, SendMessageParams},
-    proto::{dht::JoinMessage, envelope::DhtMessageType},
+    proto::{
+        dht::{JoinMessage, PeerAddressUpdateMessage},
+        envelope::DhtMessageType,
+    },
     storage::{DbConnection, DhtDatabase, DhtMetadataKey, StorageError},
     DhtConfig,
 };
@@ -45,18 +50,24 @@ use futures::{
     StreamExt,
 };
 use log::*;
-use std::{cmp, fmt, fmt::Display, sync::Arc};
+use std::{cmp, collections::HashMap, fmt, fmt::Display, sync::Arc};
 use tari_comms::{
     connectivity::{ConnectivityError, ConnectivityRequester, ConnectivitySelection},
-    peer_manager::{NodeId, NodeIdentity, PeerFeatures, PeerManager, PeerManagerError, PeerQuery, PeerQuerySortBy},
+    peer_manager::{node_id::NodeDistance, NodeId, NodeIdentity, PeerFeatures, PeerManager, PeerManagerError},
+    protocol::SignedPeerIdentity,
+    PeerConnection,
 };
 use tari_shutdown::ShutdownSignal;
-use tari_utilities::message_format::{MessageFormat, MessageFormatError};
+use tari_utilities::{
+    message_format::{MessageFormat, MessageFormatError},
+    ByteArray,
+};
 use thiserror::Error;
 use tokio::task;
-use ttl_cache::TtlCache;
 
 const LOG_TARGET: &str = "comms::dht::actor";
+/// The number of peers fetched from the peer manager at a time when selecting the closest peers
+const CLOSEST_PEER_SELECTION_BATCH_SIZE: usize = 32;
 
 #[derive(Debug, Error)]
 pub enum DhtActorError {
@@ -70,6 +81,8 @@ pub enum DhtActorError {
     PeerManagerError(#[from] PeerManagerError),
     #[error("Failed to broadcast join message: {0}")]
     FailedToBroadcastJoinMessage(DhtOutboundError),
+    #[error("Failed to send peer address update: {0}")]
+    FailedToSendPeerAddressUpdate(DhtOutboundError),
     #[error("DiscoveryError: {0}")]
     DiscoveryError(#[from] DhtDiscoveryError),
     #[error("StorageError: {0}")]
@@ -100,13 +113,30 @@ impl From<SendError> for DhtActorError {
 pub enum DhtRequest {
     /// Send a Join request to the network
     SendJoin,
+    /// Send a Join request directly to the given peers
+    SendJoinTo(Vec<NodeId>),
+    /// Relay the signed identity of a peer that has new addresses to the peer's neighbours
+    SendPeerAddressUpdate(Box<SignedPeerIdentity>),
     /// Inserts a message signature to the msg hash cache. This operation replies with a boolean
     /// which is true if the signature already exists in the cache, otherwise false
     MsgHashCacheInsert(Vec<u8>, oneshot::Sender<bool>),
+    /// Returns the approximate number of bytes used by the msg hash cache
+    MsgHashCacheMemoryUsage(oneshot::Sender<usize>),
+    /// Returns the hit, miss and eviction counters of the msg hash cache
+    MsgHashCacheStats(oneshot::Sender<DedupCacheStats>),
+    /// Removes all entries from the msg hash cache
+    MsgHashCacheClear(oneshot::Sender<()>),
     /// Fetch selected peers according to the broadcast strategy
     SelectPeers(BroadcastStrategy, oneshot::Sender<Vec<NodeId>>),
     GetMetadata(DhtMetadataKey, oneshot::Sender<Result<Option<Vec<u8>>, DhtActorError>>),
     SetMetadata(DhtMetadataKey, Vec<u8>, oneshot::Sender<Result<(), DhtActorError>>),
+    /// Record that stored messages were received from the given peer
+    RecordSafRetrieval(Box<NodeId>),
+    /// Returns the last time stored messages were received from the given peer
+    GetLastSafRetrieval(Box<NodeId>, oneshot::Sender<Option<DateTime<Utc>>>),
+    /// Record the outcome of a peer discovery. True if the discovery succeeded, otherwise false
+    RecordDiscoveryResult(bool),
+    GetDiscoveryStats(oneshot::Sender<DiscoveryStats>),
 }
 
 impl Display for DhtRequest {
@@ -114,12 +144,23 @@ impl Display for DhtRequest {
         use DhtRequest::*;
         match self {
             SendJoin => f.write_str("SendJoin"),
+            SendJoinTo(peers) => f.write_str(&format!("SendJoinTo ({} peer(s))", peers.len())),
+            SendPeerAddressUpdate(identity) => {
+                f.write_str(&format!("SendPeerAddressUpdate ({})", identity.public_key()))
+            },
             MsgHashCacheInsert(_, _) => f.write_str("MsgHashCacheInsert"),
+            MsgHashCacheMemoryUsage(_) => f.write_str("MsgHashCacheMemoryUsage"),
+            MsgHashCacheStats(_) => f.write_str("MsgHashCacheStats"),
+            MsgHashCacheClear(_) => f.write_str("MsgHashCacheClear"),
             SelectPeers(s, _) => f.write_str(&format!("SelectPeers (Strategy={})", s)),
             GetMetadata(key, _) => f.write_str(&format!("GetMetadata (key={})", key)),
             SetMetadata(key, value, _) => {
                 f.write_str(&format!("SetMetadata (key={}, value={} bytes)", key, value.len()))
             },
+            RecordSafRetrieval(node_id) => f.write_str(&format!("RecordSafRetrieval ({})", node_id.short_str())),
+            GetLastSafRetrieval(node_id, _) => f.write_str(&format!("GetLastSafRetrieval ({})", node_id.short_str())),
+            RecordDiscoveryResult(succeeded) => f.write_str(&format!("RecordDiscoveryResult ({})", succeeded)),
+            GetDiscoveryStats(_) => f.write_str("GetDiscoveryStats"),
         }
     }
 }
@@ -138,6 +179,23 @@ impl DhtRequester {
         self.sender.send(DhtRequest::SendJoin).await.map_err(Into::into)
     }
 
+    /// Send a Join message directly to the given peers, for example when a peer or seed has been added manually. Unlike
+    /// `send_join`, this does not rely on the closest peers propagating the Join.
+    pub async fn send_join_to(&mut self, peers: Vec<NodeId>) -> Result<(), DhtActorError> {
+        self.sender
+            .send(DhtRequest::SendJoinTo(peers))
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Relay the signed identity of a peer that has new addresses to the peer's neighbours
+    pub async fn send_peer_address_update(&mut self, identity: SignedPeerIdentity) -> Result<(), DhtActorError> {
+        self.sender
+            .send(DhtRequest::SendPeerAddressUpdate(Box::new(identity)))
+            .await
+            .map_err(Into::into)
+    }
+
     pub async fn select_peers(&mut self, broadcast_strategy: BroadcastStrategy) -> Result<Vec<NodeId>, DhtActorError> {
         let (reply_tx, reply_rx) = oneshot::channel();
         self.sender
@@ -155,6 +213,28 @@ impl DhtRequester {
         reply_rx.await.map_err(|_| DhtActorError::ReplyCanceled)
     }
 
+    /// Returns the approximate number of bytes used by the message hash (dedup) cache
+    pub async fn get_msg_hash_cache_memory_usage(&mut self) -> Result<usize, DhtActorError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender.send(DhtRequest::MsgHashCacheMemoryUsage(reply_tx)).await?;
+        reply_rx.await.map_err(|_| DhtActorError::ReplyCanceled)
+    }
+
+    /// Returns the hit, miss and eviction counters of the message hash (dedup) cache
+    pub async fn get_msg_hash_cache_stats(&mut self) -> Result<DedupCacheStats, DhtActorError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender.send(DhtRequest::MsgHashCacheStats(reply_tx)).await?;
+        reply_rx.await.map_err(|_| DhtActorError::ReplyCanceled)
+    }
+
+    /// Removes all entries from the message hash (dedup) cache. Any message received after this call is treated as
+    /// new, even if it was seen before.
+    pub async fn clear_msg_hash_cache(&mut self) -> Result<(), DhtActorError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender.send(DhtRequest::MsgHashCacheClear(reply_tx)).await?;
+        reply_rx.await.map_err(|_| DhtActorError::ReplyCanceled)
+    }
+
     pub async fn get_metadata<T: MessageFormat>(&mut self, key: DhtMetadataKey) -> Result<Option<T>, DhtActorError> {
         let (reply_tx, reply_rx) = oneshot::channel();
         self.sender.send(DhtRequest::GetMetadata(key, reply_tx)).await?;
@@ -172,6 +252,39 @@ impl DhtRequester {
         self.sender.send(DhtRequest::SetMetadata(key, bytes, reply_tx)).await?;
         reply_rx.await.map_err(|_| DhtActorError::ReplyCanceled)?
     }
+
+    /// Record that stored messages were received from the given peer. The time is persisted so that subsequent store
+    /// and forward requests to this peer (including after a restart) only request newer messages.
+    pub async fn record_saf_retrieval(&mut self, node_id: NodeId) -> Result<(), DhtActorError> {
+        self.sender
+            .send(DhtRequest::RecordSafRetrieval(Box::new(node_id)))
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Returns the last time stored messages were received from the given peer, if known
+    pub async fn get_last_saf_retrieval(&mut self, node_id: NodeId) -> Result<Option<DateTime<Utc>>, DhtActorError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(DhtRequest::GetLastSafRetrieval(Box::new(node_id), reply_tx))
+            .await?;
+        reply_rx.await.map_err(|_| DhtActorError::ReplyCanceled)
+    }
+
+    /// Record the outcome of a peer discovery
+    pub async fn record_discovery_result(&mut self, succeeded: bool) -> Result<(), DhtActorError> {
+        self.sender
+            .send(DhtRequest::RecordDiscoveryResult(succeeded))
+            .await
+            .map_err(Into::into)
+    }
+
+    /// Returns the discovery success history of this node
+    pub async fn get_discovery_stats(&mut self) -> Result<DiscoveryStats, DhtActorError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender.send(DhtRequest::GetDiscoveryStats(reply_tx)).await?;
+        reply_rx.await.map_err(|_| DhtActorError::ReplyCanceled)
+    }
 }
 
 pub struct DhtActor {
@@ -183,7 +296,9 @@ pub struct DhtActor {
     config: DhtConfig,
     shutdown_signal: Option<ShutdownSignal>,
     request_rx: Fuse<mpsc::Receiver<DhtRequest>>,
-    msg_hash_cache: TtlCache<Vec<u8>, ()>,
+    msg_hash_cache: DedupCache,
+    saf_retrievals: HashMap<NodeId, DateTime<Utc>>,
+    discovery_stats: DiscoveryStats,
 }
 
 impl DhtActor {
@@ -200,7 +315,7 @@ impl DhtActor {
     ) -> Self
     {
         Self {
-            msg_hash_cache: TtlCache::new(config.msg_hash_cache_capacity),
+            msg_hash_cache: DedupCache::new(config.msg_hash_cache_capacity, config.msg_hash_cache_ttl),
             config,
             database: DhtDatabase::new(conn),
             outbound_requester,
@@ -209,6 +324,8 @@ impl DhtActor {
             node_identity,
             shutdown_signal: Some(shutdown_signal),
             request_rx: request_rx.fuse(),
+            saf_retrievals: HashMap::new(),
+            discovery_stats: DiscoveryStats::default(),
         }
     }
 
@@ -234,6 +351,7 @@ impl DhtActor {
                 .map(|dt| format!("Dht has been offline since '{}'", dt))
                 .unwrap_or_else(String::new)
         );
+        self.load_persisted_state().await;
 
         let mut pending_jobs = FuturesUnordered::new();
 
@@ -246,7 +364,13 @@ impl DhtActor {
             futures::select! {
                 request = self.request_rx.select_next_some() => {
                     trace!(target: LOG_TARGET, "DhtActor received request: {}", request);
-                    pending_jobs.push(self.request_handler(request));
+                    match request {
+                        // Persisted state updates are handled in order so that a newer value is never overwritten by
+                        // an older one
+                        DhtRequest::RecordSafRetrieval(node_id) => self.record_saf_retrieval(*node_id).await,
+                        DhtRequest::RecordDiscoveryResult(succeeded) => self.record_discovery_result(succeeded).await,
+                        request => pending_jobs.push(self.request_handler(request)),
+                    }
                 },
 
                 result = pending_jobs.select_next_some() => {
@@ -258,6 +382,7 @@ impl DhtActor {
                 _ = shutdown_signal => {
                     info!(target: LOG_TARGET, "DhtActor is shutting down because it received a shutdown signal.");
                     self.mark_shutdown_time().await;
+                    self.persist_msg_hash_cache().await;
                     break Ok(());
                 },
             }
@@ -274,24 +399,157 @@ impl DhtActor {
         }
     }
 
+    async fn persist_msg_hash_cache(&self) {
+        if !self.config.msg_hash_cache_persist {
+            return;
+        }
+        let snapshot = self.msg_hash_cache.snapshot();
+        let num_entries = snapshot.len();
+        match self
+            .database
+            .set_metadata_value(DhtMetadataKey::MsgHashCache, snapshot)
+            .await
+        {
+            Ok(_) => debug!(target: LOG_TARGET, "Persisted {} message hash(es)", num_entries),
+            Err(err) => warn!(target: LOG_TARGET, "Failed to persist message hash cache: {:?}", err),
+        }
+    }
+
+    async fn load_persisted_state(&mut self) {
+        if self.config.msg_hash_cache_persist {
+            match self
+                .database
+                .get_metadata_value::<Vec<(Vec<u8>, DateTime<Utc>)>>(DhtMetadataKey::MsgHashCache)
+                .await
+            {
+                Ok(Some(entries)) => {
+                    self.msg_hash_cache.restore(entries);
+                    debug!(
+                        target: LOG_TARGET,
+                        "Restored {} message hash(es)",
+                        self.msg_hash_cache.stats().num_entries
+                    );
+                },
+                Ok(None) => {},
+                Err(err) => warn!(target: LOG_TARGET, "Failed to load message hash cache: {:?}", err),
+            }
+        }
+
+        match self
+            .database
+            .get_metadata_value::<HashMap<NodeId, DateTime<Utc>>>(DhtMetadataKey::SafRetrievalTimestamps)
+            .await
+        {
+            Ok(Some(saf_retrievals)) => {
+                self.saf_retrievals = saf_retrievals;
+                self.prune_saf_retrievals();
+            },
+            Ok(None) => {},
+            Err(err) => warn!(target: LOG_TARGET, "Failed to load SAF retrieval timestamps: {:?}", err),
+        }
+
+        match self
+            .database
+            .get_metadata_value::<DiscoveryStats>(DhtMetadataKey::DiscoveryStats)
+            .await
+        {
+            Ok(Some(stats)) => self.discovery_stats = stats,
+            Ok(None) => {},
+            Err(err) => warn!(target: LOG_TARGET, "Failed to load discovery stats: {:?}", err),
+        }
+    }
+
+    /// Removes SAF retrieval timestamps that are older than the minimum SAF request period. These are no longer
+    /// needed because SAF requests always include at least that period.
+    fn prune_saf_retrievals(&mut self) {
+        let min_period = chrono::Duration::from_std(self.config.saf_minimum_request_period)
+            .unwrap_or_else(|_| chrono::Duration::max_value());
+        let oldest = Utc::now().checked_sub_signed(min_period);
+        self.saf_retrievals
+            .retain(|_, retrieved_at| oldest.map(|t| *retrieved_at > t).unwrap_or(true));
+    }
+
+    async fn record_saf_retrieval(&mut self, node_id: NodeId) {
+        self.saf_retrievals.insert(node_id, Utc::now());
+        self.prune_saf_retrievals();
+        if let Err(err) = self
+            .database
+            .set_metadata_value(DhtMetadataKey::SafRetrievalTimestamps, self.saf_retrievals.clone())
+            .await
+        {
+            warn!(
+                target: LOG_TARGET,
+                "Failed to persist SAF retrieval timestamps: {:?}", err
+            );
+        }
+    }
+
+    async fn record_discovery_result(&mut self, succeeded: bool) {
+        self.discovery_stats.record(succeeded);
+        if let Err(err) = self
+            .database
+            .set_metadata_value(DhtMetadataKey::DiscoveryStats, self.discovery_stats.clone())
+            .await
+        {
+            warn!(target: LOG_TARGET, "Failed to persist discovery stats: {:?}", err);
+        }
+    }
+
     fn request_handler(&mut self, request: DhtRequest) -> BoxFuture<'static, Result<(), DhtActorError>> {
         use DhtRequest::*;
         match request {
             SendJoin => {
                 let node_identity = Arc::clone(&self.node_identity);
                 let outbound_requester = self.outbound_requester.clone();
-                Box::pin(Self::broadcast_join(node_identity, outbound_requester))
+                let db = self.database.clone();
+                Box::pin(async move {
+                    Self::broadcast_join(node_identity, outbound_requester).await?;
+                    db.set_metadata_value(DhtMetadataKey::LastJoinTimestamp, Utc::now())
+                        .await?;
+                    Ok(())
+                })
+            },
+            SendJoinTo(peers) => {
+                let node_identity = Arc::clone(&self.node_identity);
+                let outbound_requester = self.outbound_requester.clone();
+                Box::pin(Self::send_join_to_peers(node_identity, outbound_requester, peers))
+            },
+            SendPeerAddressUpdate(identity) => {
+                let outbound_requester = self.outbound_requester.clone();
+                Box::pin(Self::send_peer_address_update(outbound_requester, *identity))
             },
             MsgHashCacheInsert(hash, reply_tx) => {
                 // No locks needed here. Downside is this isn't really async, however this should be
                 // fine as it is very quick
-                let already_exists = self
-                    .msg_hash_cache
-                    .insert(hash, (), self.config.msg_hash_cache_ttl)
-                    .is_some();
+                let already_exists = self.msg_hash_cache.insert(hash);
                 let result = reply_tx.send(already_exists).map_err(|_| DhtActorError::ReplyCanceled);
                 Box::pin(future::ready(result))
             },
+            MsgHashCacheMemoryUsage(reply_tx) => {
+                let usage = self.msg_hash_cache.memory_usage();
+                let result = reply_tx.send(usage).map_err(|_| DhtActorError::ReplyCanceled);
+                Box::pin(future::ready(result))
+            },
+            MsgHashCacheStats(reply_tx) => {
+                let stats = self.msg_hash_cache.stats();
+                let result = reply_tx.send(stats).map_err(|_| DhtActorError::ReplyCanceled);
+                Box::pin(future::ready(result))
+            },
+            MsgHashCacheClear(reply_tx) => {
+                self.msg_hash_cache.clear();
+                info!(target: LOG_TARGET, "Message hash cache cleared");
+                if !self.config.msg_hash_cache_persist {
+                    let result = reply_tx.send(()).map_err(|_| DhtActorError::ReplyCanceled);
+                    return Box::pin(future::ready(result));
+                }
+                // Remove the persisted snapshot so that the cleared entries are not restored on the next startup
+                let db = self.database.clone();
+                Box::pin(async move {
+                    db.set_metadata_value(DhtMetadataKey::MsgHashCache, Vec::<(Vec<u8>, DateTime<Utc>)>::new())
+                        .await?;
+                    reply_tx.send(()).map_err(|_| DhtActorError::ReplyCanceled)
+                })
+            },
             SelectPeers(broadcast_strategy, reply_tx) => {
                 let peer_manager = Arc::clone(&self.peer_manager);
                 let node_identity = Arc::clone(&self.node_identity);
@@ -332,6 +590,20 @@ impl DhtActor {
                     Ok(())
                 })
             },
+            RecordSafRetrieval(_) | RecordDiscoveryResult(_) => {
+                unreachable!("persisted state updates are handled in the DhtActor run loop")
+            },
+            GetLastSafRetrieval(node_id, reply_tx) => {
+                let last_retrieval = self.saf_retrievals.get(&node_id).copied();
+                let result = reply_tx.send(last_retrieval).map_err(|_| DhtActorError::ReplyCanceled);
+                Box::pin(future::ready(result))
+            },
+            GetDiscoveryStats(reply_tx) => {
+                let result = reply_tx
+                    .send(self.discovery_stats.clone())
+                    .map_err(|_| DhtActorError::ReplyCanceled);
+                Box::pin(future::ready(result))
+            },
         }
     }
 
@@ -360,6 +632,67 @@ impl DhtActor {
         Ok(())
     }
 
+    async fn send_join_to_peers(
+        node_identity: Arc<NodeIdentity>,
+        mut outbound_requester: OutboundMessageRequester,
+        peers: Vec<NodeId>,
+    ) -> Result<(), DhtActorError>
+    {
+        debug!(target: LOG_TARGET, "Sending Join message to {} peer(s)", peers.len());
+
+        for peer in peers {
+            let message = JoinMessage::from(&node_identity);
+            // A manual join may be repeated to the same peer, so outbound duplicate suppression is bypassed
+            outbound_requester
+                .send_message_no_header(
+                    SendMessageParams::new()
+                        .direct_node_id(peer)
+                        .with_destination(node_identity.node_id().clone().into())
+                        .with_dht_message_type(DhtMessageType::Join)
+                        .force_origin()
+                        .allow_duplicates()
+                        .finish(),
+                    message,
+                )
+                .await
+                .map_err(DhtActorError::FailedToBroadcastJoinMessage)?;
+        }
+
+        Ok(())
+    }
+
+    async fn send_peer_address_update(
+        mut outbound_requester: OutboundMessageRequester,
+        identity: SignedPeerIdentity,
+    ) -> Result<(), DhtActorError>
+    {
+        let node_id = NodeId::from_public_key(identity.public_key());
+        debug!(
+            target: LOG_TARGET,
+            "Sending address update for peer '{}' to its neighbours",
+            node_id.short_str()
+        );
+        let message = PeerAddressUpdateMessage {
+            public_key: identity.public_key().to_vec(),
+            identity: identity.to_encoded_identity(),
+        };
+
+        // The peer already knows its own addresses, so it is excluded
+        outbound_requester
+            .send_message_no_header(
+                SendMessageParams::new()
+                    .propagate(node_id.clone().into(), vec![node_id.clone()])
+                    .with_destination(node_id.into())
+                    .with_dht_message_type(DhtMessageType::PeerAddressUpdate)
+                    .finish(),
+                message,
+            )
+            .await
+            .map_err(DhtActorError::FailedToSendPeerAddressUpdate)?;
+
+        Ok(())
+    }
+
     async fn select_peers(
         config: DhtConfig,
         node_identity: Arc<NodeIdentity>,
@@ -386,6 +719,21 @@ impl DhtActor {
                     .map(|peer| peer.map(|p| vec![p.node_id]).unwrap_or_default())
                     .map_err(Into::into)
             },
+            DirectOrClosest(node_id) => {
+                // Send directly if connected, otherwise send to peers closer to the destination
+                let connections =
+                    Self::select_propagation_connections(&config, &node_identity, &mut connectivity, &node_id, &[])
+                        .await?;
+                if connections.iter().all(|conn| conn.peer_node_id() != &*node_id) {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Not connected to peer '{}'. Sending to {} closer peer(s).",
+                        node_id.short_str(),
+                        connections.len()
+                    );
+                }
+                Ok(connections.iter().map(|conn| conn.peer_node_id()).cloned().collect())
+            },
             Flood(exclude) => {
                 let peers = connectivity
                     .select_connections(ConnectivitySelection::all_nodes(exclude))
@@ -430,10 +778,19 @@ impl DhtActor {
 
                 Ok(candidates)
             },
+            Regional(regional_request) => {
+                Self::select_peers_within_distance(
+                    &peer_manager,
+                    &regional_request.node_id,
+                    &regional_request.max_distance,
+                    &regional_request.excluded_peers,
+                )
+                .await
+            },
             Random(n, excluded) => {
                 // Send to a random set of peers of size n that are Communication Nodes
                 Ok(peer_manager
-                    .random_peers(n, &excluded)
+                    .random_peers_with_rng(n, &excluded, &mut config.rng.clone())
                     .await?
                     .into_iter()
                     .map(|p| p.node_id)
@@ -473,51 +830,39 @@ impl DhtActor {
                     .cloned()
                     .or_else(|| destination.public_key().map(|pk| NodeId::from_public_key(pk)));
 
-                let connections = match dest_node_id {
-                    Some(node_id) => {
-                        let dest_connection = connectivity.get_connection(node_id.clone()).await?;
-                        // If the peer was added to the exclude list, we don't want to send directly to the peer.
-                        // This ensures that we don't just send a message back to the peer that sent it.
-                        let dest_connection = dest_connection.filter(|c| !exclude.contains(c.peer_node_id()));
-                        match dest_connection {
-                            Some(conn) => {
-                                // We're connected to the destination, so send the message directly
-                                vec![conn]
-                            },
-                            None => {
-                                // Select connections closer to the destination
-                                let mut connections = connectivity
-                                    .select_connections(ConnectivitySelection::closest_to(
-                                        node_id.clone(),
-                                        config.num_neighbouring_nodes,
-                                        exclude.clone(),
-                                    ))
-                                    .await?;
-
-                                // Exclude candidates that are further away from the destination than this node
-                                // unless this node has not selected a big enough sample i.e. this node is not well
-                                // connected
-                                if connections.len() >= config.propagation_factor {
-                                    let dist_from_dest = node_identity.node_id().distance(&node_id);
-                                    let before_len = connections.len();
-                                    connections = connections
-                                        .into_iter()
-                                        .filter(|conn| conn.peer_node_id().distance(&node_id) <= dist_from_dest)
-                                        .collect::<Vec<_>>();
-
-                                    debug!(
-                                        target: LOG_TARGET,
-                                        "Filtered out {} node(s) that are further away than this node.",
-                                        before_len - connections.len()
-                                    );
+                let connections = match (dest_node_id, destination.node_ids()) {
+                    (Some(node_id), _) => {
+                        Self::select_propagation_connections(
+                            &config,
+                            &node_identity,
+                            &mut connectivity,
+                            &node_id,
+                            &exclude,
+                        )
+                        .await?
+                    },
+                    (None, Some(node_ids)) => {
+                        // Propagate toward each destination, only sending once to peers that are on the path to more
+                        // than one destination
+                        let mut connections = Vec::<PeerConnection>::new();
+                        for node_id in node_ids {
+                            let selected = Self::select_propagation_connections(
+                                &config,
+                                &node_identity,
+                                &mut connectivity,
+                                node_id,
+                                &exclude,
+                            )
+                            .await?;
+                            for conn in selected {
+                                if connections.iter().all(|c| c.peer_node_id() != conn.peer_node_id()) {
+                                    connections.push(conn);
                                 }
-
-                                connections.truncate(config.propagation_factor);
-                                connections
-                            },
+                            }
                         }
+                        connections
                     },
-                    None => {
+                    (None, None) => {
                         debug!(
                             target: LOG_TARGET,
                             "No destination for propagation, sending to {} random peers", config.propagation_factor
@@ -563,6 +908,57 @@ impl DhtActor {
         }
     }
 
+    /// Selects connections to propagate a message toward `node_id`. If this node is connected to `node_id` that
+    /// connection is selected, otherwise up to `propagation_factor` connected peers that are closer to the destination
+    /// are selected.
+    async fn select_propagation_connections(
+        config: &DhtConfig,
+        node_identity: &NodeIdentity,
+        connectivity: &mut ConnectivityRequester,
+        node_id: &NodeId,
+        exclude: &[NodeId],
+    ) -> Result<Vec<PeerConnection>, DhtActorError>
+    {
+        let dest_connection = connectivity.get_connection(node_id.clone()).await?;
+        // If the peer was added to the exclude list, we don't want to send directly to the peer.
+        // This ensures that we don't just send a message back to the peer that sent it.
+        let dest_connection = dest_connection.filter(|c| !exclude.contains(c.peer_node_id()));
+        if let Some(conn) = dest_connection {
+            // We're connected to the destination, so send the message directly
+            return Ok(vec![conn]);
+        }
+
+        // Select connections closer to the destination
+        let mut connections = connectivity
+            .select_connections(ConnectivitySelection::closest_to(
+                node_id.clone(),
+                config.num_neighbouring_nodes,
+                exclude.to_vec(),
+            ))
+            .await?;
+
+        // Exclude candidates that are further away from the destination than this node
+        // unless this node has not selected a big enough sample i.e. this node is not well
+        // connected
+        if connections.len() >= config.propagation_factor {
+            let dist_from_dest = node_identity.node_id().distance(node_id);
+            let before_len = connections.len();
+            connections = connections
+                .into_iter()
+                .filter(|conn| conn.peer_node_id().distance(node_id) <= dist_from_dest)
+                .collect::<Vec<_>>();
+
+            debug!(
+                target: LOG_TARGET,
+                "Filtered out {} node(s) that are further away than this node.",
+                before_len - connections.len()
+            );
+        }
+
+        connections.truncate(config.propagation_factor);
+        Ok(connections)
+    }
+
     /// Selects at least `n` MESSAGE_PROPAGATION peers (assuming that many are known) that are closest to `node_id` as
     /// well as other peers which do not advertise the MESSAGE_PROPAGATION flag (unless excluded by some other means
     /// e.g. `excluded` list, filter_predicate etc. The filter_predicate is called on each peer excluding them from
@@ -589,35 +985,44 @@ impl DhtActor {
         let mut banned_count = 0;
         let mut excluded_count = 0;
         let mut filtered_out_node_count = 0;
-        let query = PeerQuery::new()
-            .select_where(|peer| {
+
+        // Candidates are sorted using the in-memory node id index and then fetched in batches, closest first, until
+        // enough eligible peers have been found.
+        let candidates = peer_manager.node_ids_by_distance(node_id, &[]).await;
+        let mut peers = Vec::with_capacity(n);
+        for batch in candidates.chunks(CLOSEST_PEER_SELECTION_BATCH_SIZE) {
+            for peer in peer_manager.get_many(batch).await? {
                 if peer.is_banned() {
                     banned_count += 1;
-                    return false;
+                    continue;
                 }
 
                 if !peer.features.contains(features) {
                     filtered_out_node_count += 1;
-                    return false;
+                    continue;
                 }
 
                 if peer.is_offline() {
                     connect_ineligable_count += 1;
-                    return false;
+                    continue;
                 }
 
-                let is_excluded = excluded_peers.contains(&peer.node_id);
-                if is_excluded {
+                if excluded_peers.contains(&peer.node_id) {
                     excluded_count += 1;
-                    return false;
+                    continue;
                 }
 
-                true
-            })
-            .sort_by(PeerQuerySortBy::DistanceFrom(&node_id))
-            .limit(n);
+                peers.push(peer);
+                if peers.len() == n {
+                    break;
+                }
+            }
+
+            if peers.len() == n {
+                break;
+            }
+        }
 
-        let peers = peer_manager.perform_query(query).await?;
         let total_excluded = banned_count + connect_ineligable_count + excluded_count + filtered_out_node_count;
         if total_excluded > 0 {
             debug!(
@@ -636,20 +1041,63 @@ impl DhtActor {
 
         Ok(peers.into_iter().map(|p| p.node_id).collect())
     }
+
+    /// Selects every known Communication Node that is not banned or offline and whose distance to `node_id` is less
+    /// than `max_distance`.
+    async fn select_peers_within_distance(
+        peer_manager: &PeerManager,
+        node_id: &NodeId,
+        max_distance: &NodeDistance,
+        excluded_peers: &[NodeId],
+    ) -> Result<Vec<NodeId>, DhtActorError>
+    {
+        // Candidates are sorted by distance, so only those within the threshold need to be fetched
+        let candidates = peer_manager
+            .node_ids_by_distance(node_id, excluded_peers)
+            .await
+            .into_iter()
+            .take_while(|n| n.distance(node_id) < *max_distance)
+            .collect::<Vec<_>>();
+
+        let mut peers = Vec::with_capacity(candidates.len());
+        for batch in candidates.chunks(CLOSEST_PEER_SELECTION_BATCH_SIZE) {
+            let selected = peer_manager
+                .get_many(batch)
+                .await?
+                .into_iter()
+                .filter(|peer| {
+                    !peer.is_banned() && !peer.is_offline() && peer.features.contains(PeerFeatures::COMMUNICATION_NODE)
+                })
+                .map(|peer| peer.node_id);
+            peers.extend(selected);
+        }
+
+        debug!(
+            target: LOG_TARGET,
+            "Regional Peer Selection: {} of {} peer(s) within distance {} of {} selected",
+            peers.len(),
+            candidates.len(),
+            max_distance,
+            node_id.short_str()
+        );
+
+        Ok(peers)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{
-        broadcast_strategy::BroadcastClosestRequest,
+        broadcast_strategy::{BroadcastClosestRequest, BroadcastRegionalRequest},
         envelope::NodeDestination,
         test_utils::{build_peer_manager, make_client_identity, make_node_identity},
     };
     use chrono::{DateTime, Utc};
+    use std::time::Duration;
     use tari_comms::test_utils::mocks::{create_connectivity_mock, create_peer_connection_mock_pair};
     use tari_shutdown::Shutdown;
-    use tari_test_utils::random;
+    use tari_test_utils::{async_assert_eventually, random};
 
     async fn db_connection() -> DbConnection {
         let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
@@ -684,6 +1132,17 @@ mod test {
         requester.send_join().await.unwrap();
         let (params, _) = unwrap_oms_send_msg!(out_rx.next().await.unwrap());
         assert_eq!(params.dht_message_type, DhtMessageType::Join);
+
+        let peers = vec![
+            make_node_identity().node_id().clone(),
+            make_node_identity().node_id().clone(),
+        ];
+        requester.send_join_to(peers.clone()).await.unwrap();
+        for peer in peers {
+            let (params, _) = unwrap_oms_send_msg!(out_rx.next().await.unwrap());
+            assert_eq!(params.dht_message_type, DhtMessageType::Join);
+            assert_eq!(params.broadcast_strategy.direct_node_id(), Some(&peer));
+        }
     }
 
     #[tokio_macros::test_basic]
@@ -717,6 +1176,66 @@ mod test {
         assert_eq!(is_dup, true);
         let is_dup = requester.insert_message_hash(Vec::new()).await.unwrap();
         assert_eq!(is_dup, false);
+
+        let usage = requester.get_msg_hash_cache_memory_usage().await.unwrap();
+        assert!(usage >= 3);
+
+        let stats = requester.get_msg_hash_cache_stats().await.unwrap();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.num_entries, 2);
+
+        requester.clear_msg_hash_cache().await.unwrap();
+        let is_dup = requester.insert_message_hash(vec![1u8, 2, 3]).await.unwrap();
+        assert_eq!(is_dup, false);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn msg_hash_cache_persisted_on_shutdown() {
+        let conn = db_connection().await;
+        let (connectivity_manager, mock) = create_connectivity_mock();
+        mock.spawn();
+
+        let spawn_actor = |shutdown: &Shutdown| {
+            let (out_tx, _) = mpsc::channel(1);
+            let (actor_tx, actor_rx) = mpsc::channel(1);
+            DhtActor::new(
+                DhtConfig {
+                    msg_hash_cache_persist: true,
+                    ..Default::default()
+                },
+                conn.clone(),
+                make_node_identity(),
+                build_peer_manager(),
+                connectivity_manager.clone(),
+                OutboundMessageRequester::new(out_tx),
+                actor_rx,
+                shutdown.to_signal(),
+            )
+            .spawn();
+            DhtRequester::new(actor_tx)
+        };
+
+        let mut shutdown = Shutdown::new();
+        let mut requester = spawn_actor(&shutdown);
+        assert_eq!(requester.insert_message_hash(vec![1u8, 2, 3]).await.unwrap(), false);
+        shutdown.trigger().unwrap();
+
+        let db = DhtDatabase::new(conn.clone());
+        async_assert_eventually!(
+            db.get_metadata_value::<Vec<(Vec<u8>, DateTime<Utc>)>>(DhtMetadataKey::MsgHashCache)
+                .await
+                .unwrap()
+                .map(|entries| entries.len()),
+            expect = Some(1),
+            max_attempts = 20,
+            interval = Duration::from_millis(10),
+        );
+
+        // The restarted actor treats the message as a duplicate
+        let shutdown = Shutdown::new();
+        let mut requester = spawn_actor(&shutdown);
+        assert_eq!(requester.insert_message_hash(vec![1u8, 2, 3]).await.unwrap(), true);
     }
 
     #[tokio_macros::test_basic]
@@ -786,6 +1305,28 @@ mod test {
             .unwrap();
         assert_eq!(peers.len(), 1);
 
+        // Peers on the path to multiple destinations are only selected once
+        let destination = NodeDestination::multicast(vec![
+            conn_out.peer_node_id().clone(),
+            make_node_identity().node_id().clone(),
+        ])
+        .unwrap();
+        let peers = requester
+            .select_peers(BroadcastStrategy::Propagate(destination, Vec::new()))
+            .await
+            .unwrap();
+        assert_eq!(peers.len(), 1);
+
+        // Not connected to the destination, so closer peers are selected
+        let peers = requester
+            .select_peers(BroadcastStrategy::DirectOrClosest(Box::new(
+                make_node_identity().node_id().clone(),
+            )))
+            .await
+            .unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(&peers[0], conn_out.peer_node_id());
+
         let send_request = Box::new(BroadcastClosestRequest {
             node_id: node_identity.node_id().clone(),
             excluded_peers: vec![],
@@ -797,6 +1338,29 @@ mod test {
             .unwrap();
         assert_eq!(peers.len(), 2);
 
+        // The client peer is not a communication node so only the other node is within the region
+        let regional_request = Box::new(BroadcastRegionalRequest {
+            node_id: node_identity.node_id().clone(),
+            max_distance: NodeDistance::max_distance(),
+            excluded_peers: vec![],
+        });
+        let peers = requester
+            .select_peers(BroadcastStrategy::Regional(regional_request))
+            .await
+            .unwrap();
+        assert_eq!(peers.len(), 1);
+
+        let regional_request = Box::new(BroadcastRegionalRequest {
+            node_id: node_identity.node_id().clone(),
+            max_distance: NodeDistance::zero(),
+            excluded_peers: vec![],
+        });
+        let peers = requester
+            .select_peers(BroadcastStrategy::Regional(regional_request))
+            .await
+            .unwrap();
+        assert!(peers.is_empty());
+
         let peers = requester
             .select_peers(BroadcastStrategy::DirectNodeId(Box::new(
                 client_node_identity.node_id().clone(),
@@ -865,4 +1429,55 @@ mod test {
 
         shutdown.trigger().unwrap();
     }
+
+    #[tokio_macros::test_basic]
+    async fn persisted_state_restored_on_restart() {
+        let conn = db_connection().await;
+        let (connectivity_manager, mock) = create_connectivity_mock();
+        mock.spawn();
+        let peer_node_id = make_node_identity().node_id().clone();
+        let shutdown = Shutdown::new();
+
+        let spawn_actor = || {
+            let (out_tx, _) = mpsc::channel(1);
+            let (actor_tx, actor_rx) = mpsc::channel(1);
+            DhtActor::new(
+                Default::default(),
+                conn.clone(),
+                make_node_identity(),
+                build_peer_manager(),
+                connectivity_manager.clone(),
+                OutboundMessageRequester::new(out_tx),
+                actor_rx,
+                shutdown.to_signal(),
+            )
+            .spawn();
+            DhtRequester::new(actor_tx)
+        };
+
+        let mut requester = spawn_actor();
+        assert!(requester
+            .get_last_saf_retrieval(peer_node_id.clone())
+            .await
+            .unwrap()
+            .is_none());
+        requester.record_saf_retrieval(peer_node_id.clone()).await.unwrap();
+        requester.record_discovery_result(true).await.unwrap();
+        requester.record_discovery_result(false).await.unwrap();
+        let stats = requester.get_discovery_stats().await.unwrap();
+        assert_eq!(stats.num_succeeded, 1);
+        assert_eq!(stats.num_failed, 1);
+        assert!(stats.last_succeeded_at.is_some());
+        let last_retrieval = requester
+            .get_last_saf_retrieval(peer_node_id.clone())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // A new actor using the same database restores the state
+        let mut requester = spawn_actor();
+        assert_eq!(requester.get_discovery_stats().await.unwrap(), stats);
+        let restored = requester.get_last_saf_retrieval(peer_node_id).await.unwrap().unwrap();
+        assert_eq!(restored, last_retrieval);
+    }
 }
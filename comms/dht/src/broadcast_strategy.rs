@@ -21,13 +21,17 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::envelope::NodeDestination;
+use serde::{Deserialize, Serialize};
 use std::{
     fmt,
     fmt::{Display, Formatter},
 };
-use tari_comms::{peer_manager::node_id::NodeId, types::CommsPublicKey};
+use tari_comms::{
+    peer_manager::node_id::{NodeDistance, NodeId},
+    types::CommsPublicKey,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BroadcastClosestRequest {
     pub node_id: NodeId,
     pub excluded_peers: Vec<NodeId>,
@@ -46,18 +50,45 @@ impl Display for BroadcastClosestRequest {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BroadcastRegionalRequest {
+    pub node_id: NodeId,
+    /// Peers are selected if their distance to `node_id` is less than this distance
+    pub max_distance: NodeDistance,
+    pub excluded_peers: Vec<NodeId>,
+}
+
+impl Display for BroadcastRegionalRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RegionalRequest: node_id = {}, max_distance = {}, excluded_peers = {} peer(s)",
+            self.node_id,
+            self.max_distance,
+            self.excluded_peers.len(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BroadcastStrategy {
     /// Send to a particular peer matching the given node ID
     DirectNodeId(Box<NodeId>),
     /// Send to a particular peer matching the given Public Key
     DirectPublicKey(Box<CommsPublicKey>),
+    /// Send directly to the peer matching the given node ID if connected, otherwise propagate toward the peer's
+    /// network region
+    DirectOrClosest(Box<NodeId>),
     /// Send to all connected peers. If no peers are connected, no messages are sent.
     Flood(Vec<NodeId>),
     /// Send to a random set of peers of size n that are Communication Nodes, excluding the given node IDs
     Random(usize, Vec<NodeId>),
     /// Send to all n nearest Communication Nodes according to the given BroadcastClosestRequest
     Closest(Box<BroadcastClosestRequest>),
+    /// Send to every known connectable Communication Node within the distance threshold given in the
+    /// BroadcastRegionalRequest. Unlike `Closest`, the number of peers selected depends on the density of peers in the
+    /// region.
+    Regional(Box<BroadcastRegionalRequest>),
     Broadcast(Vec<NodeId>),
     /// Propagate to a set of closest neighbours and random peers
     Propagate(NodeDestination, Vec<NodeId>),
@@ -69,8 +100,10 @@ impl fmt::Display for BroadcastStrategy {
         match self {
             DirectPublicKey(pk) => write!(f, "DirectPublicKey({})", pk),
             DirectNodeId(node_id) => write!(f, "DirectNodeId({})", node_id),
+            DirectOrClosest(node_id) => write!(f, "DirectOrClosest({})", node_id),
             Flood(excluded) => write!(f, "Flood({} excluded)", excluded.len()),
             Closest(request) => write!(f, "Closest({})", request),
+            Regional(request) => write!(f, "Regional({})", request),
             Random(n, excluded) => write!(f, "Random({}, {} excluded)", n, excluded.len()),
             Broadcast(excluded) => write!(f, "Broadcast({} excluded)", excluded.len()),
             Propagate(destination, excluded) => write!(f, "Propagate({}, {} excluded)", destination, excluded.len(),),
@@ -82,7 +115,10 @@ impl BroadcastStrategy {
     /// Returns true if this strategy will send multiple messages, otherwise false
     pub fn is_multi_message(&self) -> bool {
         use BroadcastStrategy::*;
-        matches!(self, Closest(_) | Flood(_) | Broadcast(_) | Random(_, _) | Propagate(_, _))
+        matches!(
+            self,
+            Closest(_) | Regional(_) | Flood(_) | Broadcast(_) | Random(_, _) | Propagate(_, _) | DirectOrClosest(_)
+        )
     }
 
     pub fn is_direct(&self) -> bool {
@@ -138,7 +174,20 @@ mod test {
             .is_direct(),
             false
         );
+        assert_eq!(
+            BroadcastStrategy::Regional(Box::new(BroadcastRegionalRequest {
+                node_id: NodeId::default(),
+                max_distance: NodeDistance::max_distance(),
+                excluded_peers: Default::default(),
+            }))
+            .is_direct(),
+            false
+        );
         assert_eq!(BroadcastStrategy::Random(0, vec![]).is_direct(), false);
+        assert_eq!(
+            BroadcastStrategy::DirectOrClosest(Box::new(NodeId::default())).is_direct(),
+            false
+        );
     }
 
     #[test]
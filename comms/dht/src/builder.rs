@@ -24,8 +24,13 @@ use crate::{dht::DhtInitializationError, outbound::DhtOutboundRequest, DbConnect
 use futures::channel::mpsc;
 use std::{sync::Arc, time::Duration};
 use tari_comms::{
+    admin::AdminCommands,
     connectivity::ConnectivityRequester,
     peer_manager::{NodeIdentity, PeerManager},
+    protocol::messaging::OutboundQueueUsage,
+    security_events::SecurityEventPublisher,
+    utils::rng::SharedRng,
+    ShutdownReporter,
 };
 use tari_shutdown::ShutdownSignal;
 
@@ -35,6 +40,10 @@ pub struct DhtBuilder {
     config: DhtConfig,
     outbound_tx: mpsc::Sender<DhtOutboundRequest>,
     connectivity: ConnectivityRequester,
+    outbound_queue_usage: Option<OutboundQueueUsage>,
+    shutdown_reporter: Option<ShutdownReporter>,
+    security_events: Option<SecurityEventPublisher>,
+    admin_commands: Option<AdminCommands>,
     shutdown_signal: ShutdownSignal,
 }
 
@@ -56,6 +65,10 @@ impl DhtBuilder {
             peer_manager,
             outbound_tx,
             connectivity,
+            outbound_queue_usage: None,
+            shutdown_reporter: None,
+            security_events: None,
+            admin_commands: None,
             shutdown_signal,
         }
     }
@@ -100,6 +113,11 @@ impl DhtBuilder {
         self
     }
 
+    pub fn with_signature_cache_persistence(mut self) -> Self {
+        self.config.msg_hash_cache_persist = true;
+        self
+    }
+
     pub fn with_num_random_nodes(mut self, n: usize) -> Self {
         self.config.num_random_nodes = n;
         self
@@ -130,7 +148,42 @@ impl DhtBuilder {
         self
     }
 
-    /// Build and initialize a Dht object.
+    /// Set the source of randomness used for peer selection and backoff jitter. A seeded `SharedRng` makes these
+    /// decisions reproducible in simulations.
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.config.rng = rng;
+        self
+    }
+
+    /// Include the memory used by the comms outbound message queue in memory usage reports. This should be the same
+    /// `OutboundQueueUsage` given to the `MessagingProtocolExtension`.
+    pub fn with_outbound_queue_usage(mut self, outbound_queue_usage: OutboundQueueUsage) -> Self {
+        self.outbound_queue_usage = Some(outbound_queue_usage);
+        self
+    }
+
+    /// Include the number of stored messages in the comms shutdown report. The comms shutdown future waits for the
+    /// store and forward service to record its count before resolving.
+    pub fn with_shutdown_reporter(mut self, shutdown_reporter: ShutdownReporter) -> Self {
+        self.shutdown_reporter = Some(shutdown_reporter);
+        self
+    }
+
+    /// Publish DHT security events (e.g. messages with an invalid origin signature) on the given publisher, usually
+    /// the one returned from `CommsNode::security_events`.
+    pub fn with_security_events(mut self, security_events: SecurityEventPublisher) -> Self {
+        self.security_events = Some(security_events);
+        self
+    }
+
+    /// Register the DHT admin commands (e.g. `dashboard`) on the given commands, usually the ones returned from
+    /// `UnspawnedCommsNode::admin_commands`. See `tari_comms::admin`.
+    pub fn with_admin_commands(mut self, admin_commands: AdminCommands) -> Self {
+        self.admin_commands = Some(admin_commands);
+        self
+    }
+
+    /// Build and initialize a Dht object. An error is returned if the config is invalid (see `DhtConfig::validate`).
     ///
     /// Will panic not in a tokio runtime context
     pub async fn build(self) -> Result<Dht, DhtInitializationError> {
@@ -140,6 +193,10 @@ impl DhtBuilder {
             self.peer_manager,
             self.outbound_tx,
             self.connectivity,
+            self.outbound_queue_usage,
+            self.shutdown_reporter,
+            self.security_events.unwrap_or_default(),
+            self.admin_commands,
             self.shutdown_signal,
         )
         .await
@@ -20,12 +20,48 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::{envelope::Network, network_discovery::NetworkDiscoveryConfig, storage::DbConnectionUrl};
-use std::time::Duration;
+use crate::{
+    envelope::Network,
+    inbound::LatencyBudgetConfig,
+    network_discovery::NetworkDiscoveryConfig,
+    storage::DbConnectionUrl,
+    store_forward::SafStoreFilter,
+};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time::Duration};
+use tari_common::{
+    configuration::{optional_seconds, seconds},
+    ConfigurationError,
+};
+use tari_comms::{backoff::BackoffPolicy, peer_manager::Peer, types::CommsPublicKey, utils::rng::SharedRng};
 
-#[derive(Debug, Clone)]
+/// The maximum `outbound_buffer_size`. Each pending outbound request holds a message body of up to
+/// `saf_max_message_size` bytes, so a full buffer of this size may hold several GiB at the default message size limit.
+pub const MAX_OUTBOUND_BUFFER_SIZE: usize = 10_000;
+/// The maximum `msg_hash_cache_capacity`. Each entry uses approximately 100 bytes, i.e. up to 500 MiB of memory.
+pub const MAX_MSG_HASH_CACHE_CAPACITY: usize = 5_000_000;
+/// The maximum `saf_msg_storage_capacity`. Stored messages are kept in the DHT database, which may grow to tens of GiB
+/// of disk space at this capacity.
+pub const MAX_SAF_MSG_STORAGE_CAPACITY: usize = 10_000_000;
+/// The maximum `saf_max_returned_messages`. All messages returned for a single request are loaded into memory at once.
+pub const MAX_SAF_RETURNED_MESSAGES: usize = 10_000;
+/// The maximum `saf_max_concurrent_stored_messages`. Each concurrently processed message uses a pipeline task.
+pub const MAX_SAF_CONCURRENT_STORED_MESSAGES: usize = 256;
+/// The maximum `control_message_buffer_size`.
+pub const MAX_CONTROL_MESSAGE_BUFFER_SIZE: usize = 10_000;
+/// The maximum `control_message_max_concurrent_tasks`. Each concurrently handled control message may perform database
+/// reads and writes, so this should not greatly exceed the number of database connections available to the DHT.
+pub const MAX_CONTROL_MESSAGE_CONCURRENT_TASKS: usize = 1_000;
+
+/// DHT configuration. This can be loaded from a configuration file, in which case durations are given in seconds and
+/// any setting that is not given takes its default value. `database_url`, `network`, `plaintext_policy` and
+/// `saf_store_filters` are not loaded and must be set by the application.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct DhtConfig {
-    /// The `DbConnectionUrl` for the Dht database. Default: In-memory database
+    /// The `DbConnectionUrl` for the Dht database. A file-backed (or shared in-memory) database must not be shared
+    /// between DHT instances in the same process. Default: In-memory database
+    #[serde(skip)]
     pub database_url: DbConnectionUrl,
     /// The size of the buffer (channel) which holds pending outbound message requests.
     /// Default: 20
@@ -42,9 +78,18 @@ pub struct DhtConfig {
     /// Send to this many peers when using the propagate strategy
     /// Default: 4
     pub propagation_factor: usize,
+    /// The maximum number of peers that a message received from another peer is forwarded to. This limits how much
+    /// traffic a single inbound message can cause this node to send.
+    /// Default: 8
+    pub forward_max_amplification: usize,
+    /// The maximum rate, in bytes per second, at which this node forwards messages on behalf of other peers. Messages
+    /// are forwarded to fewer peers, or dropped, once the budget is exhausted. None disables the budget.
+    /// Default: 1 MiB/s
+    pub forward_bandwidth_budget: Option<usize>,
     /// The amount of seconds added to the current time (Utc) which will then be used to check if the message has
     /// expired or not when processing the message
     /// Default: 10800
+    #[serde(with = "seconds")]
     pub saf_msg_validity: Duration,
     /// The maximum number of messages that can be stored using the Store-and-forward middleware.
     /// Default: 100,000
@@ -53,32 +98,111 @@ pub struct DhtConfig {
     /// not within one of this nodes _n_ closest nodes.
     /// Default 8
     pub saf_num_closest_nodes: usize,
+    /// The number of closest connected store and forward nodes from which stored messages are requested concurrently
+    /// when requesting from neighbours. Messages returned by more than one node are deduplicated.
+    /// Default: 3
+    pub saf_num_request_nodes: usize,
     /// The maximum number of messages to return from a store and forward retrieval request.
     /// Default: 100
     pub saf_max_returned_messages: usize,
     /// The time-to-live duration used for storage of low priority messages by the Store-and-forward middleware.
     /// Default: 6 hours
+    #[serde(with = "seconds")]
     pub saf_low_priority_msg_storage_ttl: Duration,
     /// The time-to-live duration used for storage of high priority messages by the Store-and-forward middleware.
     /// Default: 3 days
+    #[serde(with = "seconds")]
     pub saf_high_priority_msg_storage_ttl: Duration,
+    /// The time-to-live duration used for storage of messages destined for client peers that this node serves (see
+    /// `StoreAndForwardRequester::register_client`).
+    /// Default: 7 days
+    #[serde(with = "seconds")]
+    pub saf_client_msg_storage_ttl: Duration,
+    /// When true, stored messages for a served client are pushed to the client as soon as it connects, and as they
+    /// arrive while the client is connected, rather than waiting for the client to request them.
+    /// Default: true
+    pub saf_client_push_on_connect: bool,
     /// The limit on the message size to store in SAF storage in bytes. Default 500 KiB
     pub saf_max_message_size: usize,
+    /// Stored messages returned in response to a SAF request are split into responses of at most this many bytes.
+    /// Default: 64 KiB
+    pub saf_max_response_chunk_size: usize,
+    /// The maximum rate, in bytes per second, at which chunked SAF responses are sent to a peer. Pacing responses
+    /// prevents large stored-message bundles from delaying latency-sensitive messages on slow links. None disables
+    /// pacing.
+    /// Default: 128 KiB/s
+    pub saf_response_pacing_rate: Option<usize>,
+    /// The maximum number of messages from a single stored messages response that are validated and decrypted
+    /// concurrently. Each message is passed on as soon as it and the messages before it have been processed.
+    /// Default: 8
+    pub saf_max_concurrent_stored_messages: usize,
     /// When true, store and forward messages are requested from peers on connect (Default: true)
     pub saf_auto_request: bool,
+    /// The maximum number of attempts to send a request for stored messages to a peer.
+    /// Default: 3
+    pub saf_request_max_attempts: usize,
+    /// The backoff between attempts to send a request for stored messages. Backoff delays are given in milliseconds.
+    /// Default: exponential, starting at 2 secs and doubling up to 1 min, with 20% jitter
+    pub saf_request_backoff: BackoffPolicy,
     /// The minimum period used to request SAF messages from a peer. When requesting SAF messages,
     /// it will request messages since the DHT last went offline, but this may be a small amount of
     /// time, so `minimum_request_period` can be used so that messages aren't missed.
     /// Default: 3 days
+    #[serde(with = "seconds")]
     pub saf_minimum_request_period: Duration,
+    /// The interval at which stored messages are checked against this node's network region. Messages whose
+    /// destination is no longer in the region (e.g. because closer nodes have joined) are propagated toward the
+    /// destination, where closer nodes will store them, and removed from this node's store. None disables the check.
+    /// Default: 30 minutes
+    #[serde(with = "optional_seconds")]
+    pub saf_handoff_interval: Option<Duration>,
+    /// When true, a signed rejection is sent in response to requests for stored messages that this node will not
+    /// serve (e.g. because the requester is not in this node's network region), so that the requester can request
+    /// stored messages from another node.
+    /// Default: true
+    pub saf_send_rejections: bool,
+    /// The minimum interval between requests for stored messages from a single peer. Requests made sooner are rejected
+    /// as rate limited. None disables the limit.
+    /// Default: None
+    #[serde(with = "optional_seconds")]
+    pub saf_min_request_interval: Option<Duration>,
+    /// Store filters registered when the DHT is initialized. When any store filters are registered, only messages
+    /// matching at least one of them are stored for peers. Special-purpose nodes can use these to opt out of storing
+    /// unrelated traffic. Further filters can be added at runtime using `StoreAndForwardRequester::add_store_filter`.
+    /// Default: no filters (all eligible messages are stored)
+    #[serde(skip)]
+    pub saf_store_filters: Vec<SafStoreFilter>,
+    /// The number of recent outbound messages, and their send results, kept in the outbound audit log (see
+    /// `Dht::outbound_audit_log`). Zero disables the audit log.
+    /// Default: 1000
+    pub outbound_audit_log_capacity: usize,
+    /// The number of recent inbound messages that failed in the pipeline kept, along with their header fields,
+    /// sizes, timing and error chain, in the pipeline error log (see `Dht::pipeline_error_log`). Zero disables
+    /// the log.
+    /// Default: 0
+    pub pipeline_error_log_capacity: usize,
+    /// Latency budgets for the stages of the inbound pipeline. Messages that exceed a budget are reported with a
+    /// `DhtEvent::PipelineLatencyBudgetExceeded` event.
+    /// Default: disabled
+    pub latency_budget: LatencyBudgetConfig,
+    /// The maximum number of messages that may be scheduled for sending at a later time (see
+    /// `OutboundMessageRequester::schedule_message`). Zero disables scheduled sends.
+    /// Default: 1000
+    pub max_scheduled_messages: usize,
     /// The max capacity of the message hash cache
     /// Default: 100,000
     pub msg_hash_cache_capacity: usize,
     /// The time-to-live for items in the message hash cache
     /// Default: 300s (5 mins)
+    #[serde(with = "seconds")]
     pub msg_hash_cache_ttl: Duration,
+    /// When true, the message hash cache is saved when the DHT shuts down and restored on startup, so that messages
+    /// seen shortly before a restart are not propagated again.
+    /// Default: false
+    pub msg_hash_cache_persist: bool,
     /// The duration to wait for a peer discovery to complete before giving up.
     /// Default: 2 minutes
+    #[serde(with = "seconds")]
     pub discovery_request_timeout: Duration,
     /// Set to true to automatically broadcast a join message when ready, otherwise false. Default: false
     pub auto_join: bool,
@@ -86,34 +210,166 @@ pub struct DhtConfig {
     /// enough connections to the network as determined by comms ConnectivityManager. If a join was sent and then state
     /// change happens again after this period, another join will be sent.
     /// Default: 10 minutes
+    #[serde(with = "seconds")]
     pub join_cooldown_interval: Duration,
+    /// If the node loses connectivity for longer than this period, a Join is re-broadcast, the neighbouring and random
+    /// pools are refreshed and stored messages are requested from neighbours once the node is back online.
+    /// Default: 5 minutes
+    #[serde(with = "seconds")]
+    pub rejoin_offline_threshold: Duration,
+    /// Set to true to relay the signed identity of a known peer to the peer's neighbours when the peer is found to
+    /// have new addresses, so that the network learns of the new addresses quickly. Address updates received from
+    /// other nodes are relayed further if they change the peer's addresses. Default: false
+    pub propagate_peer_address_updates: bool,
+    /// Relayed peer address updates signed by the peer longer ago than this are discarded. This limits the period in
+    /// which an old update can be replayed to revert a peer's addresses.
+    /// Default: 1 hour
+    #[serde(with = "seconds")]
+    pub peer_address_update_max_age: Duration,
     /// The interval to update the neighbouring and random pools, if necessary.
     /// Default: 2 minutes
+    #[serde(with = "seconds")]
     pub connectivity_update_interval: Duration,
     /// The interval to change the random pool peers.
     /// Default: 2 hours
+    #[serde(with = "seconds")]
     pub connectivity_random_pool_refresh: Duration,
+    /// The maximum number of peers that were connected before the last shutdown to reconnect to on startup, before the
+    /// neighbouring and random pools are dialed. Set to 0 to disable.
+    /// Default: 8
+    pub warm_start_max_peers: usize,
+    /// Peers that were last seen connected longer ago than this are not reconnected to on startup.
+    /// Default: 24 hours
+    #[serde(with = "seconds")]
+    pub warm_start_max_age: Duration,
+    /// The maximum time to wait for reconnections to recent peers on startup before the neighbouring and random pools
+    /// are dialed.
+    /// Default: 10 seconds
+    #[serde(with = "seconds")]
+    pub warm_start_timeout: Duration,
     /// The active Network. Default: TestNet
+    #[serde(skip)]
     pub network: Network,
     /// Network discovery config
     pub network_discovery: NetworkDiscoveryConfig,
+    /// The source of randomness used to select random peers and to apply jitter to backoffs. Simulations can use a
+    /// seeded `SharedRng` so that a run can be replayed exactly. Random connections used for broadcast and propagation
+    /// are selected by the comms connectivity manager (see `CommsBuilder::with_rng`). A seeded `SharedRng` is
+    /// re-seeded from the node id when the DHT is initialized, so DHT instances created from the same config do not
+    /// share a sequence.
+    /// Default: OS RNG
+    #[serde(skip)]
+    pub rng: SharedRng,
     /// Length of time to ban a peer if the peer misbehaves at the DHT-level.
     /// Default: 6 hrs
+    #[serde(with = "seconds")]
     pub ban_duration: Duration,
     /// This allows the use of test addresses in the network.
     pub allow_test_addresses: bool,
-    /// The maximum number of messages over `flood_ban_timespan` to allow before banning the peer (for `ban_duration`)
+    /// The maximum number of messages over `flood_ban_timespan` to allow before banning the peer (for `ban_duration`).
+    /// Trusted and seed peers are never banned for flooding.
     /// Default: 1000 messages
     pub flood_ban_max_msg_count: usize,
     /// The timespan over which to calculate the max message rate.
     /// `flood_ban_max_count / flood_ban_timespan (as seconds) = avg. messages per second over the timespan`
     /// Default: 100 seconds
+    #[serde(with = "seconds")]
     pub flood_ban_timespan: Duration,
     /// Once a peer has been marked as offline, wait at least this length of time before reconsidering them.
     /// In a situation where a node is not well-connected and many nodes are locally marked as offline, we can retry
     /// peers that were previously tried.
     /// Default: 24 hours
+    #[serde(with = "seconds")]
     pub offline_peer_cooldown: Duration,
+    /// The interval at which the approximate memory usage of the SAF store, dedup cache, peer database cache and
+    /// outbound message queue is written to the metrics collector. None disables memory usage reporting.
+    /// Default: 1 minute
+    #[serde(with = "optional_seconds")]
+    pub memory_usage_report_interval: Option<Duration>,
+    /// The file to which snapshots of key metrics (peer counts, SAF occupancy, bandwidth and error counts) are
+    /// periodically written, so that the health of the node can be analysed after the fact. None disables metrics
+    /// snapshots.
+    /// Default: None
+    pub metrics_snapshot_path: Option<PathBuf>,
+    /// The interval at which a metrics snapshot is written to `metrics_snapshot_path`.
+    /// Default: 5 minutes
+    #[serde(with = "seconds")]
+    pub metrics_snapshot_interval: Duration,
+    /// The number of metrics snapshots kept in `metrics_snapshot_path`. Once this is reached, the oldest snapshot is
+    /// overwritten.
+    /// Default: 2016 (one week at the default interval)
+    pub metrics_snapshot_capacity: usize,
+    /// Controls whether domain messages that are not encrypted are accepted by this node. DHT protocol messages (e.g.
+    /// Join, Discovery) are not affected by this policy. Cleartext domain messages sent by untrusted peers are never
+    /// accepted.
+    /// Default: `PlaintextPolicy::Accept`
+    #[serde(skip)]
+    pub plaintext_policy: PlaintextPolicy,
+    /// If set, an outbound message that is identical to a message sent to the same destination within this window is
+    /// not sent. Messages sent with `SendMessageParams::allow_duplicates` are never suppressed. None disables outbound
+    /// duplicate suppression.
+    /// Default: None
+    #[serde(with = "optional_seconds")]
+    pub outbound_dedup_window: Option<Duration>,
+    /// When true, the decryption and origin signature verification of inbound messages run on the blocking thread
+    /// pool rather than on the pipeline task, so that a high volume of encrypted traffic does not starve the async
+    /// runtime.
+    /// Default: false
+    pub inbound_crypto_offload: bool,
+    /// The number of inbound DHT control messages (Join, Discovery and store and forward) that may be queued for
+    /// handling. Control messages are handled separately from domain messages so that they cannot be starved by a
+    /// flood of domain messages. Control messages received while the queue is full are discarded.
+    /// Default: 100
+    pub control_message_buffer_size: usize,
+    /// The maximum number of control messages that are handled concurrently.
+    /// Default: 10
+    pub control_message_max_concurrent_tasks: usize,
+    /// The maximum number of control messages handled within `control_message_rate_limit_restock_interval`.
+    /// Default: 50
+    pub control_message_rate_limit_capacity: usize,
+    /// Default: 1 second
+    #[serde(with = "seconds")]
+    pub control_message_rate_limit_restock_interval: Duration,
+    /// Domain message types that are delivered in send order for each origin. Messages of these types that arrive out
+    /// of order are held until the preceding messages arrive. Sequenced messages are passed to the next service one at
+    /// a time, so a slow handler for one sequenced message type delays all other sequenced message types.
+    /// Default: empty (no messages are reordered)
+    pub sequenced_message_types: Vec<i32>,
+    /// The maximum time that a sequenced message is held while waiting for a preceding message, after which the
+    /// missing message is skipped.
+    /// Default: 2 seconds
+    #[serde(with = "seconds")]
+    pub sequence_reorder_timeout: Duration,
+    /// The maximum number of sequenced messages held for each origin and message type. Once exceeded, missing
+    /// messages are skipped.
+    /// Default: 100
+    pub sequence_max_buffered: usize,
+    /// The maximum number of origin and message type pairs for which sequencing state is kept. Once reached, messages
+    /// from further origins are delivered without reordering.
+    /// Default: 1000
+    pub sequence_max_origins: usize,
+    /// The maximum number of sequenced messages held across all origins. Once reached, missing messages are skipped
+    /// instead of holding further messages.
+    /// Default: 10000
+    pub sequence_max_total_buffered: usize,
+    /// The window over which inbound messages, joins and pongs are counted to detect a network partition. If any count
+    /// falls below its threshold, a `NetworkPartitionSuspected` event is emitted. None disables partition detection.
+    /// Default: 10 minutes
+    #[serde(with = "optional_seconds")]
+    pub network_heartbeat_window: Option<Duration>,
+    /// The minimum number of inbound messages expected within `network_heartbeat_window`.
+    /// Default: 1
+    pub network_heartbeat_min_messages: usize,
+    /// The minimum number of Join messages expected within `network_heartbeat_window`.
+    /// Default: 0
+    pub network_heartbeat_min_joins: usize,
+    /// The minimum number of liveness pongs expected within `network_heartbeat_window`.
+    /// Default: 0
+    pub network_heartbeat_min_pongs: usize,
+    /// If true, the neighbouring and random pools are refreshed, a Join is re-broadcast and stored messages are
+    /// requested when a network partition is suspected.
+    /// Default: false
+    pub network_heartbeat_reconnect: bool,
 }
 
 impl DhtConfig {
@@ -143,6 +399,133 @@ impl DhtConfig {
             ..Default::default()
         }
     }
+
+    /// Configuration for dedicated relay and store and forward infrastructure nodes that handle a high volume of
+    /// traffic. Buffers, concurrency limits and caches are larger than the defaults and inbound crypto is offloaded to
+    /// the blocking thread pool. Nodes using this configuration should expect to use in the order of 1-2 GiB of memory
+    /// and several GiB of disk space for stored messages, and should have at least 4 CPU cores available.
+    pub fn high_throughput() -> Self {
+        Self {
+            outbound_buffer_size: 1000,
+            forward_max_amplification: 16,
+            forward_bandwidth_budget: Some(16 * 1024 * 1024),
+            saf_msg_storage_capacity: 1_000_000,
+            saf_max_returned_messages: 500,
+            saf_response_pacing_rate: Some(1024 * 1024),
+            saf_max_concurrent_stored_messages: 32,
+            msg_hash_cache_capacity: 1_000_000,
+            msg_hash_cache_ttl: Duration::from_secs(30 * 60),
+            msg_hash_cache_persist: true,
+            inbound_crypto_offload: true,
+            control_message_buffer_size: 1000,
+            control_message_max_concurrent_tasks: 50,
+            control_message_rate_limit_capacity: 500,
+            flood_ban_max_msg_count: 100_000,
+            ..Default::default()
+        }
+    }
+
+    /// Checks that the configured values are within their valid ranges
+    pub fn validate(&self) -> Result<(), ConfigurationError> {
+        let non_zero = [
+            ("outbound_buffer_size", self.outbound_buffer_size),
+            ("num_neighbouring_nodes", self.num_neighbouring_nodes),
+            ("broadcast_factor", self.broadcast_factor),
+            ("propagation_factor", self.propagation_factor),
+            ("saf_num_closest_nodes", self.saf_num_closest_nodes),
+            ("saf_max_returned_messages", self.saf_max_returned_messages),
+            ("saf_max_message_size", self.saf_max_message_size),
+            ("saf_max_response_chunk_size", self.saf_max_response_chunk_size),
+            (
+                "saf_max_concurrent_stored_messages",
+                self.saf_max_concurrent_stored_messages,
+            ),
+            ("saf_request_max_attempts", self.saf_request_max_attempts),
+            ("msg_hash_cache_capacity", self.msg_hash_cache_capacity),
+            ("control_message_buffer_size", self.control_message_buffer_size),
+            (
+                "control_message_max_concurrent_tasks",
+                self.control_message_max_concurrent_tasks,
+            ),
+            (
+                "control_message_rate_limit_capacity",
+                self.control_message_rate_limit_capacity,
+            ),
+            ("metrics_snapshot_capacity", self.metrics_snapshot_capacity),
+            (
+                "network_discovery.max_sync_peers",
+                self.network_discovery.max_sync_peers,
+            ),
+            ("latency_budget.shed_threshold", self.latency_budget.shed_threshold),
+        ];
+        if let Some((field, _)) = non_zero.iter().find(|(_, value)| *value == 0) {
+            return Err(ConfigurationError::new(field, "must be greater than zero"));
+        }
+
+        let upper_bounds = [
+            (
+                "outbound_buffer_size",
+                self.outbound_buffer_size,
+                MAX_OUTBOUND_BUFFER_SIZE,
+            ),
+            (
+                "msg_hash_cache_capacity",
+                self.msg_hash_cache_capacity,
+                MAX_MSG_HASH_CACHE_CAPACITY,
+            ),
+            (
+                "saf_msg_storage_capacity",
+                self.saf_msg_storage_capacity,
+                MAX_SAF_MSG_STORAGE_CAPACITY,
+            ),
+            (
+                "saf_max_returned_messages",
+                self.saf_max_returned_messages,
+                MAX_SAF_RETURNED_MESSAGES,
+            ),
+            (
+                "saf_max_concurrent_stored_messages",
+                self.saf_max_concurrent_stored_messages,
+                MAX_SAF_CONCURRENT_STORED_MESSAGES,
+            ),
+            (
+                "control_message_buffer_size",
+                self.control_message_buffer_size,
+                MAX_CONTROL_MESSAGE_BUFFER_SIZE,
+            ),
+            (
+                "control_message_max_concurrent_tasks",
+                self.control_message_max_concurrent_tasks,
+                MAX_CONTROL_MESSAGE_CONCURRENT_TASKS,
+            ),
+        ];
+        if let Some((field, _, max)) = upper_bounds.iter().find(|(_, value, max)| value > max) {
+            return Err(ConfigurationError::new(
+                field,
+                &format!("must not be greater than {}", max),
+            ));
+        }
+
+        if self.saf_response_pacing_rate == Some(0) {
+            return Err(ConfigurationError::new(
+                "saf_response_pacing_rate",
+                "must be greater than zero if set",
+            ));
+        }
+
+        let backoffs = [
+            ("saf_request_backoff", &self.saf_request_backoff),
+            (
+                "network_discovery.on_failure_backoff",
+                &self.network_discovery.on_failure_backoff,
+            ),
+        ];
+        for (field, backoff) in backoffs.iter() {
+            backoff.validate().map_err(|err| ConfigurationError::new(field, &err))?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for DhtConfig {
@@ -151,32 +534,144 @@ impl Default for DhtConfig {
             num_neighbouring_nodes: 8,
             num_random_nodes: 4,
             propagation_factor: 4,
+            forward_max_amplification: 8,
+            forward_bandwidth_budget: Some(1024 * 1024),
             broadcast_factor: 8,
             outbound_buffer_size: 20,
             saf_num_closest_nodes: 10,
+            saf_num_request_nodes: 3,
             saf_max_returned_messages: 50,
             saf_msg_storage_capacity: 100_000,
             saf_low_priority_msg_storage_ttl: Duration::from_secs(6 * 60 * 60), // 6 hours
             saf_high_priority_msg_storage_ttl: Duration::from_secs(3 * 24 * 60 * 60), // 3 days
+            saf_client_msg_storage_ttl: Duration::from_secs(7 * 24 * 60 * 60),  // 7 days
+            saf_client_push_on_connect: true,
             saf_auto_request: true,
+            saf_request_max_attempts: 3,
+            saf_request_backoff: BackoffPolicy::exponential(Duration::from_secs(2), 2.0, Duration::from_secs(60))
+                .with_jitter(0.2),
             saf_max_message_size: 512 * 1024,
+            saf_max_response_chunk_size: 64 * 1024,
+            saf_response_pacing_rate: Some(128 * 1024),
+            saf_max_concurrent_stored_messages: 8,
             saf_minimum_request_period: Duration::from_secs(3 * 24 * 60 * 60), // 3 days
+            saf_handoff_interval: Some(Duration::from_secs(30 * 60)),
+            saf_send_rejections: true,
+            saf_min_request_interval: None,
+            saf_store_filters: Vec::new(),
+            outbound_audit_log_capacity: 1000,
+            pipeline_error_log_capacity: 0,
+            latency_budget: Default::default(),
+            max_scheduled_messages: 1000,
             msg_hash_cache_capacity: 100_000,
             msg_hash_cache_ttl: Duration::from_secs(5 * 60),
+            msg_hash_cache_persist: false,
             database_url: DbConnectionUrl::Memory,
             discovery_request_timeout: Duration::from_secs(2 * 60),
             connectivity_update_interval: Duration::from_secs(2 * 60),
             connectivity_random_pool_refresh: Duration::from_secs(2 * 60 * 60),
+            warm_start_max_peers: 8,
+            warm_start_max_age: Duration::from_secs(24 * 60 * 60),
+            warm_start_timeout: Duration::from_secs(10),
             auto_join: false,
             join_cooldown_interval: Duration::from_secs(10 * 60),
+            rejoin_offline_threshold: Duration::from_secs(5 * 60),
+            propagate_peer_address_updates: false,
+            peer_address_update_max_age: Duration::from_secs(60 * 60),
             network: Network::TestNet,
             network_discovery: Default::default(),
+            rng: SharedRng::os(),
             ban_duration: Duration::from_secs(6 * 60 * 60),
             allow_test_addresses: false,
             flood_ban_max_msg_count: 10000,
             flood_ban_timespan: Duration::from_secs(100),
             offline_peer_cooldown: Duration::from_secs(24 * 60 * 60),
             saf_msg_validity: Duration::from_secs(10800),
+            memory_usage_report_interval: Some(Duration::from_secs(60)),
+            metrics_snapshot_path: None,
+            metrics_snapshot_interval: Duration::from_secs(5 * 60),
+            metrics_snapshot_capacity: 7 * 24 * 12,
+            plaintext_policy: PlaintextPolicy::Accept,
+            outbound_dedup_window: None,
+            inbound_crypto_offload: false,
+            control_message_buffer_size: 100,
+            control_message_max_concurrent_tasks: 10,
+            control_message_rate_limit_capacity: 50,
+            control_message_rate_limit_restock_interval: Duration::from_secs(1),
+            sequenced_message_types: Vec::new(),
+            sequence_reorder_timeout: Duration::from_secs(2),
+            sequence_max_buffered: 100,
+            sequence_max_origins: 1000,
+            sequence_max_total_buffered: 10_000,
+            network_heartbeat_window: Some(Duration::from_secs(10 * 60)),
+            network_heartbeat_min_messages: 1,
+            network_heartbeat_min_joins: 0,
+            network_heartbeat_min_pongs: 0,
+            network_heartbeat_reconnect: false,
         }
     }
 }
+
+/// Policy for inbound domain messages that are not encrypted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaintextPolicy {
+    /// Accept all cleartext domain messages
+    Accept,
+    /// Only accept cleartext domain messages that have an authenticated origin (a valid origin MAC) which is either
+    /// one of the given public keys, or a trusted or seed peer that sent the message itself. Messages without an
+    /// authenticated origin are rejected.
+    AcceptFromWhitelisted(Vec<CommsPublicKey>),
+    /// Discard all cleartext domain messages
+    Reject,
+}
+
+impl PlaintextPolicy {
+    /// Returns true if a cleartext domain message from the given source peer and (optional) authenticated origin is
+    /// accepted by this policy
+    pub fn is_accepted(&self, source_peer: &Peer, origin: Option<&CommsPublicKey>) -> bool {
+        use PlaintextPolicy::*;
+        if source_peer.trust_level().is_untrusted() {
+            return false;
+        }
+        match (self, origin) {
+            (Accept, _) => true,
+            (AcceptFromWhitelisted(_), None) => false,
+            (AcceptFromWhitelisted(whitelist), Some(origin)) => {
+                whitelist.contains(origin) ||
+                    (*origin == source_peer.public_key && source_peer.trust_level().is_trusted())
+            },
+            (Reject, _) => false,
+        }
+    }
+}
+
+impl Default for PlaintextPolicy {
+    fn default() -> Self {
+        PlaintextPolicy::Accept
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        DhtConfig::default().validate().unwrap();
+        DhtConfig::high_throughput().validate().unwrap();
+
+        let config = DhtConfig {
+            msg_hash_cache_capacity: MAX_MSG_HASH_CACHE_CAPACITY + 1,
+            ..DhtConfig::high_throughput()
+        };
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("msg_hash_cache_capacity"));
+
+        let config = DhtConfig {
+            outbound_buffer_size: 0,
+            ..Default::default()
+        };
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("outbound_buffer_size"));
+    }
+}
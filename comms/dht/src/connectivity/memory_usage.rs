@@ -0,0 +1,122 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{MemoryUsageSource, MetricsCollectorHandle};
+use crate::{store_forward::StoreAndForwardRequester, DhtRequester};
+use futures::StreamExt;
+use log::*;
+use std::{sync::Arc, time::Duration};
+use tari_comms::{protocol::messaging::OutboundQueueUsage, PeerManager};
+use tari_shutdown::ShutdownSignal;
+use tokio::{task, time};
+
+const LOG_TARGET: &str = "comms::dht::metrics::memory_usage";
+
+/// Periodically writes the approximate memory usage of DHT and comms caches, stores and queues to the metrics
+/// collector.
+pub struct MemoryUsageReporter {
+    interval: Duration,
+    dht_requester: DhtRequester,
+    saf_requester: StoreAndForwardRequester,
+    peer_manager: Arc<PeerManager>,
+    outbound_queue_usage: Option<OutboundQueueUsage>,
+    metrics_collector: MetricsCollectorHandle,
+    shutdown_signal: ShutdownSignal,
+}
+
+impl MemoryUsageReporter {
+    pub fn new(
+        interval: Duration,
+        dht_requester: DhtRequester,
+        saf_requester: StoreAndForwardRequester,
+        peer_manager: Arc<PeerManager>,
+        outbound_queue_usage: Option<OutboundQueueUsage>,
+        metrics_collector: MetricsCollectorHandle,
+        shutdown_signal: ShutdownSignal,
+    ) -> Self
+    {
+        Self {
+            interval,
+            dht_requester,
+            saf_requester,
+            peer_manager,
+            outbound_queue_usage,
+            metrics_collector,
+            shutdown_signal,
+        }
+    }
+
+    pub fn spawn(self) {
+        task::spawn(self.run());
+    }
+
+    async fn run(mut self) {
+        let mut shutdown_signal = self.shutdown_signal.clone();
+        let mut ticker = time::interval(self.interval).fuse();
+        loop {
+            futures::select! {
+                _ = ticker.select_next_some() => {
+                    self.report().await;
+                },
+                _ = shutdown_signal => {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Memory usage reporter is shutting down because it received the shutdown signal"
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn report(&mut self) {
+        use MemoryUsageSource::*;
+
+        match self.saf_requester.get_storage_size().await {
+            Ok(num_bytes) => self.write(SafStorage, num_bytes),
+            Err(err) => debug!(target: LOG_TARGET, "Failed to get SAF storage size: {}", err),
+        }
+
+        match self.dht_requester.get_msg_hash_cache_memory_usage().await {
+            Ok(num_bytes) => self.write(DedupCache, num_bytes),
+            Err(err) => debug!(target: LOG_TARGET, "Failed to get dedup cache memory usage: {}", err),
+        }
+
+        let num_bytes = self.peer_manager.approx_memory_usage().await;
+        self.write(PeerDbCache, num_bytes);
+
+        if let Some(usage) = self.outbound_queue_usage.as_ref() {
+            let num_bytes = usage.approx_memory_usage();
+            let num_messages = usage.num_messages();
+            self.write(OutboundQueue, num_bytes);
+            self.metrics_collector.write_metric_outbound_queue_depth(num_messages);
+        }
+
+        if let Ok(report) = self.metrics_collector.get_memory_usage().await {
+            trace!(target: LOG_TARGET, "Approximate memory usage: {:?}", report);
+        }
+    }
+
+    fn write(&mut self, source: MemoryUsageSource, num_bytes: usize) {
+        self.metrics_collector.write_metric_memory_usage(source, num_bytes);
+    }
+}
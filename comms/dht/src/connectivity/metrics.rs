@@ -20,6 +20,7 @@
 //  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use crate::{codec::EnvelopeWireFormat, envelope::DhtMessageType};
 use futures::{
     channel::{mpsc, mpsc::SendError, oneshot, oneshot::Canceled},
     future,
@@ -32,7 +33,7 @@ use std::{
     future::Future,
     time::{Duration, Instant},
 };
-use tari_comms::peer_manager::NodeId;
+use tari_comms::{peer_manager::NodeId, protocol::messaging::SendFailReason};
 use tokio::task;
 
 const LOG_TARGET: &str = "comms::dht::metrics";
@@ -47,6 +48,16 @@ enum MetricOp {
 pub enum MetricWrite {
     MessageReceived(NodeId),
     ClearMetrics(NodeId),
+    MemoryUsage(MemoryUsageSource, usize),
+    WireFormat(WireFormatDirection, EnvelopeWireFormat),
+    OutboundSendLatency(OutboundPriorityClass, Duration),
+    OutboundSendFailed(SendFailReason),
+    OutboundQueueDepth(usize),
+    EnvelopeChecksumFailed,
+    JoinReceived,
+    PongReceived,
+    MessageForwarded { num_copies: usize, num_bytes: usize },
+    ForwardDropped,
 }
 
 #[derive(Debug)]
@@ -54,12 +65,255 @@ pub enum MetricRead {
     MessagesReceivedGetTimeseries(NodeId, oneshot::Sender<TimeSeries<()>>),
     MessagesReceivedRateExceeding((usize, Duration), oneshot::Sender<Vec<(NodeId, f32)>>),
     MessagesReceivedTotalCountInTimespan(Duration, oneshot::Sender<usize>),
+    MemoryUsage(oneshot::Sender<MemoryUsageReport>),
+    WireFormat(oneshot::Sender<WireFormatCounts>),
+    OutboundSend(oneshot::Sender<OutboundSendMetrics>),
+    EnvelopeChecksumFailures(oneshot::Sender<usize>),
+    NetworkHeartbeat(Duration, oneshot::Sender<NetworkHeartbeat>),
+    Forward(oneshot::Sender<ForwardMetrics>),
+}
+
+/// A component for which approximate memory usage is reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryUsageSource {
+    /// Stored messages held for offline peers
+    SafStorage,
+    /// Message hashes kept for deduplication
+    DedupCache,
+    /// In-memory peer database indexes
+    PeerDbCache,
+    /// Messages waiting to be sent to peers
+    OutboundQueue,
+}
+
+/// Approximate memory usage in bytes of each reported component
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryUsageReport {
+    pub saf_storage: usize,
+    pub dedup_cache: usize,
+    pub peer_db_cache: usize,
+    pub outbound_queue: usize,
+}
+
+impl MemoryUsageReport {
+    /// Returns the total approximate memory usage in bytes across all components
+    pub fn total(&self) -> usize {
+        self.saf_storage + self.dedup_cache + self.peer_db_cache + self.outbound_queue
+    }
+
+    fn set(&mut self, source: MemoryUsageSource, num_bytes: usize) {
+        use MemoryUsageSource::*;
+        match source {
+            SafStorage => self.saf_storage = num_bytes,
+            DedupCache => self.dedup_cache = num_bytes,
+            PeerDbCache => self.peer_db_cache = num_bytes,
+            OutboundQueue => self.outbound_queue = num_bytes,
+        }
+    }
+}
+
+/// Whether an envelope was received or sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormatDirection {
+    Inbound,
+    Outbound,
+}
+
+/// The number of envelopes received and sent in each wire format. This shows how prevalent the legacy wire format still
+/// is on the network, which is used to decide when support for it can be removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WireFormatCounts {
+    pub inbound_legacy: usize,
+    pub inbound_v1: usize,
+    pub inbound_direct: usize,
+    pub outbound_legacy: usize,
+    pub outbound_v1: usize,
+    pub outbound_direct: usize,
+}
+
+impl WireFormatCounts {
+    /// Returns the proportion (0.0 to 1.0) of received envelopes that used the legacy wire format
+    pub fn inbound_legacy_ratio(&self) -> f32 {
+        let total = self.inbound_legacy + self.inbound_v1 + self.inbound_direct;
+        if total == 0 {
+            return 0.0;
+        }
+        self.inbound_legacy as f32 / total as f32
+    }
+
+    /// Returns the proportion (0.0 to 1.0) of sent envelopes that used the legacy wire format
+    pub fn outbound_legacy_ratio(&self) -> f32 {
+        let total = self.outbound_legacy + self.outbound_v1 + self.outbound_direct;
+        if total == 0 {
+            return 0.0;
+        }
+        self.outbound_legacy as f32 / total as f32
+    }
+
+    fn inc(&mut self, direction: WireFormatDirection, format: EnvelopeWireFormat) {
+        use EnvelopeWireFormat::*;
+        use WireFormatDirection::*;
+        let count = match (direction, format) {
+            (Inbound, Legacy) => &mut self.inbound_legacy,
+            (Inbound, V1) => &mut self.inbound_v1,
+            (Inbound, Direct) => &mut self.inbound_direct,
+            (Outbound, Legacy) => &mut self.outbound_legacy,
+            (Outbound, V1) => &mut self.outbound_v1,
+            (Outbound, Direct) => &mut self.outbound_direct,
+        };
+        *count = count.saturating_add(1);
+    }
+}
+
+/// The class of an outbound message. Send latency is tracked separately for each class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutboundPriorityClass {
+    /// DHT protocol messages (Join, Discovery and store and forward)
+    Dht,
+    /// Domain (application) messages
+    Domain,
+}
+
+impl OutboundPriorityClass {
+    pub fn from_message_type(message_type: DhtMessageType) -> Self {
+        if message_type.is_dht_message() || message_type.is_saf_message() {
+            OutboundPriorityClass::Dht
+        } else {
+            OutboundPriorityClass::Domain
+        }
+    }
+}
+
+/// Upper bounds (in milliseconds) of the latency histogram buckets. Latencies above the last bound are counted in an
+/// overflow bucket.
+const LATENCY_BUCKET_BOUNDS_MS: [u64; 8] = [1, 5, 10, 50, 100, 500, 1_000, 5_000];
+
+/// A fixed-bucket histogram of latencies
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [usize; LATENCY_BUCKET_BOUNDS_MS.len() + 1],
+    count: usize,
+    total: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let latency_ms = latency.as_millis();
+        let idx = LATENCY_BUCKET_BOUNDS_MS
+            .iter()
+            .position(|bound| latency_ms <= u128::from(*bound))
+            .unwrap_or(LATENCY_BUCKET_BOUNDS_MS.len());
+        self.buckets[idx] += 1;
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    /// The number of latencies recorded
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.total / self.count as u32)
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Returns (upper bound, count) for each bucket. The last bucket has no upper bound.
+    pub fn buckets(&self) -> Vec<(Option<Duration>, usize)> {
+        LATENCY_BUCKET_BOUNDS_MS
+            .iter()
+            .map(|ms| Some(Duration::from_millis(*ms)))
+            .chain(Some(None))
+            .zip(self.buckets.iter().copied())
+            .collect()
+    }
+}
+
+/// Outbound messaging metrics: the time from a message being queued to it being written to the peer's substream, the
+/// number of send failures for each reason, and the last reported outbound queue depth.
+#[derive(Debug, Clone, Default)]
+pub struct OutboundSendMetrics {
+    pub queue_depth: usize,
+    latencies: HashMap<OutboundPriorityClass, LatencyHistogram>,
+    failures: HashMap<SendFailReason, usize>,
+}
+
+impl OutboundSendMetrics {
+    /// Returns the send latency histogram for the given class, if any messages of that class have been sent
+    pub fn latency(&self, class: OutboundPriorityClass) -> Option<&LatencyHistogram> {
+        self.latencies.get(&class)
+    }
+
+    pub fn num_failures(&self, reason: SendFailReason) -> usize {
+        self.failures.get(&reason).copied().unwrap_or(0)
+    }
+
+    pub fn total_failures(&self) -> usize {
+        self.failures.values().sum()
+    }
+
+    fn record_latency(&mut self, class: OutboundPriorityClass, latency: Duration) {
+        self.latencies.entry(class).or_default().record(latency);
+    }
+
+    fn record_failure(&mut self, reason: SendFailReason) {
+        *self.failures.entry(reason).or_default() += 1;
+    }
+}
+
+/// The number of inbound messages, joins and pongs received within a window. A drop in these counts indicates that the
+/// node may have been partitioned from the network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkHeartbeat {
+    pub window: Duration,
+    pub num_messages: usize,
+    pub num_joins: usize,
+    pub num_pongs: usize,
+}
+
+/// Counts of messages forwarded on behalf of other peers. The ratio of copies to forwarded messages is the effective
+/// amplification factor of this node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ForwardMetrics {
+    /// The number of inbound messages that were forwarded
+    pub num_forwarded: usize,
+    /// The total number of outbound copies sent for forwarded messages
+    pub num_copies: usize,
+    /// The total number of bytes sent for forwarded messages
+    pub num_bytes: usize,
+    /// The number of inbound messages that were not forwarded because the forwarding bandwidth budget was exhausted
+    pub num_dropped: usize,
+}
+
+impl ForwardMetrics {
+    /// Returns the mean number of outbound copies sent per forwarded message
+    pub fn amplification_factor(&self) -> f32 {
+        if self.num_forwarded == 0 {
+            return 0.0;
+        }
+        self.num_copies as f32 / self.num_forwarded as f32
+    }
 }
 
 #[derive(Debug)]
 struct MetricsState {
     messages_recv: HashMap<NodeId, TimeSeries<()>>,
     all_messages_recv: TimeSeries<()>,
+    memory_usage: MemoryUsageReport,
+    wire_format_counts: WireFormatCounts,
+    outbound_send: OutboundSendMetrics,
+    num_envelope_checksum_failures: usize,
+    joins_recv: TimeSeries<()>,
+    pongs_recv: TimeSeries<()>,
+    forward: ForwardMetrics,
 }
 
 impl Default for MetricsState {
@@ -67,6 +321,13 @@ impl Default for MetricsState {
         Self {
             all_messages_recv: TimeSeries::new(100000),
             messages_recv: HashMap::<NodeId, TimeSeries<()>>::new(),
+            memory_usage: Default::default(),
+            wire_format_counts: Default::default(),
+            outbound_send: Default::default(),
+            num_envelope_checksum_failures: 0,
+            joins_recv: TimeSeries::new(10_000),
+            pongs_recv: TimeSeries::new(10_000),
+            forward: Default::default(),
         }
     }
 }
@@ -121,6 +382,16 @@ impl MetricsState {
         let since = Instant::now() - timespan;
         self.all_messages_recv.count_since(since)
     }
+
+    pub fn get_network_heartbeat(&self, window: Duration) -> NetworkHeartbeat {
+        let since = Instant::now() - window;
+        NetworkHeartbeat {
+            window,
+            num_messages: self.all_messages_recv.count_since(since),
+            num_joins: self.joins_recv.count_since(since),
+            num_pongs: self.pongs_recv.count_since(since),
+        }
+    }
 }
 
 pub struct MetricsCollector {
@@ -164,6 +435,39 @@ impl MetricsCollector {
             ClearMetrics(node_id) => {
                 self.state.drop_metrics(&node_id);
             },
+            MemoryUsage(source, num_bytes) => {
+                self.state.memory_usage.set(source, num_bytes);
+            },
+            WireFormat(direction, format) => {
+                self.state.wire_format_counts.inc(direction, format);
+            },
+            OutboundSendLatency(class, latency) => {
+                self.state.outbound_send.record_latency(class, latency);
+            },
+            OutboundSendFailed(reason) => {
+                self.state.outbound_send.record_failure(reason);
+            },
+            OutboundQueueDepth(depth) => {
+                self.state.outbound_send.queue_depth = depth;
+            },
+            EnvelopeChecksumFailed => {
+                self.state.num_envelope_checksum_failures += 1;
+            },
+            JoinReceived => {
+                self.state.joins_recv.inc();
+            },
+            PongReceived => {
+                self.state.pongs_recv.inc();
+            },
+            MessageForwarded { num_copies, num_bytes } => {
+                let forward = &mut self.state.forward;
+                forward.num_forwarded += 1;
+                forward.num_copies += num_copies;
+                forward.num_bytes += num_bytes;
+            },
+            ForwardDropped => {
+                self.state.forward.num_dropped += 1;
+            },
         }
     }
 
@@ -179,6 +483,24 @@ impl MetricsCollector {
             MessagesReceivedTotalCountInTimespan(timespan, reply) => {
                 let _ = reply.send(self.state.message_received_get_total_count_in_timespan(timespan));
             },
+            MemoryUsage(reply) => {
+                let _ = reply.send(self.state.memory_usage.clone());
+            },
+            WireFormat(reply) => {
+                let _ = reply.send(self.state.wire_format_counts.clone());
+            },
+            OutboundSend(reply) => {
+                let _ = reply.send(self.state.outbound_send.clone());
+            },
+            EnvelopeChecksumFailures(reply) => {
+                let _ = reply.send(self.state.num_envelope_checksum_failures);
+            },
+            NetworkHeartbeat(window, reply) => {
+                let _ = reply.send(self.state.get_network_heartbeat(window));
+            },
+            Forward(reply) => {
+                let _ = reply.send(self.state.forward);
+            },
         }
     }
 }
@@ -274,6 +596,65 @@ impl MetricsCollectorHandle {
         self.write(MetricWrite::MessageReceived(node_id))
     }
 
+    /// Write the approximate memory usage in bytes for the given source. Returning true if the metric was queued for
+    /// collection, otherwise false.
+    pub fn write_metric_memory_usage(&mut self, source: MemoryUsageSource, num_bytes: usize) -> bool {
+        self.write(MetricWrite::MemoryUsage(source, num_bytes))
+    }
+
+    /// Count an envelope that was received or sent in the given wire format. Returning true if the metric was queued
+    /// for collection, otherwise false.
+    pub fn write_metric_wire_format(&mut self, direction: WireFormatDirection, format: EnvelopeWireFormat) -> bool {
+        self.write(MetricWrite::WireFormat(direction, format))
+    }
+
+    /// Record the time taken from queuing an outbound message to writing it to the peer's substream. Returning true if
+    /// the metric was queued for collection, otherwise false.
+    pub fn write_metric_outbound_send_latency(&mut self, class: OutboundPriorityClass, latency: Duration) -> bool {
+        self.write(MetricWrite::OutboundSendLatency(class, latency))
+    }
+
+    /// Count an outbound message that failed to send. Returning true if the metric was queued for collection, otherwise
+    /// false.
+    pub fn write_metric_outbound_send_failed(&mut self, reason: SendFailReason) -> bool {
+        self.write(MetricWrite::OutboundSendFailed(reason))
+    }
+
+    /// Write the number of messages waiting in the outbound queues. Returning true if the metric was queued for
+    /// collection, otherwise false.
+    pub fn write_metric_outbound_queue_depth(&mut self, depth: usize) -> bool {
+        self.write(MetricWrite::OutboundQueueDepth(depth))
+    }
+
+    /// Count a received envelope that was rejected because its body did not match the body checksum. These are
+    /// counted separately from other failures because they indicate corruption rather than an invalid signature.
+    /// Returning true if the metric was queued for collection, otherwise false.
+    pub fn write_metric_envelope_checksum_failed(&mut self) -> bool {
+        self.write(MetricWrite::EnvelopeChecksumFailed)
+    }
+
+    /// Count a received Join message. Returning true if the metric was queued for collection, otherwise false.
+    pub fn write_metric_join_received(&mut self) -> bool {
+        self.write(MetricWrite::JoinReceived)
+    }
+
+    /// Count a received liveness Pong. Returning true if the metric was queued for collection, otherwise false.
+    pub fn write_metric_pong_received(&mut self) -> bool {
+        self.write(MetricWrite::PongReceived)
+    }
+
+    /// Count an inbound message that was forwarded as `num_copies` outbound messages totalling `num_bytes`. Returning
+    /// true if the metric was queued for collection, otherwise false.
+    pub fn write_metric_message_forwarded(&mut self, num_copies: usize, num_bytes: usize) -> bool {
+        self.write(MetricWrite::MessageForwarded { num_copies, num_bytes })
+    }
+
+    /// Count an inbound message that was not forwarded because the forwarding bandwidth budget was exhausted.
+    /// Returning true if the metric was queued for collection, otherwise false.
+    pub fn write_metric_forward_dropped(&mut self) -> bool {
+        self.write(MetricWrite::ForwardDropped)
+    }
+
     /// Clear the metrics for a `NodeId`. Err is returned if the metric collector has been shut down.
     pub async fn clear_metrics(&mut self, node_id: NodeId) -> Result<(), MetricsError> {
         self.inner
@@ -329,6 +710,58 @@ impl MetricsCollectorHandle {
             .await?;
         reply_rx.await.map_err(Into::into)
     }
+
+    /// Get the last reported approximate memory usage for caches, stores and queues
+    pub async fn get_memory_usage(&mut self) -> Result<MemoryUsageReport, MetricsError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.inner
+            .send(MetricOp::Read(MetricRead::MemoryUsage(reply_tx)))
+            .await?;
+        reply_rx.await.map_err(Into::into)
+    }
+
+    /// Get the number of envelopes received and sent in each wire format
+    pub async fn get_wire_format_counts(&mut self) -> Result<WireFormatCounts, MetricsError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.inner
+            .send(MetricOp::Read(MetricRead::WireFormat(reply_tx)))
+            .await?;
+        reply_rx.await.map_err(Into::into)
+    }
+
+    /// Get the outbound send latency histograms, failure counts and queue depth
+    pub async fn get_outbound_send_metrics(&mut self) -> Result<OutboundSendMetrics, MetricsError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.inner
+            .send(MetricOp::Read(MetricRead::OutboundSend(reply_tx)))
+            .await?;
+        reply_rx.await.map_err(Into::into)
+    }
+
+    /// Get the number of received envelopes that were rejected because of a body checksum mismatch
+    pub async fn get_envelope_checksum_failures(&mut self) -> Result<usize, MetricsError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.inner
+            .send(MetricOp::Read(MetricRead::EnvelopeChecksumFailures(reply_tx)))
+            .await?;
+        reply_rx.await.map_err(Into::into)
+    }
+
+    /// Get the number of inbound messages, joins and pongs received within the given window
+    pub async fn get_network_heartbeat(&mut self, window: Duration) -> Result<NetworkHeartbeat, MetricsError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.inner
+            .send(MetricOp::Read(MetricRead::NetworkHeartbeat(window, reply_tx)))
+            .await?;
+        reply_rx.await.map_err(Into::into)
+    }
+
+    /// Get the counts of messages forwarded on behalf of other peers
+    pub async fn get_forward_metrics(&mut self) -> Result<ForwardMetrics, MetricsError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.inner.send(MetricOp::Read(MetricRead::Forward(reply_tx))).await?;
+        reply_rx.await.map_err(Into::into)
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -23,13 +23,45 @@
 #[cfg(test)]
 mod test;
 
+mod memory_usage;
+pub(crate) use memory_usage::MemoryUsageReporter;
+
 mod metrics;
-pub use metrics::{MetricsCollector, MetricsCollectorHandle};
+pub use metrics::{
+    ForwardMetrics,
+    LatencyHistogram,
+    MemoryUsageReport,
+    MemoryUsageSource,
+    MetricsCollector,
+    MetricsCollectorHandle,
+    NetworkHeartbeat,
+    OutboundPriorityClass,
+    OutboundSendMetrics,
+    WireFormatCounts,
+    WireFormatDirection,
+};
 
-use crate::{connectivity::metrics::MetricsError, event::DhtEvent, DhtActorError, DhtConfig, DhtRequester};
-use futures::{stream::Fuse, StreamExt};
+mod snapshots;
+pub(crate) use snapshots::MetricsSnapshotWriter;
+pub use snapshots::{MetricsSnapshot, MetricsSnapshotError, MetricsSnapshotStore};
+
+use crate::{
+    connectivity::metrics::MetricsError,
+    event::{DhtEvent, DhtEventSender},
+    storage::DhtMetadataKey,
+    store_forward::{StoreAndForwardError, StoreAndForwardRequester},
+    DhtActorError,
+    DhtConfig,
+    DhtRequester,
+};
+use chrono::{DateTime, Utc};
+use futures::{future, stream::Fuse, StreamExt};
 use log::*;
-use std::{sync::Arc, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tari_comms::{
     connectivity::{ConnectivityError, ConnectivityEvent, ConnectivityEventRx, ConnectivityRequester},
     peer_manager::{node_id::NodeDistance, NodeId, PeerManagerError, PeerQuery, PeerQuerySortBy},
@@ -53,6 +85,12 @@ pub enum DhtConnectivityError {
     SendJoinFailed(#[from] DhtActorError),
     #[error("Metrics error: {0}")]
     MetricError(#[from] MetricsError),
+    #[error("Failed to request stored messages: {0}")]
+    SafRequestFailed(#[from] StoreAndForwardError),
+    #[error("Failed to persist recent peers: {0}")]
+    PersistRecentPeersFailed(DhtActorError),
+    #[error("Failed to send peer address update: {0}")]
+    SendPeerAddressUpdateFailed(DhtActorError),
 }
 
 /// # DHT Connectivity Actor
@@ -70,6 +108,7 @@ pub struct DhtConnectivity {
     node_identity: Arc<NodeIdentity>,
     connectivity: ConnectivityRequester,
     dht_requester: DhtRequester,
+    saf_requester: StoreAndForwardRequester,
 
     /// List of neighbours managed by DhtConnectivity ordered by distance from this node
     neighbours: Vec<NodeId>,
@@ -77,8 +116,12 @@ pub struct DhtConnectivity {
     random_pool: Vec<NodeId>,
     /// Used to track when the random peer pool was last refreshed
     random_pool_last_refresh: Option<Instant>,
+    /// Currently connected (non-client) peers. These are periodically persisted so that they can be reconnected to
+    /// after a restart.
+    connected_peers: HashSet<NodeId>,
     stats: Stats,
     dht_events: Fuse<broadcast::Receiver<Arc<DhtEvent>>>,
+    event_publisher: DhtEventSender,
 
     metrics_collector: MetricsCollectorHandle,
 
@@ -93,7 +136,8 @@ impl DhtConnectivity {
         node_identity: Arc<NodeIdentity>,
         connectivity: ConnectivityRequester,
         dht_requester: DhtRequester,
-        dht_events: broadcast::Receiver<Arc<DhtEvent>>,
+        saf_requester: StoreAndForwardRequester,
+        event_publisher: DhtEventSender,
         metrics_collector: MetricsCollectorHandle,
         shutdown_signal: ShutdownSignal,
     ) -> Self
@@ -106,10 +150,13 @@ impl DhtConnectivity {
             node_identity,
             connectivity,
             dht_requester,
+            saf_requester,
             metrics_collector,
             random_pool_last_refresh: None,
+            connected_peers: HashSet::new(),
             stats: Stats::new(),
-            dht_events: dht_events.fuse(),
+            dht_events: event_publisher.subscribe().fuse(),
+            event_publisher,
             shutdown_signal: Some(shutdown_signal),
         }
     }
@@ -140,6 +187,8 @@ impl DhtConnectivity {
             .expect("DhtConnectivity initialized without a shutdown_signal");
 
         debug!(target: LOG_TARGET, "DHT connectivity starting");
+        self.restore_join_last_sent_at().await;
+        self.reconnect_to_recent_peers().await;
         self.refresh_neighbour_pool().await?;
 
         let mut ticker = time::interval(self.config.connectivity_update_interval).fuse();
@@ -169,6 +218,12 @@ impl DhtConnectivity {
                     if let Err(err) = self.check_and_ban_flooding_peers().await {
                         debug!(target: LOG_TARGET, "Error checking for peer flooding: {:?}", err);
                     }
+                    if let Err(err) = self.check_network_heartbeat().await {
+                        debug!(target: LOG_TARGET, "Error checking network heartbeat: {:?}", err);
+                    }
+                    if let Err(err) = self.persist_recent_peers().await {
+                        debug!(target: LOG_TARGET, "Error persisting recent peers: {:?}", err);
+                    }
                },
 
                _ = shutdown_signal => {
@@ -207,6 +262,22 @@ impl DhtConnectivity {
             .await?;
 
         for (peer, mps) in nodes {
+            // Trusted peers (e.g. the operator's own infrastructure nodes) are exempt from flood bans
+            match self.peer_manager.find_by_node_id(&peer).await {
+                Ok(p) if p.trust_level().is_trusted() => {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Not banning {} peer `{}` for flooding. Message rate: {:.2}m/s",
+                        p.trust_level(),
+                        peer,
+                        mps
+                    );
+                    continue;
+                },
+                Ok(_) => {},
+                Err(err) if err.is_peer_not_found() => {},
+                Err(err) => return Err(err.into()),
+            }
             warn!(
                 target: LOG_TARGET,
                 "Banning peer `{}` because of flooding. Message rate: {:.2}m/s", peer, mps
@@ -222,6 +293,61 @@ impl DhtConnectivity {
         Ok(())
     }
 
+    /// Checks the number of inbound messages, joins and pongs received over the heartbeat window. If any falls below
+    /// its threshold, a `NetworkPartitionSuspected` event is emitted (at most once per window) and, if configured, the
+    /// node attempts to reconnect to the network.
+    async fn check_network_heartbeat(&mut self) -> Result<(), DhtConnectivityError> {
+        let window = match self.config.network_heartbeat_window {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+        // The heartbeat is only meaningful once a full window has elapsed while online
+        if self.stats.is_offline() || self.stats.started_at().elapsed() < window {
+            return Ok(());
+        }
+
+        let heartbeat = self.metrics_collector.get_network_heartbeat(window).await?;
+        let is_below_threshold = heartbeat.num_messages < self.config.network_heartbeat_min_messages ||
+            heartbeat.num_joins < self.config.network_heartbeat_min_joins ||
+            heartbeat.num_pongs < self.config.network_heartbeat_min_pongs;
+
+        if !is_below_threshold {
+            if self.stats.clear_partition_suspected() {
+                info!(target: LOG_TARGET, "Network heartbeat has recovered ({:?})", heartbeat);
+            }
+            return Ok(());
+        }
+
+        let recently_reported = self
+            .stats
+            .partition_suspected_at()
+            .map(|at| at.elapsed() < window)
+            .unwrap_or(false);
+        if recently_reported {
+            return Ok(());
+        }
+
+        warn!(
+            target: LOG_TARGET,
+            "Network partition suspected. Received {} message(s), {} join(s) and {} pong(s) in the last {:.0?}",
+            heartbeat.num_messages,
+            heartbeat.num_joins,
+            heartbeat.num_pongs,
+            window
+        );
+        self.stats.mark_partition_suspected();
+        // An error only means that there are no subscribers
+        let _ = self
+            .event_publisher
+            .send(Arc::new(DhtEvent::NetworkPartitionSuspected(heartbeat)));
+
+        if self.config.network_heartbeat_reconnect {
+            self.reconnect_to_network().await?;
+        }
+
+        Ok(())
+    }
+
     async fn refresh_peer_pools(&mut self) -> Result<(), DhtConnectivityError> {
         info!(
             target: LOG_TARGET,
@@ -382,12 +508,19 @@ impl DhtConnectivity {
         use ConnectivityEvent::*;
         match event {
             PeerConnected(conn) => {
+                if !conn.peer_features().is_client() {
+                    self.connected_peers.insert(conn.peer_node_id().clone());
+                }
                 self.handle_new_peer_connected(conn).await?;
             },
+            PeerDisconnected(node_id) => {
+                self.connected_peers.remove(node_id);
+            },
             ManagedPeerDisconnected(node_id) |
             ManagedPeerConnectFailed(node_id) |
             PeerOffline(node_id) |
             PeerBanned(node_id) => {
+                self.connected_peers.remove(node_id);
                 if self.metrics_collector.clear_metrics(node_id.clone()).await.is_err() {
                     warn!(
                         target: LOG_TARGET,
@@ -397,6 +530,13 @@ impl DhtConnectivity {
                 self.replace_managed_peer(node_id).await?;
             },
             ConnectivityStateOnline(n) => {
+                if let Some(offline_duration) = self.stats.mark_online() {
+                    if offline_duration >= self.config.rejoin_offline_threshold {
+                        self.rejoin_network(offline_duration).await?;
+                        return Ok(());
+                    }
+                }
+
                 if self.config.auto_join && self.should_send_join() {
                     debug!(
                         target: LOG_TARGET,
@@ -411,14 +551,55 @@ impl DhtConnectivity {
                 }
             },
             ConnectivityStateOffline => {
+                self.stats.mark_offline();
                 self.refresh_peer_pools().await?;
             },
+            PeerAddressesChanged(identity) => {
+                if self.config.propagate_peer_address_updates {
+                    self.dht_requester
+                        .send_peer_address_update((**identity).clone())
+                        .await
+                        .map_err(DhtConnectivityError::SendPeerAddressUpdateFailed)?;
+                }
+            },
             _ => {},
         }
 
         Ok(())
     }
 
+    /// Called when the node comes back online after being offline for longer than
+    /// `DhtConfig::rejoin_offline_threshold`. Our advertised presence and neighbour view are likely stale, so the
+    /// neighbour and random pools are refreshed, a Join is re-broadcast (ignoring the join cooldown) and stored
+    /// messages are requested from our neighbours.
+    async fn rejoin_network(&mut self, offline_duration: Duration) -> Result<(), DhtConnectivityError> {
+        info!(
+            target: LOG_TARGET,
+            "Node is back online after being offline for {:.0?}. Re-joining the network.", offline_duration
+        );
+        self.reconnect_to_network().await
+    }
+
+    /// Refreshes the neighbour and random pools, re-broadcasts a Join (ignoring the join cooldown) and requests stored
+    /// messages from our neighbours.
+    async fn reconnect_to_network(&mut self) -> Result<(), DhtConnectivityError> {
+        self.refresh_peer_pools().await?;
+
+        if self.config.auto_join {
+            self.dht_requester
+                .send_join()
+                .await
+                .map_err(DhtConnectivityError::SendJoinFailed)?;
+            self.stats.mark_join_sent();
+        }
+
+        if self.config.saf_auto_request {
+            self.saf_requester.request_saf_messages_from_neighbours().await?;
+        }
+
+        Ok(())
+    }
+
     async fn replace_managed_peer(&mut self, current_peer: &NodeId) -> Result<(), DhtConnectivityError> {
         if !self.is_managed(current_peer) {
             debug!(target: LOG_TARGET, "{} is not managed. Ignoring", current_peer);
@@ -604,10 +785,130 @@ impl DhtConnectivity {
     }
 
     async fn fetch_random_peers(&self, n: usize, excluded: &[NodeId]) -> Result<Vec<NodeId>, DhtConnectivityError> {
-        let peers = self.peer_manager.random_peers(n, excluded).await?;
+        let peers = self
+            .peer_manager
+            .random_peers_with_rng(n, excluded, &mut self.config.rng.clone())
+            .await?;
         Ok(peers.into_iter().map(|p| p.node_id).collect())
     }
 
+    /// Restores the time that the last Join was sent, so that restarting the node does not cause a Join to be sent
+    /// before the join cooldown has elapsed
+    async fn restore_join_last_sent_at(&mut self) {
+        match self
+            .dht_requester
+            .get_metadata::<DateTime<Utc>>(DhtMetadataKey::LastJoinTimestamp)
+            .await
+        {
+            Ok(Some(sent_at)) => {
+                // A timestamp in the future (e.g. clock change) is treated as just sent
+                let elapsed = Utc::now().signed_duration_since(sent_at).to_std().unwrap_or_default();
+                debug!(target: LOG_TARGET, "Last Join was sent {:.0?} ago", elapsed);
+                self.stats.set_join_last_sent_at(Instant::now().checked_sub(elapsed));
+            },
+            Ok(None) => {},
+            Err(err) => debug!(target: LOG_TARGET, "Failed to load last join time: {:?}", err),
+        }
+    }
+
+    /// Dials the peers that were connected before the last shutdown (most recently connected first), so that the node
+    /// is useful soon after a restart. Waits up to `DhtConfig::warm_start_timeout` for the dials to complete before
+    /// returning.
+    async fn reconnect_to_recent_peers(&mut self) {
+        if self.config.warm_start_max_peers == 0 {
+            return;
+        }
+
+        let recent_peers = match self
+            .dht_requester
+            .get_metadata::<HashMap<NodeId, DateTime<Utc>>>(DhtMetadataKey::RecentPeers)
+            .await
+        {
+            Ok(Some(recent_peers)) => recent_peers,
+            Ok(None) => return,
+            Err(err) => {
+                debug!(target: LOG_TARGET, "Failed to load recent peers: {:?}", err);
+                return;
+            },
+        };
+
+        let now = Utc::now();
+        let max_age = self.config.warm_start_max_age;
+        let mut recent_peers = recent_peers
+            .into_iter()
+            // A timestamp in the future (e.g. clock change) is treated as recent
+            .filter(|(_, last_seen)| {
+                now.signed_duration_since(*last_seen)
+                    .to_std()
+                    .map(|age| age <= max_age)
+                    .unwrap_or(true)
+            })
+            .collect::<Vec<_>>();
+        recent_peers.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        let mut node_ids = Vec::with_capacity(self.config.warm_start_max_peers);
+        for (node_id, _) in recent_peers {
+            if node_ids.len() >= self.config.warm_start_max_peers {
+                break;
+            }
+            match self.peer_manager.find_by_node_id(&node_id).await {
+                Ok(peer) if !peer.is_banned() => node_ids.push(node_id),
+                _ => {},
+            }
+        }
+
+        if node_ids.is_empty() {
+            return;
+        }
+
+        info!(
+            target: LOG_TARGET,
+            "Reconnecting to {} peer(s) that were connected before the last shutdown",
+            node_ids.len()
+        );
+        let dials = node_ids.into_iter().map(|node_id| {
+            let mut connectivity = self.connectivity.clone();
+            async move { connectivity.dial_peer(node_id).await }
+        });
+        match time::timeout(self.config.warm_start_timeout, future::join_all(dials)).await {
+            Ok(results) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Reconnected to {} of {} recent peer(s)",
+                    results.iter().filter(|r| r.is_ok()).count(),
+                    results.len()
+                );
+            },
+            Err(_) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Recent peers did not all reconnect within {:.0?}", self.config.warm_start_timeout
+                );
+            },
+        }
+    }
+
+    /// Persists the currently connected peers so that they can be reconnected to after a restart. Nothing is persisted
+    /// while no peers are connected, so that the last known connections are kept.
+    async fn persist_recent_peers(&mut self) -> Result<(), DhtConnectivityError> {
+        if self.config.warm_start_max_peers == 0 || self.connected_peers.is_empty() {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let recent_peers = self
+            .connected_peers
+            .iter()
+            .cloned()
+            .map(|node_id| (node_id, now))
+            .collect::<HashMap<_, _>>();
+        self.dht_requester
+            .set_metadata(DhtMetadataKey::RecentPeers, recent_peers)
+            .await
+            .map_err(DhtConnectivityError::PersistRecentPeersFailed)?;
+        Ok(())
+    }
+
     fn should_send_join(&self) -> bool {
         let cooldown = self.config.join_cooldown_interval;
         self.stats
@@ -617,16 +918,29 @@ impl DhtConnectivity {
     }
 }
 
-/// Basic connectivity stats. Right now, it is only used to track the last time a join message was sent to prevent the
-/// node spamming the network if local connectivity changes.
-#[derive(Debug, Default)]
+/// Basic connectivity stats. Used to track the last time a join message was sent to prevent the node spamming the
+/// network if local connectivity changes, when the node went offline so that a re-join can be triggered after an
+/// extended disconnection, and when a network partition was last suspected.
+#[derive(Debug)]
 struct Stats {
+    started_at: Instant,
     join_last_sent_at: Option<Instant>,
+    offline_since: Option<Instant>,
+    partition_suspected_at: Option<Instant>,
 }
 
 impl Stats {
     pub fn new() -> Self {
-        Default::default()
+        Self {
+            started_at: Instant::now(),
+            join_last_sent_at: None,
+            offline_since: None,
+            partition_suspected_at: None,
+        }
+    }
+
+    pub fn started_at(&self) -> Instant {
+        self.started_at
     }
 
     pub fn join_last_sent_at(&self) -> Option<Instant> {
@@ -636,4 +950,37 @@ impl Stats {
     pub fn mark_join_sent(&mut self) {
         self.join_last_sent_at = Some(Instant::now());
     }
+
+    pub fn set_join_last_sent_at(&mut self, sent_at: Option<Instant>) {
+        self.join_last_sent_at = sent_at;
+    }
+
+    /// Record that the node is offline. If the node is already offline, the original offline time is kept.
+    pub fn mark_offline(&mut self) {
+        if self.offline_since.is_none() {
+            self.offline_since = Some(Instant::now());
+        }
+    }
+
+    /// Record that the node is online, returning how long the node was offline for, if it was offline.
+    pub fn mark_online(&mut self) -> Option<Duration> {
+        self.offline_since.take().map(|since| since.elapsed())
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline_since.is_some()
+    }
+
+    pub fn partition_suspected_at(&self) -> Option<Instant> {
+        self.partition_suspected_at
+    }
+
+    pub fn mark_partition_suspected(&mut self) {
+        self.partition_suspected_at = Some(Instant::now());
+    }
+
+    /// Clears a suspected partition, returning true if a partition was previously suspected
+    pub fn clear_partition_suspected(&mut self) -> bool {
+        self.partition_suspected_at.take().is_some()
+    }
 }
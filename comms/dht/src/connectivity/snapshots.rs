@@ -0,0 +1,446 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::MetricsCollectorHandle;
+use crate::store_forward::StoreAndForwardRequester;
+use chrono::{DateTime, TimeZone, Utc};
+use futures::StreamExt;
+use log::*;
+use std::{
+    cmp,
+    convert::TryInto,
+    fmt,
+    fs,
+    fs::{File, OpenOptions},
+    io,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tari_comms::{connectivity::ConnectivityRequester, PeerManager};
+use tari_shutdown::ShutdownSignal;
+use thiserror::Error;
+use tokio::{task, time};
+
+const LOG_TARGET: &str = "comms::dht::metrics::snapshots";
+
+/// Identifies a metrics snapshot file
+const SNAPSHOT_FILE_MAGIC: &[u8; 4] = b"TMS1";
+/// Magic, capacity, next slot and number of snapshots
+const HEADER_SIZE: usize = 16;
+const NUM_FIELDS: usize = 10;
+const RECORD_SIZE: usize = NUM_FIELDS * 8;
+
+#[derive(Debug, Error)]
+pub enum MetricsSnapshotError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Metrics snapshot capacity must be greater than zero")]
+    ZeroCapacity,
+    #[error("Metrics snapshot task failed: {0}")]
+    TaskFailed(#[from] task::JoinError),
+}
+
+/// A snapshot of key node metrics at a point in time. Counters are totals since the node was started unless otherwise
+/// stated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub timestamp: DateTime<Utc>,
+    pub num_active_connections: usize,
+    pub num_known_peers: usize,
+    /// Approximate size of the messages held in the SAF store
+    pub saf_storage_bytes: usize,
+    /// Total bytes read on the connections that were active when the snapshot was taken
+    pub bytes_read: u64,
+    /// Total bytes written on the connections that were active when the snapshot was taken
+    pub bytes_written: u64,
+    /// The number of inbound messages received within the snapshot interval
+    pub num_messages_received: usize,
+    pub num_send_failures: usize,
+    pub num_checksum_failures: usize,
+    pub num_forwards_dropped: usize,
+}
+
+impl MetricsSnapshot {
+    fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let fields: [u64; NUM_FIELDS] = [
+            self.timestamp.timestamp() as u64,
+            self.num_active_connections as u64,
+            self.num_known_peers as u64,
+            self.saf_storage_bytes as u64,
+            self.bytes_read,
+            self.bytes_written,
+            self.num_messages_received as u64,
+            self.num_send_failures as u64,
+            self.num_checksum_failures as u64,
+            self.num_forwards_dropped as u64,
+        ];
+        let mut buf = [0u8; RECORD_SIZE];
+        for (chunk, field) in buf.chunks_exact_mut(8).zip(fields.iter()) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        buf
+    }
+
+    /// Returns None if the record does not contain a valid timestamp
+    fn from_bytes(buf: &[u8; RECORD_SIZE]) -> Option<Self> {
+        let mut fields = [0u64; NUM_FIELDS];
+        for (field, chunk) in fields.iter_mut().zip(buf.chunks_exact(8)) {
+            *field = u64::from_le_bytes(chunk.try_into().expect("chunk is 8 bytes"));
+        }
+        let timestamp = Utc.timestamp_opt(fields[0] as i64, 0).single()?;
+        Some(Self {
+            timestamp,
+            num_active_connections: fields[1] as usize,
+            num_known_peers: fields[2] as usize,
+            saf_storage_bytes: fields[3] as usize,
+            bytes_read: fields[4],
+            bytes_written: fields[5],
+            num_messages_received: fields[6] as usize,
+            num_send_failures: fields[7] as usize,
+            num_checksum_failures: fields[8] as usize,
+            num_forwards_dropped: fields[9] as usize,
+        })
+    }
+}
+
+impl fmt::Display for MetricsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} conns: {}, peers: {}, SAF: {} bytes, read: {} bytes, written: {} bytes, msgs: {}, send failures: {}, \
+             checksum failures: {}, forwards dropped: {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.num_active_connections,
+            self.num_known_peers,
+            self.saf_storage_bytes,
+            self.bytes_read,
+            self.bytes_written,
+            self.num_messages_received,
+            self.num_send_failures,
+            self.num_checksum_failures,
+            self.num_forwards_dropped
+        )
+    }
+}
+
+/// A fixed size ring of metrics snapshots kept in a file. Once the capacity is reached, each new snapshot overwrites
+/// the oldest one, so the file never grows beyond `capacity` snapshots.
+#[derive(Clone)]
+pub struct MetricsSnapshotStore {
+    inner: Arc<Mutex<SnapshotFile>>,
+}
+
+impl MetricsSnapshotStore {
+    /// Opens the snapshot file at the given path, creating it if it does not exist. Existing snapshots are discarded
+    /// if the file was created with a different capacity or is not a snapshot file.
+    pub fn open<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self, MetricsSnapshotError> {
+        if capacity == 0 {
+            return Err(MetricsSnapshotError::ZeroCapacity);
+        }
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().read(true).write(true).create(true).open(path)?;
+        let snapshot_file = SnapshotFile::load(file, capacity)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(snapshot_file)),
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        acquire_lock!(self.inner).capacity
+    }
+
+    pub fn len(&self) -> usize {
+        acquire_lock!(self.inner).len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes a snapshot to the file, overwriting the oldest snapshot if the store is full
+    pub fn append(&self, snapshot: &MetricsSnapshot) -> Result<(), MetricsSnapshotError> {
+        acquire_lock!(self.inner).append(snapshot).map_err(Into::into)
+    }
+
+    /// Returns at most `limit` of the most recent snapshots, most recent first
+    pub fn snapshots(&self, limit: Option<usize>) -> Result<Vec<MetricsSnapshot>, MetricsSnapshotError> {
+        acquire_lock!(self.inner).read(limit).map_err(Into::into)
+    }
+}
+
+struct SnapshotFile {
+    file: File,
+    capacity: usize,
+    /// The slot to which the next snapshot is written
+    next: usize,
+    len: usize,
+}
+
+impl SnapshotFile {
+    fn load(mut file: File, capacity: usize) -> io::Result<Self> {
+        let mut header = [0u8; HEADER_SIZE];
+        let is_empty = file.metadata()?.len() == 0;
+        let header = match file.read_exact(&mut header) {
+            Ok(_) if &header[..4] == SNAPSHOT_FILE_MAGIC && read_u32(&header[4..8]) as usize == capacity => {
+                Some(header)
+            },
+            Ok(_) => None,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(err) => return Err(err),
+        };
+
+        match header {
+            Some(header) => Ok(Self {
+                file,
+                capacity,
+                next: read_u32(&header[8..12]) as usize % capacity,
+                len: cmp::min(read_u32(&header[12..16]) as usize, capacity),
+            }),
+            None => {
+                if !is_empty {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Metrics snapshot file has an unrecognised format or capacity. Existing snapshots are \
+                         discarded."
+                    );
+                }
+                let mut snapshot_file = Self {
+                    file,
+                    capacity,
+                    next: 0,
+                    len: 0,
+                };
+                snapshot_file.file.set_len(0)?;
+                snapshot_file.write_header()?;
+                Ok(snapshot_file)
+            },
+        }
+    }
+
+    fn append(&mut self, snapshot: &MetricsSnapshot) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(slot_offset(self.next)))?;
+        self.file.write_all(&snapshot.to_bytes())?;
+        self.next = (self.next + 1) % self.capacity;
+        self.len = cmp::min(self.len + 1, self.capacity);
+        self.write_header()?;
+        self.file.sync_data()
+    }
+
+    fn read(&mut self, limit: Option<usize>) -> io::Result<Vec<MetricsSnapshot>> {
+        let num_snapshots = cmp::min(self.len, limit.unwrap_or(self.len));
+        let mut snapshots = Vec::with_capacity(num_snapshots);
+        let mut buf = [0u8; RECORD_SIZE];
+        for i in 0..num_snapshots {
+            let slot = (self.next + self.capacity - 1 - i) % self.capacity;
+            self.file.seek(SeekFrom::Start(slot_offset(slot)))?;
+            self.file.read_exact(&mut buf)?;
+            match MetricsSnapshot::from_bytes(&buf) {
+                Some(snapshot) => snapshots.push(snapshot),
+                None => warn!(target: LOG_TARGET, "Skipping corrupt metrics snapshot in slot {}", slot),
+            }
+        }
+        Ok(snapshots)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut header = [0u8; HEADER_SIZE];
+        header[..4].copy_from_slice(SNAPSHOT_FILE_MAGIC);
+        header[4..8].copy_from_slice(&(self.capacity as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&(self.next as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&(self.len as u32).to_le_bytes());
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)
+    }
+}
+
+fn slot_offset(slot: usize) -> u64 {
+    (HEADER_SIZE + slot * RECORD_SIZE) as u64
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().expect("slice is 4 bytes"))
+}
+
+/// Periodically collects a `MetricsSnapshot` and writes it to the `MetricsSnapshotStore`.
+pub(crate) struct MetricsSnapshotWriter {
+    interval: Duration,
+    store: MetricsSnapshotStore,
+    connectivity: ConnectivityRequester,
+    peer_manager: Arc<PeerManager>,
+    saf_requester: StoreAndForwardRequester,
+    metrics_collector: MetricsCollectorHandle,
+    shutdown_signal: ShutdownSignal,
+}
+
+impl MetricsSnapshotWriter {
+    pub fn new(
+        interval: Duration,
+        store: MetricsSnapshotStore,
+        connectivity: ConnectivityRequester,
+        peer_manager: Arc<PeerManager>,
+        saf_requester: StoreAndForwardRequester,
+        metrics_collector: MetricsCollectorHandle,
+        shutdown_signal: ShutdownSignal,
+    ) -> Self
+    {
+        Self {
+            interval,
+            store,
+            connectivity,
+            peer_manager,
+            saf_requester,
+            metrics_collector,
+            shutdown_signal,
+        }
+    }
+
+    pub fn spawn(self) {
+        task::spawn(self.run());
+    }
+
+    async fn run(mut self) {
+        let mut shutdown_signal = self.shutdown_signal.clone();
+        // The first tick of an interval completes immediately, so skip it to allow metrics to accumulate
+        let mut ticker = time::interval(self.interval).skip(1).fuse();
+        loop {
+            futures::select! {
+                _ = ticker.select_next_some() => {
+                    let snapshot = self.take_snapshot().await;
+                    if let Err(err) = self.write(snapshot).await {
+                        warn!(target: LOG_TARGET, "Failed to write metrics snapshot: {}", err);
+                    }
+                },
+                _ = shutdown_signal => {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Metrics snapshot writer is shutting down because it received the shutdown signal"
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn take_snapshot(&mut self) -> MetricsSnapshot {
+        let connections = self.connectivity.get_active_connections().await.unwrap_or_else(|err| {
+            debug!(target: LOG_TARGET, "Failed to get active connections: {}", err);
+            Vec::new()
+        });
+        let (bytes_read, bytes_written) = connections.iter().fold((0, 0), |(read, written), conn| {
+            (read + conn.bytes_read(), written + conn.bytes_written())
+        });
+        let saf_storage_bytes = self.saf_requester.get_storage_size().await.unwrap_or_else(|err| {
+            debug!(target: LOG_TARGET, "Failed to get SAF storage size: {}", err);
+            0
+        });
+        let metrics = &mut self.metrics_collector;
+        let num_messages_received = metrics
+            .get_total_message_count_in_timespan(self.interval)
+            .await
+            .unwrap_or_default();
+        let send_metrics = metrics.get_outbound_send_metrics().await.unwrap_or_default();
+        let num_checksum_failures = metrics.get_envelope_checksum_failures().await.unwrap_or_default();
+        let forward_metrics = metrics.get_forward_metrics().await.unwrap_or_default();
+
+        MetricsSnapshot {
+            timestamp: Utc::now(),
+            num_active_connections: connections.len(),
+            num_known_peers: self.peer_manager.count().await,
+            saf_storage_bytes,
+            bytes_read,
+            bytes_written,
+            num_messages_received,
+            num_send_failures: send_metrics.total_failures(),
+            num_checksum_failures,
+            num_forwards_dropped: forward_metrics.num_dropped,
+        }
+    }
+
+    async fn write(&self, snapshot: MetricsSnapshot) -> Result<(), MetricsSnapshotError> {
+        let store = self.store.clone();
+        task::spawn_blocking(move || store.append(&snapshot)).await?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_snapshot(n: usize) -> MetricsSnapshot {
+        MetricsSnapshot {
+            timestamp: Utc.timestamp(1_600_000_000 + n as i64, 0),
+            num_active_connections: n,
+            num_known_peers: 100 + n,
+            saf_storage_bytes: 1024,
+            bytes_read: 1 << 40,
+            bytes_written: 1 << 20,
+            num_messages_received: 10,
+            num_send_failures: 1,
+            num_checksum_failures: 2,
+            num_forwards_dropped: 3,
+        }
+    }
+
+    #[test]
+    fn ring_overwrites_oldest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshots.dat");
+        let store = MetricsSnapshotStore::open(&path, 3).unwrap();
+        assert!(store.is_empty());
+        for n in 0..5 {
+            store.append(&make_snapshot(n)).unwrap();
+        }
+        assert_eq!(store.len(), 3);
+        let snapshots = store.snapshots(None).unwrap();
+        assert_eq!(snapshots, vec![make_snapshot(4), make_snapshot(3), make_snapshot(2)]);
+        assert_eq!(store.snapshots(Some(1)).unwrap(), vec![make_snapshot(4)]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), slot_offset(3));
+    }
+
+    #[test]
+    fn reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snapshots.dat");
+        let store = MetricsSnapshotStore::open(&path, 3).unwrap();
+        store.append(&make_snapshot(1)).unwrap();
+        store.append(&make_snapshot(2)).unwrap();
+        drop(store);
+
+        let store = MetricsSnapshotStore::open(&path, 3).unwrap();
+        assert_eq!(store.snapshots(None).unwrap(), vec![make_snapshot(2), make_snapshot(1)]);
+        drop(store);
+
+        // A change in capacity discards the existing snapshots
+        let store = MetricsSnapshotStore::open(&path, 4).unwrap();
+        assert!(store.is_empty());
+
+        assert!(matches!(
+            MetricsSnapshotStore::open(&path, 0),
+            Err(MetricsSnapshotError::ZeroCapacity)
+        ));
+    }
+}
@@ -22,14 +22,23 @@
 
 use crate::{
     connectivity::{DhtConnectivity, MetricsCollector},
-    test_utils::{build_peer_manager, create_dht_actor_mock, make_node_identity, DhtMockState},
+    storage::DhtMetadataKey,
+    test_utils::{
+        build_peer_manager,
+        create_dht_actor_mock,
+        create_store_and_forward_mock,
+        make_node_identity,
+        DhtMockState,
+        StoreAndForwardMockState,
+    },
     DhtConfig,
 };
+use chrono::{DateTime, Utc};
 use rand::{rngs::OsRng, seq::SliceRandom};
-use std::{iter::repeat_with, sync::Arc, time::Duration};
+use std::{collections::HashMap, iter::repeat_with, sync::Arc, time::Duration};
 use tari_comms::{
     connectivity::ConnectivityEvent,
-    peer_manager::{Peer, PeerFeatures},
+    peer_manager::{NodeId, Peer, PeerFeatures},
     test_utils::{
         count_string_occurrences,
         mocks::{create_connectivity_mock, create_dummy_peer_connection, ConnectivityManagerMockState},
@@ -40,7 +49,8 @@ use tari_comms::{
 };
 use tari_shutdown::Shutdown;
 use tari_test_utils::async_assert;
-use tokio::sync::broadcast;
+use tari_utilities::message_format::MessageFormat;
+use tokio::{sync::broadcast, time};
 
 async fn setup(
     config: DhtConfig,
@@ -49,6 +59,7 @@ async fn setup(
 ) -> (
     DhtConnectivity,
     DhtMockState,
+    StoreAndForwardMockState,
     ConnectivityManagerMockState,
     Arc<PeerManager>,
     Arc<NodeIdentity>,
@@ -67,6 +78,7 @@ async fn setup(
     let (dht_requester, mock) = create_dht_actor_mock(1);
     let dht_state = mock.get_shared_state();
     mock.spawn();
+    let (saf_requester, saf_state) = create_store_and_forward_mock();
     let (event_publisher, _) = broadcast::channel(1);
 
     let dht_connectivity = DhtConnectivity::new(
@@ -75,7 +87,8 @@ async fn setup(
         node_identity.clone(),
         connectivity,
         dht_requester,
-        event_publisher.subscribe(),
+        saf_requester,
+        event_publisher,
         MetricsCollector::spawn(),
         shutdown.to_signal(),
     );
@@ -83,6 +96,7 @@ async fn setup(
     (
         dht_connectivity,
         dht_state,
+        saf_state,
         connectivity_state,
         peer_manager,
         node_identity,
@@ -99,7 +113,7 @@ async fn initialize() {
         ..Default::default()
     };
     let peers = repeat_with(|| make_node_identity().to_peer()).take(10).collect();
-    let (dht_connectivity, _, connectivity, peer_manager, node_identity, _shutdown) =
+    let (dht_connectivity, _, _, connectivity, peer_manager, node_identity, _shutdown) =
         setup(config, make_node_identity(), peers).await;
     dht_connectivity.spawn();
     let neighbours = peer_manager
@@ -146,7 +160,7 @@ async fn added_neighbours() {
         num_random_nodes: 0,
         ..Default::default()
     };
-    let (dht_connectivity, _, connectivity, _, _, _shutdown) = setup(config, node_identity, peers).await;
+    let (dht_connectivity, _, _, connectivity, _, _, _shutdown) = setup(config, node_identity, peers).await;
     dht_connectivity.spawn();
 
     // Wait for calls to add peers
@@ -191,7 +205,7 @@ async fn reinitialize_pools_when_offline() {
         num_random_nodes: 0,
         ..Default::default()
     };
-    let (dht_connectivity, _, connectivity, _, _, _shutdown) = setup(config, node_identity, peers).await;
+    let (dht_connectivity, _, _, connectivity, _, _, _shutdown) = setup(config, node_identity, peers).await;
     dht_connectivity.spawn();
 
     // Wait for calls to add peers
@@ -220,13 +234,168 @@ async fn reinitialize_pools_when_offline() {
     assert_eq!(managed.len(), 5);
 }
 
+#[tokio_macros::test_basic]
+#[allow(clippy::redundant_closure)]
+async fn rejoin_after_extended_disconnection() {
+    let node_identity = make_node_identity();
+    let peers = repeat_with(|| make_node_identity().to_peer()).take(5).collect();
+
+    let config = DhtConfig {
+        num_neighbouring_nodes: 5,
+        num_random_nodes: 0,
+        auto_join: true,
+        saf_auto_request: true,
+        rejoin_offline_threshold: Duration::from_millis(0),
+        ..Default::default()
+    };
+    let (dht_connectivity, dht_state, saf_state, connectivity, _, _, _shutdown) =
+        setup(config, node_identity, peers).await;
+    dht_connectivity.spawn();
+
+    async_assert!(
+        connectivity.call_count().await >= 1,
+        max_attempts = 20,
+        interval = Duration::from_millis(10),
+    );
+    connectivity.take_calls().await;
+    // Recent peers and the last join time were loaded on startup
+    assert_eq!(dht_state.call_count(), 2);
+
+    connectivity.publish_event(ConnectivityEvent::ConnectivityStateOnline(5));
+    async_assert!(
+        dht_state.call_count() >= 3,
+        max_attempts = 20,
+        interval = Duration::from_millis(10),
+    );
+    // Not previously offline, so no SAF request is made by DhtConnectivity
+    assert_eq!(saf_state.call_count(), 0);
+
+    connectivity.publish_event(ConnectivityEvent::ConnectivityStateOffline);
+    connectivity.publish_event(ConnectivityEvent::ConnectivityStateOnline(5));
+
+    async_assert!(
+        saf_state.call_count() >= 1,
+        max_attempts = 20,
+        interval = Duration::from_millis(10),
+    );
+    let calls = saf_state.take_calls().await;
+    assert_eq!(
+        count_string_occurrences(&calls, &["SendStoreForwardRequestNeighbours"]),
+        1
+    );
+    // The join cooldown is ignored when re-joining
+    assert_eq!(dht_state.call_count(), 4);
+}
+
+#[tokio_macros::test_basic]
+async fn join_cooldown_persists_across_restarts() {
+    let peers = repeat_with(|| make_node_identity().to_peer()).take(5).collect();
+    let config = DhtConfig {
+        num_neighbouring_nodes: 5,
+        num_random_nodes: 0,
+        auto_join: true,
+        join_cooldown_interval: Duration::from_secs(10 * 60),
+        ..Default::default()
+    };
+    let (dht_connectivity, dht_state, _, connectivity, _, _, _shutdown) =
+        setup(config, make_node_identity(), peers).await;
+    // A join was sent shortly before the restart
+    dht_state.set_setting(
+        DhtMetadataKey::LastJoinTimestamp,
+        (Utc::now() - chrono::Duration::seconds(30)).to_binary().unwrap(),
+    );
+    dht_connectivity.spawn();
+
+    async_assert!(
+        connectivity.call_count().await >= 1,
+        max_attempts = 20,
+        interval = Duration::from_millis(10),
+    );
+    connectivity.publish_event(ConnectivityEvent::ConnectivityStateOnline(5));
+    time::delay_for(Duration::from_millis(50)).await;
+    // Only recent peers and the last join time were requested, no join was sent
+    assert_eq!(dht_state.call_count(), 2);
+}
+
+#[tokio_macros::test_basic]
+async fn warm_start_reconnects_to_recent_peers() {
+    let peers = repeat_with(|| make_node_identity().to_peer())
+        .take(3)
+        .collect::<Vec<_>>();
+    let config = DhtConfig {
+        num_neighbouring_nodes: 5,
+        num_random_nodes: 0,
+        warm_start_max_age: Duration::from_secs(24 * 60 * 60),
+        ..Default::default()
+    };
+    let now = Utc::now();
+    let recent_peers = vec![
+        (peers[0].node_id.clone(), now - chrono::Duration::minutes(1)),
+        (peers[1].node_id.clone(), now - chrono::Duration::hours(1)),
+        // Too old to reconnect to
+        (peers[2].node_id.clone(), now - chrono::Duration::days(2)),
+        // Unknown peer
+        (make_node_identity().node_id().clone(), now),
+    ]
+    .into_iter()
+    .collect::<HashMap<_, _>>();
+    let (dht_connectivity, dht_state, _, connectivity, _, _, _shutdown) =
+        setup(config, make_node_identity(), peers).await;
+    dht_state.set_setting(DhtMetadataKey::RecentPeers, recent_peers.to_binary().unwrap());
+    dht_connectivity.spawn();
+
+    async_assert!(
+        connectivity.call_count().await >= 3,
+        max_attempts = 20,
+        interval = Duration::from_millis(10),
+    );
+    let calls = connectivity
+        .take_calls()
+        .await
+        .into_iter()
+        .filter(|call| !call.starts_with("WaitStarted"))
+        .collect::<Vec<_>>();
+    assert_eq!(count_string_occurrences(&calls, &["DialPeer"]), 2);
+    // Recent peers are dialed before the neighbour pool
+    assert!(calls[0].starts_with("DialPeer"));
+    assert!(calls[1].starts_with("DialPeer"));
+}
+
+#[tokio_macros::test_basic]
+async fn connected_peers_are_persisted() {
+    let config = DhtConfig {
+        num_neighbouring_nodes: 5,
+        num_random_nodes: 0,
+        connectivity_update_interval: Duration::from_millis(50),
+        ..Default::default()
+    };
+    let (dht_connectivity, dht_state, _, connectivity, _, _, _shutdown) =
+        setup(config, make_node_identity(), vec![]).await;
+    dht_connectivity.spawn();
+
+    let peer = make_node_identity();
+    let (conn, _) = create_dummy_peer_connection(peer.node_id().clone());
+    connectivity.publish_event(ConnectivityEvent::PeerConnected(conn));
+
+    async_assert!(
+        dht_state.get_setting(&DhtMetadataKey::RecentPeers).is_some(),
+        max_attempts = 20,
+        interval = Duration::from_millis(10),
+    );
+    let recent_peers =
+        HashMap::<NodeId, DateTime<Utc>>::from_binary(&dht_state.get_setting(&DhtMetadataKey::RecentPeers).unwrap())
+            .unwrap();
+    assert_eq!(recent_peers.len(), 1);
+    assert!(recent_peers.contains_key(peer.node_id()));
+}
+
 #[tokio_macros::test_basic]
 async fn insert_neighbour() {
     let node_identity = make_node_identity();
     let node_identities =
         ordered_node_identities_by_distance(node_identity.node_id(), 10, PeerFeatures::COMMUNICATION_NODE);
 
-    let (mut dht_connectivity, _, _, _, _, _) = setup(Default::default(), node_identity.clone(), vec![]).await;
+    let (mut dht_connectivity, _, _, _, _, _, _) = setup(Default::default(), node_identity.clone(), vec![]).await;
     dht_connectivity.config.num_neighbouring_nodes = 8;
 
     let shuffled = {
@@ -261,6 +430,7 @@ async fn insert_neighbour() {
 mod metrics {
     mod collector {
         use crate::connectivity::MetricsCollector;
+        use std::time::Duration;
         use tari_comms::peer_manager::NodeId;
 
         #[tokio_macros::test_basic]
@@ -291,5 +461,24 @@ mod metrics {
                 .unwrap();
             assert_eq!(ts.count(), 0);
         }
+
+        #[tokio_macros::test_basic]
+        async fn it_counts_the_network_heartbeat() {
+            let mut metric_collector = MetricsCollector::spawn();
+            (0..3).for_each(|_| {
+                assert!(metric_collector.write_metric_message_received(NodeId::default()));
+            });
+            assert!(metric_collector.write_metric_join_received());
+            assert!(metric_collector.write_metric_pong_received());
+            assert!(metric_collector.write_metric_pong_received());
+
+            let heartbeat = metric_collector
+                .get_network_heartbeat(Duration::from_secs(60))
+                .await
+                .unwrap();
+            assert_eq!(heartbeat.num_messages, 3);
+            assert_eq!(heartbeat.num_joins, 1);
+            assert_eq!(heartbeat.num_pongs, 2);
+        }
     }
 }
@@ -21,4 +21,10 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 /// Version for DHT envelope
-pub const DHT_ENVELOPE_HEADER_VERSION: u32 = 0;
+pub const DHT_ENVELOPE_HEADER_VERSION: u32 = tari_comms_dht_codec::DHT_ENVELOPE_HEADER_VERSION;
+
+/// The comms protocol version from which peers accept envelopes in the versioned wire format
+pub const DHT_ENVELOPE_V1_PROTOCOL_VERSION: u8 = 1;
+
+/// The comms protocol version from which peers accept envelopes in the compact direct wire format
+pub const DHT_DIRECT_ENVELOPE_PROTOCOL_VERSION: u8 = 3;
@@ -20,6 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use std::borrow::Cow;
 use tari_comms::types::CommsPublicKey;
 use tari_crypto::{
     keys::{DiffieHellmanSharedSecret, PublicKey},
@@ -45,11 +46,31 @@ pub fn encrypt(cipher_key: &CommsPublicKey, plain_text: &[u8]) -> Result<Vec<u8>
     ChaCha20::seal_with_integral_nonce(&plain_text.to_vec(), cipher_key.as_bytes())
 }
 
+/// Returns the bytes signed by the origin MAC of a message. The sequence number of a sequenced message is signed along
+/// with the body so that it cannot be altered in transit. Unsequenced messages (sequence 0) sign the body alone.
+pub fn origin_mac_challenge(body: &[u8], sequence: u64) -> Cow<'_, [u8]> {
+    if sequence == 0 {
+        return Cow::Borrowed(body);
+    }
+    let mut challenge = Vec::with_capacity(body.len() + 8);
+    challenge.extend_from_slice(body);
+    challenge.extend_from_slice(&sequence.to_le_bytes());
+    Cow::Owned(challenge)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use tari_utilities::hex::from_hex;
 
+    #[test]
+    fn origin_mac_challenge_covers_sequence() {
+        let body = b"Sequenced";
+        assert_eq!(&*origin_mac_challenge(body, 0), body);
+        assert_ne!(origin_mac_challenge(body, 1), origin_mac_challenge(body, 2));
+        assert!(origin_mac_challenge(body, 1).starts_with(body));
+    }
+
     #[test]
     fn encrypt_decrypt() {
         let key = CommsPublicKey::default();
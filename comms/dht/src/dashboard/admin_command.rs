@@ -0,0 +1,129 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::DashboardCollector;
+use log::*;
+use tari_comms::admin::AdminCommands;
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+
+const LOG_TARGET: &str = "comms::dht::dashboard::admin_command";
+
+/// The name of the admin command that serves dashboard snapshots
+pub const ADMIN_COMMAND: &str = "dashboard";
+
+/// Registers the `dashboard` admin command. The command writes a [DashboardSnapshot](super::DashboardSnapshot) as a
+/// single JSON line and then closes the connection.
+pub fn register_admin_command(commands: &AdminCommands, collector: DashboardCollector) {
+    commands.register(ADMIN_COMMAND, move |socket| serve_client(socket, collector.clone()));
+}
+
+async fn serve_client(mut socket: TcpStream, mut collector: DashboardCollector) {
+    let mut line = collector.collect().await.to_json();
+    line.push('\n');
+    if let Err(err) = socket.write_all(line.as_bytes()).await {
+        debug!(target: LOG_TARGET, "Failed to write dashboard snapshot: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        store_forward::StoredMessage,
+        test_utils::{build_peer_manager, create_dht_actor_mock, create_store_and_forward_mock, make_node_identity},
+    };
+    use chrono::Utc;
+    use serde_json::Value;
+    use tari_comms::{
+        admin::AdminSocket,
+        test_utils::mocks::{create_connectivity_mock, create_dummy_peer_connection},
+    };
+    use tari_shutdown::Shutdown;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio_macros::test_basic]
+    async fn serves_snapshot() {
+        let node_identity = make_node_identity();
+        let peer_manager = build_peer_manager();
+        let neighbour = make_node_identity();
+        peer_manager.add_peer(neighbour.to_peer()).await.unwrap();
+
+        let (connectivity, mock) = create_connectivity_mock();
+        let connectivity_state = mock.get_shared_state();
+        mock.spawn();
+        let (conn, _) = create_dummy_peer_connection(neighbour.node_id().clone());
+        connectivity_state.add_active_connection(conn).await;
+        let (dht_requester, mock) = create_dht_actor_mock(1);
+        mock.spawn();
+        let (saf_requester, saf_state) = create_store_and_forward_mock();
+        saf_state
+            .add_message(StoredMessage {
+                id: 1,
+                version: 0,
+                origin_pubkey: None,
+                message_type: 0,
+                destination_pubkey: Some("abcd".to_string()),
+                destination_node_id: None,
+                header: vec![0; 10],
+                body: vec![0; 20],
+                is_encrypted: true,
+                priority: 0,
+                stored_at: Utc::now().naive_utc(),
+                body_hash: String::new(),
+                mailbox_tag: None,
+                destination_node_ids: None,
+            })
+            .await;
+
+        let shutdown = Shutdown::new();
+        let collector = DashboardCollector::new(
+            8,
+            node_identity.clone(),
+            peer_manager,
+            connectivity,
+            dht_requester,
+            saf_requester,
+        );
+        let commands = AdminCommands::new();
+        register_admin_command(&commands, collector);
+        let addr = AdminSocket::new(commands, shutdown.to_signal())
+            .spawn("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        socket.write_all(b"dashboard\n").await.unwrap();
+        let mut buf = String::new();
+        socket.read_to_string(&mut buf).await.unwrap();
+        let value = serde_json::from_str::<Value>(&buf).unwrap();
+        assert_eq!(value["node_id"], node_identity.node_id().to_string());
+        assert_eq!(value["neighbourhood"]["num_connected_neighbours"], 1);
+        assert_eq!(
+            value["neighbourhood"]["neighbours"][0]["node_id"],
+            neighbour.node_id().to_string()
+        );
+        assert_eq!(value["saf"]["total_bytes"], 30);
+        assert_eq!(value["saf"]["destinations"][0]["destination"], "abcd");
+        assert_eq!(value["connections"].as_array().unwrap().len(), 1);
+        assert_eq!(value["discovery"]["num_succeeded"], 0);
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{
+    BandwidthSnapshot,
+    ConnectionSnapshot,
+    DashboardSnapshot,
+    NeighbourSnapshot,
+    NeighbourhoodSnapshot,
+    SafOccupancySnapshot,
+};
+use crate::{store_forward::StoreAndForwardRequester, DhtRequester};
+use chrono::Utc;
+use log::*;
+use std::sync::Arc;
+use tari_comms::{
+    connection_manager::ConnectionInfo,
+    connectivity::ConnectivityRequester,
+    peer_manager::{NodeId, NodeIdentity, PeerFeatures},
+    PeerManager,
+};
+
+const LOG_TARGET: &str = "comms::dht::dashboard";
+
+/// The maximum number of destinations included in the SAF occupancy of a snapshot
+const MAX_SAF_DESTINATIONS: usize = 20;
+
+/// Collects a [DashboardSnapshot] from the DHT and comms services. A part of the snapshot that cannot be collected
+/// (e.g. because a service is shutting down) is logged and left empty rather than failing the whole snapshot.
+#[derive(Clone)]
+pub struct DashboardCollector {
+    num_neighbouring_nodes: usize,
+    node_identity: Arc<NodeIdentity>,
+    peer_manager: Arc<PeerManager>,
+    connectivity: ConnectivityRequester,
+    dht_requester: DhtRequester,
+    saf_requester: StoreAndForwardRequester,
+}
+
+impl DashboardCollector {
+    pub fn new(
+        num_neighbouring_nodes: usize,
+        node_identity: Arc<NodeIdentity>,
+        peer_manager: Arc<PeerManager>,
+        connectivity: ConnectivityRequester,
+        dht_requester: DhtRequester,
+        saf_requester: StoreAndForwardRequester,
+    ) -> Self
+    {
+        Self {
+            num_neighbouring_nodes,
+            node_identity,
+            peer_manager,
+            connectivity,
+            dht_requester,
+            saf_requester,
+        }
+    }
+
+    pub async fn collect(&mut self) -> DashboardSnapshot {
+        let connections = self
+            .connectivity
+            .get_active_connection_info()
+            .await
+            .unwrap_or_else(|err| {
+                debug!(target: LOG_TARGET, "Failed to get active connections: {}", err);
+                Vec::new()
+            });
+        let bandwidth = connections
+            .iter()
+            .fold(BandwidthSnapshot::default(), |mut bandwidth, conn| {
+                bandwidth.bytes_read += conn.bytes_read;
+                bandwidth.bytes_written += conn.bytes_written;
+                bandwidth
+            });
+        let discovery = self.dht_requester.get_discovery_stats().await.unwrap_or_else(|err| {
+            debug!(target: LOG_TARGET, "Failed to get discovery stats: {}", err);
+            Default::default()
+        });
+
+        let neighbourhood = self.neighbourhood(&connections).await;
+        let saf = self.saf_occupancy().await;
+        let connections = self.connection_snapshots(&connections).await;
+        DashboardSnapshot {
+            timestamp: Utc::now(),
+            node_id: self.node_identity.node_id().to_string(),
+            neighbourhood,
+            saf,
+            discovery: discovery.into(),
+            connections,
+            bandwidth,
+        }
+    }
+
+    async fn neighbourhood(&self, connections: &[ConnectionInfo]) -> NeighbourhoodSnapshot {
+        let node_id = self.node_identity.node_id();
+        let neighbours = self
+            .peer_manager
+            .closest_peers(
+                node_id,
+                self.num_neighbouring_nodes,
+                &[],
+                Some(PeerFeatures::COMMUNICATION_NODE),
+            )
+            .await
+            .unwrap_or_else(|err| {
+                debug!(target: LOG_TARGET, "Failed to get neighbouring peers: {}", err);
+                Vec::new()
+            });
+        let is_connected = |peer_node_id: &NodeId| connections.iter().any(|conn| conn.peer_node_id == *peer_node_id);
+        let neighbours = neighbours
+            .iter()
+            .map(|peer| NeighbourSnapshot {
+                node_id: peer.node_id.to_string(),
+                distance: node_id.distance(&peer.node_id).to_string(),
+                is_connected: is_connected(&peer.node_id),
+            })
+            .collect::<Vec<_>>();
+
+        let num_connected_neighbours = neighbours.iter().filter(|n| n.is_connected).count();
+        let num_connected_clients = connections.iter().filter(|conn| conn.peer_features.is_client()).count();
+        let num_connected_non_neighbours = connections
+            .len()
+            .saturating_sub(num_connected_clients + num_connected_neighbours);
+        NeighbourhoodSnapshot {
+            target_num_neighbours: self.num_neighbouring_nodes,
+            neighbours,
+            num_connected_neighbours,
+            num_connected_non_neighbours,
+            num_connected_clients,
+        }
+    }
+
+    async fn saf_occupancy(&mut self) -> SafOccupancySnapshot {
+        let total_bytes = self.saf_requester.get_storage_size().await.unwrap_or_else(|err| {
+            debug!(target: LOG_TARGET, "Failed to get SAF storage size: {}", err);
+            0
+        });
+        let destinations = self
+            .saf_requester
+            .count_messages_by_destination(MAX_SAF_DESTINATIONS)
+            .await
+            .unwrap_or_else(|err| {
+                debug!(
+                    target: LOG_TARGET,
+                    "Failed to get SAF occupancy by destination: {}", err
+                );
+                Vec::new()
+            });
+        SafOccupancySnapshot {
+            total_bytes,
+            destinations,
+        }
+    }
+
+    async fn connection_snapshots(&self, connections: &[ConnectionInfo]) -> Vec<ConnectionSnapshot> {
+        let mut snapshots = Vec::with_capacity(connections.len());
+        for conn in connections {
+            let latency = self
+                .peer_manager
+                .get_peer_info(&conn.peer_node_id)
+                .await
+                .ok()
+                .and_then(|info| info.addresses.into_iter().find(|a| a.address == conn.address))
+                .and_then(|addr| addr.avg_latency);
+            snapshots.push(ConnectionSnapshot::new(conn, latency.map(|l| l.as_millis() as u64)));
+        }
+        snapshots
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # DHT dashboard
+//!
+//! A [DashboardSnapshot](self::DashboardSnapshot) combines the state a node dashboard needs to display (neighbourhood
+//! composition, store and forward occupancy by destination, discovery outcomes, active connections with their
+//! latencies and bandwidth counters) so that it can be fetched in a single round-trip.
+//!
+//! The `dashboard` command of the comms admin socket (see `tari_comms::admin`) writes a snapshot as a single line of
+//! JSON and then closes the connection. For example, `echo dashboard | nc 127.0.0.1 18190` prints:
+//!
+//! ```text
+//! {"timestamp":"2020-11-18T09:12:44Z","node_id":"b4d2...","neighbourhood":{...},"saf":{...},"discovery":{...},...}
+//! ```
+
+mod collector;
+pub use collector::DashboardCollector;
+
+mod snapshot;
+pub use snapshot::{
+    BandwidthSnapshot,
+    ConnectionSnapshot,
+    DashboardSnapshot,
+    DiscoverySnapshot,
+    NeighbourSnapshot,
+    NeighbourhoodSnapshot,
+    SafOccupancySnapshot,
+};
+
+mod admin_command;
+pub use admin_command::{register_admin_command, ADMIN_COMMAND};
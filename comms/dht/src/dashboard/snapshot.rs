@@ -0,0 +1,131 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{discovery::DiscoveryStats, store_forward::SafDestinationUsage};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tari_comms::connection_manager::ConnectionInfo;
+
+/// A point-in-time view of the DHT state of this node, suitable for display in node dashboards
+#[derive(Debug, Clone, Serialize)]
+pub struct DashboardSnapshot {
+    pub timestamp: DateTime<Utc>,
+    pub node_id: String,
+    pub neighbourhood: NeighbourhoodSnapshot,
+    pub saf: SafOccupancySnapshot,
+    pub discovery: DiscoverySnapshot,
+    pub connections: Vec<ConnectionSnapshot>,
+    pub bandwidth: BandwidthSnapshot,
+}
+
+impl DashboardSnapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("DashboardSnapshot is always serializable")
+    }
+}
+
+/// The composition of the connected peers relative to this node's neighbourhood
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NeighbourhoodSnapshot {
+    /// The number of neighbours that this node aims to be connected to (`DhtConfig::num_neighbouring_nodes`)
+    pub target_num_neighbours: usize,
+    /// The closest known communication nodes that are not banned or offline, closest first
+    pub neighbours: Vec<NeighbourSnapshot>,
+    pub num_connected_neighbours: usize,
+    /// The number of connected communication nodes that are not neighbours (e.g. the random peer pool)
+    pub num_connected_non_neighbours: usize,
+    pub num_connected_clients: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NeighbourSnapshot {
+    pub node_id: String,
+    /// The hex-encoded XOR distance from this node
+    pub distance: String,
+    pub is_connected: bool,
+}
+
+/// Store and forward storage held by this node
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SafOccupancySnapshot {
+    /// The total number of header and body bytes held for all destinations
+    pub total_bytes: usize,
+    /// The destinations with the most stored messages, most messages first
+    pub destinations: Vec<SafDestinationUsage>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiscoverySnapshot {
+    pub num_succeeded: u64,
+    pub num_failed: u64,
+    /// The proportion of completed discoveries that succeeded, or None if no discoveries have completed
+    pub success_ratio: Option<f64>,
+    pub last_succeeded_at: Option<DateTime<Utc>>,
+}
+
+impl From<DiscoveryStats> for DiscoverySnapshot {
+    fn from(stats: DiscoveryStats) -> Self {
+        Self {
+            success_ratio: stats.success_ratio(),
+            num_succeeded: stats.num_succeeded,
+            num_failed: stats.num_failed,
+            last_succeeded_at: stats.last_succeeded_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionSnapshot {
+    pub node_id: String,
+    pub address: String,
+    pub direction: String,
+    pub is_client: bool,
+    pub age_secs: u64,
+    pub handshake_ms: Option<u64>,
+    /// The average measured latency of the connected address, or None if it has not been measured
+    pub latency_ms: Option<u64>,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl ConnectionSnapshot {
+    pub(super) fn new(info: &ConnectionInfo, latency_ms: Option<u64>) -> Self {
+        Self {
+            node_id: info.peer_node_id.to_string(),
+            address: info.address.to_string(),
+            direction: info.direction.to_string(),
+            is_client: info.peer_features.is_client(),
+            age_secs: info.age.as_secs(),
+            handshake_ms: info.handshake_duration.map(|d| d.as_millis() as u64),
+            latency_ms,
+            bytes_read: info.bytes_read,
+            bytes_written: info.bytes_written,
+        }
+    }
+}
+
+/// Bandwidth counters totalled over the connections that were active when the snapshot was taken
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct BandwidthSnapshot {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
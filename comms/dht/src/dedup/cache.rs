@@ -0,0 +1,255 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use chrono::{DateTime, Utc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+    time::{Duration, Instant},
+};
+
+/// Counters for the message hash (dedup) cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupCacheStats {
+    /// The number of message hashes that were already in the cache i.e. duplicate messages
+    pub hits: u64,
+    /// The number of message hashes that were not in the cache
+    pub misses: u64,
+    /// The number of entries that were evicted to make space for newer entries
+    pub evictions: u64,
+    /// The number of entries currently in the cache
+    pub num_entries: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    expires_at: Instant,
+    seq: u64,
+}
+
+/// A size-bounded, time-bounded set of message hashes. Once the cache is at capacity the least recently seen hash is
+/// evicted, and entries are ignored and removed once they have expired.
+pub(crate) struct DedupCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<Vec<u8>, Entry>,
+    /// Hashes ordered from least to most recently seen
+    recency: BTreeMap<u64, Vec<u8>>,
+    next_seq: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl DedupCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            next_seq: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Inserts the hash into the cache, returning true if it was already present (and not expired), otherwise false.
+    /// The expiry time of an existing entry is reset.
+    pub fn insert(&mut self, hash: Vec<u8>) -> bool {
+        let now = Instant::now();
+        let already_exists = match self.remove(&hash) {
+            Some(entry) => entry.expires_at > now,
+            None => false,
+        };
+        if already_exists {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        self.insert_entry(hash, now + self.ttl);
+        already_exists
+    }
+
+    fn insert_entry(&mut self, hash: Vec<u8>, expires_at: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            self.remove_expired();
+        }
+        while self.entries.len() >= self.capacity {
+            let oldest_seq = match self.recency.keys().next() {
+                Some(seq) => *seq,
+                None => break,
+            };
+            if let Some(evicted) = self.recency.remove(&oldest_seq) {
+                self.entries.remove(&evicted);
+                self.evictions += 1;
+            }
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.recency.insert(seq, hash.clone());
+        self.entries.insert(hash, Entry { expires_at, seq });
+    }
+
+    fn remove(&mut self, hash: &[u8]) -> Option<Entry> {
+        let entry = self.entries.remove(hash)?;
+        self.recency.remove(&entry.seq);
+        Some(entry)
+    }
+
+    fn remove_expired(&mut self) {
+        let now = Instant::now();
+        let recency = &mut self.recency;
+        self.entries.retain(|_, entry| {
+            let is_live = entry.expires_at > now;
+            if !is_live {
+                recency.remove(&entry.seq);
+            }
+            is_live
+        });
+    }
+
+    /// Removes all entries from the cache. The hit, miss and eviction counters are not reset.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    pub fn stats(&self) -> DedupCacheStats {
+        DedupCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            num_entries: self.entries.len(),
+        }
+    }
+
+    /// Returns the approximate number of bytes used by the cache
+    pub fn memory_usage(&self) -> usize {
+        // Each hash is stored twice: once as the entry key and once in the recency index
+        let entry_overhead = 2 * mem::size_of::<Vec<u8>>() + mem::size_of::<Entry>() + mem::size_of::<u64>();
+        self.entries
+            .keys()
+            .map(|hash| 2 * hash.len() + entry_overhead)
+            .sum::<usize>()
+    }
+
+    /// Returns the unexpired entries, ordered from least to most recently seen, together with their expiry time.
+    pub fn snapshot(&self) -> Vec<(Vec<u8>, DateTime<Utc>)> {
+        let now = Instant::now();
+        let now_utc = Utc::now();
+        self.recency
+            .values()
+            .filter_map(|hash| {
+                let entry = self.entries.get(hash)?;
+                let remaining = entry.expires_at.checked_duration_since(now)?;
+                let remaining = chrono::Duration::from_std(remaining).ok()?;
+                Some((hash.clone(), now_utc + remaining))
+            })
+            .collect()
+    }
+
+    /// Restores entries previously returned by `snapshot`. Expired entries are skipped. The counters are not affected.
+    pub fn restore<I: IntoIterator<Item = (Vec<u8>, DateTime<Utc>)>>(&mut self, entries: I) {
+        let now = Instant::now();
+        let now_utc = Utc::now();
+        for (hash, expires_at) in entries {
+            let remaining = match expires_at.signed_duration_since(now_utc).to_std() {
+                Ok(remaining) => remaining,
+                // Expired
+                Err(_) => continue,
+            };
+            self.remove(&hash);
+            self.insert_entry(hash, now + remaining);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn insert_and_evict() {
+        let mut cache = DedupCache::new(2, Duration::from_secs(60));
+        assert!(!cache.insert(vec![1]));
+        assert!(!cache.insert(vec![2]));
+        assert!(cache.insert(vec![1]));
+        // [2] is the least recently seen and is evicted
+        assert!(!cache.insert(vec![3]));
+        assert!(cache.insert(vec![1]));
+        assert!(!cache.insert(vec![2]));
+
+        assert_eq!(cache.stats(), DedupCacheStats {
+            hits: 2,
+            misses: 4,
+            evictions: 2,
+            num_entries: 2,
+        });
+
+        cache.clear();
+        assert_eq!(cache.stats().num_entries, 0);
+        assert_eq!(cache.memory_usage(), 0);
+        assert!(!cache.insert(vec![1]));
+    }
+
+    #[test]
+    fn expired_entries() {
+        let mut cache = DedupCache::new(2, Duration::from_millis(1));
+        assert!(!cache.insert(vec![1]));
+        thread::sleep(Duration::from_millis(5));
+        assert!(!cache.insert(vec![1]));
+        thread::sleep(Duration::from_millis(5));
+        // Expired entries make space before any entry is evicted
+        cache.insert(vec![2]);
+        cache.insert(vec![3]);
+        assert_eq!(cache.stats().evictions, 0);
+        thread::sleep(Duration::from_millis(5));
+        assert!(cache.snapshot().is_empty());
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut cache = DedupCache::new(10, Duration::from_secs(60));
+        cache.insert(vec![1]);
+        cache.insert(vec![2]);
+        let snapshot = cache.snapshot();
+        assert_eq!(snapshot.len(), 2);
+
+        let mut restored = DedupCache::new(10, Duration::from_secs(60));
+        restored.restore(
+            snapshot
+                .into_iter()
+                .chain(Some((vec![3], Utc::now() - chrono::Duration::seconds(1)))),
+        );
+        assert_eq!(restored.stats().num_entries, 2);
+        assert!(restored.insert(vec![1]));
+        assert!(restored.insert(vec![2]));
+        assert!(!restored.insert(vec![3]));
+    }
+}
@@ -20,19 +20,22 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::{actor::DhtRequester, inbound::DhtInboundMessage};
-use digest::Input;
+mod cache;
+pub(crate) use cache::DedupCache;
+pub use cache::DedupCacheStats;
+
+use crate::{actor::DhtRequester, inbound::DhtInboundMessage, message_key};
 use futures::{task::Context, Future};
 use log::*;
 use std::task::Poll;
-use tari_comms::{pipeline::PipelineError, types::Challenge};
+use tari_comms::pipeline::PipelineError;
 use tari_utilities::hex::Hex;
 use tower::{layer::Layer, Service, ServiceExt};
 
 const LOG_TARGET: &str = "comms::dht::dedup";
 
 fn hash_inbound_message(message: &DhtInboundMessage) -> Vec<u8> {
-    Challenge::new().chain(&message.body).result().to_vec()
+    message_key::message_key_from_header(&message.dht_header, &message.body)
 }
 
 /// # DHT Deduplication middleware
@@ -159,7 +162,7 @@ mod test {
     #[test]
     fn deterministic_hash() {
         const TEST_MSG: &[u8] = b"test123";
-        const EXPECTED_HASH: &str = "90cccd774db0ac8c6ea2deff0e26fc52768a827c91c737a2e050668d8c39c224";
+        const EXPECTED_HASH: &str = "e918d604d0d7b68ee6c0ddb025f4d5fe8e15213608f9f32c40c8fb557cbc0c82";
         let node_identity = make_node_identity();
         let msg = make_dht_inbound_message(&node_identity, TEST_MSG.to_vec(), DhtMessageFlags::empty(), false);
         let hash1 = hash_inbound_message(&msg);
@@ -23,20 +23,60 @@
 use self::outbound::OutboundMessageRequester;
 use crate::{
     actor::{DhtActor, DhtRequest, DhtRequester},
-    connectivity::{DhtConnectivity, MetricsCollector, MetricsCollectorHandle},
+    connectivity::{
+        DhtConnectivity,
+        MemoryUsageReporter,
+        MetricsCollector,
+        MetricsCollectorHandle,
+        MetricsSnapshotError,
+        MetricsSnapshotStore,
+        MetricsSnapshotWriter,
+    },
+    dashboard::{self, DashboardCollector},
     discovery::{DhtDiscoveryRequest, DhtDiscoveryRequester, DhtDiscoveryService},
     event::{DhtEventReceiver, DhtEventSender},
     inbound,
-    inbound::{DecryptedDhtMessage, DhtInboundMessage, MetricsLayer},
+    inbound::{
+        DecryptedDhtMessage,
+        DhtInboundMessage,
+        DhtMessageHandlers,
+        LatencyBudgetTracker,
+        MetricsLayer,
+        PipelineErrorLog,
+        PipelineStage,
+    },
     logging_middleware::MessageLoggingLayer,
     network_discovery::DhtNetworkDiscovery,
     outbound,
-    outbound::DhtOutboundRequest,
+    outbound::{
+        DhtOutboundRequest,
+        MessageSequencer,
+        OutboundAuditLog,
+        OutboundBufferUsage,
+        OutboundEventReceiver,
+        OutboundEventSender,
+        ScheduledSendDatabase,
+        ScheduledSendRequest,
+        ScheduledSendRequester,
+        ScheduledSendService,
+    },
     proto::envelope::DhtMessageType,
     rpc,
     storage::{DbConnection, StorageError},
     store_forward,
-    store_forward::{StoreAndForwardError, StoreAndForwardRequest, StoreAndForwardRequester, StoreAndForwardService},
+    store_forward::{
+        SafCapability,
+        SafResponse,
+        SafResponseSender,
+        SafResponseSummary,
+        SafStoreFilters,
+        ServedClients,
+        StoreAndForwardError,
+        StoreAndForwardRequest,
+        StoreAndForwardRequester,
+        StoreAndForwardService,
+        SAF_CAPABILITY_METADATA_KEY,
+    },
     tower_filter,
     DedupLayer,
     DhtActorError,
@@ -44,12 +84,17 @@ use crate::{
 };
 use futures::{channel::mpsc, future, Future};
 use log::*;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
+use tari_common::ConfigurationError;
 use tari_comms::{
+    admin::AdminCommands,
     connectivity::ConnectivityRequester,
     message::{InboundMessage, OutboundMessage},
     peer_manager::{NodeIdentity, PeerFeatures, PeerManager},
-    pipeline::PipelineError,
+    pipeline::{CatchPanicLayer, PanicCounter, PipelineError},
+    protocol::messaging::OutboundQueueUsage,
+    security_events::SecurityEventPublisher,
+    ShutdownReporter,
 };
 use tari_shutdown::ShutdownSignal;
 use thiserror::Error;
@@ -61,16 +106,22 @@ const LOG_TARGET: &str = "comms::dht";
 const DHT_ACTOR_CHANNEL_SIZE: usize = 100;
 const DHT_DISCOVERY_CHANNEL_SIZE: usize = 100;
 const DHT_SAF_SERVICE_CHANNEL_SIZE: usize = 100;
+const DHT_SCHEDULED_SEND_CHANNEL_SIZE: usize = 100;
 const DHT_EVENT_BROADCAST_CHANNEL_SIZE: usize = 100;
+const OUTBOUND_EVENT_BROADCAST_CHANNEL_SIZE: usize = 100;
 
 #[derive(Debug, Error)]
 pub enum DhtInitializationError {
+    #[error("Invalid DHT configuration: {0}")]
+    InvalidConfiguration(#[from] ConfigurationError),
     #[error("Database initialization failed: {0}")]
     DatabaseMigrationFailed(#[from] StorageError),
     #[error("StoreAndForwardInitializationError: {0}")]
     StoreAndForwardInitializationError(#[from] StoreAndForwardError),
     #[error("DhtActorInitializationError: {0}")]
     DhtActorInitializationError(#[from] DhtActorError),
+    #[error("MetricsSnapshotError: {0}")]
+    MetricsSnapshotError(#[from] MetricsSnapshotError),
 }
 
 /// Responsible for starting the DHT actor, building the DHT middleware stack and as a factory
@@ -90,47 +141,134 @@ pub struct Dht {
     /// Sender for SAF requests
     saf_sender: mpsc::Sender<StoreAndForwardRequest>,
     /// Sender for SAF repsonse signals
-    saf_response_signal_sender: mpsc::Sender<()>,
+    saf_response_signal_sender: mpsc::Sender<SafResponseSummary>,
+    /// Queues responses to requests for stored messages to be sent in the background
+    saf_response_sender: mpsc::Sender<SafResponse>,
     /// Sender for DHT discovery requests
     discovery_sender: mpsc::Sender<DhtDiscoveryRequest>,
+    /// Sender for scheduled send requests
+    scheduled_send_sender: mpsc::Sender<ScheduledSendRequest>,
     /// Connectivity actor requester
     connectivity: ConnectivityRequester,
     /// Event stream sender
     event_publisher: DhtEventSender,
+    /// Outbound message send result event stream sender
+    outbound_event_publisher: OutboundEventSender,
+    /// Rolling log of recent outbound messages
+    outbound_audit_log: OutboundAuditLog,
+    /// Rolling log of inbound messages that failed in the pipeline
+    pipeline_error_log: PipelineErrorLog,
+    /// Times inbound messages through the pipeline stages against their latency budgets
+    latency_budget_tracker: LatencyBudgetTracker,
+    /// Assigns sequence numbers to direct messages sent by outbound requesters
+    message_sequencer: MessageSequencer,
+    /// Accounts for the requests waiting in the outbound message buffer
+    outbound_buffer_usage: OutboundBufferUsage,
     /// Used by MetricsLayer to collect metrics and to inform heuristics for peer banning
     metrics_collector: MetricsCollectorHandle,
+    /// Periodic snapshots of key metrics, if enabled
+    metrics_snapshot_store: Option<MetricsSnapshotStore>,
+    /// Memory usage of the comms outbound message queue, if it is shared with the DHT
+    outbound_queue_usage: Option<OutboundQueueUsage>,
+    /// Counts panics caught in the inbound and outbound middleware
+    pipeline_panic_counter: PanicCounter,
+    /// Client peers that this node stores and pushes messages for
+    served_clients: ServedClients,
+    /// Filters that determine which undeliverable messages this node stores for peers
+    saf_store_filters: SafStoreFilters,
+    /// Handlers for extension DHT message types registered by downstream crates
+    message_handlers: DhtMessageHandlers,
+    /// Used to include the number of stored messages in the comms shutdown report, if set
+    shutdown_reporter: Option<ShutdownReporter>,
+    /// Publishes security events (e.g. invalid message signatures) for external intrusion detection
+    security_events: SecurityEventPublisher,
 }
 
 impl Dht {
+    #[allow(clippy::too_many_arguments)]
     pub async fn initialize(
-        config: DhtConfig,
+        mut config: DhtConfig,
         node_identity: Arc<NodeIdentity>,
         peer_manager: Arc<PeerManager>,
         outbound_tx: mpsc::Sender<DhtOutboundRequest>,
         connectivity: ConnectivityRequester,
+        outbound_queue_usage: Option<OutboundQueueUsage>,
+        shutdown_reporter: Option<ShutdownReporter>,
+        security_events: SecurityEventPublisher,
+        admin_commands: Option<AdminCommands>,
         shutdown_signal: ShutdownSignal,
     ) -> Result<Self, DhtInitializationError>
     {
+        config.validate()?;
+        config.rng = config.rng.for_instance(node_identity.node_id().as_ref());
+
         let (dht_sender, dht_receiver) = mpsc::channel(DHT_ACTOR_CHANNEL_SIZE);
         let (discovery_sender, discovery_receiver) = mpsc::channel(DHT_DISCOVERY_CHANNEL_SIZE);
         let (saf_sender, saf_receiver) = mpsc::channel(DHT_SAF_SERVICE_CHANNEL_SIZE);
         let (saf_response_signal_sender, saf_response_signal_receiver) = mpsc::channel(DHT_SAF_SERVICE_CHANNEL_SIZE);
+        let (saf_response_sender, saf_response_receiver) = mpsc::channel(DHT_SAF_SERVICE_CHANNEL_SIZE);
+        let (scheduled_send_sender, scheduled_send_receiver) = mpsc::channel(DHT_SCHEDULED_SEND_CHANNEL_SIZE);
         let (event_publisher, _) = broadcast::channel(DHT_EVENT_BROADCAST_CHANNEL_SIZE);
+        let (outbound_event_publisher, _) = broadcast::channel(OUTBOUND_EVENT_BROADCAST_CHANNEL_SIZE);
+        let outbound_audit_log = OutboundAuditLog::new(config.outbound_audit_log_capacity);
+        if outbound_audit_log.capacity() > 0 {
+            outbound_audit_log.spawn_recorder(outbound_event_publisher.subscribe());
+        }
+
+        let pipeline_error_log = PipelineErrorLog::new(config.pipeline_error_log_capacity);
+        let outbound_buffer_usage = OutboundBufferUsage::new(config.outbound_buffer_size);
+        let latency_budget_tracker =
+            LatencyBudgetTracker::new(config.latency_budget, node_identity.clone(), event_publisher.clone());
 
         let metrics_collector = MetricsCollector::spawn();
+        let metrics_snapshot_store = config
+            .metrics_snapshot_path
+            .as_ref()
+            .map(|path| MetricsSnapshotStore::open(path, config.metrics_snapshot_capacity))
+            .transpose()?;
+        let saf_store_filters = SafStoreFilters::new();
+        for filter in config.saf_store_filters.iter().cloned() {
+            saf_store_filters.add(filter);
+        }
+
+        // Advertise the storage this node offers so that peers only request stored messages from nodes that store them
+        if node_identity.has_peer_features(PeerFeatures::DHT_STORE_FORWARD) {
+            let capability = SafCapability::from_config(&config);
+            debug!(
+                target: LOG_TARGET,
+                "Advertising store and forward capability: {}", capability
+            );
+            node_identity.set_metadata(SAF_CAPABILITY_METADATA_KEY, capability.to_encoded_bytes());
+        }
 
         let dht = Self {
             node_identity,
             peer_manager,
             metrics_collector,
+            metrics_snapshot_store,
             config,
             outbound_tx,
             dht_sender,
             saf_sender,
             saf_response_signal_sender,
+            saf_response_sender,
             connectivity,
             discovery_sender,
+            scheduled_send_sender,
             event_publisher: event_publisher.clone(),
+            outbound_event_publisher,
+            outbound_audit_log,
+            pipeline_error_log,
+            latency_budget_tracker,
+            message_sequencer: MessageSequencer::new(),
+            outbound_buffer_usage,
+            outbound_queue_usage,
+            pipeline_panic_counter: PanicCounter::new(),
+            served_clients: ServedClients::new(),
+            saf_store_filters,
+            message_handlers: DhtMessageHandlers::new(),
+            shutdown_reporter,
+            security_events,
         };
 
         let conn = DbConnection::connect_and_migrate(dht.config.database_url.clone())
@@ -146,7 +284,22 @@ impl Dht {
             saf_response_signal_receiver,
         )
         .spawn();
+        dht.saf_response_sender(saf_response_receiver, shutdown_signal.clone())
+            .spawn();
+        if dht.config.max_scheduled_messages > 0 {
+            dht.scheduled_send_service(conn.clone(), scheduled_send_receiver, shutdown_signal.clone())
+                .spawn();
+        }
         dht.actor(conn, dht_receiver, shutdown_signal.clone()).spawn();
+        if let Some(interval) = dht.config.memory_usage_report_interval {
+            dht.memory_usage_reporter(interval, shutdown_signal.clone()).spawn();
+        }
+        if let Some(store) = dht.metrics_snapshot_store.clone() {
+            dht.metrics_snapshot_writer(store, shutdown_signal.clone()).spawn();
+        }
+        if let Some(commands) = admin_commands {
+            dashboard::register_admin_command(&commands, dht.dashboard_collector());
+        }
         dht.discovery_service(discovery_receiver, shutdown_signal).spawn();
 
         debug!(target: LOG_TARGET, "Dht initialization complete.");
@@ -154,6 +307,12 @@ impl Dht {
         Ok(dht)
     }
 
+    /// Returns the number of panics caught in the inbound and outbound middleware. A panic in a middleware layer is
+    /// logged and converted into an error for the message being handled, so that the pipeline can continue.
+    pub fn pipeline_panic_count(&self) -> usize {
+        self.pipeline_panic_counter.get()
+    }
+
     /// Create a DHT RPC service
     pub fn rpc_service(&self) -> rpc::DhtService<rpc::DhtRpcServiceImpl> {
         rpc::DhtService::new(rpc::DhtRpcServiceImpl::new(self.peer_manager.clone()))
@@ -191,11 +350,30 @@ impl Dht {
             Arc::clone(&self.node_identity),
             Arc::clone(&self.peer_manager),
             self.outbound_requester(),
+            self.dht_requester(),
             request_receiver,
             shutdown_signal,
         )
     }
 
+    fn scheduled_send_service(
+        &self,
+        conn: DbConnection,
+        request_rx: mpsc::Receiver<ScheduledSendRequest>,
+        shutdown_signal: ShutdownSignal,
+    ) -> ScheduledSendService
+    {
+        // The scheduler sends using a requester without a scheduler, since scheduled messages are sent immediately
+        ScheduledSendService::new(
+            ScheduledSendDatabase::new(conn),
+            OutboundMessageRequester::new(self.outbound_tx.clone())
+                .with_buffer_usage(self.outbound_buffer_usage.clone()),
+            self.config.max_scheduled_messages,
+            request_rx,
+            shutdown_signal,
+        )
+    }
+
     fn connectivity_service(&self, shutdown_signal: ShutdownSignal) -> DhtConnectivity {
         DhtConnectivity::new(
             self.config.clone(),
@@ -203,7 +381,39 @@ impl Dht {
             self.node_identity.clone(),
             self.connectivity.clone(),
             self.dht_requester(),
-            self.event_publisher.subscribe(),
+            self.store_and_forward_requester(),
+            self.event_publisher.clone(),
+            self.metrics_collector.clone(),
+            shutdown_signal,
+        )
+    }
+
+    /// Create the memory usage reporter
+    fn memory_usage_reporter(&self, interval: Duration, shutdown_signal: ShutdownSignal) -> MemoryUsageReporter {
+        MemoryUsageReporter::new(
+            interval,
+            self.dht_requester(),
+            self.store_and_forward_requester(),
+            self.peer_manager.clone(),
+            self.outbound_queue_usage.clone(),
+            self.metrics_collector.clone(),
+            shutdown_signal,
+        )
+    }
+
+    /// Create the metrics snapshot writer
+    fn metrics_snapshot_writer(
+        &self,
+        store: MetricsSnapshotStore,
+        shutdown_signal: ShutdownSignal,
+    ) -> MetricsSnapshotWriter
+    {
+        MetricsSnapshotWriter::new(
+            self.config.metrics_snapshot_interval,
+            store,
+            self.connectivity.clone(),
+            self.peer_manager.clone(),
+            self.store_and_forward_requester(),
             self.metrics_collector.clone(),
             shutdown_signal,
         )
@@ -226,11 +436,12 @@ impl Dht {
         conn: DbConnection,
         request_rx: mpsc::Receiver<StoreAndForwardRequest>,
         shutdown_signal: ShutdownSignal,
-        saf_response_signal_rx: mpsc::Receiver<()>,
+        saf_response_signal_rx: mpsc::Receiver<SafResponseSummary>,
     ) -> StoreAndForwardService
     {
-        StoreAndForwardService::new(
+        let service = StoreAndForwardService::new(
             self.config.clone(),
+            Arc::clone(&self.node_identity),
             conn,
             self.peer_manager.clone(),
             self.dht_requester(),
@@ -238,14 +449,56 @@ impl Dht {
             self.outbound_requester(),
             request_rx,
             saf_response_signal_rx,
+            self.served_clients.clone(),
+            self.saf_store_filters.clone(),
             self.event_publisher.clone(),
             shutdown_signal,
+        );
+        match self.shutdown_reporter.clone() {
+            Some(reporter) => service.with_shutdown_reporter(reporter),
+            None => service,
+        }
+    }
+
+    fn saf_response_sender(
+        &self,
+        response_rx: mpsc::Receiver<SafResponse>,
+        shutdown_signal: ShutdownSignal,
+    ) -> SafResponseSender
+    {
+        SafResponseSender::new(
+            self.config.saf_response_pacing_rate,
+            self.outbound_requester(),
+            self.store_and_forward_requester(),
+            response_rx,
+            shutdown_signal,
         )
     }
 
+    fn forward_layer(&self) -> store_forward::ForwardLayer {
+        let layer = store_forward::ForwardLayer::new(
+            self.outbound_requester(),
+            self.node_identity.features().contains(PeerFeatures::DHT_STORE_FORWARD),
+        )
+        .with_max_amplification(self.config.forward_max_amplification)
+        .with_metrics_collector(self.metrics_collector.clone());
+
+        match self.config.forward_bandwidth_budget {
+            Some(bytes_per_sec) => layer.with_bandwidth_budget(bytes_per_sec),
+            None => layer,
+        }
+    }
+
     /// Return a new OutboundMessageRequester connected to the receiver
     pub fn outbound_requester(&self) -> OutboundMessageRequester {
-        OutboundMessageRequester::new(self.outbound_tx.clone())
+        let requester = OutboundMessageRequester::new(self.outbound_tx.clone())
+            .with_sequencer(self.message_sequencer.clone())
+            .with_buffer_usage(self.outbound_buffer_usage.clone());
+        if self.config.max_scheduled_messages > 0 {
+            requester.with_scheduler(ScheduledSendRequester::new(self.scheduled_send_sender.clone()))
+        } else {
+            requester
+        }
     }
 
     /// Returns a requester for the DhtActor associated with this instance
@@ -268,10 +521,57 @@ impl Dht {
         self.event_publisher.subscribe()
     }
 
+    /// Returns a subscription to `OutboundEvent`s, which are published once the send result of each outbound message
+    /// is known
+    pub fn subscribe_outbound_events(&self) -> OutboundEventReceiver {
+        self.outbound_event_publisher.subscribe()
+    }
+
+    /// Returns the rolling log of recent outbound messages and their send results. The log is empty if
+    /// `DhtConfig::outbound_audit_log_capacity` is zero.
+    pub fn outbound_audit_log(&self) -> OutboundAuditLog {
+        self.outbound_audit_log.clone()
+    }
+
+    /// Returns the usage of the outbound message buffer, which is shared by all outbound requesters created by this
+    /// instance
+    pub fn outbound_buffer_usage(&self) -> OutboundBufferUsage {
+        self.outbound_buffer_usage.clone()
+    }
+
+    /// Returns the rolling log of inbound messages that failed in the pipeline. The log is empty if
+    /// `DhtConfig::pipeline_error_log_capacity` is zero.
+    pub fn pipeline_error_log(&self) -> PipelineErrorLog {
+        self.pipeline_error_log.clone()
+    }
+
+    /// Returns the registry of handlers for extension DHT message types. Handlers may be registered or removed at any
+    /// time and apply to every inbound middleware stack built from this instance.
+    pub fn message_handlers(&self) -> DhtMessageHandlers {
+        self.message_handlers.clone()
+    }
+
     pub fn metrics_collector(&self) -> MetricsCollectorHandle {
         self.metrics_collector.clone()
     }
 
+    /// Returns a collector of the DHT state shown in node dashboards
+    pub fn dashboard_collector(&self) -> DashboardCollector {
+        DashboardCollector::new(
+            self.config.num_neighbouring_nodes,
+            Arc::clone(&self.node_identity),
+            Arc::clone(&self.peer_manager),
+            self.connectivity.clone(),
+            self.dht_requester(),
+            self.store_and_forward_requester(),
+        )
+    }
+
+    /// Returns the store of periodic metrics snapshots, or None if `DhtConfig::metrics_snapshot_path` is not set
+    pub fn metrics_snapshots(&self) -> Option<MetricsSnapshotStore> {
+        self.metrics_snapshot_store.clone()
+    }
+
     /// Returns an the full DHT stack as a `tower::layer::Layer`. This can be composed with
     /// other inbound middleware services which expect an DecryptedDhtMessage
     pub fn inbound_middleware_layer<S>(
@@ -293,30 +593,49 @@ impl Dht {
         // FIXME: There is an unresolved stack overflow issue on windows in debug mode during runtime, but not in
         //        release mode, related to the amount of layers. (issue #1416)
         ServiceBuilder::new()
+            .layer(CatchPanicLayer::new("Inbound", self.pipeline_panic_counter.clone()))
             .layer(MetricsLayer::new(self.metrics_collector.clone()))
-            .layer(inbound::DeserializeLayer::new(self.peer_manager.clone()))
+            .layer(self.latency_budget_tracker.layer(PipelineStage::Deserialize))
+            .layer(inbound::DeserializeLayer::new(
+                self.node_identity.clone(),
+                self.peer_manager.clone(),
+                self.metrics_collector.clone(),
+            ))
+            .layer(inbound::PipelineDiagnosticsLayer::new(self.pipeline_error_log.clone()))
             .layer(inbound::ValidateLayer::new(self.config.network))
             .layer(DedupLayer::new(self.dht_requester()))
             .layer(tower_filter::FilterLayer::new(self.unsupported_saf_messages_filter()))
+            .layer(inbound::ControlChannelLayer::new(
+                self.config.control_message_buffer_size,
+                self.config.control_message_max_concurrent_tasks,
+                self.config.control_message_rate_limit_capacity,
+                self.config.control_message_rate_limit_restock_interval,
+            ))
             .layer(MessageLoggingLayer::new(format!(
                 "Inbound [{}]",
                 self.node_identity.node_id().short_str()
             )))
-            .layer(inbound::DecryptionLayer::new(
-                self.config.clone(),
-                self.node_identity.clone(),
-                self.connectivity.clone(),
-            ))
+            .layer(self.latency_budget_tracker.layer(PipelineStage::Decrypt))
+            .layer(
+                inbound::DecryptionLayer::new(
+                    self.config.clone(),
+                    self.node_identity.clone(),
+                    self.connectivity.clone(),
+                )
+                .with_security_events(self.security_events.clone()),
+            )
+            .layer(self.latency_budget_tracker.layer(PipelineStage::StoreAndForward))
             .layer(store_forward::StoreLayer::new(
                 self.config.clone(),
                 Arc::clone(&self.peer_manager),
                 Arc::clone(&self.node_identity),
                 self.store_and_forward_requester(),
+                self.served_clients.clone(),
+                self.saf_store_filters.clone(),
             ))
-            .layer(store_forward::ForwardLayer::new(
-                self.outbound_requester(),
-                self.node_identity.features().contains(PeerFeatures::DHT_STORE_FORWARD),
-            ))
+            .layer(self.latency_budget_tracker.layer(PipelineStage::Forward))
+            .layer(self.forward_layer())
+            .layer(self.latency_budget_tracker.layer(PipelineStage::Handlers))
             .layer(store_forward::MessageHandlerLayer::new(
                 self.config.clone(),
                 self.store_and_forward_requester(),
@@ -325,12 +644,23 @@ impl Dht {
                 Arc::clone(&self.peer_manager),
                 self.outbound_requester(),
                 self.saf_response_signal_sender.clone(),
+                self.saf_response_sender.clone(),
             ))
             .layer(inbound::DhtHandlerLayer::new(
+                self.config.clone(),
                 Arc::clone(&self.node_identity),
                 Arc::clone(&self.peer_manager),
                 self.discovery_service_requester(),
                 self.outbound_requester(),
+                self.message_handlers.clone(),
+                self.event_publisher.clone(),
+            ))
+            .layer(inbound::SequencingLayer::new(
+                self.config.sequenced_message_types.clone(),
+                self.config.sequence_reorder_timeout,
+                self.config.sequence_max_buffered,
+                self.config.sequence_max_origins,
+                self.config.sequence_max_total_buffered,
             ))
             .into_inner()
     }
@@ -354,30 +684,38 @@ impl Dht {
         S::Future: Send,
     {
         ServiceBuilder::new()
-            .layer(outbound::BroadcastLayer::new(
-                Arc::clone(&self.node_identity),
-                self.dht_requester(),
-                self.discovery_service_requester(),
-                self.config.network,
-                chrono::Duration::from_std(self.config.saf_msg_validity).unwrap(),
-            ))
+            .layer(CatchPanicLayer::new("Outbound", self.pipeline_panic_counter.clone()))
+            .layer(
+                outbound::BroadcastLayer::new(
+                    Arc::clone(&self.node_identity),
+                    self.dht_requester(),
+                    self.discovery_service_requester(),
+                    outbound::WireFormatSelector::new(self.peer_manager.clone(), self.connectivity.clone()),
+                    self.config.network,
+                    chrono::Duration::from_std(self.config.saf_msg_validity).unwrap(),
+                )
+                .with_duplicate_window(self.config.outbound_dedup_window)
+                .with_event_publisher(self.outbound_event_publisher.clone()),
+            )
             .layer(MessageLoggingLayer::new(format!(
                 "Outbound [{}]",
                 self.node_identity.node_id().short_str()
             )))
-            .layer(outbound::SerializeLayer)
+            .layer(outbound::SerializeLayer::new(self.metrics_collector.clone()))
             .into_inner()
     }
 
     /// Produces a filter predicate which disallows store and forward messages if that feature is not
-    /// supported by the node.
+    /// supported by the node. If `DhtConfig::saf_send_rejections` is set, requests are allowed through so that they can
+    /// be rejected.
     fn unsupported_saf_messages_filter(
         &self,
     ) -> impl tower_filter::Predicate<DhtInboundMessage, Future = future::Ready<Result<(), PipelineError>>> + Clone + Send
     {
         let node_identity = Arc::clone(&self.node_identity);
+        let send_rejections = self.config.saf_send_rejections;
         move |msg: &DhtInboundMessage| {
-            if node_identity.has_peer_features(PeerFeatures::DHT_STORE_FORWARD) {
+            if send_rejections || node_identity.has_peer_features(PeerFeatures::DHT_STORE_FORWARD) {
                 return future::ready(Ok(()));
             }
 
@@ -402,6 +740,7 @@ impl Dht {
 #[cfg(test)]
 mod test {
     use crate::{
+        config::DhtConfig,
         crypt,
         envelope::DhtMessageFlags,
         outbound::mock::create_outbound_service_mock,
@@ -414,6 +753,7 @@ mod test {
             make_node_identity,
         },
         DhtBuilder,
+        DhtInitializationError,
     };
     use futures::{channel::mpsc, StreamExt};
     use std::{sync::Arc, time::Duration};
@@ -424,9 +764,34 @@ mod test {
         wrap_in_envelope_body,
     };
     use tari_shutdown::Shutdown;
+    use tari_test_utils::unpack_enum;
     use tokio::{task, time};
     use tower::{layer::Layer, Service};
 
+    #[tokio_macros::test_basic]
+    async fn build_rejects_invalid_config() {
+        let node_identity = make_node_identity();
+        let (connectivity, _) = create_connectivity_mock();
+        let (out_tx, _) = mpsc::channel(10);
+        let shutdown = Shutdown::new();
+        let err = DhtBuilder::new(
+            node_identity,
+            build_peer_manager(),
+            out_tx,
+            connectivity,
+            shutdown.to_signal(),
+        )
+        .with_config(DhtConfig {
+            outbound_buffer_size: 0,
+            ..DhtConfig::default_local_test()
+        })
+        .build()
+        .await
+        .err()
+        .expect("invalid config was accepted");
+        unpack_enum!(DhtInitializationError::InvalidConfiguration(_err) = err);
+    }
+
     #[tokio_macros::test_basic]
     async fn stack_unencrypted() {
         let node_identity = make_node_identity();
@@ -626,7 +991,9 @@ mod test {
         });
         let inbound_message = make_comms_inbound_message(&node_identity, dht_envelope.to_encoded_bytes().into());
 
-        service.call(inbound_message).await.unwrap_err();
+        // SAF messages are handled on the control channel, so the error is not returned to the caller
+        service.call(inbound_message).await.unwrap();
+        time::delay_for(Duration::from_millis(100)).await;
         // This seems like the best way to tell that an open channel is empty without the test blocking indefinitely
         assert_eq!(
             format!("{}", next_service_rx.try_next().unwrap_err()),
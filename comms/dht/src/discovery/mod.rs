@@ -23,7 +23,13 @@
 mod error;
 mod requester;
 mod service;
+mod stats;
 
 pub(crate) use self::requester::DhtDiscoveryRequest;
 
-pub use self::{error::DhtDiscoveryError, requester::DhtDiscoveryRequester, service::DhtDiscoveryService};
+pub use self::{
+    error::DhtDiscoveryError,
+    requester::DhtDiscoveryRequester,
+    service::DhtDiscoveryService,
+    stats::DiscoveryStats,
+};
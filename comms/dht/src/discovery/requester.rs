@@ -20,7 +20,11 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::{discovery::DhtDiscoveryError, envelope::NodeDestination, proto::dht::DiscoveryResponseMessage};
+use crate::{
+    discovery::DhtDiscoveryError,
+    envelope::NodeDestination,
+    proto::dht::{DiscoveryHintMessage, DiscoveryResponseMessage},
+};
 use futures::{
     channel::{mpsc, oneshot},
     SinkExt,
@@ -29,7 +33,10 @@ use std::{
     fmt::{Display, Error, Formatter},
     time::Duration,
 };
-use tari_comms::{peer_manager::Peer, types::CommsPublicKey};
+use tari_comms::{
+    peer_manager::{NodeId, Peer},
+    types::CommsPublicKey,
+};
 use tokio::time;
 
 #[derive(Debug)]
@@ -40,6 +47,8 @@ pub enum DhtDiscoveryRequest {
         oneshot::Sender<Result<Peer, DhtDiscoveryError>>,
     ),
     NotifyDiscoveryResponseReceived(Box<DiscoveryResponseMessage>),
+    NotifyDiscoveryHintReceived(Box<DiscoveryHintMessage>),
+    GetStoredMessageProviders(Box<CommsPublicKey>, oneshot::Sender<Vec<NodeId>>),
 }
 
 impl Display for DhtDiscoveryRequest {
@@ -50,6 +59,8 @@ impl Display for DhtDiscoveryRequest {
             NotifyDiscoveryResponseReceived(discovery_resp) => {
                 write!(f, "NotifyDiscoveryResponseReceived({:#?})", discovery_resp)
             },
+            NotifyDiscoveryHintReceived(hint) => write!(f, "NotifyDiscoveryHintReceived({:#?})", hint),
+            GetStoredMessageProviders(public_key, _) => write!(f, "GetStoredMessageProviders({})", public_key),
         }
     }
 }
@@ -120,4 +131,33 @@ impl DhtDiscoveryRequester {
 
         Ok(())
     }
+
+    pub async fn notify_discovery_hint_received(
+        &mut self,
+        hint: DiscoveryHintMessage,
+    ) -> Result<(), DhtDiscoveryError>
+    {
+        self.sender
+            .send(DhtDiscoveryRequest::NotifyDiscoveryHintReceived(Box::new(hint)))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns the node ids of store and forward nodes that have hinted that they are holding messages for the given
+    /// public key. Subsequent messages for the peer may be routed to these nodes.
+    pub async fn get_stored_message_providers(
+        &mut self,
+        public_key: CommsPublicKey,
+    ) -> Result<Vec<NodeId>, DhtDiscoveryError>
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(DhtDiscoveryRequest::GetStoredMessageProviders(
+                Box::new(public_key),
+                reply_tx,
+            ))
+            .await?;
+        reply_rx.await.map_err(|_| DhtDiscoveryError::ReplyCanceled)
+    }
 }
@@ -21,10 +21,11 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::{
+    actor::DhtRequester,
     discovery::{requester::DhtDiscoveryRequest, DhtDiscoveryError},
     envelope::{DhtMessageType, NodeDestination},
     outbound::{OutboundEncryption, OutboundMessageRequester, SendMessageParams},
-    proto::dht::{DiscoveryMessage, DiscoveryResponseMessage},
+    proto::dht::{DiscoveryHintMessage, DiscoveryMessage, DiscoveryResponseMessage},
     DhtConfig,
 };
 use futures::{
@@ -50,6 +51,10 @@ use tari_utilities::{hex::Hex, ByteArray};
 use tokio::{task, time};
 
 const LOG_TARGET: &str = "comms::dht::discovery_service";
+/// The maximum number of peers for which stored message provider hints are kept
+const MAX_STORED_MESSAGE_HINT_PEERS: usize = 1000;
+/// The maximum number of stored message providers kept for each peer
+const MAX_STORED_MESSAGE_PROVIDERS_PER_PEER: usize = 5;
 
 struct DiscoveryRequestState {
     reply_tx: oneshot::Sender<Result<Peer, DhtDiscoveryError>>,
@@ -71,10 +76,12 @@ pub struct DhtDiscoveryService {
     config: DhtConfig,
     node_identity: Arc<NodeIdentity>,
     outbound_requester: OutboundMessageRequester,
+    dht_requester: DhtRequester,
     peer_manager: Arc<PeerManager>,
     request_rx: Option<mpsc::Receiver<DhtDiscoveryRequest>>,
     shutdown_signal: Option<ShutdownSignal>,
     inflight_discoveries: HashMap<u64, DiscoveryRequestState>,
+    stored_message_providers: HashMap<CommsPublicKey, Vec<NodeId>>,
 }
 
 impl DhtDiscoveryService {
@@ -83,6 +90,7 @@ impl DhtDiscoveryService {
         node_identity: Arc<NodeIdentity>,
         peer_manager: Arc<PeerManager>,
         outbound_requester: OutboundMessageRequester,
+        dht_requester: DhtRequester,
         request_rx: mpsc::Receiver<DhtDiscoveryRequest>,
         shutdown_signal: ShutdownSignal,
     ) -> Self
@@ -90,11 +98,13 @@ impl DhtDiscoveryService {
         Self {
             config,
             outbound_requester,
+            dht_requester,
             node_identity,
             peer_manager,
             shutdown_signal: Some(shutdown_signal),
             request_rx: Some(request_rx),
             inflight_discoveries: HashMap::new(),
+            stored_message_providers: HashMap::new(),
         }
     }
 
@@ -146,6 +156,64 @@ impl DhtDiscoveryService {
             },
 
             NotifyDiscoveryResponseReceived(discovery_msg) => self.handle_discovery_response(discovery_msg).await,
+            NotifyDiscoveryHintReceived(hint) => self.handle_discovery_hint(*hint),
+            GetStoredMessageProviders(public_key, reply_tx) => {
+                let providers = self
+                    .stored_message_providers
+                    .get(&*public_key)
+                    .cloned()
+                    .unwrap_or_default();
+                let _ = reply_tx.send(providers);
+            },
+        }
+    }
+
+    fn handle_discovery_hint(&mut self, hint: DiscoveryHintMessage) {
+        let public_key = match CommsPublicKey::from_bytes(&hint.public_key) {
+            Ok(pk) => pk,
+            Err(err) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Discarding discovery hint with invalid public key: {}", err
+                );
+                return;
+            },
+        };
+        let node_ids = hint
+            .saf_node_ids
+            .iter()
+            .filter_map(|node_id| NodeId::from_bytes(node_id).ok())
+            .take(MAX_STORED_MESSAGE_PROVIDERS_PER_PEER)
+            .collect::<Vec<_>>();
+        if node_ids.is_empty() {
+            return;
+        }
+
+        debug!(
+            target: LOG_TARGET,
+            "Received hint that {} node(s) are holding stored messages for peer '{}'",
+            node_ids.len(),
+            public_key
+        );
+
+        if !self.stored_message_providers.contains_key(&public_key) &&
+            self.stored_message_providers.len() >= MAX_STORED_MESSAGE_HINT_PEERS
+        {
+            // Make room by discarding an arbitrary entry
+            if let Some(key) = self.stored_message_providers.keys().next().cloned() {
+                self.stored_message_providers.remove(&key);
+            }
+        }
+
+        let providers = self.stored_message_providers.entry(public_key).or_insert_with(Vec::new);
+        for node_id in node_ids {
+            if !providers.contains(&node_id) {
+                providers.push(node_id);
+            }
+        }
+        if providers.len() > MAX_STORED_MESSAGE_PROVIDERS_PER_PEER {
+            let excess = providers.len() - MAX_STORED_MESSAGE_PROVIDERS_PER_PEER;
+            providers.drain(..excess);
         }
     }
 
@@ -199,6 +267,9 @@ impl DhtDiscoveryService {
                             (Instant::now() - start_ts).as_secs_f32()
                         );
 
+                        // The peer is online, so the stored message hints are no longer needed
+                        self.stored_message_providers.remove(&*public_key);
+
                         for request in self.collect_all_discovery_requests(&public_key) {
                             if !reply_tx.is_canceled() {
                                 let _ = request.reply_tx.send(Ok(peer.clone()));
@@ -210,6 +281,7 @@ impl DhtDiscoveryService {
                             "Discovery request for Node Id {} completed successfully",
                             peer.node_id.to_hex(),
                         );
+                        self.record_discovery_result(true).await;
                     },
                     Err(err) => {
                         debug!(
@@ -219,6 +291,7 @@ impl DhtDiscoveryService {
                             err,
                             (Instant::now() - start_ts).as_secs_f32()
                         );
+                        self.record_discovery_result(false).await;
                     },
                 }
 
@@ -294,6 +367,7 @@ impl DhtDiscoveryService {
         let nonce = OsRng.next_u64();
         if let Err(err) = self.send_discover(nonce, destination, dest_pubkey.clone()).await {
             let _ = reply_tx.send(Err(err));
+            self.record_discovery_result(false).await;
             return Ok(());
         }
 
@@ -306,11 +380,12 @@ impl DhtDiscoveryService {
             .filter(|(_, state)| !state.reply_tx.is_canceled())
             .collect();
 
-        trace!(
-            target: LOG_TARGET,
-            "{} inflight request(s) cleared",
-            inflight_count - self.inflight_discoveries.len()
-        );
+        let num_cleared = inflight_count - self.inflight_discoveries.len();
+        trace!(target: LOG_TARGET, "{} inflight request(s) cleared", num_cleared);
+        // Cancelled requests did not receive a response in time
+        for _ in 0..num_cleared {
+            self.record_discovery_result(false).await;
+        }
 
         // Add the new inflight request.
         self.inflight_discoveries
@@ -325,6 +400,12 @@ impl DhtDiscoveryService {
         Ok(())
     }
 
+    async fn record_discovery_result(&mut self, succeeded: bool) {
+        if let Err(err) = self.dht_requester.record_discovery_result(succeeded).await {
+            debug!(target: LOG_TARGET, "Failed to record discovery result: {}", err);
+        }
+    }
+
     async fn send_discover(
         &mut self,
         nonce: u64,
@@ -334,7 +415,12 @@ impl DhtDiscoveryService {
     {
         let discover_msg = DiscoveryMessage {
             node_id: self.node_identity.node_id().to_vec(),
-            addresses: vec![self.node_identity.public_address().to_string()],
+            addresses: self
+                .node_identity
+                .public_addresses()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
             peer_features: self.node_identity.features().bits(),
             nonce,
         };
@@ -395,7 +481,7 @@ mod test {
     use crate::{
         discovery::DhtDiscoveryRequester,
         outbound::mock::create_outbound_service_mock,
-        test_utils::{build_peer_manager, make_node_identity},
+        test_utils::{build_peer_manager, create_dht_actor_mock, make_node_identity},
     };
     use std::time::Duration;
     use tari_shutdown::Shutdown;
@@ -407,6 +493,8 @@ mod test {
         let (outbound_requester, outbound_mock) = create_outbound_service_mock(10);
         let oms_mock_state = outbound_mock.get_state();
         task::spawn(outbound_mock.run());
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        task::spawn(dht_mock.run());
 
         let (sender, receiver) = mpsc::channel(10);
         // Requester which timeout instantly
@@ -418,6 +506,7 @@ mod test {
             node_identity,
             peer_manager,
             outbound_requester,
+            dht_requester,
             receiver,
             shutdown.to_signal(),
         )
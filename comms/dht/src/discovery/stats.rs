@@ -0,0 +1,55 @@
+// Copyright 2019, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Discovery success history. These stats are persisted by the DHT actor and survive restarts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveryStats {
+    /// The number of discoveries that resulted in a valid discovery response
+    pub num_succeeded: u64,
+    /// The number of discoveries that timed out or received an invalid discovery response
+    pub num_failed: u64,
+    /// The time of the last successful discovery
+    pub last_succeeded_at: Option<DateTime<Utc>>,
+}
+
+impl DiscoveryStats {
+    pub fn record(&mut self, succeeded: bool) {
+        if succeeded {
+            self.num_succeeded += 1;
+            self.last_succeeded_at = Some(Utc::now());
+        } else {
+            self.num_failed += 1;
+        }
+    }
+
+    /// Returns the proportion of completed discoveries that succeeded, or None if no discoveries have completed
+    pub fn success_ratio(&self) -> Option<f64> {
+        let total = self.num_succeeded + self.num_failed;
+        if total == 0 {
+            return None;
+        }
+        Some(self.num_succeeded as f64 / total as f64)
+    }
+}
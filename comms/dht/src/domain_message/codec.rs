@@ -0,0 +1,155 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::ContentType;
+use serde::{de::DeserializeOwned, Serialize};
+use std::marker::PhantomData;
+use tari_comms::message::MessageExt;
+use tari_utilities::message_format::{MessageFormat, MessageFormatError};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DomainCodecError {
+    #[error("Failed to decode protobuf message: {0}")]
+    ProtobufDecodeError(#[from] prost::DecodeError),
+    #[error("MessageFormatError: {0}")]
+    MessageFormatError(#[from] MessageFormatError),
+    #[error("Message body is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("Unsupported content type {0}")]
+    UnsupportedContentType(i32),
+    #[error("Expected content type {expected:?} but the message has content type {actual:?}")]
+    ContentTypeMismatch { expected: ContentType, actual: ContentType },
+}
+
+/// Encodes and decodes domain message bodies of type `T`. The content type returned by the codec is signalled in the
+/// `MessageHeader` so that the receiver can select the matching codec. Embedders may implement this trait to use a
+/// serialisation format not provided by this crate.
+pub trait DomainMessageCodec<T> {
+    /// The content type signalled in the header of messages encoded by this codec
+    fn content_type(&self) -> ContentType;
+
+    fn encode(&self, message: &T) -> Result<Vec<u8>, DomainCodecError>;
+
+    fn decode(&self, bytes: &[u8]) -> Result<T, DomainCodecError>;
+}
+
+/// Protocol buffers codec. This is the codec used for all messages that do not specify a content type.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProtobufCodec;
+
+impl<T> DomainMessageCodec<T> for ProtobufCodec
+where T: prost::Message + Default
+{
+    fn content_type(&self) -> ContentType {
+        ContentType::Protobuf
+    }
+
+    fn encode(&self, message: &T) -> Result<Vec<u8>, DomainCodecError> {
+        Ok(message.to_encoded_bytes())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<T, DomainCodecError> {
+        T::decode(bytes).map_err(Into::into)
+    }
+}
+
+/// JSON codec for any serde serializable type
+#[derive(Debug, Clone, Copy)]
+pub struct JsonCodec<T>(PhantomData<T>);
+
+impl<T> JsonCodec<T> {
+    pub fn new() -> Self {
+        JsonCodec(PhantomData)
+    }
+}
+
+impl<T> Default for JsonCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> DomainMessageCodec<T> for JsonCodec<T>
+where T: Serialize + DeserializeOwned
+{
+    fn content_type(&self) -> ContentType {
+        ContentType::Json
+    }
+
+    fn encode(&self, message: &T) -> Result<Vec<u8>, DomainCodecError> {
+        Ok(message.to_json()?.into_bytes())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<T, DomainCodecError> {
+        let json = std::str::from_utf8(bytes).map_err(|_| DomainCodecError::InvalidUtf8)?;
+        T::from_json(json).map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::proto::dht::JoinMessage;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct TestMessage {
+        name: String,
+        value: u64,
+    }
+
+    #[test]
+    fn protobuf_codec() {
+        let msg = JoinMessage {
+            node_id: vec![1, 2, 3],
+            addresses: vec!["/ip4/127.0.0.1/tcp/9000".to_string()],
+            peer_features: 1,
+            nonce: 123,
+            metadata: vec![],
+        };
+        let bytes = DomainMessageCodec::<JoinMessage>::encode(&ProtobufCodec, &msg).unwrap();
+        let decoded: JoinMessage = ProtobufCodec.decode(&bytes).unwrap();
+        assert_eq!(decoded, msg);
+        assert_eq!(
+            DomainMessageCodec::<JoinMessage>::content_type(&ProtobufCodec),
+            ContentType::Protobuf
+        );
+    }
+
+    #[test]
+    fn json_codec() {
+        let codec = JsonCodec::new();
+        let msg = TestMessage {
+            name: "tari".to_string(),
+            value: 42,
+        };
+        let bytes = codec.encode(&msg).unwrap();
+        assert!(std::str::from_utf8(&bytes).unwrap().contains("\"tari\""));
+        let decoded = codec.decode(&bytes).unwrap();
+        assert_eq!(decoded, msg);
+        assert_eq!(codec.content_type(), ContentType::Json);
+
+        let err = codec.decode(&[0xff, 0xfe]).unwrap_err();
+        assert!(matches!(err, DomainCodecError::InvalidUtf8));
+    }
+}
@@ -20,6 +20,9 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+mod codec;
+pub use codec::{DomainCodecError, DomainMessageCodec, JsonCodec, ProtobufCodec};
+
 use rand::{rngs::OsRng, RngCore};
 use std::cmp;
 
@@ -47,6 +50,10 @@ impl<T> OutboundDomainMessage<T> {
         }
     }
 
+    pub fn message_type(&self) -> i32 {
+        self.message_type
+    }
+
     pub fn into_inner(self) -> T {
         self.inner
     }
@@ -60,7 +67,7 @@ impl<T> OutboundDomainMessage<T> {
     }
 }
 
-pub use crate::proto::message_header::MessageHeader;
+pub use crate::proto::message_header::{ContentType, MessageHeader};
 
 impl MessageHeader {
     pub fn new(message_type: i32) -> Self {
@@ -69,6 +76,7 @@ impl MessageHeader {
             // In the unimaginably unlikely case that a nonce of 0 chosen,
             // change it to 1 because 0 is exclusively for message propagation
             nonce: cmp::max(1, OsRng.next_u64()),
+            content_type: ContentType::Protobuf as i32,
         }
     }
 
@@ -77,6 +85,13 @@ impl MessageHeader {
         Self {
             message_type,
             nonce: PROPAGATION_NONCE,
+            content_type: ContentType::Protobuf as i32,
         }
     }
+
+    /// Sets the content type of the message body described by this header
+    pub fn with_content_type(mut self, content_type: ContentType) -> Self {
+        self.content_type = content_type as i32;
+        self
+    }
 }
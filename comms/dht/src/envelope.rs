@@ -20,6 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use crate::codec;
 use bitflags::bitflags;
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
@@ -30,11 +31,18 @@ use std::{
     fmt::Display,
 };
 use tari_comms::{message::MessageTag, peer_manager::NodeId, types::CommsPublicKey, NodeIdentity};
-use tari_utilities::{ByteArray, ByteArrayError};
+use tari_utilities::ByteArray;
 use thiserror::Error;
 
 // Re-export applicable protos
-pub use crate::proto::envelope::{dht_header::Destination, DhtEnvelope, DhtHeader, DhtMessageType, Network};
+pub use crate::proto::envelope::{
+    dht_header::Destination,
+    DestinationNodeIds,
+    DhtEnvelope,
+    DhtHeader,
+    DhtMessageType,
+    Network,
+};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use prost_types::Timestamp;
 use tari_utilities::epoch_time::EpochTime;
@@ -63,6 +71,9 @@ pub(crate) fn epochtime_to_datetime(datetime: EpochTime) -> DateTime<Utc> {
     DateTime::from(datetime)
 }
 
+/// The maximum number of node ids that a `NodeDestination::NodeIds` destination may contain
+pub const MAX_MULTICAST_DESTINATIONS: usize = 8;
+
 #[derive(Debug, Error)]
 pub enum DhtMessageError {
     #[error("Invalid node destination")]
@@ -106,6 +117,11 @@ impl DhtMessageFlags {
 }
 
 impl DhtMessageType {
+    /// Returns true if this is a domain (non-DHT) message
+    pub fn is_domain_message(self) -> bool {
+        matches!(self, DhtMessageType::None)
+    }
+
     pub fn is_dht_message(self) -> bool {
         self.is_dht_discovery() || self.is_dht_join()
     }
@@ -120,7 +136,7 @@ impl DhtMessageType {
 
     pub fn is_saf_message(self) -> bool {
         use DhtMessageType::*;
-        matches!(self, SafRequestMessages | SafStoredMessages)
+        matches!(self, SafRequestMessages | SafStoredMessages | SafRejection)
     }
 }
 
@@ -139,6 +155,10 @@ pub struct DhtMessageHeader {
     pub flags: DhtMessageFlags,
     pub message_tag: MessageTag,
     pub expires: Option<EpochTime>,
+    /// Mailbox tag for messages with an undisclosed destination. Empty if not set.
+    pub mailbox_tag: Vec<u8>,
+    /// Per-origin sequence number for the domain message type. 0 if the message is not sequenced.
+    pub sequence: u64,
 }
 
 impl DhtMessageHeader {
@@ -194,6 +214,8 @@ impl TryFrom<DhtHeader> for DhtMessageHeader {
             flags: DhtMessageFlags::from_bits(header.flags).ok_or_else(|| DhtMessageError::InvalidMessageFlags)?,
             message_tag: MessageTag::from(header.message_tag),
             expires: expires.map(datetime_to_epochtime),
+            mailbox_tag: header.mailbox_tag,
+            sequence: header.sequence,
         })
     }
 }
@@ -226,6 +248,8 @@ impl From<DhtMessageHeader> for DhtHeader {
             flags: header.flags.bits(),
             message_tag: header.message_tag.as_value(),
             expires: expires.map(datetime_to_timestamp),
+            mailbox_tag: header.mailbox_tag,
+            sequence: header.sequence,
         }
     }
 }
@@ -234,6 +258,7 @@ impl DhtEnvelope {
     pub fn new(header: DhtHeader, body: Bytes) -> Self {
         Self {
             header: Some(header),
+            body_checksum: codec::body_checksum(&body),
             body: body.to_vec(),
         }
     }
@@ -249,6 +274,8 @@ pub enum NodeDestination {
     PublicKey(Box<CommsPublicKey>),
     /// Destined for a particular node id, or network region
     NodeId(Box<NodeId>),
+    /// Destined for a small set of node ids (at most `MAX_MULTICAST_DESTINATIONS`)
+    NodeIds(Vec<NodeId>),
 }
 
 impl NodeDestination {
@@ -258,6 +285,7 @@ impl NodeDestination {
             Unknown => Vec::default(),
             PublicKey(pk) => pk.to_vec(),
             NodeId(node_id) => node_id.to_vec(),
+            NodeIds(node_ids) => node_ids.iter().flat_map(|node_id| node_id.to_vec()).collect(),
         }
     }
 
@@ -267,6 +295,7 @@ impl NodeDestination {
             Unknown => None,
             PublicKey(pk) => Some(pk),
             NodeId(_) => None,
+            NodeIds(_) => None,
         }
     }
 
@@ -276,6 +305,24 @@ impl NodeDestination {
             Unknown => None,
             PublicKey(_) => None,
             NodeId(node_id) => Some(node_id),
+            NodeIds(_) => None,
+        }
+    }
+
+    /// Returns the node ids of a multicast destination
+    pub fn node_ids(&self) -> Option<&[NodeId]> {
+        match self {
+            NodeDestination::NodeIds(node_ids) => Some(node_ids),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the given node identity is the destination or, for a multicast destination, one of the
+    /// destinations
+    pub fn includes_node_identity(&self, node_identity: &NodeIdentity) -> bool {
+        match self {
+            NodeDestination::NodeIds(node_ids) => node_ids.iter().any(|n| n == node_identity.node_id()),
+            _ => self.equals_node_identity(node_identity),
         }
     }
 
@@ -285,6 +332,17 @@ impl NodeDestination {
             .or_else(|| self.public_key().map(NodeId::from_public_key))
     }
 
+    /// Create a multicast destination for the given node ids. Duplicate node ids are removed. An error is returned if
+    /// no node ids, or more than `MAX_MULTICAST_DESTINATIONS` node ids are given.
+    pub fn multicast(mut node_ids: Vec<NodeId>) -> Result<Self, DhtMessageError> {
+        node_ids.sort();
+        node_ids.dedup();
+        if node_ids.is_empty() || node_ids.len() > MAX_MULTICAST_DESTINATIONS {
+            return Err(DhtMessageError::InvalidDestination);
+        }
+        Ok(NodeDestination::NodeIds(node_ids))
+    }
+
     pub fn is_unknown(&self) -> bool {
         matches!(self, NodeDestination::Unknown)
     }
@@ -325,6 +383,11 @@ impl Display for NodeDestination {
             NodeDestination::Unknown => write!(f, "Unknown"),
             NodeDestination::NodeId(node_id) => write!(f, "NodeId({})", node_id),
             NodeDestination::PublicKey(public_key) => write!(f, "PublicKey({})", public_key),
+            NodeDestination::NodeIds(node_ids) => write!(
+                f,
+                "NodeIds({})",
+                node_ids.iter().map(|n| n.short_str()).collect::<Vec<_>>().join(", ")
+            ),
         }
     }
 }
@@ -336,16 +399,27 @@ impl Default for NodeDestination {
 }
 
 impl TryFrom<Destination> for NodeDestination {
-    type Error = ByteArrayError;
+    type Error = DhtMessageError;
 
     fn try_from(destination: Destination) -> Result<Self, Self::Error> {
         match destination {
             Destination::Unknown(_) => Ok(NodeDestination::Unknown),
-            Destination::PublicKey(pk) => {
-                CommsPublicKey::from_bytes(&pk).map(|pk| NodeDestination::PublicKey(Box::new(pk)))
-            },
-            Destination::NodeId(node_id) => {
-                NodeId::from_bytes(&node_id).map(|node_id| NodeDestination::NodeId(Box::new(node_id)))
+            Destination::PublicKey(pk) => CommsPublicKey::from_bytes(&pk)
+                .map(|pk| NodeDestination::PublicKey(Box::new(pk)))
+                .map_err(|_| DhtMessageError::InvalidDestination),
+            Destination::NodeId(node_id) => NodeId::from_bytes(&node_id)
+                .map(|node_id| NodeDestination::NodeId(Box::new(node_id)))
+                .map_err(|_| DhtMessageError::InvalidDestination),
+            Destination::NodeIds(DestinationNodeIds { node_ids }) => {
+                if node_ids.len() > MAX_MULTICAST_DESTINATIONS {
+                    return Err(DhtMessageError::InvalidDestination);
+                }
+                let node_ids = node_ids
+                    .iter()
+                    .map(|node_id| NodeId::from_bytes(node_id))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| DhtMessageError::InvalidDestination)?;
+                NodeDestination::multicast(node_ids)
             },
         }
     }
@@ -370,6 +444,9 @@ impl From<NodeDestination> for Destination {
             Unknown => Destination::Unknown(true),
             PublicKey(pk) => Destination::PublicKey(pk.to_vec()),
             NodeId(node_id) => Destination::NodeId(node_id.to_vec()),
+            NodeIds(node_ids) => Destination::NodeIds(DestinationNodeIds {
+                node_ids: node_ids.iter().map(|node_id| node_id.to_vec()).collect(),
+            }),
         }
     }
 }
@@ -20,8 +20,14 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::network_discovery::DhtNetworkDiscoveryRoundInfo;
+use crate::{
+    connectivity::NetworkHeartbeat,
+    inbound::PipelineLatencyReport,
+    network_discovery::DhtNetworkDiscoveryRoundInfo,
+};
+use chrono::{DateTime, Utc};
 use std::sync::Arc;
+use tari_comms::{peer_manager::NodeId, types::CommsPublicKey};
 use tokio::sync::broadcast;
 
 pub type DhtEventSender = broadcast::Sender<Arc<DhtEvent>>;
@@ -35,4 +41,32 @@ pub enum DhtEvent {
 
     /// Emitted by the NetworkDiscovery actor once a round of peer syncing has completed.
     NetworkDiscoveryPeersAdded(DhtNetworkDiscoveryRoundInfo),
+
+    /// Emitted by the DHT connectivity actor when the rate of inbound messages, joins or pongs falls below the
+    /// configured thresholds, indicating that this node may be partitioned from the network.
+    NetworkPartitionSuspected(NetworkHeartbeat),
+
+    /// Emitted by the store and forward service when a provider returns messages that originated from this node. The
+    /// given number of messages sent by this node were stored by the provider but never delivered.
+    StoreAndForwardDeliveryFailed(NodeId, usize),
+
+    /// Emitted by the inbound pipeline when a message exceeds the latency budget of at least one pipeline stage (see
+    /// `DhtConfig::latency_budget`).
+    PipelineLatencyBudgetExceeded(PipelineLatencyReport),
+
+    /// Emitted when an authenticated join, discovery or stored message authored by the given peer is received. The
+    /// timestamp is the time at which the peer is known to have been active, which for a stored message is the time
+    /// that the store and forward provider stored it.
+    PeerSeen(CommsPublicKey, PeerSeenSource, DateTime<Utc>),
+}
+
+/// The kind of message from which the DHT observed that a peer was active on the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerSeenSource {
+    /// The peer announced itself with a join message
+    Join,
+    /// The peer sent a discovery request or a discovery response
+    Discovery,
+    /// A store and forward provider returned a message that the peer authored while this node was offline
+    StoreAndForward,
 }
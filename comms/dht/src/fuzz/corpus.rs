@@ -0,0 +1,161 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    envelope::DhtMessageFlags,
+    fuzz::{encode_frames, FuzzError},
+    proto::envelope::{dht_header::Destination, DhtEnvelope, DhtHeader, Network},
+    storage::{DbConnection, DbConnectionUrl},
+    store_forward::database::{StoreAndForwardDatabase, StoredMessage},
+};
+use log::*;
+use prost::{DecodeError, Message};
+use std::{fs, path::Path};
+use tari_comms::{message::MessageExt, Bytes};
+
+const LOG_TARGET: &str = "comms::dht::fuzz::corpus";
+
+/// Export up to `limit` of the most recently stored messages in the store and forward database at `database_url` to
+/// `output_dir`, one file per message. Each file contains a single framed `DhtEnvelope` which can be passed directly
+/// to [InboundPipelineHarness::process_bytes](crate::fuzz::InboundPipelineHarness::process_bytes).
+///
+/// Messages are sanitised before they are written: the destination, message tag and cleartext origin MAC are removed
+/// and the network is set to the local test network used by the harness. Message bodies are exported as is.
+///
+/// Returns the number of messages that were exported.
+pub async fn export_saf_corpus<P: AsRef<Path>>(
+    database_url: DbConnectionUrl,
+    output_dir: P,
+    limit: usize,
+) -> Result<usize, FuzzError>
+{
+    let output_dir = output_dir.as_ref();
+    fs::create_dir_all(output_dir)?;
+
+    let conn = DbConnection::connect_url(database_url).await?;
+    let db = StoreAndForwardDatabase::new(conn);
+    let messages = db.find_recent_messages(limit as i64).await?;
+
+    let mut num_exported = 0;
+    for message in messages {
+        let id = message.id;
+        let file_name = message.body_hash.clone();
+        let envelope = match to_sanitised_envelope(message) {
+            Ok(envelope) => envelope,
+            Err(err) => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Skipping stored message {} because the header could not be decoded: {}", id, err
+                );
+                continue;
+            },
+        };
+
+        let data = encode_frames(Some(Bytes::from(envelope.to_encoded_bytes()))).await?;
+        fs::write(output_dir.join(file_name), data)?;
+        num_exported += 1;
+    }
+
+    debug!(
+        target: LOG_TARGET,
+        "Exported {} message(s) to '{}'",
+        num_exported,
+        output_dir.to_string_lossy()
+    );
+
+    Ok(num_exported)
+}
+
+fn to_sanitised_envelope(message: StoredMessage) -> Result<DhtEnvelope, DecodeError> {
+    let mut header = DhtHeader::decode(message.header.as_slice())?;
+    header.destination = Some(Destination::Unknown(true));
+    header.message_tag = 0;
+    header.network = Network::LocalTest as i32;
+    // The origin MAC of an unencrypted message contains the origin public key in the clear
+    if !DhtMessageFlags::from_bits_truncate(header.flags).is_encrypted() {
+        header.origin_mac.clear();
+    }
+
+    // The body checksum is left empty so that mutated bodies are not rejected before reaching the rest of the pipeline
+    Ok(DhtEnvelope {
+        header: Some(header),
+        body: message.body,
+        body_checksum: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        fuzz::decode_frames,
+        store_forward::database::{NewStoredMessage, SafStorage},
+        test_utils::{make_dht_header, make_keypair, make_node_identity},
+    };
+    use tari_comms::message::MessageTag;
+    use tari_test_utils::random;
+
+    #[tokio_macros::test_basic]
+    async fn export_saf_corpus_sanitises_messages() {
+        let db_url = DbConnectionUrl::MemoryShared(random::string(8));
+        let conn = DbConnection::connect_and_migrate(db_url.clone()).await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+
+        let node_identity = make_node_identity();
+        let (e_sk, e_pk) = make_keypair();
+        let mut dht_header = make_dht_header(
+            &node_identity,
+            &e_pk,
+            &e_sk,
+            b"body",
+            DhtMessageFlags::empty(),
+            true,
+            MessageTag::new(),
+        );
+        dht_header.destination = node_identity.public_key().clone().into();
+        dht_header.network = Network::MainNet;
+        db.insert_message_if_unique(NewStoredMessage {
+            header: DhtHeader::from(dht_header).to_encoded_bytes(),
+            body: b"body".to_vec(),
+            body_hash: "0123".to_string(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let output_dir = tempfile::tempdir().unwrap();
+        let num_exported = export_saf_corpus(db_url, output_dir.path(), 10).await.unwrap();
+        assert_eq!(num_exported, 1);
+
+        let data = fs::read(output_dir.path().join("0123")).unwrap();
+        let (frames, framing_error) = decode_frames(&data).await;
+        assert!(!framing_error);
+        assert_eq!(frames.len(), 1);
+        let envelope = DhtEnvelope::decode(frames[0].as_ref()).unwrap();
+        let header = envelope.header.unwrap();
+        assert_eq!(header.destination, Some(Destination::Unknown(true)));
+        assert!(header.origin_mac.is_empty());
+        assert_eq!(header.message_tag, 0);
+        assert_eq!(header.network, Network::LocalTest as i32);
+        assert_eq!(envelope.body, b"body".to_vec());
+    }
+}
@@ -0,0 +1,48 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{storage::StorageError, DhtInitializationError};
+use prost::DecodeError;
+use std::io;
+use tari_comms::peer_manager::{NodeIdentityError, PeerManagerError};
+use tari_storage::lmdb_store::LMDBError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FuzzError {
+    #[error("Failed to create the peer database: {0}")]
+    PeerDatabaseError(#[from] LMDBError),
+    #[error("Peer database '{0}' was not created")]
+    PeerDatabaseNotCreated(String),
+    #[error("PeerManagerError: {0}")]
+    PeerManagerError(#[from] PeerManagerError),
+    #[error("NodeIdentityError: {0}")]
+    NodeIdentityError(#[from] NodeIdentityError),
+    #[error("DhtInitializationError: {0}")]
+    DhtInitializationError(#[from] DhtInitializationError),
+    #[error("StorageError: {0}")]
+    StorageError(#[from] StorageError),
+    #[error("Failed to decode stored message header: {0}")]
+    DecodeError(#[from] DecodeError),
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+}
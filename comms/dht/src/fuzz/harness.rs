@@ -0,0 +1,390 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    fuzz::FuzzError,
+    inbound::DecryptedDhtMessage,
+    outbound::{DhtOutboundRequest, SendMessageResponse},
+    proto::{
+        envelope::{dht_header::Destination, DhtEnvelope, DhtHeader, DhtMessageType, Network},
+        store_forward::StoredMessagesRequest,
+    },
+    Dht,
+    DhtBuilder,
+    DhtConfig,
+};
+use futures::{channel::mpsc, pin_mut, AsyncReadExt, AsyncWriteExt, FutureExt, SinkExt, StreamExt};
+use log::*;
+use rand::{
+    rngs::{OsRng, StdRng},
+    RngCore,
+    SeedableRng,
+};
+use std::{
+    env,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tari_comms::{
+    memsocket::MemorySocket,
+    message::{InboundMessage, MessageExt},
+    multiaddr::Multiaddr,
+    peer_manager::{NodeIdentity, PeerFeatures, PeerManager},
+    pipeline::{PipelineError, SinkService},
+    protocol::messaging::MessagingProtocol,
+    test_utils::mocks::create_connectivity_mock,
+    wrap_in_envelope_body,
+    Bytes,
+};
+use tari_shutdown::Shutdown;
+use tari_storage::{
+    lmdb_store::{db, LMDBBuilder, LMDBConfig},
+    LMDBWrapper,
+};
+use tower::{layer::Layer, ServiceExt};
+
+const LOG_TARGET: &str = "comms::dht::fuzz::harness";
+
+/// Seed for the RNG used to generate the harness node identities. Using a fixed seed means that node ids, and
+/// therefore routing decisions and ECDH shared secrets, are the same on every run.
+const IDENTITY_RNG_SEED: u64 = 0x7a71_f022;
+const PEER_DATABASE_NAME: &str = "peers";
+const OUTBOUND_CHANNEL_SIZE: usize = 100;
+
+/// The result of processing a single input through the [InboundPipelineHarness].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HarnessOutcome {
+    /// The number of frames decoded from the input
+    pub num_frames: usize,
+    /// The number of frames for which the pipeline returned an error
+    pub num_rejected: usize,
+    /// The number of messages that passed through the entire pipeline
+    pub num_delivered: usize,
+    /// The number of outbound requests (forwards, store and forward responses etc.) made while processing the input
+    pub num_outbound_requests: usize,
+    /// True if the input ended with an invalid or incomplete frame
+    pub framing_error: bool,
+}
+
+/// A deterministic harness that drives the full DHT inbound pipeline from raw bytes.
+///
+/// Input bytes are treated as the stream received on a messaging substream from a single known peer. The messaging
+/// protocol framing is applied to the input and each frame is passed through the inbound middleware to completion
+/// before the next frame is read. Outbound requests made by the pipeline are acknowledged and counted, and the
+/// connectivity manager is mocked so that no network activity takes place.
+pub struct InboundPipelineHarness {
+    node_identity: Arc<NodeIdentity>,
+    source_identity: Arc<NodeIdentity>,
+    peer_manager: Arc<PeerManager>,
+    dht: Dht,
+    outbound_rx: mpsc::Receiver<DhtOutboundRequest>,
+    next_service_tx: mpsc::UnboundedSender<DecryptedDhtMessage>,
+    next_service_rx: mpsc::UnboundedReceiver<DecryptedDhtMessage>,
+    peer_db_path: PathBuf,
+    _shutdown: Shutdown,
+}
+
+impl InboundPipelineHarness {
+    /// Create a new harness. The harness node and the source peer identities are the same for every harness.
+    pub async fn new() -> Result<Self, FuzzError> {
+        Self::with_config(DhtConfig::default_local_test()).await
+    }
+
+    /// Create a new harness using the given DHT config. The config network must match the network of the input
+    /// messages, otherwise they will be discarded by the pipeline.
+    pub async fn with_config(config: DhtConfig) -> Result<Self, FuzzError> {
+        let mut rng = StdRng::seed_from_u64(IDENTITY_RNG_SEED);
+        let node_identity = Arc::new(NodeIdentity::random(
+            &mut rng,
+            Multiaddr::empty(),
+            PeerFeatures::COMMUNICATION_NODE,
+        )?);
+        let source_identity = Arc::new(NodeIdentity::random(
+            &mut rng,
+            Multiaddr::empty(),
+            PeerFeatures::COMMUNICATION_NODE,
+        )?);
+
+        let peer_db_path = env::temp_dir().join(format!("tari_dht_fuzz_{}", OsRng.next_u64()));
+        let peer_manager = Arc::new(create_peer_manager(&peer_db_path)?);
+        peer_manager.add_peer(node_identity.to_peer()).await?;
+        peer_manager.add_peer(source_identity.to_peer()).await?;
+
+        let (connectivity, connectivity_mock) = create_connectivity_mock();
+        connectivity_mock.spawn();
+
+        let shutdown = Shutdown::new();
+        let (outbound_tx, outbound_rx) = mpsc::channel(OUTBOUND_CHANNEL_SIZE);
+        let dht = DhtBuilder::new(
+            Arc::clone(&node_identity),
+            Arc::clone(&peer_manager),
+            outbound_tx,
+            connectivity,
+            shutdown.to_signal(),
+        )
+        .with_config(config)
+        .build()
+        .await?;
+
+        let (next_service_tx, next_service_rx) = mpsc::unbounded();
+
+        Ok(Self {
+            node_identity,
+            source_identity,
+            peer_manager,
+            dht,
+            outbound_rx,
+            next_service_tx,
+            next_service_rx,
+            peer_db_path,
+            _shutdown: shutdown,
+        })
+    }
+
+    /// The identity of the node receiving the input
+    pub fn node_identity(&self) -> Arc<NodeIdentity> {
+        Arc::clone(&self.node_identity)
+    }
+
+    /// The identity of the peer from which the input is received
+    pub fn source_identity(&self) -> Arc<NodeIdentity> {
+        Arc::clone(&self.source_identity)
+    }
+
+    pub fn peer_manager(&self) -> Arc<PeerManager> {
+        Arc::clone(&self.peer_manager)
+    }
+
+    /// Returns framed input containing a store and forward request, from the source peer, for all messages stored for
+    /// the source peer. Each call returns a distinct request so that the request is not discarded as a duplicate.
+    pub async fn encode_stored_messages_request(&self) -> io::Result<Vec<u8>> {
+        let body = wrap_in_envelope_body!(StoredMessagesRequest::new()).to_encoded_bytes();
+        let header = DhtHeader {
+            destination: Some(Destination::Unknown(true)),
+            message_type: DhtMessageType::SafRequestMessages as i32,
+            network: Network::LocalTest as i32,
+            ..Default::default()
+        };
+        let envelope = DhtEnvelope::new(header, body.into());
+        encode_frames(Some(Bytes::from(envelope.to_encoded_bytes()))).await
+    }
+
+    /// Process `data` as if it was received on a messaging substream from the source peer.
+    pub async fn process_bytes(&mut self, data: &[u8]) -> HarnessOutcome {
+        let (frames, framing_error) = decode_frames(data).await;
+        let mut outcome = HarnessOutcome {
+            framing_error,
+            ..Default::default()
+        };
+
+        for frame in frames {
+            outcome.num_frames += 1;
+            if let Err(err) = self.process_frame(frame, &mut outcome).await {
+                debug!(target: LOG_TARGET, "Inbound pipeline returned an error: {}", err);
+                outcome.num_rejected += 1;
+            }
+        }
+
+        outcome
+    }
+
+    async fn process_frame(&mut self, frame: Bytes, outcome: &mut HarnessOutcome) -> Result<(), PipelineError> {
+        let message = InboundMessage::new(self.source_identity.node_id().clone(), frame);
+        let service = self
+            .dht
+            .inbound_middleware_layer()
+            .layer(SinkService::new(self.next_service_tx.clone()));
+
+        let pipeline = service.oneshot(message).fuse();
+        pin_mut!(pipeline);
+        let result = loop {
+            futures::select! {
+                result = pipeline => break result,
+                request = self.outbound_rx.select_next_some() => {
+                    outcome.num_outbound_requests += 1;
+                    reply_to_outbound_request(request);
+                },
+            }
+        };
+
+        while let Ok(Some(request)) = self.outbound_rx.try_next() {
+            outcome.num_outbound_requests += 1;
+            reply_to_outbound_request(request);
+        }
+        while let Ok(Some(_)) = self.next_service_rx.try_next() {
+            outcome.num_delivered += 1;
+        }
+
+        result
+    }
+}
+
+impl Drop for InboundPipelineHarness {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.peer_db_path) {
+            debug!(
+                target: LOG_TARGET,
+                "Failed to remove harness peer database at '{}': {}",
+                self.peer_db_path.to_string_lossy(),
+                err
+            );
+        }
+    }
+}
+
+/// Split `data` into frames using the messaging protocol framing. Returns the complete frames that were read and
+/// true if the input ended with an invalid or incomplete frame.
+pub async fn decode_frames(data: &[u8]) -> (Vec<Bytes>, bool) {
+    let (mut writer, reader) = MemorySocket::new_pair();
+    // Memory sockets are unbounded, so the whole input is written before the reader is polled
+    if writer.write_all(data).await.is_err() {
+        return (Vec::new(), true);
+    }
+    drop(writer);
+
+    let mut framed = MessagingProtocol::framed(reader);
+    let mut frames = Vec::new();
+    while let Some(result) = framed.next().await {
+        match result {
+            Ok(frame) => frames.push(frame.freeze()),
+            Err(_) => return (frames, true),
+        }
+    }
+
+    (frames, false)
+}
+
+/// Encode each message using the messaging protocol framing, producing input suitable for
+/// [InboundPipelineHarness::process_bytes].
+pub async fn encode_frames<I>(messages: I) -> io::Result<Vec<u8>>
+where I: IntoIterator<Item = Bytes> {
+    let (writer, mut reader) = MemorySocket::new_pair();
+    let mut framed = MessagingProtocol::framed(writer);
+    for message in messages {
+        framed.send(message).await?;
+    }
+    drop(framed);
+
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).await?;
+    Ok(buf)
+}
+
+fn reply_to_outbound_request(request: DhtOutboundRequest) {
+    match request {
+        DhtOutboundRequest::SendMessage(_, _, reply_tx) => {
+            let _ = reply_tx.send(SendMessageResponse::Queued(Vec::new().into()));
+        },
+    }
+}
+
+fn create_peer_manager(path: &Path) -> Result<PeerManager, FuzzError> {
+    fs::create_dir_all(path)?;
+    let datastore = LMDBBuilder::new()
+        .set_path(path)
+        .set_env_config(LMDBConfig::default())
+        .set_max_number_of_databases(1)
+        .add_database(PEER_DATABASE_NAME, db::CREATE)
+        .build()?;
+    let peer_database = datastore
+        .get_handle(PEER_DATABASE_NAME)
+        .ok_or_else(|| FuzzError::PeerDatabaseNotCreated(PEER_DATABASE_NAME.to_string()))?;
+    let peer_manager = PeerManager::new(LMDBWrapper::new(Arc::new(peer_database)), None)?;
+    Ok(peer_manager)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{envelope::DhtMessageFlags, test_utils::make_dht_envelope};
+    use tari_comms::message::MessageTag;
+
+    #[tokio_macros::test_basic]
+    async fn frames_roundtrip() {
+        let messages = vec![Bytes::from_static(b"A"), Bytes::from_static(b"BC"), Bytes::new()];
+        let data = encode_frames(messages.clone()).await.unwrap();
+        let (frames, framing_error) = decode_frames(&data).await;
+        assert!(!framing_error);
+        assert_eq!(frames, messages);
+
+        let (frames, framing_error) = decode_frames(&data[..data.len() - 1]).await;
+        assert!(framing_error);
+        assert_eq!(frames, messages[..2].to_vec());
+    }
+
+    #[tokio_macros::test_basic]
+    async fn process_bytes_delivers_valid_messages() {
+        let mut harness = InboundPipelineHarness::new().await.unwrap();
+
+        let msg = wrap_in_envelope_body!(b"public".to_vec());
+        let unencrypted = make_dht_envelope(
+            &harness.source_identity(),
+            msg.to_encoded_bytes(),
+            DhtMessageFlags::empty(),
+            false,
+            MessageTag::new(),
+        );
+        let msg = wrap_in_envelope_body!(b"secret".to_vec());
+        let encrypted = make_dht_envelope(
+            &harness.node_identity(),
+            msg.to_encoded_bytes(),
+            DhtMessageFlags::ENCRYPTED,
+            true,
+            MessageTag::new(),
+        );
+        let data = encode_frames(vec![
+            unencrypted.to_encoded_bytes().into(),
+            encrypted.to_encoded_bytes().into(),
+        ])
+        .await
+        .unwrap();
+
+        let outcome = harness.process_bytes(&data).await;
+        assert_eq!(outcome.num_frames, 2);
+        assert_eq!(outcome.num_rejected, 0);
+        assert_eq!(outcome.num_delivered, 2);
+        assert!(!outcome.framing_error);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn process_bytes_handles_malformed_input() {
+        let mut harness = InboundPipelineHarness::new().await.unwrap();
+
+        let outcome = harness.process_bytes(&[]).await;
+        assert_eq!(outcome, HarnessOutcome::default());
+
+        // Frame header claims more bytes than are available
+        let outcome = harness.process_bytes(&[0, 0, 0, 10, 1, 2]).await;
+        assert_eq!(outcome.num_frames, 0);
+        assert!(outcome.framing_error);
+
+        let data = encode_frames(vec![Bytes::from_static(b"not a dht envelope")])
+            .await
+            .unwrap();
+        let outcome = harness.process_bytes(&data).await;
+        assert_eq!(outcome.num_frames, 1);
+        assert_eq!(outcome.num_delivered, 0);
+        assert!(!outcome.framing_error);
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::outbound::DhtOutboundError;
+use prost::DecodeError;
+use tari_utilities::ciphers::cipher::CipherError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GroupError {
+    #[error("CipherError: {0}")]
+    CipherError(#[from] CipherError),
+    #[error("DhtOutboundError: {0}")]
+    DhtOutboundError(#[from] DhtOutboundError),
+    #[error("Failed to decode group message: {0}")]
+    DecodeError(#[from] DecodeError),
+    #[error("Group message envelope is empty")]
+    EmptyEnvelope,
+    #[error("Invalid group id")]
+    InvalidGroupId,
+    #[error("Invalid group key")]
+    InvalidGroupKey,
+    #[error("Invalid public key")]
+    InvalidPublicKey,
+    #[error("Group message MAC is invalid")]
+    InvalidMac,
+    #[error("Group invite or message signature is invalid")]
+    InvalidSignature,
+    #[error("Failed to sign group invite or message")]
+    SigningFailed,
+    #[error("Message is for a different group")]
+    GroupMismatch,
+    #[error("Message was encrypted with the key for epoch {actual}, but the current epoch is {expected}")]
+    EpochMismatch { expected: u64, actual: u64 },
+    #[error("Public key is not a member of the group")]
+    NotAMember,
+    #[error("Only the creator of the group can invite members")]
+    NotGroupCreator,
+}
@@ -0,0 +1,140 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::GroupError;
+use crate::crypt;
+use clear_on_drop::clear::Clear;
+use digest::Digest;
+use rand::{rngs::OsRng, RngCore};
+use std::{fmt, ops::Deref};
+use tari_comms::types::{Challenge, CommsPublicKey, CommsSecretKey};
+use tari_crypto::keys::PublicKey;
+
+/// The length in bytes of a group key
+pub const GROUP_KEY_LENGTH: usize = 32;
+
+/// A symmetric key shared by the members of a group session. The key is zeroed when it is dropped.
+#[derive(Clone)]
+pub struct GroupKey([u8; GROUP_KEY_LENGTH]);
+
+impl GroupKey {
+    /// Generates a new random group key
+    pub fn random() -> Self {
+        let mut key = [0u8; GROUP_KEY_LENGTH];
+        OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// Encrypts the group key for the given member. A new ephemeral keypair is used for each member so that the
+    /// wrapped keys sent to different members cannot be linked. The wrapped key does not identify who wrapped it;
+    /// invites bind it to the group creator with a signature.
+    pub fn wrap_for(&self, member: &CommsPublicKey) -> Result<WrappedGroupKey, GroupError> {
+        let (e_sk, e_pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let shared_secret = crypt::generate_ecdh_secret(&e_sk, member);
+        let ciphertext = crypt::encrypt(&shared_secret, &self.0)?;
+        Ok(WrappedGroupKey {
+            ephemeral_public_key: e_pk,
+            ciphertext,
+        })
+    }
+
+    /// Decrypts a group key that was wrapped for the public key of `secret_key`
+    pub fn unwrap(secret_key: &CommsSecretKey, wrapped: &WrappedGroupKey) -> Result<Self, GroupError> {
+        let shared_secret = crypt::generate_ecdh_secret(secret_key, &wrapped.ephemeral_public_key);
+        let plaintext = crypt::decrypt(&shared_secret, &wrapped.ciphertext)?;
+        if plaintext.len() != GROUP_KEY_LENGTH {
+            return Err(GroupError::InvalidGroupKey);
+        }
+        let mut key = [0u8; GROUP_KEY_LENGTH];
+        key.copy_from_slice(&plaintext);
+        Ok(Self(key))
+    }
+
+    /// Derives a key for a single purpose (e.g. encryption or authentication) from the group key, so that the same key
+    /// is never used for more than one purpose
+    pub(super) fn derive(&self, domain: &[u8]) -> DerivedKey {
+        let mut key = [0u8; GROUP_KEY_LENGTH];
+        key.copy_from_slice(&Challenge::new().chain(domain).chain(&self.0).result());
+        DerivedKey(key)
+    }
+}
+
+impl Drop for GroupKey {
+    fn drop(&mut self) {
+        Clear::clear(&mut self.0);
+    }
+}
+
+impl fmt::Debug for GroupKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GroupKey(<redacted>)")
+    }
+}
+
+/// A single purpose key derived from a group key. The key is zeroed when it is dropped.
+pub(super) struct DerivedKey([u8; GROUP_KEY_LENGTH]);
+
+impl Deref for DerivedKey {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for DerivedKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for DerivedKey {
+    fn drop(&mut self) {
+        Clear::clear(&mut self.0);
+    }
+}
+
+/// A group key encrypted for a single member
+#[derive(Debug, Clone)]
+pub struct WrappedGroupKey {
+    pub ephemeral_public_key: CommsPublicKey,
+    pub ciphertext: Vec<u8>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wrap_unwrap() {
+        let (sk, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let key = GroupKey::random();
+        let wrapped = key.wrap_for(&pk).unwrap();
+        assert_ne!(wrapped.ciphertext[..], key.0[..]);
+        let unwrapped = GroupKey::unwrap(&sk, &wrapped).unwrap();
+        assert_eq!(unwrapped.0, key.0);
+
+        // Each wrap uses a new ephemeral key
+        let wrapped2 = key.wrap_for(&pk).unwrap();
+        assert_ne!(wrapped.ephemeral_public_key, wrapped2.ephemeral_public_key);
+    }
+}
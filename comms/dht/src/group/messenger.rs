@@ -0,0 +1,201 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{GroupError, GroupPayload, GroupSession};
+use crate::{
+    outbound::{OutboundEncryption, OutboundMessageRequester, SendMessageParams, SendMessageResponse},
+    proto::group::GroupEnvelope,
+};
+use log::*;
+use prost::Message;
+use std::sync::Arc;
+use tari_comms::{
+    message::MessageExt,
+    peer_manager::{NodeId, NodeIdentity},
+    types::CommsPublicKey,
+};
+
+const LOG_TARGET: &str = "comms::dht::group";
+
+/// The public key of a group member and the response for the message sent to that member
+pub type GroupSendResponse = (CommsPublicKey, SendMessageResponse);
+
+/// Sends group invites and group messages to group members as DHT extension messages of the given extension type.
+///
+/// Each member is sent a copy of the message that is encrypted for that member. The copy is sent directly to the
+/// member if connected, otherwise it is sent to peers closer to the member which store it for the member (store and
+/// forward). This node is never sent a copy.
+#[derive(Clone)]
+pub struct GroupMessenger {
+    outbound: OutboundMessageRequester,
+    extension_type: u32,
+    node_identity: Arc<NodeIdentity>,
+}
+
+impl GroupMessenger {
+    pub fn new(outbound: OutboundMessageRequester, extension_type: u32, node_identity: Arc<NodeIdentity>) -> Self {
+        Self {
+            outbound,
+            extension_type,
+            node_identity,
+        }
+    }
+
+    /// Sends an invite containing the current group key to every member of the session. This node must be the creator
+    /// of the group.
+    pub async fn send_invites(&mut self, session: &GroupSession) -> Result<Vec<GroupSendResponse>, GroupError> {
+        let mut responses = Vec::with_capacity(session.members().len());
+        for member in self.recipients(session) {
+            let response = self.send_invite(session, &member).await?;
+            responses.push((member, response));
+        }
+        Ok(responses)
+    }
+
+    /// Sends an invite containing the current group key to a single member of the session
+    pub async fn send_invite(
+        &mut self,
+        session: &GroupSession,
+        member: &CommsPublicKey,
+    ) -> Result<SendMessageResponse, GroupError>
+    {
+        let invite = session.invite_for(&self.node_identity, member)?;
+        self.send_to_member(member.clone(), GroupPayload::Invite(invite)).await
+    }
+
+    /// Encrypts the message with the group key, signs it and sends it to every member of the session
+    pub async fn send_message<T: prost::Message>(
+        &mut self,
+        session: &GroupSession,
+        message: &T,
+    ) -> Result<Vec<GroupSendResponse>, GroupError>
+    {
+        let group_message = session.encrypt(&self.node_identity, &message.to_encoded_bytes())?;
+        let mut responses = Vec::with_capacity(session.members().len());
+        for member in self.recipients(session) {
+            let response = self
+                .send_to_member(member.clone(), GroupPayload::Message(group_message.clone()))
+                .await?;
+            responses.push((member, response));
+        }
+        debug!(
+            target: LOG_TARGET,
+            "Sent group message for group '{}' to {} member(s)",
+            session.group_id(),
+            responses.len()
+        );
+        Ok(responses)
+    }
+
+    fn recipients(&self, session: &GroupSession) -> Vec<CommsPublicKey> {
+        session
+            .members()
+            .iter()
+            .filter(|pk| *pk != self.node_identity.public_key())
+            .cloned()
+            .collect()
+    }
+
+    async fn send_to_member(
+        &mut self,
+        member: CommsPublicKey,
+        payload: GroupPayload,
+    ) -> Result<SendMessageResponse, GroupError>
+    {
+        let params = SendMessageParams::new()
+            .direct_or_closest(NodeId::from_public_key(&member))
+            .with_destination(member.clone().into())
+            .with_encryption(OutboundEncryption::EncryptFor(Box::new(member)))
+            .finish();
+        let envelope = GroupEnvelope { payload: Some(payload) };
+        let response = self
+            .outbound
+            .send_extension_message(params, self.extension_type, envelope)
+            .await?;
+        Ok(response)
+    }
+}
+
+/// Decodes the body of a group extension message received by a registered extension handler
+pub fn decode_group_payload(body: &[u8]) -> Result<GroupPayload, GroupError> {
+    GroupEnvelope::decode(body)?.payload.ok_or(GroupError::EmptyEnvelope)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        envelope::DhtMessageType,
+        outbound::mock::create_outbound_service_mock,
+        proto::dht::ExtensionMessage,
+        test_utils::make_node_identity,
+    };
+    use rand::rngs::OsRng;
+    use tari_comms::message::EnvelopeBody;
+    use tari_crypto::keys::PublicKey;
+    use tari_test_utils::unpack_enum;
+
+    #[tokio_macros::test_basic]
+    async fn send_message() {
+        let node_identity = make_node_identity();
+        let (sk1, pk1) = CommsPublicKey::random_keypair(&mut OsRng);
+        let (_, pk2) = CommsPublicKey::random_keypair(&mut OsRng);
+        let (requester, mock) = create_outbound_service_mock(10);
+        let mock_state = mock.get_state();
+        tokio::spawn(mock.run());
+
+        let session = GroupSession::create(&node_identity, vec![pk1.clone(), pk2.clone()]);
+        let mut messenger = GroupMessenger::new(requester, 1000, node_identity.clone());
+        let responses = messenger.send_invites(&session).await.unwrap();
+        assert_eq!(responses.len(), 2);
+        let responses = messenger.send_message(&session, &b"hello".to_vec()).await.unwrap();
+        assert_eq!(responses.len(), 2);
+
+        let calls = mock_state.take_calls();
+        assert_eq!(calls.len(), 4);
+        for (params, _) in &calls {
+            assert_eq!(params.dht_message_type, DhtMessageType::Extension);
+            assert!(params.encryption.is_encrypt());
+        }
+
+        let decode_body = |body: &[u8]| {
+            let ext = EnvelopeBody::decode(body)
+                .unwrap()
+                .decode_part::<ExtensionMessage>(0)
+                .unwrap()
+                .unwrap();
+            assert_eq!(ext.extension_type, 1000);
+            decode_group_payload(&ext.body).unwrap()
+        };
+
+        let (params, body) = &calls[0];
+        assert_eq!(params.encryption.public_key(), Some(&pk1));
+        unpack_enum!(GroupPayload::Invite(invite) = decode_body(body));
+        let joined = GroupSession::from_invite(&sk1, node_identity.public_key(), &invite).unwrap();
+
+        let (_, body) = &calls[2];
+        unpack_enum!(GroupPayload::Message(group_message) = decode_body(body));
+        let (sender, plaintext) = joined.decrypt(&group_message).unwrap();
+        assert_eq!(sender, *node_identity.public_key());
+        assert_eq!(Vec::<u8>::decode(plaintext.as_slice()).unwrap(), b"hello".to_vec());
+    }
+}
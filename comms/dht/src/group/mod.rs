@@ -0,0 +1,56 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Group sessions
+//!
+//! End-to-end encrypted messaging between a fixed set of members, intended as a building block for multiparty
+//! protocols (e.g. multisig wallets).
+//!
+//! A [GroupSession](self::GroupSession) holds a random symmetric group key. The creator of the group sends each member
+//! a [GroupInvite](self::GroupInvite) containing the group key wrapped (encrypted) for the member's public key, signed
+//! by the creator. Members only join using invites signed by the creator they expect (e.g. the authenticated origin of
+//! the invite message). Once a member has joined, group messages are encrypted once with the group key, authenticated
+//! with a MAC and signed by the sending member. Because every member knows the group key, the MAC only shows that the
+//! message was sent by some member; the signature identifies which one.
+//!
+//! The [GroupMessenger](self::GroupMessenger) sends invites and group messages as DHT extension messages of a type
+//! chosen by the application. The DHT sends a copy to each member, directly if the member is connected or otherwise to
+//! peers closer to the member, which store the message until the member comes online (store and forward). Recipients
+//! register a handler for the extension type (see `Dht::message_handlers`) and decode the body using
+//! [decode_group_payload](self::decode_group_payload).
+//!
+//! Removing a member rotates the group key. Remaining members must be sent new invites before they can read messages
+//! encrypted with the new key.
+
+mod error;
+pub use error::GroupError;
+
+mod key;
+pub use key::{GroupKey, WrappedGroupKey, GROUP_KEY_LENGTH};
+
+mod messenger;
+pub use messenger::{decode_group_payload, GroupMessenger, GroupSendResponse};
+
+mod session;
+pub use session::{GroupId, GroupSession, GROUP_ID_LENGTH};
+
+pub use crate::proto::group::{group_envelope::Payload as GroupPayload, GroupInvite, GroupMessage};
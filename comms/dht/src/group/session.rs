@@ -0,0 +1,467 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{GroupError, GroupInvite, GroupKey, GroupMessage, WrappedGroupKey};
+use digest::Digest;
+use rand::{rngs::OsRng, RngCore};
+use std::{convert::TryFrom, fmt};
+use tari_comms::{
+    peer_manager::NodeIdentity,
+    types::{Challenge, CommsPublicKey, CommsSecretKey},
+    utils::signature,
+};
+use tari_utilities::{
+    ciphers::{chacha20::ChaCha20, cipher::Cipher},
+    hex::to_hex,
+    message_format::MessageFormat,
+    ByteArray,
+};
+
+/// The length in bytes of a group id
+pub const GROUP_ID_LENGTH: usize = 32;
+
+const ENCRYPTION_KEY_DOMAIN: &[u8] = b"tari.dht.group.encryption_key";
+const MAC_KEY_DOMAIN: &[u8] = b"tari.dht.group.mac_key";
+const INVITE_SIGNATURE_DOMAIN: &[u8] = b"tari.dht.group.invite";
+const MESSAGE_SIGNATURE_DOMAIN: &[u8] = b"tari.dht.group.message";
+
+/// Uniquely identifies a group session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GroupId([u8; GROUP_ID_LENGTH]);
+
+impl GroupId {
+    pub fn random() -> Self {
+        let mut id = [0u8; GROUP_ID_LENGTH];
+        OsRng.fill_bytes(&mut id);
+        Self(id)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl TryFrom<&[u8]> for GroupId {
+    type Error = GroupError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.len() != GROUP_ID_LENGTH {
+            return Err(GroupError::InvalidGroupId);
+        }
+        let mut id = [0u8; GROUP_ID_LENGTH];
+        id.copy_from_slice(bytes);
+        Ok(Self(id))
+    }
+}
+
+impl fmt::Display for GroupId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", to_hex(&self.0))
+    }
+}
+
+/// The state of a group session held by each member: the group id, the members and the current group key. Invites are
+/// signed by the creator of the group and messages are signed by the member that sent them, so that a member cannot
+/// impersonate another member or hand out the group key on the creator's behalf.
+#[derive(Debug, Clone)]
+pub struct GroupSession {
+    group_id: GroupId,
+    epoch: u64,
+    key: GroupKey,
+    creator: CommsPublicKey,
+    members: Vec<CommsPublicKey>,
+}
+
+impl GroupSession {
+    /// Creates a new group session with a random group id and key. The node creating the group is always a member.
+    pub fn create<I: IntoIterator<Item = CommsPublicKey>>(node_identity: &NodeIdentity, members: I) -> Self {
+        let mut session = Self {
+            group_id: GroupId::random(),
+            epoch: 0,
+            key: GroupKey::random(),
+            creator: node_identity.public_key().clone(),
+            members: vec![node_identity.public_key().clone()],
+        };
+        for member in members {
+            session.add_member(member);
+        }
+        session
+    }
+
+    /// Joins a group session using an invite sent to the public key of `secret_key`. The invite must be signed by
+    /// `creator`, which is typically the authenticated origin of the invite message.
+    pub fn from_invite(
+        secret_key: &CommsSecretKey,
+        creator: &CommsPublicKey,
+        invite: &GroupInvite,
+    ) -> Result<Self, GroupError>
+    {
+        let group_id = GroupId::try_from(invite.group_id.as_slice())?;
+        let wrapped = WrappedGroupKey {
+            ephemeral_public_key: CommsPublicKey::from_bytes(&invite.ephemeral_public_key)
+                .map_err(|_| GroupError::InvalidPublicKey)?,
+            ciphertext: invite.wrapped_key.clone(),
+        };
+        let members = invite
+            .members
+            .iter()
+            .map(|pk| CommsPublicKey::from_bytes(pk).map_err(|_| GroupError::InvalidPublicKey))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !members.contains(creator) {
+            return Err(GroupError::NotAMember);
+        }
+        let challenge = invite_challenge(&group_id, invite.epoch, &members, &wrapped);
+        if !signature::verify(creator, &invite.signature, challenge) {
+            return Err(GroupError::InvalidSignature);
+        }
+
+        Ok(Self {
+            group_id,
+            epoch: invite.epoch,
+            key: GroupKey::unwrap(secret_key, &wrapped)?,
+            creator: creator.clone(),
+            members,
+        })
+    }
+
+    pub fn group_id(&self) -> &GroupId {
+        &self.group_id
+    }
+
+    /// The epoch of the current group key. The epoch is incremented each time the key is rotated.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// The public key of the member that created the group. Only the creator can invite members.
+    pub fn creator(&self) -> &CommsPublicKey {
+        &self.creator
+    }
+
+    pub fn members(&self) -> &[CommsPublicKey] {
+        &self.members
+    }
+
+    pub fn is_member(&self, public_key: &CommsPublicKey) -> bool {
+        self.members.contains(public_key)
+    }
+
+    /// Adds a member to the group. The new member must be sent an invite. Returns false if the public key is already a
+    /// member.
+    pub fn add_member(&mut self, public_key: CommsPublicKey) -> bool {
+        if self.is_member(&public_key) {
+            return false;
+        }
+        self.members.push(public_key);
+        true
+    }
+
+    /// Removes a member from the group and rotates the group key, so that the removed member cannot read later
+    /// messages. The remaining members must be sent new invites. Returns false if the public key is not a member or is
+    /// the creator of the group.
+    pub fn remove_member(&mut self, public_key: &CommsPublicKey) -> bool {
+        if *public_key == self.creator {
+            return false;
+        }
+        let len = self.members.len();
+        self.members.retain(|pk| pk != public_key);
+        if self.members.len() == len {
+            return false;
+        }
+        self.rotate_key();
+        true
+    }
+
+    /// Replaces the group key with a new random key and increments the epoch
+    pub fn rotate_key(&mut self) {
+        self.key = GroupKey::random();
+        self.epoch += 1;
+    }
+
+    /// Returns an invite for the given member containing the group key wrapped for that member, signed by
+    /// `node_identity`, which must be the creator of the group
+    pub fn invite_for(&self, node_identity: &NodeIdentity, member: &CommsPublicKey) -> Result<GroupInvite, GroupError> {
+        if *node_identity.public_key() != self.creator {
+            return Err(GroupError::NotGroupCreator);
+        }
+        if !self.is_member(member) {
+            return Err(GroupError::NotAMember);
+        }
+        let wrapped = self.key.wrap_for(member)?;
+        let challenge = invite_challenge(&self.group_id, self.epoch, &self.members, &wrapped);
+        Ok(GroupInvite {
+            group_id: self.group_id.as_bytes().to_vec(),
+            epoch: self.epoch,
+            members: self.members.iter().map(|pk| pk.to_vec()).collect(),
+            ephemeral_public_key: wrapped.ephemeral_public_key.to_vec(),
+            wrapped_key: wrapped.ciphertext,
+            signature: sign(node_identity, challenge)?,
+        })
+    }
+
+    /// Encrypts a message with the current group key and signs it with `node_identity`, which must be a member
+    pub fn encrypt(&self, node_identity: &NodeIdentity, plaintext: &[u8]) -> Result<GroupMessage, GroupError> {
+        let sender = node_identity.public_key();
+        if !self.is_member(sender) {
+            return Err(GroupError::NotAMember);
+        }
+        let encryption_key = self.key.derive(ENCRYPTION_KEY_DOMAIN);
+        let ciphertext = ChaCha20::seal_with_integral_nonce(&plaintext.to_vec(), &encryption_key)?;
+        let mac = self.calculate_mac(self.epoch, &ciphertext);
+        let challenge = message_challenge(&self.group_id, self.epoch, sender, &ciphertext);
+        Ok(GroupMessage {
+            group_id: self.group_id.as_bytes().to_vec(),
+            epoch: self.epoch,
+            ciphertext,
+            mac,
+            sender_public_key: sender.to_vec(),
+            signature: sign(node_identity, challenge)?,
+        })
+    }
+
+    /// Authenticates and decrypts a group message, returning the public key of the member that sent it along with the
+    /// plaintext. Messages that are not signed by a member, are not authenticated with the group key or were encrypted
+    /// with the key of a different epoch are rejected.
+    pub fn decrypt(&self, message: &GroupMessage) -> Result<(CommsPublicKey, Vec<u8>), GroupError> {
+        if message.group_id != self.group_id.as_bytes() {
+            return Err(GroupError::GroupMismatch);
+        }
+        if message.epoch != self.epoch {
+            return Err(GroupError::EpochMismatch {
+                expected: self.epoch,
+                actual: message.epoch,
+            });
+        }
+        let sender =
+            CommsPublicKey::from_bytes(&message.sender_public_key).map_err(|_| GroupError::InvalidPublicKey)?;
+        if !self.is_member(&sender) {
+            return Err(GroupError::NotAMember);
+        }
+        let challenge = message_challenge(&self.group_id, message.epoch, &sender, &message.ciphertext);
+        if !signature::verify(&sender, &message.signature, challenge) {
+            return Err(GroupError::InvalidSignature);
+        }
+        let mac = self.calculate_mac(message.epoch, &message.ciphertext);
+        if !constant_time_eq(&mac, &message.mac) {
+            return Err(GroupError::InvalidMac);
+        }
+        let encryption_key = self.key.derive(ENCRYPTION_KEY_DOMAIN);
+        let plaintext = ChaCha20::open_with_integral_nonce(&message.ciphertext, &encryption_key)?;
+        Ok((sender, plaintext))
+    }
+
+    fn calculate_mac(&self, epoch: u64, ciphertext: &[u8]) -> Vec<u8> {
+        Challenge::new()
+            .chain(self.key.derive(MAC_KEY_DOMAIN))
+            .chain(self.group_id.as_bytes())
+            .chain(epoch.to_le_bytes())
+            .chain(ciphertext)
+            .result()
+            .to_vec()
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn invite_challenge(group_id: &GroupId, epoch: u64, members: &[CommsPublicKey], wrapped: &WrappedGroupKey) -> Vec<u8> {
+    let mut challenge = INVITE_SIGNATURE_DOMAIN.to_vec();
+    challenge.extend_from_slice(group_id.as_bytes());
+    challenge.extend_from_slice(&epoch.to_le_bytes());
+    challenge.extend_from_slice(&(members.len() as u64).to_le_bytes());
+    for member in members {
+        challenge.extend_from_slice(member.as_bytes());
+    }
+    challenge.extend_from_slice(wrapped.ephemeral_public_key.as_bytes());
+    challenge.extend_from_slice(&wrapped.ciphertext);
+    challenge
+}
+
+fn message_challenge(group_id: &GroupId, epoch: u64, sender: &CommsPublicKey, ciphertext: &[u8]) -> Vec<u8> {
+    let mut challenge = MESSAGE_SIGNATURE_DOMAIN.to_vec();
+    challenge.extend_from_slice(group_id.as_bytes());
+    challenge.extend_from_slice(&epoch.to_le_bytes());
+    challenge.extend_from_slice(sender.as_bytes());
+    challenge.extend_from_slice(ciphertext);
+    challenge
+}
+
+fn sign(node_identity: &NodeIdentity, challenge: Vec<u8>) -> Result<Vec<u8>, GroupError> {
+    signature::sign(&mut OsRng, node_identity.secret_key().clone(), challenge)
+        .map_err(|_| GroupError::SigningFailed)?
+        .to_binary()
+        .map_err(|_| GroupError::SigningFailed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_node_identity;
+    use tari_crypto::keys::PublicKey;
+    use tari_test_utils::unpack_enum;
+
+    #[test]
+    fn encrypt_decrypt() {
+        let node_identity = make_node_identity();
+        let session = GroupSession::create(&node_identity, vec![]);
+        let msg = session.encrypt(&node_identity, b"Rendezvous at dawn").unwrap();
+        assert_ne!(msg.ciphertext, b"Rendezvous at dawn".to_vec());
+        let (sender, plaintext) = session.decrypt(&msg).unwrap();
+        assert_eq!(sender, *node_identity.public_key());
+        assert_eq!(plaintext, b"Rendezvous at dawn".to_vec());
+
+        let mut tampered = msg.clone();
+        tampered.ciphertext[0] ^= 0xff;
+        unpack_enum!(GroupError::InvalidSignature = session.decrypt(&tampered).err().unwrap());
+
+        let other_session = GroupSession::create(&node_identity, vec![]);
+        unpack_enum!(GroupError::GroupMismatch = other_session.decrypt(&msg).err().unwrap());
+
+        let non_member = make_node_identity();
+        unpack_enum!(GroupError::NotAMember = session.encrypt(&non_member, b"hello").err().unwrap());
+    }
+
+    #[test]
+    fn invite() {
+        let creator = make_node_identity();
+        let (sk2, pk2) = CommsPublicKey::random_keypair(&mut OsRng);
+        let (_, pk3) = CommsPublicKey::random_keypair(&mut OsRng);
+        let session = GroupSession::create(&creator, vec![creator.public_key().clone(), pk2.clone(), pk2.clone()]);
+        assert_eq!(session.members().len(), 2);
+        unpack_enum!(GroupError::NotAMember = session.invite_for(&creator, &pk3).err().unwrap());
+
+        let invite = session.invite_for(&creator, &pk2).unwrap();
+        let joined = GroupSession::from_invite(&sk2, creator.public_key(), &invite).unwrap();
+        assert_eq!(joined.group_id(), session.group_id());
+        assert_eq!(joined.members(), session.members());
+        assert_eq!(joined.creator(), creator.public_key());
+        let msg = session.encrypt(&creator, b"hello").unwrap();
+        assert_eq!(joined.decrypt(&msg).unwrap().1, b"hello".to_vec());
+
+        // An invite unwrapped by the wrong member produces the wrong key
+        let not_joined = GroupSession::from_invite(creator.secret_key(), creator.public_key(), &invite).unwrap();
+        unpack_enum!(GroupError::InvalidMac = not_joined.decrypt(&msg).err().unwrap());
+    }
+
+    #[test]
+    fn forged_invites_are_rejected() {
+        let creator = make_node_identity();
+        let member = make_node_identity();
+        let (sk3, pk3) = CommsPublicKey::random_keypair(&mut OsRng);
+        let session = GroupSession::create(&creator, vec![member.public_key().clone()]);
+        let invite = session.invite_for(&creator, member.public_key()).unwrap();
+
+        // Only the creator can invite members
+        let joined = GroupSession::from_invite(member.secret_key(), creator.public_key(), &invite).unwrap();
+        unpack_enum!(GroupError::NotGroupCreator = joined.invite_for(&member, creator.public_key()).err().unwrap());
+
+        // A member cannot hand out the group key as if it came from the creator
+        let mut forged_session = joined;
+        forged_session.creator = member.public_key().clone();
+        forged_session.add_member(pk3.clone());
+        let forged = forged_session.invite_for(&member, &pk3).unwrap();
+        unpack_enum!(
+            GroupError::InvalidSignature = GroupSession::from_invite(&sk3, creator.public_key(), &forged)
+                .err()
+                .unwrap()
+        );
+
+        // The members, epoch and wrapped key are covered by the signature
+        let mut tampered = invite.clone();
+        tampered.members.push(pk3.to_vec());
+        unpack_enum!(
+            GroupError::InvalidSignature =
+                GroupSession::from_invite(member.secret_key(), creator.public_key(), &tampered)
+                    .err()
+                    .unwrap()
+        );
+        let mut tampered = invite.clone();
+        tampered.epoch += 1;
+        unpack_enum!(
+            GroupError::InvalidSignature =
+                GroupSession::from_invite(member.secret_key(), creator.public_key(), &tampered)
+                    .err()
+                    .unwrap()
+        );
+        let mut tampered = invite;
+        tampered.wrapped_key = session.key.wrap_for(member.public_key()).unwrap().ciphertext;
+        unpack_enum!(
+            GroupError::InvalidSignature =
+                GroupSession::from_invite(member.secret_key(), creator.public_key(), &tampered)
+                    .err()
+                    .unwrap()
+        );
+    }
+
+    #[test]
+    fn forged_messages_are_rejected() {
+        let creator = make_node_identity();
+        let member = make_node_identity();
+        let session = GroupSession::create(&creator, vec![member.public_key().clone()]);
+        let joined = GroupSession::from_invite(
+            member.secret_key(),
+            creator.public_key(),
+            &session.invite_for(&creator, member.public_key()).unwrap(),
+        )
+        .unwrap();
+
+        let msg = joined.encrypt(&member, b"From the member").unwrap();
+        let (sender, _) = session.decrypt(&msg).unwrap();
+        assert_eq!(sender, *member.public_key());
+
+        // A member that knows the group key cannot send a message as another member
+        let mut forged = msg.clone();
+        forged.sender_public_key = creator.public_key().to_vec();
+        unpack_enum!(GroupError::InvalidSignature = session.decrypt(&forged).err().unwrap());
+
+        // Nor can anyone re-sign a message as a non-member
+        let non_member = make_node_identity();
+        let mut forged = msg;
+        forged.sender_public_key = non_member.public_key().to_vec();
+        forged.signature = sign(
+            &non_member,
+            message_challenge(session.group_id(), 0, non_member.public_key(), &forged.ciphertext),
+        )
+        .unwrap();
+        unpack_enum!(GroupError::NotAMember = session.decrypt(&forged).err().unwrap());
+    }
+
+    #[test]
+    fn remove_member_rotates_key() {
+        let creator = make_node_identity();
+        let (sk2, pk2) = CommsPublicKey::random_keypair(&mut OsRng);
+        let mut session = GroupSession::create(&creator, vec![pk2.clone()]);
+        let removed =
+            GroupSession::from_invite(&sk2, creator.public_key(), &session.invite_for(&creator, &pk2).unwrap())
+                .unwrap();
+
+        assert!(!session.remove_member(creator.public_key()));
+        assert!(session.remove_member(&pk2));
+        assert!(!session.remove_member(&pk2));
+        assert_eq!(session.epoch(), 1);
+        let msg = session.encrypt(&creator, b"secret").unwrap();
+        unpack_enum!(GroupError::EpochMismatch { expected, actual } = removed.decrypt(&msg).err().unwrap());
+        assert_eq!(expected, 0);
+        assert_eq!(actual, 1);
+    }
+}
@@ -0,0 +1,239 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+use crate::inbound::DhtInboundMessage;
+use futures::{task::Context, Future, StreamExt};
+use log::*;
+use std::{task::Poll, time::Duration};
+use tari_comms::{bounded_executor::BoundedExecutor, pipeline::PipelineError, rate_limit::RateLimit};
+use tokio::{
+    sync::{mpsc, mpsc::error::TrySendError},
+    task,
+};
+use tower::{layer::Layer, Service, ServiceExt};
+
+const LOG_TARGET: &str = "comms::dht::control_channel";
+
+/// # Control channel middleware
+///
+/// Routes DHT control messages (Join, Discovery and store and forward messages) onto a dedicated bounded channel that
+/// is handled by its own worker task, with its own concurrency and rate limits. Domain messages are passed directly
+/// to the next service. This prevents a flood of domain messages from starving network maintenance.
+///
+/// Control messages that arrive while the channel is full are discarded.
+#[derive(Clone)]
+pub struct ControlChannelMiddleware<S> {
+    next_service: S,
+    control_tx: mpsc::Sender<DhtInboundMessage>,
+}
+
+impl<S> ControlChannelMiddleware<S> {
+    pub fn new(service: S, control_tx: mpsc::Sender<DhtInboundMessage>) -> Self {
+        Self {
+            next_service: service,
+            control_tx,
+        }
+    }
+}
+
+impl<S> Service<DhtInboundMessage> for ControlChannelMiddleware<S>
+where S: Service<DhtInboundMessage, Response = (), Error = PipelineError> + Clone + 'static
+{
+    type Error = PipelineError;
+    type Response = ();
+
+    type Future = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, message: DhtInboundMessage) -> Self::Future {
+        let next_service = self.next_service.clone();
+        let mut control_tx = self.control_tx.clone();
+        async move {
+            let message_type = message.dht_header.message_type;
+            if !message_type.is_dht_message() && !message_type.is_saf_message() {
+                return next_service.oneshot(message).await;
+            }
+
+            trace!(
+                target: LOG_TARGET,
+                "Passing {} message {} to the control channel",
+                message_type,
+                message.tag
+            );
+            match control_tx.try_send(message) {
+                Ok(_) => Ok(()),
+                Err(TrySendError::Closed(_)) => Err(anyhow::anyhow!("Control message channel is closed")),
+                Err(TrySendError::Full(message)) => {
+                    // TODO: #banheuristic - a peer that fills the control channel may be misbehaving
+                    warn!(
+                        target: LOG_TARGET,
+                        "Control message channel is full. Discarding {} message {} from peer '{}'",
+                        message_type,
+                        message.tag,
+                        message.source_peer.node_id.short_str()
+                    );
+                    Ok(())
+                },
+            }
+        }
+    }
+}
+
+/// Handles messages received on the control channel
+struct ControlMessageWorker<S> {
+    control_rx: mpsc::Receiver<DhtInboundMessage>,
+    service: S,
+    executor: BoundedExecutor,
+    rate_limit_capacity: usize,
+    rate_limit_restock_interval: Duration,
+}
+
+impl<S> ControlMessageWorker<S>
+where
+    S: Service<DhtInboundMessage, Response = (), Error = PipelineError> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    async fn run(self) {
+        let mut stream = self
+            .control_rx
+            .rate_limit(self.rate_limit_capacity, self.rate_limit_restock_interval);
+
+        while let Some(message) = stream.next().await {
+            let service = self.service.clone();
+            self.executor
+                .spawn(async move {
+                    if let Err(err) = service.oneshot(message).await {
+                        warn!(
+                            target: LOG_TARGET,
+                            "Control message pipeline returned an error: '{}'", err
+                        );
+                    }
+                })
+                .await;
+        }
+
+        debug!(target: LOG_TARGET, "Control message worker has shut down");
+    }
+}
+
+pub struct ControlChannelLayer {
+    buffer_size: usize,
+    max_concurrent_tasks: usize,
+    rate_limit_capacity: usize,
+    rate_limit_restock_interval: Duration,
+}
+
+impl ControlChannelLayer {
+    pub fn new(
+        buffer_size: usize,
+        max_concurrent_tasks: usize,
+        rate_limit_capacity: usize,
+        rate_limit_restock_interval: Duration,
+    ) -> Self
+    {
+        Self {
+            buffer_size,
+            max_concurrent_tasks,
+            rate_limit_capacity,
+            rate_limit_restock_interval,
+        }
+    }
+}
+
+impl<S> Layer<S> for ControlChannelLayer
+where
+    S: Service<DhtInboundMessage, Response = (), Error = PipelineError> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Service = ControlChannelMiddleware<S>;
+
+    /// Spawns the control message worker for the given service. The worker exits once every clone of the returned
+    /// middleware has been dropped.
+    fn layer(&self, service: S) -> Self::Service {
+        let (control_tx, control_rx) = mpsc::channel(self.buffer_size);
+        let worker = ControlMessageWorker {
+            control_rx,
+            service: service.clone(),
+            executor: BoundedExecutor::from_current(self.max_concurrent_tasks),
+            rate_limit_capacity: self.rate_limit_capacity,
+            rate_limit_restock_interval: self.rate_limit_restock_interval,
+        };
+        task::spawn(worker.run());
+        ControlChannelMiddleware::new(service, control_tx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        envelope::DhtMessageFlags,
+        proto::envelope::DhtMessageType,
+        test_utils::{make_dht_inbound_message, make_node_identity, service_spy},
+    };
+    use tokio::time;
+
+    fn make_message(message_type: DhtMessageType) -> DhtInboundMessage {
+        let node_identity = make_node_identity();
+        let mut msg = make_dht_inbound_message(&node_identity, vec![], DhtMessageFlags::empty(), false);
+        msg.dht_header.message_type = message_type;
+        msg
+    }
+
+    #[tokio_macros::test_basic]
+    async fn routes_control_messages_to_worker() {
+        let spy = service_spy();
+        let mut service = ControlChannelLayer::new(10, 1, 10, Duration::from_secs(1)).layer(spy.to_service());
+
+        service.call(make_message(DhtMessageType::None)).await.unwrap();
+        // Domain messages are passed directly to the next service
+        assert_eq!(spy.call_count(), 1);
+
+        service.call(make_message(DhtMessageType::Join)).await.unwrap();
+        service
+            .call(make_message(DhtMessageType::SafRequestMessages))
+            .await
+            .unwrap();
+
+        let mut attempts = 0;
+        while spy.call_count() < 3 {
+            attempts += 1;
+            assert!(attempts < 100, "Control messages were not handled");
+            time::delay_for(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio_macros::test_basic]
+    async fn discards_control_messages_when_full() {
+        let spy = service_spy();
+        // Zero rate limit capacity: the worker never takes a message off the channel
+        let mut service = ControlChannelLayer::new(1, 1, 0, Duration::from_secs(1)).layer(spy.to_service());
+
+        for _ in 0..5 {
+            service.call(make_message(DhtMessageType::Discovery)).await.unwrap();
+        }
+        time::delay_for(Duration::from_millis(50)).await;
+        assert_eq!(spy.call_count(), 0);
+    }
+}
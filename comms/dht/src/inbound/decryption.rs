@@ -26,21 +26,24 @@ use crate::{
     inbound::message::{DecryptedDhtMessage, DhtInboundMessage},
     proto::envelope::OriginMac,
     DhtConfig,
+    PlaintextPolicy,
 };
 use futures::{task::Context, Future};
 use log::*;
 use prost::Message;
-use std::{sync::Arc, task::Poll, time::Duration};
+use std::{sync::Arc, task::Poll};
 use tari_comms::{
     connectivity::ConnectivityRequester,
     message::EnvelopeBody,
     peer_manager::NodeIdentity,
     pipeline::PipelineError,
+    security_events::{SecurityEventKind, SecurityEventPublisher},
     types::CommsPublicKey,
     utils::signature,
 };
-use tari_utilities::ByteArray;
+use tari_utilities::{hex::Hex, ByteArray};
 use thiserror::Error;
+use tokio::task;
 use tower::{layer::Layer, Service, ServiceExt};
 
 const LOG_TARGET: &str = "comms::middleware::decryption";
@@ -65,6 +68,8 @@ enum DecryptionError {
     EnvelopeBodyDecodeFailed,
     #[error("Failed to decrypt message body")]
     MessageBodyDecryptionFailed,
+    #[error("Cleartext message rejected by the plaintext policy")]
+    PlaintextMessageRejected,
 }
 
 /// This layer is responsible for attempting to decrypt inbound messages.
@@ -72,6 +77,7 @@ pub struct DecryptionLayer {
     node_identity: Arc<NodeIdentity>,
     connectivity: ConnectivityRequester,
     config: DhtConfig,
+    security_events: SecurityEventPublisher,
 }
 
 impl DecryptionLayer {
@@ -80,8 +86,15 @@ impl DecryptionLayer {
             node_identity,
             connectivity,
             config,
+            security_events: Default::default(),
         }
     }
+
+    /// Set the publisher used to report messages with invalid origin signatures
+    pub fn with_security_events(mut self, security_events: SecurityEventPublisher) -> Self {
+        self.security_events = security_events;
+        self
+    }
 }
 
 impl<S> Layer<S> for DecryptionLayer {
@@ -94,6 +107,7 @@ impl<S> Layer<S> for DecryptionLayer {
             self.connectivity.clone(),
             service,
         )
+        .with_security_events(self.security_events.clone())
     }
 }
 
@@ -103,6 +117,7 @@ pub struct DecryptionService<S> {
     config: DhtConfig,
     node_identity: Arc<NodeIdentity>,
     connectivity: ConnectivityRequester,
+    security_events: SecurityEventPublisher,
     inner: S,
 }
 
@@ -118,9 +133,16 @@ impl<S> DecryptionService<S> {
             node_identity,
             connectivity,
             config,
+            security_events: Default::default(),
             inner: service,
         }
     }
+
+    /// Set the publisher used to report messages with invalid origin signatures
+    pub fn with_security_events(mut self, security_events: SecurityEventPublisher) -> Self {
+        self.security_events = security_events;
+        self
+    }
 }
 
 impl<S> Service<DhtInboundMessage> for DecryptionService<S>
@@ -140,7 +162,8 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError> + Cl
             self.inner.clone(),
             Arc::clone(&self.node_identity),
             self.connectivity.clone(),
-            self.config.ban_duration,
+            self.security_events.clone(),
+            self.config.clone(),
             msg,
         )
     }
@@ -153,7 +176,8 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
         next_service: S,
         node_identity: Arc<NodeIdentity>,
         mut connectivity: ConnectivityRequester,
-        ban_duration: Duration,
+        security_events: SecurityEventPublisher,
+        config: DhtConfig,
         message: DhtInboundMessage,
     ) -> Result<(), PipelineError>
     {
@@ -161,7 +185,17 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
         let source = message.source_peer.clone();
         let trace_id = message.dht_header.message_tag;
         let tag = message.tag;
-        match Self::validate_and_decrypt_message(node_identity, message).await {
+        let ban_duration = config.ban_duration;
+        let result = if config.inbound_crypto_offload {
+            task::spawn_blocking(move || {
+                Self::validate_and_decrypt_message(node_identity, &config.plaintext_policy, message)
+            })
+            .await?
+        } else {
+            Self::validate_and_decrypt_message(node_identity, &config.plaintext_policy, message)
+        };
+
+        match result {
             Ok(msg) => next_service.oneshot(msg).await,
 
             Err(err @ OriginMacNotProvided) |
@@ -169,6 +203,13 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             Err(err @ OriginMacInvalidSignature) => {
                 // This message should not have been propagated, or has been manipulated in some way. Ban the source of
                 // this message.
+                if let OriginMacInvalidSignature = err {
+                    security_events.publish(SecurityEventKind::InvalidSignature {
+                        node_id: Some(source.node_id.to_hex()),
+                        address: None,
+                        context: "message origin MAC".to_string(),
+                    });
+                }
                 connectivity
                     .ban_peer_until(source.node_id.clone(), ban_duration, err.to_string())
                     .await?;
@@ -184,19 +225,41 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
                 );
                 Ok(())
             },
+            Err(PlaintextMessageRejected) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Cleartext message rejected by plaintext policy ({}, peer={}, trace={}). Message discarded",
+                    tag,
+                    source.node_id,
+                    trace_id
+                );
+                Ok(())
+            },
             Err(err) => Err(err.into()),
         }
     }
 
-    async fn validate_and_decrypt_message(
+    fn validate_and_decrypt_message(
         node_identity: Arc<NodeIdentity>,
+        plaintext_policy: &PlaintextPolicy,
         message: DhtInboundMessage,
     ) -> Result<DecryptedDhtMessage, DecryptionError>
     {
         let dht_header = &message.dht_header;
 
         if !dht_header.flags.contains(DhtMessageFlags::ENCRYPTED) {
-            return Self::success_not_encrypted(message).await;
+            let is_domain_message = dht_header.message_type.is_domain_message();
+            // Avoid verifying the origin MAC if the message will be rejected regardless of its origin
+            if is_domain_message && *plaintext_policy == PlaintextPolicy::Reject {
+                return Err(DecryptionError::PlaintextMessageRejected);
+            }
+            let decrypted = Self::success_not_encrypted(message)?;
+            if is_domain_message &&
+                !plaintext_policy.is_accepted(&decrypted.source_peer, decrypted.authenticated_origin.as_ref())
+            {
+                return Err(DecryptionError::PlaintextMessageRejected);
+            }
+            return Ok(decrypted);
         }
         trace!(
             target: LOG_TARGET,
@@ -218,7 +281,7 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             Ok((public_key, signature)) => {
                 // If this fails, discard the message because we decrypted and deserialized the message with our shared
                 // ECDH secret but the message could not be authenticated
-                Self::authenticate_origin_mac(&public_key, &signature, &message.body)?;
+                Self::authenticate_origin_mac(&public_key, &signature, &message.body, dht_header.sequence)?;
                 public_key
             },
             Err(err) => {
@@ -311,9 +374,10 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
         public_key: &CommsPublicKey,
         signature: &[u8],
         body: &[u8],
+        sequence: u64,
     ) -> Result<(), DecryptionError>
     {
-        if signature::verify(public_key, signature, body) {
+        if signature::verify(public_key, signature, crypt::origin_mac_challenge(body, sequence)) {
             Ok(())
         } else {
             Err(DecryptionError::OriginMacInvalidSignature)
@@ -355,7 +419,7 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             .map_err(|_| DecryptionError::MessageBodyDecryptionFailed)
     }
 
-    async fn success_not_encrypted(message: DhtInboundMessage) -> Result<DecryptedDhtMessage, DecryptionError> {
+    fn success_not_encrypted(message: DhtInboundMessage) -> Result<DecryptedDhtMessage, DecryptionError> {
         let authenticated_pk = if message.dht_header.origin_mac.is_empty() {
             None
         } else {
@@ -363,7 +427,12 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
                 .map_err(|_| DecryptionError::OriginMacClearTextDecodeFailed)?;
             let public_key = CommsPublicKey::from_bytes(&origin_mac.public_key)
                 .map_err(|_| DecryptionError::OriginMacInvalidPublicKey)?;
-            Self::authenticate_origin_mac(&public_key, &origin_mac.signature, &message.body)?;
+            Self::authenticate_origin_mac(
+                &public_key,
+                &origin_mac.signature,
+                &message.body,
+                message.dht_header.sequence,
+            )?;
             Some(public_key)
         };
 
@@ -397,12 +466,17 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
 mod test {
     use super::*;
     use crate::{
-        envelope::DhtMessageFlags,
+        envelope::{DhtMessageFlags, DhtMessageType},
         test_utils::{make_dht_inbound_message, make_node_identity},
     };
     use futures::{executor::block_on, future};
     use std::sync::Mutex;
-    use tari_comms::{message::MessageExt, test_utils::mocks::create_connectivity_mock, wrap_in_envelope_body};
+    use tari_comms::{
+        message::MessageExt,
+        peer_manager::PeerTrustLevel,
+        test_utils::mocks::create_connectivity_mock,
+        wrap_in_envelope_body,
+    };
     use tari_test_utils::{counter_context, unpack_enum};
     use tower::service_fn;
 
@@ -445,6 +519,36 @@ mod test {
         assert_eq!(decrypted.decryption_result.unwrap(), plain_text_msg);
     }
 
+    #[tokio_macros::test_basic]
+    async fn decrypt_inbound_success_offloaded() {
+        let result = Arc::new(Mutex::new(None));
+        let result_clone = result.clone();
+        let service = service_fn(move |msg: DecryptedDhtMessage| {
+            *result_clone.lock().unwrap() = Some(msg);
+            future::ready(Result::<(), PipelineError>::Ok(()))
+        });
+        let node_identity = make_node_identity();
+        let (connectivity, _) = create_connectivity_mock();
+        let config = DhtConfig {
+            inbound_crypto_offload: true,
+            ..Default::default()
+        };
+        let mut service = DecryptionService::new(config, node_identity.clone(), connectivity, service);
+
+        let plain_text_msg = wrap_in_envelope_body!(b"Secret plans".to_vec());
+        let inbound_msg = make_dht_inbound_message(
+            &node_identity,
+            plain_text_msg.to_encoded_bytes(),
+            DhtMessageFlags::ENCRYPTED,
+            true,
+        );
+
+        service.call(inbound_msg).await.unwrap();
+        let decrypted = result.lock().unwrap().take().unwrap();
+        assert_eq!(decrypted.decryption_succeeded(), true);
+        assert_eq!(decrypted.decryption_result.unwrap(), plain_text_msg);
+    }
+
     #[test]
     fn decrypt_inbound_fail() {
         let result = Mutex::new(None);
@@ -490,4 +594,121 @@ mod test {
         unpack_enum!(DecryptionError::MessageRejectDecryptionFailed = err);
         assert!(result.lock().unwrap().is_none());
     }
+
+    #[tokio_macros::test_basic]
+    async fn decrypt_inbound_fail_altered_sequence() {
+        let (connectivity, mock) = create_connectivity_mock();
+        mock.spawn();
+        let result = Mutex::new(None);
+        let service = service_fn(|msg: DecryptedDhtMessage| {
+            *result.lock().unwrap() = Some(msg);
+            future::ready(Result::<(), PipelineError>::Ok(()))
+        });
+        let node_identity = make_node_identity();
+        let mut service = DecryptionService::new(Default::default(), node_identity.clone(), connectivity, service);
+
+        let plain_text_msg = wrap_in_envelope_body!(b"Secret plans".to_vec());
+        let mut inbound_msg = make_dht_inbound_message(
+            &node_identity,
+            plain_text_msg.to_encoded_bytes(),
+            DhtMessageFlags::ENCRYPTED,
+            true,
+        );
+        // The origin MAC was created for an unsequenced message
+        inbound_msg.dht_header.sequence = 1;
+
+        let err = service.call(inbound_msg).await.unwrap_err();
+        let err = err.downcast::<DecryptionError>().unwrap();
+        unpack_enum!(DecryptionError::OriginMacInvalidSignature = err);
+        assert!(result.lock().unwrap().is_none());
+    }
+
+    #[tokio_macros::test_basic]
+    async fn plaintext_policy() {
+        let (connectivity, _) = create_connectivity_mock();
+        let result = Arc::new(Mutex::new(None));
+        let service = service_fn({
+            let result = result.clone();
+            move |msg: DecryptedDhtMessage| {
+                *result.lock().unwrap() = Some(msg);
+                future::ready(Result::<(), PipelineError>::Ok(()))
+            }
+        });
+        let node_identity = make_node_identity();
+        let origin_identity = make_node_identity();
+        let plain_text_msg = wrap_in_envelope_body!(b"Not a secret".to_vec());
+        let inbound_msg = make_dht_inbound_message(
+            &origin_identity,
+            plain_text_msg.to_encoded_bytes(),
+            DhtMessageFlags::NONE,
+            true,
+        );
+
+        let config = DhtConfig {
+            plaintext_policy: PlaintextPolicy::Reject,
+            ..Default::default()
+        };
+        let mut service = DecryptionService::new(config, node_identity, connectivity, service);
+        service.call(inbound_msg.clone()).await.unwrap();
+        assert!(result.lock().unwrap().is_none());
+
+        // DHT messages are not subject to the plaintext policy
+        let mut join_msg = inbound_msg.clone();
+        join_msg.dht_header.message_type = DhtMessageType::Join;
+        service.call(join_msg).await.unwrap();
+        assert!(result.lock().unwrap().take().is_some());
+
+        let other_identity = make_node_identity();
+        service.config.plaintext_policy =
+            PlaintextPolicy::AcceptFromWhitelisted(vec![other_identity.public_key().clone()]);
+        service.call(inbound_msg.clone()).await.unwrap();
+        assert!(result.lock().unwrap().is_none());
+
+        // Cleartext messages from trusted peers are accepted without being whitelisted
+        let with_trust_level = |trust_level| {
+            let mut msg = inbound_msg.clone();
+            let mut peer = Clone::clone(&*msg.source_peer);
+            peer.set_trust_level(trust_level);
+            msg.source_peer = Arc::new(peer);
+            msg
+        };
+        service.call(with_trust_level(PeerTrustLevel::Trusted)).await.unwrap();
+        assert!(result.lock().unwrap().take().is_some());
+
+        // ...but only if the origin of the message is authenticated
+        let mut msg = with_trust_level(PeerTrustLevel::Trusted);
+        msg.dht_header.origin_mac = Vec::new();
+        service.call(msg).await.unwrap();
+        assert!(result.lock().unwrap().is_none());
+
+        // Cleartext messages from untrusted peers are never accepted
+        service.config.plaintext_policy = PlaintextPolicy::Accept;
+        service.call(with_trust_level(PeerTrustLevel::Untrusted)).await.unwrap();
+        assert!(result.lock().unwrap().is_none());
+
+        service.config.plaintext_policy =
+            PlaintextPolicy::AcceptFromWhitelisted(vec![origin_identity.public_key().clone()]);
+        service.call(inbound_msg.clone()).await.unwrap();
+        let decrypted = result.lock().unwrap().take().unwrap();
+        assert_eq!(decrypted.decryption_result.unwrap(), plain_text_msg);
+
+        // The whitelist applies to the authenticated origin, not to the peer that sent the message
+        let mut no_origin_msg = inbound_msg.clone();
+        no_origin_msg.dht_header.origin_mac = Vec::new();
+        service.call(no_origin_msg).await.unwrap();
+        assert!(result.lock().unwrap().is_none());
+
+        let mut relayed_msg = inbound_msg;
+        let mut relay = Clone::clone(&*relayed_msg.source_peer);
+        relay.public_key = other_identity.public_key().clone();
+        relay.node_id = other_identity.node_id().clone();
+        relayed_msg.source_peer = Arc::new(relay);
+        service.call(relayed_msg.clone()).await.unwrap();
+        assert!(result.lock().unwrap().take().is_some());
+
+        service.config.plaintext_policy =
+            PlaintextPolicy::AcceptFromWhitelisted(vec![other_identity.public_key().clone()]);
+        service.call(relayed_msg).await.unwrap();
+        assert!(result.lock().unwrap().is_none());
+    }
 }
@@ -20,12 +20,17 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::{inbound::DhtInboundMessage, proto::envelope::DhtEnvelope};
+use crate::{
+    codec,
+    codec::{DhtCodecError, EnvelopeWireFormat},
+    connectivity::{MetricsCollectorHandle, WireFormatDirection},
+    envelope::DhtMessageType,
+    inbound::DhtInboundMessage,
+};
 use futures::{task::Context, Future};
 use log::*;
-use prost::Message;
-use std::{convert::TryInto, sync::Arc, task::Poll};
-use tari_comms::{message::InboundMessage, pipeline::PipelineError, PeerManager};
+use std::{sync::Arc, task::Poll};
+use tari_comms::{message::InboundMessage, peer_manager::NodeIdentity, pipeline::PipelineError, PeerManager};
 use tower::{layer::Layer, Service, ServiceExt};
 
 const LOG_TARGET: &str = "comms::dht::deserialize";
@@ -35,16 +40,30 @@ const LOG_TARGET: &str = "comms::dht::deserialize";
 /// Takes in an `InboundMessage` and deserializes the body into a [DhtEnvelope].
 /// The `next_service` is called with a constructed [DhtInboundMessage] which contains
 /// the relevant comms-level and dht-level information.
+///
+/// Envelopes in both the legacy and versioned wire formats are accepted. The wire format of each envelope is counted
+/// by the metrics collector. Envelopes in the direct wire format omit the destination, as they are always destined
+/// for this node, so the destination is set to this node's public key.
 #[derive(Clone)]
 pub struct DhtDeserializeMiddleware<S> {
     next_service: S,
+    node_identity: Arc<NodeIdentity>,
     peer_manager: Arc<PeerManager>,
+    metrics_collector: MetricsCollectorHandle,
 }
 
 impl<S> DhtDeserializeMiddleware<S> {
-    pub fn new(peer_manager: Arc<PeerManager>, service: S) -> Self {
+    pub fn new(
+        node_identity: Arc<NodeIdentity>,
+        peer_manager: Arc<PeerManager>,
+        metrics_collector: MetricsCollectorHandle,
+        service: S,
+    ) -> Self
+    {
         Self {
+            node_identity,
             peer_manager,
+            metrics_collector,
             next_service: service,
         }
     }
@@ -64,27 +83,32 @@ where S: Service<DhtInboundMessage, Response = (), Error = PipelineError> + Clon
 
     fn call(&mut self, message: InboundMessage) -> Self::Future {
         let next_service = self.next_service.clone();
+        let node_identity = self.node_identity.clone();
         let peer_manager = self.peer_manager.clone();
+        let mut metrics_collector = self.metrics_collector.clone();
         async move {
             trace!(target: LOG_TARGET, "Deserializing InboundMessage {}", message.tag);
 
             let InboundMessage {
-                source_peer,
-                mut body,
-                tag,
-                ..
+                source_peer, body, tag, ..
             } = message;
 
             if body.is_empty() {
                 return Err(anyhow::anyhow!("Received empty message from peer '{}'", source_peer));
             }
 
-            match DhtEnvelope::decode(&mut body) {
-                Ok(dht_envelope) => {
+            match codec::decode_envelope_with_format(&body) {
+                Ok((wire_format, mut dht_header, body)) => {
+                    if wire_format == EnvelopeWireFormat::Direct {
+                        dht_header.destination = node_identity.public_key().clone().into();
+                    }
+                    metrics_collector.write_metric_wire_format(WireFormatDirection::Inbound, wire_format);
+                    if dht_header.message_type == DhtMessageType::Join {
+                        metrics_collector.write_metric_join_received();
+                    }
                     let source_peer = peer_manager.find_by_node_id(&source_peer).await.map(Arc::new)?;
 
-                    let inbound_msg =
-                        DhtInboundMessage::new(tag, dht_envelope.header.try_into()?, source_peer, dht_envelope.body);
+                    let inbound_msg = DhtInboundMessage::new(tag, dht_header, source_peer, body);
                     trace!(
                         target: LOG_TARGET,
                         "Deserialization succeeded. Passing message {} onto next service (Trace: {})",
@@ -94,6 +118,14 @@ where S: Service<DhtInboundMessage, Response = (), Error = PipelineError> + Clon
 
                     next_service.oneshot(inbound_msg).await
                 },
+                Err(err @ DhtCodecError::BodyChecksumMismatch) => {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Discarding corrupt message {} from peer '{}': {}", tag, source_peer, err
+                    );
+                    metrics_collector.write_metric_envelope_checksum_failed();
+                    Err(err.into())
+                },
                 Err(err) => {
                     error!(target: LOG_TARGET, "DHT deserialization failed: {}", err);
                     Err(err.into())
@@ -104,12 +136,23 @@ where S: Service<DhtInboundMessage, Response = (), Error = PipelineError> + Clon
 }
 
 pub struct DeserializeLayer {
+    node_identity: Arc<NodeIdentity>,
     peer_manager: Arc<PeerManager>,
+    metrics_collector: MetricsCollectorHandle,
 }
 
 impl DeserializeLayer {
-    pub fn new(peer_manager: Arc<PeerManager>) -> Self {
-        Self { peer_manager }
+    pub fn new(
+        node_identity: Arc<NodeIdentity>,
+        peer_manager: Arc<PeerManager>,
+        metrics_collector: MetricsCollectorHandle,
+    ) -> Self
+    {
+        Self {
+            node_identity,
+            peer_manager,
+            metrics_collector,
+        }
     }
 }
 
@@ -117,7 +160,12 @@ impl<S> Layer<S> for DeserializeLayer {
     type Service = DhtDeserializeMiddleware<S>;
 
     fn layer(&self, service: S) -> Self::Service {
-        DhtDeserializeMiddleware::new(self.peer_manager.clone(), service)
+        DhtDeserializeMiddleware::new(
+            self.node_identity.clone(),
+            self.peer_manager.clone(),
+            self.metrics_collector.clone(),
+            service,
+        )
     }
 }
 
@@ -125,6 +173,7 @@ impl<S> Layer<S> for DeserializeLayer {
 mod test {
     use super::*;
     use crate::{
+        connectivity::{MetricsCollector, WireFormatCounts},
         envelope::DhtMessageFlags,
         test_utils::{
             build_peer_manager,
@@ -134,6 +183,7 @@ mod test {
             service_spy,
         },
     };
+    use std::convert::TryInto;
     use tari_comms::message::{MessageExt, MessageTag};
 
     #[tokio_macros::test_basic]
@@ -143,7 +193,10 @@ mod test {
         let node_identity = make_node_identity();
         peer_manager.add_peer(node_identity.to_peer()).await.unwrap();
 
-        let mut deserialize = DeserializeLayer::new(peer_manager).layer(spy.to_service::<PipelineError>());
+        let mut metrics_collector = MetricsCollector::spawn();
+        let our_node_identity = make_node_identity();
+        let mut deserialize = DeserializeLayer::new(our_node_identity.clone(), peer_manager, metrics_collector.clone())
+            .layer(spy.to_service::<PipelineError>());
 
         let dht_envelope = make_dht_envelope(
             &node_identity,
@@ -166,6 +219,78 @@ mod test {
 
         let msg = spy.pop_request().unwrap();
         assert_eq!(msg.body, b"A".to_vec());
-        assert_eq!(msg.dht_header, dht_envelope.header.unwrap().try_into().unwrap());
+        assert_eq!(msg.dht_header, dht_envelope.header.clone().unwrap().try_into().unwrap());
+
+        // Envelopes in the versioned wire format are also accepted
+        let header = dht_envelope.header.unwrap();
+        let bytes = codec::encode_envelope_with_format(EnvelopeWireFormat::V1, header.clone(), b"B".to_vec());
+        deserialize
+            .ready_and()
+            .await
+            .unwrap()
+            .call(make_comms_inbound_message(&node_identity, bytes.into()))
+            .await
+            .unwrap();
+
+        let msg = spy.pop_request().unwrap();
+        assert_eq!(msg.body, b"B".to_vec());
+
+        // Envelopes in the direct wire format are destined for this node
+        let bytes = codec::encode_envelope_with_format(EnvelopeWireFormat::Direct, header, b"C".to_vec());
+        deserialize
+            .ready_and()
+            .await
+            .unwrap()
+            .call(make_comms_inbound_message(&node_identity, bytes.into()))
+            .await
+            .unwrap();
+
+        let msg = spy.pop_request().unwrap();
+        assert_eq!(msg.body, b"C".to_vec());
+        assert!(msg.dht_header.destination.equals_node_identity(&our_node_identity));
+
+        let counts = metrics_collector.get_wire_format_counts().await.unwrap();
+        assert_eq!(counts, WireFormatCounts {
+            inbound_legacy: 1,
+            inbound_v1: 1,
+            inbound_direct: 1,
+            ..Default::default()
+        });
+    }
+
+    #[tokio_macros::test_basic]
+    async fn reject_corrupt_envelope() {
+        let spy = service_spy();
+        let peer_manager = build_peer_manager();
+        let node_identity = make_node_identity();
+        peer_manager.add_peer(node_identity.to_peer()).await.unwrap();
+
+        let mut metrics_collector = MetricsCollector::spawn();
+        let mut deserialize = DeserializeLayer::new(make_node_identity(), peer_manager, metrics_collector.clone())
+            .layer(spy.to_service::<PipelineError>());
+
+        let mut dht_envelope = make_dht_envelope(
+            &node_identity,
+            b"A".to_vec(),
+            DhtMessageFlags::empty(),
+            false,
+            MessageTag::new(),
+        );
+        dht_envelope.body = b"B".to_vec();
+
+        deserialize
+            .ready_and()
+            .await
+            .unwrap()
+            .call(make_comms_inbound_message(
+                &node_identity,
+                dht_envelope.to_encoded_bytes().into(),
+            ))
+            .await
+            .unwrap_err();
+
+        assert_eq!(spy.call_count(), 0);
+        let num_failures = metrics_collector.get_envelope_checksum_failures().await.unwrap();
+        assert_eq!(num_failures, 1);
     }
 }
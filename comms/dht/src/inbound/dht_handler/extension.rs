@@ -0,0 +1,247 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Handlers for extension DHT messages.
+//!
+//! Downstream crates can define their own DHT message types without changes to the DHT protocol. These messages are
+//! sent with the `DhtMessageType::Extension` message type and a body containing the custom extension type, and are
+//! dispatched by the DHT handler middleware (the same middleware that handles join and discovery messages) to the
+//! handler registered for a range that includes that type. Extension messages for which no handler is registered are
+//! discarded.
+
+use crate::inbound::DecryptedDhtMessage;
+use futures::{future::BoxFuture, Future, FutureExt};
+use std::{
+    fmt,
+    ops::RangeInclusive,
+    sync::{Arc, RwLock},
+};
+use tari_comms::pipeline::PipelineError;
+use thiserror::Error;
+
+/// Identifies a handler registered with `DhtMessageHandlers`
+pub type DhtMessageHandlerId = u64;
+
+/// Handles extension DHT messages of the types for which it is registered.
+///
+/// This is implemented for async functions and closures that take the extension type, the message body and the
+/// message.
+pub trait DhtMessageHandler: Send + Sync + 'static {
+    fn handle(
+        &self,
+        extension_type: u32,
+        body: Vec<u8>,
+        message: DecryptedDhtMessage,
+    ) -> BoxFuture<'static, Result<(), PipelineError>>;
+}
+
+impl<F, Fut> DhtMessageHandler for F
+where
+    F: Fn(u32, Vec<u8>, DecryptedDhtMessage) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), PipelineError>> + Send + 'static,
+{
+    fn handle(
+        &self,
+        extension_type: u32,
+        body: Vec<u8>,
+        message: DecryptedDhtMessage,
+    ) -> BoxFuture<'static, Result<(), PipelineError>>
+    {
+        (self)(extension_type, body, message).boxed()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DhtMessageHandlerError {
+    #[error("The extension type range {start}..={end} is empty")]
+    EmptyRange { start: u32, end: u32 },
+    #[error("The extension type range {start}..={end} overlaps the range of handler {id}")]
+    OverlappingRange {
+        start: u32,
+        end: u32,
+        id: DhtMessageHandlerId,
+    },
+}
+
+/// The set of extension message handlers registered with this node. Each handler is registered for a range of
+/// extension types and ranges may not overlap, so every extension type has at most one handler.
+///
+/// This is cheap to clone and all clones share the same handlers.
+#[derive(Clone, Default)]
+pub struct DhtMessageHandlers {
+    inner: Arc<RwLock<HandlersInner>>,
+}
+
+#[derive(Default)]
+struct HandlersInner {
+    next_id: DhtMessageHandlerId,
+    handlers: Vec<RegisteredHandler>,
+}
+
+struct RegisteredHandler {
+    id: DhtMessageHandlerId,
+    range: RangeInclusive<u32>,
+    handler: Arc<dyn DhtMessageHandler>,
+}
+
+impl DhtMessageHandlers {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers a handler for the given range of extension types, returning an id that can be used to remove it
+    pub fn register<H>(
+        &self,
+        range: RangeInclusive<u32>,
+        handler: H,
+    ) -> Result<DhtMessageHandlerId, DhtMessageHandlerError>
+    where
+        H: DhtMessageHandler,
+    {
+        let (start, end) = (*range.start(), *range.end());
+        if start > end {
+            return Err(DhtMessageHandlerError::EmptyRange { start, end });
+        }
+
+        let mut inner = acquire_lock!(self.inner, write);
+        if let Some(existing) = inner
+            .handlers
+            .iter()
+            .find(|h| start <= *h.range.end() && *h.range.start() <= end)
+        {
+            return Err(DhtMessageHandlerError::OverlappingRange {
+                start,
+                end,
+                id: existing.id,
+            });
+        }
+
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.handlers.push(RegisteredHandler {
+            id,
+            range,
+            handler: Arc::new(handler),
+        });
+        Ok(id)
+    }
+
+    /// Removes a handler. Returns true if the handler was registered.
+    pub fn remove(&self, id: DhtMessageHandlerId) -> bool {
+        let mut inner = acquire_lock!(self.inner, write);
+        let len = inner.handlers.len();
+        inner.handlers.retain(|h| h.id != id);
+        inner.handlers.len() < len
+    }
+
+    /// Returns the handler registered for the given extension type, if any
+    pub fn get(&self, extension_type: u32) -> Option<Arc<dyn DhtMessageHandler>> {
+        acquire_lock!(self.inner, read)
+            .handlers
+            .iter()
+            .find(|h| h.range.contains(&extension_type))
+            .map(|h| Arc::clone(&h.handler))
+    }
+
+    /// Returns the registered extension type ranges
+    pub fn ranges(&self) -> Vec<(DhtMessageHandlerId, RangeInclusive<u32>)> {
+        acquire_lock!(self.inner, read)
+            .handlers
+            .iter()
+            .map(|h| (h.id, h.range.clone()))
+            .collect()
+    }
+}
+
+impl fmt::Debug for DhtMessageHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DhtMessageHandlers")
+            .field("ranges", &self.ranges())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        envelope::DhtMessageFlags,
+        test_utils::{make_dht_inbound_message, make_node_identity},
+    };
+    use futures::future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn noop_handler(_: u32, _: Vec<u8>, _: DecryptedDhtMessage) -> future::Ready<Result<(), PipelineError>> {
+        future::ready(Ok(()))
+    }
+
+    #[test]
+    fn register_and_remove() {
+        let handlers = DhtMessageHandlers::new();
+        let id = handlers.register(100..=199, noop_handler).unwrap();
+        handlers.register(200..=200, noop_handler).unwrap();
+        assert!(handlers.get(99).is_none());
+        assert!(handlers.get(100).is_some());
+        assert!(handlers.get(200).is_some());
+        assert!(handlers.get(201).is_none());
+
+        let err = handlers.register(150..=250, noop_handler).unwrap_err();
+        assert!(matches!(err, DhtMessageHandlerError::OverlappingRange { id: existing, .. } if existing == id));
+        let (start, end) = (10, 9);
+        let err = handlers.register(start..=end, noop_handler).unwrap_err();
+        assert!(matches!(err, DhtMessageHandlerError::EmptyRange { start: 10, end: 9 }));
+
+        assert!(handlers.remove(id));
+        assert!(!handlers.remove(id));
+        assert!(handlers.get(100).is_none());
+        handlers.register(150..=199, noop_handler).unwrap();
+        assert_eq!(handlers.ranges().len(), 2);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn handler_is_called() {
+        let handlers = DhtMessageHandlers::new();
+        let num_calls = Arc::new(AtomicUsize::new(0));
+        let calls = num_calls.clone();
+        handlers
+            .register(
+                1000..=1000,
+                move |extension_type: u32, body: Vec<u8>, _: DecryptedDhtMessage| {
+                    assert_eq!(extension_type, 1000);
+                    assert_eq!(body, b"ext".to_vec());
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    future::ready(Ok(()))
+                },
+            )
+            .unwrap();
+
+        let inbound_msg = make_dht_inbound_message(&make_node_identity(), b"".to_vec(), DhtMessageFlags::NONE, false);
+        let msg = DecryptedDhtMessage::failed(inbound_msg);
+        handlers
+            .get(1000)
+            .unwrap()
+            .handle(1000, b"ext".to_vec(), msg)
+            .await
+            .unwrap();
+        assert_eq!(num_calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -20,32 +20,41 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use super::middleware::DhtHandlerMiddleware;
-use crate::{discovery::DhtDiscoveryRequester, outbound::OutboundMessageRequester};
+use super::{middleware::DhtHandlerMiddleware, DhtMessageHandlers};
+use crate::{discovery::DhtDiscoveryRequester, event::DhtEventSender, outbound::OutboundMessageRequester, DhtConfig};
 use std::sync::Arc;
 use tari_comms::peer_manager::{NodeIdentity, PeerManager};
 use tower::layer::Layer;
 
 pub struct DhtHandlerLayer {
+    config: DhtConfig,
     peer_manager: Arc<PeerManager>,
     node_identity: Arc<NodeIdentity>,
     outbound_service: OutboundMessageRequester,
     discovery_requester: DhtDiscoveryRequester,
+    message_handlers: DhtMessageHandlers,
+    event_publisher: DhtEventSender,
 }
 
 impl DhtHandlerLayer {
     pub fn new(
+        config: DhtConfig,
         node_identity: Arc<NodeIdentity>,
         peer_manager: Arc<PeerManager>,
         discovery_requester: DhtDiscoveryRequester,
         outbound_service: OutboundMessageRequester,
+        message_handlers: DhtMessageHandlers,
+        event_publisher: DhtEventSender,
     ) -> Self
     {
         Self {
+            config,
             node_identity,
             peer_manager,
             discovery_requester,
             outbound_service,
+            message_handlers,
+            event_publisher,
         }
     }
 }
@@ -56,10 +65,13 @@ impl<S> Layer<S> for DhtHandlerLayer {
     fn layer(&self, service: S) -> Self::Service {
         DhtHandlerMiddleware::new(
             service,
+            self.config.clone(),
             Arc::clone(&self.node_identity),
             Arc::clone(&self.peer_manager),
             self.outbound_service.clone(),
             self.discovery_requester.clone(),
+            self.message_handlers.clone(),
+            self.event_publisher.clone(),
         )
     }
 }
@@ -20,8 +20,14 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use super::task::ProcessDhtMessage;
-use crate::{discovery::DhtDiscoveryRequester, inbound::DecryptedDhtMessage, outbound::OutboundMessageRequester};
+use super::{task::ProcessDhtMessage, DhtMessageHandlers};
+use crate::{
+    discovery::DhtDiscoveryRequester,
+    event::DhtEventSender,
+    inbound::DecryptedDhtMessage,
+    outbound::OutboundMessageRequester,
+    DhtConfig,
+};
 use futures::{task::Context, Future};
 use std::{sync::Arc, task::Poll};
 use tari_comms::{
@@ -33,28 +39,37 @@ use tower::Service;
 #[derive(Clone)]
 pub struct DhtHandlerMiddleware<S> {
     next_service: S,
+    config: DhtConfig,
     peer_manager: Arc<PeerManager>,
     node_identity: Arc<NodeIdentity>,
     outbound_service: OutboundMessageRequester,
     discovery_requester: DhtDiscoveryRequester,
+    message_handlers: DhtMessageHandlers,
+    event_publisher: DhtEventSender,
 }
 
 impl<S> DhtHandlerMiddleware<S> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         next_service: S,
+        config: DhtConfig,
         node_identity: Arc<NodeIdentity>,
         peer_manager: Arc<PeerManager>,
         outbound_service: OutboundMessageRequester,
-
         discovery_requester: DhtDiscoveryRequester,
+        message_handlers: DhtMessageHandlers,
+        event_publisher: DhtEventSender,
     ) -> Self
     {
         Self {
             next_service,
+            config,
             node_identity,
             peer_manager,
             outbound_service,
             discovery_requester,
+            message_handlers,
+            event_publisher,
         }
     }
 }
@@ -74,10 +89,13 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError> + Cl
     fn call(&mut self, message: DecryptedDhtMessage) -> Self::Future {
         ProcessDhtMessage::new(
             self.next_service.clone(),
+            self.config.clone(),
             Arc::clone(&self.peer_manager),
             self.outbound_service.clone(),
             Arc::clone(&self.node_identity),
             self.discovery_requester.clone(),
+            self.message_handlers.clone(),
+            self.event_publisher.clone(),
             message,
         )
         .run()
@@ -20,8 +20,10 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+mod extension;
 mod layer;
 mod middleware;
 mod task;
 
+pub use extension::{DhtMessageHandler, DhtMessageHandlerError, DhtMessageHandlerId, DhtMessageHandlers};
 pub use layer::DhtHandlerLayer;
@@ -23,20 +23,32 @@
 use crate::{
     discovery::DhtDiscoveryRequester,
     envelope::NodeDestination,
-    inbound::{error::DhtInboundError, message::DecryptedDhtMessage},
+    event::{DhtEvent, DhtEventSender, PeerSeenSource},
+    inbound::{dht_handler::DhtMessageHandlers, error::DhtInboundError, message::DecryptedDhtMessage},
     outbound::{OutboundMessageRequester, SendMessageParams},
     proto::{
-        dht::{DiscoveryMessage, DiscoveryResponseMessage, JoinMessage},
+        dht::{
+            DiscoveryHintMessage,
+            DiscoveryMessage,
+            DiscoveryResponseMessage,
+            ExtensionMessage,
+            JoinMessage,
+            PeerAddressUpdateMessage,
+        },
         envelope::DhtMessageType,
     },
+    DhtConfig,
 };
+use chrono::Utc;
 use log::*;
 use std::sync::Arc;
 use tari_comms::{
     message::MessageExt,
     peer_manager::{NodeId, NodeIdentity, PeerFeatures, PeerManager},
     pipeline::PipelineError,
+    protocol::SignedPeerIdentity,
     types::CommsPublicKey,
+    validate_peer_addresses,
 };
 use tari_utilities::{hex::Hex, ByteArray};
 use tower::{Service, ServiceExt};
@@ -45,31 +57,41 @@ const LOG_TARGET: &str = "comms::dht::dht_handler";
 
 pub struct ProcessDhtMessage<S> {
     next_service: S,
+    config: DhtConfig,
     peer_manager: Arc<PeerManager>,
     outbound_service: OutboundMessageRequester,
     node_identity: Arc<NodeIdentity>,
     message: Option<DecryptedDhtMessage>,
     discovery_requester: DhtDiscoveryRequester,
+    message_handlers: DhtMessageHandlers,
+    event_publisher: DhtEventSender,
 }
 
 impl<S> ProcessDhtMessage<S>
 where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         next_service: S,
+        config: DhtConfig,
         peer_manager: Arc<PeerManager>,
         outbound_service: OutboundMessageRequester,
         node_identity: Arc<NodeIdentity>,
         discovery_requester: DhtDiscoveryRequester,
+        message_handlers: DhtMessageHandlers,
+        event_publisher: DhtEventSender,
         message: DecryptedDhtMessage,
     ) -> Self
     {
         Self {
             next_service,
+            config,
             peer_manager,
             outbound_service,
             node_identity,
             discovery_requester,
+            message_handlers,
+            event_publisher,
             message: Some(message),
         }
     }
@@ -101,6 +123,9 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             DhtMessageType::Join => self.handle_join(message).await?,
             DhtMessageType::Discovery => self.handle_discover(message).await?,
             DhtMessageType::DiscoveryResponse => self.handle_discover_response(message).await?,
+            DhtMessageType::DiscoveryHint => self.handle_discovery_hint(message).await?,
+            DhtMessageType::PeerAddressUpdate => self.handle_peer_address_update(message).await?,
+            DhtMessageType::Extension => self.handle_extension(message).await?,
             // Not a DHT message, call downstream middleware
             _ => {
                 trace!(
@@ -130,6 +155,15 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
         }
     }
 
+    /// Let subscribers know that the given peer was active on the network just now. Stored messages are not reported
+    /// here, the store and forward service reports them with the time that they were stored.
+    fn publish_peer_seen(&self, public_key: CommsPublicKey, source: PeerSeenSource) {
+        // An error only means that there are no subscribers
+        let _ = self
+            .event_publisher
+            .send(Arc::new(DhtEvent::PeerSeen(public_key, source, Utc::now())));
+    }
+
     async fn handle_join(&mut self, message: DecryptedDhtMessage) -> Result<(), DhtInboundError> {
         let DecryptedDhtMessage {
             decryption_result,
@@ -171,7 +205,7 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
 
         let node_id = self.validate_raw_node_id(&authenticated_pk, &join_msg.node_id)?;
 
-        let origin_peer = self
+        let mut origin_peer = self
             .peer_manager
             .add_or_update_online_peer(
                 &authenticated_pk,
@@ -181,6 +215,15 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             )
             .await?;
 
+        if !join_msg.metadata.is_empty() {
+            origin_peer.set_advertised_metadata(join_msg.metadata.into_iter().map(|entry| (entry.key, entry.value)));
+            self.peer_manager.add_peer(origin_peer.clone()).await?;
+        }
+
+        if !is_saf_message {
+            self.publish_peer_seen(authenticated_pk, PeerSeenSource::Join);
+        }
+
         // DO NOT propagate this peer if this node has banned them
         if origin_peer.is_banned() {
             debug!(
@@ -250,9 +293,192 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             .notify_discovery_response_received(discover_msg)
             .await?;
 
+        if !message.is_saf_message {
+            if let Some(authenticated_pk) = message.authenticated_origin {
+                self.publish_peer_seen(authenticated_pk, PeerSeenSource::Discovery);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_discovery_hint(&mut self, message: DecryptedDhtMessage) -> Result<(), DhtInboundError> {
+        trace!(
+            target: LOG_TARGET,
+            "Received discovery hint message from {}",
+            message.source_peer.node_id.short_str()
+        );
+
+        let msg = message
+            .success()
+            .expect("already checked that this message decrypted successfully");
+
+        let hint_msg = msg
+            .decode_part::<DiscoveryHintMessage>(0)?
+            .ok_or_else(|| DhtInboundError::InvalidMessageBody)?;
+
+        self.discovery_requester
+            .notify_discovery_hint_received(hint_msg)
+            .await?;
+
         Ok(())
     }
 
+    async fn handle_peer_address_update(&mut self, message: DecryptedDhtMessage) -> Result<(), DhtInboundError> {
+        let DecryptedDhtMessage {
+            decryption_result,
+            dht_header,
+            source_peer,
+            is_saf_message,
+            ..
+        } = message;
+
+        let body = decryption_result.expect("already checked that this message decrypted successfully");
+        let update_msg = body
+            .decode_part::<PeerAddressUpdateMessage>(0)?
+            .ok_or_else(|| DhtInboundError::InvalidMessageBody)?;
+
+        let public_key = CommsPublicKey::from_bytes(&update_msg.public_key)
+            .map_err(|_| DhtInboundError::InvalidPeerAddressUpdate("Invalid public key".to_string()))?;
+        if &public_key == self.node_identity.public_key() {
+            debug!(
+                target: LOG_TARGET,
+                "Received an address update for this node. Discarding it."
+            );
+            return Ok(());
+        }
+
+        // The update may be relayed by any node, so it is only trusted if it was signed by the peer
+        let identity = SignedPeerIdentity::decode(public_key, &update_msg.identity)
+            .map_err(|err| DhtInboundError::InvalidPeerAddressUpdate(err.to_string()))?;
+        if !identity.is_valid() {
+            return Err(DhtInboundError::InvalidPeerAddressUpdate(
+                "Invalid signature".to_string(),
+            ));
+        }
+        let node_id = self.validate_raw_node_id(identity.public_key(), identity.raw_node_id())?;
+
+        let max_age = chrono::Duration::from_std(self.config.peer_address_update_max_age)
+            .unwrap_or_else(|_| chrono::Duration::max_value());
+        let timestamp = match identity.timestamp() {
+            Some(timestamp) if Utc::now() - timestamp <= max_age => timestamp.naive_utc(),
+            _ => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Discarding address update for peer '{}' because it is too old or has no timestamp",
+                    node_id.short_str()
+                );
+                return Ok(());
+            },
+        };
+
+        let addresses = identity.addresses();
+        if addresses.is_empty() || validate_peer_addresses(&addresses, self.config.allow_test_addresses).is_err() {
+            return Err(DhtInboundError::InvalidAddresses);
+        }
+
+        // Only the addresses of known peers are updated
+        let mut peer = match self.peer_manager.find_by_public_key(identity.public_key()).await {
+            Ok(peer) => peer,
+            Err(err) if err.is_peer_not_found() => {
+                trace!(
+                    target: LOG_TARGET,
+                    "Discarding address update for unknown peer '{}'",
+                    node_id.short_str()
+                );
+                return Ok(());
+            },
+            Err(err) => return Err(err.into()),
+        };
+
+        if peer.is_banned() {
+            debug!(
+                target: LOG_TARGET,
+                "Discarding address update for banned peer '{}'",
+                node_id.short_str()
+            );
+            return Ok(());
+        }
+
+        // An update that is not newer than the identity last applied may be a replay intended to roll back the
+        // peer's addresses
+        if peer
+            .identity_timestamp
+            .map(|applied| timestamp <= applied)
+            .unwrap_or(false)
+        {
+            debug!(
+                target: LOG_TARGET,
+                "Discarding address update for peer '{}' because it is not newer than the last applied identity",
+                node_id.short_str()
+            );
+            return Ok(());
+        }
+
+        if !peer.addresses.update_advertised_addresses(addresses) {
+            trace!(
+                target: LOG_TARGET,
+                "Address update for peer '{}' did not change its addresses",
+                node_id.short_str()
+            );
+            return Ok(());
+        }
+        peer.identity_timestamp = Some(timestamp);
+
+        debug!(
+            target: LOG_TARGET,
+            "Updated addresses of peer '{}' from address update relayed by '{}'",
+            node_id.short_str(),
+            source_peer.node_id.short_str()
+        );
+        self.peer_manager.add_peer(peer).await?;
+
+        // Updates that change our view of the peer are relayed further so that the network converges on the new
+        // addresses. Updates that change nothing stop here.
+        if self.config.propagate_peer_address_updates && !is_saf_message {
+            self.outbound_service
+                .send_raw(
+                    SendMessageParams::new()
+                        .propagate(node_id.clone().into(), vec![node_id, source_peer.node_id.clone()])
+                        .with_dht_header(dht_header)
+                        .finish(),
+                    body.to_encoded_bytes(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_extension(&mut self, message: DecryptedDhtMessage) -> Result<(), PipelineError> {
+        let ExtensionMessage { extension_type, body } = message
+            .success()
+            .expect("already checked that this message decrypted successfully")
+            .decode_part::<ExtensionMessage>(0)?
+            .ok_or_else(|| DhtInboundError::InvalidMessageBody)?;
+
+        match self.message_handlers.get(extension_type) {
+            Some(handler) => {
+                trace!(
+                    target: LOG_TARGET,
+                    "Dispatching extension message type {} to registered handler (Trace: {})",
+                    extension_type,
+                    message.dht_header.message_tag
+                );
+                handler.handle(extension_type, body, message).await
+            },
+            None => {
+                debug!(
+                    target: LOG_TARGET,
+                    "No handler registered for extension message type {}. Discarding message (Trace: {})",
+                    extension_type,
+                    message.dht_header.message_tag
+                );
+                Ok(())
+            },
+        }
+    }
+
     async fn handle_discover(&mut self, message: DecryptedDhtMessage) -> Result<(), DhtInboundError> {
         let msg = message
             .success()
@@ -292,6 +518,10 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             )
             .await?;
 
+        if !message.is_saf_message {
+            self.publish_peer_seen(authenticated_pk.clone(), PeerSeenSource::Discovery);
+        }
+
         // Don't send a join request to the origin peer if they are banned
         if origin_peer.is_banned() {
             warn!(
@@ -318,7 +548,12 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
     {
         let response = DiscoveryResponseMessage {
             node_id: self.node_identity.node_id().to_vec(),
-            addresses: vec![self.node_identity.public_address().to_string()],
+            addresses: self
+                .node_identity
+                .public_addresses()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
             peer_features: self.node_identity.features().bits(),
             nonce,
         };
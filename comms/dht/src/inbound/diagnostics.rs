@@ -0,0 +1,314 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    envelope::{DhtMessageFlags, DhtMessageType, NodeDestination},
+    inbound::DhtInboundMessage,
+};
+use chrono::{DateTime, Utc};
+use futures::{task::Context, Future};
+use log::*;
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, Instant},
+};
+use tari_comms::{message::MessageTag, peer_manager::NodeId, pipeline::PipelineError};
+use tower::{layer::Layer, Service, ServiceExt};
+
+const LOG_TARGET: &str = "comms::dht::inbound::diagnostics";
+
+/// A structured diagnostic record of an inbound message that failed terminally in the pipeline
+#[derive(Debug, Clone)]
+pub struct PipelineErrorRecord {
+    /// The time at which the error was returned
+    pub timestamp: DateTime<Utc>,
+    pub tag: MessageTag,
+    /// The peer that sent the message
+    pub source_peer: NodeId,
+    pub version: u32,
+    pub message_type: DhtMessageType,
+    pub destination: NodeDestination,
+    pub flags: DhtMessageFlags,
+    pub message_tag: MessageTag,
+    pub sequence: u64,
+    pub is_saf_message: bool,
+    /// Size of the message body in bytes
+    pub body_size: usize,
+    /// Time spent in the pipeline layers following deserialization before the error was returned
+    pub elapsed: Duration,
+    /// The error followed by each of its causes
+    pub error_chain: Vec<String>,
+}
+
+impl PipelineErrorRecord {
+    /// Captures the message fields. The elapsed time and error chain are filled in if the message fails.
+    fn from_message(message: &DhtInboundMessage) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            tag: message.tag,
+            source_peer: message.source_peer.node_id.clone(),
+            version: message.version,
+            message_type: message.dht_header.message_type,
+            destination: message.dht_header.destination.clone(),
+            flags: message.dht_header.flags,
+            message_tag: message.dht_header.message_tag,
+            sequence: message.dht_header.sequence,
+            is_saf_message: message.is_saf_message,
+            body_size: message.body.len(),
+            elapsed: Duration::from_secs(0),
+            error_chain: Vec::new(),
+        }
+    }
+
+    fn with_error(mut self, elapsed: Duration, err: &PipelineError) -> Self {
+        self.timestamp = Utc::now();
+        self.elapsed = elapsed;
+        self.error_chain = err.chain().map(ToString::to_string).collect();
+        self
+    }
+}
+
+impl fmt::Display for PipelineErrorRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "[{}] {} from peer {} (Trace: {})",
+            self.timestamp, self.tag, self.source_peer, self.message_tag
+        )?;
+        writeln!(
+            f,
+            "  Header: v{}, Type: {}, Dest: {}, Flags: {:?}, Seq: {}, SAF: {}",
+            self.version, self.message_type, self.destination, self.flags, self.sequence, self.is_saf_message
+        )?;
+        writeln!(f, "  Body: {} byte(s), Elapsed: {:.2?}", self.body_size, self.elapsed)?;
+        for (i, err) in self.error_chain.iter().enumerate() {
+            if i == 0 {
+                writeln!(f, "  Error: {}", err)?;
+            } else {
+                writeln!(f, "    Caused by: {}", err)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A bounded, rolling log of inbound messages that failed in the pipeline. Once the log is at capacity, the oldest
+/// record is discarded for each new record.
+///
+/// This is cheap to clone and all clones share the same log.
+#[derive(Debug, Clone)]
+pub struct PipelineErrorLog {
+    records: Arc<Mutex<VecDeque<PipelineErrorRecord>>>,
+    capacity: usize,
+}
+
+impl PipelineErrorLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn record(&self, record: PipelineErrorRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = acquire_lock!(self.records);
+        if records.len() >= self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Returns at most `limit` of the most recent records, most recent first
+    pub fn records(&self, limit: Option<usize>) -> Vec<PipelineErrorRecord> {
+        let records = acquire_lock!(self.records);
+        records
+            .iter()
+            .rev()
+            .take(limit.unwrap_or(std::usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        acquire_lock!(self.records).clear();
+    }
+
+    pub fn len(&self) -> usize {
+        acquire_lock!(self.records).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// # Pipeline diagnostics middleware
+///
+/// Records a `PipelineErrorRecord` in the `PipelineErrorLog` for every message for which the rest of the pipeline
+/// returns an error. The error is passed on unchanged. Messages pass straight through if the log is disabled.
+#[derive(Clone)]
+pub struct PipelineDiagnostics<S> {
+    next_service: S,
+    log: PipelineErrorLog,
+}
+
+impl<S> PipelineDiagnostics<S> {
+    pub fn new(service: S, log: PipelineErrorLog) -> Self {
+        Self {
+            next_service: service,
+            log,
+        }
+    }
+}
+
+impl<S> Service<DhtInboundMessage> for PipelineDiagnostics<S>
+where S: Service<DhtInboundMessage, Response = (), Error = PipelineError> + Clone + 'static
+{
+    type Error = PipelineError;
+    type Response = ();
+
+    type Future = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, message: DhtInboundMessage) -> Self::Future {
+        let next_service = self.next_service.clone();
+        let log = self.log.clone();
+        async move {
+            if !log.is_enabled() {
+                return next_service.oneshot(message).await;
+            }
+
+            let record = PipelineErrorRecord::from_message(&message);
+            let timer = Instant::now();
+            match next_service.oneshot(message).await {
+                Ok(_) => Ok(()),
+                Err(err) => {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Recording pipeline error for message {} (Trace: {}): {}", record.tag, record.message_tag, err
+                    );
+                    log.record(record.with_error(timer.elapsed(), &err));
+                    Err(err)
+                },
+            }
+        }
+    }
+}
+
+pub struct PipelineDiagnosticsLayer {
+    log: PipelineErrorLog,
+}
+
+impl PipelineDiagnosticsLayer {
+    pub fn new(log: PipelineErrorLog) -> Self {
+        Self { log }
+    }
+}
+
+impl<S> Layer<S> for PipelineDiagnosticsLayer {
+    type Service = PipelineDiagnostics<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        PipelineDiagnostics::new(service, self.log.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{make_dht_inbound_message, make_node_identity, service_spy};
+    use futures::future;
+
+    fn make_failing_service() -> impl Service<DhtInboundMessage, Response = (), Error = PipelineError> + Clone {
+        tower::service_fn(|_: DhtInboundMessage| {
+            future::ready(Err(anyhow::anyhow!("Invalid signature").context("Decryption failed")))
+        })
+    }
+
+    #[tokio_macros::test_basic]
+    async fn records_failed_messages() {
+        let log = PipelineErrorLog::new(10);
+        let node_identity = make_node_identity();
+        let msg = make_dht_inbound_message(&node_identity, b"test".to_vec(), DhtMessageFlags::NONE, false);
+        let tag = msg.tag;
+        let body_size = msg.body.len();
+
+        let mut service = PipelineDiagnosticsLayer::new(log.clone()).layer(make_failing_service());
+        let err = service.call(msg).await.unwrap_err();
+        assert_eq!(err.to_string(), "Decryption failed");
+
+        let records = log.records(None);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].tag, tag);
+        assert_eq!(records[0].source_peer, *node_identity.node_id());
+        assert_eq!(records[0].body_size, body_size);
+        assert_eq!(records[0].error_chain, vec!["Decryption failed", "Invalid signature"]);
+
+        let spy = service_spy();
+        let mut service = PipelineDiagnosticsLayer::new(log.clone()).layer(spy.to_service());
+        let msg = make_dht_inbound_message(&node_identity, b"test".to_vec(), DhtMessageFlags::NONE, false);
+        service.call(msg).await.unwrap();
+        assert!(spy.is_called());
+        assert_eq!(log.len(), 1);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn rolling_log() {
+        let log = PipelineErrorLog::new(2);
+        let node_identity = make_node_identity();
+        let mut service = PipelineDiagnosticsLayer::new(log.clone()).layer(make_failing_service());
+        let msgs = (0..3)
+            .map(|_| make_dht_inbound_message(&node_identity, b"test".to_vec(), DhtMessageFlags::NONE, false))
+            .collect::<Vec<_>>();
+        let tags = msgs.iter().map(|msg| msg.tag).collect::<Vec<_>>();
+        for msg in msgs {
+            service.call(msg).await.unwrap_err();
+        }
+        // Most recent first
+        let records = log.records(None).into_iter().map(|r| r.tag).collect::<Vec<_>>();
+        assert_eq!(records, vec![tags[2], tags[1]]);
+        assert_eq!(log.records(Some(1)).len(), 1);
+
+        let log = PipelineErrorLog::new(0);
+        let mut service = PipelineDiagnosticsLayer::new(log.clone()).layer(make_failing_service());
+        let msg = make_dht_inbound_message(&node_identity, b"test".to_vec(), DhtMessageFlags::NONE, false);
+        service.call(msg).await.unwrap_err();
+        assert!(log.is_empty());
+    }
+}
@@ -38,6 +38,8 @@ pub enum DhtInboundError {
     InvalidNodeId,
     #[error("All given addresses were invalid")]
     InvalidAddresses,
+    #[error("Peer address update is invalid: {0}")]
+    InvalidPeerAddressUpdate(String),
     #[error("DhtDiscoveryError: {0}")]
     DhtDiscoveryError(#[from] DhtDiscoveryError),
     #[error("OriginRequired: {0}")]
@@ -0,0 +1,581 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    event::{DhtEvent, DhtEventSender},
+    inbound::{DecryptedDhtMessage, DhtInboundMessage},
+};
+use futures::{future::BoxFuture, task::Context, FutureExt};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, Instant},
+};
+use tari_comms::{
+    message::{InboundMessage, MessageTag},
+    peer_manager::{NodeId, NodeIdentity},
+    pipeline::PipelineError,
+};
+use tower::{layer::Layer, Service, ServiceExt};
+
+const LOG_TARGET: &str = "comms::dht::inbound::latency_budget";
+
+/// The stages of the inbound pipeline that are timed by the `LatencyBudgetTracker`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PipelineStage {
+    /// Deserialization, validation, deduplication and control message routing
+    Deserialize,
+    /// Decryption and origin authentication
+    Decrypt,
+    /// Storage of messages for offline peers
+    StoreAndForward,
+    /// Forwarding of messages to other peers
+    Forward,
+    /// Store and forward, DHT and domain message handling. Time spent here is recorded but has no budget.
+    Handlers,
+}
+
+impl fmt::Display for PipelineStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Latency budgets for the inbound pipeline stages. When loaded from a configuration file, budgets are given in
+/// milliseconds.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LatencyBudgetConfig {
+    /// True to time inbound messages through each pipeline stage and emit a
+    /// `DhtEvent::PipelineLatencyBudgetExceeded` event for messages that exceed the budget of any stage.
+    /// Default: false
+    pub enabled: bool,
+    /// Default: 20ms
+    #[serde(with = "millis")]
+    pub deserialize: Duration,
+    /// Default: 20ms
+    #[serde(with = "millis")]
+    pub decrypt: Duration,
+    /// Default: 100ms
+    #[serde(with = "millis")]
+    pub store_and_forward: Duration,
+    /// Default: 50ms
+    #[serde(with = "millis")]
+    pub forward: Duration,
+    /// When true, domain messages that are destined for other nodes are discarded before decryption while the
+    /// pipeline is persistently over budget.
+    /// Default: false
+    pub shed_low_priority: bool,
+    /// The pipeline is persistently over budget once this many consecutive messages have exceeded a budget, until a
+    /// message is handled within budget.
+    /// Default: 10
+    pub shed_threshold: usize,
+}
+
+impl LatencyBudgetConfig {
+    /// Returns the budget for the given stage, or None if the stage has no budget
+    pub fn budget(&self, stage: PipelineStage) -> Option<Duration> {
+        use PipelineStage::*;
+        match stage {
+            Deserialize => Some(self.deserialize),
+            Decrypt => Some(self.decrypt),
+            StoreAndForward => Some(self.store_and_forward),
+            Forward => Some(self.forward),
+            Handlers => None,
+        }
+    }
+}
+
+impl Default for LatencyBudgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            deserialize: Duration::from_millis(20),
+            decrypt: Duration::from_millis(20),
+            store_and_forward: Duration::from_millis(100),
+            forward: Duration::from_millis(50),
+            shed_low_priority: false,
+            shed_threshold: 10,
+        }
+    }
+}
+
+/// The time a message spent in a single pipeline stage, excluding the time spent in later stages
+#[derive(Debug, Clone)]
+pub struct StageTiming {
+    pub stage: PipelineStage,
+    pub elapsed: Duration,
+    pub budget: Option<Duration>,
+}
+
+impl StageTiming {
+    pub fn is_over_budget(&self) -> bool {
+        self.budget.map(|budget| self.elapsed > budget).unwrap_or(false)
+    }
+}
+
+/// The per-stage timings of an inbound message that exceeded the latency budget of at least one pipeline stage
+#[derive(Debug, Clone)]
+pub struct PipelineLatencyReport {
+    pub tag: MessageTag,
+    /// The peer that sent the message
+    pub source_peer: NodeId,
+    /// The total time spent in the pipeline
+    pub total: Duration,
+    /// Timings for each stage that the message reached, in pipeline order. Control messages are handled on a separate
+    /// task, so only the `Deserialize` stage is timed for them.
+    pub stages: Vec<StageTiming>,
+    /// True if low-priority messages are being discarded because the pipeline is persistently over budget
+    pub is_shedding: bool,
+}
+
+impl PipelineLatencyReport {
+    pub fn over_budget_stages(&self) -> impl Iterator<Item = &StageTiming> {
+        self.stages.iter().filter(|timing| timing.is_over_budget())
+    }
+}
+
+impl fmt::Display for PipelineLatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} from peer {} took {:.2?} (",
+            self.tag,
+            self.source_peer.short_str(),
+            self.total
+        )?;
+        for (i, timing) in self.stages.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {:.2?}", timing.stage, timing.elapsed)?;
+            if let Some(budget) = timing.budget {
+                write!(f, "/{:.2?}", budget)?;
+            }
+            if timing.is_over_budget() {
+                write!(f, " OVER BUDGET")?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Default)]
+struct TrackerState {
+    in_flight: HashMap<MessageTag, InFlightMessage>,
+    num_consecutive_over_budget: usize,
+    is_shedding: bool,
+}
+
+struct InFlightMessage {
+    source_peer: NodeId,
+    /// The time spent in each stage, including the time spent in later stages
+    inclusive_timings: Vec<(PipelineStage, Duration)>,
+}
+
+/// Times inbound messages through the pipeline stages, reports messages that exceed the stage budgets and determines
+/// when low-priority messages are shed.
+///
+/// This is cheap to clone and all clones share the same state.
+#[derive(Clone)]
+pub struct LatencyBudgetTracker {
+    config: LatencyBudgetConfig,
+    node_identity: Arc<NodeIdentity>,
+    event_publisher: DhtEventSender,
+    state: Arc<Mutex<TrackerState>>,
+}
+
+impl LatencyBudgetTracker {
+    pub fn new(config: LatencyBudgetConfig, node_identity: Arc<NodeIdentity>, event_publisher: DhtEventSender) -> Self {
+        Self {
+            config,
+            node_identity,
+            event_publisher,
+            state: Default::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Returns true if low-priority messages are being discarded because the pipeline is persistently over budget
+    pub fn is_shedding(&self) -> bool {
+        acquire_lock!(self.state).is_shedding
+    }
+
+    /// Returns a layer which times the given stage. A layer must be added for each stage, in pipeline order, and the
+    /// `Deserialize` layer must be the first.
+    pub fn layer(&self, stage: PipelineStage) -> LatencyBudgetLayer {
+        LatencyBudgetLayer {
+            tracker: self.clone(),
+            stage,
+        }
+    }
+
+    fn begin(&self, tag: MessageTag, source_peer: NodeId) {
+        acquire_lock!(self.state).in_flight.insert(tag, InFlightMessage {
+            source_peer,
+            inclusive_timings: Vec::with_capacity(5),
+        });
+    }
+
+    fn record(&self, tag: MessageTag, stage: PipelineStage, elapsed: Duration) {
+        // The message is no longer tracked if the first stage has already completed (e.g. for control messages)
+        if let Some(message) = acquire_lock!(self.state).in_flight.get_mut(&tag) {
+            message.inclusive_timings.push((stage, elapsed));
+        }
+    }
+
+    fn abandon(&self, tag: MessageTag) {
+        acquire_lock!(self.state).in_flight.remove(&tag);
+    }
+
+    fn finish(&self, tag: MessageTag, total: Duration) {
+        let mut state = acquire_lock!(self.state);
+        let message = match state.in_flight.remove(&tag) {
+            Some(m) => m,
+            None => return,
+        };
+        let stages = self.to_stage_timings(message.inclusive_timings);
+        if !stages.iter().any(StageTiming::is_over_budget) {
+            state.num_consecutive_over_budget = 0;
+            if state.is_shedding {
+                info!(
+                    target: LOG_TARGET,
+                    "Inbound pipeline is within budget. No longer shedding messages"
+                );
+                state.is_shedding = false;
+            }
+            return;
+        }
+
+        state.num_consecutive_over_budget += 1;
+        if self.config.shed_low_priority &&
+            !state.is_shedding &&
+            state.num_consecutive_over_budget >= self.config.shed_threshold
+        {
+            warn!(
+                target: LOG_TARGET,
+                "Inbound pipeline exceeded its latency budget for {} consecutive messages. Shedding low-priority \
+                 messages",
+                state.num_consecutive_over_budget
+            );
+            state.is_shedding = true;
+        }
+
+        let report = PipelineLatencyReport {
+            tag,
+            source_peer: message.source_peer,
+            total,
+            stages,
+            is_shedding: state.is_shedding,
+        };
+        drop(state);
+        debug!(target: LOG_TARGET, "Latency budget exceeded: {}", report);
+        let _ = self
+            .event_publisher
+            .send(Arc::new(DhtEvent::PipelineLatencyBudgetExceeded(report)));
+    }
+
+    /// Converts the inclusive stage timings into the time spent in each stage alone
+    fn to_stage_timings(&self, mut inclusive_timings: Vec<(PipelineStage, Duration)>) -> Vec<StageTiming> {
+        inclusive_timings.sort_by_key(|(stage, _)| *stage);
+        let mut stages = Vec::with_capacity(inclusive_timings.len());
+        for (i, (stage, inclusive)) in inclusive_timings.iter().enumerate() {
+            let later = inclusive_timings
+                .get(i + 1)
+                .map(|(_, elapsed)| *elapsed)
+                .unwrap_or_default();
+            stages.push(StageTiming {
+                stage: *stage,
+                elapsed: inclusive.checked_sub(later).unwrap_or_default(),
+                budget: self.config.budget(*stage),
+            });
+        }
+        stages
+    }
+}
+
+/// Removes a message from the tracker if the pipeline future is dropped before the message has been handled
+struct InFlightGuard<'a> {
+    tracker: &'a LatencyBudgetTracker,
+    tag: MessageTag,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.tracker.abandon(self.tag);
+    }
+}
+
+/// An inbound pipeline message that can be timed by the `LatencyBudgetTracker`
+pub trait TimedMessage {
+    fn tag(&self) -> MessageTag;
+
+    fn source_node_id(&self) -> &NodeId;
+
+    /// Returns true if this message may be discarded while the pipeline is persistently over budget
+    fn is_low_priority(&self, _node_identity: &NodeIdentity) -> bool {
+        false
+    }
+}
+
+impl TimedMessage for InboundMessage {
+    fn tag(&self) -> MessageTag {
+        self.tag
+    }
+
+    fn source_node_id(&self) -> &NodeId {
+        &self.source_peer
+    }
+}
+
+impl TimedMessage for DhtInboundMessage {
+    fn tag(&self) -> MessageTag {
+        self.tag
+    }
+
+    fn source_node_id(&self) -> &NodeId {
+        &self.source_peer.node_id
+    }
+
+    fn is_low_priority(&self, node_identity: &NodeIdentity) -> bool {
+        let destination = &self.dht_header.destination;
+        self.dht_header.message_type.is_domain_message() &&
+            !destination.is_unknown() &&
+            !destination.includes_node_identity(node_identity)
+    }
+}
+
+impl TimedMessage for DecryptedDhtMessage {
+    fn tag(&self) -> MessageTag {
+        self.tag
+    }
+
+    fn source_node_id(&self) -> &NodeId {
+        &self.source_peer.node_id
+    }
+}
+
+/// # Latency budget middleware
+///
+/// Times the pipeline stage that follows this service. Messages pass straight through if latency budgets are
+/// disabled.
+///
+/// The rest of the pipeline and its futures are boxed, so that each stage boundary splits the (large) nested services
+/// and futures of the inbound pipeline. Without this, the pipeline overflows a 2MiB thread stack in debug builds.
+#[derive(Clone)]
+pub struct LatencyBudget<S> {
+    next_service: Box<S>,
+    tracker: LatencyBudgetTracker,
+    stage: PipelineStage,
+}
+
+impl<S, T> Service<T> for LatencyBudget<S>
+where
+    S: Service<T, Response = (), Error = PipelineError> + Clone + Send + 'static,
+    S::Future: Send,
+    T: TimedMessage + Send + 'static,
+{
+    type Error = PipelineError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = ();
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, message: T) -> Self::Future {
+        let next_service = self.next_service.clone();
+        let tracker = self.tracker.clone();
+        let stage = self.stage;
+        async move {
+            if !tracker.is_enabled() {
+                return next_service.oneshot(message).await;
+            }
+
+            if tracker.is_shedding() && message.is_low_priority(&tracker.node_identity) {
+                debug!(
+                    target: LOG_TARGET,
+                    "Inbound pipeline is over budget. Discarding low-priority message {}",
+                    message.tag()
+                );
+                return Ok(());
+            }
+
+            let tag = message.tag();
+            let is_first_stage = stage == PipelineStage::Deserialize;
+            let _guard = if is_first_stage {
+                tracker.begin(tag, message.source_node_id().clone());
+                Some(InFlightGuard { tracker: &tracker, tag })
+            } else {
+                None
+            };
+
+            let timer = Instant::now();
+            let result = next_service.oneshot(message).await;
+            let elapsed = timer.elapsed();
+            tracker.record(tag, stage, elapsed);
+            if is_first_stage {
+                tracker.finish(tag, elapsed);
+            }
+            result
+        }
+        .boxed()
+    }
+}
+
+pub struct LatencyBudgetLayer {
+    tracker: LatencyBudgetTracker,
+    stage: PipelineStage,
+}
+
+impl<S> Layer<S> for LatencyBudgetLayer {
+    type Service = LatencyBudget<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        LatencyBudget {
+            next_service: Box::new(service),
+            tracker: self.tracker.clone(),
+            stage: self.stage,
+        }
+    }
+}
+
+mod millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where D: Deserializer<'de> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+
+    pub fn serialize<S>(duration: &Duration, s: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        s.serialize_u64(duration.as_millis() as u64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        envelope::DhtMessageFlags,
+        test_utils::{make_comms_inbound_message, make_dht_inbound_message, make_node_identity, service_spy},
+    };
+    use tari_test_utils::unpack_enum;
+    use tokio::{sync::broadcast, time};
+    use tower::service_fn;
+
+    #[tokio_macros::test_basic]
+    async fn reports_stage_over_budget() {
+        let node_identity = make_node_identity();
+        let (event_publisher, mut event_rx) = broadcast::channel(10);
+        let config = LatencyBudgetConfig {
+            enabled: true,
+            decrypt: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let tracker = LatencyBudgetTracker::new(config, node_identity.clone(), event_publisher);
+
+        let decrypt_stage = tracker
+            .layer(PipelineStage::Decrypt)
+            .layer(service_fn(|_: DhtInboundMessage| async {
+                time::delay_for(Duration::from_millis(20)).await;
+                Result::<_, PipelineError>::Ok(())
+            }));
+        let node_identity_clone = node_identity.clone();
+        let mut service =
+            tracker
+                .layer(PipelineStage::Deserialize)
+                .layer(service_fn(move |inbound: InboundMessage| {
+                    let mut msg =
+                        make_dht_inbound_message(&node_identity_clone, vec![], DhtMessageFlags::empty(), false);
+                    msg.tag = inbound.tag;
+                    decrypt_stage.clone().oneshot(msg)
+                }));
+
+        service
+            .call(make_comms_inbound_message(&node_identity, b"".to_vec().into()))
+            .await
+            .unwrap();
+
+        let event = event_rx.recv().await.unwrap();
+        unpack_enum!(DhtEvent::PipelineLatencyBudgetExceeded(report) = &*event);
+        assert_eq!(report.stages.len(), 2);
+        assert_eq!(report.stages[0].stage, PipelineStage::Deserialize);
+        assert!(!report.stages[0].is_over_budget());
+        assert_eq!(report.stages[1].stage, PipelineStage::Decrypt);
+        assert!(report.stages[1].is_over_budget());
+        assert_eq!(report.over_budget_stages().count(), 1);
+        assert!(!report.is_shedding);
+        assert!(!tracker.is_shedding());
+    }
+
+    #[tokio_macros::test_basic]
+    async fn sheds_low_priority_messages() {
+        let node_identity = make_node_identity();
+        let (event_publisher, _event_rx) = broadcast::channel(10);
+        let config = LatencyBudgetConfig {
+            enabled: true,
+            deserialize: Duration::from_millis(0),
+            shed_low_priority: true,
+            shed_threshold: 1,
+            ..Default::default()
+        };
+        let tracker = LatencyBudgetTracker::new(config, node_identity.clone(), event_publisher);
+
+        let mut service = tracker
+            .layer(PipelineStage::Deserialize)
+            .layer(service_fn(|_: InboundMessage| async {
+                time::delay_for(Duration::from_millis(1)).await;
+                Result::<_, PipelineError>::Ok(())
+            }));
+        service
+            .call(make_comms_inbound_message(&node_identity, b"".to_vec().into()))
+            .await
+            .unwrap();
+        assert!(tracker.is_shedding());
+
+        let spy = service_spy();
+        let mut decrypt_stage = tracker
+            .layer(PipelineStage::Decrypt)
+            .layer(spy.to_service::<PipelineError>());
+
+        // Messages destined for other nodes are discarded
+        let mut msg = make_dht_inbound_message(&node_identity, vec![], DhtMessageFlags::empty(), false);
+        msg.dht_header.destination = make_node_identity().public_key().clone().into();
+        decrypt_stage.call(msg.clone()).await.unwrap();
+        assert_eq!(spy.call_count(), 0);
+
+        // Messages destined for this node are not
+        msg.dht_header.destination = node_identity.public_key().clone().into();
+        decrypt_stage.call(msg).await.unwrap();
+        assert_eq!(spy.call_count(), 1);
+    }
+}
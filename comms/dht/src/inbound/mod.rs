@@ -20,14 +20,36 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+mod control_channel;
+pub use control_channel::ControlChannelLayer;
+
 mod decryption;
 pub use decryption::DecryptionLayer;
 
 mod deserialize;
 pub use deserialize::DeserializeLayer;
 
+mod diagnostics;
+pub use diagnostics::{PipelineDiagnosticsLayer, PipelineErrorLog, PipelineErrorRecord};
+
 mod dht_handler;
-pub use dht_handler::DhtHandlerLayer;
+pub use dht_handler::{
+    DhtHandlerLayer,
+    DhtMessageHandler,
+    DhtMessageHandlerError,
+    DhtMessageHandlerId,
+    DhtMessageHandlers,
+};
+
+mod latency_budget;
+pub use latency_budget::{
+    LatencyBudgetConfig,
+    LatencyBudgetLayer,
+    LatencyBudgetTracker,
+    PipelineLatencyReport,
+    PipelineStage,
+    StageTiming,
+};
 
 mod metrics;
 pub use metrics::MetricsLayer;
@@ -37,5 +59,8 @@ mod error;
 mod message;
 pub use message::{DecryptedDhtMessage, DhtInboundMessage};
 
+mod sequencing;
+pub use sequencing::SequencingLayer;
+
 mod validate;
 pub use validate::ValidateLayer;
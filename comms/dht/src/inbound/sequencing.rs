@@ -0,0 +1,610 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{domain_message::MessageHeader, inbound::DecryptedDhtMessage};
+use futures::{task::Context, Future, StreamExt};
+use log::*;
+use std::{
+    cmp,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+    task::Poll,
+    time::{Duration, Instant},
+};
+use tari_comms::{peer_manager::NodeId, pipeline::PipelineError};
+use tokio::{sync::mpsc, task, time};
+use tower::{layer::Layer, Service, ServiceExt};
+
+const LOG_TARGET: &str = "comms::dht::inbound::sequencing";
+
+/// Sequencing state for an origin is discarded once no messages have been received from the origin for this long
+const IDLE_ORIGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Size of the channel used to pass sequenced messages to the reorder worker
+const SEQUENCED_MESSAGE_BUFFER_SIZE: usize = 100;
+
+/// A message that is subject to sequencing
+struct SequencedMessage {
+    origin: NodeId,
+    message_type: i32,
+    sequence: u64,
+    message: DecryptedDhtMessage,
+}
+
+/// # Sequencing middleware
+///
+/// Messages of the configured domain message types that carry a sequence number are passed to a reorder worker,
+/// which delivers messages from each origin in send order. A message that arrives ahead of its predecessor is held
+/// until the missing message arrives or the reorder timeout elapses, at which point the gap is skipped. All other
+/// messages are passed directly to the next service.
+///
+/// A single worker delivers sequenced messages to the next service one at a time, so a slow handler for one sequenced
+/// message delays the sequenced messages of every other origin and message type. Unsequenced messages are not
+/// affected.
+#[derive(Clone)]
+pub struct SequencingMiddleware<S> {
+    next_service: S,
+    message_types: Arc<HashSet<i32>>,
+    sequenced_tx: mpsc::Sender<SequencedMessage>,
+}
+
+impl<S> SequencingMiddleware<S> {
+    fn new(service: S, message_types: Arc<HashSet<i32>>, sequenced_tx: mpsc::Sender<SequencedMessage>) -> Self {
+        Self {
+            next_service: service,
+            message_types,
+            sequenced_tx,
+        }
+    }
+}
+
+impl<S> Service<DecryptedDhtMessage> for SequencingMiddleware<S>
+where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError> + Clone + 'static
+{
+    type Error = PipelineError;
+    type Response = ();
+
+    type Future = impl Future<Output = Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, message: DecryptedDhtMessage) -> Self::Future {
+        let next_service = self.next_service.clone();
+        let message_types = self.message_types.clone();
+        let mut sequenced_tx = self.sequenced_tx.clone();
+        async move {
+            let message_type = match get_sequenced_message_type(&message, &message_types) {
+                Some(t) => t,
+                None => return next_service.oneshot(message).await,
+            };
+
+            let origin = message
+                .authenticated_origin()
+                .map(NodeId::from_public_key)
+                .unwrap_or_else(|| message.source_peer.node_id.clone());
+            let sequenced = SequencedMessage {
+                origin,
+                message_type,
+                sequence: message.dht_header.sequence,
+                message,
+            };
+            sequenced_tx
+                .send(sequenced)
+                .await
+                .map_err(|_| anyhow::anyhow!("Sequencing worker has shut down"))?;
+            Ok(())
+        }
+    }
+}
+
+/// Returns the domain message type if the message should be sequenced
+fn get_sequenced_message_type(message: &DecryptedDhtMessage, message_types: &HashSet<i32>) -> Option<i32> {
+    if message_types.is_empty() || message.dht_header.sequence == 0 {
+        return None;
+    }
+    let header = message.success()?.decode_part::<MessageHeader>(0).ok()??;
+    Some(header.message_type).filter(|t| message_types.contains(t))
+}
+
+/// Reorder state for messages of a single message type from a single origin
+struct OriginSequence {
+    next_sequence: u64,
+    buffered: BTreeMap<u64, (Instant, DecryptedDhtMessage)>,
+    last_received_at: Instant,
+}
+
+impl OriginSequence {
+    /// Senders number their messages from 1, so a message received before its predecessors is held for the reorder
+    /// timeout like any other out of order message.
+    fn new() -> Self {
+        Self {
+            next_sequence: 1,
+            buffered: BTreeMap::new(),
+            last_received_at: Instant::now(),
+        }
+    }
+
+    /// Remove and return buffered messages that directly follow the last delivered message
+    fn take_ready(&mut self) -> Vec<DecryptedDhtMessage> {
+        let mut ready = Vec::new();
+        while let Some((_, msg)) = self.buffered.remove(&self.next_sequence) {
+            ready.push(msg);
+            self.next_sequence += 1;
+        }
+        ready
+    }
+
+    /// Skip the gap before the first buffered message, returning the messages that can then be delivered
+    fn skip_gap(&mut self) -> Vec<DecryptedDhtMessage> {
+        match self.buffered.keys().next() {
+            Some(first) => {
+                self.next_sequence = *first;
+                self.take_ready()
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns true if the oldest buffered message has waited for at least `timeout`. Messages after the gap can
+    /// arrive out of order, so the oldest message is not necessarily the one with the lowest sequence number.
+    fn is_gap_expired(&self, timeout: Duration) -> bool {
+        self.buffered
+            .values()
+            .map(|(received_at, _)| *received_at)
+            .min()
+            .map(|received_at| received_at.elapsed() >= timeout)
+            .unwrap_or(false)
+    }
+}
+
+/// Reorders sequenced messages and delivers them to the next service one at a time
+struct SequencingWorker<S> {
+    service: S,
+    reorder_timeout: Duration,
+    max_buffered: usize,
+    max_origins: usize,
+    max_total_buffered: usize,
+    origins: HashMap<(NodeId, i32), OriginSequence>,
+    num_buffered: usize,
+}
+
+impl<S> SequencingWorker<S>
+where
+    S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    async fn run(mut self, sequenced_rx: mpsc::Receiver<SequencedMessage>) {
+        let check_interval = cmp::max(self.reorder_timeout / 2, Duration::from_millis(10));
+        let mut ticker = time::interval(check_interval).fuse();
+        let mut sequenced_rx = sequenced_rx.fuse();
+
+        loop {
+            futures::select! {
+                msg = sequenced_rx.next() => {
+                    match msg {
+                        Some(msg) => self.handle_message(msg).await,
+                        None => break,
+                    }
+                },
+                _ = ticker.next() => {
+                    self.skip_expired_gaps().await;
+                },
+            }
+        }
+
+        debug!(target: LOG_TARGET, "Sequencing worker has shut down");
+    }
+
+    async fn handle_message(&mut self, msg: SequencedMessage) {
+        let SequencedMessage {
+            origin,
+            message_type,
+            sequence,
+            message,
+        } = msg;
+        let key = (origin.clone(), message_type);
+        if !self.origins.contains_key(&key) && !self.make_room_for_origin() {
+            debug!(
+                target: LOG_TARGET,
+                "Reorder state is held for the maximum of {} origins. Delivering message #{} of type {} from '{}' as \
+                 is",
+                self.max_origins,
+                sequence,
+                message_type,
+                origin.short_str()
+            );
+            self.deliver(vec![message]).await;
+            return;
+        }
+
+        let is_full = self.num_buffered >= self.max_total_buffered;
+        let state = self.origins.entry(key).or_insert_with(OriginSequence::new);
+        state.last_received_at = Instant::now();
+
+        if sequence < state.next_sequence {
+            // The gap before this message was already skipped, or the origin has restarted its sequence
+            debug!(
+                target: LOG_TARGET,
+                "Delivering late message #{} (expected #{}) of type {} from '{}'",
+                sequence,
+                state.next_sequence,
+                message_type,
+                origin.short_str()
+            );
+            self.deliver(vec![message]).await;
+            return;
+        }
+
+        if state.buffered.contains_key(&sequence) {
+            // Never replace a buffered message. Exact duplicates are discarded before this point, so this is a
+            // different message with the same sequence number (e.g. the origin restarted its sequence) and
+            // is delivered as is.
+            debug!(
+                target: LOG_TARGET,
+                "Delivering message with already buffered sequence #{} of type {} from '{}'",
+                sequence,
+                message_type,
+                origin.short_str()
+            );
+            self.deliver(vec![message]).await;
+            return;
+        }
+
+        state.buffered.insert(sequence, (Instant::now(), message));
+        self.num_buffered += 1;
+        let mut ready = state.take_ready();
+        if state.buffered.len() > self.max_buffered {
+            warn!(
+                target: LOG_TARGET,
+                "Reorder buffer for message type {} from '{}' is full. Skipping missing message #{}",
+                message_type,
+                origin.short_str(),
+                state.next_sequence
+            );
+            ready.extend(state.skip_gap());
+        } else if is_full && !state.buffered.is_empty() {
+            warn!(
+                target: LOG_TARGET,
+                "Reorder buffers hold the maximum of {} messages. Skipping missing message #{} of type {} from '{}'",
+                self.max_total_buffered,
+                state.next_sequence,
+                message_type,
+                origin.short_str()
+            );
+            ready.extend(state.skip_gap());
+        }
+        self.num_buffered -= ready.len();
+        self.deliver(ready).await;
+    }
+
+    /// Returns true if reorder state can be held for another origin. If the maximum number of origins is reached, the
+    /// state of the least recently active origin without buffered messages is discarded to make room.
+    fn make_room_for_origin(&mut self) -> bool {
+        if self.origins.len() < self.max_origins {
+            return true;
+        }
+        let idle_key = self
+            .origins
+            .iter()
+            .filter(|(_, state)| state.buffered.is_empty())
+            .min_by_key(|(_, state)| state.last_received_at)
+            .map(|(key, _)| key.clone());
+        match idle_key {
+            Some(key) => {
+                self.origins.remove(&key);
+                true
+            },
+            None => false,
+        }
+    }
+
+    async fn skip_expired_gaps(&mut self) {
+        let reorder_timeout = self.reorder_timeout;
+        let mut ready = Vec::new();
+        for (key, state) in self.origins.iter_mut() {
+            let (origin, message_type) = key;
+            while state.is_gap_expired(reorder_timeout) {
+                debug!(
+                    target: LOG_TARGET,
+                    "Timed out waiting for message #{} of type {} from '{}'",
+                    state.next_sequence,
+                    message_type,
+                    origin.short_str()
+                );
+                ready.extend(state.skip_gap());
+            }
+        }
+        self.origins
+            .retain(|_, state| !state.buffered.is_empty() || state.last_received_at.elapsed() < IDLE_ORIGIN_TIMEOUT);
+        self.num_buffered -= ready.len();
+        self.deliver(ready).await;
+    }
+
+    async fn deliver(&mut self, messages: Vec<DecryptedDhtMessage>) {
+        for message in messages {
+            let service = self.service.clone();
+            if let Err(err) = service.oneshot(message).await {
+                warn!(
+                    target: LOG_TARGET,
+                    "Sequenced message pipeline returned an error: '{}'", err
+                );
+            }
+        }
+    }
+}
+
+pub struct SequencingLayer {
+    message_types: Arc<HashSet<i32>>,
+    reorder_timeout: Duration,
+    max_buffered: usize,
+    max_origins: usize,
+    max_total_buffered: usize,
+}
+
+impl SequencingLayer {
+    /// Create a sequencing layer for the given domain message types. Messages of other types are not reordered.
+    ///
+    /// `max_buffered` limits the messages held for a single origin and message type, `max_origins` limits the number
+    /// of origin and message type pairs for which reorder state is held and `max_total_buffered` limits the messages
+    /// held across all origins.
+    pub fn new(
+        message_types: Vec<i32>,
+        reorder_timeout: Duration,
+        max_buffered: usize,
+        max_origins: usize,
+        max_total_buffered: usize,
+    ) -> Self
+    {
+        Self {
+            message_types: Arc::new(message_types.into_iter().collect()),
+            reorder_timeout,
+            max_buffered,
+            max_origins,
+            max_total_buffered,
+        }
+    }
+}
+
+impl<S> Layer<S> for SequencingLayer
+where
+    S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Service = SequencingMiddleware<S>;
+
+    /// Spawns the reorder worker for the given service. The worker exits once every clone of the returned middleware
+    /// has been dropped.
+    fn layer(&self, service: S) -> Self::Service {
+        let (sequenced_tx, sequenced_rx) = mpsc::channel(SEQUENCED_MESSAGE_BUFFER_SIZE);
+        if !self.message_types.is_empty() {
+            let worker = SequencingWorker {
+                service: service.clone(),
+                reorder_timeout: self.reorder_timeout,
+                max_buffered: self.max_buffered,
+                max_origins: self.max_origins,
+                max_total_buffered: self.max_total_buffered,
+                origins: HashMap::new(),
+                num_buffered: 0,
+            };
+            task::spawn(worker.run(sequenced_rx));
+        }
+        SequencingMiddleware::new(service, self.message_types.clone(), sequenced_tx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        envelope::DhtMessageFlags,
+        test_utils::{make_dht_inbound_message, make_node_identity, service_spy},
+    };
+    use tari_comms::{message::MessageExt, peer_manager::NodeIdentity, wrap_in_envelope_body};
+
+    fn make_message(node_identity: &NodeIdentity, message_type: i32, sequence: u64) -> DecryptedDhtMessage {
+        let body = wrap_in_envelope_body!(MessageHeader::new(message_type), sequence.to_le_bytes().to_vec());
+        let mut inbound =
+            make_dht_inbound_message(node_identity, body.to_encoded_bytes(), DhtMessageFlags::empty(), false);
+        inbound.dht_header.sequence = sequence;
+        DecryptedDhtMessage::succeeded(body, None, inbound)
+    }
+
+    async fn wait_for_calls<F: Fn() -> usize>(call_count: F, n: usize) {
+        let mut attempts = 0;
+        while call_count() < n {
+            attempts += 1;
+            assert!(attempts < 100, "Expected {} call(s) but got {}", n, call_count());
+            time::delay_for(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio_macros::test_basic]
+    async fn passes_through_unsequenced_messages() {
+        let spy = service_spy();
+        let mut service = SequencingLayer::new(vec![1], Duration::from_secs(10), 10, 100, 100).layer(spy.to_service());
+        let node_identity = make_node_identity();
+
+        // Not a sequenced message type
+        service.call(make_message(&node_identity, 2, 5)).await.unwrap();
+        // Not sequenced by the sender
+        service.call(make_message(&node_identity, 1, 0)).await.unwrap();
+        assert_eq!(spy.call_count(), 2);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn reorders_messages_from_origin() {
+        let spy = service_spy();
+        let mut service = SequencingLayer::new(vec![1], Duration::from_secs(10), 10, 100, 100).layer(spy.to_service());
+        let node_identity = make_node_identity();
+
+        for sequence in &[1, 3, 4, 2, 5] {
+            service.call(make_message(&node_identity, 1, *sequence)).await.unwrap();
+        }
+
+        wait_for_calls(|| spy.call_count(), 5).await;
+        let sequences = spy
+            .take_requests()
+            .into_iter()
+            .map(|msg| msg.dht_header.sequence)
+            .collect::<Vec<_>>();
+        assert_eq!(sequences, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn holds_first_message_until_predecessor_arrives() {
+        let spy = service_spy();
+        let mut service = SequencingLayer::new(vec![1], Duration::from_secs(10), 10, 100, 100).layer(spy.to_service());
+        let node_identity = make_node_identity();
+
+        service.call(make_message(&node_identity, 1, 2)).await.unwrap();
+        service.call(make_message(&node_identity, 1, 1)).await.unwrap();
+
+        wait_for_calls(|| spy.call_count(), 2).await;
+        let sequences = spy
+            .take_requests()
+            .into_iter()
+            .map(|msg| msg.dht_header.sequence)
+            .collect::<Vec<_>>();
+        assert_eq!(sequences, vec![1, 2]);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn skips_gap_after_timeout() {
+        let spy = service_spy();
+        let mut service =
+            SequencingLayer::new(vec![1], Duration::from_millis(50), 10, 100, 100).layer(spy.to_service());
+        let node_identity = make_node_identity();
+
+        service.call(make_message(&node_identity, 1, 1)).await.unwrap();
+        service.call(make_message(&node_identity, 1, 3)).await.unwrap();
+        wait_for_calls(|| spy.call_count(), 1).await;
+        // Message 3 is held until the gap times out
+        wait_for_calls(|| spy.call_count(), 2).await;
+        // The late message is delivered immediately
+        service.call(make_message(&node_identity, 1, 2)).await.unwrap();
+        wait_for_calls(|| spy.call_count(), 3).await;
+
+        let sequences = spy
+            .take_requests()
+            .into_iter()
+            .map(|msg| msg.dht_header.sequence)
+            .collect::<Vec<_>>();
+        assert_eq!(sequences, vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn gap_expires_from_oldest_buffered_message() {
+        let node_identity = make_node_identity();
+        let mut state = OriginSequence::new();
+        state.next_sequence = 2;
+        // Message 5 arrives before message 3, so the gap before message 3 has been open since message 5 arrived
+        let now = Instant::now();
+        state
+            .buffered
+            .insert(5, (now - Duration::from_secs(10), make_message(&node_identity, 1, 5)));
+        state.buffered.insert(3, (now, make_message(&node_identity, 1, 3)));
+        assert!(state.is_gap_expired(Duration::from_secs(5)));
+
+        let ready = state.skip_gap();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].dht_header.sequence, 3);
+        // Message 5 has still waited longer than the timeout, so the gap before it has also expired
+        assert!(state.is_gap_expired(Duration::from_secs(5)));
+        let ready = state.skip_gap();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].dht_header.sequence, 5);
+        assert!(!state.is_gap_expired(Duration::from_secs(5)));
+    }
+
+    #[tokio_macros::test_basic]
+    async fn skips_gap_when_buffer_full() {
+        let spy = service_spy();
+        let mut service = SequencingLayer::new(vec![1], Duration::from_secs(10), 2, 100, 100).layer(spy.to_service());
+        let node_identity = make_node_identity();
+
+        for sequence in &[1, 3, 4, 5] {
+            service.call(make_message(&node_identity, 1, *sequence)).await.unwrap();
+        }
+        wait_for_calls(|| spy.call_count(), 4).await;
+    }
+
+    #[tokio_macros::test_basic]
+    async fn does_not_replace_buffered_message() {
+        let spy = service_spy();
+        let mut service = SequencingLayer::new(vec![1], Duration::from_secs(10), 10, 100, 100).layer(spy.to_service());
+        let node_identity = make_node_identity();
+
+        service.call(make_message(&node_identity, 1, 1)).await.unwrap();
+        let buffered = make_message(&node_identity, 1, 3);
+        let buffered_tag = buffered.tag;
+        service.call(buffered).await.unwrap();
+        // A different message with the same sequence is delivered immediately
+        let other = make_message(&node_identity, 1, 3);
+        let other_tag = other.tag;
+        service.call(other).await.unwrap();
+        service.call(make_message(&node_identity, 1, 2)).await.unwrap();
+        wait_for_calls(|| spy.call_count(), 4).await;
+
+        let requests = spy.take_requests();
+        let sequences = requests.iter().map(|msg| msg.dht_header.sequence).collect::<Vec<_>>();
+        assert_eq!(sequences, vec![1, 3, 2, 3]);
+        assert_eq!(requests[1].tag, other_tag);
+        assert_eq!(requests[3].tag, buffered_tag);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn limits_tracked_origins() {
+        let spy = service_spy();
+        let mut service = SequencingLayer::new(vec![1], Duration::from_secs(10), 10, 1, 100).layer(spy.to_service());
+        let node_identity1 = make_node_identity();
+        let node_identity2 = make_node_identity();
+
+        service.call(make_message(&node_identity1, 1, 2)).await.unwrap();
+        // The only origin slot has a buffered message, so messages from other origins are not reordered
+        service.call(make_message(&node_identity2, 1, 2)).await.unwrap();
+        wait_for_calls(|| spy.call_count(), 1).await;
+        let requests = spy.take_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].source_peer.public_key, *node_identity2.public_key());
+
+        service.call(make_message(&node_identity1, 1, 1)).await.unwrap();
+        wait_for_calls(|| spy.call_count(), 3).await;
+    }
+
+    #[tokio_macros::test_basic]
+    async fn limits_total_buffered_messages() {
+        let spy = service_spy();
+        let mut service = SequencingLayer::new(vec![1], Duration::from_secs(10), 10, 100, 2).layer(spy.to_service());
+        let node_identity1 = make_node_identity();
+        let node_identity2 = make_node_identity();
+
+        service.call(make_message(&node_identity1, 1, 2)).await.unwrap();
+        service.call(make_message(&node_identity1, 1, 3)).await.unwrap();
+        // The buffers are full, so the gap before this message is skipped
+        service.call(make_message(&node_identity2, 1, 2)).await.unwrap();
+        wait_for_calls(|| spy.call_count(), 1).await;
+        let requests = spy.take_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].source_peer.public_key, *node_identity2.public_key());
+    }
+}
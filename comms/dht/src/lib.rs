@@ -133,28 +133,43 @@ mod builder;
 pub use builder::DhtBuilder;
 
 mod connectivity;
-pub use connectivity::MetricsCollectorHandle;
+pub use connectivity::{
+    ForwardMetrics,
+    LatencyHistogram,
+    MemoryUsageReport,
+    MemoryUsageSource,
+    MetricsCollectorHandle,
+    MetricsSnapshot,
+    MetricsSnapshotError,
+    MetricsSnapshotStore,
+    NetworkHeartbeat,
+    OutboundPriorityClass,
+    OutboundSendMetrics,
+    WireFormatCounts,
+    WireFormatDirection,
+};
 
 mod config;
-pub use config::DhtConfig;
+pub use config::{DhtConfig, PlaintextPolicy};
 
 mod consts;
-mod crypt;
 
 mod dht;
 pub use dht::{Dht, DhtInitializationError};
 
 mod discovery;
-pub use discovery::DhtDiscoveryRequester;
+pub use discovery::{DhtDiscoveryError, DhtDiscoveryRequester, DiscoveryStats};
 
 mod network_discovery;
 pub use network_discovery::NetworkDiscoveryConfig;
 
 mod storage;
+#[cfg(feature = "benches")]
+pub use storage::DbConnection;
 pub use storage::DbConnectionUrl;
 
 mod dedup;
-pub use dedup::DedupLayer;
+pub use dedup::{DedupCacheStats, DedupLayer};
 
 mod logging_middleware;
 mod proto;
@@ -163,9 +178,16 @@ mod schema;
 mod tower_filter;
 
 pub mod broadcast_strategy;
+pub mod codec;
+pub mod crypt;
+pub mod dashboard;
 pub mod domain_message;
 pub mod envelope;
 pub mod event;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzz;
+pub mod group;
 pub mod inbound;
+pub mod message_key;
 pub mod outbound;
 pub mod store_forward;
@@ -0,0 +1,159 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Message keys
+//!
+//! A message key uniquely identifies a DHT message. It is used to detect duplicates in the dedup cache and as the
+//! unique key of messages held in store and forward storage.
+//!
+//! The key commits to the origin MAC (which contains the origin signature), the ephemeral public key and message
+//! type from the DHT header, and the message body. These fields are not changed as a message propagates, so every
+//! node derives the same key for the same message. The body is included so that messages without an origin MAC are
+//! still distinguished from each other.
+//!
+//! ## Collisions
+//!
+//! The dedup cache only holds keys, so it discards any message whose key has already been seen. Store and forward
+//! storage compares the header and body of a message with those of the stored message with the same key. If they
+//! match, the message is a duplicate. Otherwise, the message is a collision and is rejected with
+//! `StorageError::MessageKeyCollision`, keeping the message that was stored first. Keys are 256-bit hashes over
+//! length-prefixed fields, so a collision means that two messages share the fields that the key commits to but differ
+//! in other header fields (e.g. a forwarding peer altered the header).
+//!
+//! ## Compatibility
+//!
+//! The message key is stored in the `body_hash` column of the stored messages table, which previously held the hash
+//! of the message body alone. Existing rows are not migrated: they keep the old hash and expire as usual. Until they
+//! do, a message stored before the upgrade may be stored a second time under its new key, in which case the recipient
+//! discards the second copy it receives as a duplicate. The key is not sent over the wire, so nodes that derive keys
+//! differently remain compatible, although they do not agree on which messages are duplicates.
+
+use crate::envelope::{DhtMessageHeader, DhtMessageType};
+use digest::Digest;
+use tari_comms::types::{Challenge, CommsPublicKey};
+use tari_utilities::ByteArray;
+
+/// The length in bytes of a message key
+pub const MESSAGE_KEY_LENGTH: usize = 32;
+const MESSAGE_KEY_DOMAIN: &[u8] = b"tari.dht.message_key";
+
+/// Derives the message key from the given message parts
+pub fn derive_message_key(
+    origin_mac: &[u8],
+    ephemeral_public_key: Option<&CommsPublicKey>,
+    message_type: DhtMessageType,
+    body: &[u8],
+) -> Vec<u8>
+{
+    let ephemeral_public_key = ephemeral_public_key.map(|pk| pk.as_bytes()).unwrap_or_default();
+    Challenge::new()
+        .chain(MESSAGE_KEY_DOMAIN)
+        .chain((origin_mac.len() as u64).to_le_bytes())
+        .chain(origin_mac)
+        .chain((ephemeral_public_key.len() as u64).to_le_bytes())
+        .chain(ephemeral_public_key)
+        .chain((message_type as i32).to_le_bytes())
+        .chain((body.len() as u64).to_le_bytes())
+        .chain(body)
+        .result()
+        .to_vec()
+}
+
+/// Derives the message key of a message with the given DHT header and body
+pub fn message_key_from_header(header: &DhtMessageHeader, body: &[u8]) -> Vec<u8> {
+    derive_message_key(
+        &header.origin_mac,
+        header.ephemeral_public_key.as_ref(),
+        header.message_type,
+        body,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        envelope::DhtMessageFlags,
+        test_utils::{make_dht_header, make_keypair, make_node_identity},
+    };
+    use rand::rngs::OsRng;
+    use tari_comms::message::MessageTag;
+    use tari_crypto::keys::PublicKey;
+
+    #[test]
+    fn key_is_deterministic() {
+        let (_, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let key = derive_message_key(b"mac", Some(&pk), DhtMessageType::None, b"body");
+        assert_eq!(key.len(), MESSAGE_KEY_LENGTH);
+        assert_eq!(
+            key,
+            derive_message_key(b"mac", Some(&pk), DhtMessageType::None, b"body")
+        );
+    }
+
+    #[test]
+    fn key_commits_to_each_part() {
+        let (_, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let key = derive_message_key(b"mac", Some(&pk), DhtMessageType::None, b"body");
+        assert_ne!(
+            key,
+            derive_message_key(b"mac2", Some(&pk), DhtMessageType::None, b"body")
+        );
+        assert_ne!(key, derive_message_key(b"mac", None, DhtMessageType::None, b"body"));
+        assert_ne!(
+            key,
+            derive_message_key(b"mac", Some(&pk), DhtMessageType::Join, b"body")
+        );
+        assert_ne!(
+            key,
+            derive_message_key(b"mac", Some(&pk), DhtMessageType::None, b"body2")
+        );
+    }
+
+    #[test]
+    fn key_is_unambiguous() {
+        // Moving bytes between adjacent fields must not produce the same key
+        let key = derive_message_key(b"ab", None, DhtMessageType::None, b"c");
+        assert_ne!(key, derive_message_key(b"a", None, DhtMessageType::None, b"bc"));
+        assert_ne!(key, derive_message_key(b"", None, DhtMessageType::None, b"abc"));
+    }
+
+    #[test]
+    fn key_from_header() {
+        let node_identity = make_node_identity();
+        let (e_sk, e_pk) = make_keypair();
+        let header = make_dht_header(
+            &node_identity,
+            &e_pk,
+            &e_sk,
+            b"body",
+            DhtMessageFlags::ENCRYPTED,
+            true,
+            MessageTag::new(),
+        );
+        let key = message_key_from_header(&header, b"body");
+        assert_eq!(
+            key,
+            derive_message_key(&header.origin_mac, Some(&e_pk), header.message_type, b"body")
+        );
+    }
+}
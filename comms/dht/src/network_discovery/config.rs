@@ -20,9 +20,13 @@
 //  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
+use tari_common::configuration::seconds;
+use tari_comms::backoff::BackoffPolicy;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct NetworkDiscoveryConfig {
     /// True to enable network discovery, false to disable it.
     /// Default: true
@@ -33,13 +37,15 @@ pub struct NetworkDiscoveryConfig {
     pub min_desired_peers: usize,
     /// The period to wait once the number of rounds given by `idle_after_num_rounds` has completed.
     /// Default: 30 mins
+    #[serde(with = "seconds")]
     pub idle_period: Duration,
     /// The minimum number of network discovery rounds to perform before idling (going to sleep). If there are less
     /// than `min_desired_peers` then the actual number of rounds performed will exceed this value. Default: 10
     pub idle_after_num_rounds: usize,
-    /// Time to idle after a failed round.
-    /// Default: 5 secs
-    pub on_failure_idle_period: Duration,
+    /// The backoff to apply after a failed round. The time to idle increases with each consecutive failed round.
+    /// Backoff delays are given in milliseconds.
+    /// Default: exponential, starting at 5 secs and doubling up to 10 mins
+    pub on_failure_backoff: BackoffPolicy,
     /// The maximum number of sync peer to select for each round. The selection strategy varies depending on the
     /// current state.
     /// Default: 5
@@ -53,7 +59,7 @@ impl Default for NetworkDiscoveryConfig {
             min_desired_peers: 50,
             idle_period: Duration::from_secs(30 * 60),
             idle_after_num_rounds: 10,
-            on_failure_idle_period: Duration::from_secs(5),
+            on_failure_backoff: BackoffPolicy::exponential(Duration::from_secs(5), 2.0, Duration::from_secs(10 * 60)),
             max_sync_peers: 5,
         }
     }
@@ -74,9 +74,10 @@ impl DiscoveryReady {
             let peers = self
                 .context
                 .peer_manager
-                .random_peers(
+                .random_peers_with_rng(
                     self.config().network_discovery.max_sync_peers,
                     self.context.all_attempted_peers.read().await.as_slice(),
+                    &mut self.config().rng.clone(),
                 )
                 .await?;
             let peers = peers.into_iter().map(|p| p.node_id).collect::<Vec<_>>();
@@ -163,9 +164,10 @@ impl DiscoveryReady {
                 );
                 self.context
                     .peer_manager
-                    .random_peers(
+                    .random_peers_with_rng(
                         self.config().network_discovery.max_sync_peers,
                         self.context.all_attempted_peers.read().await.as_slice(),
+                        &mut self.config().rng.clone(),
                     )
                     .await?
                     .into_iter()
@@ -42,6 +42,7 @@ use std::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
+    time::Duration,
 };
 use tari_comms::{connectivity::ConnectivityRequester, peer_manager::NodeId, NodeIdentity, PeerManager};
 use tari_shutdown::ShutdownSignal;
@@ -163,6 +164,7 @@ impl NetworkDiscoveryContext {
 
 pub struct DhtNetworkDiscovery {
     context: NetworkDiscoveryContext,
+    num_consecutive_failures: usize,
     shutdown_signal: ShutdownSignal,
 }
 
@@ -187,6 +189,7 @@ impl DhtNetworkDiscovery {
                 last_round: Default::default(),
                 event_tx,
             },
+            num_consecutive_failures: 0,
             shutdown_signal,
         }
     }
@@ -224,19 +227,21 @@ impl DhtNetworkDiscovery {
                 let is_success = stats.is_success();
                 self.context.set_last_round(stats).await;
                 if !is_success {
-                    return State::Waiting(self.config().network_discovery.on_failure_idle_period.into());
+                    return State::Waiting(self.next_failure_backoff().into());
                 }
 
+                self.num_consecutive_failures = 0;
                 State::Ready(DiscoveryReady::new(self.context.clone()))
             },
             (State::Ready(_), StateEvent::Idle) => State::Waiting(config.idle_period.into()),
             (_, StateEvent::Shutdown) => State::Shutdown,
             (_, StateEvent::Errored(err)) => {
+                let backoff = self.next_failure_backoff();
                 error!(
                     target: LOG_TARGET,
-                    "Network discovery errored: {}. Waiting for {:.0?}", err, config.on_failure_idle_period
+                    "Network discovery errored: {}. Waiting for {:.0?}", err, backoff
                 );
-                State::Waiting(config.on_failure_idle_period.into())
+                State::Waiting(backoff.into())
             },
             (state, event) => {
                 debug!(
@@ -248,6 +253,16 @@ impl DhtNetworkDiscovery {
         }
     }
 
+    /// Records a failed round and returns the time to wait before the next round. The wait increases with each
+    /// consecutive failure according to the `on_failure_backoff` policy.
+    fn next_failure_backoff(&mut self) -> Duration {
+        self.num_consecutive_failures += 1;
+        self.config()
+            .network_discovery
+            .on_failure_backoff
+            .calculate_backoff_with_rng(self.num_consecutive_failures + 1, &mut self.config().rng.clone())
+    }
+
     #[inline]
     fn config(&self) -> &DhtConfig {
         &self.context.config
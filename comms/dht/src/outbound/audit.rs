@@ -0,0 +1,263 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::event::{OutboundEvent, OutboundEventReceiver, OutboundMessageInfo};
+use crate::envelope::DhtMessageType;
+use chrono::{DateTime, Utc};
+use log::*;
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tari_comms::{peer_manager::NodeId, protocol::messaging::SendFailReason};
+use tokio::{sync::broadcast, task};
+
+const LOG_TARGET: &str = "comms::dht::outbound::audit";
+
+/// A record of an outbound DHT message and its send result
+#[derive(Debug, Clone)]
+pub struct OutboundAuditEntry {
+    /// The time at which the send result was known
+    pub timestamp: DateTime<Utc>,
+    pub info: OutboundMessageInfo,
+    /// The reason the message failed to send, or None if the message was sent
+    pub failure: Option<SendFailReason>,
+}
+
+impl OutboundAuditEntry {
+    pub fn is_sent(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl From<&OutboundEvent> for OutboundAuditEntry {
+    fn from(event: &OutboundEvent) -> Self {
+        let failure = match event {
+            OutboundEvent::MessageSent(_) => None,
+            OutboundEvent::MessageFailed(_, reason) => Some(*reason),
+        };
+        Self {
+            timestamp: Utc::now(),
+            info: event.info().clone(),
+            failure,
+        }
+    }
+}
+
+impl fmt::Display for OutboundAuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.failure {
+            Some(reason) => write!(f, "[{}] {} FAILED: {}", self.timestamp, self.info, reason),
+            None => write!(f, "[{}] {} SENT", self.timestamp, self.info),
+        }
+    }
+}
+
+/// Selects entries from the `OutboundAuditLog`
+#[derive(Debug, Clone, Default)]
+pub struct OutboundAuditQuery {
+    destination: Option<NodeId>,
+    message_type: Option<DhtMessageType>,
+    failed_only: bool,
+    limit: Option<usize>,
+}
+
+impl OutboundAuditQuery {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only return messages sent to the given peer
+    pub fn with_destination(mut self, node_id: NodeId) -> Self {
+        self.destination = Some(node_id);
+        self
+    }
+
+    /// Only return messages with the given DHT message type
+    pub fn with_message_type(mut self, message_type: DhtMessageType) -> Self {
+        self.message_type = Some(message_type);
+        self
+    }
+
+    /// Only return messages that failed to send
+    pub fn failed_only(mut self) -> Self {
+        self.failed_only = true;
+        self
+    }
+
+    /// Return at most `limit` of the most recent matching entries
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn is_match(&self, entry: &OutboundAuditEntry) -> bool {
+        let is_destination_match = self
+            .destination
+            .as_ref()
+            .map(|node_id| *node_id == entry.info.destination_node_id)
+            .unwrap_or(true);
+        let is_type_match = self
+            .message_type
+            .map(|t| t == entry.info.dht_message_type)
+            .unwrap_or(true);
+
+        is_destination_match && is_type_match && (!self.failed_only || !entry.is_sent())
+    }
+}
+
+/// A bounded, rolling log of recently sent outbound DHT messages. Once the log is at capacity, the oldest entry is
+/// discarded for each new entry.
+///
+/// This is cheap to clone and all clones share the same log.
+#[derive(Debug, Clone)]
+pub struct OutboundAuditLog {
+    entries: Arc<Mutex<VecDeque<OutboundAuditEntry>>>,
+    capacity: usize,
+}
+
+impl OutboundAuditLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn record(&self, entry: OutboundAuditEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = acquire_lock!(self.entries);
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Returns the entries matching the query, most recent first
+    pub fn query(&self, query: &OutboundAuditQuery) -> Vec<OutboundAuditEntry> {
+        let entries = acquire_lock!(self.entries);
+        entries
+            .iter()
+            .rev()
+            .filter(|entry| query.is_match(entry))
+            .take(query.limit.unwrap_or(std::usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        acquire_lock!(self.entries).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Spawns a task that records every `OutboundEvent` received on the given subscription. The task ends when the
+    /// event publisher is dropped.
+    pub(crate) fn spawn_recorder(&self, mut events: OutboundEventReceiver) {
+        let log = self.clone();
+        task::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => log.record(OutboundAuditEntry::from(&*event)),
+                    Err(broadcast::RecvError::Lagged(n)) => {
+                        warn!(target: LOG_TARGET, "Outbound audit log missed {} event(s)", n);
+                    },
+                    Err(broadcast::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{broadcast_strategy::BroadcastStrategy, test_utils::make_node_identity};
+    use tari_comms::message::MessageTag;
+
+    fn make_entry(destination_node_id: NodeId, failure: Option<SendFailReason>) -> OutboundAuditEntry {
+        OutboundAuditEntry {
+            timestamp: Utc::now(),
+            info: OutboundMessageInfo {
+                tag: MessageTag::new(),
+                destination_node_id,
+                strategy: BroadcastStrategy::Flood(vec![]),
+                dht_message_type: DhtMessageType::None,
+                size: 10,
+                attempts: 1,
+            },
+            failure,
+        }
+    }
+
+    #[test]
+    fn rolling_log() {
+        let log = OutboundAuditLog::new(3);
+        let entries = (0..5).map(|_| make_entry(NodeId::default(), None)).collect::<Vec<_>>();
+        for entry in entries.clone() {
+            log.record(entry);
+        }
+        assert_eq!(log.len(), 3);
+        let tags = log
+            .query(&OutboundAuditQuery::new())
+            .into_iter()
+            .map(|e| e.info.tag)
+            .collect::<Vec<_>>();
+        // Most recent first
+        assert_eq!(tags, vec![
+            entries[4].info.tag,
+            entries[3].info.tag,
+            entries[2].info.tag
+        ]);
+
+        let log = OutboundAuditLog::new(0);
+        log.record(make_entry(NodeId::default(), None));
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn query() {
+        let log = OutboundAuditLog::new(10);
+        let node_id = make_node_identity().node_id().clone();
+        log.record(make_entry(NodeId::default(), None));
+        log.record(make_entry(node_id.clone(), Some(SendFailReason::PeerDialFailed)));
+        log.record(make_entry(node_id.clone(), None));
+
+        assert_eq!(log.query(&OutboundAuditQuery::new().with_limit(2)).len(), 2);
+        assert_eq!(log.query(&OutboundAuditQuery::new().with_destination(node_id)).len(), 2);
+        let failed = log.query(&OutboundAuditQuery::new().failed_only());
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].failure, Some(SendFailReason::PeerDialFailed));
+        assert!(log
+            .query(&OutboundAuditQuery::new().with_message_type(DhtMessageType::Join))
+            .is_empty());
+    }
+}
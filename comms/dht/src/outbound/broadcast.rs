@@ -20,13 +20,21 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use super::{error::DhtOutboundError, message::DhtOutboundRequest};
+use super::{
+    duplicate_filter::OutboundDuplicateFilter,
+    error::DhtOutboundError,
+    event,
+    event::OutboundEventSender,
+    message::DhtOutboundRequest,
+    wire_format::WireFormatSelector,
+};
 use crate::{
     actor::DhtRequester,
     broadcast_strategy::BroadcastStrategy,
     crypt,
     discovery::DhtDiscoveryRequester,
     envelope::{datetime_to_timestamp, DhtMessageFlags, DhtMessageHeader, NodeDestination},
+    message_key,
     outbound::{
         message::{DhtOutboundMessage, OutboundEncryption, SendFailure},
         message_params::FinalSendMessageParams,
@@ -34,6 +42,7 @@ use crate::{
         SendMessageResponse,
     },
     proto::envelope::{DhtMessageType, Network, OriginMac},
+    store_forward::mailbox,
 };
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
@@ -47,9 +56,13 @@ use futures::{
 };
 use log::*;
 use rand::rngs::OsRng;
-use std::{sync::Arc, task::Poll};
+use std::{
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::Duration,
+};
 use tari_comms::{
-    message::{MessageExt, MessageTag},
+    message::{CancellationHandle, MessageExt, MessagePriority, MessageTag},
     peer_manager::{NodeId, NodeIdentity, Peer},
     pipeline::PipelineError,
     types::{Challenge, CommsPublicKey},
@@ -60,6 +73,7 @@ use tari_crypto::{
     tari_utilities::{message_format::MessageFormat, ByteArray},
 };
 use tari_utilities::hex::Hex;
+use tokio::task;
 use tower::{layer::Layer, Service, ServiceExt};
 
 const LOG_TARGET: &str = "comms::dht::outbound::broadcast_middleware";
@@ -67,9 +81,12 @@ const LOG_TARGET: &str = "comms::dht::outbound::broadcast_middleware";
 pub struct BroadcastLayer {
     dht_requester: DhtRequester,
     dht_discovery_requester: DhtDiscoveryRequester,
+    wire_format_selector: WireFormatSelector,
     node_identity: Arc<NodeIdentity>,
     target_network: Network,
     message_validity_window: chrono::Duration,
+    duplicate_filter: Option<Arc<Mutex<OutboundDuplicateFilter>>>,
+    event_publisher: Option<OutboundEventSender>,
 }
 
 impl BroadcastLayer {
@@ -77,6 +94,7 @@ impl BroadcastLayer {
         node_identity: Arc<NodeIdentity>,
         dht_requester: DhtRequester,
         dht_discovery_requester: DhtDiscoveryRequester,
+        wire_format_selector: WireFormatSelector,
         target_network: Network,
         message_validity_window: chrono::Duration,
     ) -> Self
@@ -85,24 +103,44 @@ impl BroadcastLayer {
             node_identity,
             dht_requester,
             dht_discovery_requester,
+            wire_format_selector,
             target_network,
             message_validity_window,
+            duplicate_filter: None,
+            event_publisher: None,
         }
     }
+
+    /// Suppress identical messages sent to the same destination within the given window. Messages sent with
+    /// `SendMessageParams::allow_duplicates` are never suppressed. If `None`, duplicate suppression is disabled.
+    pub fn with_duplicate_window(mut self, window: Option<Duration>) -> Self {
+        self.duplicate_filter = window.map(|window| Arc::new(Mutex::new(OutboundDuplicateFilter::new(window))));
+        self
+    }
+
+    /// Publish an `OutboundEvent` on the given channel once the send result of each message is known
+    pub fn with_event_publisher(mut self, event_publisher: OutboundEventSender) -> Self {
+        self.event_publisher = Some(event_publisher);
+        self
+    }
 }
 
 impl<S> Layer<S> for BroadcastLayer {
     type Service = BroadcastMiddleware<S>;
 
     fn layer(&self, service: S) -> Self::Service {
-        BroadcastMiddleware::new(
+        let mut middleware = BroadcastMiddleware::new(
             service,
             Arc::clone(&self.node_identity),
             self.dht_requester.clone(),
             self.dht_discovery_requester.clone(),
+            self.wire_format_selector.clone(),
             self.target_network,
             self.message_validity_window,
-        )
+        );
+        middleware.duplicate_filter = self.duplicate_filter.clone();
+        middleware.event_publisher = self.event_publisher.clone();
+        middleware
     }
 }
 
@@ -113,9 +151,12 @@ pub struct BroadcastMiddleware<S> {
     next: S,
     dht_requester: DhtRequester,
     dht_discovery_requester: DhtDiscoveryRequester,
+    wire_format_selector: WireFormatSelector,
     node_identity: Arc<NodeIdentity>,
     target_network: Network,
     message_validity_window: chrono::Duration,
+    duplicate_filter: Option<Arc<Mutex<OutboundDuplicateFilter>>>,
+    event_publisher: Option<OutboundEventSender>,
 }
 
 impl<S> BroadcastMiddleware<S> {
@@ -124,6 +165,7 @@ impl<S> BroadcastMiddleware<S> {
         node_identity: Arc<NodeIdentity>,
         dht_requester: DhtRequester,
         dht_discovery_requester: DhtDiscoveryRequester,
+        wire_format_selector: WireFormatSelector,
         target_network: Network,
         message_validity_window: chrono::Duration,
     ) -> Self
@@ -132,11 +174,26 @@ impl<S> BroadcastMiddleware<S> {
             next: service,
             dht_requester,
             dht_discovery_requester,
+            wire_format_selector,
             node_identity,
             target_network,
             message_validity_window,
+            duplicate_filter: None,
+            event_publisher: None,
         }
     }
+
+    /// Suppress identical messages sent to the same destination within the given window
+    pub fn with_duplicate_window(mut self, window: Duration) -> Self {
+        self.duplicate_filter = Some(Arc::new(Mutex::new(OutboundDuplicateFilter::new(window))));
+        self
+    }
+
+    /// Publish an `OutboundEvent` on the given channel once the send result of each message is known
+    pub fn with_event_publisher(mut self, event_publisher: OutboundEventSender) -> Self {
+        self.event_publisher = Some(event_publisher);
+        self
+    }
 }
 
 impl<S> Service<DhtOutboundRequest> for BroadcastMiddleware<S>
@@ -157,9 +214,12 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError> + Clo
             Arc::clone(&self.node_identity),
             self.dht_requester.clone(),
             self.dht_discovery_requester.clone(),
+            self.wire_format_selector.clone(),
             self.target_network,
             msg,
             self.message_validity_window,
+            self.duplicate_filter.clone(),
+            self.event_publisher.clone(),
         )
         .handle()
     }
@@ -170,23 +230,30 @@ struct BroadcastTask<S> {
     node_identity: Arc<NodeIdentity>,
     dht_requester: DhtRequester,
     dht_discovery_requester: DhtDiscoveryRequester,
+    wire_format_selector: WireFormatSelector,
     request: Option<DhtOutboundRequest>,
     target_network: Network,
     message_validity_window: chrono::Duration,
+    duplicate_filter: Option<Arc<Mutex<OutboundDuplicateFilter>>>,
+    event_publisher: Option<OutboundEventSender>,
 }
 type FinalMessageParts = (Option<Arc<CommsPublicKey>>, Option<Bytes>, Bytes);
 
 impl<S> BroadcastTask<S>
 where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         service: S,
         node_identity: Arc<NodeIdentity>,
         dht_requester: DhtRequester,
         dht_discovery_requester: DhtDiscoveryRequester,
+        wire_format_selector: WireFormatSelector,
         target_network: Network,
         request: DhtOutboundRequest,
         message_validity_window: chrono::Duration,
+        duplicate_filter: Option<Arc<Mutex<OutboundDuplicateFilter>>>,
+        event_publisher: Option<OutboundEventSender>,
     ) -> Self
     {
         Self {
@@ -194,9 +261,12 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
             node_identity,
             dht_requester,
             dht_discovery_requester,
+            wire_format_selector,
             target_network,
             request: Some(request),
             message_validity_window,
+            duplicate_filter,
+            event_publisher,
         }
     }
 
@@ -254,6 +324,21 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
             return Err(DhtOutboundError::SendToOurselves);
         }
 
+        if is_cancelled(&params.cancellation) {
+            debug!(target: LOG_TARGET, "Message was cancelled before it was sent");
+            let _ = reply_tx.send(SendMessageResponse::Failed(SendFailure::Cancelled));
+            return Ok(Vec::new());
+        }
+
+        if !params.allow_duplicates && self.is_duplicate(&params, &body) {
+            debug!(
+                target: LOG_TARGET,
+                "Suppressing duplicate outbound message ({})", params.broadcast_strategy
+            );
+            let _ = reply_tx.send(SendMessageResponse::Failed(SendFailure::DuplicateSuppressed));
+            return Ok(Vec::new());
+        }
+
         let FinalSendMessageParams {
             broadcast_strategy,
             destination,
@@ -263,8 +348,21 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
             is_discovery_enabled,
             force_origin,
             dht_header,
+            include_mailbox_tag,
+            max_peers,
+            sequence,
+            cancellation,
+            priority,
+            ..
         } = params;
 
+        // Only keep a copy of the strategy if there is someone listening for send results
+        let event_strategy = self
+            .event_publisher
+            .as_ref()
+            .filter(|publisher| publisher.receiver_count() > 0)
+            .map(|_| broadcast_strategy.clone());
+
         match self.select_peers(broadcast_strategy.clone()).await {
             Ok(mut peers) => {
                 if reply_tx.is_canceled() {
@@ -273,6 +371,19 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
 
                 let mut reply_tx = Some(reply_tx);
 
+                if let Some(max_peers) = max_peers {
+                    if peers.len() > max_peers {
+                        debug!(
+                            target: LOG_TARGET,
+                            "Limiting message to {} of {} selected peer(s) ({})",
+                            max_peers,
+                            peers.len(),
+                            broadcast_strategy
+                        );
+                        peers.truncate(max_peers);
+                    }
+                }
+
                 trace!(
                     target: LOG_TARGET,
                     "Number of peers selected = {}, is_discovery_enabled = {}",
@@ -297,6 +408,12 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
 
                     match self.initiate_peer_discovery(target_public_key).await {
                         Ok(Some(peer)) => {
+                            // Discovery can take some time, during which the message may have been cancelled
+                            if is_cancelled(&cancellation) {
+                                debug!(target: LOG_TARGET, "Message was cancelled during peer discovery");
+                                let _ = discovery_reply_tx.send(SendMessageResponse::Failed(SendFailure::Cancelled));
+                                return Ok(Vec::new());
+                            }
                             // Set the reply_tx so that it can be used later
                             reply_tx = Some(discovery_reply_tx);
                             peers = vec![peer.node_id];
@@ -319,6 +436,10 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
                 }
 
                 let expires = Utc::now() + self.message_validity_window;
+                let mailbox_tag = encryption
+                    .public_key()
+                    .filter(|_| include_mailbox_tag)
+                    .map(|pk| Bytes::from(mailbox::current_mailbox_tag(self.node_identity.secret_key(), pk)));
 
                 match self
                     .generate_send_messages(
@@ -332,10 +453,31 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
                         is_broadcast,
                         body,
                         Some(expires),
+                        mailbox_tag,
+                        sequence,
+                        cancellation,
+                        priority,
                     )
                     .await
                 {
-                    Ok((msgs, send_states)) => {
+                    Ok((msgs, mut send_states)) => {
+                        if let Some((publisher, strategy)) = self.event_publisher.as_ref().zip(event_strategy) {
+                            send_states = msgs
+                                .iter()
+                                .zip(send_states)
+                                .map(|(msg, send_state)| {
+                                    event::publish_send_result(
+                                        publisher.clone(),
+                                        send_state,
+                                        msg.destination_node_id.clone(),
+                                        strategy.clone(),
+                                        dht_message_type,
+                                        msg.body.len(),
+                                    )
+                                })
+                                .collect();
+                        }
+
                         // Reply with the `MessageTag`s for each message
                         let _ = reply_tx
                             .take()
@@ -359,6 +501,26 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
         }
     }
 
+    /// Returns true if duplicate suppression is enabled and an identical message was recently sent to the same
+    /// destination
+    fn is_duplicate(&self, params: &FinalSendMessageParams, body: &[u8]) -> bool {
+        match self.duplicate_filter.as_ref() {
+            Some(filter) => {
+                let message_hash = params
+                    .domain_message_hash
+                    .clone()
+                    .unwrap_or_else(|| Challenge::new().chain(body).result().to_vec());
+                let key = OutboundDuplicateFilter::message_key(
+                    &params.broadcast_strategy,
+                    &params.destination,
+                    &message_hash,
+                );
+                acquire_lock!(filter).check_and_record(key)
+            },
+            None => false,
+        }
+    }
+
     async fn select_peers(&mut self, broadcast_strategy: BroadcastStrategy) -> Result<Vec<NodeId>, DhtOutboundError> {
         self.dht_requester
             .select_peers(broadcast_strategy)
@@ -424,46 +586,81 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
         is_broadcast: bool,
         body: Bytes,
         expires: Option<DateTime<Utc>>,
+        mailbox_tag: Option<Bytes>,
+        sequence: u64,
+        cancellation: Option<CancellationHandle>,
+        priority: MessagePriority,
     ) -> Result<(Vec<DhtOutboundMessage>, Vec<MessageSendState>), DhtOutboundError>
     {
         let dht_flags = encryption.flags() | extra_flags;
 
-        let (ephemeral_public_key, origin_mac, body) = self.process_encryption(&encryption, force_origin, body)?;
+        // Encryption and signing are CPU-bound so they are done on the blocking pool to avoid stalling other tasks
+        // scheduled on the same runtime worker
+        let node_identity = self.node_identity.clone();
+        let (ephemeral_public_key, origin_mac, body) =
+            task::spawn_blocking(move || process_encryption(&node_identity, &encryption, force_origin, sequence, body))
+                .await??;
 
         if is_broadcast {
-            self.add_to_dedup_cache(&body).await?;
+            // Use the same key that recipients will derive from the serialized header, so that this message is
+            // discarded if it is propagated back to us
+            let message_key = match custom_header.as_ref() {
+                Some(header) => message_key::message_key_from_header(header, &body),
+                None => message_key::derive_message_key(
+                    origin_mac.as_deref().unwrap_or_default(),
+                    ephemeral_public_key.as_deref(),
+                    dht_message_type,
+                    &body,
+                ),
+            };
+            self.add_to_dedup_cache(message_key).await?;
         }
 
+        // Only messages sent to their final destination with a header built for that peer can use the direct wire
+        // format
+        let is_direct = !is_broadcast && custom_header.is_none();
+        let wire_formats = self
+            .wire_format_selector
+            .select(&selected_peers, &destination, is_direct)
+            .await;
+
         // Construct a DhtOutboundMessage for each recipient
-        let messages = selected_peers.into_iter().map(|node_id| {
-            let (reply_tx, reply_rx) = oneshot::channel();
-            let tag = MessageTag::new();
-            let send_state = MessageSendState::new(tag, reply_rx);
-            (
-                DhtOutboundMessage {
-                    tag,
-                    destination_node_id: node_id,
-                    destination: destination.clone(),
-                    dht_message_type,
-                    network: self.target_network,
-                    dht_flags,
-                    custom_header: custom_header.clone(),
-                    body: body.clone(),
-                    reply: reply_tx.into(),
-                    ephemeral_public_key: ephemeral_public_key.clone(),
-                    origin_mac: origin_mac.clone(),
-                    is_broadcast,
-                    expires: expires.map(datetime_to_timestamp),
-                },
-                send_state,
-            )
-        });
+        let messages = selected_peers
+            .into_iter()
+            .zip(wire_formats)
+            .map(|(node_id, wire_format)| {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                let tag = MessageTag::new();
+                let send_state = MessageSendState::new(tag, reply_rx);
+                (
+                    DhtOutboundMessage {
+                        tag,
+                        destination_node_id: node_id,
+                        destination: destination.clone(),
+                        dht_message_type,
+                        network: self.target_network,
+                        dht_flags,
+                        custom_header: custom_header.clone(),
+                        body: body.clone(),
+                        reply: reply_tx.into(),
+                        ephemeral_public_key: ephemeral_public_key.clone(),
+                        origin_mac: origin_mac.clone(),
+                        is_broadcast,
+                        expires: expires.map(datetime_to_timestamp),
+                        mailbox_tag: mailbox_tag.clone(),
+                        sequence,
+                        cancellation: cancellation.clone(),
+                        priority,
+                        wire_format,
+                    },
+                    send_state,
+                )
+            });
 
         Ok(messages.unzip())
     }
 
-    async fn add_to_dedup_cache(&mut self, body: &[u8]) -> Result<bool, DhtOutboundError> {
-        let hash = Challenge::new().chain(&body).result().to_vec();
+    async fn add_to_dedup_cache(&mut self, hash: Vec<u8>) -> Result<bool, DhtOutboundError> {
         trace!(
             target: LOG_TARGET,
             "Dedup added message hash {} to cache for message",
@@ -475,49 +672,51 @@ where S: Service<DhtOutboundMessage, Response = (), Error = PipelineError>
             .await
             .map_err(|_| DhtOutboundError::FailedToInsertMessageHash)
     }
+}
 
-    fn process_encryption(
-        &self,
-        encryption: &OutboundEncryption,
-        include_origin: bool,
-        body: Bytes,
-    ) -> Result<FinalMessageParts, DhtOutboundError>
-    {
-        match encryption {
-            OutboundEncryption::EncryptFor(public_key) => {
-                trace!(target: LOG_TARGET, "Encrypting message for {}", public_key);
-                // Generate ephemeral public/private key pair and ECDH shared secret
-                let (e_sk, e_pk) = CommsPublicKey::random_keypair(&mut OsRng);
-                let shared_ephemeral_secret = crypt::generate_ecdh_secret(&e_sk, &**public_key);
-                // Encrypt the message with the body
-                let encrypted_body = crypt::encrypt(&shared_ephemeral_secret, &body)?;
-
-                // Sign the encrypted message
-                let origin_mac = create_origin_mac(&self.node_identity, &encrypted_body)?;
-                // Encrypt and set the origin field
-                let encrypted_origin_mac = crypt::encrypt(&shared_ephemeral_secret, &origin_mac)?;
-                Ok((
-                    Some(Arc::new(e_pk)),
-                    Some(encrypted_origin_mac.into()),
-                    encrypted_body.into(),
-                ))
-            },
-            OutboundEncryption::ClearText => {
-                trace!(target: LOG_TARGET, "Encryption not requested for message");
-
-                if include_origin {
-                    let origin_mac = create_origin_mac(&self.node_identity, &body)?;
-                    Ok((None, Some(origin_mac.into()), body))
-                } else {
-                    Ok((None, None, body))
-                }
-            },
-        }
+fn process_encryption(
+    node_identity: &NodeIdentity,
+    encryption: &OutboundEncryption,
+    include_origin: bool,
+    sequence: u64,
+    body: Bytes,
+) -> Result<FinalMessageParts, DhtOutboundError>
+{
+    match encryption {
+        OutboundEncryption::EncryptFor(public_key) => {
+            trace!(target: LOG_TARGET, "Encrypting message for {}", public_key);
+            // Generate ephemeral public/private key pair and ECDH shared secret
+            let (e_sk, e_pk) = CommsPublicKey::random_keypair(&mut OsRng);
+            let shared_ephemeral_secret = crypt::generate_ecdh_secret(&e_sk, &**public_key);
+            // Encrypt the message with the body
+            let encrypted_body = crypt::encrypt(&shared_ephemeral_secret, &body)?;
+
+            // Sign the encrypted message
+            let origin_mac = create_origin_mac(node_identity, &encrypted_body, sequence)?;
+            // Encrypt and set the origin field
+            let encrypted_origin_mac = crypt::encrypt(&shared_ephemeral_secret, &origin_mac)?;
+            Ok((
+                Some(Arc::new(e_pk)),
+                Some(encrypted_origin_mac.into()),
+                encrypted_body.into(),
+            ))
+        },
+        OutboundEncryption::ClearText => {
+            trace!(target: LOG_TARGET, "Encryption not requested for message");
+
+            if include_origin {
+                let origin_mac = create_origin_mac(node_identity, &body, sequence)?;
+                Ok((None, Some(origin_mac.into()), body))
+            } else {
+                Ok((None, None, body))
+            }
+        },
     }
 }
 
-fn create_origin_mac(node_identity: &NodeIdentity, body: &[u8]) -> Result<Vec<u8>, DhtOutboundError> {
-    let signature = signature::sign(&mut OsRng, node_identity.secret_key().clone(), body)?;
+fn create_origin_mac(node_identity: &NodeIdentity, body: &[u8], sequence: u64) -> Result<Vec<u8>, DhtOutboundError> {
+    let challenge = crypt::origin_mac_challenge(body, sequence);
+    let signature = signature::sign(&mut OsRng, node_identity.secret_key().clone(), challenge)?;
 
     let mac = OriginMac {
         public_key: node_identity.public_key().to_vec(),
@@ -526,12 +725,24 @@ fn create_origin_mac(node_identity: &NodeIdentity, body: &[u8]) -> Result<Vec<u8
     Ok(mac.to_encoded_bytes())
 }
 
+fn is_cancelled(cancellation: &Option<CancellationHandle>) -> bool {
+    cancellation.as_ref().map(|c| c.is_cancelled()).unwrap_or(false)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{
         outbound::SendMessageParams,
-        test_utils::{create_dht_actor_mock, create_dht_discovery_mock, make_peer, service_spy, DhtDiscoveryMockState},
+        test_utils::{
+            build_peer_manager,
+            create_dht_actor_mock,
+            create_dht_discovery_mock,
+            make_node_identity,
+            make_peer,
+            service_spy,
+            DhtDiscoveryMockState,
+        },
     };
     use futures::channel::oneshot;
     use rand::rngs::OsRng;
@@ -539,12 +750,18 @@ mod test {
     use tari_comms::{
         multiaddr::Multiaddr,
         peer_manager::{NodeId, Peer, PeerFeatures, PeerFlags},
+        test_utils::mocks::create_connectivity_mock,
         types::CommsPublicKey,
     };
     use tari_crypto::keys::PublicKey;
     use tari_test_utils::unpack_enum;
     use tokio::task;
 
+    fn create_wire_format_selector() -> WireFormatSelector {
+        let (connectivity, _) = create_connectivity_mock();
+        WireFormatSelector::new(build_peer_manager(), connectivity)
+    }
+
     #[tokio_macros::test_basic]
     async fn send_message_flood() {
         let pk = CommsPublicKey::default();
@@ -590,6 +807,7 @@ mod test {
             node_identity,
             dht_requester,
             dht_discover_requester,
+            create_wire_format_selector(),
             Network::LocalTest,
             chrono::Duration::seconds(10800),
         );
@@ -634,6 +852,7 @@ mod test {
             Arc::new(node_identity),
             dht_requester,
             dht_discover_requester,
+            create_wire_format_selector(),
             Network::LocalTest,
             chrono::Duration::seconds(10800),
         );
@@ -685,6 +904,7 @@ mod test {
             Arc::new(node_identity),
             dht_requester,
             dht_discover_requester,
+            create_wire_format_selector(),
             Network::LocalTest,
             chrono::Duration::seconds(10800),
         );
@@ -713,4 +933,101 @@ mod test {
         assert_eq!(tags.len(), 1);
         assert_eq!(spy.call_count(), 1);
     }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_duplicate_suppressed() {
+        let node_identity = make_node_identity();
+        let peer = make_peer();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock
+            .get_shared_state()
+            .set_select_peers_response(vec![peer.clone()]);
+        task::spawn(dht_mock.run());
+        let spy = service_spy();
+
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            node_identity,
+            dht_requester,
+            dht_discover_requester,
+            create_wire_format_selector(),
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        )
+        .with_duplicate_window(Duration::from_secs(60));
+
+        let mut send = |params: FinalSendMessageParams| {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            let fut = service.call(DhtOutboundRequest::SendMessage(
+                Box::new(params),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ));
+            async move {
+                fut.await.unwrap();
+                reply_rx.await.unwrap()
+            }
+        };
+
+        let params = SendMessageParams::new().direct_node_id(peer.node_id.clone()).finish();
+        let response = send(params.clone()).await;
+        unpack_enum!(SendMessageResponse::Queued(_tags) = response);
+        let response = send(params).await;
+        unpack_enum!(SendMessageResponse::Failed(err) = response);
+        unpack_enum!(SendFailure::DuplicateSuppressed = err);
+        let response = send(
+            SendMessageParams::new()
+                .direct_node_id(peer.node_id.clone())
+                .allow_duplicates()
+                .finish(),
+        )
+        .await;
+        unpack_enum!(SendMessageResponse::Queued(_tags) = response);
+        assert_eq!(spy.call_count(), 2);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_cancelled() {
+        let node_identity = make_node_identity();
+        let peer = make_peer();
+        let (dht_requester, dht_mock) = create_dht_actor_mock(10);
+        let (dht_discover_requester, _) = create_dht_discovery_mock(10, Duration::from_secs(10));
+        dht_mock
+            .get_shared_state()
+            .set_select_peers_response(vec![peer.clone()]);
+        task::spawn(dht_mock.run());
+        let spy = service_spy();
+
+        let mut service = BroadcastMiddleware::new(
+            spy.to_service::<PipelineError>(),
+            node_identity,
+            dht_requester,
+            dht_discover_requester,
+            create_wire_format_selector(),
+            Network::LocalTest,
+            chrono::Duration::seconds(10800),
+        );
+
+        let cancellation = CancellationHandle::new();
+        cancellation.cancel();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        service
+            .call(DhtOutboundRequest::SendMessage(
+                Box::new(
+                    SendMessageParams::new()
+                        .direct_node_id(peer.node_id.clone())
+                        .with_cancellation(cancellation)
+                        .finish(),
+                ),
+                Bytes::from_static(b"custom_msg"),
+                reply_tx,
+            ))
+            .await
+            .unwrap();
+
+        unpack_enum!(SendMessageResponse::Failed(err) = reply_rx.await.unwrap());
+        unpack_enum!(SendFailure::Cancelled = err);
+        assert_eq!(spy.call_count(), 0);
+    }
 }
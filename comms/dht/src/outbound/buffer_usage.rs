@@ -0,0 +1,118 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Accounting of the requests that have been submitted to the outbound message buffer (the channel that feeds the
+/// outbound pipeline) and are waiting to be processed. Clones share the same counter, so services can use a clone to
+/// shed load before the buffer is saturated.
+#[derive(Debug, Clone, Default)]
+pub struct OutboundBufferUsage {
+    num_pending: Arc<AtomicUsize>,
+    capacity: usize,
+}
+
+impl OutboundBufferUsage {
+    /// Create a new `OutboundBufferUsage` for an outbound buffer of the given capacity. A capacity of zero means that
+    /// the capacity is unknown.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            num_pending: Default::default(),
+            capacity,
+        }
+    }
+
+    /// The number of requests that are queued in, or waiting to be accepted by, the outbound buffer
+    pub fn num_pending(&self) -> usize {
+        self.num_pending.load(Ordering::Relaxed)
+    }
+
+    /// The capacity of the outbound buffer, or zero if unknown
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of pending requests as a fraction of the buffer capacity. This may be greater than 1.0 when callers
+    /// are waiting for space in the buffer. Returns 0.0 if the capacity is unknown.
+    pub fn occupancy(&self) -> f32 {
+        if self.capacity == 0 {
+            return 0.0;
+        }
+        self.num_pending() as f32 / self.capacity as f32
+    }
+
+    /// Returns true if the outbound buffer is (or is about to be) full
+    pub fn is_saturated(&self) -> bool {
+        self.capacity > 0 && self.num_pending() >= self.capacity
+    }
+
+    /// Account for a submitted request until the returned guard is dropped
+    pub(super) fn request_submitted(&self) -> PendingRequestGuard {
+        self.num_pending.fetch_add(1, Ordering::Relaxed);
+        PendingRequestGuard {
+            num_pending: self.num_pending.clone(),
+        }
+    }
+}
+
+/// Decrements the pending request count when dropped
+pub(super) struct PendingRequestGuard {
+    num_pending: Arc<AtomicUsize>,
+}
+
+impl Drop for PendingRequestGuard {
+    fn drop(&mut self) {
+        self.num_pending.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn submitted_and_processed() {
+        let usage = OutboundBufferUsage::new(2);
+        let monitor = usage.clone();
+        let guard1 = usage.request_submitted();
+        assert_eq!(monitor.num_pending(), 1);
+        assert!(!monitor.is_saturated());
+        let guard2 = usage.request_submitted();
+        assert_eq!(monitor.num_pending(), 2);
+        assert!(monitor.is_saturated());
+        assert!((monitor.occupancy() - 1.0).abs() < f32::EPSILON);
+
+        drop(guard1);
+        assert_eq!(monitor.num_pending(), 1);
+        drop(guard2);
+        assert_eq!(monitor.num_pending(), 0);
+        assert!(!monitor.is_saturated());
+
+        // A buffer with an unknown capacity is never saturated
+        let usage = OutboundBufferUsage::default();
+        let _guard = usage.request_submitted();
+        assert!(!usage.is_saturated());
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2019, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{broadcast_strategy::BroadcastStrategy, envelope::NodeDestination};
+use digest::Digest;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::{Duration, Instant},
+};
+use tari_comms::types::Challenge;
+
+/// Tracks recently sent outbound messages so that an identical message sent to the same destination within the
+/// window can be suppressed.
+pub(super) struct OutboundDuplicateFilter {
+    window: Duration,
+    recent: HashMap<Vec<u8>, Instant>,
+}
+
+impl OutboundDuplicateFilter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            recent: HashMap::new(),
+        }
+    }
+
+    /// Records the key and returns true if the same key was recorded within the window, otherwise false. The window is
+    /// not extended by a suppressed duplicate.
+    pub fn check_and_record(&mut self, key: Vec<u8>) -> bool {
+        let now = Instant::now();
+        let window = self.window;
+        self.recent.retain(|_, sent_at| now.duration_since(*sent_at) < window);
+        match self.recent.entry(key) {
+            Entry::Occupied(_) => true,
+            Entry::Vacant(entry) => {
+                entry.insert(now);
+                false
+            },
+        }
+    }
+
+    /// Returns a key for a message sent using the given broadcast strategy and destination with the given domain
+    /// message hash
+    pub fn message_key(
+        broadcast_strategy: &BroadcastStrategy,
+        destination: &NodeDestination,
+        message_hash: &[u8],
+    ) -> Vec<u8>
+    {
+        Challenge::new()
+            .chain(broadcast_strategy.to_string())
+            .chain(destination.to_string())
+            .chain(message_hash)
+            .result()
+            .to_vec()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_node_identity;
+    use std::thread;
+
+    #[test]
+    fn check_and_record() {
+        let node_identity = make_node_identity();
+        let direct = BroadcastStrategy::DirectPublicKey(Box::new(node_identity.public_key().clone()));
+        let flood = BroadcastStrategy::Flood(Default::default());
+        let key1 = OutboundDuplicateFilter::message_key(&direct, &NodeDestination::Unknown, b"msg");
+        let key2 = OutboundDuplicateFilter::message_key(&flood, &NodeDestination::Unknown, b"msg");
+        let key3 = OutboundDuplicateFilter::message_key(&direct, &NodeDestination::Unknown, b"other");
+
+        let mut filter = OutboundDuplicateFilter::new(Duration::from_millis(50));
+        assert!(!filter.check_and_record(key1.clone()));
+        assert!(filter.check_and_record(key1.clone()));
+        assert!(!filter.check_and_record(key2));
+        assert!(!filter.check_and_record(key3));
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(!filter.check_and_record(key1));
+    }
+}
@@ -20,7 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::outbound::message::SendFailure;
+use crate::{domain_message::DomainCodecError, outbound::message::SendFailure, storage::StorageError};
 use futures::channel::mpsc::SendError;
 use tari_comms::message::MessageError;
 use tari_crypto::{
@@ -28,15 +28,20 @@ use tari_crypto::{
     tari_utilities::{ciphers::cipher::CipherError, message_format::MessageFormatError},
 };
 use thiserror::Error;
+use tokio::task;
 
 #[derive(Debug, Error)]
 pub enum DhtOutboundError {
     #[error("SendError: {0}")]
-    SendError(#[from] SendError),
+    SendError(SendError),
+    #[error("The outbound message buffer is full")]
+    QueueFull,
     #[error("MessageSerializationError: {0}")]
     MessageSerializationError(#[from] MessageError),
     #[error("MessageFormatError: {0}")]
     MessageFormatError(#[from] MessageFormatError),
+    #[error("DomainCodecError: {0}")]
+    DomainCodecError(#[from] DomainCodecError),
     #[error("SignatureError: {0}")]
     SignatureError(#[from] SchnorrSignatureError),
     #[error("CipherError: {0}")]
@@ -59,6 +64,36 @@ pub enum DhtOutboundError {
     SendMessageFailed(SendFailure),
     #[error("No messages were queued for sending")]
     NoMessagesQueued,
+    #[error("Envelope preparation task failed: {0}")]
+    EnvelopePreparationFailed(#[from] task::JoinError),
+    #[error("StorageError: {0}")]
+    StorageError(#[from] StorageError),
+    #[error("Message scheduling is not available")]
+    SchedulerNotAvailable,
+    #[error("The maximum number of scheduled messages has been reached")]
+    TooManyScheduledMessages,
+    #[error("The scheduled send time is too far in the future")]
+    ScheduledTimeTooFarInFuture,
+    #[error("Messages with a DHT header cannot be scheduled")]
+    CannotScheduleWithDhtHeader,
+}
+
+impl DhtOutboundError {
+    /// Returns true if the message could not be sent because the outbound message buffer is full. Callers may retry
+    /// later or shed load.
+    pub fn is_queue_full(&self) -> bool {
+        matches!(self, DhtOutboundError::QueueFull)
+    }
+}
+
+impl From<SendError> for DhtOutboundError {
+    fn from(err: SendError) -> Self {
+        if err.is_full() {
+            DhtOutboundError::QueueFull
+        } else {
+            DhtOutboundError::SendError(err)
+        }
+    }
 }
 
 impl From<SendFailure> for DhtOutboundError {
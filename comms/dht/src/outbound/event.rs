@@ -0,0 +1,159 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{broadcast_strategy::BroadcastStrategy, envelope::DhtMessageType, outbound::MessageSendState};
+use futures::{channel::oneshot, FutureExt};
+use std::{fmt, sync::Arc};
+use tari_comms::{message::MessageTag, peer_manager::NodeId, protocol::messaging::SendFailReason};
+use tokio::{sync::broadcast, task};
+
+pub type OutboundEventSender = broadcast::Sender<Arc<OutboundEvent>>;
+pub type OutboundEventReceiver = broadcast::Receiver<Arc<OutboundEvent>>;
+
+/// Details of an outbound message included in an `OutboundEvent`
+#[derive(Debug, Clone)]
+pub struct OutboundMessageInfo {
+    pub tag: MessageTag,
+    pub destination_node_id: NodeId,
+    pub strategy: BroadcastStrategy,
+    pub dht_message_type: DhtMessageType,
+    /// The size of the message body in bytes
+    pub size: usize,
+    /// The number of attempts made by the messaging protocol to send the message
+    pub attempts: usize,
+}
+
+impl fmt::Display for OutboundMessageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} to '{}' ({}, type: {}, size: {}, attempts: {})",
+            self.tag,
+            self.destination_node_id.short_str(),
+            self.strategy,
+            self.dht_message_type,
+            self.size,
+            self.attempts
+        )
+    }
+}
+
+/// Emitted once the send result of each outbound message is known
+#[derive(Debug, Clone)]
+pub enum OutboundEvent {
+    MessageSent(OutboundMessageInfo),
+    MessageFailed(OutboundMessageInfo, SendFailReason),
+}
+
+impl OutboundEvent {
+    pub fn info(&self) -> &OutboundMessageInfo {
+        match self {
+            OutboundEvent::MessageSent(info) => info,
+            OutboundEvent::MessageFailed(info, _) => info,
+        }
+    }
+}
+
+impl fmt::Display for OutboundEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutboundEvent::MessageSent(info) => write!(f, "MessageSent({})", info),
+            OutboundEvent::MessageFailed(info, reason) => write!(f, "MessageFailed({}, {})", info, reason),
+        }
+    }
+}
+
+/// Intercepts the send result of a message so that a `MessageSent` or `MessageFailed` event can be published. The
+/// result is passed on to the returned `MessageSendState`.
+pub(super) fn publish_send_result(
+    publisher: OutboundEventSender,
+    send_state: MessageSendState,
+    destination_node_id: NodeId,
+    strategy: BroadcastStrategy,
+    dht_message_type: DhtMessageType,
+    size: usize,
+) -> MessageSendState
+{
+    let tag = send_state.tag;
+    let (reply_tx, reply_rx) = oneshot::channel();
+    task::spawn(async move {
+        let result = send_state
+            .wait_for_result()
+            .map(|r| r.unwrap_or(Err(SendFailReason::Dropped)))
+            .await;
+        let mut info = OutboundMessageInfo {
+            tag,
+            destination_node_id,
+            strategy,
+            dht_message_type,
+            size,
+            attempts: 1,
+        };
+        let event = match result {
+            Ok(_) => OutboundEvent::MessageSent(info),
+            Err(reason) => {
+                if let SendFailReason::MaxRetriesReached(attempts) = reason {
+                    info.attempts = attempts;
+                }
+                OutboundEvent::MessageFailed(info, reason)
+            },
+        };
+        // An error only means that there are no subscribers
+        let _ = publisher.send(Arc::new(event));
+        let _ = reply_tx.send(result);
+    });
+    MessageSendState::new(tag, reply_rx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio_macros::test_basic]
+    async fn it_publishes_the_send_result() {
+        let (publisher, mut subscriber) = broadcast::channel(10);
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let tag = MessageTag::new();
+        let send_state = publish_send_result(
+            publisher,
+            MessageSendState::new(tag, reply_rx),
+            NodeId::default(),
+            BroadcastStrategy::Flood(vec![]),
+            DhtMessageType::None,
+            123,
+        );
+        reply_tx.send(Err(SendFailReason::MaxRetriesReached(3))).unwrap();
+
+        let result = send_state.wait_for_result().await.unwrap();
+        assert_eq!(result, Err(SendFailReason::MaxRetriesReached(3)));
+        let event = subscriber.recv().await.unwrap();
+        match &*event {
+            OutboundEvent::MessageFailed(info, reason) => {
+                assert_eq!(info.tag, tag);
+                assert_eq!(info.attempts, 3);
+                assert_eq!(info.size, 123);
+                assert_eq!(*reason, SendFailReason::MaxRetriesReached(3));
+            },
+            _ => panic!("Unexpected event {}", event),
+        }
+    }
+}
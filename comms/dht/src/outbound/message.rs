@@ -21,22 +21,25 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::{
+    codec::EnvelopeWireFormat,
     envelope::{DhtMessageFlags, DhtMessageHeader, DhtMessageType, Network, NodeDestination},
     outbound::{message_params::FinalSendMessageParams, message_send_state::MessageSendStates},
 };
 use bytes::Bytes;
 use futures::channel::oneshot;
+use serde::{Deserialize, Serialize};
 use std::{fmt, fmt::Display, sync::Arc};
 use tari_comms::{
-    message::{MessageTag, MessagingReplyTx},
+    message::{CancellationHandle, MessagePriority, MessageTag, MessagingReplyTx},
     peer_manager::NodeId,
+    pipeline::TaggedRequest,
     types::CommsPublicKey,
 };
 use tari_utilities::hex::Hex;
 use thiserror::Error;
 
 /// Determines if an outbound message should be Encrypted and, if so, for which public key
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OutboundEncryption {
     /// Message should not be encrypted
     ClearText,
@@ -61,6 +64,14 @@ impl OutboundEncryption {
             EncryptFor(_) => true,
         }
     }
+
+    /// Returns the public key the message is encrypted for, or None if the message is not encrypted
+    pub fn public_key(&self) -> Option<&CommsPublicKey> {
+        match self {
+            OutboundEncryption::EncryptFor(pk) => Some(pk),
+            OutboundEncryption::ClearText => None,
+        }
+    }
 }
 
 impl Display for OutboundEncryption {
@@ -92,6 +103,10 @@ pub enum SendFailure {
     FailedToGenerateMessages(String),
     #[error("No messages were queued for sending")]
     NoMessagesQueued,
+    #[error("An identical message was sent to the same destination within the duplicate suppression window")]
+    DuplicateSuppressed,
+    #[error("The message was cancelled before it was sent")]
+    Cancelled,
 }
 
 #[derive(Debug)]
@@ -141,6 +156,13 @@ pub enum DhtOutboundRequest {
     SendMessage(Box<FinalSendMessageParams>, Bytes, oneshot::Sender<SendMessageResponse>),
 }
 
+impl TaggedRequest for DhtOutboundRequest {
+    fn message_tag(&self) -> Option<MessageTag> {
+        // Message tags are assigned to each message once the broadcast strategy has been applied
+        None
+    }
+}
+
 impl fmt::Display for DhtOutboundRequest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
@@ -168,6 +190,11 @@ pub struct DhtOutboundMessage {
     pub dht_flags: DhtMessageFlags,
     pub is_broadcast: bool,
     pub expires: Option<prost_types::Timestamp>,
+    pub mailbox_tag: Option<Bytes>,
+    pub sequence: u64,
+    pub cancellation: Option<CancellationHandle>,
+    pub priority: MessagePriority,
+    pub wire_format: EnvelopeWireFormat,
 }
 
 impl fmt::Display for DhtOutboundMessage {
@@ -21,13 +21,17 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::{
-    broadcast_strategy::{BroadcastClosestRequest, BroadcastStrategy},
+    broadcast_strategy::{BroadcastClosestRequest, BroadcastRegionalRequest, BroadcastStrategy},
     envelope::{DhtMessageFlags, DhtMessageHeader, NodeDestination},
     outbound::OutboundEncryption,
     proto::envelope::DhtMessageType,
 };
 use std::{fmt, fmt::Display};
-use tari_comms::{peer_manager::NodeId, types::CommsPublicKey};
+use tari_comms::{
+    message::{CancellationHandle, MessagePriority},
+    peer_manager::{node_id::NodeDistance, NodeId},
+    types::CommsPublicKey,
+};
 
 /// Configuration for outbound messages.
 ///
@@ -66,6 +70,20 @@ pub struct FinalSendMessageParams {
     pub dht_message_type: DhtMessageType,
     pub dht_message_flags: DhtMessageFlags,
     pub dht_header: Option<DhtMessageHeader>,
+    pub allow_duplicates: bool,
+    pub include_mailbox_tag: bool,
+    /// The maximum number of peers that the message is sent to. If not set, the number of peers is determined by the
+    /// broadcast strategy alone.
+    pub max_peers: Option<usize>,
+    /// Hash of the domain message excluding the message header nonce. If not set, the hash of the message body is used
+    /// for outbound duplicate suppression.
+    pub(crate) domain_message_hash: Option<Vec<u8>>,
+    /// Sequence number of the domain message for the recipient. 0 if the message is not sequenced.
+    pub(crate) sequence: u64,
+    /// Used to cancel the message before it is sent
+    pub cancellation: Option<CancellationHandle>,
+    /// The priority with which the message is sent to each peer
+    pub priority: MessagePriority,
 }
 
 impl Default for FinalSendMessageParams {
@@ -79,6 +97,13 @@ impl Default for FinalSendMessageParams {
             force_origin: false,
             is_discovery_enabled: false,
             dht_header: None,
+            allow_duplicates: false,
+            include_mailbox_tag: false,
+            max_peers: None,
+            domain_message_hash: None,
+            sequence: 0,
+            cancellation: None,
+            priority: MessagePriority::Normal,
         }
     }
 }
@@ -110,6 +135,18 @@ impl SendMessageParams {
         self
     }
 
+    /// Set broadcast_strategy to DirectOrClosest. The message is sent directly to the peer if connected, otherwise it
+    /// is propagated toward the peer's network region. If the destination has not been set, it is set to `node_id` so
+    /// that closer nodes may store the message for the peer.
+    pub fn direct_or_closest(&mut self, node_id: NodeId) -> &mut Self {
+        let params = self.params_mut();
+        if params.destination.is_unknown() {
+            params.destination = node_id.clone().into();
+        }
+        params.broadcast_strategy = BroadcastStrategy::DirectOrClosest(Box::new(node_id));
+        self
+    }
+
     /// Use the `Closest` broadcast strategy.
     ///
     /// # Parameters
@@ -135,6 +172,21 @@ impl SendMessageParams {
         self
     }
 
+    /// Use the `Regional` broadcast strategy.
+    ///
+    /// # Parameters
+    /// `node_id` - Select all connectable peers in the region around this `NodeId`
+    /// `max_distance` - Only peers closer than this distance to `node_id` are selected
+    /// `excluded_peers` - vector of `NodeId`s to exclude from broadcast.
+    pub fn regional(&mut self, node_id: NodeId, max_distance: NodeDistance, excluded_peers: Vec<NodeId>) -> &mut Self {
+        self.params_mut().broadcast_strategy = BroadcastStrategy::Regional(Box::new(BroadcastRegionalRequest {
+            node_id,
+            max_distance,
+            excluded_peers,
+        }));
+        self
+    }
+
     /// Set broadcast_strategy to Neighbours. `excluded_peers` are excluded. Only Peers that have
     /// `PeerFeatures::MESSAGE_PROPAGATION` are included.
     pub fn broadcast(&mut self, excluded_peers: Vec<NodeId>) -> &mut Self {
@@ -202,6 +254,42 @@ impl SendMessageParams {
         self
     }
 
+    /// Exempt this message from outbound duplicate suppression. This should be used for messages that legitimately
+    /// repeat within a short period, such as pings.
+    pub fn allow_duplicates(&mut self) -> &mut Self {
+        self.params_mut().allow_duplicates = true;
+        self
+    }
+
+    /// Include a mailbox tag derived from the secret shared by this node and the recipient in the message header. This
+    /// allows the recipient to retrieve the message from store and forward nodes when the destination is not disclosed,
+    /// provided that it has added this node as a mailbox contact. The tag is only included if the message is encrypted.
+    pub fn with_mailbox_tag(&mut self) -> &mut Self {
+        self.params_mut().include_mailbox_tag = true;
+        self
+    }
+
+    /// Send the message to at most `max_peers` of the peers selected by the broadcast strategy
+    pub fn with_max_peers(&mut self, max_peers: usize) -> &mut Self {
+        self.params_mut().max_peers = Some(max_peers);
+        self
+    }
+
+    /// Attach a cancellation handle to the message. If the handle is cancelled before the message has been sent to a
+    /// peer, the message is discarded and its send state resolves as cancelled. As cancelled messages are never sent,
+    /// they are not stored by store and forward nodes.
+    pub fn with_cancellation(&mut self, cancellation: CancellationHandle) -> &mut Self {
+        self.params_mut().cancellation = Some(cancellation);
+        self
+    }
+
+    /// Send the message with the given priority. Queued `Normal` priority messages are sent to a peer before queued
+    /// `Low` priority messages, so `Low` should be used for large messages that are not time sensitive.
+    pub fn with_priority(&mut self, priority: MessagePriority) -> &mut Self {
+        self.params_mut().priority = priority;
+        self
+    }
+
     /// Return the final SendMessageParams
     pub fn finish(&mut self) -> FinalSendMessageParams {
         self.params.take().expect("cannot be None")
@@ -20,17 +20,28 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+mod audit;
+pub use audit::{OutboundAuditEntry, OutboundAuditLog, OutboundAuditQuery};
+
 mod broadcast;
 pub use broadcast::BroadcastLayer;
 
+mod buffer_usage;
+pub use buffer_usage::OutboundBufferUsage;
+
+mod duplicate_filter;
+
 mod error;
 pub use error::DhtOutboundError;
 
+mod event;
+pub use event::{OutboundEvent, OutboundEventReceiver, OutboundEventSender, OutboundMessageInfo};
+
 pub(crate) mod message;
 pub use message::{DhtOutboundRequest, OutboundEncryption, SendMessageResponse};
 
 mod message_params;
-pub use message_params::SendMessageParams;
+pub use message_params::{FinalSendMessageParams, SendMessageParams};
 
 mod message_send_state;
 pub use message_send_state::{MessageSendState, MessageSendStates};
@@ -38,8 +49,18 @@ pub use message_send_state::{MessageSendState, MessageSendStates};
 mod requester;
 pub use requester::OutboundMessageRequester;
 
+mod scheduler;
+pub use scheduler::{ScheduledMessageId, ScheduledSendRequester};
+pub(crate) use scheduler::{ScheduledSendDatabase, ScheduledSendRequest, ScheduledSendService};
+
+mod sequencer;
+pub use sequencer::MessageSequencer;
+
 mod serialize;
 pub use serialize::SerializeLayer;
 
+mod wire_format;
+pub use wire_format::WireFormatSelector;
+
 #[cfg(any(test, feature = "test-mocks"))]
 pub mod mock;
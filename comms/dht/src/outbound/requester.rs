@@ -22,33 +22,81 @@
 
 use super::message::DhtOutboundRequest;
 use crate::{
-    domain_message::OutboundDomainMessage,
-    envelope::NodeDestination,
+    domain_message::{ContentType, DomainMessageCodec, MessageHeader, OutboundDomainMessage},
+    envelope::{DhtMessageType, NodeDestination},
     outbound::{
         message::{OutboundEncryption, SendMessageResponse},
         message_params::{FinalSendMessageParams, SendMessageParams},
         message_send_state::MessageSendState,
         DhtOutboundError,
         MessageSendStates,
+        MessageSequencer,
+        OutboundBufferUsage,
+        ScheduledMessageId,
+        ScheduledSendRequester,
     },
+    proto::dht::ExtensionMessage,
 };
+use chrono::{DateTime, Utc};
+use digest::Digest;
 use futures::{
     channel::{mpsc, oneshot},
+    future,
     SinkExt,
 };
 use log::*;
-use tari_comms::{message::MessageExt, peer_manager::NodeId, types::CommsPublicKey, wrap_in_envelope_body};
+use std::time::Duration;
+use tari_comms::{
+    message::{EnvelopeBody, MessageExt},
+    peer_manager::NodeId,
+    types::{Challenge, CommsPublicKey},
+    wrap_in_envelope_body,
+};
+use tokio::time;
 
 const LOG_TARGET: &str = "comms::dht::requests::outbound";
 
 #[derive(Clone)]
 pub struct OutboundMessageRequester {
     sender: mpsc::Sender<DhtOutboundRequest>,
+    sequencer: Option<MessageSequencer>,
+    scheduler: Option<ScheduledSendRequester>,
+    buffer_usage: OutboundBufferUsage,
 }
 
 impl OutboundMessageRequester {
     pub fn new(sender: mpsc::Sender<DhtOutboundRequest>) -> Self {
-        Self { sender }
+        Self {
+            sender,
+            sequencer: None,
+            scheduler: None,
+            buffer_usage: Default::default(),
+        }
+    }
+
+    /// Assign sequence numbers from the given sequencer to direct domain messages, allowing recipients to deliver
+    /// them in send order
+    pub fn with_sequencer(mut self, sequencer: MessageSequencer) -> Self {
+        self.sequencer = Some(sequencer);
+        self
+    }
+
+    /// Enable scheduled sends (see `schedule_message`) using the given scheduler
+    pub fn with_scheduler(mut self, scheduler: ScheduledSendRequester) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Account for requests submitted to the outbound message buffer using the given `OutboundBufferUsage`
+    pub fn with_buffer_usage(mut self, buffer_usage: OutboundBufferUsage) -> Self {
+        self.buffer_usage = buffer_usage;
+        self
+    }
+
+    /// Returns the usage of the outbound message buffer. Services can use this to shed load before sends start
+    /// failing with `DhtOutboundError::QueueFull`.
+    pub fn buffer_usage(&self) -> &OutboundBufferUsage {
+        &self.buffer_usage
     }
 
     /// Send directly to a peer. If the peer does not exist in the peer list, a discovery will be initiated.
@@ -94,6 +142,31 @@ impl OutboundMessageRequester {
             .expect("MessageSendStates::inner is empty!"))
     }
 
+    /// Send directly to a peer if connected, otherwise send to peers that are closer to the destination. The message is
+    /// encrypted for the destination so that, if the peer is offline, closer nodes will store the message for the peer
+    /// (store and forward).
+    pub async fn send_direct_or_closest<T>(
+        &mut self,
+        dest_public_key: CommsPublicKey,
+        message: OutboundDomainMessage<T>,
+    ) -> Result<MessageSendStates, DhtOutboundError>
+    where
+        T: prost::Message,
+    {
+        self.send_message(
+            SendMessageParams::new()
+                .direct_or_closest(NodeId::from_public_key(&dest_public_key))
+                .with_destination(dest_public_key.clone().into())
+                .with_encryption(OutboundEncryption::EncryptFor(Box::new(dest_public_key)))
+                .finish(),
+            message,
+        )
+        .await?
+        .resolve()
+        .await
+        .map_err(Into::into)
+    }
+
     /// Send to a pre-configured number of peers, for further message propagation.
     ///
     /// If the node destination is set, the message will be propagated to peers that are closer to the destination (if
@@ -246,15 +319,129 @@ impl OutboundMessageRequester {
                 message
             );
         }
-        let header = if params.broadcast_strategy.is_direct() {
-            message.to_header()
-        } else {
-            message.to_propagation_header()
-        };
-        let body = wrap_in_envelope_body!(header, message.into_inner()).to_encoded_bytes();
+        let message_type = message.message_type();
+        self.send_encoded_message(params, message_type, message.into_inner().to_encoded_bytes())
+            .await
+    }
+
+    /// Send a message with custom parameters, waiting at most `timeout` for space in the outbound message buffer. If
+    /// the buffer is still full once the timeout has elapsed, `DhtOutboundError::QueueFull` is returned and the
+    /// message is not sent. A zero timeout fails immediately if the buffer is full.
+    ///
+    /// The timeout only applies to queuing the message, not to the time taken to process it once it is queued.
+    pub async fn send_message_with_timeout<T>(
+        &mut self,
+        mut params: FinalSendMessageParams,
+        message: OutboundDomainMessage<T>,
+        timeout: Duration,
+    ) -> Result<SendMessageResponse, DhtOutboundError>
+    where
+        T: prost::Message,
+    {
+        let message_type = message.message_type();
+        let body = prepare_domain_message_body(
+            &mut params,
+            message_type,
+            ContentType::Protobuf,
+            message.into_inner().to_encoded_bytes(),
+        );
+        // The sequence number is only assigned once there is space for the message, so that a message that is not sent
+        // does not leave a gap in the recipient's sequence
+        self.send_with_timeout(params, Some(message_type), body, timeout).await
+    }
+
+    /// Send a message with custom parameters, serialising the message body using the given codec. The codec's content
+    /// type is signalled in the message header.
+    pub async fn send_message_with_codec<T, C>(
+        &mut self,
+        params: FinalSendMessageParams,
+        message: OutboundDomainMessage<T>,
+        codec: &C,
+    ) -> Result<SendMessageResponse, DhtOutboundError>
+    where
+        C: DomainMessageCodec<T>,
+    {
+        let message_type = message.message_type();
+        let message_bytes = codec.encode(&message.into_inner())?;
+        self.send_encoded_message_with_content_type(params, message_type, codec.content_type(), message_bytes)
+            .await
+    }
+
+    /// Send an already encoded domain message of the given message type with custom parameters. This allows messages
+    /// to be sent without the message type being known at compile time (e.g. for network tooling).
+    pub async fn send_encoded_message(
+        &mut self,
+        params: FinalSendMessageParams,
+        message_type: i32,
+        message_bytes: Vec<u8>,
+    ) -> Result<SendMessageResponse, DhtOutboundError>
+    {
+        self.send_encoded_message_with_content_type(params, message_type, ContentType::Protobuf, message_bytes)
+            .await
+    }
+
+    /// Send an already encoded domain message of the given message type and content type with custom parameters
+    pub async fn send_encoded_message_with_content_type(
+        &mut self,
+        mut params: FinalSendMessageParams,
+        message_type: i32,
+        content_type: ContentType,
+        message_bytes: Vec<u8>,
+    ) -> Result<SendMessageResponse, DhtOutboundError>
+    {
+        let body = self.prepare_message_body(&mut params, message_type, content_type, message_bytes);
         self.send_raw(params, body).await
     }
 
+    /// Schedule a message to be sent with custom parameters at the given time. The scheduled message is persisted, so
+    /// it will be sent even if the node restarts before `send_at`. If `send_at` is in the past, the message is sent
+    /// immediately. Scheduled messages are not sequenced.
+    ///
+    /// Returns an id that can be used to cancel the message using `cancel_scheduled`.
+    pub async fn schedule_message<T>(
+        &mut self,
+        mut params: FinalSendMessageParams,
+        message: OutboundDomainMessage<T>,
+        send_at: DateTime<Utc>,
+    ) -> Result<ScheduledMessageId, DhtOutboundError>
+    where
+        T: prost::Message,
+    {
+        let message_type = message.message_type();
+        let body = prepare_domain_message_body(
+            &mut params,
+            message_type,
+            ContentType::Protobuf,
+            message.into_inner().to_encoded_bytes(),
+        );
+        self.schedule_raw(params, body, send_at).await
+    }
+
+    /// Schedule a raw message to be sent with custom parameters at the given time
+    pub async fn schedule_raw(
+        &mut self,
+        params: FinalSendMessageParams,
+        body: Vec<u8>,
+        send_at: DateTime<Utc>,
+    ) -> Result<ScheduledMessageId, DhtOutboundError>
+    {
+        self.scheduler
+            .as_mut()
+            .ok_or(DhtOutboundError::SchedulerNotAvailable)?
+            .schedule(params, body, send_at)
+            .await
+    }
+
+    /// Cancel a scheduled message. Returns true if the message was cancelled, or false if it has already been sent or
+    /// does not exist.
+    pub async fn cancel_scheduled(&mut self, id: ScheduledMessageId) -> Result<bool, DhtOutboundError> {
+        self.scheduler
+            .as_mut()
+            .ok_or(DhtOutboundError::SchedulerNotAvailable)?
+            .cancel(id)
+            .await
+    }
+
     /// Send a message without a domain header part
     pub async fn send_message_no_header<T>(
         &mut self,
@@ -271,6 +458,25 @@ impl OutboundMessageRequester {
         self.send_raw(params, body).await
     }
 
+    /// Send a message of a custom extension type. The message is handled by the handler that the recipient has
+    /// registered for the extension type, if any (see `Dht::message_handlers`).
+    pub async fn send_extension_message<T>(
+        &mut self,
+        mut params: FinalSendMessageParams,
+        extension_type: u32,
+        message: T,
+    ) -> Result<SendMessageResponse, DhtOutboundError>
+    where
+        T: prost::Message,
+    {
+        params.dht_message_type = DhtMessageType::Extension;
+        let extension_msg = ExtensionMessage {
+            extension_type,
+            body: message.to_encoded_bytes(),
+        };
+        self.send_message_no_header(params, extension_msg).await
+    }
+
     /// Send a raw message
     pub async fn send_raw(
         &mut self,
@@ -278,6 +484,7 @@ impl OutboundMessageRequester {
         body: Vec<u8>,
     ) -> Result<SendMessageResponse, DhtOutboundError>
     {
+        let _pending = self.buffer_usage.request_submitted();
         let (reply_tx, reply_rx) = oneshot::channel();
         self.sender
             .send(DhtOutboundRequest::SendMessage(Box::new(params), body.into(), reply_tx))
@@ -288,8 +495,204 @@ impl OutboundMessageRequester {
             .map_err(|_| DhtOutboundError::RequesterReplyChannelClosed)
     }
 
+    /// Send a raw message, waiting at most `timeout` for space in the outbound message buffer (see
+    /// `send_message_with_timeout`)
+    pub async fn send_raw_with_timeout(
+        &mut self,
+        params: FinalSendMessageParams,
+        body: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<SendMessageResponse, DhtOutboundError>
+    {
+        self.send_with_timeout(params, None, body, timeout).await
+    }
+
+    /// Waits at most `timeout` for space in the outbound message buffer and then sends the message. If
+    /// `sequenced_message_type` is given, the sequence number for that message type is assigned only once the message
+    /// can be queued.
+    async fn send_with_timeout(
+        &mut self,
+        mut params: FinalSendMessageParams,
+        sequenced_message_type: Option<i32>,
+        body: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<SendMessageResponse, DhtOutboundError>
+    {
+        let _pending = self.buffer_usage.request_submitted();
+        let sender = &mut self.sender;
+        match time::timeout(timeout, future::poll_fn(|cx| sender.poll_ready(cx))).await {
+            Ok(result) => result?,
+            Err(_) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Outbound message buffer is full after waiting {:.2?} ({} pending request(s))",
+                    timeout,
+                    self.buffer_usage.num_pending()
+                );
+                return Err(DhtOutboundError::QueueFull);
+            },
+        }
+
+        if let Some(message_type) = sequenced_message_type {
+            self.assign_sequence(&mut params, message_type);
+        }
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .start_send(DhtOutboundRequest::SendMessage(Box::new(params), body.into(), reply_tx))?;
+
+        reply_rx
+            .await
+            .map_err(|_| DhtOutboundError::RequesterReplyChannelClosed)
+    }
+
+    /// Returns the encoded envelope body for a domain message and assigns a sequence number to the params if this
+    /// requester has a sequencer
+    fn prepare_message_body(
+        &self,
+        params: &mut FinalSendMessageParams,
+        message_type: i32,
+        content_type: ContentType,
+        message_bytes: Vec<u8>,
+    ) -> Vec<u8>
+    {
+        let body = prepare_domain_message_body(params, message_type, content_type, message_bytes);
+        self.assign_sequence(params, message_type);
+        body
+    }
+
+    /// Assigns the next sequence number for the message type to the params if this requester has a sequencer
+    fn assign_sequence(&self, params: &mut FinalSendMessageParams, message_type: i32) {
+        if let Some(sequencer) = self.sequencer.as_ref() {
+            params.sequence = sequencer.next_sequence(&params.broadcast_strategy, message_type);
+        }
+    }
+
     #[cfg(test)]
     pub fn get_mpsc_sender(&self) -> mpsc::Sender<DhtOutboundRequest> {
         self.sender.clone()
     }
 }
+
+/// Returns the encoded envelope body for a domain message, and sets the domain message hash on the params
+fn prepare_domain_message_body(
+    params: &mut FinalSendMessageParams,
+    message_type: i32,
+    content_type: ContentType,
+    message_bytes: Vec<u8>,
+) -> Vec<u8>
+{
+    let header = if params.broadcast_strategy.is_direct() {
+        MessageHeader::new(message_type)
+    } else {
+        MessageHeader::for_propagation(message_type)
+    }
+    .with_content_type(content_type);
+    // The header nonce is excluded so that identical domain messages are detected as duplicates
+    params.domain_message_hash = Some(
+        Challenge::new()
+            .chain(header.message_type.to_le_bytes())
+            .chain(&message_bytes)
+            .result()
+            .to_vec(),
+    );
+    let mut body = EnvelopeBody::new();
+    body.push_part(header.to_encoded_bytes());
+    body.push_part(message_bytes);
+    body.to_encoded_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        outbound::{message::SendFailure, SendMessageParams},
+        test_utils::make_node_identity,
+    };
+    use futures::{FutureExt, StreamExt};
+
+    #[tokio_macros::test_basic]
+    async fn send_raw_with_timeout() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let mut requester = OutboundMessageRequester::new(tx).with_buffer_usage(OutboundBufferUsage::new(1));
+        let usage = requester.buffer_usage().clone();
+
+        // Fill the buffer. The request is queued but not processed.
+        let send = requester.send_raw(SendMessageParams::new().flood(vec![]).finish(), vec![]);
+        assert!(send.now_or_never().is_none());
+
+        let err = requester
+            .send_raw_with_timeout(
+                SendMessageParams::new().flood(vec![]).finish(),
+                vec![],
+                Duration::from_millis(10),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.is_queue_full());
+        assert_eq!(usage.num_pending(), 0);
+
+        // Make space in the buffer
+        rx.next().await.unwrap();
+
+        let send = requester.send_raw_with_timeout(
+            SendMessageParams::new().flood(vec![]).finish(),
+            vec![],
+            Duration::from_secs(10),
+        );
+        futures::pin_mut!(send);
+        assert!(futures::poll!(send.as_mut()).is_pending());
+        assert_eq!(usage.num_pending(), 1);
+        assert!(usage.is_saturated());
+
+        let DhtOutboundRequest::SendMessage(_, _, reply_tx) = rx.next().await.unwrap();
+        reply_tx
+            .send(SendMessageResponse::Failed(SendFailure::NoMessagesQueued))
+            .unwrap();
+        let response = send.await.unwrap();
+        assert!(matches!(
+            response,
+            SendMessageResponse::Failed(SendFailure::NoMessagesQueued)
+        ));
+        assert_eq!(usage.num_pending(), 0);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_message_with_timeout_queue_full_keeps_sequence() {
+        let (tx, mut rx) = mpsc::channel(0);
+        let mut requester = OutboundMessageRequester::new(tx).with_sequencer(MessageSequencer::new());
+        let node_identity = make_node_identity();
+        let params = || {
+            SendMessageParams::new()
+                .direct_public_key(node_identity.public_key().clone())
+                .finish()
+        };
+
+        // Fill the buffer. The request is queued but not processed.
+        let send = requester.send_raw(SendMessageParams::new().flood(vec![]).finish(), vec![]);
+        assert!(send.now_or_never().is_none());
+
+        let err = requester
+            .send_message_with_timeout(
+                params(),
+                OutboundDomainMessage::new(1, 123u32),
+                Duration::from_millis(10),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.is_queue_full());
+
+        // Make space in the buffer
+        rx.next().await.unwrap();
+
+        let send = requester.send_message_with_timeout(
+            params(),
+            OutboundDomainMessage::new(1, 123u32),
+            Duration::from_secs(10),
+        );
+        futures::pin_mut!(send);
+        assert!(futures::poll!(send.as_mut()).is_pending());
+        let DhtOutboundRequest::SendMessage(params, _, _) = rx.next().await.unwrap();
+        // The message that was not sent did not use a sequence number
+        assert_eq!(params.sequence, 1);
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{NewScheduledMessage, ScheduledMessage, ScheduledMessageId};
+use crate::{
+    schema::scheduled_messages,
+    storage::{DbConnection, StorageError},
+};
+use chrono::NaiveDateTime;
+use diesel::{Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+
+/// Persists scheduled messages until they are sent or cancelled
+#[derive(Clone)]
+pub struct ScheduledSendDatabase {
+    connection: DbConnection,
+}
+
+impl ScheduledSendDatabase {
+    pub fn new(connection: DbConnection) -> Self {
+        Self { connection }
+    }
+
+    /// Inserts the scheduled message, returning the id assigned to it
+    pub async fn insert(&self, message: NewScheduledMessage) -> Result<ScheduledMessageId, StorageError> {
+        self.connection
+            .with_connection_async(move |conn| {
+                conn.transaction::<_, StorageError, _>(|| {
+                    diesel::insert_into(scheduled_messages::table)
+                        .values(message)
+                        .execute(conn)?;
+
+                    scheduled_messages::table
+                        .select(scheduled_messages::id)
+                        .order_by(scheduled_messages::id.desc())
+                        .first(conn)
+                        .map_err(Into::into)
+                })
+            })
+            .await
+    }
+
+    /// Removes and returns the scheduled message with the given id, if it exists
+    pub async fn take(&self, id: ScheduledMessageId) -> Result<Option<ScheduledMessage>, StorageError> {
+        self.connection
+            .with_connection_async(move |conn| {
+                conn.transaction::<_, StorageError, _>(|| {
+                    let message = scheduled_messages::table
+                        .find(id)
+                        .first::<ScheduledMessage>(conn)
+                        .optional()?;
+                    if message.is_some() {
+                        diesel::delete(scheduled_messages::table.find(id)).execute(conn)?;
+                    }
+                    Ok(message)
+                })
+            })
+            .await
+    }
+
+    /// Deletes the scheduled message with the given id. Returns true if the message existed, otherwise false.
+    pub async fn delete(&self, id: ScheduledMessageId) -> Result<bool, StorageError> {
+        self.connection
+            .with_connection_async(move |conn| {
+                let num_deleted = diesel::delete(scheduled_messages::table.find(id)).execute(conn)?;
+                Ok(num_deleted > 0)
+            })
+            .await
+    }
+
+    /// Returns the id and send time of all scheduled messages in the order in which they should be sent
+    pub async fn get_all_send_times(&self) -> Result<Vec<(ScheduledMessageId, NaiveDateTime)>, StorageError> {
+        self.connection
+            .with_connection_async(|conn| {
+                scheduled_messages::table
+                    .select((scheduled_messages::id, scheduled_messages::send_at))
+                    .order_by(scheduled_messages::send_at.asc())
+                    .get_results(conn)
+                    .map_err(Into::into)
+            })
+            .await
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Scheduled sends
+//!
+//! Allows outbound messages to be sent at a future time. Scheduled messages are persisted to the DHT database so that
+//! they survive a restart, and may be cancelled at any time before they are sent. The [ScheduledSendService] keeps a
+//! timer wheel of pending sends and passes each message on to the outbound pipeline once its send time is reached.
+//!
+//! Messages are scheduled and cancelled using an
+//! [OutboundMessageRequester](crate::outbound::OutboundMessageRequester) obtained from `Dht::outbound_requester`.
+
+mod database;
+pub use database::ScheduledSendDatabase;
+
+mod params;
+pub use params::ScheduledSendParams;
+
+mod requester;
+pub(crate) use requester::ScheduledSendRequest;
+pub use requester::ScheduledSendRequester;
+
+mod scheduled_message;
+pub use scheduled_message::{NewScheduledMessage, ScheduledMessage};
+
+mod service;
+pub use service::ScheduledSendService;
+
+/// Identifies a scheduled message. This is used to cancel the message before it is sent.
+pub type ScheduledMessageId = i32;
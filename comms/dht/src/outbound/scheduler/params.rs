@@ -0,0 +1,90 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    broadcast_strategy::BroadcastStrategy,
+    envelope::{DhtMessageFlags, DhtMessageType, NodeDestination},
+    outbound::{message::OutboundEncryption, DhtOutboundError, FinalSendMessageParams},
+};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// The parts of [FinalSendMessageParams](crate::outbound::FinalSendMessageParams) that are persisted along with a
+/// scheduled message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledSendParams {
+    broadcast_strategy: BroadcastStrategy,
+    destination: NodeDestination,
+    encryption: OutboundEncryption,
+    is_discovery_enabled: bool,
+    force_origin: bool,
+    dht_message_type: i32,
+    dht_message_flags: DhtMessageFlags,
+    allow_duplicates: bool,
+    include_mailbox_tag: bool,
+    max_peers: Option<usize>,
+    domain_message_hash: Option<Vec<u8>>,
+}
+
+impl TryFrom<FinalSendMessageParams> for ScheduledSendParams {
+    type Error = DhtOutboundError;
+
+    fn try_from(params: FinalSendMessageParams) -> Result<Self, Self::Error> {
+        // A DHT header is only given when forwarding a message, which cannot be deferred
+        if params.dht_header.is_some() {
+            return Err(DhtOutboundError::CannotScheduleWithDhtHeader);
+        }
+
+        Ok(Self {
+            broadcast_strategy: params.broadcast_strategy,
+            destination: params.destination,
+            encryption: params.encryption,
+            is_discovery_enabled: params.is_discovery_enabled,
+            force_origin: params.force_origin,
+            dht_message_type: params.dht_message_type as i32,
+            dht_message_flags: params.dht_message_flags,
+            allow_duplicates: params.allow_duplicates,
+            include_mailbox_tag: params.include_mailbox_tag,
+            max_peers: params.max_peers,
+            domain_message_hash: params.domain_message_hash,
+        })
+    }
+}
+
+impl From<ScheduledSendParams> for FinalSendMessageParams {
+    fn from(params: ScheduledSendParams) -> Self {
+        Self {
+            broadcast_strategy: params.broadcast_strategy,
+            destination: params.destination,
+            encryption: params.encryption,
+            is_discovery_enabled: params.is_discovery_enabled,
+            force_origin: params.force_origin,
+            dht_message_type: DhtMessageType::from_i32(params.dht_message_type).unwrap_or(DhtMessageType::None),
+            dht_message_flags: params.dht_message_flags,
+            allow_duplicates: params.allow_duplicates,
+            include_mailbox_tag: params.include_mailbox_tag,
+            max_peers: params.max_peers,
+            domain_message_hash: params.domain_message_hash,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{ScheduledMessageId, ScheduledSendParams};
+use crate::outbound::{DhtOutboundError, FinalSendMessageParams};
+use chrono::{DateTime, Utc};
+use futures::{
+    channel::{mpsc, oneshot},
+    SinkExt,
+};
+use std::{convert::TryFrom, fmt};
+
+#[derive(Debug)]
+pub enum ScheduledSendRequest {
+    Schedule(
+        Box<ScheduledSendParams>,
+        Vec<u8>,
+        DateTime<Utc>,
+        oneshot::Sender<Result<ScheduledMessageId, DhtOutboundError>>,
+    ),
+    Cancel(ScheduledMessageId, oneshot::Sender<Result<bool, DhtOutboundError>>),
+}
+
+impl fmt::Display for ScheduledSendRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ScheduledSendRequest::*;
+        match self {
+            Schedule(_, body, send_at, _) => write!(f, "Schedule({} byte(s), {})", body.len(), send_at),
+            Cancel(id, _) => write!(f, "Cancel({})", id),
+        }
+    }
+}
+
+/// Requester for the [ScheduledSendService](super::ScheduledSendService)
+#[derive(Clone)]
+pub struct ScheduledSendRequester {
+    sender: mpsc::Sender<ScheduledSendRequest>,
+}
+
+impl ScheduledSendRequester {
+    pub fn new(sender: mpsc::Sender<ScheduledSendRequest>) -> Self {
+        Self { sender }
+    }
+
+    /// Schedule a raw message body to be sent with the given parameters at `send_at`. If `send_at` is in the past,
+    /// the message is sent immediately.
+    pub async fn schedule(
+        &mut self,
+        params: FinalSendMessageParams,
+        body: Vec<u8>,
+        send_at: DateTime<Utc>,
+    ) -> Result<ScheduledMessageId, DhtOutboundError>
+    {
+        let params = ScheduledSendParams::try_from(params)?;
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(ScheduledSendRequest::Schedule(
+                Box::new(params),
+                body,
+                send_at,
+                reply_tx,
+            ))
+            .await?;
+        reply_rx
+            .await
+            .map_err(|_| DhtOutboundError::RequesterReplyChannelClosed)?
+    }
+
+    /// Cancel a scheduled message. Returns true if the message was cancelled, or false if it has already been sent or
+    /// does not exist.
+    pub async fn cancel(&mut self, id: ScheduledMessageId) -> Result<bool, DhtOutboundError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender.send(ScheduledSendRequest::Cancel(id, reply_tx)).await?;
+        reply_rx
+            .await
+            .map_err(|_| DhtOutboundError::RequesterReplyChannelClosed)?
+    }
+}
@@ -0,0 +1,386 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{
+    NewScheduledMessage,
+    ScheduledMessageId,
+    ScheduledSendDatabase,
+    ScheduledSendParams,
+    ScheduledSendRequest,
+};
+use crate::outbound::{DhtOutboundError, OutboundMessageRequester};
+use chrono::{DateTime, Utc};
+use futures::{
+    channel::{mpsc, oneshot},
+    stream::FusedStream,
+    task::{Context, Poll},
+    Stream,
+    StreamExt,
+};
+use log::*;
+use std::{collections::HashMap, pin::Pin, time::Duration};
+use tari_shutdown::ShutdownSignal;
+use tari_utilities::message_format::MessageFormat;
+use tokio::{
+    task,
+    time,
+    time::{delay_queue, DelayQueue},
+};
+
+const LOG_TARGET: &str = "comms::dht::outbound::scheduler";
+
+/// The furthest into the future that a message can be scheduled. This is limited by the timer wheel.
+const MAX_SCHEDULE_DELAY: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Sends scheduled messages once their send time is reached. Scheduled messages are persisted until they are sent or
+/// cancelled, and are reloaded when the service starts.
+pub struct ScheduledSendService {
+    database: ScheduledSendDatabase,
+    outbound_requester: OutboundMessageRequester,
+    max_scheduled_messages: usize,
+    queue: SendQueue,
+    pending: HashMap<ScheduledMessageId, delay_queue::Key>,
+    request_rx: Option<mpsc::Receiver<ScheduledSendRequest>>,
+    shutdown_signal: Option<ShutdownSignal>,
+}
+
+impl ScheduledSendService {
+    pub fn new(
+        database: ScheduledSendDatabase,
+        outbound_requester: OutboundMessageRequester,
+        max_scheduled_messages: usize,
+        request_rx: mpsc::Receiver<ScheduledSendRequest>,
+        shutdown_signal: ShutdownSignal,
+    ) -> Self
+    {
+        Self {
+            database,
+            outbound_requester,
+            max_scheduled_messages,
+            queue: SendQueue::new(),
+            pending: HashMap::new(),
+            request_rx: Some(request_rx),
+            shutdown_signal: Some(shutdown_signal),
+        }
+    }
+
+    pub fn spawn(self) {
+        task::spawn(self.run());
+    }
+
+    pub async fn run(mut self) {
+        info!(target: LOG_TARGET, "Scheduled send service started");
+        let mut shutdown_signal = self
+            .shutdown_signal
+            .take()
+            .expect("ScheduledSendService initialized without shutdown_signal");
+
+        let mut request_rx = self
+            .request_rx
+            .take()
+            .expect("ScheduledSendService initialized without request_rx")
+            .fuse();
+
+        if let Err(err) = self.load_scheduled_messages().await {
+            error!(target: LOG_TARGET, "Failed to load scheduled messages: {}", err);
+        }
+
+        loop {
+            futures::select! {
+                request = request_rx.select_next_some() => {
+                    trace!(target: LOG_TARGET, "Received request '{}'", request);
+                    self.handle_request(request).await;
+                },
+
+                expired = self.queue.select_next_some() => {
+                    match expired {
+                        Ok(id) => self.send_scheduled_message(id).await,
+                        Err(err) => error!(target: LOG_TARGET, "Scheduled send timer error: {}", err),
+                    }
+                },
+
+                _ = shutdown_signal => {
+                    info!(
+                        target: LOG_TARGET,
+                        "Scheduled send service is shutting down because the shutdown signal was received"
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn load_scheduled_messages(&mut self) -> Result<(), DhtOutboundError> {
+        let send_times = self.database.get_all_send_times().await?;
+        debug!(
+            target: LOG_TARGET,
+            "Loaded {} persisted scheduled message(s)",
+            send_times.len()
+        );
+        for (id, send_at) in send_times {
+            let delay = delay_until(DateTime::from_utc(send_at, Utc)).min(MAX_SCHEDULE_DELAY);
+            let key = self.queue.insert(id, delay);
+            self.pending.insert(id, key);
+        }
+        Ok(())
+    }
+
+    async fn handle_request(&mut self, request: ScheduledSendRequest) {
+        use ScheduledSendRequest::*;
+        match request {
+            Schedule(params, body, send_at, reply_tx) => {
+                let result = self.schedule(*params, body, send_at).await;
+                reply(reply_tx, result);
+            },
+            Cancel(id, reply_tx) => {
+                let result = self.cancel(id).await;
+                reply(reply_tx, result);
+            },
+        }
+    }
+
+    async fn schedule(
+        &mut self,
+        params: ScheduledSendParams,
+        body: Vec<u8>,
+        send_at: DateTime<Utc>,
+    ) -> Result<ScheduledMessageId, DhtOutboundError>
+    {
+        if self.pending.len() >= self.max_scheduled_messages {
+            return Err(DhtOutboundError::TooManyScheduledMessages);
+        }
+        let delay = delay_until(send_at);
+        if delay > MAX_SCHEDULE_DELAY {
+            return Err(DhtOutboundError::ScheduledTimeTooFarInFuture);
+        }
+
+        let id = self
+            .database
+            .insert(NewScheduledMessage {
+                params: params.to_binary()?,
+                body,
+                send_at: send_at.naive_utc(),
+            })
+            .await?;
+        let key = self.queue.insert(id, delay);
+        self.pending.insert(id, key);
+        debug!(
+            target: LOG_TARGET,
+            "Scheduled message {} to be sent at {} ({} pending)",
+            id,
+            send_at,
+            self.pending.len()
+        );
+        Ok(id)
+    }
+
+    async fn cancel(&mut self, id: ScheduledMessageId) -> Result<bool, DhtOutboundError> {
+        match self.pending.remove(&id) {
+            Some(key) => {
+                self.queue.remove(&key);
+                self.database.delete(id).await?;
+                debug!(target: LOG_TARGET, "Scheduled message {} cancelled", id);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn send_scheduled_message(&mut self, id: ScheduledMessageId) {
+        self.pending.remove(&id);
+        let message = match self.database.take(id).await {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Scheduled message {} was not found in the database", id
+                );
+                return;
+            },
+            Err(err) => {
+                error!(
+                    target: LOG_TARGET,
+                    "Failed to retrieve scheduled message {}: {}", id, err
+                );
+                return;
+            },
+        };
+
+        let params = match ScheduledSendParams::from_binary(&message.params) {
+            Ok(params) => params,
+            Err(err) => {
+                error!(
+                    target: LOG_TARGET,
+                    "Discarding scheduled message {} because its send parameters are invalid: {}", id, err
+                );
+                return;
+            },
+        };
+
+        debug!(
+            target: LOG_TARGET,
+            "Sending scheduled message {} (scheduled at {} for {})", id, message.scheduled_at, message.send_at
+        );
+        if let Err(err) = self.outbound_requester.send_raw(params.into(), message.body).await {
+            error!(target: LOG_TARGET, "Failed to send scheduled message {}: {}", id, err);
+        }
+    }
+}
+
+fn reply<T>(reply_tx: oneshot::Sender<Result<T, DhtOutboundError>>, result: Result<T, DhtOutboundError>) {
+    if let Err(err) = result.as_ref() {
+        warn!(target: LOG_TARGET, "Scheduled send request failed: {}", err);
+    }
+    let _ = reply_tx.send(result);
+}
+
+/// Returns the duration from now until `send_at`, or zero if `send_at` is in the past
+fn delay_until(send_at: DateTime<Utc>) -> Duration {
+    (send_at - Utc::now()).to_std().unwrap_or_default()
+}
+
+/// Timer wheel of scheduled message ids. Unlike `DelayQueue`, this stream does not end when the queue is empty, so
+/// that it can be polled in a `select!` loop for the lifetime of the service.
+struct SendQueue {
+    inner: DelayQueue<ScheduledMessageId>,
+}
+
+impl SendQueue {
+    fn new() -> Self {
+        Self {
+            inner: DelayQueue::new(),
+        }
+    }
+
+    fn insert(&mut self, id: ScheduledMessageId, delay: Duration) -> delay_queue::Key {
+        self.inner.insert(id, delay)
+    }
+
+    fn remove(&mut self, key: &delay_queue::Key) {
+        self.inner.remove(key);
+    }
+}
+
+impl Stream for SendQueue {
+    type Item = Result<ScheduledMessageId, time::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // The queue is polled again on the next iteration of the service loop, after any new messages are inserted
+        match self.inner.poll_expired(cx) {
+            Poll::Ready(Some(result)) => Poll::Ready(Some(result.map(|expired| expired.into_inner()))),
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl FusedStream for SendQueue {
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        outbound::{mock::create_outbound_service_mock, scheduler::ScheduledSendRequester, SendMessageParams},
+        storage::DbConnection,
+    };
+    use std::convert::TryFrom;
+    use tari_shutdown::Shutdown;
+    use tari_test_utils::random;
+
+    async fn setup_database() -> ScheduledSendDatabase {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        ScheduledSendDatabase::new(conn)
+    }
+
+    fn spawn_service(
+        db: ScheduledSendDatabase,
+        outbound_requester: OutboundMessageRequester,
+    ) -> (ScheduledSendRequester, Shutdown)
+    {
+        let (tx, rx) = mpsc::channel(10);
+        let shutdown = Shutdown::new();
+        ScheduledSendService::new(db, outbound_requester, 10, rx, shutdown.to_signal()).spawn();
+        (ScheduledSendRequester::new(tx), shutdown)
+    }
+
+    #[tokio_macros::test_basic]
+    async fn schedule_and_cancel() {
+        let db = setup_database().await;
+        let (outbound_requester, mock) = create_outbound_service_mock(10);
+        let mock_state = mock.get_state();
+        task::spawn(mock.run());
+        let (mut requester, _shutdown) = spawn_service(db.clone(), outbound_requester);
+
+        let later_id = requester
+            .schedule(
+                SendMessageParams::new().flood(vec![]).finish(),
+                b"later".to_vec(),
+                Utc::now() + chrono::Duration::hours(1),
+            )
+            .await
+            .unwrap();
+        requester
+            .schedule(
+                SendMessageParams::new().flood(vec![]).finish(),
+                b"now".to_vec(),
+                Utc::now(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(requester.cancel(later_id).await.unwrap(), true);
+        assert_eq!(requester.cancel(later_id).await.unwrap(), false);
+
+        time::delay_for(Duration::from_millis(100)).await;
+        assert_eq!(mock_state.call_count(), 1);
+        let (_, body) = mock_state.pop_call().unwrap();
+        assert_eq!(body.to_vec(), b"now".to_vec());
+        assert!(db.get_all_send_times().await.unwrap().is_empty());
+    }
+
+    #[tokio_macros::test_basic]
+    async fn sends_persisted_messages_on_start() {
+        let db = setup_database().await;
+        let params = ScheduledSendParams::try_from(SendMessageParams::new().flood(vec![]).finish()).unwrap();
+        db.insert(NewScheduledMessage {
+            params: params.to_binary().unwrap(),
+            body: b"persisted".to_vec(),
+            send_at: Utc::now().naive_utc(),
+        })
+        .await
+        .unwrap();
+
+        let (outbound_requester, mock) = create_outbound_service_mock(10);
+        let mock_state = mock.get_state();
+        task::spawn(mock.run());
+        let (_requester, _shutdown) = spawn_service(db.clone(), outbound_requester);
+
+        time::delay_for(Duration::from_millis(100)).await;
+        let (_, body) = mock_state.pop_call().unwrap();
+        assert_eq!(body.to_vec(), b"persisted".to_vec());
+        assert!(db.get_all_send_times().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::broadcast_strategy::BroadcastStrategy;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tari_comms::peer_manager::NodeId;
+
+/// Assigns sequence numbers to direct domain messages. Sequence numbers are kept per recipient and domain message type
+/// so that a recipient sees a gapless sequence for each message type it receives from this node, and can deliver
+/// those messages in the order in which they were sent.
+#[derive(Debug, Clone, Default)]
+pub struct MessageSequencer {
+    sequences: Arc<Mutex<HashMap<(NodeId, i32), u64>>>,
+}
+
+impl MessageSequencer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the next sequence number for a message of `message_type` sent using the given broadcast strategy.
+    /// Sequence numbers start at 1. 0 (unsequenced) is returned for messages that are not sent directly to a peer.
+    pub fn next_sequence(&self, broadcast_strategy: &BroadcastStrategy, message_type: i32) -> u64 {
+        let node_id = match broadcast_strategy {
+            BroadcastStrategy::DirectNodeId(node_id) => (**node_id).clone(),
+            BroadcastStrategy::DirectPublicKey(pk) => NodeId::from_public_key(pk),
+            _ => return 0,
+        };
+        let mut sequences = acquire_lock!(self.sequences);
+        let sequence = sequences.entry((node_id, message_type)).or_insert(0);
+        *sequence += 1;
+        *sequence
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_node_identity;
+
+    #[test]
+    fn next_sequence() {
+        let sequencer = MessageSequencer::new();
+        let node_identity = make_node_identity();
+        let by_pk = BroadcastStrategy::DirectPublicKey(Box::new(node_identity.public_key().clone()));
+        let by_node_id = BroadcastStrategy::DirectNodeId(Box::new(node_identity.node_id().clone()));
+
+        assert_eq!(sequencer.next_sequence(&by_pk, 1), 1);
+        assert_eq!(sequencer.next_sequence(&by_node_id, 1), 2);
+        // Sequences are independent for each message type
+        assert_eq!(sequencer.next_sequence(&by_pk, 2), 1);
+        // Only direct messages are sequenced
+        assert_eq!(sequencer.next_sequence(&BroadcastStrategy::Flood(Vec::new()), 1), 0);
+    }
+}
@@ -21,15 +21,17 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::{
+    codec,
+    connectivity::{MetricsCollectorHandle, OutboundPriorityClass, WireFormatDirection},
     consts::DHT_ENVELOPE_HEADER_VERSION,
     outbound::message::DhtOutboundMessage,
-    proto::envelope::{DhtEnvelope, DhtHeader},
+    proto::envelope::DhtHeader,
 };
 use futures::{task::Context, Future};
 use log::*;
-use std::task::Poll;
+use std::{task::Poll, time::Instant};
 use tari_comms::{
-    message::{MessageExt, OutboundMessage},
+    message::{MessagingReplyTx, OutboundMessage},
     pipeline::PipelineError,
     Bytes,
 };
@@ -38,14 +40,22 @@ use tower::{layer::Layer, Service, ServiceExt};
 
 const LOG_TARGET: &str = "comms::dht::serialize";
 
+/// # DHT Serialization middleware
+///
+/// Serializes a [DhtOutboundMessage] into a `DhtEnvelope` using the wire format that was selected for the peer when
+/// the send was set up (see [WireFormatSelector](crate::outbound::WireFormatSelector)).
 #[derive(Clone)]
 pub struct SerializeMiddleware<S> {
     inner: S,
+    metrics_collector: MetricsCollectorHandle,
 }
 
 impl<S> SerializeMiddleware<S> {
-    pub fn new(service: S) -> Self {
-        Self { inner: service }
+    pub fn new(metrics_collector: MetricsCollectorHandle, service: S) -> Self {
+        Self {
+            inner: service,
+            metrics_collector,
+        }
     }
 }
 
@@ -63,6 +73,7 @@ where S: Service<OutboundMessage, Response = (), Error = PipelineError> + Clone
 
     fn call(&mut self, message: DhtOutboundMessage) -> Self::Future {
         let next_service = self.inner.clone();
+        let mut metrics_collector = self.metrics_collector.clone();
         async move {
             let DhtOutboundMessage {
                 tag,
@@ -77,6 +88,11 @@ where S: Service<OutboundMessage, Response = (), Error = PipelineError> + Clone
                 origin_mac,
                 reply,
                 expires,
+                mailbox_tag,
+                sequence,
+                cancellation,
+                priority,
+                wire_format,
                 ..
             } = message;
             trace!(
@@ -95,10 +111,20 @@ where S: Service<OutboundMessage, Response = (), Error = PipelineError> + Clone
                 destination: Some(destination.into()),
                 message_tag: tag.as_value(),
                 expires,
+                mailbox_tag: mailbox_tag.map(|t| t.to_vec()).unwrap_or_else(Vec::new),
+                sequence,
             });
-            let envelope = DhtEnvelope::new(dht_header, body);
-
-            let body = Bytes::from(envelope.to_encoded_bytes());
+            metrics_collector.write_metric_wire_format(WireFormatDirection::Outbound, wire_format);
+            let body = Bytes::from(codec::encode_envelope_with_format(
+                wire_format,
+                dht_header,
+                body.to_vec(),
+            ));
+            let reply = instrument_reply(
+                metrics_collector,
+                OutboundPriorityClass::from_message_type(dht_message_type),
+                reply,
+            );
 
             trace!(
                 target: LOG_TARGET,
@@ -112,18 +138,43 @@ where S: Service<OutboundMessage, Response = (), Error = PipelineError> + Clone
                     peer_node_id: destination_node_id,
                     reply,
                     body,
+                    cancellation,
+                    priority,
                 })
                 .await
         }
     }
 }
 
-#[derive(Default)]
-pub struct SerializeLayer;
+/// Attaches an observer to the messaging reply for an outbound message so that the time taken from queuing the message
+/// to it being sent, or the reason it failed to send, is recorded when the reply resolves.
+fn instrument_reply(
+    metrics_collector: MetricsCollectorHandle,
+    class: OutboundPriorityClass,
+    reply: MessagingReplyTx,
+) -> MessagingReplyTx
+{
+    let queued_at = Instant::now();
+    reply.with_observer(move |result| {
+        let mut metrics_collector = metrics_collector;
+        match result {
+            Ok(_) => {
+                metrics_collector.write_metric_outbound_send_latency(class, queued_at.elapsed());
+            },
+            Err(reason) => {
+                metrics_collector.write_metric_outbound_send_failed(*reason);
+            },
+        }
+    })
+}
+
+pub struct SerializeLayer {
+    metrics_collector: MetricsCollectorHandle,
+}
 
 impl SerializeLayer {
-    pub fn new() -> Self {
-        Self
+    pub fn new(metrics_collector: MetricsCollectorHandle) -> Self {
+        Self { metrics_collector }
     }
 }
 
@@ -131,34 +182,106 @@ impl<S> Layer<S> for SerializeLayer {
     type Service = SerializeMiddleware<S>;
 
     fn layer(&self, service: S) -> Self::Service {
-        SerializeMiddleware::new(service)
+        SerializeMiddleware::new(self.metrics_collector.clone(), service)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::test_utils::{create_outbound_message, service_spy};
-    use futures::executor::block_on;
+    use crate::{
+        codec::EnvelopeWireFormat,
+        connectivity::{MetricsCollector, WireFormatCounts},
+        envelope::{DhtMessageType, NodeDestination},
+        proto::envelope::DhtEnvelope,
+        test_utils::{create_outbound_message, make_node_identity, service_spy},
+    };
+    use futures::channel::oneshot;
     use prost::Message;
-    use tari_comms::peer_manager::NodeId;
-    use tari_test_utils::panic_context;
+    use std::time::Duration;
+    use tari_comms::{peer_manager::NodeId, protocol::messaging::SendFailReason};
+    use tari_test_utils::async_assert_eventually;
 
-    #[test]
-    fn serialize() {
+    #[tokio_macros::test_basic]
+    async fn serialize() {
         let spy = service_spy();
-        let mut serialize = SerializeLayer.layer(spy.to_service::<PipelineError>());
+        let mut metrics_collector = MetricsCollector::spawn();
+        let mut serialize = SerializeLayer::new(metrics_collector.clone()).layer(spy.to_service::<PipelineError>());
 
-        panic_context!(cx);
-
-        assert!(serialize.poll_ready(&mut cx).is_ready());
         let body = b"A";
         let msg = create_outbound_message(body);
-        block_on(serialize.call(msg)).unwrap();
+        serialize.ready_and().await.unwrap().call(msg).await.unwrap();
 
         let mut msg = spy.pop_request().unwrap();
         let dht_envelope = DhtEnvelope::decode(&mut msg.body).unwrap();
         assert_eq!(dht_envelope.body, b"A".to_vec());
         assert_eq!(msg.peer_node_id, NodeId::default());
+
+        // The wire format selected for the peer is used
+        let mut msg = create_outbound_message(body);
+        msg.wire_format = EnvelopeWireFormat::V1;
+        serialize.ready_and().await.unwrap().call(msg).await.unwrap();
+
+        let msg = spy.pop_request().unwrap();
+        let (wire_format, _, body) = codec::decode_envelope_with_format(&msg.body).unwrap();
+        assert_eq!(wire_format, EnvelopeWireFormat::V1);
+        assert_eq!(body, b"A".to_vec());
+
+        let peer = make_node_identity().to_peer();
+        let mut msg = create_outbound_message(b"A");
+        msg.destination_node_id = peer.node_id.clone();
+        msg.destination = peer.public_key.clone().into();
+        msg.wire_format = EnvelopeWireFormat::Direct;
+        serialize.ready_and().await.unwrap().call(msg).await.unwrap();
+        let msg = spy.pop_request().unwrap();
+        let (wire_format, header, body) = codec::decode_envelope_with_format(&msg.body).unwrap();
+        assert_eq!(wire_format, EnvelopeWireFormat::Direct);
+        assert_eq!(header.destination, NodeDestination::Unknown);
+        assert_eq!(body, b"A".to_vec());
+
+        let counts = metrics_collector.get_wire_format_counts().await.unwrap();
+        assert_eq!(counts, WireFormatCounts {
+            outbound_legacy: 1,
+            outbound_v1: 1,
+            outbound_direct: 1,
+            ..Default::default()
+        });
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_metrics() {
+        let spy = service_spy();
+        let mut metrics_collector = MetricsCollector::spawn();
+        let mut serialize = SerializeLayer::new(metrics_collector.clone()).layer(spy.to_service::<PipelineError>());
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let mut msg = create_outbound_message(b"A");
+        msg.dht_message_type = DhtMessageType::Discovery;
+        msg.reply = reply_tx.into();
+        serialize.ready_and().await.unwrap().call(msg).await.unwrap();
+        spy.pop_request().unwrap().reply_success();
+        reply_rx.await.unwrap().unwrap();
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let mut msg = create_outbound_message(b"B");
+        msg.reply = reply_tx.into();
+        serialize.ready_and().await.unwrap().call(msg).await.unwrap();
+        spy.pop_request().unwrap().reply_fail(SendFailReason::PeerBanned);
+        assert_eq!(reply_rx.await.unwrap().unwrap_err(), SendFailReason::PeerBanned);
+
+        async_assert_eventually!(
+            metrics_collector
+                .get_outbound_send_metrics()
+                .await
+                .unwrap()
+                .total_failures(),
+            expect = 1,
+            max_attempts = 10,
+            interval = Duration::from_millis(10),
+        );
+        let metrics = metrics_collector.get_outbound_send_metrics().await.unwrap();
+        assert_eq!(metrics.latency(OutboundPriorityClass::Dht).unwrap().count(), 1);
+        assert!(metrics.latency(OutboundPriorityClass::Domain).is_none());
+        assert_eq!(metrics.num_failures(SendFailReason::PeerBanned), 1);
     }
 }
@@ -0,0 +1,173 @@
+// Copyright 2019, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    codec::EnvelopeWireFormat,
+    consts::{DHT_DIRECT_ENVELOPE_PROTOCOL_VERSION, DHT_ENVELOPE_V1_PROTOCOL_VERSION},
+    envelope::NodeDestination,
+};
+use log::*;
+use std::sync::Arc;
+use tari_comms::{
+    connectivity::ConnectivityRequester,
+    peer_manager::{NodeId, PeerManager},
+};
+
+const LOG_TARGET: &str = "comms::dht::outbound::wire_format";
+
+/// Selects the envelope wire format for each peer that a message is sent to. This is done once when the send is set up,
+/// so that serializing each outbound message does not require a peer or connection lookup.
+#[derive(Clone)]
+pub struct WireFormatSelector {
+    peer_manager: Arc<PeerManager>,
+    connectivity: ConnectivityRequester,
+}
+
+impl WireFormatSelector {
+    pub fn new(peer_manager: Arc<PeerManager>, connectivity: ConnectivityRequester) -> Self {
+        Self {
+            peer_manager,
+            connectivity,
+        }
+    }
+
+    /// Returns the wire format to use for each of the given peers, in the same order. The legacy wire format is used
+    /// for peers that are not known or have not advertised support for the versioned wire format. The direct wire
+    /// format is only used if `is_direct` is true, the peer is the final destination of the message, supports the
+    /// format and we already hold an active connection to it.
+    pub async fn select(
+        &mut self,
+        peers: &[NodeId],
+        destination: &NodeDestination,
+        is_direct: bool,
+    ) -> Vec<EnvelopeWireFormat>
+    {
+        let known_peers = match self.peer_manager.get_many(peers).await {
+            Ok(known_peers) => known_peers,
+            Err(err) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Unable to fetch peers to select wire format: {}", err
+                );
+                return vec![EnvelopeWireFormat::Legacy; peers.len()];
+            },
+        };
+
+        let mut formats = Vec::with_capacity(peers.len());
+        for node_id in peers {
+            let peer = match known_peers.iter().find(|peer| peer.node_id == *node_id) {
+                Some(peer) => peer,
+                None => {
+                    formats.push(EnvelopeWireFormat::Legacy);
+                    continue;
+                },
+            };
+            if is_direct &&
+                is_destined_for_peer(destination, node_id) &&
+                peer.supports_protocol_version(DHT_DIRECT_ENVELOPE_PROTOCOL_VERSION) &&
+                self.is_connected(node_id).await
+            {
+                formats.push(EnvelopeWireFormat::Direct);
+            } else if peer.supports_protocol_version(DHT_ENVELOPE_V1_PROTOCOL_VERSION) {
+                formats.push(EnvelopeWireFormat::V1);
+            } else {
+                formats.push(EnvelopeWireFormat::Legacy);
+            }
+        }
+        formats
+    }
+
+    async fn is_connected(&mut self, node_id: &NodeId) -> bool {
+        match self.connectivity.get_connection(node_id.clone()).await {
+            Ok(Some(conn)) => conn.is_connected(),
+            Ok(None) => false,
+            Err(err) => {
+                debug!(target: LOG_TARGET, "Unable to check connection to peer: {}", err);
+                false
+            },
+        }
+    }
+}
+
+/// Returns true if the peer being sent to is the final destination of the message
+fn is_destined_for_peer(destination: &NodeDestination, node_id: &NodeId) -> bool {
+    match destination {
+        NodeDestination::NodeId(dest) => **dest == *node_id,
+        NodeDestination::PublicKey(pk) => NodeId::from_key(&**pk).map(|dest| dest == *node_id).unwrap_or(false),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{build_peer_manager, make_node_identity};
+    use tari_comms::test_utils::mocks::{create_connectivity_mock, create_dummy_peer_connection};
+
+    #[tokio_macros::test_basic]
+    async fn select() {
+        let peer_manager = build_peer_manager();
+        let (connectivity, connectivity_mock) = create_connectivity_mock();
+        let connectivity_state = connectivity_mock.get_shared_state();
+        connectivity_mock.spawn();
+        let mut selector = WireFormatSelector::new(peer_manager.clone(), connectivity);
+
+        let legacy_peer = make_node_identity().to_peer();
+        peer_manager.add_peer(legacy_peer.clone()).await.unwrap();
+        let mut v1_peer = make_node_identity().to_peer();
+        v1_peer.protocol_versions = 1 << DHT_ENVELOPE_V1_PROTOCOL_VERSION;
+        peer_manager.add_peer(v1_peer.clone()).await.unwrap();
+        let mut direct_peer = make_node_identity().to_peer();
+        direct_peer.protocol_versions =
+            (1 << DHT_ENVELOPE_V1_PROTOCOL_VERSION) | (1 << DHT_DIRECT_ENVELOPE_PROTOCOL_VERSION);
+        peer_manager.add_peer(direct_peer.clone()).await.unwrap();
+        let unknown_peer = make_node_identity().to_peer();
+
+        let peers = vec![
+            legacy_peer.node_id.clone(),
+            v1_peer.node_id.clone(),
+            unknown_peer.node_id.clone(),
+        ];
+        let formats = selector.select(&peers, &NodeDestination::Unknown, false).await;
+        assert_eq!(formats, vec![
+            EnvelopeWireFormat::Legacy,
+            EnvelopeWireFormat::V1,
+            EnvelopeWireFormat::Legacy
+        ]);
+
+        // Not connected to the peer, so the versioned wire format is used
+        let peers = vec![direct_peer.node_id.clone()];
+        let destination = NodeDestination::from(direct_peer.public_key.clone());
+        let formats = selector.select(&peers, &destination, true).await;
+        assert_eq!(formats, vec![EnvelopeWireFormat::V1]);
+
+        let (conn, _conn_rx) = create_dummy_peer_connection(direct_peer.node_id.clone());
+        connectivity_state.add_active_connection(conn).await;
+        let formats = selector.select(&peers, &destination, true).await;
+        assert_eq!(formats, vec![EnvelopeWireFormat::Direct]);
+
+        // Messages that the peer should forward are not sent in the direct wire format
+        let destination = NodeDestination::from(make_node_identity().public_key().clone());
+        let formats = selector.select(&peers, &destination, true).await;
+        assert_eq!(formats, vec![EnvelopeWireFormat::V1]);
+    }
+}
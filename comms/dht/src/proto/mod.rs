@@ -40,6 +40,10 @@ pub mod dht {
     tari_comms::outdir_include!("tari.dht.rs");
 }
 
+pub mod group {
+    tari_comms::outdir_include!("tari.dht.group.rs");
+}
+
 pub mod rpc {
     tari_comms::outdir_include!("tari.dht.rpc.rs");
 }
@@ -87,9 +91,21 @@ impl<T: AsRef<NodeIdentity>> From<T> for JoinMessage {
         let node_identity = identity.as_ref();
         Self {
             node_id: node_identity.node_id().to_vec(),
-            addresses: vec![node_identity.public_address().to_string()],
+            addresses: node_identity
+                .public_addresses()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
             peer_features: node_identity.features().bits(),
             nonce: OsRng.next_u64(),
+            metadata: node_identity
+                .metadata()
+                .into_iter()
+                .map(|(key, value)| dht::PeerMetadata {
+                    key: u32::from(key),
+                    value,
+                })
+                .collect(),
         }
     }
 }
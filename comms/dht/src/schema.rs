@@ -20,7 +20,19 @@ table! {
         priority -> Integer,
         stored_at -> Timestamp,
         body_hash -> Text,
+        mailbox_tag -> Nullable<Text>,
+        destination_node_ids -> Nullable<Text>,
     }
 }
 
-allow_tables_to_appear_in_same_query!(dht_metadata, stored_messages,);
+table! {
+    scheduled_messages (id) {
+        id -> Integer,
+        params -> Binary,
+        body -> Binary,
+        send_at -> Timestamp,
+        scheduled_at -> Timestamp,
+    }
+}
+
+allow_tables_to_appear_in_same_query!(dht_metadata, scheduled_messages, stored_messages,);
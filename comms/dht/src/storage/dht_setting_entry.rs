@@ -27,6 +27,18 @@ use std::fmt;
 pub enum DhtMetadataKey {
     /// Timestamp each time the DHT is shut down
     OfflineTimestamp,
+    /// Timestamp of the last Join message broadcast by this node
+    LastJoinTimestamp,
+    /// The last time stored messages were received from each peer
+    SafRetrievalTimestamps,
+    /// Discovery success history
+    DiscoveryStats,
+    /// Snapshot of the message hash (dedup) cache
+    MsgHashCache,
+    /// Public keys of the client peers served by the store and forward service
+    SafServedClients,
+    /// The peers that this node was recently connected to and the time each was last seen connected
+    RecentPeers,
 }
 
 impl fmt::Display for DhtMetadataKey {
@@ -38,4 +38,6 @@ pub enum StorageError {
     ResultError(#[from] diesel::result::Error),
     #[error("MessageFormatError: {0}")]
     MessageFormatError(#[from] MessageFormatError),
+    #[error("A different message with the message key '{0}' is already stored")]
+    MessageKeyCollision(String),
 }
@@ -0,0 +1,126 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{config::DhtConfig, proto::store_forward as proto};
+use prost::Message;
+use std::{cmp, fmt, time::Duration};
+use tari_comms::{
+    message::MessageExt,
+    peer_manager::{Peer, PeerFeatures},
+};
+
+/// The peer metadata key under which a node advertises its store and forward capability
+pub const SAF_CAPABILITY_METADATA_KEY: u8 = 1;
+
+/// The store and forward storage offered by a node. This is advertised in the identity exchange and in join messages
+/// so that nodes only request stored messages from peers that actually store them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SafCapability {
+    /// The approximate number of messages that the node stores for other peers
+    pub storage_capacity: usize,
+    /// The approximate time for which the node retains stored messages
+    pub retention: Duration,
+}
+
+impl SafCapability {
+    /// The capability offered by a node using the given config
+    pub fn from_config(config: &DhtConfig) -> Self {
+        Self {
+            storage_capacity: config.saf_msg_storage_capacity,
+            retention: cmp::max(
+                config.saf_high_priority_msg_storage_ttl,
+                config.saf_low_priority_msg_storage_ttl,
+            ),
+        }
+    }
+
+    /// Returns the capability advertised by the peer, or None if the peer has not advertised one or the advertisement
+    /// is invalid
+    pub fn from_peer(peer: &Peer) -> Option<Self> {
+        let bytes = peer.get_metadata(SAF_CAPABILITY_METADATA_KEY)?;
+        let capability = proto::SafCapability::decode(bytes.as_slice()).ok()?;
+        Some(Self {
+            storage_capacity: capability.storage_capacity as usize,
+            retention: Duration::from_secs(capability.retention_secs),
+        })
+    }
+
+    /// Returns true if the peer stores messages for other peers. Peers that have the store and forward feature but
+    /// have not advertised a capability (i.e. nodes that predate capability advertisement) are assumed to store
+    /// messages.
+    pub fn is_provider(peer: &Peer) -> bool {
+        if !peer.features.contains(PeerFeatures::DHT_STORE_FORWARD) {
+            return false;
+        }
+
+        Self::from_peer(peer)
+            .map(|capability| capability.storage_capacity > 0 && capability.retention > Duration::from_secs(0))
+            .unwrap_or(true)
+    }
+
+    pub fn to_encoded_bytes(&self) -> Vec<u8> {
+        proto::SafCapability {
+            storage_capacity: self.storage_capacity as u64,
+            retention_secs: self.retention.as_secs(),
+        }
+        .to_encoded_bytes()
+    }
+}
+
+impl fmt::Display for SafCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "capacity = {} message(s), retention = {:.2?}",
+            self.storage_capacity, self.retention
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_node_identity;
+
+    #[test]
+    fn is_provider() {
+        let mut peer = make_node_identity().to_peer();
+        peer.features = PeerFeatures::COMMUNICATION_CLIENT;
+        assert!(!SafCapability::is_provider(&peer));
+
+        // Legacy store and forward nodes do not advertise a capability
+        peer.features = PeerFeatures::COMMUNICATION_NODE;
+        assert!(SafCapability::is_provider(&peer));
+
+        let capability = SafCapability::from_config(&Default::default());
+        peer.set_metadata(SAF_CAPABILITY_METADATA_KEY, capability.to_encoded_bytes());
+        assert_eq!(SafCapability::from_peer(&peer), Some(capability));
+        assert!(SafCapability::is_provider(&peer));
+
+        let no_storage = SafCapability {
+            storage_capacity: 0,
+            ..capability
+        };
+        peer.set_metadata(SAF_CAPABILITY_METADATA_KEY, no_storage.to_encoded_bytes());
+        assert!(!SafCapability::is_provider(&peer));
+    }
+}
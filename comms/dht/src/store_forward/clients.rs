@@ -0,0 +1,103 @@
+// Copyright 2019, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use tari_comms::{peer_manager::NodeId, types::CommsPublicKey};
+
+/// The set of client peers that this node relays stored messages for. Messages destined for a served client are
+/// stored with `StoredMessagePriority::Client` and are pushed to the client as soon as it connects.
+///
+/// This is cheap to clone and all clones share the same set of clients.
+#[derive(Debug, Clone, Default)]
+pub struct ServedClients {
+    inner: Arc<RwLock<HashMap<NodeId, CommsPublicKey>>>,
+}
+
+impl ServedClients {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a client. Returns true if the client was not already being served.
+    pub fn insert(&self, public_key: CommsPublicKey) -> bool {
+        let node_id = NodeId::from_public_key(&public_key);
+        acquire_lock!(self.inner, write).insert(node_id, public_key).is_none()
+    }
+
+    /// Removes a client. Returns true if the client was being served.
+    pub fn remove(&self, public_key: &CommsPublicKey) -> bool {
+        let node_id = NodeId::from_public_key(public_key);
+        acquire_lock!(self.inner, write).remove(&node_id).is_some()
+    }
+
+    pub fn contains(&self, node_id: &NodeId) -> bool {
+        acquire_lock!(self.inner, read).contains_key(node_id)
+    }
+
+    /// Returns the public key of the served client with the given `NodeId`, if any
+    pub fn get(&self, node_id: &NodeId) -> Option<CommsPublicKey> {
+        acquire_lock!(self.inner, read).get(node_id).cloned()
+    }
+
+    pub fn public_keys(&self) -> Vec<CommsPublicKey> {
+        acquire_lock!(self.inner, read).values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        acquire_lock!(self.inner, read).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::make_node_identity;
+
+    #[test]
+    fn insert_remove() {
+        let clients = ServedClients::new();
+        let node_identity = make_node_identity();
+        let public_key = node_identity.public_key().clone();
+
+        assert!(clients.is_empty());
+        assert!(clients.insert(public_key.clone()));
+        assert!(!clients.insert(public_key.clone()));
+        // Clones share the same set of clients
+        let cloned = clients.clone();
+        assert!(cloned.contains(node_identity.node_id()));
+        assert_eq!(clients.get(node_identity.node_id()), Some(public_key.clone()));
+        assert_eq!(clients.public_keys(), vec![public_key.clone()]);
+
+        assert!(clients.remove(&public_key));
+        assert!(!clients.remove(&public_key));
+        assert!(!clients.contains(node_identity.node_id()));
+        assert!(clients.is_empty());
+        assert!(cloned.is_empty());
+    }
+}
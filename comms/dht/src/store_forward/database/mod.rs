@@ -20,6 +20,9 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+mod saf_storage;
+pub use saf_storage::{SafBatchResult, SafDestinationUsage, SafStorage, SafWriteBatch};
+
 mod stored_message;
 pub use stored_message::{NewStoredMessage, StoredMessage};
 
@@ -30,8 +33,20 @@ use crate::{
     store_forward::message::StoredMessagePriority,
 };
 use chrono::{DateTime, NaiveDateTime, Utc};
-use diesel::{dsl, result::DatabaseErrorKind, BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl};
-use tari_comms::{peer_manager::NodeId, types::CommsPublicKey};
+use diesel::{
+    dsl,
+    query_dsl::GroupByDsl,
+    result::DatabaseErrorKind,
+    sql_types::{BigInt, Nullable, Text},
+    BoolExpressionMethods,
+    Connection,
+    ExpressionMethods,
+    QueryDsl,
+    RunQueryDsl,
+    SqliteConnection,
+    TextExpressionMethods,
+};
+use tari_comms::{async_trait, peer_manager::NodeId, types::CommsPublicKey};
 use tari_utilities::hex::Hex;
 
 pub struct StoreAndForwardDatabase {
@@ -43,17 +58,52 @@ impl StoreAndForwardDatabase {
         Self { connection }
     }
 
-    /// Inserts and returns Ok(true) if the item already existed and Ok(false) if it didn't
-    pub async fn insert_message_if_unique(&self, message: NewStoredMessage) -> Result<bool, StorageError> {
+    #[cfg(test)]
+    pub(crate) async fn get_all_messages(&self) -> Result<Vec<StoredMessage>, StorageError> {
+        self.connection
+            .with_connection_async(|conn| {
+                stored_messages::table
+                    .select(stored_messages::all_columns)
+                    .get_results(conn)
+                    .map_err(Into::into)
+            })
+            .await
+    }
+
+    /// Returns up to `limit` of the most recently stored messages, regardless of type or destination
+    #[cfg(any(test, feature = "fuzzing"))]
+    pub(crate) async fn find_recent_messages(&self, limit: i64) -> Result<Vec<StoredMessage>, StorageError> {
+        self.connection
+            .with_connection_async(move |conn| {
+                stored_messages::table
+                    .select(stored_messages::all_columns)
+                    .order_by(stored_messages::stored_at.desc())
+                    .limit(limit)
+                    .get_results(conn)
+                    .map_err(Into::into)
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl SafStorage for StoreAndForwardDatabase {
+    async fn insert_message_if_unique(&self, message: NewStoredMessage) -> Result<bool, StorageError> {
         self.connection
             .with_connection_async(move |conn| {
                 match diesel::insert_into(stored_messages::table)
-                    .values(message)
+                    .values(&message)
                     .execute(conn)
                 {
                     Ok(_) => Ok(false),
                     Err(diesel::result::Error::DatabaseError(kind, e_info)) => match kind {
-                        DatabaseErrorKind::UniqueViolation => Ok(true),
+                        DatabaseErrorKind::UniqueViolation => {
+                            if is_stored(conn, &message)? {
+                                Ok(true)
+                            } else {
+                                Err(StorageError::MessageKeyCollision(message.body_hash))
+                            }
+                        },
                         _ => Err(diesel::result::Error::DatabaseError(kind, e_info).into()),
                     },
                     Err(e) => Err(e.into()),
@@ -62,7 +112,7 @@ impl StoreAndForwardDatabase {
             .await
     }
 
-    pub async fn remove_message(&self, message_ids: Vec<i32>) -> Result<usize, StorageError> {
+    async fn remove_message(&self, message_ids: Vec<i32>) -> Result<usize, StorageError> {
         self.connection
             .with_connection_async(move |conn| {
                 diesel::delete(stored_messages::table)
@@ -73,7 +123,7 @@ impl StoreAndForwardDatabase {
             .await
     }
 
-    pub async fn find_messages_for_peer(
+    async fn find_messages_for_peer(
         &self,
         public_key: &CommsPublicKey,
         node_id: &NodeId,
@@ -90,9 +140,14 @@ impl StoreAndForwardDatabase {
                     .filter(
                         stored_messages::destination_pubkey
                             .eq(pk_hex)
-                            .or(stored_messages::destination_node_id.eq(node_id_hex)),
+                            .or(stored_messages::destination_node_id.eq(node_id_hex.clone()))
+                            .or(stored_messages::destination_node_ids.like(format!("%{}%", node_id_hex))),
+                    )
+                    // Discovery requests stored for an offline peer are delivered along with its domain messages
+                    .filter(
+                        stored_messages::message_type
+                            .eq_any(vec![DhtMessageType::None as i32, DhtMessageType::Discovery as i32]),
                     )
-                    .filter(stored_messages::message_type.eq(DhtMessageType::None as i32))
                     .into_boxed();
 
                 if let Some(since) = since {
@@ -108,7 +163,55 @@ impl StoreAndForwardDatabase {
             .await
     }
 
-    pub async fn find_anonymous_messages(
+    async fn count_messages_for_peer(
+        &self,
+        public_key: &CommsPublicKey,
+        node_id: &NodeId,
+    ) -> Result<usize, StorageError>
+    {
+        let pk_hex = public_key.to_hex();
+        let node_id_hex = node_id.to_hex();
+        self.connection
+            .with_connection_async(move |conn| {
+                let count = stored_messages::table
+                    .select(dsl::count(stored_messages::id))
+                    .filter(
+                        stored_messages::destination_pubkey
+                            .eq(pk_hex)
+                            .or(stored_messages::destination_node_id.eq(node_id_hex.clone()))
+                            .or(stored_messages::destination_node_ids.like(format!("%{}%", node_id_hex))),
+                    )
+                    .filter(stored_messages::message_type.eq(DhtMessageType::None as i32))
+                    .first::<i64>(conn)?;
+                Ok(count as usize)
+            })
+            .await
+    }
+
+    async fn delete_messages_involving_peer(
+        &self,
+        public_key: &CommsPublicKey,
+        node_id: &NodeId,
+    ) -> Result<usize, StorageError>
+    {
+        let pk_hex = public_key.to_hex();
+        let node_id_hex = node_id.to_hex();
+        self.connection
+            .with_connection_async(move |conn| {
+                diesel::delete(stored_messages::table)
+                    .filter(
+                        stored_messages::origin_pubkey
+                            .eq(pk_hex.clone())
+                            .or(stored_messages::destination_pubkey.eq(pk_hex))
+                            .or(stored_messages::destination_node_id.eq(node_id_hex)),
+                    )
+                    .execute(conn)
+                    .map_err(Into::into)
+            })
+            .await
+    }
+
+    async fn find_anonymous_messages(
         &self,
         since: Option<DateTime<Utc>>,
         limit: i64,
@@ -120,6 +223,7 @@ impl StoreAndForwardDatabase {
                     .select(stored_messages::all_columns)
                     .filter(stored_messages::origin_pubkey.is_null())
                     .filter(stored_messages::destination_pubkey.is_null())
+                    .filter(stored_messages::mailbox_tag.is_null())
                     .filter(stored_messages::is_encrypted.eq(true))
                     .filter(stored_messages::message_type.eq(DhtMessageType::None as i32))
                     .into_boxed();
@@ -137,7 +241,7 @@ impl StoreAndForwardDatabase {
             .await
     }
 
-    pub async fn find_join_messages(
+    async fn find_join_messages(
         &self,
         since: Option<DateTime<Utc>>,
         limit: i64,
@@ -163,7 +267,7 @@ impl StoreAndForwardDatabase {
             .await
     }
 
-    pub async fn find_messages_of_type_for_pubkey(
+    async fn find_messages_of_type_for_pubkey(
         &self,
         public_key: &CommsPublicKey,
         message_type: DhtMessageType,
@@ -193,19 +297,107 @@ impl StoreAndForwardDatabase {
             .await
     }
 
-    #[cfg(test)]
-    pub(crate) async fn get_all_messages(&self) -> Result<Vec<StoredMessage>, StorageError> {
+    async fn find_messages_by_mailbox_tags(
+        &self,
+        mailbox_tags: Vec<String>,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<StoredMessage>, StorageError>
+    {
         self.connection
-            .with_connection_async(|conn| {
+            .with_connection_async(move |conn| {
+                let mut query = stored_messages::table
+                    .select(stored_messages::all_columns)
+                    .filter(stored_messages::mailbox_tag.eq_any(mailbox_tags))
+                    .into_boxed();
+
+                if let Some(since) = since {
+                    query = query.filter(stored_messages::stored_at.gt(since.naive_utc()));
+                }
+
+                query
+                    .order_by(stored_messages::stored_at.desc())
+                    .limit(limit)
+                    .get_results(conn)
+                    .map_err(Into::into)
+            })
+            .await
+    }
+
+    async fn find_messages_with_destination(
+        &self,
+        after_id: i32,
+        limit: i64,
+    ) -> Result<Vec<StoredMessage>, StorageError>
+    {
+        self.connection
+            .with_connection_async(move |conn| {
                 stored_messages::table
                     .select(stored_messages::all_columns)
+                    .filter(
+                        stored_messages::destination_pubkey
+                            .is_not_null()
+                            .or(stored_messages::destination_node_id.is_not_null()),
+                    )
+                    .filter(stored_messages::id.gt(after_id))
+                    .order_by(stored_messages::id.asc())
+                    .limit(limit)
                     .get_results(conn)
                     .map_err(Into::into)
             })
             .await
     }
 
-    pub(crate) async fn delete_messages_with_priority_older_than(
+    async fn total_message_size(&self) -> Result<usize, StorageError> {
+        self.connection
+            .with_connection_async(|conn| {
+                let total = stored_messages::table
+                    .select(dsl::sql::<Nullable<BigInt>>("SUM(LENGTH(header) + LENGTH(body))"))
+                    .first::<Option<i64>>(conn)?;
+                Ok(total.unwrap_or(0) as usize)
+            })
+            .await
+    }
+
+    async fn count_messages(&self) -> Result<usize, StorageError> {
+        self.connection
+            .with_connection_async(|conn| {
+                let count = stored_messages::table
+                    .select(dsl::count(stored_messages::id))
+                    .first::<i64>(conn)?;
+                Ok(count as usize)
+            })
+            .await
+    }
+
+    async fn count_messages_by_destination(&self, limit: i64) -> Result<Vec<SafDestinationUsage>, StorageError> {
+        self.connection
+            .with_connection_async(move |conn| {
+                let rows = stored_messages::table
+                    .select((
+                        dsl::sql::<Nullable<Text>>("COALESCE(destination_pubkey, destination_node_id)"),
+                        dsl::sql::<BigInt>("COUNT(*)"),
+                        dsl::sql::<BigInt>("SUM(LENGTH(header) + LENGTH(body))"),
+                    ))
+                    .group_by(dsl::sql::<Nullable<Text>>(
+                        "COALESCE(destination_pubkey, destination_node_id)",
+                    ))
+                    .order_by(dsl::sql::<BigInt>("COUNT(*) DESC"))
+                    .limit(limit)
+                    .load::<(Option<String>, i64, i64)>(conn)?;
+                Ok(rows
+                    .into_iter()
+                    .map(|(destination, num_messages, num_bytes)| SafDestinationUsage {
+                        destination,
+                        num_messages: num_messages as usize,
+                        num_bytes: num_bytes as usize,
+                    })
+                    .collect())
+            })
+            .await
+    }
+
+    async fn delete_messages_with_priority_older_than(
         &self,
         priority: StoredMessagePriority,
         since: NaiveDateTime,
@@ -222,7 +414,7 @@ impl StoreAndForwardDatabase {
             .await
     }
 
-    pub(crate) async fn truncate_messages(&self, max_size: usize) -> Result<usize, StorageError> {
+    async fn truncate_messages(&self, max_size: usize) -> Result<usize, StorageError> {
         self.connection
             .with_connection_async(move |conn| {
                 let mut num_removed = 0;
@@ -244,12 +436,72 @@ impl StoreAndForwardDatabase {
             })
             .await
     }
+
+    async fn write_batch(&self, batch: SafWriteBatch) -> Result<SafBatchResult, StorageError> {
+        self.connection
+            .with_connection_async(move |conn| {
+                conn.transaction::<_, StorageError, _>(|| {
+                    let mut result = SafBatchResult::default();
+                    for message in batch.inserts {
+                        let num_rows = diesel::insert_or_ignore_into(stored_messages::table)
+                            .values(&message)
+                            .execute(conn)?;
+                        if num_rows == 0 {
+                            if is_stored(conn, &message)? {
+                                result.num_duplicates += 1;
+                            } else {
+                                result.num_collisions += 1;
+                            }
+                        } else {
+                            result.num_inserted += 1;
+                        }
+                    }
+                    if !batch.deletes.is_empty() {
+                        result.num_deleted = diesel::delete(stored_messages::table)
+                            .filter(stored_messages::id.eq_any(batch.deletes))
+                            .execute(conn)?;
+                    }
+                    Ok(result)
+                })
+            })
+            .await
+    }
+
+    async fn take_messages(&self, message_ids: Vec<i32>) -> Result<Vec<StoredMessage>, StorageError> {
+        self.connection
+            .with_connection_async(move |conn| {
+                conn.transaction::<_, StorageError, _>(|| {
+                    let messages: Vec<StoredMessage> = stored_messages::table
+                        .select(stored_messages::all_columns)
+                        .filter(stored_messages::id.eq_any(message_ids))
+                        .get_results(conn)?;
+                    diesel::delete(stored_messages::table)
+                        .filter(stored_messages::id.eq_any(messages.iter().map(|m| m.id).collect::<Vec<_>>()))
+                        .execute(conn)?;
+                    Ok(messages)
+                })
+            })
+            .await
+    }
+}
+
+/// Returns true if the message stored with the same message key has the same header and body. The message key does not
+/// commit to every header field, so a stored message with the same key but a different header or body is a collision
+/// rather than a duplicate.
+fn is_stored(conn: &SqliteConnection, message: &NewStoredMessage) -> Result<bool, StorageError> {
+    let (header, body) = stored_messages::table
+        .select((stored_messages::header, stored_messages::body))
+        .filter(stored_messages::body_hash.eq(&message.body_hash))
+        .first::<(Vec<u8>, Vec<u8>)>(conn)?;
+    Ok(header == message.header && body == message.body)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use tari_test_utils::random;
+    use rand::rngs::OsRng;
+    use tari_crypto::keys::PublicKey;
+    use tari_test_utils::{random, unpack_enum};
 
     #[tokio_macros::test_basic]
     async fn insert_messages() {
@@ -271,6 +523,41 @@ mod test {
         assert_eq!(messages[1].body_hash, msg2.body_hash);
     }
 
+    #[tokio_macros::test_basic]
+    async fn insert_message_key_collision() {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+        let mut msg = NewStoredMessage::default();
+        msg.body_hash.push('1');
+        msg.header = b"header".to_vec();
+        msg.body = b"body".to_vec();
+        assert_eq!(db.insert_message_if_unique(msg.clone()).await.unwrap(), false);
+        assert_eq!(db.insert_message_if_unique(msg.clone()).await.unwrap(), true);
+
+        let mut other_header = msg.clone();
+        other_header.header = b"other header".to_vec();
+        let err = db.insert_message_if_unique(other_header.clone()).await.unwrap_err();
+        unpack_enum!(StorageError::MessageKeyCollision(body_hash) = err);
+        assert_eq!(body_hash, msg.body_hash);
+
+        let mut other_body = msg.clone();
+        other_body.body = b"other body".to_vec();
+        let err = db.insert_message_if_unique(other_body.clone()).await.unwrap_err();
+        unpack_enum!(StorageError::MessageKeyCollision(_body_hash) = err);
+
+        let mut batch = SafWriteBatch::new();
+        batch.insert(msg.clone()).insert(other_header).insert(other_body);
+        let result = db.write_batch(batch).await.unwrap();
+        assert_eq!(result.num_duplicates, 1);
+        assert_eq!(result.num_collisions, 2);
+
+        let messages = db.get_all_messages().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].header, msg.header);
+        assert_eq!(messages[0].body, msg.body);
+    }
+
     #[tokio_macros::test_basic]
     async fn remove_messages() {
         let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
@@ -322,4 +609,266 @@ mod test {
         assert_eq!(messages[0].body_hash, msg3.body_hash);
         assert_eq!(messages[1].body_hash, msg4.body_hash);
     }
+
+    #[tokio_macros::test_basic]
+    async fn total_message_size() {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+        assert_eq!(db.total_message_size().await.unwrap(), 0);
+
+        let mut msg1 = NewStoredMessage::default();
+        msg1.body_hash.push('1');
+        msg1.header = vec![0u8; 10];
+        msg1.body = vec![0u8; 100];
+        let mut msg2 = NewStoredMessage::default();
+        msg2.body_hash.push('2');
+        msg2.body = vec![0u8; 50];
+        db.insert_message_if_unique(msg1).await.unwrap();
+        db.insert_message_if_unique(msg2).await.unwrap();
+        assert_eq!(db.total_message_size().await.unwrap(), 160);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn count_messages_by_destination() {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+        for (i, destination) in ["aa", "bb", "aa", "bb", "aa"].iter().enumerate() {
+            let mut msg = NewStoredMessage::default();
+            msg.body_hash = i.to_string();
+            msg.destination_pubkey = Some(destination.to_string());
+            msg.body = vec![0u8; 10];
+            db.insert_message_if_unique(msg).await.unwrap();
+        }
+        let mut msg = NewStoredMessage::default();
+        msg.body_hash = "anon".to_string();
+        db.insert_message_if_unique(msg).await.unwrap();
+
+        let usage = db.count_messages_by_destination(2).await.unwrap();
+        assert_eq!(usage, vec![
+            SafDestinationUsage {
+                destination: Some("aa".to_string()),
+                num_messages: 3,
+                num_bytes: 30,
+            },
+            SafDestinationUsage {
+                destination: Some("bb".to_string()),
+                num_messages: 2,
+                num_bytes: 20,
+            },
+        ]);
+        let usage = db.count_messages_by_destination(10).await.unwrap();
+        assert_eq!(usage.len(), 3);
+        assert_eq!(usage[2].destination, None);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn write_batch() {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+        let mut msg1 = NewStoredMessage::default();
+        msg1.body_hash.push('1');
+        db.insert_message_if_unique(msg1.clone()).await.unwrap();
+        let msg1_id = db.get_all_messages().await.unwrap()[0].id;
+
+        let mut msg2 = NewStoredMessage::default();
+        msg2.body_hash.push('2');
+        let mut batch = SafWriteBatch::new();
+        batch.insert(msg1.clone()).insert(msg2.clone()).delete(vec![msg1_id]);
+        assert_eq!(batch.len(), 3);
+        let result = db.write_batch(batch).await.unwrap();
+        assert_eq!(result, SafBatchResult {
+            num_inserted: 1,
+            num_duplicates: 1,
+            num_collisions: 0,
+            num_deleted: 1,
+        });
+        let messages = db.get_all_messages().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].body_hash, msg2.body_hash);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn take_messages() {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+        let mut msg1 = NewStoredMessage::default();
+        msg1.body_hash.push('1');
+        let mut msg2 = NewStoredMessage::default();
+        msg2.body_hash.push('2');
+        db.insert_message_if_unique(msg1.clone()).await.unwrap();
+        db.insert_message_if_unique(msg2.clone()).await.unwrap();
+        let messages = db.get_all_messages().await.unwrap();
+        let msg1_id = messages[0].id;
+
+        let taken = db.take_messages(vec![msg1_id, 999]).await.unwrap();
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].body_hash, msg1.body_hash);
+        // Already taken
+        let taken = db.take_messages(vec![msg1_id]).await.unwrap();
+        assert!(taken.is_empty());
+        let messages = db.get_all_messages().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].body_hash, msg2.body_hash);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn find_messages_by_mailbox_tags() {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+
+        let mut tagged = NewStoredMessage::default();
+        tagged.body_hash.push('1');
+        tagged.mailbox_tag = Some("aabb".to_string());
+        let mut other_tag = NewStoredMessage::default();
+        other_tag.body_hash.push('2');
+        other_tag.mailbox_tag = Some("ccdd".to_string());
+        let mut untagged = NewStoredMessage::default();
+        untagged.body_hash.push('3');
+        for msg in vec![tagged.clone(), other_tag.clone(), untagged] {
+            db.insert_message_if_unique(msg).await.unwrap();
+        }
+
+        let messages = db
+            .find_messages_by_mailbox_tags(vec!["aabb".to_string()], None, 10)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].body_hash, tagged.body_hash);
+
+        let messages = db
+            .find_messages_by_mailbox_tags(vec!["aabb".to_string(), "ccdd".to_string()], None, 10)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 2);
+
+        let messages = db.find_messages_by_mailbox_tags(vec![], None, 10).await.unwrap();
+        assert!(messages.is_empty());
+    }
+
+    #[tokio_macros::test_basic]
+    async fn find_messages_with_destination() {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+        let (_, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let node_id = NodeId::from_key(&pk).unwrap();
+
+        let mut to_pubkey = NewStoredMessage::default();
+        to_pubkey.body_hash.push('1');
+        to_pubkey.destination_pubkey = Some(pk.to_hex());
+        let mut anonymous = NewStoredMessage::default();
+        anonymous.body_hash.push('2');
+        let mut to_node_id = NewStoredMessage::default();
+        to_node_id.body_hash.push('3');
+        to_node_id.destination_node_id = Some(node_id.to_hex());
+        for msg in vec![to_pubkey.clone(), anonymous, to_node_id.clone()] {
+            db.insert_message_if_unique(msg).await.unwrap();
+        }
+
+        let messages = db.find_messages_with_destination(0, 10).await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].body_hash, to_pubkey.body_hash);
+        assert_eq!(messages[1].body_hash, to_node_id.body_hash);
+
+        let messages = db.find_messages_with_destination(messages[0].id, 10).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].body_hash, to_node_id.body_hash);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn find_messages_for_peer() {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+        let (_, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let node_id = NodeId::from_key(&pk).unwrap();
+
+        let mut domain = NewStoredMessage::default();
+        domain.body_hash.push('1');
+        domain.destination_pubkey = Some(pk.to_hex());
+        let mut discovery = NewStoredMessage::default();
+        discovery.body_hash.push('2');
+        discovery.destination_pubkey = Some(pk.to_hex());
+        discovery.message_type = DhtMessageType::Discovery as i32;
+        let mut join = NewStoredMessage::default();
+        join.body_hash.push('3');
+        join.destination_node_id = Some(node_id.to_hex());
+        join.message_type = DhtMessageType::Join as i32;
+        let mut other = NewStoredMessage::default();
+        other.body_hash.push('4');
+        for msg in vec![domain.clone(), discovery.clone(), join, other] {
+            db.insert_message_if_unique(msg).await.unwrap();
+        }
+
+        let messages = db.find_messages_for_peer(&pk, &node_id, None, 10).await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().any(|m| m.body_hash == domain.body_hash));
+        assert!(messages.iter().any(|m| m.body_hash == discovery.body_hash));
+    }
+
+    #[tokio_macros::test_basic]
+    async fn find_multicast_messages_for_peer() {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+        let (_, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let node_id = NodeId::from_key(&pk).unwrap();
+        let (_, other_pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let other_node_id = NodeId::from_key(&other_pk).unwrap();
+
+        let mut multicast = NewStoredMessage::default();
+        multicast.body_hash.push('1');
+        multicast.destination_node_ids = Some(format!("{},{}", other_node_id.to_hex(), node_id.to_hex()));
+        db.insert_message_if_unique(multicast.clone()).await.unwrap();
+
+        let messages = db.find_messages_for_peer(&pk, &node_id, None, 10).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].body_hash, multicast.body_hash);
+        let messages = db
+            .find_messages_for_peer(&other_pk, &other_node_id, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(db.count_messages_for_peer(&pk, &node_id).await.unwrap(), 1);
+
+        let (_, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let node_id = NodeId::from_key(&pk).unwrap();
+        let messages = db.find_messages_for_peer(&pk, &node_id, None, 10).await.unwrap();
+        assert!(messages.is_empty());
+    }
+
+    #[tokio_macros::test_basic]
+    async fn delete_messages_involving_peer() {
+        let conn = DbConnection::connect_memory(random::string(8)).await.unwrap();
+        conn.migrate().await.unwrap();
+        let db = StoreAndForwardDatabase::new(conn);
+        let (_, banned_pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let banned_node_id = NodeId::from_key(&banned_pk).unwrap();
+
+        let mut from_banned = NewStoredMessage::default();
+        from_banned.body_hash.push('1');
+        from_banned.origin_pubkey = Some(banned_pk.to_hex());
+        let mut to_banned = NewStoredMessage::default();
+        to_banned.body_hash.push('2');
+        to_banned.destination_node_id = Some(banned_node_id.to_hex());
+        let mut other = NewStoredMessage::default();
+        other.body_hash.push('3');
+        for msg in vec![from_banned, to_banned, other.clone()] {
+            db.insert_message_if_unique(msg).await.unwrap();
+        }
+
+        let num_deleted = db
+            .delete_messages_involving_peer(&banned_pk, &banned_node_id)
+            .await
+            .unwrap();
+        assert_eq!(num_deleted, 2);
+        let messages = db.get_all_messages().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].body_hash, other.body_hash);
+    }
 }
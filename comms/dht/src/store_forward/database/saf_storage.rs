@@ -0,0 +1,184 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{NewStoredMessage, StoredMessage};
+use crate::{envelope::DhtMessageType, storage::StorageError, store_forward::message::StoredMessagePriority};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use tari_comms::{async_trait, peer_manager::NodeId, types::CommsPublicKey};
+
+/// Storage for store and forward messages
+#[async_trait]
+pub trait SafStorage: Send + Sync {
+    /// Inserts and returns Ok(true) if the item already existed and Ok(false) if it didn't. Returns
+    /// `StorageError::MessageKeyCollision` if a different message with the same message key is already stored.
+    async fn insert_message_if_unique(&self, message: NewStoredMessage) -> Result<bool, StorageError>;
+
+    /// Removes the messages with the given ids, returning the number of messages removed
+    async fn remove_message(&self, message_ids: Vec<i32>) -> Result<usize, StorageError>;
+
+    /// Returns up to `limit` messages stored for the given peer, most recent first
+    async fn find_messages_for_peer(
+        &self,
+        public_key: &CommsPublicKey,
+        node_id: &NodeId,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<StoredMessage>, StorageError>;
+
+    /// Returns the number of messages stored for the given peer
+    async fn count_messages_for_peer(
+        &self,
+        public_key: &CommsPublicKey,
+        node_id: &NodeId,
+    ) -> Result<usize, StorageError>;
+
+    /// Removes all messages that originate from, or are destined for, the given peer, returning the number removed
+    async fn delete_messages_involving_peer(
+        &self,
+        public_key: &CommsPublicKey,
+        node_id: &NodeId,
+    ) -> Result<usize, StorageError>;
+
+    /// Returns up to `limit` encrypted messages that do not disclose their origin or destination, most recent first
+    async fn find_anonymous_messages(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<StoredMessage>, StorageError>;
+
+    /// Returns up to `limit` stored join messages, most recent first
+    async fn find_join_messages(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<StoredMessage>, StorageError>;
+
+    /// Returns up to `limit` messages of the given type destined for the given public key, most recent first
+    async fn find_messages_of_type_for_pubkey(
+        &self,
+        public_key: &CommsPublicKey,
+        message_type: DhtMessageType,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<StoredMessage>, StorageError>;
+
+    /// Returns up to `limit` messages with any of the given (hex-encoded) mailbox tags, most recent first
+    async fn find_messages_by_mailbox_tags(
+        &self,
+        mailbox_tags: Vec<String>,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<StoredMessage>, StorageError>;
+
+    /// Returns up to `limit` messages that have a destination public key or node id and an id greater than `after_id`,
+    /// ordered by id
+    async fn find_messages_with_destination(
+        &self,
+        after_id: i32,
+        limit: i64,
+    ) -> Result<Vec<StoredMessage>, StorageError>;
+
+    /// Returns the total number of header and body bytes held in the store
+    async fn total_message_size(&self) -> Result<usize, StorageError>;
+
+    /// Returns the number of messages held in the store
+    async fn count_messages(&self) -> Result<usize, StorageError>;
+
+    /// Returns the number of messages and bytes held for each of the `limit` destinations with the most messages, most
+    /// messages first
+    async fn count_messages_by_destination(&self, limit: i64) -> Result<Vec<SafDestinationUsage>, StorageError>;
+
+    /// Removes messages of the given priority that were stored before `since`, returning the number removed
+    async fn delete_messages_with_priority_older_than(
+        &self,
+        priority: StoredMessagePriority,
+        since: NaiveDateTime,
+    ) -> Result<usize, StorageError>;
+
+    /// Removes the oldest messages so that at most `max_size` messages remain, returning the number removed
+    async fn truncate_messages(&self, max_size: usize) -> Result<usize, StorageError>;
+
+    /// Applies all inserts and deletes in the batch within a single transaction. Either all operations are applied or
+    /// none are. Inserting a message that already exists is not an error and is counted as a duplicate.
+    async fn write_batch(&self, batch: SafWriteBatch) -> Result<SafBatchResult, StorageError>;
+
+    /// Atomically removes and returns the messages with the given ids. Ids that do not exist are ignored, so a message
+    /// is only ever returned to one caller.
+    async fn take_messages(&self, message_ids: Vec<i32>) -> Result<Vec<StoredMessage>, StorageError>;
+}
+
+/// A set of store and forward inserts and deletes that are applied within a single transaction
+#[derive(Debug, Clone, Default)]
+pub struct SafWriteBatch {
+    pub(super) inserts: Vec<NewStoredMessage>,
+    pub(super) deletes: Vec<i32>,
+}
+
+impl SafWriteBatch {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, message: NewStoredMessage) -> &mut Self {
+        self.inserts.push(message);
+        self
+    }
+
+    pub fn delete<I: IntoIterator<Item = i32>>(&mut self, message_ids: I) -> &mut Self {
+        self.deletes.extend(message_ids);
+        self
+    }
+
+    /// Returns the number of operations in the batch
+    pub fn len(&self) -> usize {
+        self.inserts.len() + self.deletes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inserts.is_empty() && self.deletes.is_empty()
+    }
+}
+
+/// The stored messages held for a destination
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SafDestinationUsage {
+    /// The hex-encoded destination public key, or node id if the public key is not known. None for messages that do
+    /// not disclose their destination.
+    pub destination: Option<String>,
+    pub num_messages: usize,
+    /// The total number of header and body bytes held for the destination
+    pub num_bytes: usize,
+}
+
+/// The result of applying a [SafWriteBatch]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SafBatchResult {
+    /// The number of messages that were inserted
+    pub num_inserted: usize,
+    /// The number of messages that were not inserted because they already exist
+    pub num_duplicates: usize,
+    /// The number of messages that were not inserted because a different message with the same message key exists
+    pub num_collisions: usize,
+    /// The number of messages that were deleted
+    pub num_deleted: usize,
+}
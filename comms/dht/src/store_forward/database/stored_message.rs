@@ -22,14 +22,14 @@
 
 use crate::{
     inbound::DecryptedDhtMessage,
+    message_key,
     proto::envelope::DhtHeader,
     schema::stored_messages,
     store_forward::message::StoredMessagePriority,
 };
 use chrono::NaiveDateTime;
-use digest::Input;
 use std::convert::TryInto;
-use tari_comms::{message::MessageExt, types::Challenge};
+use tari_comms::message::MessageExt;
 use tari_utilities::hex::Hex;
 
 #[derive(Clone, Debug, Insertable, Default)]
@@ -45,6 +45,9 @@ pub struct NewStoredMessage {
     pub is_encrypted: bool,
     pub priority: i32,
     pub body_hash: String,
+    pub mailbox_tag: Option<String>,
+    /// Comma-separated hex node ids of a multicast destination
+    pub destination_node_ids: Option<String>,
 }
 
 impl NewStoredMessage {
@@ -61,6 +64,7 @@ impl NewStoredMessage {
             Ok(envelope_body) => envelope_body.to_encoded_bytes(),
             Err(encrypted_body) => encrypted_body,
         };
+        let body_hash = message_key::message_key_from_header(&dht_header, &body).to_hex();
 
         Some(Self {
             version: version.try_into().ok()?,
@@ -68,13 +72,23 @@ impl NewStoredMessage {
             message_type: dht_header.message_type as i32,
             destination_pubkey: dht_header.destination.public_key().map(|pk| pk.to_hex()),
             destination_node_id: dht_header.destination.node_id().map(|node_id| node_id.to_hex()),
+            destination_node_ids: dht_header.destination.node_ids().map(|node_ids| {
+                node_ids
+                    .iter()
+                    .map(|node_id| node_id.to_hex())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }),
             is_encrypted: dht_header.flags.is_encrypted(),
+            mailbox_tag: Some(&dht_header.mailbox_tag)
+                .filter(|tag| !tag.is_empty())
+                .map(|tag| tag.to_hex()),
             priority: priority as i32,
             header: {
                 let dht_header: DhtHeader = dht_header.into();
                 dht_header.to_encoded_bytes()
             },
-            body_hash: Challenge::new().chain(body.clone()).result().to_vec().to_hex(),
+            body_hash,
             body,
         })
     }
@@ -94,4 +108,6 @@ pub struct StoredMessage {
     pub priority: i32,
     pub stored_at: NaiveDateTime,
     pub body_hash: String,
+    pub mailbox_tag: Option<String>,
+    pub destination_node_ids: Option<String>,
 }
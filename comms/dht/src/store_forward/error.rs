@@ -20,9 +20,15 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::{actor::DhtActorError, envelope::DhtMessageError, outbound::DhtOutboundError, storage::StorageError};
+use crate::{
+    actor::DhtActorError,
+    codec::DhtCodecError,
+    envelope::DhtMessageError,
+    outbound::DhtOutboundError,
+    storage::StorageError,
+};
 use prost::DecodeError;
-use tari_comms::{message::MessageError, peer_manager::PeerManagerError};
+use tari_comms::{connectivity::ConnectivityError, message::MessageError, peer_manager::PeerManagerError};
 use tari_utilities::{byte_array::ByteArrayError, ciphers::cipher::CipherError};
 use thiserror::Error;
 
@@ -54,8 +60,12 @@ pub enum StoreAndForwardError {
     DhtActorError(#[from] DhtActorError),
     #[error("Received duplicate stored message")]
     DuplicateMessage,
+    #[error("Received a stored message that originated from this node")]
+    MessageOriginatedFromSelf,
     #[error("Unable to decode message: {0}")]
     DecodeError(#[from] DecodeError),
+    #[error("DhtCodecError: {0}")]
+    DhtCodecError(#[from] DhtCodecError),
     #[error("Dht header was not provided")]
     DhtHeaderNotProvided,
     #[error("Message origin is for all forwarded messages")]
@@ -81,4 +91,12 @@ pub enum StoreAndForwardError {
     InvalidDhtMessageType,
     #[error("Failed to send request for store and forward messages: {0}")]
     RequestMessagesFailed(DhtOutboundError),
+    #[error("ConnectivityError: {0}")]
+    ConnectivityError(#[from] ConnectivityError),
+    #[error("Failed to sign store and forward rejection")]
+    SafRejectionSigningFailed,
+    #[error("Received store and forward rejection with an invalid signature")]
+    InvalidSafRejectionSignature,
+    #[error("The maximum number of mailbox contacts has been reached")]
+    TooManyMailboxContacts,
 }
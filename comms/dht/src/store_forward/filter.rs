@@ -0,0 +1,238 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    envelope::{DhtMessageType, NodeDestination},
+    inbound::DecryptedDhtMessage,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use tari_comms::{peer_manager::NodeId, types::CommsPublicKey};
+use tari_utilities::ByteArray;
+
+/// Identifies a filter registered with `SafStoreFilters`
+pub type SafStoreFilterId = u64;
+
+/// A predicate over undeliverable messages that this node would be willing to store for its peers. A filter matches a
+/// message if the message matches every criterion that has been set, and matches any value within a criterion. A
+/// filter without criteria matches every message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SafStoreFilter {
+    message_types: Vec<DhtMessageType>,
+    origins: Vec<CommsPublicKey>,
+    destinations: Vec<SafDestinationFilter>,
+}
+
+impl SafStoreFilter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Match messages with the given DHT message type
+    pub fn with_message_type(mut self, message_type: DhtMessageType) -> Self {
+        self.message_types.push(message_type);
+        self
+    }
+
+    /// Match messages from the given origin. The origin of an encrypted message is only known to its recipient, so
+    /// this criterion never matches messages that this node is unable to decrypt.
+    pub fn with_origin(mut self, origin: CommsPublicKey) -> Self {
+        self.origins.push(origin);
+        self
+    }
+
+    /// Match messages whose destination matches the given destination filter
+    pub fn with_destination(mut self, destination: SafDestinationFilter) -> Self {
+        self.destinations.push(destination);
+        self
+    }
+
+    pub fn is_match(&self, message: &DecryptedDhtMessage) -> bool {
+        let header = &message.dht_header;
+        let is_type_match = self.message_types.is_empty() || self.message_types.contains(&header.message_type);
+        let is_origin_match = self.origins.is_empty() ||
+            message
+                .authenticated_origin()
+                .map(|pk| self.origins.contains(pk))
+                .unwrap_or(false);
+        let is_destination_match =
+            self.destinations.is_empty() || self.destinations.iter().any(|d| d.is_match(&header.destination));
+
+        is_type_match && is_origin_match && is_destination_match
+    }
+}
+
+/// Matches the destination of a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SafDestinationFilter {
+    /// Messages that do not disclose their destination
+    Unknown,
+    /// Messages destined for the given peer, whether addressed by public key or node id
+    Peer(Box<NodeId>),
+    /// Messages destined for a node id that starts with the given bytes
+    NodeIdPrefix(Vec<u8>),
+}
+
+impl SafDestinationFilter {
+    pub fn is_match(&self, destination: &NodeDestination) -> bool {
+        use SafDestinationFilter::*;
+        let node_ids = match destination.node_ids() {
+            Some(node_ids) => node_ids.to_vec(),
+            None => destination.to_derived_node_id().into_iter().collect(),
+        };
+        match self {
+            Unknown => destination.is_unknown(),
+            Peer(node_id) => node_ids.iter().any(|n| n == &**node_id),
+            NodeIdPrefix(prefix) => node_ids.iter().any(|n| n.as_bytes().starts_with(prefix)),
+        }
+    }
+}
+
+/// The set of store filters registered with this node. When no filters are registered, every message that is eligible
+/// for storage is stored. Otherwise, only eligible messages that match at least one filter are stored. Messages for
+/// clients served by this node are always stored.
+///
+/// This is cheap to clone and all clones share the same filters.
+#[derive(Debug, Clone, Default)]
+pub struct SafStoreFilters {
+    inner: Arc<RwLock<FiltersInner>>,
+}
+
+#[derive(Debug, Default)]
+struct FiltersInner {
+    next_id: SafStoreFilterId,
+    filters: HashMap<SafStoreFilterId, SafStoreFilter>,
+}
+
+impl SafStoreFilters {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a filter, returning an id that can be used to remove it
+    pub fn add(&self, filter: SafStoreFilter) -> SafStoreFilterId {
+        let mut inner = acquire_lock!(self.inner, write);
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.filters.insert(id, filter);
+        id
+    }
+
+    /// Removes a filter. Returns true if the filter was registered.
+    pub fn remove(&self, id: SafStoreFilterId) -> bool {
+        acquire_lock!(self.inner, write).filters.remove(&id).is_some()
+    }
+
+    pub fn filters(&self) -> Vec<(SafStoreFilterId, SafStoreFilter)> {
+        let mut filters = acquire_lock!(self.inner, read)
+            .filters
+            .iter()
+            .map(|(id, filter)| (*id, filter.clone()))
+            .collect::<Vec<_>>();
+        filters.sort_by_key(|(id, _)| *id);
+        filters
+    }
+
+    /// Returns true if this node is interested in storing the given message
+    pub fn is_interested(&self, message: &DecryptedDhtMessage) -> bool {
+        let inner = acquire_lock!(self.inner, read);
+        inner.filters.is_empty() || inner.filters.values().any(|filter| filter.is_match(message))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        envelope::DhtMessageFlags,
+        test_utils::{make_dht_inbound_message, make_node_identity},
+    };
+
+    fn make_message(destination: NodeDestination, message_type: DhtMessageType) -> DecryptedDhtMessage {
+        let mut inbound_msg = make_dht_inbound_message(
+            &make_node_identity(),
+            b"test".to_vec(),
+            DhtMessageFlags::ENCRYPTED,
+            true,
+        );
+        inbound_msg.dht_header.destination = destination;
+        inbound_msg.dht_header.message_type = message_type;
+        DecryptedDhtMessage::failed(inbound_msg)
+    }
+
+    #[test]
+    fn filter_is_match() {
+        let dest = make_node_identity();
+        let msg = make_message(
+            NodeDestination::PublicKey(Box::new(dest.public_key().clone())),
+            DhtMessageType::None,
+        );
+
+        assert!(SafStoreFilter::new().is_match(&msg));
+        assert!(SafStoreFilter::new()
+            .with_message_type(DhtMessageType::Discovery)
+            .with_message_type(DhtMessageType::None)
+            .is_match(&msg));
+        assert!(!SafStoreFilter::new()
+            .with_message_type(DhtMessageType::Discovery)
+            .is_match(&msg));
+        assert!(SafStoreFilter::new()
+            .with_destination(SafDestinationFilter::Peer(Box::new(dest.node_id().clone())))
+            .is_match(&msg));
+        assert!(SafStoreFilter::new()
+            .with_destination(SafDestinationFilter::NodeIdPrefix(
+                dest.node_id().as_bytes()[..2].to_vec()
+            ))
+            .is_match(&msg));
+        assert!(!SafStoreFilter::new()
+            .with_destination(SafDestinationFilter::Unknown)
+            .is_match(&msg));
+        // The origin of an undecryptable message is not known
+        assert!(!SafStoreFilter::new()
+            .with_origin(make_node_identity().public_key().clone())
+            .is_match(&msg));
+        // All criteria must match
+        assert!(!SafStoreFilter::new()
+            .with_message_type(DhtMessageType::None)
+            .with_destination(SafDestinationFilter::Unknown)
+            .is_match(&msg));
+    }
+
+    #[test]
+    fn is_interested() {
+        let filters = SafStoreFilters::new();
+        let msg = make_message(NodeDestination::Unknown, DhtMessageType::None);
+        assert!(filters.is_interested(&msg));
+
+        let id = filters.add(SafStoreFilter::new().with_message_type(DhtMessageType::Discovery));
+        assert!(!filters.is_interested(&msg));
+        filters.add(SafStoreFilter::new().with_destination(SafDestinationFilter::Unknown));
+        assert!(filters.is_interested(&msg));
+        assert_eq!(filters.filters().len(), 2);
+
+        assert!(filters.remove(id));
+        assert!(!filters.remove(id));
+        assert_eq!(filters.filters().len(), 1);
+    }
+}
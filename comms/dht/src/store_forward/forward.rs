@@ -23,12 +23,18 @@
 use crate::{
     envelope::NodeDestination,
     inbound::DecryptedDhtMessage,
-    outbound::{OutboundMessageRequester, SendMessageParams},
+    outbound::{OutboundMessageRequester, SendMessageParams, SendMessageResponse},
     store_forward::error::StoreAndForwardError,
+    MetricsCollectorHandle,
 };
 use futures::{task::Context, Future};
 use log::*;
-use std::task::Poll;
+use std::{
+    cmp,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::Instant,
+};
 use tari_comms::{peer_manager::Peer, pipeline::PipelineError};
 use tari_utilities::epoch_time::EpochTime;
 use tower::{layer::Layer, Service, ServiceExt};
@@ -39,6 +45,8 @@ const LOG_TARGET: &str = "comms::dht::storeforward::forward";
 pub struct ForwardLayer {
     outbound_service: OutboundMessageRequester,
     is_enabled: bool,
+    limits: ForwardLimits,
+    metrics_collector: Option<MetricsCollectorHandle>,
 }
 
 impl ForwardLayer {
@@ -46,20 +54,43 @@ impl ForwardLayer {
         Self {
             outbound_service,
             is_enabled,
+            limits: Default::default(),
+            metrics_collector: None,
         }
     }
+
+    /// Forward each message to at most `max_amplification` peers
+    pub fn with_max_amplification(mut self, max_amplification: usize) -> Self {
+        self.limits.max_amplification = max_amplification;
+        self
+    }
+
+    /// Limit the rate at which messages are forwarded to `bytes_per_sec` across all forwarded messages
+    pub fn with_bandwidth_budget(mut self, bytes_per_sec: usize) -> Self {
+        self.limits.budget = Some(ForwardBudget::new(bytes_per_sec));
+        self
+    }
+
+    /// Record forwarded and dropped messages in the given metrics collector
+    pub fn with_metrics_collector(mut self, metrics_collector: MetricsCollectorHandle) -> Self {
+        self.metrics_collector = Some(metrics_collector);
+        self
+    }
 }
 
 impl<S> Layer<S> for ForwardLayer {
     type Service = ForwardMiddleware<S>;
 
     fn layer(&self, service: S) -> Self::Service {
-        ForwardMiddleware::new(
+        let mut middleware = ForwardMiddleware::new(
             service,
             // Pass in just the config item needed by the middleware for almost free copies
             self.outbound_service.clone(),
             self.is_enabled,
-        )
+        );
+        middleware.limits = self.limits.clone();
+        middleware.metrics_collector = self.metrics_collector.clone();
+        middleware
     }
 }
 
@@ -71,6 +102,8 @@ pub struct ForwardMiddleware<S> {
     next_service: S,
     outbound_service: OutboundMessageRequester,
     is_enabled: bool,
+    limits: ForwardLimits,
+    metrics_collector: Option<MetricsCollectorHandle>,
 }
 
 impl<S> ForwardMiddleware<S> {
@@ -79,6 +112,8 @@ impl<S> ForwardMiddleware<S> {
             next_service: service,
             outbound_service,
             is_enabled,
+            limits: Default::default(),
+            metrics_collector: None,
         }
     }
 }
@@ -99,6 +134,8 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError> + Cl
         let next_service = self.next_service.clone();
         let outbound_service = self.outbound_service.clone();
         let is_enabled = self.is_enabled;
+        let limits = self.limits.clone();
+        let metrics_collector = self.metrics_collector.clone();
         async move {
             if !is_enabled {
                 trace!(
@@ -116,7 +153,7 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError> + Cl
                 message.tag,
                 message.dht_header.message_tag
             );
-            let forwarder = Forwarder::new(next_service, outbound_service);
+            let forwarder = Forwarder::new(next_service, outbound_service, limits, metrics_collector);
             forwarder.handle(message).await
         }
     }
@@ -127,13 +164,23 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError> + Cl
 struct Forwarder<S> {
     next_service: S,
     outbound_service: OutboundMessageRequester,
+    limits: ForwardLimits,
+    metrics_collector: Option<MetricsCollectorHandle>,
 }
 
 impl<S> Forwarder<S> {
-    pub fn new(service: S, outbound_service: OutboundMessageRequester) -> Self {
+    pub fn new(
+        service: S,
+        outbound_service: OutboundMessageRequester,
+        limits: ForwardLimits,
+        metrics_collector: Option<MetricsCollectorHandle>,
+    ) -> Self
+    {
         Self {
             next_service: service,
             outbound_service,
+            limits,
+            metrics_collector,
         }
     }
 }
@@ -213,6 +260,18 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
 
         let mut send_params = SendMessageParams::new();
         match (dest_node_id, is_saf_stored) {
+            // Discovery messages are only stored in case the destination is offline, so they are still propagated
+            // (possibly to a connected client destination) as they would be if they were not stored
+            (Some(_), Some(true)) if dht_header.message_type.is_dht_discovery() => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Stored discovery message for {}, propagating it. Tag#{}",
+                    dht_header.destination,
+                    dht_header.message_tag
+                );
+
+                send_params.propagate(dht_header.destination.clone(), excluded_peers);
+            },
             (Some(node_id), Some(true)) => {
                 debug!(
                     target: LOG_TARGET,
@@ -232,9 +291,40 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             },
         };
 
-        if !is_already_forwarded {
-            send_params.with_dht_header(dht_header.clone());
-            self.outbound_service.send_raw(send_params.finish(), body).await?;
+        if *is_already_forwarded {
+            return Ok(());
+        }
+
+        let body_len = body.len();
+        let max_copies = self.limits.reserve(body_len);
+        if max_copies == 0 {
+            debug!(
+                target: LOG_TARGET,
+                "Forwarding bandwidth budget exhausted. Dropping message {} from peer '{}' (Trace: {})",
+                message.tag,
+                source_peer.node_id.short_str(),
+                dht_header.message_tag
+            );
+            if let Some(metrics_collector) = self.metrics_collector.as_mut() {
+                metrics_collector.write_metric_forward_dropped();
+            }
+            return Ok(());
+        }
+
+        send_params
+            .with_dht_header(dht_header.clone())
+            .with_max_peers(max_copies);
+        let response = self.outbound_service.send_raw(send_params.finish(), body).await?;
+        let num_copies = match &response {
+            SendMessageResponse::Queued(send_states) => send_states.len(),
+            _ => 0,
+        };
+        // Return the budget reserved for copies that were not sent
+        self.limits.release(body_len, max_copies.saturating_sub(num_copies));
+        if num_copies > 0 {
+            if let Some(metrics_collector) = self.metrics_collector.as_mut() {
+                metrics_collector.write_metric_message_forwarded(num_copies, num_copies * body_len);
+            }
         }
 
         Ok(())
@@ -253,16 +343,97 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
     }
 }
 
+/// Limits on the traffic generated by forwarding messages
+#[derive(Debug, Clone)]
+struct ForwardLimits {
+    max_amplification: usize,
+    budget: Option<ForwardBudget>,
+}
+
+impl ForwardLimits {
+    /// Reserves budget for copies of a message of `size` bytes, returning the number of copies that may be sent
+    fn reserve(&self, size: usize) -> usize {
+        match self.budget.as_ref() {
+            Some(budget) => budget.reserve(size, self.max_amplification),
+            None => self.max_amplification,
+        }
+    }
+
+    /// Returns the budget reserved for `num_copies` copies of a message of `size` bytes that were not sent
+    fn release(&self, size: usize, num_copies: usize) {
+        if let Some(budget) = self.budget.as_ref() {
+            budget.release(size * num_copies);
+        }
+    }
+}
+
+impl Default for ForwardLimits {
+    fn default() -> Self {
+        Self {
+            max_amplification: std::usize::MAX,
+            budget: None,
+        }
+    }
+}
+
+/// A bandwidth budget shared by all forwarded messages. The budget is replenished at a fixed rate and up to one
+/// second of unused budget can accumulate.
+#[derive(Debug, Clone)]
+struct ForwardBudget {
+    bytes_per_sec: usize,
+    state: Arc<Mutex<BudgetState>>,
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    available: usize,
+    last_refill: Instant,
+}
+
+impl ForwardBudget {
+    fn new(bytes_per_sec: usize) -> Self {
+        Self {
+            bytes_per_sec,
+            state: Arc::new(Mutex::new(BudgetState {
+                available: bytes_per_sec,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    /// Reserves budget for up to `max_copies` copies of a message of `size` bytes, returning the number of copies
+    /// reserved
+    fn reserve(&self, size: usize, max_copies: usize) -> usize {
+        let mut state = acquire_lock!(self.state);
+        let refill = (state.last_refill.elapsed().as_millis() as usize).saturating_mul(self.bytes_per_sec) / 1000;
+        if refill > 0 {
+            state.available = cmp::min(self.bytes_per_sec, state.available.saturating_add(refill));
+            state.last_refill = Instant::now();
+        }
+        let num_copies = cmp::min(max_copies, state.available / cmp::max(size, 1));
+        state.available -= num_copies * size;
+        num_copies
+    }
+
+    /// Returns previously reserved budget
+    fn release(&self, num_bytes: usize) {
+        let mut state = acquire_lock!(self.state);
+        state.available = cmp::min(self.bytes_per_sec, state.available.saturating_add(num_bytes));
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{
-        envelope::DhtMessageFlags,
+        broadcast_strategy::BroadcastStrategy,
+        envelope::{DhtMessageFlags, DhtMessageType},
         outbound::mock::create_outbound_service_mock,
         test_utils::{make_dht_inbound_message, make_node_identity, service_spy},
     };
     use futures::{channel::mpsc, executor::block_on};
     use tari_comms::wrap_in_envelope_body;
+    use tari_test_utils::unpack_enum;
     use tokio::runtime::Runtime;
 
     #[test]
@@ -313,4 +484,111 @@ mod test {
         assert_eq!(&body.to_vec(), &sample_body);
         assert_eq!(params.dht_header.unwrap(), header);
     }
+
+    #[test]
+    fn decryption_failed_amplification_limit() {
+        let mut rt = Runtime::new().unwrap();
+        let spy = service_spy();
+        let (oms_requester, oms_mock) = create_outbound_service_mock(1);
+        let oms_mock_state = oms_mock.get_state();
+        rt.spawn(oms_mock.run());
+
+        let mut service = ForwardLayer::new(oms_requester, true)
+            .with_max_amplification(2)
+            .layer(spy.to_service::<PipelineError>());
+
+        let inbound_msg = make_dht_inbound_message(
+            &make_node_identity(),
+            b"Lorem ipsum".to_vec(),
+            DhtMessageFlags::empty(),
+            false,
+        );
+        rt.block_on(service.call(DecryptedDhtMessage::failed(inbound_msg)))
+            .unwrap();
+        assert!(spy.is_called());
+
+        let (params, _) = oms_mock_state.pop_call().unwrap();
+        assert_eq!(params.max_peers, Some(2));
+    }
+
+    #[test]
+    fn decryption_failed_stored_discovery_is_propagated() {
+        let mut rt = Runtime::new().unwrap();
+        let spy = service_spy();
+        let (oms_requester, oms_mock) = create_outbound_service_mock(2);
+        let oms_mock_state = oms_mock.get_state();
+        rt.spawn(oms_mock.run());
+
+        let mut service = ForwardLayer::new(oms_requester, true).layer(spy.to_service::<PipelineError>());
+
+        let dest_node_id = make_node_identity().node_id().clone();
+        for message_type in &[DhtMessageType::None, DhtMessageType::Discovery] {
+            let mut inbound_msg = make_dht_inbound_message(
+                &make_node_identity(),
+                b"Lorem ipsum".to_vec(),
+                DhtMessageFlags::ENCRYPTED,
+                true,
+            );
+            inbound_msg.dht_header.message_type = *message_type;
+            inbound_msg.dht_header.destination = dest_node_id.clone().into();
+            let mut msg = DecryptedDhtMessage::failed(inbound_msg);
+            msg.set_saf_stored(true);
+            rt.block_on(service.call(msg)).unwrap();
+        }
+        assert_eq!(spy.call_count(), 2);
+
+        let mut calls = oms_mock_state
+            .take_calls()
+            .into_iter()
+            .map(|(params, _)| params.broadcast_strategy);
+        // Stored messages are sent to the closest connected peers...
+        unpack_enum!(BroadcastStrategy::Closest(_request) = calls.next().unwrap());
+        // ...except for discovery messages, which are propagated
+        unpack_enum!(BroadcastStrategy::Propagate(destination, _excluded) = calls.next().unwrap());
+        assert_eq!(destination, NodeDestination::from(dest_node_id));
+    }
+
+    #[test]
+    fn decryption_failed_budget_exhausted() {
+        let mut rt = Runtime::new().unwrap();
+        let spy = service_spy();
+        let (oms_requester, oms_mock) = create_outbound_service_mock(2);
+        let oms_mock_state = oms_mock.get_state();
+        rt.spawn(oms_mock.run());
+
+        let sample_body = b"Lorem ipsum";
+        // Enough budget to forward a single copy of one message
+        let mut service = ForwardLayer::new(oms_requester, true)
+            .with_bandwidth_budget(sample_body.len())
+            .layer(spy.to_service::<PipelineError>());
+
+        for _ in 0..2 {
+            let inbound_msg = make_dht_inbound_message(
+                &make_node_identity(),
+                sample_body.to_vec(),
+                DhtMessageFlags::empty(),
+                false,
+            );
+            rt.block_on(service.call(DecryptedDhtMessage::failed(inbound_msg)))
+                .unwrap();
+        }
+        assert_eq!(spy.call_count(), 2);
+
+        assert_eq!(oms_mock_state.call_count(), 1);
+        let (params, _) = oms_mock_state.pop_call().unwrap();
+        assert_eq!(params.max_peers, Some(1));
+    }
+
+    #[test]
+    fn forward_budget_reserve_release() {
+        let budget = ForwardBudget::new(100);
+        assert_eq!(budget.reserve(30, 8), 3);
+        assert_eq!(budget.reserve(30, 8), 0);
+        budget.release(60);
+        assert_eq!(budget.reserve(30, 1), 1);
+        assert_eq!(budget.reserve(30, 8), 1);
+        // Releasing cannot exceed the budget
+        budget.release(1000);
+        assert_eq!(budget.reserve(10, 100), 10);
+    }
 }
@@ -0,0 +1,221 @@
+// Copyright 2019, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Mailbox tags allow a recipient to request stored messages that were sent with an undisclosed destination, without
+//! the store and forward node returning every undisclosed message to every requester.
+//!
+//! A tag is a short hash of the Diffie-Hellman secret shared by the sender and recipient and the current epoch (day).
+//! Only the sender and recipient can compute the tag, so a node that knows the recipient public key cannot use it to
+//! find the recipient's messages. The recipient requests the tags for each sender it expects messages from (see
+//! `StoreAndForwardRequester::add_mailbox_contact`). Tags are deliberately short so that many sender/recipient pairs
+//! share each tag, and they rotate every epoch so that messages between the same pair on different days cannot be
+//! linked by tag.
+
+use crate::crypt;
+use chrono::{DateTime, Utc};
+use digest::Digest;
+use std::cmp;
+use tari_comms::types::{Challenge, CommsPublicKey, CommsSecretKey};
+use tari_utilities::ByteArray;
+
+/// The length in bytes of a mailbox tag
+pub const MAILBOX_TAG_LENGTH: usize = 2;
+/// The length in seconds of a mailbox epoch
+const MAILBOX_EPOCH_SECS: i64 = 24 * 60 * 60;
+/// The maximum number of tags included in a single store and forward request
+pub const MAX_MAILBOX_TAGS_PER_REQUEST: usize = 32;
+/// The maximum number of store and forward requests that the mailbox tags for a single retrieval are split across
+pub const MAX_MAILBOX_REQUESTS: usize = 8;
+/// The maximum number of mailbox contacts. The current-epoch tags of every contact always fit in
+/// `MAX_MAILBOX_REQUESTS` requests.
+pub const MAX_MAILBOX_CONTACTS: usize = MAX_MAILBOX_TAGS_PER_REQUEST * MAX_MAILBOX_REQUESTS;
+const MAILBOX_TAG_DOMAIN: &[u8] = b"tari.dht.saf.mailbox_tag";
+
+/// Returns the mailbox tag at the given epoch for messages between the owner of `secret_key` and the owner of
+/// `public_key`. The sender and recipient derive the same tag from their own secret key and the other's public key.
+pub fn mailbox_tag(secret_key: &CommsSecretKey, public_key: &CommsPublicKey, epoch: u64) -> Vec<u8> {
+    let shared_secret = crypt::generate_ecdh_secret(secret_key, public_key);
+    let hash = Challenge::new()
+        .chain(MAILBOX_TAG_DOMAIN)
+        .chain(shared_secret.as_bytes())
+        .chain(epoch.to_be_bytes())
+        .result();
+    hash[..MAILBOX_TAG_LENGTH].to_vec()
+}
+
+/// Returns the mailbox epoch for the given time
+pub fn mailbox_epoch(time: DateTime<Utc>) -> u64 {
+    (time.timestamp().max(0) / MAILBOX_EPOCH_SECS) as u64
+}
+
+/// Returns the mailbox tag in the current epoch for messages between the owner of `secret_key` and the owner of
+/// `public_key`
+pub fn current_mailbox_tag(secret_key: &CommsSecretKey, public_key: &CommsPublicKey) -> Vec<u8> {
+    mailbox_tag(secret_key, public_key, mailbox_epoch(Utc::now()))
+}
+
+/// Mailbox tags split into batches of at most `MAX_MAILBOX_TAGS_PER_REQUEST`, one batch per store and forward request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MailboxTagBatches {
+    pub batches: Vec<Vec<Vec<u8>>>,
+    /// The number of the oldest epochs whose tags were omitted because they did not fit in `MAX_MAILBOX_REQUESTS`
+    /// requests
+    pub num_omitted_epochs: u64,
+}
+
+/// Returns the mailbox tags for messages sent by each of `contacts` to the owner of `secret_key` for each epoch from
+/// `since` until now, most recent epoch first. The tags of an epoch are included for every contact, or omitted for
+/// every contact if they do not fit in `MAX_MAILBOX_REQUESTS` requests. The current epoch is always included.
+pub fn mailbox_tags_since(
+    secret_key: &CommsSecretKey,
+    contacts: &[CommsPublicKey],
+    since: DateTime<Utc>,
+) -> MailboxTagBatches
+{
+    if contacts.is_empty() {
+        return Default::default();
+    }
+    let current = mailbox_epoch(Utc::now());
+    let first = cmp::min(mailbox_epoch(since), current);
+    let num_epochs = current - first + 1;
+    let max_epochs = cmp::max(MAX_MAILBOX_TAGS_PER_REQUEST * MAX_MAILBOX_REQUESTS / contacts.len(), 1) as u64;
+    let num_included = cmp::min(num_epochs, max_epochs);
+
+    let tags = (current + 1 - num_included..=current)
+        .rev()
+        .flat_map(|epoch| {
+            contacts
+                .iter()
+                .map(move |contact| mailbox_tag(secret_key, contact, epoch))
+        })
+        .collect::<Vec<_>>();
+
+    MailboxTagBatches {
+        batches: tags
+            .chunks(MAX_MAILBOX_TAGS_PER_REQUEST)
+            .map(|batch| batch.to_vec())
+            .collect(),
+        num_omitted_epochs: num_epochs - num_included,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+    use rand::rngs::OsRng;
+    use tari_crypto::keys::PublicKey;
+
+    #[test]
+    fn tag_rotates_each_epoch() {
+        let (sk, _) = CommsPublicKey::random_keypair(&mut OsRng);
+        let (_, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let tag = mailbox_tag(&sk, &pk, 100);
+        assert_eq!(tag.len(), MAILBOX_TAG_LENGTH);
+        assert_eq!(tag, mailbox_tag(&sk, &pk, 100));
+        assert_ne!(tag, mailbox_tag(&sk, &pk, 101));
+    }
+
+    #[test]
+    fn tag_is_shared_by_sender_and_recipient() {
+        let (sender_sk, sender_pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let (recipient_sk, recipient_pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let tag = mailbox_tag(&sender_sk, &recipient_pk, 100);
+        assert_eq!(tag, mailbox_tag(&recipient_sk, &sender_pk, 100));
+
+        // The tag cannot be derived from the recipient public key alone
+        let (other_sk, _) = CommsPublicKey::random_keypair(&mut OsRng);
+        let tags = (0..8)
+            .map(|epoch| mailbox_tag(&sender_sk, &recipient_pk, epoch))
+            .collect::<Vec<_>>();
+        let other_tags = (0..8)
+            .map(|epoch| mailbox_tag(&other_sk, &recipient_pk, epoch))
+            .collect::<Vec<_>>();
+        assert_ne!(tags, other_tags);
+    }
+
+    #[test]
+    fn tags_since() {
+        let (sk, _) = CommsPublicKey::random_keypair(&mut OsRng);
+        let (_, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let tags = mailbox_tags_since(&sk, &[pk.clone()], Utc::now() - Duration::days(2));
+        assert_eq!(tags.batches.len(), 1);
+        assert_eq!(tags.batches[0].len(), 3);
+        assert_eq!(tags.batches[0][0], current_mailbox_tag(&sk, &pk));
+        assert_eq!(tags.num_omitted_epochs, 0);
+
+        let tags = mailbox_tags_since(&sk, &[pk.clone()], Utc::now() - Duration::days(1000));
+        assert_eq!(tags.batches.len(), MAX_MAILBOX_REQUESTS);
+        assert!(tags
+            .batches
+            .iter()
+            .all(|batch| batch.len() == MAX_MAILBOX_TAGS_PER_REQUEST));
+        assert_eq!(
+            tags.num_omitted_epochs,
+            1001 - (MAX_MAILBOX_TAGS_PER_REQUEST * MAX_MAILBOX_REQUESTS) as u64
+        );
+
+        let tags = mailbox_tags_since(&sk, &[pk.clone()], Utc::now());
+        assert_eq!(tags.batches, vec![vec![current_mailbox_tag(&sk, &pk)]]);
+
+        let (_, other_pk) = CommsPublicKey::random_keypair(&mut OsRng);
+        let tags = mailbox_tags_since(&sk, &[pk.clone(), other_pk.clone()], Utc::now());
+        assert_eq!(tags.batches, vec![vec![
+            current_mailbox_tag(&sk, &pk),
+            current_mailbox_tag(&sk, &other_pk)
+        ]]);
+
+        assert_eq!(mailbox_tags_since(&sk, &[], Utc::now()), Default::default());
+    }
+
+    #[test]
+    fn tags_since_includes_current_epoch_for_every_contact() {
+        let (sk, _) = CommsPublicKey::random_keypair(&mut OsRng);
+        let contacts = (0..MAX_MAILBOX_TAGS_PER_REQUEST * 3 + 1)
+            .map(|_| CommsPublicKey::random_keypair(&mut OsRng).1)
+            .collect::<Vec<_>>();
+        let tags = mailbox_tags_since(&sk, &contacts, Utc::now() - Duration::days(30));
+        assert!(tags.batches.len() <= MAX_MAILBOX_REQUESTS);
+        assert!(tags
+            .batches
+            .iter()
+            .all(|batch| batch.len() <= MAX_MAILBOX_TAGS_PER_REQUEST));
+        // 97 contacts fit 2 epochs in 256 tags
+        assert_eq!(tags.num_omitted_epochs, 29);
+
+        let requested = tags.batches.into_iter().flatten().collect::<Vec<_>>();
+        assert_eq!(requested.len(), contacts.len() * 2);
+        for contact in &contacts {
+            assert!(requested.contains(&current_mailbox_tag(&sk, contact)));
+        }
+
+        let contacts = (0..MAX_MAILBOX_CONTACTS)
+            .map(|_| CommsPublicKey::random_keypair(&mut OsRng).1)
+            .collect::<Vec<_>>();
+        let tags = mailbox_tags_since(&sk, &contacts, Utc::now() - Duration::days(30));
+        let requested = tags.batches.into_iter().flatten().collect::<Vec<_>>();
+        assert_eq!(requested.len(), MAX_MAILBOX_CONTACTS);
+        for contact in &contacts {
+            assert!(requested.contains(&current_mailbox_tag(&sk, contact)));
+        }
+    }
+}
@@ -24,20 +24,37 @@ use crate::{
     envelope::datetime_to_timestamp,
     proto::{
         envelope::DhtHeader,
-        store_forward::{StoredMessage, StoredMessagesRequest, StoredMessagesResponse},
+        store_forward::{
+            saf_rejection::SafRejectionReason,
+            SafRejection,
+            StoredMessage,
+            StoredMessagesRequest,
+            StoredMessagesResponse,
+        },
     },
     store_forward::{database, StoreAndForwardError},
 };
 use chrono::{DateTime, Utc};
 use prost::Message;
 use rand::{rngs::OsRng, RngCore};
-use std::convert::{TryFrom, TryInto};
+use std::{
+    convert::{TryFrom, TryInto},
+    time::Duration,
+};
+use tari_comms::{
+    peer_manager::{NodeId, NodeIdentity},
+    types::CommsPublicKey,
+    utils::signature,
+};
+use tari_crypto::tari_utilities::{message_format::MessageFormat, ByteArray};
 
 impl StoredMessagesRequest {
     pub fn new() -> Self {
         Self {
             since: None,
             request_id: OsRng.next_u32(),
+            mailbox_tags: Vec::new(),
+            mailbox_only: false,
         }
     }
 
@@ -46,6 +63,8 @@ impl StoredMessagesRequest {
         Self {
             since: Some(datetime_to_timestamp(since)),
             request_id: OsRng.next_u32(),
+            mailbox_tags: Vec::new(),
+            mailbox_only: false,
         }
     }
 }
@@ -79,6 +98,59 @@ impl TryFrom<database::StoredMessage> for StoredMessage {
     }
 }
 
+impl SafRejection {
+    /// Create a rejection of the request with the given id from `requester`, signed by `node_identity`
+    pub fn new(
+        node_identity: &NodeIdentity,
+        requester: &NodeId,
+        request_id: u32,
+        reason: SafRejectionReason,
+        retry_after: Option<Duration>,
+    ) -> Result<Self, StoreAndForwardError>
+    {
+        let mut rejection = Self {
+            request_id,
+            reason: reason as i32,
+            retry_after_secs: retry_after.map(|d| d.as_secs()).unwrap_or(0),
+            signature: Vec::new(),
+        };
+        rejection.signature = signature::sign(
+            &mut OsRng,
+            node_identity.secret_key().clone(),
+            rejection.challenge(requester),
+        )
+        .map_err(|_| StoreAndForwardError::SafRejectionSigningFailed)?
+        .to_binary()
+        .map_err(|_| StoreAndForwardError::SafRejectionSigningFailed)?;
+        Ok(rejection)
+    }
+
+    /// Returns true if this rejection was signed by the given public key for the given requester
+    pub fn verify(&self, public_key: &CommsPublicKey, requester: &NodeId) -> bool {
+        signature::verify(public_key, &self.signature, self.challenge(requester))
+    }
+
+    /// The time after which the request may be retried, if given
+    pub fn retry_after(&self) -> Option<Duration> {
+        if self.retry_after_secs == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.retry_after_secs))
+        }
+    }
+
+    /// The bytes that are signed by the rejecting node. The requester's node id is included so that a rejection cannot
+    /// be replayed to another requester.
+    fn challenge(&self, requester: &NodeId) -> Vec<u8> {
+        let mut challenge = Vec::with_capacity(16 + requester.as_bytes().len());
+        challenge.extend_from_slice(&self.request_id.to_le_bytes());
+        challenge.extend_from_slice(&self.reason.to_le_bytes());
+        challenge.extend_from_slice(&self.retry_after_secs.to_le_bytes());
+        challenge.extend_from_slice(requester.as_bytes());
+        challenge
+    }
+}
+
 impl StoredMessagesResponse {
     pub fn messages(&self) -> &Vec<StoredMessage> {
         &self.messages
@@ -89,4 +161,6 @@ impl StoredMessagesResponse {
 pub enum StoredMessagePriority {
     Low = 1,
     High = 10,
+    /// Messages destined for a client peer that this node serves
+    Client = 20,
 }
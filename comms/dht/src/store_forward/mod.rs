@@ -25,17 +25,36 @@ type SafResult<T> = Result<T, StoreAndForwardError>;
 mod service;
 pub use service::{StoreAndForwardRequest, StoreAndForwardRequester, StoreAndForwardService};
 
-mod database;
-pub use database::StoredMessage;
+mod capability;
+pub use capability::{SafCapability, SAF_CAPABILITY_METADATA_KEY};
+
+mod clients;
+pub use clients::ServedClients;
+
+pub(crate) mod database;
+#[cfg(feature = "benches")]
+pub use database::{NewStoredMessage, StoreAndForwardDatabase};
+pub use database::{SafBatchResult, SafDestinationUsage, SafStorage, SafWriteBatch, StoredMessage};
 
 mod error;
 pub use error::StoreAndForwardError;
 
+mod filter;
+pub use filter::{SafDestinationFilter, SafStoreFilter, SafStoreFilterId, SafStoreFilters};
+
 mod forward;
 pub use forward::ForwardLayer;
 
+pub mod mailbox;
+
 mod message;
 
+mod provider_stats;
+pub use provider_stats::{SafProviderStats, SafResponseSummary};
+
+mod response_sender;
+pub use response_sender::{SafResponse, SafResponseSender};
+
 mod saf_handler;
 pub use saf_handler::MessageHandlerLayer;
 
@@ -0,0 +1,92 @@
+// Copyright 2019, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use chrono::{DateTime, Utc};
+use std::{cmp, collections::HashMap};
+use tari_comms::{peer_manager::NodeId, types::CommsPublicKey};
+
+/// A summary of the messages contained in a stored messages response from a store and forward provider
+#[derive(Debug, Clone)]
+pub struct SafResponseSummary {
+    pub provider: NodeId,
+    /// Messages that had not been received before
+    pub num_new: usize,
+    /// Messages that had already been received, from this or another provider
+    pub num_duplicate: usize,
+    /// Messages that should never have been stored or forwarded by the provider
+    pub num_invalid: usize,
+    /// Messages that originated from this node. These were held by the provider instead of being delivered.
+    pub num_undelivered: usize,
+    /// The authenticated authors of new messages, and the latest time at which the provider stored a message from each
+    pub authors_seen: HashMap<CommsPublicKey, DateTime<Utc>>,
+}
+
+impl SafResponseSummary {
+    pub fn new(provider: NodeId) -> Self {
+        Self {
+            provider,
+            num_new: 0,
+            num_duplicate: 0,
+            num_invalid: 0,
+            num_undelivered: 0,
+            authors_seen: HashMap::new(),
+        }
+    }
+
+    /// Record that the provider stored a message from the given author at `stored_at`. The provider chooses the stored
+    /// time, so a time in the future is recorded as now.
+    pub fn record_author_seen(&mut self, author: CommsPublicKey, stored_at: DateTime<Utc>) {
+        let stored_at = cmp::min(stored_at, Utc::now());
+        let seen_at = self.authors_seen.entry(author).or_insert(stored_at);
+        *seen_at = cmp::max(*seen_at, stored_at);
+    }
+}
+
+/// Cumulative statistics for the stored messages returned by a store and forward provider. These can be used to
+/// assess how useful (or harmful) the provider is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SafProviderStats {
+    pub num_responses: usize,
+    pub num_new_messages: usize,
+    pub num_duplicate_messages: usize,
+    pub num_invalid_messages: usize,
+    pub num_undelivered_messages: usize,
+    /// The number of requests for stored messages that the provider rejected
+    pub num_rejections: usize,
+    pub last_response: Option<DateTime<Utc>>,
+}
+
+impl SafProviderStats {
+    pub(super) fn record(&mut self, summary: &SafResponseSummary) {
+        self.num_responses += 1;
+        self.num_new_messages += summary.num_new;
+        self.num_duplicate_messages += summary.num_duplicate;
+        self.num_invalid_messages += summary.num_invalid;
+        self.num_undelivered_messages += summary.num_undelivered;
+        self.last_response = Some(Utc::now());
+    }
+
+    pub(super) fn record_rejection(&mut self) {
+        self.num_rejections += 1;
+        self.last_response = Some(Utc::now());
+    }
+}
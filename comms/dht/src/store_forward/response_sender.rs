@@ -0,0 +1,334 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    outbound::{OutboundMessageRequester, SendMessageParams},
+    proto::{
+        envelope::DhtMessageType,
+        store_forward::{stored_messages_response::SafResponseType, StoredMessage, StoredMessagesResponse},
+    },
+    store_forward::StoreAndForwardRequester,
+};
+use futures::{channel::mpsc, StreamExt};
+use log::*;
+use prost::Message;
+use std::time::Duration;
+use tari_comms::{message::MessagePriority, types::CommsPublicKey};
+use tari_shutdown::ShutdownSignal;
+use tokio::{task, time};
+
+const LOG_TARGET: &str = "comms::dht::storeforward::response_sender";
+
+/// A chunked response to a request for stored messages, queued to be sent by the `SafResponseSender`
+#[derive(Debug)]
+pub struct SafResponse {
+    pub recipient: CommsPublicKey,
+    pub request_id: u32,
+    pub response_type: SafResponseType,
+    /// The ids of the stored messages in each chunk, along with the messages
+    pub chunks: Vec<(Vec<i32>, Vec<StoredMessage>)>,
+}
+
+/// Sends queued SAF responses in the background, pacing the chunks of each response so that large stored-message
+/// bundles do not saturate slow links. Responses are sent one at a time, so the pacing rate applies to all SAF
+/// responses sent by this node. Inbound message handlers queue responses rather than sending them, so they are never
+/// delayed by pacing.
+///
+/// Chunks are sent with `MessagePriority::Low`, so latency-sensitive messages (e.g. liveness and blocks) that are
+/// queued for the same peer are sent before any queued chunk.
+///
+/// Queued responses contain the stored messages themselves, so removing a message from storage (e.g. the purge when a
+/// peer is banned) does not remove it from a response that has already been queued.
+pub struct SafResponseSender {
+    pacing_rate: Option<usize>,
+    outbound_requester: OutboundMessageRequester,
+    saf_requester: StoreAndForwardRequester,
+    response_rx: mpsc::Receiver<SafResponse>,
+    shutdown_signal: ShutdownSignal,
+}
+
+impl SafResponseSender {
+    pub fn new(
+        pacing_rate: Option<usize>,
+        outbound_requester: OutboundMessageRequester,
+        saf_requester: StoreAndForwardRequester,
+        response_rx: mpsc::Receiver<SafResponse>,
+        shutdown_signal: ShutdownSignal,
+    ) -> Self
+    {
+        Self {
+            pacing_rate,
+            outbound_requester,
+            saf_requester,
+            response_rx,
+            shutdown_signal,
+        }
+    }
+
+    pub fn spawn(self) {
+        task::spawn(self.run());
+    }
+
+    async fn run(mut self) {
+        let mut shutdown_signal = self.shutdown_signal.clone();
+        loop {
+            futures::select! {
+                response = self.response_rx.select_next_some() => {
+                    self.send_response(response).await;
+                },
+                _ = shutdown_signal => {
+                    debug!(
+                        target: LOG_TARGET,
+                        "SAF response sender is shutting down because it received the shutdown signal"
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn send_response(&mut self, response: SafResponse) {
+        let SafResponse {
+            recipient,
+            request_id,
+            response_type,
+            chunks,
+        } = response;
+        let num_chunks = chunks.len();
+
+        for (i, (message_ids, chunk)) in chunks.into_iter().enumerate() {
+            let stored_messages = StoredMessagesResponse {
+                messages: chunk,
+                request_id,
+                response_type: response_type as i32,
+            };
+            let response_size = stored_messages.encoded_len();
+
+            debug!(
+                target: LOG_TARGET,
+                "Sending {} {:?} stored message(s) to peer '{}' ({} bytes, chunk {} of {})",
+                stored_messages.messages.len(),
+                response_type,
+                recipient,
+                response_size,
+                i + 1,
+                num_chunks
+            );
+            let result = match self
+                .outbound_requester
+                .send_message_no_header(
+                    SendMessageParams::new()
+                        .direct_public_key(recipient.clone())
+                        .with_dht_message_type(DhtMessageType::SafStoredMessages)
+                        .with_priority(MessagePriority::Low)
+                        .finish(),
+                    stored_messages,
+                )
+                .await
+            {
+                Ok(response) => response.resolve().await.map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+
+            match result {
+                // Mailbox tags are not unique to a recipient, so mailbox messages are left to expire rather than being
+                // removed on delivery
+                Ok(_) if response_type == SafResponseType::Mailbox => {},
+                Ok(_) => {
+                    let num_requested = message_ids.len();
+                    match self.saf_requester.take_messages(message_ids).await {
+                        Ok(taken) => debug!(
+                            target: LOG_TARGET,
+                            "Removed {}/{} stored message(s) for peer '{}'",
+                            taken.len(),
+                            num_requested,
+                            recipient
+                        ),
+                        Err(err) => error!(target: LOG_TARGET, "Failed to remove sent stored messages: {}", err),
+                    }
+                },
+                Err(err) => {
+                    error!(
+                        target: LOG_TARGET,
+                        "Failed to send stored messages to peer '{}': {}", recipient, err
+                    );
+                    // Remaining chunks are left in storage for the next request
+                    break;
+                },
+            }
+
+            // Pace the remaining chunks so that large responses do not saturate slow links
+            if i + 1 < num_chunks {
+                if let Some(delay) = pacing_delay(response_size, self.pacing_rate) {
+                    trace!(target: LOG_TARGET, "Pacing SAF response for {:.2?}", delay);
+                    time::delay_for(delay).await;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the time to wait after sending `num_bytes` to remain within `rate` bytes per second
+fn pacing_delay(num_bytes: usize, rate: Option<usize>) -> Option<Duration> {
+    match rate {
+        Some(rate) if rate > 0 => Some(Duration::from_millis((num_bytes as u64 * 1000) / rate as u64)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        outbound::mock::create_outbound_service_mock,
+        test_utils::{create_store_and_forward_mock, make_node_identity},
+    };
+    use futures::SinkExt;
+    use tari_comms::message::EnvelopeBody;
+    use tari_shutdown::Shutdown;
+    use tari_test_utils::async_assert_eventually;
+
+    #[test]
+    fn pacing_delay_for_rate() {
+        assert_eq!(pacing_delay(1024, None), None);
+        assert_eq!(pacing_delay(1024, Some(0)), None);
+        assert_eq!(pacing_delay(1024, Some(1024)), Some(Duration::from_secs(1)));
+        assert_eq!(pacing_delay(512, Some(1024)), Some(Duration::from_millis(500)));
+    }
+
+    #[tokio_macros::test_basic]
+    async fn send_chunks_and_take_messages() {
+        let (outbound_requester, outbound_mock) = create_outbound_service_mock(10);
+        let outbound_state = outbound_mock.get_state();
+        task::spawn(outbound_mock.run());
+        let (saf_requester, saf_mock_state) = create_store_and_forward_mock();
+        let (mut response_tx, response_rx) = mpsc::channel(1);
+        let shutdown = Shutdown::new();
+        SafResponseSender::new(
+            None,
+            outbound_requester,
+            saf_requester,
+            response_rx,
+            shutdown.to_signal(),
+        )
+        .spawn();
+
+        let msg = |n: u8| StoredMessage {
+            version: 0,
+            dht_header: None,
+            body: vec![n; 10],
+            stored_at: None,
+        };
+        let node_identity = make_node_identity();
+        response_tx
+            .send(SafResponse {
+                recipient: node_identity.public_key().clone(),
+                request_id: 123,
+                response_type: SafResponseType::ForMe,
+                chunks: vec![(vec![1, 2], vec![msg(1), msg(2)]), (vec![3], vec![msg(3)])],
+            })
+            .await
+            .unwrap();
+
+        async_assert_eventually!(
+            outbound_state.call_count(),
+            expect = 2,
+            max_attempts = 20,
+            interval = Duration::from_millis(100)
+        );
+        let calls = outbound_state.take_calls();
+        let (params, body) = &calls[1];
+        assert_eq!(params.dht_message_type, DhtMessageType::SafStoredMessages);
+        assert_eq!(params.priority, MessagePriority::Low);
+        let body = EnvelopeBody::decode(body.to_vec().as_slice()).unwrap();
+        let response = body.decode_part::<StoredMessagesResponse>(0).unwrap().unwrap();
+        assert_eq!(response.request_id, 123);
+        assert_eq!(response.messages, vec![msg(3)]);
+
+        async_assert_eventually!(
+            saf_mock_state.call_count(),
+            expect = 2,
+            max_attempts = 20,
+            interval = Duration::from_millis(100)
+        );
+        let calls = saf_mock_state.take_calls().await;
+        assert!(calls[0].contains("TakeMessages([1, 2]"));
+        assert!(calls[1].contains("TakeMessages([3]"));
+    }
+
+    #[tokio_macros::test_basic]
+    async fn queued_response_survives_purge() {
+        let (outbound_requester, outbound_mock) = create_outbound_service_mock(10);
+        let outbound_state = outbound_mock.get_state();
+        task::spawn(outbound_mock.run());
+        let (saf_requester, saf_mock_state) = create_store_and_forward_mock();
+        let (mut response_tx, response_rx) = mpsc::channel(1);
+        let shutdown = Shutdown::new();
+
+        let message = StoredMessage {
+            version: 0,
+            dht_header: None,
+            body: vec![1; 10],
+            stored_at: None,
+        };
+        // The response was queued before the message was purged, so the message is no longer in storage
+        assert!(saf_mock_state.get_messages().await.is_empty());
+        response_tx
+            .send(SafResponse {
+                recipient: make_node_identity().public_key().clone(),
+                request_id: 123,
+                response_type: SafResponseType::ForMe,
+                chunks: vec![(vec![1], vec![message.clone()])],
+            })
+            .await
+            .unwrap();
+
+        SafResponseSender::new(
+            None,
+            outbound_requester,
+            saf_requester,
+            response_rx,
+            shutdown.to_signal(),
+        )
+        .spawn();
+
+        // The purged message is still sent
+        async_assert_eventually!(
+            outbound_state.call_count(),
+            expect = 1,
+            max_attempts = 20,
+            interval = Duration::from_millis(100)
+        );
+        let (_, body) = outbound_state.pop_call().unwrap();
+        let body = EnvelopeBody::decode(body.to_vec().as_slice()).unwrap();
+        let response = body.decode_part::<StoredMessagesResponse>(0).unwrap().unwrap();
+        assert_eq!(response.messages, vec![message]);
+
+        async_assert_eventually!(
+            saf_mock_state.call_count(),
+            expect = 1,
+            max_attempts = 20,
+            interval = Duration::from_millis(100)
+        );
+        assert!(saf_mock_state.take_calls().await[0].contains("TakeMessages([1]"));
+    }
+}
@@ -25,7 +25,7 @@ use crate::{
     actor::DhtRequester,
     config::DhtConfig,
     outbound::OutboundMessageRequester,
-    store_forward::StoreAndForwardRequester,
+    store_forward::{SafResponse, SafResponseSummary, StoreAndForwardRequester},
 };
 use futures::channel::mpsc;
 use std::sync::Arc;
@@ -39,10 +39,12 @@ pub struct MessageHandlerLayer {
     peer_manager: Arc<PeerManager>,
     node_identity: Arc<NodeIdentity>,
     outbound_service: OutboundMessageRequester,
-    saf_response_signal_sender: mpsc::Sender<()>,
+    saf_response_signal_sender: mpsc::Sender<SafResponseSummary>,
+    saf_response_sender: mpsc::Sender<SafResponse>,
 }
 
 impl MessageHandlerLayer {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: DhtConfig,
         saf_requester: StoreAndForwardRequester,
@@ -50,7 +52,8 @@ impl MessageHandlerLayer {
         node_identity: Arc<NodeIdentity>,
         peer_manager: Arc<PeerManager>,
         outbound_service: OutboundMessageRequester,
-        saf_response_signal_sender: mpsc::Sender<()>,
+        saf_response_signal_sender: mpsc::Sender<SafResponseSummary>,
+        saf_response_sender: mpsc::Sender<SafResponse>,
     ) -> Self
     {
         Self {
@@ -61,6 +64,7 @@ impl MessageHandlerLayer {
             peer_manager,
             outbound_service,
             saf_response_signal_sender,
+            saf_response_sender,
         }
     }
 }
@@ -78,6 +82,7 @@ impl<S> Layer<S> for MessageHandlerLayer {
             Arc::clone(&self.peer_manager),
             self.outbound_service.clone(),
             self.saf_response_signal_sender.clone(),
+            self.saf_response_sender.clone(),
         )
     }
 }
@@ -26,7 +26,7 @@ use crate::{
     config::DhtConfig,
     inbound::DecryptedDhtMessage,
     outbound::OutboundMessageRequester,
-    store_forward::StoreAndForwardRequester,
+    store_forward::{SafResponse, SafResponseSummary, StoreAndForwardRequester},
 };
 use futures::{channel::mpsc, task::Context, Future};
 use std::{sync::Arc, task::Poll};
@@ -45,7 +45,8 @@ pub struct MessageHandlerMiddleware<S> {
     peer_manager: Arc<PeerManager>,
     node_identity: Arc<NodeIdentity>,
     outbound_service: OutboundMessageRequester,
-    saf_response_signal_sender: mpsc::Sender<()>,
+    saf_response_signal_sender: mpsc::Sender<SafResponseSummary>,
+    saf_response_sender: mpsc::Sender<SafResponse>,
 }
 
 impl<S> MessageHandlerMiddleware<S> {
@@ -58,7 +59,8 @@ impl<S> MessageHandlerMiddleware<S> {
         node_identity: Arc<NodeIdentity>,
         peer_manager: Arc<PeerManager>,
         outbound_service: OutboundMessageRequester,
-        saf_response_signal_sender: mpsc::Sender<()>,
+        saf_response_signal_sender: mpsc::Sender<SafResponseSummary>,
+        saf_response_sender: mpsc::Sender<SafResponse>,
     ) -> Self
     {
         Self {
@@ -70,6 +72,7 @@ impl<S> MessageHandlerMiddleware<S> {
             peer_manager,
             outbound_service,
             saf_response_signal_sender,
+            saf_response_sender,
         }
     }
 }
@@ -97,6 +100,7 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError> + Cl
             Arc::clone(&self.node_identity),
             message,
             self.saf_response_signal_sender.clone(),
+            self.saf_response_sender.clone(),
         )
         .run()
     }
@@ -23,5 +23,6 @@
 mod layer;
 mod middleware;
 mod task;
+pub(super) use task::chunk_stored_messages;
 
 pub use layer::MessageHandlerLayer;
@@ -22,35 +22,46 @@
 
 use crate::{
     actor::DhtRequester,
+    codec::StoredMessagesResponseReader,
     config::DhtConfig,
     crypt,
     envelope::{timestamp_to_datetime, DhtMessageFlags, DhtMessageHeader, NodeDestination},
     inbound::{DecryptedDhtMessage, DhtInboundMessage},
+    message_key,
     outbound::{OutboundMessageRequester, SendMessageParams},
     proto::{
+        dht::DiscoveryHintMessage,
         envelope::{DhtMessageType, OriginMac},
         store_forward::{
+            saf_rejection::SafRejectionReason,
             stored_messages_response::SafResponseType,
+            SafRejection,
             StoredMessage as ProtoStoredMessage,
             StoredMessagesRequest,
-            StoredMessagesResponse,
         },
     },
-    store_forward::{error::StoreAndForwardError, service::FetchStoredMessageQuery, StoreAndForwardRequester},
+    store_forward::{
+        error::StoreAndForwardError,
+        mailbox,
+        service::FetchStoredMessageQuery,
+        SafResponse,
+        SafResponseSummary,
+        StoreAndForwardRequester,
+    },
 };
-use digest::Digest;
-use futures::{channel::mpsc, future, stream, Future, SinkExt, StreamExt};
+use chrono::{DateTime, Utc};
+use futures::{channel::mpsc, future, future::Either, stream::FuturesOrdered, Future, SinkExt, StreamExt};
 use log::*;
 use prost::Message;
-use std::{convert::TryInto, sync::Arc};
+use std::{convert::TryInto, sync::Arc, time::Duration};
 use tari_comms::{
     message::{EnvelopeBody, MessageTag},
     peer_manager::{NodeIdentity, Peer, PeerFeatures, PeerManager, PeerManagerError},
     pipeline::PipelineError,
-    types::{Challenge, CommsPublicKey},
+    types::CommsPublicKey,
     utils::signature,
 };
-use tari_utilities::{convert::try_convert_all, ByteArray};
+use tari_utilities::{convert::try_convert_all, hex::Hex, ByteArray};
 use tower::{Service, ServiceExt};
 
 const LOG_TARGET: &str = "comms::dht::storeforward::handler";
@@ -64,7 +75,8 @@ pub struct MessageHandlerTask<S> {
     node_identity: Arc<NodeIdentity>,
     message: Option<DecryptedDhtMessage>,
     saf_requester: StoreAndForwardRequester,
-    saf_response_signal_sender: mpsc::Sender<()>,
+    saf_response_signal_sender: mpsc::Sender<SafResponseSummary>,
+    saf_response_sender: mpsc::Sender<SafResponse>,
 }
 
 impl<S> MessageHandlerTask<S>
@@ -80,7 +92,8 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
         outbound_service: OutboundMessageRequester,
         node_identity: Arc<NodeIdentity>,
         message: DecryptedDhtMessage,
-        saf_response_signal_sender: mpsc::Sender<()>,
+        saf_response_signal_sender: mpsc::Sender<SafResponseSummary>,
+        saf_response_sender: mpsc::Sender<SafResponse>,
     ) -> Self
     {
         Self {
@@ -93,6 +106,7 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             node_identity,
             message: Some(message),
             saf_response_signal_sender,
+            saf_response_sender,
         }
     }
 
@@ -124,17 +138,26 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
                     debug!(
                         target: LOG_TARGET,
                         "Received store and forward request {} from peer '{}' however, this node is not a store and \
-                         forward node. Request ignored. (Trace: {})",
+                         forward node. Request rejected. (Trace: {})",
                         message.tag,
                         message.source_peer.node_id.short_str(),
                         message.dht_header.message_tag
                     );
+                    let request = decode_stored_messages_request(&message)?;
+                    self.send_rejection(&message, request.request_id, SafRejectionReason::NotSupported, None)
+                        .await?;
                 }
             },
 
             DhtMessageType::SafStoredMessages => self.handle_stored_messages(message).await?,
+            DhtMessageType::SafRejection => self.handle_saf_rejection(message).await?,
             // Not a SAF message, call downstream middleware
             _ => {
+                if message.dht_header.message_type.is_dht_discovery() && message.decryption_failed() {
+                    if let Err(err) = self.send_discovery_hint_if_required(&message).await {
+                        debug!(target: LOG_TARGET, "Failed to send discovery hint because '{}'", err);
+                    }
+                }
                 trace!(
                     target: LOG_TARGET,
                     "Passing message {} onto next service (Trace: {})",
@@ -148,6 +171,58 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
         Ok(())
     }
 
+    /// If this node is holding stored messages for the offline peer being discovered, let the peer that sent the
+    /// discovery request know so that subsequent traffic for the peer can be routed to this node.
+    async fn send_discovery_hint_if_required(
+        &mut self,
+        message: &DecryptedDhtMessage,
+    ) -> Result<(), StoreAndForwardError>
+    {
+        if !self.node_identity.has_peer_features(PeerFeatures::DHT_STORE_FORWARD) {
+            return Ok(());
+        }
+
+        let public_key = match message.dht_header.destination.public_key() {
+            Some(pk) => pk.clone(),
+            None => return Ok(()),
+        };
+
+        match self.peer_manager.find_by_public_key(&public_key).await {
+            Ok(peer) if !peer.is_offline() => return Ok(()),
+            Ok(_) => {},
+            Err(err) if err.is_peer_not_found() => {},
+            Err(err) => return Err(err.into()),
+        }
+
+        let num_stored = self.saf_requester.count_messages_for_peer(public_key.clone()).await?;
+        if num_stored == 0 {
+            return Ok(());
+        }
+
+        debug!(
+            target: LOG_TARGET,
+            "Sending discovery hint to peer '{}': this node is holding {} message(s) for the peer being discovered \
+             (Trace: {})",
+            message.source_peer.node_id.short_str(),
+            num_stored,
+            message.dht_header.message_tag
+        );
+        self.outbound_service
+            .send_message_no_header(
+                SendMessageParams::new()
+                    .direct_node_id(message.source_peer.node_id.clone())
+                    .with_dht_message_type(DhtMessageType::DiscoveryHint)
+                    .finish(),
+                DiscoveryHintMessage {
+                    public_key: public_key.to_vec(),
+                    saf_node_ids: vec![self.node_identity.node_id().to_vec()],
+                },
+            )
+            .await?;
+
+        Ok(())
+    }
+
     async fn handle_stored_messages_request(
         &mut self,
         message: DecryptedDhtMessage,
@@ -160,13 +235,13 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             message.source_peer.public_key,
             message.dht_header.message_tag
         );
-        let msg = message
-            .success()
-            .expect("already checked that this message decrypted successfully");
+        let retrieve_msgs = decode_stored_messages_request(&message)?;
 
-        let retrieve_msgs = msg
-            .decode_part::<StoredMessagesRequest>(0)?
-            .ok_or_else(|| StoreAndForwardError::InvalidEnvelopeBody)?;
+        if let Some((reason, retry_after)) = self.check_request_rejection(&message, &retrieve_msgs).await? {
+            return self
+                .send_rejection(&message, retrieve_msgs.request_id, reason, retry_after)
+                .await;
+        }
 
         let source_pubkey = Box::new(message.source_peer.public_key.clone());
         let source_node_id = Box::new(message.source_peer.node_id.clone());
@@ -184,61 +259,189 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             query.since(since);
         }
 
-        let response_types = vec![SafResponseType::ForMe];
+        // Follow-up requests carry the mailbox tags that did not fit in the requester's first request
+        let mut response_types = if retrieve_msgs.mailbox_only {
+            Vec::new()
+        } else {
+            vec![SafResponseType::ForMe]
+        };
+
+        if !retrieve_msgs.mailbox_tags.is_empty() {
+            let mailbox_tags = retrieve_msgs
+                .mailbox_tags
+                .iter()
+                .take(mailbox::MAX_MAILBOX_TAGS_PER_REQUEST)
+                .map(|tag| tag.to_hex())
+                .collect();
+            query.with_mailbox_tags(mailbox_tags);
+            response_types.push(SafResponseType::Mailbox);
+        }
 
+        let source_pubkey_hex = message.source_peer.public_key.to_hex();
         for resp_type in response_types {
             query.with_response_type(resp_type);
-            let messages = self.saf_requester.fetch_messages(query.clone()).await?;
-
+            let mut messages = self.saf_requester.fetch_messages(query.clone()).await?;
+            // A node must never receive its own messages back from a store and forward provider, as these would be
+            // counted as delivered when they never reached the recipient
+            messages.retain(|msg| msg.origin_pubkey.as_ref() != Some(&source_pubkey_hex));
             let message_ids = messages.iter().map(|msg| msg.id).collect::<Vec<_>>();
-            let stored_messages = StoredMessagesResponse {
-                messages: try_convert_all(messages)?,
-                request_id: retrieve_msgs.request_id,
-                response_type: resp_type as i32,
-            };
-
+            let chunks = chunk_stored_messages(
+                message_ids,
+                try_convert_all(messages)?,
+                self.config.saf_max_response_chunk_size,
+            );
             debug!(
                 target: LOG_TARGET,
-                "Responding to received message retrieval request with {} {:?} message(s)",
-                stored_messages.messages().len(),
-                resp_type
+                "Queuing response to message retrieval request with {} {:?} message(s) in {} chunk(s)",
+                chunks.iter().map(|(ids, _)| ids.len()).sum::<usize>(),
+                resp_type,
+                chunks.len()
             );
-            match self
-                .outbound_service
-                .send_message_no_header(
-                    SendMessageParams::new()
-                        .direct_public_key(message.source_peer.public_key.clone())
-                        .with_dht_message_type(DhtMessageType::SafStoredMessages)
-                        .finish(),
-                    stored_messages,
-                )
+            // Responses are sent and paced by the SafResponseSender so that this handler is not held up
+            let response = SafResponse {
+                recipient: message.source_peer.public_key.clone(),
+                request_id: retrieve_msgs.request_id,
+                response_type: resp_type,
+                chunks,
+            };
+            if let Err(err) = self.saf_response_sender.try_send(response) {
+                // The messages remain stored, so the peer will receive them on a later request
+                warn!(
+                    target: LOG_TARGET,
+                    "Dropping {:?} SAF response for peer '{}': {}",
+                    resp_type,
+                    message.source_peer.node_id.short_str(),
+                    err
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the reason that the request should be rejected, and the time after which it may be retried, if this
+    /// node will not serve the request
+    async fn check_request_rejection(
+        &mut self,
+        message: &DecryptedDhtMessage,
+        request: &StoredMessagesRequest,
+    ) -> Result<Option<(SafRejectionReason, Option<Duration>)>, StoreAndForwardError>
+    {
+        let source_peer = &message.source_peer;
+        if self.config.saf_min_request_interval.is_some() {
+            if let Some(retry_after) = self
+                .saf_requester
+                .check_request_rate(source_peer.node_id.clone(), request.request_id)
                 .await?
-                .resolve()
-                .await
             {
-                Ok(_) => {
-                    debug!(
-                        target: LOG_TARGET,
-                        "Removing {} stored message(s) for peer '{}'",
-                        message_ids.len(),
-                        message.source_peer.node_id.short_str()
-                    );
-                    self.saf_requester.remove_messages(message_ids).await?;
-                },
-                Err(err) => {
-                    error!(
-                        target: LOG_TARGET,
-                        "Failed to send stored messages to peer '{}': {}",
-                        message.source_peer.node_id.short_str(),
-                        err
-                    );
-                },
+                return Ok(Some((SafRejectionReason::RateLimited, Some(retry_after))));
             }
         }
 
+        // Mailbox messages are stored regardless of the recipient's network region
+        if !request.mailbox_tags.is_empty() {
+            return Ok(None);
+        }
+
+        let is_in_region = self
+            .peer_manager
+            .in_network_region(
+                &source_peer.node_id,
+                self.node_identity.node_id(),
+                self.config.num_neighbouring_nodes,
+            )
+            .await?;
+        if is_in_region {
+            return Ok(None);
+        }
+
+        // Messages stored before the requester left this node's region (or because the requester is a served client)
+        // are still returned
+        let num_stored = self
+            .saf_requester
+            .count_messages_for_peer(source_peer.public_key.clone())
+            .await?;
+        if num_stored > 0 {
+            return Ok(None);
+        }
+
+        Ok(Some((SafRejectionReason::OutOfRegion, None)))
+    }
+
+    /// Send a signed rejection of a request for stored messages, so that the requester can request stored messages from
+    /// another node instead of waiting for a response
+    async fn send_rejection(
+        &mut self,
+        message: &DecryptedDhtMessage,
+        request_id: u32,
+        reason: SafRejectionReason,
+        retry_after: Option<Duration>,
+    ) -> Result<(), StoreAndForwardError>
+    {
+        if !self.config.saf_send_rejections {
+            return Ok(());
+        }
+
+        debug!(
+            target: LOG_TARGET,
+            "Rejecting store and forward request {} from peer '{}' ({:?}) (Trace: {})",
+            request_id,
+            message.source_peer.node_id.short_str(),
+            reason,
+            message.dht_header.message_tag
+        );
+        let rejection = SafRejection::new(
+            &self.node_identity,
+            &message.source_peer.node_id,
+            request_id,
+            reason,
+            retry_after,
+        )?;
+        self.outbound_service
+            .send_message_no_header(
+                SendMessageParams::new()
+                    .direct_public_key(message.source_peer.public_key.clone())
+                    .with_dht_message_type(DhtMessageType::SafRejection)
+                    .finish(),
+                rejection,
+            )
+            .await?;
+
         Ok(())
     }
 
+    async fn handle_saf_rejection(&mut self, message: DecryptedDhtMessage) -> Result<(), StoreAndForwardError> {
+        let msg = message
+            .success()
+            .expect("already checked that this message decrypted successfully");
+        let rejection = msg
+            .decode_part::<SafRejection>(0)?
+            .ok_or_else(|| StoreAndForwardError::InvalidEnvelopeBody)?;
+
+        if !rejection.verify(&message.source_peer.public_key, self.node_identity.node_id()) {
+            return Err(StoreAndForwardError::InvalidSafRejectionSignature);
+        }
+        let reason = SafRejectionReason::from_i32(rejection.reason).ok_or(StoreAndForwardError::MalformedMessage)?;
+
+        debug!(
+            target: LOG_TARGET,
+            "Peer '{}' rejected store and forward request {} ({:?}, retry after {:?}) (Trace: {})",
+            message.source_peer.node_id.short_str(),
+            rejection.request_id,
+            reason,
+            rejection.retry_after(),
+            message.dht_header.message_tag
+        );
+        self.saf_requester
+            .provider_rejected_request(
+                message.source_peer.node_id.clone(),
+                rejection.request_id,
+                reason,
+                rejection.retry_after(),
+            )
+            .await
+    }
+
     async fn handle_stored_messages(mut self, message: DecryptedDhtMessage) -> Result<(), StoreAndForwardError> {
         trace!(
             target: LOG_TARGET,
@@ -252,16 +455,18 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
         let msg = message
             .success()
             .expect("already checked that this message decrypted successfully");
-        let response = msg
-            .decode_part::<StoredMessagesResponse>(0)?
+        // Stored messages are decoded and processed one at a time rather than decoding the entire response up front
+        let part = msg
+            .get_part(0)
             .ok_or_else(|| StoreAndForwardError::InvalidEnvelopeBody)?;
-        let source_peer = Arc::new(message.source_peer);
+        let response = StoredMessagesResponseReader::new(part)?;
+        let source_peer = Arc::new(message.source_peer.clone());
 
         debug!(
             target: LOG_TARGET,
             "Received {} stored messages of type {} from peer `{}` (Trace: {})",
-            response.messages().len(),
-            SafResponseType::from_i32(response.response_type)
+            response.num_messages(),
+            SafResponseType::from_i32(response.response_type())
                 .as_ref()
                 .map(|t| format!("{:?}", t))
                 .unwrap_or_else(|| "<Invalid>".to_string()),
@@ -269,96 +474,159 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             message_tag
         );
 
-        let tasks = response
-            .messages
-            .into_iter()
-            // Map to futures which process the stored message
-            .map(|msg| self.process_incoming_stored_message(Arc::clone(&source_peer), msg));
+        // Subsequent requests to this peer only need messages stored after this point
+        if let Err(err) = self.dht_requester.record_saf_retrieval(source_node_id.clone()).await {
+            warn!(target: LOG_TARGET, "Failed to record SAF retrieval: {}", err);
+        }
 
-        let successful_msgs_iter = future::join_all(tasks)
-            .await
-            .into_iter()
-            .map(|result| {
-                match &result {
-                    Ok(msg) => {
-                        trace!(target: LOG_TARGET, "Recv SAF message: {}", msg);
-                    },
-                    // Failed decryption is acceptable, the message wasn't for this node so we
-                    // simply discard the message.
-                    // TODO: Should we add this message to our SAF store?
-                    Err(err @ StoreAndForwardError::DecryptionFailed) => {
-                        debug!(
-                            target: LOG_TARGET,
-                            "Unable to decrypt stored message sent by {}: {}",
-                            source_peer.node_id.short_str(),
-                            err
-                        );
-                    },
-                    // The peer that originally sent this message is not known to us.
-                    // TODO: Should we try to discover this peer?
-                    Err(StoreAndForwardError::PeerManagerError(PeerManagerError::PeerNotFoundError)) => {
-                        debug!(target: LOG_TARGET, "Origin peer not found. Discarding stored message.");
+        // Responses from multiple providers are merged by the message hash dedup check, so only the first provider to
+        // deliver a message passes it on to the next service
+        let mut summary = SafResponseSummary::new(source_node_id.clone());
+        // Messages are validated and decrypted concurrently, and each is passed on as soon as it is ready (in the order
+        // that the provider sent them) rather than waiting for the whole response to be processed
+        let max_concurrent = self.config.saf_max_concurrent_stored_messages;
+        let mut stored_messages = response.messages();
+        let mut in_progress = FuturesOrdered::new();
+        loop {
+            while in_progress.len() < max_concurrent {
+                match stored_messages.next() {
+                    Some(Ok(stored_message)) => in_progress.push(Either::Left(
+                        self.process_incoming_stored_message(Arc::clone(&source_peer), stored_message),
+                    )),
+                    Some(Err(err)) => {
+                        in_progress.push(Either::Right(future::ready(Err(StoreAndForwardError::from(err)))))
                     },
+                    None => break,
+                }
+            }
 
-                    // Failed to send request to Dht Actor, something has gone very wrong
-                    Err(StoreAndForwardError::DhtActorError(err)) => {
-                        error!(
-                            target: LOG_TARGET,
-                            "DhtActor returned an error. {}. This could indicate a system malfunction.", err
-                        );
-                    },
-                    // Duplicate message detected, no problem it happens.
-                    Err(StoreAndForwardError::DuplicateMessage) => {
-                        debug!(
-                            target: LOG_TARGET,
-                            "Store and forward received a duplicate message. Message discarded."
-                        );
-                    },
+            let result = match in_progress.next().await {
+                Some(result) => result,
+                None => break,
+            };
 
-                    // Every other error shouldn't happen if the sending node is behaving
-                    Err(err) => {
-                        // TODO: #banheuristics
-                        warn!(
-                            target: LOG_TARGET,
-                            "SECURITY: invalid store and forward message was discarded from NodeId={}. Reason: {}. \
-                             These messages should never have been forwarded. This is a sign of a badly behaving node.",
-                            source_peer.node_id.short_str(),
-                            err
-                        );
-                    },
-                }
+            match result {
+                Ok((msg, stored_at)) => {
+                    trace!(target: LOG_TARGET, "Recv SAF message: {}", msg);
+                    summary.num_new += 1;
+                    if let Some((author, stored_at)) = msg.authenticated_origin.clone().zip(stored_at) {
+                        summary.record_author_seen(author, stored_at);
+                    }
+                    let result = match self.next_service.ready_and().await {
+                        Ok(service) => service.call(msg).await,
+                        Err(err) => Err(err),
+                    };
+                    if let Err(err) = result {
+                        error!(target: LOG_TARGET, "Error when calling next service: {}", err);
+                    }
+                },
+                Err(err) => {
+                    match &err {
+                        StoreAndForwardError::DuplicateMessage => summary.num_duplicate += 1,
+                        StoreAndForwardError::MessageOriginatedFromSelf => summary.num_undelivered += 1,
+                        err if Self::is_invalid_stored_message(err) => summary.num_invalid += 1,
+                        _ => {},
+                    }
+                    Self::log_stored_message_error(&source_peer, err)
+                },
+            }
+        }
 
-                result
-            })
-            .filter(Result::is_ok)
-            .map(Result::unwrap);
+        debug!(
+            target: LOG_TARGET,
+            "Stored messages response from peer `{}`: {} new, {} duplicate, {} invalid, {} undelivered (Trace: {})",
+            source_node_id,
+            summary.num_new,
+            summary.num_duplicate,
+            summary.num_invalid,
+            summary.num_undelivered,
+            message_tag
+        );
 
         // Let the SAF Service know we got a SAF response.
         let _ = self
             .saf_response_signal_sender
-            .send(())
+            .send(summary)
             .await
             .map_err(|e| warn!(target: LOG_TARGET, "Error sending SAF response signal; {:?}", e));
 
-        self.next_service
-            .call_all(stream::iter(successful_msgs_iter))
-            .unordered()
-            .for_each(|service_result| {
-                if let Err(err) = service_result {
-                    error!(target: LOG_TARGET, "Error when calling next service: {}", err);
-                }
-                future::ready(())
-            })
-            .await;
-
         Ok(())
     }
 
+    /// Returns true if the error indicates that the provider should never have stored or forwarded the message
+    fn is_invalid_stored_message(err: &StoreAndForwardError) -> bool {
+        !matches!(
+            err,
+            StoreAndForwardError::DecryptionFailed |
+                StoreAndForwardError::PeerManagerError(PeerManagerError::PeerNotFoundError) |
+                StoreAndForwardError::DhtActorError(_) |
+                StoreAndForwardError::DuplicateMessage |
+                StoreAndForwardError::MessageOriginatedFromSelf
+        )
+    }
+
+    fn log_stored_message_error(source_peer: &Peer, err: StoreAndForwardError) {
+        match err {
+            // Failed decryption is acceptable, the message wasn't for this node so we
+            // simply discard the message.
+            // TODO: Should we add this message to our SAF store?
+            err @ StoreAndForwardError::DecryptionFailed => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Unable to decrypt stored message sent by {}: {}",
+                    source_peer.node_id.short_str(),
+                    err
+                );
+            },
+            // The peer that originally sent this message is not known to us.
+            // TODO: Should we try to discover this peer?
+            StoreAndForwardError::PeerManagerError(PeerManagerError::PeerNotFoundError) => {
+                debug!(target: LOG_TARGET, "Origin peer not found. Discarding stored message.");
+            },
+
+            // Failed to send request to Dht Actor, something has gone very wrong
+            StoreAndForwardError::DhtActorError(err) => {
+                error!(
+                    target: LOG_TARGET,
+                    "DhtActor returned an error. {}. This could indicate a system malfunction.", err
+                );
+            },
+            // Duplicate message detected, no problem it happens.
+            StoreAndForwardError::DuplicateMessage => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Store and forward received a duplicate message. Message discarded."
+                );
+            },
+
+            // This node sent the message, but it was held by the provider and never reached the recipient
+            StoreAndForwardError::MessageOriginatedFromSelf => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Peer {} returned a stored message that originated from this node. The message was not delivered.",
+                    source_peer.node_id.short_str()
+                );
+            },
+
+            // Every other error shouldn't happen if the sending node is behaving
+            err => {
+                // TODO: #banheuristics
+                warn!(
+                    target: LOG_TARGET,
+                    "SECURITY: invalid store and forward message was discarded from NodeId={}. Reason: {}. These \
+                     messages should never have been forwarded. This is a sign of a badly behaving node.",
+                    source_peer.node_id.short_str(),
+                    err
+                );
+            },
+        }
+    }
+
     fn process_incoming_stored_message(
         &self,
         source_peer: Arc<Peer>,
         message: ProtoStoredMessage,
-    ) -> impl Future<Output = Result<DecryptedDhtMessage, StoreAndForwardError>>
+    ) -> impl Future<Output = Result<(DecryptedDhtMessage, Option<DateTime<Utc>>), StoreAndForwardError>>
     {
         let node_identity = Arc::clone(&self.node_identity);
         let peer_manager = Arc::clone(&self.peer_manager);
@@ -404,26 +672,33 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             // Check that the destination is either undisclosed, for us or for our network region
             Self::check_destination(&config, &peer_manager, &node_identity, &dht_header).await?;
             // Check that the message has not already been received.
-            Self::check_duplicate(&mut dht_requester, &message.body).await?;
+            Self::check_duplicate(&mut dht_requester, &dht_header, &message.body).await?;
 
             // Attempt to decrypt the message (if applicable), and deserialize it
             let (authenticated_pk, decrypted_body) =
                 Self::authenticate_and_decrypt_if_required(&node_identity, &dht_header, &message.body)?;
+            if authenticated_pk.as_ref() == Some(node_identity.public_key()) {
+                return Err(StoreAndForwardError::MessageOriginatedFromSelf);
+            }
 
             let mut inbound_msg =
                 DhtInboundMessage::new(MessageTag::new(), dht_header, Arc::clone(&source_peer), message.body);
             inbound_msg.is_saf_message = true;
 
-            Ok(DecryptedDhtMessage::succeeded(
-                decrypted_body,
-                authenticated_pk,
-                inbound_msg,
+            Ok((
+                DecryptedDhtMessage::succeeded(decrypted_body, authenticated_pk, inbound_msg),
+                message.stored_at.map(timestamp_to_datetime),
             ))
         }
     }
 
-    async fn check_duplicate(dht_requester: &mut DhtRequester, body: &[u8]) -> Result<(), StoreAndForwardError> {
-        let msg_hash = Challenge::new().chain(body).result().to_vec();
+    async fn check_duplicate(
+        dht_requester: &mut DhtRequester,
+        dht_header: &DhtMessageHeader,
+        body: &[u8],
+    ) -> Result<(), StoreAndForwardError>
+    {
+        let msg_hash = message_key::message_key_from_header(dht_header, body);
         if dht_requester.insert_message_hash(msg_hash).await? {
             Err(StoreAndForwardError::DuplicateMessage)
         } else {
@@ -447,6 +722,20 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
                 .in_network_region(node_identity.node_id(), node_id, config.num_neighbouring_nodes)
                 .await
                 .unwrap_or(false),
+            // Pass this check if we are one of the destinations or one of the destinations is in this node's region
+            NodeDestination::NodeIds(node_ids) => {
+                let mut is_valid = node_ids.iter().any(|node_id| node_identity.node_id() == node_id);
+                for node_id in node_ids {
+                    if is_valid {
+                        break;
+                    }
+                    is_valid = peer_manager
+                        .in_network_region(node_identity.node_id(), node_id, config.num_neighbouring_nodes)
+                        .await
+                        .unwrap_or(false);
+                }
+                is_valid
+            },
         };
 
         if is_valid_destination {
@@ -475,7 +764,7 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             );
             let shared_secret = crypt::generate_ecdh_secret(node_identity.secret_key(), ephemeral_public_key);
             let decrypted = crypt::decrypt(&shared_secret, &header.origin_mac)?;
-            let authenticated_pk = Self::authenticate_message(&decrypted, body)?;
+            let authenticated_pk = Self::authenticate_message(&decrypted, body, header.sequence)?;
 
             trace!(
                 target: LOG_TARGET,
@@ -491,7 +780,7 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             Ok((Some(authenticated_pk), envelope_body))
         } else {
             let authenticated_pk = if !header.origin_mac.is_empty() {
-                Some(Self::authenticate_message(&header.origin_mac, body)?)
+                Some(Self::authenticate_message(&header.origin_mac, body, header.sequence)?)
             } else {
                 None
             };
@@ -500,12 +789,21 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
         }
     }
 
-    fn authenticate_message(origin_mac_body: &[u8], body: &[u8]) -> Result<CommsPublicKey, StoreAndForwardError> {
+    fn authenticate_message(
+        origin_mac_body: &[u8],
+        body: &[u8],
+        sequence: u64,
+    ) -> Result<CommsPublicKey, StoreAndForwardError>
+    {
         let origin_mac = OriginMac::decode(origin_mac_body)?;
         let public_key =
             CommsPublicKey::from_bytes(&origin_mac.public_key).map_err(|_| StoreAndForwardError::InvalidOriginMac)?;
 
-        if signature::verify(&public_key, &origin_mac.signature, body) {
+        if signature::verify(
+            &public_key,
+            &origin_mac.signature,
+            crypt::origin_mac_challenge(body, sequence),
+        ) {
             Ok(public_key)
         } else {
             Err(StoreAndForwardError::InvalidOriginMac)
@@ -513,30 +811,68 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
     }
 }
 
+/// Splits the stored messages into chunks whose encoded size does not exceed `max_chunk_size`. A single message larger
+/// than `max_chunk_size` is placed in its own chunk. At least one (possibly empty) chunk is always returned so that
+/// the requester receives a response.
+pub(in crate::store_forward) fn chunk_stored_messages(
+    message_ids: Vec<i32>,
+    messages: Vec<ProtoStoredMessage>,
+    max_chunk_size: usize,
+) -> Vec<(Vec<i32>, Vec<ProtoStoredMessage>)>
+{
+    let mut chunks = vec![(Vec::new(), Vec::new())];
+    let mut current_size = 0;
+    for (id, msg) in message_ids.into_iter().zip(messages) {
+        let msg_size = msg.encoded_len();
+        let (ids, msgs) = chunks.last_mut().expect("chunks is never empty");
+        if !msgs.is_empty() && current_size + msg_size > max_chunk_size {
+            chunks.push((vec![id], vec![msg]));
+            current_size = msg_size;
+        } else {
+            ids.push(id);
+            msgs.push(msg);
+            current_size += msg_size;
+        }
+    }
+    chunks
+}
+
+fn decode_stored_messages_request(
+    message: &DecryptedDhtMessage,
+) -> Result<StoredMessagesRequest, StoreAndForwardError> {
+    message
+        .success()
+        .expect("already checked that this message decrypted successfully")
+        .decode_part::<StoredMessagesRequest>(0)?
+        .ok_or_else(|| StoreAndForwardError::InvalidEnvelopeBody)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{
         envelope::DhtMessageFlags,
-        proto::envelope::DhtHeader,
+        proto::{envelope::DhtHeader, store_forward::StoredMessagesResponse},
         store_forward::{message::StoredMessagePriority, StoredMessage},
         test_utils::{
             build_peer_manager,
             create_dht_actor_mock,
             create_store_and_forward_mock,
+            make_client_identity,
             make_dht_header,
             make_dht_inbound_message,
+            make_dht_inbound_message_for,
             make_keypair,
             make_node_identity,
             service_spy,
         },
     };
     use chrono::Utc;
-    use futures::channel::mpsc;
+    use futures::{channel::mpsc, StreamExt};
     use prost::Message;
     use std::time::Duration;
     use tari_comms::{message::MessageExt, wrap_in_envelope_body};
-    use tari_test_utils::collect_stream;
+    use tari_test_utils::{async_assert_eventually, collect_stream};
     use tari_utilities::hex::Hex;
     use tokio::runtime::Handle;
 
@@ -544,7 +880,7 @@ mod test {
 
     fn make_stored_message(node_identity: &NodeIdentity, dht_header: DhtMessageHeader) -> StoredMessage {
         let body = b"A".to_vec();
-        let body_hash = Challenge::new().chain(body.clone()).result().to_vec().to_hex();
+        let body_hash = message_key::message_key_from_header(&dht_header, &body).to_hex();
         StoredMessage {
             id: 1,
             version: 0,
@@ -558,6 +894,8 @@ mod test {
             priority: StoredMessagePriority::High as i32,
             stored_at: Utc::now().naive_utc(),
             body_hash,
+            mailbox_tag: None,
+            destination_node_ids: None,
         }
     }
 
@@ -568,7 +906,7 @@ mod test {
         let (requester, mock_state) = create_store_and_forward_mock();
 
         let peer_manager = build_peer_manager();
-        let (oms_tx, mut oms_rx) = mpsc::channel(1);
+        let (oms_tx, _) = mpsc::channel(1);
 
         let node_identity = make_node_identity();
 
@@ -600,6 +938,7 @@ mod test {
         let (tx, _) = mpsc::channel(1);
         let dht_requester = DhtRequester::new(tx);
         let (saf_response_signal_sender, _saf_response_signal_receiver) = mpsc::channel(20);
+        let (saf_response_sender, mut saf_response_receiver) = mpsc::channel(20);
 
         // First test that the task will respond if there are no messages to send.
         let task = MessageHandlerTask::new(
@@ -612,15 +951,16 @@ mod test {
             node_identity.clone(),
             message.clone(),
             saf_response_signal_sender.clone(),
+            saf_response_sender.clone(),
         );
 
         rt_handle.spawn(task.run());
 
-        let (_, body) = unwrap_oms_send_msg!(oms_rx.next().await.unwrap());
-        let body = body.to_vec();
-        let body = EnvelopeBody::decode(body.as_slice()).unwrap();
-        let msg = body.decode_part::<StoredMessagesResponse>(0).unwrap().unwrap();
-        assert_eq!(msg.messages().len(), 0);
+        let response = saf_response_receiver.next().await.unwrap();
+        assert_eq!(response.recipient, *node_identity.public_key());
+        assert_eq!(response.response_type, SafResponseType::ForMe);
+        assert_eq!(response.chunks.len(), 1);
+        assert!(response.chunks[0].1.is_empty());
         assert!(!spy.is_called());
 
         assert_eq!(mock_state.call_count(), 1);
@@ -630,7 +970,7 @@ mod test {
         assert!(calls[0].contains(format!("{:?}", since).as_str()));
 
         mock_state
-            .add_message(make_stored_message(&node_identity, dht_header))
+            .add_message(make_stored_message(&make_node_identity(), dht_header))
             .await;
 
         // Now lets test its response where there are messages to return.
@@ -644,16 +984,16 @@ mod test {
             node_identity.clone(),
             message,
             saf_response_signal_sender,
+            saf_response_sender,
         );
 
         rt_handle.spawn(task.run());
 
-        let (_, body) = unwrap_oms_send_msg!(oms_rx.next().await.unwrap());
-        let body = body.to_vec();
-        let body = EnvelopeBody::decode(body.as_slice()).unwrap();
-        let msg = body.decode_part::<StoredMessagesResponse>(0).unwrap().unwrap();
-        assert_eq!(msg.messages().len(), 1);
-        assert_eq!(msg.messages()[0].body, b"A");
+        let response = saf_response_receiver.next().await.unwrap();
+        assert_eq!(response.chunks.len(), 1);
+        let (_, messages) = &response.chunks[0];
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].body, b"A");
         assert!(!spy.is_called());
 
         assert_eq!(mock_state.call_count(), 2);
@@ -663,6 +1003,72 @@ mod test {
         assert!(calls[0].contains(format!("{:?}", since).as_str()));
     }
 
+    #[tokio_macros::test_basic]
+    async fn mailbox_only_request() {
+        let spy = service_spy();
+        let (requester, mock_state) = create_store_and_forward_mock();
+        let (oms_tx, _) = mpsc::channel(1);
+        let node_identity = make_node_identity();
+
+        let request = StoredMessagesRequest {
+            mailbox_tags: vec![vec![0xaa, 0xbb]],
+            mailbox_only: true,
+            ..StoredMessagesRequest::new()
+        };
+        let mut message = DecryptedDhtMessage::succeeded(
+            wrap_in_envelope_body!(request),
+            None,
+            make_dht_inbound_message(&node_identity, b"Mailbox".to_vec(), DhtMessageFlags::ENCRYPTED, true),
+        );
+        message.dht_header.message_type = DhtMessageType::SafRequestMessages;
+
+        let (tx, _) = mpsc::channel(1);
+        let (saf_response_signal_sender, _saf_response_signal_receiver) = mpsc::channel(20);
+        let (saf_response_sender, mut saf_response_receiver) = mpsc::channel(20);
+        let task = MessageHandlerTask::new(
+            Default::default(),
+            spy.to_service::<PipelineError>(),
+            requester,
+            DhtRequester::new(tx),
+            build_peer_manager(),
+            OutboundMessageRequester::new(oms_tx),
+            node_identity,
+            message,
+            saf_response_signal_sender,
+            saf_response_sender,
+        );
+        task.run().await.unwrap();
+
+        // Only the mailbox messages are returned
+        let response = saf_response_receiver.next().await.unwrap();
+        assert_eq!(response.response_type, SafResponseType::Mailbox);
+        assert!(saf_response_receiver.next().await.is_none());
+        assert_eq!(mock_state.call_count(), 1);
+        let calls = mock_state.take_calls().await;
+        assert!(calls[0].contains("Mailbox"));
+    }
+
+    #[test]
+    fn chunk_stored_messages_by_size() {
+        let chunks = chunk_stored_messages(vec![], vec![], 100);
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].1.is_empty());
+
+        let msg = |n: usize| ProtoStoredMessage {
+            version: 0,
+            dht_header: None,
+            body: vec![0u8; n],
+            stored_at: None,
+        };
+        let size = msg(40).encoded_len();
+        let chunks = chunk_stored_messages(vec![1, 2, 3, 4], vec![msg(40), msg(40), msg(40), msg(500)], size * 2);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].0, vec![1, 2]);
+        assert_eq!(chunks[1].0, vec![3]);
+        // Oversized messages are sent on their own
+        assert_eq!(chunks[2].0, vec![4]);
+    }
+
     #[tokio_macros::test_basic]
     async fn receive_stored_messages() {
         let rt_handle = Handle::current();
@@ -673,10 +1079,14 @@ mod test {
         let (oms_tx, _) = mpsc::channel(1);
 
         let node_identity = make_node_identity();
+        let origin_identity = make_node_identity();
+        let make_msg = |body: Vec<u8>, flags: DhtMessageFlags| {
+            make_dht_inbound_message_for(&origin_identity, node_identity.public_key(), body, flags, true)
+        };
 
         let msg_a = wrap_in_envelope_body!(&b"A".to_vec()).to_encoded_bytes();
 
-        let inbound_msg_a = make_dht_inbound_message(&node_identity, msg_a.clone(), DhtMessageFlags::ENCRYPTED, true);
+        let inbound_msg_a = make_msg(msg_a.clone(), DhtMessageFlags::ENCRYPTED);
         // Need to know the peer to process a stored message
         peer_manager
             .add_peer(Clone::clone(&*inbound_msg_a.source_peer))
@@ -684,7 +1094,7 @@ mod test {
             .unwrap();
 
         let msg_b = &wrap_in_envelope_body!(b"B".to_vec()).to_encoded_bytes();
-        let inbound_msg_b = make_dht_inbound_message(&node_identity, msg_b.clone(), DhtMessageFlags::ENCRYPTED, true);
+        let inbound_msg_b = make_msg(msg_b.clone(), DhtMessageFlags::ENCRYPTED);
         // Need to know the peer to process a stored message
         peer_manager
             .add_peer(Clone::clone(&*inbound_msg_b.source_peer))
@@ -713,8 +1123,10 @@ mod test {
             ),
         );
         message.dht_header.message_type = DhtMessageType::SafStoredMessages;
+        let source_node_id = message.source_peer.node_id.clone();
 
         let (dht_requester, mock) = create_dht_actor_mock(1);
+        let dht_mock_state = mock.get_shared_state();
         rt_handle.spawn(mock.run());
         let (saf_response_signal_sender, mut saf_response_signal_receiver) = mpsc::channel(20);
 
@@ -728,6 +1140,7 @@ mod test {
             node_identity,
             message,
             saf_response_signal_sender,
+            mpsc::channel(1).0,
         );
 
         task.run().await.unwrap();
@@ -748,5 +1161,338 @@ mod test {
             timeout = Duration::from_secs(20)
         );
         assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].provider, source_node_id);
+        assert_eq!(signals[0].num_new, 3);
+        assert_eq!(signals[0].num_duplicate, 0);
+        assert_eq!(signals[0].num_invalid, 0);
+        // Only the authenticated author of the encrypted messages is seen
+        assert_eq!(signals[0].authors_seen.len(), 1);
+        assert!(signals[0].authors_seen.contains_key(origin_identity.public_key()));
+        async_assert_eventually!(
+            dht_mock_state.get_last_saf_retrieval(&source_node_id).is_some(),
+            expect = true,
+            max_attempts = 10,
+            interval = Duration::from_millis(10),
+        );
+    }
+
+    #[tokio_macros::test_basic]
+    async fn receive_stored_messages_concurrently_in_order() {
+        let rt_handle = Handle::current();
+        let spy = service_spy();
+        let (requester, _) = create_store_and_forward_mock();
+        let (oms_tx, _) = mpsc::channel(1);
+        let node_identity = make_node_identity();
+
+        let bodies = (0..5u8).map(|i| vec![i]).collect::<Vec<_>>();
+        let stored_messages = bodies
+            .iter()
+            .map(|body| {
+                let msg = wrap_in_envelope_body!(body.clone()).to_encoded_bytes();
+                let header =
+                    make_dht_inbound_message(&node_identity, msg.clone(), DhtMessageFlags::empty(), false).dht_header;
+                ProtoStoredMessage::new(0, header, msg)
+            })
+            .collect();
+        let mut message = DecryptedDhtMessage::succeeded(
+            wrap_in_envelope_body!(StoredMessagesResponse {
+                messages: stored_messages,
+                request_id: 123,
+                response_type: 0
+            }),
+            None,
+            make_dht_inbound_message(
+                &node_identity,
+                b"Stored message".to_vec(),
+                DhtMessageFlags::ENCRYPTED,
+                true,
+            ),
+        );
+        message.dht_header.message_type = DhtMessageType::SafStoredMessages;
+
+        let (dht_requester, mock) = create_dht_actor_mock(1);
+        rt_handle.spawn(mock.run());
+        let (saf_response_signal_sender, mut saf_response_signal_receiver) = mpsc::channel(20);
+
+        let task = MessageHandlerTask::new(
+            DhtConfig {
+                saf_max_concurrent_stored_messages: 2,
+                ..Default::default()
+            },
+            spy.to_service::<PipelineError>(),
+            requester,
+            dht_requester,
+            build_peer_manager(),
+            OutboundMessageRequester::new(oms_tx),
+            node_identity,
+            message,
+            saf_response_signal_sender,
+            mpsc::channel(1).0,
+        );
+
+        task.run().await.unwrap();
+        let msgs = spy
+            .take_requests()
+            .into_iter()
+            .map(|req| req.success().unwrap().decode_part::<Vec<u8>>(0).unwrap().unwrap())
+            .collect::<Vec<_>>();
+        // All messages are passed on in the order that the provider sent them
+        assert_eq!(msgs, bodies);
+        let signals = collect_stream!(
+            saf_response_signal_receiver,
+            take = 1,
+            timeout = Duration::from_secs(20)
+        );
+        assert_eq!(signals[0].num_new, 5);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn receive_stored_messages_originating_from_self() {
+        let spy = service_spy();
+        let (requester, _) = create_store_and_forward_mock();
+
+        let peer_manager = build_peer_manager();
+        let (oms_tx, _) = mpsc::channel(1);
+
+        let node_identity = make_node_identity();
+
+        let body = wrap_in_envelope_body!(&b"A".to_vec()).to_encoded_bytes();
+        let own_msg = make_dht_inbound_message(&node_identity, body, DhtMessageFlags::ENCRYPTED, true);
+        peer_manager
+            .add_peer(Clone::clone(&*own_msg.source_peer))
+            .await
+            .unwrap();
+        let stored_msg = ProtoStoredMessage::new(0, own_msg.dht_header, own_msg.body);
+
+        let mut message = DecryptedDhtMessage::succeeded(
+            wrap_in_envelope_body!(StoredMessagesResponse {
+                messages: vec![stored_msg],
+                request_id: 123,
+                response_type: 0
+            }),
+            None,
+            make_dht_inbound_message(
+                &make_node_identity(),
+                b"Stored message".to_vec(),
+                DhtMessageFlags::ENCRYPTED,
+                true,
+            ),
+        );
+        message.dht_header.message_type = DhtMessageType::SafStoredMessages;
+        let source_node_id = message.source_peer.node_id.clone();
+
+        let (dht_requester, mock) = create_dht_actor_mock(1);
+        Handle::current().spawn(mock.run());
+        let (saf_response_signal_sender, mut saf_response_signal_receiver) = mpsc::channel(20);
+
+        let task = MessageHandlerTask::new(
+            Default::default(),
+            spy.to_service::<PipelineError>(),
+            requester,
+            dht_requester,
+            peer_manager,
+            OutboundMessageRequester::new(oms_tx),
+            node_identity,
+            message,
+            saf_response_signal_sender,
+            mpsc::channel(1).0,
+        );
+
+        task.run().await.unwrap();
+        // The message is not passed on as if it had been delivered
+        assert!(!spy.is_called());
+        let signals = collect_stream!(
+            saf_response_signal_receiver,
+            take = 1,
+            timeout = Duration::from_secs(20)
+        );
+        assert_eq!(signals[0].provider, source_node_id);
+        assert_eq!(signals[0].num_new, 0);
+        assert_eq!(signals[0].num_invalid, 0);
+        assert_eq!(signals[0].num_undelivered, 1);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn discovery_hint_sent_for_offline_peer() {
+        let rt_handle = Handle::current();
+        let spy = service_spy();
+        let (requester, mock_state) = create_store_and_forward_mock();
+
+        let peer_manager = build_peer_manager();
+        let (oms_tx, mut oms_rx) = mpsc::channel(1);
+
+        let node_identity = make_node_identity();
+        let offline_identity = make_node_identity();
+
+        let (e_sk, e_pk) = make_keypair();
+        let dht_header = make_dht_header(
+            &node_identity,
+            &e_pk,
+            &e_sk,
+            &[],
+            DhtMessageFlags::empty(),
+            false,
+            MessageTag::new(),
+        );
+        let mut stored_message = make_stored_message(&node_identity, dht_header);
+        stored_message.destination_pubkey = Some(offline_identity.public_key().to_hex());
+        mock_state.add_message(stored_message).await;
+
+        let mut inbound_msg =
+            make_dht_inbound_message(&node_identity, b"Discovery".to_vec(), DhtMessageFlags::ENCRYPTED, true);
+        inbound_msg.dht_header.message_type = DhtMessageType::Discovery;
+        inbound_msg.dht_header.destination = offline_identity.public_key().clone().into();
+        let message = DecryptedDhtMessage::failed(inbound_msg);
+        let source_node_id = message.source_peer.node_id.clone();
+
+        let (dht_requester, _) = create_dht_actor_mock(1);
+        let (saf_response_signal_sender, _) = mpsc::channel(1);
+
+        let task = MessageHandlerTask::new(
+            Default::default(),
+            spy.to_service::<PipelineError>(),
+            requester,
+            dht_requester,
+            peer_manager,
+            OutboundMessageRequester::new(oms_tx),
+            node_identity.clone(),
+            message,
+            saf_response_signal_sender,
+            mpsc::channel(1).0,
+        );
+
+        let join_handle = rt_handle.spawn(task.run());
+
+        let (params, body) = unwrap_oms_send_msg!(oms_rx.next().await.unwrap());
+        assert_eq!(params.dht_message_type, DhtMessageType::DiscoveryHint);
+        assert_eq!(params.broadcast_strategy.direct_node_id().unwrap(), &source_node_id);
+        let body = EnvelopeBody::decode(body.to_vec().as_slice()).unwrap();
+        let hint = body.decode_part::<DiscoveryHintMessage>(0).unwrap().unwrap();
+        assert_eq!(hint.public_key, offline_identity.public_key().to_vec());
+        assert_eq!(hint.saf_node_ids, vec![node_identity.node_id().to_vec()]);
+
+        join_handle.await.unwrap().unwrap();
+        // The undecryptable message is still passed on to the next service
+        assert!(spy.is_called());
+    }
+
+    #[tokio_macros::test_basic]
+    async fn request_rejected_if_not_saf_node() {
+        let rt_handle = Handle::current();
+        let spy = service_spy();
+        let (requester, _) = create_store_and_forward_mock();
+
+        let peer_manager = build_peer_manager();
+        let (oms_tx, mut oms_rx) = mpsc::channel(1);
+
+        let node_identity = make_client_identity();
+        let requester_identity = make_node_identity();
+
+        let request = StoredMessagesRequest::new();
+        let mut message = DecryptedDhtMessage::succeeded(
+            wrap_in_envelope_body!(request.clone()),
+            None,
+            make_dht_inbound_message(&requester_identity, vec![], DhtMessageFlags::empty(), false),
+        );
+        message.dht_header.message_type = DhtMessageType::SafRequestMessages;
+
+        let (dht_requester, _) = create_dht_actor_mock(1);
+        let (saf_response_signal_sender, _) = mpsc::channel(1);
+
+        let task = MessageHandlerTask::new(
+            Default::default(),
+            spy.to_service::<PipelineError>(),
+            requester,
+            dht_requester,
+            peer_manager,
+            OutboundMessageRequester::new(oms_tx),
+            node_identity.clone(),
+            message,
+            saf_response_signal_sender,
+            mpsc::channel(1).0,
+        );
+
+        let join_handle = rt_handle.spawn(task.run());
+
+        let (params, body) = unwrap_oms_send_msg!(oms_rx.next().await.unwrap());
+        assert_eq!(params.dht_message_type, DhtMessageType::SafRejection);
+        assert_eq!(
+            params.broadcast_strategy.direct_public_key().unwrap(),
+            requester_identity.public_key()
+        );
+        let body = EnvelopeBody::decode(body.to_vec().as_slice()).unwrap();
+        let rejection = body.decode_part::<SafRejection>(0).unwrap().unwrap();
+        assert_eq!(rejection.request_id, request.request_id);
+        assert_eq!(rejection.reason, SafRejectionReason::NotSupported as i32);
+        assert!(rejection.retry_after().is_none());
+        assert!(rejection.verify(node_identity.public_key(), requester_identity.node_id()));
+        // The rejection is bound to the requester and cannot be replayed to another node
+        assert!(!rejection.verify(node_identity.public_key(), node_identity.node_id()));
+
+        join_handle.await.unwrap().unwrap();
+        assert!(!spy.is_called());
+    }
+
+    #[tokio_macros::test_basic]
+    async fn receive_saf_rejection() {
+        let spy = service_spy();
+        let (requester, mock_state) = create_store_and_forward_mock();
+        let (oms_tx, _) = mpsc::channel(1);
+
+        let node_identity = make_node_identity();
+        let provider_identity = make_node_identity();
+
+        let make_task = |rejection: SafRejection| {
+            let mut message = DecryptedDhtMessage::succeeded(
+                wrap_in_envelope_body!(rejection),
+                None,
+                make_dht_inbound_message(&provider_identity, vec![], DhtMessageFlags::empty(), false),
+            );
+            message.dht_header.message_type = DhtMessageType::SafRejection;
+            let (dht_requester, _) = create_dht_actor_mock(1);
+            let (saf_response_signal_sender, _) = mpsc::channel(1);
+            MessageHandlerTask::new(
+                Default::default(),
+                spy.to_service::<PipelineError>(),
+                requester.clone(),
+                dht_requester,
+                build_peer_manager(),
+                OutboundMessageRequester::new(oms_tx.clone()),
+                node_identity.clone(),
+                message,
+                saf_response_signal_sender,
+                mpsc::channel(1).0,
+            )
+        };
+
+        let rejection = SafRejection::new(
+            &provider_identity,
+            node_identity.node_id(),
+            123,
+            SafRejectionReason::RateLimited,
+            Some(Duration::from_secs(30)),
+        )
+        .unwrap();
+
+        make_task(rejection.clone()).run().await.unwrap();
+        async_assert_eventually!(
+            mock_state.call_count(),
+            expect = 1,
+            max_attempts = 20,
+            interval = Duration::from_millis(10)
+        );
+        let calls = mock_state.take_calls().await;
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].contains("ProviderRejectedRequest"));
+        assert!(calls[0].contains("RateLimited"));
+
+        // Tampering with the rejection invalidates the signature
+        let mut forged = rejection;
+        forged.reason = SafRejectionReason::OutOfRegion as i32;
+        make_task(forged).run().await.unwrap_err();
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert_eq!(mock_state.call_count(), 1);
+        assert!(mock_state.take_calls().await.is_empty());
+        assert!(!spy.is_called());
     }
 }
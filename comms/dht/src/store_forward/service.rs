@@ -21,42 +21,75 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use super::{
-    database::{NewStoredMessage, StoreAndForwardDatabase, StoredMessage},
+    clients::ServedClients,
+    database::{NewStoredMessage, SafDestinationUsage, SafStorage, StoreAndForwardDatabase, StoredMessage},
+    filter::{SafStoreFilter, SafStoreFilterId, SafStoreFilters},
+    mailbox,
     message::StoredMessagePriority,
+    saf_handler::chunk_stored_messages,
+    SafCapability,
+    SafProviderStats,
+    SafResponseSummary,
     SafResult,
     StoreAndForwardError,
 };
 use crate::{
-    envelope::DhtMessageType,
-    event::{DhtEvent, DhtEventSender},
-    outbound::{OutboundMessageRequester, SendMessageParams},
-    proto::store_forward::{stored_messages_response::SafResponseType, StoredMessagesRequest},
-    storage::{DbConnection, DhtMetadataKey},
+    envelope::{DhtMessageHeader, DhtMessageType},
+    event::{DhtEvent, DhtEventSender, PeerSeenSource},
+    outbound::{message::SendFailure, DhtOutboundError, OutboundMessageRequester, SendMessageParams},
+    proto::{
+        envelope::DhtHeader,
+        store_forward::{
+            saf_rejection::SafRejectionReason,
+            stored_messages_response::SafResponseType,
+            StoredMessagesRequest,
+            StoredMessagesResponse,
+        },
+    },
+    storage::{DbConnection, DhtMetadataKey, StorageError},
     DhtConfig,
     DhtRequester,
 };
 use chrono::{DateTime, NaiveDateTime, Utc};
 use futures::{
     channel::{mpsc, oneshot},
+    future,
+    future::Either,
+    stream,
     stream::Fuse,
     SinkExt,
     StreamExt,
 };
 use log::*;
-use std::{cmp, convert::TryFrom, sync::Arc, time::Duration};
+use prost::Message;
+use std::{
+    cmp,
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    iter,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tari_comms::{
+    backoff::BackoffPolicy,
     connectivity::{ConnectivityEvent, ConnectivityEventRx, ConnectivityRequester},
-    peer_manager::{NodeId, PeerFeatures},
+    peer_manager::{NodeId, NodeIdentity, PeerFeatures, PeerManagerEvent, PeerManagerEventRx},
     types::CommsPublicKey,
+    utils::rng::SharedRng,
     PeerManager,
+    ShutdownReporter,
 };
-use tari_shutdown::ShutdownSignal;
+use tari_shutdown::{Shutdown, ShutdownSignal};
+use tari_utilities::{convert::try_convert_all, hex::Hex};
 use tokio::{task, time};
 
 const LOG_TARGET: &str = "comms::dht::storeforward::actor";
 /// The interval to initiate a database cleanup.
 /// This involves cleaning up messages which have been stored too long according to their priority
 const CLEANUP_INTERVAL: Duration = Duration::from_secs(10 * 60); // 10 mins
+/// The period for which a provider that rejected a request for stored messages is not sent further requests, unless
+/// the provider gave a retry time
+const REJECTED_PROVIDER_EXCLUSION_PERIOD: Duration = Duration::from_secs(30 * 60); // 30 mins
 
 #[derive(Debug, Clone)]
 pub struct FetchStoredMessageQuery {
@@ -64,6 +97,7 @@ pub struct FetchStoredMessageQuery {
     node_id: Box<NodeId>,
     since: Option<DateTime<Utc>>,
     response_type: SafResponseType,
+    mailbox_tags: Vec<String>,
 }
 
 impl FetchStoredMessageQuery {
@@ -73,6 +107,7 @@ impl FetchStoredMessageQuery {
             node_id,
             since: None,
             response_type: SafResponseType::Anonymous,
+            mailbox_tags: Vec::new(),
         }
     }
 
@@ -85,6 +120,12 @@ impl FetchStoredMessageQuery {
         self.response_type = response_type;
         self
     }
+
+    /// Sets the hex-encoded mailbox tags to query for. Only used for `SafResponseType::Mailbox` queries.
+    pub fn with_mailbox_tags(&mut self, mailbox_tags: Vec<String>) -> &mut Self {
+        self.mailbox_tags = mailbox_tags;
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -92,8 +133,23 @@ pub enum StoreAndForwardRequest {
     FetchMessages(FetchStoredMessageQuery, oneshot::Sender<SafResult<Vec<StoredMessage>>>),
     InsertMessage(NewStoredMessage, oneshot::Sender<SafResult<bool>>),
     RemoveMessages(Vec<i32>),
+    TakeMessages(Vec<i32>, oneshot::Sender<SafResult<Vec<StoredMessage>>>),
+    CountMessagesForPeer(Box<CommsPublicKey>, oneshot::Sender<SafResult<usize>>),
+    GetStorageSize(oneshot::Sender<SafResult<usize>>),
+    CountMessagesByDestination(usize, oneshot::Sender<SafResult<Vec<SafDestinationUsage>>>),
     SendStoreForwardRequestToPeer(Box<NodeId>),
     SendStoreForwardRequestNeighbours,
+    CheckRequestRate(Box<NodeId>, u32, oneshot::Sender<Option<Duration>>),
+    ProviderRejectedRequest(Box<NodeId>, u32, SafRejectionReason, Option<Duration>),
+    GetProviderStats(oneshot::Sender<HashMap<NodeId, SafProviderStats>>),
+    RegisterClient(Box<CommsPublicKey>, oneshot::Sender<SafResult<bool>>),
+    UnregisterClient(Box<CommsPublicKey>, oneshot::Sender<SafResult<bool>>),
+    GetServedClients(oneshot::Sender<Vec<CommsPublicKey>>),
+    AddStoreFilter(SafStoreFilter, oneshot::Sender<SafStoreFilterId>),
+    RemoveStoreFilter(SafStoreFilterId, oneshot::Sender<bool>),
+    GetStoreFilters(oneshot::Sender<Vec<(SafStoreFilterId, SafStoreFilter)>>),
+    AddMailboxContact(Box<CommsPublicKey>, oneshot::Sender<SafResult<bool>>),
+    RemoveMailboxContact(Box<CommsPublicKey>, oneshot::Sender<bool>),
 }
 
 #[derive(Clone)]
@@ -132,6 +188,51 @@ impl StoreAndForwardRequester {
         Ok(())
     }
 
+    /// Atomically removes and returns the stored messages with the given ids. Messages that have already been removed
+    /// are not returned.
+    pub async fn take_messages(&mut self, message_ids: Vec<i32>) -> SafResult<Vec<StoredMessage>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::TakeMessages(message_ids, reply_tx))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)?
+    }
+
+    /// Returns the number of messages that this node is storing for the given peer
+    pub async fn count_messages_for_peer(&mut self, public_key: CommsPublicKey) -> SafResult<usize> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::CountMessagesForPeer(
+                Box::new(public_key),
+                reply_tx,
+            ))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)?
+    }
+
+    /// Returns the approximate number of bytes of stored messages held by this node
+    pub async fn get_storage_size(&mut self) -> SafResult<usize> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::GetStorageSize(reply_tx))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)?
+    }
+
+    /// Returns the number of stored messages and bytes held for each of the `limit` destinations with the most stored
+    /// messages, most messages first
+    pub async fn count_messages_by_destination(&mut self, limit: usize) -> SafResult<Vec<SafDestinationUsage>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::CountMessagesByDestination(limit, reply_tx))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)?
+    }
+
     pub async fn request_saf_messages_from_peer(&mut self, node_id: NodeId) -> SafResult<()> {
         self.sender
             .send(StoreAndForwardRequest::SendStoreForwardRequestToPeer(Box::new(node_id)))
@@ -140,6 +241,8 @@ impl StoreAndForwardRequester {
         Ok(())
     }
 
+    /// Request stored messages from the closest connected store and forward nodes (see
+    /// `DhtConfig::saf_num_request_nodes`). Responses from each node are deduplicated before being processed.
     pub async fn request_saf_messages_from_neighbours(&mut self) -> SafResult<()> {
         self.sender
             .send(StoreAndForwardRequest::SendStoreForwardRequestNeighbours)
@@ -147,54 +250,244 @@ impl StoreAndForwardRequester {
             .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
         Ok(())
     }
+
+    /// Record a request for stored messages from the given peer. If the peer has made a request within
+    /// `DhtConfig::saf_min_request_interval`, the request is not recorded and the time after which the peer may
+    /// request again is returned. Follow-up requests that carry the remaining mailbox tags of the peer's last request
+    /// (i.e. have the same request id) are not rate limited.
+    pub async fn check_request_rate(&mut self, node_id: NodeId, request_id: u32) -> SafResult<Option<Duration>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::CheckRequestRate(
+                Box::new(node_id),
+                request_id,
+                reply_tx,
+            ))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)
+    }
+
+    /// Notify the service that a store and forward node rejected the request with the given id. The node is not sent
+    /// further requests for a period and, if the request was for stored messages from neighbours, the next closest
+    /// store and forward node is requested instead.
+    pub async fn provider_rejected_request(
+        &mut self,
+        node_id: NodeId,
+        request_id: u32,
+        reason: SafRejectionReason,
+        retry_after: Option<Duration>,
+    ) -> SafResult<()>
+    {
+        self.sender
+            .send(StoreAndForwardRequest::ProviderRejectedRequest(
+                Box::new(node_id),
+                request_id,
+                reason,
+                retry_after,
+            ))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        Ok(())
+    }
+
+    /// Returns statistics for the stored messages returned by each store and forward node since this node started
+    pub async fn get_provider_stats(&mut self) -> SafResult<HashMap<NodeId, SafProviderStats>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::GetProviderStats(reply_tx))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)
+    }
+
+    /// Register a client peer (e.g. a wallet) that this node serves. Messages destined for the client are stored with
+    /// client priority (see `DhtConfig::saf_client_msg_storage_ttl`) and, if `DhtConfig::saf_client_push_on_connect`
+    /// is set, pushed to the client whenever it is connected. Registered clients are persisted across restarts.
+    /// Returns false if the client was already registered.
+    pub async fn register_client(&mut self, public_key: CommsPublicKey) -> SafResult<bool> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::RegisterClient(Box::new(public_key), reply_tx))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)?
+    }
+
+    /// Stop serving a client peer. Messages already stored for the client are kept until they expire. Returns false if
+    /// the client was not registered.
+    pub async fn unregister_client(&mut self, public_key: CommsPublicKey) -> SafResult<bool> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::UnregisterClient(Box::new(public_key), reply_tx))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)?
+    }
+
+    /// Returns the public keys of the client peers served by this node
+    pub async fn get_served_clients(&mut self) -> SafResult<Vec<CommsPublicKey>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::GetServedClients(reply_tx))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)
+    }
+
+    /// Register a store filter. Once any store filters are registered, only undeliverable messages that match at least
+    /// one filter are stored for peers (see `SafStoreFilters`). Returns an id that can be used to remove the filter.
+    pub async fn add_store_filter(&mut self, filter: SafStoreFilter) -> SafResult<SafStoreFilterId> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::AddStoreFilter(filter, reply_tx))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)
+    }
+
+    /// Remove a previously registered store filter. Returns false if the filter was not registered.
+    pub async fn remove_store_filter(&mut self, id: SafStoreFilterId) -> SafResult<bool> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::RemoveStoreFilter(id, reply_tx))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)
+    }
+
+    /// Returns the registered store filters
+    pub async fn get_store_filters(&mut self) -> SafResult<Vec<(SafStoreFilterId, SafStoreFilter)>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::GetStoreFilters(reply_tx))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)
+    }
+
+    /// Add a peer that this node expects to receive undisclosed-destination messages from. Requests for stored
+    /// messages include the mailbox tags shared with each contact, so that store and forward nodes return messages the
+    /// contact sent with a mailbox tag. Returns false if the peer was already a contact, or an error if there are
+    /// already `MAX_MAILBOX_CONTACTS` contacts.
+    pub async fn add_mailbox_contact(&mut self, public_key: CommsPublicKey) -> SafResult<bool> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::AddMailboxContact(
+                Box::new(public_key),
+                reply_tx,
+            ))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)?
+    }
+
+    /// Remove a mailbox contact. Returns false if the peer was not a contact.
+    pub async fn remove_mailbox_contact(&mut self, public_key: CommsPublicKey) -> SafResult<bool> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(StoreAndForwardRequest::RemoveMailboxContact(
+                Box::new(public_key),
+                reply_tx,
+            ))
+            .await
+            .map_err(|_| StoreAndForwardError::RequesterChannelClosed)?;
+        reply_rx.await.map_err(|_| StoreAndForwardError::RequestCancelled)
+    }
+}
+
+/// A request for stored messages received from a peer
+struct ReceivedRequest {
+    received_at: Instant,
+    request_id: u32,
+    /// The number of requests with `request_id` that were accepted
+    num_requests: usize,
 }
 
 pub struct StoreAndForwardService {
     config: DhtConfig,
+    node_identity: Arc<NodeIdentity>,
     dht_requester: DhtRequester,
-    database: StoreAndForwardDatabase,
+    database: Box<dyn SafStorage>,
     peer_manager: Arc<PeerManager>,
+    connectivity: ConnectivityRequester,
     connection_events: Fuse<ConnectivityEventRx>,
+    peer_manager_events: Fuse<PeerManagerEventRx>,
     outbound_requester: OutboundMessageRequester,
     request_rx: Fuse<mpsc::Receiver<StoreAndForwardRequest>>,
     shutdown_signal: Option<ShutdownSignal>,
     num_received_saf_responses: Option<usize>,
     num_online_peers: Option<usize>,
-    saf_response_signal_rx: Fuse<mpsc::Receiver<()>>,
+    saf_response_signal_rx: Fuse<mpsc::Receiver<SafResponseSummary>>,
+    provider_stats: HashMap<NodeId, SafProviderStats>,
+    /// The id of the outstanding request for stored messages sent to each provider
+    pending_provider_requests: HashMap<NodeId, u32>,
+    /// Providers that rejected a request, and the time until which they are not sent further requests
+    rejected_providers: HashMap<NodeId, Instant>,
+    /// The last request for stored messages received from each peer, used for rate limiting
+    last_requests: HashMap<NodeId, ReceivedRequest>,
+    served_clients: ServedClients,
+    store_filters: SafStoreFilters,
+    /// Peers that this node expects undisclosed-destination messages from, used to derive mailbox tags
+    mailbox_contacts: HashSet<CommsPublicKey>,
     event_publisher: DhtEventSender,
+    shutdown_reporter: Option<ShutdownReporter>,
+    complete_trigger: Shutdown,
 }
 
 impl StoreAndForwardService {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: DhtConfig,
+        node_identity: Arc<NodeIdentity>,
         conn: DbConnection,
         peer_manager: Arc<PeerManager>,
         dht_requester: DhtRequester,
         connectivity: ConnectivityRequester,
         outbound_requester: OutboundMessageRequester,
         request_rx: mpsc::Receiver<StoreAndForwardRequest>,
-        saf_response_signal_rx: mpsc::Receiver<()>,
+        saf_response_signal_rx: mpsc::Receiver<SafResponseSummary>,
+        served_clients: ServedClients,
+        store_filters: SafStoreFilters,
         event_publisher: DhtEventSender,
         shutdown_signal: ShutdownSignal,
     ) -> Self
     {
         Self {
             config,
-            database: StoreAndForwardDatabase::new(conn),
+            node_identity,
+            database: Box::new(StoreAndForwardDatabase::new(conn)),
+            peer_manager_events: peer_manager.get_event_subscription().fuse(),
             peer_manager,
             dht_requester,
             request_rx: request_rx.fuse(),
             connection_events: connectivity.get_event_subscription().fuse(),
+            connectivity,
             outbound_requester,
             shutdown_signal: Some(shutdown_signal),
             num_received_saf_responses: Some(0),
             num_online_peers: None,
             saf_response_signal_rx: saf_response_signal_rx.fuse(),
+            provider_stats: HashMap::new(),
+            pending_provider_requests: HashMap::new(),
+            rejected_providers: HashMap::new(),
+            last_requests: HashMap::new(),
+            served_clients,
+            store_filters,
+            mailbox_contacts: HashSet::new(),
             event_publisher,
+            shutdown_reporter: None,
+            complete_trigger: Shutdown::new(),
         }
     }
 
+    /// Record the number of messages held in storage to the comms shutdown report when shutting down
+    pub fn with_shutdown_reporter(mut self, shutdown_reporter: ShutdownReporter) -> Self {
+        shutdown_reporter.register_complete_signal(self.complete_trigger.to_signal());
+        self.shutdown_reporter = Some(shutdown_reporter);
+        self
+    }
+
     pub fn spawn(self) {
         info!(target: LOG_TARGET, "Store and forward service started");
         task::spawn(Self::run(self));
@@ -206,7 +499,16 @@ impl StoreAndForwardService {
             .take()
             .expect("StoreAndForwardActor initialized without shutdown_signal");
 
+        if let Err(err) = self.load_served_clients().await {
+            error!(target: LOG_TARGET, "Failed to load served clients: {:?}", err);
+        }
+
         let mut cleanup_ticker = time::interval(CLEANUP_INTERVAL).fuse();
+        let mut handoff_ticker = match self.config.saf_handoff_interval {
+            Some(interval) => Either::Left(time::interval_at(time::Instant::now() + interval, interval)),
+            None => Either::Right(stream::empty()),
+        }
+        .fuse();
 
         loop {
             futures::select! {
@@ -222,13 +524,38 @@ impl StoreAndForwardService {
                     }
                 },
 
+                event = self.peer_manager_events.select_next_some() => {
+                    if let Ok(event) = event {
+                        if let Err(err) = self.handle_peer_manager_event(&event).await {
+                            error!(target: LOG_TARGET, "Error handling peer manager event: {:?}", err);
+                        }
+                    }
+                },
+
                 _ = cleanup_ticker.select_next_some() => {
                     if let Err(err) = self.cleanup().await {
                         error!(target: LOG_TARGET, "Error when performing store and forward cleanup: {:?}", err);
                     }
                 },
 
-                _ = self.saf_response_signal_rx.select_next_some() => {
+                _ = handoff_ticker.select_next_some() => {
+                    if let Err(err) = self.handoff_messages_outside_region().await {
+                        error!(target: LOG_TARGET, "Error when handing off stored messages: {:?}", err);
+                    }
+                },
+
+                summary = self.saf_response_signal_rx.select_next_some() => {
+                    self.pending_provider_requests.remove(&summary.provider);
+                    self.provider_stats.entry(summary.provider.clone()).or_default().record(&summary);
+                    if summary.num_undelivered > 0 {
+                        self.publish_event(DhtEvent::StoreAndForwardDeliveryFailed(
+                            summary.provider.clone(),
+                            summary.num_undelivered,
+                        ));
+                    }
+                    for (public_key, seen_at) in summary.authors_seen {
+                        self.publish_event(DhtEvent::PeerSeen(public_key, PeerSeenSource::StoreAndForward, seen_at));
+                    }
                     if let Some(n) = self.num_received_saf_responses {
                         self.num_received_saf_responses = Some(n + 1);
                         self.check_saf_response_threshold();
@@ -237,12 +564,22 @@ impl StoreAndForwardService {
 
                 _ = shutdown_signal => {
                     info!(target: LOG_TARGET, "StoreAndForwardActor is shutting down because the shutdown signal was triggered");
+                    self.report_shutdown().await;
                     break;
                 }
             }
         }
     }
 
+    async fn report_shutdown(&self) {
+        if let Some(reporter) = self.shutdown_reporter.as_ref() {
+            match self.database.count_messages().await {
+                Ok(count) => reporter.set_component_count("saf_messages_held", count),
+                Err(err) => error!(target: LOG_TARGET, "Failed to count stored messages: {:?}", err),
+            }
+        }
+    }
+
     async fn handle_request(&mut self, request: StoreAndForwardRequest) {
         use StoreAndForwardRequest::*;
         trace!(target: LOG_TARGET, "Request: {:?}", request);
@@ -262,11 +599,16 @@ impl StoreAndForwardService {
             InsertMessage(msg, reply_tx) => {
                 let public_key = msg.destination_pubkey.clone();
                 let node_id = msg.destination_node_id.clone();
+                let node_ids = msg.destination_node_ids.clone();
+                let client_node_id = Some(&msg)
+                    .filter(|msg| msg.priority == StoredMessagePriority::Client as i32)
+                    .and_then(stored_message_destination_node_id);
                 match self.database.insert_message_if_unique(msg).await {
                     Ok(existed) => {
                         let pub_key = public_key
                             .map(|p| format!("public key '{}'", p))
                             .or_else(|| node_id.map(|n| format!("node id '{}'", n)))
+                            .or_else(|| node_ids.map(|n| format!("node ids '{}'", n)))
                             .unwrap_or_else(|| "<Anonymous>".to_string());
                         if !existed {
                             info!(target: LOG_TARGET, "Stored message for {}", pub_key);
@@ -274,6 +616,15 @@ impl StoreAndForwardService {
                             info!(target: LOG_TARGET, "SAF message for {} already stored", pub_key);
                         }
                         let _ = reply_tx.send(Ok(existed));
+                        if let Some(client_node_id) = client_node_id.filter(|_| !existed) {
+                            if let Err(err) = self.push_to_client_if_connected(&client_node_id).await {
+                                warn!(target: LOG_TARGET, "Failed to push stored message to client: {:?}", err);
+                            }
+                        }
+                    },
+                    Err(err @ StorageError::MessageKeyCollision(_)) => {
+                        warn!(target: LOG_TARGET, "Message was not stored: {}", err);
+                        let _ = reply_tx.send(Err(err.into()));
                     },
                     Err(err) => {
                         error!(target: LOG_TARGET, "InsertMessage failed because '{:?}'", err);
@@ -285,6 +636,34 @@ impl StoreAndForwardService {
                 Ok(_) => trace!(target: LOG_TARGET, "Removed messages: {:?}", message_ids),
                 Err(err) => error!(target: LOG_TARGET, "RemoveMessage failed because '{:?}'", err),
             },
+            TakeMessages(message_ids, reply_tx) => {
+                let result = self.database.take_messages(message_ids).await;
+                if let Err(err) = result.as_ref() {
+                    error!(target: LOG_TARGET, "TakeMessages failed because '{:?}'", err);
+                }
+                let _ = reply_tx.send(result.map_err(Into::into));
+            },
+            CountMessagesForPeer(public_key, reply_tx) => {
+                let node_id = NodeId::from_public_key(&public_key);
+                let result = self
+                    .database
+                    .count_messages_for_peer(&public_key, &node_id)
+                    .await
+                    .map_err(Into::into);
+                let _ = reply_tx.send(result);
+            },
+            GetStorageSize(reply_tx) => {
+                let result = self.database.total_message_size().await.map_err(Into::into);
+                let _ = reply_tx.send(result);
+            },
+            CountMessagesByDestination(limit, reply_tx) => {
+                let result = self
+                    .database
+                    .count_messages_by_destination(limit as i64)
+                    .await
+                    .map_err(Into::into);
+                let _ = reply_tx.send(result);
+            },
             SendStoreForwardRequestToPeer(node_id) => {
                 if let Err(err) = self.request_stored_messages_from_peer(&node_id).await {
                     error!(target: LOG_TARGET, "Error sending store and forward request: {:?}", err);
@@ -298,6 +677,62 @@ impl StoreAndForwardService {
                     );
                 }
             },
+            CheckRequestRate(node_id, request_id, reply_tx) => {
+                let _ = reply_tx.send(self.check_request_rate(*node_id, request_id));
+            },
+            ProviderRejectedRequest(node_id, request_id, reason, retry_after) => {
+                if let Err(err) = self
+                    .handle_provider_rejection(*node_id, request_id, reason, retry_after)
+                    .await
+                {
+                    error!(
+                        target: LOG_TARGET,
+                        "Error handling store and forward rejection: {:?}", err
+                    );
+                }
+            },
+            GetProviderStats(reply_tx) => {
+                let _ = reply_tx.send(self.provider_stats.clone());
+            },
+            RegisterClient(public_key, reply_tx) => {
+                let _ = reply_tx.send(self.register_client(*public_key).await);
+            },
+            UnregisterClient(public_key, reply_tx) => {
+                let is_removed = self.served_clients.remove(&public_key);
+                if is_removed {
+                    info!(target: LOG_TARGET, "No longer serving client '{}'", public_key);
+                }
+                let result = if is_removed {
+                    self.persist_served_clients().await
+                } else {
+                    Ok(())
+                };
+                let _ = reply_tx.send(result.map(|_| is_removed));
+            },
+            GetServedClients(reply_tx) => {
+                let _ = reply_tx.send(self.served_clients.public_keys());
+            },
+            AddStoreFilter(filter, reply_tx) => {
+                let id = self.store_filters.add(filter);
+                debug!(target: LOG_TARGET, "Added store filter {}", id);
+                let _ = reply_tx.send(id);
+            },
+            RemoveStoreFilter(id, reply_tx) => {
+                let is_removed = self.store_filters.remove(id);
+                if is_removed {
+                    debug!(target: LOG_TARGET, "Removed store filter {}", id);
+                }
+                let _ = reply_tx.send(is_removed);
+            },
+            GetStoreFilters(reply_tx) => {
+                let _ = reply_tx.send(self.store_filters.filters());
+            },
+            AddMailboxContact(public_key, reply_tx) => {
+                let _ = reply_tx.send(self.add_mailbox_contact(*public_key));
+            },
+            RemoveMailboxContact(public_key, reply_tx) => {
+                let _ = reply_tx.send(self.mailbox_contacts.remove(&public_key));
+            },
         }
     }
 
@@ -307,6 +742,12 @@ impl StoreAndForwardService {
         #[allow(clippy::single_match)]
         match event {
             PeerConnected(conn) => {
+                if self.config.saf_client_push_on_connect {
+                    if let Some(public_key) = self.served_clients.get(conn.peer_node_id()) {
+                        self.push_messages_to_client(&public_key, conn.peer_node_id()).await?;
+                    }
+                }
+
                 if !self.config.saf_auto_request {
                     debug!(
                         target: LOG_TARGET,
@@ -315,13 +756,16 @@ impl StoreAndForwardService {
                     return Ok(());
                 }
 
-                // Whenever we connect to a peer, request SAF messages
-                let features = self.peer_manager.get_peer_features(conn.peer_node_id()).await?;
-                if features.contains(PeerFeatures::DHT_STORE_FORWARD) {
+                // Whenever we connect to a peer that stores messages, request SAF messages
+                let peer = self.peer_manager.find_by_node_id(conn.peer_node_id()).await?;
+                if SafCapability::is_provider(&peer) && !self.is_rejected_provider(&peer.node_id) {
                     info!(
                         target: LOG_TARGET,
-                        "Connected peer '{}' is a SAF node. Requesting stored messages.",
-                        conn.peer_node_id().short_str()
+                        "Connected peer '{}' is a SAF node ({}). Requesting stored messages.",
+                        conn.peer_node_id().short_str(),
+                        SafCapability::from_peer(&peer)
+                            .map(|capability| capability.to_string())
+                            .unwrap_or_else(|| "capability not advertised".to_string())
                     );
                     self.request_stored_messages_from_peer(conn.peer_node_id()).await?;
                 }
@@ -339,56 +783,467 @@ impl StoreAndForwardService {
         Ok(())
     }
 
+    async fn handle_peer_manager_event(&mut self, event: &PeerManagerEvent) -> SafResult<()> {
+        match event {
+            // Bans made by the connectivity manager, the ban list import and any other peer manager ban path
+            PeerManagerEvent::PeerBanned(node_id) => self.purge_messages_for_banned_peer(node_id).await,
+        }
+    }
+
+    async fn register_client(&mut self, public_key: CommsPublicKey) -> SafResult<bool> {
+        let node_id = NodeId::from_public_key(&public_key);
+        if !self.served_clients.insert(public_key.clone()) {
+            return Ok(false);
+        }
+        info!(target: LOG_TARGET, "Serving client '{}'", node_id.short_str());
+        self.persist_served_clients().await?;
+
+        // Deliver anything already stored for the client if it is connected
+        if let Err(err) = self.push_to_client_if_connected(&node_id).await {
+            warn!(
+                target: LOG_TARGET,
+                "Failed to push stored messages to client: {:?}", err
+            );
+        }
+        Ok(true)
+    }
+
+    async fn load_served_clients(&mut self) -> SafResult<()> {
+        let clients = self
+            .dht_requester
+            .get_metadata::<Vec<CommsPublicKey>>(DhtMetadataKey::SafServedClients)
+            .await?
+            .unwrap_or_default();
+        let num_clients = clients.len();
+        for public_key in clients {
+            self.served_clients.insert(public_key);
+        }
+        if num_clients > 0 {
+            info!(target: LOG_TARGET, "Serving {} registered client(s)", num_clients);
+        }
+        Ok(())
+    }
+
+    async fn persist_served_clients(&mut self) -> SafResult<()> {
+        self.dht_requester
+            .set_metadata(DhtMetadataKey::SafServedClients, self.served_clients.public_keys())
+            .await?;
+        Ok(())
+    }
+
+    /// Pushes stored messages to the served client with the given `NodeId` if it is currently connected
+    async fn push_to_client_if_connected(&mut self, node_id: &NodeId) -> SafResult<()> {
+        if !self.config.saf_client_push_on_connect {
+            return Ok(());
+        }
+        let public_key = match self.served_clients.get(node_id) {
+            Some(public_key) => public_key,
+            None => return Ok(()),
+        };
+        let is_connected = self
+            .connectivity
+            .get_connection(node_id.clone())
+            .await?
+            .map(|conn| conn.is_connected())
+            .unwrap_or(false);
+        if is_connected {
+            self.push_messages_to_client(&public_key, node_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends the messages stored for a served client directly to the client without waiting for a request. Messages
+    /// are removed from storage once they have been sent.
+    async fn push_messages_to_client(&mut self, public_key: &CommsPublicKey, node_id: &NodeId) -> SafResult<()> {
+        let limit = i64::try_from(self.config.saf_max_returned_messages).unwrap_or(std::i64::MAX);
+        let messages = self
+            .database
+            .find_messages_for_peer(public_key, node_id, None, limit)
+            .await?;
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let message_ids = messages.iter().map(|msg| msg.id).collect::<Vec<_>>();
+        let chunks = chunk_stored_messages(
+            message_ids,
+            try_convert_all(messages)?,
+            self.config.saf_max_response_chunk_size,
+        );
+        let mut num_pushed = 0;
+        for (message_ids, chunk) in chunks {
+            // Clients accept stored messages responses that were not requested, the request id is not used
+            let response = StoredMessagesResponse {
+                messages: chunk,
+                request_id: 0,
+                response_type: SafResponseType::ForMe as i32,
+            };
+            let result = self
+                .outbound_requester
+                .send_message_no_header(
+                    SendMessageParams::new()
+                        .direct_public_key(public_key.clone())
+                        .with_dht_message_type(DhtMessageType::SafStoredMessages)
+                        .finish(),
+                    response,
+                )
+                .await?
+                .resolve()
+                .await;
+            match result {
+                Ok(_) => {
+                    num_pushed += message_ids.len();
+                    self.database.remove_message(message_ids).await?;
+                },
+                Err(err) => {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Failed to push stored messages to client '{}': {}",
+                        node_id.short_str(),
+                        err
+                    );
+                    // Remaining messages are left in storage for the next push or request
+                    break;
+                },
+            }
+        }
+
+        info!(
+            target: LOG_TARGET,
+            "Pushed {} stored message(s) to client '{}'",
+            num_pushed,
+            node_id.short_str()
+        );
+        Ok(())
+    }
+
+    /// Removes stored messages that originate from, or are destined for, a peer that has been banned.
+    ///
+    /// Only the stored messages are removed. Messages that were read from storage before the purge are still sent:
+    /// chunks of a SAF response queued in the `SafResponseSender` and messages already passed to the outbound
+    /// pipeline. Of these, messages destined for the banned peer are never delivered because the messaging protocol
+    /// fails sends to banned peers, but messages from the banned peer are still delivered to their recipients. Copies
+    /// stored by other nodes are not affected.
+    async fn purge_messages_for_banned_peer(&mut self, node_id: &NodeId) -> SafResult<()> {
+        let peer = match self.peer_manager.find_by_node_id(node_id).await {
+            Ok(peer) => peer,
+            Err(err) if err.is_peer_not_found() => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Banned peer '{}' not found in peer manager. Not purging stored messages",
+                    node_id.short_str()
+                );
+                return Ok(());
+            },
+            Err(err) => return Err(err.into()),
+        };
+
+        let num_deleted = self
+            .database
+            .delete_messages_involving_peer(&peer.public_key, &peer.node_id)
+            .await?;
+        if num_deleted > 0 {
+            info!(
+                target: LOG_TARGET,
+                "Purged {} stored message(s) to or from banned peer '{}'",
+                num_deleted,
+                node_id.short_str()
+            );
+        }
+        Ok(())
+    }
+
     async fn request_stored_messages_from_peer(&mut self, node_id: &NodeId) -> SafResult<()> {
-        let request = self.get_saf_request().await?;
+        let requests = self.get_saf_requests_for_peer(node_id).await?;
+        self.pending_provider_requests
+            .insert(node_id.clone(), requests[0].request_id);
         info!(
             target: LOG_TARGET,
-            "Sending store and forward request to peer '{}' (Since = {:?})", node_id, request.since
+            "Sending store and forward request to peer '{}' (Since = {:?})", node_id, requests[0].since
         );
 
-        self.outbound_requester
-            .send_message_no_header(
-                SendMessageParams::new()
-                    .direct_node_id(node_id.clone())
-                    .with_dht_message_type(DhtMessageType::SafRequestMessages)
-                    .finish(),
-                request,
+        let outbound_requester = self.outbound_requester.clone();
+        let backoff = self.config.saf_request_backoff;
+        let rng = self.config.rng.clone();
+        let max_attempts = self.config.saf_request_max_attempts;
+        let node_id = node_id.clone();
+        task::spawn(async move {
+            let result = send_saf_requests(
+                outbound_requester,
+                node_id.clone(),
+                requests,
+                backoff,
+                rng,
+                max_attempts,
             )
-            .await
-            .map_err(StoreAndForwardError::RequestMessagesFailed)?;
+            .await;
+            if let Err(err) = result {
+                warn!(
+                    target: LOG_TARGET,
+                    "Failed to send store and forward request to peer '{}': {}",
+                    node_id.short_str(),
+                    err
+                );
+            }
+        });
 
         Ok(())
     }
 
+    /// Requests stored messages from the closest connected store and forward nodes concurrently. Only peers that
+    /// advertise the store and forward capability are queried.
     async fn request_stored_messages_neighbours(&mut self) -> SafResult<()> {
-        let request = self.get_saf_request().await?;
+        let providers = self.select_saf_providers().await?;
+        if providers.is_empty() {
+            debug!(
+                target: LOG_TARGET,
+                "No connected peers offer store and forward storage. Not requesting stored messages."
+            );
+            return Ok(());
+        }
+
+        let mut requests = Vec::with_capacity(providers.len());
+        for node_id in providers {
+            let peer_requests = self.get_saf_requests_for_peer(&node_id).await?;
+            self.pending_provider_requests
+                .insert(node_id.clone(), peer_requests[0].request_id);
+            requests.push((node_id, peer_requests));
+        }
         info!(
             target: LOG_TARGET,
-            "Sending store and forward request to neighbours (Since = {:?})", request.since
+            "Sending store and forward request to {} closest store and forward node(s)",
+            requests.len()
         );
-        self.outbound_requester
-            .send_message_no_header(
-                SendMessageParams::new()
-                    .broadcast(vec![])
-                    .with_dht_message_type(DhtMessageType::SafRequestMessages)
-                    .finish(),
-                request,
-            )
-            .await
-            .map_err(StoreAndForwardError::RequestMessagesFailed)?;
+
+        let outbound_requester = self.outbound_requester.clone();
+        let backoff = self.config.saf_request_backoff;
+        let rng = self.config.rng.clone();
+        let max_attempts = self.config.saf_request_max_attempts;
+        task::spawn(async move {
+            let results = future::join_all(requests.into_iter().map(|(node_id, requests)| {
+                let outbound_requester = outbound_requester.clone();
+                let rng = rng.clone();
+                async move {
+                    let result = send_saf_requests(
+                        outbound_requester,
+                        node_id.clone(),
+                        requests,
+                        backoff,
+                        rng,
+                        max_attempts,
+                    )
+                    .await;
+                    (node_id, result)
+                }
+            }))
+            .await;
+
+            for (node_id, result) in results {
+                if let Err(err) = result {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Failed to send store and forward request to peer '{}': {}",
+                        node_id.short_str(),
+                        err
+                    );
+                }
+            }
+        });
 
         Ok(())
     }
 
-    async fn get_saf_request(&mut self) -> SafResult<StoredMessagesRequest> {
-        let request = self
+    /// Returns the closest connected peers that advertise the store and forward capability, up to
+    /// `saf_num_request_nodes`
+    async fn select_saf_providers(&mut self) -> SafResult<Vec<NodeId>> {
+        let mut providers = self.find_connected_saf_providers().await?;
+        providers.truncate(self.config.saf_num_request_nodes);
+        Ok(providers)
+    }
+
+    /// Returns all connected peers that advertise the store and forward capability and have not recently rejected a
+    /// request, closest first
+    async fn find_connected_saf_providers(&mut self) -> SafResult<Vec<NodeId>> {
+        let connections = self.connectivity.get_active_connections().await?;
+        let mut providers = Vec::with_capacity(connections.len());
+        for conn in connections {
+            if !conn.peer_features().contains(PeerFeatures::DHT_STORE_FORWARD) {
+                continue;
+            }
+            if self.is_rejected_provider(conn.peer_node_id()) {
+                continue;
+            }
+            let peer = self.peer_manager.find_by_node_id(conn.peer_node_id()).await?;
+            if SafCapability::is_provider(&peer) {
+                providers.push(peer.node_id);
+            }
+        }
+
+        let node_id = self.node_identity.node_id();
+        providers.sort_by_key(|n| n.distance(node_id));
+        Ok(providers)
+    }
+
+    /// Returns true if the provider rejected a request and should not yet be sent another
+    fn is_rejected_provider(&mut self, node_id: &NodeId) -> bool {
+        let now = Instant::now();
+        self.rejected_providers.retain(|_, until| *until > now);
+        self.rejected_providers.contains_key(node_id)
+    }
+
+    async fn handle_provider_rejection(
+        &mut self,
+        node_id: NodeId,
+        request_id: u32,
+        reason: SafRejectionReason,
+        retry_after: Option<Duration>,
+    ) -> SafResult<()>
+    {
+        if self.pending_provider_requests.get(&node_id) != Some(&request_id) {
+            debug!(
+                target: LOG_TARGET,
+                "Ignoring store and forward rejection from peer '{}' for unknown request {}",
+                node_id.short_str(),
+                request_id
+            );
+            return Ok(());
+        }
+        self.pending_provider_requests.remove(&node_id);
+
+        info!(
+            target: LOG_TARGET,
+            "Store and forward node '{}' rejected request {} ({:?})",
+            node_id.short_str(),
+            request_id,
+            reason
+        );
+        self.provider_stats
+            .entry(node_id.clone())
+            .or_default()
+            .record_rejection();
+        let exclusion_period = retry_after.unwrap_or(REJECTED_PROVIDER_EXCLUSION_PERIOD);
+        self.rejected_providers
+            .insert(node_id, Instant::now() + exclusion_period);
+
+        let providers = self.find_connected_saf_providers().await?;
+        let replacement = providers
+            .into_iter()
+            .find(|node_id| !self.pending_provider_requests.contains_key(node_id));
+        match replacement {
+            Some(node_id) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Requesting stored messages from store and forward node '{}' instead",
+                    node_id.short_str()
+                );
+                self.request_stored_messages_from_peer(&node_id).await?;
+            },
+            None => {
+                debug!(
+                    target: LOG_TARGET,
+                    "No other connected store and forward nodes to request stored messages from"
+                );
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Returns the time after which the peer may request stored messages again if it has made a request within
+    /// `saf_min_request_interval`, otherwise the request is recorded and None is returned. Up to
+    /// `MAX_MAILBOX_REQUESTS` requests with the same request id are accepted, as the requester splits its mailbox tags
+    /// across that many requests.
+    fn check_request_rate(&mut self, node_id: NodeId, request_id: u32) -> Option<Duration> {
+        let min_interval = self.config.saf_min_request_interval?;
+        let now = Instant::now();
+        if let Some(last_request) = self.last_requests.get_mut(&node_id) {
+            let elapsed = now.duration_since(last_request.received_at);
+            if elapsed < min_interval {
+                if last_request.request_id == request_id && last_request.num_requests < mailbox::MAX_MAILBOX_REQUESTS {
+                    last_request.num_requests += 1;
+                    return None;
+                }
+                return Some(min_interval - elapsed);
+            }
+        }
+        self.last_requests
+            .retain(|_, last_request| now.duration_since(last_request.received_at) < min_interval);
+        self.last_requests.insert(node_id, ReceivedRequest {
+            received_at: now,
+            request_id,
+            num_requests: 1,
+        });
+        None
+    }
+
+    fn add_mailbox_contact(&mut self, public_key: CommsPublicKey) -> SafResult<bool> {
+        if self.mailbox_contacts.contains(&public_key) {
+            return Ok(false);
+        }
+        if self.mailbox_contacts.len() >= mailbox::MAX_MAILBOX_CONTACTS {
+            return Err(StoreAndForwardError::TooManyMailboxContacts);
+        }
+        Ok(self.mailbox_contacts.insert(public_key))
+    }
+
+    /// Returns the requests for stored messages to send to the given peer. The first request is for all messages for
+    /// this node. The mailbox tags are split across it and any follow-up requests, which have the same request id and
+    /// only query mailbox tags.
+    async fn get_saf_requests_for_peer(&mut self, node_id: &NodeId) -> SafResult<Vec<StoredMessagesRequest>> {
+        let since = self.get_saf_request_since().await?;
+        let last_retrieval = self.dht_requester.get_last_saf_retrieval(node_id.clone()).await?;
+        // Messages stored by this peer before we last retrieved from it have already been received
+        let since = match (since, last_retrieval) {
+            (Some(since), Some(last_retrieval)) => Some(cmp::max(since, last_retrieval)),
+            (since, last_retrieval) => since.or(last_retrieval),
+        };
+
+        let mut request = since
+            .map(StoredMessagesRequest::since)
+            .unwrap_or_else(StoredMessagesRequest::new);
+
+        let mut tag_batches = self.get_mailbox_tags(since).into_iter();
+        request.mailbox_tags = tag_batches.next().unwrap_or_default();
+        let follow_up_requests = tag_batches
+            .map(|mailbox_tags| StoredMessagesRequest {
+                since: request.since.clone(),
+                request_id: request.request_id,
+                mailbox_tags,
+                mailbox_only: true,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(iter::once(request).chain(follow_up_requests).collect())
+    }
+
+    /// Returns the mailbox tags shared with each mailbox contact covering every epoch since `since`, or since the
+    /// minimum request period if `since` is not given, in batches of at most `MAX_MAILBOX_TAGS_PER_REQUEST`
+    fn get_mailbox_tags(&self, since: Option<DateTime<Utc>>) -> Vec<Vec<Vec<u8>>> {
+        let since = since.unwrap_or_else(|| since_utc(self.config.saf_minimum_request_period));
+        let contacts = self.mailbox_contacts.iter().cloned().collect::<Vec<_>>();
+        let tags = mailbox::mailbox_tags_since(self.node_identity.secret_key(), &contacts, since);
+        if tags.num_omitted_epochs > 0 {
+            warn!(
+                target: LOG_TARGET,
+                "Not requesting mailbox messages from the oldest {} day(s) since {}. Tags for {} mailbox contact(s) \
+                 over that period do not fit in {} requests.",
+                tags.num_omitted_epochs,
+                since,
+                contacts.len(),
+                mailbox::MAX_MAILBOX_REQUESTS
+            );
+        }
+        tags.batches
+    }
+
+    async fn get_saf_request_since(&mut self) -> SafResult<Option<DateTime<Utc>>> {
+        let since = self
             .dht_requester
             .get_metadata(DhtMetadataKey::OfflineTimestamp)
             .await?
-            .map(|t| StoredMessagesRequest::since(cmp::min(t, since_utc(self.config.saf_minimum_request_period))))
-            .unwrap_or_else(StoredMessagesRequest::new);
+            .map(|t| cmp::min(t, since_utc(self.config.saf_minimum_request_period)));
 
-        Ok(request)
+        Ok(since)
     }
 
     fn check_saf_response_threshold(&mut self) {
@@ -435,6 +1290,10 @@ impl StoreAndForwardService {
                     .await?
             },
             Anonymous => db.find_anonymous_messages(query.since, limit).await?,
+            Mailbox => {
+                db.find_messages_by_mailbox_tags(query.mailbox_tags, query.since, limit)
+                    .await?
+            },
         };
 
         Ok(messages)
@@ -459,6 +1318,15 @@ impl StoreAndForwardService {
             .await?;
         debug!(target: LOG_TARGET, "Cleaned {} old high priority messages", num_removed);
 
+        let num_removed = self
+            .database
+            .delete_messages_with_priority_older_than(
+                StoredMessagePriority::Client,
+                since(self.config.saf_client_msg_storage_ttl),
+            )
+            .await?;
+        debug!(target: LOG_TARGET, "Cleaned {} old client messages", num_removed);
+
         let num_removed = self
             .database
             .truncate_messages(self.config.saf_msg_storage_capacity)
@@ -473,6 +1341,88 @@ impl StoreAndForwardService {
         Ok(())
     }
 
+    /// Propagates stored messages whose destination is no longer within this node's network region toward their
+    /// destination and removes them from the store. Nodes that are now closer to the destination will store the
+    /// message when they receive it, keeping stored messages near their recipients as the network changes.
+    async fn handoff_messages_outside_region(&mut self) -> SafResult<()> {
+        let node_id = self.node_identity.node_id().clone();
+        let n = self.config.num_neighbouring_nodes;
+        let node_threshold = self
+            .peer_manager
+            .calc_region_threshold(&node_id, n, PeerFeatures::COMMUNICATION_NODE)
+            .await?;
+        let client_threshold = self
+            .peer_manager
+            .calc_region_threshold(&node_id, n, PeerFeatures::COMMUNICATION_CLIENT)
+            .await?;
+        let limit = i64::try_from(self.config.saf_max_returned_messages).unwrap_or(std::i64::MAX);
+
+        let mut handed_off = Vec::new();
+        let mut after_id = 0;
+        loop {
+            let messages = self.database.find_messages_with_destination(after_id, limit).await?;
+            after_id = match messages.last() {
+                Some(message) => message.id,
+                None => break,
+            };
+
+            for message in messages {
+                let dht_header = match decode_stored_header(&message.header) {
+                    Ok(header) => header,
+                    Err(err) => {
+                        debug!(
+                            target: LOG_TARGET,
+                            "Stored message {} has an invalid header: {:?}", message.id, err
+                        );
+                        continue;
+                    },
+                };
+                let dest_node_id = match dht_header.destination.to_derived_node_id() {
+                    Some(dest_node_id) => dest_node_id,
+                    None => continue,
+                };
+                let distance = node_id.distance(&dest_node_id);
+                if distance <= node_threshold || distance <= client_threshold {
+                    continue;
+                }
+
+                let mut send_params = SendMessageParams::new();
+                send_params
+                    .propagate(dht_header.destination.clone(), vec![])
+                    .with_dht_header(dht_header);
+                let result = self
+                    .outbound_requester
+                    .send_raw(send_params.finish(), message.body)
+                    .await?
+                    .resolve()
+                    .await;
+                match result {
+                    Ok(_) => handed_off.push(message.id),
+                    Err(err) => {
+                        debug!(
+                            target: LOG_TARGET,
+                            "Unable to hand off stored message {} for '{}': {:?}",
+                            message.id,
+                            dest_node_id.short_str(),
+                            err
+                        );
+                    },
+                }
+            }
+        }
+
+        if !handed_off.is_empty() {
+            info!(
+                target: LOG_TARGET,
+                "Handed off {} stored message(s) whose destination is no longer in this node's region",
+                handed_off.len()
+            );
+            self.database.remove_message(handed_off).await?;
+        }
+
+        Ok(())
+    }
+
     fn publish_event(&mut self, event: DhtEvent) {
         let _ = self.event_publisher.send(Arc::new(event)).map_err(|_| {
             trace!(
@@ -483,6 +1433,95 @@ impl StoreAndForwardService {
     }
 }
 
+/// Sends a request for stored messages directly to the given peer, retrying with the given backoff until the request
+/// is sent or `max_attempts` is reached. `rng` is used to apply jitter to the backoff.
+/// Sends the requests to the peer in order, stopping at the first request that could not be sent
+async fn send_saf_requests(
+    outbound_requester: OutboundMessageRequester,
+    node_id: NodeId,
+    requests: Vec<StoredMessagesRequest>,
+    backoff: BackoffPolicy,
+    rng: SharedRng,
+    max_attempts: usize,
+) -> SafResult<()>
+{
+    for request in requests {
+        send_saf_request(
+            outbound_requester.clone(),
+            node_id.clone(),
+            request,
+            backoff,
+            rng.clone(),
+            max_attempts,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn send_saf_request(
+    mut outbound_requester: OutboundMessageRequester,
+    node_id: NodeId,
+    request: StoredMessagesRequest,
+    backoff: BackoffPolicy,
+    mut rng: SharedRng,
+    max_attempts: usize,
+) -> SafResult<()>
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        time::delay_for(backoff.calculate_backoff_with_rng(attempts, &mut rng)).await;
+
+        let response = outbound_requester
+            .send_message_no_header(
+                SendMessageParams::new()
+                    .direct_node_id(node_id.clone())
+                    .with_dht_message_type(DhtMessageType::SafRequestMessages)
+                    .finish(),
+                request.clone(),
+            )
+            .await
+            .map_err(StoreAndForwardError::RequestMessagesFailed)?;
+
+        let err = match response.resolve().await {
+            Ok(send_states) => {
+                if send_states.wait_single().await {
+                    return Ok(());
+                }
+                SendFailure::General("the request was not sent".to_string())
+            },
+            Err(err) => err,
+        };
+
+        if attempts >= max_attempts {
+            return Err(StoreAndForwardError::RequestMessagesFailed(DhtOutboundError::from(err)));
+        }
+        debug!(
+            target: LOG_TARGET,
+            "Failed to send store and forward request to peer '{}' (attempt {} of {}): {}. Retrying...",
+            node_id.short_str(),
+            attempts,
+            max_attempts,
+            err
+        );
+    }
+}
+
+/// Returns the `NodeId` of the destination of a stored message, if it has one
+fn stored_message_destination_node_id(msg: &NewStoredMessage) -> Option<NodeId> {
+    msg.destination_pubkey
+        .as_ref()
+        .and_then(|pk| CommsPublicKey::from_hex(pk).ok())
+        .map(|pk| NodeId::from_public_key(&pk))
+        .or_else(|| msg.destination_node_id.as_ref().and_then(|n| NodeId::from_hex(n).ok()))
+}
+
+fn decode_stored_header(header: &[u8]) -> SafResult<DhtMessageHeader> {
+    let header = DhtHeader::decode(header)?;
+    Ok(DhtMessageHeader::try_from(header)?)
+}
+
 fn since(period: Duration) -> NaiveDateTime {
     use chrono::Duration as OldDuration;
     let period = OldDuration::from_std(period).expect("period was out of range for chrono::Duration");
@@ -23,9 +23,12 @@
 use super::StoreAndForwardRequester;
 use crate::{
     inbound::DecryptedDhtMessage,
+    storage::StorageError,
     store_forward::{
+        clients::ServedClients,
         database::NewStoredMessage,
         error::StoreAndForwardError,
+        filter::SafStoreFilters,
         message::StoredMessagePriority,
         SafResult,
     },
@@ -35,7 +38,7 @@ use futures::{task::Context, Future};
 use log::*;
 use std::{sync::Arc, task::Poll};
 use tari_comms::{
-    peer_manager::{NodeIdentity, PeerFeatures, PeerManager},
+    peer_manager::{NodeId, NodeIdentity, Peer, PeerFeatures, PeerManager, PeerManagerError},
     pipeline::PipelineError,
 };
 use tari_utilities::epoch_time::EpochTime;
@@ -49,6 +52,8 @@ pub struct StoreLayer {
     config: DhtConfig,
     node_identity: Arc<NodeIdentity>,
     saf_requester: StoreAndForwardRequester,
+    served_clients: ServedClients,
+    store_filters: SafStoreFilters,
 }
 
 impl StoreLayer {
@@ -57,6 +62,8 @@ impl StoreLayer {
         peer_manager: Arc<PeerManager>,
         node_identity: Arc<NodeIdentity>,
         saf_requester: StoreAndForwardRequester,
+        served_clients: ServedClients,
+        store_filters: SafStoreFilters,
     ) -> Self
     {
         Self {
@@ -64,6 +71,8 @@ impl StoreLayer {
             config,
             node_identity,
             saf_requester,
+            served_clients,
+            store_filters,
         }
     }
 }
@@ -78,6 +87,8 @@ impl<S> Layer<S> for StoreLayer {
             Arc::clone(&self.peer_manager),
             Arc::clone(&self.node_identity),
             self.saf_requester.clone(),
+            self.served_clients.clone(),
+            self.store_filters.clone(),
         )
     }
 }
@@ -89,6 +100,8 @@ pub struct StoreMiddleware<S> {
     peer_manager: Arc<PeerManager>,
     node_identity: Arc<NodeIdentity>,
     saf_requester: StoreAndForwardRequester,
+    served_clients: ServedClients,
+    store_filters: SafStoreFilters,
 }
 
 impl<S> StoreMiddleware<S> {
@@ -98,6 +111,8 @@ impl<S> StoreMiddleware<S> {
         peer_manager: Arc<PeerManager>,
         node_identity: Arc<NodeIdentity>,
         saf_requester: StoreAndForwardRequester,
+        served_clients: ServedClients,
+        store_filters: SafStoreFilters,
     ) -> Self
     {
         Self {
@@ -106,6 +121,8 @@ impl<S> StoreMiddleware<S> {
             peer_manager,
             node_identity,
             saf_requester,
+            served_clients,
+            store_filters,
         }
     }
 }
@@ -129,6 +146,8 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError> + Cl
             Arc::clone(&self.peer_manager),
             Arc::clone(&self.node_identity),
             self.saf_requester.clone(),
+            self.served_clients.clone(),
+            self.store_filters.clone(),
         )
         .handle(msg)
     }
@@ -142,6 +161,8 @@ struct StoreTask<S> {
     config: DhtConfig,
     node_identity: Arc<NodeIdentity>,
     saf_requester: StoreAndForwardRequester,
+    served_clients: ServedClients,
+    store_filters: SafStoreFilters,
 }
 
 impl<S> StoreTask<S> {
@@ -151,6 +172,8 @@ impl<S> StoreTask<S> {
         peer_manager: Arc<PeerManager>,
         node_identity: Arc<NodeIdentity>,
         saf_requester: StoreAndForwardRequester,
+        served_clients: ServedClients,
+        store_filters: SafStoreFilters,
     ) -> Self
     {
         Self {
@@ -158,6 +181,8 @@ impl<S> StoreTask<S> {
             peer_manager,
             node_identity,
             saf_requester,
+            served_clients,
+            store_filters,
             next_service,
         }
     }
@@ -171,10 +196,11 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
     /// The criteria for storing a message is:
     /// 1. Messages MUST have a message origin set and be encrypted (Join messages are the exception)
     /// 1. Unencrypted Join messages - this increases the knowledge the network has of peers (Low priority)
-    /// 1. Encrypted Discovery messages - so that nodes are aware of other nodes that are looking for them (High
-    /// priority) 1. Encrypted messages addressed to the neighbourhood - some node in the neighbourhood may be
-    /// interested in this message (High priority) 1. Encrypted messages addressed to a particular public key or
-    /// node id that this node knows about
+    /// 1. Encrypted Discovery messages - so that offline nodes can respond to other nodes that were looking for them
+    /// once they reconnect (High priority)
+    /// 1. Encrypted messages addressed to the neighbourhood - some node in the neighbourhood may be interested in this
+    /// message (High priority)
+    /// 1. Encrypted messages addressed to a particular public key or node id that this node knows about
     async fn handle(mut self, mut message: DecryptedDhtMessage) -> Result<(), PipelineError> {
         if !self.node_identity.features().contains(PeerFeatures::DHT_STORE_FORWARD) {
             trace!(
@@ -188,10 +214,20 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
         }
 
         message.set_saf_stored(false);
-        if let Some(priority) = self.get_storage_priority(&message).await? {
-            message.set_saf_stored(true);
-            let existing = self.store(priority, message.clone()).await?;
-            message.set_already_forwarded(existing);
+        let priority = self
+            .get_storage_priority(&message)
+            .await?
+            .filter(|priority| self.is_wanted(*priority, &message));
+        if let Some(priority) = priority {
+            match self.store(priority, message.clone()).await {
+                Ok(existing) => {
+                    message.set_saf_stored(true);
+                    message.set_already_forwarded(existing);
+                },
+                // A different message with the same key is stored. This message is not stored but is still passed on.
+                Err(StoreAndForwardError::StorageError(StorageError::MessageKeyCollision(_))) => {},
+                Err(err) => return Err(err.into()),
+            }
         }
 
         trace!(
@@ -205,6 +241,22 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
         Ok(())
     }
 
+    /// Messages for served clients are always stored, other messages must match the registered store filters
+    fn is_wanted(&self, priority: StoredMessagePriority, message: &DecryptedDhtMessage) -> bool {
+        if matches!(priority, StoredMessagePriority::Client) || self.store_filters.is_interested(message) {
+            return true;
+        }
+        debug!(
+            target: LOG_TARGET,
+            "Message {} from peer '{}' not eligible for SAF storage because it does not match any store filter \
+             (Trace: {})",
+            message.tag,
+            message.source_peer.node_id.short_str(),
+            message.dht_header.message_tag
+        );
+        false
+    }
+
     async fn get_storage_priority(&self, message: &DecryptedDhtMessage) -> SafResult<Option<StoredMessagePriority>> {
         let log_not_eligible = |reason: &str| {
             debug!(
@@ -217,6 +269,11 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             );
         };
 
+        if message.source_peer.trust_level().is_untrusted() {
+            log_not_eligible("it was sent by an untrusted peer");
+            return Ok(None);
+        }
+
         if message.body_len() > self.config.saf_max_message_size {
             log_not_eligible(&format!(
                 "the message body exceeded the maximum storage size (body size={}, max={})",
@@ -236,11 +293,6 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             return Ok(None);
         }
 
-        if message.dht_header.message_type.is_dht_discovery() {
-            log_not_eligible("it is a discovery message");
-            return Ok(None);
-        }
-
         if message
             .authenticated_origin()
             .map(|pk| pk == self.node_identity.public_key())
@@ -362,17 +414,42 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
             return Ok(None);
         }
 
-        // TODO: Remove this once we have a layer that filters out all messages to/from banned peers
         if let Some(origin_pk) = message.authenticated_origin() {
-            if let Ok(peer) = self.peer_manager.find_by_public_key(origin_pk).await {
-                if peer.is_banned() {
-                    log_not_eligible("source peer is banned by this node");
-                    return Ok(None);
-                }
+            let origin_peer = optional_peer(peer_manager.find_by_public_key(origin_pk).await)?;
+            if origin_peer.map(|p| p.is_banned()).unwrap_or(false) {
+                log_not_eligible("the origin peer is banned by this node");
+                return Ok(None);
             }
         }
 
-        match message.dht_header.destination.to_derived_node_id() {
+        if let Some(node_ids) = message.dht_header.destination.node_ids() {
+            let priority = self.get_priority_for_node_ids(node_ids).await?;
+            if priority.is_none() {
+                log_not_eligible("none of the multicast destinations are eligible");
+            }
+            return Ok(priority);
+        }
+
+        let dest_node_id = message.dht_header.destination.to_derived_node_id();
+        let dest_peer = match dest_node_id.as_ref() {
+            Some(dest_node_id) => optional_peer(peer_manager.find_by_node_id(dest_node_id).await)?,
+            None => None,
+        };
+        if dest_peer.as_ref().map(|p| p.is_banned()).unwrap_or(false) {
+            log_not_eligible("the destination peer is banned by this node");
+            return Ok(None);
+        }
+
+        // Messages for clients that this node serves are kept regardless of the client's network region
+        if dest_node_id
+            .as_ref()
+            .map(|node_id| self.served_clients.contains(node_id))
+            .unwrap_or(false)
+        {
+            return Ok(Some(StoredMessagePriority::Client));
+        }
+
+        match dest_node_id {
             // No destination provided,
             None => {
                 if message.dht_header.message_type.is_dht_discovery() {
@@ -394,23 +471,54 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
                     return Ok(None);
                 }
 
-                match peer_manager.find_by_node_id(&dest_node_id).await {
-                    Ok(peer) if peer.is_banned() => {
-                        log_not_eligible("destination peer is banned.");
-                        Ok(None)
-                    },
+                match dest_peer {
                     // We know the peer, they aren't banned and they are in our network region, keep the message for
                     // them
-                    Ok(_) => Ok(Some(StoredMessagePriority::High)),
+                    Some(_) => Ok(Some(StoredMessagePriority::High)),
                     // We don't know this peer, let's keep the message for a short while (default: 6 hours) because they
                     // are in our neighbourhood.
-                    Err(err) if err.is_peer_not_found() => Ok(Some(StoredMessagePriority::Low)),
-                    Err(err) => Err(err.into()),
+                    None => Ok(Some(StoredMessagePriority::Low)),
                 }
             },
         }
     }
 
+    /// A multicast message is stored if any of its destinations, other than this node, is a served client or is an
+    /// unbanned peer in this node's network region. The highest priority of the eligible destinations is returned.
+    async fn get_priority_for_node_ids(&self, node_ids: &[NodeId]) -> SafResult<Option<StoredMessagePriority>> {
+        let node_identity = &self.node_identity;
+        let mut highest_priority = None;
+        for node_id in node_ids.iter().filter(|node_id| *node_id != node_identity.node_id()) {
+            let dest_peer = optional_peer(self.peer_manager.find_by_node_id(node_id).await)?;
+            if dest_peer.as_ref().map(|p| p.is_banned()).unwrap_or(false) {
+                continue;
+            }
+
+            let priority = if self.served_clients.contains(node_id) {
+                StoredMessagePriority::Client
+            } else if self
+                .peer_manager
+                .in_network_region(node_id, node_identity.node_id(), self.config.num_neighbouring_nodes)
+                .await?
+            {
+                if dest_peer.is_some() {
+                    StoredMessagePriority::High
+                } else {
+                    StoredMessagePriority::Low
+                }
+            } else {
+                continue;
+            };
+
+            highest_priority = match highest_priority {
+                Some(highest) if highest as i32 >= priority as i32 => Some(highest),
+                _ => Some(priority),
+            };
+        }
+
+        Ok(highest_priority)
+    }
+
     async fn store(&mut self, priority: StoredMessagePriority, message: DecryptedDhtMessage) -> SafResult<bool> {
         debug!(
             target: LOG_TARGET,
@@ -433,11 +541,21 @@ where S: Service<DecryptedDhtMessage, Response = (), Error = PipelineError>
     }
 }
 
+/// Converts a peer lookup result into `Ok(None)` if the peer is not known
+fn optional_peer(lookup: Result<Peer, PeerManagerError>) -> SafResult<Option<Peer>> {
+    match lookup {
+        Ok(peer) => Ok(Some(peer)),
+        Err(err) if err.is_peer_not_found() => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::{
-        envelope::{DhtMessageFlags, NodeDestination},
+        envelope::{DhtMessageFlags, DhtMessageType, NodeDestination},
+        store_forward::SafStoreFilter,
         test_utils::{
             build_peer_manager,
             create_store_and_forward_mock,
@@ -448,7 +566,7 @@ mod test {
     };
     use chrono::Utc;
     use std::time::Duration;
-    use tari_comms::wrap_in_envelope_body;
+    use tari_comms::{peer_manager::PeerTrustLevel, wrap_in_envelope_body};
     use tari_test_utils::async_assert_eventually;
     use tari_utilities::hex::Hex;
 
@@ -459,8 +577,15 @@ mod test {
         let spy = service_spy();
         let peer_manager = build_peer_manager();
         let node_identity = make_node_identity();
-        let mut service = StoreLayer::new(Default::default(), peer_manager, node_identity, requester)
-            .layer(spy.to_service::<PipelineError>());
+        let mut service = StoreLayer::new(
+            Default::default(),
+            peer_manager,
+            node_identity,
+            requester,
+            ServedClients::new(),
+            SafStoreFilters::new(),
+        )
+        .layer(spy.to_service::<PipelineError>());
 
         let inbound_msg =
             make_dht_inbound_message(&make_node_identity(), b"".to_vec(), DhtMessageFlags::empty(), false);
@@ -478,8 +603,15 @@ mod test {
         let spy = service_spy();
         let peer_manager = build_peer_manager();
         let node_identity = make_node_identity();
-        let mut service = StoreLayer::new(Default::default(), peer_manager, node_identity, requester)
-            .layer(spy.to_service::<PipelineError>());
+        let mut service = StoreLayer::new(
+            Default::default(),
+            peer_manager,
+            node_identity,
+            requester,
+            ServedClients::new(),
+            SafStoreFilters::new(),
+        )
+        .layer(spy.to_service::<PipelineError>());
 
         let msg_node_identity = make_node_identity();
         let inbound_msg = make_dht_inbound_message(
@@ -507,8 +639,15 @@ mod test {
         let origin_node_identity = make_node_identity();
         peer_manager.add_peer(origin_node_identity.to_peer()).await.unwrap();
         let node_identity = make_node_identity();
-        let mut service = StoreLayer::new(Default::default(), peer_manager, node_identity, requester)
-            .layer(spy.to_service::<PipelineError>());
+        let mut service = StoreLayer::new(
+            Default::default(),
+            peer_manager,
+            node_identity,
+            requester,
+            ServedClients::new(),
+            SafStoreFilters::new(),
+        )
+        .layer(spy.to_service::<PipelineError>());
 
         let mut inbound_msg = make_dht_inbound_message(
             &origin_node_identity,
@@ -538,6 +677,100 @@ mod test {
         assert!(duration.num_seconds() <= 5);
     }
 
+    #[tokio_macros::test_basic]
+    async fn discovery_message_should_store() {
+        let (requester, mock_state) = create_store_and_forward_mock();
+        let spy = service_spy();
+        let peer_manager = build_peer_manager();
+        let target_node_identity = make_node_identity();
+        peer_manager.add_peer(target_node_identity.to_peer()).await.unwrap();
+        let mut service = StoreLayer::new(
+            Default::default(),
+            peer_manager,
+            make_node_identity(),
+            requester,
+            ServedClients::new(),
+            SafStoreFilters::new(),
+        )
+        .layer(spy.to_service::<PipelineError>());
+
+        let mut inbound_msg = make_dht_inbound_message(
+            &target_node_identity,
+            b"Where are you?".to_vec(),
+            DhtMessageFlags::ENCRYPTED,
+            true,
+        );
+        inbound_msg.dht_header.message_type = DhtMessageType::Discovery;
+        inbound_msg.dht_header.destination =
+            NodeDestination::PublicKey(Box::new(target_node_identity.public_key().clone()));
+        service.call(DecryptedDhtMessage::failed(inbound_msg)).await.unwrap();
+        assert_eq!(spy.is_called(), true);
+
+        async_assert_eventually!(
+            mock_state.call_count(),
+            expect = 1,
+            max_attempts = 10,
+            interval = Duration::from_millis(10),
+        );
+
+        let message = mock_state.get_messages().await.remove(0);
+        assert_eq!(message.message_type, DhtMessageType::Discovery as i32);
+        assert_eq!(message.priority, StoredMessagePriority::High as i32);
+        assert_eq!(
+            message.destination_pubkey.unwrap(),
+            target_node_identity.public_key().to_hex()
+        );
+    }
+
+    #[tokio_macros::test_basic]
+    async fn multicast_message_should_store() {
+        let (requester, mock_state) = create_store_and_forward_mock();
+        let spy = service_spy();
+        let peer_manager = build_peer_manager();
+        let node_identity = make_node_identity();
+        let origin_node_identity = make_node_identity();
+        let known_node_identity = make_node_identity();
+        peer_manager.add_peer(known_node_identity.to_peer()).await.unwrap();
+        let mut service = StoreLayer::new(
+            Default::default(),
+            peer_manager,
+            node_identity.clone(),
+            requester,
+            ServedClients::new(),
+            SafStoreFilters::new(),
+        )
+        .layer(spy.to_service::<PipelineError>());
+
+        let mut inbound_msg = make_dht_inbound_message(
+            &origin_node_identity,
+            b"Will you keep this for us?".to_vec(),
+            DhtMessageFlags::ENCRYPTED,
+            true,
+        );
+        inbound_msg.dht_header.destination = NodeDestination::multicast(vec![
+            node_identity.node_id().clone(),
+            known_node_identity.node_id().clone(),
+        ])
+        .unwrap();
+        service.call(DecryptedDhtMessage::failed(inbound_msg)).await.unwrap();
+        assert!(spy.is_called());
+
+        async_assert_eventually!(
+            mock_state.call_count(),
+            expect = 1,
+            max_attempts = 10,
+            interval = Duration::from_millis(10),
+        );
+
+        let message = mock_state.get_messages().await.remove(0);
+        assert_eq!(message.priority, StoredMessagePriority::High as i32);
+        assert!(message.destination_pubkey.is_none());
+        assert!(message.destination_node_id.is_none());
+        let destination_node_ids = message.destination_node_ids.unwrap();
+        assert!(destination_node_ids.contains(&node_identity.node_id().to_hex()));
+        assert!(destination_node_ids.contains(&known_node_identity.node_id().to_hex()));
+    }
+
     #[tokio_macros::test_basic]
     async fn decryption_failed_banned_peer() {
         let (requester, mock_state) = create_store_and_forward_mock();
@@ -548,8 +781,15 @@ mod test {
         peer.ban_for(Duration::from_secs(1_000_000 /* 🧏 */), "for being evil".to_string());
         peer_manager.add_peer(peer).await.unwrap();
         let node_identity = make_node_identity();
-        let mut service = StoreLayer::new(Default::default(), peer_manager, node_identity, requester)
-            .layer(spy.to_service::<PipelineError>());
+        let mut service = StoreLayer::new(
+            Default::default(),
+            peer_manager,
+            node_identity,
+            requester,
+            ServedClients::new(),
+            SafStoreFilters::new(),
+        )
+        .layer(spy.to_service::<PipelineError>());
 
         let mut inbound_msg = make_dht_inbound_message(
             &origin_node_identity,
@@ -567,4 +807,155 @@ mod test {
         let messages = mock_state.get_messages().await;
         assert!(messages.is_empty());
     }
+
+    #[tokio_macros::test_basic]
+    async fn decryption_failed_untrusted_source_peer() {
+        let (requester, mock_state) = create_store_and_forward_mock();
+        let spy = service_spy();
+        let peer_manager = build_peer_manager();
+        let origin_node_identity = make_node_identity();
+        peer_manager.add_peer(origin_node_identity.to_peer()).await.unwrap();
+        let node_identity = make_node_identity();
+        let mut service = StoreLayer::new(
+            Default::default(),
+            peer_manager,
+            node_identity,
+            requester,
+            ServedClients::new(),
+            SafStoreFilters::new(),
+        )
+        .layer(spy.to_service::<PipelineError>());
+
+        let mut inbound_msg = make_dht_inbound_message(
+            &origin_node_identity,
+            b"Will you keep this for me?".to_vec(),
+            DhtMessageFlags::ENCRYPTED,
+            true,
+        );
+        inbound_msg.dht_header.destination =
+            NodeDestination::PublicKey(Box::new(origin_node_identity.public_key().clone()));
+        let mut source_peer = Clone::clone(&*inbound_msg.source_peer);
+        source_peer.set_trust_level(PeerTrustLevel::Untrusted);
+        inbound_msg.source_peer = Arc::new(source_peer);
+        service.call(DecryptedDhtMessage::failed(inbound_msg)).await.unwrap();
+        assert_eq!(spy.is_called(), true);
+
+        assert_eq!(mock_state.call_count(), 0);
+    }
+
+    #[tokio_macros::test_basic]
+    async fn decryption_failed_banned_destination() {
+        let (requester, mock_state) = create_store_and_forward_mock();
+        let spy = service_spy();
+        let peer_manager = build_peer_manager();
+        let node_identity = make_node_identity();
+        let dest_node_identity = make_node_identity();
+        let mut peer = dest_node_identity.to_peer();
+        peer.ban_for(Duration::from_secs(60 * 60), "misbehaving".to_string());
+        peer_manager.add_peer(peer).await.unwrap();
+        let mut service = StoreLayer::new(
+            Default::default(),
+            peer_manager,
+            node_identity,
+            requester,
+            ServedClients::new(),
+            SafStoreFilters::new(),
+        )
+        .layer(spy.to_service::<PipelineError>());
+
+        let origin_node_identity = make_node_identity();
+        let mut inbound_msg = make_dht_inbound_message(
+            &origin_node_identity,
+            b"Please deliver this".to_vec(),
+            DhtMessageFlags::ENCRYPTED,
+            true,
+        );
+        inbound_msg.dht_header.destination =
+            NodeDestination::PublicKey(Box::new(dest_node_identity.public_key().clone()));
+        service.call(DecryptedDhtMessage::failed(inbound_msg)).await.unwrap();
+        assert_eq!(spy.is_called(), true);
+
+        assert_eq!(mock_state.call_count(), 0);
+        assert!(mock_state.get_messages().await.is_empty());
+    }
+
+    #[tokio_macros::test_basic]
+    async fn decryption_failed_served_client() {
+        let (requester, mock_state) = create_store_and_forward_mock();
+        let spy = service_spy();
+        let peer_manager = build_peer_manager();
+        let node_identity = make_node_identity();
+        // The client is not a known peer and therefore would not otherwise be considered in this node's region
+        let client_node_identity = make_node_identity();
+        let served_clients = ServedClients::new();
+        served_clients.insert(client_node_identity.public_key().clone());
+        let mut service = StoreLayer::new(
+            Default::default(),
+            peer_manager,
+            node_identity,
+            requester,
+            served_clients,
+            SafStoreFilters::new(),
+        )
+        .layer(spy.to_service::<PipelineError>());
+
+        let origin_node_identity = make_node_identity();
+        let mut inbound_msg = make_dht_inbound_message(
+            &origin_node_identity,
+            b"For my client".to_vec(),
+            DhtMessageFlags::ENCRYPTED,
+            true,
+        );
+        inbound_msg.dht_header.destination =
+            NodeDestination::PublicKey(Box::new(client_node_identity.public_key().clone()));
+        service.call(DecryptedDhtMessage::failed(inbound_msg)).await.unwrap();
+        assert_eq!(spy.is_called(), true);
+
+        async_assert_eventually!(
+            mock_state.call_count(),
+            expect = 1,
+            max_attempts = 10,
+            interval = Duration::from_millis(10),
+        );
+        let message = mock_state.get_messages().await.remove(0);
+        assert_eq!(message.priority, StoredMessagePriority::Client as i32);
+        assert_eq!(
+            message.destination_pubkey.unwrap(),
+            client_node_identity.public_key().to_hex()
+        );
+    }
+
+    #[tokio_macros::test_basic]
+    async fn decryption_failed_not_matching_store_filter() {
+        let (requester, mock_state) = create_store_and_forward_mock();
+        let spy = service_spy();
+        let peer_manager = build_peer_manager();
+        let origin_node_identity = make_node_identity();
+        peer_manager.add_peer(origin_node_identity.to_peer()).await.unwrap();
+        let node_identity = make_node_identity();
+        let store_filters = SafStoreFilters::new();
+        store_filters.add(SafStoreFilter::new().with_message_type(DhtMessageType::Discovery));
+        let mut service = StoreLayer::new(
+            Default::default(),
+            peer_manager,
+            node_identity,
+            requester,
+            ServedClients::new(),
+            store_filters,
+        )
+        .layer(spy.to_service::<PipelineError>());
+
+        let mut inbound_msg = make_dht_inbound_message(
+            &origin_node_identity,
+            b"Will you keep this for me?".to_vec(),
+            DhtMessageFlags::ENCRYPTED,
+            true,
+        );
+        inbound_msg.dht_header.destination =
+            NodeDestination::PublicKey(Box::new(origin_node_identity.public_key().clone()));
+        service.call(DecryptedDhtMessage::failed(inbound_msg)).await.unwrap();
+        assert_eq!(spy.is_called(), true);
+
+        assert_eq!(mock_state.call_count(), 0);
+    }
 }
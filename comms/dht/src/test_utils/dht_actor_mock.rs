@@ -23,8 +23,10 @@
 
 use crate::{
     actor::{DhtRequest, DhtRequester},
+    discovery::DiscoveryStats,
     storage::DhtMetadataKey,
 };
+use chrono::{DateTime, Utc};
 use futures::{channel::mpsc, stream::Fuse, StreamExt};
 use std::{
     collections::HashMap,
@@ -34,7 +36,7 @@ use std::{
         RwLock,
     },
 };
-use tari_comms::peer_manager::Peer;
+use tari_comms::peer_manager::{NodeId, Peer};
 use tokio::task;
 
 pub fn create_dht_actor_mock(buf_size: usize) -> (DhtRequester, DhtActorMock) {
@@ -48,6 +50,8 @@ pub struct DhtMockState {
     call_count: Arc<AtomicUsize>,
     select_peers: Arc<RwLock<Vec<Peer>>>,
     settings: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+    saf_retrievals: Arc<RwLock<HashMap<NodeId, DateTime<Utc>>>>,
+    discovery_stats: Arc<RwLock<DiscoveryStats>>,
 }
 
 impl DhtMockState {
@@ -57,6 +61,8 @@ impl DhtMockState {
             call_count: Arc::new(AtomicUsize::new(0)),
             select_peers: Arc::new(RwLock::new(Vec::new())),
             settings: Arc::new(RwLock::new(HashMap::new())),
+            saf_retrievals: Arc::new(RwLock::new(HashMap::new())),
+            discovery_stats: Arc::new(RwLock::new(DiscoveryStats::default())),
         }
     }
 
@@ -74,9 +80,31 @@ impl DhtMockState {
         self.call_count.fetch_add(1, Ordering::SeqCst);
     }
 
+    pub fn call_count(&self) -> usize {
+        self.call_count.load(Ordering::SeqCst)
+    }
+
+    pub fn set_setting(&self, key: DhtMetadataKey, value: Vec<u8>) -> &Self {
+        self.settings.write().unwrap().insert(key.to_string(), value);
+        self
+    }
+
     pub fn get_setting(&self, key: &DhtMetadataKey) -> Option<Vec<u8>> {
         self.settings.read().unwrap().get(&key.to_string()).map(Clone::clone)
     }
+
+    pub fn set_last_saf_retrieval(&self, node_id: NodeId, timestamp: DateTime<Utc>) -> &Self {
+        self.saf_retrievals.write().unwrap().insert(node_id, timestamp);
+        self
+    }
+
+    pub fn get_last_saf_retrieval(&self, node_id: &NodeId) -> Option<DateTime<Utc>> {
+        self.saf_retrievals.read().unwrap().get(node_id).copied()
+    }
+
+    pub fn get_discovery_stats(&self) -> DiscoveryStats {
+        self.discovery_stats.read().unwrap().clone()
+    }
 }
 
 pub struct DhtActorMock {
@@ -110,11 +138,20 @@ impl DhtActorMock {
         use DhtRequest::*;
         self.state.inc_call_count();
         match req {
-            SendJoin => {},
+            SendJoin | SendJoinTo(_) | SendPeerAddressUpdate(_) => {},
             MsgHashCacheInsert(_, reply_tx) => {
                 let v = self.state.signature_cache_insert.load(Ordering::SeqCst);
                 reply_tx.send(v).unwrap();
             },
+            MsgHashCacheMemoryUsage(reply_tx) => {
+                let _ = reply_tx.send(0);
+            },
+            MsgHashCacheStats(reply_tx) => {
+                let _ = reply_tx.send(Default::default());
+            },
+            MsgHashCacheClear(reply_tx) => {
+                let _ = reply_tx.send(());
+            },
             SelectPeers(_, reply_tx) => {
                 let lock = self.state.select_peers.read().unwrap();
                 reply_tx
@@ -134,6 +171,18 @@ impl DhtActorMock {
                 self.state.settings.write().unwrap().insert(key.to_string(), value);
                 reply_tx.send(Ok(())).unwrap();
             },
+            RecordSafRetrieval(node_id) => {
+                self.state.set_last_saf_retrieval(*node_id, Utc::now());
+            },
+            GetLastSafRetrieval(node_id, reply_tx) => {
+                let _ = reply_tx.send(self.state.get_last_saf_retrieval(&node_id));
+            },
+            RecordDiscoveryResult(succeeded) => {
+                self.state.discovery_stats.write().unwrap().record(succeeded);
+            },
+            GetDiscoveryStats(reply_tx) => {
+                let _ = reply_tx.send(self.state.get_discovery_stats());
+            },
         }
     }
 }
@@ -107,6 +107,10 @@ impl DhtDiscoveryMock {
                 reply_tx.send(Ok(lock.clone())).unwrap();
             },
             NotifyDiscoveryResponseReceived(_) => {},
+            NotifyDiscoveryHintReceived(_) => {},
+            GetStoredMessageProviders(_, reply_tx) => {
+                let _ = reply_tx.send(Vec::new());
+            },
         }
     }
 }
@@ -20,6 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 use crate::{
+    codec::EnvelopeWireFormat,
     crypt,
     envelope::{DhtMessageFlags, DhtMessageHeader, NodeDestination},
     inbound::DhtInboundMessage,
@@ -82,7 +83,7 @@ pub fn make_dht_header(
         destination: NodeDestination::Unknown,
         ephemeral_public_key: if flags.is_encrypted() { Some(e_pk.clone()) } else { None },
         origin_mac: if include_origin {
-            make_valid_origin_mac(node_identity, &e_sk, message, flags)
+            make_valid_origin_mac(node_identity, e_sk, message, flags)
         } else {
             Vec::new()
         },
@@ -91,6 +92,8 @@ pub fn make_dht_header(
         flags,
         message_tag: trace,
         expires: None,
+        mailbox_tag: Vec::new(),
+        sequence: 0,
     }
 }
 
@@ -100,6 +103,18 @@ pub fn make_valid_origin_mac(
     body: &[u8],
     flags: DhtMessageFlags,
 ) -> Vec<u8>
+{
+    make_valid_origin_mac_for(node_identity, node_identity.public_key(), e_sk, body, flags)
+}
+
+/// Makes an origin MAC signed by `node_identity` and, if encrypted, readable by `recipient`
+pub fn make_valid_origin_mac_for(
+    node_identity: &NodeIdentity,
+    recipient: &CommsPublicKey,
+    e_sk: &CommsSecretKey,
+    body: &[u8],
+    flags: DhtMessageFlags,
+) -> Vec<u8>
 {
     let mac = OriginMac {
         public_key: node_identity.public_key().to_vec(),
@@ -110,7 +125,7 @@ pub fn make_valid_origin_mac(
     };
     let body = mac.to_encoded_bytes();
     if flags.is_encrypted() {
-        let shared_secret = crypt::generate_ecdh_secret(e_sk, node_identity.public_key());
+        let shared_secret = crypt::generate_ecdh_secret(e_sk, recipient);
         crypt::encrypt(&shared_secret, &body).unwrap()
     } else {
         body
@@ -123,9 +138,22 @@ pub fn make_dht_inbound_message(
     flags: DhtMessageFlags,
     include_origin: bool,
 ) -> DhtInboundMessage
+{
+    make_dht_inbound_message_for(node_identity, node_identity.public_key(), body, flags, include_origin)
+}
+
+/// Makes an inbound message originating from (and sent by) `node_identity` that, if encrypted, is readable by
+/// `recipient`
+pub fn make_dht_inbound_message_for(
+    node_identity: &NodeIdentity,
+    recipient: &CommsPublicKey,
+    body: Vec<u8>,
+    flags: DhtMessageFlags,
+    include_origin: bool,
+) -> DhtInboundMessage
 {
     let msg_tag = MessageTag::new();
-    let envelope = make_dht_envelope(node_identity, body, flags, include_origin, msg_tag);
+    let envelope = make_dht_envelope_for(node_identity, recipient, body, flags, include_origin, msg_tag);
     DhtInboundMessage::new(
         msg_tag,
         envelope.header.unwrap().try_into().unwrap(),
@@ -148,6 +176,25 @@ pub fn make_keypair() -> (CommsSecretKey, CommsPublicKey) {
 
 pub fn make_dht_envelope(
     node_identity: &NodeIdentity,
+    message: Vec<u8>,
+    flags: DhtMessageFlags,
+    include_origin: bool,
+    trace: MessageTag,
+) -> DhtEnvelope
+{
+    make_dht_envelope_for(
+        node_identity,
+        node_identity.public_key(),
+        message,
+        flags,
+        include_origin,
+        trace,
+    )
+}
+
+pub fn make_dht_envelope_for(
+    node_identity: &NodeIdentity,
+    recipient: &CommsPublicKey,
     mut message: Vec<u8>,
     flags: DhtMessageFlags,
     include_origin: bool,
@@ -156,11 +203,14 @@ pub fn make_dht_envelope(
 {
     let (e_sk, e_pk) = make_keypair();
     if flags.is_encrypted() {
-        let shared_secret = crypt::generate_ecdh_secret(&e_sk, node_identity.public_key());
+        let shared_secret = crypt::generate_ecdh_secret(&e_sk, recipient);
         message = crypt::encrypt(&shared_secret, &message).unwrap();
     }
-    let header = make_dht_header(node_identity, &e_pk, &e_sk, &message, flags, include_origin, trace).into();
-    DhtEnvelope::new(header, message.into())
+    let mut header = make_dht_header(node_identity, &e_pk, &e_sk, &message, flags, false, trace);
+    if include_origin {
+        header.origin_mac = make_valid_origin_mac_for(node_identity, recipient, &e_sk, &message, flags);
+    }
+    DhtEnvelope::new(header.into(), message.into())
 }
 
 pub fn build_peer_manager() -> Arc<PeerManager> {
@@ -197,5 +247,10 @@ pub fn create_outbound_message(body: &[u8]) -> DhtOutboundMessage {
         origin_mac: None,
         is_broadcast: false,
         expires: None,
+        mailbox_tag: None,
+        sequence: 0,
+        cancellation: None,
+        priority: Default::default(),
+        wire_format: EnvelopeWireFormat::Legacy,
     }
 }
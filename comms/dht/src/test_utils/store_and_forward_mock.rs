@@ -20,17 +20,18 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::store_forward::{StoreAndForwardRequest, StoreAndForwardRequester, StoredMessage};
+use crate::store_forward::{SafDestinationUsage, StoreAndForwardRequest, StoreAndForwardRequester, StoredMessage};
 use chrono::Utc;
-use digest::Input;
 use futures::{channel::mpsc, stream::Fuse, StreamExt};
 use log::*;
 use rand::{rngs::OsRng, RngCore};
-use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    Arc,
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
-use tari_comms::types::Challenge;
 use tari_utilities::hex::Hex;
 use tokio::{runtime, sync::RwLock};
 
@@ -128,11 +129,13 @@ impl StoreAndForwardMock {
                     destination_pubkey: msg.destination_pubkey,
                     destination_node_id: msg.destination_node_id,
                     header: msg.header,
-                    body: msg.body.clone(),
+                    body: msg.body,
                     is_encrypted: msg.is_encrypted,
                     priority: msg.priority,
                     stored_at: Utc::now().naive_utc(),
-                    body_hash: Challenge::new().chain(msg.body).result().to_vec().to_hex(),
+                    body_hash: msg.body_hash,
+                    mailbox_tag: msg.mailbox_tag,
+                    destination_node_ids: msg.destination_node_ids,
                 });
                 reply_tx.send(Ok(false)).unwrap();
             },
@@ -141,8 +144,81 @@ impl StoreAndForwardMock {
                     self.state.stored_messages.write().await.retain(|msg| msg.id != id);
                 }
             },
+            TakeMessages(message_ids, reply_tx) => {
+                let mut msgs = self.state.stored_messages.write().await;
+                let (taken, remaining): (Vec<_>, Vec<_>) =
+                    msgs.drain(..).partition(|msg| message_ids.contains(&msg.id));
+                *msgs = remaining;
+                let _ = reply_tx.send(Ok(taken));
+            },
+            CountMessagesForPeer(public_key, reply_tx) => {
+                let public_key = public_key.to_hex();
+                let msgs = self.state.stored_messages.read().await;
+                let count = msgs
+                    .iter()
+                    .filter(|msg| msg.destination_pubkey.as_ref() == Some(&public_key))
+                    .count();
+                let _ = reply_tx.send(Ok(count));
+            },
+            GetStorageSize(reply_tx) => {
+                let msgs = self.state.stored_messages.read().await;
+                let size = msgs.iter().map(|msg| msg.header.len() + msg.body.len()).sum();
+                let _ = reply_tx.send(Ok(size));
+            },
+            CountMessagesByDestination(limit, reply_tx) => {
+                let msgs = self.state.stored_messages.read().await;
+                let mut usage = HashMap::<_, SafDestinationUsage>::new();
+                for msg in msgs.iter() {
+                    let destination = msg
+                        .destination_pubkey
+                        .clone()
+                        .or_else(|| msg.destination_node_id.clone());
+                    let entry = usage.entry(destination.clone()).or_insert_with(|| SafDestinationUsage {
+                        destination,
+                        num_messages: 0,
+                        num_bytes: 0,
+                    });
+                    entry.num_messages += 1;
+                    entry.num_bytes += msg.header.len() + msg.body.len();
+                }
+                let mut usage = usage.into_iter().map(|(_, u)| u).collect::<Vec<_>>();
+                usage.sort_by(|a, b| b.num_messages.cmp(&a.num_messages));
+                usage.truncate(limit);
+                let _ = reply_tx.send(Ok(usage));
+            },
             SendStoreForwardRequestToPeer(_) => {},
             SendStoreForwardRequestNeighbours => {},
+            CheckRequestRate(_, _, reply_tx) => {
+                let _ = reply_tx.send(None);
+            },
+            ProviderRejectedRequest(_, _, _, _) => {},
+            GetProviderStats(reply_tx) => {
+                let _ = reply_tx.send(Default::default());
+            },
+            RegisterClient(_, reply_tx) => {
+                let _ = reply_tx.send(Ok(true));
+            },
+            UnregisterClient(_, reply_tx) => {
+                let _ = reply_tx.send(Ok(true));
+            },
+            GetServedClients(reply_tx) => {
+                let _ = reply_tx.send(Vec::new());
+            },
+            AddStoreFilter(_, reply_tx) => {
+                let _ = reply_tx.send(0);
+            },
+            RemoveStoreFilter(_, reply_tx) => {
+                let _ = reply_tx.send(true);
+            },
+            GetStoreFilters(reply_tx) => {
+                let _ = reply_tx.send(Vec::new());
+            },
+            AddMailboxContact(_, reply_tx) => {
+                let _ = reply_tx.send(Ok(true));
+            },
+            RemoveMailboxContact(_, reply_tx) => {
+                let _ = reply_tx.send(true);
+            },
         }
     }
 }
@@ -0,0 +1,255 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    header::check_unknown,
+    wire::{encode_bytes_field, encode_length_delimited, next_field, Field},
+    CodecError,
+    Destination,
+    DhtHeader,
+};
+use alloc::vec::Vec;
+use blake2::VarBlake2b;
+use digest::{Input, VariableOutput};
+
+/// The length in bytes of the `DhtEnvelope` body checksum
+pub const BODY_CHECKSUM_LENGTH: usize = 8;
+
+/// Marker byte that prefixes envelopes in the versioned wire format. A protobuf-encoded `DhtEnvelope` never starts with
+/// a zero byte (field number 0 is not valid), so the marker unambiguously distinguishes the versioned format from the
+/// legacy format.
+const VERSIONED_WIRE_FORMAT_MARKER: u8 = 0x00;
+
+/// The wire format of an encoded `DhtEnvelope`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvelopeWireFormat {
+    /// A bare protobuf-encoded `DhtEnvelope`
+    Legacy,
+    /// A protobuf-encoded `DhtEnvelope` prefixed with a marker byte and the wire format version (1). This allows
+    /// future changes to the envelope to be detected without trial decoding.
+    V1,
+    /// A compact envelope (wire format version 2) for messages sent directly to a connected peer that is also the
+    /// final destination. The destination, expiry, mailbox tag and body checksum are omitted: the recipient is
+    /// implied by the connection and the transport already provides integrity. The recipient decodes the destination
+    /// as `Destination::Unknown`.
+    Direct,
+}
+
+impl EnvelopeWireFormat {
+    fn version_byte(self) -> Option<u8> {
+        match self {
+            EnvelopeWireFormat::Legacy => None,
+            EnvelopeWireFormat::V1 => Some(1),
+            EnvelopeWireFormat::Direct => Some(2),
+        }
+    }
+}
+
+impl Default for EnvelopeWireFormat {
+    fn default() -> Self {
+        EnvelopeWireFormat::Legacy
+    }
+}
+
+/// A `DhtEnvelope`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DhtEnvelope {
+    pub header: Option<DhtHeader>,
+    pub body: Vec<u8>,
+    pub body_checksum: Vec<u8>,
+}
+
+impl DhtEnvelope {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, CodecError> {
+        let mut envelope = Self::default();
+        while let Some(field) = next_field(&mut buf)? {
+            match field {
+                Field::LengthDelimited(1, v) => envelope.header = Some(DhtHeader::decode(v)?),
+                Field::LengthDelimited(2, v) => envelope.body = v.to_vec(),
+                Field::LengthDelimited(3, v) => envelope.body_checksum = v.to_vec(),
+                field => check_unknown(&field, &[1, 2, 3])?,
+            }
+        }
+        Ok(envelope)
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(header) = self.header.as_ref() {
+            let mut header_buf = Vec::new();
+            header.encode(&mut header_buf);
+            encode_length_delimited(1, &header_buf, buf);
+        }
+        encode_bytes_field(2, &self.body, buf);
+        encode_bytes_field(3, &self.body_checksum, buf);
+    }
+}
+
+/// Splits the wire format prefix from an encoded envelope, returning the wire format and the protobuf-encoded
+/// `DhtEnvelope`.
+pub fn split_wire_format(bytes: &[u8]) -> Result<(EnvelopeWireFormat, &[u8]), CodecError> {
+    let (format, bytes) = match bytes {
+        [] | [VERSIONED_WIRE_FORMAT_MARKER] => return Err(CodecError::EmptyMessage),
+        [VERSIONED_WIRE_FORMAT_MARKER, 1, rest @ ..] => (EnvelopeWireFormat::V1, rest),
+        [VERSIONED_WIRE_FORMAT_MARKER, 2, rest @ ..] => (EnvelopeWireFormat::Direct, rest),
+        [VERSIONED_WIRE_FORMAT_MARKER, version, ..] => return Err(CodecError::UnsupportedWireFormat(*version)),
+        bytes => (EnvelopeWireFormat::Legacy, bytes),
+    };
+    if bytes.is_empty() {
+        return Err(CodecError::EmptyMessage);
+    }
+    Ok((format, bytes))
+}
+
+/// Decode a `DhtEnvelope` in any supported wire format, returning the wire format that was used along with the
+/// envelope. The body checksum is checked if present. The header is not validated, see `DhtHeader::validate`.
+pub fn decode_envelope(bytes: &[u8]) -> Result<(EnvelopeWireFormat, DhtEnvelope), CodecError> {
+    let (format, bytes) = split_wire_format(bytes)?;
+    let mut envelope = DhtEnvelope::decode(bytes)?;
+    if format == EnvelopeWireFormat::Direct {
+        if let Some(header) = envelope.header.as_mut() {
+            header.destination.get_or_insert(Destination::Unknown(true));
+        }
+    }
+    if !envelope.body_checksum.is_empty() && envelope.body_checksum != body_checksum(&envelope.body) {
+        return Err(CodecError::BodyChecksumMismatch);
+    }
+    Ok((format, envelope))
+}
+
+/// Encode the given header and body as a `DhtEnvelope` in the given wire format.
+pub fn encode_envelope(format: EnvelopeWireFormat, mut header: DhtHeader, body: Vec<u8>) -> Vec<u8> {
+    let body_checksum = if format == EnvelopeWireFormat::Direct {
+        header.destination = None;
+        header.expires = None;
+        header.mailbox_tag = Vec::new();
+        Vec::new()
+    } else {
+        body_checksum(&body)
+    };
+    let envelope = DhtEnvelope {
+        header: Some(header),
+        body,
+        body_checksum,
+    };
+    let mut buf = Vec::with_capacity(2 + envelope.body.len() + 256);
+    if let Some(version) = format.version_byte() {
+        buf.push(VERSIONED_WIRE_FORMAT_MARKER);
+        buf.push(version);
+    }
+    envelope.encode(&mut buf);
+    buf
+}
+
+/// Returns the checksum of an envelope body. This is a cheap integrity check and is not a substitute for the origin
+/// MAC or signature.
+pub fn body_checksum(body: &[u8]) -> Vec<u8> {
+    // Blake256 is Blake2b with a 32 byte output
+    let mut hasher = VarBlake2b::new(32).expect("32 is a valid Blake2b output size");
+    hasher.input(body);
+    let mut checksum = Vec::with_capacity(BODY_CHECKSUM_LENGTH);
+    hasher.variable_result(|hash| checksum.extend_from_slice(&hash[..BODY_CHECKSUM_LENGTH]));
+    checksum
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FLAG_ENCRYPTED;
+    use alloc::vec;
+
+    fn make_header() -> DhtHeader {
+        DhtHeader {
+            destination: Some(Destination::PublicKey(vec![1; 32])),
+            message_tag: 123,
+            mailbox_tag: vec![2; 2],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dual_decode() {
+        let legacy = encode_envelope(EnvelopeWireFormat::Legacy, make_header(), b"body".to_vec());
+        let v1 = encode_envelope(EnvelopeWireFormat::V1, make_header(), b"body".to_vec());
+        assert_eq!(&v1[2..], legacy.as_slice());
+
+        let (format, envelope) = decode_envelope(&legacy).unwrap();
+        assert_eq!(format, EnvelopeWireFormat::Legacy);
+        assert_eq!(envelope.header.unwrap(), make_header());
+        assert_eq!(envelope.body, b"body".to_vec());
+
+        let (format, envelope) = decode_envelope(&v1).unwrap();
+        assert_eq!(format, EnvelopeWireFormat::V1);
+        assert_eq!(envelope.header.unwrap(), make_header());
+
+        assert_eq!(
+            decode_envelope(&[VERSIONED_WIRE_FORMAT_MARKER, 3, 1, 2, 3]),
+            Err(CodecError::UnsupportedWireFormat(3))
+        );
+        assert_eq!(
+            decode_envelope(&[VERSIONED_WIRE_FORMAT_MARKER, 1]),
+            Err(CodecError::EmptyMessage)
+        );
+        assert_eq!(decode_envelope(&[]), Err(CodecError::EmptyMessage));
+        assert!(decode_envelope(&[0xff; 8]).is_err());
+    }
+
+    #[test]
+    fn direct_format_omits_routing_fields() {
+        let mut header = make_header();
+        header.flags = FLAG_ENCRYPTED;
+        let direct = encode_envelope(EnvelopeWireFormat::Direct, header.clone(), b"body".to_vec());
+        let (format, envelope) = decode_envelope(&direct).unwrap();
+        assert_eq!(format, EnvelopeWireFormat::Direct);
+        assert!(envelope.body_checksum.is_empty());
+        let decoded = envelope.header.unwrap();
+        assert_eq!(decoded.destination, Some(Destination::Unknown(true)));
+        assert!(decoded.mailbox_tag.is_empty());
+        assert_eq!(decoded.flags, header.flags);
+        assert_eq!(decoded.message_tag, header.message_tag);
+    }
+
+    #[test]
+    fn body_checksum_mismatch() {
+        let mut envelope = DhtEnvelope {
+            header: Some(make_header()),
+            body: b"body".to_vec(),
+            body_checksum: body_checksum(b"body"),
+        };
+        assert_eq!(envelope.body_checksum.len(), BODY_CHECKSUM_LENGTH);
+        let encode = |envelope: &DhtEnvelope| {
+            let mut buf = Vec::new();
+            envelope.encode(&mut buf);
+            buf
+        };
+        decode_envelope(&encode(&envelope)).unwrap();
+
+        // Envelopes without a checksum are accepted
+        envelope.body_checksum.clear();
+        decode_envelope(&encode(&envelope)).unwrap();
+
+        envelope.body_checksum = body_checksum(b"corrupted");
+        assert_eq!(
+            decode_envelope(&encode(&envelope)),
+            Err(CodecError::BodyChecksumMismatch)
+        );
+    }
+}
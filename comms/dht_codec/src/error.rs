@@ -0,0 +1,59 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    /// The message was empty
+    EmptyMessage,
+    /// The envelope uses a wire format version that is not supported
+    UnsupportedWireFormat(u8),
+    /// The envelope body does not match the body checksum
+    BodyChecksumMismatch,
+    /// The header version is newer than this codec supports
+    UnsupportedVersion(u32),
+    /// The header is for a different network
+    NetworkMismatch { expected: i32, got: i32 },
+    /// The header is not valid for its flags (e.g. an encrypted message without an ephemeral public key)
+    InvalidHeader,
+    /// The protobuf encoding is malformed
+    Malformed(&'static str),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CodecError::*;
+        match self {
+            EmptyMessage => write!(f, "Message body was empty"),
+            UnsupportedWireFormat(v) => write!(f, "Unsupported envelope wire format version {}", v),
+            BodyChecksumMismatch => write!(f, "Envelope body does not match the body checksum"),
+            UnsupportedVersion(v) => write!(f, "Unsupported envelope version {}", v),
+            NetworkMismatch { expected, got } => write!(f, "Message is for network {} but expected {}", got, expected),
+            InvalidHeader => write!(f, "Message header is invalid"),
+            Malformed(reason) => write!(f, "Malformed message: {}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CodecError {}
@@ -0,0 +1,271 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    wire::{
+        encode_bytes_field,
+        encode_key,
+        encode_length_delimited,
+        encode_varint,
+        encode_varint_field,
+        next_field,
+        Field,
+        WireType,
+    },
+    CodecError,
+};
+use alloc::vec::Vec;
+
+/// The latest `DhtHeader` version supported by this codec
+pub const DHT_ENVELOPE_HEADER_VERSION: u32 = 0;
+/// `DhtHeader::flags` bit that is set if the message body is encrypted
+pub const FLAG_ENCRYPTED: u32 = 0x01;
+
+/// A `google.protobuf.Timestamp`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timestamp {
+    pub seconds: i64,
+    pub nanos: i32,
+}
+
+impl Timestamp {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, CodecError> {
+        let mut timestamp = Self::default();
+        while let Some(field) = next_field(&mut buf)? {
+            match field {
+                Field::Varint(1, v) => timestamp.seconds = v as i64,
+                Field::Varint(2, v) => timestamp.nanos = v as i64 as i32,
+                field => check_unknown(&field, &[1, 2])?,
+            }
+        }
+        Ok(timestamp)
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        encode_varint_field(1, self.seconds as u64, buf);
+        encode_varint_field(2, i64::from(self.nanos) as u64, buf);
+    }
+}
+
+/// The `destination` of a `DhtHeader`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    /// The sender has chosen not to disclose the message destination
+    Unknown(bool),
+    /// Destined for a particular public key
+    PublicKey(Vec<u8>),
+    /// Destined for a particular node id, or network region
+    NodeId(Vec<u8>),
+    /// Destined for a small set of node ids
+    NodeIds(Vec<Vec<u8>>),
+}
+
+/// A `DhtHeader`, with each field in its wire representation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DhtHeader {
+    pub version: u32,
+    pub destination: Option<Destination>,
+    pub origin_mac: Vec<u8>,
+    pub ephemeral_public_key: Vec<u8>,
+    pub message_type: i32,
+    pub network: i32,
+    pub flags: u32,
+    pub message_tag: u64,
+    pub expires: Option<Timestamp>,
+    pub mailbox_tag: Vec<u8>,
+    pub sequence: u64,
+}
+
+impl DhtHeader {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, CodecError> {
+        let mut header = Self::default();
+        while let Some(field) = next_field(&mut buf)? {
+            match field {
+                Field::Varint(1, v) => header.version = v as u32,
+                Field::Varint(2, v) => header.destination = Some(Destination::Unknown(v != 0)),
+                Field::LengthDelimited(3, v) => header.destination = Some(Destination::PublicKey(v.to_vec())),
+                Field::LengthDelimited(4, v) => header.destination = Some(Destination::NodeId(v.to_vec())),
+                Field::LengthDelimited(12, v) => header.destination = Some(Destination::NodeIds(decode_node_ids(v)?)),
+                Field::LengthDelimited(5, v) => header.origin_mac = v.to_vec(),
+                Field::LengthDelimited(6, v) => header.ephemeral_public_key = v.to_vec(),
+                Field::Varint(7, v) => header.message_type = v as i32,
+                Field::Varint(8, v) => header.network = v as i32,
+                Field::Varint(9, v) => header.flags = v as u32,
+                Field::Varint(10, v) => header.message_tag = v,
+                Field::LengthDelimited(11, v) => header.expires = Some(Timestamp::decode(v)?),
+                Field::LengthDelimited(13, v) => header.mailbox_tag = v.to_vec(),
+                Field::Varint(14, v) => header.sequence = v,
+                field => check_unknown(&field, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14])?,
+            }
+        }
+        Ok(header)
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        encode_varint_field(1, u64::from(self.version), buf);
+        match &self.destination {
+            Some(Destination::Unknown(v)) => {
+                encode_key(2, WireType::Varint, buf);
+                encode_varint(*v as u64, buf);
+            },
+            Some(Destination::PublicKey(v)) => encode_length_delimited(3, v, buf),
+            Some(Destination::NodeId(v)) => encode_length_delimited(4, v, buf),
+            Some(Destination::NodeIds(node_ids)) => {
+                let mut node_ids_buf = Vec::new();
+                for node_id in node_ids {
+                    encode_length_delimited(1, node_id, &mut node_ids_buf);
+                }
+                encode_length_delimited(12, &node_ids_buf, buf);
+            },
+            None => {},
+        }
+        encode_bytes_field(5, &self.origin_mac, buf);
+        encode_bytes_field(6, &self.ephemeral_public_key, buf);
+        encode_varint_field(7, i64::from(self.message_type) as u64, buf);
+        encode_varint_field(8, i64::from(self.network) as u64, buf);
+        encode_varint_field(9, u64::from(self.flags), buf);
+        encode_varint_field(10, self.message_tag, buf);
+        if let Some(expires) = self.expires.as_ref() {
+            let mut expires_buf = Vec::new();
+            expires.encode(&mut expires_buf);
+            encode_length_delimited(11, &expires_buf, buf);
+        }
+        encode_bytes_field(13, &self.mailbox_tag, buf);
+        encode_varint_field(14, self.sequence, buf);
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_ENCRYPTED != 0
+    }
+
+    /// Check that the header has a supported version, is for the given network and that an encrypted message has an
+    /// origin MAC and an ephemeral public key.
+    pub fn validate(&self, network: i32) -> Result<(), CodecError> {
+        if self.version > DHT_ENVELOPE_HEADER_VERSION {
+            return Err(CodecError::UnsupportedVersion(self.version));
+        }
+        if self.network != network {
+            return Err(CodecError::NetworkMismatch {
+                expected: network,
+                got: self.network,
+            });
+        }
+        if self.is_encrypted() && (self.origin_mac.is_empty() || self.ephemeral_public_key.is_empty()) {
+            return Err(CodecError::InvalidHeader);
+        }
+        Ok(())
+    }
+}
+
+fn decode_node_ids(mut buf: &[u8]) -> Result<Vec<Vec<u8>>, CodecError> {
+    let mut node_ids = Vec::new();
+    while let Some(field) = next_field(&mut buf)? {
+        match field {
+            Field::LengthDelimited(1, v) => node_ids.push(v.to_vec()),
+            field => check_unknown(&field, &[1])?,
+        }
+    }
+    Ok(node_ids)
+}
+
+/// Unknown fields are skipped, as prost would. A known field with an unexpected wire type is an error.
+pub(crate) fn check_unknown(field: &Field<'_>, known_tags: &[u32]) -> Result<(), CodecError> {
+    if known_tags.contains(&field.tag()) {
+        return Err(CodecError::Malformed("unexpected wire type"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    fn make_header() -> DhtHeader {
+        DhtHeader {
+            version: 0,
+            destination: Some(Destination::NodeIds(vec![vec![1; 13], vec![2; 13]])),
+            origin_mac: vec![3; 64],
+            ephemeral_public_key: vec![4; 32],
+            message_type: 21,
+            network: 2,
+            flags: FLAG_ENCRYPTED,
+            message_tag: 123,
+            expires: Some(Timestamp {
+                seconds: 1_600_000_000,
+                nanos: -1,
+            }),
+            mailbox_tag: vec![5; 2],
+            sequence: 7,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let header = make_header();
+        let mut buf = Vec::new();
+        header.encode(&mut buf);
+        assert_eq!(DhtHeader::decode(&buf).unwrap(), header);
+
+        let header = DhtHeader {
+            destination: Some(Destination::Unknown(true)),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        header.encode(&mut buf);
+        assert_eq!(DhtHeader::decode(&buf).unwrap(), header);
+
+        let mut buf = Vec::new();
+        DhtHeader::default().encode(&mut buf);
+        assert!(buf.is_empty());
+        assert_eq!(DhtHeader::decode(&buf).unwrap(), DhtHeader::default());
+    }
+
+    #[test]
+    fn unknown_fields_are_skipped() {
+        let mut buf = Vec::new();
+        make_header().encode(&mut buf);
+        encode_bytes_field(100, b"future field", &mut buf);
+        assert_eq!(DhtHeader::decode(&buf).unwrap(), make_header());
+
+        // A known field with the wrong wire type
+        let mut buf = Vec::new();
+        encode_bytes_field(1, b"not a varint", &mut buf);
+        assert!(DhtHeader::decode(&buf).is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let mut header = make_header();
+        header.validate(2).unwrap();
+        assert_eq!(
+            header.validate(0),
+            Err(CodecError::NetworkMismatch { expected: 0, got: 2 })
+        );
+        header.ephemeral_public_key.clear();
+        assert_eq!(header.validate(2), Err(CodecError::InvalidHeader));
+        header.flags = 0;
+        header.validate(2).unwrap();
+        header.version = DHT_ENVELOPE_HEADER_VERSION + 1;
+        assert_eq!(header.validate(2), Err(CodecError::UnsupportedVersion(1)));
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Tari DHT wire codec
+//!
+//! Encoding, decoding and validation of `DhtEnvelope`s and stored messages without `std`. Only `alloc` is required, so
+//! constrained integrations (e.g. hardware wallets) can parse and validate Tari DHT messages without the comms stack.
+//! This crate also builds for `wasm32-unknown-unknown` (checked in CI), so browser tools can encode and validate DHT
+//! envelopes. `tari_comms_dht` itself does not build for wasm32, since it depends on the tokio runtime, native sockets
+//! and SQLite through `tari_comms`.
+//!
+//! The message types in this crate mirror the protobuf messages in `tari_comms_dht`'s `envelope.proto` and
+//! `store_forward.proto` field for field. Fields are kept in their wire representation (e.g. the message type and
+//! network are `i32`s) and are interpreted by `tari_comms_dht`, which re-exports this crate as `codec::wire`.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod envelope;
+pub use envelope::{
+    body_checksum,
+    decode_envelope,
+    encode_envelope,
+    split_wire_format,
+    DhtEnvelope,
+    EnvelopeWireFormat,
+    BODY_CHECKSUM_LENGTH,
+};
+
+mod error;
+pub use error::CodecError;
+
+mod header;
+pub use header::{Destination, DhtHeader, Timestamp, DHT_ENVELOPE_HEADER_VERSION, FLAG_ENCRYPTED};
+
+mod stored;
+pub use stored::{StoredMessage, StoredMessagesIter, StoredMessagesResponseReader, StoredRecordsIter};
+
+pub mod wire;
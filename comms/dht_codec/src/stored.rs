@@ -0,0 +1,227 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    header::check_unknown,
+    wire::{encode_length_delimited, encode_varint_field, next_field, Field},
+    CodecError,
+    DhtHeader,
+    Timestamp,
+};
+use alloc::vec::Vec;
+
+/// Field numbers of `StoredMessagesResponse`
+const STORED_MESSAGES_FIELD: u32 = 1;
+const REQUEST_ID_FIELD: u32 = 2;
+const RESPONSE_TYPE_FIELD: u32 = 3;
+
+/// A `StoredMessage`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoredMessage {
+    pub stored_at: Option<Timestamp>,
+    pub version: u32,
+    pub dht_header: Option<DhtHeader>,
+    pub body: Vec<u8>,
+}
+
+impl StoredMessage {
+    pub fn decode(mut buf: &[u8]) -> Result<Self, CodecError> {
+        if buf.is_empty() {
+            return Err(CodecError::EmptyMessage);
+        }
+        let mut msg = Self::default();
+        while let Some(field) = next_field(&mut buf)? {
+            match field {
+                Field::LengthDelimited(1, v) => msg.stored_at = Some(Timestamp::decode(v)?),
+                Field::Varint(2, v) => msg.version = v as u32,
+                Field::LengthDelimited(3, v) => msg.dht_header = Some(DhtHeader::decode(v)?),
+                Field::LengthDelimited(4, v) => msg.body = v.to_vec(),
+                field => check_unknown(&field, &[1, 2, 3, 4])?,
+            }
+        }
+        Ok(msg)
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        if let Some(stored_at) = self.stored_at.as_ref() {
+            let mut stored_at_buf = Vec::new();
+            stored_at.encode(&mut stored_at_buf);
+            encode_length_delimited(1, &stored_at_buf, buf);
+        }
+        encode_varint_field(2, u64::from(self.version), buf);
+        if let Some(header) = self.dht_header.as_ref() {
+            let mut header_buf = Vec::new();
+            header.encode(&mut header_buf);
+            encode_length_delimited(3, &header_buf, buf);
+        }
+        if !self.body.is_empty() {
+            encode_length_delimited(4, &self.body, buf);
+        }
+    }
+}
+
+/// Reads an encoded `StoredMessagesResponse` without decoding all of its messages up front.
+///
+/// Each `StoredMessage` is a length-prefixed record within the response, so the records can be decoded, validated and
+/// dropped one at a time. This keeps memory usage bounded by the largest single record rather than the whole response.
+#[derive(Debug, Clone)]
+pub struct StoredMessagesResponseReader<'a> {
+    bytes: &'a [u8],
+    request_id: u32,
+    response_type: i32,
+    num_messages: usize,
+}
+
+impl<'a> StoredMessagesResponseReader<'a> {
+    /// Scans the framing of the response. Only the field boundaries are checked; the individual messages are decoded
+    /// lazily by `messages`.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, CodecError> {
+        let mut reader = Self {
+            bytes,
+            request_id: 0,
+            response_type: 0,
+            num_messages: 0,
+        };
+        let mut buf = bytes;
+        while let Some(field) = next_field(&mut buf)? {
+            match field {
+                Field::LengthDelimited(STORED_MESSAGES_FIELD, _) => reader.num_messages += 1,
+                Field::Varint(REQUEST_ID_FIELD, v) => reader.request_id = v as u32,
+                Field::Varint(RESPONSE_TYPE_FIELD, v) => reader.response_type = v as i32,
+                field => check_unknown(&field, &[STORED_MESSAGES_FIELD, REQUEST_ID_FIELD, RESPONSE_TYPE_FIELD])?,
+            }
+        }
+        Ok(reader)
+    }
+
+    pub fn request_id(&self) -> u32 {
+        self.request_id
+    }
+
+    /// The `SafResponseType` of this response as an i32
+    pub fn response_type(&self) -> i32 {
+        self.response_type
+    }
+
+    pub fn num_messages(&self) -> usize {
+        self.num_messages
+    }
+
+    /// Returns an iterator over the encoded `StoredMessage` records in the response
+    pub fn records(&self) -> StoredRecordsIter<'a> {
+        StoredRecordsIter { buf: self.bytes }
+    }
+
+    /// Returns an iterator that decodes each `StoredMessage` in the response as it is consumed
+    pub fn messages(&self) -> StoredMessagesIter<'a> {
+        StoredMessagesIter {
+            records: self.records(),
+        }
+    }
+}
+
+/// Iterator over the encoded `StoredMessage` records in a `StoredMessagesResponse`
+pub struct StoredRecordsIter<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for StoredRecordsIter<'a> {
+    type Item = Result<&'a [u8], CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match next_field(&mut self.buf) {
+                Ok(Some(Field::LengthDelimited(STORED_MESSAGES_FIELD, record))) => return Some(Ok(record)),
+                Ok(Some(_)) => continue,
+                Ok(None) => return None,
+                Err(err) => {
+                    // Do not continue after a framing error
+                    self.buf = &[];
+                    return Some(Err(err));
+                },
+            }
+        }
+    }
+}
+
+/// Iterator over the `StoredMessage`s in a `StoredMessagesResponse`. See `StoredMessagesResponseReader`.
+pub struct StoredMessagesIter<'a> {
+    records: StoredRecordsIter<'a>,
+}
+
+impl Iterator for StoredMessagesIter<'_> {
+    type Item = Result<StoredMessage, CodecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next().map(|record| record.and_then(StoredMessage::decode))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn stored_messages_response_reader() {
+        let messages = (1..=3u32)
+            .map(|i| StoredMessage {
+                stored_at: Some(Timestamp {
+                    seconds: 1_600_000_000,
+                    nanos: 0,
+                }),
+                version: i,
+                dht_header: Some(DhtHeader {
+                    message_tag: u64::from(i),
+                    ..Default::default()
+                }),
+                body: vec![i as u8; 10],
+            })
+            .collect::<Vec<_>>();
+        let mut bytes = Vec::new();
+        for msg in &messages {
+            let mut msg_buf = Vec::new();
+            msg.encode(&mut msg_buf);
+            encode_length_delimited(STORED_MESSAGES_FIELD, &msg_buf, &mut bytes);
+        }
+        encode_varint_field(REQUEST_ID_FIELD, 123, &mut bytes);
+        encode_varint_field(RESPONSE_TYPE_FIELD, 2, &mut bytes);
+
+        let reader = StoredMessagesResponseReader::new(&bytes).unwrap();
+        assert_eq!(reader.request_id(), 123);
+        assert_eq!(reader.response_type(), 2);
+        assert_eq!(reader.num_messages(), 3);
+        assert_eq!(reader.records().count(), 3);
+        let decoded = reader.messages().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(decoded, messages);
+
+        let empty = StoredMessagesResponseReader::new(&[]).unwrap();
+        assert_eq!(empty.num_messages(), 0);
+        assert_eq!(empty.messages().count(), 0);
+
+        // Truncated record
+        assert!(matches!(
+            StoredMessagesResponseReader::new(&bytes[..bytes.len() - 20]),
+            Err(CodecError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Protobuf wire format primitives. Only what is needed to read and write the DHT messages is implemented.
+
+use crate::CodecError;
+use alloc::vec::Vec;
+
+const MAX_VARINT_LEN: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireType {
+    Varint = 0,
+    SixtyFourBit = 1,
+    LengthDelimited = 2,
+    StartGroup = 3,
+    EndGroup = 4,
+    ThirtyTwoBit = 5,
+}
+
+/// A single field read from a protobuf message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field<'a> {
+    Varint(u32, u64),
+    LengthDelimited(u32, &'a [u8]),
+    /// A fixed-width (32 or 64 bit) field. None of the DHT messages use these, so their value is not returned.
+    Fixed(u32),
+}
+
+impl Field<'_> {
+    pub fn tag(&self) -> u32 {
+        match self {
+            Field::Varint(tag, _) | Field::LengthDelimited(tag, _) | Field::Fixed(tag) => *tag,
+        }
+    }
+}
+
+/// Reads a varint from the start of `buf`, advancing past it
+pub fn decode_varint(buf: &mut &[u8]) -> Result<u64, CodecError> {
+    let mut value = 0u64;
+    for i in 0..MAX_VARINT_LEN {
+        let byte = *buf.get(i).ok_or(CodecError::Malformed("truncated varint"))?;
+        // The tenth byte may only contribute the most significant bit
+        if i == MAX_VARINT_LEN - 1 && byte > 1 {
+            return Err(CodecError::Malformed("varint overflow"));
+        }
+        value |= u64::from(byte & 0x7f) << (i * 7);
+        if byte < 0x80 {
+            *buf = &buf[i + 1..];
+            return Ok(value);
+        }
+    }
+    Err(CodecError::Malformed("varint overflow"))
+}
+
+/// Reads a field key from the start of `buf`, returning the field number and wire type
+pub fn decode_key(buf: &mut &[u8]) -> Result<(u32, WireType), CodecError> {
+    let key = decode_varint(buf)?;
+    if key > u64::from(u32::max_value()) {
+        return Err(CodecError::Malformed("invalid field key"));
+    }
+    let wire_type = match key & 0x07 {
+        0 => WireType::Varint,
+        1 => WireType::SixtyFourBit,
+        2 => WireType::LengthDelimited,
+        3 => WireType::StartGroup,
+        4 => WireType::EndGroup,
+        5 => WireType::ThirtyTwoBit,
+        _ => return Err(CodecError::Malformed("invalid wire type")),
+    };
+    let tag = (key >> 3) as u32;
+    if tag == 0 {
+        return Err(CodecError::Malformed("invalid field number"));
+    }
+    Ok((tag, wire_type))
+}
+
+/// Reads the next field from `buf`, advancing past it. Returns None once `buf` is empty.
+pub fn next_field<'a>(buf: &mut &'a [u8]) -> Result<Option<Field<'a>>, CodecError> {
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    let (tag, wire_type) = decode_key(buf)?;
+    let field = match wire_type {
+        WireType::Varint => Field::Varint(tag, decode_varint(buf)?),
+        WireType::LengthDelimited => {
+            let len = decode_varint(buf)?;
+            if len > buf.len() as u64 {
+                return Err(CodecError::Malformed("field length exceeds the buffer"));
+            }
+            let (value, rest) = buf.split_at(len as usize);
+            *buf = rest;
+            Field::LengthDelimited(tag, value)
+        },
+        WireType::SixtyFourBit | WireType::ThirtyTwoBit => {
+            let len = if wire_type == WireType::SixtyFourBit { 8 } else { 4 };
+            if len > buf.len() {
+                return Err(CodecError::Malformed("fixed field exceeds the buffer"));
+            }
+            *buf = &buf[len..];
+            Field::Fixed(tag)
+        },
+        WireType::StartGroup | WireType::EndGroup => {
+            return Err(CodecError::Malformed("groups are not supported"));
+        },
+    };
+    Ok(Some(field))
+}
+
+pub fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+pub fn encode_key(tag: u32, wire_type: WireType, buf: &mut Vec<u8>) {
+    encode_varint(u64::from(tag) << 3 | wire_type as u64, buf);
+}
+
+/// Encodes a varint field. As in proto3, nothing is written for a zero value.
+pub fn encode_varint_field(tag: u32, value: u64, buf: &mut Vec<u8>) {
+    if value != 0 {
+        encode_key(tag, WireType::Varint, buf);
+        encode_varint(value, buf);
+    }
+}
+
+/// Encodes a bytes (or embedded message) field, even if `value` is empty. This is used for oneof and message fields,
+/// which are written whenever they are set.
+pub fn encode_length_delimited(tag: u32, value: &[u8], buf: &mut Vec<u8>) {
+    encode_key(tag, WireType::LengthDelimited, buf);
+    encode_varint(value.len() as u64, buf);
+    buf.extend_from_slice(value);
+}
+
+/// Encodes a bytes field. As in proto3, nothing is written for an empty value.
+pub fn encode_bytes_field(tag: u32, value: &[u8], buf: &mut Vec<u8>) {
+    if !value.is_empty() {
+        encode_length_delimited(tag, value, buf);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn varint_roundtrip() {
+        for value in &[0u64, 1, 127, 128, 300, u64::from(u32::max_value()), u64::max_value()] {
+            let mut buf = Vec::new();
+            encode_varint(*value, &mut buf);
+            let mut slice = buf.as_slice();
+            assert_eq!(decode_varint(&mut slice).unwrap(), *value);
+            assert!(slice.is_empty());
+        }
+        assert_eq!(
+            decode_varint(&mut [0x80u8].as_ref()),
+            Err(CodecError::Malformed("truncated varint"))
+        );
+        assert_eq!(
+            decode_varint(&mut [0xffu8; 10].as_ref()),
+            Err(CodecError::Malformed("varint overflow"))
+        );
+    }
+
+    #[test]
+    fn fields() {
+        let mut buf = Vec::new();
+        encode_varint_field(1, 0, &mut buf);
+        assert!(buf.is_empty());
+        encode_varint_field(1, 150, &mut buf);
+        encode_bytes_field(2, b"abc", &mut buf);
+        encode_length_delimited(3, &[], &mut buf);
+        assert_eq!(buf, vec![0x08, 0x96, 0x01, 0x12, 3, b'a', b'b', b'c', 0x1a, 0]);
+
+        let mut slice = buf.as_slice();
+        assert_eq!(next_field(&mut slice).unwrap(), Some(Field::Varint(1, 150)));
+        assert_eq!(next_field(&mut slice).unwrap(), Some(Field::LengthDelimited(2, b"abc")));
+        assert_eq!(next_field(&mut slice).unwrap(), Some(Field::LengthDelimited(3, b"")));
+        assert_eq!(next_field(&mut slice).unwrap(), None);
+
+        // Truncated length-delimited field
+        assert!(next_field(&mut &buf[3..7]).is_err());
+    }
+}
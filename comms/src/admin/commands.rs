@@ -0,0 +1,81 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use futures::{future::BoxFuture, Future, FutureExt};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use tokio::net::TcpStream;
+
+/// Serves an admin command on the connection of the client that sent it
+pub type AdminCommandHandler = Arc<dyn Fn(TcpStream) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// The commands served on the [AdminSocket](super::AdminSocket). Clones share the same set of commands, so components
+/// built on comms may register their own commands before or after the socket is spawned.
+#[derive(Clone, Default)]
+pub struct AdminCommands {
+    handlers: Arc<RwLock<HashMap<String, AdminCommandHandler>>>,
+}
+
+impl AdminCommands {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Register a handler for the named command, replacing any existing handler for that command
+    pub fn register<F, Fut>(&self, name: &str, handler: F)
+    where
+        F: Fn(TcpStream) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handler: AdminCommandHandler = Arc::new(move |socket| handler(socket).boxed());
+        acquire_write_lock!(self.handlers).insert(name.to_string(), handler);
+    }
+
+    /// Returns the handler for the named command, if one is registered
+    pub fn get(&self, name: &str) -> Option<AdminCommandHandler> {
+        acquire_read_lock!(self.handlers).get(name).cloned()
+    }
+
+    /// Returns the names of the registered commands in sorted order
+    pub fn names(&self) -> Vec<String> {
+        let mut names = acquire_read_lock!(self.handlers).keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn register() {
+        let commands = AdminCommands::new();
+        assert!(commands.get("status").is_none());
+        commands.register("status", |_| async {});
+        commands.register("dashboard", |_| async {});
+        assert!(commands.get("status").is_some());
+        assert_eq!(commands.names(), vec!["dashboard".to_string(), "status".to_string()]);
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Admin socket
+//!
+//! The [AdminSocket](self::AdminSocket) is a single TCP listener shared by the local operator tooling (intrusion
+//! detection, node dashboards). A client connects, sends the name of a command followed by a newline, and the handler
+//! registered for that command in [AdminCommands](self::AdminCommands) takes over the connection. For example:
+//!
+//! ```text
+//! $ echo security_events | nc 127.0.0.1 18190
+//! {"timestamp":"2020-11-02T10:21:03Z","event":"peer_banned","node_id":"e6f2...","duration_secs":3600,"reason":"..."}
+//! ```
+//!
+//! A client that sends an unknown command receives a JSON line with an `error` and the available `commands`.
+//!
+//! The commands disclose information about the node and its peers, so the socket can only be bound to a loopback
+//! address. The number of clients that are served at the same time is limited; clients that connect once this limit
+//! is reached are disconnected immediately.
+
+mod commands;
+pub use commands::{AdminCommandHandler, AdminCommands};
+
+mod socket;
+pub use socket::AdminSocket;
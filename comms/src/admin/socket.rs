@@ -0,0 +1,274 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::AdminCommands;
+use futures::FutureExt;
+use log::*;
+use serde_json::json;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tari_shutdown::ShutdownSignal;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task,
+    time,
+};
+
+const LOG_TARGET: &str = "comms::admin::socket";
+
+/// The maximum length of a command line sent by a client, including the newline
+const MAX_COMMAND_LEN: usize = 64;
+/// The time a client has to send its command after connecting
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves the registered [AdminCommands] to clients connected to a loopback TCP socket. See [admin](crate::admin) for
+/// the protocol.
+pub struct AdminSocket {
+    commands: AdminCommands,
+    max_clients: usize,
+    shutdown_signal: ShutdownSignal,
+}
+
+impl AdminSocket {
+    /// The default for the maximum number of clients that are served at the same time
+    pub const DEFAULT_MAX_CLIENTS: usize = 8;
+
+    pub fn new(commands: AdminCommands, shutdown_signal: ShutdownSignal) -> Self {
+        Self {
+            commands,
+            max_clients: Self::DEFAULT_MAX_CLIENTS,
+            shutdown_signal,
+        }
+    }
+
+    /// Set the maximum number of clients that are served at the same time. Clients that connect once this limit is
+    /// reached are disconnected immediately.
+    pub fn with_max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
+
+    /// Binds the socket to the given loopback address and spawns a task that serves connected clients until the
+    /// shutdown signal is triggered. Returns the bound address. An `InvalidInput` error is returned if the address is
+    /// not a loopback address.
+    pub async fn spawn(self, addr: SocketAddr) -> io::Result<SocketAddr> {
+        if !addr.ip().is_loopback() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Admin socket address '{}' is not a loopback address", addr),
+            ));
+        }
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        info!(
+            target: LOG_TARGET,
+            "Serving admin commands on '{}' (commands: {})",
+            local_addr,
+            self.commands.names().join(", ")
+        );
+        task::spawn(self.run(listener));
+        Ok(local_addr)
+    }
+
+    async fn run(self, mut listener: TcpListener) {
+        let mut shutdown_signal = self.shutdown_signal.clone();
+        let num_clients = Arc::new(AtomicUsize::new(0));
+        loop {
+            futures::select! {
+                result = listener.accept().fuse() => match result {
+                    Ok((socket, addr)) => {
+                        if num_clients.load(Ordering::SeqCst) >= self.max_clients {
+                            warn!(
+                                target: LOG_TARGET,
+                                "Disconnecting admin client '{}' because {} clients are already connected",
+                                addr,
+                                self.max_clients
+                            );
+                            continue;
+                        }
+                        debug!(target: LOG_TARGET, "Admin client connected from '{}'", addr);
+                        let guard = ClientGuard::new(num_clients.clone());
+                        task::spawn(serve_client(socket, self.commands.clone(), guard));
+                    },
+                    Err(err) => {
+                        warn!(target: LOG_TARGET, "Failed to accept admin client: {}", err);
+                    },
+                },
+                _ = shutdown_signal => {
+                    info!(target: LOG_TARGET, "Admin socket is shutting down");
+                    break;
+                },
+            }
+        }
+    }
+}
+
+/// Counts a connected client until it is dropped
+struct ClientGuard(Arc<AtomicUsize>);
+
+impl ClientGuard {
+    fn new(num_clients: Arc<AtomicUsize>) -> Self {
+        num_clients.fetch_add(1, Ordering::SeqCst);
+        Self(num_clients)
+    }
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+async fn serve_client(mut socket: TcpStream, commands: AdminCommands, _guard: ClientGuard) {
+    let command = match time::timeout(COMMAND_TIMEOUT, read_command(&mut socket)).await {
+        Ok(Ok(command)) => command,
+        Ok(Err(err)) => {
+            debug!(target: LOG_TARGET, "Failed to read admin command: {}", err);
+            return;
+        },
+        Err(_) => {
+            debug!(
+                target: LOG_TARGET,
+                "Admin client did not send a command within {:.0?}", COMMAND_TIMEOUT
+            );
+            return;
+        },
+    };
+
+    match commands.get(&command) {
+        Some(handler) => {
+            debug!(target: LOG_TARGET, "Serving admin command '{}'", command);
+            handler(socket).await;
+        },
+        None => {
+            let mut line = json!({
+                "error": format!("Unknown command '{}'", command),
+                "commands": commands.names(),
+            })
+            .to_string();
+            line.push('\n');
+            let _ = socket.write_all(line.as_bytes()).await;
+        },
+    }
+}
+
+/// Reads a single newline terminated command. Bytes are read one at a time so that nothing the client sends after the
+/// command is consumed before the handler takes over the connection.
+async fn read_command(socket: &mut TcpStream) -> io::Result<String> {
+    let mut buf = Vec::with_capacity(MAX_COMMAND_LEN);
+    loop {
+        let byte = socket.read_u8().await?;
+        if byte == b'\n' {
+            break;
+        }
+        if buf.len() + 1 >= MAX_COMMAND_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Admin command is too long"));
+        }
+        buf.push(byte);
+    }
+    let command = String::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(command.trim().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime;
+    use serde_json::Value;
+    use tari_shutdown::Shutdown;
+
+    async fn spawn_socket(commands: AdminCommands, max_clients: usize, shutdown: &Shutdown) -> SocketAddr {
+        AdminSocket::new(commands, shutdown.to_signal())
+            .with_max_clients(max_clients)
+            .spawn("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn send_command(addr: SocketAddr, command: &str) -> String {
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        socket.write_all(command.as_bytes()).await.unwrap();
+        socket.write_all(b"\n").await.unwrap();
+        let mut buf = String::new();
+        socket.read_to_string(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[runtime::test_basic]
+    async fn rejects_non_loopback_address() {
+        let shutdown = Shutdown::new();
+        let err = AdminSocket::new(AdminCommands::new(), shutdown.to_signal())
+            .spawn("0.0.0.0:0".parse().unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[runtime::test_basic]
+    async fn serves_commands() {
+        let shutdown = Shutdown::new();
+        let commands = AdminCommands::new();
+        commands.register("greet", |mut socket: TcpStream| async move {
+            socket.write_all(b"hello\n").await.unwrap();
+        });
+        let addr = spawn_socket(commands, AdminSocket::DEFAULT_MAX_CLIENTS, &shutdown).await;
+
+        assert_eq!(send_command(addr, "greet").await, "hello\n");
+        assert_eq!(send_command(addr, " greet\r").await, "hello\n");
+
+        let value = serde_json::from_str::<Value>(&send_command(addr, "unknown").await).unwrap();
+        assert_eq!(value["error"], "Unknown command 'unknown'");
+        assert_eq!(value["commands"][0], "greet");
+    }
+
+    #[runtime::test_basic]
+    async fn limits_concurrent_clients() {
+        let shutdown = Shutdown::new();
+        let commands = AdminCommands::new();
+        commands.register("hold", |mut socket: TcpStream| async move {
+            socket.write_all(b"held\n").await.unwrap();
+            // Keep the connection open until the client closes it
+            let _ = socket.read_u8().await;
+        });
+        let addr = spawn_socket(commands, 1, &shutdown).await;
+
+        let mut held = TcpStream::connect(addr).await.unwrap();
+        held.write_all(b"hold\n").await.unwrap();
+        let mut buf = [0u8; 5];
+        held.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"held\n");
+
+        // The second client is disconnected without being served
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        let mut buf = Vec::new();
+        let _ = socket.read_to_end(&mut buf).await;
+        assert!(buf.is_empty());
+    }
+}
@@ -20,6 +20,8 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use rand::{rngs::OsRng, Rng};
+use serde::{Deserialize, Serialize};
 use std::{cmp::min, time::Duration};
 
 pub type BoxedBackoff = Box<dyn Backoff + Send + Sync>;
@@ -79,6 +81,166 @@ impl Backoff for ConstantBackoff {
     }
 }
 
+/// A configurable retry policy, used wherever a network operation is retried (e.g. dialing, network discovery and
+/// store and forward requests). The backoff for the first attempt is always zero.
+///
+/// When loaded from a configuration file, durations are given in milliseconds e.g.
+/// ```toml
+/// dial_backoff = { type = "exponential", initial_delay = 500, factor = 2.0, max_delay = 60000, jitter = 0.2 }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BackoffPolicy {
+    /// Wait the same amount of time between every attempt
+    Constant {
+        #[serde(with = "millis")]
+        delay: Duration,
+    },
+    /// Wait `initial_delay * factor^n` after the nth failed attempt, up to `max_delay`
+    Exponential {
+        #[serde(with = "millis")]
+        initial_delay: Duration,
+        factor: f32,
+        #[serde(with = "millis")]
+        max_delay: Duration,
+        /// The proportion of the delay (0.0 - 1.0) to randomly subtract, so that many nodes retrying at the same time
+        /// are spread out
+        #[serde(default)]
+        jitter: f32,
+    },
+    /// Wait `initial_delay * fib(n)` after the nth failed attempt, up to `max_delay`. This grows more slowly than
+    /// exponential backoff.
+    Fibonacci {
+        #[serde(with = "millis")]
+        initial_delay: Duration,
+        #[serde(with = "millis")]
+        max_delay: Duration,
+        /// The proportion of the delay (0.0 - 1.0) to randomly subtract
+        #[serde(default)]
+        jitter: f32,
+    },
+}
+
+impl BackoffPolicy {
+    pub fn constant(delay: Duration) -> Self {
+        BackoffPolicy::Constant { delay }
+    }
+
+    pub fn exponential(initial_delay: Duration, factor: f32, max_delay: Duration) -> Self {
+        BackoffPolicy::Exponential {
+            initial_delay,
+            factor,
+            max_delay,
+            jitter: 0.0,
+        }
+    }
+
+    pub fn fibonacci(initial_delay: Duration, max_delay: Duration) -> Self {
+        BackoffPolicy::Fibonacci {
+            initial_delay,
+            max_delay,
+            jitter: 0.0,
+        }
+    }
+
+    /// Randomly reduce each delay by up to the given proportion (0.0 - 1.0). This has no effect on a constant backoff.
+    pub fn with_jitter(mut self, jitter: f32) -> Self {
+        match &mut self {
+            BackoffPolicy::Constant { .. } => {},
+            BackoffPolicy::Exponential { jitter: j, .. } | BackoffPolicy::Fibonacci { jitter: j, .. } => {
+                *j = jitter.max(0.0).min(1.0);
+            },
+        }
+        self
+    }
+
+    /// Returns an error message if the policy is not valid
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            BackoffPolicy::Constant { .. } => Ok(()),
+            BackoffPolicy::Exponential { factor, jitter, .. } if *factor < 1.0 || !(0.0..=1.0).contains(jitter) => Err(
+                "exponential backoff requires a factor of at least 1.0 and a jitter between 0.0 and 1.0".to_string(),
+            ),
+            BackoffPolicy::Fibonacci { jitter, .. } if !(0.0..=1.0).contains(jitter) => {
+                Err("fibonacci backoff requires a jitter between 0.0 and 1.0".to_string())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Calculates the backoff for the given number of attempts, using the given RNG to apply jitter. This allows the
+    /// jitter to be made deterministic (e.g. with a seeded `SharedRng`).
+    pub fn calculate_backoff_with_rng<R: Rng + ?Sized>(&self, attempts: usize, rng: &mut R) -> Duration {
+        if attempts <= 1 {
+            return Duration::from_secs(0);
+        }
+        let num_failed = attempts - 1;
+        match *self {
+            BackoffPolicy::Constant { delay } => delay,
+            BackoffPolicy::Exponential {
+                initial_delay,
+                factor,
+                max_delay,
+                jitter,
+            } => {
+                let multiplier = (factor as f64).powi(min(num_failed - 1, 63) as i32);
+                let secs = (initial_delay.as_secs_f64() * multiplier).min(max_delay.as_secs_f64());
+                Self::apply_jitter(Duration::from_secs_f64(secs), jitter, rng)
+            },
+            BackoffPolicy::Fibonacci {
+                initial_delay,
+                max_delay,
+                jitter,
+            } => {
+                let (mut a, mut b) = (0u64, 1u64);
+                for _ in 0..min(num_failed, 90) {
+                    let next = a.saturating_add(b);
+                    a = b;
+                    b = next;
+                }
+                let secs = (initial_delay.as_secs_f64() * a as f64).min(max_delay.as_secs_f64());
+                Self::apply_jitter(Duration::from_secs_f64(secs), jitter, rng)
+            },
+        }
+    }
+
+    fn apply_jitter<R: Rng + ?Sized>(delay: Duration, jitter: f32, rng: &mut R) -> Duration {
+        if jitter <= 0.0 || delay == Duration::from_secs(0) {
+            return delay;
+        }
+        let reduction = rng.gen_range(0.0, jitter.min(1.0) as f64);
+        delay.mul_f64(1.0 - reduction)
+    }
+}
+
+impl Default for BackoffPolicy {
+    /// Exponential backoff starting at 500ms, doubling up to a minute
+    fn default() -> Self {
+        BackoffPolicy::exponential(Duration::from_millis(500), 2.0, Duration::from_secs(60))
+    }
+}
+
+impl Backoff for BackoffPolicy {
+    fn calculate_backoff(&self, attempts: usize) -> Duration {
+        self.calculate_backoff_with_rng(attempts, &mut OsRng)
+    }
+}
+
+mod millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where D: Deserializer<'de> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+
+    pub fn serialize<S>(duration: &Duration, s: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        s.serialize_u64(duration.as_millis() as u64)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -109,4 +271,79 @@ mod test {
         assert_eq!(backoff.calculate_backoff(1).as_secs(), 0);
         assert_eq!(backoff.calculate_backoff(200).as_secs(), 0);
     }
+
+    mod backoff_policy {
+        use super::*;
+
+        #[test]
+        fn constant() {
+            let backoff = BackoffPolicy::constant(Duration::from_millis(500));
+            assert_eq!(backoff.calculate_backoff(1), Duration::from_secs(0));
+            assert_eq!(backoff.calculate_backoff(2), Duration::from_millis(500));
+            assert_eq!(backoff.calculate_backoff(100), Duration::from_millis(500));
+        }
+
+        #[test]
+        fn exponential() {
+            let backoff = BackoffPolicy::exponential(Duration::from_secs(1), 2.0, Duration::from_secs(60));
+            let delays = (1..=9)
+                .map(|n| backoff.calculate_backoff(n).as_secs())
+                .collect::<Vec<_>>();
+            assert_eq!(delays, vec![0, 1, 2, 4, 8, 16, 32, 60, 60]);
+            assert_eq!(backoff.calculate_backoff(usize::MAX), Duration::from_secs(60));
+        }
+
+        #[test]
+        fn fibonacci() {
+            let backoff = BackoffPolicy::fibonacci(Duration::from_secs(1), Duration::from_secs(10));
+            let delays = (1..=9)
+                .map(|n| backoff.calculate_backoff(n).as_secs())
+                .collect::<Vec<_>>();
+            assert_eq!(delays, vec![0, 1, 1, 2, 3, 5, 8, 10, 10]);
+            assert_eq!(backoff.calculate_backoff(usize::MAX), Duration::from_secs(10));
+        }
+
+        #[test]
+        fn jitter() {
+            let backoff =
+                BackoffPolicy::exponential(Duration::from_secs(10), 2.0, Duration::from_secs(60)).with_jitter(0.5);
+            for _ in 0..100 {
+                let delay = backoff.calculate_backoff(2);
+                assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(10));
+            }
+            assert_eq!(backoff.calculate_backoff(1), Duration::from_secs(0));
+        }
+
+        #[test]
+        fn seeded_jitter() {
+            use crate::utils::rng::SharedRng;
+            let backoff =
+                BackoffPolicy::exponential(Duration::from_secs(10), 2.0, Duration::from_secs(60)).with_jitter(0.5);
+            let delays = |rng: &mut SharedRng| {
+                (2..10)
+                    .map(|n| backoff.calculate_backoff_with_rng(n, rng))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                delays(&mut SharedRng::from_seed(1)),
+                delays(&mut SharedRng::from_seed(1))
+            );
+        }
+
+        #[test]
+        fn validate() {
+            assert!(BackoffPolicy::default().validate().is_ok());
+            assert!(
+                BackoffPolicy::exponential(Duration::from_secs(1), 0.5, Duration::from_secs(60))
+                    .validate()
+                    .is_err()
+            );
+            let backoff = BackoffPolicy::Fibonacci {
+                initial_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(10),
+                jitter: 2.0,
+            };
+            assert!(backoff.validate().is_err());
+        }
+    }
 }
@@ -76,6 +76,12 @@ impl BoundedExecutor {
         self.semaphore.available_permits()
     }
 
+    /// Waits (asynchronously) until at least one task can be spawned on this executor. This does not reserve a slot,
+    /// so a subsequent call to `try_spawn` may still fail if another caller spawns a task in the meantime.
+    pub async fn wait_available(&self) {
+        let _permit = self.semaphore.acquire().await;
+    }
+
     pub fn try_spawn<F>(&self, future: F) -> Result<JoinHandle<F::Output>, TrySpawnError>
     where
         F: Future + Send + 'static,
@@ -20,8 +20,9 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use super::{CommsBuilderError, CommsShutdown};
+use super::{CommsBuilderError, CommsShutdown, ShutdownReporter};
 use crate::{
+    admin::{AdminCommands, AdminSocket},
     connection_manager::{
         ConnectionManager,
         ConnectionManagerEvent,
@@ -30,7 +31,7 @@ use crate::{
     },
     connectivity::{ConnectivityEventRx, ConnectivityManager, ConnectivityRequest, ConnectivityRequester},
     multiaddr::Multiaddr,
-    noise::NoiseConfig,
+    noise::{NoiseConfig, RekeyPolicy},
     peer_manager::{NodeIdentity, PeerManager},
     protocol::{
         ProtocolExtension,
@@ -40,14 +41,16 @@ use crate::{
         ProtocolNotificationTx,
         Protocols,
     },
+    security_events::{self, SecurityEventPublisher, SecurityEventReceiver},
     tor,
     transports::Transport,
+    utils::multiaddr::{multiaddr_port, multiaddr_with_port},
     CommsBuilder,
     Substream,
 };
 use futures::{channel::mpsc, AsyncRead, AsyncWrite, StreamExt};
 use log::*;
-use std::{iter, sync::Arc, time::Duration};
+use std::{sync::Arc, time::Duration};
 use tari_shutdown::ShutdownSignal;
 use tokio::{sync::broadcast, time};
 
@@ -65,6 +68,9 @@ pub struct UnspawnedCommsNode {
     pub(super) protocol_extensions: ProtocolExtensions,
     pub(super) protocols: Protocols<Substream>,
     pub(super) shutdown_signal: ShutdownSignal,
+    pub(super) shutdown_reporter: ShutdownReporter,
+    pub(super) security_events: SecurityEventPublisher,
+    pub(super) admin_commands: AdminCommands,
 }
 
 impl UnspawnedCommsNode {
@@ -117,10 +123,12 @@ impl UnspawnedCommsNode {
         self
     }
 
-    /// Wait until the ConnectionManager emits a Listening event. This is the signal that comms is ready.
+    /// Wait until the ConnectionManager emits a Listening event. This is the signal that comms is ready. Returns the
+    /// main listening address and the auxiliary listening addresses.
     async fn wait_listening(
         mut events: broadcast::Receiver<Arc<ConnectionManagerEvent>>,
-    ) -> Result<Multiaddr, CommsBuilderError> {
+    ) -> Result<(Multiaddr, Vec<Multiaddr>), CommsBuilderError> {
+        let mut auxiliary_addrs = Vec::new();
         loop {
             let event = time::timeout(Duration::from_secs(10), events.next())
                 .await
@@ -129,13 +137,25 @@ impl UnspawnedCommsNode {
                 .map_err(|_| CommsBuilderError::ConnectionManagerEventStreamLagged)?;
 
             match &*event {
-                ConnectionManagerEvent::Listening(addr) => return Ok(addr.clone()),
+                ConnectionManagerEvent::AuxiliaryListening(addr) => auxiliary_addrs.push(addr.clone()),
+                ConnectionManagerEvent::Listening(addr) => return Ok((addr.clone(), auxiliary_addrs)),
                 ConnectionManagerEvent::ListenFailed(err) => return Err(err.clone().into()),
                 _ => {},
             }
         }
     }
 
+    /// Spawn the comms stack using the transport set by
+    /// [CommsBuilder::with_transport](crate::CommsBuilder::with_transport).
+    pub async fn spawn(mut self) -> Result<CommsNode, CommsBuilderError> {
+        let transport = self
+            .builder
+            .transport
+            .take()
+            .ok_or(CommsBuilderError::TransportNotSet)?;
+        self.spawn_with_transport(transport).await
+    }
+
     pub async fn spawn_with_transport<TTransport>(self, transport: TTransport) -> Result<CommsNode, CommsBuilderError>
     where
         TTransport: Transport + Unpin + Send + Sync + Clone + 'static,
@@ -152,6 +172,9 @@ impl UnspawnedCommsNode {
             peer_manager,
             protocol_extensions,
             protocols,
+            shutdown_reporter,
+            security_events,
+            admin_commands,
         } = self;
 
         let CommsBuilder {
@@ -159,9 +182,19 @@ impl UnspawnedCommsNode {
             hidden_service_ctl,
             connection_manager_config,
             connectivity_config,
+            admin_socket_addr,
+            rng,
             ..
         } = builder;
 
+        if let Some(addr) = admin_socket_addr {
+            security_events::register_admin_command(&admin_commands, security_events.clone(), shutdown_signal.clone());
+            AdminSocket::new(admin_commands, shutdown_signal.clone())
+                .spawn(addr)
+                .await
+                .map_err(CommsBuilderError::AdminSocketBindFailed)?;
+        }
+
         //---------------------------------- Connectivity Manager --------------------------------------------//
         let connectivity_manager = ConnectivityManager {
             config: connectivity_config,
@@ -171,12 +204,17 @@ impl UnspawnedCommsNode {
             node_identity: node_identity.clone(),
             peer_manager: peer_manager.clone(),
             shutdown_signal: shutdown_signal.clone(),
+            shutdown_reporter: shutdown_reporter.clone(),
+            security_events: security_events.clone(),
+            rng: rng.for_instance(node_identity.node_id().as_ref()),
         };
 
         let mut ext_context = ProtocolExtensionContext::new(
             connectivity_requester.clone(),
             peer_manager.clone(),
             shutdown_signal.clone(),
+            shutdown_reporter.clone(),
+            security_events.clone(),
         );
 
         debug!(
@@ -188,7 +226,10 @@ impl UnspawnedCommsNode {
 
         //---------------------------------- Connection Manager --------------------------------------------//
 
-        let noise_config = NoiseConfig::new(node_identity.clone());
+        let noise_config = NoiseConfig::new(node_identity.clone()).with_rekey_policy(RekeyPolicy {
+            interval: connection_manager_config.noise_rekey_interval,
+            max_bytes: connection_manager_config.noise_rekey_bytes,
+        });
 
         let mut connection_manager = ConnectionManager::new(
             connection_manager_config,
@@ -202,6 +243,7 @@ impl UnspawnedCommsNode {
             shutdown_signal.clone(),
         );
 
+        connection_manager.set_security_event_publisher(security_events.clone());
         ext_context.register_complete_signal(connection_manager.complete_signal());
         connection_manager.add_protocols(ext_context.take_protocols().expect("Protocols already taken"));
         connection_manager.add_protocols(protocols);
@@ -224,7 +266,15 @@ impl UnspawnedCommsNode {
             node_identity.node_id()
         );
 
-        let listening_addr = Self::wait_listening(connection_manager_event_subscription).await?;
+        let (listening_addr, auxiliary_listening_addrs) =
+            Self::wait_listening(connection_manager_event_subscription).await?;
+        // A public address with port 0 advertises the port that the OS assigned to the listener
+        let public_address = node_identity.public_address();
+        if multiaddr_port(&public_address) == Some(0) {
+            if let Some(addr) = with_bound_port(&public_address, &listening_addr) {
+                node_identity.set_public_address(addr);
+            }
+        }
         let mut hidden_service = None;
         if let Some(mut ctl) = hidden_service_ctl {
             ctl.set_proxied_addr(listening_addr.clone());
@@ -237,16 +287,30 @@ impl UnspawnedCommsNode {
             "Your node's public address is '{}'",
             node_identity.public_address()
         );
+        let auxiliary_addresses = auxiliary_listening_addrs
+            .iter()
+            .filter_map(|addr| with_bound_port(&node_identity.public_address(), addr))
+            .collect::<Vec<_>>();
+        if !auxiliary_addresses.is_empty() {
+            info!(
+                target: LOG_TARGET,
+                "Your node's auxiliary public addresses are '{:?}'", auxiliary_addresses
+            );
+            node_identity.set_auxiliary_addresses(auxiliary_addresses);
+        }
 
         Ok(CommsNode {
             shutdown_signal,
             connection_manager_requester,
             connectivity_requester,
             listening_addr,
+            auxiliary_listening_addrs,
             node_identity,
             peer_manager,
             hidden_service,
             complete_signals: ext_context.drain_complete_signals(),
+            shutdown_reporter,
+            security_events,
         })
     }
 
@@ -274,6 +338,24 @@ impl UnspawnedCommsNode {
     pub fn shutdown_signal(&self) -> ShutdownSignal {
         self.shutdown_signal.clone()
     }
+
+    /// Returns the `ShutdownReporter` for this node. Components built on comms may use this to include their own
+    /// counts in the shutdown report.
+    pub fn shutdown_reporter(&self) -> ShutdownReporter {
+        self.shutdown_reporter.clone()
+    }
+
+    /// Returns the `SecurityEventPublisher` for this node. Components built on comms may use this to publish their own
+    /// security events.
+    pub fn security_events(&self) -> SecurityEventPublisher {
+        self.security_events.clone()
+    }
+
+    /// Returns the commands served on the admin socket, if one is configured. Components built on comms may register
+    /// their own commands. See [admin](crate::admin).
+    pub fn admin_commands(&self) -> AdminCommands {
+        self.admin_commands.clone()
+    }
 }
 
 /// CommsNode is a handle to a comms node.
@@ -295,10 +377,16 @@ pub struct CommsNode {
     peer_manager: Arc<PeerManager>,
     /// The resolved Ip-Tcp listening address.
     listening_addr: Multiaddr,
+    /// The resolved listening addresses of the auxiliary listeners
+    auxiliary_listening_addrs: Vec<Multiaddr>,
     /// `Some` if the comms node is configured to run via a hidden service, otherwise `None`
     hidden_service: Option<tor::HiddenService>,
     /// The 'reciprocal' shutdown signals for each comms service
     complete_signals: Vec<ShutdownSignal>,
+    /// Records the work that was dropped or in flight when comms shut down
+    shutdown_reporter: ShutdownReporter,
+    /// Publishes security-relevant events for external intrusion detection
+    security_events: SecurityEventPublisher,
 }
 
 impl CommsNode {
@@ -332,6 +420,11 @@ impl CommsNode {
         &self.listening_addr
     }
 
+    /// Return the addresses that the auxiliary listeners of this node are listening on
+    pub fn auxiliary_listening_addresses(&self) -> &[Multiaddr] {
+        &self.auxiliary_listening_addrs
+    }
+
     /// Return the Ip/Tcp address that this node is listening on
     pub fn hidden_service(&self) -> Option<&tor::HiddenService> {
         self.hidden_service.as_ref()
@@ -347,9 +440,26 @@ impl CommsNode {
         self.shutdown_signal.clone()
     }
 
+    /// Returns the `ShutdownReporter` for this node
+    pub fn shutdown_reporter(&self) -> ShutdownReporter {
+        self.shutdown_reporter.clone()
+    }
+
+    /// Get a subscription to security events. See [security_events](crate::security_events).
+    pub fn subscribe_security_events(&self) -> SecurityEventReceiver {
+        self.security_events.subscribe()
+    }
+
     /// Wait for comms to shutdown once the shutdown signal is triggered and for comms services to shut down.
-    /// The object is consumed to ensure that no handles/channels are kept after shutdown
+    /// The object is consumed to ensure that no handles/channels are kept after shutdown. The returned future resolves
+    /// to a [CommsShutdownReport](crate::CommsShutdownReport) summarising any work that was dropped.
     pub fn wait_until_shutdown(self) -> CommsShutdown {
-        CommsShutdown::new(iter::once(self.shutdown_signal).chain(self.complete_signals))
+        CommsShutdown::new(self.shutdown_signal, self.complete_signals, self.shutdown_reporter)
     }
 }
+
+/// Returns the public address with its port replaced by the port of the bound listener address, or None if either
+/// address does not have a port
+fn with_bound_port(public_address: &Multiaddr, bound_address: &Multiaddr) -> Option<Multiaddr> {
+    multiaddr_with_port(public_address, multiaddr_port(bound_address)?)
+}
@@ -26,6 +26,7 @@ use crate::{
     protocol::ProtocolExtensionError,
     tor::HiddenServiceControllerError,
 };
+use std::io;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -38,6 +39,8 @@ pub enum CommsBuilderError {
     NodeIdentityNotSet,
     #[error("Shutdown signa not set. Call `with_shutdown_signal(shutdown_signal)` on [CommsBuilder]")]
     ShutdownSignalNotSet,
+    #[error("Transport not set. Call `with_transport(transport)` on [CommsBuilder]")]
+    TransportNotSet,
     #[error("The PeerStorage was not provided to the CommsBuilder. Use `with_peer_storage` to set it.")]
     PeerStorageNotProvided,
     #[error("Unable to receive a ConnectionManagerEvent within timeout")]
@@ -50,4 +53,6 @@ pub enum CommsBuilderError {
     CommsProtocolExtensionError(#[from] ProtocolExtensionError),
     #[error("Failed to initialize tor hidden service: {0}")]
     HiddenServiceControllerError(#[from] HiddenServiceControllerError),
+    #[error("Failed to bind the admin socket: {0}")]
+    AdminSocketBindFailed(io::Error),
 }
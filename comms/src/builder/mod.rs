@@ -30,7 +30,7 @@ mod comms_node;
 pub use comms_node::{CommsNode, UnspawnedCommsNode};
 
 mod shutdown;
-pub use shutdown::CommsShutdown;
+pub use shutdown::{CommsShutdown, CommsShutdownReport, ShutdownReporter};
 
 mod error;
 pub use error::CommsBuilderError;
@@ -42,17 +42,26 @@ mod placeholder;
 mod tests;
 
 use crate::{
-    backoff::{Backoff, BoxedBackoff, ExponentialBackoff},
-    connection_manager::{ConnectionManagerConfig, ConnectionManagerRequester},
+    admin::AdminCommands,
+    backoff::{Backoff, BackoffPolicy, BoxedBackoff},
+    connection_manager::{
+        ConnectionManagerConfig,
+        ConnectionManagerRequester,
+        ListenerPortStrategy,
+        PeerVersionPolicy,
+    },
     connectivity::{ConnectivityConfig, ConnectivityRequester},
     multiaddr::Multiaddr,
-    peer_manager::{NodeIdentity, PeerManager},
+    peer_manager::{NodeIdentity, PeerDbRepairPolicy, PeerManager},
     protocol::ProtocolExtensions,
+    security_events::SecurityEventPublisher,
     tor,
+    transports::{BoxedTransport, Transport, TransportSocket},
     types::CommsDatabase,
+    utils::rng::SharedRng,
 };
 use futures::channel::mpsc;
-use std::{fs::File, sync::Arc};
+use std::{fs::File, net::SocketAddr, sync::Arc, time::Duration};
 use tari_shutdown::ShutdownSignal;
 use tokio::sync::broadcast;
 
@@ -60,11 +69,17 @@ use tokio::sync::broadcast;
 pub struct CommsBuilder {
     peer_storage: Option<CommsDatabase>,
     peer_storage_file_lock: Option<File>,
+    // The integrity check only applies to the LMDB peer database, which is not used in tests
+    #[cfg_attr(test, allow(dead_code))]
+    peer_db_repair_policy: PeerDbRepairPolicy,
     node_identity: Option<Arc<NodeIdentity>>,
     dial_backoff: BoxedBackoff,
     hidden_service_ctl: Option<tor::HiddenServiceController>,
     connection_manager_config: ConnectionManagerConfig,
     connectivity_config: ConnectivityConfig,
+    transport: Option<BoxedTransport>,
+    admin_socket_addr: Option<SocketAddr>,
+    rng: SharedRng,
 
     shutdown_signal: Option<ShutdownSignal>,
 }
@@ -74,11 +89,15 @@ impl Default for CommsBuilder {
         Self {
             peer_storage: None,
             peer_storage_file_lock: None,
+            peer_db_repair_policy: PeerDbRepairPolicy::default(),
             node_identity: None,
-            dial_backoff: Box::new(ExponentialBackoff::default()),
+            dial_backoff: Box::new(BackoffPolicy::default()),
             hidden_service_ctl: None,
             connection_manager_config: ConnectionManagerConfig::default(),
             connectivity_config: ConnectivityConfig::default(),
+            transport: None,
+            admin_socket_addr: None,
+            rng: SharedRng::os(),
             shutdown_signal: None,
         }
     }
@@ -104,6 +123,22 @@ impl CommsBuilder {
         self
     }
 
+    /// Set the transport used to listen for and dial connections. The transport is used when
+    /// [UnspawnedCommsNode::spawn](crate::UnspawnedCommsNode::spawn) is called.
+    pub fn with_transport<T>(mut self, transport: T) -> Self
+    where
+        T: Transport + Send + Sync + 'static,
+        T::Output: TransportSocket + 'static,
+        T::Error: 'static,
+        T::Inbound: 'static,
+        T::Listener: 'static,
+        T::ListenFuture: 'static,
+        T::DialFuture: 'static,
+    {
+        self.transport = Some(BoxedTransport::new(transport));
+        self
+    }
+
     /// Set the user agent string for this comms node. This string is sent once when establishing a connection.
     pub fn with_user_agent<T: ToString>(mut self, user_agent: T) -> Self {
         self.connection_manager_config.user_agent = user_agent.to_string();
@@ -126,6 +161,19 @@ impl CommsBuilder {
         self
     }
 
+    /// Listen for incoming connections on the given addresses in addition to the listener address. The ports that the
+    /// auxiliary listeners are bound to are advertised to peers alongside the public address.
+    pub fn with_auxiliary_listener_addresses(mut self, addresses: Vec<Multiaddr>) -> Self {
+        self.connection_manager_config.auxiliary_listener_addresses = addresses;
+        self
+    }
+
+    /// Set the strategy that determines the port that listener addresses with port 0 bind to
+    pub fn with_listener_port_strategy(mut self, strategy: ListenerPortStrategy) -> Self {
+        self.connection_manager_config.listener_port_strategy = strategy;
+        self
+    }
+
     pub fn with_listener_liveness_max_sessions(mut self, max_sessions: usize) -> Self {
         self.connection_manager_config.liveness_max_sessions = max_sessions;
         self
@@ -136,6 +184,14 @@ impl CommsBuilder {
         self
     }
 
+    /// Serve the admin commands (e.g. security events for external intrusion detection tooling) on the given loopback
+    /// TCP address. Spawning comms fails if the address is not a loopback address. See [admin](crate::admin) for
+    /// details.
+    pub fn with_admin_socket(mut self, addr: SocketAddr) -> Self {
+        self.admin_socket_addr = Some(addr);
+        self
+    }
+
     /// The maximum number of connection tasks that will be spawned at the same time. Once this limit is reached, peers
     /// attempting to connect will have to wait for another connection attempt to complete.
     pub fn with_max_simultaneous_inbound_connects(mut self, max_simultaneous_inbound_connects: usize) -> Self {
@@ -149,12 +205,89 @@ impl CommsBuilder {
         self
     }
 
+    /// The maximum time to wait for the transport to establish a connection to a peer address.
+    pub fn with_dial_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_manager_config.dial_connect_timeout = timeout;
+        self
+    }
+
+    /// The maximum time to wait for the noise handshake to complete.
+    pub fn with_noise_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_manager_config.noise_handshake_timeout = timeout;
+        self
+    }
+
+    /// The maximum time to wait for the peer identity exchange to complete.
+    pub fn with_identity_exchange_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_manager_config.identity_exchange_timeout = timeout;
+        self
+    }
+
+    /// Set the policy to apply to peers that do not support the required comms protocol version.
+    pub fn with_peer_version_policy(mut self, policy: PeerVersionPolicy) -> Self {
+        self.connection_manager_config.peer_version_policy = policy;
+        self
+    }
+
+    /// A `PeerClockSkew` event is published if a peer's clock differs from ours by more than `max_skew`.
+    pub fn with_max_clock_skew(mut self, max_skew: Duration) -> Self {
+        self.connection_manager_config.max_clock_skew = max_skew;
+        self
+    }
+
+    /// Require inbound peers to solve a handshake puzzle of the given difficulty while more than
+    /// `accept_rate_threshold` connections per second are being accepted.
+    pub fn with_handshake_puzzle(mut self, accept_rate_threshold: usize, difficulty: u8) -> Self {
+        self.connection_manager_config.puzzle_accept_rate_threshold = Some(accept_rate_threshold);
+        self.connection_manager_config.puzzle_difficulty = difficulty;
+        self
+    }
+
+    /// Set the thresholds after which the noise transport session of a connection is rekeyed. Rekeying is only
+    /// initiated with peers that support it. None disables the respective threshold.
+    pub fn with_noise_rekey(mut self, interval: Option<Duration>, max_bytes: Option<u64>) -> Self {
+        self.connection_manager_config.noise_rekey_interval = interval;
+        self.connection_manager_config.noise_rekey_bytes = max_bytes;
+        self
+    }
+
+    /// Request a handshake puzzle when dialing any peer, rather than only peers that advertise support for handshake
+    /// puzzles. Only enable this if all peers support handshake puzzles.
+    pub fn with_dial_puzzle(mut self, dial_with_puzzle: bool) -> Self {
+        self.connection_manager_config.dial_with_puzzle = dial_with_puzzle;
+        self
+    }
+
+    /// Peers that take longer than `threshold` to complete the identity exchange on `min_occurrences` consecutive
+    /// connections are considered slow and are deprioritised when selecting peers to broadcast to.
+    pub fn with_slow_peer_detection(mut self, threshold: Duration, min_occurrences: usize) -> Self {
+        self.connectivity_config.slow_peer_handshake_threshold = threshold;
+        self.connectivity_config.slow_peer_min_occurrences = min_occurrences;
+        self
+    }
+
     /// Sets the minimum required connectivity as a percentage of peers added to the connectivity manager peer set.
     pub fn with_min_connectivity(mut self, min_connectivity: f32) -> Self {
         self.connectivity_config.min_connectivity = min_connectivity;
         self
     }
 
+    /// Keep at most `max_size` peers in the peer database. The lowest-value peers are periodically removed when this is
+    /// exceeded. Pinned peers are never removed.
+    pub fn with_max_peer_db_size(mut self, max_size: usize) -> Self {
+        self.connectivity_config.max_peer_db_size = Some(max_size);
+        self
+    }
+
+    /// Set the source of randomness used for non-cryptographic decisions, such as selecting random peers to broadcast
+    /// to. Simulations can use a seeded `SharedRng` so that a run can be replayed exactly. A seeded `SharedRng` is
+    /// re-seeded from the node id, so comms nodes built with the same `SharedRng` do not share a sequence. The default
+    /// is the OS RNG.
+    pub fn with_rng(mut self, rng: SharedRng) -> Self {
+        self.rng = rng;
+        self
+    }
+
     /// Call to disable connection reaping. Usually you would want to have this enabled, however there are some test
     /// cases where disabling this is desirable.
     pub fn disable_connection_reaping(mut self) -> Self {
@@ -169,8 +302,15 @@ impl CommsBuilder {
         self
     }
 
+    /// Set what the startup integrity check does with invalid records in the peer database. The default is
+    /// [PeerDbRepairPolicy::Repair].
+    pub fn with_peer_db_repair_policy(mut self, policy: PeerDbRepairPolicy) -> Self {
+        self.peer_db_repair_policy = policy;
+        self
+    }
+
     /// Set the backoff that [ConnectionManager] uses when dialing peers. This is optional. If omitted the default
-    /// ExponentialBackoff is used. [ConnectionManager]: crate::connection_manager::next::ConnectionManager
+    /// BackoffPolicy is used. [ConnectionManager]: crate::connection_manager::next::ConnectionManager
     pub fn with_dial_backoff<T>(mut self, backoff: T) -> Self
     where T: Backoff + Send + Sync + 'static {
         self.dial_backoff = Box::new(backoff);
@@ -184,7 +324,10 @@ impl CommsBuilder {
             Some(storage) => {
                 // TODO: Peer manager should be refactored to be backend agnostic
                 #[cfg(not(test))]
-                PeerManager::migrate_lmdb(&storage.inner())?;
+                {
+                    PeerManager::migrate_lmdb(&storage.inner())?;
+                    PeerManager::check_lmdb_integrity(&storage.inner(), self.peer_db_repair_policy)?;
+                }
 
                 let peer_manager = PeerManager::new(storage, file_lock).map_err(CommsBuilderError::PeerManagerError)?;
                 Ok(Arc::new(peer_manager))
@@ -228,6 +371,9 @@ impl CommsBuilder {
             connectivity_rx,
             peer_manager,
             protocol_extensions: ProtocolExtensions::new(),
+            shutdown_reporter: ShutdownReporter::new(),
+            security_events: SecurityEventPublisher::default(),
+            admin_commands: AdminCommands::new(),
         })
     }
 }
@@ -20,34 +20,230 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use futures::{future, future::JoinAll, FutureExt};
+use futures::{future, future::BoxFuture, FutureExt};
+use log::*;
 use std::{
+    collections::BTreeMap,
+    fmt,
     future::Future,
     pin::Pin,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
+    time::Duration,
 };
 use tari_shutdown::ShutdownSignal;
+use tokio::time;
 
-/// Future which resolves once comms has shut down
+const LOG_TARGET: &str = "comms::shutdown";
+
+/// Summary of the work that was dropped or still in flight when comms shut down. This is returned from the
+/// [CommsShutdown](self::CommsShutdown) future so that embedders can verify a clean teardown.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommsShutdownReport {
+    /// The number of queued outbound messages that could not be sent before shutting down
+    pub num_outbound_messages_dropped: usize,
+    /// The number of peer connections that were closed
+    pub num_connections_closed: usize,
+    /// The number of comms tasks that completed their shutdown
+    pub num_tasks_awaited: usize,
+    /// The number of comms tasks that did not complete their shutdown within the shutdown timeout and were no longer
+    /// waited for
+    pub num_tasks_aborted: usize,
+    /// Counts recorded by components built on top of comms, for e.g. the number of store and forward messages held
+    pub component_counts: BTreeMap<&'static str, usize>,
+}
+
+impl CommsShutdownReport {
+    /// Returns true if no outbound messages were dropped and all tasks completed their shutdown
+    pub fn is_clean(&self) -> bool {
+        self.num_outbound_messages_dropped == 0 && self.num_tasks_aborted == 0
+    }
+}
+
+impl fmt::Display for CommsShutdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "outbound messages dropped: {}, connections closed: {}, tasks awaited: {}, tasks aborted: {}",
+            self.num_outbound_messages_dropped,
+            self.num_connections_closed,
+            self.num_tasks_awaited,
+            self.num_tasks_aborted
+        )?;
+        for (name, count) in &self.component_counts {
+            write!(f, ", {}: {}", name, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Handle used by comms components to record what they dropped or closed while shutting down. Clones of this handle
+/// record to the same report.
+#[derive(Clone, Default)]
+pub struct ShutdownReporter {
+    report: Arc<Mutex<CommsShutdownReport>>,
+    complete_signals: Arc<Mutex<Vec<ShutdownSignal>>>,
+}
+
+impl ShutdownReporter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add to the number of queued outbound messages that could not be sent
+    pub fn add_outbound_messages_dropped(&self, n: usize) {
+        acquire_lock!(self.report).num_outbound_messages_dropped += n;
+    }
+
+    /// Add to the number of peer connections that were closed
+    pub fn add_connections_closed(&self, n: usize) {
+        acquire_lock!(self.report).num_connections_closed += n;
+    }
+
+    /// Record a count for a component outside of comms. Recording the same name again replaces the previous count.
+    pub fn set_component_count(&self, name: &'static str, count: usize) {
+        acquire_lock!(self.report).component_counts.insert(name, count);
+    }
+
+    /// Register a signal that is triggered once a component outside of comms has completed its shutdown. The
+    /// shutdown report is only produced once registered components have completed (or the shutdown timeout has
+    /// elapsed), so that any counts they record are included.
+    pub fn register_complete_signal(&self, signal: ShutdownSignal) {
+        acquire_lock!(self.complete_signals).push(signal);
+    }
+
+    fn take_complete_signals(&self) -> Vec<ShutdownSignal> {
+        acquire_lock!(self.complete_signals).drain(..).collect()
+    }
+
+    fn set_tasks(&self, num_awaited: usize, num_aborted: usize) {
+        let mut report = acquire_lock!(self.report);
+        report.num_tasks_awaited = num_awaited;
+        report.num_tasks_aborted = num_aborted;
+    }
+
+    fn report(&self) -> CommsShutdownReport {
+        acquire_lock!(self.report).clone()
+    }
+}
+
+/// Future which resolves with a [CommsShutdownReport](self::CommsShutdownReport) once comms has shut down
 #[must_use = "futures do nothing unless you `.await` or poll them"]
 pub struct CommsShutdown {
-    signals: JoinAll<ShutdownSignal>,
+    shutdown_signal: Option<ShutdownSignal>,
+    complete_signals: Vec<ShutdownSignal>,
+    reporter: ShutdownReporter,
+    timeout: Option<Duration>,
+    fut: Option<BoxFuture<'static, CommsShutdownReport>>,
 }
 
 impl CommsShutdown {
-    pub fn new<I>(triggers: I) -> Self
+    pub fn new<I>(shutdown_signal: ShutdownSignal, complete_signals: I, reporter: ShutdownReporter) -> Self
     where I: IntoIterator<Item = ShutdownSignal> {
         Self {
-            signals: future::join_all(triggers),
+            shutdown_signal: Some(shutdown_signal),
+            complete_signals: complete_signals.into_iter().collect(),
+            reporter,
+            timeout: None,
+            fut: None,
         }
     }
+
+    /// Stop waiting for tasks that have not completed their shutdown within `timeout` of the shutdown signal being
+    /// triggered. These tasks are counted as aborted in the report. By default, all tasks are waited for.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 }
 
 impl Future for CommsShutdown {
-    type Output = ();
+    type Output = CommsShutdownReport;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let _ = futures::ready!(self.signals.poll_unpin(cx));
-        Poll::Ready(())
+        if self.fut.is_none() {
+            let shutdown_signal = self
+                .shutdown_signal
+                .take()
+                .expect("CommsShutdown polled after completion");
+            let complete_signals = self.complete_signals.drain(..).collect();
+            let fut = wait_for_shutdown(shutdown_signal, complete_signals, self.reporter.clone(), self.timeout);
+            self.fut = Some(fut.boxed());
+        }
+
+        self.fut.as_mut().expect("fut was set above").poll_unpin(cx)
+    }
+}
+
+async fn wait_for_shutdown(
+    shutdown_signal: ShutdownSignal,
+    complete_signals: Vec<ShutdownSignal>,
+    reporter: ShutdownReporter,
+    timeout: Option<Duration>,
+) -> CommsShutdownReport
+{
+    let _ = shutdown_signal.await;
+
+    let signals = complete_signals.into_iter().chain(reporter.take_complete_signals());
+    let completed = match timeout {
+        Some(timeout) => {
+            future::join_all(signals.map(|signal| time::timeout(timeout, signal).map(|res| res.is_ok()))).await
+        },
+        None => future::join_all(signals.map(|signal| signal.map(|_| true))).await,
+    };
+    let num_awaited = completed.iter().filter(|c| **c).count();
+    reporter.set_tasks(num_awaited, completed.len() - num_awaited);
+
+    let report = reporter.report();
+    if report.is_clean() {
+        info!(target: LOG_TARGET, "Comms shut down cleanly ({})", report);
+    } else {
+        warn!(target: LOG_TARGET, "Comms shut down with work outstanding ({})", report);
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::runtime;
+    use tari_shutdown::Shutdown;
+
+    #[runtime::test_basic]
+    async fn report_includes_recorded_counts() {
+        let mut shutdown = Shutdown::new();
+        let component_shutdown = Shutdown::new();
+        let reporter = ShutdownReporter::new();
+        reporter.register_complete_signal(component_shutdown.to_signal());
+        reporter.add_connections_closed(2);
+        reporter.set_component_count("saf_messages_held", 5);
+
+        let comms_shutdown = CommsShutdown::new(shutdown.to_signal(), vec![], reporter.clone());
+        shutdown.trigger().unwrap();
+        drop(component_shutdown);
+
+        let report = comms_shutdown.await;
+        assert_eq!(report.num_connections_closed, 2);
+        assert_eq!(report.num_tasks_awaited, 1);
+        assert_eq!(report.num_tasks_aborted, 0);
+        assert_eq!(report.component_counts.get("saf_messages_held"), Some(&5));
+        assert!(report.is_clean());
+    }
+
+    #[runtime::test_basic]
+    async fn tasks_not_completed_within_timeout_are_aborted() {
+        let mut shutdown = Shutdown::new();
+        let task_shutdown = Shutdown::new();
+        let reporter = ShutdownReporter::new();
+
+        let comms_shutdown = CommsShutdown::new(shutdown.to_signal(), vec![task_shutdown.to_signal()], reporter)
+            .with_timeout(Duration::from_millis(10));
+        shutdown.trigger().unwrap();
+
+        let report = comms_shutdown.await;
+        assert_eq!(report.num_tasks_awaited, 0);
+        assert_eq!(report.num_tasks_aborted, 1);
+        assert!(!report.is_clean());
+        drop(task_shutdown);
     }
 }
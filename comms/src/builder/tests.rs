@@ -22,7 +22,7 @@
 
 use crate::{
     backoff::ConstantBackoff,
-    builder::CommsBuilder,
+    builder::{CommsBuilder, CommsBuilderError},
     connection_manager::ConnectionManagerEvent,
     memsocket,
     message::{InboundMessage, OutboundMessage},
@@ -366,6 +366,82 @@ async fn peer_to_peer_messaging_simultaneous() {
     comms_node2.wait_until_shutdown().await;
 }
 
+#[runtime::test_basic]
+async fn spawn_with_builder_transport() {
+    let shutdown = Shutdown::new();
+    let addr = format!("/memory/{}", memsocket::acquire_next_memsocket_port())
+        .parse::<Multiaddr>()
+        .unwrap();
+    let node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+    node_identity.set_public_address(addr.clone());
+
+    let err = CommsBuilder::new()
+        .with_shutdown_signal(shutdown.to_signal())
+        .with_listener_address(addr.clone())
+        .with_peer_storage(HashmapDatabase::new(), None)
+        .with_node_identity(node_identity.clone())
+        .build()
+        .unwrap()
+        .spawn()
+        .await
+        .err()
+        .unwrap();
+    unpack_enum!(CommsBuilderError::TransportNotSet = err);
+
+    let comms_node = CommsBuilder::new()
+        .with_shutdown_signal(shutdown.to_signal())
+        .with_listener_address(addr.clone())
+        .with_peer_storage(HashmapDatabase::new(), None)
+        .with_node_identity(node_identity)
+        .with_transport(MemoryTransport)
+        .build()
+        .unwrap()
+        .spawn()
+        .await
+        .unwrap();
+
+    assert_eq!(*comms_node.listening_address(), addr);
+
+    drop(shutdown);
+    comms_node.wait_until_shutdown().await;
+}
+
+#[runtime::test_basic]
+async fn auxiliary_listeners_advertise_bound_ports() {
+    let shutdown = Shutdown::new();
+    let addr = "/memory/0".parse::<Multiaddr>().unwrap();
+    let node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+    node_identity.set_public_address(addr.clone());
+
+    let comms_node = CommsBuilder::new()
+        .with_shutdown_signal(shutdown.to_signal())
+        .with_listener_address(addr.clone())
+        .with_auxiliary_listener_addresses(vec![addr.clone(), addr])
+        .with_peer_storage(HashmapDatabase::new(), None)
+        .with_node_identity(node_identity.clone())
+        .with_transport(MemoryTransport)
+        .build()
+        .unwrap()
+        .spawn()
+        .await
+        .unwrap();
+
+    unpack_enum!(Protocol::Memory(port) = comms_node.listening_address().iter().next().unwrap());
+    assert!(port > 0);
+    assert_eq!(node_identity.public_address(), *comms_node.listening_address());
+    assert_eq!(comms_node.auxiliary_listening_addresses().len(), 2);
+    assert_eq!(
+        node_identity.auxiliary_addresses(),
+        comms_node.auxiliary_listening_addresses()
+    );
+    let public_addresses = node_identity.public_addresses();
+    assert_eq!(public_addresses.len(), 3);
+    assert!(has_unique_elements(public_addresses));
+
+    drop(shutdown);
+    comms_node.wait_until_shutdown().await;
+}
+
 fn has_unique_elements<T>(iter: T) -> bool
 where
     T: IntoIterator,
@@ -0,0 +1,487 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//! Fragmentation and reassembly of payloads that are larger than the maximum frame size of a substream.
+//!
+//! A payload is split into sequence-numbered fragments that share a message id. Each fragment fits within a single
+//! frame and is prefixed with an 8 byte header:
+//!
+//! ```text
+//! | message id (u32 BE) | sequence number (u16 BE) | total fragments (u16 BE) | payload ... |
+//! ```
+//!
+//! The receiving side passes every frame to a [Reassembler](self::Reassembler), which returns the complete payload
+//! once all fragments for a message have been received. The number of partially received messages and the bytes they
+//! hold are bounded. Partially received messages that have timed out are discarded when the owner of the reassembler
+//! calls `prune_expired`, which should be done on an interval.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::{
+    cmp,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// The size in bytes of the header prefixed to each fragment
+pub const FRAGMENT_HEADER_LENGTH: usize = 8;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum FragmentError {
+    #[error("Maximum frame size must be larger than the fragment header")]
+    FrameSizeTooSmall,
+    #[error("A payload of {0} bytes requires too many fragments")]
+    TooManyFragments(usize),
+    #[error("Frame is too short to contain a fragment header")]
+    TruncatedHeader,
+    #[error("Fragment {seq} is out of range for a message with {total} fragments")]
+    SequenceOutOfRange { seq: u16, total: u16 },
+    #[error("Fragment for message {message_id} has a total of {got} but earlier fragments had a total of {expected}")]
+    InconsistentTotal { message_id: u32, expected: u16, got: u16 },
+    #[error("Message {message_id} exceeds the maximum reassembled size of {max_size} bytes")]
+    MessageTooLarge { message_id: u32, max_size: usize },
+    #[error("Message {message_id} has {total} fragments which exceeds the maximum of {max}")]
+    FragmentLimitExceeded { message_id: u32, total: u16, max: u16 },
+    #[error("Cannot start reassembling message {message_id}: the maximum of {max} pending messages has been reached")]
+    TooManyPendingMessages { message_id: u32, max: usize },
+    #[error("Message {message_id} was discarded: the maximum of {max_bytes} buffered bytes has been reached")]
+    BufferLimitExceeded { message_id: u32, max_bytes: usize },
+}
+
+/// Splits payloads into fragments that each fit within `max_frame_size`. Each payload is assigned the next message
+/// id.
+#[derive(Debug, Clone)]
+pub struct Fragmenter {
+    max_frame_size: usize,
+    next_message_id: u32,
+}
+
+impl Fragmenter {
+    pub fn new(max_frame_size: usize) -> Result<Self, FragmentError> {
+        if max_frame_size <= FRAGMENT_HEADER_LENGTH {
+            return Err(FragmentError::FrameSizeTooSmall);
+        }
+        Ok(Self {
+            max_frame_size,
+            next_message_id: 0,
+        })
+    }
+
+    /// Split the payload into fragments. An empty payload results in a single empty fragment.
+    pub fn fragment(&mut self, payload: Bytes) -> Result<Vec<Bytes>, FragmentError> {
+        let chunk_size = self.max_frame_size - FRAGMENT_HEADER_LENGTH;
+        let num_fragments = cmp::max(1, (payload.len() + chunk_size - 1) / chunk_size);
+        if num_fragments > u16::MAX as usize {
+            return Err(FragmentError::TooManyFragments(payload.len()));
+        }
+
+        let message_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+
+        let fragments = (0..num_fragments)
+            .map(|seq| {
+                let start = seq * chunk_size;
+                let end = cmp::min(start + chunk_size, payload.len());
+                let mut buf = BytesMut::with_capacity(FRAGMENT_HEADER_LENGTH + end - start);
+                buf.put_u32(message_id);
+                buf.put_u16(seq as u16);
+                buf.put_u16(num_fragments as u16);
+                buf.extend_from_slice(&payload[start..end]);
+                buf.freeze()
+            })
+            .collect();
+
+        Ok(fragments)
+    }
+}
+
+/// Limits for a [Reassembler](self::Reassembler)
+#[derive(Debug, Clone)]
+pub struct ReassemblerConfig {
+    /// Messages larger than this are rejected
+    pub max_message_size: usize,
+    /// Messages made up of more fragments than this are rejected before any state is kept for them
+    pub max_fragments: u16,
+    /// The maximum number of partially reassembled messages
+    pub max_pending_messages: usize,
+    /// The maximum number of bytes held across all partially reassembled messages
+    pub max_buffered_bytes: usize,
+    /// Partially reassembled messages are discarded by `prune_expired` once this much time has passed since their
+    /// first fragment was received
+    pub timeout: Duration,
+}
+
+impl Default for ReassemblerConfig {
+    fn default() -> Self {
+        Self {
+            max_message_size: 4 * 1024 * 1024,
+            max_fragments: 1024,
+            max_pending_messages: 16,
+            max_buffered_bytes: 8 * 1024 * 1024,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+struct PendingMessage {
+    total: u16,
+    fragments: HashMap<u16, Bytes>,
+    size: usize,
+    started_at: Instant,
+}
+
+/// Reassembles fragments produced by a [Fragmenter](self::Fragmenter) into complete payloads
+pub struct Reassembler {
+    config: ReassemblerConfig,
+    pending: HashMap<u32, PendingMessage>,
+    num_buffered_bytes: usize,
+}
+
+impl Reassembler {
+    pub fn new(config: ReassemblerConfig) -> Self {
+        Self {
+            config,
+            pending: HashMap::new(),
+            num_buffered_bytes: 0,
+        }
+    }
+
+    /// Add a received frame. Returns the complete payload if this frame is the last outstanding fragment of its
+    /// message, otherwise None. Duplicate fragments are ignored. If an error is returned, any partially reassembled
+    /// state for the message is discarded.
+    pub fn push(&mut self, mut frame: Bytes) -> Result<Option<Bytes>, FragmentError> {
+        if frame.len() < FRAGMENT_HEADER_LENGTH {
+            return Err(FragmentError::TruncatedHeader);
+        }
+        let message_id = frame.get_u32();
+        let seq = frame.get_u16();
+        let total = frame.get_u16();
+        if seq >= total {
+            self.remove_pending(message_id);
+            return Err(FragmentError::SequenceOutOfRange { seq, total });
+        }
+        if total > self.config.max_fragments {
+            self.remove_pending(message_id);
+            return Err(FragmentError::FragmentLimitExceeded {
+                message_id,
+                total,
+                max: self.config.max_fragments,
+            });
+        }
+        if frame.len() > self.config.max_message_size {
+            self.remove_pending(message_id);
+            return Err(FragmentError::MessageTooLarge {
+                message_id,
+                max_size: self.config.max_message_size,
+            });
+        }
+
+        if total == 1 {
+            return Ok(Some(frame));
+        }
+
+        if !self.pending.contains_key(&message_id) && self.pending.len() >= self.config.max_pending_messages {
+            return Err(FragmentError::TooManyPendingMessages {
+                message_id,
+                max: self.config.max_pending_messages,
+            });
+        }
+
+        let pending = self.pending.entry(message_id).or_insert_with(|| PendingMessage {
+            total,
+            fragments: HashMap::new(),
+            size: 0,
+            started_at: Instant::now(),
+        });
+
+        if pending.total != total {
+            let expected = pending.total;
+            self.remove_pending(message_id);
+            return Err(FragmentError::InconsistentTotal {
+                message_id,
+                expected,
+                got: total,
+            });
+        }
+
+        if pending.fragments.contains_key(&seq) {
+            return Ok(None);
+        }
+
+        if pending.size + frame.len() > self.config.max_message_size {
+            self.remove_pending(message_id);
+            return Err(FragmentError::MessageTooLarge {
+                message_id,
+                max_size: self.config.max_message_size,
+            });
+        }
+        if self.num_buffered_bytes + frame.len() > self.config.max_buffered_bytes {
+            self.remove_pending(message_id);
+            return Err(FragmentError::BufferLimitExceeded {
+                message_id,
+                max_bytes: self.config.max_buffered_bytes,
+            });
+        }
+        pending.size += frame.len();
+        self.num_buffered_bytes += frame.len();
+        pending.fragments.insert(seq, frame);
+
+        if pending.fragments.len() < pending.total as usize {
+            return Ok(None);
+        }
+
+        let mut pending = self.remove_pending(message_id).expect("pending message exists");
+        let mut buf = BytesMut::with_capacity(pending.size);
+        for seq in 0..pending.total {
+            let fragment = pending
+                .fragments
+                .remove(&seq)
+                .expect("all fragments have been received");
+            buf.extend_from_slice(&fragment);
+        }
+        Ok(Some(buf.freeze()))
+    }
+
+    fn remove_pending(&mut self, message_id: u32) -> Option<PendingMessage> {
+        let pending = self.pending.remove(&message_id)?;
+        self.num_buffered_bytes -= pending.size;
+        Some(pending)
+    }
+
+    /// Discard partially reassembled messages that have exceeded the reassembly timeout. Returns the number of
+    /// messages that were discarded.
+    pub fn prune_expired(&mut self) -> usize {
+        let timeout = self.config.timeout;
+        let num_pending = self.pending.len();
+        let mut num_bytes_discarded = 0;
+        self.pending.retain(|_, pending| {
+            let is_expired = pending.started_at.elapsed() >= timeout;
+            if is_expired {
+                num_bytes_discarded += pending.size;
+            }
+            !is_expired
+        });
+        self.num_buffered_bytes -= num_bytes_discarded;
+        num_pending - self.pending.len()
+    }
+
+    /// Returns the number of messages that are partially reassembled
+    pub fn num_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the number of bytes held for partially reassembled messages
+    pub fn num_buffered_bytes(&self) -> usize {
+        self.num_buffered_bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::OsRng, RngCore};
+    use std::thread;
+
+    fn config(max_message_size: usize, timeout: Duration) -> ReassemblerConfig {
+        ReassemblerConfig {
+            max_message_size,
+            timeout,
+            ..Default::default()
+        }
+    }
+
+    fn random_payload(len: usize) -> Bytes {
+        let mut buf = vec![0u8; len];
+        OsRng.fill_bytes(&mut buf);
+        buf.into()
+    }
+
+    #[test]
+    fn fragment_and_reassemble() {
+        let mut fragmenter = Fragmenter::new(100).unwrap();
+        let mut reassembler = Reassembler::new(config(10_000, Duration::from_secs(10)));
+
+        let payload = random_payload(1000);
+        let mut fragments = fragmenter.fragment(payload.clone()).unwrap();
+        assert_eq!(fragments.len(), 11);
+        assert!(fragments.iter().all(|f| f.len() <= 100));
+
+        // Fragments may arrive out of order
+        let last = fragments.remove(3);
+        for fragment in fragments {
+            assert!(reassembler.push(fragment).unwrap().is_none());
+        }
+        assert_eq!(reassembler.num_pending(), 1);
+        let reassembled = reassembler.push(last).unwrap().unwrap();
+        assert_eq!(reassembled, payload);
+        assert_eq!(reassembler.num_pending(), 0);
+    }
+
+    #[test]
+    fn single_and_empty_payloads() {
+        let mut fragmenter = Fragmenter::new(100).unwrap();
+        let mut reassembler = Reassembler::new(config(10_000, Duration::from_secs(10)));
+
+        let fragments = fragmenter.fragment(Bytes::new()).unwrap();
+        assert_eq!(fragments.len(), 1);
+        assert!(reassembler.push(fragments[0].clone()).unwrap().unwrap().is_empty());
+
+        let payload = random_payload(92);
+        let fragments = fragmenter.fragment(payload.clone()).unwrap();
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(reassembler.push(fragments[0].clone()).unwrap().unwrap(), payload);
+    }
+
+    #[test]
+    fn interleaved_messages_and_duplicates() {
+        let mut fragmenter = Fragmenter::new(20).unwrap();
+        let mut reassembler = Reassembler::new(config(10_000, Duration::from_secs(10)));
+
+        let payload1 = random_payload(50);
+        let payload2 = random_payload(30);
+        let fragments1 = fragmenter.fragment(payload1.clone()).unwrap();
+        let fragments2 = fragmenter.fragment(payload2.clone()).unwrap();
+
+        assert!(reassembler.push(fragments1[0].clone()).unwrap().is_none());
+        assert!(reassembler.push(fragments2[0].clone()).unwrap().is_none());
+        assert!(reassembler.push(fragments1[0].clone()).unwrap().is_none());
+        assert!(reassembler.push(fragments1[1].clone()).unwrap().is_none());
+        assert_eq!(reassembler.push(fragments2[2].clone()).unwrap(), None);
+        assert_eq!(reassembler.push(fragments2[1].clone()).unwrap().unwrap(), payload2);
+        assert_eq!(reassembler.push(fragments1[2].clone()).unwrap(), None);
+        assert_eq!(reassembler.push(fragments1[3].clone()).unwrap(), None);
+        assert_eq!(reassembler.push(fragments1[4].clone()).unwrap().unwrap(), payload1);
+    }
+
+    #[test]
+    fn rejects_oversized_messages() {
+        let mut fragmenter = Fragmenter::new(20).unwrap();
+        let mut reassembler = Reassembler::new(config(30, Duration::from_secs(10)));
+
+        let fragments = fragmenter.fragment(random_payload(50)).unwrap();
+        reassembler.push(fragments[0].clone()).unwrap();
+        reassembler.push(fragments[1].clone()).unwrap();
+        let err = reassembler.push(fragments[2].clone()).unwrap_err();
+        assert_eq!(err, FragmentError::MessageTooLarge {
+            message_id: 0,
+            max_size: 30
+        });
+        assert_eq!(reassembler.num_pending(), 0);
+    }
+
+    #[test]
+    fn rejects_invalid_fragments() {
+        let mut reassembler = Reassembler::new(config(1000, Duration::from_secs(10)));
+        let err = reassembler.push(Bytes::from_static(&[0, 0, 0])).unwrap_err();
+        assert_eq!(err, FragmentError::TruncatedHeader);
+
+        let err = reassembler
+            .push(Bytes::from_static(&[0, 0, 0, 1, 0, 2, 0, 2]))
+            .unwrap_err();
+        assert_eq!(err, FragmentError::SequenceOutOfRange { seq: 2, total: 2 });
+
+        reassembler.push(Bytes::from_static(&[0, 0, 0, 1, 0, 0, 0, 2])).unwrap();
+        let err = reassembler
+            .push(Bytes::from_static(&[0, 0, 0, 1, 0, 1, 0, 3]))
+            .unwrap_err();
+        assert_eq!(err, FragmentError::InconsistentTotal {
+            message_id: 1,
+            expected: 2,
+            got: 3
+        });
+    }
+
+    #[test]
+    fn discards_expired_messages() {
+        let mut fragmenter = Fragmenter::new(20).unwrap();
+        let mut reassembler = Reassembler::new(config(1000, Duration::from_millis(1)));
+
+        let fragments = fragmenter.fragment(random_payload(50)).unwrap();
+        reassembler.push(fragments[0].clone()).unwrap();
+        assert_eq!(reassembler.num_pending(), 1);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(reassembler.prune_expired(), 1);
+        assert_eq!(reassembler.num_pending(), 0);
+    }
+
+    #[test]
+    fn rejects_too_many_fragments() {
+        let mut reassembler = Reassembler::new(ReassemblerConfig {
+            max_fragments: 10,
+            ..Default::default()
+        });
+        let err = reassembler
+            .push(Bytes::from_static(&[0, 0, 0, 1, 0, 0, 0xff, 0xff]))
+            .unwrap_err();
+        assert_eq!(err, FragmentError::FragmentLimitExceeded {
+            message_id: 1,
+            total: u16::MAX,
+            max: 10
+        });
+        assert_eq!(reassembler.num_pending(), 0);
+    }
+
+    #[test]
+    fn limits_pending_messages_and_buffered_bytes() {
+        let mut fragmenter = Fragmenter::new(20).unwrap();
+        let mut reassembler = Reassembler::new(ReassemblerConfig {
+            max_pending_messages: 2,
+            max_buffered_bytes: 30,
+            ..Default::default()
+        });
+
+        let fragments1 = fragmenter.fragment(random_payload(30)).unwrap();
+        let fragments2 = fragmenter.fragment(random_payload(30)).unwrap();
+        let fragments3 = fragmenter.fragment(random_payload(30)).unwrap();
+        reassembler.push(fragments1[0].clone()).unwrap();
+        reassembler.push(fragments2[0].clone()).unwrap();
+        assert_eq!(reassembler.num_buffered_bytes(), 24);
+        let err = reassembler.push(fragments3[0].clone()).unwrap_err();
+        assert_eq!(err, FragmentError::TooManyPendingMessages { message_id: 2, max: 2 });
+
+        let err = reassembler.push(fragments2[1].clone()).unwrap_err();
+        assert_eq!(err, FragmentError::BufferLimitExceeded {
+            message_id: 1,
+            max_bytes: 30
+        });
+        assert_eq!(reassembler.num_pending(), 1);
+        assert_eq!(reassembler.num_buffered_bytes(), 12);
+
+        // Room has been made for message 3
+        reassembler.push(fragments3[0].clone()).unwrap();
+        assert_eq!(reassembler.num_pending(), 2);
+    }
+
+    #[test]
+    fn expired_messages_are_only_discarded_when_pruned() {
+        let mut fragmenter = Fragmenter::new(20).unwrap();
+        let mut reassembler = Reassembler::new(config(1000, Duration::from_millis(1)));
+
+        let fragments = fragmenter.fragment(random_payload(24)).unwrap();
+        reassembler.push(fragments[0].clone()).unwrap();
+        thread::sleep(Duration::from_millis(10));
+        let other = fragmenter.fragment(random_payload(24)).unwrap();
+        reassembler.push(other[0].clone()).unwrap();
+        assert_eq!(reassembler.num_pending(), 2);
+        assert_eq!(reassembler.prune_expired(), 1);
+        assert_eq!(reassembler.num_buffered_bytes(), 12);
+    }
+}
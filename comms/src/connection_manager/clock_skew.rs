@@ -0,0 +1,98 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::peer_manager::NodeId;
+use std::collections::VecDeque;
+
+/// Keeps the most recent clock skew measurements from identity exchanges with distinct peers, so that the skew of this
+/// node's clock relative to the network can be estimated.
+#[derive(Debug)]
+pub(super) struct ClockSkewTracker {
+    samples: VecDeque<(NodeId, i64)>,
+    capacity: usize,
+}
+
+impl ClockSkewTracker {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record the clock skew of a peer. Any previous measurement for the peer is replaced.
+    pub fn record(&mut self, node_id: NodeId, skew: i64) {
+        if let Some(pos) = self.samples.iter().position(|(n, _)| *n == node_id) {
+            self.samples.remove(pos);
+        }
+        if self.samples.len() >= self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((node_id, skew));
+    }
+
+    /// Returns the estimated skew in seconds of this node's clock relative to the median of the peers' clocks. The
+    /// estimate is positive if our clock is ahead. None is returned if no measurements have been recorded.
+    pub fn estimated_local_skew(&self) -> Option<i64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut skews = self.samples.iter().map(|(_, skew)| *skew).collect::<Vec<_>>();
+        skews.sort_unstable();
+        let mid = skews.len() / 2;
+        let median = if skews.len() % 2 == 0 {
+            (skews[mid - 1] + skews[mid]) / 2
+        } else {
+            skews[mid]
+        };
+        // A peer's skew is measured relative to our clock, so if the median peer is behind us we are ahead of it
+        Some(-median)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::node_id;
+
+    #[test]
+    fn estimated_local_skew() {
+        let mut tracker = ClockSkewTracker::new(3);
+        assert!(tracker.estimated_local_skew().is_none());
+
+        let node_ids = (0..4).map(|_| node_id::random()).collect::<Vec<_>>();
+        tracker.record(node_ids[0].clone(), -100);
+        assert_eq!(tracker.estimated_local_skew(), Some(100));
+
+        tracker.record(node_ids[1].clone(), -10);
+        tracker.record(node_ids[2].clone(), 20);
+        assert_eq!(tracker.estimated_local_skew(), Some(10));
+
+        // A new measurement for a known peer replaces the old one
+        tracker.record(node_ids[0].clone(), -4);
+        assert_eq!(tracker.estimated_local_skew(), Some(4));
+
+        // The oldest measurement is discarded once the capacity is reached
+        tracker.record(node_ids[3].clone(), 30);
+        assert_eq!(tracker.estimated_local_skew(), Some(-20));
+    }
+}
@@ -20,7 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use super::types::ConnectionDirection;
+use super::types::{max_protocol_version, supports_protocol_version, ConnectionDirection, PeerVersionPolicy};
 use crate::{
     connection_manager::error::ConnectionManagerError,
     multiaddr::{Multiaddr, Protocol},
@@ -28,8 +28,9 @@ use crate::{
     peer_manager::{NodeId, NodeIdentity, Peer, PeerFeatures, PeerFlags},
     proto::identity::PeerIdentityMsg,
     protocol,
-    protocol::ProtocolId,
+    protocol::{ProtocolId, SignedPeerIdentity},
     types::CommsPublicKey,
+    utils::multiaddr::multiaddr_ip,
     PeerManager,
 };
 use futures::StreamExt;
@@ -83,12 +84,18 @@ pub fn is_valid_base_node_node_id(node_id: &NodeId, public_key: &CommsPublicKey)
 ///
 /// The following process is used to validate the peer:
 /// 1. Check the offered node identity is a valid base node identity (TODO: This won't work for DAN nodes)
+/// 1. Check that the identity was signed by the authenticated public key, if the peer's protocol version includes
+///    identity signing
+/// 1. Check the peer's protocol version against the `PeerVersionPolicy`
 /// 1. Check if we know the peer, if so, is the peer banned, if so, return an error
 /// 1. Check that the offered addresses are valid
-/// 1. Update or add the peer, returning it's NodeId
+/// 1. Update or add the peer, recording the estimated clock skew and the timestamp of a signed identity, returning
+///    it's NodeId
+/// 1. If the addresses of a known peer have changed, return the signed identity so that the change can be published
 ///
 /// If the `allow_test_addrs` parameter is true, loopback, local link and other addresses normally not considered valid
 /// for p2p comms will be accepted.
+#[allow(clippy::too_many_arguments)]
 pub async fn validate_and_add_peer_from_peer_identity(
     peer_manager: &PeerManager,
     known_peer: Option<Peer>,
@@ -96,7 +103,9 @@ pub async fn validate_and_add_peer_from_peer_identity(
     mut peer_identity: PeerIdentityMsg,
     dialed_addr: Option<&Multiaddr>,
     allow_test_addrs: bool,
-) -> Result<(NodeId, Vec<ProtocolId>), ConnectionManagerError>
+    version_policy: PeerVersionPolicy,
+    clock_skew: Option<i64>,
+) -> Result<(NodeId, Vec<ProtocolId>, Option<SignedPeerIdentity>), ConnectionManagerError>
 {
     // let peer_manager = peer_manager.inner();
     // Validate the given node id for base nodes
@@ -107,6 +116,31 @@ pub async fn validate_and_add_peer_from_peer_identity(
         return Err(ConnectionManagerError::PeerIdentityInvalidNodeId);
     }
 
+    // Peers that do not sign their identity are still authenticated by the noise handshake, but their identity cannot
+    // be relayed to other nodes
+    let is_signed = supports_protocol_version(
+        peer_identity.protocol_versions,
+        protocol::IDENTITY_SIGNATURE_PROTOCOL_VERSION,
+    );
+    if is_signed && !protocol::verify_identity_signature(&authenticated_public_key, &peer_identity) {
+        return Err(ConnectionManagerError::PeerIdentityInvalidSignature);
+    }
+
+    check_peer_version(&peer_node_id, &peer_identity, version_policy)?;
+
+    let signed_identity = if is_signed {
+        Some(SignedPeerIdentity::new(
+            authenticated_public_key.clone(),
+            peer_identity.clone(),
+        ))
+    } else {
+        None
+    };
+    let identity_timestamp = signed_identity
+        .as_ref()
+        .and_then(|identity| identity.timestamp())
+        .map(|timestamp| timestamp.naive_utc());
+
     let addresses = peer_identity
         .addresses
         .into_iter()
@@ -127,8 +161,14 @@ pub async fn validate_and_add_peer_from_peer_identity(
         .collect::<Vec<_>>();
 
     peer_identity.user_agent.truncate(MAX_USER_AGENT_LEN);
+    let advertised_metadata = peer_identity
+        .metadata
+        .into_iter()
+        .map(|entry| (entry.key, entry.value))
+        .collect::<Vec<_>>();
 
     // Add or update the peer
+    let mut address_update = None;
     let peer = match known_peer {
         Some(mut peer) => {
             debug!(
@@ -137,7 +177,14 @@ pub async fn validate_and_add_peer_from_peer_identity(
                 peer.node_id.short_str()
             );
             peer.connection_stats.set_connection_success();
-            peer.addresses = addresses.into();
+            if peer.addresses.update_advertised_addresses(addresses) {
+                debug!(
+                    target: LOG_TARGET,
+                    "Peer '{}' addresses changed. Addresses that are no longer advertised will be re-validated.",
+                    peer.node_id.short_str()
+                );
+                address_update = signed_identity;
+            }
             peer.set_offline(false);
             if let Some(addr) = dialed_addr {
                 peer.addresses.mark_successful_connection_attempt(addr);
@@ -145,6 +192,12 @@ pub async fn validate_and_add_peer_from_peer_identity(
             peer.features = PeerFeatures::from_bits_truncate(peer_identity.features);
             peer.supported_protocols = supported_protocols.clone();
             peer.user_agent = peer_identity.user_agent;
+            peer.protocol_versions = peer_identity.protocol_versions;
+            peer.clock_skew = clock_skew;
+            if identity_timestamp.is_some() {
+                peer.identity_timestamp = identity_timestamp;
+            }
+            peer.set_advertised_metadata(advertised_metadata);
             peer
         },
         None => {
@@ -163,6 +216,10 @@ pub async fn validate_and_add_peer_from_peer_identity(
                 peer_identity.user_agent,
             );
             new_peer.connection_stats.set_connection_success();
+            new_peer.protocol_versions = peer_identity.protocol_versions;
+            new_peer.clock_skew = clock_skew;
+            new_peer.identity_timestamp = identity_timestamp;
+            new_peer.set_advertised_metadata(advertised_metadata);
             if let Some(addr) = dialed_addr {
                 new_peer.addresses.mark_successful_connection_attempt(addr);
             }
@@ -172,7 +229,36 @@ pub async fn validate_and_add_peer_from_peer_identity(
 
     peer_manager.add_peer(peer).await?;
 
-    Ok((peer_node_id, supported_protocols))
+    Ok((peer_node_id, supported_protocols, address_update))
+}
+
+fn check_peer_version(
+    node_id: &NodeId,
+    peer_identity: &PeerIdentityMsg,
+    version_policy: PeerVersionPolicy,
+) -> Result<(), ConnectionManagerError>
+{
+    let version = max_protocol_version(peer_identity.protocol_versions);
+    if version_policy.should_refuse(peer_identity.protocol_versions) {
+        debug!(
+            target: LOG_TARGET,
+            "Refusing connection to peer '{}' (user agent: '{}') because protocol version {} is not supported",
+            node_id.short_str(),
+            peer_identity.user_agent,
+            version
+        );
+        return Err(ConnectionManagerError::PeerVersionNotSupported(version));
+    }
+    if version_policy.should_warn(peer_identity.protocol_versions) {
+        warn!(
+            target: LOG_TARGET,
+            "Peer '{}' (user agent: '{}') is using an outdated protocol version {}",
+            node_id.short_str(),
+            peer_identity.user_agent,
+            version
+        );
+    }
+    Ok(())
 }
 
 pub async fn find_unbanned_peer(
@@ -188,6 +274,16 @@ pub async fn find_unbanned_peer(
     }
 }
 
+/// Returns true if the IP address of `address` is an address at which a known, unbanned peer has been seen. This is
+/// used to recognise known peers before the noise handshake has authenticated them. Addresses that do not contain an IP
+/// address (e.g. a memory address or a connection over tor) never match.
+pub async fn is_known_peer_ip(peer_manager: &PeerManager, address: &Multiaddr) -> bool {
+    match multiaddr_ip(address) {
+        Some(ip) => peer_manager.is_known_peer_ip(&ip).await,
+        None => false,
+    }
+}
+
 pub fn validate_peer_addresses<'a, A: IntoIterator<Item = &'a Multiaddr>>(
     addresses: A,
     allow_test_addrs: bool,
@@ -213,6 +309,15 @@ fn validate_address(addr: &Multiaddr, allow_test_addrs: bool) -> Result<(), Conn
         None => Ok(()),
     };
 
+    // TCP addresses may optionally be followed by a single websocket component
+    let expect_end_of_tcp_address = |mut iter: multiaddr::Iter<'_>| match iter.next() {
+        Some(Protocol::Ws(_)) | Some(Protocol::Wss(_)) | None => expect_end_of_address(iter),
+        Some(p) => Err(ConnectionManagerError::InvalidMultiaddr(format!(
+            "Unexpected multiaddress component '{}'",
+            p
+        ))),
+    };
+
     match proto {
         Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_) => {
             let tcp = addr_iter.next().ok_or_else(|| {
@@ -221,7 +326,7 @@ fn validate_address(addr: &Multiaddr, allow_test_addrs: bool) -> Result<(), Conn
 
             validate_tcp_port(tcp)?;
 
-            expect_end_of_address(addr_iter)
+            expect_end_of_tcp_address(addr_iter)
         },
 
         Protocol::Ip4(addr)
@@ -239,12 +344,20 @@ fn validate_address(addr: &Multiaddr, allow_test_addrs: bool) -> Result<(), Conn
             ))
         },
         Protocol::Ip4(_) | Protocol::Ip6(_) => {
-            let tcp = addr_iter.next().ok_or_else(|| {
+            let transport = addr_iter.next().ok_or_else(|| {
                 ConnectionManagerError::InvalidMultiaddr("Address does not include a TCP port".to_string())
             })?;
 
-            validate_tcp_port(tcp)?;
-            expect_end_of_address(addr_iter)
+            match transport {
+                Protocol::Udp(port) => {
+                    validate_quic(port, addr_iter.next())?;
+                    expect_end_of_address(addr_iter)
+                },
+                tcp => {
+                    validate_tcp_port(tcp)?;
+                    expect_end_of_tcp_address(addr_iter)
+                },
+            }
         },
         Protocol::Memory(0) => Err(ConnectionManagerError::InvalidMultiaddr(
             "Cannot connect to a zero memory port".to_string(),
@@ -268,6 +381,24 @@ fn validate_address(addr: &Multiaddr, allow_test_addrs: bool) -> Result<(), Conn
     }
 }
 
+fn validate_quic(udp_port: u16, expected_quic: Option<Protocol>) -> Result<(), ConnectionManagerError> {
+    if udp_port == 0 {
+        return Err(ConnectionManagerError::InvalidMultiaddr(
+            "Cannot connect to a zero UDP port".to_string(),
+        ));
+    }
+    match expected_quic {
+        Some(Protocol::Quic) => Ok(()),
+        Some(p) => Err(ConnectionManagerError::InvalidMultiaddr(format!(
+            "Expected QUIC address component but got '{}'",
+            p
+        ))),
+        None => Err(ConnectionManagerError::InvalidMultiaddr(
+            "UDP addresses are only supported for QUIC".to_string(),
+        )),
+    }
+}
+
 fn validate_tcp_port(expected_tcp: Protocol) -> Result<(), ConnectionManagerError> {
     match expected_tcp {
         Protocol::Tcp(0) => Err(ConnectionManagerError::InvalidMultiaddr(
@@ -284,7 +415,42 @@ fn validate_tcp_port(expected_tcp: Protocol) -> Result<(), ConnectionManagerErro
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{
+        runtime,
+        test_utils::{node_identity::build_node_identity, test_node::build_peer_manager},
+    };
     use multiaddr::multiaddr;
+    use std::time::Duration;
+
+    #[runtime::test_basic]
+    async fn known_peer_ip() {
+        let peer_manager = build_peer_manager();
+        let mut peer = build_node_identity(PeerFeatures::COMMUNICATION_NODE).to_peer();
+        let address = "/ip4/172.0.0.1/tcp/18189".parse::<Multiaddr>().unwrap();
+        peer.addresses = vec![address.clone()].into();
+        peer_manager.add_peer(peer.clone()).await.unwrap();
+
+        // Inbound connections come from an arbitrary port
+        let is_known = |addr: &str| {
+            let addr = addr.parse::<Multiaddr>().unwrap();
+            let peer_manager = peer_manager.clone();
+            async move { is_known_peer_ip(&peer_manager, &addr).await }
+        };
+        // An address that has only been advertised is not known
+        assert!(!is_known("/ip4/172.0.0.1/tcp/45678").await);
+
+        peer.addresses.mark_successful_connection_attempt(&address);
+        peer_manager.add_peer(peer.clone()).await.unwrap();
+        assert!(is_known("/ip4/172.0.0.1/tcp/45678").await);
+        assert!(!is_known("/ip4/172.0.0.2/tcp/18189").await);
+        assert!(!is_known("/memory/0").await);
+
+        peer_manager
+            .ban_peer_by_node_id(&peer.node_id, Duration::from_secs(60), "".to_string())
+            .await
+            .unwrap();
+        assert!(!is_known("/ip4/172.0.0.1/tcp/45678").await);
+    }
 
     #[test]
     fn validate_address_strict() {
@@ -296,10 +462,16 @@ mod test {
                 .parse()
                 .unwrap(),
             multiaddr!(Dnsaddr("mike-magic-nodes.com"), Tcp(1u16)),
+            "/ip4/172.0.0.1/tcp/80/ws".parse().unwrap(),
+            "/dns4/mike-magic-nodes.com/tcp/443/wss".parse().unwrap(),
+            "/ip4/172.0.0.1/udp/18189/quic".parse().unwrap(),
         ];
 
         let invalid = &[
             multiaddr!(Ip4([127, 0, 0, 1]), Tcp(1u16)),
+            "/ip4/172.0.0.1/tcp/80/ws/ws".parse().unwrap(),
+            "/ip4/172.0.0.1/udp/18189".parse().unwrap(),
+            "/ip4/172.0.0.1/udp/0/quic".parse().unwrap(),
             multiaddr!(Ip4([169, 254, 0, 1]), Tcp(1u16)),
             multiaddr!(Ip4([172, 0, 0, 1])),
             "/onion/aaimaq4ygg2iegci:1234/http".parse().unwrap(),
@@ -341,4 +513,50 @@ mod test {
             validate_address(addr, true).unwrap_err();
         }
     }
+
+    #[runtime::test_basic]
+    async fn only_signing_peers_must_sign_their_identity() {
+        let peer_manager = build_peer_manager();
+        let node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        let mut identity = PeerIdentityMsg {
+            node_id: node_identity.node_id().to_vec(),
+            addresses: vec![node_identity.public_address().to_string()],
+            features: node_identity.features().bits(),
+            ..Default::default()
+        };
+
+        // A peer that does not support identity signing is accepted without a signature
+        identity.protocol_versions =
+            protocol::SUPPORTED_PROTOCOL_VERSIONS & !(1 << protocol::IDENTITY_SIGNATURE_PROTOCOL_VERSION);
+        let (node_id, _, address_update) = validate_and_add_peer_from_peer_identity(
+            &peer_manager,
+            None,
+            node_identity.public_key().clone(),
+            identity.clone(),
+            None,
+            true,
+            Default::default(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(&node_id, node_identity.node_id());
+        assert!(address_update.is_none());
+
+        // A peer that supports identity signing must sign it
+        identity.protocol_versions = protocol::SUPPORTED_PROTOCOL_VERSIONS;
+        let err = validate_and_add_peer_from_peer_identity(
+            &peer_manager,
+            None,
+            node_identity.public_key().clone(),
+            identity,
+            None,
+            true,
+            Default::default(),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, ConnectionManagerError::PeerIdentityInvalidSignature));
+    }
 }
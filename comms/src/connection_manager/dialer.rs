@@ -20,7 +20,11 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use super::{error::ConnectionManagerError, peer_connection::PeerConnection, types::ConnectionDirection};
+use super::{
+    error::ConnectionManagerError,
+    peer_connection::PeerConnection,
+    types::{supports_protocol_version, ConnectionDirection, PeerVersionPolicy},
+};
 use crate::{
     backoff::Backoff,
     connection_manager::{
@@ -28,13 +32,14 @@ use crate::{
         dial_state::DialState,
         manager::{ConnectionManagerConfig, ConnectionManagerEvent},
         peer_connection,
+        puzzle,
         wire_mode::WireMode,
     },
     multiaddr::Multiaddr,
     multiplexing::Yamux,
     noise::{NoiseConfig, NoiseSocket},
     peer_manager::{NodeId, NodeIdentity, Peer, PeerFeatures, PeerManager},
-    protocol::ProtocolId,
+    protocol::{self, ProtocolId},
     transports::Transport,
     types::CommsPublicKey,
 };
@@ -52,7 +57,11 @@ use futures::{
     StreamExt,
 };
 use log::*;
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tari_crypto::tari_utilities::hex::Hex;
 use tari_shutdown::{Shutdown, ShutdownSignal};
 use tokio::time;
@@ -274,6 +283,11 @@ where
 
         let backoff = Arc::clone(&self.backoff);
         let max_attempts = self.config.max_dial_attempts;
+        let connect_timeout = self.config.dial_connect_timeout;
+        let noise_handshake_timeout = self.config.noise_handshake_timeout;
+        let dial_with_puzzle = self.config.dial_with_puzzle;
+        let identity_exchange_timeout = self.config.identity_exchange_timeout;
+        let version_policy = self.config.peer_version_policy;
 
         let dial_state = DialState::new(peer, reply_tx, cancel_signal);
         let node_identity = Arc::clone(&self.node_identity);
@@ -285,8 +299,17 @@ where
         let allow_test_addresses = self.config.allow_test_addresses;
 
         let dial_fut = async move {
-            let (dial_state, dial_result) =
-                Self::dial_peer_with_retry(dial_state, noise_config, transport, backoff, max_attempts).await;
+            let (dial_state, dial_result) = Self::dial_peer_with_retry(
+                dial_state,
+                noise_config,
+                transport,
+                backoff,
+                max_attempts,
+                connect_timeout,
+                noise_handshake_timeout,
+                dial_with_puzzle,
+            )
+            .await;
 
             let cancel_signal = dial_state.get_cancel_signal();
 
@@ -310,6 +333,8 @@ where
                         supported_protocols,
                         user_agent,
                         allow_test_addresses,
+                        identity_exchange_timeout,
+                        version_policy,
                         cancel_signal,
                     )
                     .await;
@@ -350,11 +375,14 @@ where
         our_supported_protocols: Vec<ProtocolId>,
         user_agent: String,
         allow_test_addresses: bool,
+        identity_exchange_timeout: Duration,
+        version_policy: PeerVersionPolicy,
         cancel_signal: ShutdownSignal,
     ) -> Result<PeerConnection, ConnectionManagerError>
     {
         static CONNECTION_DIRECTION: ConnectionDirection = ConnectionDirection::Outbound;
 
+        let rekey_control = socket.rekey_control();
         let mut muxer = Yamux::upgrade_connection(socket, CONNECTION_DIRECTION)
             .await
             .map_err(|err| ConnectionManagerError::YamuxUpgradeFailure(err.to_string()))?;
@@ -367,14 +395,21 @@ where
             return Err(ConnectionManagerError::DialCancelled);
         }
 
-        let peer_identity = common::perform_identity_exchange(
-            &mut muxer,
-            &node_identity,
-            CONNECTION_DIRECTION,
-            &our_supported_protocols,
-            user_agent,
+        let timer = Instant::now();
+        let peer_identity = time::timeout(
+            identity_exchange_timeout,
+            common::perform_identity_exchange(
+                &mut muxer,
+                &node_identity,
+                CONNECTION_DIRECTION,
+                &our_supported_protocols,
+                user_agent,
+            ),
         )
-        .await?;
+        .await
+        .map_err(|_| ConnectionManagerError::IdentityExchangeTimeout)??;
+        let handshake_duration = timer.elapsed();
+        let clock_skew = protocol::estimate_clock_skew(&peer_identity);
         if cancel_signal.is_terminated() {
             muxer.get_yamux_control().close().await?;
             return Err(ConnectionManagerError::DialCancelled);
@@ -388,19 +423,31 @@ where
             features
         );
         trace!(target: LOG_TARGET, "{:?}", peer_identity);
+        let peer_protocol_versions = peer_identity.protocol_versions;
 
         // Check if we know the peer and if it is banned
         let known_peer = common::find_unbanned_peer(&peer_manager, &authenticated_public_key).await?;
 
-        let (peer_node_id, their_supported_protocols) = common::validate_and_add_peer_from_peer_identity(
-            &peer_manager,
-            known_peer,
-            authenticated_public_key,
-            peer_identity,
-            Some(&dialed_addr),
-            allow_test_addresses,
-        )
-        .await?;
+        let (peer_node_id, their_supported_protocols, address_update) =
+            common::validate_and_add_peer_from_peer_identity(
+                &peer_manager,
+                known_peer,
+                authenticated_public_key,
+                peer_identity,
+                Some(&dialed_addr),
+                allow_test_addresses,
+                version_policy,
+                clock_skew,
+            )
+            .await?;
+
+        if let Some(address_update) = address_update {
+            // The connection is not affected if the event cannot be published
+            let _ = conn_man_notifier
+                .clone()
+                .send(ConnectionManagerEvent::PeerAddressesChanged(Box::new(address_update)))
+                .await;
+        }
 
         if cancel_signal.is_terminated() {
             muxer.get_yamux_control().close().await?;
@@ -414,6 +461,10 @@ where
             peer_node_id.short_str()
         );
 
+        if supports_protocol_version(peer_protocol_versions, protocol::NOISE_REKEY_PROTOCOL_VERSION) {
+            rekey_control.enable();
+        }
+
         peer_connection::create(
             muxer,
             dialed_addr,
@@ -423,15 +474,21 @@ where
             conn_man_notifier,
             our_supported_protocols,
             their_supported_protocols,
+            handshake_duration,
+            clock_skew,
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn dial_peer_with_retry(
         dial_state: DialState,
         noise_config: NoiseConfig,
         transport: TTransport,
         backoff: Arc<TBackoff>,
         max_attempts: usize,
+        connect_timeout: Duration,
+        noise_handshake_timeout: Duration,
+        dial_with_puzzle: bool,
     ) -> (DialState, DialResult<TTransport::Output>)
     {
         // Container for dial state
@@ -455,7 +512,15 @@ where
             futures::select! {
                 _ = delay => {
                     debug!(target: LOG_TARGET, "[Attempt {}] Connecting to peer '{}'", current_state.num_attempts(), current_state.peer.node_id.short_str());
-                    match Self::dial_peer(current_state, &noise_config, &current_transport).await {
+                    let dial_result = Self::dial_peer(
+                        current_state,
+                        &noise_config,
+                        &current_transport,
+                        connect_timeout,
+                        noise_handshake_timeout,
+                        dial_with_puzzle,
+                    ).await;
+                    match dial_result {
                         (state, Ok((socket, addr))) => {
                             debug!(target: LOG_TARGET, "Dial succeeded for peer '{}' after {} attempt(s)", state.peer.node_id.short_str(), state.num_attempts());
                             break (state, Ok((socket, addr)));
@@ -489,12 +554,22 @@ where
         dial_state: DialState,
         noise_config: &NoiseConfig,
         transport: &TTransport,
+        connect_timeout: Duration,
+        noise_handshake_timeout: Duration,
+        dial_with_puzzle: bool,
     ) -> (
         DialState,
         Result<(NoiseSocket<TTransport::Output>, Multiaddr), ConnectionManagerError>,
     )
     {
         let mut addr_iter = dial_state.peer.addresses.iter();
+        let public_key = &dial_state.peer.public_key;
+        // Peers that advertise support for handshake puzzles are always asked for one so that they can accept this
+        // node while under a connection flood
+        let dial_with_puzzle = dial_with_puzzle ||
+            dial_state
+                .peer
+                .supports_protocol_version(protocol::HANDSHAKE_PUZZLE_PROTOCOL_VERSION);
         let cancel_signal = dial_state.get_cancel_signal();
         loop {
             let result = match addr_iter.next() {
@@ -507,23 +582,34 @@ where
                     );
 
                     let dial_fut = async move {
-                        let mut socket = transport
-                            .dial(address.clone())
-                            .map_err(|err| ConnectionManagerError::TransportError(err.to_string()))?
+                        let dial = transport
+                            .dial_peer(address.clone(), public_key)
+                            .map_err(|err| ConnectionManagerError::TransportError(err.to_string()))?;
+                        let mut socket = time::timeout(connect_timeout, dial)
                             .await
+                            .map_err(|_| ConnectionManagerError::DialConnectTimeout)?
                             .map_err(|err| ConnectionManagerError::TransportError(err.to_string()))?;
                         debug!(
                             target: LOG_TARGET,
                             "Socket established on '{}'. Performing noise upgrade protocol", address
                         );
 
+                        let wire_mode = if dial_with_puzzle {
+                            WireMode::CommsWithPuzzle
+                        } else {
+                            WireMode::Comms
+                        };
                         socket
-                            .write(&[WireMode::Comms as u8])
+                            .write(&[wire_mode as u8])
                             .await
                             .map_err(|_| ConnectionManagerError::WireFormatSendFailed)?;
 
+                        if dial_with_puzzle {
+                            puzzle::solve_listener_puzzle(&mut socket, noise_handshake_timeout).await?;
+                        }
+
                         let noise_socket = time::timeout(
-                            Duration::from_secs(30),
+                            noise_handshake_timeout,
                             noise_config.upgrade_socket(socket, ConnectionDirection::Outbound),
                         )
                         .await
@@ -66,6 +66,10 @@ pub enum ConnectionManagerError {
     IncomingListenerStreamClosed,
     #[error("The peer offered a NodeId that failed to validate against it's public key")]
     PeerIdentityInvalidNodeId,
+    #[error("The peer identity signature was not valid for the authenticated public key")]
+    PeerIdentityInvalidSignature,
+    #[error("The peer protocol version ({0}) is not supported")]
+    PeerVersionNotSupported(u8),
     #[error("Peer is banned, denying connection")]
     PeerBanned,
     #[error("Unable to parse any of the network addresses offered by the connecting peer")]
@@ -80,6 +84,20 @@ pub enum ConnectionManagerError {
     WireFormatSendFailed,
     #[error("Noise protocol handshake timed out")]
     NoiseProtocolTimeout,
+    #[error("Timed out while establishing the transport connection")]
+    DialConnectTimeout,
+    #[error("Peer identity exchange timed out")]
+    IdentityExchangeTimeout,
+    #[error("Handshake puzzle exchange timed out")]
+    HandshakePuzzleTimeout,
+    #[error("The peer sent an invalid handshake puzzle solution")]
+    HandshakePuzzleInvalidSolution,
+    #[error("The handshake puzzle difficulty ({0}) exceeds the maximum difficulty this node will solve")]
+    HandshakePuzzleTooDifficult(u8),
+    #[error("Handshake puzzle exchange failed: {0}")]
+    HandshakePuzzleIoError(String),
+    #[error("Connection from an unknown peer rejected because it did not request a required handshake puzzle")]
+    HandshakePuzzleRequired,
 }
 
 impl From<yamux::ConnectionError> for ConnectionManagerError {
@@ -24,33 +24,34 @@ use super::{
     common,
     error::ConnectionManagerError,
     peer_connection::{self, PeerConnection},
-    types::ConnectionDirection,
+    types::{supports_protocol_version, ConnectionDirection, PeerVersionPolicy},
     ConnectionManagerConfig,
     ConnectionManagerEvent,
 };
 use crate::{
     bounded_executor::BoundedExecutor,
-    connection_manager::{liveness::LivenessSession, wire_mode::WireMode},
+    connection_manager::{liveness::LivenessSession, puzzle, puzzle::AcceptRateTracker, wire_mode::WireMode},
     multiaddr::Multiaddr,
     multiplexing::Yamux,
     noise::NoiseConfig,
     peer_manager::{NodeIdentity, PeerFeatures},
-    protocol::ProtocolId,
+    protocol::{self, ProtocolId},
     runtime,
+    security_events::{SecurityEventKind, SecurityEventPublisher},
     transports::Transport,
     utils::multiaddr::multiaddr_to_socketaddr,
     PeerManager,
 };
-use futures::{channel::mpsc, future, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, SinkExt, StreamExt};
+use futures::{channel::mpsc, future, stream, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, SinkExt, StreamExt};
 use log::*;
 use std::{
     convert::TryInto,
-    mem,
+    iter,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tari_crypto::tari_utilities::hex::Hex;
 use tari_shutdown::ShutdownSignal;
@@ -70,6 +71,8 @@ pub struct PeerListener<TTransport> {
     listening_address: Option<Multiaddr>,
     our_supported_protocols: Vec<ProtocolId>,
     liveness_session_count: Arc<AtomicUsize>,
+    accept_rate_tracker: AcceptRateTracker,
+    security_events: SecurityEventPublisher,
 }
 
 impl<TTransport> PeerListener<TTransport>
@@ -99,6 +102,8 @@ where
             our_supported_protocols: Vec::new(),
             bounded_executor: BoundedExecutor::from_current(config.max_simultaneous_inbound_connects),
             liveness_session_count: Arc::new(AtomicUsize::new(config.liveness_max_sessions)),
+            accept_rate_tracker: AcceptRateTracker::new(),
+            security_events: SecurityEventPublisher::default(),
             config,
         }
     }
@@ -109,14 +114,29 @@ where
         self
     }
 
+    /// Set the publisher used to report failed inbound handshakes
+    pub fn set_security_event_publisher(&mut self, security_events: SecurityEventPublisher) -> &mut Self {
+        self.security_events = security_events;
+        self
+    }
+
     pub async fn run(mut self) {
         let mut shutdown_signal = self.shutdown_signal.clone();
 
         match self.listen().await {
-            Ok((inbound, address)) => {
-                let inbound = inbound.fuse();
-                futures::pin_mut!(inbound);
+            Ok(listeners) => {
+                let (listeners, mut addresses) = listeners.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+                let mut inbound = stream::select_all(listeners).fuse();
 
+                let address = addresses.remove(0);
+                for auxiliary_address in addresses {
+                    info!(
+                        target: LOG_TARGET,
+                        "Listening for peer connections on '{}'", auxiliary_address
+                    );
+                    self.send_event(ConnectionManagerEvent::AuxiliaryListening(auxiliary_address))
+                        .await;
+                }
                 info!(target: LOG_TARGET, "Listening for peer connections on '{}'", address);
                 self.listening_address = Some(address.clone());
 
@@ -193,7 +213,28 @@ where
         });
     }
 
-    async fn spawn_listen_task(&self, mut socket: TTransport::Output, peer_addr: Multiaddr) {
+    /// Returns true if connecting peers must solve a handshake puzzle because the accept rate threshold has been
+    /// exceeded
+    fn record_accept_and_check_puzzle_required(&mut self) -> bool {
+        match self.config.puzzle_accept_rate_threshold {
+            Some(threshold) => {
+                let rate = self.accept_rate_tracker.record();
+                if rate > threshold {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Accept rate ({}/s) exceeds threshold ({}/s). Handshake puzzle required.", rate, threshold
+                    );
+                    true
+                } else {
+                    false
+                }
+            },
+            None => false,
+        }
+    }
+
+    async fn spawn_listen_task(&mut self, mut socket: TTransport::Output, peer_addr: Multiaddr) {
+        let is_puzzle_required = self.record_accept_and_check_puzzle_required();
         let node_identity = self.node_identity.clone();
         let peer_manager = self.peer_manager.clone();
         let mut conn_man_notifier = self.conn_man_notifier.clone();
@@ -204,10 +245,59 @@ where
         let liveness_session_count = self.liveness_session_count.clone();
         let user_agent = self.config.user_agent.clone();
         let shutdown_signal = self.shutdown_signal.clone();
+        let security_events = self.security_events.clone();
 
         let inbound_fut = async move {
             match Self::read_wire_format(&mut socket, config.time_to_first_byte).await {
-                Some(WireMode::Comms) => {
+                Some(wire_mode @ WireMode::Comms) | Some(wire_mode @ WireMode::CommsWithPuzzle) => {
+                    // A dialer that did not request a puzzle cannot be challenged, so while a puzzle is required only
+                    // known peers are accepted from such dialers. The dialer is not authenticated until after the noise
+                    // handshake, so it is first checked by IP address to avoid the handshake for unknown dialers, and
+                    // then by public key once the handshake is complete.
+                    let is_known_peer_required = is_puzzle_required && matches!(wire_mode, WireMode::Comms);
+                    if is_known_peer_required && !common::is_known_peer_ip(&peer_manager, &peer_addr).await {
+                        debug!(
+                            target: LOG_TARGET,
+                            "Rejecting connection from unknown address '{}' that did not request a required handshake \
+                             puzzle",
+                            peer_addr
+                        );
+                        let _ = socket.close().await;
+                        log_if_error!(
+                            target: LOG_TARGET,
+                            conn_man_notifier
+                                .send(ConnectionManagerEvent::PeerInboundConnectFailed(
+                                    ConnectionManagerError::HandshakePuzzleRequired,
+                                ))
+                                .await,
+                            "Failed to publish event because '{error}'",
+                        );
+                        return;
+                    }
+                    if let WireMode::CommsWithPuzzle = wire_mode {
+                        // A zero difficulty puzzle is sent when a puzzle is not required, to which any solution is
+                        // valid
+                        let difficulty = if is_puzzle_required {
+                            config.puzzle_difficulty
+                        } else {
+                            0
+                        };
+                        if let Err(err) =
+                            puzzle::challenge_dialer(&mut socket, difficulty, config.time_to_first_byte).await
+                        {
+                            debug!(
+                                target: LOG_TARGET,
+                                "Peer at address '{}' failed the handshake puzzle: {}", peer_addr, err
+                            );
+                            security_events.publish(SecurityEventKind::HandshakeFailed {
+                                address: peer_addr.to_string(),
+                                reason: err.to_string(),
+                            });
+                            let _ = socket.close().await;
+                            return;
+                        }
+                    }
+
                     let this_node_id_str = node_identity.node_id().short_str();
                     let result = Self::perform_socket_upgrade_procedure(
                         node_identity,
@@ -215,10 +305,14 @@ where
                         noise_config,
                         conn_man_notifier.clone(),
                         socket,
-                        peer_addr,
+                        peer_addr.clone(),
                         our_supported_protocols,
                         user_agent,
                         allow_test_addresses,
+                        is_known_peer_required,
+                        config.noise_handshake_timeout,
+                        config.identity_exchange_timeout,
+                        config.peer_version_policy,
                     )
                     .await;
 
@@ -239,6 +333,9 @@ where
                                 this_node_id_str,
                                 err
                             );
+                            if let Some(event) = SecurityEventKind::from_inbound_connect_error(&peer_addr, &err) {
+                                security_events.publish(event);
+                            }
                             log_if_error!(
                                 target: LOG_TARGET,
                                 conn_man_notifier
@@ -300,6 +397,10 @@ where
         our_supported_protocols: Vec<ProtocolId>,
         user_agent: String,
         allow_test_addresses: bool,
+        is_known_peer_required: bool,
+        noise_handshake_timeout: Duration,
+        identity_exchange_timeout: Duration,
+        version_policy: PeerVersionPolicy,
     ) -> Result<PeerConnection, ConnectionManagerError>
     {
         static CONNECTION_DIRECTION: ConnectionDirection = ConnectionDirection::Inbound;
@@ -309,7 +410,7 @@ where
         );
 
         let noise_socket = time::timeout(
-            Duration::from_secs(30),
+            noise_handshake_timeout,
             noise_config.upgrade_socket(socket, CONNECTION_DIRECTION),
         )
         .await
@@ -321,7 +422,11 @@ where
 
         // Check if we know the peer and if it is banned
         let known_peer = common::find_unbanned_peer(&peer_manager, &authenticated_public_key).await?;
+        if is_known_peer_required && known_peer.is_none() {
+            return Err(ConnectionManagerError::HandshakePuzzleRequired);
+        }
 
+        let rekey_control = noise_socket.rekey_control();
         let mut muxer = Yamux::upgrade_connection(noise_socket, CONNECTION_DIRECTION)
             .await
             .map_err(|err| ConnectionManagerError::YamuxUpgradeFailure(err.to_string()))?;
@@ -332,14 +437,21 @@ where
             authenticated_public_key
         );
 
-        let peer_identity = common::perform_identity_exchange(
-            &mut muxer,
-            &node_identity,
-            CONNECTION_DIRECTION,
-            &our_supported_protocols,
-            user_agent,
+        let timer = Instant::now();
+        let peer_identity = time::timeout(
+            identity_exchange_timeout,
+            common::perform_identity_exchange(
+                &mut muxer,
+                &node_identity,
+                CONNECTION_DIRECTION,
+                &our_supported_protocols,
+                user_agent,
+            ),
         )
-        .await?;
+        .await
+        .map_err(|_| ConnectionManagerError::IdentityExchangeTimeout)??;
+        let handshake_duration = timer.elapsed();
+        let clock_skew = protocol::estimate_clock_skew(&peer_identity);
 
         let features = PeerFeatures::from_bits_truncate(peer_identity.features);
         debug!(
@@ -349,16 +461,28 @@ where
             features
         );
         trace!(target: LOG_TARGET, "{:?}", peer_identity);
+        let peer_protocol_versions = peer_identity.protocol_versions;
 
-        let (peer_node_id, their_supported_protocols) = common::validate_and_add_peer_from_peer_identity(
-            &peer_manager,
-            known_peer,
-            authenticated_public_key,
-            peer_identity,
-            None,
-            allow_test_addresses,
-        )
-        .await?;
+        let (peer_node_id, their_supported_protocols, address_update) =
+            common::validate_and_add_peer_from_peer_identity(
+                &peer_manager,
+                known_peer,
+                authenticated_public_key,
+                peer_identity,
+                None,
+                allow_test_addresses,
+                version_policy,
+                clock_skew,
+            )
+            .await?;
+
+        if let Some(address_update) = address_update {
+            // The connection is not affected if the event cannot be published
+            let _ = conn_man_notifier
+                .clone()
+                .send(ConnectionManagerEvent::PeerAddressesChanged(Box::new(address_update)))
+                .await;
+        }
 
         debug!(
             target: LOG_TARGET,
@@ -367,6 +491,10 @@ where
             peer_node_id.short_str()
         );
 
+        if supports_protocol_version(peer_protocol_versions, protocol::NOISE_REKEY_PROTOCOL_VERSION) {
+            rekey_control.enable();
+        }
+
         peer_connection::create(
             muxer,
             peer_addr,
@@ -376,14 +504,51 @@ where
             conn_man_notifier,
             our_supported_protocols,
             their_supported_protocols,
+            handshake_duration,
+            clock_skew,
         )
     }
 
-    async fn listen(&mut self) -> Result<(TTransport::Listener, Multiaddr), ConnectionManagerError> {
-        let listener_address = mem::replace(&mut self.config.listener_address, Multiaddr::empty());
-        debug!(target: LOG_TARGET, "Attempting to listen on {}", listener_address);
+    /// Binds the main listener address followed by the auxiliary listener addresses, returning each listener with the
+    /// address that it is bound to
+    async fn listen(&mut self) -> Result<Vec<(TTransport::Listener, Multiaddr)>, ConnectionManagerError> {
+        let addresses = iter::once(self.config.listener_address.clone())
+            .chain(self.config.auxiliary_listener_addresses.iter().cloned())
+            .collect::<Vec<_>>();
+        let preferred_addresses = self.config.listener_port_strategy.preferred_addresses(&addresses);
+
+        let mut listeners = Vec::with_capacity(addresses.len());
+        for (address, preferred_address) in addresses.into_iter().zip(preferred_addresses) {
+            let listener = match preferred_address {
+                Some(preferred_address) => match self.bind(preferred_address.clone()).await {
+                    Ok(listener) => listener,
+                    Err(err) => {
+                        warn!(
+                            target: LOG_TARGET,
+                            "Unable to re-bind to '{}' because '{}'. Binding to '{}' instead",
+                            preferred_address,
+                            err,
+                            address
+                        );
+                        self.bind(address).await?
+                    },
+                },
+                None => self.bind(address).await?,
+            };
+            listeners.push(listener);
+        }
+
+        let bound_addresses = listeners.iter().map(|(_, addr)| addr.clone()).collect::<Vec<_>>();
+        self.config
+            .listener_port_strategy
+            .persist_bound_addresses(&bound_addresses);
+        Ok(listeners)
+    }
+
+    async fn bind(&self, address: Multiaddr) -> Result<(TTransport::Listener, Multiaddr), ConnectionManagerError> {
+        debug!(target: LOG_TARGET, "Attempting to listen on {}", address);
         self.transport
-            .listen(listener_address)
+            .listen(address)
             .map_err(|err| ConnectionManagerError::TransportError(err.to_string()))?
             .await
             .map_err(|err| ConnectionManagerError::TransportError(err.to_string()))
@@ -21,19 +21,23 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use super::{
+    clock_skew::ClockSkewTracker,
     dialer::{Dialer, DialerRequest},
     error::ConnectionManagerError,
     listener::PeerListener,
     peer_connection::PeerConnection,
+    port_reuse::ListenerPortStrategy,
     requester::ConnectionManagerRequest,
+    types::PeerVersionPolicy,
 };
 use crate::{
     backoff::Backoff,
     multiplexing::Substream,
     noise::NoiseConfig,
     peer_manager::{NodeId, NodeIdentity},
-    protocol::{ProtocolEvent, ProtocolId, Protocols},
+    protocol::{ProtocolEvent, ProtocolId, Protocols, SignedPeerIdentity},
     runtime,
+    security_events::SecurityEventPublisher,
     transports::Transport,
     types::DEFAULT_LISTENER_ADDRESS,
     PeerManager,
@@ -57,6 +61,8 @@ const LOG_TARGET: &str = "comms::connection_manager::manager";
 
 const EVENT_CHANNEL_SIZE: usize = 32;
 const DIALER_REQUEST_CHANNEL_SIZE: usize = 32;
+/// The number of peer clock skew measurements used to estimate the skew of this node's clock
+const CLOCK_SKEW_SAMPLE_SIZE: usize = 100;
 
 #[derive(Debug)]
 pub enum ConnectionManagerEvent {
@@ -65,9 +71,17 @@ pub enum ConnectionManagerEvent {
     PeerDisconnected(Box<NodeId>),
     PeerConnectFailed(Box<NodeId>, ConnectionManagerError),
     PeerInboundConnectFailed(ConnectionManagerError),
+    /// The estimated difference in seconds between the peer's clock and ours exceeds `max_clock_skew`
+    PeerClockSkew(Box<NodeId>, i64),
+    /// A known peer advertised new addresses in the identity exchange. The peer's signed identity is included so that
+    /// the update can be relayed to other nodes.
+    PeerAddressesChanged(Box<SignedPeerIdentity>),
 
     // Listener
     Listening(Multiaddr),
+    /// An auxiliary listener is listening on the given address. This is published for each auxiliary listener before
+    /// `Listening` is published for the main listener.
+    AuxiliaryListening(Multiaddr),
     ListenFailed(ConnectionManagerError),
 
     // Substreams
@@ -82,7 +96,10 @@ impl fmt::Display for ConnectionManagerEvent {
             PeerDisconnected(node_id) => write!(f, "PeerDisconnected({})", node_id.short_str()),
             PeerConnectFailed(node_id, err) => write!(f, "PeerConnectFailed({}, {:?})", node_id.short_str(), err),
             PeerInboundConnectFailed(err) => write!(f, "PeerInboundConnectFailed({:?})", err),
+            PeerClockSkew(node_id, skew) => write!(f, "PeerClockSkew({}, {}s)", node_id.short_str(), skew),
+            PeerAddressesChanged(identity) => write!(f, "PeerAddressesChanged({})", identity.public_key()),
             Listening(addr) => write!(f, "Listening({})", addr),
+            AuxiliaryListening(addr) => write!(f, "AuxiliaryListening({})", addr),
             ListenFailed(err) => write!(f, "ListenFailed({:?})", err),
             NewInboundSubstream(node_id, protocol, _) => write!(
                 f,
@@ -99,6 +116,11 @@ pub struct ConnectionManagerConfig {
     /// The address to listen on for incoming connections. This address must be supported by the transport.
     /// Default: DEFAULT_LISTENER_ADDRESS constant
     pub listener_address: Multiaddr,
+    /// Additional addresses to listen on for incoming connections, e.g. to accept connections on more than one port.
+    /// Default: empty
+    pub auxiliary_listener_addresses: Vec<Multiaddr>,
+    /// Determines the port that listener addresses with port 0 bind to. Default: ListenerPortStrategy::OsAssigned
+    pub listener_port_strategy: ListenerPortStrategy,
     /// The number of dial attempts to make before giving up. Default: 3
     pub max_dial_attempts: usize,
     /// The maximum number of connection tasks that will be spawned at the same time. Once this limit is reached, peers
@@ -109,12 +131,43 @@ pub struct ConnectionManagerConfig {
     pub allow_test_addresses: bool,
     /// The maximum time to wait for the first byte before closing the connection. Default: 7s
     pub time_to_first_byte: Duration,
+    /// The maximum time to wait for the transport to establish a connection to a peer address. Default: 20s
+    pub dial_connect_timeout: Duration,
+    /// The maximum time to wait for the noise handshake to complete. Default: 30s
+    pub noise_handshake_timeout: Duration,
+    /// The maximum time to wait for the peer identity exchange to complete. Default: 15s
+    pub identity_exchange_timeout: Duration,
     /// The number of liveness check sessions to allow. Default: 0
     pub liveness_max_sessions: usize,
     /// CIDR blocks that allowlist liveness checks. Default: Localhost only (127.0.0.1/32)
     pub liveness_cidr_allowlist: Vec<cidr::AnyIpCidr>,
     /// The user agent string for this node
     pub user_agent: String,
+    /// The policy to apply to peers that do not support the required comms protocol version.
+    /// Default: PeerVersionPolicy::AcceptAll
+    pub peer_version_policy: PeerVersionPolicy,
+    /// The maximum difference between a peer's clock and ours before a `PeerClockSkew` event is published. Clock skew
+    /// affects expiry checks and store and forward retrieval of messages since a given time. Default: 60s
+    pub max_clock_skew: Duration,
+    /// If set, inbound peers must solve a handshake puzzle before any noise handshake state is allocated whenever
+    /// more than this many connections have been accepted in the last second. While the threshold is exceeded, a
+    /// dialer that did not request a puzzle (e.g. a peer that does not support puzzles) is only accepted if it is
+    /// already a known peer. Such a dialer is closed before the noise handshake unless its IP address is the address
+    /// of a known peer, so known peers that connect over tor or from a different IP address must use a puzzle.
+    /// Default: None
+    pub puzzle_accept_rate_threshold: Option<usize>,
+    /// The number of leading zero bits required in a handshake puzzle solution. Default: 16
+    pub puzzle_difficulty: u8,
+    /// Set to true to request a handshake puzzle when dialing any peer. Peers that advertise support for handshake
+    /// puzzles are always dialed with a puzzle request. Peers that do not support handshake puzzles will reject the
+    /// connection, so this should only be enabled once the network supports them. Default: false
+    pub dial_with_puzzle: bool,
+    /// Rekey the noise transport session of a connection after this much time. Rekeying is only initiated with peers
+    /// that support it. None disables time-based rekeying. Default: 1 hour
+    pub noise_rekey_interval: Option<Duration>,
+    /// Rekey the noise transport session of a connection after this many bytes have been sent. None disables
+    /// byte-based rekeying. Default: 1 GiB
+    pub noise_rekey_bytes: Option<u64>,
 }
 
 impl Default for ConnectionManagerConfig {
@@ -123,6 +176,8 @@ impl Default for ConnectionManagerConfig {
             listener_address: DEFAULT_LISTENER_ADDRESS
                 .parse()
                 .expect("DEFAULT_LISTENER_ADDRESS is malformed"),
+            auxiliary_listener_addresses: Vec::new(),
+            listener_port_strategy: Default::default(),
             max_dial_attempts: 3,
             max_simultaneous_inbound_connects: 20,
             #[cfg(not(test))]
@@ -132,8 +187,18 @@ impl Default for ConnectionManagerConfig {
             allow_test_addresses: true,
             liveness_max_sessions: 0,
             time_to_first_byte: Duration::from_secs(7),
+            dial_connect_timeout: Duration::from_secs(20),
+            noise_handshake_timeout: Duration::from_secs(30),
+            identity_exchange_timeout: Duration::from_secs(15),
             liveness_cidr_allowlist: vec![cidr::AnyIpCidr::V4("127.0.0.1/32".parse().unwrap())],
             user_agent: Default::default(),
+            peer_version_policy: Default::default(),
+            max_clock_skew: Duration::from_secs(60),
+            puzzle_accept_rate_threshold: None,
+            puzzle_difficulty: 16,
+            dial_with_puzzle: false,
+            noise_rekey_interval: Some(Duration::from_secs(60 * 60)),
+            noise_rekey_bytes: Some(1024 * 1024 * 1024),
         }
     }
 }
@@ -151,6 +216,8 @@ pub struct ConnectionManager<TTransport, TBackoff> {
     listening_notifiers: Vec<oneshot::Sender<Multiaddr>>,
     connection_manager_events_tx: broadcast::Sender<Arc<ConnectionManagerEvent>>,
     complete_trigger: Shutdown,
+    clock_skew_tracker: ClockSkewTracker,
+    max_clock_skew: Duration,
 }
 
 impl<TTransport, TBackoff> ConnectionManager<TTransport, TBackoff>
@@ -176,6 +243,7 @@ where
 
         let (dialer_tx, dialer_rx) = mpsc::channel(DIALER_REQUEST_CHANNEL_SIZE);
 
+        let max_clock_skew = config.max_clock_skew;
         let listener = PeerListener::new(
             config.clone(),
             transport.clone(),
@@ -211,6 +279,8 @@ where
             listening_notifiers: Vec::new(),
             connection_manager_events_tx,
             complete_trigger: Shutdown::new(),
+            clock_skew_tracker: ClockSkewTracker::new(CLOCK_SKEW_SAMPLE_SIZE),
+            max_clock_skew,
         }
     }
 
@@ -219,6 +289,14 @@ where
         self
     }
 
+    /// Set the publisher used to report security events for inbound connections
+    pub fn set_security_event_publisher(&mut self, security_events: SecurityEventPublisher) -> &mut Self {
+        if let Some(listener) = self.listener.as_mut() {
+            listener.set_security_event_publisher(security_events);
+        }
+        self
+    }
+
     pub fn complete_signal(&self) -> ShutdownSignal {
         self.complete_trigger.to_signal()
     }
@@ -304,6 +382,9 @@ where
                     self.listening_notifiers.push(reply);
                 },
             },
+            GetEstimatedClockSkew(reply) => {
+                let _ = reply.send(self.clock_skew_tracker.estimated_local_skew());
+            },
         }
     }
 
@@ -318,6 +399,12 @@ where
                     let _ = notifier.send(addr.clone());
                 }
             },
+            PeerConnected(conn) => {
+                if let Some(skew) = conn.clock_skew() {
+                    self.handle_peer_clock_skew(conn.peer_node_id().clone(), skew);
+                }
+                self.publish_event(PeerConnected(conn));
+            },
             NewInboundSubstream(node_id, protocol, stream) => {
                 let proto_str = String::from_utf8_lossy(&protocol);
                 debug!(
@@ -344,6 +431,23 @@ where
         }
     }
 
+    fn handle_peer_clock_skew(&mut self, node_id: NodeId, skew: i64) {
+        self.clock_skew_tracker.record(node_id.clone(), skew);
+        if skew.abs() as u64 <= self.max_clock_skew.as_secs() {
+            return;
+        }
+        warn!(
+            target: LOG_TARGET,
+            "Peer '{}' clock differs from ours by {}s (max: {}s). Estimated skew of our clock relative to the network \
+             is {}s.",
+            node_id.short_str(),
+            skew,
+            self.max_clock_skew.as_secs(),
+            self.clock_skew_tracker.estimated_local_skew().unwrap_or(0)
+        );
+        self.publish_event(ConnectionManagerEvent::PeerClockSkew(Box::new(node_id), skew));
+    }
+
     #[inline]
     async fn send_dialer_request(&mut self, req: DialerRequest) {
         if let Err(err) = self.dialer_tx.send(req).await {
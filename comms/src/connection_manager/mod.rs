@@ -20,6 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+mod clock_skew;
 mod dial_state;
 mod dialer;
 mod listener;
@@ -28,7 +29,7 @@ mod common;
 pub use common::validate_peer_addresses;
 
 mod types;
-pub use types::ConnectionDirection;
+pub use types::{max_protocol_version, supports_protocol_version, ConnectionDirection, PeerVersionPolicy};
 
 mod requester;
 pub use requester::{ConnectionManagerRequest, ConnectionManagerRequester};
@@ -40,9 +41,13 @@ mod error;
 pub use error::{ConnectionManagerError, PeerConnectionError};
 
 mod peer_connection;
-pub use peer_connection::{ConnectionId, NegotiatedSubstream, PeerConnection, PeerConnectionRequest};
+pub use peer_connection::{ConnectionId, ConnectionInfo, NegotiatedSubstream, PeerConnection, PeerConnectionRequest};
+
+mod port_reuse;
+pub use port_reuse::ListenerPortStrategy;
 
 mod liveness;
+mod puzzle;
 mod wire_mode;
 
 #[cfg(test)]
@@ -31,7 +31,7 @@ use super::{
 use crate::{
     framing,
     framing::CanonicalFraming,
-    multiplexing::{Control, IncomingSubstreams, Substream, SubstreamCounter, Yamux},
+    multiplexing::{ByteCounter, Control, IncomingSubstreams, Substream, SubstreamCounter, Yamux},
     peer_manager::{NodeId, PeerFeatures},
     protocol::{ProtocolId, ProtocolNegotiation},
     runtime,
@@ -67,6 +67,8 @@ pub fn create(
     event_notifier: mpsc::Sender<ConnectionManagerEvent>,
     our_supported_protocols: Vec<ProtocolId>,
     their_supported_protocols: Vec<ProtocolId>,
+    handshake_duration: Duration,
+    clock_skew: Option<i64>,
 ) -> Result<PeerConnection, ConnectionManagerError>
 {
     trace!(
@@ -77,7 +79,8 @@ pub fn create(
     let (peer_tx, peer_rx) = mpsc::channel(PEER_REQUEST_BUFFER_SIZE);
     let id = ID_COUNTER.fetch_add(1, Ordering::Relaxed); // Monotonic
     let substream_counter = connection.substream_counter();
-    let peer_conn = PeerConnection::new(
+    let byte_counter = connection.byte_counter();
+    let mut peer_conn = PeerConnection::new(
         id,
         peer_tx,
         peer_node_id.clone(),
@@ -86,6 +89,9 @@ pub fn create(
         direction,
         substream_counter,
     );
+    peer_conn.handshake_duration = Some(handshake_duration);
+    peer_conn.clock_skew = clock_skew;
+    peer_conn.byte_counter = byte_counter;
     let peer_actor = PeerConnectionActor::new(
         id,
         peer_node_id,
@@ -125,6 +131,9 @@ pub struct PeerConnection {
     direction: ConnectionDirection,
     started_at: Instant,
     substream_counter: SubstreamCounter,
+    byte_counter: ByteCounter,
+    handshake_duration: Option<Duration>,
+    clock_skew: Option<i64>,
 }
 
 impl PeerConnection {
@@ -147,6 +156,9 @@ impl PeerConnection {
             direction,
             started_at: Instant::now(),
             substream_counter,
+            byte_counter: ByteCounter::new(),
+            handshake_duration: None,
+            clock_skew: None,
         }
     }
 
@@ -174,6 +186,18 @@ impl PeerConnection {
         !self.request_tx.is_closed()
     }
 
+    /// The time taken to complete the peer identity exchange when this connection was established. None if the
+    /// connection was not established by the connection manager (e.g. in tests).
+    pub fn handshake_duration(&self) -> Option<Duration> {
+        self.handshake_duration
+    }
+
+    /// The estimated difference in seconds between the peer's clock and ours, measured during the identity exchange.
+    /// The estimate is positive if the peer's clock is ahead. None if the peer did not send a timestamp.
+    pub fn clock_skew(&self) -> Option<i64> {
+        self.clock_skew
+    }
+
     pub fn age(&self) -> Duration {
         self.started_at.elapsed()
     }
@@ -182,6 +206,33 @@ impl PeerConnection {
         self.substream_counter.get()
     }
 
+    /// Total number of bytes read from the underlying transport for this connection
+    pub fn bytes_read(&self) -> u64 {
+        self.byte_counter.bytes_read()
+    }
+
+    /// Total number of bytes written to the underlying transport for this connection
+    pub fn bytes_written(&self) -> u64 {
+        self.byte_counter.bytes_written()
+    }
+
+    /// Returns a snapshot of the current state of this connection
+    pub fn info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            id: self.id,
+            peer_node_id: self.peer_node_id.clone(),
+            peer_features: self.peer_features,
+            address: self.address.clone(),
+            direction: self.direction,
+            age: self.age(),
+            substream_count: self.substream_count(),
+            bytes_read: self.bytes_read(),
+            bytes_written: self.bytes_written(),
+            handshake_duration: self.handshake_duration,
+            clock_skew: self.clock_skew,
+        }
+    }
+
     pub async fn open_substream(
         &mut self,
         protocol_id: &ProtocolId,
@@ -269,6 +320,40 @@ impl PartialEq for PeerConnection {
     }
 }
 
+/// A point-in-time snapshot of an active peer connection, suitable for display in status UIs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub id: ConnectionId,
+    pub peer_node_id: NodeId,
+    pub peer_features: PeerFeatures,
+    pub address: Multiaddr,
+    pub direction: ConnectionDirection,
+    pub age: Duration,
+    pub substream_count: usize,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub handshake_duration: Option<Duration>,
+    pub clock_skew: Option<i64>,
+}
+
+impl fmt::Display for ConnectionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Id = {}, Node ID = {}, Direction = {}, Peer Address = {}, Age = {:.0?}, Substreams = {}, Read = {} \
+             bytes, Written = {} bytes",
+            self.id,
+            self.peer_node_id.short_str(),
+            self.direction,
+            self.address,
+            self.age,
+            self.substream_count,
+            self.bytes_read,
+            self.bytes_written,
+        )
+    }
+}
+
 /// Actor for an active connection to a peer.
 struct PeerConnectionActor {
     id: ConnectionId,
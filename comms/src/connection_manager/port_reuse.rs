@@ -0,0 +1,154 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    multiaddr::Multiaddr,
+    utils::multiaddr::{multiaddr_port, multiaddr_with_port},
+};
+use log::*;
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+const LOG_TARGET: &str = "comms::connection_manager::port_reuse";
+
+/// Determines the port that a listener configured with port 0 binds to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenerPortStrategy {
+    /// Listeners configured with port 0 bind to a new port assigned by the OS every time the node starts
+    OsAssigned,
+    /// Listeners configured with port 0 re-bind to the port that they were assigned the last time the node started, so
+    /// that firewall and port forwarding rules continue to apply after a restart. The bound ports are persisted in
+    /// the given file. If a persisted port is no longer available, the OS assigns a new port.
+    ReusePersisted(PathBuf),
+}
+
+impl Default for ListenerPortStrategy {
+    fn default() -> Self {
+        ListenerPortStrategy::OsAssigned
+    }
+}
+
+impl ListenerPortStrategy {
+    /// Returns, for each listener address, the address that the listener should try to bind to before falling back to
+    /// the configured address. None if the listener should bind to the configured address.
+    pub(super) fn preferred_addresses(&self, addresses: &[Multiaddr]) -> Vec<Option<Multiaddr>> {
+        let persisted_ports = match self {
+            ListenerPortStrategy::OsAssigned => Vec::new(),
+            ListenerPortStrategy::ReusePersisted(path) => load_ports(path),
+        };
+        addresses
+            .iter()
+            .enumerate()
+            .map(|(i, addr)| {
+                if multiaddr_port(addr) != Some(0) {
+                    return None;
+                }
+                let port = persisted_ports.get(i).copied().flatten()?;
+                multiaddr_with_port(addr, port)
+            })
+            .collect()
+    }
+
+    /// Persists the ports of the bound listener addresses if the strategy reuses ports
+    pub(super) fn persist_bound_addresses(&self, addresses: &[Multiaddr]) {
+        if let ListenerPortStrategy::ReusePersisted(path) = self {
+            if let Err(err) = save_ports(path, addresses) {
+                warn!(
+                    target: LOG_TARGET,
+                    "Failed to persist listener ports to '{}': {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+/// Loads the persisted port of each listener. A listener without a valid persisted port has a `None` port.
+fn load_ports(path: &Path) -> Vec<Option<u64>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => contents.lines().map(|line| line.trim().parse().ok()).collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => {
+            warn!(
+                target: LOG_TARGET,
+                "Failed to load listener ports from '{}': {}",
+                path.display(),
+                err
+            );
+            Vec::new()
+        },
+    }
+}
+
+/// Writes the port of each bound address on its own line, in listener order
+fn save_ports(path: &Path, addresses: &[Multiaddr]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let contents = addresses
+        .iter()
+        .map(|addr| multiaddr_port(addr).map(|port| port.to_string()).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn reuse_persisted() {
+        let dir = tempdir().unwrap();
+        let strategy = ListenerPortStrategy::ReusePersisted(dir.path().join("listener_ports"));
+        let addresses = vec![
+            "/ip4/0.0.0.0/tcp/0".parse().unwrap(),
+            "/ip4/0.0.0.0/tcp/18189".parse().unwrap(),
+            "/memory/0".parse().unwrap(),
+        ];
+        assert_eq!(strategy.preferred_addresses(&addresses), vec![None, None, None]);
+
+        strategy.persist_bound_addresses(&[
+            "/ip4/0.0.0.0/tcp/40123".parse().unwrap(),
+            "/ip4/0.0.0.0/tcp/18189".parse().unwrap(),
+            "/memory/123".parse().unwrap(),
+        ]);
+        assert_eq!(strategy.preferred_addresses(&addresses), vec![
+            Some("/ip4/0.0.0.0/tcp/40123".parse().unwrap()),
+            None,
+            Some("/memory/123".parse().unwrap()),
+        ]);
+    }
+
+    #[test]
+    fn os_assigned() {
+        let addresses = vec!["/ip4/0.0.0.0/tcp/0".parse().unwrap()];
+        assert_eq!(ListenerPortStrategy::OsAssigned.preferred_addresses(&addresses), vec![
+            None
+        ]);
+    }
+}
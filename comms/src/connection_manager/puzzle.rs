@@ -0,0 +1,223 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::error::ConnectionManagerError;
+use crate::{runtime::task, types::Challenge};
+use digest::Digest;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use rand::{rngs::OsRng, RngCore};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+const NONCE_LENGTH: usize = 32;
+const PUZZLE_LENGTH: usize = 1 + NONCE_LENGTH;
+
+/// The maximum puzzle difficulty that this node will attempt to solve when dialing. Each additional bit doubles the
+/// expected work.
+pub(super) const MAX_PUZZLE_DIFFICULTY: u8 = 24;
+
+/// A client puzzle that a connecting peer must solve before the listener allocates any handshake state. A solution
+/// is an 8-byte value such that `Blake256(nonce || solution)` has at least `difficulty` leading zero bits.
+#[derive(Debug, Clone)]
+pub(super) struct Puzzle {
+    difficulty: u8,
+    nonce: [u8; NONCE_LENGTH],
+}
+
+impl Puzzle {
+    /// Create a new puzzle with a random nonce. A difficulty of zero is trivially solved by any value.
+    pub fn random(difficulty: u8) -> Self {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut nonce);
+        Self { difficulty, nonce }
+    }
+
+    pub fn difficulty(&self) -> u8 {
+        self.difficulty
+    }
+
+    pub fn to_bytes(&self) -> [u8; PUZZLE_LENGTH] {
+        let mut buf = [0u8; PUZZLE_LENGTH];
+        buf[0] = self.difficulty;
+        buf[1..].copy_from_slice(&self.nonce);
+        buf
+    }
+
+    pub fn from_bytes(bytes: &[u8; PUZZLE_LENGTH]) -> Self {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        nonce.copy_from_slice(&bytes[1..]);
+        Self {
+            difficulty: bytes[0],
+            nonce,
+        }
+    }
+
+    /// Returns true if the given solution solves this puzzle
+    pub fn verify(&self, solution: u64) -> bool {
+        if self.difficulty == 0 {
+            return true;
+        }
+        let hash = Challenge::new()
+            .chain(&self.nonce)
+            .chain(&solution.to_le_bytes())
+            .result();
+        leading_zero_bits(&hash) >= u32::from(self.difficulty)
+    }
+
+    /// Find a solution to this puzzle by brute force. The expected number of attempts is `2^difficulty`.
+    pub fn solve(&self) -> u64 {
+        (0..=u64::MAX)
+            .find(|solution| self.verify(*solution))
+            .expect("a solution exists for any difficulty less than 256 bits")
+    }
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut count = 0;
+    for byte in bytes {
+        count += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    count
+}
+
+/// Send a new puzzle of the given difficulty to the dialer and wait for a valid solution
+pub(super) async fn challenge_dialer<TSocket>(
+    socket: &mut TSocket,
+    difficulty: u8,
+    timeout: Duration,
+) -> Result<(), ConnectionManagerError>
+where
+    TSocket: AsyncRead + AsyncWrite + Unpin,
+{
+    let puzzle = Puzzle::random(difficulty);
+    let exchange = async {
+        socket.write_all(&puzzle.to_bytes()).await?;
+        socket.flush().await?;
+        let mut buf = [0u8; 8];
+        socket.read_exact(&mut buf).await?;
+        Result::<_, std::io::Error>::Ok(u64::from_le_bytes(buf))
+    };
+    let solution = time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| ConnectionManagerError::HandshakePuzzleTimeout)?
+        .map_err(|err| ConnectionManagerError::HandshakePuzzleIoError(err.to_string()))?;
+
+    if !puzzle.verify(solution) {
+        return Err(ConnectionManagerError::HandshakePuzzleInvalidSolution);
+    }
+    Ok(())
+}
+
+/// Read the puzzle sent by the listener, solve it and send the solution
+pub(super) async fn solve_listener_puzzle<TSocket>(
+    socket: &mut TSocket,
+    timeout: Duration,
+) -> Result<(), ConnectionManagerError>
+where
+    TSocket: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = [0u8; PUZZLE_LENGTH];
+    time::timeout(timeout, socket.read_exact(&mut buf))
+        .await
+        .map_err(|_| ConnectionManagerError::HandshakePuzzleTimeout)?
+        .map_err(|err| ConnectionManagerError::HandshakePuzzleIoError(err.to_string()))?;
+    let puzzle = Puzzle::from_bytes(&buf);
+    if puzzle.difficulty() > MAX_PUZZLE_DIFFICULTY {
+        return Err(ConnectionManagerError::HandshakePuzzleTooDifficult(puzzle.difficulty()));
+    }
+
+    // Solving the puzzle is CPU-bound, so it is done on the blocking thread pool
+    let solution = task::spawn_blocking(move || puzzle.solve())
+        .await
+        .map_err(|err| ConnectionManagerError::HandshakePuzzleIoError(err.to_string()))?;
+
+    socket
+        .write_all(&solution.to_le_bytes())
+        .await
+        .map_err(|err| ConnectionManagerError::HandshakePuzzleIoError(err.to_string()))?;
+    Ok(())
+}
+
+/// Tracks the rate at which inbound connections are accepted over a sliding one second window
+#[derive(Debug, Default)]
+pub(super) struct AcceptRateTracker {
+    accepts: VecDeque<Instant>,
+}
+
+impl AcceptRateTracker {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Record an accepted connection and return the number of connections accepted in the last second
+    pub fn record(&mut self) -> usize {
+        let now = Instant::now();
+        self.accepts.push_back(now);
+        while let Some(oldest) = self.accepts.front() {
+            if now.duration_since(*oldest) <= Self::WINDOW {
+                break;
+            }
+            self.accepts.pop_front();
+        }
+        self.accepts.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn solve_and_verify() {
+        let puzzle = Puzzle::random(8);
+        let solution = puzzle.solve();
+        assert!(puzzle.verify(solution));
+
+        let decoded = Puzzle::from_bytes(&puzzle.to_bytes());
+        assert_eq!(decoded.difficulty(), 8);
+        assert!(decoded.verify(solution));
+
+        assert!(Puzzle::random(0).verify(0));
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(leading_zero_bits(&[0xff]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x10]), 11);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[test]
+    fn accept_rate() {
+        let mut tracker = AcceptRateTracker::new();
+        assert_eq!(tracker.record(), 1);
+        assert_eq!(tracker.record(), 2);
+    }
+}
@@ -38,6 +38,8 @@ pub enum ConnectionManagerRequest {
     CancelDial(NodeId),
     /// Register a oneshot to get triggered when the node is listening, or has failed to listen
     NotifyListening(oneshot::Sender<Multiaddr>),
+    /// Get the estimated skew in seconds of this node's clock relative to the network
+    GetEstimatedClockSkew(oneshot::Sender<Option<i64>>),
 }
 
 /// Responsible for constructing requests to the ConnectionManagerService
@@ -123,4 +125,16 @@ impl ConnectionManagerRequester {
             .map_err(|_| ConnectionManagerError::SendToActorFailed)?;
         reply_rx.await.map_err(|_| ConnectionManagerError::ActorRequestCanceled)
     }
+
+    /// Returns the estimated skew in seconds of this node's clock relative to the median clock of recently connected
+    /// peers. The estimate is positive if our clock is ahead. None is returned if no peer has sent a timestamp in the
+    /// identity exchange yet.
+    pub async fn get_estimated_clock_skew(&mut self) -> Result<Option<i64>, ConnectionManagerError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(ConnectionManagerRequest::GetEstimatedClockSkew(reply_tx))
+            .await
+            .map_err(|_| ConnectionManagerError::SendToActorFailed)?;
+        reply_rx.await.map_err(|_| ConnectionManagerError::ActorRequestCanceled)
+    }
 }
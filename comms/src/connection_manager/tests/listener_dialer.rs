@@ -26,20 +26,24 @@ use crate::{
         dialer::{Dialer, DialerRequest},
         listener::PeerListener,
         manager::ConnectionManagerEvent,
+        wire_mode::WireMode,
+        ConnectionDirection,
         ConnectionManagerConfig,
         ConnectionManagerError,
     },
     noise::NoiseConfig,
     peer_manager::PeerFeatures,
-    protocol::ProtocolId,
+    protocol::{self, ProtocolId},
     runtime,
     test_utils::{node_identity::build_node_identity, test_node::build_peer_manager},
-    transports::MemoryTransport,
+    transports::{MemoryTransport, Transport},
 };
 use futures::{
     channel::{mpsc, oneshot},
+    future,
     AsyncReadExt,
     AsyncWriteExt,
+    FutureExt,
     SinkExt,
     StreamExt,
 };
@@ -47,7 +51,7 @@ use multiaddr::Protocol;
 use std::{error::Error, time::Duration};
 use tari_shutdown::Shutdown;
 use tari_test_utils::unpack_enum;
-use tokio::time::timeout;
+use tokio::time::{delay_for, timeout};
 
 #[runtime::test_basic]
 async fn listen() -> Result<(), Box<dyn Error>> {
@@ -175,6 +179,11 @@ async fn smoke() {
         assert_eq!(buf, *b"HELLO");
     }
 
+    let info = outbound_peer_conn.info();
+    assert_eq!(info.direction, ConnectionDirection::Outbound);
+    assert_eq!(&info.peer_node_id, node_identity1.node_id());
+    assert!(info.bytes_written > 0);
+
     conn1.disconnect().await.unwrap();
 
     shutdown.trigger().unwrap();
@@ -267,3 +276,174 @@ async fn banned() {
     timeout(Duration::from_secs(5), listener_fut).await.unwrap().unwrap();
     timeout(Duration::from_secs(5), dialer_fut).await.unwrap().unwrap();
 }
+
+#[runtime::test_basic]
+async fn handshake_puzzle() {
+    let rt_handle = runtime::current();
+    let (event_tx, mut event_rx) = mpsc::channel(10);
+    let mut shutdown = Shutdown::new();
+
+    let node_identity1 = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+    let noise_config1 = NoiseConfig::new(node_identity1.clone());
+    let listener = PeerListener::new(
+        ConnectionManagerConfig {
+            listener_address: "/memory/0".parse().unwrap(),
+            // Always require a puzzle
+            puzzle_accept_rate_threshold: Some(0),
+            puzzle_difficulty: 8,
+            ..Default::default()
+        },
+        MemoryTransport,
+        noise_config1,
+        event_tx.clone(),
+        build_peer_manager(),
+        node_identity1.clone(),
+        shutdown.to_signal(),
+    );
+    let listener_fut = rt_handle.spawn(listener.run());
+
+    let node_identity2 = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+    let noise_config2 = NoiseConfig::new(node_identity2.clone());
+    let (mut request_tx, request_rx) = mpsc::channel(1);
+    let dialer = Dialer::new(
+        ConnectionManagerConfig {
+            dial_with_puzzle: true,
+            ..Default::default()
+        },
+        node_identity2.clone(),
+        build_peer_manager(),
+        MemoryTransport,
+        noise_config2,
+        ConstantBackoff::new(Duration::from_millis(100)),
+        request_rx,
+        event_tx,
+        shutdown.to_signal(),
+    );
+    let dialer_fut = rt_handle.spawn(dialer.run());
+
+    let listen_event = event_rx.next().await.unwrap();
+    unpack_enum!(ConnectionManagerEvent::Listening(address) = listen_event);
+
+    let mut peer = node_identity1.to_peer();
+    peer.addresses = vec![address].into();
+    peer.set_id_for_test(1);
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    request_tx
+        .send(DialerRequest::Dial(Box::new(peer), reply_tx))
+        .await
+        .unwrap();
+
+    let conn = reply_rx.await.unwrap().unwrap();
+    assert_eq!(conn.peer_node_id(), node_identity1.node_id());
+
+    shutdown.trigger().unwrap();
+    timeout(Duration::from_secs(5), listener_fut).await.unwrap().unwrap();
+    timeout(Duration::from_secs(5), dialer_fut).await.unwrap().unwrap();
+}
+
+#[runtime::test_basic]
+async fn handshake_puzzle_under_flood() {
+    let rt_handle = runtime::current();
+    let (event_tx, mut event_rx) = mpsc::channel(10);
+    let mut shutdown = Shutdown::new();
+
+    let node_identity1 = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+    let noise_config1 = NoiseConfig::new(node_identity1.clone());
+    let listener = PeerListener::new(
+        ConnectionManagerConfig {
+            listener_address: "/memory/0".parse().unwrap(),
+            puzzle_accept_rate_threshold: Some(20),
+            puzzle_difficulty: 8,
+            ..Default::default()
+        },
+        MemoryTransport,
+        noise_config1,
+        event_tx.clone(),
+        build_peer_manager(),
+        node_identity1.clone(),
+        shutdown.to_signal(),
+    );
+    let listener_fut = rt_handle.spawn(listener.run());
+
+    let node_identity2 = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+    let noise_config2 = NoiseConfig::new(node_identity2.clone());
+    let (request_tx, request_rx) = mpsc::channel(1);
+    let dialer = Dialer::new(
+        ConnectionManagerConfig {
+            max_dial_attempts: 1,
+            ..Default::default()
+        },
+        node_identity2.clone(),
+        build_peer_manager(),
+        MemoryTransport,
+        noise_config2,
+        ConstantBackoff::new(Duration::from_millis(100)),
+        request_rx,
+        event_tx,
+        shutdown.to_signal(),
+    );
+    let dialer_fut = rt_handle.spawn(dialer.run());
+
+    let listen_event = event_rx.next().await.unwrap();
+    unpack_enum!(ConnectionManagerEvent::Listening(address) = listen_event);
+    // Discard the events published for the flood connections
+    rt_handle.spawn(event_rx.for_each(|_| future::ready(())));
+
+    // Flood the listener with connections that close immediately after sending the wire mode byte
+    let flood_address = address.clone();
+    let mut flood_shutdown = shutdown.to_signal();
+    rt_handle.spawn(async move {
+        loop {
+            if let Ok(dial) = MemoryTransport.dial(flood_address.clone()) {
+                if let Ok(mut socket) = dial.await {
+                    let _ = socket.write_all(&[WireMode::Comms as u8]).await;
+                }
+            }
+            futures::select! {
+                _ = delay_for(Duration::from_millis(5)).fuse() => {},
+                _ = flood_shutdown => break,
+            }
+        }
+    });
+    delay_for(Duration::from_millis(200)).await;
+
+    let dial = |protocol_versions: u32| {
+        let mut peer = node_identity1.to_peer();
+        peer.addresses = vec![address.clone()].into();
+        peer.protocol_versions = protocol_versions;
+        peer.set_id_for_test(1);
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let mut request_tx = request_tx.clone();
+        async move {
+            request_tx
+                .send(DialerRequest::Dial(Box::new(peer), reply_tx))
+                .await
+                .unwrap();
+            reply_rx.await.unwrap()
+        }
+    };
+
+    // An unknown peer that does not request a puzzle is rejected
+    assert!(dial(0).await.is_err());
+
+    // The puzzle is negotiated with a peer that advertises support for it
+    let conn = dial(1 << protocol::HANDSHAKE_PUZZLE_PROTOCOL_VERSION).await.unwrap();
+    assert_eq!(conn.peer_node_id(), node_identity1.node_id());
+
+    // A dialer that does not request a puzzle is closed before the noise handshake, unless its IP address belongs to a
+    // known peer. Memory addresses have no IP address, so even the now known dialer is rejected.
+    assert!(dial(0).await.is_err());
+    let mut socket = MemoryTransport.dial(address.clone()).unwrap().await.unwrap();
+    socket.write_all(&[WireMode::Comms as u8]).await.unwrap();
+    let mut buf = [0u8; 1];
+    let n = timeout(Duration::from_secs(5), socket.read(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(n, 0);
+
+    shutdown.trigger().unwrap();
+    timeout(Duration::from_secs(5), listener_fut).await.unwrap().unwrap();
+    timeout(Duration::from_secs(5), dialer_fut).await.unwrap().unwrap();
+}
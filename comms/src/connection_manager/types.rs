@@ -20,6 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 /// Direction of the connection relative to this node
@@ -42,3 +43,88 @@ impl fmt::Display for ConnectionDirection {
         write!(f, "{:?}", self)
     }
 }
+
+/// Policy applied to peers that support a lower comms protocol version than required
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "min_version", rename_all = "snake_case")]
+pub enum PeerVersionPolicy {
+    /// Accept peers of any protocol version
+    AcceptAll,
+    /// Accept peers below the given protocol version, but log a warning
+    WarnBelow(u8),
+    /// Refuse connections to peers below the given protocol version
+    RefuseBelow(u8),
+}
+
+impl PeerVersionPolicy {
+    /// Returns true if a connection to a peer with the given protocol version bitmap should be refused
+    pub fn should_refuse(&self, protocol_versions: u32) -> bool {
+        match self {
+            PeerVersionPolicy::RefuseBelow(min_version) => max_protocol_version(protocol_versions) < *min_version,
+            _ => false,
+        }
+    }
+
+    /// Returns true if a warning should be logged for a peer with the given protocol version bitmap
+    pub fn should_warn(&self, protocol_versions: u32) -> bool {
+        match self {
+            PeerVersionPolicy::WarnBelow(min_version) => max_protocol_version(protocol_versions) < *min_version,
+            _ => false,
+        }
+    }
+}
+
+impl Default for PeerVersionPolicy {
+    fn default() -> Self {
+        PeerVersionPolicy::AcceptAll
+    }
+}
+
+/// Returns the highest protocol version set in the protocol version bitmap. Peers that do not send a bitmap (i.e. 0)
+/// are considered to support version 0.
+pub fn max_protocol_version(protocol_versions: u32) -> u8 {
+    if protocol_versions == 0 {
+        return 0;
+    }
+    (31 - protocol_versions.leading_zeros()) as u8
+}
+
+/// Returns true if the given protocol version is set in the protocol version bitmap
+pub fn supports_protocol_version(protocol_versions: u32, version: u8) -> bool {
+    version < 32 && protocol_versions & (1 << version) != 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn supports_protocol_version_from_bitmap() {
+        assert!(!supports_protocol_version(0, 0));
+        assert!(supports_protocol_version(0b101, 0));
+        assert!(!supports_protocol_version(0b101, 1));
+        assert!(supports_protocol_version(0b101, 2));
+        assert!(!supports_protocol_version(u32::MAX, 32));
+    }
+
+    #[test]
+    fn max_protocol_version_from_bitmap() {
+        assert_eq!(max_protocol_version(0), 0);
+        assert_eq!(max_protocol_version(0b1), 0);
+        assert_eq!(max_protocol_version(0b110), 2);
+        assert_eq!(max_protocol_version(u32::MAX), 31);
+    }
+
+    #[test]
+    fn peer_version_policy() {
+        assert!(!PeerVersionPolicy::AcceptAll.should_refuse(0));
+        assert!(!PeerVersionPolicy::AcceptAll.should_warn(0));
+
+        assert!(PeerVersionPolicy::WarnBelow(1).should_warn(0b1));
+        assert!(!PeerVersionPolicy::WarnBelow(1).should_warn(0b11));
+        assert!(!PeerVersionPolicy::WarnBelow(1).should_refuse(0b1));
+
+        assert!(PeerVersionPolicy::RefuseBelow(1).should_refuse(0b1));
+        assert!(!PeerVersionPolicy::RefuseBelow(1).should_refuse(0b10));
+    }
+}
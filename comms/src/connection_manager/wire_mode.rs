@@ -23,11 +23,15 @@
 use std::convert::TryFrom;
 
 const COMMS_WIRE_MODE: u8 = 0x06;
+const COMMS_WITH_PUZZLE_WIRE_MODE: u8 = 0x07;
 const LIVENESS_WIRE_MODE: u8 = 0x46; // E
 
 #[repr(u8)]
 pub enum WireMode {
     Comms = COMMS_WIRE_MODE,
+    /// Comms connection for which the listener sends a handshake puzzle that the dialer must solve before the noise
+    /// handshake begins
+    CommsWithPuzzle = COMMS_WITH_PUZZLE_WIRE_MODE,
     Liveness = LIVENESS_WIRE_MODE,
 }
 
@@ -37,6 +41,7 @@ impl TryFrom<u8> for WireMode {
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
             COMMS_WIRE_MODE => Ok(WireMode::Comms),
+            COMMS_WITH_PUZZLE_WIRE_MODE => Ok(WireMode::CommsWithPuzzle),
             LIVENESS_WIRE_MODE => Ok(WireMode::Liveness),
             _ => Err(()),
         }
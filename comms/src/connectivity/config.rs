@@ -20,6 +20,7 @@
 //  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
+use super::ScoreDecay;
 use std::time::Duration;
 
 #[derive(Debug, Clone, Copy)]
@@ -42,6 +43,45 @@ pub struct ConnectivityConfig {
     /// The length of time to wait before disconnecting a connection that failed tie breaking.
     /// Default: 1s
     pub connection_tie_break_linger: Duration,
+    /// The cooldown after the first failed dial to a managed peer before it is dialed again. The cooldown doubles for
+    /// each consecutive failure.
+    /// Default: 10s
+    pub dial_cooldown_base: Duration,
+    /// The maximum cooldown before re-dialing a managed peer that has failed to connect.
+    /// Default: 10 minutes
+    pub dial_cooldown_max: Duration,
+    /// The maximum number of background (managed peer) dials that may be in progress at once. Dials requested by
+    /// callers are not limited.
+    /// Default: 8
+    pub max_concurrent_background_dials: usize,
+    /// A connection with an identity exchange that takes longer than this is considered slow.
+    /// Default: 5s
+    pub slow_peer_handshake_threshold: Duration,
+    /// The number of consecutive slow connections before a peer is classified as slow. Slow peers are deprioritised
+    /// when selecting peers to broadcast to.
+    /// Default: 3
+    pub slow_peer_min_occurrences: usize,
+    /// The maximum number of peers to keep in the peer database. When exceeded, the lowest-value peers are removed
+    /// during the connection pool refresh. Pinned, managed and connected peers are never removed. None disables
+    /// pruning.
+    /// Default: None
+    pub max_peer_db_size: Option<usize>,
+    /// Interval at which peer offence scores are decayed.
+    /// Default: 60s
+    pub peer_score_decay_interval: Duration,
+    /// How the failed dial score for a peer decays over time. Each failed dial adds 1 to the score. Once the score has
+    /// fully decayed, the dial cooldown is reset and the peer is no longer marked as offline.
+    /// Default: Halves every 10 minutes
+    pub failed_dial_score_decay: ScoreDecay,
+    /// How the misbehaviour score for a peer decays over time.
+    /// Default: 10 every hour
+    pub misbehaviour_score_decay: ScoreDecay,
+    /// A peer is banned once their misbehaviour score reaches this threshold.
+    /// Default: 100
+    pub misbehaviour_ban_threshold: f32,
+    /// The length of time to ban a peer that reaches the misbehaviour ban threshold.
+    /// Default: 6 hours
+    pub misbehaviour_ban_duration: Duration,
 }
 
 impl Default for ConnectivityConfig {
@@ -53,6 +93,22 @@ impl Default for ConnectivityConfig {
             is_connection_reaping_enabled: true,
             max_failures_mark_offline: 1,
             connection_tie_break_linger: Duration::from_secs(2),
+            dial_cooldown_base: Duration::from_secs(10),
+            dial_cooldown_max: Duration::from_secs(10 * 60),
+            max_concurrent_background_dials: 8,
+            slow_peer_handshake_threshold: Duration::from_secs(5),
+            slow_peer_min_occurrences: 3,
+            max_peer_db_size: None,
+            peer_score_decay_interval: Duration::from_secs(60),
+            failed_dial_score_decay: ScoreDecay::Exponential {
+                half_life: Duration::from_secs(10 * 60),
+            },
+            misbehaviour_score_decay: ScoreDecay::Linear {
+                amount: 10.0,
+                period: Duration::from_secs(60 * 60),
+            },
+            misbehaviour_ban_threshold: 100.0,
+            misbehaviour_ban_duration: Duration::from_secs(6 * 60 * 60),
         }
     }
 }
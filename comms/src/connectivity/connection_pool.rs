@@ -258,6 +258,11 @@ impl ConnectionPool {
             .count()
     }
 
+    /// Returns the number of connections that are being dialed
+    pub fn count_dialing(&self) -> usize {
+        self.count_status(ConnectionStatus::Connecting) + self.count_status(ConnectionStatus::Retrying)
+    }
+
     pub fn count_failed(&self) -> usize {
         self.count_status(ConnectionStatus::Failed)
     }
@@ -0,0 +1,245 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::peer_manager::NodeId;
+use std::{
+    cmp,
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// The maximum number of peers for which dial failures are tracked. Once reached, expired entries are pruned and then
+/// the peer with the oldest failure is forgotten to make room.
+const MAX_TRACKED_DIAL_FAILURES: usize = 1000;
+
+/// Consecutive dial failures for a peer
+#[derive(Debug, Clone, Copy)]
+struct DialFailures {
+    num_failures: u32,
+    last_failed_at: Instant,
+}
+
+/// Queue of background (i.e. managed peer) dials. Background dials are dispatched a few at a time so that they do not
+/// compete with dials requested by active sends, which bypass this queue entirely. Peers that fail to connect are
+/// placed in an exponentially increasing cooldown before they will be dialed again.
+#[derive(Debug)]
+pub struct DialQueue {
+    queue: VecDeque<NodeId>,
+    failures: HashMap<NodeId, DialFailures>,
+    cooldown_base: Duration,
+    cooldown_max: Duration,
+}
+
+impl DialQueue {
+    pub fn new(cooldown_base: Duration, cooldown_max: Duration) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            failures: HashMap::new(),
+            cooldown_base,
+            cooldown_max,
+        }
+    }
+
+    /// Add a peer to the back of the queue, if it is not already queued
+    pub fn push(&mut self, node_id: NodeId) {
+        if !self.queue.contains(&node_id) {
+            self.queue.push_back(node_id);
+        }
+    }
+
+    /// Remove a peer from the queue. This is called when a peer is dialed outside of the queue.
+    pub fn remove(&mut self, node_id: &NodeId) {
+        self.queue.retain(|n| n != node_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Record a failed dial for the peer, increasing the cooldown period before it will be dialed again
+    pub fn mark_failed(&mut self, node_id: NodeId) {
+        if !self.failures.contains_key(&node_id) && self.failures.len() >= MAX_TRACKED_DIAL_FAILURES {
+            self.prune_expired();
+            if self.failures.len() >= MAX_TRACKED_DIAL_FAILURES {
+                let oldest = self
+                    .failures
+                    .iter()
+                    .min_by_key(|(_, f)| f.last_failed_at)
+                    .map(|(n, _)| n.clone());
+                if let Some(oldest) = oldest {
+                    self.failures.remove(&oldest);
+                }
+            }
+        }
+        let entry = self.failures.entry(node_id).or_insert(DialFailures {
+            num_failures: 0,
+            last_failed_at: Instant::now(),
+        });
+        entry.num_failures = entry.num_failures.saturating_add(1);
+        entry.last_failed_at = Instant::now();
+    }
+
+    /// Record a successful connection to the peer, clearing any cooldown
+    pub fn mark_succeeded(&mut self, node_id: &NodeId) {
+        self.failures.remove(node_id);
+    }
+
+    /// Forget the failures of peers whose cooldown ended more than `cooldown_max` ago
+    pub fn prune_expired(&mut self) {
+        let cooldown_base = self.cooldown_base;
+        let cooldown_max = self.cooldown_max;
+        self.failures.retain(|_, f| {
+            let max_age = cooldown_for(cooldown_base, cooldown_max, f.num_failures) + cooldown_max;
+            f.last_failed_at.elapsed() <= max_age
+        });
+    }
+
+    /// The number of peers for which dial failures are tracked
+    #[cfg(test)]
+    pub fn num_tracked_failures(&self) -> usize {
+        self.failures.len()
+    }
+
+    /// Returns the number of consecutive failed dials for the peer
+    pub fn num_failures(&self, node_id: &NodeId) -> u32 {
+        self.failures.get(node_id).map(|f| f.num_failures).unwrap_or(0)
+    }
+
+    /// Returns the remaining cooldown for the peer, or None if the peer may be dialed now
+    pub fn cooldown_remaining(&self, node_id: &NodeId) -> Option<Duration> {
+        let failures = self.failures.get(node_id)?;
+        let cooldown = self.cooldown_for(failures.num_failures);
+        cooldown
+            .checked_sub(failures.last_failed_at.elapsed())
+            .filter(|remaining| *remaining > Duration::from_secs(0))
+    }
+
+    /// Remove and return up to `n` queued peers that are not in cooldown. Peers that are still in cooldown keep their
+    /// place in the queue.
+    pub fn pop_ready(&mut self, n: usize) -> Vec<NodeId> {
+        let mut ready = Vec::with_capacity(cmp::min(n, self.queue.len()));
+        let mut i = 0;
+        while ready.len() < n && i < self.queue.len() {
+            if self.cooldown_remaining(&self.queue[i]).is_some() {
+                i += 1;
+                continue;
+            }
+            ready.push(self.queue.remove(i).expect("index checked"));
+        }
+        ready
+    }
+
+    fn cooldown_for(&self, num_failures: u32) -> Duration {
+        cooldown_for(self.cooldown_base, self.cooldown_max, num_failures)
+    }
+}
+
+/// Cooldown is `cooldown_base * 2^(num_failures - 1)`, capped at `cooldown_max`
+fn cooldown_for(cooldown_base: Duration, cooldown_max: Duration, num_failures: u32) -> Duration {
+    if num_failures == 0 {
+        return Duration::from_secs(0);
+    }
+    let factor = 1u32.checked_shl(num_failures - 1).unwrap_or(u32::MAX);
+    cooldown_base
+        .checked_mul(factor)
+        .map(|d| cmp::min(d, cooldown_max))
+        .unwrap_or(cooldown_max)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::node_id;
+
+    #[test]
+    fn push_and_pop() {
+        let mut queue = DialQueue::new(Duration::from_secs(10), Duration::from_secs(60));
+        let node_ids = (0..3).map(|_| node_id::random()).collect::<Vec<_>>();
+        node_ids.iter().cloned().for_each(|n| queue.push(n));
+        // Duplicate push is ignored
+        queue.push(node_ids[0].clone());
+        assert_eq!(queue.len(), 3);
+
+        let popped = queue.pop_ready(2);
+        assert_eq!(popped, node_ids[..2].to_vec());
+        queue.remove(&node_ids[2]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn failed_peers_are_skipped_during_cooldown() {
+        let mut queue = DialQueue::new(Duration::from_secs(10), Duration::from_secs(60));
+        let failed = node_id::random();
+        let other = node_id::random();
+        queue.mark_failed(failed.clone());
+        queue.push(failed.clone());
+        queue.push(other.clone());
+
+        assert!(queue.cooldown_remaining(&failed).is_some());
+        assert_eq!(queue.pop_ready(2), vec![other]);
+        assert_eq!(queue.len(), 1);
+
+        queue.mark_succeeded(&failed);
+        assert!(queue.cooldown_remaining(&failed).is_none());
+        assert_eq!(queue.pop_ready(2), vec![failed]);
+    }
+
+    #[test]
+    fn cooldown_increases_exponentially() {
+        let queue = DialQueue::new(Duration::from_secs(10), Duration::from_secs(60));
+        assert_eq!(queue.cooldown_for(0), Duration::from_secs(0));
+        assert_eq!(queue.cooldown_for(1), Duration::from_secs(10));
+        assert_eq!(queue.cooldown_for(2), Duration::from_secs(20));
+        assert_eq!(queue.cooldown_for(3), Duration::from_secs(40));
+        assert_eq!(queue.cooldown_for(4), Duration::from_secs(60));
+        assert_eq!(queue.cooldown_for(100), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn failures_are_bounded() {
+        let mut queue = DialQueue::new(Duration::from_secs(10), Duration::from_secs(60));
+        let first = node_id::random();
+        queue.mark_failed(first.clone());
+        std::thread::sleep(Duration::from_millis(1));
+        for _ in 1..MAX_TRACKED_DIAL_FAILURES + 10 {
+            queue.mark_failed(node_id::random());
+        }
+        assert_eq!(queue.num_tracked_failures(), MAX_TRACKED_DIAL_FAILURES);
+        // The oldest failure is forgotten first
+        assert_eq!(queue.num_failures(&first), 0);
+    }
+
+    #[test]
+    fn expired_failures_are_pruned() {
+        let mut queue = DialQueue::new(Duration::from_millis(1), Duration::from_millis(1));
+        queue.mark_failed(node_id::random());
+        queue.prune_expired();
+        assert_eq!(queue.num_tracked_failures(), 1);
+        std::thread::sleep(Duration::from_millis(5));
+        queue.prune_expired();
+        assert_eq!(queue.num_tracked_failures(), 0);
+    }
+}
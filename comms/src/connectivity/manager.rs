@@ -23,11 +23,15 @@ use super::{
     config::ConnectivityConfig,
     connection_pool::{ConnectionPool, ConnectionStatus},
     connection_stats::PeerConnectionStats,
+    dial_queue::DialQueue,
     error::ConnectivityError,
+    peer_score::{PeerOffence, PeerScores},
     requester::{ConnectivityEvent, ConnectivityRequest},
     selection::ConnectivitySelection,
+    slow_peers::SlowPeers,
 };
 use crate::{
+    builder::ShutdownReporter,
     connection_manager::{
         ConnectionDirection,
         ConnectionManagerError,
@@ -36,12 +40,17 @@ use crate::{
     },
     peer_manager::NodeId,
     runtime::task,
-    utils::datetime::format_duration,
+    security_events::{SecurityEventKind, SecurityEventPublisher},
+    utils::{datetime::format_duration, rng::SharedRng},
     NodeIdentity,
     PeerConnection,
     PeerManager,
 };
-use futures::{channel::mpsc, stream::Fuse, StreamExt};
+use futures::{
+    channel::{mpsc, oneshot},
+    stream::Fuse,
+    StreamExt,
+};
 use log::*;
 use nom::lib::std::collections::hash_map::Entry;
 use std::{
@@ -51,10 +60,13 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
+use tari_crypto::tari_utilities::hex::Hex;
 use tari_shutdown::ShutdownSignal;
 use tokio::{sync::broadcast, task::JoinHandle, time};
 
 const LOG_TARGET: &str = "comms::connectivity::manager";
+/// Slow connection counts are forgotten for peers that have not had a slow connection for this long
+const SLOW_PEER_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
 
 /// # Connectivity Manager
 ///
@@ -76,6 +88,9 @@ pub struct ConnectivityManager {
     pub peer_manager: Arc<PeerManager>,
     pub node_identity: Arc<NodeIdentity>,
     pub shutdown_signal: ShutdownSignal,
+    pub shutdown_reporter: ShutdownReporter,
+    pub security_events: SecurityEventPublisher,
+    pub rng: SharedRng,
 }
 
 impl ConnectivityManager {
@@ -93,7 +108,17 @@ impl ConnectivityManager {
             managed_peers: Vec::new(),
 
             shutdown_signal: Some(self.shutdown_signal),
+            shutdown_reporter: self.shutdown_reporter,
+            security_events: self.security_events,
+            rng: self.rng,
             pool: ConnectionPool::new(),
+            connected_node_waiters: Vec::new(),
+            dial_queue: DialQueue::new(self.config.dial_cooldown_base, self.config.dial_cooldown_max),
+            slow_peers: SlowPeers::new(SLOW_PEER_MAX_AGE),
+            peer_scores: PeerScores::new(
+                self.config.failed_dial_score_decay,
+                self.config.misbehaviour_score_decay,
+            ),
         }
     }
 }
@@ -137,12 +162,24 @@ pub struct ConnectivityManagerActor {
     connection_manager: ConnectionManagerRequester,
     node_identity: Arc<NodeIdentity>,
     shutdown_signal: Option<ShutdownSignal>,
+    shutdown_reporter: ShutdownReporter,
+    security_events: SecurityEventPublisher,
     peer_manager: Arc<PeerManager>,
     event_tx: broadcast::Sender<Arc<ConnectivityEvent>>,
     connection_stats: HashMap<NodeId, PeerConnectionStats>,
 
     managed_peers: Vec<NodeId>,
     pool: ConnectionPool,
+    /// Pending `wait_for` requests, resolved once the given number of nodes are connected
+    connected_node_waiters: Vec<(usize, oneshot::Sender<()>)>,
+    /// Background dials for managed peers
+    dial_queue: DialQueue,
+    /// Number of consecutive slow connections for each peer
+    slow_peers: SlowPeers,
+    /// Offence scores for misbehaving and unreachable peers
+    peer_scores: PeerScores,
+    /// Used to select random connections
+    rng: SharedRng,
 }
 
 impl ConnectivityManagerActor {
@@ -169,6 +206,16 @@ impl ConnectivityManagerActor {
         )
         .fuse();
 
+        let decay_interval = self.config.peer_score_decay_interval;
+        let mut decay_ticker = time::interval_at(
+            Instant::now()
+                .checked_add(decay_interval)
+                .expect("peer_score_decay_interval cause overflow")
+                .into(),
+            decay_interval,
+        )
+        .fuse();
+
         self.publish_event(ConnectivityEvent::ConnectivityStateInitialized);
 
         loop {
@@ -191,9 +238,18 @@ impl ConnectivityManagerActor {
                     }
                 },
 
+                _ = decay_ticker.next() => {
+                    self.dial_queue.prune_expired();
+                    self.slow_peers.prune_expired();
+                    if let Err(err) = self.decay_peer_scores().await {
+                        error!(target: LOG_TARGET, "Error when decaying peer scores: {:?}", err);
+                    }
+                },
+
                 _ = shutdown_signal => {
                     info!(target: LOG_TARGET, "ConnectivityManager is shutting down because it received the shutdown signal");
-                    self.disconnect_all().await;
+                    let num_closed = self.disconnect_all().await;
+                    self.shutdown_reporter.add_connections_closed(num_closed);
                     break;
                 }
             }
@@ -225,6 +281,8 @@ impl ConnectivityManagerActor {
                         "No existing connection found for peer `{}`. Dialling...",
                         node_id.short_str()
                     );
+                    // Dials requested by callers take priority over and are not subject to the background dial queue
+                    self.dial_queue.remove(&node_id);
                     if let Err(err) = self.connection_manager.send_dial_peer(node_id, reply).await {
                         error!(
                             target: LOG_TARGET,
@@ -268,6 +326,14 @@ impl ConnectivityManagerActor {
                     error!(target: LOG_TARGET, "Error when banning peer: {:?}", err);
                 }
             },
+            ReportMisbehaviour(node_id, score, reason) => {
+                if let Err(err) = self.handle_misbehaviour(node_id, score, reason).await {
+                    error!(target: LOG_TARGET, "Error when handling peer misbehaviour: {:?}", err);
+                }
+            },
+            ForgivePeer(node_id, reply) => {
+                let _ = reply.send(self.forgive_peer(&node_id).await);
+            },
             GetActiveConnections(reply) => {
                 let _ = reply.send(
                     self.pool
@@ -277,10 +343,35 @@ impl ConnectivityManagerActor {
                         .collect(),
                 );
             },
+            WaitForConnectedNodes(n, reply) => {
+                if self.pool.count_connected_nodes() >= n {
+                    let _ = reply.send(());
+                } else {
+                    self.connected_node_waiters.push((n, reply));
+                }
+            },
         }
     }
 
-    async fn disconnect_all(&mut self) {
+    fn notify_connected_node_waiters(&mut self, num_connected_nodes: usize) {
+        if self.connected_node_waiters.is_empty() {
+            return;
+        }
+
+        let (ready, pending) = self
+            .connected_node_waiters
+            .drain(..)
+            // Discard waiters that have given up waiting
+            .filter(|(_, reply)| !reply.is_canceled())
+            .partition::<Vec<_>, _>(|(n, _)| num_connected_nodes >= *n);
+        self.connected_node_waiters = pending;
+        for (_, reply) in ready {
+            let _ = reply.send(());
+        }
+    }
+
+    /// Disconnect all connected peers, returning the number of connections that were closed
+    async fn disconnect_all(&mut self) -> usize {
         let mut node_ids = Vec::with_capacity(self.pool.count_connected());
         for mut state in self.pool.filter_drain(|_| true) {
             if let Some(conn) = state.connection_mut() {
@@ -300,9 +391,11 @@ impl ConnectivityManagerActor {
             }
         }
 
+        let num_closed = node_ids.len();
         for node_id in node_ids {
             self.publish_event(ConnectivityEvent::PeerDisconnected(node_id));
         }
+        num_closed
     }
 
     async fn refresh_connection_pool(&mut self) -> Result<(), ConnectivityError> {
@@ -324,41 +417,88 @@ impl ConnectivityManagerActor {
         // Remove disconnected/failed peers from the connection pool
         self.clean_connection_pool();
         self.update_connectivity_status();
+        if let Some(max_size) = self.config.max_peer_db_size {
+            self.prune_peer_db(max_size).await?;
+        }
+        Ok(())
+    }
+
+    /// Keep the peer database below the configured size. Managed peers and peers in the connection pool are never
+    /// removed.
+    async fn prune_peer_db(&mut self, max_size: usize) -> Result<(), ConnectivityError> {
+        let exclude = self
+            .managed_peers
+            .iter()
+            .cloned()
+            .chain(self.pool.all().into_iter().map(|state| state.node_id().clone()))
+            .collect::<Vec<_>>();
+        let num_removed = self.peer_manager.prune(max_size, &exclude).await?;
+        if num_removed > 0 {
+            debug!(
+                target: LOG_TARGET,
+                "Removed {} peer(s) from the peer database to keep it below {} peer(s)", num_removed, max_size
+            );
+        }
         Ok(())
     }
 
     async fn try_connect_managed_peers(&mut self) -> Result<(), ConnectivityError> {
         for node_id in &self.managed_peers {
             match self.pool.get_connection_status(node_id) {
-                ConnectionStatus::Failed => {
-                    let status = self.pool.set_status(node_id, ConnectionStatus::Retrying);
-                    debug!(
-                        target: LOG_TARGET,
-                        "{} peer '{}' is managed. Retrying.", status, node_id
-                    );
-                    self.connection_manager.send_dial_peer_no_reply(node_id.clone()).await?;
-                },
-                ConnectionStatus::Disconnected => {
-                    let status = self.pool.set_status(node_id, ConnectionStatus::Retrying);
-                    debug!(
-                        target: LOG_TARGET,
-                        "{} peer '{}' is managed. Retrying.", status, node_id
-                    );
-                    self.connection_manager.send_dial_peer_no_reply(node_id.clone()).await?;
-                    // self.send_dial_request(node_id.clone()).await?;
-                },
-                ConnectionStatus::NotConnected => {
-                    let status = self.pool.set_status(node_id, ConnectionStatus::Connecting);
+                ConnectionStatus::Failed | ConnectionStatus::Disconnected | ConnectionStatus::NotConnected => {
                     debug!(
                         target: LOG_TARGET,
-                        "{} peer '{}' is managed. Connecting.", status, node_id
+                        "Peer '{}' is managed but not connected. Queuing dial.", node_id
                     );
-                    self.connection_manager.send_dial_peer_no_reply(node_id.clone()).await?;
+                    self.dial_queue.push(node_id.clone());
                 },
                 _ => {},
             }
         }
 
+        self.process_dial_queue().await
+    }
+
+    /// Dispatch queued background dials, as long as fewer than `max_concurrent_background_dials` dials are in progress.
+    /// Peers that are cooling down after failed dials remain queued.
+    async fn process_dial_queue(&mut self) -> Result<(), ConnectivityError> {
+        if self.dial_queue.is_empty() {
+            return Ok(());
+        }
+
+        let num_available = self
+            .config
+            .max_concurrent_background_dials
+            .saturating_sub(self.pool.count_dialing());
+        for node_id in self.dial_queue.pop_ready(num_available) {
+            if !self.managed_peers.contains(&node_id) {
+                continue;
+            }
+            let next_status = match self.pool.insert(node_id.clone()) {
+                ConnectionStatus::Failed | ConnectionStatus::Disconnected => ConnectionStatus::Retrying,
+                ConnectionStatus::NotConnected => ConnectionStatus::Connecting,
+                // Connected or a dial is already in progress
+                _ => continue,
+            };
+            let status = self.pool.set_status(&node_id, next_status);
+            debug!(
+                target: LOG_TARGET,
+                "{} peer '{}' is managed. Dialing ({} failed attempt(s)).",
+                status,
+                node_id,
+                self.dial_queue.num_failures(&node_id)
+            );
+            self.connection_manager.send_dial_peer_no_reply(node_id).await?;
+        }
+
+        if !self.dial_queue.is_empty() {
+            trace!(
+                target: LOG_TARGET,
+                "{} background dial(s) remain queued",
+                self.dial_queue.len()
+            );
+        }
+
         Ok(())
     }
 
@@ -424,14 +564,14 @@ impl ConnectivityManagerActor {
             self.pool.count_connected_nodes()
         );
 
-        let conns = selection.select(&self.pool);
+        let slow_peers = self.slow_peers.get_slow_peers(self.config.slow_peer_min_occurrences);
+        let conns = selection.select_with_rng(&self.pool, &slow_peers, &mut self.rng.clone());
         debug!(target: LOG_TARGET, "Selected {} connections(s)", conns.len());
 
         Ok(conns.into_iter().cloned().collect())
     }
 
     async fn add_managed_peers(&mut self, node_ids: Vec<NodeId>) {
-        let pool = &mut self.pool;
         let mut should_update_connectivity = false;
         for node_id in node_ids {
             if !self.managed_peers.contains(&node_id) {
@@ -439,31 +579,10 @@ impl ConnectivityManagerActor {
                 should_update_connectivity = true;
             }
 
-            match pool.insert(node_id.clone()) {
-                ConnectionStatus::Failed => {
-                    debug!(
-                        target: LOG_TARGET,
-                        "Retrying connection to failed managed peer '{}'", node_id
-                    );
-                    pool.set_status(&node_id, ConnectionStatus::Retrying);
-                    if let Err(err) = self.connection_manager.send_dial_peer_no_reply(node_id.clone()).await {
-                        error!(
-                            target: LOG_TARGET,
-                            "Failed to send dial request to connection manager: {:?}", err
-                        );
-                        // Remove from this pool, it may be re-added later by the periodic connection refresh
-                        pool.remove(&node_id);
-                    }
-                },
-                ConnectionStatus::NotConnected | ConnectionStatus::Disconnected => {
-                    debug!(target: LOG_TARGET, "Dialing offline managed peer '{}'", node_id);
-                    pool.set_status(&node_id, ConnectionStatus::Connecting);
-                    if let Err(err) = self.connection_manager.send_dial_peer_no_reply(node_id.clone()).await {
-                        error!(
-                            target: LOG_TARGET,
-                            "Failed to send dial request to connection manager: {:?}", err
-                        );
-                    }
+            match self.pool.insert(node_id.clone()) {
+                ConnectionStatus::Failed | ConnectionStatus::NotConnected | ConnectionStatus::Disconnected => {
+                    debug!(target: LOG_TARGET, "Queuing dial to offline managed peer '{}'", node_id);
+                    self.dial_queue.push(node_id);
                 },
                 status => debug!(
                     target: LOG_TARGET,
@@ -472,6 +591,13 @@ impl ConnectivityManagerActor {
             }
         }
 
+        if let Err(err) = self.process_dial_queue().await {
+            error!(
+                target: LOG_TARGET,
+                "Failed to send dial request to connection manager: {:?}", err
+            );
+        }
+
         if should_update_connectivity {
             self.update_connectivity_status();
         }
@@ -482,10 +608,124 @@ impl ConnectivityManagerActor {
     fn remove_peer(&mut self, node_id: &NodeId) -> Option<NodeId> {
         let pos = self.managed_peers.iter().position(|n| n == node_id)?;
         let removed_peer = self.managed_peers.remove(pos);
+        self.dial_queue.remove(&removed_peer);
         self.update_connectivity_status();
         Some(removed_peer)
     }
 
+    fn record_handshake_duration(&mut self, conn: &PeerConnection) {
+        let handshake_duration = match conn.handshake_duration() {
+            Some(d) => d,
+            None => return,
+        };
+
+        if handshake_duration > self.config.slow_peer_handshake_threshold {
+            let count = self.slow_peers.record_slow(conn.peer_node_id().clone());
+            debug!(
+                target: LOG_TARGET,
+                "Peer '{}' took {:.2?} to complete the handshake ({} consecutive slow connection(s))",
+                conn.peer_node_id().short_str(),
+                handshake_duration,
+                count
+            );
+        } else {
+            self.slow_peers.clear(conn.peer_node_id());
+        }
+    }
+
+    async fn handle_misbehaviour(
+        &mut self,
+        node_id: NodeId,
+        score: f32,
+        reason: String,
+    ) -> Result<(), ConnectivityError>
+    {
+        let total = self
+            .peer_scores
+            .penalise(node_id.clone(), PeerOffence::Misbehaviour, score);
+        debug!(
+            target: LOG_TARGET,
+            "Peer '{}' misbehaved ({}). Misbehaviour score is now {:.1}",
+            node_id.short_str(),
+            reason,
+            total
+        );
+        if total >= self.config.misbehaviour_ban_threshold {
+            self.ban_peer(
+                &node_id,
+                self.config.misbehaviour_ban_duration,
+                format!(
+                    "Misbehaviour score of {:.1} reached the ban threshold ({})",
+                    total, reason
+                ),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Decay peer offence scores. Peers whose failed dial score has fully decayed have their dial cooldown reset and
+    /// are no longer considered offline.
+    async fn decay_peer_scores(&mut self) -> Result<(), ConnectivityError> {
+        if self.peer_scores.is_empty() {
+            return Ok(());
+        }
+
+        let rehabilitated = self.peer_scores.decay();
+        trace!(
+            target: LOG_TARGET,
+            "Decayed peer scores. {} peer(s) with a score remain",
+            self.peer_scores.len()
+        );
+        for (node_id, offence) in rehabilitated {
+            debug!(
+                target: LOG_TARGET,
+                "{:?} score for peer '{}' has fully decayed",
+                offence,
+                node_id.short_str()
+            );
+            if offence == PeerOffence::FailedDial {
+                self.clear_failed_dials(&node_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Manually clear all offences for a peer and lift any ban
+    async fn forgive_peer(&mut self, node_id: &NodeId) -> Result<(), ConnectivityError> {
+        info!(
+            target: LOG_TARGET,
+            "Forgiving peer '{}' (failed dial score = {:.1}, misbehaviour score = {:.1})",
+            node_id,
+            self.peer_scores.score(node_id, PeerOffence::FailedDial),
+            self.peer_scores.score(node_id, PeerOffence::Misbehaviour),
+        );
+        self.peer_scores.forgive(node_id);
+        self.slow_peers.clear(node_id);
+        self.clear_failed_dials(node_id).await?;
+        self.peer_manager.unban_peer(node_id).await?;
+        Ok(())
+    }
+
+    async fn clear_failed_dials(&mut self, node_id: &NodeId) -> Result<(), ConnectivityError> {
+        self.dial_queue.mark_succeeded(node_id);
+        self.connection_stats.remove(node_id);
+        match self.peer_manager.set_offline(node_id, false).await {
+            Ok(true) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Peer '{}' is no longer considered offline",
+                    node_id.short_str()
+                );
+            },
+            Ok(false) => {},
+            // The peer may have been removed from the peer database
+            Err(err) if err.is_peer_not_found() => {},
+            Err(err) => return Err(err.into()),
+        }
+        Ok(())
+    }
+
     fn get_connection_stat_mut(&mut self, node_id: NodeId) -> &mut PeerConnectionStats {
         match self.connection_stats.entry(node_id) {
             Entry::Occupied(entry) => entry.into_mut(),
@@ -543,7 +783,6 @@ impl ConnectivityManagerActor {
     ) -> Result<(), ConnectivityError>
     {
         use ConnectionManagerEvent::*;
-        #[allow(clippy::single_match)]
         match event {
             PeerConnected(new_conn) => {
                 self.connection_manager
@@ -604,6 +843,10 @@ impl ConnectivityManagerActor {
                     _ => {},
                 }
             },
+            PeerAddressesChanged(identity) => {
+                self.publish_event(ConnectivityEvent::PeerAddressesChanged(identity.clone()));
+                return Ok(());
+            },
             _ => {},
         }
 
@@ -626,6 +869,9 @@ impl ConnectivityManagerActor {
                     target: LOG_TARGET,
                     "Connection to peer '{}' failed because '{:?}'", node_id, err
                 );
+                self.dial_queue.mark_failed((**node_id).clone());
+                self.peer_scores
+                    .penalise((**node_id).clone(), PeerOffence::FailedDial, 1.0);
                 self.handle_peer_connection_failure(node_id).await?;
                 (&**node_id, ConnectionStatus::Failed, None)
             },
@@ -650,8 +896,10 @@ impl ConnectivityManagerActor {
         match (old_status, new_status) {
             (_, Connected) => {
                 self.mark_peer_succeeded(node_id.clone());
+                self.dial_queue.mark_succeeded(&node_id);
                 match self.pool.get_connection(&node_id).cloned() {
                     Some(conn) => {
+                        self.record_handshake_duration(&conn);
                         self.publish_event(ConnectivityEvent::PeerConnected(conn));
                     },
                     None => unreachable!(
@@ -684,6 +932,8 @@ impl ConnectivityManagerActor {
             },
         }
 
+        // A dial may have completed, so there may be capacity for more background dials
+        self.process_dial_queue().await?;
         self.update_connectivity_status();
         Ok(())
     }
@@ -738,6 +988,8 @@ impl ConnectivityManagerActor {
             num_connected_clients
         );
 
+        self.notify_connected_node_waiters(num_connected_nodes);
+
         match num_connected_nodes {
             n if n >= min_peers => {
                 self.transition(ConnectivityStatus::Online(n), min_peers);
@@ -826,9 +1078,16 @@ impl ConnectivityManagerActor {
             debug!(target: LOG_TARGET, "Banned managed peer '{}'", node_id);
         }
 
-        self.peer_manager.ban_peer_by_node_id(node_id, duration, reason).await?;
+        self.peer_manager
+            .ban_peer_by_node_id(node_id, duration, reason.clone())
+            .await?;
 
         self.publish_event(ConnectivityEvent::PeerBanned(node_id.clone()));
+        self.security_events.publish(SecurityEventKind::PeerBanned {
+            node_id: node_id.to_hex(),
+            duration_secs: duration.as_secs(),
+            reason,
+        });
 
         if let Some(conn) = self.pool.get_connection_mut(node_id) {
             conn.disconnect().await?;
@@ -27,6 +27,8 @@ pub use config::ConnectivityConfig;
 
 mod connection_pool;
 
+mod dial_queue;
+
 mod error;
 pub use error::ConnectivityError;
 
@@ -38,8 +40,13 @@ mod requester;
 pub(crate) use requester::ConnectivityRequest;
 pub use requester::{ConnectivityEvent, ConnectivityEventRx, ConnectivityEventTx, ConnectivityRequester};
 
+mod peer_score;
+pub use peer_score::ScoreDecay;
+
 mod selection;
 pub use selection::ConnectivitySelection;
 
+mod slow_peers;
+
 #[cfg(test)]
 mod test;
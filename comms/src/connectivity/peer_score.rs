@@ -0,0 +1,228 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::peer_manager::NodeId;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Scores below this value are considered to have fully decayed
+const MIN_SCORE: f32 = 0.1;
+
+/// An offence that counts against a peer's score
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PeerOffence {
+    /// This node failed to connect to the peer
+    FailedDial,
+    /// The peer misbehaved, for e.g. by sending invalid messages
+    Misbehaviour,
+}
+
+/// How the score for an offence decays over time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreDecay {
+    /// The score never decays
+    Never,
+    /// The score is reduced by `amount` every `period`
+    Linear { amount: f32, period: Duration },
+    /// The score halves every `half_life`
+    Exponential { half_life: Duration },
+}
+
+impl ScoreDecay {
+    /// Returns the score after decaying for `elapsed` time
+    pub fn apply(&self, score: f32, elapsed: Duration) -> f32 {
+        use ScoreDecay::*;
+        match self {
+            Never => score,
+            Linear { amount, period } => {
+                if period.as_secs_f32() <= 0.0 {
+                    return 0.0;
+                }
+                let decayed = amount * (elapsed.as_secs_f32() / period.as_secs_f32());
+                (score - decayed).max(0.0)
+            },
+            Exponential { half_life } => {
+                if half_life.as_secs_f32() <= 0.0 {
+                    return 0.0;
+                }
+                score * 0.5f32.powf(elapsed.as_secs_f32() / half_life.as_secs_f32())
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PeerScore {
+    failed_dial: f32,
+    misbehaviour: f32,
+}
+
+impl PeerScore {
+    fn get_mut(&mut self, offence: PeerOffence) -> &mut f32 {
+        match offence {
+            PeerOffence::FailedDial => &mut self.failed_dial,
+            PeerOffence::Misbehaviour => &mut self.misbehaviour,
+        }
+    }
+
+    fn is_clear(&self) -> bool {
+        self.failed_dial == 0.0 && self.misbehaviour == 0.0
+    }
+}
+
+/// Offence scores for peers. Scores accumulate as peers offend and decay over time according to the `ScoreDecay` for
+/// the offence, so that peers which were only transiently faulty are rehabilitated.
+#[derive(Debug)]
+pub struct PeerScores {
+    scores: HashMap<NodeId, PeerScore>,
+    failed_dial_decay: ScoreDecay,
+    misbehaviour_decay: ScoreDecay,
+    last_decayed_at: Instant,
+}
+
+impl PeerScores {
+    pub fn new(failed_dial_decay: ScoreDecay, misbehaviour_decay: ScoreDecay) -> Self {
+        Self {
+            scores: HashMap::new(),
+            failed_dial_decay,
+            misbehaviour_decay,
+            last_decayed_at: Instant::now(),
+        }
+    }
+
+    /// Add `amount` to the peer's score for the offence, returning the new score
+    pub fn penalise(&mut self, node_id: NodeId, offence: PeerOffence, amount: f32) -> f32 {
+        let score = self.scores.entry(node_id).or_default().get_mut(offence);
+        *score += amount.max(0.0);
+        *score
+    }
+
+    /// Returns the peer's current score for the offence
+    pub fn score(&self, node_id: &NodeId, offence: PeerOffence) -> f32 {
+        self.scores
+            .get(node_id)
+            .map(|s| match offence {
+                PeerOffence::FailedDial => s.failed_dial,
+                PeerOffence::Misbehaviour => s.misbehaviour,
+            })
+            .unwrap_or(0.0)
+    }
+
+    /// Clear all scores for the peer. Returns true if the peer had any score, otherwise false.
+    pub fn forgive(&mut self, node_id: &NodeId) -> bool {
+        self.scores.remove(node_id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Decay all scores by the time elapsed since the last decay. Returns the offences that have fully decayed.
+    pub fn decay(&mut self) -> Vec<(NodeId, PeerOffence)> {
+        let elapsed = self.last_decayed_at.elapsed();
+        self.last_decayed_at = Instant::now();
+        self.decay_by(elapsed)
+    }
+
+    fn decay_by(&mut self, elapsed: Duration) -> Vec<(NodeId, PeerOffence)> {
+        let mut rehabilitated = Vec::new();
+        let decay_curves = [
+            (PeerOffence::FailedDial, self.failed_dial_decay),
+            (PeerOffence::Misbehaviour, self.misbehaviour_decay),
+        ];
+        for (node_id, peer_score) in &mut self.scores {
+            for (offence, curve) in &decay_curves {
+                let score = peer_score.get_mut(*offence);
+                if *score == 0.0 {
+                    continue;
+                }
+                *score = curve.apply(*score, elapsed);
+                if *score < MIN_SCORE {
+                    *score = 0.0;
+                    rehabilitated.push((node_id.clone(), *offence));
+                }
+            }
+        }
+        self.scores.retain(|_, s| !s.is_clear());
+        rehabilitated
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::node_id;
+
+    #[test]
+    fn decay_curves() {
+        let decay = ScoreDecay::Linear {
+            amount: 10.0,
+            period: Duration::from_secs(60),
+        };
+        assert!((decay.apply(25.0, Duration::from_secs(60)) - 15.0).abs() < 0.001);
+        assert!(decay.apply(25.0, Duration::from_secs(600)).abs() < 0.001);
+
+        let decay = ScoreDecay::Exponential {
+            half_life: Duration::from_secs(60),
+        };
+        assert!((decay.apply(8.0, Duration::from_secs(120)) - 2.0).abs() < 0.001);
+
+        assert!((ScoreDecay::Never.apply(8.0, Duration::from_secs(1_000_000)) - 8.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn penalise_decay_and_forgive() {
+        let mut scores = PeerScores::new(
+            ScoreDecay::Exponential {
+                half_life: Duration::from_secs(60),
+            },
+            ScoreDecay::Never,
+        );
+        let flaky = node_id::random();
+        let malicious = node_id::random();
+        scores.penalise(flaky.clone(), PeerOffence::FailedDial, 1.0);
+        assert!((scores.penalise(flaky.clone(), PeerOffence::FailedDial, 1.0) - 2.0).abs() < 0.001);
+        scores.penalise(malicious.clone(), PeerOffence::Misbehaviour, 50.0);
+        assert_eq!(scores.len(), 2);
+
+        let rehabilitated = scores.decay_by(Duration::from_secs(60));
+        assert!(rehabilitated.is_empty());
+        assert!((scores.score(&flaky, PeerOffence::FailedDial) - 1.0).abs() < 0.001);
+
+        let rehabilitated = scores.decay_by(Duration::from_secs(600));
+        assert_eq!(rehabilitated, vec![(flaky.clone(), PeerOffence::FailedDial)]);
+        assert!(scores.score(&flaky, PeerOffence::FailedDial).abs() < 0.001);
+        assert_eq!(scores.len(), 1);
+
+        // Misbehaviour never decays
+        assert!((scores.score(&malicious, PeerOffence::Misbehaviour) - 50.0).abs() < 0.001);
+        assert!(scores.forgive(&malicious));
+        assert!(!scores.forgive(&malicious));
+        assert!(scores.is_empty());
+    }
+}
@@ -27,8 +27,9 @@ use super::{
     ConnectivitySelection,
 };
 use crate::{
-    connection_manager::{ConnectionDirection, ConnectionManagerError},
+    connection_manager::{ConnectionDirection, ConnectionInfo, ConnectionManagerError},
     peer_manager::NodeId,
+    protocol::SignedPeerIdentity,
     PeerConnection,
 };
 use futures::{
@@ -58,6 +59,8 @@ pub enum ConnectivityEvent {
     PeerBanned(NodeId),
     PeerOffline(NodeId),
     PeerConnectionWillClose(NodeId, ConnectionDirection),
+    /// A known peer advertised new addresses (see `ConnectionManagerEvent::PeerAddressesChanged`)
+    PeerAddressesChanged(Box<SignedPeerIdentity>),
 
     ConnectivityStateInitialized,
     ConnectivityStateOnline(usize),
@@ -79,6 +82,7 @@ impl fmt::Display for ConnectivityEvent {
             PeerConnectionWillClose(node_id, direction) => {
                 write!(f, "PeerConnectionWillClose({}, {})", node_id, direction)
             },
+            PeerAddressesChanged(identity) => write!(f, "PeerAddressesChanged({})", identity.public_key()),
             ConnectivityStateInitialized => write!(f, "ConnectivityStateInitialized"),
             ConnectivityStateOnline(n) => write!(f, "ConnectivityStateOnline({})", n),
             ConnectivityStateDegraded(n) => write!(f, "ConnectivityStateDegraded({})", n),
@@ -102,6 +106,9 @@ pub enum ConnectivityRequest {
     GetAllConnectionStates(oneshot::Sender<Vec<PeerConnectionState>>),
     GetActiveConnections(oneshot::Sender<Vec<PeerConnection>>),
     BanPeer(NodeId, Duration, String),
+    ReportMisbehaviour(NodeId, f32, String),
+    ForgivePeer(NodeId, oneshot::Sender<Result<(), ConnectivityError>>),
+    WaitForConnectedNodes(usize, oneshot::Sender<()>),
 }
 
 #[derive(Debug, Clone)]
@@ -214,6 +221,12 @@ impl ConnectivityRequester {
         reply_rx.await.map_err(|_| ConnectivityError::ActorResponseCancelled)
     }
 
+    /// Returns a snapshot of each active connection
+    pub async fn get_active_connection_info(&mut self) -> Result<Vec<ConnectionInfo>, ConnectivityError> {
+        let conns = self.get_active_connections().await?;
+        Ok(conns.iter().map(PeerConnection::info).collect())
+    }
+
     pub async fn ban_peer_until(
         &mut self,
         node_id: NodeId,
@@ -233,6 +246,32 @@ impl ConnectivityRequester {
             .await
     }
 
+    /// Add `score` to the peer's misbehaviour score. The peer is banned once their misbehaviour score reaches the
+    /// configured threshold. Misbehaviour scores decay over time.
+    pub async fn report_misbehaviour(
+        &mut self,
+        node_id: NodeId,
+        score: f32,
+        reason: String,
+    ) -> Result<(), ConnectivityError>
+    {
+        self.sender
+            .send(ConnectivityRequest::ReportMisbehaviour(node_id, score, reason))
+            .await
+            .map_err(|_| ConnectivityError::ActorDisconnected)?;
+        Ok(())
+    }
+
+    /// Clear all offence scores and failed dial penalties for the peer and lift any ban
+    pub async fn forgive(&mut self, node_id: NodeId) -> Result<(), ConnectivityError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(ConnectivityRequest::ForgivePeer(node_id, reply_tx))
+            .await
+            .map_err(|_| ConnectivityError::ActorDisconnected)?;
+        reply_rx.await.map_err(|_| ConnectivityError::ActorResponseCancelled)?
+    }
+
     pub async fn wait_started(&mut self) -> Result<(), ConnectivityError> {
         let (reply_tx, reply_rx) = oneshot::channel();
         self.sender
@@ -242,6 +281,25 @@ impl ConnectivityRequester {
         reply_rx.await.map_err(|_| ConnectivityError::ActorResponseCancelled)
     }
 
+    /// Waits until at least `n` communication nodes are connected. This is intended to be used by services that should
+    /// not start work (e.g. syncing) until the network layer is usable. If the timeout is reached,
+    /// `ConnectivityError::OnlineWaitTimeout` is returned containing the number of currently connected nodes.
+    pub async fn wait_for(&mut self, n: usize, timeout: Duration) -> Result<(), ConnectivityError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.sender
+            .send(ConnectivityRequest::WaitForConnectedNodes(n, reply_tx))
+            .await
+            .map_err(|_| ConnectivityError::ActorDisconnected)?;
+
+        match time::timeout(timeout, reply_rx).await {
+            Ok(result) => result.map_err(|_| ConnectivityError::ActorResponseCancelled),
+            Err(_) => {
+                let status = self.get_connectivity_status().await?;
+                Err(ConnectivityError::OnlineWaitTimeout(status.num_connected_nodes()))
+            },
+        }
+    }
+
     /// Waits for the node to get at least one connection.
     /// This is useful for testing and is not typically be needed in application code.
     pub async fn wait_for_connectivity(&mut self, timeout: Duration) -> Result<(), ConnectivityError> {
@@ -22,7 +22,7 @@
 
 use super::connection_pool::ConnectionPool;
 use crate::{connectivity::connection_pool::ConnectionStatus, peer_manager::NodeId, PeerConnection};
-use rand::{rngs::OsRng, seq::SliceRandom};
+use rand::{rngs::OsRng, seq::SliceRandom, Rng};
 use std::{fmt, fmt::Display};
 
 #[derive(Debug, Clone)]
@@ -61,12 +61,28 @@ impl ConnectivitySelection {
         }
     }
 
-    /// Select peers from the pool according to the ConnectivitySelection
-    pub fn select<'a>(&self, pool: &'a ConnectionPool) -> Vec<&'a PeerConnection> {
+    /// Select peers from the pool according to the ConnectivitySelection. `slow_peers` are ordered after other peers
+    /// when selecting all or random nodes. Closest selection is not affected because peer distance determines routing.
+    pub fn select<'a>(&self, pool: &'a ConnectionPool, slow_peers: &[NodeId]) -> Vec<&'a PeerConnection> {
+        self.select_with_rng(pool, slow_peers, &mut OsRng)
+    }
+
+    /// Select peers from the pool according to the ConnectivitySelection, using the given RNG for random selections
+    pub fn select_with_rng<'a, R: Rng + ?Sized>(
+        &self,
+        pool: &'a ConnectionPool,
+        slow_peers: &[NodeId],
+        rng: &mut R,
+    ) -> Vec<&'a PeerConnection>
+    {
         use SelectionMode::*;
         match &self.selection_mode {
-            AllNodes => select_connected_nodes(pool, &self.excluded_peers),
-            RandomNodes(n) => select_random_nodes(pool, *n, &self.excluded_peers),
+            AllNodes => {
+                let (mut nodes, slow) = partition_slow(select_connected_nodes(pool, &self.excluded_peers), slow_peers);
+                nodes.extend(slow);
+                nodes
+            },
+            RandomNodes(n) => select_random_nodes_with_rng(pool, *n, &self.excluded_peers, slow_peers, rng),
             ClosestTo(dest_node_id, n) => {
                 let mut connections = select_closest(pool, dest_node_id, &self.excluded_peers);
                 connections.truncate(*n);
@@ -100,9 +116,36 @@ pub fn select_closest<'a>(pool: &'a ConnectionPool, node_id: &NodeId, exclude: &
     nodes
 }
 
-pub fn select_random_nodes<'a>(pool: &'a ConnectionPool, n: usize, exclude: &[NodeId]) -> Vec<&'a PeerConnection> {
-    let nodes = select_connected_nodes(pool, exclude);
-    nodes.choose_multiple(&mut OsRng, n).cloned().collect()
+fn partition_slow<'a>(
+    nodes: Vec<&'a PeerConnection>,
+    slow_peers: &[NodeId],
+) -> (Vec<&'a PeerConnection>, Vec<&'a PeerConnection>)
+{
+    nodes
+        .into_iter()
+        .partition(|conn| !slow_peers.contains(conn.peer_node_id()))
+}
+
+/// Randomly select `n` nodes using the given RNG, only selecting slow peers if there are not enough other peers. The
+/// connected nodes are ordered by node id before selection, so that a seeded RNG always selects the same nodes from the
+/// same pool.
+pub fn select_random_nodes_with_rng<'a, R: Rng + ?Sized>(
+    pool: &'a ConnectionPool,
+    n: usize,
+    exclude: &[NodeId],
+    slow_peers: &[NodeId],
+    rng: &mut R,
+) -> Vec<&'a PeerConnection>
+{
+    let mut connected = select_connected_nodes(pool, exclude);
+    connected.sort_by(|a, b| a.peer_node_id().cmp(b.peer_node_id()));
+    let (nodes, slow) = partition_slow(connected, slow_peers);
+    let mut selected = nodes.choose_multiple(rng, n).cloned().collect::<Vec<_>>();
+    if selected.len() < n {
+        let remaining = n - selected.len();
+        selected.extend(slow.choose_multiple(rng, remaining).cloned());
+    }
+    selected
 }
 
 impl Display for ConnectivitySelection {
@@ -154,15 +197,41 @@ mod test {
     #[test]
     fn select_random() {
         let (pool, _receivers) = create_pool_with_connections(10);
-        let conns = select_random_nodes(&pool, 500, &[]);
+        let conns = select_random_nodes_with_rng(&pool, 500, &[], &[], &mut OsRng);
         assert_eq!(conns.len(), 10);
 
         let first_node = conns.first().unwrap().peer_node_id().clone();
-        let conns = select_random_nodes(&pool, 10, &[first_node.clone()]);
+        let conns = select_random_nodes_with_rng(&pool, 10, &[first_node.clone()], &[], &mut OsRng);
         assert_eq!(conns.len(), 9);
         assert!(conns.iter().all(|c| c.peer_node_id() != &first_node));
     }
 
+    #[test]
+    fn select_deprioritises_slow_peers() {
+        let (pool, _receivers) = create_pool_with_connections(10);
+        let slow_peers = pool
+            .all()
+            .into_iter()
+            .take(3)
+            .map(|state| state.node_id().clone())
+            .collect::<Vec<_>>();
+
+        let conns = select_random_nodes_with_rng(&pool, 7, &[], &slow_peers, &mut OsRng);
+        assert_eq!(conns.len(), 7);
+        assert!(conns.iter().all(|c| !slow_peers.contains(c.peer_node_id())));
+
+        let conns = select_random_nodes_with_rng(&pool, 9, &[], &slow_peers, &mut OsRng);
+        assert_eq!(conns.len(), 9);
+        assert_eq!(
+            conns.iter().filter(|c| slow_peers.contains(c.peer_node_id())).count(),
+            2
+        );
+
+        let conns = ConnectivitySelection::all_nodes(vec![]).select(&pool, &slow_peers);
+        assert_eq!(conns.len(), 10);
+        assert!(conns[7..].iter().all(|c| slow_peers.contains(c.peer_node_id())));
+    }
+
     #[test]
     fn select_closest_ordering() {
         let (pool, _receivers) = create_pool_with_connections(10);
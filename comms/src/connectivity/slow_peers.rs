@@ -0,0 +1,129 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::peer_manager::NodeId;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// The maximum number of peers for which slow connections are tracked. Once reached, expired entries are pruned and
+/// then the peer with the oldest slow connection is forgotten to make room.
+const MAX_TRACKED_SLOW_PEERS: usize = 1000;
+
+#[derive(Debug, Clone, Copy)]
+struct SlowConnections {
+    count: usize,
+    last_slow_at: Instant,
+}
+
+/// Counts consecutive slow connections for each peer. Counts are forgotten if the peer has not had a slow connection
+/// within `max_age`.
+#[derive(Debug)]
+pub struct SlowPeers {
+    counts: HashMap<NodeId, SlowConnections>,
+    max_age: Duration,
+}
+
+impl SlowPeers {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            counts: HashMap::new(),
+            max_age,
+        }
+    }
+
+    /// Record a slow connection for the peer, returning the number of consecutive slow connections
+    pub fn record_slow(&mut self, node_id: NodeId) -> usize {
+        if !self.counts.contains_key(&node_id) && self.counts.len() >= MAX_TRACKED_SLOW_PEERS {
+            self.prune_expired();
+            if self.counts.len() >= MAX_TRACKED_SLOW_PEERS {
+                let oldest = self
+                    .counts
+                    .iter()
+                    .min_by_key(|(_, c)| c.last_slow_at)
+                    .map(|(n, _)| n.clone());
+                if let Some(oldest) = oldest {
+                    self.counts.remove(&oldest);
+                }
+            }
+        }
+        let entry = self.counts.entry(node_id).or_insert(SlowConnections {
+            count: 0,
+            last_slow_at: Instant::now(),
+        });
+        entry.count += 1;
+        entry.last_slow_at = Instant::now();
+        entry.count
+    }
+
+    /// Clear the slow connection count for the peer
+    pub fn clear(&mut self, node_id: &NodeId) {
+        self.counts.remove(node_id);
+    }
+
+    /// Returns peers that have had at least `min_occurrences` consecutive slow connections
+    pub fn get_slow_peers(&self, min_occurrences: usize) -> Vec<NodeId> {
+        self.counts
+            .iter()
+            .filter(|(_, c)| c.count >= min_occurrences)
+            .map(|(node_id, _)| node_id.clone())
+            .collect()
+    }
+
+    /// Forget peers that have not had a slow connection within `max_age`
+    pub fn prune_expired(&mut self) {
+        let max_age = self.max_age;
+        self.counts.retain(|_, c| c.last_slow_at.elapsed() <= max_age);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::node_id;
+
+    #[test]
+    fn record_and_clear() {
+        let mut slow_peers = SlowPeers::new(Duration::from_secs(60));
+        let peer = node_id::random();
+        assert_eq!(slow_peers.record_slow(peer.clone()), 1);
+        assert_eq!(slow_peers.record_slow(peer.clone()), 2);
+        assert!(slow_peers.get_slow_peers(3).is_empty());
+        assert_eq!(slow_peers.get_slow_peers(2), vec![peer.clone()]);
+        slow_peers.clear(&peer);
+        assert!(slow_peers.get_slow_peers(1).is_empty());
+    }
+
+    #[test]
+    fn bounded_and_pruned() {
+        let mut slow_peers = SlowPeers::new(Duration::from_secs(60));
+        for _ in 0..MAX_TRACKED_SLOW_PEERS + 10 {
+            slow_peers.record_slow(node_id::random());
+        }
+        assert_eq!(slow_peers.get_slow_peers(1).len(), MAX_TRACKED_SLOW_PEERS);
+        slow_peers.max_age = Duration::from_millis(1);
+        std::thread::sleep(Duration::from_millis(5));
+        slow_peers.prune_expired();
+        assert!(slow_peers.get_slow_peers(1).is_empty());
+    }
+}
@@ -22,6 +22,7 @@
 use super::{
     config::ConnectivityConfig,
     connection_pool::ConnectionStatus,
+    error::ConnectivityError,
     manager::ConnectivityManager,
     requester::{ConnectivityEvent, ConnectivityRequester},
     selection::ConnectivitySelection,
@@ -42,7 +43,7 @@ use crate::{
 use futures::{channel::mpsc, future};
 use std::{sync::Arc, time::Duration};
 use tari_shutdown::Shutdown;
-use tari_test_utils::{collect_stream, streams, unpack_enum};
+use tari_test_utils::{async_assert_eventually, collect_stream, streams, unpack_enum};
 use tokio::sync::broadcast;
 
 #[allow(clippy::type_complexity)]
@@ -74,6 +75,9 @@ fn setup_connectivity_manager(
         connection_manager: cm_requester,
         peer_manager: peer_manager.clone(),
         shutdown_signal: shutdown.to_signal(),
+        shutdown_reporter: Default::default(),
+        security_events: Default::default(),
+        rng: Default::default(),
     }
     .create()
     .spawn();
@@ -135,6 +139,42 @@ async fn connecting_peers() {
     }
 }
 
+#[runtime::test_basic]
+async fn wait_for_connected_nodes() {
+    let (mut connectivity, mut event_stream, node_identity, peer_manager, cm_mock_state, _shutdown) =
+        setup_connectivity_manager(Default::default());
+    let peers = add_test_peers(&peer_manager, 3).await;
+
+    let connections = future::join_all(
+        peers
+            .iter()
+            .cloned()
+            .map(|peer| create_peer_connection_mock_pair(1, peer, node_identity.to_peer())),
+    )
+    .await
+    .into_iter()
+    .map(|(_, _, conn, _)| conn)
+    .collect::<Vec<_>>();
+
+    let mut events = collect_stream!(event_stream, take = 1, timeout = Duration::from_secs(10));
+    unpack_enum!(ConnectivityEvent::ConnectivityStateInitialized = &*events.remove(0).unwrap());
+
+    let err = connectivity.wait_for(3, Duration::from_millis(10)).await.unwrap_err();
+    unpack_enum!(ConnectivityError::OnlineWaitTimeout(n) = err);
+    assert_eq!(n, 0);
+
+    let mut waiter = connectivity.clone();
+    let wait_handle = task::spawn(async move { waiter.wait_for(3, Duration::from_secs(10)).await });
+
+    for conn in &connections {
+        cm_mock_state.publish_event(ConnectionManagerEvent::PeerConnected(conn.clone()));
+    }
+
+    wait_handle.await.unwrap().unwrap();
+    // Already satisfied, so returns immediately
+    connectivity.wait_for(2, Duration::from_millis(10)).await.unwrap();
+}
+
 #[runtime::test_basic]
 async fn add_many_managed_peers() {
     let (mut connectivity, mut event_stream, node_identity, peer_manager, cm_mock_state, _shutdown) =
@@ -347,6 +387,109 @@ async fn ban_peer() {
     assert!(conn.is_none());
 }
 
+#[runtime::test_basic]
+async fn misbehaviour_ban_and_forgive() {
+    let config = ConnectivityConfig {
+        misbehaviour_ban_threshold: 10.0,
+        ..Default::default()
+    };
+    let (mut connectivity, mut event_stream, _node_identity, peer_manager, _cm_mock_state, _shutdown) =
+        setup_connectivity_manager(config);
+    let peer = add_test_peers(&peer_manager, 1).await.pop().unwrap();
+
+    let mut events = collect_stream!(event_stream, take = 1, timeout = Duration::from_secs(10));
+    unpack_enum!(ConnectivityEvent::ConnectivityStateInitialized = &*events.remove(0).unwrap());
+
+    connectivity
+        .report_misbehaviour(peer.node_id.clone(), 6.0, "test".to_string())
+        .await
+        .unwrap();
+    let peer = peer_manager.find_by_node_id(&peer.node_id).await.unwrap();
+    assert!(!peer.is_banned());
+
+    connectivity
+        .report_misbehaviour(peer.node_id.clone(), 6.0, "test".to_string())
+        .await
+        .unwrap();
+    let event = collect_stream!(event_stream, take = 1, timeout = Duration::from_secs(10))
+        .pop()
+        .unwrap()
+        .unwrap();
+    unpack_enum!(ConnectivityEvent::PeerBanned(node_id) = &*event);
+    assert_eq!(node_id, &peer.node_id);
+    let peer = peer_manager.find_by_node_id(&peer.node_id).await.unwrap();
+    assert!(peer.is_banned());
+
+    connectivity.forgive(peer.node_id.clone()).await.unwrap();
+    let peer = peer_manager.find_by_node_id(&peer.node_id).await.unwrap();
+    assert!(!peer.is_banned());
+    assert!(!peer.is_offline());
+}
+
+#[runtime::test_basic]
+async fn background_dials_are_limited() {
+    let config = ConnectivityConfig {
+        max_concurrent_background_dials: 2,
+        ..Default::default()
+    };
+    let (mut connectivity, _event_stream, node_identity, peer_manager, cm_mock_state, _shutdown) =
+        setup_connectivity_manager(config);
+    let peers = add_test_peers(&peer_manager, 4).await;
+
+    connectivity
+        .add_managed_peers(peers.iter().map(|p| p.node_id.clone()).collect())
+        .await
+        .unwrap();
+
+    let states = connectivity.get_all_connection_states().await.unwrap();
+    let num_dialing = states
+        .iter()
+        .filter(|s| s.status() == ConnectionStatus::Connecting)
+        .count();
+    assert_eq!(num_dialing, 2);
+    let mut num_dial_calls = 0;
+    async_assert_eventually!(
+        {
+            num_dial_calls += count_dial_calls(&cm_mock_state).await;
+            num_dial_calls
+        },
+        expect = 2,
+        max_attempts = 20,
+        interval = Duration::from_millis(10)
+    );
+
+    // Once a dial completes, the next queued dial is sent
+    let dialed = states
+        .iter()
+        .find(|s| s.status() == ConnectionStatus::Connecting)
+        .unwrap()
+        .node_id()
+        .clone();
+    let peer = peers.iter().find(|p| p.node_id == dialed).cloned().unwrap();
+    let (_, _, conn, _) = create_peer_connection_mock_pair(1, peer, node_identity.to_peer()).await;
+    cm_mock_state.publish_event(ConnectionManagerEvent::PeerConnected(conn));
+
+    let mut num_dial_calls = 0;
+    async_assert_eventually!(
+        {
+            num_dial_calls += count_dial_calls(&cm_mock_state).await;
+            num_dial_calls
+        },
+        expect = 1,
+        max_attempts = 20,
+        interval = Duration::from_millis(10)
+    );
+}
+
+async fn count_dial_calls(cm_mock_state: &ConnectionManagerMockState) -> usize {
+    cm_mock_state
+        .take_calls()
+        .await
+        .iter()
+        .filter(|c| c.starts_with("DialPeer"))
+        .count()
+}
+
 #[runtime::test_basic]
 async fn peer_selection() {
     let config = ConnectivityConfig {
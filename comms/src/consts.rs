@@ -25,6 +25,9 @@ use std::time::Duration;
 /// The maximum number of peers to return from the flood_identities method in peer manager
 pub const PEER_MANAGER_MAX_FLOOD_PEERS: usize = 1000;
 
+/// The maximum number of peers that the peer manager keeps in its in-memory read-through cache
+pub const PEER_MANAGER_CACHE_CAPACITY: usize = 1000;
+
 /// The amount of time to consider a peer to be offline (i.e. dial to peer will fail without trying) after a failed
 /// connection attempt
 pub const PEER_OFFLINE_COOLDOWN_PERIOD: Duration = Duration::from_secs(60);
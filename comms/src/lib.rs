@@ -27,7 +27,15 @@ extern crate lazy_static;
 mod macros;
 
 mod builder;
-pub use builder::{CommsBuilder, CommsBuilderError, CommsNode, UnspawnedCommsNode};
+pub use builder::{
+    CommsBuilder,
+    CommsBuilderError,
+    CommsNode,
+    CommsShutdown,
+    CommsShutdownReport,
+    ShutdownReporter,
+    UnspawnedCommsNode,
+};
 
 pub mod connection_manager;
 pub use connection_manager::{validate_peer_addresses, PeerConnection, PeerConnectionError};
@@ -40,7 +48,7 @@ pub use peer_manager::{NodeIdentity, PeerManager};
 pub mod framing;
 
 mod common;
-pub use common::rate_limit;
+pub use common::{fragment, rate_limit};
 mod consts;
 
 mod multiplexing;
@@ -50,6 +58,7 @@ mod noise;
 mod proto;
 mod runtime;
 
+pub mod admin;
 pub mod backoff;
 pub mod bounded_executor;
 pub mod compat;
@@ -59,9 +68,11 @@ pub mod protocol;
 pub mod message;
 pub mod net_address;
 pub mod pipeline;
+pub mod security_events;
 pub mod socks;
 pub mod tor;
 pub mod transports;
+pub use transports::Transport;
 pub mod types;
 #[macro_use]
 pub mod utils;
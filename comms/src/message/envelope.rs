@@ -69,6 +69,11 @@ impl EnvelopeBody {
             .map(|i| self.parts.remove(i))
     }
 
+    /// Returns the part at the given index without decoding it. None is returned if the index is out of bounds
+    pub fn get_part(&self, index: usize) -> Option<&[u8]> {
+        self.parts.get(index).map(|part| part.as_slice())
+    }
+
     pub fn push_part(&mut self, part: Vec<u8>) {
         self.parts.push(part)
     }
@@ -71,7 +71,14 @@ mod inbound;
 pub use inbound::InboundMessage;
 
 mod outbound;
-pub use outbound::{MessagingReplyRx, MessagingReplyTx, OutboundMessage};
+pub use outbound::{
+    CancellationHandle,
+    MessagePriority,
+    MessagingReplyObserver,
+    MessagingReplyRx,
+    MessagingReplyTx,
+    OutboundMessage,
+};
 
 mod tag;
 pub use tag::MessageTag;
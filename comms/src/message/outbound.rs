@@ -26,6 +26,10 @@ use futures::channel::oneshot;
 use std::{
     fmt,
     fmt::{Error, Formatter},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 pub type MessagingReplyResult = Result<(), SendFailReason>;
@@ -39,6 +43,10 @@ pub struct OutboundMessage {
     pub peer_node_id: NodeId,
     pub body: Bytes,
     pub reply: MessagingReplyTx,
+    /// If set and cancelled before the message is sent, the message is discarded and a `SendFailReason::Cancelled`
+    /// reply is sent
+    pub cancellation: Option<CancellationHandle>,
+    pub priority: MessagePriority,
 }
 
 impl OutboundMessage {
@@ -48,6 +56,8 @@ impl OutboundMessage {
             peer_node_id,
             body,
             reply: MessagingReplyTx::none(),
+            cancellation: None,
+            priority: Default::default(),
         }
     }
 
@@ -57,9 +67,26 @@ impl OutboundMessage {
             peer_node_id,
             body,
             reply,
+            cancellation: None,
+            priority: Default::default(),
         }
     }
 
+    pub fn with_cancellation(mut self, cancellation: CancellationHandle) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: MessagePriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Returns true if this message has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.as_ref().map(|c| c.is_cancelled()).unwrap_or(false)
+    }
+
     #[inline]
     pub fn reply_success(&mut self) {
         self.reply.reply_success();
@@ -86,54 +113,142 @@ impl fmt::Display for OutboundMessage {
     }
 }
 
+/// The priority with which an outbound message is sent to a peer. Messages queued for a peer are sent in the order
+/// they were queued, except that queued `Normal` messages are always sent before queued `Low` messages. `Low` should be
+/// used for large transfers that are not time sensitive (e.g. store and forward responses), so that they do not delay
+/// latency-sensitive messages on slow links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessagePriority {
+    Normal,
+    Low,
+}
+
+impl Default for MessagePriority {
+    fn default() -> Self {
+        MessagePriority::Normal
+    }
+}
+
+/// A callback that is called with the result of sending an outbound message
+pub type MessagingReplyObserver = Box<dyn FnOnce(&MessagingReplyResult) + Send + Sync>;
+
 /// Wrapper struct for a oneshot reply sender. When this struct is dropped, an automatic fail is sent on the oneshot if
 /// a response has not already been sent.
-#[derive(Debug)]
-pub struct MessagingReplyTx(Option<oneshot::Sender<MessagingReplyResult>>);
+///
+/// An observer may be attached to be notified of the send result (e.g. to record metrics) without intercepting the
+/// reply. The observer is called exactly once, even if there is no reply sender.
+pub struct MessagingReplyTx {
+    inner: Option<oneshot::Sender<MessagingReplyResult>>,
+    observer: Option<MessagingReplyObserver>,
+}
 
 impl MessagingReplyTx {
     pub fn into_inner(mut self) -> Option<oneshot::Sender<MessagingReplyResult>> {
-        self.0.take()
+        // The result will not be known to this reply, so the observer is discarded
+        self.observer = None;
+        self.inner.take()
     }
 
     pub fn none() -> Self {
-        Self(None)
+        Self {
+            inner: None,
+            observer: None,
+        }
+    }
+
+    /// Attach an observer that is called with the result of the send. Any previously attached observer is called
+    /// before this one.
+    pub fn with_observer<F>(mut self, observer: F) -> Self
+    where F: FnOnce(&MessagingReplyResult) + Send + Sync + 'static {
+        let observer: MessagingReplyObserver = match self.observer.take() {
+            Some(prev) => Box::new(move |result: &MessagingReplyResult| {
+                prev(result);
+                observer(result);
+            }),
+            None => Box::new(observer),
+        };
+        self.observer = Some(observer);
+        self
     }
 
     pub fn reply_success(&mut self) {
-        if let Some(reply_tx) = self.0.take() {
-            let _ = reply_tx.send(Ok(()));
-        }
+        self.reply(Ok(()));
     }
 
     pub fn reply_fail(&mut self, reason: SendFailReason) {
-        if let Some(reply_tx) = self.0.take() {
-            let _ = reply_tx.send(Err(reason));
-        }
+        self.reply(Err(reason));
     }
 
     pub fn take(&mut self) -> Option<Self> {
-        self.0.take().map(Into::into)
+        if self.inner.is_none() && self.observer.is_none() {
+            return None;
+        }
+        Some(Self {
+            inner: self.inner.take(),
+            observer: self.observer.take(),
+        })
+    }
+
+    fn reply(&mut self, result: MessagingReplyResult) {
+        if let Some(observer) = self.observer.take() {
+            observer(&result);
+        }
+        if let Some(reply_tx) = self.inner.take() {
+            let _ = reply_tx.send(result);
+        }
+    }
+}
+
+impl fmt::Debug for MessagingReplyTx {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessagingReplyTx")
+            .field("inner", &self.inner)
+            .field("has_observer", &self.observer.is_some())
+            .finish()
     }
 }
 
 impl From<oneshot::Sender<MessagingReplyResult>> for MessagingReplyTx {
     fn from(inner: oneshot::Sender<MessagingReplyResult>) -> Self {
-        Self(Some(inner))
+        Some(inner).into()
     }
 }
 impl From<Option<oneshot::Sender<MessagingReplyResult>>> for MessagingReplyTx {
     fn from(inner: Option<oneshot::Sender<MessagingReplyResult>>) -> Self {
-        Self(inner)
+        Self { inner, observer: None }
     }
 }
 
 impl Drop for MessagingReplyTx {
     fn drop(&mut self) {
         // If this is dropped and the reply tx has not been used already, send an error reply
-        if let Some(reply_tx) = self.0.take() {
-            let _ = reply_tx.send(Err(SendFailReason::Dropped));
-        }
+        self.reply(Err(SendFailReason::Dropped));
+    }
+}
+
+/// A handle used to cancel outbound messages that have not been sent yet. Clones of a handle share the same
+/// cancellation state, so a single handle can cancel every message it was attached to.
+///
+/// Cancelling only affects messages that are still queued on this node. Messages that have already been written to the
+/// peer, and copies of them stored by store and forward nodes, are not affected.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationHandle {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Cancel all pending messages that this handle is attached to. Messages that have already been sent are not
+    /// affected.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
     }
 }
 
@@ -151,9 +266,34 @@ mod test {
             peer_node_id: node_id.clone(),
             reply: MessagingReplyTx::none(),
             body: TEST_MSG.clone(),
+            cancellation: None,
+            priority: MessagePriority::Normal,
         };
         assert_eq!(tag, subject.tag);
         assert_eq!(subject.body, TEST_MSG);
         assert_eq!(subject.peer_node_id, node_id);
     }
+
+    #[test]
+    fn reply_observer() {
+        let results = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let (tx, mut rx) = oneshot::channel();
+        let results_cloned = results.clone();
+        let mut reply =
+            MessagingReplyTx::from(tx).with_observer(move |result| results_cloned.lock().unwrap().push(result.clone()));
+        reply.reply_success();
+        // The observer is only called once
+        reply.reply_fail(SendFailReason::PeerBanned);
+        assert_eq!(rx.try_recv().unwrap().unwrap(), Ok(()));
+
+        // The observer is called on drop even without a reply sender
+        let results_cloned = results.clone();
+        let reply =
+            MessagingReplyTx::none().with_observer(move |result| results_cloned.lock().unwrap().push(result.clone()));
+        drop(reply);
+
+        let results = results.lock().unwrap();
+        assert_eq!(*results, vec![Ok(()), Err(SendFailReason::Dropped)]);
+    }
 }
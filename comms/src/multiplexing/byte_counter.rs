@@ -0,0 +1,137 @@
+// Copyright 2019, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use futures::{
+    io::{AsyncRead, AsyncWrite},
+    task::Context,
+};
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::Poll,
+};
+
+/// Shared counters for the total number of bytes read from and written to a socket.
+#[derive(Debug, Clone, Default)]
+pub struct ByteCounter {
+    read: Arc<AtomicU64>,
+    written: Arc<AtomicU64>,
+}
+
+impl ByteCounter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Total number of bytes read from the socket
+    pub fn bytes_read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+
+    /// Total number of bytes written to the socket
+    pub fn bytes_written(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    fn add_read(&self, n: usize) {
+        self.read.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    fn add_written(&self, n: usize) {
+        self.written.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+/// A socket wrapper that records the bytes read and written in a [ByteCounter](self::ByteCounter).
+pub struct CountedSocket<TSocket> {
+    inner: TSocket,
+    counter: ByteCounter,
+}
+
+impl<TSocket> CountedSocket<TSocket> {
+    pub fn new(inner: TSocket, counter: ByteCounter) -> Self {
+        Self { inner, counter }
+    }
+}
+
+impl<TSocket> AsyncRead for CountedSocket<TSocket>
+where TSocket: AsyncRead + Unpin
+{
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.counter.add_read(n);
+        }
+        poll
+    }
+}
+
+impl<TSocket> AsyncWrite for CountedSocket<TSocket>
+where TSocket: AsyncWrite + Unpin
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.counter.add_written(n);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{memsocket::MemorySocket, runtime};
+    use futures::{AsyncReadExt, AsyncWriteExt};
+
+    #[runtime::test_basic]
+    async fn counts_bytes() {
+        let (a, mut b) = MemorySocket::new_pair();
+        let counter = ByteCounter::new();
+        let mut socket = CountedSocket::new(a, counter.clone());
+
+        socket.write_all(b"hello").await.unwrap();
+        socket.flush().await.unwrap();
+        let mut buf = [0u8; 5];
+        b.read_exact(&mut buf).await.unwrap();
+
+        b.write_all(b"hi").await.unwrap();
+        b.flush().await.unwrap();
+        let mut buf = [0u8; 2];
+        socket.read_exact(&mut buf).await.unwrap();
+
+        assert_eq!(counter.bytes_written(), 5);
+        assert_eq!(counter.bytes_read(), 2);
+    }
+}
@@ -20,7 +20,11 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::{connection_manager::ConnectionDirection, runtime};
+use crate::{
+    connection_manager::ConnectionDirection,
+    multiplexing::{ByteCounter, CountedSocket},
+    runtime,
+};
 use futures::{
     channel::mpsc,
     future,
@@ -49,6 +53,7 @@ pub struct Yamux {
     control: Control,
     incoming: IncomingSubstreams,
     substream_counter: SubstreamCounter,
+    byte_counter: ByteCounter,
 }
 
 const MAX_BUFFER_SIZE: u32 = 8 * 1024 * 1024; // 8MB
@@ -75,6 +80,8 @@ impl Yamux {
         config.set_receive_window(RECEIVE_WINDOW);
 
         let substream_counter = SubstreamCounter::new();
+        let byte_counter = ByteCounter::new();
+        let socket = CountedSocket::new(socket, byte_counter.clone());
         let connection = yamux::Connection::new(socket, config, mode);
         let control = Control::new(connection.control(), substream_counter.clone());
         let incoming = Self::spawn_incoming_stream_worker(connection, substream_counter.clone());
@@ -83,6 +90,7 @@ impl Yamux {
             control,
             incoming,
             substream_counter,
+            byte_counter,
         })
     }
 
@@ -128,6 +136,11 @@ impl Yamux {
         self.substream_counter.clone()
     }
 
+    /// Return a ByteCounter which tracks the total bytes read and written on the underlying socket
+    pub(crate) fn byte_counter(&self) -> ByteCounter {
+        self.byte_counter.clone()
+    }
+
     pub fn is_terminated(&self) -> bool {
         self.incoming.is_terminated()
     }
@@ -98,6 +98,19 @@ impl MutliaddrWithStats {
         self.connection_attempts += 1;
     }
 
+    /// Mark that this address is no longer advertised by the peer, and must be validated by a successful connection
+    /// before it is preferred again. The address is ordered after addresses that have been seen or have not yet been
+    /// attempted.
+    pub fn mark_for_revalidation(&mut self) {
+        self.last_seen = None;
+        self.connection_attempts = self.connection_attempts.max(1);
+    }
+
+    /// The number of latency measurements included in the average latency
+    pub fn latency_sample_count(&self) -> u32 {
+        self.latency_sample_count
+    }
+
     /// Get as a Multiaddr
     pub fn as_net_address(&self) -> Multiaddr {
         self.clone().address
@@ -86,6 +86,33 @@ impl MultiaddressesWithStats {
         self.addresses.sort();
     }
 
+    /// Updates the addresses to those currently advertised by the peer, retaining the usage stats of addresses that are
+    /// still advertised. Previously seen addresses that are no longer advertised are kept, but are marked for
+    /// re-validation so that they are only tried after the advertised addresses. Addresses that are not advertised and
+    /// have not been seen since they were marked for re-validation are removed.
+    ///
+    /// Returns true if the peer advertised a new address or stopped advertising a previously seen address, otherwise
+    /// false.
+    pub fn update_advertised_addresses(&mut self, advertised: Vec<Multiaddr>) -> bool {
+        let mut is_changed = false;
+        self.addresses
+            .retain(|addr| advertised.contains(&addr.address) || addr.last_seen.is_some());
+        for addr in self.addresses.iter_mut() {
+            if !advertised.contains(&addr.address) {
+                addr.mark_for_revalidation();
+                is_changed = true;
+            }
+        }
+        for addr in &advertised {
+            if self.addresses.iter().all(|a| a.address != *addr) {
+                self.addresses.push(addr.clone().into());
+                is_changed = true;
+            }
+        }
+        self.addresses.sort();
+        is_changed
+    }
+
     /// Returns an iterator of addresses ordered from 'best' to 'worst' according to heuristics such as failed
     /// connections and latency.
     pub fn iter(&self) -> impl Iterator<Item = &Multiaddr> {
@@ -307,6 +334,31 @@ mod test {
         assert_eq!(priority_address, &net_address3);
     }
 
+    #[test]
+    fn update_advertised_addresses() {
+        let net_address1 = "/ip4/123.0.0.123/tcp/8000".parse::<Multiaddr>().unwrap();
+        let net_address2 = "/ip4/125.1.54.254/tcp/7999".parse::<Multiaddr>().unwrap();
+        let net_address3 = "/ip4/175.6.3.145/tcp/8000".parse::<Multiaddr>().unwrap();
+        let mut net_addresses = MultiaddressesWithStats::from(vec![net_address1.clone(), net_address2.clone()]);
+        assert!(net_addresses.mark_successful_connection_attempt(&net_address1));
+
+        // No change
+        assert!(!net_addresses.update_advertised_addresses(vec![net_address1.clone(), net_address2.clone()]));
+        assert_eq!(net_addresses.len(), 2);
+
+        // The peer moved from address 1 to address 3
+        assert!(net_addresses.update_advertised_addresses(vec![net_address2.clone(), net_address3.clone()]));
+        assert_eq!(net_addresses.len(), 3);
+        // Address 1 is kept for re-validation, but is tried last
+        assert_eq!(net_addresses.iter().last().unwrap(), &net_address1);
+        assert!(net_addresses.addresses.iter().all(|a| a.last_seen.is_none()));
+
+        // Address 1 was not seen since it was marked for re-validation, so it is removed
+        assert!(!net_addresses.update_advertised_addresses(vec![net_address2, net_address3]));
+        assert_eq!(net_addresses.len(), 2);
+        assert!(net_addresses.iter().all(|a| *a != net_address1));
+    }
+
     // TODO: Broken in release mode - investigate and fix
     //    #[test]
     //    fn test_stats_updates_on_addresses() {
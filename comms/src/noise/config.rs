@@ -27,6 +27,7 @@ use crate::{
     noise::{
         crypto_resolver::TariCryptoResolver,
         error::NoiseError,
+        rekey::RekeyPolicy,
         socket::{Handshake, NoiseSocket},
     },
     peer_manager::NodeIdentity,
@@ -46,6 +47,7 @@ pub(super) const NOISE_IX_PARAMETER: &str = "Noise_IX_25519_ChaChaPoly_BLAKE2b";
 pub struct NoiseConfig {
     node_identity: Arc<NodeIdentity>,
     parameters: NoiseParams,
+    rekey_policy: RekeyPolicy,
 }
 
 impl NoiseConfig {
@@ -55,9 +57,16 @@ impl NoiseConfig {
         Self {
             node_identity,
             parameters,
+            rekey_policy: RekeyPolicy::default(),
         }
     }
 
+    /// Set the thresholds after which upgraded sockets rekey their transport session
+    pub fn with_rekey_policy(mut self, rekey_policy: RekeyPolicy) -> Self {
+        self.rekey_policy = rekey_policy;
+        self
+    }
+
     /// Upgrades the given socket to using the noise protocol. The upgraded socket and the peer's static key
     /// is returned.
     pub async fn upgrade_socket<TSocket>(
@@ -86,7 +95,8 @@ impl NoiseConfig {
         };
 
         let handshake = Handshake::new(socket, handshake_state);
-        let socket = handshake.handshake_1rt().await.map_err(NoiseError::HandshakeFailed)?;
+        let mut socket = handshake.handshake_1rt().await.map_err(NoiseError::HandshakeFailed)?;
+        socket.set_rekey_policy(self.rekey_policy);
 
         Ok(socket)
     }
@@ -25,8 +25,10 @@
 mod config;
 mod crypto_resolver;
 mod error;
+mod rekey;
 mod socket;
 
 pub use config::NoiseConfig;
 pub use error::NoiseError;
+pub use rekey::{RekeyControl, RekeyPolicy};
 pub use socket::NoiseSocket;
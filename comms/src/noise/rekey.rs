@@ -0,0 +1,206 @@
+// Copyright 2019, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Thresholds after which a noise transport session rekeys its outgoing cipher state. Rekeying limits the amount of
+/// traffic that is exposed should a session key be compromised.
+#[derive(Debug, Clone, Copy)]
+pub struct RekeyPolicy {
+    /// Rekey after this much time has elapsed since the last rekey and data has been sent since then. None disables
+    /// time-based rekeying.
+    pub interval: Option<Duration>,
+    /// Rekey after this many plaintext bytes have been sent since the last rekey. None disables byte-based rekeying.
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for RekeyPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Some(Duration::from_secs(60 * 60)),
+            max_bytes: Some(1024 * 1024 * 1024),
+        }
+    }
+}
+
+/// Enables rekey initiation on a [NoiseSocket](super::NoiseSocket). Rekeying is only initiated once the remote peer is
+/// known to support it, which is only known once the socket has been handed off to the multiplexer, so this control
+/// is shared with the socket.
+#[derive(Debug, Clone, Default)]
+pub struct RekeyControl {
+    enabled: Arc<AtomicBool>,
+}
+
+impl RekeyControl {
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct RekeyState {
+    policy: RekeyPolicy,
+    control: RekeyControl,
+    last_rekey: Instant,
+    bytes_since_rekey: u64,
+    response_pending: bool,
+    awaiting_response: bool,
+    pub(super) num_outgoing: usize,
+    pub(super) num_incoming: usize,
+}
+
+impl RekeyState {
+    pub fn new(policy: RekeyPolicy) -> Self {
+        Self {
+            policy,
+            control: RekeyControl::default(),
+            last_rekey: Instant::now(),
+            bytes_since_rekey: 0,
+            response_pending: false,
+            awaiting_response: false,
+            num_outgoing: 0,
+            num_incoming: 0,
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: RekeyPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn control(&self) -> &RekeyControl {
+        &self.control
+    }
+
+    /// Returns true if the outgoing cipher state should be rekeyed before sending more data
+    pub fn is_due(&self) -> bool {
+        if !self.control.is_enabled() {
+            return false;
+        }
+        if self.response_pending {
+            return true;
+        }
+        // Nothing has been encrypted under the current key, so there is nothing to protect by rolling it. This also
+        // stops a short interval from rekeying again before the pending write is sent.
+        if self.bytes_since_rekey == 0 {
+            return false;
+        }
+        let is_interval_due = self
+            .policy
+            .interval
+            .map(|interval| self.last_rekey.elapsed() >= interval)
+            .unwrap_or(false);
+        let is_bytes_due = self
+            .policy
+            .max_bytes
+            .map(|max_bytes| self.bytes_since_rekey >= max_bytes)
+            .unwrap_or(false);
+        is_interval_due || is_bytes_due
+    }
+
+    pub fn record_bytes_sent(&mut self, num_bytes: usize) {
+        self.bytes_since_rekey = self.bytes_since_rekey.saturating_add(num_bytes as u64);
+    }
+
+    /// We rekeyed our outgoing cipher state. If a response to a peer rekey was pending, this rekey is that response,
+    /// otherwise we initiated the rekey and wait for the peer to respond.
+    pub fn on_outgoing_rekey(&mut self) {
+        if !self.response_pending {
+            self.awaiting_response = true;
+        }
+        self.last_rekey = Instant::now();
+        self.bytes_since_rekey = 0;
+        self.response_pending = false;
+        self.num_outgoing += 1;
+    }
+
+    /// The peer rekeyed. If we are waiting for a response to our own rekey, this is the response and is not answered.
+    /// Otherwise, we respond by rekeying our outgoing cipher state before the next write so that both directions roll
+    /// their keys together.
+    pub fn on_incoming_rekey(&mut self) {
+        if self.awaiting_response {
+            self.awaiting_response = false;
+        } else {
+            self.response_pending = true;
+        }
+        self.num_incoming += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn is_due() {
+        let mut state = RekeyState::new(RekeyPolicy {
+            interval: None,
+            max_bytes: Some(10),
+        });
+        state.record_bytes_sent(20);
+        // Not enabled
+        assert!(!state.is_due());
+        state.control().enable();
+        assert!(state.is_due());
+        state.on_outgoing_rekey();
+        assert!(!state.is_due());
+        // The response to our rekey is not answered
+        state.on_incoming_rekey();
+        assert!(!state.is_due());
+        // A rekey initiated by the peer is answered
+        state.on_incoming_rekey();
+        assert!(state.is_due());
+        state.on_outgoing_rekey();
+        assert!(!state.is_due());
+        state.on_incoming_rekey();
+        assert!(state.is_due());
+
+        let mut state = RekeyState::new(RekeyPolicy {
+            interval: Some(Duration::from_secs(0)),
+            max_bytes: None,
+        });
+        state.control().enable();
+        // Nothing has been sent under the current key
+        assert!(!state.is_due());
+        state.record_bytes_sent(1);
+        assert!(state.is_due());
+        state.on_outgoing_rekey();
+        assert!(!state.is_due());
+
+        let mut state = RekeyState::new(RekeyPolicy {
+            interval: None,
+            max_bytes: None,
+        });
+        state.control().enable();
+        state.record_bytes_sent(usize::MAX);
+        assert!(!state.is_due());
+    }
+}
@@ -36,7 +36,10 @@ use std::{
     task::{Context, Poll},
 };
 // use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
-use crate::types::CommsPublicKey;
+use crate::{
+    noise::rekey::{RekeyControl, RekeyPolicy, RekeyState},
+    types::CommsPublicKey,
+};
 use futures::{io::Error, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tari_crypto::tari_utilities::ByteArray;
 
@@ -126,6 +129,12 @@ enum WriteState {
 /// Encrypts data to be written to and decrypts data that is read from the underlying socket using
 /// the noise protocol. This is done by wrapping noise payloads in u16 (big endian) length prefix
 /// frames.
+///
+/// Once in transport mode, a frame containing an empty (authenticated) payload signals that the sender has rekeyed
+/// its outgoing cipher state. The receiver rekeys its incoming cipher state and responds by rekeying its own outgoing
+/// cipher state before its next write. A rekey received while waiting for the response to our own rekey is that
+/// response and is not answered, so that the peers do not keep rekeying each other. Rekeying is only initiated once
+/// enabled using the socket's [RekeyControl].
 #[derive(Debug)]
 pub struct NoiseSocket<TSocket> {
     socket: TSocket,
@@ -133,6 +142,7 @@ pub struct NoiseSocket<TSocket> {
     buffers: Box<NoiseBuffers>,
     read_state: ReadState,
     write_state: WriteState,
+    rekey: RekeyState,
 }
 
 impl<TSocket> NoiseSocket<TSocket> {
@@ -143,9 +153,32 @@ impl<TSocket> NoiseSocket<TSocket> {
             buffers: Box::new(NoiseBuffers::new()),
             read_state: ReadState::Init,
             write_state: WriteState::Init,
+            rekey: RekeyState::new(RekeyPolicy::default()),
         }
     }
 
+    /// Set the thresholds after which this socket rekeys its outgoing cipher state
+    pub fn set_rekey_policy(&mut self, policy: RekeyPolicy) {
+        self.rekey.set_policy(policy);
+    }
+
+    /// Returns the control used to enable rekey initiation on this socket. This should be enabled once the remote
+    /// peer is known to support rekeying.
+    pub fn rekey_control(&self) -> RekeyControl {
+        self.rekey.control().clone()
+    }
+
+    /// Encrypt an empty payload into the write buffer and rekey the outgoing cipher state, returning the frame length.
+    fn encrypt_rekey_frame(&mut self) -> Result<u16, snow::Error> {
+        let encrypted_len = self.state.write_message(&[], &mut self.buffers.write_encrypted)?;
+        self.state.rekey_outgoing()?;
+        self.rekey.on_outgoing_rekey();
+        debug!(target: LOG_TARGET, "Rekeyed outgoing noise session");
+        Ok(encrypted_len
+            .try_into()
+            .expect("rekey frame length should be able to fit in u16"))
+    }
+
     /// Get the raw remote static key
     pub fn get_remote_static(&self) -> Option<&[u8]> {
         self.state.get_remote_static()
@@ -296,6 +329,18 @@ where TSocket: AsyncRead + Unpin
                                 &self.buffers.read_encrypted[..(frame_len as usize)],
                                 &mut self.buffers.read_decrypted,
                             ) {
+                                // An empty payload in transport mode signals that the peer has rekeyed
+                                Ok(0) if self.state.is_transport_mode() => match self.state.rekey_incoming() {
+                                    Ok(_) => {
+                                        debug!(target: LOG_TARGET, "Peer rekeyed noise session");
+                                        self.rekey.on_incoming_rekey();
+                                        self.read_state = ReadState::Init;
+                                    },
+                                    Err(e) => {
+                                        warn!(target: LOG_TARGET, "Rekey Error: {}", e);
+                                        self.read_state = ReadState::DecryptionError(e);
+                                    },
+                                },
                                 Ok(decrypted_len) => {
                                     self.read_state = ReadState::CopyDecryptedFrame {
                                         decrypted_len,
@@ -374,11 +419,28 @@ where TSocket: AsyncWrite + Unpin
             );
             match self.write_state {
                 WriteState::Init => {
-                    if buf.is_some() {
-                        self.write_state = WriteState::BufferData { offset: 0 };
-                    } else {
+                    if buf.is_none() {
                         return Poll::Ready(Ok(None));
                     }
+                    if self.rekey.is_due() {
+                        match self.encrypt_rekey_frame() {
+                            Ok(frame_len) => {
+                                self.write_state = WriteState::WriteFrameLen {
+                                    frame_len,
+                                    buf: u16::to_be_bytes(frame_len),
+                                    offset: 0,
+                                };
+                            },
+                            Err(e) => {
+                                warn!(target: LOG_TARGET, "Rekey Error: {}", e);
+                                let err = io::Error::new(io::ErrorKind::InvalidData, format!("EncryptionError: {}", e));
+                                self.write_state = WriteState::EncryptionError(e);
+                                return Poll::Ready(Err(err));
+                            },
+                        }
+                    } else {
+                        self.write_state = WriteState::BufferData { offset: 0 };
+                    }
                 },
                 WriteState::BufferData { ref mut offset } => {
                     let bytes_buffered = if let Some(buf) = buf {
@@ -398,24 +460,33 @@ where TSocket: AsyncWrite + Unpin
                     };
 
                     if buf.is_none() || *offset == MAX_WRITE_BUFFER_LENGTH {
-                        match self.state.write_message(
-                            &self.buffers.write_decrypted[..*offset],
-                            &mut self.buffers.write_encrypted,
-                        ) {
-                            Ok(encrypted_len) => {
-                                let frame_len = encrypted_len.try_into().expect("offset should be able to fit in u16");
-                                self.write_state = WriteState::WriteFrameLen {
-                                    frame_len,
-                                    buf: u16::to_be_bytes(frame_len),
-                                    offset: 0,
-                                };
-                            },
-                            Err(e) => {
-                                warn!(target: LOG_TARGET, "Encryption Error: {}", e);
-                                let err = io::Error::new(io::ErrorKind::InvalidData, format!("EncryptionError: {}", e));
-                                self.write_state = WriteState::EncryptionError(e);
-                                return Poll::Ready(Err(err));
-                            },
+                        let plaintext_len = *offset;
+                        if plaintext_len == 0 && self.state.is_transport_mode() {
+                            // Nothing to send. Empty payloads are reserved for rekey signalling.
+                            self.write_state = WriteState::Flush;
+                        } else {
+                            match self.state.write_message(
+                                &self.buffers.write_decrypted[..plaintext_len],
+                                &mut self.buffers.write_encrypted,
+                            ) {
+                                Ok(encrypted_len) => {
+                                    self.rekey.record_bytes_sent(plaintext_len);
+                                    let frame_len =
+                                        encrypted_len.try_into().expect("offset should be able to fit in u16");
+                                    self.write_state = WriteState::WriteFrameLen {
+                                        frame_len,
+                                        buf: u16::to_be_bytes(frame_len),
+                                        offset: 0,
+                                    };
+                                },
+                                Err(e) => {
+                                    warn!(target: LOG_TARGET, "Encryption Error: {}", e);
+                                    let err =
+                                        io::Error::new(io::ErrorKind::InvalidData, format!("EncryptionError: {}", e));
+                                    self.write_state = WriteState::EncryptionError(e);
+                                    return Poll::Ready(Err(err));
+                                },
+                            }
                         }
                     }
 
@@ -618,6 +689,30 @@ impl NoiseState {
 
     proxy_state_method!(pub fn get_remote_static(&self) -> Option<&[u8]>);
 
+    pub fn is_transport_mode(&self) -> bool {
+        matches!(self, NoiseState::TransportState(_))
+    }
+
+    pub fn rekey_outgoing(&mut self) -> Result<(), snow::Error> {
+        match self {
+            NoiseState::TransportState(state) => {
+                state.rekey_outgoing();
+                Ok(())
+            },
+            _ => Err(snow::Error::State(StateProblem::HandshakeNotFinished)),
+        }
+    }
+
+    pub fn rekey_incoming(&mut self) -> Result<(), snow::Error> {
+        match self {
+            NoiseState::TransportState(state) => {
+                state.rekey_incoming();
+                Ok(())
+            },
+            _ => Err(snow::Error::State(StateProblem::HandshakeNotFinished)),
+        }
+    }
+
     pub fn into_transport_mode(self) -> Result<Self, snow::Error> {
         match self {
             NoiseState::HandshakeState(state) => Ok(NoiseState::TransportState(Box::new(state.into_transport_mode()?))),
@@ -644,7 +739,7 @@ mod test {
     use crate::{memsocket::MemorySocket, noise::config::NOISE_IX_PARAMETER, runtime};
     use futures::future::join;
     use snow::{params::NoiseParams, Builder, Error, Keypair};
-    use std::io;
+    use std::{io, time::Duration};
     use tokio::runtime::Runtime;
 
     async fn build_test_connection(
@@ -747,6 +842,125 @@ mod test {
         Ok(())
     }
 
+    #[runtime::test]
+    async fn rekey() -> io::Result<()> {
+        let ((_dialer_keypair, dialer), (_listener_keypair, listener)) = build_test_connection().await.unwrap();
+
+        let (mut a, mut b) = perform_handshake(dialer, listener).await?;
+        let policy = RekeyPolicy {
+            interval: None,
+            max_bytes: Some(10),
+        };
+        a.set_rekey_policy(policy);
+        b.set_rekey_policy(policy);
+        a.rekey_control().enable();
+        b.rekey_control().enable();
+
+        for _ in 0..3 {
+            a.write_all(b"Oathbringer").await?;
+            a.flush().await?;
+            let mut buf = [0; 11];
+            b.read_exact(&mut buf).await?;
+            assert_eq!(&buf, b"Oathbringer");
+
+            b.write_all(b"Rhythm of War").await?;
+            b.flush().await?;
+            let mut buf = [0; 13];
+            a.read_exact(&mut buf).await?;
+            assert_eq!(&buf, b"Rhythm of War");
+        }
+
+        assert_eq!(a.rekey.num_outgoing, 2);
+        assert_eq!(b.rekey.num_incoming, 2);
+        // b responds to each rekey from a
+        assert_eq!(b.rekey.num_outgoing, 2);
+        assert_eq!(a.rekey.num_incoming, 2);
+
+        Ok(())
+    }
+
+    #[runtime::test]
+    async fn rekey_response_is_not_answered() -> io::Result<()> {
+        let ((_dialer_keypair, dialer), (_listener_keypair, listener)) = build_test_connection().await.unwrap();
+
+        let (mut a, mut b) = perform_handshake(dialer, listener).await?;
+        // Only a has a rekey threshold, b only responds
+        a.set_rekey_policy(RekeyPolicy {
+            interval: None,
+            max_bytes: Some(10),
+        });
+        b.set_rekey_policy(RekeyPolicy {
+            interval: None,
+            max_bytes: None,
+        });
+        a.rekey_control().enable();
+        b.rekey_control().enable();
+
+        a.write_all(b"Oathbringer").await?;
+        a.flush().await?;
+        let mut buf = [0; 11];
+        b.read_exact(&mut buf).await?;
+
+        // The threshold is reached, so a rekeys before this write
+        a.write_all(b"Tress").await?;
+        a.flush().await?;
+        let mut buf = [0; 5];
+        b.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"Tress");
+
+        for _ in 0..3 {
+            b.write_all(b"Warbreaker").await?;
+            b.flush().await?;
+            let mut buf = [0; 10];
+            a.read_exact(&mut buf).await?;
+            assert_eq!(&buf, b"Warbreaker");
+
+            a.write_all(b"!").await?;
+            a.flush().await?;
+            let mut buf = [0; 1];
+            b.read_exact(&mut buf).await?;
+            assert_eq!(&buf, b"!");
+        }
+
+        assert_eq!(a.rekey.num_outgoing, 1);
+        assert_eq!(b.rekey.num_incoming, 1);
+        assert_eq!(b.rekey.num_outgoing, 1);
+        assert_eq!(a.rekey.num_incoming, 1);
+
+        Ok(())
+    }
+
+    #[runtime::test]
+    async fn rekey_not_initiated_until_enabled() -> io::Result<()> {
+        let ((_dialer_keypair, dialer), (_listener_keypair, listener)) = build_test_connection().await.unwrap();
+
+        let (mut a, mut b) = perform_handshake(dialer, listener).await?;
+        a.set_rekey_policy(RekeyPolicy {
+            interval: Some(Duration::from_secs(0)),
+            max_bytes: None,
+        });
+
+        a.write_all(b"Elantris").await?;
+        a.flush().await?;
+        let mut buf = [0; 8];
+        b.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"Elantris");
+        assert_eq!(a.rekey.num_outgoing, 0);
+
+        a.rekey_control().enable();
+        a.write_all(b"Warbreaker").await?;
+        a.flush().await?;
+        let mut buf = [0; 10];
+        b.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"Warbreaker");
+        assert_eq!(a.rekey.num_outgoing, 1);
+        assert_eq!(b.rekey.num_incoming, 1);
+        // b has not enabled rekeying so does not respond
+        assert_eq!(b.rekey.num_outgoing, 0);
+
+        Ok(())
+    }
+
     #[test]
     fn u16_max_writes() -> io::Result<()> {
         // Current thread runtime stack overflows, so the full tokio runtime is used here
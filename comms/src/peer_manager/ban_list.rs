@@ -0,0 +1,297 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Ban list
+//!
+//! A portable list of banned peers that can be exported from one node and imported into another. The list is
+//! serialized as JSON and may optionally be signed by the publisher of the list (e.g. a community-maintained list) so
+//! that the importing node can verify who published it and that it has not been tampered with. The publisher key in a
+//! signed list is only accepted if the importing node trusts it.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "created_at": "2020-11-20T10:00:00",
+//!   "entries": [
+//!     {
+//!       "public_key": "<hex>",
+//!       "reason": "Flooding",
+//!       "banned_until": "2020-11-21T10:00:00"
+//!     }
+//!   ],
+//!   "publisher": {
+//!     "public_key": "<hex>",
+//!     "signature": "<hex>"
+//!   }
+//! }
+//! ```
+
+use crate::{
+    peer_manager::{NodeIdentity, Peer},
+    types::CommsPublicKey,
+    utils::signature,
+};
+use chrono::{NaiveDateTime, Utc};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use tari_crypto::tari_utilities::{
+    hex::{from_hex, Hex},
+    message_format::MessageFormat,
+    ByteArray,
+};
+use thiserror::Error;
+
+/// The current version of the ban list format
+pub const BAN_LIST_VERSION: u32 = 1;
+
+#[derive(Debug, Error, Clone)]
+pub enum BanListError {
+    #[error("Unsupported ban list version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Ban list publisher signature is invalid")]
+    InvalidSignature,
+    #[error("Ban list is signed by '{0}' which is not a trusted publisher")]
+    UntrustedPublisher(Box<CommsPublicKey>),
+    #[error("Ban list is not signed by a trusted publisher")]
+    NotSigned,
+    #[error("Failed to sign ban list")]
+    SigningFailed,
+    #[error("Failed to serialize or deserialize ban list: {0}")]
+    SerializationError(String),
+}
+
+/// A single banned peer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BanListEntry {
+    /// Public key of the banned peer
+    pub public_key: CommsPublicKey,
+    /// The reason that the peer was banned
+    pub reason: String,
+    /// The time (UTC) at which the ban expires
+    pub banned_until: NaiveDateTime,
+}
+
+impl BanListEntry {
+    /// Returns a `BanListEntry` for the given peer if the peer is currently banned, otherwise None
+    pub fn from_banned_peer(peer: &Peer) -> Option<Self> {
+        peer.banned_until().map(|banned_until| Self {
+            public_key: peer.public_key.clone(),
+            reason: peer.banned_reason.clone(),
+            banned_until: *banned_until,
+        })
+    }
+
+    /// Returns true if this ban has expired
+    pub fn is_expired(&self) -> bool {
+        self.banned_until <= Utc::now().naive_utc()
+    }
+}
+
+/// The public key and signature of the publisher of a ban list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BanListPublisher {
+    pub public_key: CommsPublicKey,
+    /// Hex-encoded Schnorr signature of the ban list challenge
+    pub signature: String,
+}
+
+/// A list of banned peers that can be exchanged between nodes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BanList {
+    pub version: u32,
+    pub created_at: NaiveDateTime,
+    pub entries: Vec<BanListEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<BanListPublisher>,
+}
+
+impl BanList {
+    /// Create a new unsigned ban list containing the given entries
+    pub fn new(entries: Vec<BanListEntry>) -> Self {
+        Self {
+            version: BAN_LIST_VERSION,
+            created_at: Utc::now().naive_utc(),
+            entries,
+            publisher: None,
+        }
+    }
+
+    /// Deserialize a ban list from JSON. The version of the list is checked, but the signature is not verified (see
+    /// [verify](#method.verify)).
+    pub fn from_json(json: &str) -> Result<Self, BanListError> {
+        let ban_list =
+            serde_json::from_str::<Self>(json).map_err(|err| BanListError::SerializationError(err.to_string()))?;
+        if ban_list.version > BAN_LIST_VERSION {
+            return Err(BanListError::UnsupportedVersion(ban_list.version));
+        }
+        Ok(ban_list)
+    }
+
+    /// Serialize this ban list as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String, BanListError> {
+        serde_json::to_string_pretty(self).map_err(|err| BanListError::SerializationError(err.to_string()))
+    }
+
+    /// Returns true if this list has a publisher signature
+    pub fn is_signed(&self) -> bool {
+        self.publisher.is_some()
+    }
+
+    /// Sign this ban list as the given node. Any existing signature is replaced.
+    pub fn sign(&mut self, node_identity: &NodeIdentity) -> Result<(), BanListError> {
+        let signature = signature::sign(&mut OsRng, node_identity.secret_key().clone(), self.challenge())
+            .map_err(|_| BanListError::SigningFailed)?
+            .to_binary()
+            .map_err(|_| BanListError::SigningFailed)?;
+        self.publisher = Some(BanListPublisher {
+            public_key: node_identity.public_key().clone(),
+            signature: signature.to_hex(),
+        });
+        Ok(())
+    }
+
+    /// Verify that this list was published by one of `trusted_publishers`. A signed list is rejected if its publisher
+    /// is not trusted or the signature is invalid. An unsigned list is only accepted if no publishers are trusted.
+    pub fn verify(&self, trusted_publishers: &[CommsPublicKey]) -> Result<(), BanListError> {
+        match self.publisher.as_ref() {
+            Some(publisher) => {
+                if !trusted_publishers.contains(&publisher.public_key) {
+                    return Err(BanListError::UntrustedPublisher(Box::new(publisher.public_key.clone())));
+                }
+                let signature = from_hex(&publisher.signature).map_err(|_| BanListError::InvalidSignature)?;
+                if signature::verify(&publisher.public_key, &signature, self.challenge()) {
+                    Ok(())
+                } else {
+                    Err(BanListError::InvalidSignature)
+                }
+            },
+            None if trusted_publishers.is_empty() => Ok(()),
+            None => Err(BanListError::NotSigned),
+        }
+    }
+
+    /// The bytes that are signed by the publisher. Every field except the publisher is included.
+    fn challenge(&self) -> Vec<u8> {
+        let mut challenge = Vec::with_capacity(16 + self.entries.len() * 64);
+        challenge.extend_from_slice(&self.version.to_le_bytes());
+        challenge.extend_from_slice(&self.created_at.timestamp().to_le_bytes());
+        for entry in &self.entries {
+            challenge.extend_from_slice(entry.public_key.as_bytes());
+            challenge.extend_from_slice(&(entry.reason.len() as u64).to_le_bytes());
+            challenge.extend_from_slice(entry.reason.as_bytes());
+            challenge.extend_from_slice(&entry.banned_until.timestamp().to_le_bytes());
+        }
+        challenge
+    }
+}
+
+/// The result of merging an imported ban list into the local peer database
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BanListImportResult {
+    /// Number of previously unknown peers that were added as banned peers
+    pub num_added: usize,
+    /// Number of known peers whose ban was added or extended
+    pub num_updated: usize,
+    /// Number of entries that were expired or already covered by a local ban
+    pub num_skipped: usize,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::node_identity::build_node_identity;
+    use chrono::Duration;
+    use tari_crypto::keys::PublicKey;
+    use tari_test_utils::unpack_enum;
+
+    fn create_ban_list() -> BanList {
+        let entries = (0..3)
+            .map(|i| BanListEntry {
+                public_key: CommsPublicKey::random_keypair(&mut OsRng).1,
+                reason: format!("Reason {}", i),
+                banned_until: Utc::now().naive_utc() + Duration::hours(1),
+            })
+            .collect();
+        BanList::new(entries)
+    }
+
+    #[test]
+    fn json_roundtrip() {
+        let ban_list = create_ban_list();
+        let json = ban_list.to_json().unwrap();
+        let decoded = BanList::from_json(&json).unwrap();
+        assert_eq!(decoded, ban_list);
+        assert!(!decoded.is_signed());
+        decoded.verify(&[]).unwrap();
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let node_identity = build_node_identity(Default::default());
+        let mut ban_list = create_ban_list();
+        ban_list.sign(&node_identity).unwrap();
+        assert!(ban_list.is_signed());
+
+        let ban_list = BanList::from_json(&ban_list.to_json().unwrap()).unwrap();
+        let trusted = [node_identity.public_key().clone()];
+        ban_list.verify(&trusted).unwrap();
+
+        let mut tampered = ban_list.clone();
+        tampered.entries[1].reason = "Something else".to_string();
+        unpack_enum!(BanListError::InvalidSignature = tampered.verify(&trusted).unwrap_err());
+
+        let mut tampered = ban_list;
+        tampered.entries.pop();
+        unpack_enum!(BanListError::InvalidSignature = tampered.verify(&trusted).unwrap_err());
+    }
+
+    #[test]
+    fn rejects_untrusted_publisher() {
+        let publisher = build_node_identity(Default::default());
+        let mut ban_list = create_ban_list();
+        ban_list.sign(&publisher).unwrap();
+
+        // An edited list re-signed with another key is valid, but its publisher is not trusted
+        let other = build_node_identity(Default::default());
+        let mut resigned = ban_list.clone();
+        resigned.entries.pop();
+        resigned.sign(&other).unwrap();
+        let err = resigned.verify(&[publisher.public_key().clone()]).unwrap_err();
+        unpack_enum!(BanListError::UntrustedPublisher(public_key) = err);
+        assert_eq!(*public_key, *other.public_key());
+
+        // A signed list is rejected if no publishers are trusted
+        unpack_enum!(BanListError::UntrustedPublisher(_pk) = ban_list.verify(&[]).unwrap_err());
+
+        // An unsigned list is rejected if a publisher is expected
+        let unsigned = create_ban_list();
+        unpack_enum!(BanListError::NotSigned = unsigned.verify(&[publisher.public_key().clone()]).unwrap_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_version() {
+        let mut ban_list = create_ban_list();
+        ban_list.version = BAN_LIST_VERSION + 1;
+        let err = BanList::from_json(&ban_list.to_json().unwrap()).unwrap_err();
+        unpack_enum!(BanListError::UnsupportedVersion(_v) = err);
+    }
+}
@@ -0,0 +1,140 @@
+//  Copyright 2020 The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::peer_manager::NodeId;
+use std::sync::Arc;
+
+/// Precomputed orderings of all known node ids by distance from recently queried node ids. Most closest-peer queries
+/// are made from a small number of node ids (typically this node's own node id), so reusing the ordering avoids
+/// recomputing and sorting the distance to every known peer on each query.
+///
+/// An ordering only depends on the set of known node ids, so the index must be cleared whenever a peer is added or
+/// removed. When the index is full, the least recently used ordering is evicted. A capacity of zero disables the
+/// index.
+#[derive(Debug)]
+pub(super) struct DistanceIndex {
+    capacity: usize,
+    entries: Vec<IndexEntry>,
+    access_counter: u64,
+}
+
+#[derive(Debug)]
+struct IndexEntry {
+    node_id: NodeId,
+    ordered: Arc<Vec<NodeId>>,
+    last_access: u64,
+}
+
+impl DistanceIndex {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Vec::with_capacity(capacity),
+            access_counter: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Returns the node ids ordered by distance from the given node id, if the ordering is indexed
+    pub fn get(&mut self, node_id: &NodeId) -> Option<Arc<Vec<NodeId>>> {
+        let access = self.next_access();
+        self.entries.iter_mut().find(|e| e.node_id == *node_id).map(|entry| {
+            entry.last_access = access;
+            Arc::clone(&entry.ordered)
+        })
+    }
+
+    /// Adds the ordering of node ids by distance from the given node id, evicting the least recently used ordering if
+    /// the index is full
+    pub fn insert(&mut self, node_id: NodeId, ordered: Arc<Vec<NodeId>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.retain(|e| e.node_id != node_id);
+        if self.entries.len() >= self.capacity {
+            if let Some(pos) = self
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, e)| e.last_access)
+                .map(|(i, _)| i)
+            {
+                self.entries.swap_remove(pos);
+            }
+        }
+        let last_access = self.next_access();
+        self.entries.push(IndexEntry {
+            node_id,
+            ordered,
+            last_access,
+        });
+    }
+
+    /// Removes all orderings. This must be called whenever the set of known node ids changes.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn next_access(&mut self) -> u64 {
+        self.access_counter += 1;
+        self.access_counter
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tari_crypto::tari_utilities::ByteArray;
+
+    fn make_node_id(byte: u8) -> NodeId {
+        NodeId::from_bytes(&[byte; 13]).unwrap()
+    }
+
+    #[test]
+    fn insert_get_evict() {
+        let mut index = DistanceIndex::new(2);
+        index.insert(make_node_id(1), Arc::new(vec![make_node_id(2)]));
+        index.insert(make_node_id(2), Arc::new(vec![make_node_id(1)]));
+        assert_eq!(*index.get(&make_node_id(1)).unwrap(), vec![make_node_id(2)]);
+        // Node id 2 is the least recently used ordering
+        index.insert(make_node_id(3), Arc::new(vec![]));
+        assert_eq!(index.len(), 2);
+        assert!(index.get(&make_node_id(2)).is_none());
+        assert!(index.get(&make_node_id(1)).is_some());
+
+        index.clear();
+        assert_eq!(index.len(), 0);
+
+        let mut index = DistanceIndex::new(0);
+        assert!(!index.is_enabled());
+        index.insert(make_node_id(1), Arc::new(vec![]));
+        assert!(index.get(&make_node_id(1)).is_none());
+    }
+}
@@ -20,6 +20,7 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE
 
+use crate::peer_manager::{BanListError, PeerSnapshotError};
 use std::sync::PoisonError;
 use tari_storage::KeyValStoreError;
 use thiserror::Error;
@@ -34,6 +35,12 @@ pub enum PeerManagerError {
     DatabaseError(#[from] KeyValStoreError),
     #[error("An error occurred while migrating the database: {0}")]
     MigrationError(String),
+    #[error("An error occurred while checking the integrity of the database: {0}")]
+    IntegrityCheckError(String),
+    #[error("Ban list error: {0}")]
+    BanListError(#[from] BanListError),
+    #[error("Peer snapshot error: {0}")]
+    PeerSnapshotError(#[from] PeerSnapshotError),
 }
 
 impl PeerManagerError {
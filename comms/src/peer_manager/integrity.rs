@@ -0,0 +1,399 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//! # Peer database integrity check
+//!
+//! Validates every record in the persistent peer database before the [PeerManager](super::PeerManager) loads it. A
+//! peer database that was corrupted (e.g. by an unclean shutdown or a bad migration) can otherwise cause peer manager
+//! queries to fail or panic at runtime. The following problems are detected:
+//!
+//! - Corrupt records that cannot be deserialized
+//! - Peers whose `id` does not match the key they are stored under
+//! - Peers whose node id was not derived from their public key
+//! - More than one record with the same node id. The most recently seen peer is kept.
+//! - Orphaned addresses i.e. duplicate entries in a peer's address list. Address stats are only ever updated on the
+//!   first matching entry, so later entries are never used.
+//!
+//! What is done about each problem is determined by the [PeerDbRepairPolicy].
+
+use crate::peer_manager::{migrations::MIGRATION_VERSION_KEY, NodeId, Peer, PeerId};
+use chrono::NaiveDateTime;
+use log::*;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+use tari_storage::{
+    lmdb_store::{LMDBDatabase, LMDBError},
+    IterationResult,
+};
+
+const LOG_TARGET: &str = "comms::peer_manager::integrity";
+
+/// Determines what the integrity check does with problems it finds in the peer database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerDbRepairPolicy {
+    /// Only report problems. The peer database is not modified.
+    ReportOnly,
+    /// Repair records where possible. Corrupt and duplicate records are dropped.
+    Repair,
+    /// Drop every record that has a problem
+    DropInvalid,
+}
+
+impl Default for PeerDbRepairPolicy {
+    fn default() -> Self {
+        PeerDbRepairPolicy::Repair
+    }
+}
+
+/// The result of a peer database integrity check
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerDbIntegrityReport {
+    /// The number of records that were checked
+    pub num_records: usize,
+    /// The number of records that could not be deserialized
+    pub num_corrupt: usize,
+    /// The number of peers whose id did not match the key they are stored under
+    pub num_mismatched_ids: usize,
+    /// The number of peers whose node id was not derived from their public key
+    pub num_invalid_node_ids: usize,
+    /// The number of records that had the same node id as another record
+    pub num_duplicates: usize,
+    /// The number of duplicate address entries
+    pub num_orphaned_addresses: usize,
+    /// The number of records that were repaired
+    pub num_repaired: usize,
+    /// The number of records that were removed from the database
+    pub num_dropped: usize,
+}
+
+impl PeerDbIntegrityReport {
+    /// Returns true if any problems were found
+    pub fn has_problems(&self) -> bool {
+        self.num_corrupt > 0 ||
+            self.num_mismatched_ids > 0 ||
+            self.num_invalid_node_ids > 0 ||
+            self.num_duplicates > 0 ||
+            self.num_orphaned_addresses > 0
+    }
+}
+
+impl fmt::Display for PeerDbIntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Checked {} peer record(s): {} corrupt, {} mismatched id(s), {} invalid node id(s), {} duplicate(s), {} \
+             orphaned address(es). {} repaired, {} dropped.",
+            self.num_records,
+            self.num_corrupt,
+            self.num_mismatched_ids,
+            self.num_invalid_node_ids,
+            self.num_duplicates,
+            self.num_orphaned_addresses,
+            self.num_repaired,
+            self.num_dropped
+        )
+    }
+}
+
+/// Check every record in the LMDB peer database and apply the given repair policy
+pub fn check_lmdb(database: &LMDBDatabase, policy: PeerDbRepairPolicy) -> Result<PeerDbIntegrityReport, LMDBError> {
+    let mut check = IntegrityCheck::new(policy);
+    database.for_each_raw(|key_bytes, val_bytes| {
+        match bincode::deserialize::<PeerId>(key_bytes) {
+            Ok(MIGRATION_VERSION_KEY) => {},
+            Ok(peer_key) => match bincode::deserialize::<Peer>(val_bytes) {
+                Ok(peer) => check.check_peer(peer_key, peer),
+                Err(err) => check.corrupt_record(key_bytes, err.to_string()),
+            },
+            Err(err) => check.corrupt_record(key_bytes, err.to_string()),
+        }
+        IterationResult::Continue
+    })?;
+
+    let (report, actions) = check.finish();
+    for action in actions {
+        match action {
+            RepairAction::Update(peer_key, peer) => database.insert(&peer_key, &peer)?,
+            RepairAction::Delete(peer_key) => database.remove(&peer_key)?,
+            RepairAction::DeleteRaw(key_bytes) => database.remove(&key_bytes[..])?,
+        }
+    }
+
+    if report.has_problems() {
+        warn!(target: LOG_TARGET, "Peer database integrity check: {}", report);
+    } else {
+        debug!(target: LOG_TARGET, "Peer database integrity check: {}", report);
+    }
+
+    Ok(report)
+}
+
+#[derive(Debug)]
+enum RepairAction {
+    Update(PeerId, Peer),
+    Delete(PeerId),
+    DeleteRaw(Vec<u8>),
+}
+
+struct IntegrityCheck {
+    policy: PeerDbRepairPolicy,
+    report: PeerDbIntegrityReport,
+    /// The key, rank and whether the record was repaired for each node id that has been checked
+    node_ids: HashMap<NodeId, (PeerId, NaiveDateTime, bool)>,
+    actions: Vec<RepairAction>,
+}
+
+impl IntegrityCheck {
+    fn new(policy: PeerDbRepairPolicy) -> Self {
+        Self {
+            policy,
+            report: Default::default(),
+            node_ids: HashMap::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    fn corrupt_record(&mut self, key_bytes: &[u8], err: String) {
+        self.report.num_records += 1;
+        self.report.num_corrupt += 1;
+        warn!(
+            target: LOG_TARGET,
+            "Corrupt peer record (key = {:02x?}): {}", key_bytes, err
+        );
+        if self.policy != PeerDbRepairPolicy::ReportOnly {
+            self.actions.push(RepairAction::DeleteRaw(key_bytes.to_vec()));
+            self.report.num_dropped += 1;
+        }
+    }
+
+    fn check_peer(&mut self, peer_key: PeerId, mut peer: Peer) {
+        self.report.num_records += 1;
+        let mut has_problem = false;
+
+        if peer.id != Some(peer_key) {
+            self.report.num_mismatched_ids += 1;
+            has_problem = true;
+            peer.id = Some(peer_key);
+        }
+
+        let expected_node_id = NodeId::from_public_key(&peer.public_key);
+        if peer.node_id != expected_node_id {
+            self.report.num_invalid_node_ids += 1;
+            has_problem = true;
+            warn!(
+                target: LOG_TARGET,
+                "Peer `{}` has a node id that does not match its public key (expected `{}`)",
+                peer.node_id.short_str(),
+                expected_node_id.short_str()
+            );
+            peer.node_id = expected_node_id;
+        }
+
+        let mut seen = HashSet::new();
+        let num_addresses = peer.addresses.addresses.len();
+        peer.addresses.addresses.retain(|a| seen.insert(a.address.clone()));
+        let num_orphaned = num_addresses - peer.addresses.addresses.len();
+        if num_orphaned > 0 {
+            self.report.num_orphaned_addresses += num_orphaned;
+            has_problem = true;
+        }
+
+        if has_problem {
+            match self.policy {
+                PeerDbRepairPolicy::ReportOnly => {},
+                PeerDbRepairPolicy::Repair => {
+                    self.actions.push(RepairAction::Update(peer_key, peer.clone()));
+                    self.report.num_repaired += 1;
+                },
+                PeerDbRepairPolicy::DropInvalid => {
+                    self.actions.push(RepairAction::Delete(peer_key));
+                    self.report.num_dropped += 1;
+                    return;
+                },
+            }
+        }
+
+        let was_repaired = has_problem && self.policy == PeerDbRepairPolicy::Repair;
+        self.check_duplicate(peer_key, peer, was_repaired);
+    }
+
+    fn check_duplicate(&mut self, peer_key: PeerId, peer: Peer, was_repaired: bool) {
+        let rank = peer.last_seen().map(|dt| dt.naive_utc()).unwrap_or(peer.added_at);
+        let (existing_key, existing_rank, existing_was_repaired) = match self.node_ids.get(&peer.node_id) {
+            Some(existing) => *existing,
+            None => {
+                self.node_ids.insert(peer.node_id, (peer_key, rank, was_repaired));
+                return;
+            },
+        };
+
+        self.report.num_duplicates += 1;
+        debug!(
+            target: LOG_TARGET,
+            "Peer `{}` is stored more than once (keys {} and {})",
+            peer.node_id.short_str(),
+            existing_key,
+            peer_key
+        );
+        if self.policy == PeerDbRepairPolicy::ReportOnly {
+            return;
+        }
+
+        // Keep the most recently seen peer
+        let (drop_key, drop_was_repaired) = if rank > existing_rank {
+            self.node_ids.insert(peer.node_id, (peer_key, rank, was_repaired));
+            (existing_key, existing_was_repaired)
+        } else {
+            (peer_key, was_repaired)
+        };
+        if drop_was_repaired {
+            self.report.num_repaired -= 1;
+        }
+        self.actions.push(RepairAction::Delete(drop_key));
+        self.report.num_dropped += 1;
+    }
+
+    fn finish(self) -> (PeerDbIntegrityReport, Vec<RepairAction>) {
+        (self.report, self.actions)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        net_address::MultiaddressesWithStats,
+        peer_manager::{PeerFeatures, PeerFlags},
+        types::CommsPublicKey,
+    };
+    use chrono::Utc;
+    use multiaddr::Multiaddr;
+    use tari_crypto::keys::PublicKey;
+
+    fn create_peer(peer_key: PeerId) -> Peer {
+        let (_, pk) = CommsPublicKey::random_keypair(&mut rand::rngs::OsRng);
+        let node_id = NodeId::from_public_key(&pk);
+        let address = "/ip4/1.2.3.4/tcp/8000".parse::<Multiaddr>().unwrap();
+        let mut peer = Peer::new(
+            pk,
+            node_id,
+            MultiaddressesWithStats::from(address),
+            PeerFlags::default(),
+            PeerFeatures::COMMUNICATION_NODE,
+            Default::default(),
+            Default::default(),
+        );
+        peer.id = Some(peer_key);
+        peer
+    }
+
+    fn run_check(policy: PeerDbRepairPolicy, peers: Vec<Peer>) -> (PeerDbIntegrityReport, Vec<RepairAction>) {
+        let mut check = IntegrityCheck::new(policy);
+        check.corrupt_record(&[1, 2, 3], "invalid value".to_string());
+        // Peers are stored under the keys 1, 2, 3, ... in the order given
+        for (i, peer) in peers.into_iter().enumerate() {
+            check.check_peer(i as PeerId + 1, peer);
+        }
+        check.finish()
+    }
+
+    #[test]
+    fn valid_records() {
+        let (report, actions) = run_check(PeerDbRepairPolicy::Repair, vec![create_peer(1), create_peer(2)]);
+        assert_eq!(report.num_records, 3);
+        assert_eq!(report.num_corrupt, 1);
+        assert_eq!(report.num_dropped, 1);
+        assert_eq!(report.num_repaired, 0);
+        assert_eq!(actions.len(), 1);
+        assert!(matches!(&actions[0], RepairAction::DeleteRaw(key) if key == &[1, 2, 3]));
+    }
+
+    #[test]
+    fn repair_invalid_records() {
+        let mut mismatched_id = create_peer(1);
+        mismatched_id.id = Some(100);
+        let mut invalid_node_id = create_peer(2);
+        invalid_node_id.node_id = NodeId::default();
+        let mut orphaned_addresses = create_peer(3);
+        let address = orphaned_addresses.addresses.first().unwrap().address.clone();
+        orphaned_addresses.addresses.addresses.push(address.into());
+
+        let peers = vec![mismatched_id, invalid_node_id, orphaned_addresses];
+        let (report, actions) = run_check(PeerDbRepairPolicy::ReportOnly, peers.clone());
+        assert!(report.has_problems());
+        assert_eq!(report.num_mismatched_ids, 1);
+        assert_eq!(report.num_invalid_node_ids, 1);
+        assert_eq!(report.num_orphaned_addresses, 1);
+        assert_eq!(report.num_repaired, 0);
+        assert_eq!(report.num_dropped, 0);
+        assert!(actions.is_empty());
+
+        let (report, actions) = run_check(PeerDbRepairPolicy::Repair, peers.clone());
+        assert_eq!(report.num_repaired, 3);
+        assert_eq!(report.num_dropped, 1);
+        let repaired = actions
+            .into_iter()
+            .filter_map(|action| match action {
+                RepairAction::Update(key, peer) => Some((key, peer)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(repaired.len(), 3);
+        assert_eq!(repaired[0].1.id, Some(1));
+        assert_eq!(
+            repaired[1].1.node_id,
+            NodeId::from_public_key(&repaired[1].1.public_key)
+        );
+        assert_eq!(repaired[2].1.addresses.len(), 1);
+
+        let (report, actions) = run_check(PeerDbRepairPolicy::DropInvalid, peers);
+        assert_eq!(report.num_repaired, 0);
+        assert_eq!(report.num_dropped, 4);
+        let num_deleted = actions.iter().filter(|a| matches!(a, RepairAction::Delete(_))).count();
+        assert_eq!(num_deleted, 3);
+    }
+
+    #[test]
+    fn drop_duplicates() {
+        let mut peer1 = create_peer(1);
+        peer1.added_at = (Utc::now() - chrono::Duration::seconds(60)).naive_utc();
+        let mut peer2 = peer1.clone();
+        peer2.id = Some(2);
+        peer2.added_at = Utc::now().naive_utc();
+        let mut peer3 = peer1.clone();
+        peer3.id = Some(3);
+
+        let (report, actions) = run_check(PeerDbRepairPolicy::Repair, vec![peer1, peer2, peer3]);
+        assert_eq!(report.num_duplicates, 2);
+        assert_eq!(report.num_dropped, 3);
+        let dropped = actions
+            .iter()
+            .filter_map(|action| match action {
+                RepairAction::Delete(key) => Some(*key),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // The most recently added peer is kept
+        assert_eq!(dropped, vec![1, 3]);
+    }
+}
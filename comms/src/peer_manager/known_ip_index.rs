@@ -0,0 +1,112 @@
+//  Copyright 2020 The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+
+use crate::{
+    peer_manager::{Peer, PeerId},
+    utils::multiaddr::multiaddr_ip,
+};
+use chrono::{NaiveDateTime, Utc};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    net::IpAddr,
+};
+
+/// Index of the IP addresses at which known, unbanned peers have been seen. Used to decide whether an inbound
+/// connection comes from a known peer without querying the peer database.
+///
+/// Only addresses that have been seen (i.e. successfully connected to or received a message from) are indexed. An
+/// address that a peer has only advertised is not proof that the peer controls that IP, so it is ignored.
+#[derive(Debug, Default)]
+pub(super) struct KnownIpIndex {
+    /// The peers seen at each IP, with the time at which the peer's ban expires if it is banned
+    ips: HashMap<IpAddr, HashMap<PeerId, Option<NaiveDateTime>>>,
+    /// The IPs indexed for each peer, so that they can be removed when the peer changes
+    peer_ips: HashMap<PeerId, Vec<IpAddr>>,
+}
+
+impl KnownIpIndex {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Indexes the seen IP addresses of a peer, replacing any previously indexed for the peer key
+    pub fn insert(&mut self, peer_key: PeerId, peer_ips: PeerIps) {
+        self.remove(&peer_key);
+        if peer_ips.ips.is_empty() {
+            return;
+        }
+        for ip in &peer_ips.ips {
+            self.ips.entry(*ip).or_default().insert(peer_key, peer_ips.banned_until);
+        }
+        self.peer_ips.insert(peer_key, peer_ips.ips);
+    }
+
+    /// Removes all indexed IP addresses of the peer
+    pub fn remove(&mut self, peer_key: &PeerId) {
+        for ip in self.peer_ips.remove(peer_key).into_iter().flatten() {
+            if let Entry::Occupied(mut entry) = self.ips.entry(ip) {
+                entry.get_mut().remove(peer_key);
+                if entry.get().is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+    }
+
+    /// Returns true if an unbanned peer has been seen at the given IP address
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match self.ips.get(ip) {
+            Some(peers) => {
+                let now = Utc::now().naive_utc();
+                peers
+                    .values()
+                    .any(|banned_until| banned_until.map(|dt| dt <= now).unwrap_or(true))
+            },
+            None => false,
+        }
+    }
+}
+
+/// The IP addresses of a peer that are indexed by the `KnownIpIndex`
+#[derive(Debug)]
+pub(super) struct PeerIps {
+    ips: Vec<IpAddr>,
+    banned_until: Option<NaiveDateTime>,
+}
+
+impl PeerIps {
+    /// Returns the IP addresses at which the peer has been seen
+    pub fn from_peer(peer: &Peer) -> Self {
+        let mut ips = peer
+            .addresses
+            .addresses
+            .iter()
+            .filter(|addr| addr.last_seen.is_some())
+            .filter_map(|addr| multiaddr_ip(&addr.address))
+            .collect::<Vec<_>>();
+        ips.sort();
+        ips.dedup();
+        Self {
+            ips,
+            banned_until: peer.banned_until().copied(),
+        }
+    }
+}
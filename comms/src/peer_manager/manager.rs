@@ -21,28 +21,62 @@
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::{
+    net_address::MultiaddressesWithStats,
     peer_manager::{
+        ban_list::{BanList, BanListEntry, BanListImportResult},
+        integrity,
         migrations,
         node_id::{NodeDistance, NodeId},
         peer::{Peer, PeerFlags},
         peer_id::PeerId,
+        peer_info::PeerInfo,
+        peer_snapshot::{PeerSnapshot, PeerSnapshotEntry, PeerSnapshotError, PeerSnapshotImportResult},
         peer_storage::PeerStorage,
         wrapper::KeyValueWrapper,
+        NodeIdentity,
+        PeerBatchWriter,
+        PeerDbIntegrityReport,
+        PeerDbRepairPolicy,
         PeerFeatures,
         PeerManagerError,
         PeerQuery,
+        PeerTrustLevel,
+        PeerWriteBatch,
     },
     types::{CommsDatabase, CommsPublicKey},
 };
 use multiaddr::Multiaddr;
-use std::{fmt, fs::File, time::Duration};
+use rand::Rng;
+use std::{fmt, fs, fs::File, net::IpAddr, path::Path, sync::Arc, time::Duration};
 use tari_storage::{lmdb_store::LMDBDatabase, IterationResult};
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
+
+const EVENT_CHANNEL_SIZE: usize = 100;
+
+pub type PeerManagerEventRx = broadcast::Receiver<Arc<PeerManagerEvent>>;
+pub type PeerManagerEventTx = broadcast::Sender<Arc<PeerManagerEvent>>;
+
+/// Events published by the PeerManager when the peer database changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerManagerEvent {
+    /// The peer was banned, either directly or by importing a ban list
+    PeerBanned(NodeId),
+}
+
+impl fmt::Display for PeerManagerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PeerManagerEvent::*;
+        match self {
+            PeerBanned(node_id) => write!(f, "PeerBanned({})", node_id),
+        }
+    }
+}
 
 /// The PeerManager consist of a routing table of previously discovered peers.
 /// It also provides functionality to add, find and delete peers.
 pub struct PeerManager {
     peer_storage: RwLock<PeerStorage<KeyValueWrapper<CommsDatabase>>>,
+    event_tx: PeerManagerEventTx,
     _file_lock: Option<File>,
 }
 
@@ -50,21 +84,55 @@ impl PeerManager {
     /// Constructs a new empty PeerManager
     pub fn new(database: CommsDatabase, file_lock: Option<File>) -> Result<PeerManager, PeerManagerError> {
         let storage = PeerStorage::new_indexed(KeyValueWrapper::new(database))?;
+        let (event_tx, _) = broadcast::channel(EVENT_CHANNEL_SIZE);
         Ok(Self {
             peer_storage: RwLock::new(storage),
+            event_tx,
             _file_lock: file_lock,
         })
     }
 
+    /// Returns a subscription to peer manager events
+    pub fn get_event_subscription(&self) -> PeerManagerEventRx {
+        self.event_tx.subscribe()
+    }
+
+    fn publish_event(&self, event: PeerManagerEvent) {
+        // A send error only means that there are no subscribers
+        let _ = self.event_tx.send(Arc::new(event));
+    }
+
     /// Migrate the peer database, this only applies to the LMDB database
     pub fn migrate_lmdb(database: &LMDBDatabase) -> Result<(), PeerManagerError> {
         migrations::migrate(database).map_err(|err| PeerManagerError::MigrationError(err.to_string()))
     }
 
+    /// Check the integrity of the peer database, repairing or dropping invalid records according to the given policy.
+    /// This only applies to the LMDB database and should be called after migration and before the database is used.
+    pub fn check_lmdb_integrity(
+        database: &LMDBDatabase,
+        policy: PeerDbRepairPolicy,
+    ) -> Result<PeerDbIntegrityReport, PeerManagerError>
+    {
+        integrity::check_lmdb(database, policy).map_err(|err| PeerManagerError::IntegrityCheckError(err.to_string()))
+    }
+
     pub async fn count(&self) -> usize {
         self.peer_storage.read().await.count()
     }
 
+    /// Returns the approximate number of bytes of memory used by the peer manager's in-memory peer indexes and cache
+    pub async fn approx_memory_usage(&self) -> usize {
+        let storage = self.peer_storage.read().await;
+        storage.approx_index_memory_usage() + storage.approx_cache_memory_usage()
+    }
+
+    /// Sets the number of node ids for which an ordering of all known peers by distance is precomputed and reused by
+    /// closest peer queries. This is disabled (zero) by default. See `PeerStorage::with_distance_index_capacity`.
+    pub async fn set_distance_index_capacity(&self, capacity: usize) {
+        self.peer_storage.read().await.set_distance_index_capacity(capacity);
+    }
+
     /// Adds a peer to the routing table of the PeerManager if the peer does not already exist. When a peer already
     /// exist, the stored version will be replaced with the newly provided peer.
     pub async fn add_peer(&self, peer: Peer) -> Result<PeerId, PeerManagerError> {
@@ -83,16 +151,40 @@ impl PeerManager {
         self.peer_storage.read().await.perform_query(peer_query)
     }
 
+    /// Returns true if an unbanned peer has been seen at the given IP address. This is a lookup in an in-memory index
+    /// and does not query the peer database.
+    pub async fn is_known_peer_ip(&self, ip: &IpAddr) -> bool {
+        self.peer_storage.read().await.is_known_peer_ip(ip)
+    }
+
     /// Find the peer with the provided NodeID
     pub async fn find_by_node_id(&self, node_id: &NodeId) -> Result<Peer, PeerManagerError> {
         self.peer_storage.read().await.find_by_node_id(node_id)
     }
 
+    /// Returns a snapshot of everything known about the peer with the given NodeId
+    pub async fn get_peer_info(&self, node_id: &NodeId) -> Result<PeerInfo, PeerManagerError> {
+        self.find_by_node_id(node_id).await.map(|peer| PeerInfo::from(&peer))
+    }
+
     /// Find the peer with the provided PublicKey
     pub async fn find_by_public_key(&self, public_key: &CommsPublicKey) -> Result<Peer, PeerManagerError> {
         self.peer_storage.read().await.find_by_public_key(public_key)
     }
 
+    /// Find all peers with the given NodeIds in a single batch. Unknown NodeIds are skipped.
+    pub async fn get_many(&self, node_ids: &[NodeId]) -> Result<Vec<Peer>, PeerManagerError> {
+        self.peer_storage.read().await.get_many(node_ids)
+    }
+
+    /// Returns all known NodeIds, excluding `excluded_peers`, sorted by distance from `node_id`
+    pub async fn node_ids_by_distance(&self, node_id: &NodeId, excluded_peers: &[NodeId]) -> Vec<NodeId> {
+        self.peer_storage
+            .read()
+            .await
+            .node_ids_by_distance(node_id, excluded_peers)
+    }
+
     /// Check if a peer exist using the specified public_key
     pub async fn exists(&self, public_key: &CommsPublicKey) -> bool {
         self.peer_storage.read().await.exists(public_key)
@@ -199,6 +291,17 @@ impl PeerManager {
         self.peer_storage.read().await.random_peers(n, excluded)
     }
 
+    /// Fetch n random peers, using the given RNG to select them
+    pub async fn random_peers_with_rng<R: Rng + ?Sized>(
+        &self,
+        n: usize,
+        excluded: &[NodeId],
+        rng: &mut R,
+    ) -> Result<Vec<Peer>, PeerManagerError>
+    {
+        self.peer_storage.read().await.random_peers_with_rng(n, excluded, rng)
+    }
+
     /// Check if a specific node_id is in the network region of the N nearest neighbours of the region specified by
     /// region_node_id
     pub async fn in_network_region(
@@ -240,7 +343,9 @@ impl PeerManager {
         reason: String,
     ) -> Result<NodeId, PeerManagerError>
     {
-        self.peer_storage.write().await.ban_peer(public_key, duration, reason)
+        let node_id = self.peer_storage.write().await.ban_peer(public_key, duration, reason)?;
+        self.publish_event(PeerManagerEvent::PeerBanned(node_id.clone()));
+        Ok(node_id)
     }
 
     /// Ban the peer for a length of time specified by the duration
@@ -251,10 +356,13 @@ impl PeerManager {
         reason: String,
     ) -> Result<NodeId, PeerManagerError>
     {
-        self.peer_storage
+        let node_id = self
+            .peer_storage
             .write()
             .await
-            .ban_peer_by_node_id(node_id, duration, reason)
+            .ban_peer_by_node_id(node_id, duration, reason)?;
+        self.publish_event(PeerManagerEvent::PeerBanned(node_id.clone()));
+        Ok(node_id)
     }
 
     /// Changes the offline flag bit of the peer. Return the previous offline state.
@@ -262,6 +370,184 @@ impl PeerManager {
         self.peer_storage.write().await.set_offline(node_id, is_offline)
     }
 
+    /// Export all currently banned peers as a `BanList`
+    pub async fn export_ban_list(&self) -> Result<BanList, PeerManagerError> {
+        let banned_peers = self
+            .peer_storage
+            .read()
+            .await
+            .perform_query(PeerQuery::new().select_where(|peer| peer.is_banned()))?;
+        let entries = banned_peers.iter().filter_map(BanListEntry::from_banned_peer).collect();
+        Ok(BanList::new(entries))
+    }
+
+    /// Merge a `BanList` into the local peer database. The whole list is rejected if it is signed by a publisher that
+    /// is not in `trusted_publishers` or the signature is invalid, or if it is unsigned and `trusted_publishers` is not
+    /// empty. Expired entries are ignored and an existing local ban is only replaced if the imported ban expires later.
+    /// Unknown peers are added so that the ban applies if they are discovered later.
+    pub async fn import_ban_list(
+        &self,
+        ban_list: &BanList,
+        trusted_publishers: &[CommsPublicKey],
+    ) -> Result<BanListImportResult, PeerManagerError>
+    {
+        ban_list.verify(trusted_publishers)?;
+
+        let mut storage = self.peer_storage.write().await;
+        let mut result = BanListImportResult::default();
+        let mut banned = Vec::new();
+        for entry in &ban_list.entries {
+            if entry.is_expired() {
+                result.num_skipped += 1;
+                continue;
+            }
+
+            match storage.find_by_public_key(&entry.public_key) {
+                Ok(mut peer) => {
+                    if peer
+                        .banned_until()
+                        .filter(|until| **until >= entry.banned_until)
+                        .is_some()
+                    {
+                        result.num_skipped += 1;
+                        continue;
+                    }
+                    peer.ban_until(entry.banned_until, entry.reason.clone());
+                    banned.push(peer.node_id.clone());
+                    storage.add_peer(peer)?;
+                    result.num_updated += 1;
+                },
+                Err(err) if err.is_peer_not_found() => {
+                    let mut peer = Peer::new(
+                        entry.public_key.clone(),
+                        NodeId::from_public_key(&entry.public_key),
+                        MultiaddressesWithStats::new(Vec::new()),
+                        PeerFlags::default(),
+                        PeerFeatures::empty(),
+                        Vec::new(),
+                        String::new(),
+                    );
+                    peer.ban_until(entry.banned_until, entry.reason.clone());
+                    banned.push(peer.node_id.clone());
+                    storage.add_peer(peer)?;
+                    result.num_added += 1;
+                },
+                Err(err) => return Err(err),
+            }
+        }
+        drop(storage);
+
+        for node_id in banned {
+            self.publish_event(PeerManagerEvent::PeerBanned(node_id));
+        }
+
+        Ok(result)
+    }
+
+    /// Export all peers that are not banned to a peer snapshot file at `path`, signed by the given node identity.
+    /// Returns the number of exported peers.
+    pub async fn export_peers<P: AsRef<Path>>(
+        &self,
+        path: P,
+        node_identity: &NodeIdentity,
+    ) -> Result<usize, PeerManagerError>
+    {
+        let peers = self.peer_storage.read().await.all()?;
+        let entries = peers
+            .iter()
+            .filter_map(PeerSnapshotEntry::from_peer)
+            .collect::<Vec<_>>();
+        let num_exported = entries.len();
+        let mut snapshot = PeerSnapshot::new(entries);
+        snapshot.sign(node_identity)?;
+        fs::write(path, snapshot.to_json()?).map_err(|err| PeerSnapshotError::IoError(err.to_string()))?;
+        Ok(num_exported)
+    }
+
+    /// Import peers from the peer snapshot file at `path`. The whole snapshot is rejected if it is unsigned, the
+    /// signature is invalid or it was not signed by `expected_signer`. If `expected_signer` is None, the snapshot must
+    /// have been exported by the given node identity (e.g. when migrating this node to new hardware). Unknown peers
+    /// are added, and the addresses of known peers are merged. Peers that are banned locally are left untouched.
+    pub async fn import_peers<P: AsRef<Path>>(
+        &self,
+        path: P,
+        node_identity: &NodeIdentity,
+        expected_signer: Option<&CommsPublicKey>,
+    ) -> Result<PeerSnapshotImportResult, PeerManagerError>
+    {
+        let json = fs::read_to_string(path).map_err(|err| PeerSnapshotError::IoError(err.to_string()))?;
+        let snapshot = PeerSnapshot::from_json(&json)?;
+        snapshot.verify(expected_signer.unwrap_or_else(|| node_identity.public_key()))?;
+
+        let mut storage = self.peer_storage.write().await;
+        let mut result = PeerSnapshotImportResult::default();
+        for entry in snapshot.entries {
+            match storage.find_by_public_key(&entry.public_key) {
+                Ok(mut peer) => {
+                    if peer.is_banned() {
+                        result.num_skipped += 1;
+                        continue;
+                    }
+                    let num_addresses = peer.addresses.len();
+                    for address in &entry.addresses {
+                        peer.addresses.add_net_address(address);
+                    }
+                    if peer.addresses.len() == num_addresses {
+                        result.num_skipped += 1;
+                        continue;
+                    }
+                    storage.add_peer(peer)?;
+                    result.num_updated += 1;
+                },
+                Err(err) if err.is_peer_not_found() => {
+                    let mut flags = PeerFlags::default();
+                    flags.set(PeerFlags::PINNED, entry.is_pinned);
+                    let peer = Peer::new(
+                        entry.public_key.clone(),
+                        NodeId::from_public_key(&entry.public_key),
+                        entry.addresses.into(),
+                        flags,
+                        entry.features,
+                        Vec::new(),
+                        String::new(),
+                    );
+                    storage.add_peer(peer)?;
+                    result.num_added += 1;
+                },
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Pin a peer so that it is never removed when pruning the peer database. Returns true if the peer was already
+    /// pinned.
+    pub async fn pin_peer(&self, node_id: &NodeId) -> Result<bool, PeerManagerError> {
+        self.peer_storage.write().await.set_pinned(node_id, true)
+    }
+
+    /// Unpin a peer so that it may be removed when pruning the peer database. Returns true if the peer was pinned.
+    pub async fn unpin_peer(&self, node_id: &NodeId) -> Result<bool, PeerManagerError> {
+        self.peer_storage.write().await.set_pinned(node_id, false)
+    }
+
+    /// Set the trust level of a peer. Returns the previous trust level of the peer.
+    pub async fn set_trust_level(
+        &self,
+        node_id: &NodeId,
+        trust_level: PeerTrustLevel,
+    ) -> Result<PeerTrustLevel, PeerManagerError>
+    {
+        self.peer_storage.write().await.set_trust_level(node_id, trust_level)
+    }
+
+    /// Remove the lowest-value peers so that at most `max_size` peers remain in the peer database. Pinned peers and
+    /// peers in `exclude` are never removed. Returns the number of peers that were removed.
+    pub async fn prune(&self, max_size: usize, exclude: &[NodeId]) -> Result<usize, PeerManagerError> {
+        self.peer_storage.write().await.prune(max_size, exclude)
+    }
+
     /// Adds a new net address to the peer if it doesn't yet exist
     pub async fn add_net_address(&self, node_id: &NodeId, net_address: &Multiaddr) -> Result<(), PeerManagerError> {
         self.peer_storage.write().await.add_net_address(node_id, net_address)
@@ -286,6 +572,29 @@ impl PeerManager {
         Ok(updated_count)
     }
 
+    /// Atomically commit all upserts and deletes in the batch. The batch is written to the peer database in a single
+    /// write transaction and concurrent readers see the peer database either as it was before the batch or with every
+    /// operation applied. If the write fails, nothing is changed. Returns the number of peers that were added,
+    /// replaced or removed.
+    pub async fn commit_batch(&self, batch: PeerWriteBatch) -> Result<usize, PeerManagerError> {
+        if batch.is_empty() {
+            return Ok(0);
+        }
+        self.peer_storage.write().await.apply_batch(batch)
+    }
+
+    /// Build and atomically commit a write batch while holding the peer database write lock. Reads made through the
+    /// `PeerBatchWriter` see the peer database as it was when the batch started, together with the operations already
+    /// queued in the batch. Nothing is written if `f` returns an error.
+    pub async fn write_batch<F>(&self, f: F) -> Result<usize, PeerManagerError>
+    where F: FnOnce(&mut PeerBatchWriter<'_, KeyValueWrapper<CommsDatabase>>) -> Result<(), PeerManagerError> {
+        let mut lock = self.peer_storage.write().await;
+        let mut writer = PeerBatchWriter::new(&*lock);
+        f(&mut writer)?;
+        let batch = writer.into_batch();
+        lock.apply_batch(batch)
+    }
+
     pub async fn get_peer_features(&self, node_id: &NodeId) -> Result<PeerFeatures, PeerManagerError> {
         let peer = self.find_by_node_id(node_id).await?;
         Ok(peer.features)
@@ -314,14 +623,12 @@ impl fmt::Debug for PeerManager {
 mod test {
     use super::*;
     use crate::{
-        net_address::MultiaddressesWithStats,
-        peer_manager::{
-            node_id::NodeId,
-            peer::{Peer, PeerFlags},
-            PeerFeatures,
-        },
+        peer_manager::{node_id::NodeId, BanListError},
         runtime,
+        test_utils::node_identity::build_node_identity,
+        utils::rng::SharedRng,
     };
+    use chrono::Utc;
     use rand::rngs::OsRng;
     use tari_crypto::{keys::PublicKey, ristretto::RistrettoPublicKey};
     use tari_storage::HashmapDatabase;
@@ -456,6 +763,17 @@ mod test {
         let identities1 = peer_manager.random_peers(10, &[]).await.unwrap();
         let identities2 = peer_manager.random_peers(10, &[]).await.unwrap();
         assert_ne!(identities1, identities2);
+
+        // A seeded RNG always selects the same peers
+        let identities1 = peer_manager
+            .random_peers_with_rng(10, &[], &mut SharedRng::from_seed(1))
+            .await
+            .unwrap();
+        let identities2 = peer_manager
+            .random_peers_with_rng(10, &[], &mut SharedRng::from_seed(1))
+            .await
+            .unwrap();
+        assert_eq!(identities1, identities2);
     }
 
     #[runtime::test_basic]
@@ -577,4 +895,235 @@ mod test {
         assert_eq!(peer.is_offline(), false);
         assert_eq!(peer.connection_stats.failed_attempts(), 0);
     }
+
+    #[runtime::test_basic]
+    async fn ban_peer_publishes_event() {
+        let peer_manager = PeerManager::new(HashmapDatabase::new(), None).unwrap();
+        let peer = create_test_peer(false, PeerFeatures::COMMUNICATION_NODE);
+        peer_manager.add_peer(peer.clone()).await.unwrap();
+        let mut events = peer_manager.get_event_subscription();
+
+        peer_manager
+            .ban_peer(&peer.public_key, Duration::from_secs(100), "Test".to_string())
+            .await
+            .unwrap();
+        let event = events.try_recv().unwrap();
+        assert_eq!(*event, PeerManagerEvent::PeerBanned(peer.node_id.clone()));
+
+        peer_manager
+            .ban_peer_by_node_id(&peer.node_id, Duration::from_secs(100), "Test".to_string())
+            .await
+            .unwrap();
+        let event = events.try_recv().unwrap();
+        assert_eq!(*event, PeerManagerEvent::PeerBanned(peer.node_id.clone()));
+    }
+
+    #[runtime::test_basic]
+    async fn export_and_import_ban_list() {
+        let peer_manager = PeerManager::new(HashmapDatabase::new(), None).unwrap();
+        let banned_peer1 = create_test_peer(true, PeerFeatures::COMMUNICATION_NODE);
+        let banned_peer2 = create_test_peer(true, PeerFeatures::COMMUNICATION_NODE);
+        let peer = create_test_peer(false, PeerFeatures::COMMUNICATION_NODE);
+        peer_manager.add_peer(banned_peer1.clone()).await.unwrap();
+        peer_manager.add_peer(banned_peer2.clone()).await.unwrap();
+        peer_manager.add_peer(peer.clone()).await.unwrap();
+
+        let mut ban_list = peer_manager.export_ban_list().await.unwrap();
+        assert_eq!(ban_list.entries.len(), 2);
+        assert!(ban_list
+            .entries
+            .iter()
+            .all(|e| e.public_key == banned_peer1.public_key || e.public_key == banned_peer2.public_key));
+        let publisher = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        ban_list.sign(&publisher).unwrap();
+        let trusted_publishers = [publisher.public_key().clone()];
+
+        // The importing node knows banned_peer1 and has banned it for longer than the imported ban
+        let other_peer_manager = PeerManager::new(HashmapDatabase::new(), None).unwrap();
+        let mut local_ban = banned_peer1.clone();
+        local_ban.ban_for(Duration::from_secs(10_000), "Local ban".to_string());
+        other_peer_manager.add_peer(local_ban).await.unwrap();
+        let mut events = other_peer_manager.get_event_subscription();
+
+        // A list signed by a publisher that is not trusted is rejected
+        let err = other_peer_manager.import_ban_list(&ban_list, &[]).await.unwrap_err();
+        assert!(matches!(
+            err,
+            PeerManagerError::BanListError(BanListError::UntrustedPublisher(_))
+        ));
+
+        let result = other_peer_manager
+            .import_ban_list(&ban_list, &trusted_publishers)
+            .await
+            .unwrap();
+        assert_eq!(result.num_added, 1);
+        assert_eq!(result.num_updated, 0);
+        assert_eq!(result.num_skipped, 1);
+        // Only the newly banned peer is published
+        let event = events.try_recv().unwrap();
+        assert_eq!(*event, PeerManagerEvent::PeerBanned(banned_peer2.node_id.clone()));
+        assert!(events.try_recv().is_err());
+
+        let peer1 = other_peer_manager
+            .find_by_public_key(&banned_peer1.public_key)
+            .await
+            .unwrap();
+        assert_eq!(peer1.reason_banned(), "Local ban");
+        let peer2 = other_peer_manager
+            .find_by_public_key(&banned_peer2.public_key)
+            .await
+            .unwrap();
+        assert!(peer2.is_banned());
+        assert!(peer2.banned_until().unwrap() > &Utc::now().naive_utc());
+
+        // A tampered list is rejected
+        ban_list.entries[0].reason = "Tampered".to_string();
+        let err = other_peer_manager
+            .import_ban_list(&ban_list, &trusted_publishers)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PeerManagerError::BanListError(BanListError::InvalidSignature)
+        ));
+    }
+
+    #[runtime::test_basic]
+    async fn export_and_import_peers() {
+        let peer_manager = PeerManager::new(HashmapDatabase::new(), None).unwrap();
+        let banned_peer = create_test_peer(true, PeerFeatures::COMMUNICATION_NODE);
+        let peer1 = create_test_peer(false, PeerFeatures::COMMUNICATION_NODE);
+        let peer2 = create_test_peer(false, PeerFeatures::COMMUNICATION_CLIENT);
+        peer_manager.add_peer(banned_peer.clone()).await.unwrap();
+        peer_manager.add_peer(peer1.clone()).await.unwrap();
+        peer_manager.add_peer(peer2.clone()).await.unwrap();
+        peer_manager.pin_peer(&peer1.node_id).await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peers.json");
+        let node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        let num_exported = peer_manager.export_peers(&path, &node_identity).await.unwrap();
+        assert_eq!(num_exported, 2);
+
+        // The importing node already knows peer2 by another address
+        let other_peer_manager = PeerManager::new(HashmapDatabase::new(), None).unwrap();
+        let mut known_peer = peer2.clone();
+        known_peer.addresses = "/ip4/5.6.7.8/tcp/8000".parse::<Multiaddr>().unwrap().into();
+        other_peer_manager.add_peer(known_peer).await.unwrap();
+
+        // Snapshots signed by another node are rejected unless that node is the expected signer
+        let other_node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        let err = other_peer_manager
+            .import_peers(&path, &other_node_identity, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PeerManagerError::PeerSnapshotError(PeerSnapshotError::UnexpectedSigner(_))
+        ));
+        let err = other_peer_manager
+            .import_peers(&path, &node_identity, Some(other_node_identity.public_key()))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PeerManagerError::PeerSnapshotError(PeerSnapshotError::UnexpectedSigner(_))
+        ));
+
+        let result = other_peer_manager
+            .import_peers(&path, &node_identity, None)
+            .await
+            .unwrap();
+        assert_eq!(result.num_added, 1);
+        assert_eq!(result.num_updated, 1);
+        assert_eq!(result.num_skipped, 0);
+
+        assert!(!other_peer_manager.exists(&banned_peer.public_key).await);
+        let peer = other_peer_manager.find_by_node_id(&peer1.node_id).await.unwrap();
+        assert!(peer.is_pinned());
+        assert_eq!(peer.features, PeerFeatures::COMMUNICATION_NODE);
+        let peer = other_peer_manager.find_by_node_id(&peer2.node_id).await.unwrap();
+        assert_eq!(peer.addresses.len(), 2);
+
+        // Importing the same snapshot again learns nothing new
+        let result = other_peer_manager
+            .import_peers(&path, &other_node_identity, Some(node_identity.public_key()))
+            .await
+            .unwrap();
+        assert_eq!(result.num_added, 0);
+        assert_eq!(result.num_updated, 0);
+        assert_eq!(result.num_skipped, 2);
+
+        // A tampered snapshot is rejected
+        let json = std::fs::read_to_string(&path).unwrap();
+        let mut snapshot = PeerSnapshot::from_json(&json).unwrap();
+        snapshot.entries[0].is_pinned = !snapshot.entries[0].is_pinned;
+        std::fs::write(&path, snapshot.to_json().unwrap()).unwrap();
+        let err = other_peer_manager
+            .import_peers(&path, &node_identity, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PeerManagerError::PeerSnapshotError(PeerSnapshotError::InvalidSignature)
+        ));
+    }
+
+    #[runtime::test_basic]
+    async fn write_batch() {
+        let peer_manager = PeerManager::new(HashmapDatabase::new(), None).unwrap();
+        let peer1 = create_test_peer(false, PeerFeatures::COMMUNICATION_NODE);
+        let peer2 = create_test_peer(false, PeerFeatures::COMMUNICATION_NODE);
+        let peer3 = create_test_peer(false, PeerFeatures::COMMUNICATION_CLIENT);
+        peer_manager.add_peer(peer1.clone()).await.unwrap();
+
+        let mut batch = PeerWriteBatch::new();
+        batch
+            .upsert(peer2.clone())
+            .upsert(peer3.clone())
+            .delete(peer1.node_id.clone());
+        // Deleting an unknown peer is a no-op
+        batch.delete(create_test_peer(false, PeerFeatures::COMMUNICATION_NODE).node_id);
+        let num_changed = peer_manager.commit_batch(batch).await.unwrap();
+        assert_eq!(num_changed, 3);
+        assert_eq!(peer_manager.count().await, 2);
+        assert!(!peer_manager.exists_node_id(&peer1.node_id).await);
+
+        let num_changed = peer_manager
+            .write_batch(|writer| {
+                // Reads see the operations already queued in the batch
+                writer.delete(peer2.node_id.clone());
+                assert!(!writer.exists_node_id(&peer2.node_id));
+                assert!(writer
+                    .find_by_public_key(&peer2.public_key)
+                    .unwrap_err()
+                    .is_peer_not_found());
+
+                let mut peer = writer.find_by_node_id(&peer3.node_id)?;
+                peer.features = PeerFeatures::COMMUNICATION_NODE;
+                writer.upsert(peer);
+                assert_eq!(
+                    writer.find_by_node_id(&peer3.node_id)?.features,
+                    PeerFeatures::COMMUNICATION_NODE
+                );
+                Ok(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(num_changed, 2);
+        assert_eq!(peer_manager.count().await, 1);
+        let peer = peer_manager.find_by_node_id(&peer3.node_id).await.unwrap();
+        assert_eq!(peer.features, PeerFeatures::COMMUNICATION_NODE);
+
+        // Nothing is written if the batch closure fails
+        let err = peer_manager
+            .write_batch(|writer| {
+                writer.delete(peer3.node_id.clone());
+                Err(PeerManagerError::PeerNotFoundError)
+            })
+            .await
+            .unwrap_err();
+        assert!(err.is_peer_not_found());
+        assert!(peer_manager.exists_node_id(&peer3.node_id).await);
+    }
 }
@@ -23,6 +23,10 @@
 mod v1;
 mod v2;
 mod v3;
+mod v4;
+mod v5;
+mod v6;
+mod v7;
 
 use log::*;
 use tari_storage::lmdb_store::{LMDBDatabase, LMDBError};
@@ -36,6 +40,10 @@ pub fn migrate(database: &LMDBDatabase) -> Result<(), LMDBError> {
         v1::MigrationV1.boxed(),
         v2::MigrationV2.boxed(),
         v3::MigrationV3.boxed(),
+        v4::MigrationV4.boxed(),
+        v5::MigrationV5.boxed(),
+        v6::MigrationV6.boxed(),
+        v7::MigrationV7.boxed(),
     ];
 
     // If the database is empty there is nothing to migrate, so set it to the latest version
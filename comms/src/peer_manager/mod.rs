@@ -70,6 +70,9 @@
 //! let returned_peer = peer_manager.find_by_node_id(&node_id).unwrap();
 //! ```
 
+mod ban_list;
+pub use ban_list::{BanList, BanListEntry, BanListError, BanListImportResult, BanListPublisher, BAN_LIST_VERSION};
+
 mod connection_stats;
 
 mod error;
@@ -87,18 +90,49 @@ pub use peer::{Peer, PeerFlags};
 mod peer_features;
 pub use peer_features::PeerFeatures;
 
+mod peer_trust_level;
+pub use peer_trust_level::{PeerTrustLevel, PeerTrustLevelParseError};
+
+mod peer_info;
+pub use peer_info::{PeerAddressInfo, PeerInfo};
+
 mod peer_id;
 pub use peer_id::PeerId;
 
 mod manager;
-pub use manager::PeerManager;
+pub use manager::{PeerManager, PeerManagerEvent, PeerManagerEventRx, PeerManagerEventTx};
+
+mod peer_snapshot;
+pub use peer_snapshot::{
+    PeerSnapshot,
+    PeerSnapshotEntry,
+    PeerSnapshotError,
+    PeerSnapshotImportResult,
+    PeerSnapshotSigner,
+    PEER_SNAPSHOT_VERSION,
+};
+
+pub mod region;
+pub mod routing_vectors;
 
 mod peer_query;
 pub use peer_query::{PeerQuery, PeerQuerySortBy};
 
+mod peer_cache;
+
+mod distance_index;
+
+mod known_ip_index;
+
 mod peer_storage;
 pub use peer_storage::PeerStorage;
 
+mod integrity;
+pub use integrity::{PeerDbIntegrityReport, PeerDbRepairPolicy};
+
 mod migrations;
 
 mod wrapper;
+
+mod write_batch;
+pub use write_batch::{PeerBatchWriter, PeerWriteBatch, PeerWriteOp};
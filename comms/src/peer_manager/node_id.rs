@@ -29,6 +29,7 @@ use serde::{de, Deserialize, Deserializer, Serialize};
 use std::{
     cmp,
     cmp::Ordering,
+    collections::BinaryHeap,
     convert::{TryFrom, TryInto},
     fmt,
     hash::{Hash, Hasher},
@@ -58,7 +59,7 @@ pub enum NodeIdError {
 const NODE_XOR_DISTANCE_ARRAY_SIZE: usize = NODE_ID_ARRAY_SIZE;
 type NodeXorDistanceArray = [u8; NODE_XOR_DISTANCE_ARRAY_SIZE];
 
-#[derive(Clone, Debug, Eq, PartialOrd, Ord, Default)]
+#[derive(Clone, Debug, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
 pub struct XorDistance(NodeXorDistanceArray);
 
 impl XorDistance {
@@ -128,7 +129,7 @@ const NODE_HAMMING_DISTANCE_ARRAY_SIZE: usize = 1;
 type NodeHammingDistanceArray = [u8; NODE_HAMMING_DISTANCE_ARRAY_SIZE];
 
 /// Hold the distance calculated between two NodeId's. This is used for DHT-style routing.
-#[derive(Clone, Debug, Eq, PartialOrd, Ord, Default)]
+#[derive(Clone, Debug, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
 pub struct HammingDistance(NodeHammingDistanceArray);
 
 impl HammingDistance {
@@ -173,12 +174,22 @@ impl PartialEq for HammingDistance {
 /// Calculate the Exclusive OR between the node_id x and y.
 fn xor(x: &NodeIdArray, y: &NodeIdArray) -> NodeIdArray {
     let mut nd = [0u8; NODE_ID_ARRAY_SIZE];
-    for i in 0..nd.len() {
-        nd[i] = x[i] ^ y[i];
+    // Iterating over fixed-size arrays without indexing removes bounds checks and allows this to be vectorised
+    for (d, (a, b)) in nd.iter_mut().zip(x.iter().zip(y.iter())) {
+        *d = a ^ b;
     }
     nd
 }
 
+/// Interpret the node id bytes as a big-endian integer. The bytes occupy the most significant end of the integer, so
+/// integers compare in the same order as the byte arrays they were created from.
+#[inline]
+fn to_u128(bytes: &NodeIdArray) -> u128 {
+    let mut buf = [0u8; 16];
+    buf[..NODE_ID_ARRAY_SIZE].copy_from_slice(bytes);
+    u128::from_be_bytes(buf)
+}
+
 /// Calculate the hamming distance (the number of set (1) bits of the XOR metric)
 fn hamming_distance(nd: NodeIdArray) -> u8 {
     let xor_bytes = &nd;
@@ -250,37 +261,38 @@ impl NodeId {
         NodeDistance::from_node_ids(&self, &node_id)
     }
 
+    /// Calculate the XOR distance between the current node id and the provided node id as an integer. Distance keys
+    /// order in exactly the same way as XOR `NodeDistance`s, but are cheaper to compute and compare (a single wide
+    /// XOR and a branch-free integer comparison instead of a byte-by-byte comparison). This should be used when
+    /// ordering many node ids by distance.
+    #[inline]
+    pub fn distance_key(&self, node_id: &NodeId) -> u128 {
+        to_u128(&self.0) ^ to_u128(&node_id.0)
+    }
+
     /// Find and return the indices of the K nearest neighbours from the provided node id list
     pub fn closest_indices(&self, node_ids: &[NodeId], k: usize) -> Vec<usize> {
         let k = cmp::min(k, node_ids.len());
-        let mut indices: Vec<usize> = Vec::with_capacity(node_ids.len());
-        let mut dists: Vec<NodeDistance> = Vec::with_capacity(node_ids.len());
-        for (i, node_id) in node_ids.iter().enumerate() {
-            indices.push(i);
-            dists.push(self.distance(node_id))
+        if k == 0 {
+            return Vec::new();
         }
-        // Perform partial sort of elements only up to K elements
-        let mut nearest_node_indices: Vec<usize> = Vec::with_capacity(k);
-        for i in 0..k {
-            for j in i + 1..node_ids.len() {
-                if dists[i] > dists[j] {
-                    dists.swap(i, j);
-                    indices.swap(i, j);
-                }
+        // Keep the K nearest elements in a max-heap so that only O(n log k) comparisons are needed
+        let mut nearest = BinaryHeap::with_capacity(k + 1);
+        for (i, node_id) in node_ids.iter().enumerate() {
+            nearest.push((self.distance_key(node_id), i));
+            if nearest.len() > k {
+                nearest.pop();
             }
-            nearest_node_indices.push(indices[i]);
         }
-        nearest_node_indices
+        nearest.into_sorted_vec().into_iter().map(|(_, i)| i).collect()
     }
 
     /// Find and return the node ids of the K nearest neighbours from the provided node id list
     pub fn closest(&self, node_ids: &[NodeId], k: usize) -> Vec<NodeId> {
-        let nearest_node_indices = self.closest_indices(&node_ids.to_vec(), k);
-        let mut nearest_node_ids: Vec<NodeId> = Vec::with_capacity(nearest_node_indices.len());
-        for nearest in nearest_node_indices {
-            nearest_node_ids.push(node_ids[nearest].clone());
-        }
-        nearest_node_ids
+        self.closest_indices(node_ids, k)
+            .into_iter()
+            .map(|nearest| node_ids[nearest].clone())
+            .collect()
     }
 
     pub fn into_inner(self) -> NodeIdArray {
@@ -404,6 +416,7 @@ where D: Deserializer<'de> {
 mod test {
     use super::*;
     use crate::types::{CommsPublicKey, CommsSecretKey};
+    use rand::rngs::OsRng;
     use tari_crypto::{
         keys::{PublicKey, SecretKey},
         tari_utilities::byte_array::ByteArray,
@@ -615,4 +628,27 @@ mod test {
         assert_eq!(n12_distance, 56114865924689668092413877285545836544);
         assert_eq!(n13_distance, 228941924089749863963604860508980641792);
     }
+
+    #[test]
+    fn distance_key_orders_as_distance() {
+        let node_ids = (0..100)
+            .map(|_| {
+                let (_, pk) = CommsPublicKey::random_keypair(&mut OsRng);
+                NodeId::from_key(&pk).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let node_id = &node_ids[0];
+        for (a, b) in node_ids.iter().zip(node_ids.iter().skip(1)) {
+            assert_eq!(
+                node_id.distance_key(a).cmp(&node_id.distance_key(b)),
+                node_id.distance(a).cmp(&node_id.distance(b))
+            );
+            assert_eq!(node_id.distance_key(a), u128::try_from(node_id.distance(a)).unwrap());
+        }
+
+        let mut expected = node_ids.clone();
+        expected.sort_by_key(|n| node_id.distance(n));
+        assert_eq!(node_id.closest(&node_ids, 10), expected[..10].to_vec());
+        assert_eq!(node_id.closest(&node_ids, 0), Vec::<NodeId>::new());
+    }
 }
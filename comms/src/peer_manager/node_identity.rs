@@ -33,7 +33,7 @@ use crate::{
 use multiaddr::Multiaddr;
 use rand::{CryptoRng, Rng};
 use serde::{Deserialize, Serialize};
-use std::{fmt, sync::RwLock};
+use std::{collections::BTreeMap, fmt, sync::RwLock};
 use tari_crypto::{
     keys::{PublicKey, SecretKey},
     tari_utilities::hex::serialize_to_hex,
@@ -56,6 +56,14 @@ pub struct NodeIdentity {
     features: PeerFeatures,
     secret_key: CommsSecretKey,
     public_address: RwLock<Multiaddr>,
+    /// Additional addresses advertised to peers, such as the addresses of auxiliary listeners. These are set at
+    /// runtime and are not persisted.
+    #[serde(skip)]
+    auxiliary_addresses: RwLock<Vec<Multiaddr>>,
+    /// Metadata advertised to peers in the identity exchange, such as the capabilities of the services this node
+    /// offers. This is set by the services at runtime and is not persisted.
+    #[serde(skip)]
+    metadata: RwLock<BTreeMap<u8, Vec<u8>>>,
 }
 
 impl NodeIdentity {
@@ -75,6 +83,8 @@ impl NodeIdentity {
             features,
             secret_key,
             public_address: RwLock::new(public_address),
+            auxiliary_addresses: Default::default(),
+            metadata: Default::default(),
         })
     }
 
@@ -97,6 +107,8 @@ impl NodeIdentity {
             features,
             secret_key,
             public_address: RwLock::new(public_address),
+            auxiliary_addresses: Default::default(),
+            metadata: Default::default(),
         })
     }
 
@@ -110,6 +122,23 @@ impl NodeIdentity {
         *acquire_write_lock!(self.public_address) = address;
     }
 
+    /// Returns the additional addresses advertised to peers
+    pub fn auxiliary_addresses(&self) -> Vec<Multiaddr> {
+        acquire_read_lock!(self.auxiliary_addresses).clone()
+    }
+
+    /// Set the additional addresses advertised to peers, replacing any existing auxiliary addresses
+    pub fn set_auxiliary_addresses(&self, addresses: Vec<Multiaddr>) {
+        *acquire_write_lock!(self.auxiliary_addresses) = addresses;
+    }
+
+    /// Returns the public address followed by the auxiliary addresses. These are the addresses advertised to peers.
+    pub fn public_addresses(&self) -> Vec<Multiaddr> {
+        let mut addresses = vec![self.public_address()];
+        addresses.extend(self.auxiliary_addresses());
+        addresses
+    }
+
     /// This returns a random NodeIdentity for testing purposes. This function can panic. If public_address
     /// is None, 127.0.0.1:9000 will be used (i.e. the caller doesn't care what the control_service_address is).
     #[cfg(test)]
@@ -149,13 +178,28 @@ impl NodeIdentity {
         self.features().contains(peer_features)
     }
 
-    /// Returns a Peer with the same public key, node id, public address and features as represented in this
+    /// Set a metadata value that is advertised to peers, replacing any existing value for the key
+    pub fn set_metadata(&self, key: u8, value: Vec<u8>) {
+        acquire_write_lock!(self.metadata).insert(key, value);
+    }
+
+    /// Stop advertising the metadata value for the given key, returning the previous value if any
+    pub fn remove_metadata(&self, key: u8) -> Option<Vec<u8>> {
+        acquire_write_lock!(self.metadata).remove(&key)
+    }
+
+    /// Returns the metadata advertised to peers, ordered by key
+    pub fn metadata(&self) -> BTreeMap<u8, Vec<u8>> {
+        acquire_read_lock!(self.metadata).clone()
+    }
+
+    /// Returns a Peer with the same public key, node id, public addresses and features as represented in this
     /// NodeIdentity. _NOTE: PeerFlags, supported_protocols and user agent are empty._
     pub fn to_peer(&self) -> Peer {
         Peer::new(
             self.public_key().clone(),
             self.node_id().clone(),
-            self.public_address().into(),
+            self.public_addresses().into(),
             PeerFlags::empty(),
             self.features(),
             Default::default(),
@@ -172,6 +216,8 @@ impl Clone for NodeIdentity {
             features: self.features,
             secret_key: self.secret_key.clone(),
             public_address: RwLock::new(self.public_address()),
+            auxiliary_addresses: RwLock::new(self.auxiliary_addresses()),
+            metadata: RwLock::new(self.metadata()),
         }
     }
 }
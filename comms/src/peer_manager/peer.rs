@@ -25,6 +25,7 @@ use super::{
     node_id::{deserialize_node_id_from_hex, NodeId},
     peer_id::PeerId,
     PeerFeatures,
+    PeerTrustLevel,
 };
 use crate::{
     consts::PEER_OFFLINE_COOLDOWN_PERIOD,
@@ -39,16 +40,24 @@ use multiaddr::Multiaddr;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
+    convert::TryFrom,
     fmt::Display,
     hash::{Hash, Hasher},
     time::Duration,
 };
 use tari_crypto::tari_utilities::hex::serialize_to_hex;
 
+/// The maximum number of metadata entries accepted from a peer's advertisement
+const MAX_ADVERTISED_METADATA_ENTRIES: usize = 16;
+/// The maximum size in bytes of a single advertised metadata value
+const MAX_ADVERTISED_METADATA_VALUE_SIZE: usize = 1024;
+
 bitflags! {
     #[derive(Default, Deserialize, Serialize)]
     pub struct PeerFlags: u8 {
         const NONE = 0x00;
+        /// The peer is pinned (e.g. a seed or manually-added peer) and is never removed when pruning the peer database
+        const PINNED = 0x01;
     }
 }
 
@@ -92,6 +101,21 @@ pub struct Peer {
     /// Metadata field. This field is for use by upstream clients to record extra info about a peer.
     /// We use a hashmap here so that we can use more than one "info set"
     pub metadata: HashMap<u8, Vec<u8>>,
+    /// Bitmap of the comms protocol versions advertised by the peer in the identity exchange. Bit n is set if version
+    /// n is supported.
+    #[serde(default)]
+    pub protocol_versions: u32,
+    /// The difference in seconds between the peer's clock and ours, estimated from the timestamp the peer sent in the
+    /// last identity exchange. Positive if the peer's clock is ahead of ours.
+    #[serde(default)]
+    pub clock_skew: Option<i64>,
+    /// The level of trust this node places in the peer
+    #[serde(default)]
+    pub trust_level: PeerTrustLevel,
+    /// The timestamp of the most recent signed identity from which this peer's addresses were applied. Address updates
+    /// relayed by other nodes are only applied if they are strictly newer, so that older updates cannot be replayed.
+    #[serde(default)]
+    pub identity_timestamp: Option<NaiveDateTime>,
 }
 
 impl Peer {
@@ -121,6 +145,10 @@ impl Peer {
             supported_protocols,
             user_agent,
             metadata: HashMap::new(),
+            protocol_versions: 0,
+            clock_skew: None,
+            trust_level: Default::default(),
+            identity_timestamp: None,
         }
     }
 
@@ -228,6 +256,30 @@ impl Peer {
         self.features.contains(features)
     }
 
+    /// Returns true if the peer is pinned, otherwise false
+    pub fn is_pinned(&self) -> bool {
+        self.flags.contains(PeerFlags::PINNED)
+    }
+
+    /// Pin or unpin the peer. Pinned peers are never removed when pruning the peer database.
+    pub fn set_pinned(&mut self, is_pinned: bool) {
+        self.flags.set(PeerFlags::PINNED, is_pinned);
+    }
+
+    /// Returns the level of trust this node places in the peer
+    pub fn trust_level(&self) -> PeerTrustLevel {
+        self.trust_level
+    }
+
+    pub fn set_trust_level(&mut self, trust_level: PeerTrustLevel) {
+        self.trust_level = trust_level;
+    }
+
+    /// Returns true if the peer advertised support for the given comms protocol version
+    pub fn supports_protocol_version(&self, version: u8) -> bool {
+        version < 32 && self.protocol_versions & (1 << version) != 0
+    }
+
     /// Returns the ban status of the peer
     pub fn is_banned(&self) -> bool {
         self.banned_until().is_some()
@@ -245,6 +297,12 @@ impl Peer {
         self.banned_reason = reason;
     }
 
+    /// Bans the peer until the given time (UTC)
+    pub fn ban_until(&mut self, until: NaiveDateTime, reason: String) {
+        self.banned_until = Some(until);
+        self.banned_reason = reason;
+    }
+
     /// Unban the peer
     pub fn unban(&mut self) {
         self.banned_until = None;
@@ -275,6 +333,20 @@ impl Peer {
         self.metadata.get(&key)
     }
 
+    /// Store the metadata advertised by the peer (e.g. in the identity exchange). Entries with a key that does not fit
+    /// in a u8 or with a value larger than 1 KiB are ignored, as are any entries after the first 16.
+    pub fn set_advertised_metadata<I>(&mut self, entries: I)
+    where I: IntoIterator<Item = (u32, Vec<u8>)> {
+        let entries = entries
+            .into_iter()
+            .filter(|(_, value)| value.len() <= MAX_ADVERTISED_METADATA_VALUE_SIZE)
+            .filter_map(|(key, value)| u8::try_from(key).ok().map(|key| (key, value)))
+            .take(MAX_ADVERTISED_METADATA_ENTRIES);
+        for (key, value) in entries {
+            self.set_metadata(key, value);
+        }
+    }
+
     pub fn to_short_string(&self) -> String {
         format!(
             "{}::{}",
@@ -308,6 +380,10 @@ impl Display for Peer {
                 s.push(format!("Banned until: {}", dt));
                 s.push(format!("Reason: {}", self.banned_reason))
             }
+
+            if self.trust_level != PeerTrustLevel::Normal {
+                s.push(format!("Trust level: {}", self.trust_level));
+            }
             s.join(". ")
         };
 
@@ -407,6 +483,27 @@ mod test {
         assert_eq!(peer.is_offline(), true);
     }
 
+    #[test]
+    fn test_supports_protocol_version() {
+        let mut peer = build_node_identity(Default::default()).to_peer();
+        assert!(!peer.supports_protocol_version(0));
+        peer.protocol_versions = 0b10;
+        assert!(!peer.supports_protocol_version(0));
+        assert!(peer.supports_protocol_version(1));
+        assert!(!peer.supports_protocol_version(32));
+    }
+
+    #[test]
+    fn set_advertised_metadata() {
+        let mut peer = build_node_identity(Default::default()).to_peer();
+        peer.set_advertised_metadata(vec![(1, vec![1]), (256, vec![2]), (3, vec![0; 1025])]);
+        assert_eq!(peer.get_metadata(1), Some(&vec![1]));
+        assert_eq!(peer.metadata.len(), 1);
+
+        peer.set_advertised_metadata((0..20).map(|key| (key, vec![])));
+        assert_eq!(peer.metadata.len(), 16);
+    }
+
     #[test]
     fn test_update() {
         let mut rng = rand::rngs::OsRng;
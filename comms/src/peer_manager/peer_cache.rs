@@ -0,0 +1,143 @@
+//  Copyright 2020 The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::peer_manager::{Peer, PeerId};
+use std::{collections::HashMap, mem};
+
+/// A bounded in-memory cache of peers keyed by `PeerId`. When the cache is full, the least recently accessed peer is
+/// evicted to make space for a new entry.
+#[derive(Debug)]
+pub(super) struct PeerCache {
+    capacity: usize,
+    entries: HashMap<PeerId, CacheEntry>,
+    access_counter: u64,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    peer: Peer,
+    last_access: u64,
+}
+
+impl PeerCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            access_counter: 0,
+        }
+    }
+
+    /// Returns a copy of the cached peer, if it exists, and marks it as recently used
+    pub fn get(&mut self, peer_key: &PeerId) -> Option<Peer> {
+        let access = self.next_access();
+        self.entries.get_mut(peer_key).map(|entry| {
+            entry.last_access = access;
+            entry.peer.clone()
+        })
+    }
+
+    /// Inserts a peer into the cache, evicting the least recently used peer if the cache is full
+    pub fn insert(&mut self, peer_key: PeerId, peer: Peer) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&peer_key) && self.entries.len() >= self.capacity {
+            self.evict_lru();
+        }
+        let last_access = self.next_access();
+        self.entries.insert(peer_key, CacheEntry { peer, last_access });
+    }
+
+    /// Removes the peer from the cache. This must be called whenever the persisted peer is changed or removed.
+    pub fn invalidate(&mut self, peer_key: &PeerId) {
+        self.entries.remove(peer_key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the approximate number of bytes allocated for the cache entries, excluding heap allocations owned by
+    /// each peer
+    pub fn approx_memory_usage(&self) -> usize {
+        self.entries.capacity() * (mem::size_of::<PeerId>() + mem::size_of::<CacheEntry>())
+    }
+
+    fn evict_lru(&mut self) {
+        let lru_key = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_access)
+            .map(|(key, _)| *key);
+        if let Some(key) = lru_key {
+            self.entries.remove(&key);
+        }
+    }
+
+    fn next_access(&mut self) -> u64 {
+        self.access_counter += 1;
+        self.access_counter
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{peer_manager::PeerFeatures, test_utils::node_identity::build_node_identity};
+
+    fn make_peer() -> Peer {
+        build_node_identity(PeerFeatures::COMMUNICATION_NODE).to_peer()
+    }
+
+    #[test]
+    fn insert_get_invalidate() {
+        let mut cache = PeerCache::new(2);
+        let peer = make_peer();
+        cache.insert(1, peer.clone());
+        assert_eq!(cache.get(&1).unwrap().node_id, peer.node_id);
+        cache.invalidate(&1);
+        assert!(cache.get(&1).is_none());
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = PeerCache::new(2);
+        cache.insert(1, make_peer());
+        cache.insert(2, make_peer());
+        // Access peer 1 so that peer 2 becomes the least recently used
+        assert!(cache.get(&1).is_some());
+        cache.insert(3, make_peer());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&1).is_some());
+        assert!(cache.get(&2).is_none());
+        assert!(cache.get(&3).is_some());
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mut cache = PeerCache::new(0);
+        cache.insert(1, make_peer());
+        assert_eq!(cache.len(), 0);
+    }
+}
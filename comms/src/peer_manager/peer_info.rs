@@ -0,0 +1,171 @@
+//  Copyright 2019 The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    net_address::MutliaddrWithStats,
+    peer_manager::{NodeId, Peer, PeerFeatures, PeerTrustLevel},
+    types::CommsPublicKey,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use multiaddr::Multiaddr;
+use std::time::Duration;
+
+/// A snapshot of everything the peer manager knows about a peer, suitable for display in status UIs and admin tools.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub node_id: NodeId,
+    pub public_key: CommsPublicKey,
+    /// Known addresses for the peer, ordered from most to least preferred
+    pub addresses: Vec<PeerAddressInfo>,
+    pub features: PeerFeatures,
+    pub user_agent: String,
+    pub supported_protocols: Vec<String>,
+    pub protocol_versions: u32,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub last_connected_at: Option<NaiveDateTime>,
+    pub failed_connection_attempts: usize,
+    pub added_at: NaiveDateTime,
+    pub offline_at: Option<NaiveDateTime>,
+    /// Set if the peer is currently banned
+    pub banned_until: Option<NaiveDateTime>,
+    pub banned_reason: Option<String>,
+    pub is_pinned: bool,
+    pub clock_skew: Option<i64>,
+    pub trust_level: PeerTrustLevel,
+}
+
+impl PeerInfo {
+    pub fn is_banned(&self) -> bool {
+        self.banned_until.is_some()
+    }
+
+    /// The lowest average latency over all addresses that have latency samples, or None if no latency has been measured
+    pub fn best_latency(&self) -> Option<Duration> {
+        self.addresses.iter().filter_map(|a| a.avg_latency).min()
+    }
+}
+
+impl From<&Peer> for PeerInfo {
+    fn from(peer: &Peer) -> Self {
+        let banned_until = peer.banned_until().copied();
+        Self {
+            node_id: peer.node_id.clone(),
+            public_key: peer.public_key.clone(),
+            addresses: peer
+                .addresses
+                .addresses
+                .iter()
+                .enumerate()
+                .map(|(rank, addr)| PeerAddressInfo::new(rank, addr))
+                .collect(),
+            features: peer.features,
+            user_agent: peer.user_agent.clone(),
+            supported_protocols: peer
+                .supported_protocols
+                .iter()
+                .map(|p| String::from_utf8_lossy(p).to_string())
+                .collect(),
+            protocol_versions: peer.protocol_versions,
+            last_seen: peer.last_seen(),
+            last_connected_at: peer.connection_stats.last_connected_at,
+            failed_connection_attempts: peer.connection_stats.failed_attempts(),
+            added_at: peer.added_at,
+            offline_at: peer.offline_at,
+            banned_reason: banned_until.map(|_| peer.banned_reason.clone()),
+            banned_until,
+            is_pinned: peer.is_pinned(),
+            clock_skew: peer.clock_skew,
+            trust_level: peer.trust_level,
+        }
+    }
+}
+
+/// Address statistics for a peer address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerAddressInfo {
+    pub address: Multiaddr,
+    /// The position of this address in the peer's address preference order. 0 is the address that will be dialed
+    /// first.
+    pub rank: usize,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub connection_attempts: u32,
+    pub rejected_message_count: u32,
+    /// The average measured latency for this address, or None if it has never been measured
+    pub avg_latency: Option<Duration>,
+    pub latency_sample_count: u32,
+}
+
+impl PeerAddressInfo {
+    fn new(rank: usize, addr: &MutliaddrWithStats) -> Self {
+        let latency_sample_count = addr.latency_sample_count();
+        Self {
+            address: addr.address.clone(),
+            rank,
+            last_seen: addr.last_seen,
+            connection_attempts: addr.connection_attempts,
+            rejected_message_count: addr.rejected_message_count,
+            avg_latency: Some(addr.avg_latency).filter(|_| latency_sample_count > 0),
+            latency_sample_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{net_address::MultiaddressesWithStats, peer_manager::PeerFlags};
+    use tari_crypto::{keys::PublicKey, ristretto::RistrettoPublicKey};
+
+    #[test]
+    fn from_peer() {
+        let (_, pk) = RistrettoPublicKey::random_keypair(&mut rand::rngs::OsRng);
+        let node_id = NodeId::from_key(&pk).unwrap();
+        let mut addresses = MultiaddressesWithStats::from("/ip4/1.2.3.4/tcp/8000".parse::<Multiaddr>().unwrap());
+        addresses.add_net_address(&"/ip4/5.6.7.8/tcp/8000".parse().unwrap());
+        let address = addresses.addresses[1].address.clone();
+        addresses.update_latency(&address, Duration::from_millis(100));
+        let mut peer = Peer::new(
+            pk,
+            node_id.clone(),
+            addresses,
+            PeerFlags::default(),
+            PeerFeatures::COMMUNICATION_NODE,
+            Default::default(),
+            "user agent".to_string(),
+        );
+        peer.ban_for(Duration::from_secs(60), "spam".to_string());
+
+        let info = PeerInfo::from(&peer);
+        assert_eq!(info.node_id, node_id);
+        assert_eq!(info.user_agent, "user agent");
+        assert_eq!(info.addresses.len(), 2);
+        // The address with a measured latency is preferred
+        assert_eq!(info.addresses[0].address, address);
+        assert_eq!(info.addresses[0].rank, 0);
+        assert_eq!(info.addresses[0].avg_latency, Some(Duration::from_millis(100)));
+        assert_eq!(info.addresses[1].avg_latency, None);
+        assert_eq!(info.best_latency(), Some(Duration::from_millis(100)));
+        assert!(info.is_banned());
+        assert_eq!(info.banned_reason.as_deref(), Some("spam"));
+        assert!(info.last_seen.is_some());
+    }
+}
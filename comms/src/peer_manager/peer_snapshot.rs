@@ -0,0 +1,265 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+//! # Peer snapshot
+//!
+//! A signed, versioned snapshot of the peer database that can be exported from one node and imported into another,
+//! for example when migrating a node to new hardware or bootstrapping a second node without relying on seed nodes.
+//! The snapshot is serialized as JSON and is always signed by the node that exported it.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "created_at": "2020-11-20T10:00:00",
+//!   "entries": [
+//!     {
+//!       "public_key": "<hex>",
+//!       "addresses": ["/ip4/1.2.3.4/tcp/18141"],
+//!       "features": { "bits": 1 },
+//!       "is_pinned": false
+//!     }
+//!   ],
+//!   "signer": {
+//!     "public_key": "<hex>",
+//!     "signature": "<hex>"
+//!   }
+//! }
+//! ```
+
+use crate::{
+    peer_manager::{NodeIdentity, Peer, PeerFeatures},
+    types::CommsPublicKey,
+    utils::signature,
+};
+use chrono::{NaiveDateTime, Utc};
+use multiaddr::Multiaddr;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use tari_crypto::tari_utilities::{
+    hex::{from_hex, Hex},
+    message_format::MessageFormat,
+    ByteArray,
+};
+use thiserror::Error;
+
+/// The current version of the peer snapshot format
+pub const PEER_SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Error, Clone)]
+pub enum PeerSnapshotError {
+    #[error("Unsupported peer snapshot version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Peer snapshot is not signed")]
+    NotSigned,
+    #[error("Peer snapshot signature is invalid")]
+    InvalidSignature,
+    #[error("Peer snapshot is signed by '{0}' which is not the expected signer")]
+    UnexpectedSigner(Box<CommsPublicKey>),
+    #[error("Failed to sign peer snapshot")]
+    SigningFailed,
+    #[error("Failed to serialize or deserialize peer snapshot: {0}")]
+    SerializationError(String),
+    #[error("Failed to read or write peer snapshot file: {0}")]
+    IoError(String),
+}
+
+/// A single peer in a peer snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerSnapshotEntry {
+    pub public_key: CommsPublicKey,
+    pub addresses: Vec<Multiaddr>,
+    pub features: PeerFeatures,
+    /// True if the peer was pinned on the exporting node
+    #[serde(default)]
+    pub is_pinned: bool,
+}
+
+impl PeerSnapshotEntry {
+    /// Returns a `PeerSnapshotEntry` for the given peer, or None if the peer should not be exported (i.e. it is banned
+    /// or has no known addresses)
+    pub fn from_peer(peer: &Peer) -> Option<Self> {
+        if peer.is_banned() || peer.addresses.is_empty() {
+            return None;
+        }
+        Some(Self {
+            public_key: peer.public_key.clone(),
+            addresses: peer.addresses.iter().cloned().collect(),
+            features: peer.features,
+            is_pinned: peer.is_pinned(),
+        })
+    }
+}
+
+/// The public key and signature of the node that exported a peer snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerSnapshotSigner {
+    pub public_key: CommsPublicKey,
+    /// Hex-encoded Schnorr signature of the snapshot challenge
+    pub signature: String,
+}
+
+/// A portable snapshot of the peer database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerSnapshot {
+    pub version: u32,
+    pub created_at: NaiveDateTime,
+    pub entries: Vec<PeerSnapshotEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer: Option<PeerSnapshotSigner>,
+}
+
+impl PeerSnapshot {
+    /// Create a new unsigned peer snapshot containing the given entries
+    pub fn new(entries: Vec<PeerSnapshotEntry>) -> Self {
+        Self {
+            version: PEER_SNAPSHOT_VERSION,
+            created_at: Utc::now().naive_utc(),
+            entries,
+            signer: None,
+        }
+    }
+
+    /// Deserialize a peer snapshot from JSON. The version of the snapshot is checked, but the signature is not
+    /// verified (see [verify](#method.verify)).
+    pub fn from_json(json: &str) -> Result<Self, PeerSnapshotError> {
+        let snapshot =
+            serde_json::from_str::<Self>(json).map_err(|err| PeerSnapshotError::SerializationError(err.to_string()))?;
+        if snapshot.version > PEER_SNAPSHOT_VERSION {
+            return Err(PeerSnapshotError::UnsupportedVersion(snapshot.version));
+        }
+        Ok(snapshot)
+    }
+
+    /// Serialize this peer snapshot as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String, PeerSnapshotError> {
+        serde_json::to_string_pretty(self).map_err(|err| PeerSnapshotError::SerializationError(err.to_string()))
+    }
+
+    /// Sign this snapshot as the given node. Any existing signature is replaced.
+    pub fn sign(&mut self, node_identity: &NodeIdentity) -> Result<(), PeerSnapshotError> {
+        let signature = signature::sign(&mut OsRng, node_identity.secret_key().clone(), self.challenge())
+            .map_err(|_| PeerSnapshotError::SigningFailed)?
+            .to_binary()
+            .map_err(|_| PeerSnapshotError::SigningFailed)?;
+        self.signer = Some(PeerSnapshotSigner {
+            public_key: node_identity.public_key().clone(),
+            signature: signature.to_hex(),
+        });
+        Ok(())
+    }
+
+    /// Verify that this snapshot is signed by the given public key. Unlike ban lists, unsigned snapshots are rejected.
+    pub fn verify(&self, expected_signer: &CommsPublicKey) -> Result<(), PeerSnapshotError> {
+        let signer = self.signer.as_ref().ok_or(PeerSnapshotError::NotSigned)?;
+        if signer.public_key != *expected_signer {
+            return Err(PeerSnapshotError::UnexpectedSigner(Box::new(signer.public_key.clone())));
+        }
+        let signature = from_hex(&signer.signature).map_err(|_| PeerSnapshotError::InvalidSignature)?;
+        if signature::verify(&signer.public_key, &signature, self.challenge()) {
+            Ok(())
+        } else {
+            Err(PeerSnapshotError::InvalidSignature)
+        }
+    }
+
+    /// The bytes that are signed by the exporting node. Every field except the signer is included.
+    fn challenge(&self) -> Vec<u8> {
+        let mut challenge = Vec::with_capacity(16 + self.entries.len() * 64);
+        challenge.extend_from_slice(&self.version.to_le_bytes());
+        challenge.extend_from_slice(&self.created_at.timestamp().to_le_bytes());
+        for entry in &self.entries {
+            challenge.extend_from_slice(entry.public_key.as_bytes());
+            challenge.extend_from_slice(&(entry.addresses.len() as u64).to_le_bytes());
+            for address in &entry.addresses {
+                let address = address.to_vec();
+                challenge.extend_from_slice(&(address.len() as u64).to_le_bytes());
+                challenge.extend_from_slice(&address);
+            }
+            challenge.extend_from_slice(&entry.features.bits().to_le_bytes());
+            challenge.push(entry.is_pinned as u8);
+        }
+        challenge
+    }
+}
+
+/// The result of merging an imported peer snapshot into the local peer database
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerSnapshotImportResult {
+    /// Number of previously unknown peers that were added
+    pub num_added: usize,
+    /// Number of known peers that had new addresses merged in
+    pub num_updated: usize,
+    /// Number of entries that were skipped because the peer is banned locally or nothing new was learned
+    pub num_skipped: usize,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::node_identity::build_node_identity;
+    use tari_crypto::keys::PublicKey;
+    use tari_test_utils::unpack_enum;
+
+    fn create_snapshot() -> PeerSnapshot {
+        let entries = (0..3)
+            .map(|i| PeerSnapshotEntry {
+                public_key: CommsPublicKey::random_keypair(&mut OsRng).1,
+                addresses: vec![format!("/ip4/1.2.3.{}/tcp/18141", i).parse().unwrap()],
+                features: PeerFeatures::COMMUNICATION_NODE,
+                is_pinned: i == 0,
+            })
+            .collect();
+        PeerSnapshot::new(entries)
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let node_identity = build_node_identity(Default::default());
+        let public_key = node_identity.public_key();
+        let mut snapshot = create_snapshot();
+        unpack_enum!(PeerSnapshotError::NotSigned = snapshot.verify(public_key).unwrap_err());
+        snapshot.sign(&node_identity).unwrap();
+
+        let snapshot = PeerSnapshot::from_json(&snapshot.to_json().unwrap()).unwrap();
+        snapshot.verify(public_key).unwrap();
+        assert_eq!(&snapshot.signer.as_ref().unwrap().public_key, public_key);
+
+        let other = build_node_identity(Default::default());
+        unpack_enum!(PeerSnapshotError::UnexpectedSigner(signer) = snapshot.verify(other.public_key()).unwrap_err());
+        assert_eq!(&*signer, public_key);
+
+        let mut tampered = snapshot.clone();
+        tampered.entries[1].addresses[0] = "/ip4/6.6.6.6/tcp/18141".parse().unwrap();
+        unpack_enum!(PeerSnapshotError::InvalidSignature = tampered.verify(public_key).unwrap_err());
+
+        let mut tampered = snapshot;
+        tampered.entries[2].is_pinned = true;
+        unpack_enum!(PeerSnapshotError::InvalidSignature = tampered.verify(public_key).unwrap_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_version() {
+        let mut snapshot = create_snapshot();
+        snapshot.version = PEER_SNAPSHOT_VERSION + 1;
+        let err = PeerSnapshot::from_json(&snapshot.to_json().unwrap()).unwrap_err();
+        unpack_enum!(PeerSnapshotError::UnsupportedVersion(_v) = err);
+    }
+}
@@ -21,32 +21,58 @@
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::{
-    consts::PEER_MANAGER_MAX_FLOOD_PEERS,
+    consts::{PEER_MANAGER_CACHE_CAPACITY, PEER_MANAGER_MAX_FLOOD_PEERS},
     peer_manager::{
+        distance_index::DistanceIndex,
+        known_ip_index::{KnownIpIndex, PeerIps},
         node_id::{NodeDistance, NodeId},
         peer::{Peer, PeerFlags},
+        peer_cache::PeerCache,
         peer_id::{generate_peer_key, PeerId},
+        region,
         PeerFeatures,
         PeerManagerError,
         PeerQuery,
+        PeerTrustLevel,
+        PeerWriteBatch,
+        PeerWriteOp,
     },
     protocol::ProtocolId,
     types::{CommsDatabase, CommsPublicKey},
 };
+use chrono::NaiveDateTime;
 use log::*;
 use multiaddr::Multiaddr;
-use rand::{rngs::OsRng, seq::SliceRandom};
-use std::{collections::HashMap, time::Duration};
-use tari_storage::{IterationResult, KeyValueStore};
+use rand::{rngs::OsRng, seq::SliceRandom, Rng};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tari_storage::{IterationResult, KeyValueStore, WriteOperation};
 
 const LOG_TARGET: &str = "comms::peer_manager::peer_storage";
 
 /// PeerStorage provides a mechanism to keep a datastore and a local copy of all peers in sync and allow fast searches
 /// using the node_id, public key or net_address of a peer.
+///
+/// Peers that are looked up by node id or public key are kept in a bounded read-through cache. All writes to the
+/// datastore invalidate the cached peer so that the cache is never out of date.
+///
+/// Orderings of node ids by distance can optionally be kept in an index (see `with_distance_index_capacity`). The index
+/// is cleared whenever a peer is added or removed.
+///
+/// The IP addresses at which unbanned peers have been seen are kept in an index that is updated on every write,
+/// so that `is_known_peer_ip` does not need to scan the datastore.
 pub struct PeerStorage<DS> {
     pub(crate) peer_db: DS,
     public_key_index: HashMap<CommsPublicKey, PeerId>,
     node_id_index: HashMap<NodeId, PeerId>,
+    cache: Mutex<PeerCache>,
+    distance_index: Mutex<DistanceIndex>,
+    known_ip_index: Mutex<KnownIpIndex>,
 }
 
 impl<DS> PeerStorage<DS>
@@ -57,10 +83,12 @@ where DS: KeyValueStore<PeerId, Peer>
         // Restore peers and hashmap links from database
         let mut public_key_index = HashMap::new();
         let mut node_id_index = HashMap::new();
+        let mut known_ip_index = KnownIpIndex::new();
         let mut total_entries = 0;
         database
             .for_each_ok(|(peer_key, peer)| {
                 total_entries += 1;
+                known_ip_index.insert(peer_key, PeerIps::from_peer(&peer));
                 public_key_index.insert(peer.public_key, peer_key);
                 node_id_index.insert(peer.node_id, peer_key);
                 IterationResult::Continue
@@ -77,13 +105,58 @@ where DS: KeyValueStore<PeerId, Peer>
             peer_db: database,
             public_key_index,
             node_id_index,
+            cache: Mutex::new(PeerCache::new(PEER_MANAGER_CACHE_CAPACITY)),
+            distance_index: Mutex::new(DistanceIndex::new(0)),
+            known_ip_index: Mutex::new(known_ip_index),
         })
     }
 
+    /// Sets the maximum number of peers held in the read-through cache. Setting this to zero disables the cache.
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache = Mutex::new(PeerCache::new(capacity));
+        self
+    }
+
+    /// Sets the maximum number of node ids for which an ordering of all known node ids by distance is kept. Closest
+    /// peer queries from an indexed node id reuse the ordering instead of computing and sorting the distance to every
+    /// known peer. Each ordering holds every known node id, so this should be kept small. Setting this to zero (the
+    /// default) disables the index.
+    pub fn with_distance_index_capacity(self, capacity: usize) -> Self {
+        self.set_distance_index_capacity(capacity);
+        self
+    }
+
+    /// Sets the maximum number of node ids for which an ordering by distance is kept, discarding any indexed
+    /// orderings. See `with_distance_index_capacity`.
+    pub fn set_distance_index_capacity(&self, capacity: usize) {
+        *acquire_lock!(self.distance_index) = DistanceIndex::new(capacity);
+    }
+
     pub fn count(&self) -> usize {
         self.node_id_index.len()
     }
 
+    /// Returns the approximate number of bytes allocated for the in-memory peer indexes
+    pub fn approx_index_memory_usage(&self) -> usize {
+        self.public_key_index.capacity() * (mem::size_of::<CommsPublicKey>() + mem::size_of::<PeerId>()) +
+            self.node_id_index.capacity() * (mem::size_of::<NodeId>() + mem::size_of::<PeerId>())
+    }
+
+    /// Returns the approximate number of bytes allocated for the read-through peer cache
+    pub fn approx_cache_memory_usage(&self) -> usize {
+        acquire_lock!(self.cache).approx_memory_usage()
+    }
+
+    /// Returns the number of peers currently held in the read-through cache
+    pub fn num_cached(&self) -> usize {
+        acquire_lock!(self.cache).len()
+    }
+
+    /// Returns true if an unbanned peer has been seen at the given IP address
+    pub fn is_known_peer_ip(&self, ip: &IpAddr) -> bool {
+        acquire_lock!(self.known_ip_index).contains(ip)
+    }
+
     /// Adds a peer to the routing table of the PeerManager if the peer does not already exist. When a peer already
     /// exists, the stored version will be replaced with the newly provided peer.
     pub fn add_peer(&mut self, mut peer: Peer) -> Result<PeerId, PeerManagerError> {
@@ -93,9 +166,7 @@ where DS: KeyValueStore<PeerId, Peer>
                 trace!(target: LOG_TARGET, "Replacing peer that has NodeId '{}'", peer.node_id);
                 // Replace existing entry
                 peer.set_id(peer_key);
-                self.peer_db
-                    .insert(peer_key, peer)
-                    .map_err(PeerManagerError::DatabaseError)?;
+                self.insert_peer(peer_key, peer)?;
                 self.remove_index_links(peer_key);
                 self.add_index_links(peer_key, public_key, node_id);
                 Ok(peer_key)
@@ -106,9 +177,7 @@ where DS: KeyValueStore<PeerId, Peer>
                 // Generate new random peer key
                 let peer_key = generate_peer_key();
                 peer.set_id(peer_key);
-                self.peer_db
-                    .insert(peer_key, peer)
-                    .map_err(PeerManagerError::DatabaseError)?;
+                self.insert_peer(peer_key, peer)?;
                 self.add_index_links(peer_key, public_key, node_id);
                 Ok(peer_key)
             },
@@ -134,9 +203,7 @@ where DS: KeyValueStore<PeerId, Peer>
         match self.public_key_index.get(public_key).copied() {
             Some(peer_key) => {
                 let mut stored_peer = self
-                    .peer_db
-                    .get(&peer_key)
-                    .map_err(PeerManagerError::DatabaseError)?
+                    .get_peer(&peer_key)?
                     .expect("Public key index and peer database are out of sync!");
 
                 trace!(target: LOG_TARGET, "Updating peer '{}'", stored_peer.node_id);
@@ -151,9 +218,7 @@ where DS: KeyValueStore<PeerId, Peer>
                     supported_protocols,
                 );
 
-                self.peer_db
-                    .insert(peer_key, stored_peer)
-                    .map_err(PeerManagerError::DatabaseError)?;
+                self.insert_peer(peer_key, stored_peer)?;
 
                 Ok(())
             },
@@ -174,9 +239,7 @@ where DS: KeyValueStore<PeerId, Peer>
             .node_id_index
             .get(&node_id)
             .ok_or_else(|| PeerManagerError::PeerNotFoundError)?;
-        self.peer_db
-            .delete(&peer_key)
-            .map_err(PeerManagerError::DatabaseError)?;
+        self.remove_peer(&peer_key)?;
 
         self.remove_index_links(peer_key);
         Ok(())
@@ -186,6 +249,7 @@ where DS: KeyValueStore<PeerId, Peer>
     fn add_index_links(&mut self, peer_key: PeerId, public_key: CommsPublicKey, node_id: NodeId) {
         self.node_id_index.insert(node_id, peer_key);
         self.public_key_index.insert(public_key, peer_key);
+        acquire_lock!(self.distance_index).clear();
     }
 
     /// Remove the peer specified by a given index from the database and remove hashmap keys
@@ -196,6 +260,38 @@ where DS: KeyValueStore<PeerId, Peer>
         self.node_id_index = self.node_id_index.drain().filter(|(_, k)| k != &peer_key).collect();
         debug_assert_eq!(initial_size_pk - 1, self.public_key_index.len());
         debug_assert_eq!(initial_size_node_id - 1, self.node_id_index.len());
+        acquire_lock!(self.distance_index).clear();
+    }
+
+    /// Fetch the peer from the read-through cache, or from the database if it is not cached
+    fn get_peer(&self, peer_key: &PeerId) -> Result<Option<Peer>, PeerManagerError> {
+        if let Some(peer) = acquire_lock!(self.cache).get(peer_key) {
+            return Ok(Some(peer));
+        }
+        let maybe_peer = self.peer_db.get(peer_key).map_err(PeerManagerError::DatabaseError)?;
+        if let Some(peer) = maybe_peer.as_ref() {
+            acquire_lock!(self.cache).insert(*peer_key, peer.clone());
+        }
+        Ok(maybe_peer)
+    }
+
+    /// Write the peer to the database, invalidate the cached peer and update the known IP index
+    fn insert_peer(&self, peer_key: PeerId, peer: Peer) -> Result<(), PeerManagerError> {
+        acquire_lock!(self.cache).invalidate(&peer_key);
+        let peer_ips = PeerIps::from_peer(&peer);
+        self.peer_db
+            .insert(peer_key, peer)
+            .map_err(PeerManagerError::DatabaseError)?;
+        acquire_lock!(self.known_ip_index).insert(peer_key, peer_ips);
+        Ok(())
+    }
+
+    /// Delete the peer from the database, invalidate the cached peer and remove it from the known IP index
+    fn remove_peer(&self, peer_key: &PeerId) -> Result<(), PeerManagerError> {
+        acquire_lock!(self.cache).invalidate(peer_key);
+        self.peer_db.delete(peer_key).map_err(PeerManagerError::DatabaseError)?;
+        acquire_lock!(self.known_ip_index).remove(peer_key);
+        Ok(())
     }
 
     /// Find the peer with the provided NodeID
@@ -204,17 +300,13 @@ where DS: KeyValueStore<PeerId, Peer>
             .node_id_index
             .get(node_id)
             .ok_or_else(|| PeerManagerError::PeerNotFoundError)?;
-        Ok(self
-            .peer_db
-            .get(&peer_key)
-            .map_err(PeerManagerError::DatabaseError)?
-            .ok_or_else(|| {
-                warn!(
-                    target: LOG_TARGET,
-                    "node_id_index and peer database are out of sync! (key={}, node_id={})", peer_key, node_id
-                );
-                PeerManagerError::PeerNotFoundError
-            })?)
+        self.get_peer(peer_key)?.ok_or_else(|| {
+            warn!(
+                target: LOG_TARGET,
+                "node_id_index and peer database are out of sync! (key={}, node_id={})", peer_key, node_id
+            );
+            PeerManagerError::PeerNotFoundError
+        })
     }
 
     /// Find the peer with the provided PublicKey
@@ -223,19 +315,62 @@ where DS: KeyValueStore<PeerId, Peer>
             .public_key_index
             .get(public_key)
             .ok_or_else(|| PeerManagerError::PeerNotFoundError)?;
-        Ok(self
-            .peer_db
-            .get(peer_key)
-            .map_err(PeerManagerError::DatabaseError)?
-            .ok_or_else(|| {
-                warn!(
-                    target: LOG_TARGET,
-                    "public_key_index and peer database are out of sync! (key={}, public_key ={})",
-                    peer_key,
-                    public_key
-                );
-                PeerManagerError::PeerNotFoundError
-            })?)
+        self.get_peer(peer_key)?.ok_or_else(|| {
+            warn!(
+                target: LOG_TARGET,
+                "public_key_index and peer database are out of sync! (key={}, public_key ={})", peer_key, public_key
+            );
+            PeerManagerError::PeerNotFoundError
+        })
+    }
+
+    /// Find all peers with the given NodeIds. NodeIds that are not known are skipped. Peers are returned in the same
+    /// order as the given NodeIds.
+    pub fn get_many(&self, node_ids: &[NodeId]) -> Result<Vec<Peer>, PeerManagerError> {
+        let mut peers = Vec::with_capacity(node_ids.len());
+        for node_id in node_ids {
+            if let Some(peer_key) = self.node_id_index.get(node_id) {
+                if let Some(peer) = self.get_peer(peer_key)? {
+                    peers.push(peer);
+                }
+            }
+        }
+        Ok(peers)
+    }
+
+    /// Returns all known NodeIds, excluding `excluded_peers`, sorted by distance from the given NodeId. This only uses
+    /// the in-memory index, and so does not read from the database.
+    pub fn node_ids_by_distance(&self, node_id: &NodeId, excluded_peers: &[NodeId]) -> Vec<NodeId> {
+        let mut distance_index = acquire_lock!(self.distance_index);
+        if !distance_index.is_enabled() {
+            return self.sort_node_ids_by_distance(node_id, |n| !excluded_peers.contains(n));
+        }
+
+        let ordered = match distance_index.get(node_id) {
+            Some(ordered) => ordered,
+            None => {
+                let ordered = Arc::new(self.sort_node_ids_by_distance(node_id, |_| true));
+                distance_index.insert(node_id.clone(), Arc::clone(&ordered));
+                ordered
+            },
+        };
+        ordered
+            .iter()
+            .filter(|n| !excluded_peers.contains(n))
+            .cloned()
+            .collect()
+    }
+
+    fn sort_node_ids_by_distance<P>(&self, node_id: &NodeId, mut predicate: P) -> Vec<NodeId>
+    where P: FnMut(&NodeId) -> bool {
+        let mut node_ids = self
+            .node_id_index
+            .keys()
+            .filter(|n| predicate(n))
+            .map(|n| (node_id.distance_key(n), n))
+            .collect::<Vec<_>>();
+        node_ids.sort_unstable_by_key(|(dist, _)| *dist);
+        node_ids.into_iter().map(|(_, n)| n.clone()).collect()
     }
 
     /// Check if a peer exist using the specified public_key
@@ -310,29 +445,36 @@ where DS: KeyValueStore<PeerId, Peer>
             return Ok(Vec::new());
         }
 
-        let mut distances = Vec::new();
-        self.peer_db
-            .for_each_ok(|(_, peer)| {
-                if features.map(|f| peer.features == f).unwrap_or(true) &&
-                    !peer.is_banned() &&
-                    !peer.is_offline() &&
-                    !excluded_peers.contains(&peer.node_id)
-                {
-                    let dist = node_id.distance(&peer.node_id);
-                    distances.push((peer, dist));
+        // Peers are visited in order of distance so that only the peers that are needed are fetched
+        let mut peers = Vec::with_capacity(n);
+        for peer_node_id in self.node_ids_by_distance(node_id, excluded_peers) {
+            let peer = self.find_by_node_id(&peer_node_id)?;
+            if features.map(|f| peer.features == f).unwrap_or(true) && !peer.is_banned() && !peer.is_offline() {
+                peers.push(peer);
+                if peers.len() == n {
+                    break;
                 }
-                IterationResult::Continue
-            })
-            .map_err(PeerManagerError::DatabaseError)?;
-
-        distances.sort_by(|(_, dist_a), (_, dist_b)| dist_a.cmp(dist_b));
-        distances.truncate(n);
+            }
+        }
 
-        Ok(distances.into_iter().map(|(peer, _)| peer).collect())
+        Ok(peers)
     }
 
     /// Compile a random list of communication node peers of size _n_ that are not banned or offline
     pub fn random_peers(&self, n: usize, exclude_peers: &[NodeId]) -> Result<Vec<Peer>, PeerManagerError> {
+        self.random_peers_with_rng(n, exclude_peers, &mut OsRng)
+    }
+
+    /// Compile a random list of communication node peers of size _n_ that are not banned or offline, using the given
+    /// RNG. Peers are ordered by node id before they are shuffled, so that a seeded RNG always selects the same peers
+    /// from the same database.
+    pub fn random_peers_with_rng<R: Rng + ?Sized>(
+        &self,
+        n: usize,
+        exclude_peers: &[NodeId],
+        rng: &mut R,
+    ) -> Result<Vec<Peer>, PeerManagerError>
+    {
         if n == 0 {
             return Ok(Vec::new());
         }
@@ -351,7 +493,8 @@ where DS: KeyValueStore<PeerId, Peer>
         if peers.is_empty() {
             return Ok(Vec::new());
         }
-        peers.shuffle(&mut OsRng);
+        peers.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        peers.shuffle(rng);
         peers.truncate(n);
 
         Ok(peers)
@@ -384,33 +527,18 @@ where DS: KeyValueStore<PeerId, Peer>
         features: PeerFeatures,
     ) -> Result<NodeDistance, PeerManagerError>
     {
-        if n == 0 {
-            return Ok(NodeDistance::max_distance());
-        }
-
-        let mut dists = Vec::new();
+        let mut node_ids = Vec::new();
         self.peer_db
             .for_each_ok(|(_, peer)| {
                 if peer.features != features || peer.is_banned() || peer.is_offline() {
                     return IterationResult::Continue;
                 }
-                dists.push(region_node_id.distance(&peer.node_id));
+                node_ids.push(peer.node_id);
                 IterationResult::Continue
             })
             .map_err(PeerManagerError::DatabaseError)?;
 
-        if dists.is_empty() {
-            return Ok(NodeDistance::max_distance());
-        }
-
-        // If we have less than `n` matching peers in our threshold group, the threshold should be max
-        if dists.len() < n {
-            return Ok(NodeDistance::max_distance());
-        }
-
-        dists.sort();
-        dists.truncate(n);
-        Ok(dists.pop().expect("dists cannot be empty at this point"))
+        Ok(region::calc_region_threshold(region_node_id, &node_ids, n))
     }
 
     /// Unban the peer
@@ -420,16 +548,12 @@ where DS: KeyValueStore<PeerId, Peer>
             .get(&node_id)
             .ok_or_else(|| PeerManagerError::PeerNotFoundError)?;
         let mut peer = self
-            .peer_db
-            .get(&peer_key)
-            .map_err(PeerManagerError::DatabaseError)?
+            .get_peer(&peer_key)?
             .expect("public_key_index is out of sync with peer db");
 
         if peer.banned_until.is_some() {
             peer.unban();
-            self.peer_db
-                .insert(peer_key, peer)
-                .map_err(PeerManagerError::DatabaseError)?;
+            self.insert_peer(peer_key, peer)?;
         }
         Ok(())
     }
@@ -465,14 +589,10 @@ where DS: KeyValueStore<PeerId, Peer>
     }
 
     fn ban_peer_by_id(&mut self, id: PeerId, duration: Duration, reason: String) -> Result<NodeId, PeerManagerError> {
-        let mut peer: Peer = self
-            .peer_db
-            .get(&id)
-            .map_err(PeerManagerError::DatabaseError)?
-            .expect("index are out of sync with peer db");
+        let mut peer: Peer = self.get_peer(&id)?.expect("index are out of sync with peer db");
         peer.ban_for(duration, reason);
         let node_id = peer.node_id.clone();
-        self.peer_db.insert(id, peer).map_err(PeerManagerError::DatabaseError)?;
+        self.insert_peer(id, peer)?;
         Ok(node_id)
     }
 
@@ -483,16 +603,108 @@ where DS: KeyValueStore<PeerId, Peer>
             .get(&node_id)
             .ok_or_else(|| PeerManagerError::PeerNotFoundError)?;
         let mut peer: Peer = self
-            .peer_db
-            .get(&peer_key)
-            .map_err(PeerManagerError::DatabaseError)?
+            .get_peer(&peer_key)?
             .expect("node_id_index is out of sync with peer db");
         let was_offline = peer.is_offline();
         peer.set_offline(offline);
+        self.insert_peer(peer_key, peer)?;
+        Ok(was_offline)
+    }
+
+    /// Changes the PINNED flag bit of the peer. Returns true if the peer was previously pinned, otherwise false.
+    pub fn set_pinned(&mut self, node_id: &NodeId, is_pinned: bool) -> Result<bool, PeerManagerError> {
+        let peer_key = *self
+            .node_id_index
+            .get(&node_id)
+            .ok_or_else(|| PeerManagerError::PeerNotFoundError)?;
+        let mut peer: Peer = self
+            .get_peer(&peer_key)?
+            .expect("node_id_index is out of sync with peer db");
+        let was_pinned = peer.is_pinned();
+        peer.set_pinned(is_pinned);
+        self.insert_peer(peer_key, peer)?;
+        Ok(was_pinned)
+    }
+
+    /// Sets the trust level of the peer. Returns the previous trust level of the peer.
+    pub fn set_trust_level(
+        &mut self,
+        node_id: &NodeId,
+        trust_level: PeerTrustLevel,
+    ) -> Result<PeerTrustLevel, PeerManagerError>
+    {
+        let peer_key = *self
+            .node_id_index
+            .get(&node_id)
+            .ok_or_else(|| PeerManagerError::PeerNotFoundError)?;
+        let mut peer: Peer = self
+            .get_peer(&peer_key)?
+            .expect("node_id_index is out of sync with peer db");
+        let prev_trust_level = peer.trust_level();
+        peer.set_trust_level(trust_level);
+        self.insert_peer(peer_key, peer)?;
+        Ok(prev_trust_level)
+    }
+
+    /// Removes the lowest-value peers until at most `max_size` peers remain. Pinned peers and peers in `exclude` are
+    /// never removed, so more than `max_size` peers may remain if there are not enough peers that can be removed.
+    /// Returns the number of peers that were removed.
+    ///
+    /// Peers are ranked for removal as follows:
+    /// 1. Peers that are banned are kept over other peers so that the ban continues to be enforced
+    /// 2. Peers that are online are kept over peers that are marked as offline
+    /// 3. Peers that this node has successfully connected to are kept over peers it has never connected to
+    /// 4. More recently seen peers are kept over peers that were seen less recently
+    pub fn prune(&mut self, max_size: usize, exclude: &[NodeId]) -> Result<usize, PeerManagerError> {
+        let num_peers = self.count();
+        if num_peers <= max_size {
+            return Ok(0);
+        }
+
+        let mut candidates = Vec::with_capacity(num_peers);
         self.peer_db
-            .insert(peer_key, peer)
+            .for_each_ok(|(peer_key, peer)| {
+                if !peer.is_pinned() && !exclude.contains(&peer.node_id) {
+                    candidates.push((Self::prune_rank(&peer), peer_key));
+                }
+                IterationResult::Continue
+            })
             .map_err(PeerManagerError::DatabaseError)?;
-        Ok(was_offline)
+        candidates.sort();
+
+        let mut removed = HashSet::with_capacity(num_peers - max_size);
+        let mut result = Ok(());
+        for (_, peer_key) in candidates.into_iter().take(num_peers - max_size) {
+            if let Err(err) = self.remove_peer(&peer_key) {
+                result = Err(err);
+                break;
+            }
+            removed.insert(peer_key);
+        }
+        // Unlink every removed peer in a single pass over the indexes, including when a delete failed part way
+        self.public_key_index.retain(|_, k| !removed.contains(k));
+        self.node_id_index.retain(|_, k| !removed.contains(k));
+        acquire_lock!(self.distance_index).clear();
+        result?;
+        let num_removed = removed.len();
+
+        debug!(
+            target: LOG_TARGET,
+            "Pruned {} peer(s) from the peer database ({} remaining)",
+            num_removed,
+            self.count()
+        );
+        Ok(num_removed)
+    }
+
+    /// The rank of a peer when pruning. Peers with a lower rank are removed first.
+    fn prune_rank(peer: &Peer) -> (bool, bool, bool, NaiveDateTime) {
+        (
+            peer.is_banned(),
+            !peer.is_offline(),
+            peer.connection_stats.has_ever_connected(),
+            peer.last_seen().map(|dt| dt.naive_utc()).unwrap_or(peer.added_at),
+        )
     }
 
     /// Enables Thread safe access - Adds a new net address to the peer if it doesn't yet exist
@@ -502,14 +714,10 @@ where DS: KeyValueStore<PeerId, Peer>
             .get(&node_id)
             .ok_or_else(|| PeerManagerError::PeerNotFoundError)?;
         let mut peer: Peer = self
-            .peer_db
-            .get(&peer_key)
-            .map_err(PeerManagerError::DatabaseError)?
+            .get_peer(&peer_key)?
             .expect("node_id_index is out of sync with peer db");
         peer.addresses.add_net_address(net_address);
-        self.peer_db
-            .insert(peer_key, peer)
-            .map_err(PeerManagerError::DatabaseError)
+        self.insert_peer(peer_key, peer)
     }
 
     /// This will store metadata inside of the metadata field in the peer provided by the nodeID.
@@ -526,16 +734,141 @@ where DS: KeyValueStore<PeerId, Peer>
             .get(&node_id)
             .ok_or_else(|| PeerManagerError::PeerNotFoundError)?;
         let mut peer: Peer = self
-            .peer_db
-            .get(&peer_key)
-            .map_err(PeerManagerError::DatabaseError)?
+            .get_peer(&peer_key)?
             .expect("node_id_index is out of sync with peer db");
         let result = peer.set_metadata(key, data);
-        self.peer_db
-            .insert(peer_key, peer)
-            .map_err(PeerManagerError::DatabaseError)?;
+        self.insert_peer(peer_key, peer)?;
         Ok(result)
     }
+
+    /// Apply all operations in the batch as a single atomic write to the peer database. The indexes and cache are only
+    /// updated once the write has succeeded, so nothing is changed if an error is returned. Returns the number of peers
+    /// that were added, replaced or removed.
+    pub fn apply_batch(&mut self, batch: PeerWriteBatch) -> Result<usize, PeerManagerError> {
+        // Index links changed by the batch. `None` unlinks the key.
+        let mut public_key_changes = HashMap::<CommsPublicKey, Option<PeerId>>::new();
+        let mut node_id_changes = HashMap::<NodeId, Option<PeerId>>::new();
+        // The final state of each peer touched by the batch. `None` deletes the peer.
+        let mut writes = HashMap::<PeerId, Option<Peer>>::new();
+        // Keys generated for peers added by this batch, which are not yet in the database
+        let mut new_keys = HashSet::new();
+        let mut num_changed = 0;
+
+        for op in batch.into_operations() {
+            match op {
+                PeerWriteOp::Upsert(mut peer) => {
+                    let existing = match public_key_changes.get(&peer.public_key) {
+                        Some(change) => *change,
+                        None => self.public_key_index.get(&peer.public_key).copied(),
+                    };
+                    let peer_key = match existing {
+                        Some(peer_key) => {
+                            if let Some(prev) = self.get_batch_peer(&writes, peer_key)? {
+                                if prev.node_id != peer.node_id {
+                                    node_id_changes.insert(prev.node_id, None);
+                                }
+                            }
+                            peer_key
+                        },
+                        None => {
+                            let peer_key = generate_peer_key();
+                            new_keys.insert(peer_key);
+                            peer_key
+                        },
+                    };
+                    peer.set_id(peer_key);
+                    public_key_changes.insert(peer.public_key.clone(), Some(peer_key));
+                    node_id_changes.insert(peer.node_id.clone(), Some(peer_key));
+                    writes.insert(peer_key, Some(peer));
+                },
+                PeerWriteOp::Delete(node_id) => {
+                    let existing = match node_id_changes.get(&node_id) {
+                        Some(change) => *change,
+                        None => self.node_id_index.get(&node_id).copied(),
+                    };
+                    let peer_key = match existing {
+                        Some(peer_key) => peer_key,
+                        // Deleting a peer that does not exist is a no-op
+                        None => continue,
+                    };
+                    if let Some(prev) = self.get_batch_peer(&writes, peer_key)? {
+                        public_key_changes.insert(prev.public_key, None);
+                    }
+                    node_id_changes.insert(node_id, None);
+                    if new_keys.remove(&peer_key) {
+                        writes.remove(&peer_key);
+                    } else {
+                        writes.insert(peer_key, None);
+                    }
+                },
+            }
+            num_changed += 1;
+        }
+
+        let peer_keys = writes.keys().copied().collect::<Vec<_>>();
+        let ip_changes = writes
+            .iter()
+            .map(|(peer_key, peer)| (*peer_key, peer.as_ref().map(PeerIps::from_peer)))
+            .collect::<Vec<_>>();
+        let operations = writes
+            .into_iter()
+            .map(|(peer_key, peer)| match peer {
+                Some(peer) => WriteOperation::Insert(peer_key, peer),
+                None => WriteOperation::Delete(peer_key),
+            })
+            .collect();
+        if let Err(err) = self.peer_db.write_batch(operations) {
+            warn!(
+                target: LOG_TARGET,
+                "Peer write batch failed ({}). No changes were made", err
+            );
+            return Err(PeerManagerError::DatabaseError(err));
+        }
+
+        {
+            let mut cache = acquire_lock!(self.cache);
+            for peer_key in &peer_keys {
+                cache.invalidate(peer_key);
+            }
+        }
+        {
+            let mut known_ip_index = acquire_lock!(self.known_ip_index);
+            for (peer_key, peer_ips) in ip_changes {
+                match peer_ips {
+                    Some(peer_ips) => known_ip_index.insert(peer_key, peer_ips),
+                    None => known_ip_index.remove(&peer_key),
+                }
+            }
+        }
+        for (public_key, change) in public_key_changes {
+            match change {
+                Some(peer_key) => self.public_key_index.insert(public_key, peer_key),
+                None => self.public_key_index.remove(&public_key),
+            };
+        }
+        for (node_id, change) in node_id_changes {
+            match change {
+                Some(peer_key) => self.node_id_index.insert(node_id, peer_key),
+                None => self.node_id_index.remove(&node_id),
+            };
+        }
+        acquire_lock!(self.distance_index).clear();
+
+        Ok(num_changed)
+    }
+
+    /// Returns the peer as left by the batch writes so far, or as stored if the batch has not touched it
+    fn get_batch_peer(
+        &self,
+        writes: &HashMap<PeerId, Option<Peer>>,
+        peer_key: PeerId,
+    ) -> Result<Option<Peer>, PeerManagerError>
+    {
+        match writes.get(&peer_key) {
+            Some(peer) => Ok(peer.clone()),
+            None => self.get_peer(&peer_key),
+        }
+    }
 }
 
 impl Into<CommsDatabase> for PeerStorage<CommsDatabase> {
@@ -553,7 +886,8 @@ mod test {
     };
     use std::iter::repeat_with;
     use tari_crypto::{keys::PublicKey, ristretto::RistrettoPublicKey};
-    use tari_storage::HashmapDatabase;
+    use tari_storage::{HashmapDatabase, KeyValStoreError};
+    use tari_test_utils::unpack_enum;
 
     #[test]
     fn test_restore() {
@@ -844,4 +1178,259 @@ mod test {
         let is_in_region = peer_storage.in_network_region(far_node, &main_peer_node_id, 3).unwrap();
         assert_eq!(is_in_region, false);
     }
+
+    #[test]
+    fn prune() {
+        let mut peer_storage = PeerStorage::new_indexed(HashmapDatabase::new()).unwrap();
+
+        let mut pinned = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, true);
+        pinned.set_pinned(true);
+        let banned = create_test_peer(PeerFeatures::COMMUNICATION_NODE, true, true);
+        let mut connected = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false);
+        connected.connection_stats.set_connection_success();
+        let online = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false);
+        let offline = repeat_with(|| create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, true))
+            .take(3)
+            .collect::<Vec<_>>();
+        let excluded = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, true);
+
+        for peer in offline
+            .iter()
+            .chain(vec![&pinned, &banned, &connected, &online, &excluded])
+        {
+            peer_storage.add_peer(peer.clone()).unwrap();
+        }
+        assert_eq!(peer_storage.count(), 8);
+
+        let num_removed = peer_storage.prune(10, &[]).unwrap();
+        assert_eq!(num_removed, 0);
+
+        let num_removed = peer_storage.prune(4, &[excluded.node_id.clone()]).unwrap();
+        assert_eq!(num_removed, 4);
+        assert_eq!(peer_storage.count(), 4);
+        for peer in &offline {
+            assert!(!peer_storage.exists_node_id(&peer.node_id));
+        }
+        assert!(!peer_storage.exists_node_id(&online.node_id));
+        assert!(peer_storage.exists_node_id(&pinned.node_id));
+        assert!(peer_storage.exists_node_id(&banned.node_id));
+        assert!(peer_storage.exists_node_id(&connected.node_id));
+        assert!(peer_storage.exists_node_id(&excluded.node_id));
+
+        // Pinned and excluded peers are never removed
+        let num_removed = peer_storage.prune(0, &[excluded.node_id.clone()]).unwrap();
+        assert_eq!(num_removed, 2);
+        assert!(peer_storage.exists_node_id(&pinned.node_id));
+        assert!(peer_storage.exists_node_id(&excluded.node_id));
+
+        assert_eq!(peer_storage.set_pinned(&pinned.node_id, false).unwrap(), true);
+        let num_removed = peer_storage.prune(0, &[]).unwrap();
+        assert_eq!(num_removed, 2);
+        assert_eq!(peer_storage.count(), 0);
+    }
+
+    #[test]
+    fn cache_invalidated_on_write() {
+        let mut peer_storage = PeerStorage::new_indexed(HashmapDatabase::new())
+            .unwrap()
+            .with_cache_capacity(2);
+
+        let peers = repeat_with(|| create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false))
+            .take(3)
+            .collect::<Vec<_>>();
+        for p in &peers {
+            peer_storage.add_peer(p.clone()).unwrap();
+        }
+        assert_eq!(peer_storage.num_cached(), 0);
+
+        let node_ids = peers.iter().map(|p| p.node_id.clone()).collect::<Vec<_>>();
+        let found = peer_storage.get_many(&node_ids).unwrap();
+        assert_eq!(
+            found.iter().map(|p| &p.node_id).collect::<Vec<_>>(),
+            node_ids.iter().collect::<Vec<_>>()
+        );
+        // Cache is bounded
+        assert_eq!(peer_storage.num_cached(), 2);
+
+        // Writes are reflected in subsequent reads
+        let peer = peer_storage.find_by_node_id(&node_ids[2]).unwrap();
+        assert!(!peer.is_offline());
+        peer_storage.set_offline(&node_ids[2], true).unwrap();
+        assert!(peer_storage.find_by_node_id(&node_ids[2]).unwrap().is_offline());
+
+        peer_storage.delete_peer(&node_ids[2]).unwrap();
+        unpack_enum!(PeerManagerError::PeerNotFoundError = peer_storage.find_by_node_id(&node_ids[2]).unwrap_err());
+        let found = peer_storage.get_many(&node_ids).unwrap();
+        assert_eq!(found.len(), 2);
+    }
+
+    #[test]
+    fn distance_index_invalidated_on_peer_change() {
+        let mut peer_storage = PeerStorage::new_indexed(HashmapDatabase::new())
+            .unwrap()
+            .with_distance_index_capacity(1);
+        let expected_order = |peer_storage: &PeerStorage<_>, node_id: &NodeId| {
+            let mut expected = peer_storage.node_id_index.keys().cloned().collect::<Vec<_>>();
+            expected.sort_by_key(|n| node_id.distance(n));
+            expected
+        };
+
+        let peers = repeat_with(|| create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false))
+            .take(10)
+            .collect::<Vec<_>>();
+        for p in &peers {
+            peer_storage.add_peer(p.clone()).unwrap();
+        }
+
+        let node_id = peers[0].node_id.clone();
+        let ordered = peer_storage.node_ids_by_distance(&node_id, &[]);
+        assert_eq!(ordered, expected_order(&peer_storage, &node_id));
+        assert_eq!(acquire_lock!(peer_storage.distance_index).len(), 1);
+        // Excluded peers are filtered from the indexed ordering
+        let ordered = peer_storage.node_ids_by_distance(&node_id, &[node_id.clone()]);
+        assert_eq!(ordered, expected_order(&peer_storage, &node_id)[1..].to_vec());
+
+        let new_peer = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false);
+        peer_storage.add_peer(new_peer.clone()).unwrap();
+        assert_eq!(acquire_lock!(peer_storage.distance_index).len(), 0);
+        let ordered = peer_storage.node_ids_by_distance(&node_id, &[]);
+        assert!(ordered.contains(&new_peer.node_id));
+        assert_eq!(ordered, expected_order(&peer_storage, &node_id));
+
+        peer_storage.delete_peer(&new_peer.node_id).unwrap();
+        let ordered = peer_storage.node_ids_by_distance(&node_id, &[]);
+        assert!(!ordered.contains(&new_peer.node_id));
+
+        let closest = peer_storage
+            .closest_peers(&node_id, 3, &[node_id.clone()], None)
+            .unwrap();
+        assert_eq!(
+            closest.into_iter().map(|p| p.node_id).collect::<Vec<_>>(),
+            expected_order(&peer_storage, &node_id)[1..4].to_vec()
+        );
+    }
+
+    #[test]
+    fn known_peer_ip_index() {
+        let mut peer_storage = PeerStorage::new_indexed(HashmapDatabase::new()).unwrap();
+        let ip = "1.2.3.4".parse::<IpAddr>().unwrap();
+        let address = "/ip4/1.2.3.4/tcp/8000".parse::<Multiaddr>().unwrap();
+
+        // An address that has only been advertised is not indexed
+        let mut peer = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false);
+        peer_storage.add_peer(peer.clone()).unwrap();
+        assert!(!peer_storage.is_known_peer_ip(&ip));
+
+        peer.addresses.mark_successful_connection_attempt(&address);
+        peer_storage.add_peer(peer.clone()).unwrap();
+        assert!(peer_storage.is_known_peer_ip(&ip));
+        assert!(!peer_storage.is_known_peer_ip(&"1.2.3.5".parse().unwrap()));
+
+        peer_storage
+            .ban_peer_by_node_id(&peer.node_id, Duration::from_secs(600), "".to_string())
+            .unwrap();
+        assert!(!peer_storage.is_known_peer_ip(&ip));
+        peer_storage.unban_peer(&peer.node_id).unwrap();
+        assert!(peer_storage.is_known_peer_ip(&ip));
+
+        // The index is restored from the database
+        let peer_storage = PeerStorage::new_indexed(peer_storage.peer_db).unwrap();
+        assert!(peer_storage.is_known_peer_ip(&ip));
+
+        // The IP is known while any unbanned peer has been seen at it
+        let mut peer_storage = peer_storage;
+        let mut other_peer = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false);
+        other_peer.addresses.mark_successful_connection_attempt(&address);
+        let mut batch = PeerWriteBatch::new();
+        batch.upsert(other_peer.clone());
+        peer_storage.apply_batch(batch).unwrap();
+        peer_storage.delete_peer(&peer.node_id).unwrap();
+        assert!(peer_storage.is_known_peer_ip(&ip));
+
+        let mut batch = PeerWriteBatch::new();
+        batch.delete(other_peer.node_id.clone());
+        peer_storage.apply_batch(batch).unwrap();
+        assert!(!peer_storage.is_known_peer_ip(&ip));
+    }
+
+    /// A store whose batch writes always fail
+    struct FailingBatchDatabase(HashmapDatabase<PeerId, Peer>);
+
+    impl KeyValueStore<PeerId, Peer> for FailingBatchDatabase {
+        fn insert(&self, key: PeerId, value: Peer) -> Result<(), KeyValStoreError> {
+            self.0.insert(key, value)
+        }
+
+        fn get(&self, key: &PeerId) -> Result<Option<Peer>, KeyValStoreError> {
+            self.0.get(key)
+        }
+
+        fn size(&self) -> Result<usize, KeyValStoreError> {
+            self.0.len()
+        }
+
+        fn for_each<F>(&self, f: F) -> Result<(), KeyValStoreError>
+        where F: FnMut(Result<(PeerId, Peer), KeyValStoreError>) -> IterationResult {
+            self.0.for_each(f)
+        }
+
+        fn exists(&self, key: &PeerId) -> Result<bool, KeyValStoreError> {
+            self.0.contains_key(key)
+        }
+
+        fn delete(&self, key: &PeerId) -> Result<(), KeyValStoreError> {
+            self.0.remove(key)
+        }
+
+        fn write_batch(&self, _: Vec<WriteOperation<PeerId, Peer>>) -> Result<(), KeyValStoreError> {
+            Err(KeyValStoreError::DatabaseError("write failed".to_string()))
+        }
+    }
+
+    #[test]
+    fn apply_batch() {
+        let mut peer_storage = PeerStorage::new_indexed(HashmapDatabase::new()).unwrap();
+        let peer1 = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false);
+        let peer2 = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false);
+        peer_storage.add_peer(peer1.clone()).unwrap();
+
+        let mut replaced = peer1.clone();
+        replaced.features = PeerFeatures::COMMUNICATION_CLIENT;
+        let mut batch = PeerWriteBatch::new();
+        batch
+            .upsert(replaced)
+            .upsert(peer2.clone())
+            .delete(peer2.node_id.clone())
+            .upsert(peer2.clone());
+        assert_eq!(peer_storage.apply_batch(batch).unwrap(), 4);
+        assert_eq!(peer_storage.count(), 2);
+        assert_eq!(peer_storage.peer_db.len().unwrap(), 2);
+        assert_eq!(
+            peer_storage.find_by_public_key(&peer1.public_key).unwrap().features,
+            PeerFeatures::COMMUNICATION_CLIENT
+        );
+        assert!(peer_storage.exists_node_id(&peer2.node_id));
+
+        let mut batch = PeerWriteBatch::new();
+        batch.delete(peer1.node_id).delete(peer2.node_id);
+        assert_eq!(peer_storage.apply_batch(batch).unwrap(), 2);
+        assert_eq!(peer_storage.count(), 0);
+        assert_eq!(peer_storage.peer_db.len().unwrap(), 0);
+    }
+
+    #[test]
+    fn apply_batch_failure_changes_nothing() {
+        let mut peer_storage = PeerStorage::new_indexed(FailingBatchDatabase(HashmapDatabase::new())).unwrap();
+        let peer1 = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false);
+        let peer2 = create_test_peer(PeerFeatures::COMMUNICATION_NODE, false, false);
+        peer_storage.add_peer(peer1.clone()).unwrap();
+
+        let mut batch = PeerWriteBatch::new();
+        batch.upsert(peer2.clone()).delete(peer1.node_id.clone());
+        unpack_enum!(PeerManagerError::DatabaseError(_err) = peer_storage.apply_batch(batch).unwrap_err());
+        assert_eq!(peer_storage.count(), 1);
+        assert!(peer_storage.exists_node_id(&peer1.node_id));
+        assert!(!peer_storage.exists_node_id(&peer2.node_id));
+        assert!(peer_storage.find_by_node_id(&peer1.node_id).is_ok());
+    }
 }
@@ -0,0 +1,119 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+/// The level of trust this node places in a peer. Trusted peers (e.g. an operator's own infrastructure nodes) are given
+/// more relaxed limits than normal peers, and untrusted peers are given stricter limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum PeerTrustLevel {
+    /// The peer is not trusted
+    Untrusted,
+    /// The default trust level
+    Normal,
+    /// The peer is trusted by the operator of this node
+    Trusted,
+    /// The peer is a seed peer of this node
+    Seed,
+}
+
+impl PeerTrustLevel {
+    /// Returns true if this is the `Trusted` or `Seed` trust level
+    #[inline]
+    pub fn is_trusted(self) -> bool {
+        self >= PeerTrustLevel::Trusted
+    }
+
+    /// Returns true if this is the `Untrusted` trust level
+    #[inline]
+    pub fn is_untrusted(self) -> bool {
+        self == PeerTrustLevel::Untrusted
+    }
+}
+
+impl Default for PeerTrustLevel {
+    fn default() -> Self {
+        PeerTrustLevel::Normal
+    }
+}
+
+impl fmt::Display for PeerTrustLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use PeerTrustLevel::*;
+        match self {
+            Untrusted => write!(f, "untrusted"),
+            Normal => write!(f, "normal"),
+            Trusted => write!(f, "trusted"),
+            Seed => write!(f, "seed"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid peer trust level '{0}'. Expected one of 'untrusted', 'normal', 'trusted' or 'seed'")]
+pub struct PeerTrustLevelParseError(String);
+
+impl FromStr for PeerTrustLevel {
+    type Err = PeerTrustLevelParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use PeerTrustLevel::*;
+        match s.to_lowercase().as_str() {
+            "untrusted" => Ok(Untrusted),
+            "normal" => Ok(Normal),
+            "trusted" => Ok(Trusted),
+            "seed" => Ok(Seed),
+            _ => Err(PeerTrustLevelParseError(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        for level in &[
+            PeerTrustLevel::Untrusted,
+            PeerTrustLevel::Normal,
+            PeerTrustLevel::Trusted,
+            PeerTrustLevel::Seed,
+        ] {
+            assert_eq!(level.to_string().parse::<PeerTrustLevel>().unwrap(), *level);
+        }
+        assert_eq!("Trusted".parse::<PeerTrustLevel>().unwrap(), PeerTrustLevel::Trusted);
+        assert!("very-trusted".parse::<PeerTrustLevel>().is_err());
+    }
+
+    #[test]
+    fn is_trusted() {
+        assert!(!PeerTrustLevel::Untrusted.is_trusted());
+        assert!(!PeerTrustLevel::Normal.is_trusted());
+        assert!(PeerTrustLevel::Trusted.is_trusted());
+        assert!(PeerTrustLevel::Seed.is_trusted());
+        assert!(PeerTrustLevel::Untrusted.is_untrusted());
+        assert!(!PeerTrustLevel::Normal.is_untrusted());
+    }
+}
@@ -0,0 +1,89 @@
+//  Copyright 2019 The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Pure functions for network region calculations. These contain no storage access so that the routing behaviour of
+//! the peer manager can be verified against the golden vectors in [routing_vectors](super::routing_vectors).
+
+use crate::peer_manager::node_id::{NodeDistance, NodeId};
+
+/// Returns the distance from `region_node_id` to the `n`th closest node in `node_ids`. If `n` is zero or there are
+/// fewer than `n` node ids, the maximum distance is returned i.e. every node is in the region.
+pub fn calc_region_threshold(region_node_id: &NodeId, node_ids: &[NodeId], n: usize) -> NodeDistance {
+    if n == 0 || node_ids.len() < n {
+        return NodeDistance::max_distance();
+    }
+
+    let mut dists = node_ids
+        .iter()
+        .map(|node_id| region_node_id.distance(node_id))
+        .collect::<Vec<_>>();
+    dists.sort();
+    dists.truncate(n);
+    dists.pop().expect("dists cannot be empty at this point")
+}
+
+/// Returns true if `node_id` is at least as close to `region_node_id` as the `n`th closest of either `nodes` or
+/// `clients`.
+pub fn in_network_region(
+    node_id: &NodeId,
+    region_node_id: &NodeId,
+    nodes: &[NodeId],
+    clients: &[NodeId],
+    n: usize,
+) -> bool
+{
+    let region_node_distance = region_node_id.distance(node_id);
+    region_node_distance <= calc_region_threshold(region_node_id, nodes, n) ||
+        region_node_distance <= calc_region_threshold(region_node_id, clients, n)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn node_id(b: u8) -> NodeId {
+        NodeId::try_from(&[b; 13][..]).unwrap()
+    }
+
+    #[test]
+    fn region_threshold() {
+        let region = node_id(0);
+        let nodes = [node_id(4), node_id(1), node_id(2)];
+        assert_eq!(calc_region_threshold(&region, &nodes, 2), region.distance(&node_id(2)));
+        assert_eq!(calc_region_threshold(&region, &nodes, 0), NodeDistance::max_distance());
+        assert_eq!(calc_region_threshold(&region, &nodes, 4), NodeDistance::max_distance());
+        assert_eq!(calc_region_threshold(&region, &[], 1), NodeDistance::max_distance());
+    }
+
+    #[test]
+    fn in_region() {
+        let region = node_id(0);
+        let nodes = [node_id(1), node_id(2), node_id(8)];
+        let clients = [node_id(16), node_id(32)];
+        assert!(in_network_region(&node_id(2), &region, &nodes, &clients, 2));
+        assert!(in_network_region(&node_id(3), &region, &nodes, &clients, 2));
+        assert!(!in_network_region(&node_id(64), &region, &nodes, &clients, 2));
+        // Fewer clients than n, so the client region is unbounded
+        assert!(in_network_region(&node_id(64), &region, &nodes, &clients, 3));
+    }
+}
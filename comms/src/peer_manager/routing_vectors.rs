@@ -0,0 +1,175 @@
+//  Copyright 2019 The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Deterministic test vectors for NodeId derivation and routing calculations.
+//!
+//! The golden vectors in `comms/test_vectors/routing.json` were generated by [RoutingVectors::generate] from the
+//! public keys listed in that file. Alternative implementations can use them to check that their NodeId derivation,
+//! closest peer selection and network region checks match this implementation byte-for-byte.
+
+use crate::{
+    peer_manager::{node_id::NodeId, region},
+    types::CommsPublicKey,
+};
+use serde::{Deserialize, Serialize};
+use tari_crypto::tari_utilities::hex::Hex;
+
+/// The number of closest peers selected in each closest vector
+pub const CLOSEST_VECTOR_K: usize = 3;
+/// The region size used in each region vector
+pub const REGION_VECTOR_N: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutingVectors {
+    pub node_id_derivation: Vec<NodeIdVector>,
+    pub closest: Vec<ClosestVector>,
+    pub region: Vec<RegionVector>,
+}
+
+/// A NodeId derived from a public key. All values are hex encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeIdVector {
+    pub public_key: String,
+    pub node_id: String,
+}
+
+/// The `k` closest candidates to `node_id`, ordered from closest to furthest. All values are hex encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClosestVector {
+    pub node_id: String,
+    pub candidates: Vec<String>,
+    pub k: usize,
+    pub expected: Vec<String>,
+}
+
+/// Region thresholds around `region_node_id` and the node ids (from all nodes and clients) that are in the region.
+/// All values are hex encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegionVector {
+    pub region_node_id: String,
+    pub nodes: Vec<String>,
+    pub clients: Vec<String>,
+    pub n: usize,
+    pub node_threshold: String,
+    pub client_threshold: String,
+    pub in_region: Vec<String>,
+}
+
+impl RoutingVectors {
+    /// Generate test vectors from the given public keys. The NodeIds derived from the public keys are used as inputs
+    /// for the closest and region vectors. For region vectors, NodeIds at even indexes are nodes and those at odd
+    /// indexes are clients.
+    pub fn generate(public_keys: &[CommsPublicKey]) -> Self {
+        let node_ids = public_keys.iter().map(NodeId::from_public_key).collect::<Vec<_>>();
+
+        let node_id_derivation = public_keys
+            .iter()
+            .zip(node_ids.iter())
+            .map(|(pk, node_id)| NodeIdVector {
+                public_key: pk.to_hex(),
+                node_id: node_id.to_hex(),
+            })
+            .collect();
+
+        let others = |i: usize| {
+            node_ids
+                .iter()
+                .enumerate()
+                .filter(move |(j, _)| *j != i)
+                .map(|(j, node_id)| (j, node_id.clone()))
+        };
+
+        let closest = node_ids
+            .iter()
+            .enumerate()
+            .map(|(i, node_id)| {
+                let candidates = others(i).map(|(_, n)| n).collect::<Vec<_>>();
+                ClosestVector {
+                    node_id: node_id.to_hex(),
+                    candidates: to_hex_vec(&candidates),
+                    k: CLOSEST_VECTOR_K,
+                    expected: to_hex_vec(&node_id.closest(&candidates, CLOSEST_VECTOR_K)),
+                }
+            })
+            .collect();
+
+        let region = node_ids
+            .iter()
+            .enumerate()
+            .map(|(i, region_node_id)| {
+                let nodes = others(i)
+                    .filter(|(j, _)| j % 2 == 0)
+                    .map(|(_, n)| n)
+                    .collect::<Vec<_>>();
+                let clients = others(i)
+                    .filter(|(j, _)| j % 2 == 1)
+                    .map(|(_, n)| n)
+                    .collect::<Vec<_>>();
+                let in_region = others(i)
+                    .map(|(_, n)| n)
+                    .filter(|n| region::in_network_region(n, region_node_id, &nodes, &clients, REGION_VECTOR_N))
+                    .collect::<Vec<_>>();
+                RegionVector {
+                    region_node_id: region_node_id.to_hex(),
+                    node_threshold: region::calc_region_threshold(region_node_id, &nodes, REGION_VECTOR_N).to_hex(),
+                    client_threshold: region::calc_region_threshold(region_node_id, &clients, REGION_VECTOR_N).to_hex(),
+                    nodes: to_hex_vec(&nodes),
+                    clients: to_hex_vec(&clients),
+                    n: REGION_VECTOR_N,
+                    in_region: to_hex_vec(&in_region),
+                }
+            })
+            .collect();
+
+        Self {
+            node_id_derivation,
+            closest,
+            region,
+        }
+    }
+}
+
+fn to_hex_vec(node_ids: &[NodeId]) -> Vec<String> {
+    node_ids.iter().map(Hex::to_hex).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GOLDEN_VECTORS: &str = include_str!("../../test_vectors/routing.json");
+
+    #[test]
+    fn golden_vectors() {
+        let expected = serde_json::from_str::<RoutingVectors>(GOLDEN_VECTORS).unwrap();
+        let public_keys = expected
+            .node_id_derivation
+            .iter()
+            .map(|v| CommsPublicKey::from_hex(&v.public_key).unwrap())
+            .collect::<Vec<_>>();
+
+        let vectors = RoutingVectors::generate(&public_keys);
+        assert_eq!(vectors.node_id_derivation, expected.node_id_derivation);
+        assert_eq!(vectors.closest, expected.closest);
+        assert_eq!(vectors.region, expected.region);
+    }
+}
@@ -21,7 +21,7 @@
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::peer_manager::{migrations::MIGRATION_VERSION_KEY, Peer, PeerId};
-use tari_storage::{IterationResult, KeyValStoreError, KeyValueStore};
+use tari_storage::{IterationResult, KeyValStoreError, KeyValueStore, WriteOperation};
 
 // TODO: Hack to get around current peer database design. Once PeerManager uses a PeerDatabase abstraction and the LMDB
 //       implementation has access to multiple databases we can remove this wrapper.
@@ -85,4 +85,16 @@ where T: KeyValueStore<PeerId, Peer>
         }
         self.inner.delete(key)
     }
+
+    fn write_batch(&self, operations: Vec<WriteOperation<PeerId, Peer>>) -> Result<(), KeyValStoreError> {
+        let touches_version_key = operations.iter().any(|op| match op {
+            WriteOperation::Insert(key, _) | WriteOperation::Delete(key) => *key == MIGRATION_VERSION_KEY,
+        });
+        if touches_version_key {
+            panic!(
+                "MIGRATION_VERSION_KEY used in `KeyValueWrapper::write_batch`. MIGRATION_VERSION_KEY is a reserved key"
+            );
+        }
+        self.inner.write_batch(operations)
+    }
 }
@@ -0,0 +1,159 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    peer_manager::{NodeId, Peer, PeerId, PeerManagerError, PeerStorage},
+    types::CommsPublicKey,
+};
+use tari_storage::KeyValueStore;
+
+/// A single operation within a [PeerWriteBatch](self::PeerWriteBatch)
+#[derive(Debug, Clone)]
+pub enum PeerWriteOp {
+    /// Add the peer, or replace the stored peer that has the same public key
+    Upsert(Peer),
+    /// Remove the peer with the given NodeId. Deleting a peer that does not exist is a no-op.
+    Delete(NodeId),
+}
+
+/// A set of peer upserts and deletes that are committed to the peer database atomically. Concurrent readers of the
+/// `PeerManager` either see none or all of the operations in the batch.
+#[derive(Debug, Clone, Default)]
+pub struct PeerWriteBatch {
+    operations: Vec<PeerWriteOp>,
+}
+
+impl PeerWriteBatch {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Queue an upsert of the given peer
+    pub fn upsert(&mut self, peer: Peer) -> &mut Self {
+        self.operations.push(PeerWriteOp::Upsert(peer));
+        self
+    }
+
+    /// Queue the deletion of the peer with the given NodeId
+    pub fn delete(&mut self, node_id: NodeId) -> &mut Self {
+        self.operations.push(PeerWriteOp::Delete(node_id));
+        self
+    }
+
+    /// The number of queued operations
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    pub fn operations(&self) -> &[PeerWriteOp] {
+        &self.operations
+    }
+
+    pub(super) fn into_operations(self) -> Vec<PeerWriteOp> {
+        self.operations
+    }
+
+    /// Returns the pending state of the peer with the given NodeId. `None` is returned if the batch does not touch the
+    /// peer, `Some(None)` if the peer is deleted by the batch and `Some(Some(peer))` if it is upserted.
+    fn pending_by_node_id(&self, node_id: &NodeId) -> Option<Option<&Peer>> {
+        self.operations.iter().rev().find_map(|op| match op {
+            PeerWriteOp::Upsert(peer) if peer.node_id == *node_id => Some(Some(peer)),
+            PeerWriteOp::Delete(n) if n == node_id => Some(None),
+            _ => None,
+        })
+    }
+
+    fn pending_by_public_key(&self, public_key: &CommsPublicKey) -> Option<&Peer> {
+        self.operations.iter().rev().find_map(|op| match op {
+            PeerWriteOp::Upsert(peer) if peer.public_key == *public_key => Some(peer),
+            _ => None,
+        })
+    }
+}
+
+/// Builds a [PeerWriteBatch](self::PeerWriteBatch) while the peer database write lock is held (see
+/// `PeerManager::write_batch`). Reads reflect the peer database as it was when the batch started, with the operations
+/// already queued in the batch applied on top.
+pub struct PeerBatchWriter<'a, DS> {
+    storage: &'a PeerStorage<DS>,
+    batch: PeerWriteBatch,
+}
+
+impl<'a, DS> PeerBatchWriter<'a, DS>
+where DS: KeyValueStore<PeerId, Peer>
+{
+    pub(super) fn new(storage: &'a PeerStorage<DS>) -> Self {
+        Self {
+            storage,
+            batch: PeerWriteBatch::new(),
+        }
+    }
+
+    /// Find the peer with the given NodeId
+    pub fn find_by_node_id(&self, node_id: &NodeId) -> Result<Peer, PeerManagerError> {
+        match self.batch.pending_by_node_id(node_id) {
+            Some(Some(peer)) => Ok(peer.clone()),
+            Some(None) => Err(PeerManagerError::PeerNotFoundError),
+            None => self.storage.find_by_node_id(node_id),
+        }
+    }
+
+    /// Find the peer with the given public key
+    pub fn find_by_public_key(&self, public_key: &CommsPublicKey) -> Result<Peer, PeerManagerError> {
+        if let Some(peer) = self.batch.pending_by_public_key(public_key) {
+            return Ok(peer.clone());
+        }
+        let peer = self.storage.find_by_public_key(public_key)?;
+        match self.batch.pending_by_node_id(&peer.node_id) {
+            Some(None) => Err(PeerManagerError::PeerNotFoundError),
+            _ => Ok(peer),
+        }
+    }
+
+    /// Check if a peer with the given NodeId exists
+    pub fn exists_node_id(&self, node_id: &NodeId) -> bool {
+        match self.batch.pending_by_node_id(node_id) {
+            Some(pending) => pending.is_some(),
+            None => self.storage.exists_node_id(node_id),
+        }
+    }
+
+    /// Queue an upsert of the given peer
+    pub fn upsert(&mut self, peer: Peer) -> &mut Self {
+        self.batch.upsert(peer);
+        self
+    }
+
+    /// Queue the deletion of the peer with the given NodeId
+    pub fn delete(&mut self, node_id: NodeId) -> &mut Self {
+        self.batch.delete(node_id);
+        self
+    }
+
+    pub(super) fn into_batch(self) -> PeerWriteBatch {
+        self.batch
+    }
+}
@@ -22,7 +22,7 @@
 
 use crate::{
     message::{InboundMessage, OutboundMessage},
-    pipeline::SinkService,
+    pipeline::{OverflowPolicy, SinkService},
 };
 use futures::channel::mpsc;
 use thiserror::Error;
@@ -36,6 +36,7 @@ type OutboundMessageSinkService = SinkService<mpsc::Sender<OutboundMessage>>;
 #[derive(Default)]
 pub struct Builder<TInSvc, TOutSvc, TOutReq> {
     max_concurrent_inbound_tasks: usize,
+    inbound_overflow_policy: OverflowPolicy<InboundMessage>,
     outbound_buffer_size: usize,
     inbound: Option<TInSvc>,
     outbound_rx: Option<mpsc::Receiver<TOutReq>>,
@@ -46,6 +47,7 @@ impl Builder<(), (), ()> {
     pub fn new() -> Self {
         Self {
             max_concurrent_inbound_tasks: DEFAULT_MAX_CONCURRENT_TASKS,
+            inbound_overflow_policy: Default::default(),
             outbound_buffer_size: DEFAULT_OUTBOUND_BUFFER_SIZE,
             inbound: None,
             outbound_rx: None,
@@ -60,6 +62,13 @@ impl<TInSvc, TOutSvc, TOutReq> Builder<TInSvc, TOutSvc, TOutReq> {
         self
     }
 
+    /// Set the policy to apply when `max_concurrent_inbound_tasks` inbound messages are being handled.
+    /// Default: `OverflowPolicy::Wait`
+    pub fn inbound_overflow_policy(mut self, policy: OverflowPolicy<InboundMessage>) -> Self {
+        self.inbound_overflow_policy = policy;
+        self
+    }
+
     pub fn outbound_buffer_size(mut self, buf_size: usize) -> Self {
         self.outbound_buffer_size = buf_size;
         self
@@ -76,6 +85,7 @@ impl<TInSvc, TOutSvc, TOutReq> Builder<TInSvc, TOutSvc, TOutReq> {
             outbound_pipeline_factory: Some(Box::new(factory)),
 
             max_concurrent_inbound_tasks: self.max_concurrent_inbound_tasks,
+            inbound_overflow_policy: self.inbound_overflow_policy,
             inbound: self.inbound,
             outbound_buffer_size: self.outbound_buffer_size,
         }
@@ -87,6 +97,7 @@ impl<TInSvc, TOutSvc, TOutReq> Builder<TInSvc, TOutSvc, TOutReq> {
             inbound: Some(inbound),
 
             max_concurrent_inbound_tasks: self.max_concurrent_inbound_tasks,
+            inbound_overflow_policy: self.inbound_overflow_policy,
             outbound_rx: self.outbound_rx,
             outbound_pipeline_factory: self.outbound_pipeline_factory,
             outbound_buffer_size: self.outbound_buffer_size,
@@ -130,6 +141,7 @@ where
 
         Ok(Config {
             max_concurrent_inbound_tasks: self.max_concurrent_inbound_tasks,
+            inbound_overflow_policy: self.inbound_overflow_policy,
             inbound,
             outbound,
         })
@@ -151,6 +163,7 @@ pub struct OutboundPipelineConfig<TInStream, TPipeline> {
 
 pub struct Config<TInSvc, TOutSvc, TOutReq> {
     pub max_concurrent_inbound_tasks: usize,
+    pub inbound_overflow_policy: OverflowPolicy<InboundMessage>,
     pub inbound: TInSvc,
     pub outbound: OutboundPipelineConfig<mpsc::Receiver<TOutReq>, TOutSvc>,
 }
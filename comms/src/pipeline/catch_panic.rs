@@ -0,0 +1,233 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    message::{InboundMessage, MessageTag, OutboundMessage},
+    pipeline::PipelineError,
+};
+use futures::{future::BoxFuture, task::Context, FutureExt};
+use log::*;
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::Poll,
+};
+use tower::{layer::Layer, Service};
+
+const LOG_TARGET: &str = "comms::pipeline::catch_panic";
+
+/// Implemented by pipeline requests so that the message tag can be logged if a service panics while handling the
+/// request.
+pub trait TaggedRequest {
+    fn message_tag(&self) -> Option<MessageTag>;
+}
+
+impl TaggedRequest for InboundMessage {
+    fn message_tag(&self) -> Option<MessageTag> {
+        Some(self.tag)
+    }
+}
+
+impl TaggedRequest for OutboundMessage {
+    fn message_tag(&self) -> Option<MessageTag> {
+        Some(self.tag)
+    }
+}
+
+/// Counts the number of panics caught by `CatchPanic` services. Clones share the same count.
+#[derive(Debug, Clone, Default)]
+pub struct PanicCounter(Arc<AtomicUsize>);
+
+impl PanicCounter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the number of panics caught so far
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn increment(&self) -> usize {
+        self.0.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// # Catch panic middleware
+///
+/// Catches panics that occur while the inner services are handling a request, converting them into a
+/// `PipelineError`. Without this, a panic in a middleware layer unwinds the task handling the message, taking any
+/// other work in that task with it. The panic is logged along with the message tag and counted.
+#[derive(Clone)]
+pub struct CatchPanic<S> {
+    inner: S,
+    name: &'static str,
+    counter: PanicCounter,
+}
+
+impl<S> CatchPanic<S> {
+    pub fn new(name: &'static str, counter: PanicCounter, service: S) -> Self {
+        Self {
+            inner: service,
+            name,
+            counter,
+        }
+    }
+}
+
+impl<S, T> Service<T> for CatchPanic<S>
+where
+    S: Service<T> + Send,
+    S::Error: Into<PipelineError>,
+    S::Future: Send + 'static,
+    T: TaggedRequest,
+{
+    type Error = PipelineError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = S::Response;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let inner = &mut self.inner;
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.poll_ready(cx))) {
+            Ok(poll) => poll.map_err(Into::into),
+            Err(payload) => Poll::Ready(Err(handle_panic(self.name, &self.counter, None, payload))),
+        }
+    }
+
+    fn call(&mut self, request: T) -> Self::Future {
+        let tag = request.message_tag();
+        let name = self.name;
+        let counter = self.counter.clone();
+        let inner = &mut self.inner;
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.call(request))) {
+            Ok(fut) => AssertUnwindSafe(fut)
+                .catch_unwind()
+                .map(move |result| match result {
+                    Ok(result) => result.map_err(Into::into),
+                    Err(payload) => Err(handle_panic(name, &counter, tag, payload)),
+                })
+                .boxed(),
+            Err(payload) => {
+                let err = handle_panic(name, &counter, tag, payload);
+                async move { Err(err) }.boxed()
+            },
+        }
+    }
+}
+
+fn handle_panic(
+    name: &str,
+    counter: &PanicCounter,
+    tag: Option<MessageTag>,
+    payload: Box<dyn Any + Send>,
+) -> PipelineError
+{
+    let msg = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "<unknown panic payload>".to_string());
+    let num_panics = counter.increment();
+    error!(
+        target: LOG_TARGET,
+        "{} pipeline panicked while handling message {}: '{}' ({} panic(s) caught in total)",
+        name,
+        tag.map(|t| t.to_string()).unwrap_or_else(|| "<untagged>".to_string()),
+        msg,
+        num_panics
+    );
+    anyhow::anyhow!("{} pipeline panicked: {}", name, msg)
+}
+
+/// Layer which wraps a service in the [CatchPanic](self::CatchPanic) middleware
+pub struct CatchPanicLayer {
+    name: &'static str,
+    counter: PanicCounter,
+}
+
+impl CatchPanicLayer {
+    /// Create a new CatchPanicLayer. `name` is used to identify the pipeline in logs. Caught panics are counted by
+    /// `counter`.
+    pub fn new(name: &'static str, counter: PanicCounter) -> Self {
+        Self { name, counter }
+    }
+}
+
+impl<S> Layer<S> for CatchPanicLayer {
+    type Service = CatchPanic<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        CatchPanic::new(self.name, self.counter.clone(), service)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{runtime, test_utils::node_id};
+    use bytes::Bytes;
+    use futures::future;
+    use tower::{service_fn, ServiceExt};
+
+    #[runtime::test_basic]
+    async fn converts_panics_to_errors() {
+        let counter = PanicCounter::new();
+        let service = service_fn(|msg: InboundMessage| {
+            if msg.body.is_empty() {
+                panic!("empty body");
+            }
+            future::ready(Result::<_, PipelineError>::Ok(()))
+        });
+        let mut service = CatchPanicLayer::new("Test", counter.clone()).layer(service);
+
+        let msg = InboundMessage::new(node_id::random(), Bytes::from_static(b"A"));
+        service.ready_and().await.unwrap().call(msg).await.unwrap();
+        assert_eq!(counter.get(), 0);
+
+        let msg = InboundMessage::new(node_id::random(), Bytes::new());
+        let err = service.ready_and().await.unwrap().call(msg).await.unwrap_err();
+        assert!(err.to_string().contains("empty body"));
+        assert_eq!(counter.get(), 1);
+
+        // The service can still be used after a panic
+        let msg = InboundMessage::new(node_id::random(), Bytes::from_static(b"B"));
+        service.ready_and().await.unwrap().call(msg).await.unwrap();
+
+        let service = service_fn(|msg: InboundMessage| async move {
+            if msg.body.is_empty() {
+                panic!("async panic");
+            }
+            Result::<_, PipelineError>::Ok(())
+        });
+        let err = CatchPanicLayer::new("Test", counter.clone())
+            .layer(service)
+            .oneshot(InboundMessage::new(node_id::random(), Bytes::new()))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("async panic"));
+        assert_eq!(counter.get(), 2);
+    }
+}
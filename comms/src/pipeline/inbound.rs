@@ -20,24 +20,79 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use crate::bounded_executor::BoundedExecutor;
-use futures::{future::FusedFuture, stream::FusedStream, Stream, StreamExt};
+use crate::{bounded_executor::BoundedExecutor, message::InboundMessage, peer_manager::NodeId};
+use futures::{future::FusedFuture, pin_mut, stream::FusedStream, FutureExt, Stream, StreamExt};
 use log::*;
-use std::fmt::Display;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    fmt::Display,
+};
 use tari_shutdown::ShutdownSignal;
 use tower::{Service, ServiceExt};
 
 const LOG_TARGET: &str = "comms::pipeline::inbound";
 
+/// Determines what the inbound pipeline does when all executor slots are in use.
+pub enum OverflowPolicy<T> {
+    /// Stop reading from the inbound stream until a slot becomes available. This applies backpressure to the
+    /// inbound message stream.
+    Wait,
+    /// Continue reading from the inbound stream, buffering up to `max_pending` items. Once the buffer is full, the
+    /// item with the lowest priority (as returned by `priority`) is dropped. Of items with equal priority, the most
+    /// recently received is dropped. Buffered items are handled in order of highest priority.
+    DropLowestPriority { max_pending: usize, priority: fn(&T) -> u8 },
+    /// Continue reading from the inbound stream, queuing up to `max_pending_per_peer` items for each peer (as returned
+    /// by `peer`). Queued items are handled round-robin across peers so that a single busy peer cannot monopolise the
+    /// pipeline. Once a peer's queue is full, further items from that peer are dropped.
+    FairQueue {
+        max_pending_per_peer: usize,
+        peer: fn(&T) -> NodeId,
+    },
+}
+
+impl OverflowPolicy<InboundMessage> {
+    /// Fair queuing of inbound messages by the peer that sent them
+    pub fn fair_queue(max_pending_per_peer: usize) -> Self {
+        OverflowPolicy::FairQueue {
+            max_pending_per_peer,
+            peer: |msg| msg.source_peer.clone(),
+        }
+    }
+}
+
+impl<T> Default for OverflowPolicy<T> {
+    fn default() -> Self {
+        OverflowPolicy::Wait
+    }
+}
+
+impl<T> fmt::Debug for OverflowPolicy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverflowPolicy::Wait => write!(f, "Wait"),
+            OverflowPolicy::DropLowestPriority { max_pending, .. } => {
+                write!(f, "DropLowestPriority(max_pending = {})", max_pending)
+            },
+            OverflowPolicy::FairQueue {
+                max_pending_per_peer, ..
+            } => write!(f, "FairQueue(max_pending_per_peer = {})", max_pending_per_peer),
+        }
+    }
+}
+
 /// Calls a Service with every item received from a Stream.
 /// The difference between this can ServiceExt::call_all is
 /// that ServicePipeline doesn't keep the result of the service
 /// call and that it spawns a task for each incoming item.
-pub struct Inbound<TSvc, TStream> {
+pub struct Inbound<TSvc, TStream>
+where TStream: Stream
+{
     executor: BoundedExecutor,
     service: TSvc,
     stream: TStream,
     shutdown_signal: ShutdownSignal,
+    overflow_policy: OverflowPolicy<TStream::Item>,
 }
 
 impl<TSvc, TStream> Inbound<TSvc, TStream>
@@ -54,10 +109,30 @@ where
             stream,
             service,
             shutdown_signal,
+            overflow_policy: Default::default(),
         }
     }
 
-    pub async fn run(mut self) {
+    /// Set the policy to apply when all executor slots are in use. Default: `OverflowPolicy::Wait`
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy<TStream::Item>) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    pub async fn run(self) {
+        match self.overflow_policy {
+            OverflowPolicy::Wait => self.run_wait().await,
+            OverflowPolicy::DropLowestPriority { max_pending, priority } => {
+                self.run_buffered(PendingItems::new(max_pending, priority)).await
+            },
+            OverflowPolicy::FairQueue {
+                max_pending_per_peer,
+                peer,
+            } => self.run_buffered(PeerQueues::new(max_pending_per_peer, peer)).await,
+        }
+    }
+
+    async fn run_wait(mut self) {
         while let Some(item) = self.stream.next().await {
             // Check if the shutdown signal has been triggered.
             // If there are messages in the stream, drop them. Otherwise the stream is empty,
@@ -69,23 +144,216 @@ where
                 );
                 return;
             }
-            let service = self.service.clone();
-            // Call the service in it's own spawned task
-            self.executor
-                .spawn(async move {
-                    if let Err(err) = service.oneshot(item).await {
-                        warn!(target: LOG_TARGET, "Inbound pipeline returned an error: '{}'", err);
+            self.spawn_service_call(item).await;
+        }
+    }
+
+    /// Continue reading from the stream while all executor slots are in use, buffering items in `pending`
+    async fn run_buffered<P: PendingQueue<TStream::Item>>(mut self, mut pending: P) {
+        loop {
+            while !pending.is_empty() && self.executor.can_spawn() {
+                let item = pending.pop_next().expect("pending is not empty");
+                self.spawn_service_call(item).await;
+            }
+
+            let item = if pending.is_empty() {
+                self.stream.next().await
+            } else {
+                let slot_available = self.executor.wait_available().fuse();
+                pin_mut!(slot_available);
+                futures::select! {
+                    item = self.stream.next() => item,
+                    _ = slot_available => continue,
+                }
+            };
+
+            match item {
+                Some(item) => {
+                    if self.shutdown_signal.is_terminated() {
+                        info!(
+                            target: LOG_TARGET,
+                            "Inbound pipeline is terminating because the shutdown signal is triggered"
+                        );
+                        return;
+                    }
+                    if pending.push(item) {
+                        warn!(
+                            target: LOG_TARGET,
+                            "Inbound pipeline is overloaded. Dropped {} ({} dropped in total)",
+                            pending.dropped_description(),
+                            pending.num_dropped()
+                        );
                     }
-                })
-                .await;
+                },
+                None => break,
+            }
         }
+
+        // The stream has ended, handle the remaining items
+        while let Some(item) = pending.pop_next() {
+            self.spawn_service_call(item).await;
+        }
+    }
+
+    async fn spawn_service_call(&self, item: TStream::Item) {
+        let service = self.service.clone();
+        // Call the service in it's own spawned task
+        self.executor
+            .spawn(async move {
+                if let Err(err) = service.oneshot(item).await {
+                    warn!(target: LOG_TARGET, "Inbound pipeline returned an error: '{}'", err);
+                }
+            })
+            .await;
+    }
+}
+
+/// A bounded buffer of items that are waiting for an executor slot
+trait PendingQueue<T> {
+    /// Adds an item, returning true if an item was dropped to make space for it
+    fn push(&mut self, item: T) -> bool;
+    /// Removes the next item to handle
+    fn pop_next(&mut self) -> Option<T>;
+    fn is_empty(&self) -> bool;
+    fn num_dropped(&self) -> usize;
+    /// Describes the item that is dropped when the buffer is full
+    fn dropped_description(&self) -> &'static str;
+}
+
+/// A bounded buffer of items that evicts the lowest priority item when full
+struct PendingItems<T> {
+    items: Vec<(u8, u64, T)>,
+    max_pending: usize,
+    priority: fn(&T) -> u8,
+    next_seq: u64,
+    num_dropped: usize,
+}
+
+impl<T> PendingItems<T> {
+    fn new(max_pending: usize, priority: fn(&T) -> u8) -> Self {
+        Self {
+            items: Vec::with_capacity(max_pending),
+            max_pending,
+            priority,
+            next_seq: 0,
+            num_dropped: 0,
+        }
+    }
+
+    /// Removes the item with the highest priority. Of items with equal priority, the oldest is returned.
+    fn pop_highest(&mut self) -> Option<T> {
+        let (pos, _) = self
+            .items
+            .iter()
+            .enumerate()
+            .max_by(|(_, (p_a, s_a, _)), (_, (p_b, s_b, _))| p_a.cmp(p_b).then(s_b.cmp(s_a)))?;
+        Some(self.items.remove(pos).2)
+    }
+}
+
+impl<T> PendingQueue<T> for PendingItems<T> {
+    fn push(&mut self, item: T) -> bool {
+        let priority = (self.priority)(&item);
+        self.items.push((priority, self.next_seq, item));
+        self.next_seq += 1;
+        if self.items.len() <= self.max_pending {
+            return false;
+        }
+        // Lowest priority, then most recently received
+        let (pos, _) = self
+            .items
+            .iter()
+            .enumerate()
+            .min_by(|(_, (p_a, s_a, _)), (_, (p_b, s_b, _))| p_a.cmp(p_b).then(s_b.cmp(s_a)))
+            .expect("items is not empty");
+        self.items.remove(pos);
+        self.num_dropped += 1;
+        true
+    }
+
+    fn pop_next(&mut self) -> Option<T> {
+        self.pop_highest()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn num_dropped(&self) -> usize {
+        self.num_dropped
+    }
+
+    fn dropped_description(&self) -> &'static str {
+        "lowest priority item"
+    }
+}
+
+/// Bounded per-peer queues of items that are handled round-robin across peers
+struct PeerQueues<T> {
+    queues: HashMap<NodeId, VecDeque<T>>,
+    /// Peers that have pending items, in the order that they will be serviced
+    ready: VecDeque<NodeId>,
+    max_pending_per_peer: usize,
+    peer: fn(&T) -> NodeId,
+    num_dropped: usize,
+}
+
+impl<T> PeerQueues<T> {
+    fn new(max_pending_per_peer: usize, peer: fn(&T) -> NodeId) -> Self {
+        Self {
+            queues: HashMap::new(),
+            ready: VecDeque::new(),
+            max_pending_per_peer,
+            peer,
+            num_dropped: 0,
+        }
+    }
+}
+
+impl<T> PendingQueue<T> for PeerQueues<T> {
+    fn push(&mut self, item: T) -> bool {
+        let peer = (self.peer)(&item);
+        let queue = self.queues.entry(peer.clone()).or_insert_with(VecDeque::new);
+        if queue.len() >= self.max_pending_per_peer {
+            self.num_dropped += 1;
+            return true;
+        }
+        if queue.is_empty() {
+            self.ready.push_back(peer);
+        }
+        queue.push_back(item);
+        false
+    }
+
+    fn pop_next(&mut self) -> Option<T> {
+        let peer = self.ready.pop_front()?;
+        let queue = self.queues.get_mut(&peer).expect("ready peer has a queue");
+        let item = queue.pop_front();
+        if queue.is_empty() {
+            self.queues.remove(&peer);
+        } else {
+            self.ready.push_back(peer);
+        }
+        item
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ready.is_empty()
+    }
+
+    fn num_dropped(&self) -> usize {
+        self.num_dropped
+    }
+
+    fn dropped_description(&self) -> &'static str {
+        "item from peer with a full queue"
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::runtime;
+    use crate::{runtime, test_utils::node_id};
     use futures::{channel::mpsc, future, stream};
     use std::time::Duration;
     use tari_shutdown::Shutdown;
@@ -122,4 +390,74 @@ mod test {
             .unwrap()
             .unwrap();
     }
+
+    #[test]
+    fn pending_items_drops_lowest_priority() {
+        let mut pending = PendingItems::new(3, |i: &u8| *i);
+        assert_eq!(pending.push(2), false);
+        assert_eq!(pending.push(1), false);
+        assert_eq!(pending.push(3), false);
+        // 1 is dropped
+        assert_eq!(pending.push(2), true);
+        // The new 0 is dropped
+        assert_eq!(pending.push(0), true);
+        assert_eq!(pending.num_dropped(), 2);
+
+        assert_eq!(pending.pop_highest(), Some(3));
+        assert_eq!(pending.pop_highest(), Some(2));
+        assert_eq!(pending.pop_highest(), Some(2));
+        assert_eq!(pending.pop_highest(), None);
+    }
+
+    #[test]
+    fn peer_queues_round_robin() {
+        let peer_a = node_id::random();
+        let peer_b = node_id::random();
+        let mut pending = PeerQueues::new(2, |(peer, _): &(NodeId, u8)| peer.clone());
+        assert_eq!(pending.push((peer_a.clone(), 1)), false);
+        assert_eq!(pending.push((peer_a.clone(), 2)), false);
+        // peer_a's queue is full
+        assert_eq!(pending.push((peer_a, 3)), true);
+        assert_eq!(pending.push((peer_b, 4)), false);
+        assert_eq!(pending.num_dropped(), 1);
+
+        let order = (0..3).map(|_| pending.pop_next().unwrap().1).collect::<Vec<_>>();
+        assert_eq!(order, vec![1, 4, 2]);
+        assert!(pending.is_empty());
+        assert!(pending.queues.is_empty());
+        assert!(pending.pop_next().is_none());
+    }
+
+    #[runtime::test_basic]
+    async fn run_drop_lowest_priority() {
+        let items = vec![1u8, 2, 3, 4, 5, 6];
+        let stream = stream::iter(items.clone()).fuse();
+
+        let (mut out_tx, mut out_rx) = mpsc::channel(items.len());
+
+        let executor = Handle::current();
+        let shutdown = Shutdown::new();
+        let pipeline = Inbound::new(
+            BoundedExecutor::new(executor.clone(), 1),
+            stream,
+            service_fn(move |req| {
+                out_tx.try_send(req).unwrap();
+                future::ready(Result::<_, String>::Ok(()))
+            }),
+            shutdown.to_signal(),
+        )
+        .with_overflow_policy(OverflowPolicy::DropLowestPriority {
+            max_pending: 10,
+            priority: |i| *i,
+        });
+        let spawned_task = executor.spawn(pipeline.run());
+
+        let received = collect_stream!(out_rx, take = items.len(), timeout = Duration::from_secs(10));
+        assert!(received.iter().all(|i| items.contains(i)));
+
+        time::timeout(Duration::from_secs(5), spawned_task)
+            .await
+            .unwrap()
+            .unwrap();
+    }
 }
@@ -35,11 +35,15 @@
 mod builder;
 pub use builder::{Builder, Config, PipelineBuilderError};
 
+mod catch_panic;
+pub use catch_panic::{CatchPanic, CatchPanicLayer, PanicCounter, TaggedRequest};
+
 mod sink;
 pub use sink::SinkService;
 
 mod inbound;
 pub(crate) use inbound::Inbound;
+pub use inbound::OverflowPolicy;
 
 mod outbound;
 pub(crate) use outbound::Outbound;
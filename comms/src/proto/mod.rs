@@ -28,6 +28,10 @@ pub(crate) mod identity {
     outdir_include!("tari.comms.identity.rs");
 }
 
+pub(crate) mod relay {
+    outdir_include!("tari.comms.relay.rs");
+}
+
 pub(crate) mod rpc {
     outdir_include!("tari.comms.rpc.rs");
 }
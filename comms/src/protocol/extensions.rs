@@ -21,8 +21,10 @@
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use crate::{
+    builder::ShutdownReporter,
     connectivity::ConnectivityRequester,
     protocol::{ProtocolId, ProtocolNotificationTx, Protocols},
+    security_events::SecurityEventPublisher,
     PeerManager,
     Substream,
 };
@@ -104,6 +106,8 @@ pub struct ProtocolExtensionContext {
     protocols: Option<Protocols<Substream>>,
     complete_signals: Vec<ShutdownSignal>,
     shutdown_signal: ShutdownSignal,
+    shutdown_reporter: ShutdownReporter,
+    security_events: SecurityEventPublisher,
 }
 
 impl ProtocolExtensionContext {
@@ -111,6 +115,8 @@ impl ProtocolExtensionContext {
         connectivity: ConnectivityRequester,
         peer_manager: Arc<PeerManager>,
         shutdown_signal: ShutdownSignal,
+        shutdown_reporter: ShutdownReporter,
+        security_events: SecurityEventPublisher,
     ) -> Self
     {
         Self {
@@ -119,6 +125,8 @@ impl ProtocolExtensionContext {
             protocols: Some(Protocols::new()),
             complete_signals: Vec::new(),
             shutdown_signal,
+            shutdown_reporter,
+            security_events,
         }
     }
 
@@ -153,6 +161,16 @@ impl ProtocolExtensionContext {
         self.shutdown_signal.clone()
     }
 
+    /// Returns the `ShutdownReporter` used to record work that is dropped or in flight when comms shuts down
+    pub fn shutdown_reporter(&self) -> ShutdownReporter {
+        self.shutdown_reporter.clone()
+    }
+
+    /// Returns the `SecurityEventPublisher` used to report security-relevant events to external intrusion detection
+    pub fn security_events(&self) -> SecurityEventPublisher {
+        self.security_events.clone()
+    }
+
     pub(crate) fn drain_complete_signals(&mut self) -> Vec<ShutdownSignal> {
         self.complete_signals.drain(..).collect()
     }
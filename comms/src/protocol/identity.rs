@@ -24,19 +24,41 @@ use crate::{
     connection_manager::ConnectionDirection,
     message::MessageExt,
     peer_manager::NodeIdentity,
-    proto::identity::PeerIdentityMsg,
+    proto::identity::{PeerIdentityMetadata, PeerIdentityMsg},
     protocol::{ProtocolError, ProtocolId, ProtocolNegotiation},
+    types::CommsPublicKey,
+    utils::signature,
 };
+use chrono::{DateTime, TimeZone, Utc};
 use futures::{AsyncRead, AsyncWrite, SinkExt, StreamExt};
 use log::*;
+use multiaddr::Multiaddr;
 use prost::Message;
+use rand::rngs::OsRng;
 use std::{io, time::Duration};
-use tari_crypto::tari_utilities::ByteArray;
+use tari_crypto::tari_utilities::{message_format::MessageFormat, ByteArray};
 use thiserror::Error;
 use tokio::time;
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
 pub static IDENTITY_PROTOCOL: ProtocolId = ProtocolId::from_static(b"/tari/identity/1.0.0");
+/// Bitmap of the comms protocol versions supported by this node. Bit n is set if version n is supported.
+///
+/// - Version 0: the original protocol
+/// - Version 1: the node accepts messages in the versioned DHT envelope wire format
+/// - Version 2: the node supports noise transport session rekeying
+/// - Version 3: the node accepts messages in the compact direct DHT envelope wire format
+/// - Version 4: the node signs its identity message
+/// - Version 5: the node answers handshake puzzle requests from dialers
+pub const SUPPORTED_PROTOCOL_VERSIONS: u32 = 0b11_1111;
+/// The protocol version from which peers support noise transport session rekeying
+pub const NOISE_REKEY_PROTOCOL_VERSION: u8 = 2;
+/// The protocol version from which peers sign their identity message
+pub const IDENTITY_SIGNATURE_PROTOCOL_VERSION: u8 = 4;
+/// The protocol version from which peers answer handshake puzzle requests, so may be dialed with a puzzle request
+pub const HANDSHAKE_PUZZLE_PROTOCOL_VERSION: u8 = 5;
+/// Domain separator for the identity signature challenge
+const IDENTITY_CHALLENGE_DOMAIN: &[u8] = b"tari.comms.identity";
 const LOG_TARGET: &str = "comms::protocol::identity";
 
 pub async fn identity_exchange<'p, TSocket, P>(
@@ -84,14 +106,33 @@ where
     let supported_protocols = our_supported_protocols.into_iter().map(|p| p.to_vec()).collect();
 
     // Send this node's identity
-    let msg_bytes = PeerIdentityMsg {
+    let mut msg = PeerIdentityMsg {
         node_id: node_identity.node_id().to_vec(),
-        addresses: vec![node_identity.public_address().to_string()],
+        addresses: node_identity
+            .public_addresses()
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
         features: node_identity.features().bits(),
         supported_protocols,
         user_agent,
-    }
-    .to_encoded_bytes();
+        signature: Vec::new(),
+        protocol_versions: SUPPORTED_PROTOCOL_VERSIONS,
+        timestamp: Utc::now().timestamp() as u64,
+        metadata: node_identity
+            .metadata()
+            .into_iter()
+            .map(|(key, value)| PeerIdentityMetadata {
+                key: u32::from(key),
+                value,
+            })
+            .collect(),
+    };
+    msg.signature = signature::sign(&mut OsRng, node_identity.secret_key().clone(), identity_challenge(&msg))
+        .map_err(|_| IdentityProtocolError::SigningFailed)?
+        .to_binary()
+        .map_err(|_| IdentityProtocolError::SigningFailed)?;
+    let msg_bytes = msg.to_encoded_bytes();
 
     sink.send(msg_bytes.into()).await?;
     sink.close().await?;
@@ -105,6 +146,109 @@ where
     Ok(identity_msg)
 }
 
+/// Returns true if the identity message was signed by the given public key
+pub fn verify_identity_signature(public_key: &CommsPublicKey, identity: &PeerIdentityMsg) -> bool {
+    signature::verify(public_key, &identity.signature, identity_challenge(identity))
+}
+
+/// A peer's identity, as received in the identity exchange, along with the public key of the peer. Since the identity
+/// is signed by the peer, it can be relayed to and verified by other nodes, e.g. to let them know that the peer's
+/// addresses have changed.
+#[derive(Debug, Clone)]
+pub struct SignedPeerIdentity {
+    public_key: CommsPublicKey,
+    identity: PeerIdentityMsg,
+}
+
+impl SignedPeerIdentity {
+    pub(crate) fn new(public_key: CommsPublicKey, mut identity: PeerIdentityMsg) -> Self {
+        // Metadata is not covered by the signature, so it is not relayed
+        identity.metadata.clear();
+        Self { public_key, identity }
+    }
+
+    /// Decode a signed identity from its encoded identity message. The signature is not checked.
+    pub fn decode(public_key: CommsPublicKey, identity_bytes: &[u8]) -> Result<Self, IdentityProtocolError> {
+        let identity = PeerIdentityMsg::decode(identity_bytes)?;
+        Ok(Self::new(public_key, identity))
+    }
+
+    /// Returns the encoded identity message
+    pub fn to_encoded_identity(&self) -> Vec<u8> {
+        self.identity.to_encoded_bytes()
+    }
+
+    pub fn public_key(&self) -> &CommsPublicKey {
+        &self.public_key
+    }
+
+    /// The node id given in the identity. This should be checked against the public key.
+    pub fn raw_node_id(&self) -> &[u8] {
+        &self.identity.node_id
+    }
+
+    /// The valid addresses advertised in the identity
+    pub fn addresses(&self) -> Vec<Multiaddr> {
+        self.identity
+            .addresses
+            .iter()
+            .filter_map(|addr| addr.parse().ok())
+            .collect()
+    }
+
+    /// The time at which the peer signed the identity, if given
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        if self.identity.timestamp == 0 {
+            return None;
+        }
+        Utc.timestamp_opt(self.identity.timestamp as i64, 0).single()
+    }
+
+    /// Returns true if the identity was signed by the public key, otherwise false
+    pub fn is_valid(&self) -> bool {
+        verify_identity_signature(&self.public_key, &self.identity)
+    }
+}
+
+/// Returns the estimated difference in seconds between the peer's clock and ours, calculated from the timestamp in the
+/// peer's identity message. The estimate is positive if the peer's clock is ahead of ours. None is returned if the peer
+/// did not send a timestamp.
+///
+/// This should be called as soon as the identity message is received. The time taken for the message to reach us is
+/// not accounted for, which is acceptable given the resolution of the timestamp.
+pub fn estimate_clock_skew(identity: &PeerIdentityMsg) -> Option<i64> {
+    if identity.timestamp == 0 {
+        return None;
+    }
+    Some(identity.timestamp as i64 - Utc::now().timestamp())
+}
+
+/// The bytes that are signed by the node sending its identity. Every field except the signature and the metadata is
+/// included, so metadata is unsigned and is not relayed with the signed identity.
+fn identity_challenge(identity: &PeerIdentityMsg) -> Vec<u8> {
+    let mut challenge = Vec::with_capacity(256);
+    challenge.extend_from_slice(IDENTITY_CHALLENGE_DOMAIN);
+    extend_length_prefixed(&mut challenge, &identity.node_id);
+    challenge.extend_from_slice(&(identity.addresses.len() as u64).to_le_bytes());
+    for addr in &identity.addresses {
+        extend_length_prefixed(&mut challenge, addr.as_bytes());
+    }
+    challenge.extend_from_slice(&identity.features.to_le_bytes());
+    challenge.extend_from_slice(&(identity.supported_protocols.len() as u64).to_le_bytes());
+    for protocol in &identity.supported_protocols {
+        extend_length_prefixed(&mut challenge, protocol);
+    }
+    extend_length_prefixed(&mut challenge, identity.user_agent.as_bytes());
+    challenge.extend_from_slice(&identity.protocol_versions.to_le_bytes());
+    challenge.extend_from_slice(&identity.timestamp.to_le_bytes());
+    challenge
+}
+
+fn extend_length_prefixed(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
 #[derive(Debug, Error, Clone)]
 pub enum IdentityProtocolError {
     #[error("IoError: {0}")]
@@ -119,6 +263,8 @@ pub enum IdentityProtocolError {
     PeerUnexpectedCloseConnection,
     #[error("Timeout waiting for peer to send identity information")]
     Timeout,
+    #[error("Failed to sign identity information")]
+    SigningFailed,
 }
 
 impl From<time::Elapsed> for IdentityProtocolError {
@@ -150,10 +296,12 @@ mod test {
     use crate::{
         connection_manager::ConnectionDirection,
         peer_manager::PeerFeatures,
+        proto::identity::PeerIdentityMsg,
         runtime,
         test_utils::node_identity::build_node_identity,
         transports::{MemoryTransport, Transport},
     };
+    use chrono::Utc;
     use futures::{future, StreamExt};
     use tari_crypto::tari_utilities::ByteArray;
 
@@ -200,5 +348,79 @@ mod test {
         assert_eq!(identity2.node_id, node_identity2.node_id().to_vec());
         assert_eq!(identity2.features, node_identity2.features().bits());
         assert_eq!(identity2.addresses, vec![node_identity2.public_address().to_string()]);
+        assert_eq!(identity2.protocol_versions, super::SUPPORTED_PROTOCOL_VERSIONS);
+        assert!(super::estimate_clock_skew(&identity2).unwrap().abs() <= 1);
+
+        assert!(super::verify_identity_signature(
+            node_identity1.public_key(),
+            &identity1
+        ));
+        assert!(super::verify_identity_signature(
+            node_identity2.public_key(),
+            &identity2
+        ));
+        // Signed by a different key
+        assert!(!super::verify_identity_signature(
+            node_identity1.public_key(),
+            &identity2
+        ));
+
+        // The signed identity can be relayed to and verified by other nodes
+        let public_key = node_identity1.public_key().clone();
+        let signed_identity = super::SignedPeerIdentity::new(public_key.clone(), identity1.clone());
+        let signed_identity =
+            super::SignedPeerIdentity::decode(public_key, &signed_identity.to_encoded_identity()).unwrap();
+        assert!(signed_identity.is_valid());
+        assert_eq!(signed_identity.addresses(), vec![node_identity1.public_address()]);
+        assert!(signed_identity.timestamp().is_some());
+
+        // Tampered identity
+        let mut identity1 = identity1;
+        identity1.features = PeerFeatures::COMMUNICATION_CLIENT.bits();
+        assert!(!super::verify_identity_signature(
+            node_identity1.public_key(),
+            &identity1
+        ));
+    }
+
+    #[test]
+    fn identity_challenge_is_unambiguous() {
+        let identity1 = PeerIdentityMsg {
+            addresses: vec!["/ip4/1.2.3.4/tcp/1".to_string(), "23".to_string()],
+            ..Default::default()
+        };
+        let identity2 = PeerIdentityMsg {
+            addresses: vec!["/ip4/1.2.3.4/tcp/12".to_string(), "3".to_string()],
+            ..Default::default()
+        };
+        assert_ne!(
+            super::identity_challenge(&identity1),
+            super::identity_challenge(&identity2)
+        );
+
+        let identity1 = PeerIdentityMsg {
+            supported_protocols: vec![b"/tari/a".to_vec()],
+            user_agent: "b".to_string(),
+            ..Default::default()
+        };
+        let identity2 = PeerIdentityMsg {
+            supported_protocols: vec![b"/tari/".to_vec()],
+            user_agent: "ab".to_string(),
+            ..Default::default()
+        };
+        assert_ne!(
+            super::identity_challenge(&identity1),
+            super::identity_challenge(&identity2)
+        );
+    }
+
+    #[test]
+    fn estimate_clock_skew() {
+        let mut identity = PeerIdentityMsg::default();
+        assert!(super::estimate_clock_skew(&identity).is_none());
+
+        identity.timestamp = (Utc::now().timestamp() + 3600) as u64;
+        let skew = super::estimate_clock_skew(&identity).unwrap();
+        assert!((3599..=3600).contains(&skew));
     }
 }
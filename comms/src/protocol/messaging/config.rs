@@ -30,12 +30,17 @@ pub struct MessagingConfig {
     /// Inbound/outbound substreams are closed independently, and they may be reopened in the future once closed.
     /// (default: 8 mins)
     pub inactivity_timeout: Option<Duration>,
+    /// The length of time to allow queued outbound messages to be flushed when shutting down. Any messages that have
+    /// not been sent once this period has elapsed are failed with `SendFailReason::ShuttingDown`.
+    /// (default: 10 seconds)
+    pub shutdown_grace_period: Duration,
 }
 
 impl Default for MessagingConfig {
     fn default() -> Self {
         Self {
             inactivity_timeout: Some(Duration::from_secs(8 * 60)),
+            shutdown_grace_period: Duration::from_secs(10),
         }
     }
 }
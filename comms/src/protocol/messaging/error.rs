@@ -43,10 +43,16 @@ pub enum MessagingProtocolError {
     PeerConnectionError(#[from] PeerConnectionError),
     #[error("Failed to dial peer")]
     PeerDialFailed,
+    #[error("Timed out while dialing peer")]
+    PeerDialTimeout,
+    #[error("Peer is banned")]
+    PeerBanned,
     #[error("IO Error: {0}")]
     Io(#[from] io::Error),
     #[error("Sender error: {0}")]
     SenderError(#[from] mpsc::SendError),
     #[error("Stream closed due to inactivity")]
     Inactivity,
+    #[error("Messaging protocol is shutting down")]
+    ShuttingDown,
 }
@@ -20,7 +20,7 @@
 //  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 //  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use super::MessagingProtocol;
+use super::{MessagingConfig, MessagingProtocol, OutboundQueueUsage};
 use crate::{
     bounded_executor::BoundedExecutor,
     message::InboundMessage,
@@ -41,11 +41,31 @@ use tower::Service;
 pub struct MessagingProtocolExtension<TInPipe, TOutPipe, TOutReq> {
     event_tx: MessagingEventSender,
     pipeline: pipeline::Config<TInPipe, TOutPipe, TOutReq>,
+    config: MessagingConfig,
+    outbound_queue_usage: OutboundQueueUsage,
 }
 
 impl<TInPipe, TOutPipe, TOutReq> MessagingProtocolExtension<TInPipe, TOutPipe, TOutReq> {
     pub fn new(event_tx: MessagingEventSender, pipeline: pipeline::Config<TInPipe, TOutPipe, TOutReq>) -> Self {
-        Self { event_tx, pipeline }
+        Self {
+            event_tx,
+            pipeline,
+            config: Default::default(),
+            outbound_queue_usage: Default::default(),
+        }
+    }
+
+    /// Set the messaging protocol config
+    pub fn with_config(mut self, config: MessagingConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Share the given `OutboundQueueUsage` with the messaging protocol so that the memory used by queued outbound
+    /// messages can be reported
+    pub fn with_outbound_queue_usage(mut self, outbound_queue_usage: OutboundQueueUsage) -> Self {
+        self.outbound_queue_usage = outbound_queue_usage;
+        self
     }
 }
 
@@ -53,10 +73,10 @@ impl<TInPipe, TOutPipe, TOutReq> ProtocolExtension for MessagingProtocolExtensio
 where
     TOutPipe: Service<TOutReq, Response = ()> + Clone + Send + Sync + 'static,
     TOutPipe::Error: fmt::Display + Send + Sync,
-    TOutPipe::Future: Send + Sync + 'static,
+    TOutPipe::Future: Send + 'static,
     TInPipe: Service<InboundMessage> + Clone + Send + Sync + 'static,
     TInPipe::Error: fmt::Display + Send + Sync,
-    TInPipe::Future: Send + Sync + 'static,
+    TInPipe::Future: Send + 'static,
     TOutReq: Send + Sync + 'static,
 {
     fn install(self: Box<Self>, context: &mut ProtocolExtensionContext) -> Result<(), ProtocolExtensionError> {
@@ -67,14 +87,16 @@ where
         let (inbound_message_tx, inbound_message_rx) = mpsc::channel(consts::INBOUND_MESSAGE_BUFFER_SIZE);
 
         let messaging = MessagingProtocol::new(
-            Default::default(),
+            self.config,
             context.connectivity(),
             proto_rx,
             messaging_request_rx,
             self.event_tx,
             inbound_message_tx,
             context.shutdown_signal(),
-        );
+        )
+        .with_outbound_queue_usage(self.outbound_queue_usage)
+        .with_shutdown_reporter(context.shutdown_reporter());
 
         context.register_complete_signal(messaging.complete_signal());
 
@@ -88,7 +110,8 @@ where
             inbound_message_rx,
             self.pipeline.inbound,
             context.shutdown_signal(),
-        );
+        )
+        .with_overflow_policy(self.pipeline.inbound_overflow_policy);
         task::spawn(inbound.run());
 
         // Spawn outbound pipeline
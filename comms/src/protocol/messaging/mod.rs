@@ -31,6 +31,10 @@ mod consts;
 mod error;
 mod inbound;
 mod outbound;
+mod outbound_queue;
+
+mod queue_usage;
+pub use queue_usage::OutboundQueueUsage;
 
 mod protocol;
 pub use protocol::{
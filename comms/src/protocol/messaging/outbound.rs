@@ -20,21 +20,28 @@
 // WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use super::{error::MessagingProtocolError, MessagingEvent, MessagingProtocol, SendFailReason};
+use super::{
+    error::MessagingProtocolError,
+    outbound_queue::OutboundQueueReceiver,
+    MessagingEvent,
+    MessagingProtocol,
+    OutboundQueueUsage,
+    SendFailReason,
+};
 use crate::{
-    connection_manager::{NegotiatedSubstream, PeerConnection},
+    connection_manager::{ConnectionManagerError, NegotiatedSubstream, PeerConnection},
     connectivity::{ConnectivityError, ConnectivityRequester},
-    message::OutboundMessage,
     multiplexing::Substream,
     peer_manager::NodeId,
     protocol::messaging::protocol::MESSAGING_PROTOCOL,
 };
-use futures::{channel::mpsc, future::Either, SinkExt, StreamExt};
+use futures::{channel::mpsc, future, future::Either, pin_mut, stream::FusedStream, FutureExt, SinkExt, StreamExt};
 use log::*;
 use std::{
     io,
     time::{Duration, Instant},
 };
+use tari_shutdown::ShutdownSignal;
 use tokio::stream as tokio_stream;
 
 const LOG_TARGET: &str = "comms::protocol::messaging::outbound";
@@ -45,19 +52,23 @@ const MAX_SEND_RETRIES: usize = 1;
 
 pub struct OutboundMessaging {
     connectivity: ConnectivityRequester,
-    request_rx: mpsc::UnboundedReceiver<OutboundMessage>,
+    request_rx: OutboundQueueReceiver,
     messaging_events_tx: mpsc::Sender<MessagingEvent>,
     peer_node_id: NodeId,
     inactivity_timeout: Option<Duration>,
+    queue_usage: OutboundQueueUsage,
+    shutdown_signal: ShutdownSignal,
 }
 
 impl OutboundMessaging {
     pub fn new(
         connectivity: ConnectivityRequester,
         messaging_events_tx: mpsc::Sender<MessagingEvent>,
-        request_rx: mpsc::UnboundedReceiver<OutboundMessage>,
+        request_rx: OutboundQueueReceiver,
         peer_node_id: NodeId,
         inactivity_timeout: Option<Duration>,
+        queue_usage: OutboundQueueUsage,
+        shutdown_signal: ShutdownSignal,
     ) -> Self
     {
         Self {
@@ -66,10 +77,12 @@ impl OutboundMessaging {
             messaging_events_tx,
             peer_node_id,
             inactivity_timeout,
+            queue_usage,
+            shutdown_signal,
         }
     }
 
-    pub async fn run(self) {
+    pub async fn run(mut self) {
         debug!(
             target: LOG_TARGET,
             "Attempting to dial peer '{}' if required",
@@ -77,7 +90,16 @@ impl OutboundMessaging {
         );
         let peer_node_id = self.peer_node_id.clone();
         let mut messaging_events_tx = self.messaging_events_tx.clone();
-        match self.run_inner().await {
+        let mut shutdown_signal = self.shutdown_signal.clone();
+        let result = {
+            let inner = self.run_inner().fuse();
+            pin_mut!(inner);
+            futures::select! {
+                result = inner => result,
+                _ = shutdown_signal => Err(MessagingProtocolError::ShuttingDown),
+            }
+        };
+        match result {
             Ok(_) => {
                 debug!(
                     target: LOG_TARGET,
@@ -92,17 +114,33 @@ impl OutboundMessaging {
                     peer_node_id.short_str()
                 );
             },
+            Err(MessagingProtocolError::ShuttingDown) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Outbound messaging for peer '{}' was interrupted by shutdown",
+                    peer_node_id.short_str()
+                );
+                self.fail_all_pending_messages(SendFailReason::ShuttingDown).await;
+            },
             Err(err) => {
                 debug!(target: LOG_TARGET, "Outbound messaging substream failed: {}", err);
             },
         }
 
+        // Any messages left in the queue will never be sent. The receiver may not be polled again once it has ended.
+        if !self.request_rx.is_terminated() {
+            self.request_rx.close();
+            while let Some(out_msg) = self.request_rx.try_next() {
+                self.queue_usage.message_dequeued(&out_msg);
+            }
+        }
+
         let _ = messaging_events_tx
             .send(MessagingEvent::OutboundProtocolExited(peer_node_id))
             .await;
     }
 
-    async fn run_inner(mut self) -> Result<(), MessagingProtocolError> {
+    async fn run_inner(&mut self) -> Result<(), MessagingProtocolError> {
         let mut attempts = 0;
         let substream = loop {
             match self.try_establish().await {
@@ -112,12 +150,26 @@ impl OutboundMessaging {
                         attempts <= MAX_SEND_RETRIES,
                         "Attempt count was greater than the maximum"
                     );
+                    // There is no point retrying a banned peer
+                    if let MessagingProtocolError::PeerBanned = err {
+                        debug!(
+                            target: LOG_TARGET,
+                            "Not sending messages to peer '{}' because it is banned",
+                            self.peer_node_id.short_str()
+                        );
+                        self.fail_all_pending_messages(SendFailReason::PeerBanned).await;
+                        return Err(err);
+                    }
                     if attempts == MAX_SEND_RETRIES {
                         debug!(
                             target: LOG_TARGET,
                             "Error establishing messaging protocol: {}. Aborting because maximum retries reached.", err
                         );
-                        self.fail_all_pending_messages(SendFailReason::PeerDialFailed).await;
+                        let reason = match err {
+                            MessagingProtocolError::PeerDialTimeout => SendFailReason::PeerDialTimeout,
+                            _ => SendFailReason::PeerDialFailed,
+                        };
+                        self.fail_all_pending_messages(reason).await;
                         return Err(err);
                     }
                     debug!(
@@ -154,7 +206,17 @@ impl OutboundMessaging {
                         err
                     );
 
-                    break Err(MessagingProtocolError::PeerDialFailed);
+                    break Err(match err {
+                        ConnectivityError::ConnectionFailed(ConnectionManagerError::PeerBanned) => {
+                            MessagingProtocolError::PeerBanned
+                        },
+                        ConnectivityError::ConnectionFailed(ConnectionManagerError::DialConnectTimeout) |
+                        ConnectivityError::ConnectionFailed(ConnectionManagerError::NoiseProtocolTimeout) |
+                        ConnectivityError::ConnectionFailed(ConnectionManagerError::IdentityExchangeTimeout) => {
+                            MessagingProtocolError::PeerDialTimeout
+                        },
+                        _ => MessagingProtocolError::PeerDialFailed,
+                    });
                 },
             }
         }
@@ -203,7 +265,7 @@ impl OutboundMessaging {
     }
 
     async fn start_forwarding_messages(
-        self,
+        &mut self,
         substream: NegotiatedSubstream<Substream>,
     ) -> Result<(), MessagingProtocolError>
     {
@@ -216,13 +278,9 @@ impl OutboundMessaging {
 
         let (sink, _) = MessagingProtocol::framed(substream).split();
 
-        let Self {
-            request_rx,
-            inactivity_timeout,
-            ..
-        } = self;
-
-        let stream = match inactivity_timeout {
+        let queue_usage = self.queue_usage.clone();
+        let request_rx = &mut self.request_rx;
+        let stream = match self.inactivity_timeout {
             Some(timeout) => {
                 let s = tokio_stream::StreamExt::timeout(request_rx, timeout).map(|r| match r {
                     Ok(s) => Ok(s),
@@ -237,8 +295,20 @@ impl OutboundMessaging {
         };
 
         stream
+            .filter_map(|msg| {
+                future::ready(match msg {
+                    Ok(mut out_msg) if out_msg.is_cancelled() => {
+                        queue_usage.message_dequeued(&out_msg);
+                        debug!(target: LOG_TARGET, "Discarding cancelled message {}", out_msg);
+                        out_msg.reply_fail(SendFailReason::Cancelled);
+                        None
+                    },
+                    msg => Some(msg),
+                })
+            })
             .map(|msg| {
                 msg.map(|mut out_msg| {
+                    queue_usage.message_dequeued(&out_msg);
                     trace!(target: LOG_TARGET, "Message buffered for sending {}", out_msg);
                     out_msg.reply_success();
                     out_msg.body
@@ -260,6 +330,7 @@ impl OutboundMessaging {
         // to a failed event
         self.request_rx.close();
         while let Some(mut out_msg) = self.request_rx.next().await {
+            self.queue_usage.message_dequeued(&out_msg);
             out_msg.reply_fail(reason);
             let _ = self
                 .messaging_events_tx
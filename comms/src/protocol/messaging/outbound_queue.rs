@@ -0,0 +1,171 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::message::{MessagePriority, OutboundMessage};
+use futures::{
+    channel::mpsc,
+    stream::{Fuse, FusedStream},
+    task::{Context, Poll},
+    Stream,
+    StreamExt,
+};
+use std::pin::Pin;
+
+/// Creates the queue of outbound messages for a single peer. The queue has a lane for each `MessagePriority`.
+pub fn channel() -> (OutboundQueueSender, OutboundQueueReceiver) {
+    let (normal_tx, normal_rx) = mpsc::unbounded();
+    let (low_tx, low_rx) = mpsc::unbounded();
+    (
+        OutboundQueueSender {
+            normal: normal_tx,
+            low: low_tx,
+        },
+        OutboundQueueReceiver {
+            normal: normal_rx.fuse(),
+            low: low_rx.fuse(),
+        },
+    )
+}
+
+/// Sending side of a peer's outbound message queue
+#[derive(Debug, Clone)]
+pub struct OutboundQueueSender {
+    normal: mpsc::UnboundedSender<OutboundMessage>,
+    low: mpsc::UnboundedSender<OutboundMessage>,
+}
+
+impl OutboundQueueSender {
+    /// Queues the message in the lane for its priority. The message is returned if the queue is closed.
+    pub fn send(&self, message: OutboundMessage) -> Result<(), OutboundMessage> {
+        let lane = match message.priority {
+            MessagePriority::Normal => &self.normal,
+            MessagePriority::Low => &self.low,
+        };
+        lane.unbounded_send(message).map_err(|err| err.into_inner())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.normal.is_closed() || self.low.is_closed()
+    }
+
+    pub fn close_channel(&self) {
+        self.normal.close_channel();
+        self.low.close_channel();
+    }
+}
+
+/// Receiving side of a peer's outbound message queue. `Normal` priority messages are always received before `Low`
+/// priority messages, and messages of the same priority are received in the order they were queued. The stream ends
+/// once both lanes are closed and empty.
+#[derive(Debug)]
+pub struct OutboundQueueReceiver {
+    normal: Fuse<mpsc::UnboundedReceiver<OutboundMessage>>,
+    low: Fuse<mpsc::UnboundedReceiver<OutboundMessage>>,
+}
+
+impl OutboundQueueReceiver {
+    /// Closes both lanes. Messages that are already queued can still be received.
+    pub fn close(&mut self) {
+        self.normal.get_mut().close();
+        self.low.get_mut().close();
+    }
+
+    /// Receives the next queued message without waiting, returning None if no message is queued
+    pub fn try_next(&mut self) -> Option<OutboundMessage> {
+        if !self.normal.is_terminated() {
+            if let Ok(Some(msg)) = self.normal.get_mut().try_next() {
+                return Some(msg);
+            }
+        }
+        if !self.low.is_terminated() {
+            if let Ok(Some(msg)) = self.low.get_mut().try_next() {
+                return Some(msg);
+            }
+        }
+        None
+    }
+}
+
+impl Stream for OutboundQueueReceiver {
+    type Item = OutboundMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(Some(msg)) = Pin::new(&mut self.normal).poll_next(cx) {
+            return Poll::Ready(Some(msg));
+        }
+        if let Poll::Ready(Some(msg)) = Pin::new(&mut self.low).poll_next(cx) {
+            return Poll::Ready(Some(msg));
+        }
+        if self.is_terminated() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl FusedStream for OutboundQueueReceiver {
+    fn is_terminated(&self) -> bool {
+        self.normal.is_terminated() && self.low.is_terminated()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{peer_manager::NodeId, runtime};
+    use bytes::Bytes;
+
+    fn make_message(body: &'static [u8], priority: MessagePriority) -> OutboundMessage {
+        OutboundMessage::new(NodeId::new(), Bytes::from_static(body)).with_priority(priority)
+    }
+
+    #[runtime::test_basic]
+    async fn normal_priority_messages_are_received_first() {
+        let (sender, mut receiver) = channel();
+        sender.send(make_message(b"low1", MessagePriority::Low)).unwrap();
+        sender.send(make_message(b"normal1", MessagePriority::Normal)).unwrap();
+        sender.send(make_message(b"low2", MessagePriority::Low)).unwrap();
+        sender.send(make_message(b"normal2", MessagePriority::Normal)).unwrap();
+        sender.close_channel();
+
+        let bodies = receiver.by_ref().map(|msg| msg.body).collect::<Vec<_>>().await;
+        assert_eq!(bodies, vec![
+            Bytes::from_static(b"normal1"),
+            Bytes::from_static(b"normal2"),
+            Bytes::from_static(b"low1"),
+            Bytes::from_static(b"low2"),
+        ]);
+        assert!(receiver.is_terminated());
+    }
+
+    #[test]
+    fn closed_receiver_returns_queued_messages() {
+        let (sender, mut receiver) = channel();
+        sender.send(make_message(b"low", MessagePriority::Low)).unwrap();
+        receiver.close();
+        assert!(sender.is_closed());
+        assert!(sender.send(make_message(b"normal", MessagePriority::Normal)).is_err());
+        assert_eq!(receiver.try_next().unwrap().body, Bytes::from_static(b"low"));
+        assert!(receiver.try_next().is_none());
+    }
+}
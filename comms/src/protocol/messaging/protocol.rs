@@ -22,6 +22,7 @@
 
 use super::error::MessagingProtocolError;
 use crate::{
+    builder::ShutdownReporter,
     compat::IoCompat,
     connectivity::{ConnectivityEvent, ConnectivityRequester},
     framing,
@@ -29,14 +30,21 @@ use crate::{
     multiplexing::Substream,
     peer_manager::NodeId,
     protocol::{
-        messaging::{inbound::InboundMessaging, outbound::OutboundMessaging, MessagingConfig},
+        messaging::{
+            inbound::InboundMessaging,
+            outbound::OutboundMessaging,
+            outbound_queue,
+            outbound_queue::OutboundQueueSender,
+            MessagingConfig,
+            OutboundQueueUsage,
+        },
         ProtocolEvent,
         ProtocolNotification,
     },
     runtime::task,
 };
 use bytes::Bytes;
-use futures::{channel::mpsc, stream::Fuse, AsyncRead, AsyncWrite, SinkExt, StreamExt};
+use futures::{channel::mpsc, stream::Fuse, AsyncRead, AsyncWrite, StreamExt};
 use log::*;
 use std::{
     collections::{hash_map::Entry, HashMap},
@@ -46,7 +54,7 @@ use std::{
 };
 use tari_shutdown::{Shutdown, ShutdownSignal};
 use thiserror::Error;
-use tokio::sync::broadcast;
+use tokio::{sync::broadcast, time};
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
 const LOG_TARGET: &str = "comms::protocol::messaging";
@@ -69,10 +77,14 @@ pub enum MessagingRequest {
 
 /// The reason for dial failure. This enum should contain simple variants which describe the kind of failure that
 /// occurred
-#[derive(Debug, Error, Copy, Clone)]
+#[derive(Debug, Error, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum SendFailReason {
     #[error("Dial was attempted, but failed")]
     PeerDialFailed,
+    #[error("Dial was attempted, but timed out")]
+    PeerDialTimeout,
+    #[error("The peer is banned")]
+    PeerBanned,
     #[error("Failed to open a messaging substream to peer")]
     SubstreamOpenFailed,
     #[error("Failed to send on substream channel")]
@@ -81,6 +93,10 @@ pub enum SendFailReason {
     Dropped,
     #[error("Message could not send after {0} attempt(s)")]
     MaxRetriesReached(usize),
+    #[error("Message was not sent before the messaging protocol shut down")]
+    ShuttingDown,
+    #[error("Message was cancelled before sending")]
+    Cancelled,
 }
 
 #[derive(Debug)]
@@ -107,13 +123,16 @@ pub struct MessagingProtocol {
     config: MessagingConfig,
     connectivity: ConnectivityRequester,
     proto_notification: Fuse<mpsc::Receiver<ProtocolNotification<Substream>>>,
-    active_queues: HashMap<NodeId, mpsc::UnboundedSender<OutboundMessage>>,
+    active_queues: HashMap<NodeId, OutboundQueueSender>,
     request_rx: Fuse<mpsc::Receiver<MessagingRequest>>,
     messaging_events_tx: MessagingEventSender,
     inbound_message_tx: mpsc::Sender<InboundMessage>,
     internal_messaging_event_tx: mpsc::Sender<MessagingEvent>,
     internal_messaging_event_rx: Fuse<mpsc::Receiver<MessagingEvent>>,
     shutdown_signal: ShutdownSignal,
+    outbound_shutdown: Shutdown,
+    outbound_queue_usage: OutboundQueueUsage,
+    shutdown_reporter: ShutdownReporter,
     complete_trigger: Shutdown,
 }
 
@@ -142,10 +161,26 @@ impl MessagingProtocol {
             internal_messaging_event_tx,
             inbound_message_tx,
             shutdown_signal,
+            outbound_shutdown: Shutdown::new(),
+            outbound_queue_usage: Default::default(),
+            shutdown_reporter: Default::default(),
             complete_trigger: Shutdown::new(),
         }
     }
 
+    /// Account for queued outbound messages using the given `OutboundQueueUsage`
+    pub fn with_outbound_queue_usage(mut self, outbound_queue_usage: OutboundQueueUsage) -> Self {
+        self.outbound_queue_usage = outbound_queue_usage;
+        self
+    }
+
+    /// Record the number of outbound messages that could not be sent before shutting down using the given
+    /// `ShutdownReporter`
+    pub fn with_shutdown_reporter(mut self, shutdown_reporter: ShutdownReporter) -> Self {
+        self.shutdown_reporter = shutdown_reporter;
+        self
+    }
+
     pub fn complete_signal(&self) -> ShutdownSignal {
         self.complete_trigger.to_signal()
     }
@@ -182,12 +217,62 @@ impl MessagingProtocol {
 
                 _ = shutdown_signal => {
                     info!(target: LOG_TARGET, "MessagingProtocol is shutting down because the shutdown signal was triggered");
+                    self.flush_outbound_queues().await;
                     break;
                 }
             }
         }
     }
 
+    /// Stop accepting new messages and give the outbound handlers up to `shutdown_grace_period` to send any queued
+    /// messages. Messages that are still queued after the grace period are failed with
+    /// `SendFailReason::ShuttingDown`.
+    async fn flush_outbound_queues(&mut self) {
+        // Dispatch any messages that were requested before the shutdown signal
+        self.request_rx.get_mut().close();
+        while let Some(req) = self.request_rx.next().await {
+            if let Err(err) = self.handle_request(req).await {
+                error!(target: LOG_TARGET, "Failed to handle request because '{}'", err);
+            }
+        }
+
+        // Closing the queues allows each outbound handler to exit once all queued messages have been sent
+        self.active_queues.values().for_each(|sender| sender.close_channel());
+        let num_queues = self.active_queues.len();
+        let grace_period = self.config.shutdown_grace_period;
+        if time::timeout(grace_period, self.wait_for_outbound_handlers())
+            .await
+            .is_ok()
+        {
+            debug!(
+                target: LOG_TARGET,
+                "Flushed {} outbound message queue(s) before shutting down", num_queues
+            );
+            return;
+        }
+
+        let num_dropped = self.outbound_queue_usage.num_messages();
+        warn!(
+            target: LOG_TARGET,
+            "{} outbound message queue(s) were not flushed within {:.0?}. {} remaining message(s) will be failed.",
+            self.active_queues.len(),
+            grace_period,
+            num_dropped
+        );
+        self.shutdown_reporter.add_outbound_messages_dropped(num_dropped);
+        let _ = self.outbound_shutdown.trigger();
+        self.wait_for_outbound_handlers().await;
+    }
+
+    async fn wait_for_outbound_handlers(&mut self) {
+        while !self.active_queues.is_empty() {
+            match self.internal_messaging_event_rx.next().await {
+                Some(event) => self.handle_internal_messaging_event(event).await,
+                None => break,
+            }
+        }
+    }
+
     #[inline]
     pub fn framed<TSubstream>(socket: TSubstream) -> Framed<IoCompat<TSubstream>, LengthDelimitedCodec>
     where TSubstream: AsyncRead + AsyncWrite + Unpin {
@@ -263,8 +348,10 @@ impl MessagingProtocol {
                     let sender = Self::spawn_outbound_handler(
                         self.connectivity.clone(),
                         self.internal_messaging_event_tx.clone(),
-                        peer_node_id.clone(),
+                        peer_node_id,
                         self.config.inactivity_timeout,
+                        self.outbound_queue_usage.clone(),
+                        self.outbound_shutdown.to_signal(),
                     );
                     break entry.insert(sender);
                 },
@@ -273,16 +360,18 @@ impl MessagingProtocol {
 
         debug!(target: LOG_TARGET, "Sending message {}", out_msg);
         let tag = out_msg.tag;
-        match sender.send(out_msg).await {
+        self.outbound_queue_usage.message_queued(&out_msg);
+        match sender.send(out_msg) {
             Ok(_) => {
                 debug!(target: LOG_TARGET, "Message ({}) dispatched to outbound handler", tag,);
                 Ok(())
             },
-            Err(err) => {
+            Err(out_msg) => {
                 debug!(
                     target: LOG_TARGET,
-                    "Failed to send message on channel because '{:?}'", err
+                    "Failed to send message ({}) on channel because it is closed", out_msg
                 );
+                self.outbound_queue_usage.message_dequeued(&out_msg);
                 Err(MessagingProtocolError::MessageSendFailed)
             },
         }
@@ -293,11 +382,20 @@ impl MessagingProtocol {
         events_tx: mpsc::Sender<MessagingEvent>,
         peer_node_id: NodeId,
         inactivity_timeout: Option<Duration>,
-    ) -> mpsc::UnboundedSender<OutboundMessage>
+        queue_usage: OutboundQueueUsage,
+        shutdown_signal: ShutdownSignal,
+    ) -> OutboundQueueSender
     {
-        let (msg_tx, msg_rx) = mpsc::unbounded();
-        let outbound_messaging =
-            OutboundMessaging::new(connectivity, events_tx, msg_rx, peer_node_id, inactivity_timeout);
+        let (msg_tx, msg_rx) = outbound_queue::channel();
+        let outbound_messaging = OutboundMessaging::new(
+            connectivity,
+            events_tx,
+            msg_rx,
+            peer_node_id,
+            inactivity_timeout,
+            queue_usage,
+            shutdown_signal,
+        );
         task::spawn(outbound_messaging.run());
         msg_tx
     }
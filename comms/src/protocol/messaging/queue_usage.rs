@@ -0,0 +1,98 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::message::OutboundMessage;
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Approximate accounting of the messages waiting in the outbound messaging queues. Clones share the same counters, so
+/// a clone can be retained to monitor the queues after it has been given to the messaging protocol.
+#[derive(Debug, Clone, Default)]
+pub struct OutboundQueueUsage {
+    num_messages: Arc<AtomicUsize>,
+    num_bytes: Arc<AtomicUsize>,
+}
+
+impl OutboundQueueUsage {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The number of messages waiting to be sent
+    pub fn num_messages(&self) -> usize {
+        self.num_messages.load(Ordering::Relaxed)
+    }
+
+    /// The approximate number of bytes of memory held by messages waiting to be sent
+    pub fn approx_memory_usage(&self) -> usize {
+        self.num_bytes.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn message_queued(&self, message: &OutboundMessage) {
+        self.num_messages.fetch_add(1, Ordering::Relaxed);
+        self.num_bytes
+            .fetch_add(approx_message_size(message), Ordering::Relaxed);
+    }
+
+    pub(super) fn message_dequeued(&self, message: &OutboundMessage) {
+        self.num_messages.fetch_sub(1, Ordering::Relaxed);
+        self.num_bytes
+            .fetch_sub(approx_message_size(message), Ordering::Relaxed);
+    }
+}
+
+fn approx_message_size(message: &OutboundMessage) -> usize {
+    mem::size_of::<OutboundMessage>() + message.body.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{peer_manager::NodeId, Bytes};
+
+    #[test]
+    fn queued_and_dequeued() {
+        let usage = OutboundQueueUsage::new();
+        let monitor = usage.clone();
+        let msg1 = OutboundMessage::new(NodeId::new(), Bytes::from_static(&[0u8; 100]));
+        let msg2 = OutboundMessage::new(NodeId::new(), Bytes::from_static(&[0u8; 20]));
+        usage.message_queued(&msg1);
+        usage.message_queued(&msg2);
+        assert_eq!(monitor.num_messages(), 2);
+        assert_eq!(
+            monitor.approx_memory_usage(),
+            2 * mem::size_of::<OutboundMessage>() + 120
+        );
+
+        usage.message_dequeued(&msg1);
+        assert_eq!(monitor.num_messages(), 1);
+        assert_eq!(monitor.approx_memory_usage(), mem::size_of::<OutboundMessage>() + 20);
+        usage.message_dequeued(&msg2);
+        assert_eq!(monitor.num_messages(), 0);
+        assert_eq!(monitor.approx_memory_usage(), 0);
+    }
+}
@@ -29,12 +29,18 @@ use super::protocol::{
 };
 use crate::{
     memsocket::MemorySocket,
-    message::{InboundMessage, MessageTag, MessagingReplyRx, OutboundMessage},
+    message::{CancellationHandle, InboundMessage, MessageTag, MessagingReplyRx, OutboundMessage},
     multiplexing::Substream,
     net_address::MultiaddressesWithStats,
     peer_manager::{NodeId, NodeIdentity, Peer, PeerFeatures, PeerFlags, PeerManager},
     protocol::{
-        messaging::{inbound::InboundMessaging, SendFailReason},
+        messaging::{
+            inbound::InboundMessaging,
+            outbound::OutboundMessaging,
+            outbound_queue,
+            OutboundQueueUsage,
+            SendFailReason,
+        },
         ProtocolEvent,
         ProtocolNotification,
     },
@@ -192,6 +198,34 @@ async fn send_message_request() {
     assert_eq!(peer_conn_mock1.call_count(), 1);
 }
 
+#[runtime::test_basic]
+async fn send_message_cancelled() {
+    let (_, node_identity, conn_man_mock, _, mut request_tx, _, _, _shutdown) = spawn_messaging_protocol().await;
+
+    let peer_node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+    let (conn1, _, _, peer_conn_mock2) =
+        create_peer_connection_mock_pair(1, node_identity.to_peer(), peer_node_identity.to_peer()).await;
+    conn_man_mock.add_active_connection(conn1).await;
+
+    let cancellation = CancellationHandle::new();
+    cancellation.cancel();
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let out_msg = OutboundMessage::with_reply(peer_node_identity.node_id().clone(), TEST_MSG1.clone(), reply_tx.into())
+        .with_cancellation(cancellation);
+    request_tx.send(MessagingRequest::SendMessage(out_msg)).await.unwrap();
+    let out_msg = OutboundMessage::new(peer_node_identity.node_id().clone(), Bytes::from_static(b"TEST_MSG2"));
+    request_tx.send(MessagingRequest::SendMessage(out_msg)).await.unwrap();
+
+    let err = reply_rx.await.unwrap().unwrap_err();
+    assert_eq!(err, SendFailReason::Cancelled);
+
+    // Only the message that was not cancelled is received
+    let stream = peer_conn_mock2.next_incoming_substream().await.unwrap();
+    let mut framed = MessagingProtocol::framed(stream);
+    let msg = framed.next().await.unwrap().unwrap();
+    assert_eq!(msg, Bytes::from_static(b"TEST_MSG2"));
+}
+
 #[runtime::test_basic]
 async fn send_message_dial_failed() {
     let (_, _, conn_manager_mock, _, mut request_tx, _, mut event_tx, _shutdown) = spawn_messaging_protocol().await;
@@ -293,6 +327,8 @@ async fn many_concurrent_send_message_requests() {
             reply: reply_tx.into(),
             peer_node_id: node_id2.clone(),
             body: TEST_MSG1.clone(),
+            cancellation: None,
+            priority: Default::default(),
         };
         msg_tags.push(out_msg.tag);
         reply_rxs.push(reply_rx);
@@ -333,6 +369,8 @@ async fn many_concurrent_send_message_requests_that_fail() {
             reply: reply_tx.into(),
             peer_node_id: node_id2.clone(),
             body: TEST_MSG1.clone(),
+            cancellation: None,
+            priority: Default::default(),
         };
         msg_tags.push(out_msg.tag);
         reply_rxs.push(reply_rx);
@@ -392,3 +430,61 @@ async fn inactivity_timeout() {
 
     let _ = collect_stream!(inbound_msg_rx, take = 5, timeout = Duration::from_secs(10));
 }
+
+#[runtime::test_basic]
+async fn shutdown_flushes_queued_messages() {
+    let (_, node_identity, conn_man_mock, _, mut request_tx, _, _, mut shutdown) = spawn_messaging_protocol().await;
+
+    let peer_node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+    let (conn1, _, _, peer_conn_mock2) =
+        create_peer_connection_mock_pair(1, node_identity.to_peer(), peer_node_identity.to_peer()).await;
+    conn_man_mock.add_active_connection(conn1).await;
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let out_msg = OutboundMessage::with_reply(peer_node_identity.node_id().clone(), TEST_MSG1.clone(), reply_tx.into());
+    request_tx.send(MessagingRequest::SendMessage(out_msg)).await.unwrap();
+    shutdown.trigger().unwrap();
+
+    let stream = peer_conn_mock2.next_incoming_substream().await.unwrap();
+    let mut framed = MessagingProtocol::framed(stream);
+    let msg = framed.next().await.unwrap().unwrap();
+    assert_eq!(msg, TEST_MSG1);
+    reply_rx.await.unwrap().unwrap();
+}
+
+#[runtime::test_basic]
+async fn outbound_shutdown_fails_pending_messages() {
+    // The mock is not spawned so that the dial never completes
+    let (requester, _mock) = create_connectivity_mock();
+    let (events_tx, mut events_rx) = mpsc::channel(10);
+    let (msg_tx, msg_rx) = outbound_queue::channel();
+    let mut shutdown = Shutdown::new();
+    let peer_node_id = node_id::random();
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let out_msg = OutboundMessage::with_reply(peer_node_id.clone(), TEST_MSG1.clone(), reply_tx.into());
+    let queue_usage = OutboundQueueUsage::new();
+    queue_usage.message_queued(&out_msg);
+    msg_tx.send(out_msg).unwrap();
+
+    let outbound = OutboundMessaging::new(
+        requester,
+        events_tx,
+        msg_rx,
+        peer_node_id,
+        None,
+        queue_usage.clone(),
+        shutdown.to_signal(),
+    );
+    shutdown.trigger().unwrap();
+    outbound.run().await;
+
+    let reason = reply_rx.await.unwrap().unwrap_err();
+    unpack_enum!(SendFailReason::ShuttingDown = reason);
+    let event = events_rx.next().await.unwrap();
+    unpack_enum!(MessagingEvent::SendMessageFailed(_out_msg, _reason) = event);
+    let event = events_rx.next().await.unwrap();
+    unpack_enum!(MessagingEvent::OutboundProtocolExited(_node_id) = event);
+    assert_eq!(queue_usage.num_messages(), 0);
+    assert_eq!(queue_usage.approx_memory_usage(), 0);
+}
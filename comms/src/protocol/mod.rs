@@ -27,7 +27,18 @@ mod extensions;
 pub use extensions::{ProtocolExtension, ProtocolExtensionContext, ProtocolExtensionError, ProtocolExtensions};
 
 mod identity;
-pub use identity::{identity_exchange, IdentityProtocolError, IDENTITY_PROTOCOL};
+pub use identity::{
+    estimate_clock_skew,
+    identity_exchange,
+    verify_identity_signature,
+    IdentityProtocolError,
+    SignedPeerIdentity,
+    HANDSHAKE_PUZZLE_PROTOCOL_VERSION,
+    IDENTITY_PROTOCOL,
+    IDENTITY_SIGNATURE_PROTOCOL_VERSION,
+    NOISE_REKEY_PROTOCOL_VERSION,
+    SUPPORTED_PROTOCOL_VERSIONS,
+};
 
 mod negotiation;
 pub use negotiation::ProtocolNegotiation;
@@ -40,6 +51,8 @@ pub mod rpc;
 
 pub mod messaging;
 
+pub mod relay;
+
 /// Represents a protocol id string (e.g. /tari/transactions/1.0.0).
 /// This is atomically reference counted, so clones are shallow and cheap
 pub type ProtocolId = bytes::Bytes;
@@ -0,0 +1,99 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{
+    protocol::{read_status, write_message, RELAY_HOP_PROTOCOL},
+    RelayConfig,
+    RelayError,
+    RelayedCircuit,
+};
+use crate::{
+    connection_manager::ConnectionDirection,
+    connectivity::ConnectivityRequester,
+    noise::NoiseConfig,
+    peer_manager::NodeId,
+    proto::relay as proto,
+    NodeIdentity,
+};
+use log::*;
+use std::{sync::Arc, time::Duration};
+use tari_crypto::tari_utilities::ByteArray;
+use tokio::time;
+
+const LOG_TARGET: &str = "comms::protocol::relay::client";
+
+/// Opens circuits to peers through relays. This allows an application to open a stream to a peer that cannot be
+/// dialed directly, such as a peer behind NAT, that is connected to a peer that provides the relay service. The relay
+/// is dialed as usual, but the circuit itself is not a peer connection and is not known to the connectivity manager.
+#[derive(Clone)]
+pub struct RelayClient {
+    node_identity: Arc<NodeIdentity>,
+    connectivity: ConnectivityRequester,
+    circuit_setup_timeout: Duration,
+}
+
+impl RelayClient {
+    pub fn new(node_identity: Arc<NodeIdentity>, connectivity: ConnectivityRequester) -> Self {
+        Self {
+            node_identity,
+            connectivity,
+            circuit_setup_timeout: RelayConfig::default().circuit_setup_timeout,
+        }
+    }
+
+    /// Set the maximum time to wait for a circuit to be established
+    pub fn with_circuit_setup_timeout(mut self, timeout: Duration) -> Self {
+        self.circuit_setup_timeout = timeout;
+        self
+    }
+
+    /// Opens a circuit to `destination` through `relay`. The stream is end-to-end encrypted between this node and the
+    /// destination and the destination is authenticated, so the relay can neither read the stream nor impersonate
+    /// the destination.
+    pub async fn open_circuit(&mut self, relay: NodeId, destination: NodeId) -> Result<RelayedCircuit, RelayError> {
+        time::timeout(self.circuit_setup_timeout, self.try_open_circuit(relay, destination))
+            .await
+            .map_err(|_| RelayError::CircuitSetupTimeout)?
+    }
+
+    async fn try_open_circuit(&mut self, relay: NodeId, destination: NodeId) -> Result<RelayedCircuit, RelayError> {
+        let mut conn = self.connectivity.dial_peer(relay.clone()).await?;
+        let mut substream = conn.open_substream(&RELAY_HOP_PROTOCOL).await?.stream;
+        let request = proto::CircuitRequest {
+            node_id: destination.to_vec(),
+        };
+        write_message(&mut substream, &request).await?;
+        read_status(&mut substream).await?;
+
+        let socket = NoiseConfig::new(self.node_identity.clone())
+            .upgrade_socket(substream, ConnectionDirection::Outbound)
+            .await?;
+        let circuit = RelayedCircuit::new(destination, relay, socket)?;
+        debug!(
+            target: LOG_TARGET,
+            "Opened circuit to '{}' relayed by '{}'",
+            circuit.peer_node_id().short_str(),
+            circuit.relay_node_id().short_str()
+        );
+        Ok(circuit)
+    }
+}
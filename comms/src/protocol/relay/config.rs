@@ -0,0 +1,70 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    /// True to relay circuits between other peers. Circuits relayed for a client count towards its quota.
+    /// (default: false)
+    pub enable_relay_service: bool,
+    /// True to accept circuits that other peers open to this node through a relay.
+    /// (default: true)
+    pub accept_relayed_circuits: bool,
+    /// The maximum number of circuits that this node relays at the same time.
+    /// (default: 32)
+    pub max_circuits: usize,
+    /// The maximum number of circuits that this node relays at the same time for a single client.
+    /// (default: 2)
+    pub max_circuits_per_client: usize,
+    /// The maximum number of bytes per second relayed in each direction of a circuit.
+    /// (default: 64 KiB)
+    pub max_circuit_bandwidth: usize,
+    /// The maximum number of bytes relayed in both directions of all circuits opened by a client within each
+    /// `client_quota_period`. Circuits are closed once the quota is used.
+    /// (default: 16 MiB)
+    pub client_quota_bytes: u64,
+    /// (default: 1 hour)
+    pub client_quota_period: Duration,
+    /// The maximum time for which a circuit is relayed, after which it is closed.
+    /// (default: 30 minutes)
+    pub max_circuit_duration: Duration,
+    /// The maximum time to wait for a circuit to be established.
+    /// (default: 20 seconds)
+    pub circuit_setup_timeout: Duration,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            enable_relay_service: false,
+            accept_relayed_circuits: true,
+            max_circuits: 32,
+            max_circuits_per_client: 2,
+            max_circuit_bandwidth: 64 * 1024,
+            client_quota_bytes: 16 * 1024 * 1024,
+            client_quota_period: Duration::from_secs(60 * 60),
+            max_circuit_duration: Duration::from_secs(30 * 60),
+            circuit_setup_timeout: Duration::from_secs(20),
+        }
+    }
+}
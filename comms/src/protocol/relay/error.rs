@@ -0,0 +1,107 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{connection_manager::PeerConnectionError, connectivity::ConnectivityError, noise::NoiseError, proto};
+use std::{fmt, io};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RelayError {
+    #[error("ConnectivityError: {0}")]
+    ConnectivityError(#[from] ConnectivityError),
+    #[error("PeerConnectionError: {0}")]
+    PeerConnectionError(#[from] PeerConnectionError),
+    #[error("IO Error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to decode relay message: {0}")]
+    DecodeError(#[from] prost::DecodeError),
+    #[error("NoiseError: {0}")]
+    NoiseError(#[from] NoiseError),
+    #[error("Circuit rejected: {0}")]
+    CircuitRejected(CircuitRejectReason),
+    #[error("The substream closed unexpectedly")]
+    SubstreamClosed,
+    #[error("Relay message exceeded the maximum size")]
+    MessageTooLarge,
+    #[error("Timed out while establishing the circuit")]
+    CircuitSetupTimeout,
+    #[error("The relayed peer did not authenticate as the expected peer")]
+    PeerAuthenticationFailed,
+    #[error("The client relay quota has been used")]
+    QuotaExceeded,
+    #[error("Relay protocol has shut down")]
+    ShuttingDown,
+}
+
+/// The reason that a relay or destination peer rejected a circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitRejectReason {
+    RelayDisabled,
+    LimitReached,
+    QuotaExceeded,
+    DestinationUnreachable,
+    Refused,
+    MalformedRequest,
+}
+
+impl CircuitRejectReason {
+    /// Returns the reject reason for the given status, or None if the status indicates that the circuit is
+    /// established
+    pub(super) fn from_status(status: proto::relay::CircuitStatus) -> Option<Self> {
+        use proto::relay::CircuitStatus;
+        match status {
+            CircuitStatus::Ok => None,
+            CircuitStatus::RelayDisabled => Some(CircuitRejectReason::RelayDisabled),
+            CircuitStatus::LimitReached => Some(CircuitRejectReason::LimitReached),
+            CircuitStatus::QuotaExceeded => Some(CircuitRejectReason::QuotaExceeded),
+            CircuitStatus::DestinationUnreachable => Some(CircuitRejectReason::DestinationUnreachable),
+            CircuitStatus::Refused => Some(CircuitRejectReason::Refused),
+            CircuitStatus::MalformedRequest => Some(CircuitRejectReason::MalformedRequest),
+        }
+    }
+
+    pub(super) fn as_status(self) -> proto::relay::CircuitStatus {
+        use proto::relay::CircuitStatus;
+        match self {
+            CircuitRejectReason::RelayDisabled => CircuitStatus::RelayDisabled,
+            CircuitRejectReason::LimitReached => CircuitStatus::LimitReached,
+            CircuitRejectReason::QuotaExceeded => CircuitStatus::QuotaExceeded,
+            CircuitRejectReason::DestinationUnreachable => CircuitStatus::DestinationUnreachable,
+            CircuitRejectReason::Refused => CircuitStatus::Refused,
+            CircuitRejectReason::MalformedRequest => CircuitStatus::MalformedRequest,
+        }
+    }
+}
+
+impl fmt::Display for CircuitRejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use CircuitRejectReason::*;
+        match self {
+            RelayDisabled => write!(f, "the peer does not provide the relay service"),
+            LimitReached => write!(f, "the relay circuit limit has been reached"),
+            QuotaExceeded => write!(f, "the relay quota for this client has been used"),
+            DestinationUnreachable => write!(f, "the relay could not reach the destination peer"),
+            Refused => write!(f, "the destination peer does not accept relayed circuits"),
+            MalformedRequest => write!(f, "the circuit request was malformed"),
+        }
+    }
+}
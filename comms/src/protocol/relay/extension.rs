@@ -0,0 +1,83 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{
+    protocol::{RELAY_HOP_PROTOCOL, RELAY_STOP_PROTOCOL},
+    RelayConfig,
+    RelayProtocol,
+    RelayedCircuit,
+};
+use crate::{
+    protocol::{ProtocolExtension, ProtocolExtensionContext, ProtocolExtensionError},
+    runtime::task,
+    NodeIdentity,
+};
+use futures::channel::mpsc;
+use std::sync::Arc;
+
+const RELAY_PROTOCOL_EVENTS_BUFFER_SIZE: usize = 30;
+
+/// Installs the relay protocol. Circuits that other peers open to this node through a relay are sent on the given
+/// inbound circuit channel. Inbound circuits are not peer connections, so they are only used by the receiver of this
+/// channel.
+pub struct RelayProtocolExtension {
+    node_identity: Arc<NodeIdentity>,
+    inbound_circuit_tx: mpsc::Sender<RelayedCircuit>,
+    config: RelayConfig,
+}
+
+impl RelayProtocolExtension {
+    pub fn new(node_identity: Arc<NodeIdentity>, inbound_circuit_tx: mpsc::Sender<RelayedCircuit>) -> Self {
+        Self {
+            node_identity,
+            inbound_circuit_tx,
+            config: Default::default(),
+        }
+    }
+
+    /// Set the relay protocol config
+    pub fn with_config(mut self, config: RelayConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+impl ProtocolExtension for RelayProtocolExtension {
+    fn install(self: Box<Self>, context: &mut ProtocolExtensionContext) -> Result<(), ProtocolExtensionError> {
+        let (proto_tx, proto_rx) = mpsc::channel(RELAY_PROTOCOL_EVENTS_BUFFER_SIZE);
+        context.add_protocol(&[RELAY_HOP_PROTOCOL.clone(), RELAY_STOP_PROTOCOL.clone()], proto_tx);
+
+        let relay = RelayProtocol::new(
+            self.config,
+            self.node_identity,
+            context.connectivity(),
+            proto_rx,
+            self.inbound_circuit_tx,
+            context.shutdown_signal(),
+        )
+        .with_security_events(context.security_events());
+        context.register_complete_signal(relay.complete_signal());
+        task::spawn(relay.run());
+
+        Ok(())
+    }
+}
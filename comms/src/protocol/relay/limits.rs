@@ -0,0 +1,226 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{CircuitRejectReason, RelayConfig};
+use crate::peer_manager::NodeId;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Tracks the circuits relayed for each client and the number of bytes relayed for them in the current quota period.
+///
+/// This is cheap to clone and all clones share the same state.
+#[derive(Debug, Clone)]
+pub(super) struct RelayLimits {
+    inner: Arc<Mutex<LimitsInner>>,
+    max_circuits: usize,
+    max_circuits_per_client: usize,
+    quota_bytes: u64,
+    quota_period: Duration,
+}
+
+#[derive(Debug, Default)]
+struct LimitsInner {
+    num_circuits: usize,
+    clients: HashMap<NodeId, ClientUsage>,
+}
+
+#[derive(Debug)]
+struct ClientUsage {
+    num_circuits: usize,
+    period_start: Instant,
+    bytes_used: u64,
+}
+
+impl ClientUsage {
+    fn new() -> Self {
+        Self {
+            num_circuits: 0,
+            period_start: Instant::now(),
+            bytes_used: 0,
+        }
+    }
+
+    fn reset_if_period_elapsed(&mut self, quota_period: Duration) {
+        if self.period_start.elapsed() >= quota_period {
+            self.period_start = Instant::now();
+            self.bytes_used = 0;
+        }
+    }
+}
+
+impl RelayLimits {
+    pub fn new(config: &RelayConfig) -> Self {
+        Self {
+            inner: Default::default(),
+            max_circuits: config.max_circuits,
+            max_circuits_per_client: config.max_circuits_per_client,
+            quota_bytes: config.client_quota_bytes,
+            quota_period: config.client_quota_period,
+        }
+    }
+
+    /// Reserves a circuit for the client, returning a permit that releases the circuit when dropped
+    pub fn try_acquire(&self, client: &NodeId) -> Result<CircuitPermit, CircuitRejectReason> {
+        let mut inner = acquire_lock!(self.inner);
+        let quota_period = self.quota_period;
+        // Forget idle clients whose quota period has elapsed
+        inner
+            .clients
+            .retain(|_, usage| usage.num_circuits > 0 || usage.period_start.elapsed() < quota_period);
+
+        if inner.num_circuits >= self.max_circuits {
+            return Err(CircuitRejectReason::LimitReached);
+        }
+        let usage = inner.clients.entry(client.clone()).or_insert_with(ClientUsage::new);
+        usage.reset_if_period_elapsed(quota_period);
+        if usage.num_circuits >= self.max_circuits_per_client {
+            return Err(CircuitRejectReason::LimitReached);
+        }
+        if usage.bytes_used >= self.quota_bytes {
+            return Err(CircuitRejectReason::QuotaExceeded);
+        }
+        usage.num_circuits += 1;
+        inner.num_circuits += 1;
+
+        Ok(CircuitPermit {
+            limits: self.clone(),
+            client: client.clone(),
+        })
+    }
+
+    /// Adds the given number of relayed bytes to the client's usage. Returns false if the client has exceeded its
+    /// quota for the current period.
+    pub fn record_usage(&self, client: &NodeId, num_bytes: usize) -> bool {
+        let mut inner = acquire_lock!(self.inner);
+        match inner.clients.get_mut(client) {
+            Some(usage) => {
+                usage.reset_if_period_elapsed(self.quota_period);
+                usage.bytes_used += num_bytes as u64;
+                usage.bytes_used <= self.quota_bytes
+            },
+            None => false,
+        }
+    }
+
+    pub fn num_circuits(&self) -> usize {
+        acquire_lock!(self.inner).num_circuits
+    }
+
+    fn release(&self, client: &NodeId) {
+        let mut inner = acquire_lock!(self.inner);
+        inner.num_circuits = inner.num_circuits.saturating_sub(1);
+        if let Some(usage) = inner.clients.get_mut(client) {
+            usage.num_circuits = usage.num_circuits.saturating_sub(1);
+        }
+    }
+}
+
+/// A circuit reserved for a client. The circuit is released when this is dropped.
+#[derive(Debug)]
+pub(super) struct CircuitPermit {
+    limits: RelayLimits,
+    client: NodeId,
+}
+
+impl CircuitPermit {
+    pub fn client(&self) -> &NodeId {
+        &self.client
+    }
+
+    /// Adds the given number of relayed bytes to the client's usage. Returns false if the client has exceeded its
+    /// quota for the current period.
+    pub fn record_usage(&self, num_bytes: usize) -> bool {
+        self.limits.record_usage(&self.client, num_bytes)
+    }
+}
+
+impl Drop for CircuitPermit {
+    fn drop(&mut self) {
+        self.limits.release(&self.client);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::node_id;
+
+    fn make_limits(quota_period: Duration) -> RelayLimits {
+        RelayLimits::new(&RelayConfig {
+            max_circuits: 3,
+            max_circuits_per_client: 2,
+            client_quota_bytes: 100,
+            client_quota_period: quota_period,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn circuit_limits() {
+        let limits = make_limits(Duration::from_secs(60));
+        let client1 = node_id::random();
+        let client2 = node_id::random();
+
+        let permit1 = limits.try_acquire(&client1).unwrap();
+        let _permit2 = limits.try_acquire(&client1).unwrap();
+        assert_eq!(
+            limits.try_acquire(&client1).unwrap_err(),
+            CircuitRejectReason::LimitReached
+        );
+        let _permit3 = limits.try_acquire(&client2).unwrap();
+        assert_eq!(limits.num_circuits(), 3);
+        assert_eq!(
+            limits.try_acquire(&client2).unwrap_err(),
+            CircuitRejectReason::LimitReached
+        );
+
+        drop(permit1);
+        assert_eq!(limits.num_circuits(), 2);
+        let _permit4 = limits.try_acquire(&client1).unwrap();
+    }
+
+    #[test]
+    fn client_quota() {
+        let limits = make_limits(Duration::from_secs(60));
+        let client = node_id::random();
+
+        let permit = limits.try_acquire(&client).unwrap();
+        assert!(permit.record_usage(60));
+        assert!(permit.record_usage(40));
+        assert!(!permit.record_usage(1));
+        drop(permit);
+        // The quota is kept after the client's circuits are closed
+        assert_eq!(
+            limits.try_acquire(&client).unwrap_err(),
+            CircuitRejectReason::QuotaExceeded
+        );
+
+        let limits = make_limits(Duration::from_secs(0));
+        let permit = limits.try_acquire(&client).unwrap();
+        assert!(permit.record_usage(100));
+        // The quota period has elapsed
+        assert!(permit.record_usage(100));
+    }
+}
@@ -0,0 +1,57 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Relay protocol
+//!
+//! Two peers behind NAT are unable to dial each other. The relay protocol allows a peer (the client) to open a circuit
+//! to such a peer (the destination) through a third peer (the relay) that both are able to connect to.
+//!
+//! The client opens a hop substream to the relay, naming the destination. The relay opens a stop substream to the
+//! destination, naming the client, over an existing connection if there is one. Once the destination accepts, the
+//! relay forwards bytes between the two substreams and the client and destination perform a noise handshake over the
+//! circuit. The circuit is therefore end-to-end encrypted and both ends are authenticated.
+//!
+//! A circuit is a single end-to-end encrypted stream, not a peer connection. Circuits are not registered with the
+//! connection manager or the connectivity manager, so they are not used to dial peers, do not count as connections and
+//! do not carry messaging or other protocols. Applications open circuits using [RelayClient] and receive circuits
+//! opened to them on the channel given to [RelayProtocolExtension], and use the circuit as a stream.
+//!
+//! Peers only relay circuits if `RelayConfig::enable_relay_service` is set. Relays cap the bandwidth of each circuit,
+//! the number of circuits relayed in total and for each client, and the number of bytes relayed for each client
+//! within a quota period.
+
+mod client;
+pub use client::RelayClient;
+
+mod config;
+pub use config::RelayConfig;
+
+mod error;
+pub use error::{CircuitRejectReason, RelayError};
+
+mod extension;
+pub use extension::RelayProtocolExtension;
+
+mod limits;
+
+mod protocol;
+pub use protocol::{RelayProtocol, RelayedCircuit, RELAY_HOP_PROTOCOL, RELAY_STOP_PROTOCOL};
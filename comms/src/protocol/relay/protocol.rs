@@ -0,0 +1,602 @@
+//  Copyright 2020, The Tari Project
+//
+//  Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+//  following conditions are met:
+//
+//  1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+//  disclaimer.
+//
+//  2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+//  following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+//  3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+//  products derived from this software without specific prior written permission.
+//
+//  THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+//  INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+//  DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+//  SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+//  SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+//  WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+//  USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{
+    limits::{CircuitPermit, RelayLimits},
+    CircuitRejectReason,
+    RelayConfig,
+    RelayError,
+};
+use crate::{
+    connection_manager::ConnectionDirection,
+    connectivity::ConnectivityRequester,
+    message::MessageExt,
+    noise::{NoiseConfig, NoiseSocket},
+    peer_manager::NodeId,
+    proto::relay as proto,
+    protocol::{ProtocolEvent, ProtocolNotification, ProtocolNotificationRx},
+    runtime::task,
+    security_events::{SecurityEventKind, SecurityEventPublisher},
+    types::CommsPublicKey,
+    NodeIdentity,
+    Substream,
+};
+use bytes::Bytes;
+use futures::{
+    channel::mpsc,
+    future,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    SinkExt,
+    StreamExt,
+};
+use log::*;
+use std::{
+    cmp,
+    convert::TryFrom,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tari_crypto::tari_utilities::{hex::Hex, ByteArray};
+use tari_shutdown::{Shutdown, ShutdownSignal};
+use tokio::time;
+
+const LOG_TARGET: &str = "comms::protocol::relay";
+
+/// Spoken by clients to a relay to open a circuit to a destination peer
+pub static RELAY_HOP_PROTOCOL: Bytes = Bytes::from_static(b"/tari/relay/hop/0.1.0");
+/// Spoken by a relay to a destination peer to announce a circuit from a client
+pub static RELAY_STOP_PROTOCOL: Bytes = Bytes::from_static(b"/tari/relay/stop/0.1.0");
+
+/// The maximum size of a circuit request or response
+const MAX_RELAY_MESSAGE_SIZE: usize = 1024;
+/// The maximum number of bytes read from one side of a circuit before they are written to the other side
+const RELAY_BUFFER_SIZE: usize = 8 * 1024;
+
+/// An end-to-end encrypted stream to a peer, relayed by another peer. The relay forwards the encrypted stream but is
+/// unable to read or alter it. A circuit is a plain stream rather than a peer connection, so it cannot be used to open
+/// protocol substreams.
+pub struct RelayedCircuit {
+    peer_node_id: NodeId,
+    peer_public_key: CommsPublicKey,
+    relay_node_id: NodeId,
+    socket: NoiseSocket<Substream>,
+}
+
+impl RelayedCircuit {
+    /// Checks that the peer authenticated during the noise handshake is the expected peer
+    pub(super) fn new(
+        expected_peer: NodeId,
+        relay_node_id: NodeId,
+        socket: NoiseSocket<Substream>,
+    ) -> Result<Self, RelayError>
+    {
+        let peer_public_key = socket
+            .get_remote_public_key()
+            .ok_or(RelayError::PeerAuthenticationFailed)?;
+        if NodeId::from_public_key(&peer_public_key) != expected_peer {
+            return Err(RelayError::PeerAuthenticationFailed);
+        }
+        Ok(Self {
+            peer_node_id: expected_peer,
+            peer_public_key,
+            relay_node_id,
+            socket,
+        })
+    }
+
+    /// The peer at the other end of the circuit
+    pub fn peer_node_id(&self) -> &NodeId {
+        &self.peer_node_id
+    }
+
+    pub fn peer_public_key(&self) -> &CommsPublicKey {
+        &self.peer_public_key
+    }
+
+    /// The peer relaying the circuit
+    pub fn relay_node_id(&self) -> &NodeId {
+        &self.relay_node_id
+    }
+}
+
+impl AsyncRead for RelayedCircuit {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.socket).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for RelayedCircuit {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.socket).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.socket).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.socket).poll_close(cx)
+    }
+}
+
+/// Handles inbound relay substreams. Hop substreams are relayed to their destination if this node provides the relay
+/// service, and circuits announced on stop substreams are emitted on the inbound circuit channel.
+pub struct RelayProtocol {
+    config: RelayConfig,
+    node_identity: Arc<NodeIdentity>,
+    connectivity: ConnectivityRequester,
+    limits: RelayLimits,
+    notifications: ProtocolNotificationRx<Substream>,
+    inbound_circuit_tx: mpsc::Sender<RelayedCircuit>,
+    shutdown_signal: ShutdownSignal,
+    complete_trigger: Shutdown,
+    security_events: SecurityEventPublisher,
+}
+
+impl RelayProtocol {
+    pub fn new(
+        config: RelayConfig,
+        node_identity: Arc<NodeIdentity>,
+        connectivity: ConnectivityRequester,
+        notifications: ProtocolNotificationRx<Substream>,
+        inbound_circuit_tx: mpsc::Sender<RelayedCircuit>,
+        shutdown_signal: ShutdownSignal,
+    ) -> Self
+    {
+        Self {
+            limits: RelayLimits::new(&config),
+            config,
+            node_identity,
+            connectivity,
+            notifications,
+            inbound_circuit_tx,
+            shutdown_signal,
+            complete_trigger: Shutdown::new(),
+            security_events: Default::default(),
+        }
+    }
+
+    /// Set the publisher used to report clients that exceed their relay quota
+    pub fn with_security_events(mut self, security_events: SecurityEventPublisher) -> Self {
+        self.security_events = security_events;
+        self
+    }
+
+    pub fn complete_signal(&self) -> ShutdownSignal {
+        self.complete_trigger.to_signal()
+    }
+
+    pub async fn run(mut self) {
+        let mut shutdown_signal = self.shutdown_signal.clone();
+        loop {
+            futures::select! {
+                notification = self.notifications.select_next_some() => {
+                    self.handle_notification(notification);
+                },
+                _ = shutdown_signal => {
+                    info!(target: LOG_TARGET, "RelayProtocol is shutting down");
+                    break;
+                }
+            }
+        }
+    }
+
+    fn handle_notification(&self, notification: ProtocolNotification<Substream>) {
+        let ProtocolEvent::NewInboundSubstream(node_id, substream) = notification.event;
+        if notification.protocol == RELAY_HOP_PROTOCOL {
+            let hop = HopHandler {
+                config: self.config.clone(),
+                connectivity: self.connectivity.clone(),
+                limits: self.limits.clone(),
+                node_id: self.node_identity.node_id().clone(),
+                security_events: self.security_events.clone(),
+            };
+            task::spawn(async move {
+                if let Err(err) = hop.handle(node_id.clone(), substream).await {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Relay circuit for client '{}' failed: {}",
+                        node_id.short_str(),
+                        err
+                    );
+                }
+            });
+        } else if notification.protocol == RELAY_STOP_PROTOCOL {
+            let config = self.config.clone();
+            let node_identity = self.node_identity.clone();
+            let inbound_circuit_tx = self.inbound_circuit_tx.clone();
+            task::spawn(async move {
+                let result = time::timeout(
+                    config.circuit_setup_timeout,
+                    accept_circuit(&config, node_identity, node_id.clone(), substream, inbound_circuit_tx),
+                )
+                .await
+                .unwrap_or(Err(RelayError::CircuitSetupTimeout));
+                if let Err(err) = result {
+                    debug!(
+                        target: LOG_TARGET,
+                        "Failed to accept circuit relayed by '{}': {}",
+                        node_id.short_str(),
+                        err
+                    );
+                }
+            });
+        } else {
+            warn!(
+                target: LOG_TARGET,
+                "Received substream for unexpected protocol '{}'",
+                String::from_utf8_lossy(&notification.protocol)
+            );
+        }
+    }
+}
+
+/// Accepts a circuit announced by a relay on a stop substream
+async fn accept_circuit(
+    config: &RelayConfig,
+    node_identity: Arc<NodeIdentity>,
+    relay_node_id: NodeId,
+    mut substream: Substream,
+    mut inbound_circuit_tx: mpsc::Sender<RelayedCircuit>,
+) -> Result<(), RelayError>
+{
+    let request = read_message::<_, proto::CircuitRequest>(&mut substream).await?;
+    let initiator = match NodeId::try_from(request.node_id.as_slice()) {
+        Ok(node_id) => node_id,
+        Err(_) => {
+            write_status(&mut substream, proto::CircuitStatus::MalformedRequest).await?;
+            return Ok(());
+        },
+    };
+    if !config.accept_relayed_circuits {
+        write_status(&mut substream, proto::CircuitStatus::Refused).await?;
+        return Ok(());
+    }
+    write_status(&mut substream, proto::CircuitStatus::Ok).await?;
+
+    let socket = NoiseConfig::new(node_identity)
+        .upgrade_socket(substream, ConnectionDirection::Inbound)
+        .await?;
+    let circuit = RelayedCircuit::new(initiator, relay_node_id, socket)?;
+    debug!(
+        target: LOG_TARGET,
+        "Accepted circuit from '{}' relayed by '{}'",
+        circuit.peer_node_id().short_str(),
+        circuit.relay_node_id().short_str()
+    );
+    inbound_circuit_tx
+        .send(circuit)
+        .await
+        .map_err(|_| RelayError::ShuttingDown)?;
+    Ok(())
+}
+
+/// Relays a circuit requested by a client on a hop substream
+struct HopHandler {
+    config: RelayConfig,
+    connectivity: ConnectivityRequester,
+    limits: RelayLimits,
+    node_id: NodeId,
+    security_events: SecurityEventPublisher,
+}
+
+impl HopHandler {
+    async fn handle(mut self, client: NodeId, mut client_substream: Substream) -> Result<(), RelayError> {
+        let request = time::timeout(
+            self.config.circuit_setup_timeout,
+            read_message::<_, proto::CircuitRequest>(&mut client_substream),
+        )
+        .await
+        .unwrap_or(Err(RelayError::CircuitSetupTimeout))?;
+        if !self.config.enable_relay_service {
+            return reject(&mut client_substream, CircuitRejectReason::RelayDisabled).await;
+        }
+        let destination = match NodeId::try_from(request.node_id.as_slice()) {
+            Ok(node_id) if node_id != client && node_id != self.node_id => node_id,
+            _ => return reject(&mut client_substream, CircuitRejectReason::MalformedRequest).await,
+        };
+        let permit = match self.limits.try_acquire(&client) {
+            Ok(permit) => permit,
+            Err(reason) => {
+                if let CircuitRejectReason::QuotaExceeded = reason {
+                    self.publish_quota_exceeded(&client);
+                }
+                return reject(&mut client_substream, reason).await;
+            },
+        };
+
+        let destination_substream = match time::timeout(
+            self.config.circuit_setup_timeout,
+            self.open_stop_substream(&client, &destination),
+        )
+        .await
+        {
+            Ok(Ok(substream)) => substream,
+            Ok(Err(RelayError::CircuitRejected(reason))) => return reject(&mut client_substream, reason).await,
+            Ok(Err(err)) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Unable to open circuit to '{}' for client '{}': {}",
+                    destination.short_str(),
+                    client.short_str(),
+                    err
+                );
+                return reject(&mut client_substream, CircuitRejectReason::DestinationUnreachable).await;
+            },
+            Err(_) => return reject(&mut client_substream, CircuitRejectReason::DestinationUnreachable).await,
+        };
+        write_status(&mut client_substream, proto::CircuitStatus::Ok).await?;
+
+        debug!(
+            target: LOG_TARGET,
+            "Relaying circuit from '{}' to '{}' ({} active circuit(s))",
+            client.short_str(),
+            destination.short_str(),
+            self.limits.num_circuits()
+        );
+        let result = time::timeout(
+            self.config.max_circuit_duration,
+            splice(
+                client_substream,
+                destination_substream,
+                &permit,
+                self.config.max_circuit_bandwidth,
+            ),
+        )
+        .await;
+        match result {
+            Ok(Err(RelayError::QuotaExceeded)) => {
+                self.publish_quota_exceeded(&client);
+                Err(RelayError::QuotaExceeded)
+            },
+            Ok(result) => result,
+            Err(_) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Closing circuit from '{}' to '{}' because it reached the maximum circuit duration",
+                    client.short_str(),
+                    destination.short_str()
+                );
+                Ok(())
+            },
+        }
+    }
+
+    fn publish_quota_exceeded(&self, client: &NodeId) {
+        self.security_events.publish(SecurityEventKind::QuotaExceeded {
+            node_id: client.to_hex(),
+            quota: "relay_bytes".to_string(),
+        });
+    }
+
+    /// Opens a stop substream to the destination and announces the circuit. Peers behind NAT cannot be dialed, so an
+    /// existing connection is used if there is one.
+    async fn open_stop_substream(&mut self, client: &NodeId, destination: &NodeId) -> Result<Substream, RelayError> {
+        let mut conn = match self.connectivity.get_connection(destination.clone()).await? {
+            Some(conn) => conn,
+            None => self.connectivity.dial_peer(destination.clone()).await?,
+        };
+        let mut substream = conn.open_substream(&RELAY_STOP_PROTOCOL).await?.stream;
+        let request = proto::CircuitRequest {
+            node_id: client.to_vec(),
+        };
+        write_message(&mut substream, &request).await?;
+        read_status(&mut substream).await?;
+        Ok(substream)
+    }
+}
+
+async fn reject(substream: &mut Substream, reason: CircuitRejectReason) -> Result<(), RelayError> {
+    write_status(substream, reason.as_status()).await?;
+    Err(RelayError::CircuitRejected(reason))
+}
+
+/// Copies bytes in both directions between the client and destination substreams. Each direction is closed
+/// independently when its reader reaches EOF, so a side that has finished writing can still receive. The circuit ends
+/// once both directions have finished, or as soon as either fails (including when the client's quota is used).
+async fn splice<S>(
+    client_substream: S,
+    destination_substream: S,
+    permit: &CircuitPermit,
+    max_bandwidth: usize,
+) -> Result<(), RelayError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (client_reader, client_writer) = client_substream.split();
+    let (destination_reader, destination_writer) = destination_substream.split();
+    let upstream = copy_limited(client_reader, destination_writer, permit, max_bandwidth);
+    let downstream = copy_limited(destination_reader, client_writer, permit, max_bandwidth);
+    future::try_join(upstream, downstream).await?;
+    Ok(())
+}
+
+/// Copies bytes from the reader to the writer at no more than `max_bandwidth` bytes per second, counting them
+/// towards the client's quota
+pub(super) async fn copy_limited<R, W>(
+    mut reader: R,
+    mut writer: W,
+    permit: &CircuitPermit,
+    max_bandwidth: usize,
+) -> Result<(), RelayError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; cmp::min(RELAY_BUFFER_SIZE, cmp::max(max_bandwidth, 1))];
+    let mut window_start = Instant::now();
+    let mut window_bytes = 0;
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            writer.close().await?;
+            return Ok(());
+        }
+        if !permit.record_usage(n) {
+            debug!(
+                target: LOG_TARGET,
+                "Closing circuit because client '{}' has used its relay quota",
+                permit.client().short_str()
+            );
+            return Err(RelayError::QuotaExceeded);
+        }
+        writer.write_all(&buf[..n]).await?;
+        writer.flush().await?;
+
+        window_bytes += n;
+        if window_bytes >= max_bandwidth {
+            let elapsed = window_start.elapsed();
+            if elapsed < Duration::from_secs(1) {
+                time::delay_for(Duration::from_secs(1) - elapsed).await;
+            }
+            window_start = Instant::now();
+            window_bytes = 0;
+        }
+    }
+}
+
+pub(super) async fn write_status<S>(socket: &mut S, status: proto::CircuitStatus) -> Result<(), RelayError>
+where S: AsyncWrite + Unpin {
+    write_message(socket, &proto::CircuitResponse { status: status as i32 }).await
+}
+
+/// Reads a circuit response, returning an error if the circuit was rejected
+pub(super) async fn read_status<S>(socket: &mut S) -> Result<(), RelayError>
+where S: AsyncRead + Unpin {
+    let response = read_message::<_, proto::CircuitResponse>(socket).await?;
+    let status = proto::CircuitStatus::from_i32(response.status).unwrap_or(proto::CircuitStatus::MalformedRequest);
+    match CircuitRejectReason::from_status(status) {
+        Some(reason) => Err(RelayError::CircuitRejected(reason)),
+        None => Ok(()),
+    }
+}
+
+/// Writes a length-prefixed relay message
+pub(super) async fn write_message<S, M>(socket: &mut S, message: &M) -> Result<(), RelayError>
+where
+    S: AsyncWrite + Unpin,
+    M: prost::Message,
+{
+    let bytes = message.to_encoded_bytes();
+    socket.write_all(&(bytes.len() as u32).to_be_bytes()).await?;
+    socket.write_all(&bytes).await?;
+    socket.flush().await?;
+    Ok(())
+}
+
+/// Reads a length-prefixed relay message
+pub(super) async fn read_message<S, M>(socket: &mut S) -> Result<M, RelayError>
+where
+    S: AsyncRead + Unpin,
+    M: prost::Message + Default,
+{
+    let mut len_buf = [0u8; 4];
+    socket.read_exact(&mut len_buf).await.map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => RelayError::SubstreamClosed,
+        _ => err.into(),
+    })?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > MAX_RELAY_MESSAGE_SIZE {
+        return Err(RelayError::MessageTooLarge);
+    }
+    let mut buf = vec![0u8; len];
+    socket.read_exact(&mut buf).await?;
+    Ok(M::decode(buf.as_slice())?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{memsocket::MemorySocket, runtime, test_utils::node_id};
+    use tari_test_utils::unpack_enum;
+
+    #[runtime::test_basic]
+    async fn read_write_status() {
+        let (mut socket_a, mut socket_b) = MemorySocket::new_pair();
+        write_status(&mut socket_a, proto::CircuitStatus::Ok).await.unwrap();
+        read_status(&mut socket_b).await.unwrap();
+
+        write_status(&mut socket_a, proto::CircuitStatus::LimitReached)
+            .await
+            .unwrap();
+        let err = read_status(&mut socket_b).await.unwrap_err();
+        unpack_enum!(RelayError::CircuitRejected(reason) = err);
+        assert_eq!(reason, CircuitRejectReason::LimitReached);
+
+        drop(socket_a);
+        let err = read_status(&mut socket_b).await.unwrap_err();
+        unpack_enum!(RelayError::SubstreamClosed = err);
+    }
+
+    #[runtime::test_basic]
+    async fn copy_limited_counts_towards_quota() {
+        let limits = RelayLimits::new(&RelayConfig {
+            client_quota_bytes: 10,
+            ..Default::default()
+        });
+        let permit = limits.try_acquire(&node_id::random()).unwrap();
+
+        let (mut client, relay_in) = MemorySocket::new_pair();
+        let (relay_out, mut destination) = MemorySocket::new_pair();
+        client.write_all(b"hello").await.unwrap();
+        drop(client);
+        copy_limited(relay_in, relay_out, &permit, 1024).await.unwrap();
+        let mut buf = Vec::new();
+        destination.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello");
+
+        let (mut client, relay_in) = MemorySocket::new_pair();
+        let (relay_out, _destination) = MemorySocket::new_pair();
+        client.write_all(&[0u8; 10]).await.unwrap();
+        let err = copy_limited(relay_in, relay_out, &permit, 1024).await.unwrap_err();
+        unpack_enum!(RelayError::QuotaExceeded = err);
+    }
+
+    #[runtime::test_basic]
+    async fn splice_half_close() {
+        let limits = RelayLimits::new(&RelayConfig::default());
+        let permit = limits.try_acquire(&node_id::random()).unwrap();
+
+        let (mut client, relay_in) = MemorySocket::new_pair();
+        let (relay_out, mut destination) = MemorySocket::new_pair();
+        let circuit = splice(relay_in, relay_out, &permit, 1024);
+        let peers = async move {
+            client.write_all(b"ping").await.unwrap();
+            client.close().await.unwrap();
+            let mut buf = Vec::new();
+            destination.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"ping");
+
+            // The destination can still reply after the client has finished writing
+            destination.write_all(b"pong").await.unwrap();
+            destination.close().await.unwrap();
+            let mut buf = Vec::new();
+            client.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"pong");
+        };
+        let (result, _) = future::join(circuit, peers).await;
+        result.unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{SecurityEvent, SecurityEventKind, SecurityEventPublisher, SecurityEventReceiver};
+use crate::{admin::AdminCommands, compat::IoCompat};
+use futures::{AsyncWriteExt, FutureExt};
+use log::*;
+use tari_shutdown::ShutdownSignal;
+use tokio::{net::TcpStream, sync::broadcast::RecvError};
+
+const LOG_TARGET: &str = "comms::security_events::admin_command";
+
+/// The name of the admin command that serves security events
+pub const ADMIN_COMMAND: &str = "security_events";
+
+/// Registers the `security_events` admin command. The command serves the events published after the client connected
+/// as JSON lines until the client disconnects or the shutdown signal is triggered.
+pub fn register_admin_command(
+    commands: &AdminCommands,
+    publisher: SecurityEventPublisher,
+    shutdown_signal: ShutdownSignal,
+)
+{
+    commands.register(ADMIN_COMMAND, move |socket| {
+        serve_client(socket, publisher.subscribe(), shutdown_signal.clone())
+    });
+}
+
+async fn serve_client(socket: TcpStream, mut events: SecurityEventReceiver, mut shutdown_signal: ShutdownSignal) {
+    let mut socket = IoCompat::new(socket);
+    loop {
+        let result = futures::select! {
+            result = events.recv().fuse() => result,
+            _ = shutdown_signal => break,
+        };
+        let mut line = match result {
+            Ok(event) => event.to_json_line(),
+            Err(RecvError::Lagged(count)) => {
+                SecurityEvent::new(SecurityEventKind::EventsDropped { count }).to_json_line()
+            },
+            Err(RecvError::Closed) => break,
+        };
+        line.push('\n');
+        if let Err(err) = socket.write_all(line.as_bytes()).await {
+            debug!(target: LOG_TARGET, "Security event client disconnected: {}", err);
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{admin::AdminSocket, runtime};
+    use futures::{io::BufReader, AsyncBufReadExt};
+    use serde_json::Value;
+    use std::time::Duration;
+    use tari_shutdown::Shutdown;
+    use tokio::{task, time};
+
+    #[runtime::test_basic]
+    async fn serves_json_lines() {
+        let shutdown = Shutdown::new();
+        let publisher = SecurityEventPublisher::default();
+        let commands = AdminCommands::new();
+        register_admin_command(&commands, publisher.clone(), shutdown.to_signal());
+        let addr = AdminSocket::new(commands, shutdown.to_signal())
+            .spawn("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+
+        let mut socket = IoCompat::new(TcpStream::connect(addr).await.unwrap());
+        socket.write_all(b"security_events\n").await.unwrap();
+        let mut reader = BufReader::new(socket);
+        // Events published before the client is subscribed are not received, so publish until one is received
+        task::spawn(async move {
+            for _ in 0..100 {
+                publisher.publish(SecurityEventKind::QuotaExceeded {
+                    node_id: "abcd".to_string(),
+                    quota: "relay_bytes".to_string(),
+                });
+                time::delay_for(Duration::from_millis(10)).await;
+            }
+        });
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        let value = serde_json::from_str::<Value>(&line).unwrap();
+        assert_eq!(value["event"], "quota_exceeded");
+        assert_eq!(value["node_id"], "abcd");
+        assert_eq!(value["quota"], "relay_bytes");
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{connection_manager::ConnectionManagerError, multiaddr::Multiaddr};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// A security-relevant event that occurred at `timestamp`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SecurityEvent {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: SecurityEventKind,
+}
+
+impl SecurityEvent {
+    pub fn new(kind: SecurityEventKind) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            kind,
+        }
+    }
+
+    /// Serializes the event as a single line of JSON, without a trailing newline
+    pub fn to_json_line(&self) -> String {
+        serde_json::to_string(self).expect("SecurityEvent serialization cannot fail")
+    }
+}
+
+/// The type of security event. Node ids and public keys are hex encoded and addresses are multiaddrs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SecurityEventKind {
+    /// A signature provided by a peer failed verification
+    InvalidSignature {
+        /// The peer that sent the signature, if known
+        node_id: Option<String>,
+        /// The address of the peer, if known
+        address: Option<String>,
+        /// What was signed (e.g. a peer identity or message origin)
+        context: String,
+    },
+    /// A peer was banned
+    PeerBanned {
+        node_id: String,
+        duration_secs: u64,
+        reason: String,
+    },
+    /// A peer exceeded a usage quota and was refused service
+    QuotaExceeded { node_id: String, quota: String },
+    /// An inbound connection failed the connection handshake
+    HandshakeFailed { address: String, reason: String },
+    /// The subscriber fell behind and missed `count` events
+    EventsDropped { count: u64 },
+}
+
+impl SecurityEventKind {
+    /// Returns the security event for an inbound connection that failed with the given error. Errors that do not
+    /// indicate a handshake anomaly (e.g. transport errors) return None.
+    pub(crate) fn from_inbound_connect_error(address: &Multiaddr, err: &ConnectionManagerError) -> Option<Self> {
+        use ConnectionManagerError::*;
+        match err {
+            PeerIdentityInvalidSignature => Some(SecurityEventKind::InvalidSignature {
+                node_id: None,
+                address: Some(address.to_string()),
+                context: "peer identity".to_string(),
+            }),
+            NoiseError(_) |
+            NoiseProtocolTimeout |
+            InvalidStaticPublicKey |
+            PeerIdentityInvalidNodeId |
+            PeerIdentityNoValidAddresses |
+            PeerVersionNotSupported(_) |
+            PeerBanned |
+            IdentityProtocolError(_) |
+            IdentityExchangeTimeout |
+            HandshakePuzzleTimeout |
+            HandshakePuzzleInvalidSolution |
+            HandshakePuzzleTooDifficult(_) => Some(SecurityEventKind::HandshakeFailed {
+                address: address.to_string(),
+                reason: err.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::Value;
+    use tari_test_utils::unpack_enum;
+
+    #[test]
+    fn to_json_line() {
+        let event = SecurityEvent::new(SecurityEventKind::PeerBanned {
+            node_id: "abcd".to_string(),
+            duration_secs: 60,
+            reason: "Invalid signature".to_string(),
+        });
+        let line = event.to_json_line();
+        assert!(!line.contains('\n'));
+        let value = serde_json::from_str::<Value>(&line).unwrap();
+        assert_eq!(value["event"], "peer_banned");
+        assert_eq!(value["node_id"], "abcd");
+        assert_eq!(value["duration_secs"], 60);
+        assert_eq!(value["reason"], "Invalid signature");
+        assert!(value["timestamp"].is_string());
+    }
+
+    #[test]
+    fn from_inbound_connect_error() {
+        let addr = "/ip4/1.2.3.4/tcp/1234".parse::<Multiaddr>().unwrap();
+        let kind = SecurityEventKind::from_inbound_connect_error(&addr, &ConnectionManagerError::NoiseProtocolTimeout)
+            .unwrap();
+        unpack_enum!(SecurityEventKind::HandshakeFailed { address, reason } = kind);
+        assert_eq!(address, "/ip4/1.2.3.4/tcp/1234");
+        assert!(!reason.is_empty());
+        let kind =
+            SecurityEventKind::from_inbound_connect_error(&addr, &ConnectionManagerError::PeerIdentityInvalidSignature)
+                .unwrap();
+        assert!(matches!(kind, SecurityEventKind::InvalidSignature { .. }));
+        assert!(SecurityEventKind::from_inbound_connect_error(&addr, &ConnectionManagerError::DialCancelled).is_none());
+    }
+}
@@ -0,0 +1,48 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! # Security events
+//!
+//! Security-relevant events (invalid signatures, peer bans, quota violations and handshake failures) are published
+//! to a [SecurityEventPublisher](self::SecurityEventPublisher) so that operators can feed them into external
+//! intrusion detection tooling such as fail2ban or a SIEM.
+//!
+//! The `security_events` command of the [admin socket](crate::admin) serves the events as JSON lines (one JSON object
+//! per line) to every client that sends it. For example:
+//!
+//! ```text
+//! {"timestamp":"2020-11-02T10:21:03Z","event":"peer_banned","node_id":"e6f2...","duration_secs":3600,"reason":"..."}
+//! {"timestamp":"2020-11-02T10:21:04Z","event":"handshake_failed","address":"/ip4/1.2.3.4/tcp/4123","reason":"..."}
+//! ```
+//!
+//! Every event has a `timestamp` and an `event` type. The remaining fields depend on the event type (see
+//! [SecurityEventKind](self::SecurityEventKind)). A client that does not read events quickly enough receives an
+//! `events_dropped` event with the number of events that it missed.
+
+mod event;
+pub use event::{SecurityEvent, SecurityEventKind};
+
+mod publisher;
+pub use publisher::{SecurityEventPublisher, SecurityEventReceiver};
+
+mod admin_command;
+pub use admin_command::{register_admin_command, ADMIN_COMMAND};
@@ -0,0 +1,65 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{SecurityEvent, SecurityEventKind};
+use log::*;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+const LOG_TARGET: &str = "comms::security_events";
+
+const SECURITY_EVENT_BUFFER_SIZE: usize = 100;
+
+pub type SecurityEventReceiver = broadcast::Receiver<Arc<SecurityEvent>>;
+
+/// Publishes security events to all subscribers. Publishing when there are no subscribers does nothing, so components
+/// may always publish events whether or not an operator has enabled the admin socket.
+///
+/// This is cheap to clone and all clones publish to the same subscribers.
+#[derive(Debug, Clone)]
+pub struct SecurityEventPublisher {
+    sender: broadcast::Sender<Arc<SecurityEvent>>,
+}
+
+impl SecurityEventPublisher {
+    pub fn new(buffer_size: usize) -> Self {
+        let (sender, _) = broadcast::channel(buffer_size);
+        Self { sender }
+    }
+
+    pub fn publish(&self, kind: SecurityEventKind) {
+        let event = SecurityEvent::new(kind);
+        debug!(target: LOG_TARGET, "Security event: {:?}", event.kind);
+        // An error is returned if there are no subscribers, in which case the event is discarded
+        let _ = self.sender.send(Arc::new(event));
+    }
+
+    pub fn subscribe(&self) -> SecurityEventReceiver {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for SecurityEventPublisher {
+    fn default() -> Self {
+        Self::new(SECURITY_EVENT_BUFFER_SIZE)
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright 2019 The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    peer_manager::{NodeId, NodeIdentity, Peer, PeerFeatures, PeerFlags},
+    protocol::ProtocolId,
+    transports::MemoryTransport,
+    types::{CommsPublicKey, CommsSecretKey},
+};
+use multiaddr::Multiaddr;
+use rand::rngs::OsRng;
+use std::sync::Arc;
+use tari_crypto::keys::{PublicKey, SecretKey};
+
+fn next_memory_address() -> Multiaddr {
+    format!("/memory/{}", MemoryTransport::acquire_next_memsocket_port())
+        .parse()
+        .unwrap()
+}
+
+/// Builds a `Peer` for tests. Any field that is not set is randomly generated (keys) or defaulted.
+#[derive(Debug, Clone, Default)]
+pub struct PeerBuilder {
+    public_key: Option<CommsPublicKey>,
+    node_id: Option<NodeId>,
+    addresses: Vec<Multiaddr>,
+    flags: PeerFlags,
+    features: Option<PeerFeatures>,
+    supported_protocols: Vec<ProtocolId>,
+    user_agent: String,
+    is_offline: bool,
+}
+
+impl PeerBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_public_key(mut self, public_key: CommsPublicKey) -> Self {
+        self.public_key = Some(public_key);
+        self
+    }
+
+    /// Override the NodeId. By default the NodeId is derived from the public key.
+    pub fn with_node_id(mut self, node_id: NodeId) -> Self {
+        self.node_id = Some(node_id);
+        self
+    }
+
+    pub fn with_address(mut self, address: Multiaddr) -> Self {
+        self.addresses.push(address);
+        self
+    }
+
+    pub fn with_flags(mut self, flags: PeerFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Default: `PeerFeatures::COMMUNICATION_NODE`
+    pub fn with_features(mut self, features: PeerFeatures) -> Self {
+        self.features = Some(features);
+        self
+    }
+
+    pub fn with_supported_protocols(mut self, protocols: Vec<ProtocolId>) -> Self {
+        self.supported_protocols = protocols;
+        self
+    }
+
+    pub fn with_user_agent<T: Into<String>>(mut self, user_agent: T) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    pub fn offline(mut self) -> Self {
+        self.is_offline = true;
+        self
+    }
+
+    pub fn build(self) -> Peer {
+        let public_key = self
+            .public_key
+            .unwrap_or_else(|| CommsPublicKey::random_keypair(&mut OsRng).1);
+        let node_id = self.node_id.unwrap_or_else(|| NodeId::from_key(&public_key).unwrap());
+        let addresses = if self.addresses.is_empty() {
+            vec![next_memory_address()]
+        } else {
+            self.addresses
+        };
+
+        let mut peer = Peer::new(
+            public_key,
+            node_id,
+            addresses.into(),
+            self.flags,
+            self.features.unwrap_or(PeerFeatures::COMMUNICATION_NODE),
+            self.supported_protocols,
+            self.user_agent,
+        );
+        if self.is_offline {
+            peer.set_offline(true);
+        }
+        peer
+    }
+
+    /// Build `n` peers using this builder as a template. Each peer is given its own keys and address unless they
+    /// have been explicitly set.
+    pub fn build_many(self, n: usize) -> Vec<Peer> {
+        (0..n).map(|_| self.clone().build()).collect()
+    }
+}
+
+/// Builds a `NodeIdentity` for tests. A random secret key and a memory transport address are used unless set.
+#[derive(Debug, Clone, Default)]
+pub struct NodeIdentityBuilder {
+    secret_key: Option<CommsSecretKey>,
+    public_address: Option<Multiaddr>,
+    features: Option<PeerFeatures>,
+}
+
+impl NodeIdentityBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_secret_key(mut self, secret_key: CommsSecretKey) -> Self {
+        self.secret_key = Some(secret_key);
+        self
+    }
+
+    pub fn with_public_address(mut self, address: Multiaddr) -> Self {
+        self.public_address = Some(address);
+        self
+    }
+
+    /// Default: `PeerFeatures::COMMUNICATION_NODE`
+    pub fn with_features(mut self, features: PeerFeatures) -> Self {
+        self.features = Some(features);
+        self
+    }
+
+    pub fn build(self) -> Arc<NodeIdentity> {
+        let secret_key = self.secret_key.unwrap_or_else(|| CommsSecretKey::random(&mut OsRng));
+        let node_identity = NodeIdentity::new(
+            secret_key,
+            self.public_address.unwrap_or_else(next_memory_address),
+            self.features.unwrap_or(PeerFeatures::COMMUNICATION_NODE),
+        )
+        .unwrap();
+        Arc::new(node_identity)
+    }
+
+    /// Build a `Peer` that represents this node identity, as it would be seen by other nodes.
+    pub fn build_peer(self) -> (Arc<NodeIdentity>, Peer) {
+        let node_identity = self.build();
+        let peer = PeerBuilder::new()
+            .with_public_key(node_identity.public_key().clone())
+            .with_address(node_identity.public_address())
+            .with_features(node_identity.features())
+            .build();
+        (node_identity, peer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn peer_builder_defaults() {
+        let peer = PeerBuilder::new().build();
+        assert_eq!(peer.node_id, NodeId::from_key(&peer.public_key).unwrap());
+        assert_eq!(peer.features, PeerFeatures::COMMUNICATION_NODE);
+        assert_eq!(peer.addresses.len(), 1);
+        assert!(!peer.is_offline());
+
+        let peers = PeerBuilder::new().offline().build_many(3);
+        assert_eq!(peers.len(), 3);
+        assert!(peers.iter().all(|p| p.is_offline()));
+        assert_ne!(peers[0].node_id, peers[1].node_id);
+    }
+
+    #[test]
+    fn node_identity_builder_peer() {
+        let (node_identity, peer) = NodeIdentityBuilder::new()
+            .with_features(PeerFeatures::COMMUNICATION_CLIENT)
+            .build_peer();
+        assert_eq!(node_identity.node_id(), &peer.node_id);
+        assert_eq!(node_identity.public_key(), &peer.public_key);
+        assert_eq!(peer.features, PeerFeatures::COMMUNICATION_CLIENT);
+        assert_eq!(peer.addresses.iter().next().unwrap(), &node_identity.public_address());
+    }
+}
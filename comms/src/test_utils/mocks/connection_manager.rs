@@ -146,6 +146,9 @@ impl ConnectionManagerMock {
             },
             CancelDial(_) => {},
             NotifyListening(_reply_tx) => {},
+            GetEstimatedClockSkew(reply_tx) => {
+                let _ = reply_tx.send(None);
+            },
         }
     }
 }
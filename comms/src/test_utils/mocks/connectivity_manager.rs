@@ -178,12 +178,17 @@ impl ConnectivityManagerMock {
             },
             GetAllConnectionStates(_) => unimplemented!(),
             BanPeer(_, _, _) => {},
+            ReportMisbehaviour(_, _, _) => {},
+            ForgivePeer(_, reply) => {
+                reply.send(Ok(())).unwrap();
+            },
             GetActiveConnections(reply) => {
                 reply
                     .send(self.state.active_conns.lock().await.values().cloned().collect())
                     .unwrap();
             },
             WaitStarted(reply) => reply.send(()).unwrap(),
+            WaitForConnectedNodes(_, reply) => reply.send(()).unwrap(),
         }
     }
 }
@@ -26,6 +26,9 @@ pub use connection_manager::{create_connection_manager_mock, ConnectionManagerMo
 mod connectivity_manager;
 pub use connectivity_manager::{create_connectivity_mock, ConnectivityManagerMock, ConnectivityManagerMockState};
 
+mod peer_manager;
+pub use peer_manager::MockPeerManager;
+
 mod peer_connection;
 pub use peer_connection::{
     create_dummy_peer_connection,
@@ -0,0 +1,212 @@
+// Copyright 2019 The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{
+    peer_manager::{NodeId, Peer, PeerFeatures, PeerManagerError},
+    types::CommsPublicKey,
+};
+use futures::lock::Mutex;
+use std::{collections::HashMap, sync::Arc};
+
+/// An in-memory stand-in for `PeerManager` lookups used by tests that do not want a data store backend.
+///
+/// Responses for `find_by_public_key`, `in_network_region` and `closest_peers` can be scripted. When no response has
+/// been scripted, the mock answers from the peers that were added using `add_peer`.
+#[derive(Debug, Clone, Default)]
+pub struct MockPeerManager {
+    state: Arc<Mutex<MockPeerManagerState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockPeerManagerState {
+    calls: Vec<String>,
+    peers: Vec<Peer>,
+    find_by_public_key_responses: HashMap<CommsPublicKey, Result<Peer, PeerManagerError>>,
+    in_network_region_response: Option<bool>,
+    closest_peers_response: Option<Vec<Peer>>,
+}
+
+impl MockPeerManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub async fn add_peer(&self, peer: Peer) {
+        let mut state = self.state.lock().await;
+        state.peers.retain(|p| p.node_id != peer.node_id);
+        state.peers.push(peer);
+    }
+
+    pub async fn add_peers<I: IntoIterator<Item = Peer>>(&self, peers: I) {
+        for peer in peers {
+            self.add_peer(peer).await;
+        }
+    }
+
+    /// Script the response returned by `find_by_public_key` for the given public key
+    pub async fn set_find_by_public_key_response(
+        &self,
+        public_key: CommsPublicKey,
+        response: Result<Peer, PeerManagerError>,
+    )
+    {
+        self.state
+            .lock()
+            .await
+            .find_by_public_key_responses
+            .insert(public_key, response);
+    }
+
+    /// Script the response returned by all subsequent `in_network_region` calls
+    pub async fn set_in_network_region_response(&self, in_region: bool) {
+        self.state.lock().await.in_network_region_response = Some(in_region);
+    }
+
+    /// Script the peers returned by all subsequent `closest_peers` calls
+    pub async fn set_closest_peers_response(&self, peers: Vec<Peer>) {
+        self.state.lock().await.closest_peers_response = Some(peers);
+    }
+
+    pub async fn take_calls(&self) -> Vec<String> {
+        self.state.lock().await.calls.drain(..).collect()
+    }
+
+    pub async fn call_count(&self) -> usize {
+        self.state.lock().await.calls.len()
+    }
+
+    pub async fn find_by_public_key(&self, public_key: &CommsPublicKey) -> Result<Peer, PeerManagerError> {
+        let mut state = self.state.lock().await;
+        state.calls.push(format!("find_by_public_key({})", public_key));
+        if let Some(response) = state.find_by_public_key_responses.get(public_key) {
+            return response.clone();
+        }
+        state
+            .peers
+            .iter()
+            .find(|p| &p.public_key == public_key)
+            .cloned()
+            .ok_or(PeerManagerError::PeerNotFoundError)
+    }
+
+    pub async fn in_network_region(
+        &self,
+        node_id: &NodeId,
+        region_node_id: &NodeId,
+        n: usize,
+    ) -> Result<bool, PeerManagerError>
+    {
+        let mut state = self.state.lock().await;
+        state
+            .calls
+            .push(format!("in_network_region({}, {}, {})", node_id, region_node_id, n));
+        if let Some(in_region) = state.in_network_region_response {
+            return Ok(in_region);
+        }
+        if node_id == region_node_id {
+            return Ok(true);
+        }
+        let closest = closest_from(&state.peers, region_node_id, n, &[], None);
+        Ok(closest.iter().any(|p| &p.node_id == node_id))
+    }
+
+    pub async fn closest_peers(
+        &self,
+        node_id: &NodeId,
+        n: usize,
+        excluded_peers: &[NodeId],
+        features: Option<PeerFeatures>,
+    ) -> Result<Vec<Peer>, PeerManagerError>
+    {
+        let mut state = self.state.lock().await;
+        state.calls.push(format!("closest_peers({}, {})", node_id, n));
+        if let Some(peers) = state.closest_peers_response.as_ref() {
+            return Ok(peers.iter().take(n).cloned().collect());
+        }
+        Ok(closest_from(&state.peers, node_id, n, excluded_peers, features))
+    }
+}
+
+fn closest_from(
+    peers: &[Peer],
+    node_id: &NodeId,
+    n: usize,
+    excluded_peers: &[NodeId],
+    features: Option<PeerFeatures>,
+) -> Vec<Peer>
+{
+    let mut peers = peers
+        .iter()
+        .filter(|p| !p.is_banned() && !p.is_offline())
+        .filter(|p| !excluded_peers.contains(&p.node_id))
+        .filter(|p| features.map(|f| p.features == f).unwrap_or(true))
+        .cloned()
+        .collect::<Vec<_>>();
+    peers.sort_unstable_by_key(|p| p.node_id.distance(node_id));
+    peers.truncate(n);
+    peers
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{runtime, test_utils::PeerBuilder};
+
+    #[runtime::test_basic]
+    async fn scripted_and_seeded_responses() {
+        let mock = MockPeerManager::new();
+        let peers = PeerBuilder::new().build_many(5);
+        mock.add_peers(peers.clone()).await;
+
+        let found = mock.find_by_public_key(&peers[0].public_key).await.unwrap();
+        assert_eq!(found.node_id, peers[0].node_id);
+        let unknown = PeerBuilder::new().build();
+        assert!(mock
+            .find_by_public_key(&unknown.public_key)
+            .await
+            .unwrap_err()
+            .is_peer_not_found());
+        mock.set_find_by_public_key_response(unknown.public_key.clone(), Ok(unknown.clone()))
+            .await;
+        assert_eq!(
+            mock.find_by_public_key(&unknown.public_key).await.unwrap().node_id,
+            unknown.node_id
+        );
+
+        let closest = mock.closest_peers(&unknown.node_id, 3, &[], None).await.unwrap();
+        assert_eq!(closest.len(), 3);
+        assert!(mock
+            .in_network_region(&closest[0].node_id, &unknown.node_id, 3)
+            .await
+            .unwrap());
+
+        mock.set_closest_peers_response(vec![peers[4].clone()]).await;
+        mock.set_in_network_region_response(false).await;
+        let closest = mock.closest_peers(&unknown.node_id, 3, &[], None).await.unwrap();
+        assert_eq!(closest.len(), 1);
+        assert!(!mock
+            .in_network_region(&closest[0].node_id, &unknown.node_id, 3)
+            .await
+            .unwrap());
+        assert_eq!(mock.call_count().await, 7);
+    }
+}
@@ -27,6 +27,9 @@ cfg_test! {
     pub mod test_node;
 }
 
+mod builders;
+pub use builders::{NodeIdentityBuilder, PeerBuilder};
+
 pub mod mocks;
 pub mod node_id;
 pub mod node_identity;
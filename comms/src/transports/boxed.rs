@@ -0,0 +1,187 @@
+// Copyright 2019, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::{transports::Transport, types::CommsPublicKey};
+use futures::{
+    future::BoxFuture,
+    stream::BoxStream,
+    AsyncRead,
+    AsyncWrite,
+    FutureExt,
+    StreamExt,
+    TryFutureExt,
+    TryStreamExt,
+};
+use multiaddr::Multiaddr;
+use std::{fmt, io, sync::Arc};
+
+/// A socket that can be returned from a [BoxedTransport](self::BoxedTransport).
+///
+/// This trait is implemented for any type that meets the requirements comms has of a transport socket.
+pub trait TransportSocket: AsyncRead + AsyncWrite + Send + Sync + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Sync + Unpin> TransportSocket for T {}
+
+/// A type-erased transport socket
+pub type BoxedSocket = Box<dyn TransportSocket>;
+
+pub type BoxedInbound = BoxFuture<'static, io::Result<BoxedSocket>>;
+pub type BoxedListener = BoxStream<'static, io::Result<(BoxedInbound, Multiaddr)>>;
+pub type BoxedListenFuture = BoxFuture<'static, io::Result<(BoxedListener, Multiaddr)>>;
+pub type BoxedDialFuture = BoxFuture<'static, io::Result<BoxedSocket>>;
+
+/// A type-erased [Transport](super::Transport).
+///
+/// This allows a user-provided transport to be used by comms without the comms stack having to be generic over it.
+/// All futures, streams and sockets produced by the inner transport are boxed and errors are converted into
+/// `io::Error`s.
+#[derive(Clone)]
+pub struct BoxedTransport {
+    inner: Arc<dyn ErasedTransport>,
+}
+
+impl BoxedTransport {
+    pub fn new<T>(transport: T) -> Self
+    where
+        T: Transport + Send + Sync + 'static,
+        T::Output: TransportSocket + 'static,
+        T::Error: 'static,
+        T::Inbound: 'static,
+        T::Listener: 'static,
+        T::ListenFuture: 'static,
+        T::DialFuture: 'static,
+    {
+        Self {
+            inner: Arc::new(transport),
+        }
+    }
+}
+
+impl fmt::Debug for BoxedTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BoxedTransport")
+    }
+}
+
+impl Transport for BoxedTransport {
+    type DialFuture = BoxedDialFuture;
+    type Error = io::Error;
+    type Inbound = BoxedInbound;
+    type ListenFuture = BoxedListenFuture;
+    type Listener = BoxedListener;
+    type Output = BoxedSocket;
+
+    fn listen(&self, addr: Multiaddr) -> Result<Self::ListenFuture, Self::Error> {
+        self.inner.listen_boxed(addr)
+    }
+
+    fn dial(&self, addr: Multiaddr) -> Result<Self::DialFuture, Self::Error> {
+        self.inner.dial_boxed(addr)
+    }
+
+    fn dial_peer(&self, addr: Multiaddr, public_key: &CommsPublicKey) -> Result<Self::DialFuture, Self::Error> {
+        self.inner.dial_peer_boxed(addr, public_key)
+    }
+}
+
+trait ErasedTransport: Send + Sync {
+    fn listen_boxed(&self, addr: Multiaddr) -> io::Result<BoxedListenFuture>;
+    fn dial_boxed(&self, addr: Multiaddr) -> io::Result<BoxedDialFuture>;
+    fn dial_peer_boxed(&self, addr: Multiaddr, public_key: &CommsPublicKey) -> io::Result<BoxedDialFuture>;
+}
+
+impl<T> ErasedTransport for T
+where
+    T: Transport + Send + Sync,
+    T::Output: TransportSocket + 'static,
+    T::Error: 'static,
+    T::Inbound: 'static,
+    T::Listener: 'static,
+    T::ListenFuture: 'static,
+    T::DialFuture: 'static,
+{
+    fn listen_boxed(&self, addr: Multiaddr) -> io::Result<BoxedListenFuture> {
+        let fut = self.listen(addr).map_err(to_io_error)?;
+        let fut = fut.map_err(to_io_error).map_ok(|(listener, addr)| {
+            let listener = listener
+                .map_err(to_io_error)
+                .map_ok(|(inbound, peer_addr)| {
+                    let inbound = inbound.map_err(to_io_error).map_ok(box_socket).boxed();
+                    (inbound, peer_addr)
+                })
+                .boxed();
+            (listener, addr)
+        });
+        Ok(fut.boxed())
+    }
+
+    fn dial_boxed(&self, addr: Multiaddr) -> io::Result<BoxedDialFuture> {
+        let fut = self.dial(addr).map_err(to_io_error)?;
+        Ok(fut.map_err(to_io_error).map_ok(box_socket).boxed())
+    }
+
+    fn dial_peer_boxed(&self, addr: Multiaddr, public_key: &CommsPublicKey) -> io::Result<BoxedDialFuture> {
+        let fut = self.dial_peer(addr, public_key).map_err(to_io_error)?;
+        Ok(fut.map_err(to_io_error).map_ok(box_socket).boxed())
+    }
+}
+
+fn box_socket<S: TransportSocket + 'static>(socket: S) -> BoxedSocket {
+    Box::new(socket)
+}
+
+fn to_io_error<E>(err: E) -> io::Error
+where E: std::error::Error + Send + Sync + 'static {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{runtime, transports::MemoryTransport};
+    use futures::{AsyncReadExt, AsyncWriteExt};
+
+    #[runtime::test_basic]
+    async fn listen_and_dial() {
+        let transport = BoxedTransport::new(MemoryTransport);
+        let (mut listener, addr) = transport.listen("/memory/0".parse().unwrap()).unwrap().await.unwrap();
+
+        let (outbound, mut inbound) = futures::future::join(transport.dial(addr).unwrap(), async {
+            let (inbound, _) = listener.next().await.unwrap().unwrap();
+            inbound.await.unwrap()
+        })
+        .await;
+        let mut outbound = outbound.unwrap();
+
+        outbound.write_all(b"boxed").await.unwrap();
+        let mut buf = [0u8; 5];
+        inbound.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"boxed");
+    }
+
+    #[test]
+    fn dial_error_is_converted() {
+        let transport = BoxedTransport::new(MemoryTransport);
+        let err = transport.dial("/ip4/127.0.0.1/tcp/123".parse().unwrap()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+    }
+}
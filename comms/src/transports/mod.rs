@@ -24,14 +24,31 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::types::CommsPublicKey;
 use futures::{Future, Stream};
 use multiaddr::Multiaddr;
 
+mod boxed;
+pub use boxed::{
+    BoxedDialFuture,
+    BoxedInbound,
+    BoxedListenFuture,
+    BoxedListener,
+    BoxedSocket,
+    BoxedTransport,
+    TransportSocket,
+};
+
 mod dns;
 
 mod memory;
 pub use memory::MemoryTransport;
 
+mod peer_certificate;
+
+mod quic;
+pub use quic::{QuicSocket, QuicTransport};
+
 mod socks;
 pub use socks::{SocksConfig, SocksTransport};
 
@@ -41,6 +58,19 @@ pub use tcp::{TcpSocket, TcpTransport};
 mod tcp_with_tor;
 pub use tcp_with_tor::TcpWithTorTransport;
 
+mod tls;
+pub use tls::{TlsSocket, TlsTransport};
+
+mod websocket;
+pub use websocket::{is_websocket_address, WebSocketTransport, WsSocket};
+
+/// A transport that is able to listen for and dial connections to a [Multiaddr].
+///
+/// Comms is not tied to any particular transport. A custom transport may be provided by implementing this trait and
+/// passing it to [CommsBuilder::with_transport](crate::CommsBuilder::with_transport) or to
+/// [UnspawnedCommsNode::spawn_with_transport](crate::UnspawnedCommsNode::spawn_with_transport). Implementations
+/// that do not want to name their future types can return boxed futures (see
+/// [BoxedTransport](self::BoxedTransport)).
 pub trait Transport {
     /// The output of the transport after a connection is established
     type Output;
@@ -61,4 +91,11 @@ pub trait Transport {
 
     /// Connect (dial) to the given multiaddr
     fn dial(&self, addr: Multiaddr) -> Result<Self::DialFuture, Self::Error>;
+
+    /// Connect (dial) to the peer with the given public key at the given multiaddr. Transports that are able to
+    /// authenticate the peer (e.g. by pinning its TLS certificate) should override this method; by default it is the
+    /// same as `dial`.
+    fn dial_peer(&self, addr: Multiaddr, _public_key: &CommsPublicKey) -> Result<Self::DialFuture, Self::Error> {
+        self.dial(addr)
+    }
 }
@@ -0,0 +1,325 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Self-signed certificates which are bound to a node identity, shared by the TLS and QUIC transports.
+//!
+//! Each node presents a certificate containing an extension with its node public key and a signature, made with the
+//! node's secret key, over the certificate's public key. The TLS handshake proves that the peer holds the
+//! certificate's private key, so a valid extension proves that the peer holds the node's secret key. A dialer that
+//! knows which peer it expects pins the certificate to that peer's public key.
+
+use crate::{
+    peer_manager::NodeIdentity,
+    types::{Challenge, CommsPublicKey, CommsSecretKey},
+    utils::signature,
+};
+use digest::Digest;
+use rand::rngs::OsRng;
+use std::{io, sync::Arc};
+use tari_crypto::{
+    signatures::SchnorrSignature,
+    tari_utilities::{hex::Hex, ByteArray},
+};
+
+/// Private OID of the certificate extension containing the node identity
+const NODE_IDENTITY_EXTENSION_OID: &[u64] = &[1, 3, 6, 1, 4, 1, 57_143, 1, 1];
+/// Domain separator for the signature over the certificate public key
+const CERTIFICATE_SIGNATURE_DOMAIN: &[u8] = b"tari.comms.tls_certificate";
+/// Length of the extension value: the node public key followed by the public nonce and signature
+const EXTENSION_LEN: usize = 96;
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OCTET_STRING: u8 = 0x04;
+const DER_OID: u8 = 0x06;
+const DER_BOOLEAN: u8 = 0x01;
+const DER_TBS_VERSION: u8 = 0xa0;
+const DER_TBS_EXTENSIONS: u8 = 0xa3;
+
+/// Generate a self-signed certificate and private key bound to the given node identity. The certificate's subject
+/// alternative name is the node id, which is informational only.
+pub(super) fn generate_certificate(
+    node_identity: &NodeIdentity,
+) -> io::Result<(rustls::Certificate, rustls::PrivateKey)> {
+    let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).map_err(to_io_error)?;
+    let extension = sign_certificate_key(node_identity, key_pair.public_key_raw())?;
+
+    let mut params = rcgen::CertificateParams::new(vec![node_identity.node_id().to_hex()]);
+    params.key_pair = Some(key_pair);
+    params.custom_extensions = vec![rcgen::CustomExtension::from_oid_content(
+        NODE_IDENTITY_EXTENSION_OID,
+        extension,
+    )];
+    let cert = rcgen::Certificate::from_params(params).map_err(to_io_error)?;
+    let cert_der = cert.serialize_der().map_err(to_io_error)?;
+    Ok((
+        rustls::Certificate(cert_der),
+        rustls::PrivateKey(cert.serialize_private_key_der()),
+    ))
+}
+
+fn sign_certificate_key(node_identity: &NodeIdentity, certificate_key: &[u8]) -> io::Result<Vec<u8>> {
+    let signature = signature::sign(
+        &mut OsRng,
+        node_identity.secret_key().clone(),
+        certificate_key_challenge(certificate_key),
+    )
+    .map_err(to_io_error)?;
+    let mut extension = Vec::with_capacity(EXTENSION_LEN);
+    extension.extend_from_slice(node_identity.public_key().as_bytes());
+    extension.extend_from_slice(signature.get_public_nonce().as_bytes());
+    extension.extend_from_slice(signature.get_signature().as_bytes());
+    Ok(extension)
+}
+
+fn certificate_key_challenge(certificate_key: &[u8]) -> Vec<u8> {
+    let mut challenge = Vec::with_capacity(CERTIFICATE_SIGNATURE_DOMAIN.len() + certificate_key.len());
+    challenge.extend_from_slice(CERTIFICATE_SIGNATURE_DOMAIN);
+    challenge.extend_from_slice(certificate_key);
+    challenge
+}
+
+/// Returns the node public key that the given DER certificate is bound to, or None if the certificate does not
+/// contain a valid node identity extension.
+pub(super) fn verify_certificate(cert_der: &[u8]) -> Option<CommsPublicKey> {
+    let (certificate_key, extension) = parse_certificate(cert_der)?;
+    if extension.len() != EXTENSION_LEN {
+        return None;
+    }
+    let public_key = CommsPublicKey::from_bytes(&extension[..32]).ok()?;
+    let public_nonce = CommsPublicKey::from_bytes(&extension[32..64]).ok()?;
+    let sig = CommsSecretKey::from_bytes(&extension[64..]).ok()?;
+    let challenge = Challenge::new()
+        .chain(certificate_key_challenge(certificate_key))
+        .result()
+        .to_vec();
+    if SchnorrSignature::new(public_nonce, sig).verify_challenge(&public_key, &challenge) {
+        Some(public_key)
+    } else {
+        None
+    }
+}
+
+/// Verifies that the certificate presented by a TLS server is bound to a node identity and, if a public key is
+/// pinned, that it is bound to that public key.
+pub(super) struct PeerCertificateVerifier {
+    pinned_public_key: Option<CommsPublicKey>,
+}
+
+impl PeerCertificateVerifier {
+    /// Accept a certificate bound to any node identity. This is used when the expected peer is not known.
+    pub fn any_peer() -> Self {
+        Self {
+            pinned_public_key: None,
+        }
+    }
+
+    /// Only accept a certificate bound to the given public key
+    pub fn pinned(public_key: CommsPublicKey) -> Self {
+        Self {
+            pinned_public_key: Some(public_key),
+        }
+    }
+}
+
+impl rustls::ServerCertVerifier for PeerCertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        _roots: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        _dns_name: webpki::DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError>
+    {
+        let cert = presented_certs
+            .first()
+            .ok_or(rustls::TLSError::NoCertificatesPresented)?;
+        let public_key = verify_certificate(&cert.0)
+            .ok_or_else(|| rustls::TLSError::General("Certificate is not bound to a node identity".to_string()))?;
+        match self.pinned_public_key {
+            Some(ref pinned) if *pinned != public_key => Err(rustls::TLSError::General(format!(
+                "Certificate is bound to public key '{}' but expected '{}'",
+                public_key, pinned
+            ))),
+            _ => Ok(rustls::ServerCertVerified::assertion()),
+        }
+    }
+}
+
+/// Install a `PeerCertificateVerifier` on the given client config
+pub(super) fn set_verifier(config: &mut rustls::ClientConfig, pinned_public_key: Option<CommsPublicKey>) {
+    let verifier = match pinned_public_key {
+        Some(public_key) => PeerCertificateVerifier::pinned(public_key),
+        None => PeerCertificateVerifier::any_peer(),
+    };
+    config.dangerous().set_certificate_verifier(Arc::new(verifier));
+}
+
+/// Returns the raw subject public key and the node identity extension value of a DER certificate
+fn parse_certificate(cert_der: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (certificate, _) = read_der(cert_der, DER_SEQUENCE)?;
+    let (tbs, _) = read_der(certificate, DER_SEQUENCE)?;
+    let mut rest = tbs;
+    if rest.first() == Some(&DER_TBS_VERSION) {
+        rest = read_der(rest, DER_TBS_VERSION)?.1;
+    }
+    // Skip the serial number, signature algorithm, issuer, validity and subject
+    for _ in 0..5 {
+        rest = skip_der(rest)?;
+    }
+    let (spki, mut rest) = read_der(rest, DER_SEQUENCE)?;
+    let (_, spki) = read_der(spki, DER_SEQUENCE)?;
+    let (certificate_key, _) = read_der(spki, DER_BIT_STRING)?;
+    // The first byte of a bit string is the number of unused bits
+    let certificate_key = certificate_key.get(1..)?;
+
+    let encoded_oid = encode_oid(NODE_IDENTITY_EXTENSION_OID);
+    while !rest.is_empty() {
+        if rest[0] != DER_TBS_EXTENSIONS {
+            rest = skip_der(rest)?;
+            continue;
+        }
+        let (extensions, _) = read_der(rest, DER_TBS_EXTENSIONS)?;
+        let (mut extensions, _) = read_der(extensions, DER_SEQUENCE)?;
+        while !extensions.is_empty() {
+            let (extension, next) = read_der(extensions, DER_SEQUENCE)?;
+            extensions = next;
+            let (oid, mut extension) = read_der(extension, DER_OID)?;
+            if oid != encoded_oid.as_slice() {
+                continue;
+            }
+            if extension.first() == Some(&DER_BOOLEAN) {
+                extension = skip_der(extension)?;
+            }
+            let (value, _) = read_der(extension, DER_OCTET_STRING)?;
+            return Some((certificate_key, value));
+        }
+        return None;
+    }
+    None
+}
+
+/// Reads a DER element with the given tag, returning its contents and the remaining input
+fn read_der(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    if *input.first()? != tag {
+        return None;
+    }
+    let (len, header_len) = match *input.get(1)? {
+        len if len < 0x80 => (len as usize, 2),
+        len_of_len @ 0x81..=0x84 => {
+            let num_bytes = (len_of_len & 0x7f) as usize;
+            let len = input
+                .get(2..2 + num_bytes)?
+                .iter()
+                .fold(0usize, |len, b| (len << 8) | *b as usize);
+            (len, 2 + num_bytes)
+        },
+        _ => return None,
+    };
+    let end = header_len.checked_add(len)?;
+    Some((input.get(header_len..end)?, &input[end..]))
+}
+
+fn skip_der(input: &[u8]) -> Option<&[u8]> {
+    read_der(input, *input.first()?).map(|(_, rest)| rest)
+}
+
+fn encode_oid(arcs: &[u64]) -> Vec<u8> {
+    let mut encoded = vec![(arcs[0] * 40 + arcs[1]) as u8];
+    for arc in &arcs[2..] {
+        let mut bytes = vec![(arc & 0x7f) as u8];
+        let mut arc = arc >> 7;
+        while arc > 0 {
+            bytes.push((arc & 0x7f) as u8 | 0x80);
+            arc >>= 7;
+        }
+        encoded.extend(bytes.iter().rev());
+    }
+    encoded
+}
+
+pub(super) fn to_io_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{peer_manager::PeerFeatures, test_utils::node_identity::build_node_identity};
+    use rustls::ServerCertVerifier;
+
+    fn verify_with(verifier: &PeerCertificateVerifier, cert: rustls::Certificate) -> bool {
+        let name = webpki::DNSNameRef::try_from_ascii_str("tari").unwrap();
+        verifier
+            .verify_server_cert(&rustls::RootCertStore::empty(), &[cert], name, &[])
+            .is_ok()
+    }
+
+    #[test]
+    fn certificate_is_bound_to_node_identity() {
+        let node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        let (cert, _) = generate_certificate(&node_identity).unwrap();
+        assert_eq!(verify_certificate(&cert.0).unwrap(), *node_identity.public_key());
+
+        assert!(verify_with(&PeerCertificateVerifier::any_peer(), cert.clone()));
+        assert!(verify_with(
+            &PeerCertificateVerifier::pinned(node_identity.public_key().clone()),
+            cert.clone()
+        ));
+        let other = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        assert!(!verify_with(
+            &PeerCertificateVerifier::pinned(other.public_key().clone()),
+            cert
+        ));
+    }
+
+    #[test]
+    fn reject_certificate_without_identity() {
+        let cert = rcgen::generate_simple_self_signed(vec!["tari".to_string()]).unwrap();
+        let cert = rustls::Certificate(cert.serialize_der().unwrap());
+        assert!(verify_certificate(&cert.0).is_none());
+        assert!(!verify_with(&PeerCertificateVerifier::any_peer(), cert));
+    }
+
+    #[test]
+    fn reject_identity_signed_for_another_key() {
+        let node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        let key_pair = rcgen::KeyPair::generate(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        // Copy a valid extension into a certificate with a different key
+        let extension = sign_certificate_key(&node_identity, key_pair.public_key_raw()).unwrap();
+        let mut params = rcgen::CertificateParams::new(vec!["tari".to_string()]);
+        params.custom_extensions = vec![rcgen::CustomExtension::from_oid_content(
+            NODE_IDENTITY_EXTENSION_OID,
+            extension,
+        )];
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        assert!(verify_certificate(&cert.serialize_der().unwrap()).is_none());
+    }
+
+    #[test]
+    fn oid_encoding() {
+        // 1.2.840.113549 is the RSA Data Security OID prefix
+        assert_eq!(encode_oid(&[1, 2, 840, 113_549]), vec![
+            0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d
+        ]);
+    }
+}
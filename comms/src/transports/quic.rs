@@ -0,0 +1,403 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{
+    peer_certificate::{self, to_io_error},
+    TcpSocket,
+    TcpWithTorTransport,
+    Transport,
+};
+use crate::{
+    multiaddr::{Multiaddr, Protocol},
+    peer_manager::NodeIdentity,
+    types::CommsPublicKey,
+    utils::multiaddr::socketaddr_to_multiaddr,
+};
+use futures::{AsyncRead, AsyncWrite, Future, Stream, StreamExt};
+use log::*;
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+const LOG_TARGET: &str = "comms::transports::quic";
+
+/// QUIC requires a server name for the TLS handshake. Certificates are verified against the peer's node identity so
+/// the name is not meaningful.
+const QUIC_SERVER_NAME: &str = "tari";
+
+/// Transport implementation for QUIC with TCP fallback.
+///
+/// QUIC addresses (`/ip4/1.2.3.4/udp/18189/quic`) are dialed over QUIC, giving a single encrypted UDP flow which
+/// survives changes to the local IP address (see [rebind](QuicTransport::rebind)). All other addresses are dialed
+/// using the fallback `TcpWithTorTransport`. Because the connection manager dials a peer's addresses in order, a peer
+/// that advertises both a QUIC and a TCP address will be reached over TCP if the QUIC dial fails.
+///
+/// A single substream of the QUIC connection is used as the socket, on which the usual noise and yamux upgrades are
+/// performed.
+///
+/// As with the [TlsTransport](super::TlsTransport), each node presents a certificate bound to its node identity, which
+/// is pinned to the peer's public key when dialing a known peer.
+#[derive(Clone)]
+pub struct QuicTransport {
+    tcp_transport: TcpWithTorTransport,
+    node_identity: Arc<NodeIdentity>,
+    endpoint: Arc<Mutex<Option<quinn::Endpoint>>>,
+}
+
+impl QuicTransport {
+    /// Create a new QuicTransport for the given node identity
+    pub fn new(node_identity: Arc<NodeIdentity>) -> Self {
+        Self::with_tcp_transport(Default::default(), node_identity)
+    }
+
+    /// Create a new QuicTransport that falls back to the given TCP transport for non-QUIC addresses
+    pub fn with_tcp_transport(tcp_transport: TcpWithTorTransport, node_identity: Arc<NodeIdentity>) -> Self {
+        Self {
+            tcp_transport,
+            node_identity,
+            endpoint: Default::default(),
+        }
+    }
+
+    /// Returns true if the given address is a QUIC address
+    pub fn is_quic_address(addr: &Multiaddr) -> bool {
+        addr.iter().any(|p| p == Protocol::Quic)
+    }
+
+    /// Rebind the QUIC endpoint to a new local UDP socket. Existing QUIC connections migrate to the new socket,
+    /// which allows connections to survive a change of local IP address (e.g. moving from WiFi to mobile data).
+    pub fn rebind(&self, local_addr: SocketAddr) -> io::Result<()> {
+        let lock = acquire_lock!(self.endpoint);
+        match lock.as_ref() {
+            Some(endpoint) => {
+                let socket = UdpSocket::bind(local_addr)?;
+                endpoint.rebind(socket)?;
+                debug!(target: LOG_TARGET, "QUIC endpoint rebound to {}", local_addr);
+                Ok(())
+            },
+            None => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "QUIC endpoint has not been initialized",
+            )),
+        }
+    }
+
+    /// Returns the current QUIC endpoint or creates a client-only endpoint bound to an ephemeral port
+    fn get_or_create_client_endpoint(&self, is_ipv6: bool) -> io::Result<quinn::Endpoint> {
+        let mut lock = acquire_lock!(self.endpoint);
+        if let Some(endpoint) = lock.as_ref() {
+            return Ok(endpoint.clone());
+        }
+
+        let bind_addr = if is_ipv6 {
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)
+        } else {
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)
+        };
+        let mut builder = quinn::Endpoint::builder();
+        builder.default_client_config(client_config(None));
+        let (endpoint, _) = builder.bind(&bind_addr).map_err(to_io_error)?;
+        *lock = Some(endpoint.clone());
+        Ok(endpoint)
+    }
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send>>;
+type BoxStream<T> = Pin<Box<dyn Stream<Item = io::Result<T>> + Send>>;
+
+impl Transport for QuicTransport {
+    type DialFuture = BoxFuture<Self::Output>;
+    type Error = io::Error;
+    type Inbound = BoxFuture<Self::Output>;
+    type ListenFuture = BoxFuture<(Self::Listener, Multiaddr)>;
+    type Listener = BoxStream<(Self::Inbound, Multiaddr)>;
+    type Output = QuicSocket;
+
+    fn listen(&self, addr: Multiaddr) -> Result<Self::ListenFuture, Self::Error> {
+        if !Self::is_quic_address(&addr) {
+            let listen = self.tcp_transport.listen(addr)?;
+            return Ok(Box::pin(async move {
+                let (listener, local_addr) = listen.await?;
+                let listener = listener.map(|result| {
+                    result.map(|(inbound, peer_addr)| {
+                        let inbound: Self::Inbound = Box::pin(async move { inbound.await.map(QuicSocket::Tcp) });
+                        (inbound, peer_addr)
+                    })
+                });
+                let listener: Self::Listener = Box::pin(listener);
+                Ok((listener, local_addr))
+            }));
+        }
+
+        let socket_addr = quic_multiaddr_to_socketaddr(&addr)?;
+        let mut builder = quinn::Endpoint::builder();
+        builder.listen(server_config(&self.node_identity)?);
+        builder.default_client_config(client_config(None));
+        let (endpoint, incoming) = builder.bind(&socket_addr).map_err(to_io_error)?;
+        let local_addr = quic_socketaddr_to_multiaddr(&endpoint.local_addr()?);
+        *acquire_lock!(self.endpoint) = Some(endpoint);
+
+        Ok(Box::pin(async move {
+            let listener = incoming.map(|connecting| {
+                let peer_addr = quic_socketaddr_to_multiaddr(&connecting.remote_address());
+                let inbound: Self::Inbound = Box::pin(async move {
+                    let quinn::NewConnection {
+                        connection,
+                        mut bi_streams,
+                        ..
+                    } = connecting.await.map_err(to_io_error)?;
+                    let (send, recv) = bi_streams
+                        .next()
+                        .await
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "QUIC connection closed"))?
+                        .map_err(to_io_error)?;
+                    Ok(QuicSocket::Quic { send, recv, connection })
+                });
+                Ok((inbound, peer_addr))
+            });
+            let listener: Self::Listener = Box::pin(listener);
+            Ok((listener, local_addr))
+        }))
+    }
+
+    fn dial(&self, addr: Multiaddr) -> Result<Self::DialFuture, Self::Error> {
+        self.dial_with_pin(addr, None)
+    }
+
+    fn dial_peer(&self, addr: Multiaddr, public_key: &CommsPublicKey) -> Result<Self::DialFuture, Self::Error> {
+        self.dial_with_pin(addr, Some(public_key.clone()))
+    }
+}
+
+impl QuicTransport {
+    fn dial_with_pin(
+        &self,
+        addr: Multiaddr,
+        pinned_public_key: Option<CommsPublicKey>,
+    ) -> io::Result<BoxFuture<QuicSocket>>
+    {
+        if !Self::is_quic_address(&addr) {
+            let dial = self.tcp_transport.dial(addr)?;
+            return Ok(Box::pin(async move { dial.await.map(QuicSocket::Tcp) }));
+        }
+
+        let socket_addr = quic_multiaddr_to_socketaddr(&addr)?;
+        let endpoint = self.get_or_create_client_endpoint(socket_addr.is_ipv6())?;
+        let connecting = endpoint
+            .connect_with(client_config(pinned_public_key), &socket_addr, QUIC_SERVER_NAME)
+            .map_err(to_io_error)?;
+        Ok(Box::pin(async move {
+            let quinn::NewConnection { connection, .. } = connecting.await.map_err(to_io_error)?;
+            let (send, recv) = connection.open_bi().await.map_err(to_io_error)?;
+            Ok(QuicSocket::Quic { send, recv, connection })
+        }))
+    }
+}
+
+/// Converts a `/ip4/../udp/../quic` multiaddr to a `SocketAddr`
+fn quic_multiaddr_to_socketaddr(addr: &Multiaddr) -> io::Result<SocketAddr> {
+    let mut iter = addr.iter();
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid QUIC address '{}'", addr));
+    let ip: IpAddr = match iter.next().ok_or_else(invalid)? {
+        Protocol::Ip4(ip) => ip.into(),
+        Protocol::Ip6(ip) => ip.into(),
+        _ => return Err(invalid()),
+    };
+    let port = match iter.next().ok_or_else(invalid)? {
+        Protocol::Udp(port) => port,
+        _ => return Err(invalid()),
+    };
+    match (iter.next(), iter.next()) {
+        (Some(Protocol::Quic), None) => Ok(SocketAddr::new(ip, port)),
+        _ => Err(invalid()),
+    }
+}
+
+fn quic_socketaddr_to_multiaddr(socket_addr: &SocketAddr) -> Multiaddr {
+    let mut addr = socketaddr_to_multiaddr(socket_addr);
+    // Replace the TCP component with UDP
+    addr.pop();
+    addr.push(Protocol::Udp(socket_addr.port()));
+    addr.push(Protocol::Quic);
+    addr
+}
+
+fn server_config(node_identity: &NodeIdentity) -> io::Result<quinn::ServerConfig> {
+    let (cert, key) = peer_certificate::generate_certificate(node_identity)?;
+    let key = quinn::PrivateKey::from_der(&key.0).map_err(to_io_error)?;
+    let cert = quinn::Certificate::from_der(&cert.0).map_err(to_io_error)?;
+    let mut builder = quinn::ServerConfigBuilder::default();
+    builder
+        .certificate(quinn::CertificateChain::from_certs(vec![cert]), key)
+        .map_err(to_io_error)?;
+    Ok(builder.build())
+}
+
+fn client_config(pinned_public_key: Option<CommsPublicKey>) -> quinn::ClientConfig {
+    let mut config = quinn::ClientConfigBuilder::default().build();
+    let tls_config = Arc::get_mut(&mut config.crypto).expect("ClientConfig crypto is not shared");
+    peer_certificate::set_verifier(tls_config, pinned_public_key);
+    config
+}
+
+/// Socket returned by the `QuicTransport`. Either a bidirectional QUIC stream or a fallback TCP socket.
+pub enum QuicSocket {
+    Quic {
+        send: quinn::SendStream,
+        recv: quinn::RecvStream,
+        /// Held so that the connection is kept alive for the lifetime of the socket
+        connection: quinn::Connection,
+    },
+    Tcp(TcpSocket),
+}
+
+impl QuicSocket {
+    /// Returns true if this socket is a QUIC stream
+    pub fn is_quic(&self) -> bool {
+        matches!(self, QuicSocket::Quic { .. })
+    }
+}
+
+impl AsyncRead for QuicSocket {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        match &mut *self {
+            QuicSocket::Quic { recv, .. } => AsyncRead::poll_read(Pin::new(recv), cx, buf),
+            QuicSocket::Tcp(socket) => Pin::new(socket).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for QuicSocket {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match &mut *self {
+            QuicSocket::Quic { send, .. } => AsyncWrite::poll_write(Pin::new(send), cx, buf),
+            QuicSocket::Tcp(socket) => Pin::new(socket).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut *self {
+            QuicSocket::Quic { send, .. } => AsyncWrite::poll_flush(Pin::new(send), cx),
+            QuicSocket::Tcp(socket) => Pin::new(socket).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut *self {
+            QuicSocket::Quic { send, .. } => AsyncWrite::poll_close(Pin::new(send), cx),
+            QuicSocket::Tcp(socket) => Pin::new(socket).poll_close(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{peer_manager::PeerFeatures, runtime, runtime::task, test_utils::node_identity::build_node_identity};
+    use futures::{AsyncReadExt, AsyncWriteExt};
+
+    fn quic_transport() -> QuicTransport {
+        QuicTransport::new(build_node_identity(PeerFeatures::COMMUNICATION_NODE))
+    }
+
+    #[test]
+    fn address_conversion() {
+        let addr = "/ip4/127.0.0.1/udp/18189/quic".parse::<Multiaddr>().unwrap();
+        assert!(QuicTransport::is_quic_address(&addr));
+        let socket_addr = quic_multiaddr_to_socketaddr(&addr).unwrap();
+        assert_eq!(socket_addr, "127.0.0.1:18189".parse().unwrap());
+        assert_eq!(quic_socketaddr_to_multiaddr(&socket_addr), addr);
+
+        let addr = "/ip4/127.0.0.1/tcp/18189".parse::<Multiaddr>().unwrap();
+        assert!(!QuicTransport::is_quic_address(&addr));
+        assert!(quic_multiaddr_to_socketaddr(&addr).is_err());
+    }
+
+    #[runtime::test_basic]
+    async fn listen_and_dial_quic() {
+        let node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        let listener_transport = QuicTransport::new(node_identity.clone());
+        let (mut listener, addr) = listener_transport
+            .listen("/ip4/127.0.0.1/udp/0/quic".parse().unwrap())
+            .unwrap()
+            .await
+            .unwrap();
+
+        let accept = task::spawn(async move {
+            loop {
+                let (inbound, _) = listener.next().await.unwrap().unwrap();
+                // The connection from the dialer that pinned the wrong public key fails
+                let mut socket = match inbound.await {
+                    Ok(socket) => socket,
+                    Err(_) => continue,
+                };
+                assert!(socket.is_quic());
+                let mut buf = [0u8; 5];
+                socket.read_exact(&mut buf).await.unwrap();
+                socket.write_all(&buf).await.unwrap();
+                // Wait for the data to be acknowledged before the connection is dropped
+                socket.close().await.unwrap();
+                break;
+            }
+        });
+
+        let dialer_transport = quic_transport();
+        let other = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        assert!(dialer_transport
+            .dial_peer(addr.clone(), other.public_key())
+            .unwrap()
+            .await
+            .is_err());
+        let mut socket = dialer_transport
+            .dial_peer(addr, node_identity.public_key())
+            .unwrap()
+            .await
+            .unwrap();
+        socket.write_all(b"hello").await.unwrap();
+        socket.flush().await.unwrap();
+        let mut buf = [0u8; 5];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+        accept.await.unwrap();
+    }
+
+    #[runtime::test_basic]
+    async fn tcp_fallback() {
+        let transport = quic_transport();
+        let (mut listener, addr) = transport
+            .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap()
+            .await
+            .unwrap();
+        let accept = task::spawn(async move {
+            let (inbound, _) = listener.next().await.unwrap().unwrap();
+            assert!(!inbound.await.unwrap().is_quic());
+        });
+        let socket = transport.dial(addr).unwrap().await.unwrap();
+        assert!(!socket.is_quic());
+        accept.await.unwrap();
+    }
+}
@@ -0,0 +1,603 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::{
+    peer_certificate::{self, to_io_error},
+    Transport,
+};
+use crate::{peer_manager::NodeIdentity, types::CommsPublicKey};
+use futures::{future, ready, AsyncRead, AsyncWrite, Future, Stream, StreamExt, TryFutureExt};
+use log::*;
+use multiaddr::Multiaddr;
+use rustls::Session;
+use std::{
+    io,
+    io::{Read, Write},
+    mem,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+const LOG_TARGET: &str = "comms::transports::tls";
+
+/// The server name sent by the client in the TLS handshake. Certificates are verified against the peer's node identity
+/// so the name is not meaningful.
+const TLS_SERVER_NAME: &str = "tari";
+
+/// The first byte of a TLS record containing a handshake message (i.e. the ClientHello)
+const TLS_HANDSHAKE_RECORD_TYPE: u8 = 0x16;
+
+/// Transport which wraps connections of the inner transport in TLS.
+///
+/// This transport is intended for networks in which middleboxes drop traffic that does not look like TLS. Outbound
+/// connections perform a TLS handshake before the usual noise and yamux upgrades. Inbound connections may use TLS or
+/// not; a TLS ClientHello is detected from the first byte sent by the dialer.
+///
+/// Each node presents a self-signed certificate which is bound to its node identity. When dialing a known peer, the
+/// certificate is pinned to the peer's public key and the handshake fails if any other certificate is presented.
+///
+/// By default, a failed handshake fails the dial. If [plaintext fallback](TlsTransport::with_plaintext_fallback) is
+/// enabled, the peer is dialed again without TLS, which allows peers that do not support TLS to be reached at the cost
+/// of allowing an attacker to downgrade the connection.
+#[derive(Clone)]
+pub struct TlsTransport<T> {
+    inner: T,
+    server_config: Arc<rustls::ServerConfig>,
+    client_config: rustls::ClientConfig,
+    plaintext_fallback: bool,
+}
+
+impl<T> TlsTransport<T> {
+    /// Create a new TlsTransport which wraps connections of the given transport in TLS
+    pub fn new(inner: T, node_identity: &NodeIdentity) -> io::Result<Self> {
+        Ok(Self {
+            inner,
+            server_config: Arc::new(server_config(node_identity)?),
+            client_config: client_config(),
+            plaintext_fallback: false,
+        })
+    }
+
+    /// Set whether a peer is dialed again without TLS if the TLS handshake fails. Default: false
+    pub fn with_plaintext_fallback(mut self, plaintext_fallback: bool) -> Self {
+        self.plaintext_fallback = plaintext_fallback;
+        self
+    }
+}
+
+impl<T> TlsTransport<T>
+where
+    T: Transport<Error = io::Error> + Clone + Send + Sync + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::DialFuture: 'static,
+{
+    fn dial_with_config(
+        &self,
+        addr: Multiaddr,
+        client_config: Arc<rustls::ClientConfig>,
+    ) -> io::Result<BoxFuture<TlsSocket<T::Output>>>
+    {
+        let dial = self.inner.dial(addr.clone())?;
+        let inner = self.inner.clone();
+        let plaintext_fallback = self.plaintext_fallback;
+        Ok(Box::pin(async move {
+            let mut socket = TlsSocket::client(dial.await?, &client_config)?;
+            match socket.handshake().await {
+                Ok(_) => Ok(socket),
+                Err(err) if plaintext_fallback => {
+                    debug!(
+                        target: LOG_TARGET,
+                        "TLS handshake with '{}' failed ({}). Dialing again without TLS", addr, err
+                    );
+                    let socket = inner.dial(addr)?.await?;
+                    Ok(TlsSocket::plain(socket))
+                },
+                Err(err) => Err(err),
+            }
+        }))
+    }
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send>>;
+type BoxStream<T> = Pin<Box<dyn Stream<Item = io::Result<T>> + Send>>;
+
+impl<T> Transport for TlsTransport<T>
+where
+    T: Transport<Error = io::Error> + Clone + Send + Sync + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T::DialFuture: 'static,
+    T::Inbound: 'static,
+    T::Listener: 'static,
+    T::ListenFuture: 'static,
+{
+    type DialFuture = BoxFuture<Self::Output>;
+    type Error = io::Error;
+    type Inbound = BoxFuture<Self::Output>;
+    type ListenFuture = BoxFuture<(Self::Listener, Multiaddr)>;
+    type Listener = BoxStream<(Self::Inbound, Multiaddr)>;
+    type Output = TlsSocket<T::Output>;
+
+    fn listen(&self, addr: Multiaddr) -> Result<Self::ListenFuture, Self::Error> {
+        let listen = self.inner.listen(addr)?;
+        let server_config = self.server_config.clone();
+        Ok(Box::pin(async move {
+            let (listener, local_addr) = listen.await?;
+            let listener = listener.map(move |result| {
+                let server_config = server_config.clone();
+                result.map(|(inbound, peer_addr)| {
+                    // The TLS handshake (if any) is performed lazily when the socket is first read so that a slow
+                    // peer does not hold up the listener
+                    let inbound: Self::Inbound =
+                        Box::pin(inbound.map_ok(move |socket| TlsSocket::server(socket, &server_config)));
+                    (inbound, peer_addr)
+                })
+            });
+            let listener: Self::Listener = Box::pin(listener);
+            Ok((listener, local_addr))
+        }))
+    }
+
+    fn dial(&self, addr: Multiaddr) -> Result<Self::DialFuture, Self::Error> {
+        self.dial_with_config(addr, Arc::new(self.client_config.clone()))
+    }
+
+    fn dial_peer(&self, addr: Multiaddr, public_key: &CommsPublicKey) -> Result<Self::DialFuture, Self::Error> {
+        let mut client_config = self.client_config.clone();
+        peer_certificate::set_verifier(&mut client_config, Some(public_key.clone()));
+        self.dial_with_config(addr, Arc::new(client_config))
+    }
+}
+
+fn server_config(node_identity: &NodeIdentity) -> io::Result<rustls::ServerConfig> {
+    let (cert, key) = peer_certificate::generate_certificate(node_identity)?;
+    let mut config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+    config.set_single_cert(vec![cert], key).map_err(to_io_error)?;
+    Ok(config)
+}
+
+fn client_config() -> rustls::ClientConfig {
+    let mut config = rustls::ClientConfig::new();
+    peer_certificate::set_verifier(&mut config, None);
+    config
+}
+
+enum TlsState {
+    /// Inbound socket which has not yet received the first byte from the dialer
+    Detecting(rustls::ServerSession),
+    /// The socket is not using TLS. `first_byte` contains the byte read while detecting TLS, if any.
+    Plain { first_byte: Option<u8> },
+    Tls {
+        session: Box<dyn rustls::Session>,
+        is_eof: bool,
+        is_close_notify_sent: bool,
+    },
+}
+
+/// Socket returned by the `TlsTransport`. Depending on the peer, data is either sent over TLS or as-is.
+pub struct TlsSocket<S> {
+    io: S,
+    state: TlsState,
+}
+
+impl<S> TlsSocket<S>
+where S: AsyncRead + AsyncWrite + Unpin
+{
+    fn client(io: S, config: &Arc<rustls::ClientConfig>) -> io::Result<Self> {
+        let name = webpki::DNSNameRef::try_from_ascii_str(TLS_SERVER_NAME)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Invalid TLS server name"))?;
+        Ok(Self {
+            io,
+            state: TlsState::Tls {
+                session: Box::new(rustls::ClientSession::new(config, name)),
+                is_eof: false,
+                is_close_notify_sent: false,
+            },
+        })
+    }
+
+    fn server(io: S, config: &Arc<rustls::ServerConfig>) -> Self {
+        Self {
+            io,
+            state: TlsState::Detecting(rustls::ServerSession::new(config)),
+        }
+    }
+
+    fn plain(io: S) -> Self {
+        Self {
+            io,
+            state: TlsState::Plain { first_byte: None },
+        }
+    }
+
+    /// Returns true if this socket is using TLS. An inbound socket only knows if TLS is in use once it has been read
+    /// from.
+    pub fn is_tls(&self) -> bool {
+        matches!(self.state, TlsState::Tls { .. })
+    }
+
+    /// Drive the TLS handshake to completion. This is a no-op for plain sockets.
+    async fn handshake(&mut self) -> io::Result<()> {
+        future::poll_fn(|cx| self.poll_handshake(cx)).await
+    }
+
+    fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let Self { io, state } = self;
+        if let TlsState::Tls { session, .. } = state {
+            while session.is_handshaking() || session.wants_write() {
+                if session.wants_write() {
+                    ready!(poll_write_tls(io, session.as_mut(), cx))?;
+                    continue;
+                }
+                if ready!(poll_read_tls(io, session.as_mut(), cx))? == 0 {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Connection closed during TLS handshake",
+                    )));
+                }
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Called with the first byte received on an inbound socket to determine whether the dialer is using TLS
+    fn set_first_byte(&mut self, byte: u8) -> io::Result<()> {
+        match mem::replace(&mut self.state, TlsState::Plain { first_byte: None }) {
+            TlsState::Detecting(mut session) if byte == TLS_HANDSHAKE_RECORD_TYPE => {
+                session.read_tls(&mut &[byte][..])?;
+                self.state = TlsState::Tls {
+                    session: Box::new(session),
+                    is_eof: false,
+                    is_close_notify_sent: false,
+                };
+            },
+            TlsState::Detecting(_) => {
+                self.state = TlsState::Plain { first_byte: Some(byte) };
+            },
+            state => {
+                self.state = state;
+            },
+        }
+        Ok(())
+    }
+}
+
+impl<S> AsyncRead for TlsSocket<S>
+where S: AsyncRead + AsyncWrite + Unpin
+{
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        loop {
+            let Self { io, state } = &mut *self;
+            match state {
+                TlsState::Detecting(_) => {
+                    let mut byte = [0u8; 1];
+                    if ready!(Pin::new(io).poll_read(cx, &mut byte))? == 0 {
+                        return Poll::Ready(Ok(0));
+                    }
+                    self.set_first_byte(byte[0])?;
+                },
+                TlsState::Plain { first_byte } => {
+                    if buf.is_empty() {
+                        return Poll::Ready(Ok(0));
+                    }
+                    if let Some(byte) = first_byte.take() {
+                        buf[0] = byte;
+                        return Poll::Ready(Ok(1));
+                    }
+                    return Pin::new(io).poll_read(cx, buf);
+                },
+                TlsState::Tls { session, is_eof, .. } => {
+                    // Send any pending handshake messages or alerts. If the socket is not ready we continue to read.
+                    while session.wants_write() {
+                        match poll_write_tls(io, session.as_mut(), cx)? {
+                            Poll::Ready(_) => {},
+                            Poll::Pending => break,
+                        }
+                    }
+                    let n = session.read(buf)?;
+                    if n > 0 || *is_eof || buf.is_empty() {
+                        return Poll::Ready(Ok(n));
+                    }
+                    if ready!(poll_read_tls(io, session.as_mut(), cx))? == 0 {
+                        *is_eof = true;
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for TlsSocket<S>
+where S: AsyncRead + AsyncWrite + Unpin
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let Self { io, state } = &mut *self;
+        match state {
+            TlsState::Detecting(_) => {
+                // The dialer always writes first when using TLS, so a socket that is written to before anything has
+                // been read is not using TLS
+                *state = TlsState::Plain { first_byte: None };
+                Pin::new(io).poll_write(cx, buf)
+            },
+            TlsState::Plain { .. } => Pin::new(io).poll_write(cx, buf),
+            TlsState::Tls { session, .. } => {
+                let n = session.write(buf)?;
+                while session.wants_write() {
+                    match poll_write_tls(io, session.as_mut(), cx)? {
+                        Poll::Ready(_) => {},
+                        Poll::Pending if n == 0 => return Poll::Pending,
+                        // The plaintext has been buffered by the session and will be sent on the next write or flush
+                        Poll::Pending => break,
+                    }
+                }
+                Poll::Ready(Ok(n))
+            },
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_handshake(cx))?;
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let TlsState::Tls {
+            session,
+            is_close_notify_sent,
+            ..
+        } = &mut self.state
+        {
+            if !*is_close_notify_sent {
+                session.send_close_notify();
+                *is_close_notify_sent = true;
+            }
+        }
+        ready!(self.poll_handshake(cx))?;
+        Pin::new(&mut self.io).poll_close(cx)
+    }
+}
+
+/// Read TLS records from the socket into the session. Returns the number of bytes read, or 0 if the socket has
+/// reached EOF.
+fn poll_read_tls<S>(io: &mut S, session: &mut dyn rustls::Session, cx: &mut Context<'_>) -> Poll<io::Result<usize>>
+where S: AsyncRead + AsyncWrite + Unpin {
+    let n = match session.read_tls(&mut SyncIo { io, cx }) {
+        Ok(n) => n,
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Poll::Pending,
+        Err(err) => return Poll::Ready(Err(err)),
+    };
+
+    if let Err(err) = session.process_new_packets() {
+        // Attempt to notify the peer of the error. This is best effort as we are about to fail in any case.
+        let _ = session.write_tls(&mut SyncIo { io, cx });
+        return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, err)));
+    }
+
+    Poll::Ready(Ok(n))
+}
+
+/// Write pending TLS records from the session to the socket. An error is returned if the socket accepts no bytes.
+fn poll_write_tls<S>(io: &mut S, session: &mut dyn rustls::Session, cx: &mut Context<'_>) -> Poll<io::Result<usize>>
+where S: AsyncRead + AsyncWrite + Unpin {
+    match session.write_tls(&mut SyncIo { io, cx }) {
+        Ok(0) => Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+        Ok(n) => Poll::Ready(Ok(n)),
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
+        Err(err) => Poll::Ready(Err(err)),
+    }
+}
+
+/// Adapts an async socket to the synchronous `Read` and `Write` traits expected by rustls. `Poll::Pending` is mapped
+/// to `io::ErrorKind::WouldBlock`.
+struct SyncIo<'a, 'b, S> {
+    io: &'a mut S,
+    cx: &'a mut Context<'b>,
+}
+
+impl<S: AsyncRead + Unpin> Read for SyncIo<'_, '_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match Pin::new(&mut *self.io).poll_read(self.cx, buf) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> Write for SyncIo<'_, '_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match Pin::new(&mut *self.io).poll_write(self.cx, buf) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match Pin::new(&mut *self.io).poll_flush(self.cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        peer_manager::PeerFeatures,
+        runtime,
+        test_utils::node_identity::build_node_identity,
+        transports::TcpTransport,
+    };
+    use futures::{AsyncReadExt, AsyncWriteExt};
+    use tokio::task;
+
+    fn tls_transport() -> TlsTransport<TcpTransport> {
+        let node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        TlsTransport::new(TcpTransport::new(), &node_identity).unwrap()
+    }
+
+    async fn spawn_echo_listener(transport: TlsTransport<TcpTransport>) -> Multiaddr {
+        let (mut listener, addr) = transport
+            .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap()
+            .await
+            .unwrap();
+        task::spawn(async move {
+            while let Some(Ok((inbound, _))) = listener.next().await {
+                if let Ok(mut socket) = inbound.await {
+                    let mut buf = [0u8; 5];
+                    if socket.read_exact(&mut buf).await.is_ok() {
+                        let _ = socket.write_all(&buf).await;
+                        let _ = socket.flush().await;
+                    }
+                }
+            }
+        });
+        addr
+    }
+
+    async fn assert_echo<S: AsyncRead + AsyncWrite + Unpin>(socket: &mut S) {
+        socket.write_all(b"hello").await.unwrap();
+        socket.flush().await.unwrap();
+        let mut buf = [0u8; 5];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[runtime::test_basic]
+    async fn dial_and_accept_tls() {
+        let (mut listener, addr) = tls_transport()
+            .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap()
+            .await
+            .unwrap();
+
+        let accept = task::spawn(async move {
+            let (inbound, _) = listener.next().await.unwrap().unwrap();
+            let mut socket = inbound.await.unwrap();
+            let mut buf = [0u8; 5];
+            socket.read_exact(&mut buf).await.unwrap();
+            assert!(socket.is_tls());
+            socket.write_all(&buf).await.unwrap();
+            socket.flush().await.unwrap();
+        });
+
+        let mut socket = tls_transport().dial(addr).unwrap().await.unwrap();
+        assert!(socket.is_tls());
+        assert_echo(&mut socket).await;
+        accept.await.unwrap();
+    }
+
+    #[runtime::test_basic]
+    async fn accept_plain() {
+        let (mut listener, addr) = tls_transport()
+            .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap()
+            .await
+            .unwrap();
+
+        let accept = task::spawn(async move {
+            let (inbound, _) = listener.next().await.unwrap().unwrap();
+            let mut socket = inbound.await.unwrap();
+            let mut buf = [0u8; 5];
+            socket.read_exact(&mut buf).await.unwrap();
+            assert!(!socket.is_tls());
+            socket.write_all(&buf).await.unwrap();
+            socket.flush().await.unwrap();
+        });
+
+        let mut socket = TcpTransport::new().dial(addr).unwrap().await.unwrap();
+        assert_echo(&mut socket).await;
+        accept.await.unwrap();
+    }
+
+    #[runtime::test_basic]
+    async fn dial_falls_back_to_plain() {
+        let (mut listener, addr) = TcpTransport::new()
+            .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap()
+            .await
+            .unwrap();
+
+        let accept = task::spawn(async move {
+            // Plain listener that drops connections that do not start with the expected bytes
+            loop {
+                let (inbound, _) = listener.next().await.unwrap().unwrap();
+                let mut socket = inbound.await.unwrap();
+                let mut buf = [0u8; 5];
+                socket.read_exact(&mut buf).await.unwrap();
+                if &buf == b"hello" {
+                    socket.write_all(&buf).await.unwrap();
+                    socket.flush().await.unwrap();
+                    break;
+                }
+            }
+        });
+
+        let mut socket = tls_transport()
+            .with_plaintext_fallback(true)
+            .dial(addr)
+            .unwrap()
+            .await
+            .unwrap();
+        assert!(!socket.is_tls());
+        assert_echo(&mut socket).await;
+        accept.await.unwrap();
+    }
+
+    #[runtime::test_basic]
+    async fn dial_does_not_fall_back_to_plain_by_default() {
+        let (mut listener, addr) = TcpTransport::new()
+            .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap()
+            .await
+            .unwrap();
+
+        task::spawn(async move {
+            // Plain listener that closes every connection
+            while let Some(Ok((inbound, _))) = listener.next().await {
+                drop(inbound.await);
+            }
+        });
+
+        assert!(tls_transport().dial(addr).unwrap().await.is_err());
+    }
+
+    #[runtime::test_basic]
+    async fn dial_peer_pins_certificate() {
+        let node_identity = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        let listener_transport = TlsTransport::new(TcpTransport::new(), &node_identity).unwrap();
+        let addr = spawn_echo_listener(listener_transport).await;
+
+        let mut socket = tls_transport()
+            .dial_peer(addr.clone(), node_identity.public_key())
+            .unwrap()
+            .await
+            .unwrap();
+        assert!(socket.is_tls());
+        assert_echo(&mut socket).await;
+
+        let other = build_node_identity(PeerFeatures::COMMUNICATION_NODE);
+        let result = tls_transport().dial_peer(addr, other.public_key()).unwrap().await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::Transport;
+use crate::multiaddr::{Multiaddr, Protocol};
+use async_tungstenite::{accept_async, async_tls::client_async_tls, tungstenite, tungstenite::Message};
+use futures::{ready, AsyncRead, AsyncWrite, Future, Sink, Stream, StreamExt};
+use std::{
+    borrow::Cow,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+const DEFAULT_WS_PATH: &str = "/";
+
+/// Transport that wraps an inner stream transport (typically `TcpTransport`) in the WebSocket protocol. This allows
+/// nodes in environments that only permit HTTP(S) egress to connect to peers that expose a WebSocket listener.
+///
+/// Addresses for this transport are the inner transport address followed by `/ws` or `/wss` e.g.
+/// `/ip4/1.2.3.4/tcp/80/ws` or `/dns4/example.com/tcp/443/wss`. Secure (`wss`) addresses are only supported when
+/// dialing; a listener that should accept `wss` connections is expected to sit behind a TLS-terminating proxy.
+///
+/// This transport is part of the native comms stack, which depends on tokio and does not target wasm32. A browser
+/// client dialing a node's WebSocket listener must provide its own noise handshake and multiplexing, and can use
+/// `tari_comms_dht_codec` to encode DHT envelopes.
+#[derive(Debug, Clone, Default)]
+pub struct WebSocketTransport<T> {
+    inner: T,
+}
+
+impl<T> WebSocketTransport<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send>>;
+type BoxStream<T> = Pin<Box<dyn Stream<Item = io::Result<T>> + Send>>;
+
+impl<T> Transport for WebSocketTransport<T>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + Sync + 'static,
+    T::Inbound: 'static,
+    T::Listener: 'static,
+    T::ListenFuture: 'static,
+    T::DialFuture: 'static,
+{
+    type DialFuture = BoxFuture<Self::Output>;
+    type Error = io::Error;
+    type Inbound = BoxFuture<Self::Output>;
+    type ListenFuture = BoxFuture<(Self::Listener, Multiaddr)>;
+    type Listener = BoxStream<(Self::Inbound, Multiaddr)>;
+    type Output = WsSocket;
+
+    fn listen(&self, addr: Multiaddr) -> Result<Self::ListenFuture, Self::Error> {
+        let (inner_addr, path, is_secure) = split_ws_addr(addr)?;
+        if is_secure {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Listening on a secure WebSocket (wss) address is not supported",
+            ));
+        }
+        let listen = self.inner.listen(inner_addr).map_err(to_io_error)?;
+        Ok(Box::pin(async move {
+            let (listener, local_addr) = listen.await.map_err(to_io_error)?;
+            let listener = listener.map(|result| {
+                let (inbound, peer_addr) = result.map_err(to_io_error)?;
+                let inbound: Self::Inbound = Box::pin(async move {
+                    let socket = inbound.await.map_err(to_io_error)?;
+                    let ws = accept_async(socket).await.map_err(ws_to_io_error)?;
+                    Ok(WsSocket::new(ws))
+                });
+                Ok((inbound, peer_addr))
+            });
+            let listener: Self::Listener = Box::pin(listener);
+            Ok((listener, local_addr.with(Protocol::Ws(Cow::Owned(path)))))
+        }))
+    }
+
+    fn dial(&self, addr: Multiaddr) -> Result<Self::DialFuture, Self::Error> {
+        let url = to_ws_url(&addr)?;
+        let (inner_addr, _, _) = split_ws_addr(addr)?;
+        let dial = self.inner.dial(inner_addr).map_err(to_io_error)?;
+        Ok(Box::pin(async move {
+            let socket = dial.await.map_err(to_io_error)?;
+            // TLS is only negotiated if the URL scheme is wss
+            let (ws, _) = client_async_tls(url, socket).await.map_err(ws_to_io_error)?;
+            Ok(WsSocket::new(ws))
+        }))
+    }
+}
+
+/// Returns true if the address ends with a `/ws` or `/wss` component
+pub fn is_websocket_address(addr: &Multiaddr) -> bool {
+    matches!(addr.iter().last(), Some(Protocol::Ws(_)) | Some(Protocol::Wss(_)))
+}
+
+/// Splits a `.../ws[/path]` or `.../wss[/path]` address into the inner transport address, the websocket path and
+/// whether the address is secure.
+pub(crate) fn split_ws_addr(mut addr: Multiaddr) -> io::Result<(Multiaddr, String, bool)> {
+    match addr.pop() {
+        Some(Protocol::Ws(path)) => Ok((addr, normalize_path(&path), false)),
+        Some(Protocol::Wss(path)) => Ok((addr, normalize_path(&path), true)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not a WebSocket address", addr),
+        )),
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    if path.is_empty() {
+        DEFAULT_WS_PATH.to_string()
+    } else if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+/// Converts a WebSocket multiaddr into a URL suitable for the WebSocket client handshake.
+fn to_ws_url(addr: &Multiaddr) -> io::Result<String> {
+    let mut iter = addr.iter();
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid WebSocket address '{}'", addr),
+        )
+    };
+    let host = match iter.next().ok_or_else(invalid)? {
+        Protocol::Ip4(ip) => ip.to_string(),
+        Protocol::Ip6(ip) => format!("[{}]", ip),
+        Protocol::Dns4(host) | Protocol::Dns6(host) => host.to_string(),
+        _ => return Err(invalid()),
+    };
+    let port = match iter.next().ok_or_else(invalid)? {
+        Protocol::Tcp(port) => port,
+        _ => return Err(invalid()),
+    };
+    match iter.next().ok_or_else(invalid)? {
+        Protocol::Ws(path) => Ok(format!("ws://{}:{}{}", host, port, normalize_path(&path))),
+        Protocol::Wss(path) => Ok(format!("wss://{}:{}{}", host, port, normalize_path(&path))),
+        _ => Err(invalid()),
+    }
+}
+
+fn to_io_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+fn ws_to_io_error(err: tungstenite::Error) -> io::Error {
+    match err {
+        tungstenite::Error::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::Other, err),
+    }
+}
+
+/// Object-safe combination of the `Stream` and `Sink` halves of a websocket connection. This allows plain and TLS
+/// websocket streams to be represented by the same socket type.
+trait WsDuplex:
+    Stream<Item = Result<Message, tungstenite::Error>> + Sink<Message, Error = tungstenite::Error> + Send + Sync + Unpin
+{
+}
+
+impl<T> WsDuplex for T where T: Stream<Item = Result<Message, tungstenite::Error>>
+        + Sink<Message, Error = tungstenite::Error>
+        + Send
+        + Sync
+        + Unpin
+{
+}
+
+/// Adapts a message-oriented websocket stream into a byte stream implementing `AsyncRead`/`AsyncWrite`. Each write
+/// is sent as a single binary frame.
+pub struct WsSocket {
+    inner: Box<dyn WsDuplex>,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+}
+
+impl WsSocket {
+    fn new<S: WsDuplex + 'static>(inner: S) -> Self {
+        Self {
+            inner: Box::new(inner),
+            read_buf: Vec::new(),
+            read_pos: 0,
+        }
+    }
+}
+
+impl AsyncRead for WsSocket {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        loop {
+            if self.read_pos < self.read_buf.len() {
+                let n = std::cmp::min(buf.len(), self.read_buf.len() - self.read_pos);
+                buf[..n].copy_from_slice(&self.read_buf[self.read_pos..self.read_pos + n]);
+                self.read_pos += n;
+                return Poll::Ready(Ok(n));
+            }
+
+            match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(data))) => {
+                    self.read_buf = data;
+                    self.read_pos = 0;
+                },
+                // Control frames are handled by tungstenite, text frames are not part of the protocol
+                Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) | Some(Ok(Message::Text(_))) => continue,
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(0)),
+                Some(Err(err)) => return Poll::Ready(Err(ws_to_io_error(err))),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for WsSocket {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        ready!(Pin::new(&mut self.inner).poll_ready(cx)).map_err(ws_to_io_error)?;
+        Pin::new(&mut self.inner)
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(ws_to_io_error)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx).map_err(ws_to_io_error)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx).map_err(ws_to_io_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{runtime, runtime::task, transports::TcpTransport};
+    use futures::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn split_and_url() {
+        let addr = "/ip4/127.0.0.1/tcp/8080/ws".parse::<Multiaddr>().unwrap();
+        assert_eq!(to_ws_url(&addr).unwrap(), "ws://127.0.0.1:8080/");
+        assert!(is_websocket_address(&addr));
+        let (inner, path, is_secure) = split_ws_addr(addr).unwrap();
+        assert_eq!(inner, "/ip4/127.0.0.1/tcp/8080".parse::<Multiaddr>().unwrap());
+        assert_eq!(path, "/");
+        assert!(!is_secure);
+
+        let addr = "/dns4/example.com/tcp/443/wss".parse::<Multiaddr>().unwrap();
+        assert_eq!(to_ws_url(&addr).unwrap(), "wss://example.com:443/");
+        let (_, _, is_secure) = split_ws_addr(addr).unwrap();
+        assert!(is_secure);
+
+        let addr = "/ip4/127.0.0.1/tcp/8080".parse::<Multiaddr>().unwrap();
+        assert!(!is_websocket_address(&addr));
+        assert!(split_ws_addr(addr).is_err());
+    }
+
+    #[runtime::test_basic]
+    async fn listen_and_dial() {
+        let transport = WebSocketTransport::new(TcpTransport::new());
+        let (mut listener, addr) = transport
+            .listen("/ip4/127.0.0.1/tcp/0/ws".parse().unwrap())
+            .unwrap()
+            .await
+            .unwrap();
+
+        let accept = task::spawn(async move {
+            let (inbound, _) = listener.next().await.unwrap().unwrap();
+            let mut socket = inbound.await.unwrap();
+            let mut buf = [0u8; 5];
+            socket.read_exact(&mut buf).await.unwrap();
+            socket.write_all(&buf).await.unwrap();
+            socket.flush().await.unwrap();
+        });
+
+        let mut socket = transport.dial(addr).unwrap().await.unwrap();
+        socket.write_all(b"hello").await.unwrap();
+        socket.flush().await.unwrap();
+        let mut buf = [0u8; 5];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+        accept.await.unwrap();
+    }
+}
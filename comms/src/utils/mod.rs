@@ -23,4 +23,5 @@
 pub mod cidr;
 pub mod datetime;
 pub mod multiaddr;
+pub mod rng;
 pub mod signature;
@@ -21,6 +21,7 @@
 // USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 use crate::multiaddr::{Multiaddr, Protocol};
 use std::{
+    convert::TryFrom,
     io,
     net::{IpAddr, SocketAddr, ToSocketAddrs},
 };
@@ -77,6 +78,38 @@ pub fn socketaddr_to_multiaddr(socket_addr: &SocketAddr) -> Multiaddr {
     addr
 }
 
+/// Returns the IP address of the address, or None if the address does not contain an IP address (e.g. a DNS, onion or
+/// memory address)
+pub fn multiaddr_ip(addr: &Multiaddr) -> Option<IpAddr> {
+    addr.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+/// Returns the TCP or memory port of the address, or None if the address has no port
+pub fn multiaddr_port(addr: &Multiaddr) -> Option<u64> {
+    addr.iter().find_map(|protocol| match protocol {
+        Protocol::Tcp(port) => Some(u64::from(port)),
+        Protocol::Memory(port) => Some(port),
+        _ => None,
+    })
+}
+
+/// Returns the address with its TCP or memory port replaced with the given port, or None if the address has no port or
+/// the port is out of range for the protocol
+pub fn multiaddr_with_port(addr: &Multiaddr, port: u64) -> Option<Multiaddr> {
+    multiaddr_port(addr)?;
+    addr.iter()
+        .map(|protocol| match protocol {
+            Protocol::Tcp(_) => u16::try_from(port).ok().map(Protocol::Tcp),
+            Protocol::Memory(_) => Some(Protocol::Memory(port)),
+            protocol => Some(protocol),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -117,6 +150,24 @@ mod test {
         expect_fail("/dns4/doesntexist.theresnotldlikethis/tcp/1234")
     }
 
+    #[test]
+    fn multiaddr_with_port() {
+        let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+        assert_eq!(super::multiaddr_port(&addr), Some(0));
+        let addr = super::multiaddr_with_port(&addr, 18189).unwrap();
+        assert_eq!(addr, "/ip4/127.0.0.1/tcp/18189".parse().unwrap());
+        assert!(super::multiaddr_with_port(&addr, 1 << 16).is_none());
+
+        let addr = "/memory/0".parse().unwrap();
+        let addr = super::multiaddr_with_port(&addr, 1 << 20).unwrap();
+        assert_eq!(super::multiaddr_port(&addr), Some(1 << 20));
+
+        let addr = "/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:1234"
+            .parse()
+            .unwrap();
+        assert!(super::multiaddr_with_port(&addr, 1).is_none());
+    }
+
     #[test]
     fn multiaddr_from_components() {
         let ip: Ipv4Addr = "127.0.0.1".parse().unwrap();
@@ -0,0 +1,156 @@
+// Copyright 2020, The Tari Project
+//
+// Redistribution and use in source and binary forms, with or without modification, are permitted provided that the
+// following conditions are met:
+//
+// 1. Redistributions of source code must retain the above copyright notice, this list of conditions and the following
+// disclaimer.
+//
+// 2. Redistributions in binary form must reproduce the above copyright notice, this list of conditions and the
+// following disclaimer in the documentation and/or other materials provided with the distribution.
+//
+// 3. Neither the name of the copyright holder nor the names of its contributors may be used to endorse or promote
+// products derived from this software without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES,
+// INCLUDING, BUT NOT LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+// DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL,
+// SPECIAL, EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+// SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY,
+// WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE
+// USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use rand::{
+    rngs::{OsRng, StdRng},
+    RngCore,
+    SeedableRng,
+};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// A cheaply cloneable source of randomness for non-cryptographic decisions, such as peer selection and backoff jitter.
+///
+/// By default, the OS RNG is used. A seeded `SharedRng` produces the same sequence of values for every run so that a
+/// simulation can be replayed exactly. All clones of a seeded `SharedRng` draw from the same sequence, so a component
+/// which may be instantiated more than once in a process should use [for_instance](SharedRng::for_instance) to obtain
+/// its own sequence.
+///
+/// _Note_: This must not be used for cryptographic purposes, which is why `CryptoRng` is not implemented.
+#[derive(Clone, Default)]
+pub struct SharedRng {
+    seeded: Option<(u64, Arc<Mutex<StdRng>>)>,
+}
+
+impl SharedRng {
+    /// Returns a `SharedRng` which uses the OS RNG
+    pub fn os() -> Self {
+        Default::default()
+    }
+
+    /// Returns a `SharedRng` which produces a deterministic sequence of values for the given seed
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            seeded: Some((seed, Arc::new(Mutex::new(StdRng::seed_from_u64(seed))))),
+        }
+    }
+
+    /// Returns a `SharedRng` for the instance identified by `instance_id` (e.g. a node id). A seeded `SharedRng`
+    /// returns a new deterministic sequence derived from its seed and the `instance_id`, which is not shared with other
+    /// instances created from the same `SharedRng`. An OS `SharedRng` is returned as is.
+    pub fn for_instance(&self, instance_id: &[u8]) -> Self {
+        match self.seeded.as_ref() {
+            Some((seed, _)) => Self::from_seed(derive_seed(*seed, instance_id)),
+            None => Self::os(),
+        }
+    }
+
+    pub fn is_seeded(&self) -> bool {
+        self.seeded.is_some()
+    }
+
+    fn with_rng<F, R>(&mut self, f: F) -> R
+    where F: FnOnce(&mut dyn RngCore) -> R {
+        match self.seeded.as_ref() {
+            Some((_, rng)) => f(&mut *acquire_lock!(rng)),
+            None => f(&mut OsRng),
+        }
+    }
+}
+
+/// FNV-1a hash of the seed and instance id
+fn derive_seed(seed: u64, instance_id: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0100_0000_01b3;
+    seed.to_le_bytes()
+        .iter()
+        .chain(instance_id)
+        .fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+        })
+}
+
+impl RngCore for SharedRng {
+    fn next_u32(&mut self) -> u32 {
+        self.with_rng(|rng| rng.next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.with_rng(|rng| rng.next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.with_rng(|rng| rng.fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.with_rng(|rng| rng.try_fill_bytes(dest))
+    }
+}
+
+impl fmt::Debug for SharedRng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_seeded() {
+            write!(f, "SharedRng(seeded)")
+        } else {
+            write!(f, "SharedRng(os)")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::seq::SliceRandom;
+
+    #[test]
+    fn seeded_sequence_is_reproducible() {
+        let mut items = (0..100).collect::<Vec<_>>();
+        items.shuffle(&mut SharedRng::from_seed(123));
+        let mut items2 = (0..100).collect::<Vec<_>>();
+        items2.shuffle(&mut SharedRng::from_seed(123));
+        assert_eq!(items, items2);
+
+        // Clones share the same sequence
+        let mut rng = SharedRng::from_seed(123);
+        let mut clone = rng.clone();
+        let first = rng.next_u64();
+        assert_ne!(clone.next_u64(), first);
+        assert_eq!(SharedRng::from_seed(123).next_u64(), first);
+    }
+
+    #[test]
+    fn for_instance() {
+        let rng = SharedRng::from_seed(123);
+        let mut instance1 = rng.for_instance(b"node-1");
+        let mut instance2 = rng.for_instance(b"node-2");
+        let first = instance1.next_u64();
+        // Each instance has its own sequence which is reproducible for the same seed and instance id
+        assert_ne!(instance2.next_u64(), first);
+        assert_eq!(SharedRng::from_seed(123).for_instance(b"node-1").next_u64(), first);
+        assert_ne!(SharedRng::from_seed(321).for_instance(b"node-1").next_u64(), first);
+
+        assert!(!SharedRng::os().for_instance(b"node-1").is_seeded());
+    }
+}
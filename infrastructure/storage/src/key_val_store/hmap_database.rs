@@ -22,7 +22,7 @@
 
 use crate::key_val_store::{
     error::KeyValStoreError,
-    key_val_store::{IterationResult, KeyValueStore},
+    key_val_store::{IterationResult, KeyValueStore, WriteOperation},
 };
 use std::{collections::HashMap, hash::Hash, sync::RwLock};
 
@@ -102,6 +102,22 @@ impl<K: Clone + Eq + Hash, V: Clone> HashmapDatabase<K, V> {
             None => Err(KeyValStoreError::KeyNotFound),
         }
     }
+
+    /// Apply all of the operations while holding the write lock, so that readers see either none or all of them
+    pub fn write_batch(&self, operations: Vec<WriteOperation<K, V>>) -> Result<(), KeyValStoreError> {
+        let mut db = self.db.write().map_err(|_| KeyValStoreError::PoisonedAccess)?;
+        for op in operations {
+            match op {
+                WriteOperation::Insert(key, value) => {
+                    db.insert(key, value);
+                },
+                WriteOperation::Delete(key) => {
+                    db.remove(&key);
+                },
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<K: Clone + Eq + Hash, V: Clone> KeyValueStore<K, V> for HashmapDatabase<K, V> {
@@ -135,6 +151,11 @@ impl<K: Clone + Eq + Hash, V: Clone> KeyValueStore<K, V> for HashmapDatabase<K,
     fn delete(&self, key: &K) -> Result<(), KeyValStoreError> {
         self.remove(key)
     }
+
+    /// Apply all of the operations atomically
+    fn write_batch(&self, operations: Vec<WriteOperation<K, V>>) -> Result<(), KeyValStoreError> {
+        self.write_batch(operations)
+    }
 }
 
 #[cfg(test)]
@@ -36,6 +36,15 @@ impl Default for IterationResult {
     }
 }
 
+/// A single write within a batch passed to [KeyValueStore::write_batch]
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteOperation<K, V> {
+    /// Insert or replace the value for the key
+    Insert(K, V),
+    /// Remove the value for the key. Removing a key that does not exist is a no-op.
+    Delete(K),
+}
+
 /// General CRUD behaviour of Key-value store implementations.
 pub trait KeyValueStore<K, V> {
     /// Inserts a key-value pair into the key-value database.
@@ -69,6 +78,10 @@ pub trait KeyValueStore<K, V> {
     /// Delete a key-pair record associated with the provided `key` from the key-pair database.
     fn delete(&self, key: &K) -> Result<(), KeyValStoreError>;
 
+    /// Apply all of the operations, in order, as a single atomic write. If an error is returned, none of the operations
+    /// have been applied.
+    fn write_batch(&self, operations: Vec<WriteOperation<K, V>>) -> Result<(), KeyValStoreError>;
+
     /// Execute function `f` for each value in the database. Any errors are filtered out.
     /// This is useful for any caller which could not do any better with an error
     /// than filtering it out.
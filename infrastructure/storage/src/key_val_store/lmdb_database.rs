@@ -22,7 +22,7 @@
 
 use crate::{
     key_val_store::{
-        key_val_store::{IterationResult, KeyValueStore},
+        key_val_store::{IterationResult, KeyValueStore, WriteOperation},
         KeyValStoreError,
     },
     lmdb_store::LMDBDatabase,
@@ -104,6 +104,25 @@ where
             .remove::<K>(key)
             .map_err(|e| KeyValStoreError::DatabaseError(format!("{:?}", e)))
     }
+
+    /// Apply all of the operations in a single write transaction
+    fn write_batch(&self, operations: Vec<WriteOperation<K, V>>) -> Result<(), KeyValStoreError> {
+        self.inner
+            .with_write_transaction(|mut txn| {
+                for op in operations {
+                    match op {
+                        WriteOperation::Insert(key, value) => txn.insert(&key, &value)?,
+                        WriteOperation::Delete(key) => {
+                            if txn.exists(&key)? {
+                                txn.delete(&key)?;
+                            }
+                        },
+                    }
+                }
+                Ok(())
+            })
+            .map_err(|e| KeyValStoreError::DatabaseError(format!("{:?}", e)))
+    }
 }
 
 #[cfg(test)]
@@ -203,4 +222,28 @@ mod test {
         }
         clean_up_datastore(database_name); // In Windows file handles must be released before files can be deleted
     }
+
+    #[test]
+    fn test_lmdb_write_batch() {
+        let database_name = "test_lmdb_write_batch";
+        {
+            let datastore = init_datastore(database_name).unwrap();
+            let db = datastore.get_handle(database_name).unwrap();
+            let db = LMDBWrapper::<u64, String>::new(Arc::new(db));
+            db.insert(1, "one".to_string()).unwrap();
+
+            db.write_batch(vec![
+                WriteOperation::Insert(2, "two".to_string()),
+                WriteOperation::Delete(1),
+                WriteOperation::Delete(3),
+                WriteOperation::Insert(4, "four".to_string()),
+            ])
+            .unwrap();
+            assert!(!db.exists(&1).unwrap());
+            assert_eq!(db.get(&2).unwrap().unwrap(), "two");
+            assert_eq!(db.get(&4).unwrap().unwrap(), "four");
+            assert_eq!(db.size().unwrap(), 2);
+        }
+        clean_up_datastore(database_name);
+    }
 }
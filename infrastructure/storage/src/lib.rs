@@ -9,7 +9,7 @@ mod key_val_store;
 pub mod lmdb_store;
 
 pub use key_val_store::{
-    key_val_store::IterationResult,
+    key_val_store::{IterationResult, WriteOperation},
     lmdb_database::LMDBWrapper,
     HashmapDatabase,
     KeyValStoreError,
@@ -555,6 +555,31 @@ impl LMDBDatabase {
         Ok(())
     }
 
+    /// Iterate over the raw key and value bytes of every record in the database. Unlike `for_each`, records are not
+    /// deserialized, so this can be used to find and remove records that can no longer be deserialized.
+    pub fn for_each_raw<F>(&self, mut f: F) -> Result<(), LMDBError>
+    where F: FnMut(&[u8], &[u8]) -> IterationResult {
+        let env = self.env.clone();
+        let db = self.db.clone();
+        let txn = ReadTransaction::new(env)?;
+
+        let access = txn.access();
+        let cursor = txn.cursor(db)?;
+
+        let cursor = MaybeOwned::Owned(cursor);
+        let iter = CursorIter::new(cursor, &access, Cursor::first::<[u8], [u8]>, Cursor::next::<[u8], [u8]>)?;
+
+        for p in iter {
+            let (key_bytes, val_bytes) = p?;
+            match f(key_bytes, val_bytes) {
+                IterationResult::Break => break,
+                IterationResult::Continue => {},
+            }
+        }
+
+        Ok(())
+    }
+
     /// Checks whether a key exists in this database
     pub fn contains_key<K>(&self, key: &K) -> Result<bool, LMDBError>
     where K: AsLmdbBytes + ?Sized {
@@ -591,9 +616,10 @@ impl LMDBDatabase {
         f(wrapper)
     }
 
-    /// Create a transaction with write access on the current table.
+    /// Create a transaction with write access on the current table. The transaction is only committed if `f` succeeds.
     pub fn with_write_transaction<F>(&self, f: F) -> Result<(), LMDBError>
     where F: FnOnce(LMDBWriteTransaction) -> Result<(), LMDBError> {
+        LMDBStore::resize_if_required(&self.env, &self.env_config)?;
         let txn = WriteTransaction::new(self.env.clone())?;
         let access = txn.access();
         let wrapper = LMDBWriteTransaction { db: &self.db, access };